@@ -1,6 +1,6 @@
 use solana_program::instruction::Instruction;
 use solend_sdk::instruction::{
-    refresh_obligation, refresh_reserve, withdraw_obligation_collateral,
+    refresh_obligation, refresh_reserve_with_lending_market, withdraw_obligation_collateral,
 };
 use solend_sdk::state::{Obligation, Reserve};
 
@@ -70,11 +70,12 @@ impl SolendState {
     fn get_refresh_instructions(&self) -> Vec<Instruction> {
         let mut instructions = Vec::new();
         instructions.extend(self.reserves.iter().map(|(pubkey, reserve)| {
-            refresh_reserve(
+            refresh_reserve_with_lending_market(
                 self.lending_program_id,
                 *pubkey,
                 reserve.liquidity.pyth_oracle_pubkey,
                 reserve.liquidity.switchboard_oracle_pubkey,
+                reserve.lending_market,
             )
         }));
 
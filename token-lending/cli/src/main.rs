@@ -4,15 +4,17 @@ use solana_client::rpc_config::{RpcProgramAccountsConfig, RpcSendTransactionConf
 use solana_client::{rpc_config::RpcAccountInfoConfig, rpc_filter::RpcFilterType};
 use solana_sdk::{commitment_config::CommitmentLevel, compute_budget::ComputeBudgetInstruction};
 use solend_program::{
-    instruction::set_lending_market_owner_and_config,
+    instruction::set_lending_market_owner_and_config_with_slots_per_year,
     state::{validate_reserve_config, RateLimiterConfig},
 };
 use solend_sdk::{
     instruction::{
-        liquidate_obligation_and_redeem_reserve_collateral, redeem_reserve_collateral,
-        refresh_obligation, refresh_reserve,
+        liquidate_obligation_and_redeem_reserve_collateral,
+        redeem_reserve_collateral_with_fee_receiver, refresh_obligation,
+        refresh_reserve_with_lending_market,
     },
     state::Obligation,
+    state::PriceBoundsMode,
     state::ReserveType,
 };
 
@@ -31,23 +33,31 @@ use {
     },
     solana_client::rpc_client::RpcClient,
     solana_program::{
-        message::Message, native_token::lamports_to_sol, program_pack::Pack, pubkey::Pubkey,
+        instruction::Instruction, message::Message, native_token::lamports_to_sol,
+        program_pack::Pack, pubkey::Pubkey,
     },
     solana_sdk::{
+        account::from_account,
         commitment_config::CommitmentConfig,
+        rent::Rent,
         signature::{Keypair, Signer},
-        system_instruction,
+        system_instruction, sysvar,
         transaction::Transaction,
     },
     solend_sdk::{
         self,
-        instruction::{init_lending_market, init_reserve, update_reserve_config},
-        math::WAD,
+        add_reserve::{AddReserveBuilder, AddReserveKeypairs},
+        balance_snapshot::get_snapshot_accounts,
+        config_simulator::{simulate_config_change, ConfigChangeImpact},
+        config_watcher::ConfigChange,
+        instruction::{init_lending_market, update_reserve_config},
+        market_config::{fetch_market_state, reconcile, MarketAction, MarketConfig},
+        math::{Decimal, WAD},
+        stale_positions::{stale_positions, StalePosition, StaleThresholds},
         state::{LendingMarket, Reserve, ReserveConfig, ReserveFees},
     },
     spl_token::{
         amount_to_ui_amount,
-        instruction::{approve, revoke},
         state::{Account as Token, Mint},
         ui_amount_to_amount,
     },
@@ -111,6 +121,28 @@ struct PartialReserveConfig {
     pub added_borrow_weight_bps: Option<u64>,
     /// Type of the reseerve (Regular, Isolated)
     pub reserve_type: Option<ReserveType>,
+    /// Soft deposit cap, as a percentage of the deposit limit
+    pub deposit_soft_cap_pct: Option<u8>,
+    /// Soft borrow cap, as a percentage of the borrow limit
+    pub borrow_soft_cap_pct: Option<u8>,
+    /// Annual fee on idle liquidity in basis points
+    pub idle_liquidity_fee_bps: Option<u64>,
+    /// Liquidation fee receiver, the default pubkey to fall back to the fee receiver
+    pub liquidation_fee_receiver: Option<Pubkey>,
+    /// Utilization above which borrows are rejected, as a percentage
+    pub borrow_utilization_cap: Option<u8>,
+    /// Annual fixed borrow rate in basis points
+    pub fixed_borrow_rate_bps: Option<u16>,
+    /// Slots a fixed rate borrow keeps its rate for
+    pub fixed_borrow_max_duration_slots: Option<u64>,
+    /// Fee on redeemed liquidity in basis points
+    pub withdraw_fee_bps: Option<u16>,
+    /// Lowest plausible oracle price in basis points of the quote currency
+    pub min_price_bps: Option<u32>,
+    /// Highest plausible oracle price in basis points of the quote currency
+    pub max_price_bps: Option<u32>,
+    /// What refresh_reserve does with prices outside of the bounds (Reject, Clamp)
+    pub price_bounds_mode: Option<PriceBoundsMode>,
 }
 
 /// Reserve Fees with optional fields
@@ -221,6 +253,43 @@ fn main() {
             SubCommand::with_name("view-all-markets")
                 .about("View all markets")
         )
+        .subcommand(
+            SubCommand::with_name("stale-positions")
+                .about("Report obligations that have been idle for long and hold little value")
+                .arg(
+                    Arg::with_name("min_idle_slots")
+                        .long("min-idle-slots")
+                        .validator(is_parsable::<u64>)
+                        .value_name("INTEGER")
+                        .takes_value(true)
+                        .required(false)
+                        .help("Slots since the last refresh, about three months when not set"),
+                )
+                .arg(
+                    Arg::with_name("max_deposited_value")
+                        .long("max-deposited-value")
+                        .validator(is_parsable::<u64>)
+                        .value_name("INTEGER")
+                        .takes_value(true)
+                        .required(false)
+                        .help("Maximum deposited value in the quote currency, 10 when not set"),
+                )
+                .arg(
+                    Arg::with_name("max_borrowed_value")
+                        .long("max-borrowed-value")
+                        .validator(is_parsable::<u64>)
+                        .value_name("INTEGER")
+                        .takes_value(true)
+                        .required(false)
+                        .help("Maximum borrowed value in the quote currency, 10 when not set"),
+                )
+                .arg(
+                    Arg::with_name("json")
+                        .long("json")
+                        .takes_value(false)
+                        .help("Print json instead of csv"),
+                )
+        )
         .subcommand(
             SubCommand::with_name("view-obligation")
                 .about("View obligation")
@@ -649,6 +718,36 @@ fn main() {
                         .default_value("Regular")
                         .help("Reserve type"),
                 )
+                .arg(
+                    Arg::with_name("deposit_soft_cap_pct")
+                        .long("deposit-soft-cap-pct")
+                        .validator(is_parsable::<u8>)
+                        .value_name("INTEGER_PERCENT")
+                        .takes_value(true)
+                        .required(false)
+                        .default_value("0")
+                        .help("Soft deposit cap for alerting, as a percentage of the deposit limit. 0 to disable"),
+                )
+                .arg(
+                    Arg::with_name("borrow_soft_cap_pct")
+                        .long("borrow-soft-cap-pct")
+                        .validator(is_parsable::<u8>)
+                        .value_name("INTEGER_PERCENT")
+                        .takes_value(true)
+                        .required(false)
+                        .default_value("0")
+                        .help("Soft borrow cap for alerting, as a percentage of the borrow limit. 0 to disable"),
+                )
+                .arg(
+                    Arg::with_name("idle_liquidity_fee_bps")
+                        .long("idle-liquidity-fee-bps")
+                        .validator(is_parsable::<u64>)
+                        .value_name("INTEGER")
+                        .takes_value(true)
+                        .required(false)
+                        .default_value("0")
+                        .help("Annual fee charged on idle liquidity, in basis points"),
+                )
         )
         .subcommand(
             SubCommand::with_name("set-lending-market-owner-and-config")
@@ -715,6 +814,15 @@ fn main() {
                         .required(false)
                         .help("Risk authority address"),
                 )
+                .arg(
+                    Arg::with_name("slots_per_year")
+                        .long("slots-per-year")
+                        .validator(is_parsable::<u64>)
+                        .value_name("INTEGER")
+                        .takes_value(true)
+                        .required(false)
+                        .help("Slots per year used to compound interest"),
+                )
         )
         .subcommand(
             SubCommand::with_name("update-reserve")
@@ -982,6 +1090,126 @@ fn main() {
                         .default_value("Regular")
                         .help("Reserve type"),
                 )
+                .arg(
+                    Arg::with_name("deposit_soft_cap_pct")
+                        .long("deposit-soft-cap-pct")
+                        .validator(is_parsable::<u8>)
+                        .value_name("INTEGER_PERCENT")
+                        .takes_value(true)
+                        .required(false)
+                        .help("Soft deposit cap for alerting, as a percentage of the deposit limit"),
+                )
+                .arg(
+                    Arg::with_name("borrow_soft_cap_pct")
+                        .long("borrow-soft-cap-pct")
+                        .validator(is_parsable::<u8>)
+                        .value_name("INTEGER_PERCENT")
+                        .takes_value(true)
+                        .required(false)
+                        .help("Soft borrow cap for alerting, as a percentage of the borrow limit"),
+                )
+                .arg(
+                    Arg::with_name("idle_liquidity_fee_bps")
+                        .long("idle-liquidity-fee-bps")
+                        .validator(is_parsable::<u64>)
+                        .value_name("INTEGER")
+                        .takes_value(true)
+                        .required(false)
+                        .help("Annual fee charged on idle liquidity, in basis points"),
+                )
+                .arg(
+                    Arg::with_name("liquidation_fee_receiver")
+                        .long("liquidation-fee-receiver")
+                        .validator(is_pubkey)
+                        .value_name("PUBKEY")
+                        .takes_value(true)
+                        .required(false)
+                        .help("Liquidation fee receiver address, 11111111111111111111111111111111 to use the fee receiver"),
+                )
+                .arg(
+                    Arg::with_name("borrow_utilization_cap")
+                        .long("borrow-utilization-cap")
+                        .validator(is_parsable::<u8>)
+                        .value_name("INTEGER_PERCENT")
+                        .takes_value(true)
+                        .required(false)
+                        .help("Utilization above which borrows are rejected, 0 to disable"),
+                )
+                .arg(
+                    Arg::with_name("fixed_borrow_rate_bps")
+                        .long("fixed-borrow-rate-bps")
+                        .validator(is_parsable::<u16>)
+                        .value_name("INTEGER_BPS")
+                        .takes_value(true)
+                        .required(false)
+                        .help("Annual rate locked by fixed rate borrows, 0 to disable them"),
+                )
+                .arg(
+                    Arg::with_name("fixed_borrow_max_duration_slots")
+                        .long("fixed-borrow-max-duration-slots")
+                        .validator(is_parsable::<u64>)
+                        .value_name("INTEGER_SLOTS")
+                        .takes_value(true)
+                        .required(false)
+                        .help("Slots a fixed rate borrow keeps its rate for, 0 to disable fixed rate borrows"),
+                )
+                .arg(
+                    Arg::with_name("withdraw_fee_bps")
+                        .long("withdraw-fee-bps")
+                        .validator(is_parsable::<u16>)
+                        .value_name("INTEGER_BPS")
+                        .takes_value(true)
+                        .required(false)
+                        .help("Fee charged on redeemed liquidity, paid to the fee receiver"),
+                )
+                .arg(
+                    Arg::with_name("min_price_bps")
+                        .long("min-price-bps")
+                        .validator(is_parsable::<u32>)
+                        .value_name("INTEGER_BPS")
+                        .takes_value(true)
+                        .required(false)
+                        .help("Lowest plausible oracle price in bps of the quote currency, 0 to disable"),
+                )
+                .arg(
+                    Arg::with_name("max_price_bps")
+                        .long("max-price-bps")
+                        .validator(is_parsable::<u32>)
+                        .value_name("INTEGER_BPS")
+                        .takes_value(true)
+                        .required(false)
+                        .help("Highest plausible oracle price in bps of the quote currency, 0 to disable"),
+                )
+                .arg(
+                    Arg::with_name("price_bounds_mode")
+                        .long("price-bounds-mode")
+                        .validator(is_parsable::<PriceBoundsMode>)
+                        .value_name("PRICE_BOUNDS_MODE")
+                        .takes_value(true)
+                        .required(false)
+                        .help("Reject or Clamp oracle prices outside of the price bounds"),
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("apply")
+                .about("Converge a lending market to a TOML or JSON market config file")
+                .arg(
+                    Arg::with_name("file")
+                        .long("file")
+                        .value_name("PATH")
+                        .takes_value(true)
+                        .required(true)
+                        .help("Market config, read as TOML if it ends with .toml and JSON otherwise"),
+                )
+                .arg(
+                    Arg::with_name("lending_market_owner")
+                        .long("market-owner")
+                        .validator(is_keypair)
+                        .value_name("KEYPAIR")
+                        .takes_value(true)
+                        .required(true)
+                        .help("Current owner of the lending market, which seeds new reserves from its token accounts"),
+                )
         )
         .get_matches();
 
@@ -1065,6 +1293,20 @@ fn main() {
 
             Ok(())
         }
+        ("stale-positions", Some(arg_matches)) => {
+            let defaults = StaleThresholds::default();
+            let thresholds = StaleThresholds {
+                min_idle_slots: value_of(arg_matches, "min_idle_slots")
+                    .unwrap_or(defaults.min_idle_slots),
+                max_deposited_value: value_of::<u64>(arg_matches, "max_deposited_value")
+                    .map_or(defaults.max_deposited_value, Decimal::from),
+                max_borrowed_value: value_of::<u64>(arg_matches, "max_borrowed_value")
+                    .map_or(defaults.max_borrowed_value, Decimal::from),
+            };
+            let json = arg_matches.is_present("json");
+
+            command_stale_positions(&config, &thresholds, json)
+        }
         ("create-market", Some(arg_matches)) => {
             let lending_market_owner = pubkey_of(arg_matches, "lending_market_owner").unwrap();
             let quote_currency = quote_currency_of(arg_matches, "quote_currency").unwrap();
@@ -1141,11 +1383,14 @@ fn main() {
 
             let added_borrow_weight_bps = value_of(arg_matches, "added_borrow_weight_bps").unwrap();
             let reserve_type = value_of(arg_matches, "reserve_type").unwrap();
+            let deposit_soft_cap_pct = value_of(arg_matches, "deposit_soft_cap_pct").unwrap();
+            let borrow_soft_cap_pct = value_of(arg_matches, "borrow_soft_cap_pct").unwrap();
+            let idle_liquidity_fee_bps = value_of(arg_matches, "idle_liquidity_fee_bps").unwrap();
 
             let borrow_fee_wad = (borrow_fee * WAD as f64) as u64;
             let flash_loan_fee_wad = (flash_loan_fee * WAD as f64) as u64;
 
-            let liquidity_fee_receiver_keypair = Keypair::new();
+            let keypairs = AddReserveKeypairs::new();
             let protocol_liquidation_fee =
                 value_of(arg_matches, "protocol_liquidation_fee").unwrap();
             let protocol_take_rate = value_of(arg_matches, "protocol_take_rate").unwrap();
@@ -1189,11 +1434,22 @@ fn main() {
                     },
                     deposit_limit,
                     borrow_limit,
-                    fee_receiver: liquidity_fee_receiver_keypair.pubkey(),
+                    fee_receiver: keypairs.liquidity_fee_receiver.pubkey(),
                     protocol_liquidation_fee,
                     protocol_take_rate,
                     added_borrow_weight_bps,
                     reserve_type,
+                    deposit_soft_cap_pct,
+                    borrow_soft_cap_pct,
+                    idle_liquidity_fee_bps,
+                    liquidation_fee_receiver: None,
+                    borrow_utilization_cap: 0,
+                    fixed_borrow_rate_bps: 0,
+                    fixed_borrow_max_duration_slots: 0,
+                    withdraw_fee_bps: 0,
+                    min_price_bps: 0,
+                    max_price_bps: 0,
+                    price_bounds_mode: PriceBoundsMode::Reject,
                 },
                 source_liquidity_pubkey,
                 source_liquidity_owner_keypair,
@@ -1202,7 +1458,7 @@ fn main() {
                 pyth_product_pubkey,
                 pyth_price_pubkey,
                 switchboard_feed_pubkey,
-                liquidity_fee_receiver_keypair,
+                keypairs,
                 source_liquidity,
             )
         }
@@ -1217,6 +1473,7 @@ fn main() {
             let rate_limiter_max_outflow = value_of(arg_matches, "rate_limiter_max_outflow");
             let whitelisted_liquidator_pubkey = pubkey_of(arg_matches, "whitelisted_liquidator");
             let risk_authority_pubkey = pubkey_of(arg_matches, "risk_authority").unwrap();
+            let slots_per_year = value_of(arg_matches, "slots_per_year");
             command_set_lending_market_owner_and_config(
                 &mut config,
                 lending_market_pubkey,
@@ -1226,6 +1483,7 @@ fn main() {
                 rate_limiter_max_outflow,
                 whitelisted_liquidator_pubkey,
                 risk_authority_pubkey,
+                slots_per_year,
             )
         }
         ("update-reserve", Some(arg_matches)) => {
@@ -1260,6 +1518,18 @@ fn main() {
             let rate_limiter_max_outflow = value_of(arg_matches, "rate_limiter_max_outflow");
             let added_borrow_weight_bps = value_of(arg_matches, "added_borrow_weight_bps");
             let reserve_type = value_of(arg_matches, "reserve_type");
+            let deposit_soft_cap_pct = value_of(arg_matches, "deposit_soft_cap_pct");
+            let borrow_soft_cap_pct = value_of(arg_matches, "borrow_soft_cap_pct");
+            let idle_liquidity_fee_bps = value_of(arg_matches, "idle_liquidity_fee_bps");
+            let liquidation_fee_receiver = pubkey_of(arg_matches, "liquidation_fee_receiver");
+            let borrow_utilization_cap = value_of(arg_matches, "borrow_utilization_cap");
+            let fixed_borrow_rate_bps = value_of(arg_matches, "fixed_borrow_rate_bps");
+            let fixed_borrow_max_duration_slots =
+                value_of(arg_matches, "fixed_borrow_max_duration_slots");
+            let withdraw_fee_bps = value_of(arg_matches, "withdraw_fee_bps");
+            let min_price_bps = value_of(arg_matches, "min_price_bps");
+            let max_price_bps = value_of(arg_matches, "max_price_bps");
+            let price_bounds_mode = value_of(arg_matches, "price_bounds_mode");
 
             let borrow_fee_wad = borrow_fee.map(|fee| (fee * WAD as f64) as u64);
             let flash_loan_fee_wad = flash_loan_fee.map(|fee| (fee * WAD as f64) as u64);
@@ -1292,6 +1562,17 @@ fn main() {
                     rate_limiter_max_outflow,
                     added_borrow_weight_bps,
                     reserve_type,
+                    deposit_soft_cap_pct,
+                    borrow_soft_cap_pct,
+                    idle_liquidity_fee_bps,
+                    liquidation_fee_receiver,
+                    borrow_utilization_cap,
+                    fixed_borrow_rate_bps,
+                    fixed_borrow_max_duration_slots,
+                    withdraw_fee_bps,
+                    min_price_bps,
+                    max_price_bps,
+                    price_bounds_mode,
                 },
                 pyth_product_pubkey,
                 pyth_price_pubkey,
@@ -1301,6 +1582,13 @@ fn main() {
                 lending_market_owner_keypair,
             )
         }
+        ("apply", Some(arg_matches)) => {
+            let file = arg_matches.value_of("file").unwrap();
+            let lending_market_owner_keypair =
+                keypair_of(arg_matches, "lending_market_owner").unwrap();
+
+            command_apply(&config, file, lending_market_owner_keypair)
+        }
         _ => unreachable!(),
     }
     .map_err(|err| {
@@ -1400,7 +1688,7 @@ fn command_redeem_collateral(
     let transaction = Transaction::new(
         &vec![config.fee_payer.as_ref()],
         Message::new_with_blockhash(
-            &[redeem_reserve_collateral(
+            &[redeem_reserve_collateral_with_fee_receiver(
                 config.lending_program_id,
                 collateral_amount,
                 source_ata,
@@ -1410,6 +1698,7 @@ fn command_redeem_collateral(
                 redeem_reserve.liquidity.supply_pubkey,
                 redeem_reserve.lending_market,
                 config.fee_payer.pubkey(),
+                redeem_reserve.config.fee_receiver,
             )],
             Some(&config.fee_payer.pubkey()),
             &recent_blockhash,
@@ -1537,11 +1826,12 @@ fn command_liquidate_obligation(
 
     // refresh all reserves
     instructions.extend(reserves.iter().map(|(pubkey, reserve)| {
-        refresh_reserve(
+        refresh_reserve_with_lending_market(
             config.lending_program_id,
             *pubkey,
             reserve.liquidity.pyth_oracle_pubkey,
             reserve.liquidity.switchboard_oracle_pubkey,
+            reserve.lending_market,
         )
     }));
 
@@ -1564,7 +1854,7 @@ fn command_liquidate_obligation(
         withdraw_reserve_state.collateral.mint_pubkey,
         withdraw_reserve_state.collateral.supply_pubkey,
         withdraw_reserve_state.liquidity.supply_pubkey,
-        withdraw_reserve_state.config.fee_receiver,
+        withdraw_reserve_state.config.liquidation_fee_receiver(),
         obligation_pubkey,
         obligation_state.lending_market,
         config.fee_payer.pubkey(),
@@ -1598,204 +1888,79 @@ fn command_add_reserve(
     pyth_product_pubkey: Pubkey,
     pyth_price_pubkey: Pubkey,
     switchboard_feed_pubkey: Pubkey,
-    liquidity_fee_receiver_keypair: Keypair,
+    keypairs: AddReserveKeypairs,
     source_liquidity: Token,
 ) -> CommandResult {
-    let reserve_keypair = Keypair::new();
-    let collateral_mint_keypair = Keypair::new();
-    let collateral_supply_keypair = Keypair::new();
-    let liquidity_supply_keypair = Keypair::new();
-    let user_collateral_keypair = Keypair::new();
-    let user_transfer_authority_keypair = Keypair::new();
-
-    println!("Adding reserve {}", reserve_keypair.pubkey());
+    println!("Adding reserve {}", keypairs.reserve.pubkey());
     if config.verbose {
         println!(
             "Adding collateral mint {}",
-            collateral_mint_keypair.pubkey()
+            keypairs.collateral_mint.pubkey()
         );
         println!(
             "Adding collateral supply {}",
-            collateral_supply_keypair.pubkey()
+            keypairs.collateral_supply.pubkey()
         );
         println!(
             "Adding liquidity supply {}",
-            liquidity_supply_keypair.pubkey()
+            keypairs.liquidity_supply.pubkey()
         );
         println!(
             "Adding liquidity fee receiver {}",
-            liquidity_fee_receiver_keypair.pubkey()
+            keypairs.liquidity_fee_receiver.pubkey()
         );
         println!(
             "Adding user collateral {}",
-            user_collateral_keypair.pubkey()
-        );
-        println!(
-            "Adding user transfer authority {}",
-            user_transfer_authority_keypair.pubkey()
+            keypairs.user_collateral.pubkey()
         );
     }
 
-    let reserve_balance = config
-        .rpc_client
-        .get_minimum_balance_for_rent_exemption(Reserve::LEN)?;
-    let collateral_mint_balance = config
-        .rpc_client
-        .get_minimum_balance_for_rent_exemption(Mint::LEN)?;
-    let token_account_balance = config
-        .rpc_client
-        .get_minimum_balance_for_rent_exemption(Token::LEN)?;
-    let collateral_supply_balance = token_account_balance;
-    let user_collateral_balance = token_account_balance;
-    let liquidity_supply_balance = token_account_balance;
-    let liquidity_fee_receiver_balance = token_account_balance;
-
-    let total_balance = reserve_balance
-        + collateral_mint_balance
-        + collateral_supply_balance
-        + user_collateral_balance
-        + liquidity_supply_balance
-        + liquidity_fee_receiver_balance;
-    let recent_blockhash = config.rpc_client.get_latest_blockhash()?;
-
-    let message_1 = Message::new_with_blockhash(
-        &[
-            create_account(
-                &config.fee_payer.pubkey(),
-                &reserve_keypair.pubkey(),
-                reserve_balance,
-                Reserve::LEN as u64,
-                &config.lending_program_id,
-            ),
-            create_account(
-                &config.fee_payer.pubkey(),
-                &collateral_mint_keypair.pubkey(),
-                collateral_mint_balance,
-                Mint::LEN as u64,
-                &spl_token::id(),
-            ),
-            create_account(
-                &config.fee_payer.pubkey(),
-                &collateral_supply_keypair.pubkey(),
-                collateral_supply_balance,
-                Token::LEN as u64,
-                &spl_token::id(),
-            ),
-            create_account(
-                &config.fee_payer.pubkey(),
-                &user_collateral_keypair.pubkey(),
-                user_collateral_balance,
-                Token::LEN as u64,
-                &spl_token::id(),
-            ),
-        ],
-        Some(&config.fee_payer.pubkey()),
-        &recent_blockhash,
-    );
-
-    let message_2 = Message::new_with_blockhash(
-        &[
-            create_account(
-                &config.fee_payer.pubkey(),
-                &liquidity_supply_keypair.pubkey(),
-                liquidity_supply_balance,
-                Token::LEN as u64,
-                &spl_token::id(),
-            ),
-            create_account(
-                &config.fee_payer.pubkey(),
-                &liquidity_fee_receiver_keypair.pubkey(),
-                liquidity_fee_receiver_balance,
-                Token::LEN as u64,
-                &spl_token::id(),
-            ),
-        ],
-        Some(&config.fee_payer.pubkey()),
-        &recent_blockhash,
-    );
+    let rent = from_account::<Rent, _>(&config.rpc_client.get_account(&sysvar::rent::id())?)
+        .ok_or("Failed to deserialize the rent sysvar")?;
+    let add_reserve = AddReserveBuilder::new(
+        config.lending_program_id,
+        config.fee_payer.pubkey(),
+        lending_market_pubkey,
+        lending_market_owner_keypair.pubkey(),
+        source_liquidity.mint,
+        source_liquidity_pubkey,
+        source_liquidity_owner_keypair.pubkey(),
+        liquidity_amount,
+    )
+    .config(reserve_config)
+    .oracles(
+        pyth_product_pubkey,
+        pyth_price_pubkey,
+        switchboard_feed_pubkey,
+    )
+    .keypairs(keypairs)
+    .build(&rent);
+
+    let total_balance = rent.minimum_balance(Reserve::LEN)
+        + rent.minimum_balance(Mint::LEN)
+        + 4 * rent.minimum_balance(Token::LEN);
 
-    let message_3 = Message::new_with_blockhash(
+    let recent_blockhash = config.rpc_client.get_latest_blockhash()?;
+    let transactions = add_reserve.sign(
         &[
-            approve(
-                &spl_token::id(),
-                &source_liquidity_pubkey,
-                &user_transfer_authority_keypair.pubkey(),
-                &source_liquidity_owner_keypair.pubkey(),
-                &[],
-                liquidity_amount,
-            )
-            .unwrap(),
-            init_reserve(
-                config.lending_program_id,
-                liquidity_amount,
-                reserve_config,
-                source_liquidity_pubkey,
-                user_collateral_keypair.pubkey(),
-                reserve_keypair.pubkey(),
-                source_liquidity.mint,
-                liquidity_supply_keypair.pubkey(),
-                collateral_mint_keypair.pubkey(),
-                collateral_supply_keypair.pubkey(),
-                pyth_product_pubkey,
-                pyth_price_pubkey,
-                switchboard_feed_pubkey,
-                lending_market_pubkey,
-                lending_market_owner_keypair.pubkey(),
-                user_transfer_authority_keypair.pubkey(),
-            ),
-            revoke(
-                &spl_token::id(),
-                &source_liquidity_pubkey,
-                &source_liquidity_owner_keypair.pubkey(),
-                &[],
-            )
-            .unwrap(),
-        ],
-        Some(&config.fee_payer.pubkey()),
-        &recent_blockhash,
-    );
-
-    check_fee_payer_balance(
-        config,
-        total_balance
-            + config.rpc_client.get_fee_for_message(&message_1)?
-            + config.rpc_client.get_fee_for_message(&message_2)?
-            + config.rpc_client.get_fee_for_message(&message_3)?,
-    )?;
-
-    let transaction_1 = Transaction::new(
-        &vec![
-            config.fee_payer.as_ref(),
-            &reserve_keypair,
-            &collateral_mint_keypair,
-            &collateral_supply_keypair,
-            &user_collateral_keypair,
-        ],
-        message_1,
-        recent_blockhash,
-    );
-    send_transaction(config, transaction_1)?;
-    let transaction_2 = Transaction::new(
-        &vec![
             config.fee_payer.as_ref(),
-            &liquidity_supply_keypair,
-            &liquidity_fee_receiver_keypair,
-        ],
-        message_2,
-        recent_blockhash,
-    );
-    send_transaction(config, transaction_2)?;
-    let transaction_3 = Transaction::new(
-        &vec![
-            config.fee_payer.as_ref(),
-            &source_liquidity_owner_keypair,
             &lending_market_owner_keypair,
-            &user_transfer_authority_keypair,
+            &source_liquidity_owner_keypair,
         ],
-        message_3,
         recent_blockhash,
-    );
-    send_transaction(config, transaction_3)?;
+    )?;
+
+    let mut fees = 0;
+    for transaction in &transactions {
+        fees += config
+            .rpc_client
+            .get_fee_for_message(&transaction.message)?;
+    }
+    check_fee_payer_balance(config, total_balance + fees)?;
+
+    for transaction in transactions {
+        send_transaction(config, transaction)?;
+    }
     Ok(())
 }
 
@@ -1809,6 +1974,7 @@ fn command_set_lending_market_owner_and_config(
     rate_limiter_max_outflow: Option<u64>,
     whitelisted_liquidator_pubkey: Option<Pubkey>,
     risk_authority_pubkey: Pubkey,
+    slots_per_year: Option<u64>,
 ) -> CommandResult {
     let lending_market_info = config.rpc_client.get_account(&lending_market_pubkey)?;
     let lending_market = LendingMarket::unpack_from_slice(lending_market_info.data.borrow())?;
@@ -1816,7 +1982,7 @@ fn command_set_lending_market_owner_and_config(
 
     let recent_blockhash = config.rpc_client.get_latest_blockhash()?;
     let message = Message::new_with_blockhash(
-        &[set_lending_market_owner_and_config(
+        &[set_lending_market_owner_and_config_with_slots_per_year(
             config.lending_program_id,
             lending_market_pubkey,
             lending_market_owner_keypair.pubkey(),
@@ -1833,6 +1999,7 @@ fn command_set_lending_market_owner_and_config(
             },
             whitelisted_liquidator_pubkey,
             risk_authority_pubkey,
+            slots_per_year.unwrap_or(lending_market.slots_per_year),
         )],
         Some(&config.fee_payer.pubkey()),
         &recent_blockhash,
@@ -1862,6 +2029,7 @@ fn command_update_reserve(
     let reserve_info = config.rpc_client.get_account(&reserve_pubkey)?;
     let mut reserve = Reserve::unpack_from_slice(reserve_info.data.borrow())?;
     println!("Reserve: {:#?}", reserve);
+    let current_reserve = reserve.clone();
     let mut no_change = true;
     if reserve_config.optimal_utilization_rate.is_some()
         && reserve.config.optimal_utilization_rate
@@ -2178,6 +2346,160 @@ fn command_update_reserve(
         reserve.config.reserve_type = reserve_config.reserve_type.unwrap();
     }
 
+    if reserve_config.deposit_soft_cap_pct.is_some()
+        && reserve.config.deposit_soft_cap_pct != reserve_config.deposit_soft_cap_pct.unwrap()
+    {
+        no_change = false;
+        println!(
+            "Updating deposit_soft_cap_pct from {} to {}",
+            reserve.config.deposit_soft_cap_pct,
+            reserve_config.deposit_soft_cap_pct.unwrap(),
+        );
+        reserve.config.deposit_soft_cap_pct = reserve_config.deposit_soft_cap_pct.unwrap();
+    }
+
+    if reserve_config.borrow_soft_cap_pct.is_some()
+        && reserve.config.borrow_soft_cap_pct != reserve_config.borrow_soft_cap_pct.unwrap()
+    {
+        no_change = false;
+        println!(
+            "Updating borrow_soft_cap_pct from {} to {}",
+            reserve.config.borrow_soft_cap_pct,
+            reserve_config.borrow_soft_cap_pct.unwrap(),
+        );
+        reserve.config.borrow_soft_cap_pct = reserve_config.borrow_soft_cap_pct.unwrap();
+    }
+
+    if reserve_config.idle_liquidity_fee_bps.is_some()
+        && reserve.config.idle_liquidity_fee_bps != reserve_config.idle_liquidity_fee_bps.unwrap()
+    {
+        no_change = false;
+        println!(
+            "Updating idle_liquidity_fee_bps from {} to {}",
+            reserve.config.idle_liquidity_fee_bps,
+            reserve_config.idle_liquidity_fee_bps.unwrap(),
+        );
+        reserve.config.idle_liquidity_fee_bps = reserve_config.idle_liquidity_fee_bps.unwrap();
+    }
+
+    if let Some(liquidation_fee_receiver) = reserve_config.liquidation_fee_receiver {
+        let liquidation_fee_receiver = if liquidation_fee_receiver == Pubkey::default() {
+            None
+        } else {
+            Some(liquidation_fee_receiver)
+        };
+        if reserve.config.liquidation_fee_receiver != liquidation_fee_receiver {
+            no_change = false;
+            println!(
+                "Updating liquidation_fee_receiver from {:?} to {:?}",
+                reserve.config.liquidation_fee_receiver, liquidation_fee_receiver,
+            );
+            reserve.config.liquidation_fee_receiver = liquidation_fee_receiver;
+        }
+    }
+
+    if reserve_config.borrow_utilization_cap.is_some()
+        && reserve.config.borrow_utilization_cap != reserve_config.borrow_utilization_cap.unwrap()
+    {
+        no_change = false;
+        println!(
+            "Updating borrow_utilization_cap from {} to {}",
+            reserve.config.borrow_utilization_cap,
+            reserve_config.borrow_utilization_cap.unwrap(),
+        );
+        reserve.config.borrow_utilization_cap = reserve_config.borrow_utilization_cap.unwrap();
+    }
+
+    if reserve_config.fixed_borrow_rate_bps.is_some()
+        && reserve.config.fixed_borrow_rate_bps != reserve_config.fixed_borrow_rate_bps.unwrap()
+    {
+        no_change = false;
+        println!(
+            "Updating fixed_borrow_rate_bps from {} to {}",
+            reserve.config.fixed_borrow_rate_bps,
+            reserve_config.fixed_borrow_rate_bps.unwrap(),
+        );
+        reserve.config.fixed_borrow_rate_bps = reserve_config.fixed_borrow_rate_bps.unwrap();
+    }
+
+    if reserve_config.fixed_borrow_max_duration_slots.is_some()
+        && reserve.config.fixed_borrow_max_duration_slots
+            != reserve_config.fixed_borrow_max_duration_slots.unwrap()
+    {
+        no_change = false;
+        println!(
+            "Updating fixed_borrow_max_duration_slots from {} to {}",
+            reserve.config.fixed_borrow_max_duration_slots,
+            reserve_config.fixed_borrow_max_duration_slots.unwrap(),
+        );
+        reserve.config.fixed_borrow_max_duration_slots =
+            reserve_config.fixed_borrow_max_duration_slots.unwrap();
+    }
+
+    if reserve_config.withdraw_fee_bps.is_some()
+        && reserve.config.withdraw_fee_bps != reserve_config.withdraw_fee_bps.unwrap()
+    {
+        no_change = false;
+        println!(
+            "Updating withdraw_fee_bps from {} to {}",
+            reserve.config.withdraw_fee_bps,
+            reserve_config.withdraw_fee_bps.unwrap(),
+        );
+        reserve.config.withdraw_fee_bps = reserve_config.withdraw_fee_bps.unwrap();
+    }
+
+    if reserve_config.min_price_bps.is_some()
+        && reserve.config.min_price_bps != reserve_config.min_price_bps.unwrap()
+    {
+        no_change = false;
+        println!(
+            "Updating min_price_bps from {} to {}",
+            reserve.config.min_price_bps,
+            reserve_config.min_price_bps.unwrap(),
+        );
+        reserve.config.min_price_bps = reserve_config.min_price_bps.unwrap();
+    }
+
+    if reserve_config.max_price_bps.is_some()
+        && reserve.config.max_price_bps != reserve_config.max_price_bps.unwrap()
+    {
+        no_change = false;
+        println!(
+            "Updating max_price_bps from {} to {}",
+            reserve.config.max_price_bps,
+            reserve_config.max_price_bps.unwrap(),
+        );
+        reserve.config.max_price_bps = reserve_config.max_price_bps.unwrap();
+    }
+
+    if reserve_config.price_bounds_mode.is_some()
+        && reserve.config.price_bounds_mode != reserve_config.price_bounds_mode.unwrap()
+    {
+        no_change = false;
+        println!(
+            "Updating price_bounds_mode from {:?} to {:?}",
+            reserve.config.price_bounds_mode,
+            reserve_config.price_bounds_mode.unwrap(),
+        );
+        reserve.config.price_bounds_mode = reserve_config.price_bounds_mode.unwrap();
+    }
+
+    let rate_limiter_config = RateLimiterConfig {
+        window_duration: reserve_config
+            .rate_limiter_window_duration
+            .unwrap_or(reserve.rate_limiter.config.window_duration),
+        max_outflow: reserve_config
+            .rate_limiter_max_outflow
+            .unwrap_or(reserve.rate_limiter.config.max_outflow),
+    };
+    if rate_limiter_config != reserve.rate_limiter.config {
+        no_change = false;
+        println!(
+            "Updating rate limiter config from {:?} to {:?}",
+            reserve.rate_limiter.config, rate_limiter_config,
+        );
+    }
+
     if validate_reserve_config(reserve.config).is_err() {
         println!("Error: invalid reserve config");
         return Err("Error: invalid reserve config".into());
@@ -2188,16 +2510,21 @@ fn command_update_reserve(
         return Ok(());
     }
 
+    let slot = config.rpc_client.get_slot()?;
+    print_config_change_impact(&simulate_config_change(
+        &current_reserve,
+        &reserve.config,
+        rate_limiter_config,
+        slot,
+    )?);
+
     let recent_blockhash = config.rpc_client.get_latest_blockhash()?;
 
     let message = Message::new_with_blockhash(
         &[update_reserve_config(
             config.lending_program_id,
             reserve.config,
-            RateLimiterConfig {
-                window_duration: reserve.rate_limiter.config.window_duration,
-                max_outflow: reserve.rate_limiter.config.max_outflow,
-            },
+            rate_limiter_config,
             reserve_pubkey,
             lending_market_pubkey,
             lending_market_owner_keypair.pubkey(),
@@ -2219,8 +2546,206 @@ fn command_update_reserve(
     Ok(())
 }
 
+fn command_stale_positions(
+    config: &Config,
+    thresholds: &StaleThresholds,
+    json: bool,
+) -> CommandResult {
+    let accounts = get_snapshot_accounts(&config.lending_program_id, &config.rpc_client)?;
+    let slot = config.rpc_client.get_slot()?;
+    let positions = stale_positions(&accounts, slot, thresholds)?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&positions)?);
+    } else {
+        println!("{}", StalePosition::CSV_HEADER);
+        for position in &positions {
+            println!("{}", position.to_csv_row());
+        }
+    }
+    if config.verbose {
+        eprintln!(
+            "{} stale positions out of {} obligations at slot {}",
+            positions.len(),
+            accounts.obligations.len(),
+            slot
+        );
+    }
+    Ok(())
+}
+
+fn command_apply(
+    config: &Config,
+    file: &str,
+    lending_market_owner_keypair: Keypair,
+) -> CommandResult {
+    let contents = std::fs::read_to_string(file)?;
+    let market_config: MarketConfig = if file.ends_with(".toml") {
+        toml::from_str(&contents)?
+    } else {
+        serde_json::from_str(&contents)?
+    };
+
+    let state = fetch_market_state(
+        &config.rpc_client,
+        &config.lending_program_id,
+        &market_config.lending_market,
+    )?;
+    let plan = reconcile(
+        &config.lending_program_id,
+        &market_config,
+        &state,
+        &config.fee_payer.pubkey(),
+    )?;
+
+    for reserve in &plan.unmanaged_reserves {
+        println!("Reserve {} is not in the config, leaving it as is", reserve);
+    }
+    if plan.is_empty() {
+        println!(
+            "Lending market {} matches the config",
+            state.lending_market_pubkey
+        );
+        return Ok(());
+    }
+
+    let rent = from_account::<Rent, _>(&config.rpc_client.get_account(&sysvar::rent::id())?)
+        .ok_or("Failed to deserialize the rent sysvar")?;
+    let signers: [&dyn Signer; 2] = [config.fee_payer.as_ref(), &lending_market_owner_keypair];
+    for action in plan.actions {
+        let recent_blockhash = config.rpc_client.get_latest_blockhash()?;
+        let single = |instruction: Instruction| {
+            Transaction::new(
+                &signers,
+                Message::new(&[instruction], Some(&config.fee_payer.pubkey())),
+                recent_blockhash,
+            )
+        };
+        let transactions = match action {
+            MarketAction::UpdateMetadata { instruction } => {
+                println!("Updating lending market metadata");
+                vec![single(instruction)]
+            }
+            MarketAction::AddReserve {
+                symbol,
+                reserve,
+                builder,
+            } => {
+                println!("Adding reserve {} at {}", symbol, reserve);
+                builder.build(&rent).sign(&signers, recent_blockhash)?
+            }
+            MarketAction::UpdateReserve {
+                symbol,
+                reserve,
+                changes,
+                oracles,
+                instruction,
+            } => {
+                println!("Updating reserve {} at {}", symbol, reserve);
+                for change in &changes {
+                    print_config_change(change);
+                }
+                if let Some(oracles) = oracles {
+                    println!("  oracles: {:?}", oracles);
+                }
+                vec![single(instruction)]
+            }
+            MarketAction::SetLendingMarketConfig {
+                changes,
+                instruction,
+            } => {
+                println!("Updating lending market config");
+                for change in &changes {
+                    print_config_change(change);
+                }
+                vec![single(instruction)]
+            }
+        };
+
+        for transaction in transactions {
+            send_transaction(config, transaction)?;
+        }
+    }
+    Ok(())
+}
+
 // HELPERS
 
+fn print_config_change(change: &ConfigChange) {
+    match change {
+        ConfigChange::Owner { old, new } => println!("  owner: {} -> {}", old, new),
+        ConfigChange::RiskAuthority { old, new } => {
+            println!("  risk_authority: {} -> {}", old, new)
+        }
+        ConfigChange::WhitelistedLiquidator { old, new } => {
+            println!("  whitelisted_liquidator: {:?} -> {:?}", old, new)
+        }
+        ConfigChange::RateLimiter { old, new } => println!(
+            "  rate_limiter: {} slots, {} max outflow -> {} slots, {} max outflow",
+            old.window_duration, old.max_outflow, new.window_duration, new.max_outflow
+        ),
+        ConfigChange::SlotsPerYear { old, new } => {
+            println!("  slots_per_year: {} -> {}", old, new)
+        }
+        ConfigChange::ReserveConfig { field, old, new } => {
+            println!("  {}: {} -> {}", field, old, new)
+        }
+    }
+}
+
+fn print_config_change_impact(impact: &ConfigChangeImpact) {
+    println!(
+        "Projected impact at {} utilization:",
+        impact.utilization_rate
+    );
+    let rates = [
+        (
+            "borrow APR",
+            impact.before.borrow_apr,
+            impact.after.borrow_apr,
+        ),
+        (
+            "borrow APY",
+            impact.before.borrow_apy,
+            impact.after.borrow_apy,
+        ),
+        (
+            "supply APR",
+            impact.before.supply_apr,
+            impact.after.supply_apr,
+        ),
+        (
+            "supply APY",
+            impact.before.supply_apy,
+            impact.after.supply_apy,
+        ),
+    ];
+    for (name, before, after) in rates {
+        println!("  {}: {} -> {}", name, before, after);
+    }
+    println!(
+        "  yearly variable borrow interest: {} -> {}",
+        impact.before.variable_borrow_interest, impact.after.variable_borrow_interest
+    );
+    println!(
+        "  yearly depositor interest: {} -> {}",
+        impact.before.depositor_interest, impact.after.depositor_interest
+    );
+    println!(
+        "  remaining rate limiter outflow: {} -> {}{}",
+        impact.rate_limiter.remaining_outflow_before,
+        impact.rate_limiter.remaining_outflow_after,
+        if impact.rate_limiter.resets_window {
+            " (window resets)"
+        } else {
+            ""
+        }
+    );
+    for warning in &impact.warnings {
+        println!("  Warning: {:?}", warning);
+    }
+}
+
 fn check_fee_payer_balance(config: &Config, required_balance: u64) -> Result<(), Error> {
     let balance = config.rpc_client.get_balance(&config.fee_payer.pubkey())?;
     if balance < required_balance {
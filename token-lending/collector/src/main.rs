@@ -0,0 +1,210 @@
+//! Polls the reserves of Solend lending markets and records their TVL, utilization and rates as
+//! Prometheus metrics or SQLite rows.
+
+mod prometheus;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+
+use {
+    clap::{crate_description, crate_name, crate_version, value_t, App, Arg},
+    log::{error, info},
+    solana_clap_utils::input_validators::{is_parsable, is_pubkey, is_url},
+    solana_client::rpc_client::RpcClient,
+    solana_sdk::{commitment_config::CommitmentConfig, pubkey::Pubkey},
+    solend_sdk::market_metrics::{get_market_metrics, MarketMetrics},
+    std::{error::Error, fs, path::PathBuf, str::FromStr, thread, time::Duration},
+};
+
+struct Config {
+    rpc_client: RpcClient,
+    lending_program_id: Pubkey,
+    lending_markets: Vec<Pubkey>,
+    interval: Duration,
+    prometheus_file: Option<PathBuf>,
+    #[cfg(feature = "sqlite")]
+    sqlite: Option<sqlite::Database>,
+}
+
+fn main() {
+    solana_logger::setup_with_default("solana=info,solend_collector=info");
+
+    let default_lending_program_id: &str = &solend_sdk::solend_mainnet::id().to_string();
+
+    let app = App::new(crate_name!())
+        .about(crate_description!())
+        .version(crate_version!())
+        .arg({
+            let arg = Arg::with_name("config_file")
+                .short("C")
+                .long("config")
+                .value_name("PATH")
+                .takes_value(true)
+                .help("Configuration file to use");
+            if let Some(ref config_file) = *solana_cli_config::CONFIG_FILE {
+                arg.default_value(config_file)
+            } else {
+                arg
+            }
+        })
+        .arg(
+            Arg::with_name("json_rpc_url")
+                .long("url")
+                .value_name("URL")
+                .takes_value(true)
+                .validator(is_url)
+                .help("JSON RPC URL for the cluster.  Default from the configuration file."),
+        )
+        .arg(
+            Arg::with_name("lending_program_id")
+                .long("program")
+                .validator(is_pubkey)
+                .value_name("PUBKEY")
+                .takes_value(true)
+                .required(true)
+                .default_value(default_lending_program_id)
+                .help("Lending program ID"),
+        )
+        .arg(
+            Arg::with_name("lending_market")
+                .long("market")
+                .validator(is_pubkey)
+                .value_name("PUBKEY")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .required(true)
+                .help("Lending market to collect, can be repeated"),
+        )
+        .arg(
+            Arg::with_name("interval")
+                .long("interval")
+                .validator(is_parsable::<u64>)
+                .value_name("SECONDS")
+                .takes_value(true)
+                .default_value("60")
+                .help("Seconds between polls"),
+        )
+        .arg(
+            Arg::with_name("prometheus_file")
+                .long("prometheus-file")
+                .value_name("PATH")
+                .takes_value(true)
+                .help(
+                    "File to write the latest metrics to in the Prometheus text format, \
+                    e.g. in the node exporter's textfile collector directory. \
+                    Printed to stdout if no output is given.",
+                ),
+        );
+    #[cfg(feature = "sqlite")]
+    let app = app.arg(
+        Arg::with_name("sqlite")
+            .long("sqlite")
+            .value_name("PATH")
+            .takes_value(true)
+            .help("SQLite database to append every poll to, created if it doesn't exist"),
+    );
+    let matches = app.get_matches();
+
+    let config = {
+        let cli_config = if let Some(config_file) = matches.value_of("config_file") {
+            solana_cli_config::Config::load(config_file).unwrap_or_default()
+        } else {
+            solana_cli_config::Config::default()
+        };
+        let json_rpc_url = value_t!(matches, "json_rpc_url", String)
+            .unwrap_or_else(|_| cli_config.json_rpc_url.clone());
+
+        let lending_program_id =
+            Pubkey::from_str(matches.value_of("lending_program_id").unwrap()).unwrap();
+        let lending_markets = matches
+            .values_of("lending_market")
+            .unwrap()
+            .map(|market| Pubkey::from_str(market).unwrap())
+            .collect();
+        let interval = Duration::from_secs(value_t!(matches, "interval", u64).unwrap());
+
+        Config {
+            rpc_client: RpcClient::new_with_commitment(json_rpc_url, CommitmentConfig::confirmed()),
+            lending_program_id,
+            lending_markets,
+            interval,
+            prometheus_file: matches.value_of("prometheus_file").map(PathBuf::from),
+            #[cfg(feature = "sqlite")]
+            sqlite: matches.value_of("sqlite").map(|path| {
+                sqlite::Database::open(path).unwrap_or_else(|e| {
+                    eprintln!("error: {}", e);
+                    std::process::exit(1);
+                })
+            }),
+        }
+    };
+
+    loop {
+        let markets = collect(&config);
+        if let Err(e) = record(&config, &markets) {
+            error!("failed to record metrics: {}", e);
+        }
+        thread::sleep(config.interval);
+    }
+}
+
+/// Fetch the metrics of every configured market, skipping the ones that fail until the next poll
+fn collect(config: &Config) -> Vec<MarketMetrics> {
+    config
+        .lending_markets
+        .iter()
+        .filter_map(|lending_market| {
+            match get_market_metrics(
+                &config.rpc_client,
+                &config.lending_program_id,
+                lending_market,
+            ) {
+                Ok(metrics) => {
+                    info!(
+                        "collected {} reserves of {} at slot {}",
+                        metrics.reserves.len(),
+                        lending_market,
+                        metrics.slot
+                    );
+                    Some(metrics)
+                }
+                Err(e) => {
+                    error!("failed to collect {}: {}", lending_market, e);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+fn record(config: &Config, markets: &[MarketMetrics]) -> Result<(), Box<dyn Error>> {
+    #[cfg(feature = "sqlite")]
+    if let Some(database) = &config.sqlite {
+        database.insert(markets)?;
+    }
+
+    let text = prometheus::render(markets)?;
+    match &config.prometheus_file {
+        Some(path) => {
+            // written next to the file and renamed over it, so scrapes never read a partial file
+            let tmp_path = path.with_extension("prom.tmp");
+            fs::write(&tmp_path, text)?;
+            fs::rename(&tmp_path, path)?;
+        }
+        None if !config.has_sqlite() => print!("{}", text),
+        None => {}
+    }
+    Ok(())
+}
+
+impl Config {
+    #[cfg(feature = "sqlite")]
+    fn has_sqlite(&self) -> bool {
+        self.sqlite.is_some()
+    }
+
+    #[cfg(not(feature = "sqlite"))]
+    fn has_sqlite(&self) -> bool {
+        false
+    }
+}
@@ -0,0 +1,135 @@
+//! Prometheus text format of the collected metrics
+
+use {
+    solana_sdk::program_error::ProgramError,
+    solend_sdk::market_metrics::{MarketMetrics, ReserveMetrics},
+    std::fmt::{Display, Write},
+};
+
+type MarketGauge = (
+    &'static str,
+    &'static str,
+    fn(&MarketMetrics) -> Result<String, ProgramError>,
+);
+type ReserveGauge = (&'static str, &'static str, fn(&ReserveMetrics) -> String);
+
+const MARKET_GAUGES: &[MarketGauge] = &[
+    (
+        "solend_market_slot",
+        "Slot the market was measured at",
+        |m| Ok(m.slot.to_string()),
+    ),
+    (
+        "solend_market_supplied_value",
+        "Market value supplied to every reserve of the market, in USD",
+        |m| Ok(m.supplied_value()?.to_string()),
+    ),
+    (
+        "solend_market_borrowed_value",
+        "Market value borrowed from every reserve of the market, in USD",
+        |m| Ok(m.borrowed_value()?.to_string()),
+    ),
+    (
+        "solend_market_utilization_rate",
+        "Share of the market's supplied value that is borrowed",
+        |m| Ok(m.utilization_rate()?.to_string()),
+    ),
+];
+
+const RESERVE_GAUGES: &[ReserveGauge] = &[
+    (
+        "solend_reserve_total_supply",
+        "Liquidity supplied to the reserve, borrows included, in native units",
+        |r| r.total_supply.to_string(),
+    ),
+    (
+        "solend_reserve_borrowed_amount",
+        "Liquidity borrowed from the reserve, in native units",
+        |r| r.borrowed_amount.to_string(),
+    ),
+    (
+        "solend_reserve_supplied_value",
+        "Market value supplied to the reserve, in USD",
+        |r| r.supplied_value.to_string(),
+    ),
+    (
+        "solend_reserve_borrowed_value",
+        "Market value borrowed from the reserve, in USD",
+        |r| r.borrowed_value.to_string(),
+    ),
+    (
+        "solend_reserve_utilization_rate",
+        "Share of the reserve's supply that is borrowed",
+        |r| r.utilization_rate.to_string(),
+    ),
+    (
+        "solend_reserve_borrow_apy",
+        "Variable borrow APY of the reserve",
+        |r| r.borrow_apy.to_string(),
+    ),
+    (
+        "solend_reserve_supply_apy",
+        "Depositor APY of the reserve, net of protocol fees",
+        |r| r.supply_apy.to_string(),
+    ),
+    (
+        "solend_reserve_stale_price",
+        "1 if the reserve's oracles had no usable price and its last price was used",
+        |r| (r.stale_price as u8).to_string(),
+    ),
+];
+
+/// Render the metrics of every market as gauges, labelled by market and reserve
+pub fn render(markets: &[MarketMetrics]) -> Result<String, ProgramError> {
+    let mut text = String::new();
+    for (name, help, value) in MARKET_GAUGES {
+        write_header(&mut text, name, help);
+        for market in markets {
+            write_sample(
+                &mut text,
+                name,
+                &[("market", &market.lending_market)],
+                value(market)?,
+            );
+        }
+    }
+    for (name, help, value) in RESERVE_GAUGES {
+        write_header(&mut text, name, help);
+        for market in markets {
+            for reserve in &market.reserves {
+                write_sample(
+                    &mut text,
+                    name,
+                    &[
+                        ("market", &market.lending_market),
+                        ("reserve", &reserve.reserve),
+                        ("symbol", &reserve.symbol),
+                    ],
+                    value(reserve),
+                );
+            }
+        }
+    }
+    Ok(text)
+}
+
+fn write_header(text: &mut String, name: &str, help: &str) {
+    writeln!(text, "# HELP {} {}", name, help).unwrap();
+    writeln!(text, "# TYPE {} gauge", name).unwrap();
+}
+
+fn write_sample(text: &mut String, name: &str, labels: &[(&str, &dyn Display)], value: String) {
+    let labels = labels
+        .iter()
+        .map(|(label, value)| format!("{}=\"{}\"", label, escape(&value.to_string())))
+        .collect::<Vec<_>>()
+        .join(",");
+    writeln!(text, "{}{{{}}} {}", name, labels, value).unwrap();
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
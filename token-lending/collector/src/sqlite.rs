@@ -0,0 +1,82 @@
+//! SQLite history of the collected metrics, one row per reserve per poll
+
+use {
+    rusqlite::{params, Connection},
+    solend_sdk::market_metrics::MarketMetrics,
+    std::{
+        error::Error,
+        time::{SystemTime, UNIX_EPOCH},
+    },
+};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS reserve_metrics (
+    timestamp INTEGER NOT NULL,
+    slot INTEGER NOT NULL,
+    market TEXT NOT NULL,
+    reserve TEXT NOT NULL,
+    symbol TEXT NOT NULL,
+    mint TEXT NOT NULL,
+    total_supply REAL NOT NULL,
+    borrowed_amount REAL NOT NULL,
+    supplied_value REAL NOT NULL,
+    borrowed_value REAL NOT NULL,
+    utilization_rate REAL NOT NULL,
+    borrow_apy REAL NOT NULL,
+    supply_apy REAL NOT NULL,
+    stale_price INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS reserve_metrics_reserve_timestamp
+    ON reserve_metrics (reserve, timestamp);
+";
+
+pub struct Database {
+    connection: Connection,
+}
+
+impl Database {
+    /// Open the database, creating it and its table if they don't exist
+    pub fn open(path: &str) -> Result<Self, Box<dyn Error>> {
+        let connection = Connection::open(path)?;
+        connection.execute_batch(SCHEMA)?;
+        Ok(Self { connection })
+    }
+
+    /// Append the metrics of every reserve of the markets, stamped with the current time
+    pub fn insert(&self, markets: &[MarketMetrics]) -> Result<(), Box<dyn Error>> {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+        let transaction = self.connection.unchecked_transaction()?;
+        {
+            let mut statement = transaction.prepare(
+                "INSERT INTO reserve_metrics VALUES \
+                (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+            )?;
+            for market in markets {
+                for reserve in &market.reserves {
+                    statement.execute(params![
+                        timestamp,
+                        market.slot as i64,
+                        market.lending_market.to_string(),
+                        reserve.reserve.to_string(),
+                        reserve.symbol,
+                        reserve.mint.to_string(),
+                        real(&reserve.total_supply)?,
+                        real(&reserve.borrowed_amount)?,
+                        real(&reserve.supplied_value)?,
+                        real(&reserve.borrowed_value)?,
+                        real(&reserve.utilization_rate)?,
+                        real(&reserve.borrow_apy)?,
+                        real(&reserve.supply_apy)?,
+                        reserve.stale_price,
+                    ])?;
+                }
+            }
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+}
+
+fn real(value: &dyn ToString) -> Result<f64, Box<dyn Error>> {
+    Ok(value.to_string().parse()?)
+}
@@ -0,0 +1,133 @@
+//! Instruction types
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+};
+
+/// Instructions supported by the mock oracle program
+#[derive(Clone, Debug, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub enum MockOracleInstruction {
+    // 0
+    /// Initializes a pyth price account and its product account, without a price
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[writable]` Price account - uninitialized, PYTH_PRICE_ACCOUNT_LEN bytes.
+    ///   1. `[writable]` Product account - uninitialized, PYTH_PRODUCT_ACCOUNT_LEN bytes.
+    InitPyth {
+        /// Quote currency of the product, padded with zeroes
+        quote_currency: [u8; 32],
+    },
+
+    // 1
+    /// Sets the price of a pyth price account, published at the current slot
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[writable]` Price account.
+    SetPythPrice {
+        /// Aggregate price
+        price: i64,
+        /// Confidence interval of the aggregate price
+        conf: u64,
+        /// Exponent of the prices and confidence intervals
+        expo: i32,
+        /// Exponential moving average price
+        ema_price: i64,
+        /// Confidence interval of the exponential moving average price
+        ema_conf: u64,
+    },
+
+    // 2
+    /// Initializes a switchboard v2 aggregator account, without a price
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[writable]` Aggregator account - uninitialized, SWITCHBOARD_FEED_LEN bytes.
+    InitSwitchboard,
+
+    // 3
+    /// Sets the latest confirmed result of a switchboard v2 aggregator account, opened at the
+    /// current slot
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[writable]` Aggregator account.
+    SetSwitchboardPrice {
+        /// Mantissa of the result
+        price: i64,
+        /// Number of decimal places of the result
+        expo: i32,
+    },
+}
+
+/// Creates an 'InitPyth' instruction.
+pub fn init_pyth(
+    program_id: Pubkey,
+    price_pubkey: Pubkey,
+    product_pubkey: Pubkey,
+    quote_currency: [u8; 32],
+) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(price_pubkey, false),
+            AccountMeta::new(product_pubkey, false),
+        ],
+        data: MockOracleInstruction::InitPyth { quote_currency }
+            .try_to_vec()
+            .unwrap(),
+    }
+}
+
+/// Creates a 'SetPythPrice' instruction.
+pub fn set_pyth_price(
+    program_id: Pubkey,
+    price_pubkey: Pubkey,
+    price: i64,
+    conf: u64,
+    expo: i32,
+    ema_price: i64,
+    ema_conf: u64,
+) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![AccountMeta::new(price_pubkey, false)],
+        data: MockOracleInstruction::SetPythPrice {
+            price,
+            conf,
+            expo,
+            ema_price,
+            ema_conf,
+        }
+        .try_to_vec()
+        .unwrap(),
+    }
+}
+
+/// Creates an 'InitSwitchboard' instruction.
+pub fn init_switchboard(program_id: Pubkey, switchboard_feed: Pubkey) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![AccountMeta::new(switchboard_feed, false)],
+        data: MockOracleInstruction::InitSwitchboard.try_to_vec().unwrap(),
+    }
+}
+
+/// Creates a 'SetSwitchboardPrice' instruction.
+pub fn set_switchboard_price(
+    program_id: Pubkey,
+    switchboard_feed: Pubkey,
+    price: i64,
+    expo: i32,
+) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![AccountMeta::new(switchboard_feed, false)],
+        data: MockOracleInstruction::SetSwitchboardPrice { price, expo }
+            .try_to_vec()
+            .unwrap(),
+    }
+}
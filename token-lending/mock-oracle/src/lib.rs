@@ -0,0 +1,44 @@
+#![deny(missing_docs)]
+
+//! A mock oracle program for program tests of Solend and the programs integrating with it.
+//!
+//! Solend only reads prices from accounts owned by the market's oracle programs, so tests can't
+//! just write a price into an account. Register this program under [id()] with
+//! `solana-program-test`, create feed accounts owned by it of [PYTH_PRICE_ACCOUNT_LEN],
+//! [PYTH_PRODUCT_ACCOUNT_LEN] and [SWITCHBOARD_FEED_LEN] bytes, and use the
+//! [instruction] builders to initialize them and set their prices:
+//!
+//! ```ignore
+//! let mut test = ProgramTest::default();
+//! test.add_program(
+//!     "solend_mock_oracle",
+//!     solend_mock_oracle::id(),
+//!     processor!(solend_mock_oracle::processor::process_instruction),
+//! );
+//! ```
+//!
+//! Lending markets using the mock feeds are initialized with [id()] as both their pyth and
+//! switchboard oracle program.
+
+pub mod entrypoint;
+pub mod instruction;
+pub mod processor;
+
+use pyth_sdk_solana::state::{PriceAccount, PROD_ACCT_SIZE};
+pub use solana_program;
+use switchboard_v2::AggregatorAccountData;
+
+solana_program::declare_id!("SW1TCH7qEPTdLsDHRgPuMQjbQxKdH2aBStViMFnt64f");
+
+/// Size of a pyth price account
+pub const PYTH_PRICE_ACCOUNT_LEN: usize = std::mem::size_of::<PriceAccount>();
+
+/// Size of a pyth product account
+pub const PYTH_PRODUCT_ACCOUNT_LEN: usize = PROD_ACCT_SIZE;
+
+/// Size of a switchboard v2 aggregator account, discriminator included
+pub const SWITCHBOARD_FEED_LEN: usize = 8 + std::mem::size_of::<AggregatorAccountData>();
+
+/// Quote currency of USD prices, the usual quote currency of lending markets
+pub const USD_QUOTE_CURRENCY: [u8; 32] =
+    *b"USD\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0";
@@ -0,0 +1,167 @@
+//! Program state processor
+
+use crate::instruction::MockOracleInstruction;
+use borsh::BorshDeserialize;
+use pyth_sdk_solana::state::{
+    AccountType, PriceAccount, PriceStatus, ProductAccount, Rational, MAGIC, PROD_ACCT_SIZE,
+    PROD_ATTR_SIZE, VERSION_2,
+};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    clock::Clock,
+    entrypoint::ProgramResult,
+    msg,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    sysvar::Sysvar,
+};
+use std::{cell::RefMut, mem::size_of};
+use switchboard_v2::{AggregatorAccountData, SwitchboardDecimal};
+
+/// Anchor discriminator of switchboard v2 aggregator accounts
+const AGGREGATOR_DISCRIMINATOR: [u8; 8] = [217, 230, 65, 101, 201, 162, 27, 125];
+
+/// Processes an instruction
+pub fn process_instruction(
+    _program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    input: &[u8],
+) -> ProgramResult {
+    let instruction = MockOracleInstruction::try_from_slice(input)
+        .map_err(|_| ProgramError::InvalidInstructionData)?;
+    match instruction {
+        MockOracleInstruction::InitPyth { quote_currency } => {
+            msg!("Instruction: Init Pyth");
+            process_init_pyth(accounts, quote_currency)
+        }
+        MockOracleInstruction::SetPythPrice {
+            price,
+            conf,
+            expo,
+            ema_price,
+            ema_conf,
+        } => {
+            msg!("Instruction: Set Pyth Price");
+            process_set_pyth_price(accounts, price, conf, expo, ema_price, ema_conf)
+        }
+        MockOracleInstruction::InitSwitchboard => {
+            msg!("Instruction: Init Switchboard");
+            process_init_switchboard(accounts)
+        }
+        MockOracleInstruction::SetSwitchboardPrice { price, expo } => {
+            msg!("Instruction: Set Switchboard Price");
+            process_set_switchboard_price(accounts, price, expo)
+        }
+    }
+}
+
+fn process_init_pyth(accounts: &[AccountInfo], quote_currency: [u8; 32]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let price_account_info = next_account_info(account_info_iter)?;
+    let product_account_info = next_account_info(account_info_iter)?;
+
+    let price_account = PriceAccount {
+        magic: MAGIC,
+        ver: VERSION_2,
+        atype: AccountType::Price as u32,
+        size: 240, // PC_PRICE_T_COMP_OFFSET from pyth_client repo
+        ..PriceAccount::default()
+    };
+    price_account_info
+        .try_borrow_mut_data()?
+        .copy_from_slice(bytemuck::bytes_of(&price_account));
+
+    // the quote_currency attribute, as a pair of length-prefixed strings
+    let mut attr = [0; PROD_ATTR_SIZE];
+    let key = b"quote_currency";
+    let value = &quote_currency[..quote_currency
+        .iter()
+        .rposition(|b| *b != 0)
+        .map_or(0, |i| i + 1)];
+    attr[0] = key.len() as u8;
+    attr[1..1 + key.len()].copy_from_slice(key);
+    attr[1 + key.len()] = value.len() as u8;
+    attr[2 + key.len()..2 + key.len() + value.len()].copy_from_slice(value);
+
+    let product_account = ProductAccount {
+        magic: MAGIC,
+        ver: VERSION_2,
+        atype: AccountType::Product as u32,
+        size: PROD_ACCT_SIZE as u32,
+        px_acc: *price_account_info.key,
+        attr,
+    };
+    product_account_info
+        .try_borrow_mut_data()?
+        .copy_from_slice(bytemuck::bytes_of(&product_account));
+
+    Ok(())
+}
+
+fn process_set_pyth_price(
+    accounts: &[AccountInfo],
+    price: i64,
+    conf: u64,
+    expo: i32,
+    ema_price: i64,
+    ema_conf: u64,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let price_account_info = next_account_info(account_info_iter)?;
+    let mut data = price_account_info.try_borrow_mut_data()?;
+    let price_account: &mut PriceAccount = data
+        .get_mut(..size_of::<PriceAccount>())
+        .and_then(|data| bytemuck::try_from_bytes_mut(data).ok())
+        .ok_or(ProgramError::InvalidAccountData)?;
+
+    let slot = Clock::get()?.slot;
+    price_account.agg.price = price;
+    price_account.agg.conf = conf;
+    price_account.agg.pub_slot = slot;
+    price_account.agg.status = PriceStatus::Trading;
+    price_account.expo = expo;
+    price_account.last_slot = slot;
+    // only the values of the moving averages are read
+    price_account.ema_price = Rational {
+        val: ema_price,
+        numer: 1,
+        denom: 1,
+    };
+    price_account.ema_conf = Rational {
+        val: ema_conf as i64,
+        numer: 1,
+        denom: 1,
+    };
+
+    Ok(())
+}
+
+fn process_init_switchboard(accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let switchboard_feed_info = next_account_info(account_info_iter)?;
+    switchboard_feed_info.try_borrow_mut_data()?[0..8].copy_from_slice(&AGGREGATOR_DISCRIMINATOR);
+    Ok(())
+}
+
+fn process_set_switchboard_price(accounts: &[AccountInfo], price: i64, expo: i32) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let switchboard_feed_info = next_account_info(account_info_iter)?;
+    let data = switchboard_feed_info.try_borrow_mut_data()?;
+    if data.len() < 8 + size_of::<AggregatorAccountData>() {
+        msg!("Switchboard feed account is too small");
+        return Err(ProgramError::AccountDataTooSmall);
+    }
+
+    let mut aggregator: RefMut<AggregatorAccountData> = RefMut::map(data, |data| {
+        bytemuck::from_bytes_mut(&mut data[8..8 + size_of::<AggregatorAccountData>()])
+    });
+    aggregator.min_oracle_results = 1;
+    aggregator.latest_confirmed_round.num_success = 1;
+    aggregator.latest_confirmed_round.result = SwitchboardDecimal {
+        mantissa: price as i128,
+        scale: expo as u32,
+    };
+    aggregator.latest_confirmed_round.round_open_slot = Clock::get()?.slot;
+
+    Ok(())
+}
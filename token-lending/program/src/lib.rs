@@ -4,7 +4,9 @@
 
 pub mod entrypoint;
 pub mod processor;
-pub use solend_sdk::{error, instruction, math, oracles, state};
+pub use solend_sdk::{
+    cpi, error, error_context, events, instruction, invariants, math, oracles, state,
+};
 
 // Export current sdk types for downstream users building with a different sdk version
 pub use solana_program;
@@ -3,14 +3,18 @@
 use crate::{
     self as solend_program,
     error::LendingError,
+    error_context::log_error_context,
+    events::{LendingEvent, ReserveLimitKind},
     instruction::LendingInstruction,
-    math::{Decimal, Rate, TryAdd, TryDiv, TryMul, TrySub},
+    math::{Bps, Decimal, Rate, TryAdd, TryDiv, TryMul, TrySub},
     oracles::get_pyth_price,
     state::{
-        validate_reserve_config, CalculateBorrowResult, CalculateLiquidationResult,
+        validate_borrow_grace_config, validate_flash_loan_config, validate_reserve_config,
+        BorrowAllowances, BorrowGraceConfig, CalculateBorrowResult, CalculateLiquidationResult,
         CalculateRepayResult, InitLendingMarketParams, InitObligationParams, InitReserveParams,
         LendingMarket, NewReserveCollateralParams, NewReserveLiquidityParams, Obligation, Reserve,
-        ReserveCollateral, ReserveConfig, ReserveLiquidity,
+        ReserveCollateral, ReserveConfig, ReserveLiquidity, BORROW_ALLOWANCES_SEED,
+        BORROW_GRACE_CONFIG_SEED, MAX_SLOTS_PER_YEAR, MIN_SLOTS_PER_YEAR,
     },
 };
 use bytemuck::bytes_of;
@@ -18,24 +22,37 @@ use pyth_sdk_solana::{self, state::ProductAccount};
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
     entrypoint::ProgramResult,
-    instruction::{get_stack_height, Instruction, TRANSACTION_LEVEL_STACK_HEIGHT},
+    instruction::{get_stack_height, AccountMeta, Instruction, TRANSACTION_LEVEL_STACK_HEIGHT},
     msg,
-    program::{invoke, invoke_signed},
+    program::{invoke, invoke_signed, set_return_data},
     program_error::ProgramError,
     program_pack::{IsInitialized, Pack},
     pubkey::Pubkey,
-    system_instruction::create_account,
+    system_instruction::{allocate, assign, create_account, transfer},
     sysvar::instructions::{load_current_index_checked, load_instruction_at_checked},
     sysvar::{
+        self,
         clock::{self, Clock},
         rent::Rent,
         Sysvar,
     },
 };
-use solend_sdk::state::{LendingMarketMetadata, RateLimiter, RateLimiterConfig, ReserveType};
+use solend_sdk::state::{
+    check_policy, on_fees_redeemed, BorrowRateMode, ConfigTimelock, CrossMarketSources,
+    DeleverageConfig, DeleverageCredit, DepositMode, DerivedAccount, DustSweepConfig, FeeRebates,
+    FeeRedemptionHook, FlashLoanConfig, LendingMarketMetadata, LiquidationCircuitBreaker,
+    LiquidationStats, MarketPolicy, ObligationBorrowCap, ObligationHealth, ObligationLimits,
+    PendingConfig, PolicyAction, RateLimiter, RateLimiterConfig, ReserveAccrualHistory,
+    ReserveStatus, ReserveType, CONFIG_TIMELOCK_SEED, CROSS_MARKET_SOURCES_SEED,
+    DELEVERAGE_CONFIG_SEED, DELEVERAGE_CREDIT_SEED, DUST_SWEEP_CONFIG_SEED, FEE_REBATES_SEED,
+    FEE_REDEMPTION_HOOK_SEED, FLASH_LOAN_CONFIG_SEED, LIQUIDATION_CIRCUIT_BREAKER_SEED,
+    LIQUIDATION_STATS_SEED, LOCKED_INITIAL_COLLATERAL, MARKET_POLICY_SEED, MAX_MINT_DECIMALS,
+    OBLIGATION_BORROW_CAP_SEED, OBLIGATION_LIMITS_SEED, PENDING_CONFIG_SEED, PROGRAM_VERSION,
+    RESERVE_ACCRUAL_HISTORY_SEED,
+};
 use solend_sdk::{switchboard_v2_devnet, switchboard_v2_mainnet};
-use spl_token::state::Mint;
-use std::{cmp::min, result::Result};
+use spl_token::state::{Account as TokenAccount, Mint};
+use std::{cmp::min, iter::Peekable, result::Result, slice::Iter};
 use switchboard_program::{
     get_aggregator, get_aggregator_result, AggregatorState, RoundResult, SwitchboardAccountType,
 };
@@ -66,6 +83,7 @@ pub fn process_instruction(
             rate_limiter_config,
             whitelisted_liquidator,
             risk_authority,
+            slots_per_year,
         } => {
             msg!("Instruction: Set Lending Market Owner");
             process_set_lending_market_owner_and_config(
@@ -74,6 +92,7 @@ pub fn process_instruction(
                 rate_limiter_config,
                 whitelisted_liquidator,
                 risk_authority,
+                slots_per_year,
                 accounts,
             )
         }
@@ -114,7 +133,12 @@ pub fn process_instruction(
         }
         LendingInstruction::BorrowObligationLiquidity { liquidity_amount } => {
             msg!("Instruction: Borrow Obligation Liquidity");
-            process_borrow_obligation_liquidity(program_id, liquidity_amount, accounts)
+            process_borrow_obligation_liquidity(
+                program_id,
+                liquidity_amount,
+                BorrowRateMode::Variable,
+                accounts,
+            )
         }
         LendingInstruction::RepayObligationLiquidity { liquidity_amount } => {
             msg!("Instruction: Repay Obligation Liquidity");
@@ -135,6 +159,7 @@ pub fn process_instruction(
             process_deposit_reserve_liquidity_and_obligation_collateral(
                 program_id,
                 liquidity_amount,
+                Some(DepositMode::Collateralize),
                 accounts,
             )
         }
@@ -160,6 +185,7 @@ pub fn process_instruction(
             process_liquidate_obligation_and_redeem_reserve_collateral(
                 program_id,
                 liquidity_amount,
+                None,
                 accounts,
             )
         }
@@ -192,6 +218,201 @@ pub fn process_instruction(
             let metadata = LendingMarketMetadata::new_from_bytes(input)?;
             process_update_market_metadata(program_id, metadata, accounts)
         }
+        LendingInstruction::ApproveBorrowAllowance { liquidity_amount } => {
+            msg!("Instruction: Approve Borrow Allowance");
+            process_approve_borrow_allowance(program_id, liquidity_amount, accounts)
+        }
+        LendingInstruction::RevokeBorrowAllowance => {
+            msg!("Instruction: Revoke Borrow Allowance");
+            process_revoke_borrow_allowance(program_id, accounts)
+        }
+        LendingInstruction::LiquidateObligationForCollateral { liquidity_amount } => {
+            msg!("Instruction: Liquidate Obligation For Collateral");
+            process_liquidate_obligation_for_collateral(program_id, liquidity_amount, accounts)
+        }
+        LendingInstruction::CloseObligation => {
+            msg!("Instruction: Close Obligation");
+            process_close_obligation(program_id, accounts)
+        }
+        LendingInstruction::WindDownReserve {
+            ltv_decay_per_slot_wad,
+        } => {
+            msg!("Instruction: Wind Down Reserve");
+            process_wind_down_reserve(program_id, ltv_decay_per_slot_wad, accounts)
+        }
+        LendingInstruction::CloseReserve => {
+            msg!("Instruction: Close Reserve");
+            process_close_reserve(program_id, accounts)
+        }
+        LendingInstruction::SetLiquidationCircuitBreaker {
+            config,
+            pause_duration,
+        } => {
+            msg!("Instruction: Set Liquidation Circuit Breaker");
+            process_set_liquidation_circuit_breaker(program_id, config, pause_duration, accounts)
+        }
+        LendingInstruction::SetObligationLimits {
+            max_deposits,
+            max_borrows,
+        } => {
+            msg!("Instruction: Set Obligation Limits");
+            process_set_obligation_limits(program_id, max_deposits, max_borrows, accounts)
+        }
+        LendingInstruction::SetConfigTimelock { delay_slots } => {
+            msg!("Instruction: Set Config Timelock");
+            process_set_config_timelock(program_id, delay_slots, accounts)
+        }
+        LendingInstruction::ActivateConfig => {
+            msg!("Instruction: Activate Config");
+            process_activate_config(program_id, accounts)
+        }
+        LendingInstruction::CancelConfig => {
+            msg!("Instruction: Cancel Config");
+            process_cancel_config(program_id, accounts)
+        }
+        LendingInstruction::SetCollateralLiquidationPriority {
+            liquidation_priority,
+        } => {
+            msg!("Instruction: Set Collateral Liquidation Priority");
+            process_set_collateral_liquidation_priority(program_id, liquidation_priority, accounts)
+        }
+        LendingInstruction::SetBorrowFeeRebate { key, rebate_bps } => {
+            msg!("Instruction: Set Borrow Fee Rebate");
+            process_set_borrow_fee_rebate(program_id, key, rebate_bps, accounts)
+        }
+        LendingInstruction::SetFeeRedemptionHook {
+            hook_program,
+            accounts_hash,
+        } => {
+            msg!("Instruction: Set Fee Redemption Hook");
+            process_set_fee_redemption_hook(program_id, hook_program, accounts_hash, accounts)
+        }
+        LendingInstruction::BorrowObligationLiquidityFixedRate { liquidity_amount } => {
+            msg!("Instruction: Borrow Obligation Liquidity Fixed Rate");
+            process_borrow_obligation_liquidity(
+                program_id,
+                liquidity_amount,
+                BorrowRateMode::Fixed,
+                accounts,
+            )
+        }
+        LendingInstruction::InitLiquidationStats => {
+            msg!("Instruction: Init Liquidation Stats");
+            process_init_liquidation_stats(program_id, accounts)
+        }
+        LendingInstruction::DepositReserveLiquidityWithMode {
+            liquidity_amount,
+            mode,
+        } => {
+            msg!("Instruction: Deposit Reserve Liquidity With Mode");
+            process_deposit_reserve_liquidity_and_obligation_collateral(
+                program_id,
+                liquidity_amount,
+                mode,
+                accounts,
+            )
+        }
+        LendingInstruction::SetObligationDepositMode { mode } => {
+            msg!("Instruction: Set Obligation Deposit Mode");
+            process_set_obligation_deposit_mode(program_id, mode, accounts)
+        }
+        LendingInstruction::ViewObligationHealth => {
+            msg!("Instruction: View Obligation Health");
+            process_view_obligation_health(program_id, accounts)
+        }
+        LendingInstruction::SplitObligation { deposits, borrows } => {
+            msg!("Instruction: Split Obligation");
+            process_split_obligation(program_id, &deposits, &borrows, accounts)
+        }
+        LendingInstruction::MergeObligations => {
+            msg!("Instruction: Merge Obligations");
+            process_merge_obligations(program_id, accounts)
+        }
+        LendingInstruction::SetDustSweepThreshold {
+            max_deposited_value_wad,
+        } => {
+            msg!("Instruction: Set Dust Sweep Threshold");
+            process_set_dust_sweep_threshold(program_id, max_deposited_value_wad, accounts)
+        }
+        LendingInstruction::SweepDustObligation => {
+            msg!("Instruction: Sweep Dust Obligation");
+            process_sweep_dust_obligation(program_id, accounts)
+        }
+        LendingInstruction::SetObligationBorrowCap { max_borrowed_value } => {
+            msg!("Instruction: Set Obligation Borrow Cap");
+            process_set_obligation_borrow_cap(program_id, max_borrowed_value, accounts)
+        }
+        LendingInstruction::SetMarketPolicy {
+            policy_program,
+            policy_account,
+        } => {
+            msg!("Instruction: Set Market Policy");
+            process_set_market_policy(program_id, policy_program, policy_account, accounts)
+        }
+        LendingInstruction::SetReserveAccrualEpoch { epoch_slots } => {
+            msg!("Instruction: Set Reserve Accrual Epoch");
+            process_set_reserve_accrual_epoch(program_id, epoch_slots, accounts)
+        }
+        LendingInstruction::SetDeleverageConfig { max_exempt_value } => {
+            msg!("Instruction: Set Deleverage Config");
+            process_set_deleverage_config(program_id, max_exempt_value, accounts)
+        }
+        LendingInstruction::InitDeleverageCredit => {
+            msg!("Instruction: Init Deleverage Credit");
+            process_init_deleverage_credit(program_id, accounts)
+        }
+        LendingInstruction::SetObligationOwner => {
+            msg!("Instruction: Set Obligation Owner");
+            process_set_obligation_owner(program_id, accounts)
+        }
+        LendingInstruction::SetFlashLoanConfig {
+            flash_loans_enabled,
+            max_flash_loan_amount,
+        } => {
+            msg!("Instruction: Set Flash Loan Config");
+            process_set_flash_loan_config(
+                program_id,
+                flash_loans_enabled,
+                max_flash_loan_amount,
+                accounts,
+            )
+        }
+        LendingInstruction::LiquidateObligationAndRedeemReserveCollateralByValue {
+            max_repay_value_wad,
+        } => {
+            msg!("Instruction: Liquidate Obligation and Redeem Reserve Collateral By Value");
+            process_liquidate_obligation_and_redeem_reserve_collateral(
+                program_id,
+                u64::MAX,
+                Some(Decimal::from_scaled_val(max_repay_value_wad)),
+                accounts,
+            )
+        }
+        LendingInstruction::SetObligationHealthBuffer { health_buffer_bps } => {
+            msg!("Instruction: Set Obligation Health Buffer");
+            process_set_obligation_health_buffer(program_id, health_buffer_bps, accounts)
+        }
+        LendingInstruction::SetIgnoreHealthBuffer {
+            ignore_health_buffer,
+        } => {
+            msg!("Instruction: Set Ignore Health Buffer");
+            process_set_ignore_health_buffer(program_id, ignore_health_buffer, accounts)
+        }
+        LendingInstruction::SetFlashLoanFeeReceiver => {
+            msg!("Instruction: Set Flash Loan Fee Receiver");
+            process_set_flash_loan_fee_receiver(program_id, accounts)
+        }
+        LendingInstruction::SetBorrowGraceConfig { grace_slots } => {
+            msg!("Instruction: Set Borrow Grace Config");
+            process_set_borrow_grace_config(program_id, grace_slots, accounts)
+        }
+        LendingInstruction::SetCrossMarketSource {
+            source_reserve,
+            allowed,
+        } => {
+            msg!("Instruction: Set Cross Market Source");
+            process_set_cross_market_source(program_id, source_reserve, allowed, accounts)
+        }
     }
 }
 
@@ -235,8 +456,20 @@ fn process_set_lending_market_owner_and_config(
     rate_limiter_config: RateLimiterConfig,
     whitelisted_liquidator: Option<Pubkey>,
     risk_authority: Pubkey,
+    slots_per_year: Option<u64>,
     accounts: &[AccountInfo],
 ) -> ProgramResult {
+    if let Some(slots_per_year) = slots_per_year {
+        if !(MIN_SLOTS_PER_YEAR..=MAX_SLOTS_PER_YEAR).contains(&slots_per_year) {
+            msg!(
+                "Slots per year must be in range [{}, {}]",
+                MIN_SLOTS_PER_YEAR,
+                MAX_SLOTS_PER_YEAR
+            );
+            return Err(LendingError::InvalidConfig.into());
+        }
+    }
+
     let account_info_iter = &mut accounts.iter();
     let lending_market_info = next_account_info(account_info_iter)?;
     let lending_market_owner_info = next_account_info(account_info_iter)?;
@@ -255,20 +488,70 @@ fn process_set_lending_market_owner_and_config(
         return Err(LendingError::InvalidSigner.into());
     }
 
-    lending_market.owner = new_owner;
-    lending_market.risk_authority = risk_authority;
-
-    if rate_limiter_config != lending_market.rate_limiter.config {
-        lending_market.rate_limiter = RateLimiter::new(rate_limiter_config, Clock::get()?.slot);
+    let config_timelock_info = next_account_info(account_info_iter)?;
+    let clock = Clock::get()?;
+    if let Some(config_timelock) = unpack_derived_account::<ConfigTimelock>(
+        program_id,
+        lending_market_info.key,
+        config_timelock_info,
+    )?
+    .filter(|config_timelock| config_timelock.is_active())
+    {
+        let pending_config_info = next_account_info(account_info_iter)?;
+        return stage_config_change(
+            program_id,
+            lending_market_info,
+            lending_market_info.key,
+            pending_config_info,
+            lending_market_owner_info,
+            config_timelock.activation_slot(clock.slot),
+            (Pubkey::default(), Pubkey::default()),
+            &LendingInstruction::SetLendingMarketOwnerAndConfig {
+                new_owner,
+                rate_limiter_config,
+                whitelisted_liquidator,
+                risk_authority,
+                slots_per_year,
+            },
+        );
     }
 
-    lending_market.whitelisted_liquidator = whitelisted_liquidator;
-
+    apply_lending_market_config(
+        &mut lending_market,
+        new_owner,
+        rate_limiter_config,
+        whitelisted_liquidator,
+        risk_authority,
+        slots_per_year,
+        clock.slot,
+    );
     LendingMarket::pack(lending_market, &mut lending_market_info.data.borrow_mut())?;
 
     Ok(())
 }
 
+fn apply_lending_market_config(
+    lending_market: &mut LendingMarket,
+    new_owner: Pubkey,
+    rate_limiter_config: RateLimiterConfig,
+    whitelisted_liquidator: Option<Pubkey>,
+    risk_authority: Pubkey,
+    slots_per_year: Option<u64>,
+    slot: u64,
+) {
+    lending_market.owner = new_owner;
+    lending_market.risk_authority = risk_authority;
+
+    lending_market
+        .rate_limiter
+        .set_config(rate_limiter_config, slot);
+
+    lending_market.whitelisted_liquidator = whitelisted_liquidator;
+    if let Some(slots_per_year) = slots_per_year {
+        lending_market.slots_per_year = slots_per_year;
+    }
+}
+
 fn process_init_reserve(
     program_id: &Pubkey,
     liquidity_amount: u64,
@@ -350,11 +633,30 @@ fn process_init_reserve(
         msg!("Both price oracles are null. At least one must be non-null");
         return Err(LendingError::InvalidOracleConfig.into());
     }
-    validate_pyth_keys(&lending_market, pyth_product_info, pyth_price_info)?;
-    validate_switchboard_keys(&lending_market, switchboard_feed_info)?;
+    if switchboard_feed_info.owner == program_id {
+        validate_cross_market_source(
+            program_id,
+            lending_market_info.key,
+            reserve_liquidity_mint_info.key,
+            pyth_price_info,
+            switchboard_feed_info,
+            accounts,
+        )?;
+    } else {
+        validate_pyth_keys(&lending_market, pyth_product_info, pyth_price_info)?;
+        validate_switchboard_keys(&lending_market, switchboard_feed_info)?;
+    }
 
-    let (market_price, smoothed_market_price) =
-        get_price(Some(switchboard_feed_info), pyth_price_info, clock)?;
+    let (market_price, smoothed_market_price) = get_reserve_price(
+        program_id,
+        Some(switchboard_feed_info),
+        pyth_price_info,
+        clock,
+    )?;
+    let market_price = config.bound_price(market_price)?;
+    let smoothed_market_price = smoothed_market_price
+        .map(|price| config.bound_price(price))
+        .transpose()?;
 
     let authority_signer_seeds = &[
         lending_market_info.key.as_ref(),
@@ -374,6 +676,13 @@ fn process_init_reserve(
         msg!("Reserve liquidity mint is not owned by the token program provided");
         return Err(LendingError::InvalidTokenOwner.into());
     }
+    if reserve_liquidity_mint.decimals > MAX_MINT_DECIMALS {
+        msg!(
+            "Reserve liquidity mint decimals must be at most {}",
+            MAX_MINT_DECIMALS
+        );
+        return Err(LendingError::UnsupportedMintDecimals.into());
+    }
 
     reserve.init(InitReserveParams {
         current_slot: clock.slot,
@@ -393,9 +702,10 @@ fn process_init_reserve(
         }),
         config,
         rate_limiter_config: RateLimiterConfig::default(),
+        slots_per_year: lending_market.slots_per_year,
     });
 
-    let collateral_amount = reserve.deposit_liquidity(liquidity_amount)?;
+    let collateral_amount = reserve.deposit_initial_liquidity(liquidity_amount)?;
     Reserve::pack(reserve, &mut reserve_info.data.borrow_mut())?;
 
     spl_token_init_account(TokenInitializeAccountParams {
@@ -447,6 +757,16 @@ fn process_init_reserve(
         token_program: token_program_id.clone(),
     })?;
 
+    // nobody can withdraw collateral from the supply that no obligation deposited
+    spl_token_mint_to(TokenMintToParams {
+        mint: reserve_collateral_mint_info.clone(),
+        destination: reserve_collateral_supply_info.clone(),
+        amount: LOCKED_INITIAL_COLLATERAL,
+        authority: lending_market_authority_info.clone(),
+        authority_signer_seeds,
+        token_program: token_program_id.clone(),
+    })?;
+
     spl_token_mint_to(TokenMintToParams {
         mint: reserve_collateral_mint_info.clone(),
         destination: destination_collateral_info.clone(),
@@ -460,25 +780,15 @@ fn process_init_reserve(
 }
 
 fn process_refresh_reserve(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
-    let account_info_iter = &mut accounts.iter().peekable();
+    let account_info_iter = &mut accounts.iter();
     let reserve_info = next_account_info(account_info_iter)?;
     let pyth_price_info = next_account_info(account_info_iter)?;
-    // set switchboard to a placeholder account info
-    let mut switchboard_feed_info = None;
-    // if the next account info exists and is not the clock set it to be switchboard
-    let switchboard_peek = account_info_iter.peek().map(|a| a.key);
-    if switchboard_peek.is_some() && switchboard_peek != Some(&clock::ID) {
-        switchboard_feed_info = Some(next_account_info(account_info_iter)?);
-    }
     let clock = &Clock::get()?;
-    if account_info_iter.peek().map(|a| a.key) == Some(&clock::ID) {
-        next_account_info(account_info_iter)?;
-    }
     _refresh_reserve(
         program_id,
         reserve_info,
         pyth_price_info,
-        switchboard_feed_info,
+        account_info_iter.as_slice(),
         clock,
     )
 }
@@ -487,7 +797,7 @@ fn _refresh_reserve<'a>(
     program_id: &Pubkey,
     reserve_info: &AccountInfo<'a>,
     pyth_price_info: &AccountInfo<'a>,
-    switchboard_feed_info: Option<&AccountInfo<'a>>,
+    optional_accounts: &[AccountInfo<'a>],
     clock: &Clock,
 ) -> ProgramResult {
     let mut reserve = Reserve::unpack(&reserve_info.data.borrow())?;
@@ -495,6 +805,16 @@ fn _refresh_reserve<'a>(
         msg!("Reserve provided is not owned by the lending program");
         return Err(LendingError::InvalidAccountOwner.into());
     }
+
+    // switchboard, the clock, the lending market and the accrual history are all optional, in
+    // that order. The lending market is told apart from switchboard by its key so only passing
+    // in pyth still works.
+    let account_info_iter = &mut optional_accounts.iter().peekable();
+    let switchboard_feed_info =
+        account_info_iter.next_if(|a| a.key != &clock::ID && a.key != &reserve.lending_market);
+    account_info_iter.next_if(|a| a.key == &clock::ID);
+    let lending_market_info = account_info_iter.next_if(|a| a.key == &reserve.lending_market);
+    let accrual_history_info = account_info_iter.next();
     if &reserve.liquidity.pyth_oracle_pubkey != pyth_price_info.key {
         msg!("Reserve liquidity pyth oracle does not match the reserve liquidity pyth oracle provided");
         return Err(LendingError::InvalidAccountInput.into());
@@ -509,23 +829,69 @@ fn _refresh_reserve<'a>(
     }
 
     let (market_price, smoothed_market_price) =
-        get_price(switchboard_feed_info, pyth_price_info, clock)?;
+        get_reserve_price(program_id, switchboard_feed_info, pyth_price_info, clock)?;
+    let market_price = reserve.config.bound_price(market_price)?;
+
+    // a refresh only changes the prices, and the rest of the reserve when interest accrues
+    let accrual_slot = reserve.last_update.slot;
+    let prices = (
+        reserve.liquidity.market_price,
+        reserve.liquidity.smoothed_market_price,
+    );
 
     reserve.liquidity.market_price = market_price;
 
     if let Some(smoothed_market_price) = smoothed_market_price {
-        reserve.liquidity.smoothed_market_price = smoothed_market_price;
+        reserve.liquidity.smoothed_market_price =
+            reserve.config.bound_price(smoothed_market_price)?;
     }
 
     // currently there's no way to support two prices without a pyth oracle. So if a reserve
     // only supports switchboard, reserve.smoothed_market_price == reserve.market_price
-    if reserve.liquidity.pyth_oracle_pubkey == solend_program::NULL_PUBKEY {
+    if reserve.liquidity.pyth_oracle_pubkey == solend_program::NULL_PUBKEY
+        && smoothed_market_price.is_none()
+    {
         reserve.liquidity.smoothed_market_price = market_price;
     }
 
-    Reserve::pack(reserve, &mut reserve_info.data.borrow_mut())?;
+    // the opening values of an epoch are taken before interest accrues past its boundary
+    if let Some(accrual_history_info) = accrual_history_info {
+        let mut accrual_history = unpack_derived_account::<ReserveAccrualHistory>(
+            program_id,
+            reserve_info.key,
+            accrual_history_info,
+        )?
+        .ok_or_else(|| {
+            msg!("Reserve accrual history account is not initialized");
+            LendingError::InvalidAccountInput
+        })?;
+        accrual_history.record_epochs(&reserve, clock.slot)?;
+        ReserveAccrualHistory::pack(accrual_history, &mut accrual_history_info.data.borrow_mut())?;
+    }
+
+    let slots_per_year = match lending_market_info {
+        Some(lending_market_info) => {
+            market_slots_per_year(program_id, &reserve, lending_market_info)?
+        }
+        None => reserve.slots_per_year,
+    };
+    let repack = accrual_slot != clock.slot
+        || slots_per_year != reserve.slots_per_year
+        || prices
+            != (
+                reserve.liquidity.market_price,
+                reserve.liquidity.smoothed_market_price,
+            );
+    accrue_reserve_interest(reserve_info.key, &mut reserve, slots_per_year, clock)?;
 
-    _refresh_reserve_interest(program_id, reserve_info, clock)
+    // refreshing a reserve again in the same slot at the same prices changes nothing but
+    // `last_update`, so bundles that refresh many reserves skip the full repack
+    if repack {
+        Reserve::pack(reserve, &mut reserve_info.data.borrow_mut())?;
+    } else {
+        reserve.pack_last_update(&mut reserve_info.data.borrow_mut());
+    }
+    Ok(())
 }
 
 /// Lite version of refresh_reserve that should be used when the oracle price doesn't need to be updated
@@ -533,6 +899,7 @@ fn _refresh_reserve<'a>(
 fn _refresh_reserve_interest(
     program_id: &Pubkey,
     reserve_info: &AccountInfo<'_>,
+    lending_market_info: &AccountInfo<'_>,
     clock: &Clock,
 ) -> ProgramResult {
     let mut reserve = Reserve::unpack(&reserve_info.data.borrow())?;
@@ -541,9 +908,63 @@ fn _refresh_reserve_interest(
         return Err(LendingError::InvalidAccountOwner.into());
     }
 
+    let slots_per_year = market_slots_per_year(program_id, &reserve, lending_market_info)?;
+    accrue_reserve_interest(reserve_info.key, &mut reserve, slots_per_year, clock)?;
+    Reserve::pack(reserve, &mut reserve_info.data.borrow_mut())?;
+
+    Ok(())
+}
+
+/// Slots per year of the reserve's lending market, which interest accrues at whenever the market is
+/// passed
+fn market_slots_per_year(
+    program_id: &Pubkey,
+    reserve: &Reserve,
+    lending_market_info: &AccountInfo<'_>,
+) -> Result<u64, ProgramError> {
+    if lending_market_info.owner != program_id {
+        msg!("Lending market provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+    if &reserve.lending_market != lending_market_info.key {
+        msg!("Reserve lending market does not match the lending market provided");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+    Ok(LendingMarket::unpack(&lending_market_info.data.borrow())?.slots_per_year)
+}
+
+/// Accrues interest up to the current slot at the market's slots per year and marks the reserve
+/// fresh
+fn accrue_reserve_interest(
+    reserve_pubkey: &Pubkey,
+    reserve: &mut Reserve,
+    slots_per_year: u64,
+    clock: &Clock,
+) -> ProgramResult {
+    let supply_before = reserve.liquidity.total_supply()?.try_floor_u64()?;
+    let borrowed_before = reserve.liquidity.borrowed_amount_wads.try_floor_u64()?;
+    // a changed value also applies to the slots since the last accrual, idle fee included
+    reserve.slots_per_year = slots_per_year;
     reserve.accrue_interest(clock.slot)?;
     reserve.last_update.update_slot(clock.slot);
-    Reserve::pack(reserve, &mut reserve_info.data.borrow_mut())?;
+    // interest accrual grows both the deposits and the borrows towards their limits
+    emit_reserve_limit_event(
+        *reserve_pubkey,
+        ReserveLimitKind::Deposit,
+        supply_before,
+        reserve.liquidity.total_supply()?.try_floor_u64()?,
+        reserve.config.deposit_limit,
+    );
+    emit_reserve_limit_event(
+        *reserve_pubkey,
+        ReserveLimitKind::Borrow,
+        borrowed_before,
+        reserve.liquidity.borrowed_amount_wads.try_floor_u64()?,
+        reserve.config.borrow_limit,
+    );
+
+    #[cfg(feature = "strict-invariants")]
+    solend_program::invariants::check_reserve_invariants(reserve)?;
 
     Ok(())
 }
@@ -573,7 +994,7 @@ fn process_deposit_reserve_liquidity(
     }
     let token_program_id = next_account_info(account_info_iter)?;
 
-    _refresh_reserve_interest(program_id, reserve_info, clock)?;
+    _refresh_reserve_interest(program_id, reserve_info, lending_market_info, clock)?;
     _deposit_reserve_liquidity(
         program_id,
         liquidity_amount,
@@ -645,6 +1066,10 @@ fn _deposit_reserve_liquidity<'a>(
         msg!("Reserve is stale and must be refreshed in the current slot");
         return Err(LendingError::ReserveStale.into());
     }
+    if !reserve.status.is_active() {
+        msg!("Cannot deposit into a reserve that is winding down or closed");
+        return Err(LendingError::ReserveNotActive.into());
+    }
     let authority_signer_seeds = &[
         lending_market_info.key.as_ref(),
         &[lending_market.bump_seed],
@@ -660,16 +1085,31 @@ fn _deposit_reserve_liquidity<'a>(
         return Err(LendingError::InvalidMarketAuthority.into());
     }
 
-    if Decimal::from(liquidity_amount)
+    let supply_before = reserve.liquidity.total_supply()?.try_floor_u64()?;
+    let supply_after = Decimal::from(liquidity_amount)
         .try_add(reserve.liquidity.total_supply()?)?
-        .try_floor_u64()?
-        > reserve.config.deposit_limit
-    {
+        .try_floor_u64()?;
+    if supply_after > reserve.config.deposit_limit {
         msg!("Cannot deposit liquidity above the reserve deposit limit");
+        log_error_context(
+            LendingError::InvalidAmount,
+            &[
+                ("amount", &liquidity_amount),
+                ("total_supply", &supply_before),
+                ("deposit_limit", &reserve.config.deposit_limit),
+            ],
+        );
         return Err(LendingError::InvalidAmount.into());
     }
 
     let collateral_amount = reserve.deposit_liquidity(liquidity_amount)?;
+    emit_reserve_limit_event(
+        *reserve_info.key,
+        ReserveLimitKind::Deposit,
+        supply_before,
+        supply_after,
+        reserve.config.deposit_limit,
+    );
     reserve.last_update.mark_stale();
     Reserve::pack(reserve, &mut reserve_info.data.borrow_mut())?;
 
@@ -718,6 +1158,7 @@ fn process_redeem_reserve_collateral(
         next_account_info(account_info_iter)?;
     }
     let token_program_id = next_account_info(account_info_iter)?;
+    let reserve_liquidity_fee_receiver_info = next_account_info(account_info_iter).ok();
 
     _redeem_reserve_collateral(
         program_id,
@@ -733,6 +1174,8 @@ fn process_redeem_reserve_collateral(
         clock,
         token_program_id,
         true,
+        &mut Decimal::zero(),
+        Some(reserve_liquidity_fee_receiver_info),
     )?;
     let mut reserve = Reserve::unpack(&reserve_info.data.borrow())?;
     reserve.last_update.mark_stale();
@@ -756,6 +1199,11 @@ fn _redeem_reserve_collateral<'a>(
     clock: &Clock,
     token_program_id: &AccountInfo<'a>,
     check_rate_limits: bool,
+    // value that doesn't count against the lending market outflow limit, reduced by the value
+    // this redemption used up
+    market_outflow_exemption: &mut Decimal,
+    // None when the withdraw fee doesn't apply, as for liquidations
+    withdraw_fee_receiver_info: Option<Option<&AccountInfo<'a>>>,
 ) -> Result<u64, ProgramError> {
     let mut lending_market = LendingMarket::unpack(&lending_market_info.data.borrow())?;
     if lending_market_info.owner != program_id {
@@ -792,6 +1240,12 @@ fn _redeem_reserve_collateral<'a>(
         msg!("Reserve liquidity supply cannot be used as the destination liquidity provided");
         return Err(LendingError::InvalidAccountInput.into());
     }
+    // the destination doesn't have to belong to the user, eg when withdrawing to a custodian
+    let destination_liquidity = unpack_token_account(&destination_liquidity_info.data.borrow())?;
+    if destination_liquidity.mint != reserve.liquidity.mint_pubkey {
+        msg!("Destination liquidity token account mint does not match the reserve liquidity mint");
+        return Err(LendingError::InvalidTokenMint.into());
+    }
     if reserve.last_update.is_stale(clock.slot)? {
         msg!("Reserve is stale and must be refreshed in the current slot");
         return Err(LendingError::ReserveStale.into());
@@ -811,14 +1265,31 @@ fn _redeem_reserve_collateral<'a>(
     }
 
     let liquidity_amount = reserve.redeem_collateral(collateral_amount)?;
+    let withdraw_fee = match withdraw_fee_receiver_info {
+        Some(withdraw_fee_receiver_info) if reserve.config.withdraw_fee_bps > 0 => {
+            let withdraw_fee_receiver_info = withdraw_fee_receiver_info.ok_or_else(|| {
+                msg!("Reserve charges a withdraw fee, the reserve liquidity fee receiver must be provided");
+                LendingError::InvalidAccountInput
+            })?;
+            if &reserve.config.fee_receiver != withdraw_fee_receiver_info.key {
+                msg!("Reserve liquidity fee receiver does not match the reserve fee receiver");
+                return Err(LendingError::InvalidAccountInput.into());
+            }
+            Some((
+                withdraw_fee_receiver_info,
+                reserve.calculate_withdraw_fee(liquidity_amount)?,
+            ))
+        }
+        _ => None,
+    };
 
     if check_rate_limits {
+        let outflow_value = reserve.market_value_upper_bound(Decimal::from(liquidity_amount))?;
+        let exempt_value = min(outflow_value, *market_outflow_exemption);
+        *market_outflow_exemption = market_outflow_exemption.try_sub(exempt_value)?;
         lending_market
             .rate_limiter
-            .update(
-                clock.slot,
-                reserve.market_value_upper_bound(Decimal::from(liquidity_amount))?,
-            )
+            .update(clock.slot, outflow_value.try_sub(exempt_value)?)
             .map_err(|err| {
                 msg!("Market outflow limit exceeded! Please try again later.");
                 err
@@ -846,16 +1317,31 @@ fn _redeem_reserve_collateral<'a>(
         token_program: token_program_id.clone(),
     })?;
 
+    let mut receive_amount = liquidity_amount;
+    if let Some((withdraw_fee_receiver_info, withdraw_fee)) = withdraw_fee {
+        receive_amount = receive_amount
+            .checked_sub(withdraw_fee)
+            .ok_or(LendingError::MathOverflow)?;
+        spl_token_transfer(TokenTransferParams {
+            source: reserve_liquidity_supply_info.clone(),
+            destination: withdraw_fee_receiver_info.clone(),
+            amount: withdraw_fee,
+            authority: lending_market_authority_info.clone(),
+            authority_signer_seeds,
+            token_program: token_program_id.clone(),
+        })?;
+    }
+
     spl_token_transfer(TokenTransferParams {
         source: reserve_liquidity_supply_info.clone(),
         destination: destination_liquidity_info.clone(),
-        amount: liquidity_amount,
+        amount: receive_amount,
         authority: lending_market_authority_info.clone(),
         authority_signer_seeds,
         token_program: token_program_id.clone(),
     })?;
 
-    Ok(liquidity_amount)
+    Ok(receive_amount)
 }
 
 #[inline(never)] // avoid stack frame limit
@@ -905,28 +1391,16 @@ fn process_init_obligation(program_id: &Pubkey, accounts: &[AccountInfo]) -> Pro
     Ok(())
 }
 
-fn process_refresh_obligation(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
-    let account_info_iter = &mut accounts.iter().peekable();
-    let obligation_info = next_account_info(account_info_iter)?;
-    let clock = &Clock::get()?;
-    if account_info_iter.peek().map(|a| a.key) == Some(&clock::ID) {
-        next_account_info(account_info_iter)?;
-    }
-
-    let mut obligation = Obligation::unpack(&obligation_info.data.borrow())?;
-    if obligation_info.owner != program_id {
-        msg!("Obligation provided is not owned by the lending program");
-        return Err(LendingError::InvalidAccountOwner.into());
-    }
-
-    let mut deposited_value = Decimal::zero();
-    let mut borrowed_value = Decimal::zero();
-    let mut borrowed_value_upper_bound = Decimal::zero();
-    let mut allowed_borrow_value = Decimal::zero();
-    let mut unhealthy_borrow_value = Decimal::zero();
-    let mut super_unhealthy_borrow_value = Decimal::zero();
-
-    for (index, collateral) in obligation.deposits.iter_mut().enumerate() {
+/// Unpack the deposit reserves of `obligation` followed by its borrow reserves from the remaining
+/// accounts, checking they are the obligation's, fresh, and all there is
+fn unpack_obligation_reserves(
+    program_id: &Pubkey,
+    obligation: &Obligation,
+    account_info_iter: &mut Peekable<Iter<AccountInfo>>,
+    clock: &Clock,
+) -> Result<(Vec<Reserve>, Vec<Reserve>), ProgramError> {
+    let mut deposit_reserves = Vec::with_capacity(obligation.deposits.len());
+    for (index, collateral) in obligation.deposits.iter().enumerate() {
         let deposit_reserve_info = next_account_info(account_info_iter)?;
         if deposit_reserve_info.owner != program_id {
             msg!(
@@ -951,34 +1425,11 @@ fn process_refresh_obligation(program_id: &Pubkey, accounts: &[AccountInfo]) ->
             );
             return Err(LendingError::ReserveStale.into());
         }
+        deposit_reserves.push(deposit_reserve);
+    }
 
-        let liquidity_amount = deposit_reserve
-            .collateral_exchange_rate()?
-            .decimal_collateral_to_liquidity(collateral.deposited_amount.into())?;
-
-        let market_value = deposit_reserve.market_value(liquidity_amount)?;
-        let market_value_lower_bound =
-            deposit_reserve.market_value_lower_bound(liquidity_amount)?;
-
-        let loan_to_value_rate = Rate::from_percent(deposit_reserve.config.loan_to_value_ratio);
-        let liquidation_threshold_rate =
-            Rate::from_percent(deposit_reserve.config.liquidation_threshold);
-        let max_liquidation_threshold_rate =
-            Rate::from_percent(deposit_reserve.config.max_liquidation_threshold);
-
-        collateral.market_value = market_value;
-        deposited_value = deposited_value.try_add(market_value)?;
-        allowed_borrow_value =
-            allowed_borrow_value.try_add(market_value_lower_bound.try_mul(loan_to_value_rate)?)?;
-        unhealthy_borrow_value =
-            unhealthy_borrow_value.try_add(market_value.try_mul(liquidation_threshold_rate)?)?;
-        super_unhealthy_borrow_value = super_unhealthy_borrow_value
-            .try_add(market_value.try_mul(max_liquidation_threshold_rate)?)?;
-    }
-
-    let mut borrowing_isolated_asset = false;
-    let mut max_borrow_weight = None;
-    for (index, liquidity) in obligation.borrows.iter_mut().enumerate() {
+    let mut borrow_reserves = Vec::with_capacity(obligation.borrows.len());
+    for (index, liquidity) in obligation.borrows.iter().enumerate() {
         let borrow_reserve_info = next_account_info(account_info_iter)?;
         if borrow_reserve_info.owner != program_id {
             msg!(
@@ -1003,45 +1454,7 @@ fn process_refresh_obligation(program_id: &Pubkey, accounts: &[AccountInfo]) ->
             );
             return Err(LendingError::ReserveStale.into());
         }
-
-        if borrow_reserve.config.reserve_type == ReserveType::Isolated {
-            borrowing_isolated_asset = true;
-        }
-
-        liquidity.accrue_interest(borrow_reserve.liquidity.cumulative_borrow_rate_wads)?;
-
-        let borrow_weight_and_pubkey = (
-            borrow_reserve.config.added_borrow_weight_bps,
-            borrow_reserve_info.key,
-        );
-        max_borrow_weight = match max_borrow_weight {
-            None => {
-                if liquidity.borrowed_amount_wads > Decimal::zero() {
-                    Some((borrow_weight_and_pubkey, index))
-                } else {
-                    None
-                }
-            }
-            Some((max_borrow_weight_and_pubkey, _)) => {
-                if liquidity.borrowed_amount_wads > Decimal::zero()
-                    && borrow_weight_and_pubkey > max_borrow_weight_and_pubkey
-                {
-                    Some((borrow_weight_and_pubkey, index))
-                } else {
-                    max_borrow_weight
-                }
-            }
-        };
-
-        let market_value = borrow_reserve.market_value(liquidity.borrowed_amount_wads)?;
-        let market_value_upper_bound =
-            borrow_reserve.market_value_upper_bound(liquidity.borrowed_amount_wads)?;
-        liquidity.market_value = market_value;
-
-        borrowed_value =
-            borrowed_value.try_add(market_value.try_mul(borrow_reserve.borrow_weight())?)?;
-        borrowed_value_upper_bound = borrowed_value_upper_bound
-            .try_add(market_value_upper_bound.try_mul(borrow_reserve.borrow_weight())?)?;
+        borrow_reserves.push(borrow_reserve);
     }
 
     if account_info_iter.peek().is_some() {
@@ -1049,56 +1462,91 @@ fn process_refresh_obligation(program_id: &Pubkey, accounts: &[AccountInfo]) ->
         return Err(LendingError::InvalidAccountInput.into());
     }
 
-    obligation.deposited_value = deposited_value;
-    obligation.borrowed_value = borrowed_value;
-    obligation.borrowed_value_upper_bound = borrowed_value_upper_bound;
-    obligation.borrowing_isolated_asset = borrowing_isolated_asset;
-
-    let global_unhealthy_borrow_value = Decimal::from(70000000u64);
-    let global_allowed_borrow_value = Decimal::from(65000000u64);
-
-    obligation.allowed_borrow_value = min(allowed_borrow_value, global_allowed_borrow_value);
-    obligation.unhealthy_borrow_value = min(unhealthy_borrow_value, global_unhealthy_borrow_value);
-    obligation.super_unhealthy_borrow_value =
-        min(super_unhealthy_borrow_value, global_unhealthy_borrow_value);
+    Ok((deposit_reserves, borrow_reserves))
+}
 
-    obligation.last_update.update_slot(clock.slot);
+#[inline(never)] // avoid stack frame limit
+fn process_view_obligation_health(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter().peekable();
+    let obligation_info = next_account_info(account_info_iter)?;
+    let clock = &Clock::get()?;
 
-    // move the ObligationLiquidity with the max borrow weight to the front
-    if let Some((_, max_borrow_weight_index)) = max_borrow_weight {
-        obligation.borrows.swap(0, max_borrow_weight_index);
+    let mut obligation = Obligation::unpack(&obligation_info.data.borrow())?;
+    if obligation_info.owner != program_id {
+        msg!("Obligation provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
     }
 
-    // filter out ObligationCollaterals and ObligationLiquiditys with an amount of zero
-    obligation
-        .deposits
-        .retain(|collateral| collateral.deposited_amount > 0);
-    obligation
-        .borrows
-        .retain(|liquidity| liquidity.borrowed_amount_wads > Decimal::zero());
+    let (deposit_reserves, borrow_reserves) =
+        unpack_obligation_reserves(program_id, &obligation, account_info_iter, clock)?;
+    obligation.refresh(&deposit_reserves, &borrow_reserves, clock.slot)?;
 
-    Obligation::pack(obligation, &mut obligation_info.data.borrow_mut())?;
+    let mut health = [0u8; ObligationHealth::LEN];
+    ObligationHealth::from(&obligation).pack_into_slice(&mut health);
+    set_return_data(&health);
 
     Ok(())
 }
 
-#[inline(never)] // avoid stack frame limit
-fn process_deposit_obligation_collateral(
-    program_id: &Pubkey,
-    collateral_amount: u64,
-    accounts: &[AccountInfo],
-) -> ProgramResult {
-    if collateral_amount == 0 {
-        msg!("Collateral amount provided cannot be zero");
-        return Err(LendingError::InvalidAmount.into());
-    }
-
+fn process_refresh_obligation(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
     let account_info_iter = &mut accounts.iter().peekable();
-    let source_collateral_info = next_account_info(account_info_iter)?;
-    let destination_collateral_info = next_account_info(account_info_iter)?;
-    let deposit_reserve_info = next_account_info(account_info_iter)?;
     let obligation_info = next_account_info(account_info_iter)?;
-    let lending_market_info = next_account_info(account_info_iter)?;
+    let clock = &Clock::get()?;
+    if account_info_iter.peek().map(|a| a.key) == Some(&clock::ID) {
+        next_account_info(account_info_iter)?;
+    }
+
+    let mut obligation = Obligation::unpack(&obligation_info.data.borrow())?;
+    if obligation_info.owner != program_id {
+        msg!("Obligation provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+
+    let (deposit_reserves, borrow_reserves) =
+        unpack_obligation_reserves(program_id, &obligation, account_info_iter, clock)?;
+
+    #[cfg(feature = "strict-invariants")]
+    let reserves = obligation
+        .deposits
+        .iter()
+        .map(|collateral| collateral.deposit_reserve)
+        .zip(deposit_reserves.iter().cloned())
+        .chain(
+            obligation
+                .borrows
+                .iter()
+                .map(|liquidity| liquidity.borrow_reserve)
+                .zip(borrow_reserves.iter().cloned()),
+        )
+        .collect::<Vec<_>>();
+
+    obligation.refresh(&deposit_reserves, &borrow_reserves, clock.slot)?;
+
+    #[cfg(feature = "strict-invariants")]
+    solend_program::invariants::check_obligation_invariants(&obligation, &reserves)?;
+
+    Obligation::pack(obligation, &mut obligation_info.data.borrow_mut())?;
+
+    Ok(())
+}
+
+#[inline(never)] // avoid stack frame limit
+fn process_deposit_obligation_collateral(
+    program_id: &Pubkey,
+    collateral_amount: u64,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    if collateral_amount == 0 {
+        msg!("Collateral amount provided cannot be zero");
+        return Err(LendingError::InvalidAmount.into());
+    }
+
+    let account_info_iter = &mut accounts.iter().peekable();
+    let source_collateral_info = next_account_info(account_info_iter)?;
+    let destination_collateral_info = next_account_info(account_info_iter)?;
+    let deposit_reserve_info = next_account_info(account_info_iter)?;
+    let obligation_info = next_account_info(account_info_iter)?;
+    let lending_market_info = next_account_info(account_info_iter)?;
     let obligation_owner_info = next_account_info(account_info_iter)?;
     let user_transfer_authority_info = next_account_info(account_info_iter)?;
     let clock = &Clock::get()?;
@@ -1106,7 +1554,8 @@ fn process_deposit_obligation_collateral(
         next_account_info(account_info_iter)?;
     }
     let token_program_id = next_account_info(account_info_iter)?;
-    _refresh_reserve_interest(program_id, deposit_reserve_info, clock)?;
+    let optional_accounts = &accounts[accounts.len() - account_info_iter.len()..];
+    _refresh_reserve_interest(program_id, deposit_reserve_info, lending_market_info, clock)?;
     _deposit_obligation_collateral(
         program_id,
         collateral_amount,
@@ -1119,6 +1568,7 @@ fn process_deposit_obligation_collateral(
         user_transfer_authority_info,
         clock,
         token_program_id,
+        optional_accounts,
     )?;
     let mut reserve = Reserve::unpack(&deposit_reserve_info.data.borrow())?;
     reserve.last_update.mark_stale();
@@ -1139,6 +1589,7 @@ fn _deposit_obligation_collateral<'a>(
     user_transfer_authority_info: &AccountInfo<'a>,
     clock: &Clock,
     token_program_id: &AccountInfo<'a>,
+    optional_accounts: &[AccountInfo<'a>],
 ) -> ProgramResult {
     let lending_market = LendingMarket::unpack(&lending_market_info.data.borrow())?;
     if lending_market_info.owner != program_id {
@@ -1173,6 +1624,10 @@ fn _deposit_obligation_collateral<'a>(
         msg!("Deposit reserve is stale and must be refreshed in the current slot");
         return Err(LendingError::ReserveStale.into());
     }
+    if !deposit_reserve.status.is_active() {
+        msg!("Cannot deposit into a reserve that is winding down or closed");
+        return Err(LendingError::ReserveNotActive.into());
+    }
 
     let mut obligation = Obligation::unpack(&obligation_info.data.borrow())?;
     if obligation_info.owner != program_id {
@@ -1192,9 +1647,21 @@ fn _deposit_obligation_collateral<'a>(
         return Err(LendingError::InvalidSigner.into());
     }
 
+    let deposits_len = obligation.deposits.len();
     obligation
         .find_or_add_collateral_to_deposits(*deposit_reserve_info.key)?
         .deposit(collateral_amount)?;
+    // only new positions count against the limits, so lowering them doesn't lock anyone in
+    if obligation.deposits.len() > deposits_len {
+        if let Some((_, obligation_limits)) = find_derived_account::<ObligationLimits>(
+            program_id,
+            lending_market_info.key,
+            optional_accounts,
+            lending_market.has_obligation_limits,
+        )? {
+            obligation_limits.check_deposits(obligation.deposits.len())?;
+        }
+    }
     obligation.last_update.mark_stale();
     Obligation::pack(obligation, &mut obligation_info.data.borrow_mut())?;
     spl_token_transfer(TokenTransferParams {
@@ -1212,6 +1679,7 @@ fn _deposit_obligation_collateral<'a>(
 fn process_deposit_reserve_liquidity_and_obligation_collateral(
     program_id: &Pubkey,
     liquidity_amount: u64,
+    mode: Option<DepositMode>,
     accounts: &[AccountInfo],
 ) -> ProgramResult {
     if liquidity_amount == 0 {
@@ -1238,8 +1706,25 @@ fn process_deposit_reserve_liquidity_and_obligation_collateral(
         next_account_info(account_info_iter)?;
     }
     let token_program_id = next_account_info(account_info_iter)?;
+    let optional_accounts = &accounts[accounts.len() - account_info_iter.len()..];
+
+    let mode = match mode {
+        Some(mode) => mode,
+        None => {
+            if obligation_info.owner != program_id {
+                msg!("Obligation provided is not owned by the lending program");
+                return Err(LendingError::InvalidAccountOwner.into());
+            }
+            let obligation = Obligation::unpack(&obligation_info.data.borrow())?;
+            if &obligation.lending_market != lending_market_info.key {
+                msg!("Obligation lending market does not match the lending market provided");
+                return Err(LendingError::InvalidAccountInput.into());
+            }
+            obligation.default_deposit_mode
+        }
+    };
 
-    _refresh_reserve_interest(program_id, reserve_info, clock)?;
+    _refresh_reserve_interest(program_id, reserve_info, lending_market_info, clock)?;
     let collateral_amount = _deposit_reserve_liquidity(
         program_id,
         liquidity_amount,
@@ -1254,20 +1739,23 @@ fn process_deposit_reserve_liquidity_and_obligation_collateral(
         clock,
         token_program_id,
     )?;
-    _refresh_reserve_interest(program_id, reserve_info, clock)?;
-    _deposit_obligation_collateral(
-        program_id,
-        collateral_amount,
-        user_collateral_info,
-        destination_collateral_info,
-        reserve_info,
-        obligation_info,
-        lending_market_info,
-        obligation_owner_info,
-        user_transfer_authority_info,
-        clock,
-        token_program_id,
-    )?;
+    if mode == DepositMode::Collateralize {
+        _refresh_reserve_interest(program_id, reserve_info, lending_market_info, clock)?;
+        _deposit_obligation_collateral(
+            program_id,
+            collateral_amount,
+            user_collateral_info,
+            destination_collateral_info,
+            reserve_info,
+            obligation_info,
+            lending_market_info,
+            obligation_owner_info,
+            user_transfer_authority_info,
+            clock,
+            token_program_id,
+            optional_accounts,
+        )?;
+    }
     // mark the reserve as stale to make sure no weird bugs happen
     let mut reserve = Reserve::unpack(&reserve_info.data.borrow())?;
     reserve.last_update.mark_stale();
@@ -1300,6 +1788,18 @@ fn process_withdraw_obligation_collateral(
         next_account_info(account_info_iter)?;
     }
     let token_program_id = next_account_info(account_info_iter)?;
+    let optional_accounts = &accounts[accounts.len() - account_info_iter.len()..];
+    check_market_policy(
+        program_id,
+        lending_market_info,
+        obligation_info,
+        withdraw_reserve_info,
+        obligation_owner_info,
+        PolicyAction::Withdraw,
+        collateral_amount,
+        optional_accounts,
+    )?;
+    let health_buffer = find_health_buffer(program_id, lending_market_info, optional_accounts)?;
     _withdraw_obligation_collateral(
         program_id,
         collateral_amount,
@@ -1313,6 +1813,8 @@ fn process_withdraw_obligation_collateral(
         clock,
         token_program_id,
         false,
+        Decimal::zero(),
+        health_buffer,
     )?;
     Ok(())
 }
@@ -1331,6 +1833,8 @@ fn _withdraw_obligation_collateral<'a>(
     clock: &Clock,
     token_program_id: &AccountInfo<'a>,
     account_for_rate_limiter: bool,
+    market_outflow_exemption: Decimal,
+    health_buffer: Bps,
 ) -> Result<u64, ProgramError> {
     let lending_market = LendingMarket::unpack(&lending_market_info.data.borrow())?;
     if lending_market_info.owner != program_id {
@@ -1412,7 +1916,8 @@ fn _withdraw_obligation_collateral<'a>(
         let max_outflow_usd = lending_market
             .rate_limiter
             .clone() // remaining_outflow is a mutable call, but we don't have mutable access here
-            .remaining_outflow(clock.slot)?;
+            .remaining_outflow(clock.slot)?
+            .try_add(market_outflow_exemption)?;
 
         let max_lending_market_outflow_liquidity_amount = withdraw_reserve
             .usd_to_liquidity_amount_lower_bound(min(
@@ -1441,13 +1946,42 @@ fn _withdraw_obligation_collateral<'a>(
     };
 
     let max_withdraw_amount = obligation.max_withdraw_amount(collateral, &withdraw_reserve)?;
+    // max withdraws stop at the health buffer, other amounts past it fail
+    let buffered_max_withdraw_amount = obligation.max_withdraw_amount_within(
+        collateral,
+        &withdraw_reserve,
+        obligation.buffered_allowed_borrow_value(health_buffer)?,
+    )?;
+    if collateral_amount != u64::MAX
+        && buffered_max_withdraw_amount < max_withdraw_amount
+        && collateral_amount > buffered_max_withdraw_amount
+    {
+        msg!("Withdraw would leave the obligation within the lending market's health buffer");
+        log_error_context(
+            LendingError::HealthBufferBreached,
+            &[
+                ("amount", &collateral_amount),
+                ("max_withdraw_amount", &buffered_max_withdraw_amount),
+                ("health_buffer", &health_buffer),
+            ],
+        );
+        return Err(LendingError::HealthBufferBreached.into());
+    }
     let withdraw_amount = min(
         collateral_amount,
-        min(max_withdraw_amount, max_outflow_collateral_amount),
+        min(buffered_max_withdraw_amount, max_outflow_collateral_amount),
     );
 
     if withdraw_amount == 0 {
         msg!("Maximum withdraw value is zero");
+        log_error_context(
+            LendingError::WithdrawTooLarge,
+            &[
+                ("amount", &collateral_amount),
+                ("max_withdraw_amount", &buffered_max_withdraw_amount),
+                ("max_outflow_amount", &max_outflow_collateral_amount),
+            ],
+        );
         return Err(LendingError::WithdrawTooLarge.into());
     }
 
@@ -1471,6 +2005,7 @@ fn _withdraw_obligation_collateral<'a>(
 fn process_borrow_obligation_liquidity(
     program_id: &Pubkey,
     liquidity_amount: u64,
+    rate_mode: BorrowRateMode,
     accounts: &[AccountInfo],
 ) -> ProgramResult {
     if liquidity_amount == 0 {
@@ -1530,6 +2065,10 @@ fn process_borrow_obligation_liquidity(
         msg!("Borrow reserve is stale and must be refreshed in the current slot");
         return Err(LendingError::ReserveStale.into());
     }
+    if !borrow_reserve.status.is_active() {
+        msg!("Cannot borrow from a reserve that is winding down or closed");
+        return Err(LendingError::ReserveNotActive.into());
+    }
     if liquidity_amount != u64::MAX
         && Decimal::from(liquidity_amount)
             .try_add(borrow_reserve.liquidity.borrowed_amount_wads)?
@@ -1537,6 +2076,14 @@ fn process_borrow_obligation_liquidity(
             > borrow_reserve.config.borrow_limit
     {
         msg!("Cannot borrow above the borrow limit");
+        log_error_context(
+            LendingError::InvalidAmount,
+            &[
+                ("amount", &liquidity_amount),
+                ("borrowed", &borrow_reserve.liquidity.borrowed_amount_wads),
+                ("borrow_limit", &borrow_reserve.config.borrow_limit),
+            ],
+        );
         return Err(LendingError::InvalidAmount.into());
     }
 
@@ -1549,14 +2096,66 @@ fn process_borrow_obligation_liquidity(
         msg!("Obligation lending market does not match the lending market provided");
         return Err(LendingError::InvalidAccountInput.into());
     }
-    if &obligation.owner != obligation_owner_info.key {
-        msg!("Obligation owner does not match the obligation owner provided");
-        return Err(LendingError::InvalidObligationOwner.into());
-    }
+    let optional_accounts = &accounts[accounts.len() - account_info_iter.len()..];
+    check_market_policy(
+        program_id,
+        lending_market_info,
+        obligation_info,
+        borrow_reserve_info,
+        obligation_owner_info,
+        PolicyAction::Borrow,
+        liquidity_amount,
+        optional_accounts,
+    )?;
+    // the host fee receiver is the only token account and keeps the index it always had
+    let host_fee_receiver_info = optional_accounts
+        .first()
+        .filter(|account_info| account_info.owner == token_program_id.key);
+    // a signer other than the obligation owner borrows against its allowance
+    let borrow_allowances = if &obligation.owner != obligation_owner_info.key {
+        match find_derived_account::<BorrowAllowances>(
+            program_id,
+            obligation_info.key,
+            optional_accounts,
+            false,
+        )? {
+            Some((borrow_allowances_info, borrow_allowances))
+                if borrow_allowances
+                    .find_allowance(*obligation_owner_info.key, *borrow_reserve_info.key)
+                    .is_some() =>
+            {
+                Some((borrow_allowances_info, borrow_allowances))
+            }
+            _ => {
+                msg!("Obligation owner does not match the obligation owner provided and no borrow allowance was approved");
+                return Err(LendingError::InvalidObligationOwner.into());
+            }
+        }
+    } else {
+        None
+    };
     if !obligation_owner_info.is_signer {
         msg!("Obligation owner provided must be a signer");
         return Err(LendingError::InvalidSigner.into());
     }
+    // the calling program is read from the instructions sysvar and the signer has signed, so
+    // neither can be spoofed by passing other accounts
+    let instructions_sysvar_info = optional_accounts
+        .iter()
+        .find(|account_info| account_info.key == &sysvar::instructions::ID);
+    let fee_rebates = find_derived_account::<FeeRebates>(
+        program_id,
+        lending_market_info.key,
+        optional_accounts,
+        false,
+    )?;
+    let fee_rebate_bps = match (instructions_sysvar_info, fee_rebates) {
+        (Some(instructions_sysvar_info), Some((_, fee_rebates))) => fee_rebates.rebate_bps(
+            calling_program(program_id, instructions_sysvar_info)?,
+            *obligation_owner_info.key,
+        ),
+        _ => 0,
+    };
     if obligation.last_update.is_stale(clock.slot)? {
         msg!("Obligation is stale and must be refreshed in the current slot");
         return Err(LendingError::ObligationStale.into());
@@ -1610,17 +2209,71 @@ fn process_borrow_obligation_liquidity(
         }
     };
 
-    let remaining_borrow_value = obligation
+    let obligation_borrow_cap = find_derived_account::<ObligationBorrowCap>(
+        program_id,
+        obligation_info.key,
+        optional_accounts,
+        obligation.has_borrow_cap,
+    )?
+    .map(|(_, obligation_borrow_cap)| obligation_borrow_cap);
+    let mut remaining_borrow_value = obligation
         .remaining_borrow_value()
         .unwrap_or_else(|_| Decimal::zero());
+    // max borrows stop at the cap, other amounts above it fail once the borrow is valued
+    if let (Some(obligation_borrow_cap), u64::MAX) = (&obligation_borrow_cap, liquidity_amount) {
+        remaining_borrow_value = min(
+            remaining_borrow_value,
+            obligation_borrow_cap.remaining_borrow_value(obligation.borrowed_value),
+        );
+    }
+    let obligation_limits = find_derived_account::<ObligationLimits>(
+        program_id,
+        lending_market_info.key,
+        optional_accounts,
+        lending_market.has_obligation_limits,
+    )?
+    .map(|(_, obligation_limits)| obligation_limits);
+    let health_buffer = obligation_limits
+        .as_ref()
+        .map_or(Bps(0), |obligation_limits| {
+            obligation_limits.health_buffer()
+        });
+    let buffered_remaining_borrow_value = obligation
+        .buffered_allowed_borrow_value(health_buffer)?
+        .try_sub(obligation.borrowed_value_upper_bound)
+        .unwrap_or_else(|_| Decimal::zero());
+    // same as the cap, max borrows stop at the health buffer
+    if liquidity_amount == u64::MAX && buffered_remaining_borrow_value < remaining_borrow_value {
+        if buffered_remaining_borrow_value == Decimal::zero() {
+            msg!("Obligation is already within the lending market's health buffer");
+            log_error_context(
+                LendingError::HealthBufferBreached,
+                &[
+                    ("borrowed_value", &obligation.borrowed_value_upper_bound),
+                    ("allowed_borrow_value", &obligation.allowed_borrow_value),
+                    ("health_buffer", &health_buffer),
+                ],
+            );
+            return Err(LendingError::HealthBufferBreached.into());
+        }
+        remaining_borrow_value = buffered_remaining_borrow_value;
+    }
     if remaining_borrow_value == Decimal::zero() {
         msg!("Remaining borrow value is zero");
+        log_error_context(
+            LendingError::BorrowTooLarge,
+            &[
+                ("borrowed_value", &obligation.borrowed_value),
+                ("allowed_borrow_value", &obligation.allowed_borrow_value),
+            ],
+        );
         return Err(LendingError::BorrowTooLarge.into());
     }
 
     let remaining_reserve_capacity = Decimal::from(borrow_reserve.config.borrow_limit)
         .try_sub(borrow_reserve.liquidity.borrowed_amount_wads)
-        .unwrap_or_else(|_| Decimal::zero());
+        .unwrap_or_else(|_| Decimal::zero())
+        .min(borrow_reserve.remaining_borrow_under_utilization_cap()?);
 
     // account for rate limiter restrictions when calculating max borrow amount.
     let max_outflow_liquidity_amount = {
@@ -1637,6 +2290,14 @@ fn process_borrow_obligation_liquidity(
         )
     };
 
+    let mut remaining_borrow_amount = min(remaining_reserve_capacity, max_outflow_liquidity_amount);
+    if let Some((_, borrow_allowances)) = &borrow_allowances {
+        let allowance = borrow_allowances
+            .find_allowance(*obligation_owner_info.key, *borrow_reserve_info.key)
+            .ok_or(LendingError::InsufficientBorrowAllowance)?;
+        remaining_borrow_amount = min(remaining_borrow_amount, Decimal::from(allowance.amount));
+    }
+
     let CalculateBorrowResult {
         borrow_amount,
         receive_amount,
@@ -1645,7 +2306,8 @@ fn process_borrow_obligation_liquidity(
     } = borrow_reserve.calculate_borrow(
         liquidity_amount,
         remaining_borrow_value,
-        min(remaining_reserve_capacity, max_outflow_liquidity_amount),
+        remaining_borrow_amount,
+        fee_rebate_bps,
     )?;
 
     if receive_amount == 0 {
@@ -1653,6 +2315,45 @@ fn process_borrow_obligation_liquidity(
         return Err(LendingError::BorrowTooSmall.into());
     }
 
+    // valued the way calculate_borrow checks the remaining borrow value
+    if liquidity_amount != u64::MAX
+        && borrow_reserve
+            .market_value_upper_bound(borrow_amount)?
+            .try_mul(borrow_reserve.borrow_weight())?
+            > buffered_remaining_borrow_value
+    {
+        msg!("Borrow would leave the obligation within the lending market's health buffer");
+        log_error_context(
+            LendingError::HealthBufferBreached,
+            &[
+                ("amount", &borrow_amount),
+                ("remaining_borrow_value", &buffered_remaining_borrow_value),
+                ("health_buffer", &health_buffer),
+            ],
+        );
+        return Err(LendingError::HealthBufferBreached.into());
+    }
+
+    if let Some(obligation_borrow_cap) = &obligation_borrow_cap {
+        obligation_borrow_cap.check_borrowed_value(
+            obligation
+                .borrowed_value
+                .try_add(borrow_reserve.market_value(borrow_amount)?)?,
+        )?;
+    }
+
+    if let Some((borrow_allowances_info, mut borrow_allowances)) = borrow_allowances {
+        borrow_allowances.use_allowance(
+            *obligation_owner_info.key,
+            *borrow_reserve_info.key,
+            borrow_amount.try_ceil_u64()?,
+        )?;
+        BorrowAllowances::pack(
+            borrow_allowances,
+            &mut borrow_allowances_info.data.borrow_mut(),
+        )?;
+    }
+
     let cumulative_borrow_rate_wads = borrow_reserve.liquidity.cumulative_borrow_rate_wads;
 
     // check outflow rate limits
@@ -1677,21 +2378,115 @@ fn process_borrow_obligation_liquidity(
             })?;
     }
 
+    // borrowing back what was repaid mustn't leave credit to withdraw past the outflow limit
+    if let Some((deleverage_credit_info, mut deleverage_credit)) =
+        find_derived_account::<DeleverageCredit>(
+            program_id,
+            obligation_info.key,
+            optional_accounts,
+            obligation.has_deleverage_credit,
+        )?
+    {
+        deleverage_credit.record_borrow(
+            lending_market.rate_limiter.window_start_at(clock.slot),
+            borrow_reserve.market_value_upper_bound(borrow_amount)?,
+        )?;
+        DeleverageCredit::pack(
+            deleverage_credit,
+            &mut deleverage_credit_info.data.borrow_mut(),
+        )?;
+    }
+
     LendingMarket::pack(lending_market, &mut lending_market_info.data.borrow_mut())?;
 
+    let borrowed_before = borrow_reserve
+        .liquidity
+        .borrowed_amount_wads
+        .try_floor_u64()?;
     borrow_reserve.liquidity.borrow(borrow_amount)?;
+    emit_reserve_limit_event(
+        *borrow_reserve_info.key,
+        ReserveLimitKind::Borrow,
+        borrowed_before,
+        borrow_reserve
+            .liquidity
+            .borrowed_amount_wads
+            .try_floor_u64()?,
+        borrow_reserve.config.borrow_limit,
+    );
+    if borrow_reserve.config.borrow_utilization_cap != 0
+        && borrow_reserve.liquidity.utilization_rate()?
+            > Rate::from(borrow_reserve.config.borrow_utilization_cap())
+    {
+        msg!("Borrow would push the reserve utilization above its borrow utilization cap");
+        log_error_context(
+            LendingError::BorrowUtilizationCapExceeded,
+            &[
+                ("amount", &borrow_amount),
+                ("utilization", &borrow_reserve.liquidity.utilization_rate()?),
+                (
+                    "borrow_utilization_cap",
+                    &Rate::from(borrow_reserve.config.borrow_utilization_cap()),
+                ),
+            ],
+        );
+        return Err(LendingError::BorrowUtilizationCapExceeded.into());
+    }
     borrow_reserve.last_update.mark_stale();
-    Reserve::pack(borrow_reserve, &mut borrow_reserve_info.data.borrow_mut())?;
 
-    let obligation_liquidity = obligation
+    let borrows_len = obligation.borrows.len();
+    obligation
         .find_or_add_liquidity_to_borrows(*borrow_reserve_info.key, cumulative_borrow_rate_wads)?;
+    let (_, liquidity_index) = obligation.find_liquidity_in_borrows(*borrow_reserve_info.key)?;
 
-    obligation_liquidity.borrow(borrow_amount)?;
+    match rate_mode {
+        BorrowRateMode::Fixed => {
+            let interest_per_year = obligation.borrows[liquidity_index].borrow_fixed_rate(
+                borrow_amount,
+                &borrow_reserve,
+                clock.slot,
+            )?;
+            borrow_reserve
+                .liquidity
+                .borrow_fixed_rate(borrow_amount, interest_per_year)?;
+        }
+        BorrowRateMode::Variable => {
+            obligation.borrows[liquidity_index]
+                .convert_matured_fixed_rate(&mut borrow_reserve, clock.slot)?;
+            let grace_slots = find_derived_account::<BorrowGraceConfig>(
+                program_id,
+                borrow_reserve_info.key,
+                optional_accounts,
+                false,
+            )?
+            .map_or(0, |(_, borrow_grace_config)| {
+                borrow_grace_config.grace_slots
+            });
+            obligation.borrow_variable_rate(
+                liquidity_index,
+                borrow_amount,
+                &mut borrow_reserve,
+                clock.slot,
+                grace_slots,
+            )?;
+        }
+        BorrowRateMode::InterestFree => {
+            msg!("Interest-free borrows are only opened by the reserve's grace period");
+            return Err(LendingError::BorrowRateModeMismatch.into());
+        }
+    }
+    Reserve::pack(borrow_reserve, &mut borrow_reserve_info.data.borrow_mut())?;
+    // only new positions count against the limits, so lowering them doesn't lock anyone in
+    if obligation.borrows.len() > borrows_len {
+        if let Some(obligation_limits) = &obligation_limits {
+            obligation_limits.check_borrows(obligation.borrows.len())?;
+        }
+    }
     obligation.last_update.mark_stale();
     Obligation::pack(obligation, &mut obligation_info.data.borrow_mut())?;
 
     let mut owner_fee = borrow_fee;
-    if let Ok(host_fee_receiver_info) = next_account_info(account_info_iter) {
+    if let Some(host_fee_receiver_info) = host_fee_receiver_info {
         if host_fee > 0 {
             owner_fee = owner_fee
                 .checked_sub(host_fee)
@@ -1752,6 +2547,7 @@ fn process_repay_obligation_liquidity(
         next_account_info(account_info_iter)?;
     }
     let token_program_id = next_account_info(account_info_iter)?;
+    let optional_accounts = &accounts[accounts.len() - account_info_iter.len()..];
 
     let lending_market = LendingMarket::unpack(&lending_market_info.data.borrow())?;
     if lending_market_info.owner != program_id {
@@ -1763,7 +2559,7 @@ fn process_repay_obligation_liquidity(
         return Err(LendingError::InvalidTokenProgram.into());
     }
 
-    _refresh_reserve_interest(program_id, repay_reserve_info, clock)?;
+    _refresh_reserve_interest(program_id, repay_reserve_info, lending_market_info, clock)?;
     let mut repay_reserve = Reserve::unpack(&repay_reserve_info.data.borrow())?;
     if repay_reserve_info.owner != program_id {
         msg!("Repay reserve provided is not owned by the lending program");
@@ -1797,14 +2593,25 @@ fn process_repay_obligation_liquidity(
     }
 
     let (liquidity, liquidity_index) =
-        obligation.find_liquidity_in_borrows_mut(*repay_reserve_info.key)?;
+        obligation.find_liquidity_in_borrows(*repay_reserve_info.key)?;
     if liquidity.borrowed_amount_wads == Decimal::zero() {
         msg!("Liquidity borrowed amount is zero");
         return Err(LendingError::ObligationLiquidityEmpty.into());
     }
+    // the grace interest is shared out by the slots since the obligation was last refreshed
+    if liquidity.rate_mode == BorrowRateMode::InterestFree
+        && obligation.last_update.is_stale(clock.slot)?
+    {
+        msg!("Obligation is stale and must be refreshed in the current slot to repay an interest-free borrow");
+        return Err(LendingError::ObligationStale.into());
+    }
 
     // refreshing specific borrow instead of checking obligation stale
-    liquidity.accrue_interest(repay_reserve.liquidity.cumulative_borrow_rate_wads)?;
+    obligation.accrue_borrow_interest(liquidity_index, &repay_reserve, clock.slot)?;
+    obligation.borrows[liquidity_index]
+        .convert_matured_fixed_rate(&mut repay_reserve, clock.slot)?;
+    obligation.settle_grace_interest(liquidity_index, &mut repay_reserve, clock.slot)?;
+    let liquidity = &obligation.borrows[liquidity_index];
 
     let CalculateRepayResult {
         settle_amount,
@@ -1813,15 +2620,56 @@ fn process_repay_obligation_liquidity(
 
     if repay_amount == 0 {
         msg!("Repay amount is too small to transfer liquidity");
+        log_error_context(
+            LendingError::RepayTooSmall,
+            &[
+                ("amount", &liquidity_amount),
+                ("borrowed", &liquidity.borrowed_amount_wads),
+            ],
+        );
         return Err(LendingError::RepayTooSmall.into());
     }
 
+    if let Some((deleverage_credit_info, mut deleverage_credit)) =
+        find_derived_account::<DeleverageCredit>(
+            program_id,
+            obligation_info.key,
+            optional_accounts,
+            false,
+        )?
+    {
+        let deleverage_config = find_derived_account::<DeleverageConfig>(
+            program_id,
+            lending_market_info.key,
+            optional_accounts,
+            false,
+        )?
+        .map_or_else(
+            || DeleverageConfig::new(*lending_market_info.key, 0, 0),
+            |(_, deleverage_config)| deleverage_config,
+        );
+        if deleverage_config.is_enabled() {
+            deleverage_credit.record_repayment(
+                lending_market.rate_limiter.window_start_at(clock.slot),
+                repay_reserve.market_value_lower_bound(Decimal::from(repay_amount))?,
+            )?;
+            DeleverageCredit::pack(
+                deleverage_credit,
+                &mut deleverage_credit_info.data.borrow_mut(),
+            )?;
+        }
+    }
+
+    repay_reserve
+        .liquidity
+        .settle_fixed_rate_borrow(liquidity, settle_amount)?;
     repay_reserve.liquidity.repay(repay_amount, settle_amount)?;
     repay_reserve.last_update.mark_stale();
     Reserve::pack(repay_reserve, &mut repay_reserve_info.data.borrow_mut())?;
 
     obligation.repay(settle_amount, liquidity_index)?;
     obligation.last_update.mark_stale();
+    let obligation_owner = obligation.owner;
     Obligation::pack(obligation, &mut obligation_info.data.borrow_mut())?;
 
     spl_token_transfer(TokenTransferParams {
@@ -1833,6 +2681,15 @@ fn process_repay_obligation_liquidity(
         token_program: token_program_id.clone(),
     })?;
 
+    LendingEvent::ObligationLiquidityRepaid {
+        obligation: *obligation_info.key,
+        owner: obligation_owner,
+        payer: *user_transfer_authority_info.key,
+        reserve: *repay_reserve_info.key,
+        amount: repay_amount,
+    }
+    .emit();
+
     Ok(())
 }
 
@@ -1840,6 +2697,7 @@ fn process_repay_obligation_liquidity(
 fn _liquidate_obligation<'a>(
     program_id: &Pubkey,
     liquidity_amount: u64,
+    max_repay_value: Option<Decimal>,
     source_liquidity_info: &AccountInfo<'a>,
     destination_collateral_info: &AccountInfo<'a>,
     repay_reserve_info: &AccountInfo<'a>,
@@ -1852,6 +2710,9 @@ fn _liquidate_obligation<'a>(
     user_transfer_authority_info: &AccountInfo<'a>,
     clock: &Clock,
     token_program_id: &AccountInfo<'a>,
+    liquidation_circuit_breaker_info: Option<&AccountInfo<'a>>,
+    liquidation_stats_info: Option<&AccountInfo<'a>>,
+    collateral_only: bool,
 ) -> Result<(u64, Decimal), ProgramError> {
     let lending_market = LendingMarket::unpack(&lending_market_info.data.borrow())?;
     if lending_market_info.owner != program_id {
@@ -1940,9 +2801,22 @@ fn _liquidate_obligation<'a>(
     }
     if obligation.borrowed_value < obligation.unhealthy_borrow_value {
         msg!("Obligation is healthy and cannot be liquidated");
+        log_error_context(
+            LendingError::ObligationHealthy,
+            &[
+                ("borrowed_value", &obligation.borrowed_value),
+                ("unhealthy_borrow_value", &obligation.unhealthy_borrow_value),
+            ],
+        );
         return Err(LendingError::ObligationHealthy.into());
     }
 
+    // the authority may be a PDA signing through invoke_signed, in which case its signature
+    // carries over into the token transfers below
+    if !user_transfer_authority_info.is_signer {
+        msg!("User transfer authority provided must be a signer");
+        return Err(LendingError::InvalidSigner.into());
+    }
     if let Some(liquidator) = lending_market.whitelisted_liquidator {
         if liquidator != *user_transfer_authority_info.key {
             msg!("Liquidator is not whitelisted");
@@ -1960,6 +2834,10 @@ fn _liquidate_obligation<'a>(
         msg!("Obligation borrow is not the first liquidity in the borrows list");
         return Err(LendingError::InvalidAccountInput.into());
     }
+    let liquidity_amount = match max_repay_value {
+        Some(max_repay_value) => liquidity.liquidity_amount_for_value(max_repay_value)?,
+        None => liquidity_amount,
+    };
 
     let (collateral, collateral_index) =
         obligation.find_collateral_in_deposits(*withdraw_reserve_info.key)?;
@@ -1967,6 +2845,10 @@ fn _liquidate_obligation<'a>(
         msg!("Obligation deposit value is zero");
         return Err(LendingError::ObligationCollateralEmpty.into());
     }
+    if collateral.liquidation_priority < obligation.max_liquidation_priority() {
+        msg!("Obligation collateral with a higher liquidation priority must be seized first");
+        return Err(LendingError::LiquidationPriorityViolation.into());
+    }
 
     let authority_signer_seeds = &[
         lending_market_info.key.as_ref(),
@@ -1986,27 +2868,83 @@ fn _liquidate_obligation<'a>(
         repay_amount,
         withdraw_amount,
         bonus_rate,
-    } = withdraw_reserve.calculate_liquidation(
-        liquidity_amount,
-        &obligation,
-        liquidity,
-        collateral,
-    )?;
+    } = if collateral_only {
+        withdraw_reserve.calculate_collateral_liquidation(
+            liquidity_amount,
+            &obligation,
+            liquidity,
+            collateral,
+        )?
+    } else {
+        withdraw_reserve.calculate_liquidation(
+            liquidity_amount,
+            &obligation,
+            liquidity,
+            collateral,
+        )?
+    };
 
-    if repay_amount == 0 {
-        msg!("Liquidation is too small to transfer liquidity");
+    if repay_amount == 0 || withdraw_amount == 0 {
+        if repay_amount == 0 {
+            msg!("Liquidation is too small to transfer liquidity");
+        } else {
+            msg!("Liquidation is too small to receive collateral");
+        }
+        log_error_context(
+            LendingError::LiquidationTooSmall,
+            &[
+                ("amount", &liquidity_amount),
+                ("repay_amount", &repay_amount),
+                ("withdraw_amount", &withdraw_amount),
+            ],
+        );
         return Err(LendingError::LiquidationTooSmall.into());
     }
-    if withdraw_amount == 0 {
-        msg!("Liquidation is too small to receive collateral");
-        return Err(LendingError::LiquidationTooSmall.into());
+
+    // markets with a whitelisted liquidator only let that liquidator through already
+    if let (None, Some(liquidation_circuit_breaker_info)) = (
+        lending_market.whitelisted_liquidator,
+        liquidation_circuit_breaker_info,
+    ) {
+        if let Some(mut breaker) = unpack_derived_account::<LiquidationCircuitBreaker>(
+            program_id,
+            lending_market_info.key,
+            liquidation_circuit_breaker_info,
+        )? {
+            breaker.record_liquidation(clock.slot, repay_reserve.market_value(settle_amount)?)?;
+            LiquidationCircuitBreaker::pack(
+                breaker,
+                &mut liquidation_circuit_breaker_info.data.borrow_mut(),
+            )?;
+        }
+    }
+    if let Some(liquidation_stats_info) = liquidation_stats_info {
+        record_liquidation_stats(
+            program_id,
+            lending_market_info,
+            liquidation_stats_info,
+            clock.slot,
+            repay_reserve.market_value(repay_amount.into())?,
+            withdraw_reserve.market_value(
+                withdraw_reserve
+                    .collateral_exchange_rate()?
+                    .decimal_collateral_to_liquidity(withdraw_amount.into())?,
+            )?,
+        )?;
     }
 
+    repay_reserve
+        .liquidity
+        .settle_fixed_rate_borrow(&obligation.borrows[liquidity_index], settle_amount)?;
     repay_reserve.liquidity.repay(repay_amount, settle_amount)?;
+    obligation.repay(settle_amount, liquidity_index)?;
+    if let Ok((_, liquidity_index)) = obligation.find_liquidity_in_borrows(*repay_reserve_info.key)
+    {
+        obligation.settle_grace_interest(liquidity_index, &mut repay_reserve, clock.slot)?;
+    }
     repay_reserve.last_update.mark_stale();
     Reserve::pack(repay_reserve, &mut repay_reserve_info.data.borrow_mut())?;
 
-    obligation.repay(settle_amount, liquidity_index)?;
     obligation.withdraw(withdraw_amount, collateral_index)?;
     obligation.last_update.mark_stale();
     Obligation::pack(obligation, &mut obligation_info.data.borrow_mut())?;
@@ -2036,9 +2974,10 @@ fn _liquidate_obligation<'a>(
 fn process_liquidate_obligation_and_redeem_reserve_collateral(
     program_id: &Pubkey,
     liquidity_amount: u64,
+    max_repay_value: Option<Decimal>,
     accounts: &[AccountInfo],
 ) -> ProgramResult {
-    if liquidity_amount == 0 {
+    if liquidity_amount == 0 || max_repay_value == Some(Decimal::zero()) {
         msg!("Liquidity amount provided cannot be zero");
         return Err(LendingError::InvalidAmount.into());
     }
@@ -2059,11 +2998,37 @@ fn process_liquidate_obligation_and_redeem_reserve_collateral(
     let lending_market_authority_info = next_account_info(account_info_iter)?;
     let user_transfer_authority_info = next_account_info(account_info_iter)?;
     let token_program_id = next_account_info(account_info_iter)?;
+    let optional_accounts = &accounts[accounts.len() - account_info_iter.len()..];
+    check_market_policy(
+        program_id,
+        lending_market_info,
+        obligation_info,
+        repay_reserve_info,
+        user_transfer_authority_info,
+        PolicyAction::Liquidate,
+        liquidity_amount,
+        optional_accounts,
+    )?;
+    let liquidation_stats_info = find_derived_account::<LiquidationStats>(
+        program_id,
+        lending_market_info.key,
+        optional_accounts,
+        false,
+    )?
+    .map(|(liquidation_stats_info, _)| liquidation_stats_info);
+    let liquidation_circuit_breaker_info = find_derived_account::<LiquidationCircuitBreaker>(
+        program_id,
+        lending_market_info.key,
+        optional_accounts,
+        LendingMarket::unpack(&lending_market_info.data.borrow())?.has_liquidation_circuit_breaker,
+    )?
+    .map(|(liquidation_circuit_breaker_info, _)| liquidation_circuit_breaker_info);
     let clock = &Clock::get()?;
 
     let (withdrawn_collateral_amount, bonus_rate) = _liquidate_obligation(
         program_id,
         liquidity_amount,
+        max_repay_value,
         source_liquidity_info,
         destination_collateral_info,
         repay_reserve_info,
@@ -2076,9 +3041,17 @@ fn process_liquidate_obligation_and_redeem_reserve_collateral(
         user_transfer_authority_info,
         clock,
         token_program_id,
+        liquidation_circuit_breaker_info,
+        liquidation_stats_info,
+        false,
     )?;
 
-    _refresh_reserve_interest(program_id, withdraw_reserve_info, clock)?;
+    _refresh_reserve_interest(
+        program_id,
+        withdraw_reserve_info,
+        lending_market_info,
+        clock,
+    )?;
     let withdraw_reserve = Reserve::unpack(&withdraw_reserve_info.data.borrow())?;
     let collateral_exchange_rate = withdraw_reserve.collateral_exchange_rate()?;
     let max_redeemable_collateral = collateral_exchange_rate
@@ -2100,11 +3073,14 @@ fn process_liquidate_obligation_and_redeem_reserve_collateral(
             clock,
             token_program_id,
             false,
+            &mut Decimal::zero(),
+            None,
         )?;
         let withdraw_reserve = Reserve::unpack(&withdraw_reserve_info.data.borrow())?;
-        if &withdraw_reserve.config.fee_receiver != withdraw_reserve_liquidity_fee_receiver_info.key
+        if &withdraw_reserve.config.liquidation_fee_receiver()
+            != withdraw_reserve_liquidity_fee_receiver_info.key
         {
-            msg!("Withdraw reserve liquidity fee receiver does not match the reserve liquidity fee receiver provided");
+            msg!("Withdraw reserve liquidation fee receiver does not match the reserve liquidation fee receiver provided");
             return Err(LendingError::InvalidAccountInput.into());
         }
         let protocol_fee = withdraw_reserve
@@ -2120,38 +3096,249 @@ fn process_liquidate_obligation_and_redeem_reserve_collateral(
         })?;
     }
 
+    // the obligation is only closed if its owner is passed to refund the rent to
+    let obligation_owner = Obligation::unpack(&obligation_info.data.borrow())?.owner;
+    if let Some(obligation_owner_info) = optional_accounts
+        .iter()
+        .find(|account_info| account_info.key == &obligation_owner)
+    {
+        close_liquidated_obligation(
+            program_id,
+            obligation_info,
+            obligation_owner_info,
+            optional_accounts,
+        )?;
+    }
+
     Ok(())
 }
 
-#[inline(never)] // avoid stack frame limit
-fn process_withdraw_obligation_collateral_and_redeem_reserve_liquidity(
+/// Closes an obligation a liquidation left without deposits or borrows, refunding its rent and
+/// that of the accounts derived from it to the obligation owner. Does nothing if the obligation
+/// isn't empty or the derived accounts aren't all passed, so liquidators can always ask for it.
+fn close_liquidated_obligation(
     program_id: &Pubkey,
-    collateral_amount: u64,
-    accounts: &[AccountInfo],
+    obligation_info: &AccountInfo,
+    obligation_owner_info: &AccountInfo,
+    optional_accounts: &[AccountInfo],
 ) -> ProgramResult {
-    let account_info_iter = &mut accounts.iter().peekable();
-    let reserve_collateral_info = next_account_info(account_info_iter)?;
-    let user_collateral_info = next_account_info(account_info_iter)?;
-    let reserve_info = next_account_info(account_info_iter)?;
-    let obligation_info = next_account_info(account_info_iter)?;
-    let lending_market_info = next_account_info(account_info_iter)?;
-    let lending_market_authority_info = next_account_info(account_info_iter)?;
-    let user_liquidity_info = next_account_info(account_info_iter)?;
-    let reserve_collateral_mint_info = next_account_info(account_info_iter)?;
-    let reserve_liquidity_supply_info = next_account_info(account_info_iter)?;
-    let obligation_owner_info = next_account_info(account_info_iter)?;
-    let user_transfer_authority_info = next_account_info(account_info_iter)?;
-    let clock = &Clock::get()?;
-    if account_info_iter.peek().map(|a| a.key) == Some(&clock::ID) {
-        next_account_info(account_info_iter)?;
+    let obligation = Obligation::unpack(&obligation_info.data.borrow())?;
+    if &obligation.owner != obligation_owner_info.key {
+        msg!("Obligation owner does not match the obligation owner provided");
+        return Err(LendingError::InvalidObligationOwner.into());
+    }
+    if !obligation.deposits.is_empty() || !obligation.borrows.is_empty() {
+        return Ok(());
     }
-    let token_program_id = next_account_info(account_info_iter)?;
 
-    let liquidity_amount = _withdraw_obligation_collateral(
+    let derived_accounts = match find_obligation_derived_accounts(
         program_id,
-        collateral_amount,
-        reserve_collateral_info,
-        user_collateral_info,
+        obligation_info.key,
+        optional_accounts,
+    ) {
+        Some(derived_accounts) => derived_accounts,
+        None => {
+            msg!("Obligation left open, the accounts derived from it were not provided");
+            return Ok(());
+        }
+    };
+    close_obligation(
+        program_id,
+        obligation_info,
+        &obligation.owner,
+        obligation_owner_info,
+        &derived_accounts,
+    )
+}
+
+#[inline(never)] // avoid stack frame limit
+fn process_liquidate_obligation_for_collateral(
+    program_id: &Pubkey,
+    liquidity_amount: u64,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    if liquidity_amount == 0 {
+        msg!("Liquidity amount provided cannot be zero");
+        return Err(LendingError::InvalidAmount.into());
+    }
+
+    let account_info_iter = &mut accounts.iter();
+    let source_liquidity_info = next_account_info(account_info_iter)?;
+    let destination_collateral_info = next_account_info(account_info_iter)?;
+    let repay_reserve_info = next_account_info(account_info_iter)?;
+    let repay_reserve_liquidity_supply_info = next_account_info(account_info_iter)?;
+    let withdraw_reserve_info = next_account_info(account_info_iter)?;
+    let withdraw_reserve_collateral_mint_info = next_account_info(account_info_iter)?;
+    let withdraw_reserve_collateral_supply_info = next_account_info(account_info_iter)?;
+    let obligation_info = next_account_info(account_info_iter)?;
+    let lending_market_info = next_account_info(account_info_iter)?;
+    let lending_market_authority_info = next_account_info(account_info_iter)?;
+    let user_transfer_authority_info = next_account_info(account_info_iter)?;
+    let token_program_id = next_account_info(account_info_iter)?;
+    let optional_accounts = &accounts[accounts.len() - account_info_iter.len()..];
+    check_market_policy(
+        program_id,
+        lending_market_info,
+        obligation_info,
+        repay_reserve_info,
+        user_transfer_authority_info,
+        PolicyAction::Liquidate,
+        liquidity_amount,
+        optional_accounts,
+    )?;
+    let liquidation_stats_info = find_derived_account::<LiquidationStats>(
+        program_id,
+        lending_market_info.key,
+        optional_accounts,
+        false,
+    )?
+    .map(|(liquidation_stats_info, _)| liquidation_stats_info);
+    let liquidation_circuit_breaker_info = find_derived_account::<LiquidationCircuitBreaker>(
+        program_id,
+        lending_market_info.key,
+        optional_accounts,
+        LendingMarket::unpack(&lending_market_info.data.borrow())?.has_liquidation_circuit_breaker,
+    )?
+    .map(|(liquidation_circuit_breaker_info, _)| liquidation_circuit_breaker_info);
+    let clock = &Clock::get()?;
+
+    let (withdrawn_collateral_amount, bonus_rate) = _liquidate_obligation(
+        program_id,
+        liquidity_amount,
+        None,
+        source_liquidity_info,
+        destination_collateral_info,
+        repay_reserve_info,
+        repay_reserve_liquidity_supply_info,
+        withdraw_reserve_info,
+        withdraw_reserve_collateral_supply_info,
+        obligation_info,
+        lending_market_info,
+        lending_market_authority_info,
+        user_transfer_authority_info,
+        clock,
+        token_program_id,
+        liquidation_circuit_breaker_info,
+        liquidation_stats_info,
+        true,
+    )?;
+
+    let mut withdraw_reserve = Reserve::unpack(&withdraw_reserve_info.data.borrow())?;
+    if &withdraw_reserve.collateral.mint_pubkey != withdraw_reserve_collateral_mint_info.key {
+        msg!("Withdraw reserve collateral mint does not match the withdraw reserve collateral mint provided");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+
+    // the protocol fee is burned out of the seized cTokens and its liquidity value is moved into
+    // the accumulated protocol fees, which leaves the collateral exchange rate unchanged. no
+    // liquidity leaves the reserve, so the outflow rate limiters aren't touched.
+    let protocol_fee = min(
+        withdraw_reserve
+            .calculate_protocol_liquidation_fee(withdrawn_collateral_amount, bonus_rate)?,
+        withdrawn_collateral_amount,
+    );
+    let protocol_fee_liquidity = withdraw_reserve
+        .collateral_exchange_rate()?
+        .decimal_collateral_to_liquidity(protocol_fee.into())?;
+
+    withdraw_reserve.collateral.burn(protocol_fee)?;
+    withdraw_reserve.liquidity.accumulated_protocol_fees_wads = withdraw_reserve
+        .liquidity
+        .accumulated_protocol_fees_wads
+        .try_add(protocol_fee_liquidity)?;
+    withdraw_reserve.last_update.mark_stale();
+    Reserve::pack(
+        withdraw_reserve,
+        &mut withdraw_reserve_info.data.borrow_mut(),
+    )?;
+
+    spl_token_burn(TokenBurnParams {
+        mint: withdraw_reserve_collateral_mint_info.clone(),
+        source: destination_collateral_info.clone(),
+        amount: protocol_fee,
+        authority: user_transfer_authority_info.clone(),
+        authority_signer_seeds: &[],
+        token_program: token_program_id.clone(),
+    })?;
+
+    Ok(())
+}
+
+#[inline(never)] // avoid stack frame limit
+fn process_withdraw_obligation_collateral_and_redeem_reserve_liquidity(
+    program_id: &Pubkey,
+    collateral_amount: u64,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter().peekable();
+    let reserve_collateral_info = next_account_info(account_info_iter)?;
+    let user_collateral_info = next_account_info(account_info_iter)?;
+    let reserve_info = next_account_info(account_info_iter)?;
+    let obligation_info = next_account_info(account_info_iter)?;
+    let lending_market_info = next_account_info(account_info_iter)?;
+    let lending_market_authority_info = next_account_info(account_info_iter)?;
+    let user_liquidity_info = next_account_info(account_info_iter)?;
+    let reserve_collateral_mint_info = next_account_info(account_info_iter)?;
+    let reserve_liquidity_supply_info = next_account_info(account_info_iter)?;
+    let obligation_owner_info = next_account_info(account_info_iter)?;
+    let user_transfer_authority_info = next_account_info(account_info_iter)?;
+    let clock = &Clock::get()?;
+    if account_info_iter.peek().map(|a| a.key) == Some(&clock::ID) {
+        next_account_info(account_info_iter)?;
+    }
+    let token_program_id = next_account_info(account_info_iter)?;
+    let optional_accounts = &accounts[accounts.len() - account_info_iter.len()..];
+    check_market_policy(
+        program_id,
+        lending_market_info,
+        obligation_info,
+        reserve_info,
+        obligation_owner_info,
+        PolicyAction::Withdraw,
+        collateral_amount,
+        optional_accounts,
+    )?;
+    let health_buffer = find_health_buffer(program_id, lending_market_info, optional_accounts)?;
+    // the fee receiver is the only token account and keeps the index it always had
+    let reserve_liquidity_fee_receiver_info = optional_accounts
+        .first()
+        .filter(|account_info| account_info.owner == token_program_id.key);
+
+    let window_start = LendingMarket::unpack(&lending_market_info.data.borrow())?
+        .rate_limiter
+        .window_start_at(clock.slot);
+    let deleverage_credit = match find_derived_account::<DeleverageCredit>(
+        program_id,
+        obligation_info.key,
+        optional_accounts,
+        false,
+    )? {
+        Some((deleverage_credit_info, deleverage_credit)) => {
+            let deleverage_config = find_derived_account::<DeleverageConfig>(
+                program_id,
+                lending_market_info.key,
+                optional_accounts,
+                false,
+            )?
+            .map_or_else(
+                || DeleverageConfig::new(*lending_market_info.key, 0, 0),
+                |(_, deleverage_config)| deleverage_config,
+            );
+            let exemption =
+                deleverage_credit.available_exemption(window_start, &deleverage_config)?;
+            Some((deleverage_credit_info, deleverage_credit, exemption))
+        }
+        None => None,
+    };
+    let market_outflow_exemption = deleverage_credit
+        .as_ref()
+        .map_or_else(Decimal::zero, |(_, _, exemption)| *exemption);
+
+    let liquidity_amount = _withdraw_obligation_collateral(
+        program_id,
+        collateral_amount,
+        reserve_collateral_info,
+        user_collateral_info,
         reserve_info,
         obligation_info,
         lending_market_info,
@@ -2160,8 +3347,11 @@ fn process_withdraw_obligation_collateral_and_redeem_reserve_liquidity(
         clock,
         token_program_id,
         true,
+        market_outflow_exemption,
+        health_buffer,
     )?;
 
+    let mut remaining_exemption = market_outflow_exemption;
     _redeem_reserve_collateral(
         program_id,
         liquidity_amount,
@@ -2176,7 +3366,20 @@ fn process_withdraw_obligation_collateral_and_redeem_reserve_liquidity(
         clock,
         token_program_id,
         true,
+        &mut remaining_exemption,
+        Some(reserve_liquidity_fee_receiver_info),
     )?;
+
+    if let Some((deleverage_credit_info, mut deleverage_credit, _)) = deleverage_credit {
+        deleverage_credit.use_exemption(
+            window_start,
+            market_outflow_exemption.try_sub(remaining_exemption)?,
+        )?;
+        DeleverageCredit::pack(
+            deleverage_credit,
+            &mut deleverage_credit_info.data.borrow_mut(),
+        )?;
+    }
     Ok(())
 }
 
@@ -2196,6 +3399,7 @@ fn process_update_reserve_config(
     let pyth_product_info = next_account_info(account_info_iter)?;
     let pyth_price_info = next_account_info(account_info_iter)?;
     let switchboard_feed_info = next_account_info(account_info_iter)?;
+    let optional_accounts = account_info_iter.as_slice();
 
     let mut reserve = Reserve::unpack(&reserve_info.data.borrow())?;
     if reserve_info.owner != program_id {
@@ -2235,6 +3439,10 @@ fn process_update_reserve_config(
             msg!("permissionless markets can't edit fee receiver");
             return Err(LendingError::InvalidConfig.into());
         }
+        if reserve.config.liquidation_fee_receiver != config.liquidation_fee_receiver {
+            msg!("permissionless markets can't edit liquidation fee receiver");
+            return Err(LendingError::InvalidConfig.into());
+        }
         if reserve.config.fees != config.fees {
             msg!("permissionless markets can't edit fee configs!");
             return Err(LendingError::InvalidConfig.into());
@@ -2261,28 +3469,64 @@ fn process_update_reserve_config(
     }
 
     if signer_info.key == &lending_market.owner {
-        // if window duration or max outflow are different, then create a new rate limiter instance.
-        if rate_limiter_config != reserve.rate_limiter.config {
-            reserve.rate_limiter = RateLimiter::new(rate_limiter_config, Clock::get()?.slot);
-        }
-
         if *pyth_price_info.key != reserve.liquidity.pyth_oracle_pubkey {
             validate_pyth_keys(&lending_market, pyth_product_info, pyth_price_info)?;
-            reserve.liquidity.pyth_oracle_pubkey = *pyth_price_info.key;
         }
 
-        if *switchboard_feed_info.key != reserve.liquidity.switchboard_oracle_pubkey {
+        // cross market reserves are validated on every change, so their source has to still be
+        // allowed
+        if switchboard_feed_info.owner == program_id {
+            validate_cross_market_source(
+                program_id,
+                lending_market_info.key,
+                &reserve.liquidity.mint_pubkey,
+                pyth_price_info,
+                switchboard_feed_info,
+                optional_accounts,
+            )?;
+        } else if *switchboard_feed_info.key != reserve.liquidity.switchboard_oracle_pubkey {
             validate_switchboard_keys(&lending_market, switchboard_feed_info)?;
-            reserve.liquidity.switchboard_oracle_pubkey = *switchboard_feed_info.key;
         }
-        if reserve.liquidity.switchboard_oracle_pubkey == solend_program::NULL_PUBKEY
-            && reserve.liquidity.pyth_oracle_pubkey == solend_program::NULL_PUBKEY
+        if *switchboard_feed_info.key == solend_program::NULL_PUBKEY
+            && *pyth_price_info.key == solend_program::NULL_PUBKEY
         {
             msg!("At least one price oracle must have a non-null pubkey");
             return Err(LendingError::InvalidOracleConfig.into());
         }
 
-        reserve.config = config;
+        let config_timelock_info = next_account_info(account_info_iter)?;
+        let clock = Clock::get()?;
+        if let Some(config_timelock) = unpack_derived_account::<ConfigTimelock>(
+            program_id,
+            lending_market_info.key,
+            config_timelock_info,
+        )?
+        .filter(|config_timelock| config_timelock.is_active())
+        {
+            let pending_config_info = next_account_info(account_info_iter)?;
+            stage_config_change(
+                program_id,
+                lending_market_info,
+                reserve_info.key,
+                pending_config_info,
+                signer_info,
+                config_timelock.activation_slot(clock.slot),
+                (*pyth_price_info.key, *switchboard_feed_info.key),
+                &LendingInstruction::UpdateReserveConfig {
+                    config,
+                    rate_limiter_config,
+                },
+            )?;
+        } else {
+            apply_reserve_config(
+                &mut reserve,
+                config,
+                rate_limiter_config,
+                *pyth_price_info.key,
+                *switchboard_feed_info.key,
+                clock.slot,
+            );
+        }
     } else if signer_info.key == &lending_market.risk_authority {
         // only can disable outflows
         if rate_limiter_config.window_duration > 0 && rate_limiter_config.max_outflow == 0 {
@@ -2298,6 +3542,10 @@ fn process_update_reserve_config(
         if config.deposit_limit < reserve.config.deposit_limit {
             reserve.config.deposit_limit = config.deposit_limit;
         }
+
+        // soft caps are only used for alerting
+        reserve.config.deposit_soft_cap_pct = config.deposit_soft_cap_pct;
+        reserve.config.borrow_soft_cap_pct = config.borrow_soft_cap_pct;
     } else if *signer_info.key == solend_market_owner::id()
     // 5ph has the ability to change the
     // fees on permissionless markets
@@ -2306,6 +3554,7 @@ fn process_update_reserve_config(
         reserve.config.protocol_liquidation_fee = config.protocol_liquidation_fee;
         reserve.config.protocol_take_rate = config.protocol_take_rate;
         reserve.config.fee_receiver = config.fee_receiver;
+        reserve.config.liquidation_fee_receiver = config.liquidation_fee_receiver;
     } else {
         msg!("Signer must be the Lending market owner or risk authority");
         return Err(LendingError::InvalidSigner.into());
@@ -2316,129 +3565,2285 @@ fn process_update_reserve_config(
     Ok(())
 }
 
-#[inline(never)] // avoid stack frame limit
-fn process_redeem_fees(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
-    let account_info_iter = &mut accounts.iter().peekable();
+/// Applies a reserve config change made by the lending market owner
+fn apply_reserve_config(
+    reserve: &mut Reserve,
+    config: ReserveConfig,
+    rate_limiter_config: RateLimiterConfig,
+    pyth_oracle: Pubkey,
+    switchboard_oracle: Pubkey,
+    slot: u64,
+) {
+    // if window duration or max outflow are different, then create a new rate limiter instance.
+    reserve.rate_limiter.set_config(rate_limiter_config, slot);
+
+    reserve.liquidity.pyth_oracle_pubkey = pyth_oracle;
+    reserve.liquidity.switchboard_oracle_pubkey = switchboard_oracle;
+    reserve.config = config;
+}
+
+fn process_wind_down_reserve(
+    program_id: &Pubkey,
+    ltv_decay_per_slot_wad: u64,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
     let reserve_info = next_account_info(account_info_iter)?;
-    let reserve_liquidity_fee_receiver_info = next_account_info(account_info_iter)?;
-    let reserve_supply_liquidity_info = next_account_info(account_info_iter)?;
     let lending_market_info = next_account_info(account_info_iter)?;
-    let lending_market_authority_info = next_account_info(account_info_iter)?;
-    let token_program_id = next_account_info(account_info_iter)?;
-    let clock = &Clock::get()?;
+    let signer_info = next_account_info(account_info_iter)?;
+    let clock = Clock::get()?;
 
-    let mut reserve = Reserve::unpack(&reserve_info.data.borrow())?;
-    if reserve_info.owner != program_id {
-        msg!(
-            "Reserve provided is not owned by the lending program {} != {}",
-            &reserve_info.owner.to_string(),
-            &program_id.to_string(),
-        );
-        return Err(LendingError::InvalidAccountOwner.into());
+    let mut reserve = unpack_reserve_for_status_update(
+        program_id,
+        reserve_info,
+        lending_market_info,
+        signer_info,
+    )?;
+
+    match reserve.status {
+        ReserveStatus::Active => {}
+        ReserveStatus::WindDown {
+            ltv_decay_per_slot_wad: current_ltv_decay_per_slot_wad,
+            ..
+        } => {
+            if ltv_decay_per_slot_wad < current_ltv_decay_per_slot_wad {
+                msg!("Loan to value ratio decay of a reserve winding down can only be increased");
+                return Err(LendingError::InvalidConfig.into());
+            }
+        }
+        ReserveStatus::Closed => {
+            msg!("Reserve is already closed");
+            return Err(LendingError::InvalidConfig.into());
+        }
     }
 
-    if &reserve.config.fee_receiver != reserve_liquidity_fee_receiver_info.key {
-        msg!("Reserve liquidity fee receiver does not match the reserve liquidity fee receiver provided");
-        return Err(LendingError::InvalidAccountInput.into());
+    // restart the decay from wherever the current one got to
+    let start_loan_to_value_ratio = reserve
+        .status
+        .loan_to_value_ratio(Rate::from(reserve.config.loan_to_value_ratio()), clock.slot);
+    reserve.status = ReserveStatus::WindDown {
+        start_slot: clock.slot,
+        start_loan_to_value_ratio_wad: start_loan_to_value_ratio.to_scaled_val() as u64,
+        ltv_decay_per_slot_wad,
+    };
+
+    reserve.last_update.mark_stale();
+    Reserve::pack(reserve, &mut reserve_info.data.borrow_mut())?;
+    Ok(())
+}
+
+fn process_close_reserve(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let reserve_info = next_account_info(account_info_iter)?;
+    let lending_market_info = next_account_info(account_info_iter)?;
+    let signer_info = next_account_info(account_info_iter)?;
+
+    let mut reserve = unpack_reserve_for_status_update(
+        program_id,
+        reserve_info,
+        lending_market_info,
+        signer_info,
+    )?;
+
+    if !matches!(reserve.status, ReserveStatus::WindDown { .. }) {
+        msg!("Only reserves that are winding down can be closed");
+        return Err(LendingError::InvalidConfig.into());
     }
-    if &reserve.liquidity.supply_pubkey != reserve_supply_liquidity_info.key {
-        msg!("Reserve liquidity supply must be used as the reserve supply liquidity provided");
-        return Err(LendingError::InvalidAccountInput.into());
+    reserve.status = ReserveStatus::Closed;
+
+    reserve.last_update.mark_stale();
+    Reserve::pack(reserve, &mut reserve_info.data.borrow_mut())?;
+    Ok(())
+}
+
+/// Unpacks a reserve whose status is being updated, checking that the signer is the lending
+/// market owner or risk authority.
+fn unpack_reserve_for_status_update(
+    program_id: &Pubkey,
+    reserve_info: &AccountInfo,
+    lending_market_info: &AccountInfo,
+    signer_info: &AccountInfo,
+) -> Result<Reserve, ProgramError> {
+    let reserve = Reserve::unpack(&reserve_info.data.borrow())?;
+    if reserve_info.owner != program_id {
+        msg!("Reserve provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
     }
     if &reserve.lending_market != lending_market_info.key {
         msg!("Reserve lending market does not match the lending market provided");
         return Err(LendingError::InvalidAccountInput.into());
     }
-    if reserve.last_update.is_stale(clock.slot)? {
-        msg!("reserve is stale and must be refreshed in the current slot");
-        return Err(LendingError::ReserveStale.into());
-    }
 
     let lending_market = LendingMarket::unpack(&lending_market_info.data.borrow())?;
     if lending_market_info.owner != program_id {
         msg!("Lending market provided is not owned by the lending program");
         return Err(LendingError::InvalidAccountOwner.into());
     }
-    if &lending_market.token_program_id != token_program_id.key {
-        msg!("Lending market token program does not match the token program provided");
-        return Err(LendingError::InvalidTokenProgram.into());
-    }
-    let authority_signer_seeds = &[
-        lending_market_info.key.as_ref(),
-        &[lending_market.bump_seed],
-    ];
-    let lending_market_authority_pubkey =
-        Pubkey::create_program_address(authority_signer_seeds, program_id)?;
-    if &lending_market_authority_pubkey != lending_market_authority_info.key {
-        msg!(
-            "Derived lending market authority does not match the lending market authority provided"
-        );
-        return Err(LendingError::InvalidMarketAuthority.into());
-    }
 
-    let withdraw_amount = reserve.calculate_redeem_fees()?;
-    if withdraw_amount == 0 {
-        return Err(LendingError::InsufficientProtocolFeesToRedeem.into());
+    if signer_info.key != &lending_market.owner && signer_info.key != &lending_market.risk_authority
+    {
+        msg!("Signer must be the Lending market owner or risk authority");
+        return Err(LendingError::InvalidSigner.into());
+    }
+    if !signer_info.is_signer {
+        msg!("Lending market owner or risk authority provided must be a signer");
+        return Err(LendingError::InvalidSigner.into());
     }
 
-    reserve.liquidity.redeem_fees(withdraw_amount)?;
-    reserve.last_update.mark_stale();
-    Reserve::pack(reserve, &mut reserve_info.data.borrow_mut())?;
-
-    spl_token_transfer(TokenTransferParams {
-        source: reserve_supply_liquidity_info.clone(),
-        destination: reserve_liquidity_fee_receiver_info.clone(),
-        amount: withdraw_amount,
-        authority: lending_market_authority_info.clone(),
-        authority_signer_seeds,
-        token_program: token_program_id.clone(),
-    })?;
-
-    Ok(())
+    Ok(reserve)
 }
 
-fn process_flash_borrow_reserve_liquidity(
+#[inline(never)] // avoid stack frame limit
+fn process_set_liquidation_circuit_breaker(
     program_id: &Pubkey,
-    liquidity_amount: u64,
+    config: RateLimiterConfig,
+    pause_duration: u64,
     accounts: &[AccountInfo],
 ) -> ProgramResult {
     let account_info_iter = &mut accounts.iter();
-    let source_liquidity_info = next_account_info(account_info_iter)?;
+    let lending_market_info = next_account_info(account_info_iter)?;
+    let liquidation_circuit_breaker_info = next_account_info(account_info_iter)?;
+    let lending_market_owner_info = next_account_info(account_info_iter)?;
+
+    let mut lending_market = LendingMarket::unpack(&lending_market_info.data.borrow())?;
+    if lending_market_info.owner != program_id {
+        msg!("Lending market provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+    if &lending_market.owner != lending_market_owner_info.key {
+        msg!("Lending market owner does not match the lending market owner provided");
+        return Err(LendingError::InvalidMarketOwner.into());
+    }
+    if !lending_market_owner_info.is_signer {
+        msg!("Lending market owner provided must be a signer");
+        return Err(LendingError::InvalidSigner.into());
+    }
+
+    let mut breaker = match unpack_derived_account::<LiquidationCircuitBreaker>(
+        program_id,
+        lending_market_info.key,
+        liquidation_circuit_breaker_info,
+    )? {
+        Some(breaker) => breaker,
+        None => {
+            msg!("Creating liquidation circuit breaker account");
+            let (_, bump_seed) = Pubkey::find_program_address(
+                &[
+                    lending_market_info.key.as_ref(),
+                    LIQUIDATION_CIRCUIT_BREAKER_SEED,
+                ],
+                program_id,
+            );
+            create_derived_account(
+                lending_market_owner_info,
+                liquidation_circuit_breaker_info,
+                LiquidationCircuitBreaker::LEN,
+                program_id,
+                &[
+                    lending_market_info.key.as_ref(),
+                    LIQUIDATION_CIRCUIT_BREAKER_SEED,
+                    &[bump_seed],
+                ],
+            )?;
+            LiquidationCircuitBreaker::new(*lending_market_info.key, bump_seed)
+        }
+    };
+
+    breaker.set_config(config, pause_duration, Clock::get()?.slot);
+    lending_market.has_liquidation_circuit_breaker = breaker.is_enabled();
+    LiquidationCircuitBreaker::pack(
+        breaker,
+        &mut liquidation_circuit_breaker_info.data.borrow_mut(),
+    )?;
+    LendingMarket::pack(lending_market, &mut lending_market_info.data.borrow_mut())?;
+
+    Ok(())
+}
+
+#[inline(never)] // avoid stack frame limit
+fn process_init_liquidation_stats(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let lending_market_info = next_account_info(account_info_iter)?;
+    let liquidation_stats_info = next_account_info(account_info_iter)?;
+    let lending_market_owner_info = next_account_info(account_info_iter)?;
+
+    let lending_market = LendingMarket::unpack(&lending_market_info.data.borrow())?;
+    if lending_market_info.owner != program_id {
+        msg!("Lending market provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+    if &lending_market.owner != lending_market_owner_info.key {
+        msg!("Lending market owner does not match the lending market owner provided");
+        return Err(LendingError::InvalidMarketOwner.into());
+    }
+    if !lending_market_owner_info.is_signer {
+        msg!("Lending market owner provided must be a signer");
+        return Err(LendingError::InvalidSigner.into());
+    }
+
+    if unpack_derived_account::<LiquidationStats>(
+        program_id,
+        lending_market_info.key,
+        liquidation_stats_info,
+    )?
+    .is_some()
+    {
+        msg!("Liquidation stats account is already initialized");
+        return Err(LendingError::AlreadyInitialized.into());
+    }
+
+    let (_, bump_seed) = Pubkey::find_program_address(
+        &[lending_market_info.key.as_ref(), LIQUIDATION_STATS_SEED],
+        program_id,
+    );
+    create_derived_account(
+        lending_market_owner_info,
+        liquidation_stats_info,
+        LiquidationStats::LEN,
+        program_id,
+        &[
+            lending_market_info.key.as_ref(),
+            LIQUIDATION_STATS_SEED,
+            &[bump_seed],
+        ],
+    )?;
+    LiquidationStats::pack(
+        LiquidationStats::new(*lending_market_info.key, bump_seed),
+        &mut liquidation_stats_info.data.borrow_mut(),
+    )?;
+
+    Ok(())
+}
+
+/// Records a liquidation in the liquidation stats of a lending market, if the market has them
+#[inline(never)] // avoid stack frame limit
+fn record_liquidation_stats(
+    program_id: &Pubkey,
+    lending_market_info: &AccountInfo,
+    liquidation_stats_info: &AccountInfo,
+    slot: u64,
+    repaid_value: Decimal,
+    seized_value: Decimal,
+) -> ProgramResult {
+    if let Some(mut stats) = unpack_derived_account::<LiquidationStats>(
+        program_id,
+        lending_market_info.key,
+        liquidation_stats_info,
+    )? {
+        stats.record_liquidation(slot, repaid_value, seized_value)?;
+        LiquidationStats::pack(stats, &mut liquidation_stats_info.data.borrow_mut())?;
+    }
+    Ok(())
+}
+
+#[inline(never)] // avoid stack frame limit
+fn process_set_config_timelock(
+    program_id: &Pubkey,
+    delay_slots: u64,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let lending_market_info = next_account_info(account_info_iter)?;
+    let config_timelock_info = next_account_info(account_info_iter)?;
+    let lending_market_owner_info = next_account_info(account_info_iter)?;
+    let pending_config_info = next_account_info(account_info_iter)?;
+
+    let lending_market = LendingMarket::unpack(&lending_market_info.data.borrow())?;
+    if lending_market_info.owner != program_id {
+        msg!("Lending market provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+    if &lending_market.owner != lending_market_owner_info.key {
+        msg!("Lending market owner does not match the lending market owner provided");
+        return Err(LendingError::InvalidMarketOwner.into());
+    }
+    if !lending_market_owner_info.is_signer {
+        msg!("Lending market owner provided must be a signer");
+        return Err(LendingError::InvalidSigner.into());
+    }
+
+    let config_timelock = match unpack_derived_account::<ConfigTimelock>(
+        program_id,
+        lending_market_info.key,
+        config_timelock_info,
+    )? {
+        // lowering the delay has to wait out the current one, or it would be a way around it
+        Some(config_timelock) if delay_slots < config_timelock.delay_slots => {
+            return stage_config_change(
+                program_id,
+                lending_market_info,
+                config_timelock_info.key,
+                pending_config_info,
+                lending_market_owner_info,
+                config_timelock.activation_slot(Clock::get()?.slot),
+                (Pubkey::default(), Pubkey::default()),
+                &LendingInstruction::SetConfigTimelock { delay_slots },
+            );
+        }
+        Some(config_timelock) => ConfigTimelock {
+            delay_slots,
+            ..config_timelock
+        },
+        None => {
+            msg!("Creating config timelock account");
+            let (_, bump_seed) = Pubkey::find_program_address(
+                &[lending_market_info.key.as_ref(), CONFIG_TIMELOCK_SEED],
+                program_id,
+            );
+            create_derived_account(
+                lending_market_owner_info,
+                config_timelock_info,
+                ConfigTimelock::LEN,
+                program_id,
+                &[
+                    lending_market_info.key.as_ref(),
+                    CONFIG_TIMELOCK_SEED,
+                    &[bump_seed],
+                ],
+            )?;
+            ConfigTimelock::new(*lending_market_info.key, bump_seed, delay_slots)
+        }
+    };
+
+    ConfigTimelock::pack(config_timelock, &mut config_timelock_info.data.borrow_mut())?;
+
+    Ok(())
+}
+
+#[inline(never)] // avoid stack frame limit
+fn process_activate_config(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let lending_market_info = next_account_info(account_info_iter)?;
+    let pending_config_info = next_account_info(account_info_iter)?;
+    let target_info = next_account_info(account_info_iter)?;
+    let lending_market_owner_info = next_account_info(account_info_iter)?;
+    let clock = Clock::get()?;
+
+    let mut lending_market = LendingMarket::unpack(&lending_market_info.data.borrow())?;
+    if lending_market_info.owner != program_id {
+        msg!("Lending market provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+    if &lending_market.owner != lending_market_owner_info.key {
+        msg!("Lending market owner does not match the lending market owner provided");
+        return Err(LendingError::InvalidMarketOwner.into());
+    }
+
+    let pending_config = match unpack_pending_config(
+        program_id,
+        lending_market_info,
+        target_info.key,
+        pending_config_info,
+    )? {
+        Some(pending_config) => pending_config,
+        None => {
+            msg!("No config change is pending");
+            return Err(LendingError::InvalidAccountInput.into());
+        }
+    };
+    if !pending_config.can_activate(clock.slot) {
+        msg!(
+            "Config change can't be activated before slot {}",
+            pending_config.activation_slot
+        );
+        return Err(LendingError::ConfigChangeTimelocked.into());
+    }
+
+    match pending_config.instruction()? {
+        LendingInstruction::UpdateReserveConfig {
+            mut config,
+            rate_limiter_config,
+        } => {
+            let mut reserve = Reserve::unpack(&target_info.data.borrow())?;
+            if target_info.owner != program_id {
+                msg!("Reserve provided is not owned by the lending program");
+                return Err(LendingError::InvalidAccountOwner.into());
+            }
+            if &reserve.lending_market != lending_market_info.key {
+                msg!("Reserve lending market does not match the lending market provided");
+                return Err(LendingError::InvalidAccountInput.into());
+            }
+
+            // on permissionless markets only 5ph changes fees, which may have happened since
+            // the change was staged
+            if lending_market.owner != solend_market_owner::id() {
+                config.fees = reserve.config.fees;
+                config.protocol_liquidation_fee = reserve.config.protocol_liquidation_fee;
+                config.protocol_take_rate = reserve.config.protocol_take_rate;
+                config.fee_receiver = reserve.config.fee_receiver;
+                config.liquidation_fee_receiver = reserve.config.liquidation_fee_receiver;
+            }
+
+            apply_reserve_config(
+                &mut reserve,
+                config,
+                rate_limiter_config,
+                pending_config.pyth_oracle,
+                pending_config.switchboard_oracle,
+                clock.slot,
+            );
+            reserve.last_update.mark_stale();
+            Reserve::pack(reserve, &mut target_info.data.borrow_mut())?;
+        }
+        LendingInstruction::SetLendingMarketOwnerAndConfig {
+            new_owner,
+            rate_limiter_config,
+            whitelisted_liquidator,
+            risk_authority,
+            slots_per_year,
+        } => {
+            apply_lending_market_config(
+                &mut lending_market,
+                new_owner,
+                rate_limiter_config,
+                whitelisted_liquidator,
+                risk_authority,
+                slots_per_year,
+                clock.slot,
+            );
+            LendingMarket::pack(lending_market, &mut lending_market_info.data.borrow_mut())?;
+        }
+        LendingInstruction::SetConfigTimelock { delay_slots } => {
+            let mut config_timelock = match unpack_derived_account::<ConfigTimelock>(
+                program_id,
+                lending_market_info.key,
+                target_info,
+            )? {
+                Some(config_timelock) => config_timelock,
+                None => {
+                    msg!("Config timelock provided does not exist");
+                    return Err(LendingError::InvalidAccountInput.into());
+                }
+            };
+            config_timelock.delay_slots = delay_slots;
+            ConfigTimelock::pack(config_timelock, &mut target_info.data.borrow_mut())?;
+        }
+        _ => {
+            msg!("Pending config holds an instruction that can't be staged");
+            return Err(LendingError::InvalidAccountInput.into());
+        }
+    }
+
+    close_account(pending_config_info, lending_market_owner_info)?;
+
+    Ok(())
+}
+
+#[inline(never)] // avoid stack frame limit
+fn process_cancel_config(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let lending_market_info = next_account_info(account_info_iter)?;
+    let pending_config_info = next_account_info(account_info_iter)?;
+    let target_info = next_account_info(account_info_iter)?;
+    let signer_info = next_account_info(account_info_iter)?;
+    let lending_market_owner_info = next_account_info(account_info_iter)?;
+
+    let lending_market = LendingMarket::unpack(&lending_market_info.data.borrow())?;
+    if lending_market_info.owner != program_id {
+        msg!("Lending market provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+    if signer_info.key != &lending_market.owner && signer_info.key != &lending_market.risk_authority
+    {
+        msg!("Signer must be the Lending market owner or risk authority");
+        return Err(LendingError::InvalidSigner.into());
+    }
+    if !signer_info.is_signer {
+        msg!("Lending market owner or risk authority provided must be a signer");
+        return Err(LendingError::InvalidSigner.into());
+    }
+    if &lending_market.owner != lending_market_owner_info.key {
+        msg!("Lending market owner does not match the lending market owner provided");
+        return Err(LendingError::InvalidMarketOwner.into());
+    }
+
+    if unpack_pending_config(
+        program_id,
+        lending_market_info,
+        target_info.key,
+        pending_config_info,
+    )?
+    .is_none()
+    {
+        msg!("No config change is pending");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+
+    close_account(pending_config_info, lending_market_owner_info)?;
+
+    Ok(())
+}
+
+/// Stages a config change to `target` in its pending config account, replacing any change
+/// already pending there. The payer funds the pending config account if it doesn't exist yet.
+#[allow(clippy::too_many_arguments)]
+fn stage_config_change<'a>(
+    program_id: &Pubkey,
+    lending_market_info: &AccountInfo<'a>,
+    target: &Pubkey,
+    pending_config_info: &AccountInfo<'a>,
+    payer_info: &AccountInfo<'a>,
+    activation_slot: u64,
+    (pyth_oracle, switchboard_oracle): (Pubkey, Pubkey),
+    instruction: &LendingInstruction,
+) -> ProgramResult {
+    let mut pending_config = match unpack_pending_config(
+        program_id,
+        lending_market_info,
+        target,
+        pending_config_info,
+    )? {
+        Some(pending_config) => pending_config,
+        None => {
+            msg!("Creating pending config account");
+            let (_, bump_seed) =
+                Pubkey::find_program_address(&[target.as_ref(), PENDING_CONFIG_SEED], program_id);
+            create_derived_account(
+                payer_info,
+                pending_config_info,
+                PendingConfig::LEN,
+                program_id,
+                &[target.as_ref(), PENDING_CONFIG_SEED, &[bump_seed]],
+            )?;
+            PendingConfig {
+                version: PROGRAM_VERSION,
+                bump_seed,
+                lending_market: *lending_market_info.key,
+                target: *target,
+                ..PendingConfig::default()
+            }
+        }
+    };
+
+    pending_config.activation_slot = activation_slot;
+    pending_config.pyth_oracle = pyth_oracle;
+    pending_config.switchboard_oracle = switchboard_oracle;
+    pending_config.set_instruction(instruction)?;
+    msg!("Config change staged until slot {}", activation_slot);
+    PendingConfig::pack(pending_config, &mut pending_config_info.data.borrow_mut())?;
+
+    Ok(())
+}
+
+/// Unpacks the config change pending for `target`, or returns None if nothing is pending.
+fn unpack_pending_config(
+    program_id: &Pubkey,
+    lending_market_info: &AccountInfo,
+    target: &Pubkey,
+    pending_config_info: &AccountInfo,
+) -> Result<Option<PendingConfig>, ProgramError> {
+    let pending_config =
+        unpack_derived_account::<PendingConfig>(program_id, target, pending_config_info)?;
+    if let Some(pending_config) = &pending_config {
+        if &pending_config.lending_market != lending_market_info.key {
+            msg!("Pending config lending market does not match the lending market provided");
+            return Err(LendingError::InvalidAccountInput.into());
+        }
+    }
+    Ok(pending_config)
+}
+
+#[inline(never)] // avoid stack frame limit
+fn process_set_obligation_limits(
+    program_id: &Pubkey,
+    max_deposits: u8,
+    max_borrows: u8,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    update_obligation_limits(program_id, accounts, |obligation_limits| {
+        obligation_limits.set_limits(max_deposits, max_borrows)
+    })
+}
+
+#[inline(never)] // avoid stack frame limit
+fn process_set_obligation_health_buffer(
+    program_id: &Pubkey,
+    health_buffer_bps: u16,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    update_obligation_limits(program_id, accounts, |obligation_limits| {
+        obligation_limits.set_health_buffer(health_buffer_bps)
+    })
+}
+
+/// Applies `update` to the obligation limits of a lending market on behalf of its owner, creating
+/// the obligation limits account first if the market has none.
+fn update_obligation_limits(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    update: impl FnOnce(&mut ObligationLimits) -> ProgramResult,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let lending_market_info = next_account_info(account_info_iter)?;
+    let obligation_limits_info = next_account_info(account_info_iter)?;
+    let lending_market_owner_info = next_account_info(account_info_iter)?;
+
+    let mut lending_market = LendingMarket::unpack(&lending_market_info.data.borrow())?;
+    if lending_market_info.owner != program_id {
+        msg!("Lending market provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+    if &lending_market.owner != lending_market_owner_info.key {
+        msg!("Lending market owner does not match the lending market owner provided");
+        return Err(LendingError::InvalidMarketOwner.into());
+    }
+    if !lending_market_owner_info.is_signer {
+        msg!("Lending market owner provided must be a signer");
+        return Err(LendingError::InvalidSigner.into());
+    }
+
+    let mut obligation_limits = match unpack_derived_account::<ObligationLimits>(
+        program_id,
+        lending_market_info.key,
+        obligation_limits_info,
+    )? {
+        Some(obligation_limits) => obligation_limits,
+        None => {
+            msg!("Creating obligation limits account");
+            let (_, bump_seed) = Pubkey::find_program_address(
+                &[lending_market_info.key.as_ref(), OBLIGATION_LIMITS_SEED],
+                program_id,
+            );
+            create_derived_account(
+                lending_market_owner_info,
+                obligation_limits_info,
+                ObligationLimits::LEN,
+                program_id,
+                &[
+                    lending_market_info.key.as_ref(),
+                    OBLIGATION_LIMITS_SEED,
+                    &[bump_seed],
+                ],
+            )?;
+            ObligationLimits::new(*lending_market_info.key, bump_seed)
+        }
+    };
+
+    update(&mut obligation_limits)?;
+    ObligationLimits::pack(
+        obligation_limits,
+        &mut obligation_limits_info.data.borrow_mut(),
+    )?;
+    if !lending_market.has_obligation_limits {
+        lending_market.has_obligation_limits = true;
+        LendingMarket::pack(lending_market, &mut lending_market_info.data.borrow_mut())?;
+    }
+
+    Ok(())
+}
+
+#[inline(never)] // avoid stack frame limit
+fn process_redeem_fees(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter().peekable();
+    let reserve_info = next_account_info(account_info_iter)?;
+    let reserve_liquidity_fee_receiver_info = next_account_info(account_info_iter)?;
+    let reserve_supply_liquidity_info = next_account_info(account_info_iter)?;
+    let lending_market_info = next_account_info(account_info_iter)?;
+    let lending_market_authority_info = next_account_info(account_info_iter)?;
+    let token_program_id = next_account_info(account_info_iter)?;
+    let clock = &Clock::get()?;
+
+    let mut reserve = Reserve::unpack(&reserve_info.data.borrow())?;
+    if reserve_info.owner != program_id {
+        msg!(
+            "Reserve provided is not owned by the lending program {} != {}",
+            &reserve_info.owner.to_string(),
+            &program_id.to_string(),
+        );
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+
+    if &reserve.config.fee_receiver != reserve_liquidity_fee_receiver_info.key {
+        msg!("Reserve liquidity fee receiver does not match the reserve fee receiver");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+    if &reserve.liquidity.supply_pubkey != reserve_supply_liquidity_info.key {
+        msg!("Reserve liquidity supply must be used as the reserve supply liquidity provided");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+    if &reserve.lending_market != lending_market_info.key {
+        msg!("Reserve lending market does not match the lending market provided");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+    if reserve.last_update.is_stale(clock.slot)? {
+        msg!("reserve is stale and must be refreshed in the current slot");
+        return Err(LendingError::ReserveStale.into());
+    }
+
+    let lending_market = LendingMarket::unpack(&lending_market_info.data.borrow())?;
+    if lending_market_info.owner != program_id {
+        msg!("Lending market provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+    if &lending_market.token_program_id != token_program_id.key {
+        msg!("Lending market token program does not match the token program provided");
+        return Err(LendingError::InvalidTokenProgram.into());
+    }
+    let authority_signer_seeds = &[
+        lending_market_info.key.as_ref(),
+        &[lending_market.bump_seed],
+    ];
+    let lending_market_authority_pubkey =
+        Pubkey::create_program_address(authority_signer_seeds, program_id)?;
+    if &lending_market_authority_pubkey != lending_market_authority_info.key {
+        msg!(
+            "Derived lending market authority does not match the lending market authority provided"
+        );
+        return Err(LendingError::InvalidMarketAuthority.into());
+    }
+
+    let withdraw_amount = reserve.calculate_redeem_fees()?;
+    if withdraw_amount == 0 {
+        return Err(LendingError::InsufficientProtocolFeesToRedeem.into());
+    }
+
+    reserve.liquidity.redeem_fees(withdraw_amount)?;
+    reserve.last_update.mark_stale();
+    let has_fee_redemption_hook = reserve.has_fee_redemption_hook;
+    Reserve::pack(reserve, &mut reserve_info.data.borrow_mut())?;
+
+    spl_token_transfer(TokenTransferParams {
+        source: reserve_supply_liquidity_info.clone(),
+        destination: reserve_liquidity_fee_receiver_info.clone(),
+        amount: withdraw_amount,
+        authority: lending_market_authority_info.clone(),
+        authority_signer_seeds,
+        token_program: token_program_id.clone(),
+    })?;
+
+    if has_fee_redemption_hook {
+        let fee_redemption_hook_info = next_account_info(account_info_iter)?;
+        let hook_program_info = next_account_info(account_info_iter)?;
+        let forwarded_account_infos = account_info_iter.cloned().collect::<Vec<_>>();
+
+        let fee_redemption_hook = unpack_derived_account::<FeeRedemptionHook>(
+            program_id,
+            reserve_info.key,
+            fee_redemption_hook_info,
+        )?
+        .ok_or_else(|| {
+            msg!("Reserve fee redemption hook account must be provided");
+            LendingError::InvalidAccountInput
+        })?;
+        fee_redemption_hook.check_accounts(
+            hook_program_info.key,
+            &forwarded_account_infos
+                .iter()
+                .map(|account_info| *account_info.key)
+                .collect::<Vec<_>>(),
+        )?;
+
+        let mut hook_account_infos = vec![
+            reserve_info.clone(),
+            reserve_liquidity_fee_receiver_info.clone(),
+            lending_market_info.clone(),
+        ];
+        hook_account_infos.extend(forwarded_account_infos.iter().cloned());
+        hook_account_infos.push(hook_program_info.clone());
+        invoke(
+            &on_fees_redeemed(
+                *hook_program_info.key,
+                withdraw_amount,
+                *reserve_info.key,
+                *reserve_liquidity_fee_receiver_info.key,
+                *lending_market_info.key,
+                forwarded_account_infos
+                    .iter()
+                    .map(|account_info| AccountMeta {
+                        pubkey: *account_info.key,
+                        is_signer: account_info.is_signer,
+                        is_writable: account_info.is_writable,
+                    })
+                    .collect(),
+            ),
+            &hook_account_infos,
+        )?;
+    }
+
+    Ok(())
+}
+
+fn process_flash_borrow_reserve_liquidity(
+    program_id: &Pubkey,
+    liquidity_amount: u64,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let source_liquidity_info = next_account_info(account_info_iter)?;
+    let destination_liquidity_info = next_account_info(account_info_iter)?;
+    let reserve_info = next_account_info(account_info_iter)?;
+    let lending_market_info = next_account_info(account_info_iter)?;
+    let lending_market_authority_info = next_account_info(account_info_iter)?;
+    let sysvar_info = next_account_info(account_info_iter)?;
+    let token_program_id = next_account_info(account_info_iter)?;
+    let flash_loan_config_info = next_account_info(account_info_iter)?;
+    let clock = Clock::get()?;
+
+    _refresh_reserve_interest(program_id, reserve_info, lending_market_info, &clock)?;
+    _flash_borrow_reserve_liquidity(
+        program_id,
+        liquidity_amount,
+        source_liquidity_info,
+        destination_liquidity_info,
+        reserve_info,
+        lending_market_info,
+        lending_market_authority_info,
+        sysvar_info,
+        token_program_id,
+        flash_loan_config_info,
+    )?;
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn _flash_borrow_reserve_liquidity<'a>(
+    program_id: &Pubkey,
+    liquidity_amount: u64,
+    source_liquidity_info: &AccountInfo<'a>,
+    destination_liquidity_info: &AccountInfo<'a>,
+    reserve_info: &AccountInfo<'a>,
+    lending_market_info: &AccountInfo<'a>,
+    lending_market_authority_info: &AccountInfo<'a>,
+    sysvar_info: &AccountInfo<'a>,
+    token_program_id: &AccountInfo<'a>,
+    flash_loan_config_info: &AccountInfo<'a>,
+) -> ProgramResult {
+    let lending_market = LendingMarket::unpack(&lending_market_info.data.borrow())?;
+    if lending_market_info.owner != program_id {
+        msg!("Lending market provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+    if &lending_market.token_program_id != token_program_id.key {
+        msg!("Lending market token program does not match the token program provided");
+        return Err(LendingError::InvalidTokenProgram.into());
+    }
+    let mut reserve = Reserve::unpack(&reserve_info.data.borrow())?;
+    if reserve_info.owner != program_id {
+        msg!("Reserve provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+    if &reserve.lending_market != lending_market_info.key {
+        msg!("Reserve lending market does not match the lending market provided");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+    if &reserve.liquidity.supply_pubkey != source_liquidity_info.key {
+        msg!("Borrow reserve liquidity supply must be used as the source liquidity provided");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+    if &reserve.liquidity.supply_pubkey == destination_liquidity_info.key {
+        msg!(
+            "Borrow reserve liquidity supply cannot be used as the destination liquidity provided"
+        );
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+    let authority_signer_seeds = &[
+        lending_market_info.key.as_ref(),
+        &[lending_market.bump_seed],
+    ];
+    let lending_market_authority_pubkey =
+        Pubkey::create_program_address(authority_signer_seeds, program_id)?;
+    if &lending_market_authority_pubkey != lending_market_authority_info.key {
+        msg!(
+            "Derived lending market authority {} does not match the lending market authority provided {}",
+            &lending_market_authority_pubkey.to_string(),
+            &lending_market_authority_info.key.to_string(),
+        );
+        return Err(LendingError::InvalidMarketAuthority.into());
+    }
+
+    if reserve.config.fees.flash_loan_fee_wad == u64::MAX {
+        msg!("Flash loans are disabled for this reserve");
+        return Err(LendingError::FlashLoansDisabled.into());
+    }
+    if let Some(flash_loan_config) = unpack_derived_account::<FlashLoanConfig>(
+        program_id,
+        reserve_info.key,
+        flash_loan_config_info,
+    )? {
+        flash_loan_config.check_flash_borrow(liquidity_amount)?;
+    }
+
+    // Make sure this isnt a cpi call
+    let current_index = load_current_index_checked(sysvar_info)? as usize;
+    if is_cpi_call(program_id, current_index, sysvar_info)? {
+        msg!("Flash Borrow was called via CPI!");
+        return Err(LendingError::FlashBorrowCpi.into());
+    }
+
+    // Find and validate the flash repay instruction.
+    //
+    // 1. Ensure the instruction is for this program
+    // 2. Ensure the instruction can be unpacked into a LendingInstruction
+    // 3. Ensure that the reserve for the repay matches the borrow
+    // 4. Ensure that there are no other flash instructions in the rest of the transaction
+    // 5. Ensure that the repay amount matches the borrow amount
+    //
+    // If all of these conditions are not met, the flash borrow fails.
+    let mut i = current_index;
+    let mut found_repay_ix = false;
+
+    loop {
+        i += 1;
+
+        let ixn = match load_instruction_at_checked(i, sysvar_info) {
+            Ok(ix) => ix,
+            Err(ProgramError::InvalidArgument) => break, // out of bounds
+            Err(e) => {
+                return Err(e);
+            }
+        };
+
+        if ixn.program_id != *program_id {
+            continue;
+        }
+
+        let unpacked = LendingInstruction::unpack(ixn.data.as_slice())?;
+        match unpacked {
+            LendingInstruction::FlashRepayReserveLiquidity {
+                liquidity_amount: repay_liquidity_amount,
+                borrow_instruction_index,
+            } => {
+                if found_repay_ix {
+                    msg!("Multiple flash repays not allowed");
+                    return Err(LendingError::MultipleFlashBorrows.into());
+                }
+                if ixn.accounts[4].pubkey != *reserve_info.key {
+                    msg!("Invalid reserve account on flash repay");
+                    return Err(LendingError::InvalidFlashRepay.into());
+                }
+                if repay_liquidity_amount != liquidity_amount {
+                    msg!("Liquidity amount for flash repay doesn't match borrow");
+                    return Err(LendingError::InvalidFlashRepay.into());
+                }
+                if (borrow_instruction_index as usize) != current_index {
+                    msg!("Borrow instruction index {} for flash repay doesn't match current index {}", borrow_instruction_index, current_index);
+                    return Err(LendingError::InvalidFlashRepay.into());
+                }
+
+                found_repay_ix = true;
+            }
+            LendingInstruction::FlashBorrowReserveLiquidity { .. } => {
+                msg!("Multiple flash borrows not allowed");
+                return Err(LendingError::MultipleFlashBorrows.into());
+            }
+            _ => (),
+        };
+    }
+
+    if !found_repay_ix {
+        msg!("No flash repay found");
+        return Err(LendingError::NoFlashRepayFound.into());
+    }
+
+    reserve.liquidity.borrow(Decimal::from(liquidity_amount))?;
+    reserve.last_update.mark_stale();
+    Reserve::pack(reserve, &mut reserve_info.data.borrow_mut())?;
+
+    spl_token_transfer(TokenTransferParams {
+        source: source_liquidity_info.clone(),
+        destination: destination_liquidity_info.clone(),
+        amount: liquidity_amount,
+        authority: lending_market_authority_info.clone(),
+        authority_signer_seeds,
+        token_program: token_program_id.clone(),
+    })?;
+
+    Ok(())
+}
+
+fn process_flash_repay_reserve_liquidity(
+    program_id: &Pubkey,
+    liquidity_amount: u64,
+    borrow_instruction_index: u8,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let source_liquidity_info = next_account_info(account_info_iter)?;
     let destination_liquidity_info = next_account_info(account_info_iter)?;
+    let reserve_liquidity_fee_receiver_info = next_account_info(account_info_iter)?;
+    let host_fee_receiver_info = next_account_info(account_info_iter)?;
+    let reserve_info = next_account_info(account_info_iter)?;
+    let lending_market_info = next_account_info(account_info_iter)?;
+    let user_transfer_authority_info = next_account_info(account_info_iter)?;
+    let sysvar_info = next_account_info(account_info_iter)?;
+    let token_program_id = next_account_info(account_info_iter)?;
+    let flash_loan_config_info = next_account_info(account_info_iter)?;
+
+    _flash_repay_reserve_liquidity(
+        program_id,
+        liquidity_amount,
+        borrow_instruction_index,
+        source_liquidity_info,
+        destination_liquidity_info,
+        reserve_liquidity_fee_receiver_info,
+        host_fee_receiver_info,
+        reserve_info,
+        lending_market_info,
+        user_transfer_authority_info,
+        sysvar_info,
+        token_program_id,
+        flash_loan_config_info,
+    )?;
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn _flash_repay_reserve_liquidity<'a>(
+    program_id: &Pubkey,
+    liquidity_amount: u64,
+    borrow_instruction_index: u8,
+    source_liquidity_info: &AccountInfo<'a>,
+    destination_liquidity_info: &AccountInfo<'a>,
+    reserve_liquidity_fee_receiver_info: &AccountInfo<'a>,
+    host_fee_receiver_info: &AccountInfo<'a>,
+    reserve_info: &AccountInfo<'a>,
+    lending_market_info: &AccountInfo<'a>,
+    user_transfer_authority_info: &AccountInfo<'a>,
+    sysvar_info: &AccountInfo<'a>,
+    token_program_id: &AccountInfo<'a>,
+    flash_loan_config_info: &AccountInfo<'a>,
+) -> ProgramResult {
+    let lending_market = LendingMarket::unpack(&lending_market_info.data.borrow())?;
+    if lending_market_info.owner != program_id {
+        msg!("Lending market provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+    if &lending_market.token_program_id != token_program_id.key {
+        msg!("Lending market token program does not match the token program provided");
+        return Err(LendingError::InvalidTokenProgram.into());
+    }
+    let mut reserve = Reserve::unpack(&reserve_info.data.borrow())?;
+    if reserve_info.owner != program_id {
+        msg!("Reserve provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+    if &reserve.lending_market != lending_market_info.key {
+        msg!("Reserve lending market does not match the lending market provided");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+    if &reserve.liquidity.supply_pubkey != destination_liquidity_info.key {
+        msg!("Reserve liquidity supply does not match the reserve liquidity supply provided");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+    if &reserve.liquidity.supply_pubkey == source_liquidity_info.key {
+        msg!("Reserve liquidity supply cannot be used as the source liquidity provided");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+    let mut flash_loan_config = unpack_derived_account::<FlashLoanConfig>(
+        program_id,
+        reserve_info.key,
+        flash_loan_config_info,
+    )?;
+    let flash_loan_fee_receiver = match &flash_loan_config {
+        Some(flash_loan_config) => flash_loan_config.fee_receiver(&reserve.config.fee_receiver),
+        None => reserve.config.fee_receiver,
+    };
+    if &flash_loan_fee_receiver != reserve_liquidity_fee_receiver_info.key {
+        msg!("Flash loan fee receiver does not match the flash loan fee receiver of the reserve");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+
+    let flash_loan_amount = liquidity_amount;
+
+    let flash_loan_amount_decimal = Decimal::from(flash_loan_amount);
+    let (origination_fee, host_fee) = reserve
+        .config
+        .fees
+        .calculate_flash_loan_fees(flash_loan_amount_decimal)?;
+
+    // Make sure this isnt a cpi call
+    let current_index = load_current_index_checked(sysvar_info)? as usize;
+    if is_cpi_call(program_id, current_index, sysvar_info)? {
+        msg!("Flash Repay was called via CPI!");
+        return Err(LendingError::FlashRepayCpi.into());
+    }
+
+    // validate flash borrow
+    if (borrow_instruction_index as usize) > current_index {
+        msg!(
+            "Flash repay: borrow instruction index {} has to be less than current index {}",
+            borrow_instruction_index,
+            current_index
+        );
+        return Err(LendingError::InvalidFlashRepay.into());
+    }
+
+    let ixn = load_instruction_at_checked(borrow_instruction_index as usize, sysvar_info)?;
+    if ixn.program_id != *program_id {
+        msg!(
+            "Flash repay: supplied instruction index {} doesn't belong to program id {}",
+            borrow_instruction_index,
+            *program_id
+        );
+        return Err(LendingError::InvalidFlashRepay.into());
+    }
+
+    let unpacked = LendingInstruction::unpack(ixn.data.as_slice())?;
+    match unpacked {
+        LendingInstruction::FlashBorrowReserveLiquidity {
+            liquidity_amount: borrow_liquidity_amount,
+        } => {
+            // re-check everything here out of paranoia
+            if ixn.accounts[2].pubkey != *reserve_info.key {
+                msg!("Invalid reserve account on flash repay");
+                return Err(LendingError::InvalidFlashRepay.into());
+            }
+
+            if liquidity_amount != borrow_liquidity_amount {
+                msg!("Liquidity amount for flash repay doesn't match borrow");
+                return Err(LendingError::InvalidFlashRepay.into());
+            }
+        }
+        _ => {
+            msg!("Flash repay: Supplied borrow instruction index is not a flash borrow");
+            return Err(LendingError::InvalidFlashRepay.into());
+        }
+    };
+
+    reserve
+        .liquidity
+        .repay(flash_loan_amount, flash_loan_amount_decimal)?;
+    reserve.last_update.mark_stale();
+    Reserve::pack(reserve, &mut reserve_info.data.borrow_mut())?;
+
+    if let Some(mut flash_loan_config) = flash_loan_config.take() {
+        flash_loan_config.record_flash_loan(flash_loan_amount, origination_fee, host_fee)?;
+        FlashLoanConfig::pack(
+            flash_loan_config,
+            &mut flash_loan_config_info.data.borrow_mut(),
+        )?;
+    }
+
+    spl_token_transfer(TokenTransferParams {
+        source: source_liquidity_info.clone(),
+        destination: destination_liquidity_info.clone(),
+        amount: flash_loan_amount,
+        authority: user_transfer_authority_info.clone(),
+        authority_signer_seeds: &[],
+        token_program: token_program_id.clone(),
+    })?;
+
+    if host_fee > 0 {
+        spl_token_transfer(TokenTransferParams {
+            source: source_liquidity_info.clone(),
+            destination: host_fee_receiver_info.clone(),
+            amount: host_fee,
+            authority: user_transfer_authority_info.clone(),
+            authority_signer_seeds: &[],
+            token_program: token_program_id.clone(),
+        })?;
+    }
+
+    if origination_fee > 0 {
+        spl_token_transfer(TokenTransferParams {
+            source: source_liquidity_info.clone(),
+            destination: reserve_liquidity_fee_receiver_info.clone(),
+            amount: origination_fee,
+            authority: user_transfer_authority_info.clone(),
+            authority_signer_seeds: &[],
+            token_program: token_program_id.clone(),
+        })?;
+    }
+
+    Ok(())
+}
+
+fn process_forgive_debt(
+    program_id: &Pubkey,
+    liquidity_amount: u64,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let obligation_info = next_account_info(account_info_iter)?;
+    let reserve_info = next_account_info(account_info_iter)?;
+    let lending_market_info = next_account_info(account_info_iter)?;
+    let lending_market_owner_info = next_account_info(account_info_iter)?;
+
+    let lending_market = LendingMarket::unpack(&lending_market_info.data.borrow())?;
+    if lending_market_info.owner != program_id {
+        msg!(
+            "Lending market provided is not owned by the lending program  {} != {}",
+            &lending_market_info.owner.to_string(),
+            &program_id.to_string(),
+        );
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+    if &lending_market.owner != lending_market_owner_info.key {
+        msg!("Lending market owner does not match the lending market owner provided");
+        return Err(LendingError::InvalidMarketOwner.into());
+    }
+    if !lending_market_owner_info.is_signer {
+        msg!("Lending market owner provided must be a signer");
+        return Err(LendingError::InvalidSigner.into());
+    }
+
+    let mut reserve = Reserve::unpack(&reserve_info.data.borrow())?;
+    if reserve_info.owner != program_id {
+        msg!("Reserve provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+    if &reserve.lending_market != lending_market_info.key {
+        msg!("Reserve lending market does not match the lending market provided");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+    if reserve.last_update.is_stale(Clock::get()?.slot)? {
+        msg!("Reserve is stale and must be refreshed in the current slot");
+        return Err(LendingError::ReserveStale.into());
+    }
+
+    let mut obligation = Obligation::unpack(&obligation_info.data.borrow())?;
+    if obligation_info.owner != program_id {
+        msg!("Obligation provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+    if &obligation.lending_market != lending_market_info.key {
+        msg!("Obligation lending market does not match the lending market provided");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+    if obligation.last_update.is_stale(Clock::get()?.slot)? {
+        msg!("Obligation is stale and must be refreshed in the current slot");
+        return Err(LendingError::ObligationStale.into());
+    }
+    if !obligation.deposits.is_empty() {
+        msg!("Obligation hasn't been fully liquidated!");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+
+    // in the case where the entire reserve got rugged for whatever reason, we still don't
+    // want to forgive the entire reserve's supply because that'll mess with the ctoken ratio
+    // and cause overflow/div by zero issues in other places. therefore, we want to make sure the ctoken
+    // ratio is >= 1% after forgiveness.
+    //
+    // new ctoken ratio = (total_liquidity_supply - forgive_amount) / collateral_mint_supply >= 0.01
+    // -> forgive_amount <= (total_liquidity_supply - collateral_mint_supply * 0.01)
+    const MIN_CTOKEN_RATIO_PERCENT: u8 = 1;
+    let max_forgive_amount = reserve.liquidity.total_supply()?.try_sub(
+        Decimal::from(reserve.collateral.mint_total_supply)
+            .try_mul(Decimal::from_percent(MIN_CTOKEN_RATIO_PERCENT))?,
+    )?;
+
+    let (_, liquidity_index) = obligation.find_liquidity_in_borrows(*reserve_info.key)?;
+    obligation.settle_grace_interest(liquidity_index, &mut reserve, Clock::get()?.slot)?;
+    let liquidity = &obligation.borrows[liquidity_index];
+    let forgive_amount = min(
+        Decimal::from(liquidity_amount),
+        min(liquidity.borrowed_amount_wads, max_forgive_amount),
+    );
+
+    reserve
+        .liquidity
+        .settle_fixed_rate_borrow(liquidity, forgive_amount)?;
+    reserve.liquidity.forgive_debt(forgive_amount)?;
+    reserve.last_update.mark_stale();
+    Reserve::pack(reserve, &mut reserve_info.data.borrow_mut())?;
+
+    obligation.repay(forgive_amount, liquidity_index)?;
+    obligation.last_update.mark_stale();
+    Obligation::pack(obligation, &mut obligation_info.data.borrow_mut())?;
+
+    Ok(())
+}
+
+fn assert_rent_exempt(rent: &Rent, account_info: &AccountInfo) -> ProgramResult {
+    if !rent.is_exempt(account_info.lamports(), account_info.data_len()) {
+        msg!(
+            "Rent exempt balance insufficient got {} expected {}",
+            &account_info.lamports().to_string(),
+            &rent.minimum_balance(account_info.data_len()).to_string(),
+        );
+        Err(LendingError::NotRentExempt.into())
+    } else {
+        Ok(())
+    }
+}
+
+fn process_update_market_metadata(
+    program_id: &Pubkey,
+    metadata: &LendingMarketMetadata,
+    accounts: &[AccountInfo],
+) -> Result<(), ProgramError> {
+    let account_info_iter = &mut accounts.iter();
+    let lending_market_info = next_account_info(account_info_iter)?;
+    let lending_market_owner_info = next_account_info(account_info_iter)?;
+    let metadata_info = next_account_info(account_info_iter)?;
+
+    let lending_market = LendingMarket::unpack(&lending_market_info.data.borrow())?;
+    if lending_market_info.owner != program_id {
+        msg!("Lending market provided is not owned by the lending program",);
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+
+    if &lending_market.owner != lending_market_owner_info.key {
+        msg!("Lending market owner does not match the lending market owner provided");
+        return Err(LendingError::InvalidMarketOwner.into());
+    }
+
+    if !lending_market_owner_info.is_signer {
+        msg!("Lending market owner provided must be a signer");
+        return Err(LendingError::InvalidSigner.into());
+    }
+
+    let metadata_seeds = &[lending_market_info.key.as_ref(), b"MetaData"];
+    let (metadata_key, bump_seed) = Pubkey::find_program_address(metadata_seeds, program_id);
+    if metadata_key != *metadata_info.key {
+        msg!("Provided metadata account does not match the expected derived address");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+
+    if bump_seed != metadata.bump_seed {
+        msg!("Provided bump seed does not match the expected derived bump seed");
+        return Err(LendingError::InvalidAmount.into());
+    }
+
+    // initialize
+    if metadata_info.data_is_empty() {
+        msg!("Creating metadata account");
+
+        invoke_signed(
+            &create_account(
+                lending_market_owner_info.key,
+                metadata_info.key,
+                Rent::get()?.minimum_balance(std::mem::size_of::<LendingMarketMetadata>()),
+                std::mem::size_of::<LendingMarketMetadata>() as u64,
+                program_id,
+            ),
+            &[lending_market_owner_info.clone(), metadata_info.clone()],
+            &[&[lending_market_info.key.as_ref(), br"MetaData", &[bump_seed]]],
+        )?;
+    }
+
+    if metadata_info.owner != program_id {
+        msg!("Metadata provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+
+    let mut metadata_account_data = metadata_info.try_borrow_mut_data()?;
+    metadata_account_data.copy_from_slice(bytes_of(metadata));
+
+    Ok(())
+}
+
+#[inline(never)] // avoid stack frame limit
+fn process_approve_borrow_allowance(
+    program_id: &Pubkey,
+    liquidity_amount: u64,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let obligation_info = next_account_info(account_info_iter)?;
+    let borrow_allowances_info = next_account_info(account_info_iter)?;
+    let borrow_reserve_info = next_account_info(account_info_iter)?;
+    let delegate_info = next_account_info(account_info_iter)?;
+    let obligation_owner_info = next_account_info(account_info_iter)?;
+
+    validate_borrow_allowance_accounts(
+        program_id,
+        obligation_info,
+        borrow_reserve_info,
+        delegate_info,
+        obligation_owner_info,
+    )?;
+
+    let borrow_allowances_seeds = &[obligation_info.key.as_ref(), BORROW_ALLOWANCES_SEED];
+    let (borrow_allowances_key, bump_seed) =
+        Pubkey::find_program_address(borrow_allowances_seeds, program_id);
+    if borrow_allowances_key != *borrow_allowances_info.key {
+        msg!("Provided borrow allowances account does not match the expected derived address");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+
+    // initialize
+    if borrow_allowances_info.data_is_empty() {
+        msg!("Creating borrow allowances account");
+
+        create_derived_account(
+            obligation_owner_info,
+            borrow_allowances_info,
+            BorrowAllowances::LEN,
+            program_id,
+            &[
+                obligation_info.key.as_ref(),
+                BORROW_ALLOWANCES_SEED,
+                &[bump_seed],
+            ],
+        )?;
+
+        BorrowAllowances::pack(
+            BorrowAllowances::new(*obligation_info.key, bump_seed),
+            &mut borrow_allowances_info.data.borrow_mut(),
+        )?;
+    }
+
+    let mut borrow_allowances = unpack_existing_derived_account::<BorrowAllowances>(
+        program_id,
+        obligation_info.key,
+        borrow_allowances_info,
+    )?;
+    borrow_allowances.approve(
+        *delegate_info.key,
+        *borrow_reserve_info.key,
+        liquidity_amount,
+    )?;
+    BorrowAllowances::pack(
+        borrow_allowances,
+        &mut borrow_allowances_info.data.borrow_mut(),
+    )?;
+
+    Ok(())
+}
+
+#[inline(never)] // avoid stack frame limit
+fn process_revoke_borrow_allowance(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let obligation_info = next_account_info(account_info_iter)?;
+    let borrow_allowances_info = next_account_info(account_info_iter)?;
+    let borrow_reserve_info = next_account_info(account_info_iter)?;
+    let delegate_info = next_account_info(account_info_iter)?;
+    let obligation_owner_info = next_account_info(account_info_iter)?;
+
+    validate_borrow_allowance_accounts(
+        program_id,
+        obligation_info,
+        borrow_reserve_info,
+        delegate_info,
+        obligation_owner_info,
+    )?;
+
+    let mut borrow_allowances = unpack_existing_derived_account::<BorrowAllowances>(
+        program_id,
+        obligation_info.key,
+        borrow_allowances_info,
+    )?;
+    borrow_allowances.revoke(*delegate_info.key, *borrow_reserve_info.key);
+    BorrowAllowances::pack(
+        borrow_allowances,
+        &mut borrow_allowances_info.data.borrow_mut(),
+    )?;
+
+    Ok(())
+}
+
+fn process_close_obligation(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let obligation_info = next_account_info(account_info_iter)?;
+    let rent_destination_info = next_account_info(account_info_iter)?;
+    let obligation_owner_info = next_account_info(account_info_iter)?;
+    let derived_accounts = find_obligation_derived_accounts(
+        program_id,
+        obligation_info.key,
+        account_info_iter.as_slice(),
+    )
+    .ok_or_else(|| {
+        msg!("The accounts derived from the obligation must be provided");
+        LendingError::InvalidAccountInput
+    })?;
+
+    if obligation_info.owner != program_id {
+        msg!("Obligation provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+    let obligation = Obligation::unpack(&obligation_info.data.borrow())?;
+    if &obligation.owner != obligation_owner_info.key {
+        msg!("Obligation owner does not match the obligation owner provided");
+        return Err(LendingError::InvalidObligationOwner.into());
+    }
+    if !obligation_owner_info.is_signer {
+        msg!("Obligation owner provided must be a signer");
+        return Err(LendingError::InvalidSigner.into());
+    }
+    if !obligation.deposits.is_empty() || !obligation.borrows.is_empty() {
+        msg!("Obligation must have no deposits or borrows to be closed");
+        return Err(LendingError::ObligationNotEmpty.into());
+    }
+    if rent_destination_info.key == obligation_info.key {
+        msg!("Rent destination cannot be the obligation being closed");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+
+    close_obligation(
+        program_id,
+        obligation_info,
+        &obligation.owner,
+        rent_destination_info,
+        &derived_accounts,
+    )
+}
+
+/// Finds the accounts derived from an obligation among `accounts`, or None if any of them is
+/// missing. All of them have to be passed to close the obligation, whether they exist or not,
+/// since the program can't otherwise tell a missing account from one that was never created.
+fn find_obligation_derived_accounts<'b, 'a>(
+    program_id: &Pubkey,
+    obligation: &Pubkey,
+    accounts: &'b [AccountInfo<'a>],
+) -> Option<Vec<&'b AccountInfo<'a>>> {
+    [
+        BorrowAllowances::find_address(program_id, obligation).0,
+        ObligationBorrowCap::find_address(program_id, obligation).0,
+        DeleverageCredit::find_address(program_id, obligation).0,
+    ]
+    .iter()
+    .map(|key| accounts.iter().find(|account_info| account_info.key == key))
+    .collect()
+}
+
+/// Closes an obligation without deposits or borrows along with the accounts derived from it,
+/// since the obligation address can be initialized again and they must not outlive it. All of
+/// the rent goes to the rent destination.
+fn close_obligation(
+    program_id: &Pubkey,
+    obligation_info: &AccountInfo,
+    obligation_owner: &Pubkey,
+    rent_destination_info: &AccountInfo,
+    derived_accounts: &[&AccountInfo],
+) -> ProgramResult {
+    let mut lamports = close_account(obligation_info, rent_destination_info)?;
+    for account_info in derived_accounts {
+        // the obligation may never have had it
+        if account_info.owner == program_id && !account_info.data_is_empty() {
+            lamports = lamports
+                .checked_add(close_account(account_info, rent_destination_info)?)
+                .ok_or(LendingError::MathOverflow)?;
+        }
+    }
+
+    LendingEvent::ObligationClosed {
+        obligation: *obligation_info.key,
+        owner: *obligation_owner,
+        rent_destination: *rent_destination_info.key,
+        lamports,
+    }
+    .emit();
+
+    Ok(())
+}
+
+/// Emit a [LendingEvent::ReserveLimitThresholdCrossed] if the amount reached a new threshold
+fn emit_reserve_limit_event(
+    reserve: Pubkey,
+    kind: ReserveLimitKind,
+    before: u64,
+    after: u64,
+    limit: u64,
+) {
+    if let Some(event) =
+        LendingEvent::reserve_limit_threshold_crossed(reserve, kind, before, after, limit)
+    {
+        event.emit();
+    }
+}
+
+#[inline(never)] // avoid stack frame limit
+fn process_set_obligation_deposit_mode(
+    program_id: &Pubkey,
+    mode: DepositMode,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let obligation_info = next_account_info(account_info_iter)?;
+    let obligation_owner_info = next_account_info(account_info_iter)?;
+
+    if obligation_info.owner != program_id {
+        msg!("Obligation provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+    let mut obligation = Obligation::unpack(&obligation_info.data.borrow())?;
+    if &obligation.owner != obligation_owner_info.key {
+        msg!("Obligation owner does not match the obligation owner provided");
+        return Err(LendingError::InvalidObligationOwner.into());
+    }
+    if !obligation_owner_info.is_signer {
+        msg!("Obligation owner provided must be a signer");
+        return Err(LendingError::InvalidSigner.into());
+    }
+
+    obligation.default_deposit_mode = mode;
+    Obligation::pack(obligation, &mut obligation_info.data.borrow_mut())?;
+
+    Ok(())
+}
+
+#[inline(never)] // avoid stack frame limit
+fn process_set_ignore_health_buffer(
+    program_id: &Pubkey,
+    ignore_health_buffer: bool,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let obligation_info = next_account_info(account_info_iter)?;
+    let obligation_owner_info = next_account_info(account_info_iter)?;
+
+    if obligation_info.owner != program_id {
+        msg!("Obligation provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+    let mut obligation = Obligation::unpack(&obligation_info.data.borrow())?;
+    if &obligation.owner != obligation_owner_info.key {
+        msg!("Obligation owner does not match the obligation owner provided");
+        return Err(LendingError::InvalidObligationOwner.into());
+    }
+    if !obligation_owner_info.is_signer {
+        msg!("Obligation owner provided must be a signer");
+        return Err(LendingError::InvalidSigner.into());
+    }
+
+    obligation.ignore_health_buffer = ignore_health_buffer;
+    Obligation::pack(obligation, &mut obligation_info.data.borrow_mut())?;
+
+    Ok(())
+}
+
+#[inline(never)] // avoid stack frame limit
+fn process_set_obligation_owner(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let obligation_info = next_account_info(account_info_iter)?;
+    let lending_market_info = next_account_info(account_info_iter)?;
+    let obligation_owner_info = next_account_info(account_info_iter)?;
+    let new_obligation_owner_info = next_account_info(account_info_iter)?;
+    let borrow_allowances_info = next_account_info(account_info_iter)?;
+    let clock = &Clock::get()?;
+
+    if lending_market_info.owner != program_id {
+        msg!("Lending market provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+    let lending_market = LendingMarket::unpack(&lending_market_info.data.borrow())?;
+    let mut obligation = unpack_owner_obligation(
+        program_id,
+        obligation_info,
+        lending_market_info,
+        obligation_owner_info,
+    )?;
+    if !obligation_owner_info.is_signer {
+        msg!("Obligation owner provided must be a signer");
+        return Err(LendingError::InvalidSigner.into());
+    }
+    if !new_obligation_owner_info.is_signer {
+        msg!("New obligation owner provided must be a signer");
+        return Err(LendingError::InvalidSigner.into());
+    }
+    if new_obligation_owner_info.key == obligation_owner_info.key {
+        msg!("New obligation owner must be different from the current one");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+
+    // the whitelisted liquidator would otherwise be able to take over positions it can liquidate,
+    // or hand its own to an account that can't
+    if let Some(liquidator) = lending_market.whitelisted_liquidator {
+        if &liquidator == obligation_owner_info.key || &liquidator == new_obligation_owner_info.key
+        {
+            msg!("Obligations can't be transferred to or from the whitelisted liquidator");
+            return Err(LendingError::ObligationOwnerChangeBlocked.into());
+        }
+    }
+    if obligation.borrows.iter().any(|liquidity| {
+        liquidity.rate_mode == BorrowRateMode::Fixed
+            && clock.slot < liquidity.fixed_rate_maturity_slot
+    }) {
+        msg!("Obligation owner can't change while a borrow has a locked fixed rate");
+        return Err(LendingError::ObligationOwnerChangeBlocked.into());
+    }
+
+    // allowances were granted by the previous owner, who may no longer be trusted
+    if let Some(mut borrow_allowances) = unpack_derived_account::<BorrowAllowances>(
+        program_id,
+        obligation_info.key,
+        borrow_allowances_info,
+    )? {
+        borrow_allowances.allowances.clear();
+        BorrowAllowances::pack(
+            borrow_allowances,
+            &mut borrow_allowances_info.data.borrow_mut(),
+        )?;
+    }
+
+    obligation.owner = *new_obligation_owner_info.key;
+    Obligation::pack(obligation, &mut obligation_info.data.borrow_mut())?;
+
+    LendingEvent::ObligationOwnerChanged {
+        obligation: *obligation_info.key,
+        previous_owner: *obligation_owner_info.key,
+        new_owner: *new_obligation_owner_info.key,
+    }
+    .emit();
+
+    Ok(())
+}
+
+#[inline(never)] // avoid stack frame limit
+fn process_set_flash_loan_config(
+    program_id: &Pubkey,
+    flash_loans_enabled: bool,
+    max_flash_loan_amount: u64,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    update_flash_loan_config(program_id, accounts, |_reserve, flash_loan_config| {
+        validate_flash_loan_config(flash_loans_enabled, max_flash_loan_amount)?;
+        flash_loan_config.flash_loans_enabled = flash_loans_enabled;
+        flash_loan_config.max_flash_loan_amount = max_flash_loan_amount;
+        Ok(())
+    })
+}
+
+fn process_set_flash_loan_fee_receiver(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let flash_loan_fee_receiver_info = next_account_info(&mut accounts.iter().skip(5))?;
+    update_flash_loan_config(program_id, accounts, |reserve, flash_loan_config| {
+        if flash_loan_fee_receiver_info.key == &reserve.config.fee_receiver {
+            flash_loan_config.flash_loan_fee_receiver = Pubkey::default();
+            return Ok(());
+        }
+
+        if flash_loan_fee_receiver_info.owner != &spl_token::id() {
+            msg!("Flash loan fee receiver provided is not owned by the token program");
+            return Err(LendingError::InvalidTokenOwner.into());
+        }
+        let flash_loan_fee_receiver =
+            unpack_token_account(&flash_loan_fee_receiver_info.data.borrow())?;
+        if flash_loan_fee_receiver.mint != reserve.liquidity.mint_pubkey {
+            msg!("Flash loan fee receiver mint does not match the reserve liquidity mint");
+            return Err(LendingError::InvalidTokenMint.into());
+        }
+        flash_loan_config.flash_loan_fee_receiver = *flash_loan_fee_receiver_info.key;
+        Ok(())
+    })
+}
+
+/// Applies `update` to the flash loan config of a reserve on behalf of the lending market owner,
+/// creating the flash loan config account first if the reserve has none.
+fn update_flash_loan_config(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    update: impl FnOnce(&Reserve, &mut FlashLoanConfig) -> ProgramResult,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let reserve_info = next_account_info(account_info_iter)?;
+    let lending_market_info = next_account_info(account_info_iter)?;
+    let flash_loan_config_info = next_account_info(account_info_iter)?;
+    let lending_market_owner_info = next_account_info(account_info_iter)?;
+
+    let reserve = Reserve::unpack(&reserve_info.data.borrow())?;
+    if reserve_info.owner != program_id {
+        msg!("Reserve provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+    if &reserve.lending_market != lending_market_info.key {
+        msg!("Reserve lending market does not match the lending market provided");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+
+    let lending_market = LendingMarket::unpack(&lending_market_info.data.borrow())?;
+    if lending_market_info.owner != program_id {
+        msg!("Lending market provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+    if &lending_market.owner != lending_market_owner_info.key {
+        msg!("Lending market owner does not match the lending market owner provided");
+        return Err(LendingError::InvalidMarketOwner.into());
+    }
+    if !lending_market_owner_info.is_signer {
+        msg!("Lending market owner provided must be a signer");
+        return Err(LendingError::InvalidSigner.into());
+    }
+
+    let mut flash_loan_config = match unpack_derived_account::<FlashLoanConfig>(
+        program_id,
+        reserve_info.key,
+        flash_loan_config_info,
+    )? {
+        Some(flash_loan_config) => flash_loan_config,
+        None => {
+            msg!("Creating flash loan config account");
+            let (_, bump_seed) = Pubkey::find_program_address(
+                &[reserve_info.key.as_ref(), FLASH_LOAN_CONFIG_SEED],
+                program_id,
+            );
+            create_derived_account(
+                lending_market_owner_info,
+                flash_loan_config_info,
+                FlashLoanConfig::LEN,
+                program_id,
+                &[
+                    reserve_info.key.as_ref(),
+                    FLASH_LOAN_CONFIG_SEED,
+                    &[bump_seed],
+                ],
+            )?;
+            FlashLoanConfig::new(*reserve_info.key, bump_seed)
+        }
+    };
+
+    update(&reserve, &mut flash_loan_config)?;
+    FlashLoanConfig::pack(
+        flash_loan_config,
+        &mut flash_loan_config_info.data.borrow_mut(),
+    )?;
+
+    Ok(())
+}
+
+#[inline(never)] // avoid stack frame limit
+fn process_set_borrow_grace_config(
+    program_id: &Pubkey,
+    grace_slots: u64,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
     let reserve_info = next_account_info(account_info_iter)?;
     let lending_market_info = next_account_info(account_info_iter)?;
-    let lending_market_authority_info = next_account_info(account_info_iter)?;
-    let sysvar_info = next_account_info(account_info_iter)?;
-    let token_program_id = next_account_info(account_info_iter)?;
-    let clock = Clock::get()?;
+    let borrow_grace_config_info = next_account_info(account_info_iter)?;
+    let lending_market_owner_info = next_account_info(account_info_iter)?;
 
-    _refresh_reserve_interest(program_id, reserve_info, &clock)?;
-    _flash_borrow_reserve_liquidity(
+    let reserve = Reserve::unpack(&reserve_info.data.borrow())?;
+    if reserve_info.owner != program_id {
+        msg!("Reserve provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+    if &reserve.lending_market != lending_market_info.key {
+        msg!("Reserve lending market does not match the lending market provided");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+
+    let lending_market = LendingMarket::unpack(&lending_market_info.data.borrow())?;
+    if lending_market_info.owner != program_id {
+        msg!("Lending market provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+    if &lending_market.owner != lending_market_owner_info.key {
+        msg!("Lending market owner does not match the lending market owner provided");
+        return Err(LendingError::InvalidMarketOwner.into());
+    }
+    if !lending_market_owner_info.is_signer {
+        msg!("Lending market owner provided must be a signer");
+        return Err(LendingError::InvalidSigner.into());
+    }
+
+    validate_borrow_grace_config(grace_slots, reserve.slots_per_year)?;
+
+    let mut borrow_grace_config = match unpack_derived_account::<BorrowGraceConfig>(
         program_id,
-        liquidity_amount,
-        source_liquidity_info,
-        destination_liquidity_info,
-        reserve_info,
+        reserve_info.key,
+        borrow_grace_config_info,
+    )? {
+        Some(borrow_grace_config) => borrow_grace_config,
+        None => {
+            msg!("Creating borrow grace config account");
+            let (_, bump_seed) = Pubkey::find_program_address(
+                &[reserve_info.key.as_ref(), BORROW_GRACE_CONFIG_SEED],
+                program_id,
+            );
+            create_derived_account(
+                lending_market_owner_info,
+                borrow_grace_config_info,
+                BorrowGraceConfig::LEN,
+                program_id,
+                &[
+                    reserve_info.key.as_ref(),
+                    BORROW_GRACE_CONFIG_SEED,
+                    &[bump_seed],
+                ],
+            )?;
+            BorrowGraceConfig::new(*reserve_info.key, bump_seed)
+        }
+    };
+
+    borrow_grace_config.grace_slots = grace_slots;
+    BorrowGraceConfig::pack(
+        borrow_grace_config,
+        &mut borrow_grace_config_info.data.borrow_mut(),
+    )?;
+
+    Ok(())
+}
+
+fn process_set_cross_market_source(
+    program_id: &Pubkey,
+    source_reserve: Pubkey,
+    allowed: bool,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let lending_market_info = next_account_info(account_info_iter)?;
+    let cross_market_sources_info = next_account_info(account_info_iter)?;
+    let lending_market_owner_info = next_account_info(account_info_iter)?;
+
+    let lending_market = LendingMarket::unpack(&lending_market_info.data.borrow())?;
+    if lending_market_info.owner != program_id {
+        msg!("Lending market provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+    if &lending_market.owner != lending_market_owner_info.key {
+        msg!("Lending market owner does not match the lending market owner provided");
+        return Err(LendingError::InvalidMarketOwner.into());
+    }
+    if !lending_market_owner_info.is_signer {
+        msg!("Lending market owner provided must be a signer");
+        return Err(LendingError::InvalidSigner.into());
+    }
+
+    let mut cross_market_sources = match unpack_derived_account::<CrossMarketSources>(
+        program_id,
+        lending_market_info.key,
+        cross_market_sources_info,
+    )? {
+        Some(cross_market_sources) => cross_market_sources,
+        None => {
+            msg!("Creating cross market sources account");
+            let (_, bump_seed) = Pubkey::find_program_address(
+                &[lending_market_info.key.as_ref(), CROSS_MARKET_SOURCES_SEED],
+                program_id,
+            );
+            create_derived_account(
+                lending_market_owner_info,
+                cross_market_sources_info,
+                CrossMarketSources::LEN,
+                program_id,
+                &[
+                    lending_market_info.key.as_ref(),
+                    CROSS_MARKET_SOURCES_SEED,
+                    &[bump_seed],
+                ],
+            )?;
+            CrossMarketSources::new(*lending_market_info.key, bump_seed)
+        }
+    };
+
+    cross_market_sources.set_source(source_reserve, allowed)?;
+    CrossMarketSources::pack(
+        cross_market_sources,
+        &mut cross_market_sources_info.data.borrow_mut(),
+    )?;
+
+    Ok(())
+}
+
+/// Unpacks an obligation of `lending_market_info` owned by `obligation_owner_info`
+fn unpack_owner_obligation(
+    program_id: &Pubkey,
+    obligation_info: &AccountInfo,
+    lending_market_info: &AccountInfo,
+    obligation_owner_info: &AccountInfo,
+) -> Result<Obligation, ProgramError> {
+    if obligation_info.owner != program_id {
+        msg!("Obligation provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+    let obligation = Obligation::unpack(&obligation_info.data.borrow())?;
+    if &obligation.lending_market != lending_market_info.key {
+        msg!("Obligation lending market does not match the lending market provided");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+    if &obligation.owner != obligation_owner_info.key {
+        msg!("Obligation owner does not match the obligation owner provided");
+        return Err(LendingError::InvalidObligationOwner.into());
+    }
+    Ok(obligation)
+}
+
+/// Refreshes an obligation with reserves looked up by pubkey among `reserves`
+fn refresh_obligation_with(
+    obligation: &mut Obligation,
+    reserves: &[(Pubkey, &Reserve)],
+    slot: u64,
+) -> ProgramResult {
+    let find_reserve = |pubkey: &Pubkey| {
+        reserves
+            .iter()
+            .find(|(reserve_pubkey, _)| reserve_pubkey == pubkey)
+            .map(|(_, reserve)| (*reserve).clone())
+            .ok_or(LendingError::InvalidAccountInput)
+    };
+    let deposit_reserves = obligation
+        .deposits
+        .iter()
+        .map(|collateral| find_reserve(&collateral.deposit_reserve))
+        .collect::<Result<Vec<_>, _>>()?;
+    let borrow_reserves = obligation
+        .borrows
+        .iter()
+        .map(|liquidity| find_reserve(&liquidity.borrow_reserve))
+        .collect::<Result<Vec<_>, _>>()?;
+    obligation.refresh(&deposit_reserves, &borrow_reserves, slot)
+}
+
+#[inline(never)] // avoid stack frame limit
+fn process_split_obligation(
+    program_id: &Pubkey,
+    deposits: &[(Pubkey, u64)],
+    borrows: &[(Pubkey, u64)],
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    if deposits.is_empty() && borrows.is_empty() {
+        msg!("At least one deposit or borrow has to be moved");
+        return Err(LendingError::InvalidAmount.into());
+    }
+
+    let account_info_iter = &mut accounts.iter().peekable();
+    let source_obligation_info = next_account_info(account_info_iter)?;
+    let destination_obligation_info = next_account_info(account_info_iter)?;
+    let lending_market_info = next_account_info(account_info_iter)?;
+    let obligation_owner_info = next_account_info(account_info_iter)?;
+    let clock = &Clock::get()?;
+
+    if lending_market_info.owner != program_id {
+        msg!("Lending market provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+    if !obligation_owner_info.is_signer {
+        msg!("Obligation owner provided must be a signer");
+        return Err(LendingError::InvalidSigner.into());
+    }
+    if source_obligation_info.key == destination_obligation_info.key {
+        msg!("Source and destination obligations must be different");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+    let mut source_obligation = unpack_owner_obligation(
+        program_id,
+        source_obligation_info,
         lending_market_info,
-        lending_market_authority_info,
-        sysvar_info,
-        token_program_id,
+        obligation_owner_info,
+    )?;
+    let mut destination_obligation = unpack_owner_obligation(
+        program_id,
+        destination_obligation_info,
+        lending_market_info,
+        obligation_owner_info,
+    )?;
+    if !destination_obligation.deposits.is_empty() || !destination_obligation.borrows.is_empty() {
+        msg!("Destination obligation must have no deposits or borrows");
+        return Err(LendingError::ObligationNotEmpty.into());
+    }
+
+    let (deposit_reserves, borrow_reserves) =
+        unpack_obligation_reserves(program_id, &source_obligation, account_info_iter, clock)?;
+    let reserves = source_obligation
+        .deposits
+        .iter()
+        .map(|collateral| collateral.deposit_reserve)
+        .zip(deposit_reserves.iter())
+        .chain(
+            source_obligation
+                .borrows
+                .iter()
+                .map(|liquidity| liquidity.borrow_reserve)
+                .zip(borrow_reserves.iter()),
+        )
+        .collect::<Vec<_>>();
+
+    // accrue interest first, so the amounts moved include it
+    source_obligation.refresh(&deposit_reserves, &borrow_reserves, clock.slot)?;
+    for (deposit_reserve, collateral_amount) in deposits {
+        source_obligation.transfer_collateral(
+            *deposit_reserve,
+            *collateral_amount,
+            &mut destination_obligation,
+        )?;
+    }
+    for (borrow_reserve, liquidity_amount) in borrows {
+        source_obligation.transfer_liquidity(
+            *borrow_reserve,
+            *liquidity_amount,
+            &mut destination_obligation,
+        )?;
+    }
+
+    for obligation in [&mut source_obligation, &mut destination_obligation] {
+        refresh_obligation_with(obligation, &reserves, clock.slot)?;
+        if !obligation.borrows.is_empty()
+            && obligation.borrowed_value_upper_bound > obligation.allowed_borrow_value
+        {
+            log_error_context(
+                LendingError::ObligationSplitUnhealthy,
+                &[
+                    ("borrowed_value", &obligation.borrowed_value_upper_bound),
+                    ("allowed_borrow_value", &obligation.allowed_borrow_value),
+                ],
+            );
+            msg!("Obligations must stay within their borrow limit after the split");
+            return Err(LendingError::ObligationSplitUnhealthy.into());
+        }
+    }
+
+    Obligation::pack(
+        source_obligation,
+        &mut source_obligation_info.data.borrow_mut(),
+    )?;
+    Obligation::pack(
+        destination_obligation,
+        &mut destination_obligation_info.data.borrow_mut(),
+    )?;
+
+    Ok(())
+}
+
+#[inline(never)] // avoid stack frame limit
+fn process_merge_obligations(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter().peekable();
+    let destination_obligation_info = next_account_info(account_info_iter)?;
+    let lending_market_info = next_account_info(account_info_iter)?;
+    let obligation_owner_info = next_account_info(account_info_iter)?;
+    let obligation_limits_info = next_account_info(account_info_iter)?;
+    let clock = &Clock::get()?;
+
+    if lending_market_info.owner != program_id {
+        msg!("Lending market provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+    if !obligation_owner_info.is_signer {
+        msg!("Obligation owner provided must be a signer");
+        return Err(LendingError::InvalidSigner.into());
+    }
+    let mut destination_obligation = unpack_owner_obligation(
+        program_id,
+        destination_obligation_info,
+        lending_market_info,
+        obligation_owner_info,
+    )?;
+    if destination_obligation.last_update.is_stale(clock.slot)? {
+        msg!("Obligation is stale and must be refreshed in the current slot");
+        return Err(LendingError::ObligationStale.into());
+    }
+    let deposits_len = destination_obligation.deposits.len();
+    let borrows_len = destination_obligation.borrows.len();
+
+    if account_info_iter.peek().is_none() {
+        msg!("At least one obligation to merge has to be provided");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+    let mut merged_keys = vec![destination_obligation_info.key];
+    for source_obligation_info in account_info_iter {
+        if merged_keys.contains(&source_obligation_info.key) {
+            msg!("Obligations to merge must be distinct from each other and the destination");
+            return Err(LendingError::InvalidAccountInput.into());
+        }
+        merged_keys.push(source_obligation_info.key);
+
+        let mut source_obligation = unpack_owner_obligation(
+            program_id,
+            source_obligation_info,
+            lending_market_info,
+            obligation_owner_info,
+        )?;
+        if source_obligation.last_update.is_stale(clock.slot)? {
+            msg!("Obligation to merge is stale and must be refreshed in the current slot");
+            return Err(LendingError::ObligationStale.into());
+        }
+        destination_obligation.merge(&mut source_obligation)?;
+
+        source_obligation.last_update.mark_stale();
+        Obligation::pack(
+            source_obligation,
+            &mut source_obligation_info.data.borrow_mut(),
+        )?;
+    }
+
+    if destination_obligation.borrowing_isolated_asset && destination_obligation.borrows.len() > 1 {
+        msg!("Cannot merge an isolated tier asset borrow with other borrows");
+        return Err(LendingError::IsolatedTierAssetViolation.into());
+    }
+    // only new positions count against the limits, as for deposits and borrows
+    if destination_obligation.deposits.len() > deposits_len
+        || destination_obligation.borrows.len() > borrows_len
+    {
+        if let Some(obligation_limits) = unpack_derived_account::<ObligationLimits>(
+            program_id,
+            lending_market_info.key,
+            obligation_limits_info,
+        )? {
+            if destination_obligation.deposits.len() > deposits_len {
+                obligation_limits.check_deposits(destination_obligation.deposits.len())?;
+            }
+            if destination_obligation.borrows.len() > borrows_len {
+                obligation_limits.check_borrows(destination_obligation.borrows.len())?;
+            }
+        }
+    }
+
+    destination_obligation.last_update.mark_stale();
+    Obligation::pack(
+        destination_obligation,
+        &mut destination_obligation_info.data.borrow_mut(),
+    )?;
+
+    Ok(())
+}
+
+#[inline(never)] // avoid stack frame limit
+fn process_set_dust_sweep_threshold(
+    program_id: &Pubkey,
+    max_deposited_value_wad: u64,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let lending_market_info = next_account_info(account_info_iter)?;
+    let dust_sweep_config_info = next_account_info(account_info_iter)?;
+    let lending_market_owner_info = next_account_info(account_info_iter)?;
+
+    let lending_market = LendingMarket::unpack(&lending_market_info.data.borrow())?;
+    if lending_market_info.owner != program_id {
+        msg!("Lending market provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+    if &lending_market.owner != lending_market_owner_info.key {
+        msg!("Lending market owner does not match the lending market owner provided");
+        return Err(LendingError::InvalidMarketOwner.into());
+    }
+    if !lending_market_owner_info.is_signer {
+        msg!("Lending market owner provided must be a signer");
+        return Err(LendingError::InvalidSigner.into());
+    }
+
+    let mut dust_sweep_config = match unpack_derived_account::<DustSweepConfig>(
+        program_id,
+        lending_market_info.key,
+        dust_sweep_config_info,
+    )? {
+        Some(dust_sweep_config) => dust_sweep_config,
+        None => {
+            msg!("Creating dust sweep config account");
+            let (_, bump_seed) = Pubkey::find_program_address(
+                &[lending_market_info.key.as_ref(), DUST_SWEEP_CONFIG_SEED],
+                program_id,
+            );
+            create_derived_account(
+                lending_market_owner_info,
+                dust_sweep_config_info,
+                DustSweepConfig::LEN,
+                program_id,
+                &[
+                    lending_market_info.key.as_ref(),
+                    DUST_SWEEP_CONFIG_SEED,
+                    &[bump_seed],
+                ],
+            )?;
+            DustSweepConfig::new(*lending_market_info.key, bump_seed)
+        }
+    };
+
+    dust_sweep_config.max_deposited_value =
+        Decimal::from_scaled_val(max_deposited_value_wad.into());
+    DustSweepConfig::pack(
+        dust_sweep_config,
+        &mut dust_sweep_config_info.data.borrow_mut(),
     )?;
+
     Ok(())
 }
 
-#[allow(clippy::too_many_arguments)]
-fn _flash_borrow_reserve_liquidity<'a>(
-    program_id: &Pubkey,
-    liquidity_amount: u64,
-    source_liquidity_info: &AccountInfo<'a>,
-    destination_liquidity_info: &AccountInfo<'a>,
-    reserve_info: &AccountInfo<'a>,
-    lending_market_info: &AccountInfo<'a>,
-    lending_market_authority_info: &AccountInfo<'a>,
-    sysvar_info: &AccountInfo<'a>,
-    token_program_id: &AccountInfo<'a>,
-) -> ProgramResult {
+#[inline(never)] // avoid stack frame limit
+fn process_sweep_dust_obligation(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let obligation_info = next_account_info(account_info_iter)?;
+    let obligation_owner_info = next_account_info(account_info_iter)?;
+    let lending_market_info = next_account_info(account_info_iter)?;
+    let signer_info = next_account_info(account_info_iter)?;
+    let dust_sweep_config_info = next_account_info(account_info_iter)?;
+    let lending_market_authority_info = next_account_info(account_info_iter)?;
+    let token_program_id = next_account_info(account_info_iter)?;
+    let clock = &Clock::get()?;
+
     let lending_market = LendingMarket::unpack(&lending_market_info.data.borrow())?;
     if lending_market_info.owner != program_id {
         msg!("Lending market provided is not owned by the lending program");
@@ -2448,24 +5853,14 @@ fn _flash_borrow_reserve_liquidity<'a>(
         msg!("Lending market token program does not match the token program provided");
         return Err(LendingError::InvalidTokenProgram.into());
     }
-    let mut reserve = Reserve::unpack(&reserve_info.data.borrow())?;
-    if reserve_info.owner != program_id {
-        msg!("Reserve provided is not owned by the lending program");
-        return Err(LendingError::InvalidAccountOwner.into());
-    }
-    if &reserve.lending_market != lending_market_info.key {
-        msg!("Reserve lending market does not match the lending market provided");
-        return Err(LendingError::InvalidAccountInput.into());
-    }
-    if &reserve.liquidity.supply_pubkey != source_liquidity_info.key {
-        msg!("Borrow reserve liquidity supply must be used as the source liquidity provided");
-        return Err(LendingError::InvalidAccountInput.into());
+    if signer_info.key != &lending_market.owner && signer_info.key != &lending_market.risk_authority
+    {
+        msg!("Signer must be the Lending market owner or risk authority");
+        return Err(LendingError::InvalidSigner.into());
     }
-    if &reserve.liquidity.supply_pubkey == destination_liquidity_info.key {
-        msg!(
-            "Borrow reserve liquidity supply cannot be used as the destination liquidity provided"
-        );
-        return Err(LendingError::InvalidAccountInput.into());
+    if !signer_info.is_signer {
+        msg!("Lending market owner or risk authority provided must be a signer");
+        return Err(LendingError::InvalidSigner.into());
     }
     let authority_signer_seeds = &[
         lending_market_info.key.as_ref(),
@@ -2475,165 +5870,525 @@ fn _flash_borrow_reserve_liquidity<'a>(
         Pubkey::create_program_address(authority_signer_seeds, program_id)?;
     if &lending_market_authority_pubkey != lending_market_authority_info.key {
         msg!(
-            "Derived lending market authority {} does not match the lending market authority provided {}",
-            &lending_market_authority_pubkey.to_string(),
-            &lending_market_authority_info.key.to_string(),
+            "Derived lending market authority does not match the lending market authority provided"
         );
         return Err(LendingError::InvalidMarketAuthority.into());
     }
 
-    if reserve.config.fees.flash_loan_fee_wad == u64::MAX {
-        msg!("Flash loans are disabled for this reserve");
-        return Err(LendingError::FlashLoansDisabled.into());
+    if obligation_info.owner != program_id {
+        msg!("Obligation provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+    let obligation = Obligation::unpack(&obligation_info.data.borrow())?;
+    if &obligation.lending_market != lending_market_info.key {
+        msg!("Obligation lending market does not match the lending market provided");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+    // the rent goes back to the obligation owner
+    if &obligation.owner != obligation_owner_info.key {
+        msg!("Obligation owner does not match the obligation owner provided");
+        return Err(LendingError::InvalidObligationOwner.into());
+    }
+    if obligation.last_update.is_stale(clock.slot)? {
+        msg!("Obligation is stale and must be refreshed in the current slot");
+        return Err(LendingError::ObligationStale.into());
+    }
+    if !obligation.borrows.is_empty() {
+        msg!("Obligation must have no borrows to be swept");
+        return Err(LendingError::ObligationNotEmpty.into());
     }
 
-    // Make sure this isnt a cpi call
-    let current_index = load_current_index_checked(sysvar_info)? as usize;
-    if is_cpi_call(program_id, current_index, sysvar_info)? {
-        msg!("Flash Borrow was called via CPI!");
-        return Err(LendingError::FlashBorrowCpi.into());
+    let dust_sweep_config = unpack_derived_account::<DustSweepConfig>(
+        program_id,
+        lending_market_info.key,
+        dust_sweep_config_info,
+    )?
+    .unwrap_or_default();
+    if !dust_sweep_config.is_dust(obligation.deposited_value) {
+        log_error_context(
+            LendingError::ObligationNotDust,
+            &[
+                ("deposited_value", &obligation.deposited_value),
+                ("dust_threshold", &dust_sweep_config.max_deposited_value),
+            ],
+        );
+        msg!("Obligation deposited value must be below the dust sweep threshold");
+        return Err(LendingError::ObligationNotDust.into());
     }
 
-    // Find and validate the flash repay instruction.
-    //
-    // 1. Ensure the instruction is for this program
-    // 2. Ensure the instruction can be unpacked into a LendingInstruction
-    // 3. Ensure that the reserve for the repay matches the borrow
-    // 4. Ensure that there are no other flash instructions in the rest of the transaction
-    // 5. Ensure that the repay amount matches the borrow amount
-    //
-    // If all of these conditions are not met, the flash borrow fails.
-    let mut i = current_index;
-    let mut found_repay_ix = false;
+    // the collateral is held by the reserve collateral supply, so it is burned there and its
+    // liquidity value moved into the protocol fees, as for the protocol liquidation fee
+    for collateral in &obligation.deposits {
+        let deposit_reserve_info = next_account_info(account_info_iter)?;
+        let reserve_collateral_mint_info = next_account_info(account_info_iter)?;
+        let reserve_collateral_supply_info = next_account_info(account_info_iter)?;
+        if deposit_reserve_info.key != &collateral.deposit_reserve {
+            msg!("Deposit reserve provided does not match the deposit reserve in the obligation");
+            return Err(LendingError::InvalidAccountInput.into());
+        }
+        if deposit_reserve_info.owner != program_id {
+            msg!("Deposit reserve provided is not owned by the lending program");
+            return Err(LendingError::InvalidAccountOwner.into());
+        }
+        let mut deposit_reserve = Reserve::unpack(&deposit_reserve_info.data.borrow())?;
+        if &deposit_reserve.collateral.mint_pubkey != reserve_collateral_mint_info.key {
+            msg!("Deposit reserve collateral mint does not match the reserve collateral mint provided");
+            return Err(LendingError::InvalidAccountInput.into());
+        }
+        if &deposit_reserve.collateral.supply_pubkey != reserve_collateral_supply_info.key {
+            msg!("Deposit reserve collateral supply does not match the reserve collateral supply provided");
+            return Err(LendingError::InvalidAccountInput.into());
+        }
+        // the exchange rate only includes the interest accrued up to the last refresh
+        if deposit_reserve.last_update.is_stale(clock.slot)? {
+            msg!("Deposit reserve is stale and must be refreshed in the current slot");
+            return Err(LendingError::ReserveStale.into());
+        }
 
-    loop {
-        i += 1;
+        let liquidity_amount = deposit_reserve
+            .collateral_exchange_rate()?
+            .decimal_collateral_to_liquidity(collateral.deposited_amount.into())?;
+        deposit_reserve
+            .collateral
+            .burn(collateral.deposited_amount)?;
+        deposit_reserve.liquidity.accumulated_protocol_fees_wads = deposit_reserve
+            .liquidity
+            .accumulated_protocol_fees_wads
+            .try_add(liquidity_amount)?;
+        deposit_reserve.last_update.mark_stale();
+        Reserve::pack(deposit_reserve, &mut deposit_reserve_info.data.borrow_mut())?;
+
+        spl_token_burn(TokenBurnParams {
+            mint: reserve_collateral_mint_info.clone(),
+            source: reserve_collateral_supply_info.clone(),
+            amount: collateral.deposited_amount,
+            authority: lending_market_authority_info.clone(),
+            authority_signer_seeds,
+            token_program: token_program_id.clone(),
+        })?;
 
-        let ixn = match load_instruction_at_checked(i, sysvar_info) {
-            Ok(ix) => ix,
-            Err(ProgramError::InvalidArgument) => break, // out of bounds
-            Err(e) => {
-                return Err(e);
-            }
-        };
+        LendingEvent::DustCollateralSwept {
+            obligation: *obligation_info.key,
+            reserve: collateral.deposit_reserve,
+            authority: *signer_info.key,
+            collateral_amount: collateral.deposited_amount,
+            liquidity_amount: liquidity_amount.try_floor_u64()?,
+        }
+        .emit();
+    }
 
-        if ixn.program_id != *program_id {
-            continue;
+    let derived_accounts = find_obligation_derived_accounts(
+        program_id,
+        obligation_info.key,
+        account_info_iter.as_slice(),
+    )
+    .ok_or_else(|| {
+        msg!("The accounts derived from the obligation must be provided");
+        LendingError::InvalidAccountInput
+    })?;
+    close_obligation(
+        program_id,
+        obligation_info,
+        &obligation.owner,
+        obligation_owner_info,
+        &derived_accounts,
+    )
+}
+
+#[inline(never)] // avoid stack frame limit
+fn process_set_obligation_borrow_cap(
+    program_id: &Pubkey,
+    max_borrowed_value: u64,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let obligation_info = next_account_info(account_info_iter)?;
+    let obligation_borrow_cap_info = next_account_info(account_info_iter)?;
+    let obligation_owner_info = next_account_info(account_info_iter)?;
+
+    let mut obligation = Obligation::unpack(&obligation_info.data.borrow())?;
+    if obligation_info.owner != program_id {
+        msg!("Obligation provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+    if &obligation.owner != obligation_owner_info.key {
+        msg!("Obligation owner does not match the obligation owner provided");
+        return Err(LendingError::InvalidObligationOwner.into());
+    }
+    if !obligation_owner_info.is_signer {
+        msg!("Obligation owner provided must be a signer");
+        return Err(LendingError::InvalidSigner.into());
+    }
+
+    let obligation_borrow_cap = match unpack_derived_account::<ObligationBorrowCap>(
+        program_id,
+        obligation_info.key,
+        obligation_borrow_cap_info,
+    )? {
+        Some(obligation_borrow_cap) => ObligationBorrowCap {
+            max_borrowed_value,
+            ..obligation_borrow_cap
+        },
+        None => {
+            msg!("Creating obligation borrow cap account");
+            let (_, bump_seed) = Pubkey::find_program_address(
+                &[obligation_info.key.as_ref(), OBLIGATION_BORROW_CAP_SEED],
+                program_id,
+            );
+            create_derived_account(
+                obligation_owner_info,
+                obligation_borrow_cap_info,
+                ObligationBorrowCap::LEN,
+                program_id,
+                &[
+                    obligation_info.key.as_ref(),
+                    OBLIGATION_BORROW_CAP_SEED,
+                    &[bump_seed],
+                ],
+            )?;
+            ObligationBorrowCap::new(*obligation_info.key, bump_seed, max_borrowed_value)
         }
+    };
 
-        let unpacked = LendingInstruction::unpack(ixn.data.as_slice())?;
-        match unpacked {
-            LendingInstruction::FlashRepayReserveLiquidity {
-                liquidity_amount: repay_liquidity_amount,
-                borrow_instruction_index,
-            } => {
-                if found_repay_ix {
-                    msg!("Multiple flash repays not allowed");
-                    return Err(LendingError::MultipleFlashBorrows.into());
-                }
-                if ixn.accounts[4].pubkey != *reserve_info.key {
-                    msg!("Invalid reserve account on flash repay");
-                    return Err(LendingError::InvalidFlashRepay.into());
-                }
-                if repay_liquidity_amount != liquidity_amount {
-                    msg!("Liquidity amount for flash repay doesn't match borrow");
-                    return Err(LendingError::InvalidFlashRepay.into());
-                }
-                if (borrow_instruction_index as usize) != current_index {
-                    msg!("Borrow instruction index {} for flash repay doesn't match current index {}", borrow_instruction_index, current_index);
-                    return Err(LendingError::InvalidFlashRepay.into());
-                }
+    ObligationBorrowCap::pack(
+        obligation_borrow_cap,
+        &mut obligation_borrow_cap_info.data.borrow_mut(),
+    )?;
+    if !obligation.has_borrow_cap {
+        obligation.has_borrow_cap = true;
+        Obligation::pack(obligation, &mut obligation_info.data.borrow_mut())?;
+    }
+
+    Ok(())
+}
+
+#[inline(never)] // avoid stack frame limit
+fn process_set_collateral_liquidation_priority(
+    program_id: &Pubkey,
+    liquidation_priority: u8,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let obligation_info = next_account_info(account_info_iter)?;
+    let deposit_reserve_info = next_account_info(account_info_iter)?;
+    let obligation_owner_info = next_account_info(account_info_iter)?;
+
+    if obligation_info.owner != program_id {
+        msg!("Obligation provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+    let mut obligation = Obligation::unpack(&obligation_info.data.borrow())?;
+    if &obligation.owner != obligation_owner_info.key {
+        msg!("Obligation owner does not match the obligation owner provided");
+        return Err(LendingError::InvalidObligationOwner.into());
+    }
+    if !obligation_owner_info.is_signer {
+        msg!("Obligation owner provided must be a signer");
+        return Err(LendingError::InvalidSigner.into());
+    }
+
+    let (_, collateral_index) =
+        obligation.find_collateral_in_deposits(*deposit_reserve_info.key)?;
+    obligation.deposits[collateral_index].liquidation_priority = liquidation_priority;
+    Obligation::pack(obligation, &mut obligation_info.data.borrow_mut())?;
+
+    Ok(())
+}
+
+#[inline(never)] // avoid stack frame limit
+fn process_set_borrow_fee_rebate(
+    program_id: &Pubkey,
+    key: Pubkey,
+    rebate_bps: u16,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let lending_market_info = next_account_info(account_info_iter)?;
+    let fee_rebates_info = next_account_info(account_info_iter)?;
+    let lending_market_owner_info = next_account_info(account_info_iter)?;
+
+    let lending_market = LendingMarket::unpack(&lending_market_info.data.borrow())?;
+    if lending_market_info.owner != program_id {
+        msg!("Lending market provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+    if &lending_market.owner != lending_market_owner_info.key {
+        msg!("Lending market owner does not match the lending market owner provided");
+        return Err(LendingError::InvalidMarketOwner.into());
+    }
+    if !lending_market_owner_info.is_signer {
+        msg!("Lending market owner provided must be a signer");
+        return Err(LendingError::InvalidSigner.into());
+    }
+
+    let mut fee_rebates = match unpack_derived_account::<FeeRebates>(
+        program_id,
+        lending_market_info.key,
+        fee_rebates_info,
+    )? {
+        Some(fee_rebates) => fee_rebates,
+        None => {
+            msg!("Creating fee rebates account");
+            let (_, bump_seed) = Pubkey::find_program_address(
+                &[lending_market_info.key.as_ref(), FEE_REBATES_SEED],
+                program_id,
+            );
+            create_derived_account(
+                lending_market_owner_info,
+                fee_rebates_info,
+                FeeRebates::LEN,
+                program_id,
+                &[
+                    lending_market_info.key.as_ref(),
+                    FEE_REBATES_SEED,
+                    &[bump_seed],
+                ],
+            )?;
+            FeeRebates::new(*lending_market_info.key, bump_seed)
+        }
+    };
+
+    fee_rebates.set_rebate(key, rebate_bps)?;
+    FeeRebates::pack(fee_rebates, &mut fee_rebates_info.data.borrow_mut())?;
+
+    Ok(())
+}
+
+#[inline(never)] // avoid stack frame limit
+fn process_set_fee_redemption_hook(
+    program_id: &Pubkey,
+    hook_program: Pubkey,
+    accounts_hash: [u8; 32],
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let reserve_info = next_account_info(account_info_iter)?;
+    let lending_market_info = next_account_info(account_info_iter)?;
+    let fee_redemption_hook_info = next_account_info(account_info_iter)?;
+    let lending_market_owner_info = next_account_info(account_info_iter)?;
 
-                found_repay_ix = true;
-            }
-            LendingInstruction::FlashBorrowReserveLiquidity { .. } => {
-                msg!("Multiple flash borrows not allowed");
-                return Err(LendingError::MultipleFlashBorrows.into());
-            }
-            _ => (),
-        };
+    let mut reserve = Reserve::unpack(&reserve_info.data.borrow())?;
+    if reserve_info.owner != program_id {
+        msg!("Reserve provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+    if &reserve.lending_market != lending_market_info.key {
+        msg!("Reserve lending market does not match the lending market provided");
+        return Err(LendingError::InvalidAccountInput.into());
     }
 
-    if !found_repay_ix {
-        msg!("No flash repay found");
-        return Err(LendingError::NoFlashRepayFound.into());
+    let lending_market = LendingMarket::unpack(&lending_market_info.data.borrow())?;
+    if lending_market_info.owner != program_id {
+        msg!("Lending market provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+    if &lending_market.owner != lending_market_owner_info.key {
+        msg!("Lending market owner does not match the lending market owner provided");
+        return Err(LendingError::InvalidMarketOwner.into());
+    }
+    if !lending_market_owner_info.is_signer {
+        msg!("Lending market owner provided must be a signer");
+        return Err(LendingError::InvalidSigner.into());
+    }
+    // the hook runs after the lending program wrote the reserve, so it must not be able to call
+    // back into it or move tokens as it
+    if &hook_program == program_id || hook_program == lending_market.token_program_id {
+        msg!("Fee redemption hook program cannot be the lending or token program");
+        return Err(LendingError::InvalidConfig.into());
     }
 
-    reserve.liquidity.borrow(Decimal::from(liquidity_amount))?;
-    reserve.last_update.mark_stale();
-    Reserve::pack(reserve, &mut reserve_info.data.borrow_mut())?;
+    let mut fee_redemption_hook = match unpack_derived_account::<FeeRedemptionHook>(
+        program_id,
+        reserve_info.key,
+        fee_redemption_hook_info,
+    )? {
+        Some(fee_redemption_hook) => fee_redemption_hook,
+        None => {
+            msg!("Creating fee redemption hook account");
+            let (_, bump_seed) = Pubkey::find_program_address(
+                &[reserve_info.key.as_ref(), FEE_REDEMPTION_HOOK_SEED],
+                program_id,
+            );
+            create_derived_account(
+                lending_market_owner_info,
+                fee_redemption_hook_info,
+                FeeRedemptionHook::LEN,
+                program_id,
+                &[
+                    reserve_info.key.as_ref(),
+                    FEE_REDEMPTION_HOOK_SEED,
+                    &[bump_seed],
+                ],
+            )?;
+            FeeRedemptionHook::new(*reserve_info.key, bump_seed)
+        }
+    };
 
-    spl_token_transfer(TokenTransferParams {
-        source: source_liquidity_info.clone(),
-        destination: destination_liquidity_info.clone(),
-        amount: liquidity_amount,
-        authority: lending_market_authority_info.clone(),
-        authority_signer_seeds,
-        token_program: token_program_id.clone(),
-    })?;
+    fee_redemption_hook.hook_program = hook_program;
+    fee_redemption_hook.accounts_hash = accounts_hash;
+    reserve.has_fee_redemption_hook = fee_redemption_hook.is_enabled();
+    FeeRedemptionHook::pack(
+        fee_redemption_hook,
+        &mut fee_redemption_hook_info.data.borrow_mut(),
+    )?;
+    Reserve::pack(reserve, &mut reserve_info.data.borrow_mut())?;
 
     Ok(())
 }
 
-fn process_flash_repay_reserve_liquidity(
+#[inline(never)] // avoid stack frame limit
+fn process_set_market_policy(
     program_id: &Pubkey,
-    liquidity_amount: u64,
-    borrow_instruction_index: u8,
+    policy_program: Pubkey,
+    policy_account: Pubkey,
     accounts: &[AccountInfo],
 ) -> ProgramResult {
     let account_info_iter = &mut accounts.iter();
-    let source_liquidity_info = next_account_info(account_info_iter)?;
-    let destination_liquidity_info = next_account_info(account_info_iter)?;
-    let reserve_liquidity_fee_receiver_info = next_account_info(account_info_iter)?;
-    let host_fee_receiver_info = next_account_info(account_info_iter)?;
-    let reserve_info = next_account_info(account_info_iter)?;
     let lending_market_info = next_account_info(account_info_iter)?;
-    let user_transfer_authority_info = next_account_info(account_info_iter)?;
-    let sysvar_info = next_account_info(account_info_iter)?;
-    let token_program_id = next_account_info(account_info_iter)?;
+    let market_policy_info = next_account_info(account_info_iter)?;
+    let lending_market_owner_info = next_account_info(account_info_iter)?;
 
-    _flash_repay_reserve_liquidity(
+    let mut lending_market = LendingMarket::unpack(&lending_market_info.data.borrow())?;
+    if lending_market_info.owner != program_id {
+        msg!("Lending market provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+    if &lending_market.owner != lending_market_owner_info.key {
+        msg!("Lending market owner does not match the lending market owner provided");
+        return Err(LendingError::InvalidMarketOwner.into());
+    }
+    if !lending_market_owner_info.is_signer {
+        msg!("Lending market owner provided must be a signer");
+        return Err(LendingError::InvalidSigner.into());
+    }
+    // the policy program is called in the middle of borrows, withdrawals and liquidations, so it
+    // must not be able to call back into the lending program or move tokens as it
+    if &policy_program == program_id || policy_program == lending_market.token_program_id {
+        msg!("Market policy program cannot be the lending or token program");
+        return Err(LendingError::InvalidConfig.into());
+    }
+
+    let mut market_policy = match unpack_derived_account::<MarketPolicy>(
         program_id,
-        liquidity_amount,
-        borrow_instruction_index,
-        source_liquidity_info,
-        destination_liquidity_info,
-        reserve_liquidity_fee_receiver_info,
-        host_fee_receiver_info,
-        reserve_info,
-        lending_market_info,
-        user_transfer_authority_info,
-        sysvar_info,
-        token_program_id,
-    )?;
+        lending_market_info.key,
+        market_policy_info,
+    )? {
+        Some(market_policy) => market_policy,
+        None => {
+            msg!("Creating market policy account");
+            let (_, bump_seed) = Pubkey::find_program_address(
+                &[lending_market_info.key.as_ref(), MARKET_POLICY_SEED],
+                program_id,
+            );
+            create_derived_account(
+                lending_market_owner_info,
+                market_policy_info,
+                MarketPolicy::LEN,
+                program_id,
+                &[
+                    lending_market_info.key.as_ref(),
+                    MARKET_POLICY_SEED,
+                    &[bump_seed],
+                ],
+            )?;
+            MarketPolicy::new(*lending_market_info.key, bump_seed)
+        }
+    };
+
+    market_policy.policy_program = policy_program;
+    market_policy.policy_account = policy_account;
+    lending_market.has_market_policy = market_policy.is_enabled();
+    MarketPolicy::pack(market_policy, &mut market_policy_info.data.borrow_mut())?;
+    LendingMarket::pack(lending_market, &mut lending_market_info.data.borrow_mut())?;
+
     Ok(())
 }
 
+/// Asks the policy program of the lending market, if it has one, whether `action` may go through.
+/// The market policy, the policy program and its policy account are found among
+/// `optional_accounts`. The policy program gets a fixed set of accounts, all read-only and none
+/// signing, so it can only look at the instruction and fail it.
 #[allow(clippy::too_many_arguments)]
-fn _flash_repay_reserve_liquidity<'a>(
+fn check_market_policy<'a>(
     program_id: &Pubkey,
-    liquidity_amount: u64,
-    borrow_instruction_index: u8,
-    source_liquidity_info: &AccountInfo<'a>,
-    destination_liquidity_info: &AccountInfo<'a>,
-    reserve_liquidity_fee_receiver_info: &AccountInfo<'a>,
-    host_fee_receiver_info: &AccountInfo<'a>,
-    reserve_info: &AccountInfo<'a>,
     lending_market_info: &AccountInfo<'a>,
-    user_transfer_authority_info: &AccountInfo<'a>,
-    sysvar_info: &AccountInfo<'a>,
-    token_program_id: &AccountInfo<'a>,
+    obligation_info: &AccountInfo<'a>,
+    reserve_info: &AccountInfo<'a>,
+    signer_info: &AccountInfo<'a>,
+    action: PolicyAction,
+    amount: u64,
+    optional_accounts: &[AccountInfo<'a>],
 ) -> ProgramResult {
     let lending_market = LendingMarket::unpack(&lending_market_info.data.borrow())?;
-    if lending_market_info.owner != program_id {
-        msg!("Lending market provided is not owned by the lending program");
-        return Err(LendingError::InvalidAccountOwner.into());
-    }
-    if &lending_market.token_program_id != token_program_id.key {
-        msg!("Lending market token program does not match the token program provided");
-        return Err(LendingError::InvalidTokenProgram.into());
+    let market_policy = match find_derived_account::<MarketPolicy>(
+        program_id,
+        lending_market_info.key,
+        optional_accounts,
+        lending_market.has_market_policy,
+    )? {
+        Some((_, market_policy)) if market_policy.is_enabled() => market_policy,
+        _ => return Ok(()),
+    };
+
+    let find_account = |key: &Pubkey, name: &str| {
+        optional_accounts
+            .iter()
+            .find(|account_info| account_info.key == key)
+            .ok_or_else(|| {
+                msg!("Market {} must be provided", name);
+                LendingError::InvalidAccountInput
+            })
+    };
+    let policy_program_info = find_account(&market_policy.policy_program, "policy program")?;
+    let policy_account_info = if market_policy.policy_account != Pubkey::default() {
+        Some(find_account(
+            &market_policy.policy_account,
+            "policy account",
+        )?)
+    } else {
+        None
+    };
+
+    let mut policy_account_infos = vec![
+        lending_market_info.clone(),
+        obligation_info.clone(),
+        reserve_info.clone(),
+        signer_info.clone(),
+    ];
+    policy_account_infos.extend(policy_account_info.cloned());
+    policy_account_infos.push(policy_program_info.clone());
+    invoke(
+        &check_policy(
+            *policy_program_info.key,
+            action,
+            amount,
+            *lending_market_info.key,
+            *obligation_info.key,
+            *reserve_info.key,
+            *signer_info.key,
+            policy_account_info.map(|account_info| *account_info.key),
+        ),
+        &policy_account_infos,
+    )
+}
+
+fn process_set_reserve_accrual_epoch(
+    program_id: &Pubkey,
+    epoch_slots: u64,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    if epoch_slots == 0 {
+        msg!("Epoch length must be greater than zero");
+        return Err(LendingError::InvalidConfig.into());
     }
-    let mut reserve = Reserve::unpack(&reserve_info.data.borrow())?;
+
+    let account_info_iter = &mut accounts.iter();
+    let reserve_info = next_account_info(account_info_iter)?;
+    let accrual_history_info = next_account_info(account_info_iter)?;
+    let lending_market_info = next_account_info(account_info_iter)?;
+    let lending_market_owner_info = next_account_info(account_info_iter)?;
+
+    let reserve = Reserve::unpack(&reserve_info.data.borrow())?;
     if reserve_info.owner != program_id {
         msg!("Reserve provided is not owned by the lending program");
         return Err(LendingError::InvalidAccountOwner.into());
@@ -2642,134 +6397,70 @@ fn _flash_repay_reserve_liquidity<'a>(
         msg!("Reserve lending market does not match the lending market provided");
         return Err(LendingError::InvalidAccountInput.into());
     }
-    if &reserve.liquidity.supply_pubkey != destination_liquidity_info.key {
-        msg!("Reserve liquidity supply does not match the reserve liquidity supply provided");
-        return Err(LendingError::InvalidAccountInput.into());
-    }
-    if &reserve.liquidity.supply_pubkey == source_liquidity_info.key {
-        msg!("Reserve liquidity supply cannot be used as the source liquidity provided");
-        return Err(LendingError::InvalidAccountInput.into());
-    }
-    if &reserve.config.fee_receiver != reserve_liquidity_fee_receiver_info.key {
-        msg!("Reserve liquidity fee receiver does not match the reserve liquidity fee receiver provided");
-        return Err(LendingError::InvalidAccountInput.into());
-    }
-
-    let flash_loan_amount = liquidity_amount;
-
-    let flash_loan_amount_decimal = Decimal::from(flash_loan_amount);
-    let (origination_fee, host_fee) = reserve
-        .config
-        .fees
-        .calculate_flash_loan_fees(flash_loan_amount_decimal)?;
 
-    // Make sure this isnt a cpi call
-    let current_index = load_current_index_checked(sysvar_info)? as usize;
-    if is_cpi_call(program_id, current_index, sysvar_info)? {
-        msg!("Flash Repay was called via CPI!");
-        return Err(LendingError::FlashRepayCpi.into());
+    let lending_market = LendingMarket::unpack(&lending_market_info.data.borrow())?;
+    if lending_market_info.owner != program_id {
+        msg!("Lending market provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
     }
-
-    // validate flash borrow
-    if (borrow_instruction_index as usize) > current_index {
-        msg!(
-            "Flash repay: borrow instruction index {} has to be less than current index {}",
-            borrow_instruction_index,
-            current_index
-        );
-        return Err(LendingError::InvalidFlashRepay.into());
+    if &lending_market.owner != lending_market_owner_info.key {
+        msg!("Lending market owner does not match the lending market owner provided");
+        return Err(LendingError::InvalidMarketOwner.into());
     }
-
-    let ixn = load_instruction_at_checked(borrow_instruction_index as usize, sysvar_info)?;
-    if ixn.program_id != *program_id {
-        msg!(
-            "Flash repay: supplied instruction index {} doesn't belong to program id {}",
-            borrow_instruction_index,
-            *program_id
-        );
-        return Err(LendingError::InvalidFlashRepay.into());
+    if !lending_market_owner_info.is_signer {
+        msg!("Lending market owner provided must be a signer");
+        return Err(LendingError::InvalidSigner.into());
     }
 
-    let unpacked = LendingInstruction::unpack(ixn.data.as_slice())?;
-    match unpacked {
-        LendingInstruction::FlashBorrowReserveLiquidity {
-            liquidity_amount: borrow_liquidity_amount,
-        } => {
-            // re-check everything here out of paranoia
-            if ixn.accounts[2].pubkey != *reserve_info.key {
-                msg!("Invalid reserve account on flash repay");
-                return Err(LendingError::InvalidFlashRepay.into());
-            }
-
-            if liquidity_amount != borrow_liquidity_amount {
-                msg!("Liquidity amount for flash repay doesn't match borrow");
-                return Err(LendingError::InvalidFlashRepay.into());
-            }
+    let accrual_history = match unpack_derived_account::<ReserveAccrualHistory>(
+        program_id,
+        reserve_info.key,
+        accrual_history_info,
+    )? {
+        // epochs of different lengths don't line up, so the recorded ones are dropped
+        Some(accrual_history) if accrual_history.epoch_slots != epoch_slots => {
+            ReserveAccrualHistory::new(*reserve_info.key, accrual_history.bump_seed, epoch_slots)
         }
-        _ => {
-            msg!("Flash repay: Supplied borrow instruction index is not a flash borrow");
-            return Err(LendingError::InvalidFlashRepay.into());
+        Some(accrual_history) => accrual_history,
+        None => {
+            msg!("Creating reserve accrual history account");
+            let (_, bump_seed) = Pubkey::find_program_address(
+                &[reserve_info.key.as_ref(), RESERVE_ACCRUAL_HISTORY_SEED],
+                program_id,
+            );
+            create_derived_account(
+                lending_market_owner_info,
+                accrual_history_info,
+                ReserveAccrualHistory::LEN,
+                program_id,
+                &[
+                    reserve_info.key.as_ref(),
+                    RESERVE_ACCRUAL_HISTORY_SEED,
+                    &[bump_seed],
+                ],
+            )?;
+            ReserveAccrualHistory::new(*reserve_info.key, bump_seed, epoch_slots)
         }
     };
 
-    reserve
-        .liquidity
-        .repay(flash_loan_amount, flash_loan_amount_decimal)?;
-    reserve.last_update.mark_stale();
-    Reserve::pack(reserve, &mut reserve_info.data.borrow_mut())?;
-
-    spl_token_transfer(TokenTransferParams {
-        source: source_liquidity_info.clone(),
-        destination: destination_liquidity_info.clone(),
-        amount: flash_loan_amount,
-        authority: user_transfer_authority_info.clone(),
-        authority_signer_seeds: &[],
-        token_program: token_program_id.clone(),
-    })?;
-
-    if host_fee > 0 {
-        spl_token_transfer(TokenTransferParams {
-            source: source_liquidity_info.clone(),
-            destination: host_fee_receiver_info.clone(),
-            amount: host_fee,
-            authority: user_transfer_authority_info.clone(),
-            authority_signer_seeds: &[],
-            token_program: token_program_id.clone(),
-        })?;
-    }
-
-    if origination_fee > 0 {
-        spl_token_transfer(TokenTransferParams {
-            source: source_liquidity_info.clone(),
-            destination: reserve_liquidity_fee_receiver_info.clone(),
-            amount: origination_fee,
-            authority: user_transfer_authority_info.clone(),
-            authority_signer_seeds: &[],
-            token_program: token_program_id.clone(),
-        })?;
-    }
+    ReserveAccrualHistory::pack(accrual_history, &mut accrual_history_info.data.borrow_mut())?;
 
     Ok(())
 }
 
-fn process_forgive_debt(
+fn process_set_deleverage_config(
     program_id: &Pubkey,
-    liquidity_amount: u64,
+    max_exempt_value: u64,
     accounts: &[AccountInfo],
 ) -> ProgramResult {
     let account_info_iter = &mut accounts.iter();
-    let obligation_info = next_account_info(account_info_iter)?;
-    let reserve_info = next_account_info(account_info_iter)?;
     let lending_market_info = next_account_info(account_info_iter)?;
+    let deleverage_config_info = next_account_info(account_info_iter)?;
     let lending_market_owner_info = next_account_info(account_info_iter)?;
 
     let lending_market = LendingMarket::unpack(&lending_market_info.data.borrow())?;
     if lending_market_info.owner != program_id {
-        msg!(
-            "Lending market provided is not owned by the lending program  {} != {}",
-            &lending_market_info.owner.to_string(),
-            &program_id.to_string(),
-        );
+        msg!("Lending market provided is not owned by the lending program");
         return Err(LendingError::InvalidAccountOwner.into());
     }
     if &lending_market.owner != lending_market_owner_info.key {
@@ -2781,145 +6472,307 @@ fn process_forgive_debt(
         return Err(LendingError::InvalidSigner.into());
     }
 
-    let mut reserve = Reserve::unpack(&reserve_info.data.borrow())?;
-    if reserve_info.owner != program_id {
-        msg!("Reserve provided is not owned by the lending program");
-        return Err(LendingError::InvalidAccountOwner.into());
-    }
-    if &reserve.lending_market != lending_market_info.key {
-        msg!("Reserve lending market does not match the lending market provided");
-        return Err(LendingError::InvalidAccountInput.into());
-    }
-    if reserve.last_update.is_stale(Clock::get()?.slot)? {
-        msg!("Reserve is stale and must be refreshed in the current slot");
-        return Err(LendingError::ReserveStale.into());
-    }
+    let mut deleverage_config = match unpack_derived_account::<DeleverageConfig>(
+        program_id,
+        lending_market_info.key,
+        deleverage_config_info,
+    )? {
+        Some(deleverage_config) => deleverage_config,
+        None => {
+            msg!("Creating deleverage config account");
+            let (_, bump_seed) = Pubkey::find_program_address(
+                &[lending_market_info.key.as_ref(), DELEVERAGE_CONFIG_SEED],
+                program_id,
+            );
+            create_derived_account(
+                lending_market_owner_info,
+                deleverage_config_info,
+                DeleverageConfig::LEN,
+                program_id,
+                &[
+                    lending_market_info.key.as_ref(),
+                    DELEVERAGE_CONFIG_SEED,
+                    &[bump_seed],
+                ],
+            )?;
+            DeleverageConfig::new(*lending_market_info.key, bump_seed, 0)
+        }
+    };
+
+    deleverage_config.max_exempt_value = max_exempt_value;
+    DeleverageConfig::pack(
+        deleverage_config,
+        &mut deleverage_config_info.data.borrow_mut(),
+    )?;
+
+    Ok(())
+}
+
+fn process_init_deleverage_credit(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let obligation_info = next_account_info(account_info_iter)?;
+    let deleverage_credit_info = next_account_info(account_info_iter)?;
+    let obligation_owner_info = next_account_info(account_info_iter)?;
 
     let mut obligation = Obligation::unpack(&obligation_info.data.borrow())?;
     if obligation_info.owner != program_id {
         msg!("Obligation provided is not owned by the lending program");
         return Err(LendingError::InvalidAccountOwner.into());
     }
-    if &obligation.lending_market != lending_market_info.key {
-        msg!("Obligation lending market does not match the lending market provided");
-        return Err(LendingError::InvalidAccountInput.into());
+    if &obligation.owner != obligation_owner_info.key {
+        msg!("Obligation owner does not match the obligation owner provided");
+        return Err(LendingError::InvalidObligationOwner.into());
     }
-    if obligation.last_update.is_stale(Clock::get()?.slot)? {
-        msg!("Obligation is stale and must be refreshed in the current slot");
-        return Err(LendingError::ObligationStale.into());
+    if !obligation_owner_info.is_signer {
+        msg!("Obligation owner provided must be a signer");
+        return Err(LendingError::InvalidSigner.into());
     }
-    if !obligation.deposits.is_empty() {
-        msg!("Obligation hasn't been fully liquidated!");
-        return Err(LendingError::InvalidAccountInput.into());
+
+    if unpack_derived_account::<DeleverageCredit>(
+        program_id,
+        obligation_info.key,
+        deleverage_credit_info,
+    )?
+    .is_some()
+    {
+        msg!("Deleverage credit account is already initialized");
+        return Err(LendingError::AlreadyInitialized.into());
     }
 
-    // in the case where the entire reserve got rugged for whatever reason, we still don't
-    // want to forgive the entire reserve's supply because that'll mess with the ctoken ratio
-    // and cause overflow/div by zero issues in other places. therefore, we want to make sure the ctoken
-    // ratio is >= 1% after forgiveness.
-    //
-    // new ctoken ratio = (total_liquidity_supply - forgive_amount) / collateral_mint_supply >= 0.01
-    // -> forgive_amount <= (total_liquidity_supply - collateral_mint_supply * 0.01)
-    const MIN_CTOKEN_RATIO_PERCENT: u8 = 1;
-    let max_forgive_amount = reserve.liquidity.total_supply()?.try_sub(
-        Decimal::from(reserve.collateral.mint_total_supply)
-            .try_mul(Decimal::from_percent(MIN_CTOKEN_RATIO_PERCENT))?,
+    let (_, bump_seed) = Pubkey::find_program_address(
+        &[obligation_info.key.as_ref(), DELEVERAGE_CREDIT_SEED],
+        program_id,
+    );
+    create_derived_account(
+        obligation_owner_info,
+        deleverage_credit_info,
+        DeleverageCredit::LEN,
+        program_id,
+        &[
+            obligation_info.key.as_ref(),
+            DELEVERAGE_CREDIT_SEED,
+            &[bump_seed],
+        ],
     )?;
+    DeleverageCredit::pack(
+        DeleverageCredit::new(*obligation_info.key, bump_seed),
+        &mut deleverage_credit_info.data.borrow_mut(),
+    )?;
+    obligation.has_deleverage_credit = true;
+    Obligation::pack(obligation, &mut obligation_info.data.borrow_mut())?;
 
-    let (liquidity, liquidity_index) = obligation.find_liquidity_in_borrows(*reserve_info.key)?;
-    let forgive_amount = min(
-        Decimal::from(liquidity_amount),
-        min(liquidity.borrowed_amount_wads, max_forgive_amount),
-    );
+    Ok(())
+}
 
-    reserve.liquidity.forgive_debt(forgive_amount)?;
-    reserve.last_update.mark_stale();
-    Reserve::pack(reserve, &mut reserve_info.data.borrow_mut())?;
+/// Program that CPI'd into the lending program straight from its top level instruction, or None
+/// if the lending program was invoked at the top level. Programs further down a CPI chain can't be
+/// told apart from the top level program, so they get None as well.
+fn calling_program(
+    program_id: &Pubkey,
+    instructions_sysvar_info: &AccountInfo,
+) -> Result<Option<Pubkey>, ProgramError> {
+    // the instructions sysvar only holds top level instructions, see is_cpi_call
+    let current_index = load_current_index_checked(instructions_sysvar_info)? as usize;
+    let current_ixn = load_instruction_at_checked(current_index, instructions_sysvar_info)?;
+    if current_ixn.program_id == *program_id
+        || get_stack_height() > TRANSACTION_LEVEL_STACK_HEIGHT + 1
+    {
+        return Ok(None);
+    }
 
-    obligation.repay(forgive_amount, liquidity_index)?;
-    obligation.last_update.mark_stale();
-    Obligation::pack(obligation, &mut obligation_info.data.borrow_mut())?;
+    Ok(Some(current_ixn.program_id))
+}
 
-    Ok(())
+/// Zeroes a program owned account and moves all of its lamports to the destination, so the
+/// runtime garbage collects it at the end of the transaction. Returns the lamports moved.
+fn close_account(
+    account_info: &AccountInfo,
+    destination_info: &AccountInfo,
+) -> Result<u64, ProgramError> {
+    let lamports = account_info.lamports();
+    **destination_info.lamports.borrow_mut() = destination_info
+        .lamports()
+        .checked_add(lamports)
+        .ok_or(LendingError::MathOverflow)?;
+    **account_info.lamports.borrow_mut() = 0;
+    account_info.data.borrow_mut().fill(0);
+    Ok(lamports)
 }
 
-fn assert_rent_exempt(rent: &Rent, account_info: &AccountInfo) -> ProgramResult {
-    if !rent.is_exempt(account_info.lamports(), account_info.data_len()) {
-        msg!(
-            "Rent exempt balance insufficient got {} expected {}",
-            &account_info.lamports().to_string(),
-            &rent.minimum_balance(account_info.data_len()).to_string(),
-        );
-        Err(LendingError::NotRentExempt.into())
-    } else {
-        Ok(())
+/// Create a program owned account at a derived address. Unlike `create_account`, this still
+/// works if someone has already sent lamports to the address, which would otherwise block it.
+fn create_derived_account<'a>(
+    payer_info: &AccountInfo<'a>,
+    account_info: &AccountInfo<'a>,
+    space: usize,
+    owner: &Pubkey,
+    signer_seeds: &[&[u8]],
+) -> ProgramResult {
+    let required_lamports = Rent::get()?
+        .minimum_balance(space)
+        .saturating_sub(account_info.lamports());
+    if required_lamports > 0 {
+        invoke(
+            &transfer(payer_info.key, account_info.key, required_lamports),
+            &[payer_info.clone(), account_info.clone()],
+        )?;
     }
+    invoke_signed(
+        &allocate(account_info.key, space as u64),
+        std::slice::from_ref(account_info),
+        &[signer_seeds],
+    )?;
+    invoke_signed(
+        &assign(account_info.key, owner),
+        std::slice::from_ref(account_info),
+        &[signer_seeds],
+    )
 }
 
-fn process_update_market_metadata(
+fn validate_borrow_allowance_accounts(
     program_id: &Pubkey,
-    metadata: &LendingMarketMetadata,
-    accounts: &[AccountInfo],
-) -> Result<(), ProgramError> {
-    let account_info_iter = &mut accounts.iter();
-    let lending_market_info = next_account_info(account_info_iter)?;
-    let lending_market_owner_info = next_account_info(account_info_iter)?;
-    let metadata_info = next_account_info(account_info_iter)?;
-
-    let lending_market = LendingMarket::unpack(&lending_market_info.data.borrow())?;
-    if lending_market_info.owner != program_id {
-        msg!("Lending market provided is not owned by the lending program",);
+    obligation_info: &AccountInfo,
+    borrow_reserve_info: &AccountInfo,
+    delegate_info: &AccountInfo,
+    obligation_owner_info: &AccountInfo,
+) -> ProgramResult {
+    let obligation = Obligation::unpack(&obligation_info.data.borrow())?;
+    if obligation_info.owner != program_id {
+        msg!("Obligation provided is not owned by the lending program");
         return Err(LendingError::InvalidAccountOwner.into());
     }
-
-    if &lending_market.owner != lending_market_owner_info.key {
-        msg!("Lending market owner does not match the lending market owner provided");
-        return Err(LendingError::InvalidMarketOwner.into());
+    if &obligation.owner != obligation_owner_info.key {
+        msg!("Obligation owner does not match the obligation owner provided");
+        return Err(LendingError::InvalidObligationOwner.into());
     }
-
-    if !lending_market_owner_info.is_signer {
-        msg!("Lending market owner provided must be a signer");
+    if !obligation_owner_info.is_signer {
+        msg!("Obligation owner provided must be a signer");
         return Err(LendingError::InvalidSigner.into());
     }
+    if delegate_info.key == obligation_owner_info.key {
+        msg!("Obligation owner cannot be its own delegate");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
 
-    let metadata_seeds = &[lending_market_info.key.as_ref(), b"MetaData"];
-    let (metadata_key, bump_seed) = Pubkey::find_program_address(metadata_seeds, program_id);
-    if metadata_key != *metadata_info.key {
-        msg!("Provided metadata account does not match the expected derived address");
+    let borrow_reserve = Reserve::unpack(&borrow_reserve_info.data.borrow())?;
+    if borrow_reserve_info.owner != program_id {
+        msg!("Borrow reserve provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+    if borrow_reserve.lending_market != obligation.lending_market {
+        msg!("Borrow reserve lending market does not match the obligation lending market");
         return Err(LendingError::InvalidAccountInput.into());
     }
 
-    if bump_seed != metadata.bump_seed {
-        msg!("Provided bump seed does not match the expected derived bump seed");
-        return Err(LendingError::InvalidAmount.into());
+    Ok(())
+}
+
+/// Unpacks the account derived from `parent`, or returns None if it was never created.
+fn unpack_derived_account<T: DerivedAccount>(
+    program_id: &Pubkey,
+    parent: &Pubkey,
+    account_info: &AccountInfo,
+) -> Result<Option<T>, ProgramError> {
+    if account_info.data_is_empty() {
+        let (key, _) = T::find_address(program_id, parent);
+        if &key != account_info.key {
+            msg!(
+                "Provided {} account does not match the expected derived address",
+                T::NAME
+            );
+            return Err(LendingError::InvalidAccountInput.into());
+        }
+        return Ok(None);
     }
 
-    // initialize
-    if metadata_info.data_is_empty() {
-        msg!("Creating metadata account");
+    unpack_existing_derived_account(program_id, parent, account_info).map(Some)
+}
 
-        invoke_signed(
-            &create_account(
-                lending_market_owner_info.key,
-                metadata_info.key,
-                Rent::get()?.minimum_balance(std::mem::size_of::<LendingMarketMetadata>()),
-                std::mem::size_of::<LendingMarketMetadata>() as u64,
-                program_id,
-            ),
-            &[lending_market_owner_info.clone(), metadata_info.clone()],
-            &[&[lending_market_info.key.as_ref(), br"MetaData", &[bump_seed]]],
+/// Health buffer of the lending market's obligation limits, found among the optional accounts of
+/// an instruction
+fn find_health_buffer(
+    program_id: &Pubkey,
+    lending_market_info: &AccountInfo,
+    optional_accounts: &[AccountInfo],
+) -> Result<Bps, ProgramError> {
+    let lending_market = LendingMarket::unpack(&lending_market_info.data.borrow())?;
+    Ok(find_derived_account::<ObligationLimits>(
+        program_id,
+        lending_market_info.key,
+        optional_accounts,
+        lending_market.has_obligation_limits,
+    )?
+    .map_or(Bps(0), |(_, obligation_limits)| {
+        obligation_limits.health_buffer()
+    }))
+}
+
+/// Finds the account derived from `parent` among the optional accounts of an instruction, which
+/// can come in any order. Fails if it isn't there and `required` says the parent has one.
+fn find_derived_account<'a, 'b, T: DerivedAccount>(
+    program_id: &Pubkey,
+    parent: &Pubkey,
+    optional_accounts: &'b [AccountInfo<'a>],
+    required: bool,
+) -> Result<Option<(&'b AccountInfo<'a>, T)>, ProgramError> {
+    for account_info in optional_accounts {
+        // the data length tells the account types of the lending program apart
+        if account_info.owner != program_id || account_info.data_len() != T::LEN {
+            continue;
+        }
+        let account = T::unpack(&account_info.data.borrow())?;
+        if account.parent() != parent {
+            continue;
+        }
+        let key = Pubkey::create_program_address(
+            &[parent.as_ref(), T::SEED, &[account.bump_seed()]],
+            program_id,
         )?;
+        if &key == account_info.key {
+            return Ok(Some((account_info, account)));
+        }
     }
 
-    if metadata_info.owner != program_id {
-        msg!("Metadata provided is not owned by the lending program");
-        return Err(LendingError::InvalidAccountOwner.into());
+    if required {
+        msg!("The {} account must be provided", T::NAME);
+        return Err(LendingError::InvalidAccountInput.into());
     }
+    Ok(None)
+}
 
-    let mut metadata_account_data = metadata_info.try_borrow_mut_data()?;
-    metadata_account_data.copy_from_slice(bytes_of(metadata));
-
-    Ok(())
+/// Unpacks the account derived from `parent`, checking its owner and derived address.
+fn unpack_existing_derived_account<T: DerivedAccount>(
+    program_id: &Pubkey,
+    parent: &Pubkey,
+    account_info: &AccountInfo,
+) -> Result<T, ProgramError> {
+    if account_info.owner != program_id {
+        msg!(
+            "Provided {} account is not owned by the lending program",
+            T::NAME
+        );
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+    let account = T::unpack(&account_info.data.borrow())?;
+    if account.parent() != parent {
+        msg!(
+            "Provided {} account belongs to a different account than the one provided",
+            T::NAME
+        );
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+    let key = Pubkey::create_program_address(
+        &[parent.as_ref(), T::SEED, &[account.bump_seed()]],
+        program_id,
+    )?;
+    if &key != account_info.key {
+        msg!(
+            "Provided {} account does not match the expected derived address",
+            T::NAME
+        );
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+    Ok(account)
 }
 
 fn assert_uninitialized<T: Pack + IsInitialized>(
@@ -2938,6 +6791,11 @@ fn unpack_mint(data: &[u8]) -> Result<Mint, LendingError> {
     Mint::unpack(data).map_err(|_| LendingError::InvalidTokenMint)
 }
 
+/// Unpacks a spl_token `Account`.
+fn unpack_token_account(data: &[u8]) -> Result<TokenAccount, LendingError> {
+    TokenAccount::unpack(data).map_err(|_| LendingError::InvalidTokenAccount)
+}
+
 fn get_pyth_product_quote_currency(
     pyth_product: &ProductAccount,
 ) -> Result<[u8; 32], ProgramError> {
@@ -2983,6 +6841,39 @@ fn get_price(
     Err(LendingError::InvalidOracleConfig.into())
 }
 
+/// Same as `get_price`, but prices cross market reserves from their source reserve, which takes the
+/// place of the switchboard feed.
+fn get_reserve_price(
+    program_id: &Pubkey,
+    switchboard_feed_info: Option<&AccountInfo>,
+    pyth_price_account_info: &AccountInfo,
+    clock: &Clock,
+) -> Result<(Decimal, Option<Decimal>), ProgramError> {
+    match switchboard_feed_info {
+        Some(source_reserve_info) if source_reserve_info.owner == program_id => {
+            get_cross_market_price(source_reserve_info, clock)
+        }
+        _ => get_price(switchboard_feed_info, pyth_price_account_info, clock),
+    }
+}
+
+/// Prices a cToken of another lending market from its reserve's exchange rate and oracle prices.
+/// The source reserve must be refreshed earlier in the same slot so both are current.
+fn get_cross_market_price(
+    source_reserve_info: &AccountInfo,
+    clock: &Clock,
+) -> Result<(Decimal, Option<Decimal>), ProgramError> {
+    let source_reserve = Reserve::unpack(&source_reserve_info.data.borrow())?;
+    if source_reserve.last_update.is_stale(clock.slot)? {
+        msg!("Cross market source reserve is stale and must be refreshed in the current slot");
+        return Err(LendingError::ReserveStale.into());
+    }
+    validate_cross_market_source_pricing(&source_reserve)?;
+
+    let (market_price, smoothed_market_price) = source_reserve.collateral_prices()?;
+    Ok((market_price, Some(smoothed_market_price)))
+}
+
 fn get_switchboard_price(
     switchboard_feed_info: &AccountInfo,
     clock: &Clock,
@@ -3124,6 +7015,9 @@ fn spl_token_transfer(params: TokenTransferParams<'_, '_>) -> ProgramResult {
         amount,
         authority_signer_seeds,
     } = params;
+    // the token program would fail the transfer too, but with an error that doesn't say why
+    check_token_account_not_frozen(&source)?;
+    check_token_account_not_frozen(&destination)?;
     let result = invoke_optionally_signed(
         &spl_token::instruction::transfer(
             token_program.key,
@@ -3140,6 +7034,21 @@ fn spl_token_transfer(params: TokenTransferParams<'_, '_>) -> ProgramResult {
     result.map_err(|_| LendingError::TokenTransferFailed.into())
 }
 
+/// Fail with TokenAccountFrozen if the freeze authority of the account's mint froze it
+fn check_token_account_not_frozen(account_info: &AccountInfo) -> ProgramResult {
+    if let Ok(token_account) = TokenAccount::unpack(&account_info.data.borrow()) {
+        if token_account.is_frozen() {
+            msg!("Token account is frozen");
+            log_error_context(
+                LendingError::TokenAccountFrozen,
+                &[("account", account_info.key), ("mint", &token_account.mint)],
+            );
+            return Err(LendingError::TokenAccountFrozen.into());
+        }
+    }
+    Ok(())
+}
+
 /// Issue a spl_token `MintTo` instruction.
 fn spl_token_mint_to(params: TokenMintToParams<'_, '_>) -> ProgramResult {
     let TokenMintToParams {
@@ -3244,6 +7153,61 @@ fn validate_switchboard_keys(
     Ok(())
 }
 
+/// Checks a reserve whose liquidity is the cToken of a reserve in another lending market. Such
+/// reserves are priced only by their source reserve, which the lending market has to allow in its
+/// cross market sources. Their tier and LTV are up to the lending market owner like for any other
+/// reserve: the cTokens are only as risky as the source reserve.
+fn validate_cross_market_source(
+    program_id: &Pubkey,
+    lending_market: &Pubkey,
+    liquidity_mint: &Pubkey,
+    pyth_price_info: &AccountInfo,
+    source_reserve_info: &AccountInfo,
+    optional_accounts: &[AccountInfo],
+) -> ProgramResult {
+    let source_reserve = Reserve::unpack(&source_reserve_info.data.borrow())?;
+    if &source_reserve.lending_market == lending_market {
+        msg!("Cross market source reserve must belong to another lending market");
+        return Err(LendingError::InvalidOracleConfig.into());
+    }
+    if &source_reserve.collateral.mint_pubkey != liquidity_mint {
+        msg!(
+            "Reserve liquidity mint does not match the cross market source reserve collateral mint"
+        );
+        return Err(LendingError::InvalidOracleConfig.into());
+    }
+    if *pyth_price_info.key != solend_program::NULL_PUBKEY {
+        msg!("Cross market reserves are priced by their source reserve and can't use pyth");
+        return Err(LendingError::InvalidOracleConfig.into());
+    }
+    validate_cross_market_source_pricing(&source_reserve)?;
+
+    let allowed = match find_derived_account::<CrossMarketSources>(
+        program_id,
+        lending_market,
+        optional_accounts,
+        false,
+    )? {
+        Some((_, cross_market_sources)) => cross_market_sources.is_allowed(source_reserve_info.key),
+        None => false,
+    };
+    if !allowed {
+        msg!("Cross market source reserve is not allowed by the lending market");
+        return Err(LendingError::InvalidOracleConfig.into());
+    }
+    Ok(())
+}
+
+/// Sources have to be priced by pyth. Cross market reserves never are, so a cross market reserve
+/// can't be the source of another one, even if it becomes one after being allowed.
+fn validate_cross_market_source_pricing(source_reserve: &Reserve) -> ProgramResult {
+    if source_reserve.liquidity.pyth_oracle_pubkey == solend_program::NULL_PUBKEY {
+        msg!("Cross market source reserve must be priced by pyth");
+        return Err(LendingError::InvalidOracleConfig.into());
+    }
+    Ok(())
+}
+
 fn is_cpi_call(
     program_id: &Pubkey,
     current_index: usize,
@@ -0,0 +1,326 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use crate::solend_program_test::*;
+use helpers::*;
+use solana_program::native_token::LAMPORTS_PER_SOL;
+use solana_program::system_instruction::transfer;
+use solana_program_test::*;
+use solana_sdk::{signature::Keypair, signature::Signer};
+use solend_program::{
+    error::LendingError,
+    instruction::{
+        approve_borrow_allowance, delegated_borrow_obligation_liquidity,
+        find_borrow_allowances_address, revoke_borrow_allowance,
+    },
+    state::*,
+};
+use spl_token::state::Account as Token;
+
+async fn setup() -> (
+    SolendProgramTest,
+    Info<LendingMarket>,
+    Info<Reserve>,
+    User,
+    Info<Obligation>,
+    User,
+) {
+    let (mut test, lending_market, usdc_reserve, wsol_reserve, _lending_market_owner, user) =
+        setup_world(&test_reserve_config(), &test_reserve_config()).await;
+
+    let obligation = lending_market
+        .init_obligation(&mut test, Keypair::new(), &user)
+        .await
+        .expect("This should succeed");
+
+    lending_market
+        .deposit(&mut test, &usdc_reserve, &user, 100_000_000)
+        .await
+        .expect("This should succeed");
+
+    let usdc_reserve = test.load_account(usdc_reserve.pubkey).await;
+
+    lending_market
+        .deposit_obligation_collateral(&mut test, &usdc_reserve, &obligation, &user, 100_000_000)
+        .await
+        .expect("This should succeed");
+
+    let wsol_depositor = User::new_with_balances(
+        &mut test,
+        &[
+            (&wsol_mint::id(), 5 * LAMPORTS_PER_SOL),
+            (&wsol_reserve.account.collateral.mint_pubkey, 0),
+        ],
+    )
+    .await;
+
+    lending_market
+        .deposit(
+            &mut test,
+            &wsol_reserve,
+            &wsol_depositor,
+            5 * LAMPORTS_PER_SOL,
+        )
+        .await
+        .unwrap();
+
+    // the obligation owner pays for the borrow allowances account
+    test.process_transaction(
+        &[transfer(
+            &test.context.payer.pubkey(),
+            &user.keypair.pubkey(),
+            LAMPORTS_PER_SOL,
+        )],
+        None,
+    )
+    .await
+    .unwrap();
+
+    let delegate = User::new_with_balances(&mut test, &[(&wsol_mint::id(), 0)]).await;
+
+    let lending_market = test.load_account(lending_market.pubkey).await;
+    let wsol_reserve = test.load_account(wsol_reserve.pubkey).await;
+    let obligation = test.load_account::<Obligation>(obligation.pubkey).await;
+
+    (
+        test,
+        lending_market,
+        wsol_reserve,
+        user,
+        obligation,
+        delegate,
+    )
+}
+
+async fn delegated_borrow(
+    test: &mut SolendProgramTest,
+    lending_market: &Info<LendingMarket>,
+    borrow_reserve: &Info<Reserve>,
+    obligation: &Info<Obligation>,
+    delegate: &User,
+    liquidity_amount: u64,
+) -> Result<(), BanksClientError> {
+    let mut instructions = lending_market
+        .build_refresh_instructions(test, obligation, Some(borrow_reserve))
+        .await;
+    instructions.push(delegated_borrow_obligation_liquidity(
+        solend_program::id(),
+        liquidity_amount,
+        borrow_reserve.account.liquidity.supply_pubkey,
+        delegate.get_account(&wsol_mint::id()).unwrap(),
+        borrow_reserve.pubkey,
+        borrow_reserve.account.config.fee_receiver,
+        obligation.pubkey,
+        lending_market.pubkey,
+        delegate.keypair.pubkey(),
+        None,
+    ));
+
+    test.process_transaction(&instructions, Some(&[&delegate.keypair]))
+        .await
+}
+
+#[tokio::test]
+async fn test_success() {
+    let (mut test, lending_market, wsol_reserve, user, obligation, delegate) = setup().await;
+
+    test.process_transaction(
+        &[approve_borrow_allowance(
+            solend_program::id(),
+            2 * LAMPORTS_PER_SOL,
+            obligation.pubkey,
+            wsol_reserve.pubkey,
+            delegate.keypair.pubkey(),
+            user.keypair.pubkey(),
+        )],
+        Some(&[&user.keypair]),
+    )
+    .await
+    .unwrap();
+
+    let (borrow_allowances_pubkey, bump_seed) =
+        find_borrow_allowances_address(&solend_program::id(), &obligation.pubkey);
+    let borrow_allowances = test
+        .load_account::<BorrowAllowances>(borrow_allowances_pubkey)
+        .await;
+    assert_eq!(
+        borrow_allowances.account,
+        BorrowAllowances {
+            version: PROGRAM_VERSION,
+            bump_seed,
+            obligation: obligation.pubkey,
+            allowances: vec![BorrowAllowance {
+                delegate: delegate.keypair.pubkey(),
+                borrow_reserve: wsol_reserve.pubkey,
+                amount: 2 * LAMPORTS_PER_SOL,
+            }],
+        }
+    );
+
+    delegated_borrow(
+        &mut test,
+        &lending_market,
+        &wsol_reserve,
+        &obligation,
+        &delegate,
+        LAMPORTS_PER_SOL,
+    )
+    .await
+    .unwrap();
+
+    let delegate_balance = test
+        .load_account::<Token>(delegate.get_account(&wsol_mint::id()).unwrap())
+        .await;
+    assert_eq!(delegate_balance.account.amount, LAMPORTS_PER_SOL);
+
+    let obligation_post = test.load_account::<Obligation>(obligation.pubkey).await;
+    assert_eq!(
+        obligation_post.account.borrows[0].borrowed_amount_wads,
+        LAMPORTS_PER_SOL.into()
+    );
+
+    let borrow_allowances = test
+        .load_account::<BorrowAllowances>(borrow_allowances_pubkey)
+        .await;
+    assert_eq!(
+        borrow_allowances.account.allowances[0].amount,
+        LAMPORTS_PER_SOL
+    );
+
+    // can't borrow more than the remaining allowance
+    test.advance_clock_by_slots(1).await;
+    let res = delegated_borrow(
+        &mut test,
+        &lending_market,
+        &wsol_reserve,
+        &obligation,
+        &delegate,
+        LAMPORTS_PER_SOL + 1,
+    )
+    .await;
+
+    expect_lending_error(res, LendingError::InsufficientBorrowAllowance);
+
+    // borrowing the rest of the allowance removes it
+    delegated_borrow(
+        &mut test,
+        &lending_market,
+        &wsol_reserve,
+        &obligation,
+        &delegate,
+        u64::MAX,
+    )
+    .await
+    .unwrap();
+
+    let borrow_allowances = test
+        .load_account::<BorrowAllowances>(borrow_allowances_pubkey)
+        .await;
+    assert!(borrow_allowances.account.allowances.is_empty());
+}
+
+#[tokio::test]
+async fn test_revoke() {
+    let (mut test, lending_market, wsol_reserve, user, obligation, delegate) = setup().await;
+
+    test.process_transaction(
+        &[
+            approve_borrow_allowance(
+                solend_program::id(),
+                2 * LAMPORTS_PER_SOL,
+                obligation.pubkey,
+                wsol_reserve.pubkey,
+                delegate.keypair.pubkey(),
+                user.keypair.pubkey(),
+            ),
+            revoke_borrow_allowance(
+                solend_program::id(),
+                obligation.pubkey,
+                wsol_reserve.pubkey,
+                delegate.keypair.pubkey(),
+                user.keypair.pubkey(),
+            ),
+        ],
+        Some(&[&user.keypair]),
+    )
+    .await
+    .unwrap();
+
+    let res = delegated_borrow(
+        &mut test,
+        &lending_market,
+        &wsol_reserve,
+        &obligation,
+        &delegate,
+        LAMPORTS_PER_SOL,
+    )
+    .await;
+
+    expect_lending_error(res, LendingError::InvalidObligationOwner);
+}
+
+#[tokio::test]
+async fn test_approve_prefunded_borrow_allowances() {
+    let (mut test, lending_market, wsol_reserve, user, obligation, delegate) = setup().await;
+
+    // lamports sent to the borrow allowances address ahead of time don't block its creation
+    let (borrow_allowances_pubkey, _) =
+        find_borrow_allowances_address(&solend_program::id(), &obligation.pubkey);
+    test.process_transaction(
+        &[transfer(
+            &test.context.payer.pubkey(),
+            &borrow_allowances_pubkey,
+            1,
+        )],
+        None,
+    )
+    .await
+    .unwrap();
+
+    test.process_transaction(
+        &[approve_borrow_allowance(
+            solend_program::id(),
+            2 * LAMPORTS_PER_SOL,
+            obligation.pubkey,
+            wsol_reserve.pubkey,
+            delegate.keypair.pubkey(),
+            user.keypair.pubkey(),
+        )],
+        Some(&[&user.keypair]),
+    )
+    .await
+    .unwrap();
+
+    delegated_borrow(
+        &mut test,
+        &lending_market,
+        &wsol_reserve,
+        &obligation,
+        &delegate,
+        LAMPORTS_PER_SOL,
+    )
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn test_fail_approve_not_owner() {
+    let (mut test, _lending_market, wsol_reserve, _user, obligation, delegate) = setup().await;
+
+    let res = test
+        .process_transaction(
+            &[approve_borrow_allowance(
+                solend_program::id(),
+                2 * LAMPORTS_PER_SOL,
+                obligation.pubkey,
+                wsol_reserve.pubkey,
+                Keypair::new().pubkey(),
+                delegate.keypair.pubkey(),
+            )],
+            Some(&[&delegate.keypair]),
+        )
+        .await;
+
+    expect_lending_error(res, LendingError::InvalidObligationOwner);
+}
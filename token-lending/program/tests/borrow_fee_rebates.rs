@@ -0,0 +1,418 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use crate::solend_program_test::*;
+use helpers::cpi_proxy::{cpi_proxy, cpi_proxy_program};
+use helpers::*;
+use solana_program::{instruction::Instruction, native_token::LAMPORTS_PER_SOL, pubkey::Pubkey};
+use solana_program_test::*;
+use solana_sdk::{compute_budget::ComputeBudgetInstruction, signature::Keypair, signature::Signer};
+use solend_program::{
+    error::LendingError,
+    instruction::{
+        borrow_obligation_liquidity, find_fee_rebates_address, rebated_borrow_obligation_liquidity,
+        set_borrow_fee_rebate,
+    },
+    state::*,
+};
+use spl_token::state::Account as Token;
+
+// 1% borrow fee on wSOL
+const BORROW_FEE: u64 = LAMPORTS_PER_SOL / 100;
+
+async fn setup() -> (
+    SolendProgramTest,
+    Info<LendingMarket>,
+    Info<Reserve>,
+    Info<Obligation>,
+    User,
+    User,
+) {
+    let (test, lending_market, reserves, obligations, mut users, lending_market_owner) =
+        custom_scenario(
+            &[
+                ReserveArgs {
+                    mint: usdc_mint::id(),
+                    config: test_reserve_config(),
+                    liquidity_amount: 100_000 * FRACTIONAL_TO_USDC,
+                    price: PriceArgs {
+                        price: 1,
+                        conf: 0,
+                        expo: 0,
+                        ema_price: 1,
+                        ema_conf: 0,
+                    },
+                },
+                ReserveArgs {
+                    mint: wsol_mint::id(),
+                    config: ReserveConfig {
+                        optimal_borrow_rate: 0,
+                        max_borrow_rate: 0,
+                        fees: ReserveFees {
+                            borrow_fee_wad: 10_000_000_000_000_000,
+                            flash_loan_fee_wad: 0,
+                            host_fee_percentage: 0,
+                        },
+                        ..test_reserve_config()
+                    },
+                    liquidity_amount: 100 * LAMPORTS_PER_SOL,
+                    price: PriceArgs {
+                        price: 10,
+                        conf: 0,
+                        expo: 0,
+                        ema_price: 10,
+                        ema_conf: 0,
+                    },
+                },
+            ],
+            &[ObligationArgs {
+                deposits: vec![(usdc_mint::id(), 1_000 * FRACTIONAL_TO_USDC)],
+                borrows: vec![],
+            }],
+        )
+        .await;
+
+    let wsol_reserve = find_reserve(&reserves, &wsol_mint::id()).unwrap();
+    (
+        test,
+        lending_market,
+        wsol_reserve,
+        obligations[0].clone(),
+        users.remove(0),
+        lending_market_owner,
+    )
+}
+
+async fn set_rebate(
+    test: &mut SolendProgramTest,
+    lending_market: &Info<LendingMarket>,
+    signer: &Keypair,
+    key: Pubkey,
+    rebate_bps: u16,
+) -> Result<(), BanksClientError> {
+    test.process_transaction(
+        &[set_borrow_fee_rebate(
+            solend_program::id(),
+            key,
+            rebate_bps,
+            lending_market.pubkey,
+            signer.pubkey(),
+        )],
+        Some(&[signer]),
+    )
+    .await
+}
+
+/// Borrows 1 SOL and returns the borrow fee paid. `claim_rebate` adds the fee rebate accounts and
+/// `via_cpi` routes the borrow through the cpi proxy program.
+#[allow(clippy::too_many_arguments)]
+async fn borrow(
+    test: &mut SolendProgramTest,
+    lending_market: &Info<LendingMarket>,
+    wsol_reserve: &Info<Reserve>,
+    obligation: &Info<Obligation>,
+    user: &User,
+    claim_rebate: bool,
+    via_cpi: bool,
+    modify: impl FnOnce(&mut Instruction),
+) -> Result<u64, BanksClientError> {
+    let refresh_ixs = lending_market
+        .build_refresh_instructions(test, obligation, Some(wsol_reserve))
+        .await;
+    test.process_transaction(&refresh_ixs, None).await.unwrap();
+
+    let builder = if claim_rebate {
+        rebated_borrow_obligation_liquidity
+    } else {
+        borrow_obligation_liquidity
+    };
+    let mut instruction = builder(
+        solend_program::id(),
+        LAMPORTS_PER_SOL,
+        wsol_reserve.account.liquidity.supply_pubkey,
+        user.get_account(&wsol_mint::id()).unwrap(),
+        wsol_reserve.pubkey,
+        wsol_reserve.account.config.fee_receiver,
+        obligation.pubkey,
+        lending_market.pubkey,
+        user.keypair.pubkey(),
+        None,
+    );
+    modify(&mut instruction);
+    if via_cpi {
+        instruction = cpi_proxy(instruction);
+    }
+
+    let fee_receiver = wsol_reserve.account.config.fee_receiver;
+    let balance_before = test
+        .load_account::<Token>(fee_receiver)
+        .await
+        .account
+        .amount;
+    test.process_transaction(
+        &[
+            ComputeBudgetInstruction::set_compute_unit_limit(100_000),
+            instruction,
+        ],
+        Some(&[&user.keypair]),
+    )
+    .await?;
+    let balance_after = test
+        .load_account::<Token>(fee_receiver)
+        .await
+        .account
+        .amount;
+
+    Ok(balance_after - balance_before)
+}
+
+#[tokio::test]
+async fn test_rebate_for_whitelisted_program() {
+    let (mut test, lending_market, wsol_reserve, obligation, user, lending_market_owner) =
+        setup().await;
+
+    set_rebate(
+        &mut test,
+        &lending_market,
+        &lending_market_owner.keypair,
+        cpi_proxy_program::id(),
+        5_000,
+    )
+    .await
+    .unwrap();
+
+    let (fee_rebates_pubkey, _) =
+        find_fee_rebates_address(&solend_program::id(), &lending_market.pubkey);
+    let fee_rebates = test.load_account::<FeeRebates>(fee_rebates_pubkey).await;
+    assert_eq!(
+        fee_rebates.account.rebates,
+        vec![FeeRebate {
+            key: cpi_proxy_program::id(),
+            rebate_bps: 5_000
+        }]
+    );
+
+    let fee = borrow(
+        &mut test,
+        &lending_market,
+        &wsol_reserve,
+        &obligation,
+        &user,
+        true,
+        true,
+        |_| {},
+    )
+    .await
+    .unwrap();
+    assert_eq!(fee, BORROW_FEE / 2);
+
+    // rebates have to be claimed
+    test.advance_clock_by_slots(1).await;
+    let fee = borrow(
+        &mut test,
+        &lending_market,
+        &wsol_reserve,
+        &obligation,
+        &user,
+        false,
+        true,
+        |_| {},
+    )
+    .await
+    .unwrap();
+    assert_eq!(fee, BORROW_FEE);
+}
+
+#[tokio::test]
+async fn test_no_rebate_for_direct_calls_and_other_programs() {
+    let (mut test, lending_market, wsol_reserve, obligation, user, lending_market_owner) =
+        setup().await;
+
+    // the proxy isn't whitelisted
+    set_rebate(
+        &mut test,
+        &lending_market,
+        &lending_market_owner.keypair,
+        Pubkey::new_unique(),
+        5_000,
+    )
+    .await
+    .unwrap();
+
+    let fee = borrow(
+        &mut test,
+        &lending_market,
+        &wsol_reserve,
+        &obligation,
+        &user,
+        true,
+        true,
+        |_| {},
+    )
+    .await
+    .unwrap();
+    assert_eq!(fee, BORROW_FEE);
+
+    // the proxy is whitelisted, but the borrow doesn't come from it
+    set_rebate(
+        &mut test,
+        &lending_market,
+        &lending_market_owner.keypair,
+        cpi_proxy_program::id(),
+        5_000,
+    )
+    .await
+    .unwrap();
+
+    test.advance_clock_by_slots(1).await;
+    let fee = borrow(
+        &mut test,
+        &lending_market,
+        &wsol_reserve,
+        &obligation,
+        &user,
+        true,
+        false,
+        |_| {},
+    )
+    .await
+    .unwrap();
+    assert_eq!(fee, BORROW_FEE);
+}
+
+#[tokio::test]
+async fn test_rebate_for_whitelisted_signer() {
+    let (mut test, lending_market, wsol_reserve, obligation, user, lending_market_owner) =
+        setup().await;
+
+    set_rebate(
+        &mut test,
+        &lending_market,
+        &lending_market_owner.keypair,
+        user.keypair.pubkey(),
+        10_000,
+    )
+    .await
+    .unwrap();
+
+    let fee = borrow(
+        &mut test,
+        &lending_market,
+        &wsol_reserve,
+        &obligation,
+        &user,
+        true,
+        false,
+        |_| {},
+    )
+    .await
+    .unwrap();
+    assert_eq!(fee, 0);
+
+    // removing the rebate brings the fee back
+    set_rebate(
+        &mut test,
+        &lending_market,
+        &lending_market_owner.keypair,
+        user.keypair.pubkey(),
+        0,
+    )
+    .await
+    .unwrap();
+
+    test.advance_clock_by_slots(1).await;
+    let fee = borrow(
+        &mut test,
+        &lending_market,
+        &wsol_reserve,
+        &obligation,
+        &user,
+        true,
+        false,
+        |_| {},
+    )
+    .await
+    .unwrap();
+    assert_eq!(fee, BORROW_FEE);
+}
+
+#[tokio::test]
+async fn test_no_rebate_for_spoofed_fee_rebates() {
+    let (mut test, lending_market, wsol_reserve, obligation, user, lending_market_owner) =
+        setup().await;
+
+    set_rebate(
+        &mut test,
+        &lending_market,
+        &lending_market_owner.keypair,
+        cpi_proxy_program::id(),
+        10_000,
+    )
+    .await
+    .unwrap();
+
+    // a fee rebates account that isn't the market's pda isn't picked up
+    let fee = borrow(
+        &mut test,
+        &lending_market,
+        &wsol_reserve,
+        &obligation,
+        &user,
+        true,
+        false,
+        |instruction| instruction.accounts.last_mut().unwrap().pubkey = Pubkey::new_unique(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(fee, BORROW_FEE);
+
+    // another market's fee rebates
+    let other_market = test
+        .init_lending_market(&lending_market_owner, &Keypair::new())
+        .await
+        .unwrap();
+    set_rebate(
+        &mut test,
+        &other_market,
+        &lending_market_owner.keypair,
+        user.keypair.pubkey(),
+        10_000,
+    )
+    .await
+    .unwrap();
+    let (other_fee_rebates, _) =
+        find_fee_rebates_address(&solend_program::id(), &other_market.pubkey);
+
+    test.advance_clock_by_slots(1).await;
+    let fee = borrow(
+        &mut test,
+        &lending_market,
+        &wsol_reserve,
+        &obligation,
+        &user,
+        true,
+        false,
+        |instruction| instruction.accounts.last_mut().unwrap().pubkey = other_fee_rebates,
+    )
+    .await
+    .unwrap();
+    assert_eq!(fee, BORROW_FEE);
+}
+
+#[tokio::test]
+async fn test_fail_set_rebate_invalid_owner() {
+    let (mut test, lending_market, _wsol_reserve, _obligation, user, _lending_market_owner) =
+        setup().await;
+
+    let res = set_rebate(
+        &mut test,
+        &lending_market,
+        &user.keypair,
+        user.keypair.pubkey(),
+        10_000,
+    )
+    .await;
+
+    expect_lending_error(res, LendingError::InvalidMarketOwner);
+}
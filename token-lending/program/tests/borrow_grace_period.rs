@@ -0,0 +1,328 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use crate::solend_program_test::*;
+use helpers::*;
+use solana_program::native_token::LAMPORTS_PER_SOL;
+use solana_program_test::*;
+use solana_sdk::signature::{Keypair, Signer};
+use solend_program::{
+    error::LendingError,
+    instruction::{find_borrow_grace_config_address, set_borrow_grace_config},
+    math::{Decimal, TryAdd, TrySub},
+    state::{BorrowGraceConfig, BorrowRateMode, LendingMarket, Obligation, Reserve, ReserveConfig},
+};
+
+struct Setup {
+    test: SolendProgramTest,
+    lending_market: Info<LendingMarket>,
+    wsol_reserve: Info<Reserve>,
+    obligation: Info<Obligation>,
+    lending_market_owner: User,
+    user: User,
+}
+
+// an obligation with 100 USDC of collateral, and 5 SOL to borrow from a reserve taking 10% of
+// the interest as protocol fees
+async fn setup() -> Setup {
+    let (mut test, lending_market, usdc_reserve, wsol_reserve, lending_market_owner, user) =
+        setup_world(
+            &test_reserve_config(),
+            &ReserveConfig {
+                protocol_take_rate: 10,
+                ..test_reserve_config()
+            },
+        )
+        .await;
+
+    let obligation = lending_market
+        .init_obligation(&mut test, Keypair::new(), &user)
+        .await
+        .unwrap();
+    lending_market
+        .deposit(&mut test, &usdc_reserve, &user, 100_000_000)
+        .await
+        .unwrap();
+    let usdc_reserve = test.load_account(usdc_reserve.pubkey).await;
+    lending_market
+        .deposit_obligation_collateral(&mut test, &usdc_reserve, &obligation, &user, 100_000_000)
+        .await
+        .unwrap();
+
+    let wsol_depositor = User::new_with_balances(
+        &mut test,
+        &[
+            (&wsol_mint::id(), 5 * LAMPORTS_PER_SOL),
+            (&wsol_reserve.account.collateral.mint_pubkey, 0),
+        ],
+    )
+    .await;
+    lending_market
+        .deposit(
+            &mut test,
+            &wsol_reserve,
+            &wsol_depositor,
+            5 * LAMPORTS_PER_SOL,
+        )
+        .await
+        .unwrap();
+
+    let wsol_reserve = test.load_account(wsol_reserve.pubkey).await;
+    let obligation = test.load_account(obligation.pubkey).await;
+    Setup {
+        test,
+        lending_market,
+        wsol_reserve,
+        obligation,
+        lending_market_owner,
+        user,
+    }
+}
+
+async fn set_grace_slots(setup: &mut Setup, grace_slots: u64) -> Result<(), BanksClientError> {
+    setup
+        .test
+        .process_transaction(
+            &[set_borrow_grace_config(
+                solend_program::id(),
+                grace_slots,
+                setup.wsol_reserve.pubkey,
+                setup.lending_market.pubkey,
+                setup.lending_market_owner.keypair.pubkey(),
+            )],
+            Some(&[&setup.lending_market_owner.keypair]),
+        )
+        .await
+}
+
+async fn borrow(setup: &mut Setup, liquidity_amount: u64) -> Result<(), BanksClientError> {
+    let wsol_reserve = setup.test.load_account(setup.wsol_reserve.pubkey).await;
+    setup
+        .lending_market
+        .borrow_obligation_liquidity(
+            &mut setup.test,
+            &wsol_reserve,
+            &setup.obligation,
+            &setup.user,
+            None,
+            liquidity_amount,
+        )
+        .await
+}
+
+async fn refreshed_accounts(setup: &mut Setup) -> (Obligation, Reserve) {
+    let obligation = setup.test.load_account(setup.obligation.pubkey).await;
+    setup
+        .lending_market
+        .refresh_obligation(&mut setup.test, &obligation)
+        .await
+        .unwrap();
+    (
+        setup
+            .test
+            .load_account::<Obligation>(setup.obligation.pubkey)
+            .await
+            .account,
+        setup
+            .test
+            .load_account::<Reserve>(setup.wsol_reserve.pubkey)
+            .await
+            .account,
+    )
+}
+
+async fn repay(setup: &mut Setup, liquidity_amount: u64) -> Result<(), BanksClientError> {
+    let wsol_reserve = setup.test.load_account(setup.wsol_reserve.pubkey).await;
+    setup
+        .lending_market
+        .repay_obligation_liquidity(
+            &mut setup.test,
+            &wsol_reserve,
+            &setup.obligation,
+            &setup.user,
+            liquidity_amount,
+        )
+        .await
+}
+
+async fn loaded_accounts(setup: &mut Setup) -> (Obligation, Reserve) {
+    (
+        setup
+            .test
+            .load_account::<Obligation>(setup.obligation.pubkey)
+            .await
+            .account,
+        setup
+            .test
+            .load_account::<Reserve>(setup.wsol_reserve.pubkey)
+            .await
+            .account,
+    )
+}
+
+#[tokio::test]
+async fn test_interest_free_grace_period() {
+    let mut setup = setup().await;
+    set_grace_slots(&mut setup, 1_000).await.unwrap();
+
+    let (borrow_grace_config_pubkey, _) =
+        find_borrow_grace_config_address(&solend_program::id(), &setup.wsol_reserve.pubkey);
+    let borrow_grace_config = setup
+        .test
+        .load_account::<BorrowGraceConfig>(borrow_grace_config_pubkey)
+        .await;
+    assert_eq!(
+        borrow_grace_config.account.reserve,
+        setup.wsol_reserve.pubkey
+    );
+    assert_eq!(borrow_grace_config.account.grace_slots, 1_000);
+
+    borrow(&mut setup, LAMPORTS_PER_SOL).await.unwrap();
+    let slot = setup.test.get_clock().await.slot;
+    let (obligation, _) = loaded_accounts(&mut setup).await;
+    let borrowed_amount_wads = obligation.borrows[0].borrowed_amount_wads;
+    assert_eq!(
+        obligation.borrows[0].rate_mode,
+        BorrowRateMode::InterestFree
+    );
+    assert_eq!(obligation.grace_end_slot, slot + 1_000);
+
+    // interest accrues on the reserve and the obligation alike, to be waived
+    setup.test.advance_clock_by_slots(500).await;
+    let (obligation, reserve) = refreshed_accounts(&mut setup).await;
+    assert_eq!(
+        obligation.borrows[0].borrowed_amount_wads,
+        reserve.liquidity.borrowed_amount_wads
+    );
+    assert_eq!(
+        obligation.grace_interest_wads,
+        obligation.borrows[0]
+            .borrowed_amount_wads
+            .try_sub(borrowed_amount_wads)
+            .unwrap()
+    );
+    let protocol_fees = reserve.liquidity.accumulated_protocol_fees_wads;
+    assert!(protocol_fees > Decimal::zero());
+    assert!(protocol_fees < obligation.grace_interest_wads);
+
+    // repaying waives what the protocol fees can fund, the borrower owes the rest
+    repay(&mut setup, 1).await.unwrap();
+    let (repaid_obligation, repaid_reserve) = loaded_accounts(&mut setup).await;
+    assert_eq!(
+        repaid_reserve.liquidity.accumulated_protocol_fees_wads,
+        Decimal::zero()
+    );
+    assert_eq!(repaid_obligation.grace_interest_wads, Decimal::zero());
+    assert_eq!(
+        repaid_obligation.borrows[0].borrowed_amount_wads,
+        obligation.borrows[0]
+            .borrowed_amount_wads
+            .try_sub(protocol_fees)
+            .unwrap()
+            .try_sub(Decimal::from(1u64))
+            .unwrap()
+    );
+    assert_eq!(
+        repaid_obligation.borrows[0].borrowed_amount_wads,
+        repaid_reserve.liquidity.borrowed_amount_wads
+    );
+    assert_eq!(
+        repaid_obligation.borrows[0].rate_mode,
+        BorrowRateMode::InterestFree
+    );
+
+    // the next touch past the grace period waives what's left off both sides alike, and
+    // converts the borrow to the variable rate
+    setup.test.advance_clock_by_slots(1_000).await;
+    let (obligation, reserve) = refreshed_accounts(&mut setup).await;
+    repay(&mut setup, 1).await.unwrap();
+    let (repaid_obligation, repaid_reserve) = loaded_accounts(&mut setup).await;
+    assert_eq!(
+        repaid_obligation.borrows[0].rate_mode,
+        BorrowRateMode::Variable
+    );
+    assert_eq!(repaid_obligation.grace_end_slot, 0);
+    let waived_interest = reserve
+        .liquidity
+        .accumulated_protocol_fees_wads
+        .try_sub(repaid_reserve.liquidity.accumulated_protocol_fees_wads)
+        .unwrap();
+    assert!(waived_interest > Decimal::zero());
+    assert_eq!(
+        obligation.borrows[0]
+            .borrowed_amount_wads
+            .try_sub(repaid_obligation.borrows[0].borrowed_amount_wads)
+            .unwrap(),
+        waived_interest.try_add(Decimal::from(1u64)).unwrap()
+    );
+    assert_eq!(
+        reserve
+            .liquidity
+            .borrowed_amount_wads
+            .try_sub(repaid_reserve.liquidity.borrowed_amount_wads)
+            .unwrap(),
+        waived_interest.try_add(Decimal::from(1u64)).unwrap()
+    );
+}
+
+#[tokio::test]
+async fn test_fail_repay_interest_free_borrow_with_stale_obligation() {
+    let mut setup = setup().await;
+    set_grace_slots(&mut setup, 1_000).await.unwrap();
+    borrow(&mut setup, LAMPORTS_PER_SOL).await.unwrap();
+
+    setup.test.advance_clock_by_slots(1).await;
+    let wsol_reserve = setup.test.load_account(setup.wsol_reserve.pubkey).await;
+    setup
+        .lending_market
+        .refresh_reserve(&mut setup.test, &wsol_reserve)
+        .await
+        .unwrap();
+    let res = repay(&mut setup, 1).await;
+    expect_lending_error(res, LendingError::ObligationStale);
+}
+
+#[tokio::test]
+async fn test_no_grace_period_without_config() {
+    let mut setup = setup().await;
+    borrow(&mut setup, LAMPORTS_PER_SOL).await.unwrap();
+
+    let obligation = setup
+        .test
+        .load_account::<Obligation>(setup.obligation.pubkey)
+        .await;
+    assert_eq!(
+        obligation.account.borrows[0].rate_mode,
+        BorrowRateMode::Variable
+    );
+}
+
+#[tokio::test]
+async fn test_fail_invalid_config() {
+    let mut setup = setup().await;
+
+    let grace_slots = setup.wsol_reserve.account.slots_per_year + 1;
+    let res = set_grace_slots(&mut setup, grace_slots).await;
+    expect_lending_error(res, LendingError::InvalidConfig);
+}
+
+#[tokio::test]
+async fn test_fail_not_lending_market_owner() {
+    let mut setup = setup().await;
+
+    let res = setup
+        .test
+        .process_transaction(
+            &[set_borrow_grace_config(
+                solend_program::id(),
+                1_000,
+                setup.wsol_reserve.pubkey,
+                setup.lending_market.pubkey,
+                setup.user.keypair.pubkey(),
+            )],
+            Some(&[&setup.user.keypair]),
+        )
+        .await;
+    expect_lending_error(res, LendingError::InvalidMarketOwner);
+}
@@ -13,11 +13,14 @@ use std::collections::HashSet;
 use helpers::*;
 use solana_program::native_token::LAMPORTS_PER_SOL;
 use solana_program_test::*;
-use solana_sdk::{
-    instruction::InstructionError, signature::Keypair, transaction::TransactionError,
-};
+use solana_sdk::signature::Keypair;
 use solend_program::state::*;
-use solend_program::{error::LendingError, math::Decimal};
+use solend_program::{
+    error::LendingError,
+    error_context::parse_error_contexts,
+    instruction::borrow_obligation_liquidity,
+    math::{Decimal, Rate},
+};
 
 async fn setup(
     wsol_reserve_config: &ReserveConfig,
@@ -240,7 +243,8 @@ async fn test_success() {
                     .liquidity
                     .cumulative_borrow_rate_wads,
                 market_value: Decimal::zero(), // we only update this retroactively on a
-                                               // refresh_obligation
+                // refresh_obligation
+                ..ObligationLiquidity::default()
             }],
             deposited_value: Decimal::from(100u64),
             borrowed_value: Decimal::zero(),
@@ -346,17 +350,40 @@ async fn test_fail_borrow_over_reserve_borrow_limit() {
             host_fee_receiver.get_account(&wsol_mint::id()),
             LAMPORTS_PER_SOL + 1,
         )
-        .await
-        .err()
-        .unwrap()
-        .unwrap();
+        .await;
 
-    assert_eq!(
-        res,
-        TransactionError::InstructionError(
-            1,
-            InstructionError::Custom(LendingError::InvalidAmount as u32)
+    expect_lending_error(res, LendingError::InvalidAmount);
+
+    // the limit it ran into is logged alongside the error
+    let (res, logs, _) = test
+        .simulate_transaction(
+            &[borrow_obligation_liquidity(
+                solend_program::id(),
+                LAMPORTS_PER_SOL + 1,
+                wsol_reserve.account.liquidity.supply_pubkey,
+                user.get_account(&wsol_mint::id()).unwrap(),
+                wsol_reserve.pubkey,
+                wsol_reserve.account.config.fee_receiver,
+                obligation.pubkey,
+                lending_market.pubkey,
+                user.keypair.pubkey(),
+                None,
+            )],
+            Some(&[&user.keypair]),
         )
+        .await;
+    expect_lending_error(res, LendingError::InvalidAmount);
+
+    let contexts = parse_error_contexts(&logs);
+    assert_eq!(contexts.len(), 1);
+    assert_eq!(contexts[0].error, LendingError::InvalidAmount);
+    assert_eq!(
+        contexts[0].get("amount"),
+        Some((LAMPORTS_PER_SOL + 1).to_string().as_str())
+    );
+    assert_eq!(
+        contexts[0].get("borrow_limit"),
+        Some(LAMPORTS_PER_SOL.to_string().as_str())
     );
 }
 
@@ -417,18 +444,9 @@ async fn test_fail_reserve_borrow_rate_limit_exceeded() {
                 host_fee_receiver.get_account(&wsol_mint::id()),
                 1,
             )
-            .await
-            .err()
-            .unwrap()
-            .unwrap();
-
-        assert_eq!(
-            res,
-            TransactionError::InstructionError(
-                1,
-                InstructionError::Custom(LendingError::OutflowRateLimitExceeded as u32)
-            )
-        );
+            .await;
+
+        expect_lending_error(res, LendingError::OutflowRateLimitExceeded);
 
         test.advance_clock_by_slots(1).await;
     }
@@ -443,18 +461,9 @@ async fn test_fail_reserve_borrow_rate_limit_exceeded() {
             host_fee_receiver.get_account(&wsol_mint::id()),
             LAMPORTS_PER_SOL / 10 + 1,
         )
-        .await
-        .err()
-        .unwrap()
-        .unwrap();
+        .await;
 
-    assert_eq!(
-        res,
-        TransactionError::InstructionError(
-            1,
-            InstructionError::Custom(LendingError::OutflowRateLimitExceeded as u32)
-        )
-    );
+    expect_lending_error(res, LendingError::OutflowRateLimitExceeded);
 
     lending_market
         .borrow_obligation_liquidity(
@@ -600,3 +609,135 @@ async fn test_borrow_max_rate_limiter() {
 
     assert_eq!(balance_changes, expected_balance_changes);
 }
+
+#[tokio::test]
+async fn test_borrow_up_to_utilization_cap() {
+    // 6 SOL are supplied, so the cap is hit at 3 SOL borrowed
+    let (mut test, lending_market, _, wsol_reserve, user, obligation, host_fee_receiver, _) =
+        setup(&ReserveConfig {
+            optimal_utilization_rate: 50,
+            max_utilization_rate: 90,
+            borrow_utilization_cap: 50,
+            ..test_reserve_config()
+        })
+        .await;
+
+    lending_market
+        .borrow_obligation_liquidity(
+            &mut test,
+            &wsol_reserve,
+            &obligation,
+            &user,
+            host_fee_receiver.get_account(&wsol_mint::id()),
+            3 * LAMPORTS_PER_SOL,
+        )
+        .await
+        .unwrap();
+
+    let wsol_reserve = test.load_account::<Reserve>(wsol_reserve.pubkey).await;
+    assert_eq!(
+        wsol_reserve.account.liquidity.utilization_rate().unwrap(),
+        Rate::from_percent(50)
+    );
+
+    let res = lending_market
+        .borrow_obligation_liquidity(
+            &mut test,
+            &wsol_reserve,
+            &obligation,
+            &user,
+            host_fee_receiver.get_account(&wsol_mint::id()),
+            1,
+        )
+        .await;
+
+    expect_lending_error(res, LendingError::BorrowUtilizationCapExceeded);
+}
+
+#[tokio::test]
+async fn test_borrow_max_utilization_cap() {
+    let (mut test, lending_market, _, wsol_reserve, user, obligation, host_fee_receiver, _) =
+        setup(&ReserveConfig {
+            optimal_utilization_rate: 50,
+            max_utilization_rate: 90,
+            borrow_utilization_cap: 50,
+            ..test_reserve_config()
+        })
+        .await;
+
+    let balance_before = user.get_balance(&mut test, &wsol_mint::id()).await.unwrap();
+    lending_market
+        .borrow_obligation_liquidity(
+            &mut test,
+            &wsol_reserve,
+            &obligation,
+            &user,
+            host_fee_receiver.get_account(&wsol_mint::id()),
+            u64::MAX,
+        )
+        .await
+        .unwrap();
+
+    // capped by utilization before the obligation's 5 SOL of borrowing power
+    let balance_after = user.get_balance(&mut test, &wsol_mint::id()).await.unwrap();
+    assert_eq!(balance_after - balance_before, 3 * LAMPORTS_PER_SOL);
+}
+
+#[tokio::test]
+async fn test_borrow_fixed_rate() {
+    let (mut test, lending_market, _, wsol_reserve, user, obligation, host_fee_receiver, _) =
+        setup(&ReserveConfig {
+            fixed_borrow_rate_bps: 500,
+            fixed_borrow_max_duration_slots: 1_000,
+            ..test_reserve_config()
+        })
+        .await;
+
+    lending_market
+        .borrow_obligation_liquidity_fixed_rate(
+            &mut test,
+            &wsol_reserve,
+            &obligation,
+            &user,
+            LAMPORTS_PER_SOL,
+        )
+        .await
+        .unwrap();
+
+    let obligation_post = test.load_account::<Obligation>(obligation.pubkey).await;
+    let liquidity = &obligation_post.account.borrows[0];
+    assert_eq!(liquidity.rate_mode, BorrowRateMode::Fixed);
+    // 5% of the 1 SOL borrowed
+    assert_eq!(liquidity.fixed_interest_per_year, LAMPORTS_PER_SOL / 20);
+    assert_eq!(
+        liquidity.fixed_rate_maturity_slot,
+        liquidity.fixed_rate_accrual_slot + 1_000
+    );
+
+    let wsol_reserve_post = test.load_account::<Reserve>(wsol_reserve.pubkey).await;
+    assert_eq!(
+        wsol_reserve_post
+            .account
+            .liquidity
+            .fixed_borrowed_amount_wads,
+        liquidity.borrowed_amount_wads
+    );
+    assert_eq!(
+        wsol_reserve_post.account.liquidity.fixed_interest_per_year,
+        liquidity.fixed_interest_per_year
+    );
+
+    // the position can't take variable rate debt until its fixed rate matures
+    let res = lending_market
+        .borrow_obligation_liquidity(
+            &mut test,
+            &wsol_reserve_post,
+            &obligation,
+            &user,
+            host_fee_receiver.get_account(&wsol_mint::id()),
+            LAMPORTS_PER_SOL,
+        )
+        .await;
+
+    expect_lending_error(res, LendingError::BorrowRateModeMismatch);
+}
@@ -5,8 +5,6 @@ use crate::solend_program_test::setup_world;
 use crate::solend_program_test::BalanceChecker;
 use crate::solend_program_test::TokenBalanceChange;
 use solana_program::native_token::LAMPORTS_PER_SOL;
-use solana_sdk::instruction::InstructionError;
-use solana_sdk::transaction::TransactionError;
 use solend_program::error::LendingError;
 use solend_program::state::ReserveConfig;
 use solend_sdk::state::ReserveFees;
@@ -148,18 +146,9 @@ async fn test_borrow() {
     {
         let res = lending_market
             .withdraw_obligation_collateral(&mut test, &usdc_reserve, &obligation, &user, u64::MAX)
-            .await
-            .err()
-            .unwrap()
-            .unwrap();
+            .await;
 
-        assert_eq!(
-            res,
-            TransactionError::InstructionError(
-                1,
-                InstructionError::Custom(LendingError::WithdrawTooLarge as u32)
-            )
-        );
+        expect_lending_error(res, LendingError::WithdrawTooLarge);
     }
 
     // deposit another 50 USDC
@@ -331,17 +320,9 @@ async fn test_liquidation() {
                 &liquidator,
                 u64::MAX,
             )
-            .await
-            .err()
-            .unwrap()
-            .unwrap();
-        assert_eq!(
-            res,
-            TransactionError::InstructionError(
-                1,
-                InstructionError::Custom(LendingError::ObligationHealthy as u32)
-            )
-        );
+            .await;
+
+        expect_lending_error(res, LendingError::ObligationHealthy);
     }
 
     // what is the minimum borrow weight we need for the obligation to be eligible for liquidation?
@@ -0,0 +1,256 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use crate::solend_program_test::*;
+use helpers::*;
+use solana_program::native_token::LAMPORTS_PER_SOL;
+use solana_program::program_pack::Pack;
+use solana_program::sysvar::rent::Rent;
+use solana_program_test::*;
+use solana_sdk::{signature::Keypair, signature::Signer};
+use solend_program::{
+    error::LendingError,
+    instruction::{
+        approve_borrow_allowance, close_obligation, find_borrow_allowances_address,
+        find_deleverage_credit_address, find_obligation_borrow_cap_address, init_deleverage_credit,
+        set_obligation_borrow_cap,
+    },
+    state::*,
+};
+
+#[tokio::test]
+async fn test_success() {
+    let (mut test, lending_market, usdc_reserve, _wsol_reserve, _lending_market_owner, user) =
+        setup_world(&test_reserve_config(), &test_reserve_config()).await;
+
+    let obligation = lending_market
+        .init_obligation(&mut test, Keypair::new(), &user)
+        .await
+        .unwrap();
+
+    // an allowance needs a borrow allowances account, which is closed along with the obligation
+    test.process_transaction(
+        &[approve_borrow_allowance(
+            solend_program::id(),
+            LAMPORTS_PER_SOL,
+            obligation.pubkey,
+            usdc_reserve.pubkey,
+            Keypair::new().pubkey(),
+            user.keypair.pubkey(),
+        )],
+        Some(&[&user.keypair]),
+    )
+    .await
+    .unwrap();
+
+    let (borrow_allowances_pubkey, _) =
+        find_borrow_allowances_address(&solend_program::id(), &obligation.pubkey);
+    let borrow_allowances_lamports = test
+        .context
+        .banks_client
+        .get_balance(borrow_allowances_pubkey)
+        .await
+        .unwrap();
+
+    // so is its borrow cap
+    test.process_transaction(
+        &[set_obligation_borrow_cap(
+            solend_program::id(),
+            1_000,
+            obligation.pubkey,
+            user.keypair.pubkey(),
+        )],
+        Some(&[&user.keypair]),
+    )
+    .await
+    .unwrap();
+    let (obligation_borrow_cap_pubkey, _) =
+        find_obligation_borrow_cap_address(&solend_program::id(), &obligation.pubkey);
+    let obligation_borrow_cap_lamports = test
+        .context
+        .banks_client
+        .get_balance(obligation_borrow_cap_pubkey)
+        .await
+        .unwrap();
+
+    // and its deleverage credit
+    test.process_transaction(
+        &[init_deleverage_credit(
+            solend_program::id(),
+            obligation.pubkey,
+            user.keypair.pubkey(),
+        )],
+        Some(&[&user.keypair]),
+    )
+    .await
+    .unwrap();
+    let (deleverage_credit_pubkey, _) =
+        find_deleverage_credit_address(&solend_program::id(), &obligation.pubkey);
+    let deleverage_credit_lamports = test
+        .context
+        .banks_client
+        .get_balance(deleverage_credit_pubkey)
+        .await
+        .unwrap();
+
+    // rent goes to an account other than the owner, e.g. a service that paid for the obligation
+    let rent_destination = Keypair::new().pubkey();
+    test.process_transaction(
+        &[close_obligation(
+            solend_program::id(),
+            obligation.pubkey,
+            rent_destination,
+            user.keypair.pubkey(),
+        )],
+        Some(&[&user.keypair]),
+    )
+    .await
+    .unwrap();
+
+    let rent_destination_lamports = test
+        .context
+        .banks_client
+        .get_balance(rent_destination)
+        .await
+        .unwrap();
+    assert_eq!(
+        rent_destination_lamports,
+        Rent::default().minimum_balance(Obligation::LEN)
+            + borrow_allowances_lamports
+            + obligation_borrow_cap_lamports
+            + deleverage_credit_lamports
+    );
+
+    for pubkey in [
+        obligation.pubkey,
+        borrow_allowances_pubkey,
+        obligation_borrow_cap_pubkey,
+        deleverage_credit_pubkey,
+    ] {
+        assert!(test
+            .context
+            .banks_client
+            .get_account(pubkey)
+            .await
+            .unwrap()
+            .is_none());
+    }
+}
+
+#[tokio::test]
+async fn test_reinit_obligation_drops_borrow_allowances() {
+    let (mut test, lending_market, usdc_reserve, _wsol_reserve, _lending_market_owner, user) =
+        setup_world(&test_reserve_config(), &test_reserve_config()).await;
+
+    let obligation_keypair = Keypair::new();
+    let obligation = lending_market
+        .init_obligation(
+            &mut test,
+            Keypair::from_bytes(&obligation_keypair.to_bytes()).unwrap(),
+            &user,
+        )
+        .await
+        .unwrap();
+    test.process_transaction(
+        &[approve_borrow_allowance(
+            solend_program::id(),
+            LAMPORTS_PER_SOL,
+            obligation.pubkey,
+            usdc_reserve.pubkey,
+            Keypair::new().pubkey(),
+            user.keypair.pubkey(),
+        )],
+        Some(&[&user.keypair]),
+    )
+    .await
+    .unwrap();
+
+    // the borrow allowances account can't be left out to keep it around
+    let (borrow_allowances_pubkey, _) =
+        find_borrow_allowances_address(&solend_program::id(), &obligation.pubkey);
+    let mut instruction = close_obligation(
+        solend_program::id(),
+        obligation.pubkey,
+        user.keypair.pubkey(),
+        user.keypair.pubkey(),
+    );
+    instruction
+        .accounts
+        .retain(|account| account.pubkey != borrow_allowances_pubkey);
+    let res = test
+        .process_transaction(&[instruction], Some(&[&user.keypair]))
+        .await;
+    expect_lending_error(res, LendingError::InvalidAccountInput);
+
+    test.process_transaction(
+        &[close_obligation(
+            solend_program::id(),
+            obligation.pubkey,
+            user.keypair.pubkey(),
+            user.keypair.pubkey(),
+        )],
+        Some(&[&user.keypair]),
+    )
+    .await
+    .unwrap();
+
+    // an obligation initialized again at the same address starts without allowances
+    lending_market
+        .init_obligation(&mut test, obligation_keypair, &user)
+        .await
+        .unwrap();
+    assert!(test
+        .context
+        .banks_client
+        .get_account(borrow_allowances_pubkey)
+        .await
+        .unwrap()
+        .is_none());
+}
+
+#[tokio::test]
+async fn test_fail_obligation_not_empty() {
+    let (mut test, _lending_market, _usdc_reserve, _wsol_reserve, user, obligation, _) =
+        scenario_1(&test_reserve_config(), &test_reserve_config()).await;
+
+    let res = test
+        .process_transaction(
+            &[close_obligation(
+                solend_program::id(),
+                obligation.pubkey,
+                user.keypair.pubkey(),
+                user.keypair.pubkey(),
+            )],
+            Some(&[&user.keypair]),
+        )
+        .await;
+
+    expect_lending_error(res, LendingError::ObligationNotEmpty);
+}
+
+#[tokio::test]
+async fn test_fail_invalid_owner() {
+    let (mut test, lending_market, _usdc_reserve, _wsol_reserve, _lending_market_owner, user) =
+        setup_world(&test_reserve_config(), &test_reserve_config()).await;
+
+    let obligation = lending_market
+        .init_obligation(&mut test, Keypair::new(), &user)
+        .await
+        .unwrap();
+
+    let attacker = Keypair::new();
+    let res = test
+        .process_transaction(
+            &[close_obligation(
+                solend_program::id(),
+                obligation.pubkey,
+                attacker.pubkey(),
+                attacker.pubkey(),
+            )],
+            Some(&[&attacker]),
+        )
+        .await;
+
+    expect_lending_error(res, LendingError::InvalidObligationOwner);
+}
@@ -0,0 +1,243 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use crate::solend_program_test::*;
+use helpers::*;
+use solana_program::pubkey::Pubkey;
+use solana_program_test::*;
+use solana_sdk::{signature::Keypair, signature::Signer};
+use solend_program::{
+    error::LendingError,
+    instruction::{
+        activate_config, cancel_config, find_config_timelock_address, find_pending_config_address,
+        set_config_timelock,
+    },
+    state::*,
+};
+
+const DELAY_SLOTS: u64 = 100;
+
+async fn setup() -> (SolendProgramTest, Info<LendingMarket>, Info<Reserve>, User) {
+    let (mut test, lending_market, usdc_reserve, _wsol_reserve, lending_market_owner, _user) =
+        setup_world(&test_reserve_config(), &test_reserve_config()).await;
+
+    set_timelock(
+        &mut test,
+        &lending_market,
+        &lending_market_owner.keypair,
+        DELAY_SLOTS,
+    )
+    .await
+    .unwrap();
+
+    (test, lending_market, usdc_reserve, lending_market_owner)
+}
+
+async fn set_timelock(
+    test: &mut SolendProgramTest,
+    lending_market: &Info<LendingMarket>,
+    signer: &Keypair,
+    delay_slots: u64,
+) -> Result<(), BanksClientError> {
+    test.process_transaction(
+        &[set_config_timelock(
+            solend_program::id(),
+            delay_slots,
+            lending_market.pubkey,
+            signer.pubkey(),
+        )],
+        Some(&[signer]),
+    )
+    .await
+}
+
+async fn activate(
+    test: &mut SolendProgramTest,
+    lending_market: &Info<LendingMarket>,
+    target: Pubkey,
+) -> Result<(), BanksClientError> {
+    test.process_transaction(
+        &[activate_config(
+            solend_program::id(),
+            target,
+            lending_market.pubkey,
+            lending_market.account.owner,
+        )],
+        None,
+    )
+    .await
+}
+
+async fn pending_config_exists(test: &mut SolendProgramTest, target: Pubkey) -> bool {
+    let (pending_config_pubkey, _) = find_pending_config_address(&solend_program::id(), &target);
+    test.context
+        .banks_client
+        .get_account(pending_config_pubkey)
+        .await
+        .unwrap()
+        .is_some()
+}
+
+#[tokio::test]
+async fn test_reserve_config_change_is_staged_then_activated() {
+    let (mut test, lending_market, usdc_reserve, lending_market_owner) = setup().await;
+
+    let new_config = ReserveConfig {
+        loan_to_value_ratio: usdc_reserve.account.config.loan_to_value_ratio - 1,
+        ..usdc_reserve.account.config
+    };
+    lending_market
+        .update_reserve_config(
+            &mut test,
+            &lending_market_owner,
+            &usdc_reserve,
+            new_config,
+            usdc_reserve.account.rate_limiter.config,
+            None,
+        )
+        .await
+        .unwrap();
+
+    // nothing changes until the change is activated
+    let usdc_reserve_post = test.load_account::<Reserve>(usdc_reserve.pubkey).await;
+    assert_eq!(
+        usdc_reserve_post.account.config,
+        usdc_reserve.account.config
+    );
+    assert!(pending_config_exists(&mut test, usdc_reserve.pubkey).await);
+
+    let res = activate(&mut test, &lending_market, usdc_reserve.pubkey).await;
+
+    expect_lending_error(res, LendingError::ConfigChangeTimelocked);
+
+    test.advance_clock_by_slots(DELAY_SLOTS).await;
+    activate(&mut test, &lending_market, usdc_reserve.pubkey)
+        .await
+        .unwrap();
+
+    let usdc_reserve_post = test.load_account::<Reserve>(usdc_reserve.pubkey).await;
+    assert_eq!(usdc_reserve_post.account.config, new_config);
+    assert!(!pending_config_exists(&mut test, usdc_reserve.pubkey).await);
+}
+
+#[tokio::test]
+async fn test_risk_authority_cancels_market_config_change() {
+    let (mut test, lending_market, _usdc_reserve, _wsol_reserve, lending_market_owner, _user) =
+        setup_world(&test_reserve_config(), &test_reserve_config()).await;
+
+    let risk_authority = User::new_with_keypair(Keypair::new());
+    lending_market
+        .set_lending_market_owner_and_config(
+            &mut test,
+            &lending_market_owner,
+            &lending_market_owner.keypair.pubkey(),
+            lending_market.account.rate_limiter.config,
+            lending_market.account.whitelisted_liquidator,
+            risk_authority.keypair.pubkey(),
+        )
+        .await
+        .unwrap();
+    set_timelock(
+        &mut test,
+        &lending_market,
+        &lending_market_owner.keypair,
+        DELAY_SLOTS,
+    )
+    .await
+    .unwrap();
+
+    let lending_market = test
+        .load_account::<LendingMarket>(lending_market.pubkey)
+        .await;
+    lending_market
+        .set_lending_market_owner_and_config(
+            &mut test,
+            &lending_market_owner,
+            &Pubkey::new_unique(),
+            lending_market.account.rate_limiter.config,
+            lending_market.account.whitelisted_liquidator,
+            lending_market.account.risk_authority,
+        )
+        .await
+        .unwrap();
+
+    let lending_market_post = test
+        .load_account::<LendingMarket>(lending_market.pubkey)
+        .await;
+    assert_eq!(lending_market_post.account, lending_market.account);
+    assert!(pending_config_exists(&mut test, lending_market.pubkey).await);
+
+    test.process_transaction(
+        &[cancel_config(
+            solend_program::id(),
+            lending_market.pubkey,
+            lending_market.pubkey,
+            risk_authority.keypair.pubkey(),
+            lending_market.account.owner,
+        )],
+        Some(&[&risk_authority.keypair]),
+    )
+    .await
+    .unwrap();
+    assert!(!pending_config_exists(&mut test, lending_market.pubkey).await);
+
+    test.advance_clock_by_slots(DELAY_SLOTS).await;
+    let res = activate(&mut test, &lending_market, lending_market.pubkey).await;
+
+    expect_lending_error(res, LendingError::InvalidAccountInput);
+
+    let lending_market_post = test
+        .load_account::<LendingMarket>(lending_market.pubkey)
+        .await;
+    assert_eq!(lending_market_post.account, lending_market.account);
+}
+
+#[tokio::test]
+async fn test_lowering_delay_is_staged() {
+    let (mut test, lending_market, _usdc_reserve, lending_market_owner) = setup().await;
+    let (config_timelock_pubkey, _) =
+        find_config_timelock_address(&solend_program::id(), &lending_market.pubkey);
+
+    // raising the delay applies immediately
+    set_timelock(
+        &mut test,
+        &lending_market,
+        &lending_market_owner.keypair,
+        2 * DELAY_SLOTS,
+    )
+    .await
+    .unwrap();
+    let config_timelock = test
+        .load_account::<ConfigTimelock>(config_timelock_pubkey)
+        .await;
+    assert_eq!(config_timelock.account.delay_slots, 2 * DELAY_SLOTS);
+
+    // lowering it waits out the current delay
+    set_timelock(&mut test, &lending_market, &lending_market_owner.keypair, 0)
+        .await
+        .unwrap();
+    let config_timelock = test
+        .load_account::<ConfigTimelock>(config_timelock_pubkey)
+        .await;
+    assert_eq!(config_timelock.account.delay_slots, 2 * DELAY_SLOTS);
+
+    test.advance_clock_by_slots(2 * DELAY_SLOTS).await;
+    activate(&mut test, &lending_market, config_timelock_pubkey)
+        .await
+        .unwrap();
+    let config_timelock = test
+        .load_account::<ConfigTimelock>(config_timelock_pubkey)
+        .await;
+    assert_eq!(config_timelock.account.delay_slots, 0);
+}
+
+#[tokio::test]
+async fn test_fail_invalid_owner() {
+    let (mut test, lending_market, _usdc_reserve, _wsol_reserve, _lending_market_owner, user) =
+        setup_world(&test_reserve_config(), &test_reserve_config()).await;
+
+    let res = set_timelock(&mut test, &lending_market, &user.keypair, DELAY_SLOTS).await;
+
+    expect_lending_error(res, LendingError::InvalidMarketOwner);
+}
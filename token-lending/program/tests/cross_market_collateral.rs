@@ -0,0 +1,215 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use crate::solend_program_test::*;
+use helpers::*;
+use solana_program_test::*;
+use solana_sdk::signature::{Keypair, Signer};
+use solend_program::{
+    error::LendingError,
+    instruction::set_cross_market_source,
+    state::{LendingMarket, Reserve},
+    NULL_PUBKEY,
+};
+
+/// Sets up a second lending market owned by `user`, who holds cUSDC of the first market
+async fn setup() -> (
+    SolendProgramTest,
+    Info<LendingMarket>,
+    Info<Reserve>,
+    Info<LendingMarket>,
+    User,
+) {
+    let (mut test, lending_market, usdc_reserve, _wsol_reserve, _lending_market_owner, user) =
+        setup_world(&test_reserve_config(), &test_reserve_config()).await;
+
+    lending_market
+        .deposit(&mut test, &usdc_reserve, &user, 100_000_000)
+        .await
+        .unwrap();
+
+    let secondary_market = test
+        .init_lending_market(&user, &Keypair::new())
+        .await
+        .unwrap();
+
+    let usdc_reserve = test.load_account::<Reserve>(usdc_reserve.pubkey).await;
+    (test, lending_market, usdc_reserve, secondary_market, user)
+}
+
+async fn allow_source(
+    test: &mut SolendProgramTest,
+    lending_market: &Info<LendingMarket>,
+    lending_market_owner: &User,
+    source_reserve: &Info<Reserve>,
+) -> Result<(), BanksClientError> {
+    test.process_transaction(
+        &[set_cross_market_source(
+            solend_program::id(),
+            source_reserve.pubkey,
+            true,
+            lending_market.pubkey,
+            lending_market_owner.keypair.pubkey(),
+        )],
+        Some(&[&lending_market_owner.keypair]),
+    )
+    .await
+}
+
+fn cross_market_oracle(source_reserve: &Info<Reserve>) -> Oracle {
+    Oracle {
+        pyth_product_pubkey: NULL_PUBKEY,
+        pyth_price_pubkey: NULL_PUBKEY,
+        switchboard_feed_pubkey: Some(source_reserve.pubkey),
+    }
+}
+
+#[tokio::test]
+async fn test_success() {
+    let (mut test, lending_market, usdc_reserve, secondary_market, user) = setup().await;
+
+    allow_source(&mut test, &secondary_market, &user, &usdc_reserve)
+        .await
+        .unwrap();
+    lending_market
+        .refresh_reserve(&mut test, &usdc_reserve)
+        .await
+        .unwrap();
+    // cTokens can be regular collateral, the allowlist is what gates them
+    let cusdc_reserve = test
+        .init_reserve(
+            &secondary_market,
+            &user,
+            &usdc_reserve.account.collateral.mint_pubkey,
+            &test_reserve_config(),
+            &Keypair::new(),
+            1_000_000,
+            Some(cross_market_oracle(&usdc_reserve)),
+        )
+        .await
+        .unwrap();
+
+    let usdc_reserve = test.load_account::<Reserve>(usdc_reserve.pubkey).await;
+    let (market_price, smoothed_market_price) = usdc_reserve.account.collateral_prices().unwrap();
+    assert_eq!(cusdc_reserve.account.liquidity.market_price, market_price);
+    assert_eq!(
+        cusdc_reserve.account.liquidity.smoothed_market_price,
+        smoothed_market_price
+    );
+
+    // USDC doubles in price
+    test.set_price(
+        &usdc_mint::id(),
+        &PriceArgs {
+            price: 2,
+            conf: 0,
+            expo: 0,
+            ema_price: 1,
+            ema_conf: 0,
+        },
+    )
+    .await;
+    test.advance_clock_by_slots(1).await;
+
+    // the source reserve has to be refreshed first
+    let res = secondary_market
+        .refresh_reserve(&mut test, &cusdc_reserve)
+        .await;
+    expect_lending_error(res, LendingError::ReserveStale);
+
+    lending_market
+        .refresh_reserve(&mut test, &usdc_reserve)
+        .await
+        .unwrap();
+    secondary_market
+        .refresh_reserve(&mut test, &cusdc_reserve)
+        .await
+        .unwrap();
+
+    let usdc_reserve = test.load_account::<Reserve>(usdc_reserve.pubkey).await;
+    let cusdc_reserve = test.load_account::<Reserve>(cusdc_reserve.pubkey).await;
+    let (market_price, smoothed_market_price) = usdc_reserve.account.collateral_prices().unwrap();
+    assert_eq!(cusdc_reserve.account.liquidity.market_price, market_price);
+    assert_eq!(
+        cusdc_reserve.account.liquidity.smoothed_market_price,
+        smoothed_market_price
+    );
+    assert!(market_price > smoothed_market_price);
+}
+
+#[tokio::test]
+async fn test_fail_source_not_allowed() {
+    let (mut test, lending_market, usdc_reserve, secondary_market, user) = setup().await;
+
+    lending_market
+        .refresh_reserve(&mut test, &usdc_reserve)
+        .await
+        .unwrap();
+    let res = test
+        .init_reserve(
+            &secondary_market,
+            &user,
+            &usdc_reserve.account.collateral.mint_pubkey,
+            &test_reserve_config(),
+            &Keypair::new(),
+            1_000_000,
+            Some(cross_market_oracle(&usdc_reserve)),
+        )
+        .await;
+
+    expect_lending_error(res, LendingError::InvalidOracleConfig);
+}
+
+#[tokio::test]
+async fn test_fail_chained_source() {
+    let (mut test, lending_market, usdc_reserve, secondary_market, mut user) = setup().await;
+
+    allow_source(&mut test, &secondary_market, &user, &usdc_reserve)
+        .await
+        .unwrap();
+    lending_market
+        .refresh_reserve(&mut test, &usdc_reserve)
+        .await
+        .unwrap();
+    let cusdc_reserve = test
+        .init_reserve(
+            &secondary_market,
+            &user,
+            &usdc_reserve.account.collateral.mint_pubkey,
+            &test_reserve_config(),
+            &Keypair::new(),
+            1_000_000,
+            Some(cross_market_oracle(&usdc_reserve)),
+        )
+        .await
+        .unwrap();
+
+    // a third market can allow the cross market reserve, but can't price from it
+    let tertiary_market = test
+        .init_lending_market(&user, &Keypair::new())
+        .await
+        .unwrap();
+    allow_source(&mut test, &tertiary_market, &user, &cusdc_reserve)
+        .await
+        .unwrap();
+    user.create_token_account(&cusdc_reserve.account.collateral.mint_pubkey, &mut test)
+        .await;
+    secondary_market
+        .refresh_reserve(&mut test, &cusdc_reserve)
+        .await
+        .unwrap();
+    let res = test
+        .init_reserve(
+            &tertiary_market,
+            &user,
+            &cusdc_reserve.account.collateral.mint_pubkey,
+            &test_reserve_config(),
+            &Keypair::new(),
+            1_000,
+            Some(cross_market_oracle(&cusdc_reserve)),
+        )
+        .await;
+
+    expect_lending_error(res, LendingError::InvalidOracleConfig);
+}
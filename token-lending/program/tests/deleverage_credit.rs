@@ -0,0 +1,417 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use crate::solend_program_test::*;
+use helpers::*;
+use solana_program::native_token::LAMPORTS_PER_SOL;
+use solana_program_test::*;
+use solana_sdk::{
+    compute_budget::ComputeBudgetInstruction,
+    instruction::InstructionError,
+    signature::{Keypair, Signer},
+    transaction::TransactionError,
+};
+use solend_program::{
+    error::LendingError,
+    instruction::{
+        append_deleverage_accounts, borrow_obligation_liquidity, find_deleverage_config_address,
+        find_deleverage_credit_address, init_deleverage_credit, repay_obligation_liquidity,
+        set_deleverage_config, withdraw_obligation_collateral_and_redeem_reserve_collateral,
+    },
+    math::Decimal,
+    state::{
+        DeleverageConfig, DeleverageCredit, LendingMarket, Obligation, RateLimiterConfig, Reserve,
+    },
+};
+
+const WINDOW_DURATION: u64 = 1_000;
+
+struct Setup {
+    test: SolendProgramTest,
+    lending_market: Info<LendingMarket>,
+    usdc_reserve: Info<Reserve>,
+    wsol_reserve: Info<Reserve>,
+    user: User,
+    obligation: Info<Obligation>,
+}
+
+// the market lets $100 out per window, and exempts up to $1000 of repaid value per window
+async fn setup() -> Setup {
+    let (
+        mut test,
+        lending_market,
+        usdc_reserve,
+        wsol_reserve,
+        user,
+        obligation,
+        lending_market_owner,
+    ) = scenario_1(&test_reserve_config(), &test_reserve_config()).await;
+
+    lending_market
+        .set_lending_market_owner_and_config(
+            &mut test,
+            &lending_market_owner,
+            &lending_market_owner.keypair.pubkey(),
+            RateLimiterConfig {
+                window_duration: WINDOW_DURATION,
+                max_outflow: 100,
+            },
+            None,
+            lending_market.account.risk_authority,
+        )
+        .await
+        .unwrap();
+
+    test.process_transaction(
+        &[set_deleverage_config(
+            solend_program::id(),
+            1_000,
+            lending_market.pubkey,
+            lending_market_owner.keypair.pubkey(),
+        )],
+        Some(&[&lending_market_owner.keypair]),
+    )
+    .await
+    .unwrap();
+
+    let (deleverage_config_pubkey, _) =
+        find_deleverage_config_address(&solend_program::id(), &lending_market.pubkey);
+    let deleverage_config = test
+        .load_account::<DeleverageConfig>(deleverage_config_pubkey)
+        .await;
+    assert_eq!(
+        deleverage_config.account.lending_market,
+        lending_market.pubkey
+    );
+    assert_eq!(deleverage_config.account.max_exempt_value, 1_000);
+
+    test.process_transaction(
+        &[init_deleverage_credit(
+            solend_program::id(),
+            obligation.pubkey,
+            user.keypair.pubkey(),
+        )],
+        Some(&[&user.keypair]),
+    )
+    .await
+    .unwrap();
+
+    // start at the beginning of a rate limiter window
+    let slot = test.get_clock().await.slot;
+    test.advance_clock_by_slots(WINDOW_DURATION - slot % WINDOW_DURATION)
+        .await;
+
+    let lending_market = test.load_account(lending_market.pubkey).await;
+    Setup {
+        test,
+        lending_market,
+        usdc_reserve,
+        wsol_reserve,
+        user,
+        obligation,
+    }
+}
+
+async fn repay(setup: &mut Setup, liquidity_amount: u64, with_credit: bool) {
+    let mut instruction = repay_obligation_liquidity(
+        solend_program::id(),
+        liquidity_amount,
+        setup.user.get_account(&wsol_mint::id()).unwrap(),
+        setup.wsol_reserve.account.liquidity.supply_pubkey,
+        setup.wsol_reserve.pubkey,
+        setup.obligation.pubkey,
+        setup.lending_market.pubkey,
+        setup.user.keypair.pubkey(),
+    );
+    if with_credit {
+        append_deleverage_accounts(
+            &mut instruction,
+            setup.obligation.pubkey,
+            setup.lending_market.pubkey,
+        );
+    }
+
+    setup
+        .test
+        .process_transaction(&[instruction], Some(&[&setup.user.keypair]))
+        .await
+        .unwrap();
+}
+
+async fn withdraw(
+    setup: &mut Setup,
+    collateral_amount: u64,
+    obligation: &Info<Obligation>,
+) -> Result<u64, BanksClientError> {
+    let refresh_ixs = setup
+        .lending_market
+        .build_refresh_instructions(&mut setup.test, &setup.obligation, None)
+        .await;
+    setup
+        .test
+        .process_transaction(&refresh_ixs, None)
+        .await
+        .unwrap();
+
+    let mut instruction = withdraw_obligation_collateral_and_redeem_reserve_collateral(
+        solend_program::id(),
+        collateral_amount,
+        setup.usdc_reserve.account.collateral.supply_pubkey,
+        setup
+            .user
+            .get_account(&setup.usdc_reserve.account.collateral.mint_pubkey)
+            .unwrap(),
+        setup.usdc_reserve.pubkey,
+        setup.obligation.pubkey,
+        setup.lending_market.pubkey,
+        setup.user.get_account(&usdc_mint::id()).unwrap(),
+        setup.usdc_reserve.account.collateral.mint_pubkey,
+        setup.usdc_reserve.account.liquidity.supply_pubkey,
+        setup.user.keypair.pubkey(),
+        setup.user.keypair.pubkey(),
+    );
+    append_deleverage_accounts(
+        &mut instruction,
+        obligation.pubkey,
+        setup.lending_market.pubkey,
+    );
+
+    let balance_before = setup
+        .user
+        .get_balance(&mut setup.test, &usdc_mint::id())
+        .await
+        .unwrap();
+    setup
+        .test
+        .process_transaction(
+            &[
+                ComputeBudgetInstruction::set_compute_unit_limit(100_000),
+                instruction,
+            ],
+            Some(&[&setup.user.keypair]),
+        )
+        .await?;
+    let balance_after = setup
+        .user
+        .get_balance(&mut setup.test, &usdc_mint::id())
+        .await
+        .unwrap();
+
+    Ok(balance_after - balance_before)
+}
+
+async fn load_credit(setup: &mut Setup) -> DeleverageCredit {
+    let (deleverage_credit_pubkey, _) =
+        find_deleverage_credit_address(&solend_program::id(), &setup.obligation.pubkey);
+    setup
+        .test
+        .load_account::<DeleverageCredit>(deleverage_credit_pubkey)
+        .await
+        .account
+}
+
+#[tokio::test]
+async fn test_repayment_exempts_withdrawal() {
+    let mut setup = setup().await;
+    let obligation = setup.obligation.clone();
+
+    // 5 SOL at $10
+    repay(&mut setup, 5 * LAMPORTS_PER_SOL, true).await;
+    let credit = load_credit(&mut setup).await;
+    assert_eq!(credit.repaid_value, Decimal::from(50u64));
+    assert_eq!(credit.exempt_value, Decimal::zero());
+
+    // $100 of limit plus $50 of exemption
+    let withdrawn = withdraw(&mut setup, 200_000_000, &obligation)
+        .await
+        .unwrap();
+    assert_eq!(withdrawn, 150_000_000);
+
+    let credit = load_credit(&mut setup).await;
+    assert_eq!(credit.exempt_value, Decimal::from(50u64));
+
+    // the credit is used up
+    let res = withdraw(&mut setup, 1_000_000, &obligation)
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        res,
+        TransactionError::InstructionError(
+            1,
+            InstructionError::Custom(LendingError::WithdrawTooLarge as u32)
+        )
+    );
+}
+
+#[tokio::test]
+async fn test_repayment_without_credit_account() {
+    let mut setup = setup().await;
+    let obligation = setup.obligation.clone();
+
+    repay(&mut setup, 5 * LAMPORTS_PER_SOL, false).await;
+    assert_eq!(load_credit(&mut setup).await.repaid_value, Decimal::zero());
+
+    let withdrawn = withdraw(&mut setup, 200_000_000, &obligation)
+        .await
+        .unwrap();
+    assert_eq!(withdrawn, 100_000_000);
+}
+
+#[tokio::test]
+async fn test_borrowing_takes_back_credit() {
+    let mut setup = setup().await;
+    let obligation = setup.obligation.clone();
+    let obligation_info = setup
+        .test
+        .load_account::<Obligation>(obligation.pubkey)
+        .await;
+    assert!(obligation_info.account.has_deleverage_credit);
+
+    // repaying 5 SOL and borrowing it right back doesn't leave any credit
+    repay(&mut setup, 5 * LAMPORTS_PER_SOL, true).await;
+    setup
+        .lending_market
+        .borrow_obligation_liquidity(
+            &mut setup.test,
+            &setup.wsol_reserve,
+            &obligation_info,
+            &setup.user,
+            None,
+            5 * LAMPORTS_PER_SOL,
+        )
+        .await
+        .unwrap();
+    assert_eq!(load_credit(&mut setup).await.repaid_value, Decimal::zero());
+
+    // the borrow took $50 of the $100 limit, and there is no exemption to go past the rest
+    let withdrawn = withdraw(&mut setup, 200_000_000, &obligation)
+        .await
+        .unwrap();
+    assert_eq!(withdrawn, 50_000_000);
+}
+
+#[tokio::test]
+async fn test_fail_borrow_without_credit_account() {
+    let mut setup = setup().await;
+    let obligation = setup
+        .test
+        .load_account::<Obligation>(setup.obligation.pubkey)
+        .await;
+    let refresh_ixs = setup
+        .lending_market
+        .build_refresh_instructions(&mut setup.test, &obligation, Some(&setup.wsol_reserve))
+        .await;
+    setup
+        .test
+        .process_transaction(&refresh_ixs, None)
+        .await
+        .unwrap();
+
+    // leaving the credit account out would keep the credit of earlier repayments
+    let (deleverage_credit_pubkey, _) =
+        find_deleverage_credit_address(&solend_program::id(), &obligation.pubkey);
+    let mut instruction = borrow_obligation_liquidity(
+        solend_program::id(),
+        LAMPORTS_PER_SOL,
+        setup.wsol_reserve.account.liquidity.supply_pubkey,
+        setup.user.get_account(&wsol_mint::id()).unwrap(),
+        setup.wsol_reserve.pubkey,
+        setup.wsol_reserve.account.config.fee_receiver,
+        obligation.pubkey,
+        setup.lending_market.pubkey,
+        setup.user.keypair.pubkey(),
+        None,
+    );
+    instruction
+        .accounts
+        .retain(|meta| meta.pubkey != deleverage_credit_pubkey);
+    let res = setup
+        .test
+        .process_transaction(&[instruction], Some(&[&setup.user.keypair]))
+        .await;
+
+    expect_lending_error(res, LendingError::InvalidAccountInput);
+}
+
+#[tokio::test]
+async fn test_credit_expires_with_window() {
+    let mut setup = setup().await;
+    let obligation = setup.obligation.clone();
+
+    repay(&mut setup, 5 * LAMPORTS_PER_SOL, true).await;
+    setup.test.advance_clock_by_slots(WINDOW_DURATION).await;
+
+    // the previous window's outflow was zero, so only the $100 limit applies
+    let withdrawn = withdraw(&mut setup, 200_000_000, &obligation)
+        .await
+        .unwrap();
+    assert_eq!(withdrawn, 100_000_000);
+}
+
+#[tokio::test]
+async fn test_other_obligation_credit() {
+    let mut setup = setup().await;
+
+    let other_user = User::new_with_balances(&mut setup.test, &[]).await;
+    let other_obligation = setup
+        .lending_market
+        .init_obligation(&mut setup.test, Keypair::new(), &other_user)
+        .await
+        .unwrap();
+    setup
+        .test
+        .process_transaction(
+            &[init_deleverage_credit(
+                solend_program::id(),
+                other_obligation.pubkey,
+                other_user.keypair.pubkey(),
+            )],
+            Some(&[&other_user.keypair]),
+        )
+        .await
+        .unwrap();
+
+    repay(&mut setup, 5 * LAMPORTS_PER_SOL, true).await;
+
+    let res = withdraw(&mut setup, 200_000_000, &other_obligation)
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        res,
+        TransactionError::InstructionError(
+            1,
+            InstructionError::Custom(LendingError::InvalidAccountInput as u32)
+        )
+    );
+}
+
+#[tokio::test]
+async fn test_set_deleverage_config_not_owner() {
+    let mut setup = setup().await;
+    let not_owner = Keypair::new();
+
+    let res = setup
+        .test
+        .process_transaction(
+            &[set_deleverage_config(
+                solend_program::id(),
+                u64::MAX,
+                setup.lending_market.pubkey,
+                not_owner.pubkey(),
+            )],
+            Some(&[&not_owner]),
+        )
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        res,
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(LendingError::InvalidMarketOwner as u32)
+        )
+    );
+}
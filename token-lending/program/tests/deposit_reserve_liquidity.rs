@@ -117,18 +117,9 @@ async fn test_fail_exceed_deposit_limit() {
 
     let res = lending_market
         .deposit(&mut test, &usdc_reserve, &user, 200_000_000_000)
-        .await
-        .err()
-        .unwrap()
-        .unwrap();
+        .await;
 
-    assert_eq!(
-        res,
-        TransactionError::InstructionError(
-            1,
-            InstructionError::Custom(LendingError::InvalidAmount as u32)
-        )
-    );
+    expect_lending_error(res, LendingError::InvalidAmount);
 }
 
 #[tokio::test]
@@ -0,0 +1,137 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use helpers::solend_program_test::{setup_world, Info, SolendProgramTest, User};
+use helpers::*;
+use solana_program_test::*;
+use solana_sdk::{
+    compute_budget::ComputeBudgetInstruction,
+    signature::{Keypair, Signer},
+};
+use solend_program::{
+    instruction::{deposit_reserve_liquidity_with_mode, set_obligation_deposit_mode},
+    state::{DepositMode, LendingMarket, Obligation, Reserve},
+};
+
+async fn setup() -> (
+    SolendProgramTest,
+    Info<LendingMarket>,
+    Info<Reserve>,
+    User,
+    Info<Obligation>,
+) {
+    let (mut test, lending_market, usdc_reserve, _, _, user) =
+        setup_world(&test_reserve_config(), &test_reserve_config()).await;
+
+    let obligation = lending_market
+        .init_obligation(&mut test, Keypair::new(), &user)
+        .await
+        .expect("This should succeed");
+
+    (test, lending_market, usdc_reserve, user, obligation)
+}
+
+async fn deposit(
+    test: &mut SolendProgramTest,
+    lending_market: &Info<LendingMarket>,
+    reserve: &Info<Reserve>,
+    obligation: &Info<Obligation>,
+    user: &User,
+    mode: Option<DepositMode>,
+) -> Result<(), BanksClientError> {
+    test.process_transaction(
+        &[
+            ComputeBudgetInstruction::set_compute_unit_limit(80_000),
+            deposit_reserve_liquidity_with_mode(
+                solend_program::id(),
+                1_000_000,
+                mode,
+                user.get_account(&reserve.account.liquidity.mint_pubkey)
+                    .unwrap(),
+                user.get_account(&reserve.account.collateral.mint_pubkey)
+                    .unwrap(),
+                reserve.pubkey,
+                reserve.account.liquidity.supply_pubkey,
+                reserve.account.collateral.mint_pubkey,
+                lending_market.pubkey,
+                reserve.account.collateral.supply_pubkey,
+                obligation.pubkey,
+                user.keypair.pubkey(),
+                reserve.account.liquidity.pyth_oracle_pubkey,
+                reserve.account.liquidity.switchboard_oracle_pubkey,
+                user.keypair.pubkey(),
+            ),
+        ],
+        Some(&[&user.keypair]),
+    )
+    .await
+}
+
+#[tokio::test]
+async fn test_obligation_default_mode() {
+    let (mut test, lending_market, usdc_reserve, user, obligation) = setup().await;
+    let collateral_mint = usdc_reserve.account.collateral.mint_pubkey;
+
+    test.process_transaction(
+        &[set_obligation_deposit_mode(
+            solend_program::id(),
+            DepositMode::Hold,
+            obligation.pubkey,
+            user.keypair.pubkey(),
+        )],
+        Some(&[&user.keypair]),
+    )
+    .await
+    .unwrap();
+
+    let obligation_post = test.load_account::<Obligation>(obligation.pubkey).await;
+    assert_eq!(
+        obligation_post.account.default_deposit_mode,
+        DepositMode::Hold
+    );
+
+    // the default keeps the collateral out of the obligation
+    let balance_before = user.get_balance(&mut test, &collateral_mint).await.unwrap();
+    deposit(
+        &mut test,
+        &lending_market,
+        &usdc_reserve,
+        &obligation,
+        &user,
+        None,
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(
+        user.get_balance(&mut test, &collateral_mint).await.unwrap(),
+        balance_before + 1_000_000
+    );
+    let obligation_post = test.load_account::<Obligation>(obligation.pubkey).await;
+    assert!(obligation_post.account.deposits.is_empty());
+
+    // an explicit mode overrides it
+    test.advance_clock_by_slots(1).await;
+    deposit(
+        &mut test,
+        &lending_market,
+        &usdc_reserve,
+        &obligation,
+        &user,
+        Some(DepositMode::Collateralize),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(
+        user.get_balance(&mut test, &collateral_mint).await.unwrap(),
+        balance_before + 1_000_000
+    );
+    let obligation_post = test.load_account::<Obligation>(obligation.pubkey).await;
+    assert_eq!(obligation_post.account.deposits.len(), 1);
+    assert_eq!(
+        obligation_post.account.deposits[0].deposited_amount,
+        1_000_000
+    );
+}
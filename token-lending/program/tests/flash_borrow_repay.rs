@@ -193,17 +193,9 @@ async fn test_fail_disable_flash_loans() {
             ],
             Some(&[&user.keypair]),
         )
-        .await
-        .unwrap_err()
-        .unwrap();
+        .await;
 
-    assert_eq!(
-        res,
-        TransactionError::InstructionError(
-            0,
-            InstructionError::Custom(LendingError::FlashLoansDisabled as u32)
-        )
-    );
+    expect_lending_error(res, LendingError::FlashLoansDisabled);
 }
 
 #[tokio::test]
@@ -256,17 +248,9 @@ async fn test_fail_double_borrow() {
             ],
             Some(&[&user.keypair]),
         )
-        .await
-        .unwrap_err()
-        .unwrap();
+        .await;
 
-    assert_eq!(
-        res,
-        TransactionError::InstructionError(
-            0,
-            InstructionError::Custom(LendingError::MultipleFlashBorrows as u32)
-        )
-    );
+    expect_lending_error(res, LendingError::MultipleFlashBorrows);
 }
 
 #[tokio::test]
@@ -323,17 +307,9 @@ async fn test_fail_double_repay() {
             ],
             Some(&[&user.keypair]),
         )
-        .await
-        .unwrap_err()
-        .unwrap();
+        .await;
 
-    assert_eq!(
-        res,
-        TransactionError::InstructionError(
-            0,
-            InstructionError::Custom(LendingError::MultipleFlashBorrows as u32)
-        )
-    );
+    expect_lending_error(res, LendingError::MultipleFlashBorrows);
 }
 
 #[tokio::test]
@@ -398,17 +374,9 @@ async fn test_fail_only_one_flash_ix_pair_per_tx() {
             ],
             Some(&[&user.keypair]),
         )
-        .await
-        .unwrap_err()
-        .unwrap();
+        .await;
 
-    assert_eq!(
-        res,
-        TransactionError::InstructionError(
-            0,
-            InstructionError::Custom(LendingError::MultipleFlashBorrows as u32)
-        )
-    );
+    expect_lending_error(res, LendingError::MultipleFlashBorrows);
 }
 
 #[tokio::test]
@@ -455,17 +423,9 @@ async fn test_fail_invalid_repay_ix() {
                 ],
                 Some(&[&user.keypair]),
             )
-            .await
-            .unwrap_err()
-            .unwrap();
+            .await;
 
-        assert_eq!(
-            res,
-            TransactionError::InstructionError(
-                0,
-                InstructionError::Custom(LendingError::InvalidFlashRepay as u32)
-            )
-        );
+        expect_lending_error(res, LendingError::InvalidFlashRepay);
     }
 
     // case 2: invalid liquidity amount
@@ -496,17 +456,9 @@ async fn test_fail_invalid_repay_ix() {
                 ],
                 Some(&[&user.keypair]),
             )
-            .await
-            .unwrap_err()
-            .unwrap();
+            .await;
 
-        assert_eq!(
-            res,
-            TransactionError::InstructionError(
-                0,
-                InstructionError::Custom(LendingError::InvalidFlashRepay as u32)
-            )
-        );
+        expect_lending_error(res, LendingError::InvalidFlashRepay);
     }
 
     // case 3: no repay
@@ -523,17 +475,9 @@ async fn test_fail_invalid_repay_ix() {
                 )],
                 None,
             )
-            .await
-            .unwrap_err()
-            .unwrap();
+            .await;
 
-        assert_eq!(
-            res,
-            TransactionError::InstructionError(
-                0,
-                InstructionError::Custom(LendingError::NoFlashRepayFound as u32)
-            )
-        );
+        expect_lending_error(res, LendingError::NoFlashRepayFound);
     }
 
     // case 4: cpi repay
@@ -565,17 +509,9 @@ async fn test_fail_invalid_repay_ix() {
                 ],
                 Some(&[&user.keypair]),
             )
-            .await
-            .unwrap_err()
-            .unwrap();
+            .await;
 
-        assert_eq!(
-            res,
-            TransactionError::InstructionError(
-                0,
-                InstructionError::Custom(LendingError::NoFlashRepayFound as u32)
-            )
-        );
+        expect_lending_error(res, LendingError::NoFlashRepayFound);
     }
 
     // case 5: insufficient funds to pay fees on repay.
@@ -642,17 +578,9 @@ async fn test_fail_invalid_repay_ix() {
                 )],
                 Some(&[&user.keypair]),
             )
-            .await
-            .unwrap_err()
-            .unwrap();
+            .await;
 
-        assert_eq!(
-            res,
-            TransactionError::InstructionError(
-                0,
-                InstructionError::Custom(LendingError::InvalidFlashRepay as u32)
-            )
-        );
+        expect_lending_error(res, LendingError::InvalidFlashRepay);
     }
 
     // case 7: Incorrect borrow instruction index -- points to itself
@@ -683,17 +611,9 @@ async fn test_fail_invalid_repay_ix() {
                 ],
                 Some(&[&user.keypair]),
             )
-            .await
-            .unwrap_err()
-            .unwrap();
+            .await;
 
-        assert_eq!(
-            res,
-            TransactionError::InstructionError(
-                0,
-                InstructionError::Custom(LendingError::InvalidFlashRepay as u32)
-            )
-        );
+        expect_lending_error(res, LendingError::InvalidFlashRepay);
     }
 
     // case 8: Incorrect borrow instruction index -- points to some other program
@@ -734,17 +654,9 @@ async fn test_fail_invalid_repay_ix() {
                 ],
                 Some(&[&user.keypair]),
             )
-            .await
-            .unwrap_err()
-            .unwrap();
+            .await;
 
-        assert_eq!(
-            res,
-            TransactionError::InstructionError(
-                1,
-                InstructionError::Custom(LendingError::InvalidFlashRepay as u32)
-            )
-        );
+        expect_lending_error(res, LendingError::InvalidFlashRepay);
     }
     // case 9: Incorrect borrow instruction index -- points to a later borrow
     {
@@ -786,17 +698,9 @@ async fn test_fail_invalid_repay_ix() {
                 ],
                 Some(&[&user.keypair]),
             )
-            .await
-            .unwrap_err()
-            .unwrap();
+            .await;
 
-        assert_eq!(
-            res,
-            TransactionError::InstructionError(
-                0,
-                InstructionError::Custom(LendingError::InvalidFlashRepay as u32)
-            )
-        );
+        expect_lending_error(res, LendingError::InvalidFlashRepay);
     }
 }
 
@@ -840,17 +744,9 @@ async fn test_fail_insufficient_liquidity_for_borrow() {
             ],
             Some(&[&user.keypair]),
         )
-        .await
-        .unwrap_err()
-        .unwrap();
+        .await;
 
-    assert_eq!(
-        res,
-        TransactionError::InstructionError(
-            0,
-            InstructionError::Custom(LendingError::InsufficientLiquidity as u32)
-        )
-    );
+    expect_lending_error(res, LendingError::InsufficientLiquidity);
 }
 
 #[tokio::test]
@@ -886,17 +782,9 @@ async fn test_fail_cpi_borrow() {
             )],
             None,
         )
-        .await
-        .unwrap_err()
-        .unwrap();
+        .await;
 
-    assert_eq!(
-        res,
-        TransactionError::InstructionError(
-            0,
-            InstructionError::Custom(LendingError::FlashBorrowCpi as u32)
-        )
-    );
+    expect_lending_error(res, LendingError::FlashBorrowCpi);
 }
 
 #[tokio::test]
@@ -932,17 +820,9 @@ async fn test_fail_cpi_repay() {
             )],
             Some(&[&user.keypair]),
         )
-        .await
-        .unwrap_err()
-        .unwrap();
+        .await;
 
-    assert_eq!(
-        res,
-        TransactionError::InstructionError(
-            0,
-            InstructionError::Custom(LendingError::FlashRepayCpi as u32)
-        )
-    );
+    expect_lending_error(res, LendingError::FlashRepayCpi);
 }
 
 #[tokio::test]
@@ -966,7 +846,7 @@ async fn test_fail_repay_from_diff_reserve() {
             &usdc_mint::id(),
             &test_reserve_config(),
             &Keypair::new(),
-            10,
+            1000,
             None,
         )
         .await
@@ -0,0 +1,340 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use crate::solend_program_test::*;
+use helpers::*;
+use solana_program::pubkey::Pubkey;
+use solana_program_test::*;
+use solana_sdk::signature::Signer;
+use solend_program::{
+    error::LendingError,
+    instruction::{
+        find_flash_loan_config_address, flash_borrow_reserve_liquidity,
+        flash_repay_reserve_liquidity, set_flash_loan_config, set_flash_loan_fee_receiver,
+    },
+    math::Decimal,
+    state::{FlashLoanConfig, LendingMarket, Reserve, ReserveConfig, ReserveFees},
+};
+use spl_token::state::Account as Token;
+
+struct Setup {
+    test: SolendProgramTest,
+    lending_market: Info<LendingMarket>,
+    usdc_reserve: Info<Reserve>,
+    lending_market_owner: User,
+    user: User,
+}
+
+// a market with 100k USDC to flash borrow for a 0.3% fee, 20% of it to the host
+async fn setup() -> Setup {
+    let (mut test, lending_market, usdc_reserve, _, lending_market_owner, user) = setup_world(
+        &ReserveConfig {
+            deposit_limit: u64::MAX,
+            fees: ReserveFees {
+                host_fee_percentage: 20,
+                flash_loan_fee_wad: 3_000_000_000_000_000,
+                ..test_reserve_config().fees
+            },
+            ..test_reserve_config()
+        },
+        &test_reserve_config(),
+    )
+    .await;
+
+    lending_market
+        .deposit(&mut test, &usdc_reserve, &user, 100_000_000_000)
+        .await
+        .unwrap();
+
+    let usdc_reserve = test.load_account(usdc_reserve.pubkey).await;
+    Setup {
+        test,
+        lending_market,
+        usdc_reserve,
+        lending_market_owner,
+        user,
+    }
+}
+
+async fn set_config(
+    setup: &mut Setup,
+    flash_loans_enabled: bool,
+    max_flash_loan_amount: u64,
+) -> Result<(), BanksClientError> {
+    setup
+        .test
+        .process_transaction(
+            &[set_flash_loan_config(
+                solend_program::id(),
+                flash_loans_enabled,
+                max_flash_loan_amount,
+                setup.usdc_reserve.pubkey,
+                setup.lending_market.pubkey,
+                setup.lending_market_owner.keypair.pubkey(),
+            )],
+            Some(&[&setup.lending_market_owner.keypair]),
+        )
+        .await
+}
+
+async fn flash_loan(setup: &mut Setup, liquidity_amount: u64) -> Result<(), BanksClientError> {
+    let user_usdc = setup.user.get_account(&usdc_mint::id()).unwrap();
+    let fee_receiver = setup.usdc_reserve.account.config.fee_receiver;
+    flash_loan_with_fee_receivers(setup, liquidity_amount, fee_receiver, user_usdc).await
+}
+
+async fn flash_loan_with_fee_receivers(
+    setup: &mut Setup,
+    liquidity_amount: u64,
+    fee_receiver: Pubkey,
+    host_fee_receiver: Pubkey,
+) -> Result<(), BanksClientError> {
+    let user_usdc = setup.user.get_account(&usdc_mint::id()).unwrap();
+    setup
+        .test
+        .process_transaction(
+            &[
+                flash_borrow_reserve_liquidity(
+                    solend_program::id(),
+                    liquidity_amount,
+                    setup.usdc_reserve.account.liquidity.supply_pubkey,
+                    user_usdc,
+                    setup.usdc_reserve.pubkey,
+                    setup.lending_market.pubkey,
+                ),
+                flash_repay_reserve_liquidity(
+                    solend_program::id(),
+                    liquidity_amount,
+                    0,
+                    user_usdc,
+                    setup.usdc_reserve.account.liquidity.supply_pubkey,
+                    fee_receiver,
+                    host_fee_receiver,
+                    setup.usdc_reserve.pubkey,
+                    setup.lending_market.pubkey,
+                    setup.user.keypair.pubkey(),
+                ),
+            ],
+            Some(&[&setup.user.keypair]),
+        )
+        .await
+}
+
+#[tokio::test]
+async fn test_max_flash_loan_amount() {
+    let mut setup = setup().await;
+    set_config(&mut setup, true, 1_000_000).await.unwrap();
+
+    let (flash_loan_config_pubkey, _) =
+        find_flash_loan_config_address(&solend_program::id(), &setup.usdc_reserve.pubkey);
+    let flash_loan_config = setup
+        .test
+        .load_account::<FlashLoanConfig>(flash_loan_config_pubkey)
+        .await;
+    assert_eq!(flash_loan_config.account.reserve, setup.usdc_reserve.pubkey);
+    assert!(flash_loan_config.account.flash_loans_enabled);
+    assert_eq!(flash_loan_config.account.max_flash_loan_amount, 1_000_000);
+
+    flash_loan(&mut setup, 1_000_000).await.unwrap();
+
+    let res = flash_loan(&mut setup, 1_000_001).await;
+    expect_lending_error(res, LendingError::FlashLoanTooLarge);
+}
+
+#[tokio::test]
+async fn test_disable_flash_loans() {
+    let mut setup = setup().await;
+    flash_loan(&mut setup, 1_000_000).await.unwrap();
+
+    set_config(&mut setup, false, u64::MAX).await.unwrap();
+    let res = flash_loan(&mut setup, 1_000_000).await;
+    expect_lending_error(res, LendingError::FlashLoansDisabled);
+
+    setup.test.advance_clock_by_slots(1).await;
+    set_config(&mut setup, true, u64::MAX).await.unwrap();
+    flash_loan(&mut setup, 1_000_000).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_fail_invalid_config() {
+    let mut setup = setup().await;
+
+    let res = set_config(&mut setup, true, 0).await;
+    expect_lending_error(res, LendingError::InvalidConfig);
+}
+
+#[tokio::test]
+async fn test_fail_not_lending_market_owner() {
+    let mut setup = setup().await;
+
+    let res = setup
+        .test
+        .process_transaction(
+            &[set_flash_loan_config(
+                solend_program::id(),
+                false,
+                0,
+                setup.usdc_reserve.pubkey,
+                setup.lending_market.pubkey,
+                setup.user.keypair.pubkey(),
+            )],
+            Some(&[&setup.user.keypair]),
+        )
+        .await;
+    expect_lending_error(res, LendingError::InvalidMarketOwner);
+}
+
+async fn set_fee_receiver(
+    setup: &mut Setup,
+    flash_loan_fee_receiver: Pubkey,
+) -> Result<(), BanksClientError> {
+    setup
+        .test
+        .process_transaction(
+            &[set_flash_loan_fee_receiver(
+                solend_program::id(),
+                flash_loan_fee_receiver,
+                setup.usdc_reserve.pubkey,
+                setup.lending_market.pubkey,
+                setup.lending_market_owner.keypair.pubkey(),
+            )],
+            Some(&[&setup.lending_market_owner.keypair]),
+        )
+        .await
+}
+
+#[tokio::test]
+async fn test_flash_loan_fee_receiver() {
+    let mut setup = setup().await;
+    let owner = setup.lending_market_owner.keypair.pubkey();
+    let flash_loan_fee_receiver = setup
+        .test
+        .create_token_account(&owner, &usdc_mint::id())
+        .await;
+    let host_fee_receiver = setup
+        .test
+        .create_token_account(&owner, &usdc_mint::id())
+        .await;
+    let reserve_fee_receiver = setup.usdc_reserve.account.config.fee_receiver;
+
+    set_fee_receiver(&mut setup, flash_loan_fee_receiver)
+        .await
+        .unwrap();
+
+    let (flash_loan_config_pubkey, _) =
+        find_flash_loan_config_address(&solend_program::id(), &setup.usdc_reserve.pubkey);
+    let flash_loan_config = setup
+        .test
+        .load_account::<FlashLoanConfig>(flash_loan_config_pubkey)
+        .await;
+    assert_eq!(
+        flash_loan_config.account.flash_loan_fee_receiver,
+        flash_loan_fee_receiver
+    );
+    // setting the fee receiver leaves flash loans on
+    assert!(flash_loan_config.account.flash_loans_enabled);
+
+    // the reserve fee receiver no longer takes flash loan fees
+    let res = flash_loan_with_fee_receivers(
+        &mut setup,
+        1_000_000,
+        reserve_fee_receiver,
+        host_fee_receiver,
+    )
+    .await;
+    expect_lending_error(res, LendingError::InvalidAccountInput);
+
+    flash_loan_with_fee_receivers(
+        &mut setup,
+        1_000_000,
+        flash_loan_fee_receiver,
+        host_fee_receiver,
+    )
+    .await
+    .unwrap();
+
+    // the host fee is split off the same way as with the reserve fee receiver
+    let (protocol_fee, host_fee) = setup
+        .usdc_reserve
+        .account
+        .config
+        .fees
+        .calculate_flash_loan_fees(Decimal::from(1_000_000u64))
+        .unwrap();
+    assert_eq!((protocol_fee, host_fee), (2_400, 600));
+
+    let flash_loan_fee_receiver_balance = setup
+        .test
+        .load_account::<Token>(flash_loan_fee_receiver)
+        .await
+        .account
+        .amount;
+    assert_eq!(flash_loan_fee_receiver_balance, protocol_fee);
+    let host_fee_receiver_balance = setup
+        .test
+        .load_account::<Token>(host_fee_receiver)
+        .await
+        .account
+        .amount;
+    assert_eq!(host_fee_receiver_balance, host_fee);
+
+    let flash_loan_config = setup
+        .test
+        .load_account::<FlashLoanConfig>(flash_loan_config_pubkey)
+        .await;
+    assert_eq!(flash_loan_config.account.flash_loan_count, 1);
+    assert_eq!(flash_loan_config.account.flash_loan_volume, 1_000_000);
+    assert_eq!(
+        flash_loan_config.account.flash_loan_protocol_fees,
+        protocol_fee as u128
+    );
+    assert_eq!(
+        flash_loan_config.account.flash_loan_host_fees,
+        host_fee as u128
+    );
+
+    // routing the fees back to the reserve fee receiver
+    setup.test.advance_clock_by_slots(1).await;
+    set_fee_receiver(&mut setup, reserve_fee_receiver)
+        .await
+        .unwrap();
+    let flash_loan_config = setup
+        .test
+        .load_account::<FlashLoanConfig>(flash_loan_config_pubkey)
+        .await;
+    assert_eq!(
+        flash_loan_config.account.flash_loan_fee_receiver,
+        Pubkey::default()
+    );
+    flash_loan(&mut setup, 1_000_000).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_fail_invalid_flash_loan_fee_receiver() {
+    let mut setup = setup().await;
+    let owner = setup.lending_market_owner.keypair.pubkey();
+
+    // token account of another mint
+    let wsol_account = setup
+        .test
+        .create_token_account(&owner, &wsol_mint::id())
+        .await;
+    let res = set_fee_receiver(&mut setup, wsol_account).await;
+    expect_lending_error(res, LendingError::InvalidTokenMint);
+
+    let res = setup
+        .test
+        .process_transaction(
+            &[set_flash_loan_fee_receiver(
+                solend_program::id(),
+                setup.user.get_account(&usdc_mint::id()).unwrap(),
+                setup.usdc_reserve.pubkey,
+                setup.lending_market.pubkey,
+                setup.user.keypair.pubkey(),
+            )],
+            Some(&[&setup.user.keypair]),
+        )
+        .await;
+    expect_lending_error(res, LendingError::InvalidMarketOwner);
+}
@@ -20,8 +20,6 @@ use crate::solend_program_test::PriceArgs;
 use crate::solend_program_test::ReserveArgs;
 
 use solana_program::native_token::LAMPORTS_PER_SOL;
-use solana_sdk::instruction::InstructionError;
-use solana_sdk::transaction::TransactionError;
 use solend_program::error::LendingError;
 
 use solend_program::state::ReserveConfig;
@@ -114,17 +112,9 @@ async fn test_forgive_debt_success_easy() {
             &wsol_reserve,
             u64::MAX,
         )
-        .await
-        .unwrap_err()
-        .unwrap();
+        .await;
 
-    assert_eq!(
-        err,
-        TransactionError::InstructionError(
-            3,
-            InstructionError::Custom(LendingError::InvalidAccountInput as u32)
-        )
-    );
+    expect_lending_error(err, LendingError::InvalidAccountInput);
 
     // liquidate everything first. 0.2 SOL is repaid, 2 USDC is withdrawn
     {
@@ -198,6 +188,8 @@ async fn test_forgive_debt_success_easy() {
                 // 0.2 SOL is repaid on liquidation
                 available_amount: LAMPORTS_PER_SOL / 5
                     + wsol_reserve.account.liquidity.available_amount,
+                // the remaining 0.8 SOL is forgiven
+                lifetime_forgiven_amount_wads: Decimal::from(LAMPORTS_PER_SOL * 4 / 5),
                 ..wsol_reserve.account.liquidity
             },
             ..wsol_reserve.account.clone()
@@ -299,17 +291,9 @@ async fn test_forgive_debt_fail_invalid_signer() {
             &wsol_reserve,
             u64::MAX,
         )
-        .await
-        .unwrap_err()
-        .unwrap();
+        .await;
 
-    assert_eq!(
-        err,
-        TransactionError::InstructionError(
-            3,
-            InstructionError::Custom(LendingError::InvalidMarketOwner as u32)
-        )
-    );
+    expect_lending_error(err, LendingError::InvalidMarketOwner);
 }
 
 fn malicious_forgive_debt(
@@ -405,15 +389,7 @@ async fn test_forgive_debt_fail_no_signer() {
             )],
             None,
         )
-        .await
-        .unwrap_err()
-        .unwrap();
+        .await;
 
-    assert_eq!(
-        err,
-        TransactionError::InstructionError(
-            0,
-            InstructionError::Custom(LendingError::InvalidSigner as u32)
-        )
-    );
+    expect_lending_error(err, LendingError::InvalidSigner);
 }
@@ -0,0 +1,59 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use helpers::solend_program_test::{setup_world, Info, SolendProgramTest, User};
+use helpers::*;
+use solana_program::{program_pack::Pack, pubkey::Pubkey};
+use solana_program_test::*;
+use solend_program::{
+    error::LendingError,
+    state::{LendingMarket, Reserve},
+};
+use spl_token::state::{Account as Token, AccountState};
+
+async fn setup() -> (SolendProgramTest, Info<LendingMarket>, Info<Reserve>, User) {
+    let (test, lending_market, usdc_reserve, _, _, user) =
+        setup_world(&test_reserve_config(), &test_reserve_config()).await;
+
+    (test, lending_market, usdc_reserve, user)
+}
+
+// what the freeze authority of the mint would do, none of the test mints have one
+async fn freeze_token_account(test: &mut SolendProgramTest, pubkey: Pubkey) {
+    let mut account = test
+        .context
+        .banks_client
+        .get_account(pubkey)
+        .await
+        .unwrap()
+        .unwrap();
+    let mut token_account = Token::unpack(&account.data).unwrap();
+    token_account.state = AccountState::Frozen;
+    Token::pack(token_account, &mut account.data).unwrap();
+    test.context.set_account(&pubkey, &account.into());
+}
+
+#[tokio::test]
+async fn test_fail_frozen_user_account() {
+    let (mut test, lending_market, usdc_reserve, user) = setup().await;
+
+    freeze_token_account(&mut test, user.get_account(&usdc_mint::id()).unwrap()).await;
+
+    let res = lending_market
+        .deposit(&mut test, &usdc_reserve, &user, 1_000_000)
+        .await;
+    expect_lending_error(res, LendingError::TokenAccountFrozen);
+}
+
+#[tokio::test]
+async fn test_fail_frozen_reserve_supply() {
+    let (mut test, lending_market, usdc_reserve, user) = setup().await;
+
+    freeze_token_account(&mut test, usdc_reserve.account.liquidity.supply_pubkey).await;
+
+    let res = lending_market
+        .deposit(&mut test, &usdc_reserve, &user, 1_000_000)
+        .await;
+    expect_lending_error(res, LendingError::TokenAccountFrozen);
+}
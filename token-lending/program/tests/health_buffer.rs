@@ -0,0 +1,241 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use crate::solend_program_test::*;
+use helpers::*;
+use solana_program::native_token::LAMPORTS_PER_SOL;
+use solana_program_test::*;
+use solana_sdk::{signature::Keypair, signature::Signer};
+use solend_program::{
+    error::LendingError,
+    instruction::{
+        find_obligation_limits_address, set_ignore_health_buffer, set_obligation_health_buffer,
+    },
+    math::Decimal,
+    state::{LendingMarket, Obligation, ObligationLimits, Reserve, MAX_HEALTH_BUFFER_BPS},
+};
+
+async fn setup() -> (
+    SolendProgramTest,
+    Info<LendingMarket>,
+    Info<Reserve>,
+    Info<Reserve>,
+    User,
+    Info<Obligation>,
+    User,
+) {
+    let (mut test, lending_market, usdc_reserve, wsol_reserve, lending_market_owner, user) =
+        setup_world(&test_reserve_config(), &test_reserve_config()).await;
+
+    let obligation = lending_market
+        .init_obligation(&mut test, Keypair::new(), &user)
+        .await
+        .unwrap();
+
+    // $100 of USDC, enough to borrow $50
+    lending_market
+        .deposit_reserve_liquidity_and_obligation_collateral(
+            &mut test,
+            &usdc_reserve,
+            &obligation,
+            &user,
+            100_000_000,
+        )
+        .await
+        .unwrap();
+
+    let wsol_depositor = User::new_with_balances(
+        &mut test,
+        &[
+            (&wsol_mint::id(), 5 * LAMPORTS_PER_SOL),
+            (&wsol_reserve.account.collateral.mint_pubkey, 0),
+        ],
+    )
+    .await;
+    lending_market
+        .deposit(
+            &mut test,
+            &wsol_reserve,
+            &wsol_depositor,
+            5 * LAMPORTS_PER_SOL,
+        )
+        .await
+        .unwrap();
+
+    let usdc_reserve = test.load_account(usdc_reserve.pubkey).await;
+    let wsol_reserve = test.load_account(wsol_reserve.pubkey).await;
+    let obligation = test.load_account(obligation.pubkey).await;
+    (
+        test,
+        lending_market,
+        usdc_reserve,
+        wsol_reserve,
+        user,
+        obligation,
+        lending_market_owner,
+    )
+}
+
+async fn set_health_buffer(
+    test: &mut SolendProgramTest,
+    lending_market: &Info<LendingMarket>,
+    signer: &Keypair,
+    health_buffer_bps: u16,
+) -> Result<(), BanksClientError> {
+    test.process_transaction(
+        &[set_obligation_health_buffer(
+            solend_program::id(),
+            health_buffer_bps,
+            lending_market.pubkey,
+            signer.pubkey(),
+        )],
+        Some(&[signer]),
+    )
+    .await
+}
+
+#[tokio::test]
+async fn test_success() {
+    let (
+        mut test,
+        lending_market,
+        usdc_reserve,
+        wsol_reserve,
+        user,
+        obligation,
+        lending_market_owner,
+    ) = setup().await;
+
+    // 20% of the $50 allowed borrow value is kept free
+    set_health_buffer(
+        &mut test,
+        &lending_market,
+        &lending_market_owner.keypair,
+        2_000,
+    )
+    .await
+    .unwrap();
+
+    let (limits_pubkey, _) =
+        find_obligation_limits_address(&solend_program::id(), &lending_market.pubkey);
+    let limits = test.load_account::<ObligationLimits>(limits_pubkey).await;
+    assert_eq!(limits.account.health_buffer_bps, 2_000);
+
+    // $45 is within the allowed borrow value but not outside the buffer
+    let res = lending_market
+        .borrow_obligation_liquidity(
+            &mut test,
+            &wsol_reserve,
+            &obligation,
+            &user,
+            None,
+            45 * LAMPORTS_PER_SOL / 10,
+        )
+        .await;
+    expect_lending_error(res, LendingError::HealthBufferBreached);
+
+    // max borrows stop at the buffer
+    lending_market
+        .borrow_obligation_liquidity(&mut test, &wsol_reserve, &obligation, &user, None, u64::MAX)
+        .await
+        .unwrap();
+
+    let obligation_post = test.load_account::<Obligation>(obligation.pubkey).await;
+    let borrowed_amount_wads = obligation_post.account.borrows[0].borrowed_amount_wads;
+    assert!(borrowed_amount_wads > Decimal::from(3 * LAMPORTS_PER_SOL));
+    assert!(borrowed_amount_wads <= Decimal::from(4 * LAMPORTS_PER_SOL));
+
+    // withdrawing any collateral would end up within the buffer
+    test.advance_clock_by_slots(1).await;
+    let res = lending_market
+        .withdraw_obligation_collateral(
+            &mut test,
+            &usdc_reserve,
+            &obligation_post,
+            &user,
+            1_000_000,
+        )
+        .await;
+    expect_lending_error(res, LendingError::HealthBufferBreached);
+
+    // opting out lets the obligation go down to the allowed borrow value
+    test.process_transaction(
+        &[set_ignore_health_buffer(
+            solend_program::id(),
+            true,
+            obligation.pubkey,
+            user.keypair.pubkey(),
+        )],
+        Some(&[&user.keypair]),
+    )
+    .await
+    .unwrap();
+
+    let obligation_post = test.load_account::<Obligation>(obligation.pubkey).await;
+    assert!(obligation_post.account.ignore_health_buffer);
+
+    test.advance_clock_by_slots(1).await;
+    lending_market
+        .withdraw_obligation_collateral(
+            &mut test,
+            &usdc_reserve,
+            &obligation_post,
+            &user,
+            1_000_000,
+        )
+        .await
+        .unwrap();
+
+    test.advance_clock_by_slots(1).await;
+    lending_market
+        .borrow_obligation_liquidity(
+            &mut test,
+            &wsol_reserve,
+            &obligation_post,
+            &user,
+            None,
+            LAMPORTS_PER_SOL / 2,
+        )
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_fail_invalid_health_buffer() {
+    let (mut test, lending_market, _usdc_reserve, _wsol_reserve, _user, _obligation, owner) =
+        setup().await;
+
+    let res = set_health_buffer(
+        &mut test,
+        &lending_market,
+        &owner.keypair,
+        MAX_HEALTH_BUFFER_BPS + 1,
+    )
+    .await;
+
+    expect_lending_error(res, LendingError::InvalidConfig);
+}
+
+#[tokio::test]
+async fn test_fail_invalid_owner() {
+    let (mut test, lending_market, _usdc_reserve, _wsol_reserve, user, obligation, _) =
+        setup().await;
+
+    let res = set_health_buffer(&mut test, &lending_market, &user.keypair, 2_000).await;
+    expect_lending_error(res, LendingError::InvalidMarketOwner);
+
+    let other_user = User::new_with_balances(&mut test, &[]).await;
+    let res = test
+        .process_transaction(
+            &[set_ignore_health_buffer(
+                solend_program::id(),
+                true,
+                obligation.pubkey,
+                other_user.keypair.pubkey(),
+            )],
+            Some(&[&other_user.keypair]),
+        )
+        .await;
+    expect_lending_error(res, LendingError::InvalidObligationOwner);
+}
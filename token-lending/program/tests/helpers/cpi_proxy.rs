@@ -0,0 +1,53 @@
+use solana_program::{
+    account_info::AccountInfo,
+    entrypoint::ProgramResult,
+    instruction::{AccountMeta, Instruction},
+    program::invoke,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+pub mod cpi_proxy_program {
+    use solana_sdk::declare_id;
+    declare_id!("CpiProxy11111111111111111111111111111111111");
+}
+
+/// Forwards the instruction data to the program passed as the last account, with the other
+/// accounts in the same order
+pub fn process_instruction(
+    _program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    let (target_program_info, accounts) = accounts
+        .split_last()
+        .ok_or(ProgramError::NotEnoughAccountKeys)?;
+
+    invoke(
+        &Instruction {
+            program_id: *target_program_info.key,
+            accounts: accounts
+                .iter()
+                .map(|account| AccountMeta {
+                    pubkey: *account.key,
+                    is_signer: account.is_signer,
+                    is_writable: account.is_writable,
+                })
+                .collect(),
+            data: instruction_data.to_vec(),
+        },
+        accounts,
+    )
+}
+
+/// Wraps an instruction into a call to the cpi proxy, which invokes it
+pub fn cpi_proxy(instruction: Instruction) -> Instruction {
+    let mut accounts = instruction.accounts;
+    accounts.push(AccountMeta::new_readonly(instruction.program_id, false));
+
+    Instruction {
+        program_id: cpi_proxy_program::id(),
+        accounts,
+        data: instruction.data,
+    }
+}
@@ -0,0 +1,34 @@
+use solana_program::{
+    account_info::AccountInfo, entrypoint::ProgramResult, program_error::ProgramError,
+    pubkey::Pubkey,
+};
+use solend_program::state::ON_FEES_REDEEMED_DISCRIMINATOR;
+use std::convert::TryInto;
+
+pub mod fee_redemption_hook_program {
+    use solana_sdk::declare_id;
+    declare_id!("FeeHook111111111111111111111111111111111111");
+}
+
+/// Implements `on_fees_redeemed` by writing the redeemed amount into the first forwarded account
+pub fn process_instruction(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    let (discriminator, amount) = instruction_data.split_at(8);
+    if discriminator != ON_FEES_REDEEMED_DISCRIMINATOR {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    let amount: [u8; 8] = amount
+        .try_into()
+        .map_err(|_| ProgramError::InvalidInstructionData)?;
+
+    let record_info = accounts.get(3).ok_or(ProgramError::NotEnoughAccountKeys)?;
+    if record_info.owner != program_id {
+        return Err(ProgramError::IllegalOwner);
+    }
+    record_info.data.borrow_mut()[..8].copy_from_slice(&amount);
+
+    Ok(())
+}
@@ -15,7 +15,8 @@ use std::mem::size_of;
 use thiserror::Error;
 
 use solend_program::{
-    instruction::flash_borrow_reserve_liquidity, instruction::flash_repay_reserve_liquidity,
+    instruction::find_flash_loan_config_address, instruction::flash_borrow_reserve_liquidity,
+    instruction::flash_repay_reserve_liquidity,
 };
 
 pub mod proxy_program {
@@ -225,6 +226,10 @@ pub fn repay_proxy(
             AccountMeta::new_readonly(user_transfer_authority_pubkey, true),
             AccountMeta::new_readonly(sysvar::instructions::id(), false),
             AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new(
+                find_flash_loan_config_address(&token_lending_pubkey, &reserve_pubkey).0,
+                false,
+            ),
         ],
         data: FlashLoanProxyInstruction::ProxyRepay {
             liquidity_amount,
@@ -258,6 +263,10 @@ pub fn borrow_proxy(
             AccountMeta::new_readonly(sysvar::instructions::id(), false),
             AccountMeta::new_readonly(spl_token::id(), false),
             AccountMeta::new_readonly(sysvar::clock::id(), false),
+            AccountMeta::new_readonly(
+                find_flash_loan_config_address(&token_lending_pubkey, &reserve_pubkey).0,
+                false,
+            ),
         ],
         data: FlashLoanProxyInstruction::ProxyBorrow { liquidity_amount }.pack(),
     }
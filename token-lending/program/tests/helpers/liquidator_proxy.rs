@@ -0,0 +1,92 @@
+use solana_program::{
+    account_info::AccountInfo,
+    entrypoint::ProgramResult,
+    instruction::{AccountMeta, Instruction},
+    program::invoke_signed,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+use std::convert::TryInto;
+
+use solend_program::instruction::liquidate_obligation_and_redeem_reserve_collateral;
+
+pub mod liquidator_program {
+    use solana_sdk::declare_id;
+    declare_id!("LiqdProxy1111111111111111111111111111111111");
+}
+
+pub const LIQUIDATOR_AUTHORITY_SEED: &[u8] = b"liquidator";
+
+pub fn find_liquidator_authority_address() -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[LIQUIDATOR_AUTHORITY_SEED], &liquidator_program::id())
+}
+
+/// Liquidates through `LiquidateObligationAndRedeemReserveCollateral`, with a PDA of this program
+/// signing as the user transfer authority. Takes the accounts of the lending instruction followed
+/// by the lending program.
+pub fn process_instruction(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    let liquidity_amount = instruction_data
+        .get(..8)
+        .and_then(|slice| slice.try_into().ok())
+        .map(u64::from_le_bytes)
+        .ok_or(ProgramError::InvalidInstructionData)?;
+    if accounts.len() != 19 {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    }
+
+    let (liquidator_authority, bump_seed) =
+        Pubkey::find_program_address(&[LIQUIDATOR_AUTHORITY_SEED], program_id);
+    if accounts[13].key != &liquidator_authority {
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    invoke_signed(
+        &liquidate_obligation_and_redeem_reserve_collateral(
+            *accounts[18].key,
+            liquidity_amount,
+            *accounts[0].key,
+            *accounts[1].key,
+            *accounts[2].key,
+            *accounts[3].key,
+            *accounts[4].key,
+            *accounts[5].key,
+            *accounts[6].key,
+            *accounts[7].key,
+            *accounts[8].key,
+            *accounts[9].key,
+            *accounts[10].key,
+            *accounts[11].key,
+            liquidator_authority,
+        ),
+        accounts,
+        &[&[LIQUIDATOR_AUTHORITY_SEED, &[bump_seed]]],
+    )
+}
+
+/// Wraps a `LiquidateObligationAndRedeemReserveCollateral` instruction built for the liquidator
+/// authority into a call to the liquidator program
+pub fn liquidate_proxy(liquidate_instruction: Instruction) -> Instruction {
+    let liquidity_amount = liquidate_instruction.data[1..9].to_vec();
+    let mut accounts: Vec<AccountMeta> = liquidate_instruction
+        .accounts
+        .into_iter()
+        .map(|meta| AccountMeta {
+            is_signer: false,
+            ..meta
+        })
+        .collect();
+    accounts.push(AccountMeta::new_readonly(
+        liquidate_instruction.program_id,
+        false,
+    ));
+
+    Instruction {
+        program_id: liquidator_program::id(),
+        accounts,
+        data: liquidity_amount,
+    }
+}
@@ -0,0 +1,45 @@
+use solana_program::{
+    account_info::AccountInfo, entrypoint::ProgramResult, program_error::ProgramError,
+    pubkey::Pubkey,
+};
+use solend_program::state::{PolicyAction, CHECK_POLICY_DISCRIMINATOR};
+use std::convert::{TryFrom, TryInto};
+
+pub mod market_policy_program {
+    use solana_sdk::declare_id;
+    declare_id!("PoLicy1111111111111111111111111111111111111");
+}
+
+/// Largest liquidity amount the policy lets through in a single borrow
+pub const POLICY_BORROW_LIMIT: u64 = 1_000_000_000;
+
+/// Implements `check_policy` by requiring the policy account to be owned by this program and
+/// rejecting borrows above [POLICY_BORROW_LIMIT]. Fails if it is handed a signature or write access.
+pub fn process_instruction(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    if instruction_data.len() != 17 || instruction_data[..8] != CHECK_POLICY_DISCRIMINATOR {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    let action = PolicyAction::try_from(instruction_data[8])?;
+    let amount = u64::from_le_bytes(instruction_data[9..].try_into().unwrap());
+
+    if accounts
+        .iter()
+        .any(|account_info| account_info.is_signer || account_info.is_writable)
+    {
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    let record_info = accounts.get(4).ok_or(ProgramError::NotEnoughAccountKeys)?;
+    if record_info.owner != program_id {
+        return Err(ProgramError::IllegalOwner);
+    }
+    if action == PolicyAction::Borrow && amount > POLICY_BORROW_LIMIT {
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    Ok(())
+}
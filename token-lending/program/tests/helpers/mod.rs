@@ -1,28 +1,31 @@
 #![allow(dead_code)]
 
+pub mod cpi_proxy;
+pub mod fee_redemption_hook;
 pub mod flash_loan_proxy;
 pub mod flash_loan_receiver;
 pub mod genesis;
-pub mod mock_pyth;
+pub mod liquidator_proxy;
+pub mod market_policy;
+pub mod scenario;
 pub mod solend_program_test;
 
-use bytemuck::{cast_slice_mut, from_bytes_mut, try_cast_slice_mut, Pod, PodCastError};
-
 use solana_program::{program_option::COption, program_pack::Pack, pubkey::Pubkey};
 use solana_program_test::*;
 use solana_sdk::{
     account::Account,
+    instruction::InstructionError,
     signature::{Keypair, Signer},
+    transaction::TransactionError,
+};
+use solend_program::{
+    error::LendingError,
+    state::{PriceBoundsMode, ReserveConfig, ReserveFees, ReserveType},
 };
-use solend_program::state::{ReserveConfig, ReserveFees, ReserveType};
 
 use spl_token::state::Mint;
 
-use std::mem::size_of;
-use switchboard_v2::AggregatorAccountData;
-
-pub const QUOTE_CURRENCY: [u8; 32] =
-    *b"USD\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0";
+pub const QUOTE_CURRENCY: [u8; 32] = solend_mock_oracle::USD_QUOTE_CURRENCY;
 
 pub const LAMPORTS_TO_SOL: u64 = 1_000_000_000;
 pub const FRACTIONAL_TO_USDC: u64 = 1_000_000;
@@ -52,6 +55,54 @@ pub fn test_reserve_config() -> ReserveConfig {
         protocol_take_rate: 0,
         added_borrow_weight_bps: 0,
         reserve_type: ReserveType::Regular,
+        deposit_soft_cap_pct: 0,
+        borrow_soft_cap_pct: 0,
+        idle_liquidity_fee_bps: 0,
+        liquidation_fee_receiver: None,
+        borrow_utilization_cap: 0,
+        fixed_borrow_rate_bps: 0,
+        fixed_borrow_max_duration_slots: 0,
+        withdraw_fee_bps: 0,
+        min_price_bps: 0,
+        max_price_bps: 0,
+        price_bounds_mode: PriceBoundsMode::Reject,
+    }
+}
+
+/// Errors a failed transaction can be reported as by the test helpers
+pub trait IntoTransactionError {
+    fn into_transaction_error(self) -> TransactionError;
+}
+
+impl IntoTransactionError for TransactionError {
+    fn into_transaction_error(self) -> TransactionError {
+        self
+    }
+}
+
+impl IntoTransactionError for BanksClientError {
+    fn into_transaction_error(self) -> TransactionError {
+        self.unwrap()
+    }
+}
+
+/// Asserts that a transaction failed with `expected`, whichever of its instructions raised it
+#[track_caller]
+pub fn expect_lending_error<T: std::fmt::Debug, E: IntoTransactionError>(
+    result: Result<T, E>,
+    expected: LendingError,
+) {
+    let error = match result {
+        Ok(value) => panic!(
+            "expected {:?}, transaction succeeded: {:?}",
+            expected, value
+        ),
+        Err(error) => error.into_transaction_error(),
+    };
+    match error {
+        TransactionError::InstructionError(_, InstructionError::Custom(code))
+            if code == expected.clone() as u32 => {}
+        error => panic!("expected {:?}, got {:?}", expected, error),
     }
 }
 
@@ -102,13 +153,6 @@ pub struct TestMint {
     pub decimals: u8,
 }
 
-pub fn load_mut<T: Pod>(data: &mut [u8]) -> Result<&mut T, PodCastError> {
-    let size = size_of::<T>();
-    Ok(from_bytes_mut(cast_slice_mut::<u8, u8>(
-        try_cast_slice_mut(&mut data[0..size])?,
-    )))
-}
-
 fn add_mint(test: &mut ProgramTest, mint: Pubkey, decimals: u8, authority: Pubkey) {
     test.add_packable_account(
         mint,
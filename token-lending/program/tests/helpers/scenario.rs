@@ -0,0 +1,333 @@
+//! Declarative multi-step scenarios on top of `custom_scenario`.
+//!
+//! A scenario is a set of reserves and obligations followed by a timeline of steps: price
+//! changes, clock advancement, user actions and assertions on the reloaded accounts. Actions are
+//! expected to succeed unless followed by `expect_error`.
+//!
+//! ```ignore
+//! Scenario::new()
+//!     .reserve(usdc_args)
+//!     .reserve(wsol_args)
+//!     .obligation(ObligationArgs { .. })
+//!     .set_price(usdc_mint::id(), depeg_price)
+//!     .liquidate(0, wsol_mint::id(), usdc_mint::id(), u64::MAX)
+//!     .forgive_debt(0, wsol_mint::id(), u64::MAX)
+//!     .check(|state| assert!(state.obligation(0).account.borrows.is_empty()))
+//!     .run()
+//!     .await;
+//! ```
+
+use solana_program::pubkey::Pubkey;
+use solana_program_test::BanksClientError;
+use solana_sdk::{instruction::InstructionError, transaction::TransactionError};
+use solend_program::{
+    error::LendingError,
+    state::{LendingMarket, Obligation, Reserve},
+};
+
+use super::solend_program_test::{
+    custom_scenario, Info, ObligationArgs, PriceArgs, ReserveArgs, SolendProgramTest, User,
+};
+
+/// A single thing that happens on the scenario's timeline
+pub enum Action {
+    SetPrice {
+        mint: Pubkey,
+        price: PriceArgs,
+    },
+    AdvanceSlots(u64),
+    /// Liquidates the obligation at index `obligation` with the scenario's liquidator
+    Liquidate {
+        obligation: usize,
+        repay_mint: Pubkey,
+        withdraw_mint: Pubkey,
+        liquidity_amount: u64,
+    },
+    /// Forgives debt as the lending market owner
+    ForgiveDebt {
+        obligation: usize,
+        mint: Pubkey,
+        liquidity_amount: u64,
+    },
+    /// Repays as the obligation owner
+    Repay {
+        obligation: usize,
+        mint: Pubkey,
+        liquidity_amount: u64,
+    },
+    /// Withdraws and redeems collateral as the obligation owner
+    Withdraw {
+        obligation: usize,
+        mint: Pubkey,
+        collateral_amount: u64,
+    },
+}
+
+enum Step {
+    Act {
+        action: Action,
+        expected_error: Option<LendingError>,
+    },
+    Check(Box<dyn Fn(&ScenarioState)>),
+}
+
+#[derive(Default)]
+pub struct Scenario {
+    reserves: Vec<ReserveArgs>,
+    obligations: Vec<ObligationArgs>,
+    steps: Vec<Step>,
+}
+
+/// Accounts of a running scenario, reloaded after every action
+pub struct ScenarioState {
+    pub test: SolendProgramTest,
+    pub lending_market: Info<LendingMarket>,
+    pub reserves: Vec<Info<Reserve>>,
+    pub obligations: Vec<Info<Obligation>>,
+    pub users: Vec<User>,
+    pub lending_market_owner: User,
+    /// Holds the liquidity amount of every reserve and token accounts for every cToken
+    pub liquidator: User,
+}
+
+impl Scenario {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn reserve(mut self, reserve_args: ReserveArgs) -> Self {
+        self.reserves.push(reserve_args);
+        self
+    }
+
+    pub fn obligation(mut self, obligation_args: ObligationArgs) -> Self {
+        self.obligations.push(obligation_args);
+        self
+    }
+
+    pub fn then(mut self, action: Action) -> Self {
+        self.steps.push(Step::Act {
+            action,
+            expected_error: None,
+        });
+        self
+    }
+
+    pub fn set_price(self, mint: Pubkey, price: PriceArgs) -> Self {
+        self.then(Action::SetPrice { mint, price })
+    }
+
+    pub fn advance_slots(self, slots: u64) -> Self {
+        self.then(Action::AdvanceSlots(slots))
+    }
+
+    pub fn liquidate(
+        self,
+        obligation: usize,
+        repay_mint: Pubkey,
+        withdraw_mint: Pubkey,
+        liquidity_amount: u64,
+    ) -> Self {
+        self.then(Action::Liquidate {
+            obligation,
+            repay_mint,
+            withdraw_mint,
+            liquidity_amount,
+        })
+    }
+
+    pub fn forgive_debt(self, obligation: usize, mint: Pubkey, liquidity_amount: u64) -> Self {
+        self.then(Action::ForgiveDebt {
+            obligation,
+            mint,
+            liquidity_amount,
+        })
+    }
+
+    pub fn repay(self, obligation: usize, mint: Pubkey, liquidity_amount: u64) -> Self {
+        self.then(Action::Repay {
+            obligation,
+            mint,
+            liquidity_amount,
+        })
+    }
+
+    pub fn withdraw(self, obligation: usize, mint: Pubkey, collateral_amount: u64) -> Self {
+        self.then(Action::Withdraw {
+            obligation,
+            mint,
+            collateral_amount,
+        })
+    }
+
+    /// Expects the previous action to fail with `error` instead of succeeding
+    pub fn expect_error(mut self, error: LendingError) -> Self {
+        match self.steps.last_mut() {
+            Some(Step::Act { expected_error, .. }) => *expected_error = Some(error),
+            _ => panic!("expect_error must follow an action"),
+        }
+        self
+    }
+
+    /// Runs `check` against the accounts as they are at this point of the timeline
+    pub fn check(mut self, check: impl Fn(&ScenarioState) + 'static) -> Self {
+        self.steps.push(Step::Check(Box::new(check)));
+        self
+    }
+
+    /// Sets up the reserves and obligations, then plays the timeline. The final state is
+    /// returned so tests can keep going by hand.
+    pub async fn run(self) -> ScenarioState {
+        let (mut test, lending_market, reserves, obligations, users, lending_market_owner) =
+            custom_scenario(&self.reserves, &self.obligations).await;
+
+        let mut liquidator_balances = vec![];
+        for (reserve_args, reserve) in self.reserves.iter().zip(reserves.iter()) {
+            liquidator_balances.push((&reserve_args.mint, reserve_args.liquidity_amount));
+            liquidator_balances.push((&reserve.account.collateral.mint_pubkey, 0));
+        }
+        let liquidator = User::new_with_balances(&mut test, &liquidator_balances).await;
+
+        let mut state = ScenarioState {
+            test,
+            lending_market,
+            reserves,
+            obligations,
+            users,
+            lending_market_owner,
+            liquidator,
+        };
+
+        for (i, step) in self.steps.into_iter().enumerate() {
+            match step {
+                Step::Act {
+                    action,
+                    expected_error,
+                } => {
+                    let res = state.execute(action).await;
+                    match (res, expected_error) {
+                        (Ok(()), None) => (),
+                        (Ok(()), Some(expected)) => {
+                            panic!("step {}: expected {:?}, but succeeded", i, expected)
+                        }
+                        (Err(err), None) => panic!("step {}: unexpected error {:?}", i, err),
+                        (Err(err), Some(expected)) => match err.unwrap() {
+                            TransactionError::InstructionError(
+                                _,
+                                InstructionError::Custom(code),
+                            ) if code == expected.clone() as u32 => {}
+                            err => panic!("step {}: expected {:?}, got {:?}", i, expected, err),
+                        },
+                    }
+                    state.reload().await;
+                }
+                Step::Check(check) => check(&state),
+            }
+        }
+
+        state
+    }
+}
+
+impl ScenarioState {
+    pub fn reserve(&self, mint: &Pubkey) -> &Info<Reserve> {
+        self.reserves
+            .iter()
+            .find(|reserve| reserve.account.liquidity.mint_pubkey == *mint)
+            .unwrap()
+    }
+
+    pub fn obligation(&self, index: usize) -> &Info<Obligation> {
+        &self.obligations[index]
+    }
+
+    pub async fn reload(&mut self) {
+        for reserve in self.reserves.iter_mut() {
+            *reserve = self.test.load_account(reserve.pubkey).await;
+        }
+        for obligation in self.obligations.iter_mut() {
+            *obligation = self.test.load_account(obligation.pubkey).await;
+        }
+        self.lending_market = self.test.load_account(self.lending_market.pubkey).await;
+    }
+
+    async fn execute(&mut self, action: Action) -> Result<(), BanksClientError> {
+        match action {
+            Action::SetPrice { mint, price } => {
+                self.test.set_price(&mint, &price).await;
+                Ok(())
+            }
+            Action::AdvanceSlots(slots) => {
+                self.test.advance_clock_by_slots(slots).await;
+                Ok(())
+            }
+            Action::Liquidate {
+                obligation,
+                repay_mint,
+                withdraw_mint,
+                liquidity_amount,
+            } => {
+                let repay_reserve = self.reserve(&repay_mint).clone();
+                let withdraw_reserve = self.reserve(&withdraw_mint).clone();
+                self.lending_market
+                    .liquidate_obligation_and_redeem_reserve_collateral(
+                        &mut self.test,
+                        &repay_reserve,
+                        &withdraw_reserve,
+                        &self.obligations[obligation],
+                        &self.liquidator,
+                        liquidity_amount,
+                    )
+                    .await
+            }
+            Action::ForgiveDebt {
+                obligation,
+                mint,
+                liquidity_amount,
+            } => {
+                let reserve = self.reserve(&mint).clone();
+                self.lending_market
+                    .forgive_debt(
+                        &mut self.test,
+                        &self.obligations[obligation],
+                        &self.lending_market_owner,
+                        &reserve,
+                        liquidity_amount,
+                    )
+                    .await
+            }
+            Action::Repay {
+                obligation,
+                mint,
+                liquidity_amount,
+            } => {
+                let reserve = self.reserve(&mint).clone();
+                self.lending_market
+                    .repay_obligation_liquidity(
+                        &mut self.test,
+                        &reserve,
+                        &self.obligations[obligation],
+                        &self.users[obligation],
+                        liquidity_amount,
+                    )
+                    .await
+            }
+            Action::Withdraw {
+                obligation,
+                mint,
+                collateral_amount,
+            } => {
+                let reserve = self.reserve(&mint).clone();
+                self.lending_market
+                    .withdraw_obligation_collateral_and_redeem_reserve_collateral(
+                        &mut self.test,
+                        &reserve,
+                        &self.obligations[obligation],
+                        &self.users[obligation],
+                        collateral_amount,
+                    )
+                    .await
+            }
+        }
+    }
+}
@@ -1,17 +1,20 @@
 use bytemuck::checked::from_bytes;
 use solend_sdk::instruction::*;
+use solend_sdk::math::Decimal;
 use solend_sdk::state::*;
 
 use super::{
+    cpi_proxy::cpi_proxy_program,
+    fee_redemption_hook::{self, fee_redemption_hook_program},
     flash_loan_proxy::proxy_program,
-    mock_pyth::{init_switchboard, set_switchboard_price},
+    liquidator_proxy::liquidator_program,
+    market_policy::{self, market_policy_program},
 };
 use crate::helpers::*;
 use solana_program::native_token::LAMPORTS_PER_SOL;
 use solend_program::state::RateLimiterConfig;
 use solend_sdk::{instruction::update_reserve_config, NULL_PUBKEY};
 
-use pyth_sdk_solana::state::PROD_ACCT_SIZE;
 use solana_program::{
     clock::Clock,
     instruction::Instruction,
@@ -24,26 +27,28 @@ use solana_sdk::{
     compute_budget::ComputeBudgetInstruction,
     signature::{Keypair, Signer},
     system_instruction::create_account,
-    transaction::Transaction,
+    transaction::{Transaction, TransactionError},
+    transaction_context::TransactionReturnData,
 };
 use solend_program::{
     instruction::{
         deposit_obligation_collateral, deposit_reserve_liquidity, forgive_debt,
         init_lending_market, init_reserve, liquidate_obligation_and_redeem_reserve_collateral,
-        redeem_fees, redeem_reserve_collateral, repay_obligation_liquidity,
-        set_lending_market_owner_and_config, withdraw_obligation_collateral,
+        liquidate_obligation_for_collateral, redeem_fees, redeem_reserve_collateral,
+        repay_obligation_liquidity, set_lending_market_owner_and_config,
+        withdraw_obligation_collateral,
     },
     processor::process_instruction,
     state::{LendingMarket, Reserve, ReserveConfig},
 };
 
 use spl_token::state::{Account as Token, Mint};
-use std::{
-    collections::{HashMap, HashSet},
-    str::FromStr,
-};
+use std::collections::{HashMap, HashSet};
 
-use super::mock_pyth::{init, mock_pyth_program, set_price};
+use solend_mock_oracle::{
+    instruction::{init_pyth, init_switchboard, set_pyth_price, set_switchboard_price},
+    PYTH_PRICE_ACCOUNT_LEN, PYTH_PRODUCT_ACCOUNT_LEN, SWITCHBOARD_FEED_LEN,
+};
 
 pub struct SolendProgramTest {
     pub context: ProgramTestContext,
@@ -72,9 +77,9 @@ impl SolendProgramTest {
     pub async fn start_with_test(mut test: ProgramTest) -> Self {
         test.prefer_bpf(false);
         test.add_program(
-            "mock_pyth",
-            mock_pyth_program::id(),
-            processor!(mock_pyth::process_instruction),
+            "solend_mock_oracle",
+            solend_mock_oracle::id(),
+            processor!(solend_mock_oracle::processor::process_instruction),
         );
 
         test.add_program(
@@ -83,6 +88,36 @@ impl SolendProgramTest {
             processor!(flash_loan_proxy::process_instruction),
         );
 
+        test.add_program(
+            "liquidator_proxy",
+            liquidator_program::id(),
+            processor!(liquidator_proxy::process_instruction),
+        );
+
+        test.add_program(
+            "cpi_proxy",
+            cpi_proxy_program::id(),
+            processor!(cpi_proxy::process_instruction),
+        );
+
+        test.add_program(
+            "vault",
+            vault::id(),
+            processor!(vault::processor::process_instruction),
+        );
+
+        test.add_program(
+            "fee_redemption_hook",
+            fee_redemption_hook_program::id(),
+            processor!(fee_redemption_hook::process_instruction),
+        );
+
+        test.add_program(
+            "market_policy",
+            market_policy_program::id(),
+            processor!(market_policy::process_instruction),
+        );
+
         let authority = Keypair::new();
 
         add_mint(&mut test, usdc_mint::id(), 6, authority.pubkey());
@@ -115,9 +150,9 @@ impl SolendProgramTest {
 
         test.prefer_bpf(false);
         test.add_program(
-            "mock_pyth",
-            mock_pyth_program::id(),
-            processor!(mock_pyth::process_instruction),
+            "solend_mock_oracle",
+            solend_mock_oracle::id(),
+            processor!(solend_mock_oracle::processor::process_instruction),
         );
 
         test.add_program(
@@ -126,6 +161,36 @@ impl SolendProgramTest {
             processor!(flash_loan_proxy::process_instruction),
         );
 
+        test.add_program(
+            "liquidator_proxy",
+            liquidator_program::id(),
+            processor!(liquidator_proxy::process_instruction),
+        );
+
+        test.add_program(
+            "cpi_proxy",
+            cpi_proxy_program::id(),
+            processor!(cpi_proxy::process_instruction),
+        );
+
+        test.add_program(
+            "vault",
+            vault::id(),
+            processor!(vault::processor::process_instruction),
+        );
+
+        test.add_program(
+            "fee_redemption_hook",
+            fee_redemption_hook_program::id(),
+            processor!(fee_redemption_hook::process_instruction),
+        );
+
+        test.add_program(
+            "market_policy",
+            market_policy_program::id(),
+            processor!(market_policy::process_instruction),
+        );
+
         let authority = Keypair::new();
 
         add_mint(&mut test, usdc_mint::id(), 6, authority.pubkey());
@@ -177,6 +242,64 @@ impl SolendProgramTest {
             .await
     }
 
+    /// Simulate a transaction, returning its result, logs and return data
+    pub async fn simulate_transaction(
+        &mut self,
+        instructions: &[Instruction],
+        signers: Option<&[&Keypair]>,
+    ) -> (
+        Result<(), TransactionError>,
+        Vec<String>,
+        Option<TransactionReturnData>,
+    ) {
+        let mut transaction =
+            Transaction::new_with_payer(instructions, Some(&self.context.payer.pubkey()));
+
+        let mut all_signers = vec![&self.context.payer];
+        if let Some(signers) = signers {
+            all_signers.extend_from_slice(signers);
+        }
+        transaction.sign(&all_signers, self.context.last_blockhash);
+
+        let simulation = self
+            .context
+            .banks_client
+            .simulate_transaction(transaction)
+            .await
+            .unwrap();
+
+        let (logs, return_data) = simulation
+            .simulation_details
+            .map(|details| (details.logs, details.return_data))
+            .unwrap_or_default();
+        (simulation.result.unwrap(), logs, return_data)
+    }
+
+    /// Simulate a successful transaction, returning the compute units it consumed
+    pub async fn simulate_compute_units(
+        &mut self,
+        instructions: &[Instruction],
+        signers: Option<&[&Keypair]>,
+    ) -> u64 {
+        let mut transaction =
+            Transaction::new_with_payer(instructions, Some(&self.context.payer.pubkey()));
+
+        let mut all_signers = vec![&self.context.payer];
+        if let Some(signers) = signers {
+            all_signers.extend_from_slice(signers);
+        }
+        transaction.sign(&all_signers, self.context.last_blockhash);
+
+        let simulation = self
+            .context
+            .banks_client
+            .simulate_transaction(transaction)
+            .await
+            .unwrap();
+        simulation.result.unwrap().unwrap();
+        simulation.simulation_details.unwrap().units_consumed
+    }
+
     pub async fn load_optional_account<T: Pack + IsInitialized>(
         &mut self,
         acc_pk: Pubkey,
@@ -381,8 +504,8 @@ impl SolendProgramTest {
                         owner.keypair.pubkey(),
                         QUOTE_CURRENCY,
                         lending_market_key.pubkey(),
-                        mock_pyth_program::id(),
-                        mock_pyth_program::id(), // TODO suspicious
+                        solend_mock_oracle::id(),
+                        solend_mock_oracle::id(), // TODO suspicious
                     ),
                 ],
                 Some(&[lending_market_key]),
@@ -399,17 +522,18 @@ impl SolendProgramTest {
 
     pub async fn init_pyth_feed(&mut self, mint: &Pubkey) {
         let pyth_price_pubkey = self
-            .create_account(3312, &mock_pyth_program::id(), None)
+            .create_account(PYTH_PRICE_ACCOUNT_LEN, &solend_mock_oracle::id(), None)
             .await;
         let pyth_product_pubkey = self
-            .create_account(PROD_ACCT_SIZE, &mock_pyth_program::id(), None)
+            .create_account(PYTH_PRODUCT_ACCOUNT_LEN, &solend_mock_oracle::id(), None)
             .await;
 
         self.process_transaction(
-            &[init(
-                mock_pyth_program::id(),
+            &[init_pyth(
+                solend_mock_oracle::id(),
                 pyth_price_pubkey,
                 pyth_product_pubkey,
+                QUOTE_CURRENCY,
             )],
             None,
         )
@@ -429,8 +553,8 @@ impl SolendProgramTest {
     pub async fn set_price(&mut self, mint: &Pubkey, price: &PriceArgs) {
         let oracle = self.mints.get(mint).unwrap().unwrap();
         self.process_transaction(
-            &[set_price(
-                mock_pyth_program::id(),
+            &[set_pyth_price(
+                solend_mock_oracle::id(),
                 oracle.pyth_price_pubkey,
                 price.price,
                 price.conf,
@@ -446,16 +570,12 @@ impl SolendProgramTest {
 
     pub async fn init_switchboard_feed(&mut self, mint: &Pubkey) -> Pubkey {
         let switchboard_feed_pubkey = self
-            .create_account(
-                std::mem::size_of::<AggregatorAccountData>() + 8,
-                &mock_pyth_program::id(),
-                None,
-            )
+            .create_account(SWITCHBOARD_FEED_LEN, &solend_mock_oracle::id(), None)
             .await;
 
         self.process_transaction(
             &[init_switchboard(
-                mock_pyth_program::id(),
+                solend_mock_oracle::id(),
                 switchboard_feed_pubkey,
             )],
             None,
@@ -476,7 +596,7 @@ impl SolendProgramTest {
         let oracle = self.mints.get(mint).unwrap().unwrap();
         self.process_transaction(
             &[set_switchboard_price(
-                mock_pyth_program::id(),
+                solend_mock_oracle::id(),
                 oracle.switchboard_feed_pubkey.unwrap(),
                 price.price,
                 price.expo,
@@ -527,7 +647,7 @@ impl SolendProgramTest {
         let res = self
             .process_transaction(
                 &[
-                    ComputeBudgetInstruction::set_compute_unit_limit(70_000),
+                    ComputeBudgetInstruction::set_compute_unit_limit(80_000),
                     init_reserve(
                         solend_program::id(),
                         liquidity_amount,
@@ -544,7 +664,7 @@ impl SolendProgramTest {
                         reserve_collateral_supply_pubkey,
                         oracle.pyth_product_pubkey,
                         oracle.pyth_price_pubkey,
-                        Pubkey::from_str("nu11111111111111111111111111111111111111111").unwrap(),
+                        oracle.switchboard_feed_pubkey.unwrap_or(NULL_PUBKEY),
                         lending_market.pubkey,
                         lending_market_owner.keypair.pubkey(),
                         lending_market_owner.keypair.pubkey(),
@@ -723,7 +843,7 @@ impl Info<LendingMarket> {
         let oracle = oracle.unwrap_or(&default_oracle);
 
         let instructions = [
-            ComputeBudgetInstruction::set_compute_unit_limit(20_000),
+            ComputeBudgetInstruction::set_compute_unit_limit(40_000),
             update_reserve_config(
                 solend_program::id(),
                 config,
@@ -750,7 +870,7 @@ impl Info<LendingMarket> {
         liquidity_amount: u64,
     ) -> Result<(), BanksClientError> {
         let instructions = [
-            ComputeBudgetInstruction::set_compute_unit_limit(70_000),
+            ComputeBudgetInstruction::set_compute_unit_limit(75_000),
             deposit_reserve_liquidity_and_obligation_collateral(
                 solend_program::id(),
                 liquidity_amount,
@@ -784,13 +904,14 @@ impl Info<LendingMarket> {
     ) -> Result<(), BanksClientError> {
         let instructions = [
             ComputeBudgetInstruction::set_compute_unit_limit(48_000),
-            refresh_reserve(
+            refresh_reserve_with_lending_market(
                 solend_program::id(),
                 reserve.pubkey,
                 reserve.account.liquidity.pyth_oracle_pubkey,
                 reserve.account.liquidity.switchboard_oracle_pubkey,
+                reserve.account.lending_market,
             ),
-            redeem_reserve_collateral(
+            redeem_reserve_collateral_with_fee_receiver(
                 solend_program::id(),
                 collateral_amount,
                 user.get_account(&reserve.account.collateral.mint_pubkey)
@@ -802,6 +923,7 @@ impl Info<LendingMarket> {
                 reserve.account.liquidity.supply_pubkey,
                 self.pubkey,
                 user.keypair.pubkey(),
+                reserve.account.config.fee_receiver,
             ),
         ];
 
@@ -852,7 +974,7 @@ impl Info<LendingMarket> {
         collateral_amount: u64,
     ) -> Result<(), BanksClientError> {
         let instructions = [
-            ComputeBudgetInstruction::set_compute_unit_limit(38_000),
+            ComputeBudgetInstruction::set_compute_unit_limit(45_000),
             deposit_obligation_collateral(
                 solend_program::id(),
                 collateral_amount,
@@ -879,11 +1001,12 @@ impl Info<LendingMarket> {
         test.process_transaction(
             &[
                 ComputeBudgetInstruction::set_compute_unit_limit(40_000),
-                refresh_reserve(
+                refresh_reserve_with_lending_market(
                     solend_program::id(),
                     reserve.pubkey,
                     reserve.account.liquidity.pyth_oracle_pubkey,
                     reserve.account.liquidity.switchboard_oracle_pubkey,
+                    reserve.account.lending_market,
                 ),
             ],
             None,
@@ -924,11 +1047,12 @@ impl Info<LendingMarket> {
         let mut instructions: Vec<Instruction> = reserves
             .into_iter()
             .map(|reserve| {
-                refresh_reserve(
+                refresh_reserve_with_lending_market(
                     solend_program::id(),
                     reserve.pubkey,
                     reserve.account.liquidity.pyth_oracle_pubkey,
                     reserve.account.liquidity.switchboard_oracle_pubkey,
+                    reserve.account.lending_market,
                 )
             })
             .collect();
@@ -997,7 +1121,7 @@ impl Info<LendingMarket> {
             .await;
         test.process_transaction(&refresh_ixs, None).await.unwrap();
 
-        let mut instructions = vec![ComputeBudgetInstruction::set_compute_unit_limit(55_000)];
+        let mut instructions = vec![ComputeBudgetInstruction::set_compute_unit_limit(70_000)];
         instructions.push(borrow_obligation_liquidity(
             solend_program::id(),
             liquidity_amount,
@@ -1016,6 +1140,42 @@ impl Info<LendingMarket> {
             .await
     }
 
+    pub async fn borrow_obligation_liquidity_fixed_rate(
+        &self,
+        test: &mut SolendProgramTest,
+        borrow_reserve: &Info<Reserve>,
+        obligation: &Info<Obligation>,
+        user: &User,
+        liquidity_amount: u64,
+    ) -> Result<(), BanksClientError> {
+        let obligation = test.load_account::<Obligation>(obligation.pubkey).await;
+
+        let refresh_ixs = self
+            .build_refresh_instructions(test, &obligation, Some(borrow_reserve))
+            .await;
+        test.process_transaction(&refresh_ixs, None).await.unwrap();
+
+        let instructions = [
+            ComputeBudgetInstruction::set_compute_unit_limit(70_000),
+            borrow_obligation_liquidity_fixed_rate(
+                solend_program::id(),
+                liquidity_amount,
+                borrow_reserve.account.liquidity.supply_pubkey,
+                user.get_account(&borrow_reserve.account.liquidity.mint_pubkey)
+                    .unwrap(),
+                borrow_reserve.pubkey,
+                borrow_reserve.account.config.fee_receiver,
+                obligation.pubkey,
+                self.pubkey,
+                user.keypair.pubkey(),
+                None,
+            ),
+        ];
+
+        test.process_transaction(&instructions, Some(&[&user.keypair]))
+            .await
+    }
+
     pub async fn repay_obligation_liquidity(
         &self,
         test: &mut SolendProgramTest,
@@ -1025,7 +1185,7 @@ impl Info<LendingMarket> {
         liquidity_amount: u64,
     ) -> Result<(), BanksClientError> {
         let instructions = [
-            ComputeBudgetInstruction::set_compute_unit_limit(27_000),
+            ComputeBudgetInstruction::set_compute_unit_limit(30_000),
             repay_obligation_liquidity(
                 solend_program::id(),
                 liquidity_amount,
@@ -1050,11 +1210,12 @@ impl Info<LendingMarket> {
     ) -> Result<(), BanksClientError> {
         let instructions = [
             ComputeBudgetInstruction::set_compute_unit_limit(40_000),
-            refresh_reserve(
+            refresh_reserve_with_lending_market(
                 solend_program::id(),
                 reserve.pubkey,
                 reserve.account.liquidity.pyth_oracle_pubkey,
                 reserve.account.liquidity.switchboard_oracle_pubkey,
+                reserve.account.lending_market,
             ),
             redeem_fees(
                 solend_program::id(),
@@ -1100,7 +1261,89 @@ impl Info<LendingMarket> {
                     withdraw_reserve.account.collateral.mint_pubkey,
                     withdraw_reserve.account.collateral.supply_pubkey,
                     withdraw_reserve.account.liquidity.supply_pubkey,
-                    withdraw_reserve.account.config.fee_receiver,
+                    withdraw_reserve.account.config.liquidation_fee_receiver(),
+                    obligation.pubkey,
+                    self.pubkey,
+                    user.keypair.pubkey(),
+                ),
+            ],
+            Some(&[&user.keypair]),
+        )
+        .await
+    }
+
+    pub async fn liquidate_obligation_and_redeem_reserve_collateral_by_value(
+        &self,
+        test: &mut SolendProgramTest,
+        repay_reserve: &Info<Reserve>,
+        withdraw_reserve: &Info<Reserve>,
+        obligation: &Info<Obligation>,
+        user: &User,
+        max_repay_value: Decimal,
+    ) -> Result<(), BanksClientError> {
+        let refresh_ixs = self
+            .build_refresh_instructions(test, obligation, None)
+            .await;
+        test.process_transaction(&refresh_ixs, None).await.unwrap();
+
+        test.process_transaction(
+            &[
+                ComputeBudgetInstruction::set_compute_unit_limit(100_000),
+                liquidate_obligation_and_redeem_reserve_collateral_by_value(
+                    solend_program::id(),
+                    max_repay_value,
+                    user.get_account(&repay_reserve.account.liquidity.mint_pubkey)
+                        .unwrap(),
+                    user.get_account(&withdraw_reserve.account.collateral.mint_pubkey)
+                        .unwrap(),
+                    user.get_account(&withdraw_reserve.account.liquidity.mint_pubkey)
+                        .unwrap(),
+                    repay_reserve.pubkey,
+                    repay_reserve.account.liquidity.supply_pubkey,
+                    withdraw_reserve.pubkey,
+                    withdraw_reserve.account.collateral.mint_pubkey,
+                    withdraw_reserve.account.collateral.supply_pubkey,
+                    withdraw_reserve.account.liquidity.supply_pubkey,
+                    withdraw_reserve.account.config.liquidation_fee_receiver(),
+                    obligation.pubkey,
+                    self.pubkey,
+                    user.keypair.pubkey(),
+                ),
+            ],
+            Some(&[&user.keypair]),
+        )
+        .await
+    }
+
+    pub async fn liquidate_obligation_for_collateral(
+        &self,
+        test: &mut SolendProgramTest,
+        repay_reserve: &Info<Reserve>,
+        withdraw_reserve: &Info<Reserve>,
+        obligation: &Info<Obligation>,
+        user: &User,
+        liquidity_amount: u64,
+    ) -> Result<(), BanksClientError> {
+        let refresh_ixs = self
+            .build_refresh_instructions(test, obligation, None)
+            .await;
+        test.process_transaction(&refresh_ixs, None).await.unwrap();
+
+        test.process_transaction(
+            &[
+                ComputeBudgetInstruction::set_compute_unit_limit(100_000),
+                liquidate_obligation_for_collateral(
+                    solend_program::id(),
+                    liquidity_amount,
+                    user.get_account(&repay_reserve.account.liquidity.mint_pubkey)
+                        .unwrap(),
+                    user.get_account(&withdraw_reserve.account.collateral.mint_pubkey)
+                        .unwrap(),
+                    repay_reserve.pubkey,
+                    repay_reserve.account.liquidity.supply_pubkey,
+                    withdraw_reserve.pubkey,
+                    withdraw_reserve.account.collateral.mint_pubkey,
+                    withdraw_reserve.account.collateral.supply_pubkey,
                     obligation.pubkey,
                     self.pubkey,
                     user.keypair.pubkey(),
@@ -1151,6 +1394,27 @@ impl Info<LendingMarket> {
         obligation: &Info<Obligation>,
         user: &User,
         collateral_amount: u64,
+    ) -> Result<(), BanksClientError> {
+        self.withdraw_obligation_collateral_and_redeem_reserve_collateral_to(
+            test,
+            withdraw_reserve,
+            obligation,
+            user,
+            user.get_account(&withdraw_reserve.account.liquidity.mint_pubkey)
+                .unwrap(),
+            collateral_amount,
+        )
+        .await
+    }
+
+    pub async fn withdraw_obligation_collateral_and_redeem_reserve_collateral_to(
+        &self,
+        test: &mut SolendProgramTest,
+        withdraw_reserve: &Info<Reserve>,
+        obligation: &Info<Obligation>,
+        user: &User,
+        destination_liquidity_pubkey: Pubkey,
+        collateral_amount: u64,
     ) -> Result<(), BanksClientError> {
         let obligation = test.load_account::<Obligation>(obligation.pubkey).await;
 
@@ -1162,7 +1426,7 @@ impl Info<LendingMarket> {
         test.process_transaction(
             &[
                 ComputeBudgetInstruction::set_compute_unit_limit(70_000),
-                withdraw_obligation_collateral_and_redeem_reserve_collateral(
+                withdraw_obligation_collateral_and_redeem_reserve_collateral_with_fee_receiver(
                     solend_program::id(),
                     collateral_amount,
                     withdraw_reserve.account.collateral.supply_pubkey,
@@ -1171,12 +1435,12 @@ impl Info<LendingMarket> {
                     withdraw_reserve.pubkey,
                     obligation.pubkey,
                     self.pubkey,
-                    user.get_account(&withdraw_reserve.account.liquidity.mint_pubkey)
-                        .unwrap(),
+                    destination_liquidity_pubkey,
                     withdraw_reserve.account.collateral.mint_pubkey,
                     withdraw_reserve.account.liquidity.supply_pubkey,
                     user.keypair.pubkey(),
                     user.keypair.pubkey(),
+                    withdraw_reserve.account.config.fee_receiver,
                 ),
             ],
             Some(&[&user.keypair]),
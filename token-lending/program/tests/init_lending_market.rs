@@ -4,15 +4,12 @@ mod helpers;
 
 use helpers::solend_program_test::{SolendProgramTest, User};
 use helpers::*;
-use mock_pyth::mock_pyth_program;
-use solana_program::instruction::InstructionError;
 use solana_program_test::*;
 use solana_sdk::signature::Keypair;
 use solana_sdk::signer::Signer;
-use solana_sdk::transaction::TransactionError;
 use solend_program::error::LendingError;
 use solend_program::instruction::init_lending_market;
-use solend_program::state::{LendingMarket, RateLimiter, PROGRAM_VERSION};
+use solend_program::state::{LendingMarket, RateLimiter, PROGRAM_VERSION, SLOTS_PER_YEAR};
 
 #[tokio::test]
 async fn test_success() {
@@ -33,11 +30,15 @@ async fn test_success() {
             owner: lending_market_owner.keypair.pubkey(),
             quote_currency: QUOTE_CURRENCY,
             token_program_id: spl_token::id(),
-            oracle_program_id: mock_pyth_program::id(),
-            switchboard_oracle_program_id: mock_pyth_program::id(),
+            oracle_program_id: solend_mock_oracle::id(),
+            switchboard_oracle_program_id: solend_mock_oracle::id(),
             rate_limiter: RateLimiter::default(),
             whitelisted_liquidator: None,
             risk_authority: lending_market_owner.keypair.pubkey(),
+            slots_per_year: SLOTS_PER_YEAR,
+            has_obligation_limits: false,
+            has_market_policy: false,
+            has_liquidation_circuit_breaker: false,
         }
     );
 }
@@ -63,20 +64,12 @@ async fn test_already_initialized() {
                 lending_market_owner.keypair.pubkey(),
                 QUOTE_CURRENCY,
                 keypair.pubkey(),
-                mock_pyth_program::id(),
-                mock_pyth_program::id(),
+                solend_mock_oracle::id(),
+                solend_mock_oracle::id(),
             )],
             None,
         )
-        .await
-        .unwrap_err()
-        .unwrap();
+        .await;
 
-    assert_eq!(
-        res,
-        TransactionError::InstructionError(
-            0,
-            InstructionError::Custom(LendingError::AlreadyInitialized as u32)
-        )
-    );
+    expect_lending_error(res, LendingError::AlreadyInitialized);
 }
@@ -4,16 +4,19 @@ mod helpers;
 
 use helpers::solend_program_test::{setup_world, Info, SolendProgramTest, User};
 use helpers::*;
-use solana_program::instruction::InstructionError;
+use solana_program::native_token::LAMPORTS_PER_SOL;
+use solana_program::program_pack::Pack;
+use solana_program::system_instruction::transfer;
+use solana_program::sysvar::rent::Rent;
 use solana_program_test::*;
+use solana_sdk::pubkey::Pubkey;
 use solana_sdk::signature::Keypair;
 
 use solana_sdk::signer::Signer;
-use solana_sdk::transaction::TransactionError;
 use solend_program::error::LendingError;
-use solend_program::instruction::init_obligation;
+use solend_program::instruction::{close_obligation, create_and_init_obligation, init_obligation};
 use solend_program::math::Decimal;
-use solend_program::state::{LastUpdate, LendingMarket, Obligation, PROGRAM_VERSION};
+use solend_program::state::{DepositMode, LastUpdate, LendingMarket, Obligation, PROGRAM_VERSION};
 
 async fn setup() -> (SolendProgramTest, Info<LendingMarket>, User) {
     let (test, lending_market, _, _, _, user) =
@@ -49,7 +52,11 @@ async fn test_success() {
             allowed_borrow_value: Decimal::zero(),
             unhealthy_borrow_value: Decimal::zero(),
             super_unhealthy_borrow_value: Decimal::zero(),
-            borrowing_isolated_asset: false
+            borrowing_isolated_asset: false,
+            default_deposit_mode: DepositMode::Collateralize,
+            ignore_health_buffer: false,
+            has_borrow_cap: false,
+            has_deleverage_credit: false,
         }
     );
 }
@@ -78,15 +85,79 @@ async fn test_already_initialized() {
             )],
             Some(&[&user.keypair]),
         )
-        .await
-        .unwrap_err()
-        .unwrap();
+        .await;
+
+    expect_lending_error(res, LendingError::AlreadyInitialized);
+}
+
+#[tokio::test]
+async fn test_success_separate_rent_payer() {
+    let (mut test, lending_market, user) = setup().await;
+
+    // an app pays for the obligation, the user only signs as its owner
+    let app = Keypair::new();
+    test.process_transaction(
+        &[transfer(
+            &test.context.payer.pubkey(),
+            &app.pubkey(),
+            LAMPORTS_PER_SOL,
+        )],
+        None,
+    )
+    .await
+    .unwrap();
+
+    let user_lamports = get_balance(&mut test, user.keypair.pubkey()).await;
+
+    let obligation_keypair = Keypair::new();
+    test.process_transaction(
+        &create_and_init_obligation(
+            solend_program::id(),
+            app.pubkey(),
+            obligation_keypair.pubkey(),
+            lending_market.pubkey,
+            user.keypair.pubkey(),
+        ),
+        Some(&[&app, &obligation_keypair, &user.keypair]),
+    )
+    .await
+    .unwrap();
+
+    let obligation = test
+        .load_account::<Obligation>(obligation_keypair.pubkey())
+        .await;
+    assert_eq!(obligation.account.owner, user.keypair.pubkey());
 
+    let rent = Rent::default().minimum_balance(Obligation::LEN);
     assert_eq!(
-        res,
-        TransactionError::InstructionError(
-            0,
-            InstructionError::Custom(LendingError::AlreadyInitialized as u32)
-        )
+        get_balance(&mut test, app.pubkey()).await,
+        LAMPORTS_PER_SOL - rent
+    );
+    assert_eq!(
+        get_balance(&mut test, user.keypair.pubkey()).await,
+        user_lamports
     );
+
+    // closing the obligation can hand the rent back to the app
+    test.process_transaction(
+        &[close_obligation(
+            solend_program::id(),
+            obligation.pubkey,
+            app.pubkey(),
+            user.keypair.pubkey(),
+        )],
+        Some(&[&user.keypair]),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(get_balance(&mut test, app.pubkey()).await, LAMPORTS_PER_SOL);
+    assert_eq!(
+        get_balance(&mut test, user.keypair.pubkey()).await,
+        user_lamports
+    );
+}
+
+async fn get_balance(test: &mut SolendProgramTest, pubkey: Pubkey) -> u64 {
+    test.context.banks_client.get_balance(pubkey).await.unwrap()
 }
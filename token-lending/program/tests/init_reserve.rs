@@ -18,18 +18,17 @@ use helpers::*;
 use solana_program::example_mocks::solana_sdk::Pubkey;
 use solana_program::program_pack::Pack;
 use solana_program_test::*;
-use solana_sdk::{
-    instruction::InstructionError,
-    signature::{Keypair, Signer},
-    transaction::TransactionError,
-};
+use solana_sdk::signature::{Keypair, Signer};
 use solend_program::state::LastUpdate;
 use solend_program::state::RateLimiter;
 
 use solend_program::state::Reserve;
 use solend_program::state::ReserveCollateral;
 use solend_program::state::ReserveLiquidity;
+use solend_program::state::ReserveStatus;
+use solend_program::state::LOCKED_INITIAL_COLLATERAL;
 use solend_program::state::PROGRAM_VERSION;
+use solend_program::state::SLOTS_PER_YEAR;
 use solend_program::NULL_PUBKEY;
 
 use solend_program::{
@@ -96,7 +95,7 @@ async fn test_success() {
     test.process_transaction(
         &[init_reserve(
             solend_program::id(),
-            1000,
+            2000,
             reserve_config,
             lending_market_owner.get_account(&wsol_mint::id()).unwrap(),
             destination_collateral_pubkey,
@@ -124,7 +123,7 @@ async fn test_success() {
         TokenBalanceChange {
             token_account: lending_market_owner.get_account(&wsol_mint::id()).unwrap(),
             mint: wsol_mint::id(),
-            diff: -1000,
+            diff: -2000,
         },
         TokenBalanceChange {
             token_account: destination_collateral_pubkey,
@@ -134,6 +133,11 @@ async fn test_success() {
         TokenBalanceChange {
             token_account: reserve_liquidity_supply_pubkey,
             mint: wsol_mint::id(),
+            diff: 2000,
+        },
+        TokenBalanceChange {
+            token_account: reserve_collateral_supply_pubkey,
+            mint: reserve_collateral_mint_pubkey,
             diff: 1000,
         },
     ]);
@@ -143,7 +147,7 @@ async fn test_success() {
         mint_supply_changes,
         HashSet::from([MintSupplyChange {
             mint: reserve_collateral_mint_pubkey,
-            diff: 1000,
+            diff: 2000,
         }])
     );
 
@@ -164,20 +168,26 @@ async fn test_success() {
                 supply_pubkey: reserve_liquidity_supply_pubkey,
                 pyth_oracle_pubkey: oracle.pyth_price_pubkey,
                 switchboard_oracle_pubkey: NULL_PUBKEY,
-                available_amount: 1000,
+                available_amount: 2000,
                 borrowed_amount_wads: Decimal::zero(),
                 cumulative_borrow_rate_wads: Decimal::one(),
                 accumulated_protocol_fees_wads: Decimal::zero(),
                 market_price: Decimal::from(10u64),
                 smoothed_market_price: Decimal::from(10u64),
+                lifetime_forgiven_amount_wads: Decimal::zero(),
+                fixed_borrowed_amount_wads: Decimal::zero(),
+                fixed_interest_per_year: 0,
             },
             collateral: ReserveCollateral {
                 mint_pubkey: reserve_collateral_mint_pubkey,
-                mint_total_supply: 1000,
+                mint_total_supply: 2000,
                 supply_pubkey: reserve_collateral_supply_pubkey,
             },
             config: reserve_config,
-            rate_limiter: RateLimiter::new(RateLimiterConfig::default(), 1001)
+            rate_limiter: RateLimiter::new(RateLimiterConfig::default(), 1001),
+            slots_per_year: SLOTS_PER_YEAR,
+            status: ReserveStatus::Active,
+            has_fee_redemption_hook: false,
         }
     );
 }
@@ -200,17 +210,29 @@ async fn test_init_reserve_null_oracles() {
                 switchboard_feed_pubkey: Some(NULL_PUBKEY),
             }),
         )
-        .await
-        .unwrap_err()
-        .unwrap();
+        .await;
 
-    assert_eq!(
-        res,
-        TransactionError::InstructionError(
-            1,
-            InstructionError::Custom(LendingError::InvalidOracleConfig as u32)
+    expect_lending_error(res, LendingError::InvalidOracleConfig);
+}
+
+#[tokio::test]
+async fn test_initial_liquidity_too_small() {
+    let (mut test, lending_market, lending_market_owner) = setup().await;
+
+    // not enough to lock the initial collateral
+    let res = test
+        .init_reserve(
+            &lending_market,
+            &lending_market_owner,
+            &wsol_mint::id(),
+            &test_reserve_config(),
+            &Keypair::new(),
+            LOCKED_INITIAL_COLLATERAL - 1,
+            None,
         )
-    );
+        .await;
+
+    expect_lending_error(res, LendingError::InvalidAmount);
 }
 
 #[tokio::test]
@@ -240,17 +262,9 @@ async fn test_already_initialized() {
             1000,
             None,
         )
-        .await
-        .unwrap_err()
-        .unwrap();
+        .await;
 
-    assert_eq!(
-        res,
-        TransactionError::InstructionError(
-            1,
-            InstructionError::Custom(LendingError::AlreadyInitialized as u32)
-        )
-    );
+    expect_lending_error(res, LendingError::AlreadyInitialized);
 }
 
 #[tokio::test]
@@ -286,16 +300,8 @@ async fn test_invalid_fees() {
                 1000,
                 None,
             )
-            .await
-            .unwrap_err()
-            .unwrap();
+            .await;
 
-        assert_eq!(
-            res,
-            TransactionError::InstructionError(
-                1,
-                InstructionError::Custom(LendingError::InvalidConfig as u32)
-            )
-        );
+        expect_lending_error(res, LendingError::InvalidConfig);
     }
 }
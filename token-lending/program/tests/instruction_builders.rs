@@ -0,0 +1,837 @@
+#![cfg(feature = "test-bpf")]
+//! Runs the instruction built by every sdk builder through the processor against a live market,
+//! with the scenario_1 accounts wherever the builder asks for one. The instructions don't have to
+//! succeed, but they must fail on what the accounts hold rather than on how many were passed, so a
+//! builder whose accounts drift from the order the processor reads them in fails here.
+//!
+//! Every `LendingInstruction` variant needs a case, or this file doesn't compile.
+
+mod helpers;
+
+use crate::solend_program_test::*;
+use helpers::*;
+use solana_program::{instruction::Instruction, pubkey::Pubkey};
+use solana_program_test::*;
+use solana_sdk::{
+    instruction::InstructionError,
+    signature::{Keypair, Signer},
+    transaction::TransactionError,
+};
+use solend_program::{
+    instruction::*,
+    math::Decimal,
+    state::{
+        DepositMode, LendingMarket, LendingMarketMetadata, Obligation, RateLimiterConfig, Reserve,
+        MARKET_DESCRIPTION_SIZE, MARKET_IMAGE_URL_SIZE, MARKET_NAME_SIZE, PADDING_SIZE,
+        SLOTS_PER_YEAR,
+    },
+};
+use solend_sdk::NULL_PUBKEY;
+
+struct World {
+    test: SolendProgramTest,
+    lending_market: Info<LendingMarket>,
+    usdc_reserve: Info<Reserve>,
+    wsol_reserve: Info<Reserve>,
+    user: User,
+    obligation: Info<Obligation>,
+    lending_market_owner: User,
+}
+
+impl World {
+    async fn new() -> Self {
+        let (
+            test,
+            lending_market,
+            usdc_reserve,
+            wsol_reserve,
+            user,
+            obligation,
+            lending_market_owner,
+        ) = scenario_1(&test_reserve_config(), &test_reserve_config()).await;
+        Self {
+            test,
+            lending_market,
+            usdc_reserve,
+            wsol_reserve,
+            user,
+            obligation,
+            lending_market_owner,
+        }
+    }
+
+    fn id(&self) -> Pubkey {
+        solend_program::id()
+    }
+
+    fn market(&self) -> Pubkey {
+        self.lending_market.pubkey
+    }
+
+    fn owner(&self) -> Pubkey {
+        self.lending_market_owner.keypair.pubkey()
+    }
+
+    fn user(&self) -> Pubkey {
+        self.user.keypair.pubkey()
+    }
+
+    fn user_account(&self, mint: &Pubkey) -> Pubkey {
+        self.user.get_account(mint).unwrap()
+    }
+
+    fn user_usdc(&self) -> Pubkey {
+        self.user_account(&usdc_mint::id())
+    }
+
+    fn user_cusdc(&self) -> Pubkey {
+        self.user_account(&self.usdc_reserve.account.collateral.mint_pubkey)
+    }
+
+    fn user_wsol(&self) -> Pubkey {
+        self.user_account(&wsol_mint::id())
+    }
+
+    fn reserve_pubkeys(&self) -> Vec<Pubkey> {
+        vec![self.usdc_reserve.pubkey, self.wsol_reserve.pubkey]
+    }
+
+    fn rate_limiter_config(&self) -> RateLimiterConfig {
+        RateLimiterConfig {
+            window_duration: 10,
+            max_outflow: 10,
+        }
+    }
+
+    /// Processes `instruction` signed by the user and the lending market owner, whichever it asks
+    /// for, and checks it got past reading its accounts
+    async fn assert_accounts_accepted(&mut self, instruction: Instruction) {
+        let mut signers: Vec<&Keypair> = vec![];
+        for meta in instruction.accounts.iter().filter(|meta| meta.is_signer) {
+            let signer = [&self.user.keypair, &self.lending_market_owner.keypair]
+                .iter()
+                .copied()
+                .find(|keypair| keypair.pubkey() == meta.pubkey)
+                .unwrap_or_else(|| panic!("builder asks {} to sign", meta.pubkey));
+            if !signers
+                .iter()
+                .any(|keypair| keypair.pubkey() == signer.pubkey())
+            {
+                signers.push(signer);
+            }
+        }
+
+        let result = self
+            .test
+            .process_transaction(&[instruction], Some(&signers))
+            .await;
+        if let Err(error) = result {
+            match error.into_transaction_error() {
+                TransactionError::InstructionError(
+                    _,
+                    error @ (InstructionError::NotEnoughAccountKeys
+                    | InstructionError::MissingAccount),
+                ) => panic!(
+                    "instruction accounts don't match the processor: {:?}",
+                    error
+                ),
+                TransactionError::InstructionError(..) => (),
+                error => panic!("transaction failed before running: {:?}", error),
+            }
+        }
+    }
+}
+
+/// One `name: Variant => builder` case per instruction variant, then any other builders of an
+/// already covered variant
+macro_rules! instruction_builder_tests {
+    (
+        variants { $($name:ident: $variant:ident => $build:expr,)* }
+        other_builders { $($other_name:ident: $other_variant:ident => $other_build:expr,)* }
+    ) => {
+        /// Name of the case covering the variant of `instruction`. Doesn't compile when a variant
+        /// is missing from `variants`.
+        fn covering_case(instruction: &LendingInstruction) -> &'static str {
+            match instruction {
+                $(LendingInstruction::$variant { .. } => stringify!($name),)*
+            }
+        }
+
+        $(
+            #[tokio::test]
+            async fn $name() {
+                let mut world = World::new().await;
+                let build: fn(&World) -> Instruction = $build;
+                let instruction = build(&world);
+
+                let unpacked = LendingInstruction::unpack(&instruction.data).unwrap();
+                assert_eq!(covering_case(&unpacked), stringify!($name));
+                world.assert_accounts_accepted(instruction).await;
+            }
+        )*
+
+        $(
+            #[tokio::test]
+            async fn $other_name() {
+                let mut world = World::new().await;
+                let build: fn(&World) -> Instruction = $other_build;
+                let instruction = build(&world);
+
+                let unpacked = LendingInstruction::unpack(&instruction.data).unwrap();
+                assert!(matches!(unpacked, LendingInstruction::$other_variant { .. }));
+                world.assert_accounts_accepted(instruction).await;
+            }
+        )*
+    };
+}
+
+instruction_builder_tests! {
+    variants {
+        init_lending_market_builder: InitLendingMarket => |w| init_lending_market(
+            w.id(),
+            w.owner(),
+            [0u8; 32],
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        ),
+        set_lending_market_owner_and_config_builder: SetLendingMarketOwnerAndConfig => |w| {
+            set_lending_market_owner_and_config(
+                w.id(),
+                w.market(),
+                w.owner(),
+                w.owner(),
+                w.rate_limiter_config(),
+                None,
+                w.owner(),
+            )
+        },
+        init_reserve_builder: InitReserve => |w| init_reserve(
+            w.id(),
+            1_000,
+            test_reserve_config(),
+            w.user_usdc(),
+            w.user_cusdc(),
+            Pubkey::new_unique(),
+            usdc_mint::id(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            w.usdc_reserve.account.liquidity.pyth_oracle_pubkey,
+            w.usdc_reserve.account.liquidity.switchboard_oracle_pubkey,
+            w.market(),
+            w.owner(),
+            w.owner(),
+        ),
+        refresh_reserve_builder: RefreshReserve => |w| refresh_reserve(
+            w.id(),
+            w.wsol_reserve.pubkey,
+            w.wsol_reserve.account.liquidity.pyth_oracle_pubkey,
+            w.wsol_reserve.account.liquidity.switchboard_oracle_pubkey,
+        ),
+        deposit_reserve_liquidity_builder: DepositReserveLiquidity => |w| {
+            deposit_reserve_liquidity(
+                w.id(),
+                1,
+                w.user_usdc(),
+                w.user_cusdc(),
+                w.usdc_reserve.pubkey,
+                w.usdc_reserve.account.liquidity.supply_pubkey,
+                w.usdc_reserve.account.collateral.mint_pubkey,
+                w.market(),
+                w.user(),
+            )
+        },
+        redeem_reserve_collateral_builder: RedeemReserveCollateral => |w| {
+            redeem_reserve_collateral(
+                w.id(),
+                1,
+                w.user_cusdc(),
+                w.user_usdc(),
+                w.usdc_reserve.pubkey,
+                w.usdc_reserve.account.collateral.mint_pubkey,
+                w.usdc_reserve.account.liquidity.supply_pubkey,
+                w.market(),
+                w.user(),
+            )
+        },
+        init_obligation_builder: InitObligation => |w| init_obligation(
+            w.id(),
+            Pubkey::new_unique(),
+            w.market(),
+            w.user(),
+        ),
+        refresh_obligation_builder: RefreshObligation => |w| refresh_obligation(
+            w.id(),
+            w.obligation.pubkey,
+            w.reserve_pubkeys(),
+        ),
+        deposit_obligation_collateral_builder: DepositObligationCollateral => |w| {
+            deposit_obligation_collateral(
+                w.id(),
+                1,
+                w.user_cusdc(),
+                w.usdc_reserve.account.collateral.supply_pubkey,
+                w.usdc_reserve.pubkey,
+                w.obligation.pubkey,
+                w.market(),
+                w.user(),
+                w.user(),
+            )
+        },
+        withdraw_obligation_collateral_builder: WithdrawObligationCollateral => |w| {
+            withdraw_obligation_collateral(
+                w.id(),
+                1,
+                w.usdc_reserve.account.collateral.supply_pubkey,
+                w.user_cusdc(),
+                w.usdc_reserve.pubkey,
+                w.obligation.pubkey,
+                w.market(),
+                w.user(),
+            )
+        },
+        borrow_obligation_liquidity_builder: BorrowObligationLiquidity => |w| {
+            borrow_obligation_liquidity(
+                w.id(),
+                1,
+                w.wsol_reserve.account.liquidity.supply_pubkey,
+                w.user_wsol(),
+                w.wsol_reserve.pubkey,
+                w.wsol_reserve.account.config.fee_receiver,
+                w.obligation.pubkey,
+                w.market(),
+                w.user(),
+                None,
+            )
+        },
+        repay_obligation_liquidity_builder: RepayObligationLiquidity => |w| {
+            repay_obligation_liquidity(
+                w.id(),
+                1,
+                w.user_wsol(),
+                w.wsol_reserve.account.liquidity.supply_pubkey,
+                w.wsol_reserve.pubkey,
+                w.obligation.pubkey,
+                w.market(),
+                w.user(),
+            )
+        },
+        liquidate_obligation_builder: LiquidateObligation => |w| liquidate_obligation(
+            w.id(),
+            1,
+            w.user_wsol(),
+            w.user_cusdc(),
+            w.wsol_reserve.pubkey,
+            w.wsol_reserve.account.liquidity.supply_pubkey,
+            w.usdc_reserve.pubkey,
+            w.usdc_reserve.account.collateral.supply_pubkey,
+            w.obligation.pubkey,
+            w.market(),
+            w.user(),
+        ),
+        // deprecated before it got a builder
+        flash_loan_builder: FlashLoan => |w| Instruction {
+            program_id: w.id(),
+            accounts: vec![],
+            data: LendingInstruction::FlashLoan { amount: 1 }.pack(),
+        },
+        deposit_reserve_liquidity_and_obligation_collateral_builder:
+            DepositReserveLiquidityAndObligationCollateral => |w| {
+            deposit_reserve_liquidity_and_obligation_collateral(
+                w.id(),
+                1,
+                w.user_usdc(),
+                w.user_cusdc(),
+                w.usdc_reserve.pubkey,
+                w.usdc_reserve.account.liquidity.supply_pubkey,
+                w.usdc_reserve.account.collateral.mint_pubkey,
+                w.market(),
+                w.usdc_reserve.account.collateral.supply_pubkey,
+                w.obligation.pubkey,
+                w.user(),
+                w.usdc_reserve.account.liquidity.pyth_oracle_pubkey,
+                w.usdc_reserve.account.liquidity.switchboard_oracle_pubkey,
+                w.user(),
+            )
+        },
+        withdraw_obligation_collateral_and_redeem_reserve_collateral_builder:
+            WithdrawObligationCollateralAndRedeemReserveCollateral => |w| {
+            withdraw_obligation_collateral_and_redeem_reserve_collateral(
+                w.id(),
+                1,
+                w.usdc_reserve.account.collateral.supply_pubkey,
+                w.user_cusdc(),
+                w.usdc_reserve.pubkey,
+                w.obligation.pubkey,
+                w.market(),
+                w.user_usdc(),
+                w.usdc_reserve.account.collateral.mint_pubkey,
+                w.usdc_reserve.account.liquidity.supply_pubkey,
+                w.user(),
+                w.user(),
+            )
+        },
+        update_reserve_config_builder: UpdateReserveConfig => |w| update_reserve_config(
+            w.id(),
+            w.wsol_reserve.account.config,
+            w.rate_limiter_config(),
+            w.wsol_reserve.pubkey,
+            w.market(),
+            w.owner(),
+            Pubkey::new_unique(),
+            w.wsol_reserve.account.liquidity.pyth_oracle_pubkey,
+            w.wsol_reserve.account.liquidity.switchboard_oracle_pubkey,
+        ),
+        liquidate_obligation_and_redeem_reserve_collateral_builder:
+            LiquidateObligationAndRedeemReserveCollateral => |w| {
+            liquidate_obligation_and_redeem_reserve_collateral(
+                w.id(),
+                1,
+                w.user_wsol(),
+                w.user_cusdc(),
+                w.user_usdc(),
+                w.wsol_reserve.pubkey,
+                w.wsol_reserve.account.liquidity.supply_pubkey,
+                w.usdc_reserve.pubkey,
+                w.usdc_reserve.account.collateral.mint_pubkey,
+                w.usdc_reserve.account.collateral.supply_pubkey,
+                w.usdc_reserve.account.liquidity.supply_pubkey,
+                w.usdc_reserve.account.config.fee_receiver,
+                w.obligation.pubkey,
+                w.market(),
+                w.user(),
+            )
+        },
+        redeem_fees_builder: RedeemFees => |w| redeem_fees(
+            w.id(),
+            w.wsol_reserve.pubkey,
+            w.wsol_reserve.account.config.fee_receiver,
+            w.wsol_reserve.account.liquidity.supply_pubkey,
+            w.market(),
+        ),
+        flash_borrow_reserve_liquidity_builder: FlashBorrowReserveLiquidity => |w| {
+            flash_borrow_reserve_liquidity(
+                w.id(),
+                1,
+                w.wsol_reserve.account.liquidity.supply_pubkey,
+                w.user_wsol(),
+                w.wsol_reserve.pubkey,
+                w.market(),
+            )
+        },
+        flash_repay_reserve_liquidity_builder: FlashRepayReserveLiquidity => |w| {
+            flash_repay_reserve_liquidity(
+                w.id(),
+                1,
+                0,
+                w.user_wsol(),
+                w.wsol_reserve.account.liquidity.supply_pubkey,
+                w.wsol_reserve.account.config.fee_receiver,
+                w.user_wsol(),
+                w.wsol_reserve.pubkey,
+                w.market(),
+                w.user(),
+            )
+        },
+        forgive_debt_builder: ForgiveDebt => |w| forgive_debt(
+            w.id(),
+            1,
+            w.wsol_reserve.pubkey,
+            w.obligation.pubkey,
+            w.market(),
+            w.owner(),
+        ),
+        update_market_metadata_builder: UpdateMarketMetadata => |w| update_market_metadata(
+            w.id(),
+            LendingMarketMetadata {
+                bump_seed: 0,
+                market_name: [1u8; MARKET_NAME_SIZE],
+                market_description: [2u8; MARKET_DESCRIPTION_SIZE],
+                market_image_url: [3u8; MARKET_IMAGE_URL_SIZE],
+                lookup_tables: [NULL_PUBKEY; 4],
+                padding: [0u8; PADDING_SIZE],
+            },
+            w.market(),
+            w.owner(),
+        ),
+        approve_borrow_allowance_builder: ApproveBorrowAllowance => |w| {
+            approve_borrow_allowance(
+                w.id(),
+                1,
+                w.obligation.pubkey,
+                w.wsol_reserve.pubkey,
+                w.owner(),
+                w.user(),
+            )
+        },
+        revoke_borrow_allowance_builder: RevokeBorrowAllowance => |w| revoke_borrow_allowance(
+            w.id(),
+            w.obligation.pubkey,
+            w.wsol_reserve.pubkey,
+            w.owner(),
+            w.user(),
+        ),
+        liquidate_obligation_for_collateral_builder: LiquidateObligationForCollateral => |w| {
+            liquidate_obligation_for_collateral(
+                w.id(),
+                1,
+                w.user_wsol(),
+                w.user_cusdc(),
+                w.wsol_reserve.pubkey,
+                w.wsol_reserve.account.liquidity.supply_pubkey,
+                w.usdc_reserve.pubkey,
+                w.usdc_reserve.account.collateral.mint_pubkey,
+                w.usdc_reserve.account.collateral.supply_pubkey,
+                w.obligation.pubkey,
+                w.market(),
+                w.user(),
+            )
+        },
+        close_obligation_builder: CloseObligation => |w| close_obligation(
+            w.id(),
+            w.obligation.pubkey,
+            w.user(),
+            w.user(),
+        ),
+        wind_down_reserve_builder: WindDownReserve => |w| wind_down_reserve(
+            w.id(),
+            1,
+            w.wsol_reserve.pubkey,
+            w.market(),
+            w.owner(),
+        ),
+        close_reserve_builder: CloseReserve => |w| close_reserve(
+            w.id(),
+            w.wsol_reserve.pubkey,
+            w.market(),
+            w.owner(),
+        ),
+        set_liquidation_circuit_breaker_builder: SetLiquidationCircuitBreaker => |w| {
+            set_liquidation_circuit_breaker(
+                w.id(),
+                w.rate_limiter_config(),
+                10,
+                w.market(),
+                w.owner(),
+            )
+        },
+        set_obligation_limits_builder: SetObligationLimits => |w| set_obligation_limits(
+            w.id(),
+            6,
+            5,
+            w.market(),
+            w.owner(),
+        ),
+        set_config_timelock_builder: SetConfigTimelock => |w| set_config_timelock(
+            w.id(),
+            10,
+            w.market(),
+            w.owner(),
+        ),
+        activate_config_builder: ActivateConfig => |w| activate_config(
+            w.id(),
+            w.wsol_reserve.pubkey,
+            w.market(),
+            w.owner(),
+        ),
+        cancel_config_builder: CancelConfig => |w| cancel_config(
+            w.id(),
+            w.wsol_reserve.pubkey,
+            w.market(),
+            w.owner(),
+            w.owner(),
+        ),
+        set_collateral_liquidation_priority_builder: SetCollateralLiquidationPriority => |w| {
+            set_collateral_liquidation_priority(
+                w.id(),
+                1,
+                w.obligation.pubkey,
+                w.usdc_reserve.pubkey,
+                w.user(),
+            )
+        },
+        set_borrow_fee_rebate_builder: SetBorrowFeeRebate => |w| set_borrow_fee_rebate(
+            w.id(),
+            w.user(),
+            100,
+            w.market(),
+            w.owner(),
+        ),
+        set_fee_redemption_hook_builder: SetFeeRedemptionHook => |w| set_fee_redemption_hook(
+            w.id(),
+            Pubkey::new_unique(),
+            [0u8; 32],
+            w.wsol_reserve.pubkey,
+            w.market(),
+            w.owner(),
+        ),
+        borrow_obligation_liquidity_fixed_rate_builder: BorrowObligationLiquidityFixedRate => |w| {
+            borrow_obligation_liquidity_fixed_rate(
+                w.id(),
+                1,
+                w.wsol_reserve.account.liquidity.supply_pubkey,
+                w.user_wsol(),
+                w.wsol_reserve.pubkey,
+                w.wsol_reserve.account.config.fee_receiver,
+                w.obligation.pubkey,
+                w.market(),
+                w.user(),
+                None,
+            )
+        },
+        init_liquidation_stats_builder: InitLiquidationStats => |w| init_liquidation_stats(
+            w.id(),
+            w.market(),
+            w.owner(),
+        ),
+        deposit_reserve_liquidity_with_mode_builder: DepositReserveLiquidityWithMode => |w| {
+            deposit_reserve_liquidity_with_mode(
+                w.id(),
+                1,
+                Some(DepositMode::Collateralize),
+                w.user_usdc(),
+                w.user_cusdc(),
+                w.usdc_reserve.pubkey,
+                w.usdc_reserve.account.liquidity.supply_pubkey,
+                w.usdc_reserve.account.collateral.mint_pubkey,
+                w.market(),
+                w.usdc_reserve.account.collateral.supply_pubkey,
+                w.obligation.pubkey,
+                w.user(),
+                w.usdc_reserve.account.liquidity.pyth_oracle_pubkey,
+                w.usdc_reserve.account.liquidity.switchboard_oracle_pubkey,
+                w.user(),
+            )
+        },
+        set_obligation_deposit_mode_builder: SetObligationDepositMode => |w| {
+            set_obligation_deposit_mode(
+                w.id(),
+                DepositMode::Collateralize,
+                w.obligation.pubkey,
+                w.user(),
+            )
+        },
+        view_obligation_health_builder: ViewObligationHealth => |w| view_obligation_health(
+            w.id(),
+            w.obligation.pubkey,
+            w.reserve_pubkeys(),
+        ),
+        split_obligation_builder: SplitObligation => |w| split_obligation(
+            w.id(),
+            vec![(w.usdc_reserve.pubkey, 1)],
+            vec![],
+            w.obligation.pubkey,
+            Pubkey::new_unique(),
+            w.market(),
+            w.user(),
+            w.reserve_pubkeys(),
+        ),
+        merge_obligations_builder: MergeObligations => |w| merge_obligations(
+            w.id(),
+            w.obligation.pubkey,
+            w.market(),
+            w.user(),
+            vec![Pubkey::new_unique()],
+        ),
+        set_dust_sweep_threshold_builder: SetDustSweepThreshold => |w| {
+            set_dust_sweep_threshold(w.id(), 1, w.market(), w.owner())
+        },
+        sweep_dust_obligation_builder: SweepDustObligation => |w| sweep_dust_obligation(
+            w.id(),
+            w.obligation.pubkey,
+            w.user(),
+            w.market(),
+            w.owner(),
+            vec![(
+                w.usdc_reserve.pubkey,
+                w.usdc_reserve.account.collateral.mint_pubkey,
+                w.usdc_reserve.account.collateral.supply_pubkey,
+            )],
+        ),
+        set_obligation_borrow_cap_builder: SetObligationBorrowCap => |w| {
+            set_obligation_borrow_cap(w.id(), 1, w.obligation.pubkey, w.user())
+        },
+        set_market_policy_builder: SetMarketPolicy => |w| set_market_policy(
+            w.id(),
+            Pubkey::new_unique(),
+            None,
+            w.market(),
+            w.owner(),
+        ),
+        set_reserve_accrual_epoch_builder: SetReserveAccrualEpoch => |w| {
+            set_reserve_accrual_epoch(w.id(), 100, w.wsol_reserve.pubkey, w.market(), w.owner())
+        },
+        set_deleverage_config_builder: SetDeleverageConfig => |w| {
+            set_deleverage_config(w.id(), 1, w.market(), w.owner())
+        },
+        init_deleverage_credit_builder: InitDeleverageCredit => |w| {
+            init_deleverage_credit(w.id(), w.obligation.pubkey, w.user())
+        },
+        set_obligation_owner_builder: SetObligationOwner => |w| set_obligation_owner(
+            w.id(),
+            w.obligation.pubkey,
+            w.market(),
+            w.user(),
+            w.owner(),
+        ),
+        set_flash_loan_config_builder: SetFlashLoanConfig => |w| set_flash_loan_config(
+            w.id(),
+            true,
+            1,
+            w.wsol_reserve.pubkey,
+            w.market(),
+            w.owner(),
+        ),
+        liquidate_obligation_and_redeem_reserve_collateral_by_value_builder:
+            LiquidateObligationAndRedeemReserveCollateralByValue => |w| {
+            liquidate_obligation_and_redeem_reserve_collateral_by_value(
+                w.id(),
+                Decimal::one(),
+                w.user_wsol(),
+                w.user_cusdc(),
+                w.user_usdc(),
+                w.wsol_reserve.pubkey,
+                w.wsol_reserve.account.liquidity.supply_pubkey,
+                w.usdc_reserve.pubkey,
+                w.usdc_reserve.account.collateral.mint_pubkey,
+                w.usdc_reserve.account.collateral.supply_pubkey,
+                w.usdc_reserve.account.liquidity.supply_pubkey,
+                w.usdc_reserve.account.config.fee_receiver,
+                w.obligation.pubkey,
+                w.market(),
+                w.user(),
+            )
+        },
+        set_obligation_health_buffer_builder: SetObligationHealthBuffer => |w| {
+            set_obligation_health_buffer(w.id(), 200, w.market(), w.owner())
+        },
+        set_ignore_health_buffer_builder: SetIgnoreHealthBuffer => |w| {
+            set_ignore_health_buffer(w.id(), true, w.obligation.pubkey, w.user())
+        },
+        set_flash_loan_fee_receiver_builder: SetFlashLoanFeeReceiver => |w| {
+            set_flash_loan_fee_receiver(
+                w.id(),
+                w.wsol_reserve.account.config.fee_receiver,
+                w.wsol_reserve.pubkey,
+                w.market(),
+                w.owner(),
+            )
+        },
+        set_borrow_grace_config_builder: SetBorrowGraceConfig => |w| set_borrow_grace_config(
+            w.id(),
+            1,
+            w.wsol_reserve.pubkey,
+            w.market(),
+            w.owner(),
+        ),
+        set_cross_market_source_builder: SetCrossMarketSource => |w| set_cross_market_source(
+            w.id(),
+            w.wsol_reserve.pubkey,
+            true,
+            w.market(),
+            w.owner(),
+        ),
+    }
+    other_builders {
+        set_lending_market_owner_and_config_with_slots_per_year_builder: SetLendingMarketOwnerAndConfig => |w| {
+            set_lending_market_owner_and_config_with_slots_per_year(
+                w.id(),
+                w.market(),
+                w.owner(),
+                w.owner(),
+                w.rate_limiter_config(),
+                None,
+                w.owner(),
+                SLOTS_PER_YEAR,
+            )
+        },
+        refresh_reserve_with_lending_market_builder: RefreshReserve => |w| {
+            refresh_reserve_with_lending_market(
+                w.id(),
+                w.wsol_reserve.pubkey,
+                w.wsol_reserve.account.liquidity.pyth_oracle_pubkey,
+                w.wsol_reserve.account.liquidity.switchboard_oracle_pubkey,
+                w.wsol_reserve.account.lending_market,
+            )
+        },
+        refresh_reserve_with_accrual_history_builder: RefreshReserve => |w| {
+            refresh_reserve_with_accrual_history(
+                w.id(),
+                w.wsol_reserve.pubkey,
+                w.wsol_reserve.account.liquidity.pyth_oracle_pubkey,
+                w.wsol_reserve.account.liquidity.switchboard_oracle_pubkey,
+            )
+        },
+        delegated_borrow_obligation_liquidity_builder: BorrowObligationLiquidity => |w| {
+            delegated_borrow_obligation_liquidity(
+                w.id(),
+                1,
+                w.wsol_reserve.account.liquidity.supply_pubkey,
+                w.user_wsol(),
+                w.wsol_reserve.pubkey,
+                w.wsol_reserve.account.config.fee_receiver,
+                w.obligation.pubkey,
+                w.market(),
+                w.owner(),
+                None,
+            )
+        },
+        rebated_borrow_obligation_liquidity_builder: BorrowObligationLiquidity => |w| {
+            rebated_borrow_obligation_liquidity(
+                w.id(),
+                1,
+                w.wsol_reserve.account.liquidity.supply_pubkey,
+                w.user_wsol(),
+                w.wsol_reserve.pubkey,
+                w.wsol_reserve.account.config.fee_receiver,
+                w.obligation.pubkey,
+                w.market(),
+                w.user(),
+                None,
+            )
+        },
+        redeem_fees_with_hook_builder: RedeemFees => |w| redeem_fees_with_hook(
+            w.id(),
+            w.wsol_reserve.pubkey,
+            w.wsol_reserve.account.config.fee_receiver,
+            w.wsol_reserve.account.liquidity.supply_pubkey,
+            w.market(),
+            Pubkey::new_unique(),
+            vec![],
+        ),
+        redeem_reserve_collateral_with_fee_receiver_builder: RedeemReserveCollateral => |w| {
+            redeem_reserve_collateral_with_fee_receiver(
+                w.id(),
+                1,
+                w.user_cusdc(),
+                w.user_usdc(),
+                w.usdc_reserve.pubkey,
+                w.usdc_reserve.account.collateral.mint_pubkey,
+                w.usdc_reserve.account.liquidity.supply_pubkey,
+                w.market(),
+                w.user(),
+                w.usdc_reserve.account.config.fee_receiver,
+            )
+        },
+        withdraw_obligation_collateral_and_redeem_reserve_collateral_with_fee_receiver_builder:
+            WithdrawObligationCollateralAndRedeemReserveCollateral => |w| {
+            withdraw_obligation_collateral_and_redeem_reserve_collateral_with_fee_receiver(
+                w.id(),
+                1,
+                w.usdc_reserve.account.collateral.supply_pubkey,
+                w.user_cusdc(),
+                w.usdc_reserve.pubkey,
+                w.obligation.pubkey,
+                w.market(),
+                w.user_usdc(),
+                w.usdc_reserve.account.collateral.mint_pubkey,
+                w.usdc_reserve.account.liquidity.supply_pubkey,
+                w.user(),
+                w.user(),
+                w.usdc_reserve.account.config.fee_receiver,
+            )
+        },
+    }
+}
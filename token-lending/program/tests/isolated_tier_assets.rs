@@ -7,8 +7,6 @@ use crate::solend_program_test::PriceArgs;
 use crate::solend_program_test::ReserveArgs;
 
 use solana_program::native_token::LAMPORTS_PER_SOL;
-use solana_sdk::instruction::InstructionError;
-use solana_sdk::transaction::TransactionError;
 use solend_program::error::LendingError;
 use solend_sdk::math::Decimal;
 
@@ -117,6 +115,7 @@ async fn test_refresh_obligation() {
                 cumulative_borrow_rate_wads: Decimal::one(),
                 borrowed_amount_wads: Decimal::from(LAMPORTS_PER_SOL),
                 market_value: Decimal::from(10u64),
+                ..ObligationLiquidity::default()
             }],
             borrowed_value: Decimal::from(10u64),
             borrowed_value_upper_bound: Decimal::from(10u64),
@@ -275,16 +274,9 @@ async fn borrow_isolated_asset_invalid() {
 
     let err = lending_market
         .borrow_obligation_liquidity(&mut test, bonk_reserve, &obligations[0], &users[0], None, 1)
-        .await
-        .unwrap_err()
-        .unwrap();
-    assert_eq!(
-        err,
-        TransactionError::InstructionError(
-            1,
-            InstructionError::Custom(LendingError::IsolatedTierAssetViolation as u32)
-        )
-    );
+        .await;
+
+    expect_lending_error(err, LendingError::IsolatedTierAssetViolation);
 }
 
 #[tokio::test]
@@ -368,17 +360,9 @@ async fn borrow_regular_asset_invalid() {
             None,
             LAMPORTS_PER_SOL,
         )
-        .await
-        .unwrap_err()
-        .unwrap();
+        .await;
 
-    assert_eq!(
-        err,
-        TransactionError::InstructionError(
-            1,
-            InstructionError::Custom(LendingError::IsolatedTierAssetViolation as u32)
-        )
-    );
+    expect_lending_error(err, LendingError::IsolatedTierAssetViolation);
 }
 
 #[tokio::test]
@@ -472,15 +456,7 @@ async fn invalid_borrow_due_to_reserve_config_change() {
     // obligation has two borrows
     let err = lending_market
         .borrow_obligation_liquidity(&mut test, bonk_reserve, &obligations[0], &users[0], None, 1)
-        .await
-        .unwrap_err()
-        .unwrap();
+        .await;
 
-    assert_eq!(
-        err,
-        TransactionError::InstructionError(
-            1,
-            InstructionError::Custom(LendingError::IsolatedTierAssetViolation as u32)
-        )
-    );
+    expect_lending_error(err, LendingError::IsolatedTierAssetViolation);
 }
@@ -5,11 +5,9 @@ mod helpers;
 use crate::solend_program_test::scenario_1;
 
 use helpers::*;
-use solana_program::instruction::InstructionError;
 
 use solana_program_test::*;
 
-use solana_sdk::transaction::TransactionError;
 use solend_program::error::LendingError;
 
 #[tokio::test]
@@ -26,15 +24,7 @@ async fn test_fail_deprecated() {
             &user,
             1,
         )
-        .await
-        .unwrap_err()
-        .unwrap();
-
-    assert_eq!(
-        res,
-        TransactionError::InstructionError(
-            3,
-            InstructionError::Custom(LendingError::DeprecatedInstruction as u32)
-        )
-    );
+        .await;
+
+    expect_lending_error(res, LendingError::DeprecatedInstruction);
 }
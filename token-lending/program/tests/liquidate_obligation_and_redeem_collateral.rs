@@ -1,14 +1,22 @@
 #![cfg(feature = "test-bpf")]
 
 use crate::solend_program_test::custom_scenario;
+use crate::solend_program_test::find_reserve;
+use crate::solend_program_test::Info;
 use crate::solend_program_test::MintSupplyChange;
 use crate::solend_program_test::ObligationArgs;
 use crate::solend_program_test::ReserveArgs;
-use solana_sdk::instruction::InstructionError;
+use crate::solend_program_test::SolendProgramTest;
+use solana_program::program_pack::Pack;
+use solana_program::sysvar::rent::Rent;
+use solana_sdk::compute_budget::ComputeBudgetInstruction;
+use solana_sdk::instruction::Instruction;
 use solana_sdk::native_token::LAMPORTS_PER_SOL;
 use solana_sdk::signer::Signer;
-use solana_sdk::transaction::TransactionError;
 use solend_program::error::LendingError;
+use solend_program::instruction::append_auto_close_accounts;
+use solend_program::instruction::find_borrow_allowances_address;
+use solend_program::instruction::liquidate_obligation_and_redeem_reserve_collateral;
 use solend_program::math::TrySub;
 use solend_program::state::LastUpdate;
 use solend_program::state::ObligationCollateral;
@@ -214,7 +222,8 @@ async fn test_success_new() {
             deposits: [ObligationCollateral {
                 deposit_reserve: usdc_reserve.pubkey,
                 deposited_amount: (100_000 - expected_usdc_withdrawn) * FRACTIONAL_TO_USDC,
-                market_value: Decimal::from(100_000u64) // old value
+                market_value: Decimal::from(100_000u64), // old value
+                liquidation_priority: 0,
             }]
             .to_vec(),
             borrows: [ObligationLiquidity {
@@ -224,6 +233,7 @@ async fn test_success_new() {
                     .try_sub(Decimal::from(expected_borrow_repaid * LAMPORTS_TO_SOL))
                     .unwrap(),
                 market_value: Decimal::from(55_000u64),
+                ..ObligationLiquidity::default()
             }]
             .to_vec(),
             deposited_value: Decimal::from(100_000u64),
@@ -236,6 +246,134 @@ async fn test_success_new() {
     );
 }
 
+#[tokio::test]
+async fn test_success_liquidation_fee_receiver_override() {
+    let (
+        mut test,
+        lending_market,
+        usdc_reserve,
+        wsol_reserve,
+        _user,
+        obligation,
+        lending_market_owner,
+    ) = scenario_1(
+        &ReserveConfig {
+            optimal_borrow_rate: 0,
+            max_borrow_rate: 0,
+            fees: ReserveFees::default(),
+            ..test_reserve_config()
+        },
+        &test_reserve_config(),
+    )
+    .await;
+
+    let insurance_vault = User::new_with_balances(&mut test, &[(&usdc_mint::id(), 0)]).await;
+    lending_market
+        .update_reserve_config(
+            &mut test,
+            &lending_market_owner,
+            &usdc_reserve,
+            ReserveConfig {
+                liquidation_fee_receiver: insurance_vault.get_account(&usdc_mint::id()),
+                ..usdc_reserve.account.config
+            },
+            usdc_reserve.account.rate_limiter.config,
+            None,
+        )
+        .await
+        .unwrap();
+    let usdc_reserve = test.load_account::<Reserve>(usdc_reserve.pubkey).await;
+
+    let liquidator = User::new_with_balances(
+        &mut test,
+        &[
+            (&wsol_mint::id(), 100 * LAMPORTS_TO_SOL),
+            (&usdc_reserve.account.collateral.mint_pubkey, 0),
+            (&usdc_mint::id(), 0),
+        ],
+    )
+    .await;
+
+    let balance_checker =
+        BalanceChecker::start(&mut test, &[&usdc_reserve, &insurance_vault]).await;
+
+    test.set_price(
+        &wsol_mint::id(),
+        &PriceArgs {
+            price: 5500,
+            conf: 0,
+            expo: 0,
+            ema_price: 5500,
+            ema_conf: 0,
+        },
+    )
+    .await;
+
+    lending_market
+        .liquidate_obligation_and_redeem_reserve_collateral(
+            &mut test,
+            &wsol_reserve,
+            &usdc_reserve,
+            &obligation,
+            &liquidator,
+            u64::MAX,
+        )
+        .await
+        .unwrap();
+
+    // same liquidation as test_success_new, but the 110 USDC protocol fee goes to the vault
+    // instead of the fee receiver
+    let (balance_changes, _mint_supply_changes) =
+        balance_checker.find_balance_changes(&mut test).await;
+    let bonus = (usdc_reserve.account.config.liquidation_bonus
+        + usdc_reserve.account.config.protocol_liquidation_fee / 10) as u64;
+    let expected_usdc_withdrawn =
+        10 * (LIQUIDATION_CLOSE_FACTOR as u64) / 100 * 5500 * (100 + bonus) / 100;
+
+    let expected_balance_changes = HashSet::from([
+        TokenBalanceChange {
+            token_account: insurance_vault.get_account(&usdc_mint::id()).unwrap(),
+            mint: usdc_mint::id(),
+            diff: (110 * FRACTIONAL_TO_USDC) as i128,
+        },
+        TokenBalanceChange {
+            token_account: usdc_reserve.account.collateral.supply_pubkey,
+            mint: usdc_reserve.account.collateral.mint_pubkey,
+            diff: -((expected_usdc_withdrawn * FRACTIONAL_TO_USDC) as i128),
+        },
+        TokenBalanceChange {
+            token_account: usdc_reserve.account.liquidity.supply_pubkey,
+            mint: usdc_mint::id(),
+            diff: -((expected_usdc_withdrawn * FRACTIONAL_TO_USDC) as i128),
+        },
+    ]);
+    assert_eq!(balance_changes, expected_balance_changes);
+
+    // passing the regular fee receiver is rejected
+    test.advance_clock_by_slots(1).await;
+    let res = lending_market
+        .liquidate_obligation_and_redeem_reserve_collateral(
+            &mut test,
+            &wsol_reserve,
+            &Info {
+                account: Reserve {
+                    config: ReserveConfig {
+                        liquidation_fee_receiver: None,
+                        ..usdc_reserve.account.config
+                    },
+                    ..usdc_reserve.account.clone()
+                },
+                ..usdc_reserve
+            },
+            &obligation,
+            &liquidator,
+            u64::MAX,
+        )
+        .await;
+
+    expect_lending_error(res, LendingError::InvalidAccountInput);
+}
+
 #[tokio::test]
 async fn test_whitelisting_liquidator() {
     let (
@@ -310,17 +448,9 @@ async fn test_whitelisting_liquidator() {
             &rando_liquidator,
             u64::MAX,
         )
-        .await
-        .unwrap_err()
-        .unwrap();
+        .await;
 
-    assert_eq!(
-        err,
-        TransactionError::InstructionError(
-            1,
-            InstructionError::Custom(LendingError::NotWhitelistedLiquidator as u32)
-        )
-    );
+    expect_lending_error(err, LendingError::NotWhitelistedLiquidator);
 
     lending_market
         .liquidate_obligation_and_redeem_reserve_collateral(
@@ -631,17 +761,9 @@ async fn test_liquidity_ordering() {
             &liquidator,
             u64::MAX,
         )
-        .await
-        .unwrap_err()
-        .unwrap();
+        .await;
 
-    assert_eq!(
-        err,
-        TransactionError::InstructionError(
-            1,
-            InstructionError::Custom(LendingError::InvalidAccountInput as u32)
-        )
-    );
+    expect_lending_error(err, LendingError::InvalidAccountInput);
 
     // this should pass though
     lending_market
@@ -656,3 +778,203 @@ async fn test_liquidity_ordering() {
         .await
         .unwrap();
 }
+
+/// Obligation whose $1 borrow is below the full liquidation threshold and, with the 5% bonus,
+/// takes all of the collateral, along with a refreshed liquidator able to repay it
+async fn setup_fully_liquidatable_obligation() -> (
+    SolendProgramTest,
+    Info<LendingMarket>,
+    Info<Reserve>,
+    Info<Reserve>,
+    Info<Obligation>,
+    User,
+) {
+    let (mut test, lending_market, reserves, obligations, _users, _lending_market_owner) =
+        custom_scenario(
+            &[
+                ReserveArgs {
+                    mint: usdc_mint::id(),
+                    config: test_reserve_config(),
+                    liquidity_amount: 100_000 * FRACTIONAL_TO_USDC,
+                    price: PriceArgs {
+                        price: 1,
+                        conf: 0,
+                        expo: 0,
+                        ema_price: 1,
+                        ema_conf: 0,
+                    },
+                },
+                ReserveArgs {
+                    mint: wsol_mint::id(),
+                    config: ReserveConfig {
+                        optimal_borrow_rate: 0,
+                        max_borrow_rate: 0,
+                        super_max_borrow_rate: 0,
+                        ..test_reserve_config()
+                    },
+                    liquidity_amount: 100 * LAMPORTS_PER_SOL,
+                    price: PriceArgs {
+                        price: 10,
+                        conf: 0,
+                        expo: 0,
+                        ema_price: 10,
+                        ema_conf: 0,
+                    },
+                },
+            ],
+            // $1.05 of USDC against $0.50 of SOL
+            &[ObligationArgs {
+                deposits: vec![(usdc_mint::id(), 1_050_000)],
+                borrows: vec![(wsol_mint::id(), LAMPORTS_PER_SOL / 20)],
+            }],
+        )
+        .await;
+
+    let usdc_reserve = find_reserve(&reserves, &usdc_mint::id()).unwrap();
+    let wsol_reserve = find_reserve(&reserves, &wsol_mint::id()).unwrap();
+    let obligation = obligations[0].clone();
+
+    test.set_price(
+        &wsol_mint::id(),
+        &PriceArgs {
+            price: 20,
+            conf: 0,
+            expo: 0,
+            ema_price: 20,
+            ema_conf: 0,
+        },
+    )
+    .await;
+
+    let liquidator = User::new_with_balances(
+        &mut test,
+        &[
+            (&wsol_mint::id(), LAMPORTS_PER_SOL),
+            (&usdc_reserve.account.collateral.mint_pubkey, 0),
+            (&usdc_mint::id(), 0),
+        ],
+    )
+    .await;
+
+    let refresh_ixs = lending_market
+        .build_refresh_instructions(&mut test, &obligation, None)
+        .await;
+    test.process_transaction(&refresh_ixs, None).await.unwrap();
+
+    (
+        test,
+        lending_market,
+        usdc_reserve,
+        wsol_reserve,
+        obligation,
+        liquidator,
+    )
+}
+
+fn liquidate_and_close(
+    lending_market: &Info<LendingMarket>,
+    usdc_reserve: &Info<Reserve>,
+    wsol_reserve: &Info<Reserve>,
+    obligation: &Info<Obligation>,
+    liquidator: &User,
+) -> Instruction {
+    let mut instruction = liquidate_obligation_and_redeem_reserve_collateral(
+        solend_program::id(),
+        u64::MAX,
+        liquidator.get_account(&wsol_mint::id()).unwrap(),
+        liquidator
+            .get_account(&usdc_reserve.account.collateral.mint_pubkey)
+            .unwrap(),
+        liquidator.get_account(&usdc_mint::id()).unwrap(),
+        wsol_reserve.pubkey,
+        wsol_reserve.account.liquidity.supply_pubkey,
+        usdc_reserve.pubkey,
+        usdc_reserve.account.collateral.mint_pubkey,
+        usdc_reserve.account.collateral.supply_pubkey,
+        usdc_reserve.account.liquidity.supply_pubkey,
+        usdc_reserve.account.config.liquidation_fee_receiver(),
+        obligation.pubkey,
+        lending_market.pubkey,
+        liquidator.keypair.pubkey(),
+    );
+    append_auto_close_accounts(
+        &mut instruction,
+        obligation.pubkey,
+        obligation.account.owner,
+    );
+    instruction
+}
+
+#[tokio::test]
+async fn test_auto_close_fully_liquidated_obligation() {
+    let (mut test, lending_market, usdc_reserve, wsol_reserve, obligation, liquidator) =
+        setup_fully_liquidatable_obligation().await;
+    let owner = obligation.account.owner;
+
+    let owner_lamports = test.context.banks_client.get_balance(owner).await.unwrap();
+    test.process_transaction(
+        &[
+            ComputeBudgetInstruction::set_compute_unit_limit(100_000),
+            liquidate_and_close(
+                &lending_market,
+                &usdc_reserve,
+                &wsol_reserve,
+                &obligation,
+                &liquidator,
+            ),
+        ],
+        Some(&[&liquidator.keypair]),
+    )
+    .await
+    .unwrap();
+
+    assert!(test
+        .context
+        .banks_client
+        .get_account(obligation.pubkey)
+        .await
+        .unwrap()
+        .is_none());
+    assert_eq!(
+        test.context.banks_client.get_balance(owner).await.unwrap(),
+        owner_lamports + Rent::default().minimum_balance(Obligation::LEN)
+    );
+    assert_eq!(
+        liquidator.get_balance(&mut test, &wsol_mint::id()).await,
+        Some(LAMPORTS_PER_SOL - LAMPORTS_PER_SOL / 20)
+    );
+}
+
+#[tokio::test]
+async fn test_auto_close_requires_derived_accounts() {
+    let (mut test, lending_market, usdc_reserve, wsol_reserve, obligation, liquidator) =
+        setup_fully_liquidatable_obligation().await;
+
+    // the liquidation goes through, but the obligation stays open rather than leave its
+    // borrow allowances behind
+    let (borrow_allowances_pubkey, _) =
+        find_borrow_allowances_address(&solend_program::id(), &obligation.pubkey);
+    let mut instruction = liquidate_and_close(
+        &lending_market,
+        &usdc_reserve,
+        &wsol_reserve,
+        &obligation,
+        &liquidator,
+    );
+    instruction
+        .accounts
+        .retain(|account| account.pubkey != borrow_allowances_pubkey);
+    test.process_transaction(
+        &[
+            ComputeBudgetInstruction::set_compute_unit_limit(100_000),
+            instruction,
+        ],
+        Some(&[&liquidator.keypair]),
+    )
+    .await
+    .unwrap();
+
+    let obligation_post = test.load_account::<Obligation>(obligation.pubkey).await;
+    assert!(obligation_post.account.deposits.is_empty());
+    assert!(obligation_post.account.borrows.is_empty());
+}
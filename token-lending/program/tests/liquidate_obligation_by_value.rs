@@ -0,0 +1,143 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use crate::solend_program_test::*;
+use helpers::*;
+use solana_program_test::*;
+use solana_sdk::native_token::LAMPORTS_PER_SOL;
+use solend_program::{
+    error::LendingError,
+    math::Decimal,
+    state::{LendingMarket, Obligation, Reserve, ReserveConfig, ReserveFees},
+};
+
+struct Setup {
+    test: SolendProgramTest,
+    lending_market: Info<LendingMarket>,
+    usdc_reserve: Info<Reserve>,
+    wsol_reserve: Info<Reserve>,
+    obligation: Info<Obligation>,
+    liquidator: User,
+}
+
+// 100k USDC of collateral against 10 SOL of debt, liquidatable once SOL trades at 5.5k
+async fn setup() -> Setup {
+    let (mut test, lending_market, usdc_reserve, wsol_reserve, _, obligation, _) = scenario_1(
+        &ReserveConfig {
+            optimal_borrow_rate: 0,
+            max_borrow_rate: 0,
+            fees: ReserveFees::default(),
+            ..test_reserve_config()
+        },
+        &test_reserve_config(),
+    )
+    .await;
+
+    let liquidator = User::new_with_balances(
+        &mut test,
+        &[
+            (&wsol_mint::id(), 100 * LAMPORTS_PER_SOL),
+            (&usdc_reserve.account.collateral.mint_pubkey, 0),
+            (&usdc_mint::id(), 0),
+        ],
+    )
+    .await;
+
+    Setup {
+        test,
+        lending_market,
+        usdc_reserve,
+        wsol_reserve,
+        obligation,
+        liquidator,
+    }
+}
+
+async fn set_sol_price(setup: &mut Setup, price: i64) {
+    setup
+        .test
+        .set_price(
+            &wsol_mint::id(),
+            &PriceArgs {
+                price,
+                conf: 0,
+                expo: 0,
+                ema_price: price,
+                ema_conf: 0,
+            },
+        )
+        .await;
+}
+
+async fn liquidate(setup: &mut Setup, max_repay_value: Decimal) -> Result<u64, BanksClientError> {
+    let balance_checker = BalanceChecker::start(&mut setup.test, &[&setup.liquidator]).await;
+    setup
+        .lending_market
+        .liquidate_obligation_and_redeem_reserve_collateral_by_value(
+            &mut setup.test,
+            &setup.wsol_reserve,
+            &setup.usdc_reserve,
+            &setup.obligation,
+            &setup.liquidator,
+            max_repay_value,
+        )
+        .await?;
+
+    let (balance_changes, _) = balance_checker.find_balance_changes(&mut setup.test).await;
+    let repaid = balance_changes
+        .iter()
+        .find(|change| change.mint == wsol_mint::id())
+        .map(|change| -change.diff)
+        .unwrap_or(0);
+    Ok(repaid as u64)
+}
+
+#[tokio::test]
+async fn test_success() {
+    let mut setup = setup().await;
+    set_sol_price(&mut setup, 5500).await;
+
+    let repaid = liquidate(&mut setup, Decimal::from(5500u64)).await.unwrap();
+    assert_eq!(repaid, LAMPORTS_PER_SOL);
+}
+
+#[tokio::test]
+async fn test_price_moves_after_quote() {
+    let mut setup = setup().await;
+
+    // quoted 1 SOL for 5.5k, but SOL went up before the liquidation landed
+    set_sol_price(&mut setup, 6000).await;
+    let repaid = liquidate(&mut setup, Decimal::from(5500u64)).await.unwrap();
+    assert_eq!(repaid, 5500 * LAMPORTS_PER_SOL / 6000);
+    assert!(repaid as u128 * 6000 <= 5500 * LAMPORTS_PER_SOL as u128);
+}
+
+#[tokio::test]
+async fn test_close_factor_still_applies() {
+    let mut setup = setup().await;
+    set_sol_price(&mut setup, 5500).await;
+
+    // 20% of the 55k borrowed value at most
+    let repaid = liquidate(&mut setup, Decimal::from(u64::MAX))
+        .await
+        .unwrap();
+    assert_eq!(repaid, 2 * LAMPORTS_PER_SOL);
+}
+
+#[tokio::test]
+async fn test_fail_zero_value() {
+    let mut setup = setup().await;
+    set_sol_price(&mut setup, 5500).await;
+
+    let res = liquidate(&mut setup, Decimal::zero()).await;
+    expect_lending_error(res.map(|_| ()), LendingError::InvalidAmount);
+}
+
+#[tokio::test]
+async fn test_fail_healthy_obligation() {
+    let mut setup = setup().await;
+
+    let res = liquidate(&mut setup, Decimal::from(5500u64)).await;
+    expect_lending_error(res.map(|_| ()), LendingError::ObligationHealthy);
+}
@@ -0,0 +1,262 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use crate::solend_program_test::scenario_1;
+use crate::solend_program_test::BalanceChecker;
+use crate::solend_program_test::MintSupplyChange;
+use crate::solend_program_test::PriceArgs;
+use crate::solend_program_test::TokenBalanceChange;
+use crate::solend_program_test::User;
+use helpers::*;
+use solana_program_test::*;
+use solana_sdk::signer::Signer;
+use solend_program::error::LendingError;
+use solend_program::math::Decimal;
+use solend_program::state::LendingMarket;
+use solend_program::state::RateLimiterConfig;
+use solend_program::state::Reserve;
+use solend_program::state::ReserveConfig;
+use solend_program::state::ReserveFees;
+use solend_program::state::COLLATERAL_LIQUIDATION_EXTRA_BONUS_BPS;
+use solend_program::state::LIQUIDATION_CLOSE_FACTOR;
+
+use std::collections::HashSet;
+
+#[tokio::test]
+async fn test_success() {
+    let (mut test, lending_market, usdc_reserve, wsol_reserve, user, obligation, _) = scenario_1(
+        &ReserveConfig {
+            optimal_borrow_rate: 0,
+            max_borrow_rate: 0,
+            fees: ReserveFees::default(),
+            ..test_reserve_config()
+        },
+        &test_reserve_config(),
+    )
+    .await;
+
+    let liquidator = User::new_with_balances(
+        &mut test,
+        &[
+            (&wsol_mint::id(), 100 * LAMPORTS_TO_SOL),
+            (&usdc_reserve.account.collateral.mint_pubkey, 0),
+        ],
+    )
+    .await;
+
+    let balance_checker = BalanceChecker::start(
+        &mut test,
+        &[&usdc_reserve, &user, &wsol_reserve, &liquidator],
+    )
+    .await;
+
+    // obligation gets liquidated at a sol price of 5.5k, see
+    // liquidate_obligation_and_redeem_collateral::test_success_new
+    test.set_price(
+        &wsol_mint::id(),
+        &PriceArgs {
+            price: 5500,
+            conf: 0,
+            expo: 0,
+            ema_price: 5500,
+            ema_conf: 0,
+        },
+    )
+    .await;
+
+    lending_market
+        .liquidate_obligation_for_collateral(
+            &mut test,
+            &wsol_reserve,
+            &usdc_reserve,
+            &obligation,
+            &liquidator,
+            u64::MAX,
+        )
+        .await
+        .unwrap();
+
+    let (balance_changes, mint_supply_changes) =
+        balance_checker.find_balance_changes(&mut test).await;
+
+    // 55k * 0.2 => 11k worth of SOL gets repaid
+    // => 11k worth of cUSDC gets withdrawn + bonus.
+    // bonus is 5.5%:
+    // - 1% protocol liquidation fee: 110
+    // - 4% liquidator bonus: 440
+    // - 0.5% extra bonus for not redeeming: 55
+    let bonus_bps = (usdc_reserve.account.config.liquidation_bonus as u64
+        + usdc_reserve.account.config.protocol_liquidation_fee as u64 / 10)
+        * 100
+        + COLLATERAL_LIQUIDATION_EXTRA_BONUS_BPS;
+
+    let expected_borrow_repaid = 10 * (LIQUIDATION_CLOSE_FACTOR as u64) / 100;
+    let expected_cusdc_withdrawn = expected_borrow_repaid * 5500 * (10_000 + bonus_bps) / 10_000;
+
+    let expected_protocol_liquidation_fee = 110;
+
+    let expected_balance_changes = HashSet::from([
+        // liquidator
+        TokenBalanceChange {
+            token_account: liquidator
+                .get_account(&usdc_reserve.account.collateral.mint_pubkey)
+                .unwrap(),
+            mint: usdc_reserve.account.collateral.mint_pubkey,
+            diff: ((expected_cusdc_withdrawn - expected_protocol_liquidation_fee)
+                * FRACTIONAL_TO_USDC) as i128,
+        },
+        TokenBalanceChange {
+            token_account: liquidator.get_account(&wsol_mint::id()).unwrap(),
+            mint: wsol_mint::id(),
+            diff: -((expected_borrow_repaid * LAMPORTS_TO_SOL) as i128),
+        },
+        // usdc reserve
+        TokenBalanceChange {
+            token_account: usdc_reserve.account.collateral.supply_pubkey,
+            mint: usdc_reserve.account.collateral.mint_pubkey,
+            diff: -((expected_cusdc_withdrawn * FRACTIONAL_TO_USDC) as i128),
+        },
+        // wsol reserve
+        TokenBalanceChange {
+            token_account: wsol_reserve.account.liquidity.supply_pubkey,
+            mint: wsol_mint::id(),
+            diff: (expected_borrow_repaid * LAMPORTS_TO_SOL) as i128,
+        },
+    ]);
+    assert_eq!(balance_changes, expected_balance_changes);
+
+    // only the protocol fee is burned, no liquidity leaves the usdc reserve
+    assert_eq!(
+        mint_supply_changes,
+        HashSet::from([MintSupplyChange {
+            mint: usdc_reserve.account.collateral.mint_pubkey,
+            diff: -((expected_protocol_liquidation_fee * FRACTIONAL_TO_USDC) as i128)
+        }])
+    );
+
+    let usdc_reserve_post = test.load_account::<Reserve>(usdc_reserve.pubkey).await;
+    assert_eq!(
+        usdc_reserve_post.account.liquidity.available_amount,
+        usdc_reserve.account.liquidity.available_amount
+    );
+    assert_eq!(
+        usdc_reserve_post
+            .account
+            .liquidity
+            .accumulated_protocol_fees_wads,
+        Decimal::from(expected_protocol_liquidation_fee * FRACTIONAL_TO_USDC)
+    );
+    assert_eq!(
+        usdc_reserve_post.account.collateral.mint_total_supply,
+        usdc_reserve.account.collateral.mint_total_supply
+            - expected_protocol_liquidation_fee * FRACTIONAL_TO_USDC
+    );
+    assert_eq!(
+        usdc_reserve_post
+            .account
+            .collateral_exchange_rate()
+            .unwrap()
+            .collateral_to_liquidity(FRACTIONAL_TO_USDC)
+            .unwrap(),
+        usdc_reserve
+            .account
+            .collateral_exchange_rate()
+            .unwrap()
+            .collateral_to_liquidity(FRACTIONAL_TO_USDC)
+            .unwrap()
+    );
+}
+
+#[tokio::test]
+async fn test_success_outflow_rate_limit_exhausted() {
+    let (
+        mut test,
+        lending_market,
+        usdc_reserve,
+        wsol_reserve,
+        _user,
+        obligation,
+        lending_market_owner,
+    ) = scenario_1(&test_reserve_config(), &test_reserve_config()).await;
+
+    // ie, within 10 slots, the maximum outflow is $10
+    lending_market
+        .set_lending_market_owner_and_config(
+            &mut test,
+            &lending_market_owner,
+            &lending_market_owner.keypair.pubkey(),
+            RateLimiterConfig {
+                window_duration: 10,
+                max_outflow: 10,
+            },
+            None,
+            lending_market.account.risk_authority,
+        )
+        .await
+        .unwrap();
+
+    let liquidator = User::new_with_balances(
+        &mut test,
+        &[
+            (&wsol_mint::id(), 100 * LAMPORTS_TO_SOL),
+            (&usdc_reserve.account.collateral.mint_pubkey, 0),
+            (&usdc_mint::id(), 0),
+        ],
+    )
+    .await;
+
+    test.set_price(
+        &wsol_mint::id(),
+        &PriceArgs {
+            price: 5500,
+            conf: 0,
+            expo: 0,
+            ema_price: 5500,
+            ema_conf: 0,
+        },
+    )
+    .await;
+
+    let lending_market = test
+        .load_account::<LendingMarket>(lending_market.pubkey)
+        .await;
+
+    // the seized collateral is worth far more than the outflow limit, but since it isn't redeemed
+    // the liquidation doesn't count towards it
+    lending_market
+        .liquidate_obligation_for_collateral(
+            &mut test,
+            &wsol_reserve,
+            &usdc_reserve,
+            &obligation,
+            &liquidator,
+            u64::MAX,
+        )
+        .await
+        .unwrap();
+
+    let lending_market_post = test
+        .load_account::<LendingMarket>(lending_market.pubkey)
+        .await;
+    assert_eq!(lending_market_post.account, lending_market.account);
+
+    let usdc_reserve_post = test.load_account::<Reserve>(usdc_reserve.pubkey).await;
+    assert_eq!(
+        usdc_reserve_post.account.rate_limiter,
+        usdc_reserve.account.rate_limiter
+    );
+
+    // redeeming the cTokens afterwards is still subject to the outflow limit
+    test.advance_clock_by_slots(1).await;
+    let res = lending_market
+        .redeem(
+            &mut test,
+            &usdc_reserve_post,
+            &liquidator,
+            100 * FRACTIONAL_TO_USDC,
+        )
+        .await;
+
+    expect_lending_error(res, LendingError::OutflowRateLimitExceeded);
+}
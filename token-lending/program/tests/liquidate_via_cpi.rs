@@ -0,0 +1,183 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use crate::solend_program_test::*;
+use helpers::liquidator_proxy::{find_liquidator_authority_address, liquidate_proxy};
+use helpers::*;
+use solana_program::{instruction::Instruction, pubkey::Pubkey};
+use solana_program_test::*;
+use solana_sdk::compute_budget::ComputeBudgetInstruction;
+use solend_program::{
+    error::LendingError,
+    instruction::liquidate_obligation_and_redeem_reserve_collateral,
+    state::{LendingMarket, Obligation, Reserve, ReserveConfig, ReserveFees},
+};
+use spl_token::state::Account as Token;
+
+struct PdaLiquidator {
+    source_liquidity: Pubkey,
+    destination_collateral: Pubkey,
+    destination_liquidity: Pubkey,
+}
+
+async fn setup_liquidation() -> (
+    SolendProgramTest,
+    Info<LendingMarket>,
+    Info<Reserve>,
+    Info<Reserve>,
+    Info<Obligation>,
+    PdaLiquidator,
+) {
+    let (mut test, lending_market, usdc_reserve, wsol_reserve, _user, obligation, _) = scenario_1(
+        &ReserveConfig {
+            optimal_borrow_rate: 0,
+            max_borrow_rate: 0,
+            fees: ReserveFees::default(),
+            ..test_reserve_config()
+        },
+        &test_reserve_config(),
+    )
+    .await;
+
+    let (liquidator_authority, _) = find_liquidator_authority_address();
+    let liquidator = PdaLiquidator {
+        source_liquidity: test
+            .create_token_account(&liquidator_authority, &wsol_mint::id())
+            .await,
+        destination_collateral: test
+            .create_token_account(
+                &liquidator_authority,
+                &usdc_reserve.account.collateral.mint_pubkey,
+            )
+            .await,
+        destination_liquidity: test
+            .create_token_account(&liquidator_authority, &usdc_mint::id())
+            .await,
+    };
+    test.mint_to(
+        &wsol_mint::id(),
+        &liquidator.source_liquidity,
+        100 * LAMPORTS_TO_SOL,
+    )
+    .await;
+
+    // close LTV is 0.55, we've deposited 100k USDC and borrowed 10 SOL.
+    // obligation gets liquidated if 100k * 0.55 = 10 SOL * sol_price => sol_price = 5.5k
+    test.set_price(
+        &wsol_mint::id(),
+        &PriceArgs {
+            price: 5500,
+            conf: 0,
+            expo: 0,
+            ema_price: 5500,
+            ema_conf: 0,
+        },
+    )
+    .await;
+
+    let refresh_ixs = lending_market
+        .build_refresh_instructions(&mut test, &obligation, None)
+        .await;
+    test.process_transaction(&refresh_ixs, None).await.unwrap();
+
+    (
+        test,
+        lending_market,
+        usdc_reserve,
+        wsol_reserve,
+        obligation,
+        liquidator,
+    )
+}
+
+fn liquidate_instruction(
+    lending_market: &Info<LendingMarket>,
+    repay_reserve: &Info<Reserve>,
+    withdraw_reserve: &Info<Reserve>,
+    obligation: &Info<Obligation>,
+    liquidator: &PdaLiquidator,
+) -> Instruction {
+    liquidate_obligation_and_redeem_reserve_collateral(
+        solend_program::id(),
+        u64::MAX,
+        liquidator.source_liquidity,
+        liquidator.destination_collateral,
+        liquidator.destination_liquidity,
+        repay_reserve.pubkey,
+        repay_reserve.account.liquidity.supply_pubkey,
+        withdraw_reserve.pubkey,
+        withdraw_reserve.account.collateral.mint_pubkey,
+        withdraw_reserve.account.collateral.supply_pubkey,
+        withdraw_reserve.account.liquidity.supply_pubkey,
+        withdraw_reserve.account.config.liquidation_fee_receiver(),
+        obligation.pubkey,
+        lending_market.pubkey,
+        find_liquidator_authority_address().0,
+    )
+}
+
+#[tokio::test]
+async fn test_success_pda_liquidator() {
+    let (mut test, lending_market, usdc_reserve, wsol_reserve, obligation, liquidator) =
+        setup_liquidation().await;
+
+    test.process_transaction(
+        &[
+            ComputeBudgetInstruction::set_compute_unit_limit(120_000),
+            liquidate_proxy(liquidate_instruction(
+                &lending_market,
+                &wsol_reserve,
+                &usdc_reserve,
+                &obligation,
+                &liquidator,
+            )),
+        ],
+        None,
+    )
+    .await
+    .unwrap();
+
+    let source_liquidity = test
+        .load_account::<Token>(liquidator.source_liquidity)
+        .await;
+    let destination_liquidity = test
+        .load_account::<Token>(liquidator.destination_liquidity)
+        .await;
+    assert!(source_liquidity.account.amount < 100 * LAMPORTS_TO_SOL);
+    assert!(destination_liquidity.account.amount > 0);
+
+    let obligation_post = test.load_account::<Obligation>(obligation.pubkey).await;
+    assert!(
+        obligation_post.account.borrows[0].borrowed_amount_wads
+            < obligation.account.borrows[0].borrowed_amount_wads
+    );
+}
+
+#[tokio::test]
+async fn test_fail_pda_liquidator_not_signing() {
+    let (mut test, lending_market, usdc_reserve, wsol_reserve, obligation, liquidator) =
+        setup_liquidation().await;
+
+    // calling the lending program directly, the liquidator authority can't sign
+    let mut instruction = liquidate_instruction(
+        &lending_market,
+        &wsol_reserve,
+        &usdc_reserve,
+        &obligation,
+        &liquidator,
+    );
+    instruction.accounts[13].is_signer = false;
+
+    let res = test
+        .process_transaction(
+            &[
+                ComputeBudgetInstruction::set_compute_unit_limit(100_000),
+                instruction,
+            ],
+            None,
+        )
+        .await;
+
+    expect_lending_error(res, LendingError::InvalidSigner);
+}
@@ -0,0 +1,245 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use crate::solend_program_test::*;
+use helpers::*;
+use solana_program_test::*;
+use solana_sdk::{compute_budget::ComputeBudgetInstruction, signature::Keypair, signature::Signer};
+use solend_program::{
+    error::LendingError,
+    instruction::{
+        find_liquidation_circuit_breaker_address, liquidate_obligation_for_collateral,
+        set_liquidation_circuit_breaker,
+    },
+    state::*,
+};
+
+async fn set_breaker(
+    test: &mut SolendProgramTest,
+    lending_market: &Info<LendingMarket>,
+    signer: &Keypair,
+    config: RateLimiterConfig,
+    pause_duration: u64,
+) -> Result<(), BanksClientError> {
+    test.process_transaction(
+        &[set_liquidation_circuit_breaker(
+            solend_program::id(),
+            config,
+            pause_duration,
+            lending_market.pubkey,
+            signer.pubkey(),
+        )],
+        Some(&[signer]),
+    )
+    .await
+}
+
+#[tokio::test]
+async fn test_breaker_pauses_liquidations() {
+    let (
+        mut test,
+        lending_market,
+        usdc_reserve,
+        wsol_reserve,
+        _user,
+        obligation,
+        lending_market_owner,
+    ) = scenario_1(&test_reserve_config(), &test_reserve_config()).await;
+
+    // $1k of liquidations every 100 slots
+    set_breaker(
+        &mut test,
+        &lending_market,
+        &lending_market_owner.keypair,
+        RateLimiterConfig {
+            window_duration: 100,
+            max_outflow: 1_000,
+        },
+        50,
+    )
+    .await
+    .unwrap();
+
+    let lending_market_post = test
+        .load_account::<LendingMarket>(lending_market.pubkey)
+        .await;
+    assert!(lending_market_post.account.has_liquidation_circuit_breaker);
+
+    let liquidator = User::new_with_balances(
+        &mut test,
+        &[
+            (&wsol_mint::id(), 100 * LAMPORTS_TO_SOL),
+            (&usdc_reserve.account.collateral.mint_pubkey, 0),
+            (&usdc_mint::id(), 0),
+        ],
+    )
+    .await;
+
+    test.set_price(
+        &wsol_mint::id(),
+        &PriceArgs {
+            price: 5500,
+            conf: 0,
+            expo: 0,
+            ema_price: 5500,
+            ema_conf: 0,
+        },
+    )
+    .await;
+
+    // the first liquidation is worth $11k, so it goes through and trips the breaker
+    lending_market
+        .liquidate_obligation_and_redeem_reserve_collateral(
+            &mut test,
+            &wsol_reserve,
+            &usdc_reserve,
+            &obligation,
+            &liquidator,
+            u64::MAX,
+        )
+        .await
+        .unwrap();
+
+    let (breaker_pubkey, _) =
+        find_liquidation_circuit_breaker_address(&solend_program::id(), &lending_market.pubkey);
+    let breaker = test
+        .load_account::<LiquidationCircuitBreaker>(breaker_pubkey)
+        .await;
+    assert_eq!(breaker.account.lending_market, lending_market.pubkey);
+    assert!(breaker.account.paused_until_slot > 0);
+
+    test.advance_clock_by_slots(1).await;
+    let res = lending_market
+        .liquidate_obligation_for_collateral(
+            &mut test,
+            &wsol_reserve,
+            &usdc_reserve,
+            &obligation,
+            &liquidator,
+            u64::MAX,
+        )
+        .await;
+
+    expect_lending_error(res, LendingError::LiquidationCircuitBreakerTripped);
+
+    // liquidations resume once the pause is over
+    test.advance_clock_by_slots(50).await;
+    lending_market
+        .liquidate_obligation_and_redeem_reserve_collateral(
+            &mut test,
+            &wsol_reserve,
+            &usdc_reserve,
+            &obligation,
+            &liquidator,
+            u64::MAX,
+        )
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_fail_missing_breaker_account() {
+    let (
+        mut test,
+        lending_market,
+        usdc_reserve,
+        wsol_reserve,
+        _user,
+        obligation,
+        lending_market_owner,
+    ) = scenario_1(&test_reserve_config(), &test_reserve_config()).await;
+
+    set_breaker(
+        &mut test,
+        &lending_market,
+        &lending_market_owner.keypair,
+        RateLimiterConfig {
+            window_duration: 100,
+            max_outflow: 1_000,
+        },
+        50,
+    )
+    .await
+    .unwrap();
+
+    let liquidator = User::new_with_balances(
+        &mut test,
+        &[
+            (&wsol_mint::id(), 100 * LAMPORTS_TO_SOL),
+            (&usdc_reserve.account.collateral.mint_pubkey, 0),
+        ],
+    )
+    .await;
+
+    test.set_price(
+        &wsol_mint::id(),
+        &PriceArgs {
+            price: 5500,
+            conf: 0,
+            expo: 0,
+            ema_price: 5500,
+            ema_conf: 0,
+        },
+    )
+    .await;
+
+    let refresh_ixs = lending_market
+        .build_refresh_instructions(&mut test, &obligation, None)
+        .await;
+    test.process_transaction(&refresh_ixs, None).await.unwrap();
+
+    let (breaker_pubkey, _) =
+        find_liquidation_circuit_breaker_address(&solend_program::id(), &lending_market.pubkey);
+    let mut liquidate_ix = liquidate_obligation_for_collateral(
+        solend_program::id(),
+        u64::MAX,
+        liquidator.get_account(&wsol_mint::id()).unwrap(),
+        liquidator
+            .get_account(&usdc_reserve.account.collateral.mint_pubkey)
+            .unwrap(),
+        wsol_reserve.pubkey,
+        wsol_reserve.account.liquidity.supply_pubkey,
+        usdc_reserve.pubkey,
+        usdc_reserve.account.collateral.mint_pubkey,
+        usdc_reserve.account.collateral.supply_pubkey,
+        obligation.pubkey,
+        lending_market.pubkey,
+        liquidator.keypair.pubkey(),
+    );
+    liquidate_ix
+        .accounts
+        .retain(|account| account.pubkey != breaker_pubkey);
+
+    let res = test
+        .process_transaction(
+            &[
+                ComputeBudgetInstruction::set_compute_unit_limit(100_000),
+                liquidate_ix,
+            ],
+            Some(&[&liquidator.keypair]),
+        )
+        .await;
+
+    expect_lending_error(res, LendingError::InvalidAccountInput);
+}
+
+#[tokio::test]
+async fn test_fail_invalid_owner() {
+    let (mut test, lending_market, _usdc_reserve, _wsol_reserve, user, _obligation, _) =
+        scenario_1(&test_reserve_config(), &test_reserve_config()).await;
+
+    let res = set_breaker(
+        &mut test,
+        &lending_market,
+        &user.keypair,
+        RateLimiterConfig {
+            window_duration: 100,
+            max_outflow: 1_000,
+        },
+        50,
+    )
+    .await;
+
+    expect_lending_error(res, LendingError::InvalidMarketOwner);
+}
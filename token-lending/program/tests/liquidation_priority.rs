@@ -0,0 +1,145 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use crate::solend_program_test::*;
+use helpers::*;
+use solana_program::native_token::LAMPORTS_PER_SOL;
+use solana_program_test::*;
+use solana_sdk::{signature::Keypair, signature::Signer};
+use solend_program::{
+    error::LendingError, instruction::set_collateral_liquidation_priority, state::*,
+};
+
+async fn set_priority(
+    test: &mut SolendProgramTest,
+    obligation: &Info<Obligation>,
+    reserve: &Info<Reserve>,
+    signer: &Keypair,
+    liquidation_priority: u8,
+) -> Result<(), BanksClientError> {
+    test.process_transaction(
+        &[set_collateral_liquidation_priority(
+            solend_program::id(),
+            liquidation_priority,
+            obligation.pubkey,
+            reserve.pubkey,
+            signer.pubkey(),
+        )],
+        Some(&[signer]),
+    )
+    .await
+}
+
+fn price(price: i64) -> PriceArgs {
+    PriceArgs {
+        price,
+        conf: 0,
+        expo: 0,
+        ema_price: price,
+        ema_conf: 0,
+    }
+}
+
+#[tokio::test]
+async fn test_liquidators_respect_priority() {
+    let (mut test, lending_market, reserves, obligations, users, _lending_market_owner) =
+        custom_scenario(
+            &[
+                ReserveArgs {
+                    mint: usdc_mint::id(),
+                    config: test_reserve_config(),
+                    liquidity_amount: 100_000 * FRACTIONAL_TO_USDC,
+                    price: price(1),
+                },
+                ReserveArgs {
+                    mint: usdt_mint::id(),
+                    config: test_reserve_config(),
+                    liquidity_amount: 100_000 * FRACTIONAL_TO_USDC,
+                    price: price(1),
+                },
+                ReserveArgs {
+                    mint: wsol_mint::id(),
+                    config: test_reserve_config(),
+                    liquidity_amount: 100 * LAMPORTS_PER_SOL,
+                    price: price(10),
+                },
+            ],
+            &[ObligationArgs {
+                deposits: vec![
+                    (usdc_mint::id(), 100 * FRACTIONAL_TO_USDC),
+                    (usdt_mint::id(), 100 * FRACTIONAL_TO_USDC),
+                ],
+                borrows: vec![(wsol_mint::id(), 9 * LAMPORTS_PER_SOL)],
+            }],
+        )
+        .await;
+
+    let usdc_reserve = find_reserve(&reserves, &usdc_mint::id()).unwrap();
+    let usdt_reserve = find_reserve(&reserves, &usdt_mint::id()).unwrap();
+    let wsol_reserve = find_reserve(&reserves, &wsol_mint::id()).unwrap();
+    let obligation = obligations[0].clone();
+
+    // only the owner can set priorities
+    let res = set_priority(&mut test, &obligation, &usdt_reserve, &Keypair::new(), 1).await;
+
+    expect_lending_error(res, LendingError::InvalidObligationOwner);
+
+    // seize usdt before usdc
+    set_priority(&mut test, &obligation, &usdt_reserve, &users[0].keypair, 1)
+        .await
+        .unwrap();
+    let obligation_post = test.load_account::<Obligation>(obligation.pubkey).await;
+    let (usdt_collateral, _) = obligation_post
+        .account
+        .find_collateral_in_deposits(usdt_reserve.pubkey)
+        .unwrap();
+    assert_eq!(usdt_collateral.liquidation_priority, 1);
+
+    // borrowed value of 9 * 13 = 117 is above the unhealthy borrow value of 200 * 0.55 = 110
+    test.set_price(&wsol_mint::id(), &price(13)).await;
+
+    let liquidator = User::new_with_balances(
+        &mut test,
+        &[
+            (&wsol_mint::id(), 100 * LAMPORTS_PER_SOL),
+            (&usdc_reserve.account.collateral.mint_pubkey, 0),
+            (&usdc_mint::id(), 0),
+            (&usdt_reserve.account.collateral.mint_pubkey, 0),
+            (&usdt_mint::id(), 0),
+        ],
+    )
+    .await;
+
+    let res = lending_market
+        .liquidate_obligation_and_redeem_reserve_collateral(
+            &mut test,
+            &wsol_reserve,
+            &usdc_reserve,
+            &obligation,
+            &liquidator,
+            u64::MAX,
+        )
+        .await;
+
+    expect_lending_error(res, LendingError::LiquidationPriorityViolation);
+
+    lending_market
+        .liquidate_obligation_and_redeem_reserve_collateral(
+            &mut test,
+            &wsol_reserve,
+            &usdt_reserve,
+            &obligation,
+            &liquidator,
+            u64::MAX,
+        )
+        .await
+        .unwrap();
+
+    let obligation_post = test.load_account::<Obligation>(obligation.pubkey).await;
+    let (usdt_collateral, _) = obligation_post
+        .account
+        .find_collateral_in_deposits(usdt_reserve.pubkey)
+        .unwrap();
+    assert!(usdt_collateral.deposited_amount < 100 * FRACTIONAL_TO_USDC);
+}
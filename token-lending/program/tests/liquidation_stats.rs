@@ -0,0 +1,122 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use crate::solend_program_test::*;
+use helpers::*;
+use solana_program_test::*;
+use solana_sdk::{signature::Keypair, signature::Signer};
+use solend_program::{
+    error::LendingError,
+    instruction::{find_liquidation_stats_address, init_liquidation_stats},
+    math::Decimal,
+    state::*,
+};
+
+async fn init_stats(
+    test: &mut SolendProgramTest,
+    lending_market: &Info<LendingMarket>,
+    signer: &Keypair,
+) -> Result<(), BanksClientError> {
+    test.process_transaction(
+        &[init_liquidation_stats(
+            solend_program::id(),
+            lending_market.pubkey,
+            signer.pubkey(),
+        )],
+        Some(&[signer]),
+    )
+    .await
+}
+
+#[tokio::test]
+async fn test_liquidations_are_recorded() {
+    let (
+        mut test,
+        lending_market,
+        usdc_reserve,
+        wsol_reserve,
+        _user,
+        obligation,
+        lending_market_owner,
+    ) = scenario_1(&test_reserve_config(), &test_reserve_config()).await;
+
+    let liquidator = User::new_with_balances(
+        &mut test,
+        &[
+            (&wsol_mint::id(), 100 * LAMPORTS_TO_SOL),
+            (&usdc_reserve.account.collateral.mint_pubkey, 0),
+            (&usdc_mint::id(), 0),
+        ],
+    )
+    .await;
+
+    test.set_price(
+        &wsol_mint::id(),
+        &PriceArgs {
+            price: 5500,
+            conf: 0,
+            expo: 0,
+            ema_price: 5500,
+            ema_conf: 0,
+        },
+    )
+    .await;
+
+    // liquidations before the stats exist aren't recorded
+    lending_market
+        .liquidate_obligation_and_redeem_reserve_collateral(
+            &mut test,
+            &wsol_reserve,
+            &usdc_reserve,
+            &obligation,
+            &liquidator,
+            u64::MAX,
+        )
+        .await
+        .unwrap();
+
+    init_stats(&mut test, &lending_market, &lending_market_owner.keypair)
+        .await
+        .unwrap();
+
+    test.advance_clock_by_slots(1).await;
+    lending_market
+        .liquidate_obligation_for_collateral(
+            &mut test,
+            &wsol_reserve,
+            &usdc_reserve,
+            &obligation,
+            &liquidator,
+            u64::MAX,
+        )
+        .await
+        .unwrap();
+
+    let (stats_pubkey, _) =
+        find_liquidation_stats_address(&solend_program::id(), &lending_market.pubkey);
+    let stats = test.load_account::<LiquidationStats>(stats_pubkey).await;
+    assert_eq!(stats.account.lending_market, lending_market.pubkey);
+    assert_eq!(stats.account.liquidation_count, 1);
+    assert!(stats.account.total_repaid_value > Decimal::zero());
+    assert_eq!(
+        stats.account.max_repaid_value,
+        stats.account.total_repaid_value
+    );
+    assert!(stats.account.average_bonus_rate().unwrap() > Decimal::zero());
+
+    // can't be created twice
+    let res = init_stats(&mut test, &lending_market, &lending_market_owner.keypair).await;
+
+    expect_lending_error(res, LendingError::AlreadyInitialized);
+}
+
+#[tokio::test]
+async fn test_fail_invalid_owner() {
+    let (mut test, lending_market, _usdc_reserve, _wsol_reserve, user, _obligation, _) =
+        scenario_1(&test_reserve_config(), &test_reserve_config()).await;
+
+    let res = init_stats(&mut test, &lending_market, &user.keypair).await;
+
+    expect_lending_error(res, LendingError::InvalidMarketOwner);
+}
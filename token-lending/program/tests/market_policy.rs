@@ -0,0 +1,237 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use crate::solend_program_test::*;
+use helpers::market_policy::{market_policy_program, POLICY_BORROW_LIMIT};
+use helpers::*;
+use solana_program::{native_token::LAMPORTS_PER_SOL, pubkey::Pubkey};
+use solana_program_test::*;
+use solana_sdk::{
+    compute_budget::ComputeBudgetInstruction,
+    instruction::InstructionError,
+    signature::{Keypair, Signer},
+    transaction::TransactionError,
+};
+use solend_program::{
+    error::LendingError,
+    instruction::{
+        append_policy_accounts, borrow_obligation_liquidity, find_market_policy_address,
+        set_market_policy,
+    },
+    state::{LendingMarket, MarketPolicy, Obligation, Reserve},
+};
+
+async fn setup() -> (
+    SolendProgramTest,
+    Info<LendingMarket>,
+    Info<Reserve>,
+    User,
+    Info<Obligation>,
+    User,
+    Pubkey,
+) {
+    let (mut test, lending_market, _, wsol_reserve, user, obligation, lending_market_owner) =
+        scenario_1(&test_reserve_config(), &test_reserve_config()).await;
+
+    let record = test
+        .create_account(8, &market_policy_program::id(), None)
+        .await;
+    test.process_transaction(
+        &[set_market_policy(
+            solend_program::id(),
+            market_policy_program::id(),
+            Some(record),
+            lending_market.pubkey,
+            lending_market_owner.keypair.pubkey(),
+        )],
+        Some(&[&lending_market_owner.keypair]),
+    )
+    .await
+    .unwrap();
+
+    let (market_policy_pubkey, _) =
+        find_market_policy_address(&solend_program::id(), &lending_market.pubkey);
+    let market_policy = test
+        .load_account::<MarketPolicy>(market_policy_pubkey)
+        .await;
+    assert_eq!(market_policy.account.lending_market, lending_market.pubkey);
+    assert_eq!(
+        market_policy.account.policy_program,
+        market_policy_program::id()
+    );
+    assert_eq!(market_policy.account.policy_account, record);
+
+    (
+        test,
+        lending_market,
+        wsol_reserve,
+        user,
+        obligation,
+        lending_market_owner,
+        record,
+    )
+}
+
+async fn borrow_with_policy(
+    test: &mut SolendProgramTest,
+    lending_market: &Info<LendingMarket>,
+    borrow_reserve: &Info<Reserve>,
+    obligation: &Info<Obligation>,
+    user: &User,
+    policy_account: Pubkey,
+    liquidity_amount: u64,
+) -> Result<(), BanksClientError> {
+    let refresh_ixs = lending_market
+        .build_refresh_instructions(test, obligation, Some(borrow_reserve))
+        .await;
+    test.process_transaction(&refresh_ixs, None).await.unwrap();
+
+    let mut borrow = borrow_obligation_liquidity(
+        solend_program::id(),
+        liquidity_amount,
+        borrow_reserve.account.liquidity.supply_pubkey,
+        user.get_account(&borrow_reserve.account.liquidity.mint_pubkey)
+            .unwrap(),
+        borrow_reserve.pubkey,
+        borrow_reserve.account.config.fee_receiver,
+        obligation.pubkey,
+        lending_market.pubkey,
+        user.keypair.pubkey(),
+        None,
+    );
+    append_policy_accounts(
+        &mut borrow,
+        market_policy_program::id(),
+        Some(policy_account),
+    );
+
+    test.process_transaction(
+        &[
+            ComputeBudgetInstruction::set_compute_unit_limit(80_000),
+            borrow,
+        ],
+        Some(&[&user.keypair]),
+    )
+    .await
+}
+
+#[tokio::test]
+async fn test_borrow_calls_policy() {
+    let (mut test, lending_market, wsol_reserve, user, obligation, _, record) = setup().await;
+
+    let balance_checker = BalanceChecker::start(&mut test, &[&user]).await;
+    borrow_with_policy(
+        &mut test,
+        &lending_market,
+        &wsol_reserve,
+        &obligation,
+        &user,
+        record,
+        LAMPORTS_PER_SOL / 2,
+    )
+    .await
+    .unwrap();
+
+    let (balance_changes, _) = balance_checker.find_balance_changes(&mut test).await;
+    assert!(balance_changes.contains(&TokenBalanceChange {
+        token_account: user
+            .get_account(&wsol_reserve.account.liquidity.mint_pubkey)
+            .unwrap(),
+        mint: wsol_reserve.account.liquidity.mint_pubkey,
+        diff: (LAMPORTS_PER_SOL / 2) as i128,
+    }));
+}
+
+#[tokio::test]
+async fn test_fail_borrow_rejected_by_policy() {
+    let (mut test, lending_market, wsol_reserve, user, obligation, _, record) = setup().await;
+
+    let res = borrow_with_policy(
+        &mut test,
+        &lending_market,
+        &wsol_reserve,
+        &obligation,
+        &user,
+        record,
+        POLICY_BORROW_LIMIT + 1,
+    )
+    .await
+    .unwrap_err()
+    .unwrap();
+    assert_eq!(
+        res,
+        TransactionError::InstructionError(1, InstructionError::InvalidArgument)
+    );
+
+    // the caller can't swap the policy account for one of its own
+    let res = borrow_with_policy(
+        &mut test,
+        &lending_market,
+        &wsol_reserve,
+        &obligation,
+        &user,
+        Pubkey::new_unique(),
+        LAMPORTS_PER_SOL / 2,
+    )
+    .await;
+
+    expect_lending_error(res, LendingError::InvalidAccountInput);
+}
+
+#[tokio::test]
+async fn test_fail_borrow_skipping_policy() {
+    let (mut test, lending_market, wsol_reserve, user, obligation, _, _) = setup().await;
+
+    // the policy program is required once the market has a policy
+    let res = lending_market
+        .borrow_obligation_liquidity(
+            &mut test,
+            &wsol_reserve,
+            &obligation,
+            &user,
+            None,
+            LAMPORTS_PER_SOL / 2,
+        )
+        .await;
+
+    expect_lending_error(res, LendingError::InvalidAccountInput);
+}
+
+#[tokio::test]
+async fn test_fail_set_market_policy() {
+    let (mut test, lending_market, _, _, _, lending_market_owner, _) = setup().await;
+
+    // the lending program can't be its own policy
+    let res = test
+        .process_transaction(
+            &[set_market_policy(
+                solend_program::id(),
+                solend_program::id(),
+                None,
+                lending_market.pubkey,
+                lending_market_owner.keypair.pubkey(),
+            )],
+            Some(&[&lending_market_owner.keypair]),
+        )
+        .await;
+
+    expect_lending_error(res, LendingError::InvalidConfig);
+
+    // only the lending market owner can set a policy
+    let impostor = Keypair::new();
+    let res = test
+        .process_transaction(
+            &[set_market_policy(
+                solend_program::id(),
+                Pubkey::default(),
+                None,
+                lending_market.pubkey,
+                impostor.pubkey(),
+            )],
+            Some(&[&impostor]),
+        )
+        .await;
+
+    expect_lending_error(res, LendingError::InvalidMarketOwner);
+}
@@ -0,0 +1,278 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use crate::solend_program_test::*;
+use helpers::*;
+use solana_program::native_token::LAMPORTS_PER_SOL;
+use solana_program_test::*;
+use solana_sdk::{signature::Keypair, signature::Signer, system_instruction::transfer};
+use solend_program::{
+    error::LendingError,
+    instruction::{
+        borrow_obligation_liquidity, find_obligation_borrow_cap_address, set_obligation_borrow_cap,
+    },
+    math::Decimal,
+    state::{LendingMarket, Obligation, ObligationBorrowCap, Reserve},
+};
+
+async fn setup() -> (
+    SolendProgramTest,
+    Info<LendingMarket>,
+    Info<Reserve>,
+    User,
+    Info<Obligation>,
+) {
+    let (mut test, lending_market, usdc_reserve, wsol_reserve, _lending_market_owner, user) =
+        setup_world(&test_reserve_config(), &test_reserve_config()).await;
+
+    let obligation = lending_market
+        .init_obligation(&mut test, Keypair::new(), &user)
+        .await
+        .unwrap();
+
+    // $100 of USDC, enough to borrow $50
+    lending_market
+        .deposit_reserve_liquidity_and_obligation_collateral(
+            &mut test,
+            &usdc_reserve,
+            &obligation,
+            &user,
+            100_000_000,
+        )
+        .await
+        .unwrap();
+
+    let wsol_depositor = User::new_with_balances(
+        &mut test,
+        &[
+            (&wsol_mint::id(), 5 * LAMPORTS_PER_SOL),
+            (&wsol_reserve.account.collateral.mint_pubkey, 0),
+        ],
+    )
+    .await;
+    lending_market
+        .deposit(
+            &mut test,
+            &wsol_reserve,
+            &wsol_depositor,
+            5 * LAMPORTS_PER_SOL,
+        )
+        .await
+        .unwrap();
+
+    // the obligation owner pays for the borrow cap account
+    test.process_transaction(
+        &[transfer(
+            &test.context.payer.pubkey(),
+            &user.keypair.pubkey(),
+            LAMPORTS_PER_SOL,
+        )],
+        None,
+    )
+    .await
+    .unwrap();
+
+    let wsol_reserve = test.load_account(wsol_reserve.pubkey).await;
+    let obligation = test.load_account(obligation.pubkey).await;
+    (test, lending_market, wsol_reserve, user, obligation)
+}
+
+#[tokio::test]
+async fn test_success() {
+    let (mut test, lending_market, wsol_reserve, user, obligation) = setup().await;
+
+    // $20
+    test.process_transaction(
+        &[set_obligation_borrow_cap(
+            solend_program::id(),
+            20,
+            obligation.pubkey,
+            user.keypair.pubkey(),
+        )],
+        Some(&[&user.keypair]),
+    )
+    .await
+    .unwrap();
+
+    let (obligation_borrow_cap_pubkey, _) =
+        find_obligation_borrow_cap_address(&solend_program::id(), &obligation.pubkey);
+    let obligation_borrow_cap = test
+        .load_account::<ObligationBorrowCap>(obligation_borrow_cap_pubkey)
+        .await;
+    assert_eq!(
+        obligation_borrow_cap.account,
+        ObligationBorrowCap {
+            bump_seed: obligation_borrow_cap.account.bump_seed,
+            ..ObligationBorrowCap::new(obligation.pubkey, 0, 20)
+        }
+    );
+
+    // $10
+    lending_market
+        .borrow_obligation_liquidity(
+            &mut test,
+            &wsol_reserve,
+            &obligation,
+            &user,
+            None,
+            LAMPORTS_PER_SOL,
+        )
+        .await
+        .unwrap();
+
+    // $20 more fits the collateral but not the cap
+    test.advance_clock_by_slots(1).await;
+    let res = lending_market
+        .borrow_obligation_liquidity(
+            &mut test,
+            &wsol_reserve,
+            &obligation,
+            &user,
+            None,
+            2 * LAMPORTS_PER_SOL,
+        )
+        .await;
+    expect_lending_error(res, LendingError::ObligationBorrowCapExceeded);
+
+    // max borrows stop at the cap
+    lending_market
+        .borrow_obligation_liquidity(&mut test, &wsol_reserve, &obligation, &user, None, u64::MAX)
+        .await
+        .unwrap();
+
+    let obligation_post = test.load_account::<Obligation>(obligation.pubkey).await;
+    let borrowed_amount_wads = obligation_post.account.borrows[0].borrowed_amount_wads;
+    assert!(borrowed_amount_wads > Decimal::from(LAMPORTS_PER_SOL));
+    assert!(borrowed_amount_wads <= Decimal::from(2 * LAMPORTS_PER_SOL));
+
+    // raising the cap lets the obligation borrow again
+    test.process_transaction(
+        &[set_obligation_borrow_cap(
+            solend_program::id(),
+            u64::MAX,
+            obligation.pubkey,
+            user.keypair.pubkey(),
+        )],
+        Some(&[&user.keypair]),
+    )
+    .await
+    .unwrap();
+
+    test.advance_clock_by_slots(1).await;
+    lending_market
+        .borrow_obligation_liquidity(
+            &mut test,
+            &wsol_reserve,
+            &obligation,
+            &user,
+            None,
+            LAMPORTS_PER_SOL,
+        )
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_set_prefunded_borrow_cap() {
+    let (mut test, _lending_market, _wsol_reserve, user, obligation) = setup().await;
+
+    // lamports sent to the borrow cap address ahead of time don't block its creation
+    let (obligation_borrow_cap_pubkey, _) =
+        find_obligation_borrow_cap_address(&solend_program::id(), &obligation.pubkey);
+    test.process_transaction(
+        &[transfer(
+            &test.context.payer.pubkey(),
+            &obligation_borrow_cap_pubkey,
+            1,
+        )],
+        None,
+    )
+    .await
+    .unwrap();
+
+    test.process_transaction(
+        &[set_obligation_borrow_cap(
+            solend_program::id(),
+            20,
+            obligation.pubkey,
+            user.keypair.pubkey(),
+        )],
+        Some(&[&user.keypair]),
+    )
+    .await
+    .unwrap();
+
+    let obligation_borrow_cap = test
+        .load_account::<ObligationBorrowCap>(obligation_borrow_cap_pubkey)
+        .await;
+    assert_eq!(obligation_borrow_cap.account.max_borrowed_value, 20);
+}
+
+#[tokio::test]
+async fn test_fail_invalid_owner() {
+    let (mut test, _lending_market, _wsol_reserve, _user, obligation) = setup().await;
+
+    let other_user = User::new_with_balances(&mut test, &[]).await;
+    let res = test
+        .process_transaction(
+            &[set_obligation_borrow_cap(
+                solend_program::id(),
+                20,
+                obligation.pubkey,
+                other_user.keypair.pubkey(),
+            )],
+            Some(&[&other_user.keypair]),
+        )
+        .await;
+
+    expect_lending_error(res, LendingError::InvalidObligationOwner);
+}
+
+#[tokio::test]
+async fn test_fail_borrow_without_borrow_cap_account() {
+    let (mut test, lending_market, wsol_reserve, user, obligation) = setup().await;
+
+    test.process_transaction(
+        &[set_obligation_borrow_cap(
+            solend_program::id(),
+            20,
+            obligation.pubkey,
+            user.keypair.pubkey(),
+        )],
+        Some(&[&user.keypair]),
+    )
+    .await
+    .unwrap();
+    let obligation = test.load_account::<Obligation>(obligation.pubkey).await;
+    assert!(obligation.account.has_borrow_cap);
+
+    let refresh_ixs = lending_market
+        .build_refresh_instructions(&mut test, &obligation, Some(&wsol_reserve))
+        .await;
+    test.process_transaction(&refresh_ixs, None).await.unwrap();
+
+    // leaving the borrow cap account out doesn't get around the cap
+    let (obligation_borrow_cap_pubkey, _) =
+        find_obligation_borrow_cap_address(&solend_program::id(), &obligation.pubkey);
+    let mut instruction = borrow_obligation_liquidity(
+        solend_program::id(),
+        LAMPORTS_PER_SOL,
+        wsol_reserve.account.liquidity.supply_pubkey,
+        user.get_account(&wsol_mint::id()).unwrap(),
+        wsol_reserve.pubkey,
+        wsol_reserve.account.config.fee_receiver,
+        obligation.pubkey,
+        lending_market.pubkey,
+        user.keypair.pubkey(),
+        None,
+    );
+    instruction
+        .accounts
+        .retain(|meta| meta.pubkey != obligation_borrow_cap_pubkey);
+    let res = test
+        .process_transaction(&[instruction], Some(&[&user.keypair]))
+        .await;
+
+    expect_lending_error(res, LendingError::InvalidAccountInput);
+}
@@ -0,0 +1,208 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use crate::solend_program_test::*;
+use helpers::*;
+use solana_program_test::*;
+use solana_sdk::{signature::Keypair, signature::Signer};
+use solend_program::{
+    error::LendingError,
+    instruction::{
+        deposit_reserve_liquidity_and_obligation_collateral, find_obligation_limits_address,
+        set_obligation_limits,
+    },
+    state::*,
+};
+
+async fn set_limits(
+    test: &mut SolendProgramTest,
+    lending_market: &Info<LendingMarket>,
+    signer: &Keypair,
+    max_deposits: u8,
+    max_borrows: u8,
+) -> Result<(), BanksClientError> {
+    test.process_transaction(
+        &[set_obligation_limits(
+            solend_program::id(),
+            max_deposits,
+            max_borrows,
+            lending_market.pubkey,
+            signer.pubkey(),
+        )],
+        Some(&[signer]),
+    )
+    .await
+}
+
+#[tokio::test]
+async fn test_limits_block_new_positions() {
+    let (
+        mut test,
+        lending_market,
+        usdc_reserve,
+        wsol_reserve,
+        user,
+        obligation,
+        lending_market_owner,
+    ) = scenario_1(&test_reserve_config(), &test_reserve_config()).await;
+
+    set_limits(
+        &mut test,
+        &lending_market,
+        &lending_market_owner.keypair,
+        1,
+        1,
+    )
+    .await
+    .unwrap();
+
+    let (limits_pubkey, _) =
+        find_obligation_limits_address(&solend_program::id(), &lending_market.pubkey);
+    let limits = test.load_account::<ObligationLimits>(limits_pubkey).await;
+    assert_eq!(limits.account.lending_market, lending_market.pubkey);
+    assert_eq!(limits.account.max_deposits, 1);
+    assert_eq!(limits.account.max_borrows, 1);
+
+    // adding to an existing deposit is fine
+    lending_market
+        .deposit_reserve_liquidity_and_obligation_collateral(
+            &mut test,
+            &usdc_reserve,
+            &obligation,
+            &user,
+            1_000_000,
+        )
+        .await
+        .unwrap();
+
+    // a second deposit is not
+    let res = lending_market
+        .deposit_reserve_liquidity_and_obligation_collateral(
+            &mut test,
+            &wsol_reserve,
+            &obligation,
+            &user,
+            LAMPORTS_TO_SOL,
+        )
+        .await;
+
+    expect_lending_error(res, LendingError::ObligationPositionLimit);
+
+    // neither is a second borrow
+    let obligation = test.load_account::<Obligation>(obligation.pubkey).await;
+    let res = lending_market
+        .borrow_obligation_liquidity(
+            &mut test,
+            &usdc_reserve,
+            &obligation,
+            &user,
+            lending_market_owner.get_account(&usdc_mint::id()),
+            1_000_000,
+        )
+        .await;
+
+    expect_lending_error(res, LendingError::ObligationPositionLimit);
+
+    // raising the limits lets the obligation add positions again
+    set_limits(
+        &mut test,
+        &lending_market,
+        &lending_market_owner.keypair,
+        2,
+        2,
+    )
+    .await
+    .unwrap();
+
+    test.advance_clock_by_slots(1).await;
+    lending_market
+        .deposit_reserve_liquidity_and_obligation_collateral(
+            &mut test,
+            &wsol_reserve,
+            &obligation,
+            &user,
+            LAMPORTS_TO_SOL,
+        )
+        .await
+        .unwrap();
+
+    let obligation = test.load_account::<Obligation>(obligation.pubkey).await;
+    assert_eq!(obligation.account.deposits.len(), 2);
+}
+
+#[tokio::test]
+async fn test_fail_deposit_without_limits_account() {
+    let (
+        mut test,
+        lending_market,
+        _usdc_reserve,
+        wsol_reserve,
+        user,
+        obligation,
+        lending_market_owner,
+    ) = scenario_1(&test_reserve_config(), &test_reserve_config()).await;
+
+    set_limits(
+        &mut test,
+        &lending_market,
+        &lending_market_owner.keypair,
+        1,
+        1,
+    )
+    .await
+    .unwrap();
+    let lending_market = test
+        .load_account::<LendingMarket>(lending_market.pubkey)
+        .await;
+    assert!(lending_market.account.has_obligation_limits);
+
+    // leaving the limits account out doesn't get around the limits
+    let (limits_pubkey, _) =
+        find_obligation_limits_address(&solend_program::id(), &lending_market.pubkey);
+    let mut instruction = deposit_reserve_liquidity_and_obligation_collateral(
+        solend_program::id(),
+        LAMPORTS_TO_SOL,
+        user.get_account(&wsol_mint::id()).unwrap(),
+        user.get_account(&wsol_reserve.account.collateral.mint_pubkey)
+            .unwrap(),
+        wsol_reserve.pubkey,
+        wsol_reserve.account.liquidity.supply_pubkey,
+        wsol_reserve.account.collateral.mint_pubkey,
+        lending_market.pubkey,
+        wsol_reserve.account.collateral.supply_pubkey,
+        obligation.pubkey,
+        user.keypair.pubkey(),
+        wsol_reserve.account.liquidity.pyth_oracle_pubkey,
+        wsol_reserve.account.liquidity.switchboard_oracle_pubkey,
+        user.keypair.pubkey(),
+    );
+    instruction
+        .accounts
+        .retain(|meta| meta.pubkey != limits_pubkey);
+    let res = test
+        .process_transaction(&[instruction], Some(&[&user.keypair]))
+        .await;
+
+    expect_lending_error(res, LendingError::InvalidAccountInput);
+}
+
+#[tokio::test]
+async fn test_fail_invalid_limits() {
+    let (mut test, lending_market, _usdc_reserve, _wsol_reserve, _user, _obligation, owner) =
+        scenario_1(&test_reserve_config(), &test_reserve_config()).await;
+
+    let res = set_limits(&mut test, &lending_market, &owner.keypair, 0, 1).await;
+
+    expect_lending_error(res, LendingError::InvalidConfig);
+}
+
+#[tokio::test]
+async fn test_fail_invalid_owner() {
+    let (mut test, lending_market, _usdc_reserve, _wsol_reserve, user, _obligation, _) =
+        scenario_1(&test_reserve_config(), &test_reserve_config()).await;
+
+    let res = set_limits(&mut test, &lending_market, &user.keypair, 1, 1).await;
+
+    expect_lending_error(res, LendingError::InvalidMarketOwner);
+}
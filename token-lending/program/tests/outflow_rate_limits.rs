@@ -1,10 +1,8 @@
 #![cfg(feature = "test-bpf")]
 
-use solana_program::instruction::InstructionError;
 use solana_sdk::native_token::LAMPORTS_PER_SOL;
 use solana_sdk::signature::Signer;
 use solana_sdk::signer::keypair::Keypair;
-use solana_sdk::transaction::TransactionError;
 
 mod helpers;
 
@@ -161,18 +159,9 @@ async fn test_outflow_reserve() {
                 host_fee_receiver.get_account(&wsol_mint::id()),
                 1,
             )
-            .await
-            .err()
-            .unwrap()
-            .unwrap();
-
-        assert_eq!(
-            res,
-            TransactionError::InstructionError(
-                1,
-                InstructionError::Custom(LendingError::OutflowRateLimitExceeded as u32)
-            )
-        );
+            .await;
+
+        expect_lending_error(res, LendingError::OutflowRateLimitExceeded);
 
         let res = lending_market
             .withdraw_obligation_collateral_and_redeem_reserve_collateral(
@@ -182,33 +171,15 @@ async fn test_outflow_reserve() {
                 &user,
                 1,
             )
-            .await
-            .err()
-            .unwrap()
-            .unwrap();
-
-        assert_eq!(
-            res,
-            TransactionError::InstructionError(
-                1,
-                InstructionError::Custom(LendingError::WithdrawTooLarge as u32)
-            )
-        );
+            .await;
+
+        expect_lending_error(res, LendingError::WithdrawTooLarge);
 
         let res = lending_market
             .redeem(&mut test, &wsol_reserve, &wsol_depositor, 1)
-            .await
-            .err()
-            .unwrap()
-            .unwrap();
-
-        assert_eq!(
-            res,
-            TransactionError::InstructionError(
-                2,
-                InstructionError::Custom(LendingError::OutflowRateLimitExceeded as u32)
-            )
-        );
+            .await;
+
+        expect_lending_error(res, LendingError::OutflowRateLimitExceeded);
 
         test.advance_clock_by_slots(1).await;
     }
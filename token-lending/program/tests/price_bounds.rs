@@ -0,0 +1,140 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use crate::solend_program_test::*;
+use helpers::*;
+use solana_program_test::*;
+use solana_sdk::transaction::TransactionError;
+use solend_program::{
+    error::LendingError,
+    instruction::refresh_reserve,
+    math::Decimal,
+    state::{PriceBoundsMode, Reserve, ReserveConfig},
+};
+
+fn stablecoin_reserve_config(price_bounds_mode: PriceBoundsMode) -> ReserveConfig {
+    ReserveConfig {
+        min_price_bps: 9_000,
+        max_price_bps: 11_000,
+        price_bounds_mode,
+        ..test_reserve_config()
+    }
+}
+
+async fn refresh_usdc_reserve(
+    test: &mut SolendProgramTest,
+    usdc_reserve: &Info<Reserve>,
+    price: i64,
+    ema_price: i64,
+) -> Result<(), TransactionError> {
+    // prices are in cents
+    test.set_price(
+        &usdc_mint::id(),
+        &PriceArgs {
+            price,
+            conf: 0,
+            expo: -2,
+            ema_price,
+            ema_conf: 0,
+        },
+    )
+    .await;
+    test.advance_clock_by_slots(1).await;
+
+    test.process_transaction(
+        &[refresh_reserve(
+            solend_program::id(),
+            usdc_reserve.pubkey,
+            usdc_reserve.account.liquidity.pyth_oracle_pubkey,
+            usdc_reserve.account.liquidity.switchboard_oracle_pubkey,
+        )],
+        None,
+    )
+    .await
+    .map_err(|e| e.unwrap())
+}
+
+#[tokio::test]
+async fn test_reject_bad_print() {
+    let (mut test, _lending_market, usdc_reserve, _, _, _) = setup_world(
+        &stablecoin_reserve_config(PriceBoundsMode::Reject),
+        &test_reserve_config(),
+    )
+    .await;
+
+    // USDC at $10
+    let res = refresh_usdc_reserve(&mut test, &usdc_reserve, 1_000, 100).await;
+    expect_lending_error(res, LendingError::PriceOutOfBounds);
+
+    let usdc_reserve_post = test.load_account::<Reserve>(usdc_reserve.pubkey).await;
+    assert_eq!(
+        usdc_reserve_post.account.liquidity.market_price,
+        Decimal::one()
+    );
+}
+
+#[tokio::test]
+async fn test_reject_depeg_below_floor() {
+    let (mut test, _lending_market, usdc_reserve, _, _, _) = setup_world(
+        &stablecoin_reserve_config(PriceBoundsMode::Reject),
+        &test_reserve_config(),
+    )
+    .await;
+
+    // a mild depeg stays within the bounds
+    refresh_usdc_reserve(&mut test, &usdc_reserve, 95, 100)
+        .await
+        .unwrap();
+    let usdc_reserve_post = test.load_account::<Reserve>(usdc_reserve.pubkey).await;
+    assert_eq!(
+        usdc_reserve_post.account.liquidity.market_price,
+        Decimal::from_percent(95)
+    );
+
+    // the floor is inclusive
+    refresh_usdc_reserve(&mut test, &usdc_reserve, 90, 100)
+        .await
+        .unwrap();
+
+    // a real depeg below the floor freezes the reserve until the bounds are updated
+    let res = refresh_usdc_reserve(&mut test, &usdc_reserve, 50, 100).await;
+    expect_lending_error(res, LendingError::PriceOutOfBounds);
+}
+
+#[tokio::test]
+async fn test_clamp_depeg() {
+    let (mut test, _lending_market, usdc_reserve, _, _, _) = setup_world(
+        &stablecoin_reserve_config(PriceBoundsMode::Clamp),
+        &test_reserve_config(),
+    )
+    .await;
+
+    // the spot price depegs to $0.50 while the ema price lags behind
+    refresh_usdc_reserve(&mut test, &usdc_reserve, 50, 95)
+        .await
+        .unwrap();
+    let usdc_reserve_post = test.load_account::<Reserve>(usdc_reserve.pubkey).await;
+    assert_eq!(
+        usdc_reserve_post.account.liquidity.market_price,
+        Decimal::from_percent(90)
+    );
+    assert_eq!(
+        usdc_reserve_post.account.liquidity.smoothed_market_price,
+        Decimal::from_percent(95)
+    );
+
+    // USDC at $10
+    refresh_usdc_reserve(&mut test, &usdc_reserve, 1_000, 1_000)
+        .await
+        .unwrap();
+    let usdc_reserve_post = test.load_account::<Reserve>(usdc_reserve.pubkey).await;
+    assert_eq!(
+        usdc_reserve_post.account.liquidity.market_price,
+        Decimal::from_percent(110)
+    );
+    assert_eq!(
+        usdc_reserve_post.account.liquidity.smoothed_market_price,
+        Decimal::from_percent(110)
+    );
+}
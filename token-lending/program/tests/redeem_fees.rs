@@ -6,17 +6,27 @@ use crate::solend_program_test::scenario_1;
 use crate::solend_program_test::BalanceChecker;
 use crate::solend_program_test::PriceArgs;
 use crate::solend_program_test::TokenBalanceChange;
+use crate::solend_program_test::{Info, SolendProgramTest, User};
 use solana_program::native_token::LAMPORTS_PER_SOL;
 use solend_program::state::LastUpdate;
 use solend_program::state::ReserveLiquidity;
 use solend_program::state::{Reserve, ReserveConfig};
 use std::collections::HashSet;
 
+use helpers::fee_redemption_hook::fee_redemption_hook_program;
 use helpers::*;
+use solana_program::{instruction::AccountMeta, pubkey::Pubkey};
 use solana_program_test::*;
+use solana_sdk::{
+    instruction::InstructionError,
+    signature::{Keypair, Signer},
+    transaction::TransactionError,
+};
 use solend_program::{
+    error::LendingError,
+    instruction::{redeem_fees_with_hook, refresh_reserve, set_fee_redemption_hook},
     math::{Decimal, TrySub},
-    state::SLOTS_PER_YEAR,
+    state::{fee_redemption_hook_accounts_hash, LendingMarket, SLOTS_PER_YEAR},
 };
 
 #[tokio::test]
@@ -108,3 +118,172 @@ async fn test_success() {
         }
     );
 }
+
+async fn setup_hook() -> (
+    SolendProgramTest,
+    Info<LendingMarket>,
+    Info<Reserve>,
+    User,
+    Pubkey,
+) {
+    let (mut test, lending_market, _, wsol_reserve, user, _, lending_market_owner) = scenario_1(
+        &test_reserve_config(),
+        &ReserveConfig {
+            protocol_take_rate: 10,
+            ..test_reserve_config()
+        },
+    )
+    .await;
+
+    test.advance_clock_by_slots(SLOTS_PER_YEAR).await;
+    lending_market
+        .refresh_reserve(&mut test, &wsol_reserve)
+        .await
+        .unwrap();
+    lending_market
+        .deposit(&mut test, &wsol_reserve, &user, LAMPORTS_PER_SOL)
+        .await
+        .unwrap();
+
+    let record = test
+        .create_account(8, &fee_redemption_hook_program::id(), None)
+        .await;
+    test.process_transaction(
+        &[set_fee_redemption_hook(
+            solend_program::id(),
+            fee_redemption_hook_program::id(),
+            fee_redemption_hook_accounts_hash(&[record]),
+            wsol_reserve.pubkey,
+            lending_market.pubkey,
+            lending_market_owner.keypair.pubkey(),
+        )],
+        Some(&[&lending_market_owner.keypair]),
+    )
+    .await
+    .unwrap();
+
+    let wsol_reserve = test.load_account::<Reserve>(wsol_reserve.pubkey).await;
+    assert!(wsol_reserve.account.has_fee_redemption_hook);
+
+    (
+        test,
+        lending_market,
+        wsol_reserve,
+        lending_market_owner,
+        record,
+    )
+}
+
+async fn redeem_fees_with_hook_accounts(
+    test: &mut SolendProgramTest,
+    lending_market: &Info<LendingMarket>,
+    reserve: &Info<Reserve>,
+    forwarded_account: Pubkey,
+) -> Result<(), BanksClientError> {
+    test.process_transaction(
+        &[
+            refresh_reserve(
+                solend_program::id(),
+                reserve.pubkey,
+                reserve.account.liquidity.pyth_oracle_pubkey,
+                reserve.account.liquidity.switchboard_oracle_pubkey,
+            ),
+            redeem_fees_with_hook(
+                solend_program::id(),
+                reserve.pubkey,
+                reserve.account.config.fee_receiver,
+                reserve.account.liquidity.supply_pubkey,
+                lending_market.pubkey,
+                fee_redemption_hook_program::id(),
+                vec![AccountMeta::new(forwarded_account, false)],
+            ),
+        ],
+        None,
+    )
+    .await
+}
+
+#[tokio::test]
+async fn test_redeem_fees_calls_hook() {
+    let (mut test, lending_market, wsol_reserve, _, record) = setup_hook().await;
+    let expected_fees = wsol_reserve.account.calculate_redeem_fees().unwrap();
+
+    redeem_fees_with_hook_accounts(&mut test, &lending_market, &wsol_reserve, record)
+        .await
+        .unwrap();
+
+    let record = test
+        .context
+        .banks_client
+        .get_account(record)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(record.data, expected_fees.to_le_bytes());
+}
+
+#[tokio::test]
+async fn test_fail_redeem_fees_skipping_hook() {
+    let (mut test, lending_market, wsol_reserve, _, _) = setup_hook().await;
+
+    // the hook accounts are required once the reserve has a hook
+    let res = lending_market
+        .redeem_fees(&mut test, &wsol_reserve)
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        res,
+        TransactionError::InstructionError(2, InstructionError::NotEnoughAccountKeys)
+    );
+
+    // forwarded accounts other than the ones the owner set up
+    let other_record = test
+        .create_account(8, &fee_redemption_hook_program::id(), None)
+        .await;
+    let res =
+        redeem_fees_with_hook_accounts(&mut test, &lending_market, &wsol_reserve, other_record)
+            .await;
+
+    expect_lending_error(res, LendingError::InvalidAccountInput);
+}
+
+#[tokio::test]
+async fn test_fail_set_fee_redemption_hook() {
+    let (mut test, lending_market, wsol_reserve, lending_market_owner, record) = setup_hook().await;
+
+    // the lending program can't hook into itself
+    let res = test
+        .process_transaction(
+            &[set_fee_redemption_hook(
+                solend_program::id(),
+                solend_program::id(),
+                fee_redemption_hook_accounts_hash(&[record]),
+                wsol_reserve.pubkey,
+                lending_market.pubkey,
+                lending_market_owner.keypair.pubkey(),
+            )],
+            Some(&[&lending_market_owner.keypair]),
+        )
+        .await;
+
+    expect_lending_error(res, LendingError::InvalidConfig);
+
+    // only the lending market owner can set a hook
+    let impostor = Keypair::new();
+    let res = test
+        .process_transaction(
+            &[set_fee_redemption_hook(
+                solend_program::id(),
+                fee_redemption_hook_program::id(),
+                fee_redemption_hook_accounts_hash(&[]),
+                wsol_reserve.pubkey,
+                lending_market.pubkey,
+                impostor.pubkey(),
+            )],
+            Some(&[&impostor]),
+        )
+        .await;
+
+    expect_lending_error(res, LendingError::InvalidMarketOwner);
+}
@@ -258,7 +258,8 @@ async fn test_success() {
                 borrow_reserve: wsol_reserve.pubkey,
                 cumulative_borrow_rate_wads: new_cumulative_borrow_rate,
                 borrowed_amount_wads: new_borrowed_amount_wads,
-                market_value: new_borrow_value
+                market_value: new_borrow_value,
+                ..ObligationLiquidity::default()
             }]
             .to_vec(),
 
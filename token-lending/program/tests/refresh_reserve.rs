@@ -11,10 +11,10 @@ use crate::solend_program_test::SolendProgramTest;
 use crate::solend_program_test::SwitchboardPriceArgs;
 use crate::solend_program_test::User;
 use helpers::*;
-use solana_program::instruction::InstructionError;
 use solana_program::native_token::LAMPORTS_PER_SOL;
 use solana_program_test::*;
-use solana_sdk::{signature::Keypair, transaction::TransactionError};
+use solana_sdk::signature::Keypair;
+use solend_program::instruction::refresh_reserve;
 use solend_program::state::LastUpdate;
 use solend_program::state::LendingMarket;
 use solend_program::state::Obligation;
@@ -219,18 +219,9 @@ async fn test_fail_pyth_price_stale() {
 
     let res = lending_market
         .refresh_reserve(&mut test, &wsol_reserve)
-        .await
-        .unwrap_err()
-        .unwrap();
-    println!("{:?}", res);
+        .await;
 
-    assert_eq!(
-        res,
-        TransactionError::InstructionError(
-            1,
-            InstructionError::Custom(LendingError::NullOracleConfig as u32),
-        ),
-    );
+    expect_lending_error(res, LendingError::NullOracleConfig);
 }
 
 #[tokio::test]
@@ -353,3 +344,44 @@ async fn test_success_only_switchboard_reserve() {
         Decimal::from(8u64)
     );
 }
+
+#[tokio::test]
+async fn test_refresh_unchanged_skips_repack() {
+    let (mut test, lending_market, _, wsol_reserve, _, _) = setup().await;
+    let refresh = refresh_reserve(
+        solend_program::id(),
+        wsol_reserve.pubkey,
+        wsol_reserve.account.liquidity.pyth_oracle_pubkey,
+        wsol_reserve.account.liquidity.switchboard_oracle_pubkey,
+    );
+
+    test.advance_clock_by_slots(1).await;
+    lending_market
+        .refresh_reserve(&mut test, &wsol_reserve)
+        .await
+        .unwrap();
+
+    // refreshed again in the same slot at the same prices, only last_update is packed
+    let unchanged_units = test.simulate_compute_units(&[refresh.clone()], None).await;
+
+    // in the same slot at a new price, the whole reserve is repacked
+    test.set_price(
+        &wsol_mint::id(),
+        &PriceArgs {
+            price: 20,
+            conf: 1,
+            expo: 1,
+            ema_price: 15,
+            ema_conf: 1,
+        },
+    )
+    .await;
+    let repack_units = test.simulate_compute_units(&[refresh], None).await;
+
+    assert!(
+        unchanged_units < repack_units,
+        "unchanged refresh used {} CUs, repacking refresh used {}",
+        unchanged_units,
+        repack_units
+    );
+}
@@ -5,11 +5,14 @@ mod helpers;
 use crate::solend_program_test::scenario_1;
 use std::collections::HashSet;
 
-use helpers::solend_program_test::{BalanceChecker, TokenBalanceChange};
+use helpers::solend_program_test::{BalanceChecker, TokenBalanceChange, User};
 use helpers::*;
 use solana_program::native_token::LAMPORTS_PER_SOL;
 use solana_program_test::*;
 
+use solana_sdk::signature::Signer;
+use solend_program::events::LendingEvent;
+use solend_program::instruction::repay_obligation_liquidity;
 use solend_program::math::TryDiv;
 use solend_program::state::{LastUpdate, ObligationLiquidity, ReserveLiquidity, SLOTS_PER_YEAR};
 use solend_program::{
@@ -110,3 +113,59 @@ async fn test_success() {
         }
     );
 }
+
+#[tokio::test]
+async fn test_repay_on_behalf() {
+    let (mut test, lending_market, _, wsol_reserve, user, obligation, _) =
+        scenario_1(&test_reserve_config(), &test_reserve_config()).await;
+
+    let payer = User::new_with_balances(&mut test, &[(&wsol_mint::id(), LAMPORTS_PER_SOL)]).await;
+    test.advance_clock_by_slots(1).await;
+
+    let instruction = repay_obligation_liquidity(
+        solend_program::id(),
+        LAMPORTS_PER_SOL,
+        payer.get_account(&wsol_mint::id()).unwrap(),
+        wsol_reserve.account.liquidity.supply_pubkey,
+        wsol_reserve.pubkey,
+        obligation.pubkey,
+        lending_market.pubkey,
+        payer.keypair.pubkey(),
+    );
+
+    // the repayment is attributed to the payer
+    let (res, logs, _) = test
+        .simulate_transaction(&[instruction.clone()], Some(&[&payer.keypair]))
+        .await;
+    res.unwrap();
+    let events = LendingEvent::from_logs(&logs);
+    assert_eq!(
+        events,
+        vec![LendingEvent::ObligationLiquidityRepaid {
+            obligation: obligation.pubkey,
+            owner: user.keypair.pubkey(),
+            payer: payer.keypair.pubkey(),
+            reserve: wsol_reserve.pubkey,
+            amount: LAMPORTS_PER_SOL,
+        }]
+    );
+    assert!(events[0].is_third_party_repayment());
+
+    test.process_transaction(&[instruction], Some(&[&payer.keypair]))
+        .await
+        .unwrap();
+
+    assert_eq!(
+        payer.get_balance(&mut test, &wsol_mint::id()).await,
+        Some(0)
+    );
+    let obligation_post = test.load_account::<Obligation>(obligation.pubkey).await;
+    assert!(
+        obligation_post.account.borrows[0].borrowed_amount_wads
+            < obligation.account.borrows[0].borrowed_amount_wads
+    );
+    assert!(
+        obligation_post.account.borrows[0].borrowed_amount_wads
+            > Decimal::from(9 * LAMPORTS_PER_SOL)
+    );
+}
@@ -0,0 +1,173 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use crate::solend_program_test::*;
+use helpers::*;
+use solana_program_test::*;
+use solana_sdk::signature::{Keypair, Signer};
+use solend_program::{
+    error::LendingError,
+    instruction::{
+        find_reserve_accrual_history_address, refresh_reserve_with_accrual_history,
+        set_reserve_accrual_epoch,
+    },
+    math::Rate,
+    state::{LendingMarket, Reserve, ReserveAccrualHistory},
+};
+
+const EPOCH_SLOTS: u64 = 100;
+
+async fn set_accrual_epoch(
+    test: &mut SolendProgramTest,
+    lending_market: &Info<LendingMarket>,
+    reserve: &Info<Reserve>,
+    signer: &Keypair,
+    epoch_slots: u64,
+) -> Result<(), BanksClientError> {
+    test.process_transaction(
+        &[set_reserve_accrual_epoch(
+            solend_program::id(),
+            epoch_slots,
+            reserve.pubkey,
+            lending_market.pubkey,
+            signer.pubkey(),
+        )],
+        Some(&[signer]),
+    )
+    .await
+}
+
+async fn refresh_with_history(
+    test: &mut SolendProgramTest,
+    reserve: &Info<Reserve>,
+) -> Result<(), BanksClientError> {
+    test.process_transaction(
+        &[refresh_reserve_with_accrual_history(
+            solend_program::id(),
+            reserve.pubkey,
+            reserve.account.liquidity.pyth_oracle_pubkey,
+            reserve.account.liquidity.switchboard_oracle_pubkey,
+        )],
+        None,
+    )
+    .await
+}
+
+#[tokio::test]
+async fn test_record_epoch_openings() {
+    let (mut test, lending_market, _, wsol_reserve, _, _, lending_market_owner) =
+        scenario_1(&test_reserve_config(), &test_reserve_config()).await;
+
+    set_accrual_epoch(
+        &mut test,
+        &lending_market,
+        &wsol_reserve,
+        &lending_market_owner.keypair,
+        EPOCH_SLOTS,
+    )
+    .await
+    .unwrap();
+
+    let (accrual_history_pubkey, _) =
+        find_reserve_accrual_history_address(&solend_program::id(), &wsol_reserve.pubkey);
+    let accrual_history = test
+        .load_account::<ReserveAccrualHistory>(accrual_history_pubkey)
+        .await;
+    assert_eq!(accrual_history.account.reserve, wsol_reserve.pubkey);
+    assert_eq!(accrual_history.account.epoch_slots, EPOCH_SLOTS);
+    assert!(accrual_history.account.epochs.is_empty());
+
+    for _ in 0..2 {
+        test.advance_clock_by_slots(EPOCH_SLOTS * 2).await;
+        let reserve = test.load_account::<Reserve>(wsol_reserve.pubkey).await;
+        refresh_with_history(&mut test, &reserve).await.unwrap();
+
+        // the opening values are the ones the reserve had at the boundary, not at the refresh
+        let epoch = test.get_clock().await.slot / EPOCH_SLOTS;
+        let mut opening = reserve.account.clone();
+        opening.accrue_interest(epoch * EPOCH_SLOTS).unwrap();
+
+        let accrual_history = test
+            .load_account::<ReserveAccrualHistory>(accrual_history_pubkey)
+            .await;
+        let latest = accrual_history.account.latest_epoch().unwrap();
+        assert_eq!(latest.epoch, epoch);
+        assert_eq!(
+            latest.cumulative_borrow_rate_wads,
+            opening.liquidity.cumulative_borrow_rate_wads
+        );
+        assert_eq!(
+            latest.accumulated_protocol_fees_wads,
+            opening.liquidity.accumulated_protocol_fees_wads
+        );
+    }
+
+    // the epochs between the two refreshes are filled in
+    let accrual_history = test
+        .load_account::<ReserveAccrualHistory>(accrual_history_pubkey)
+        .await
+        .account;
+    let epochs: Vec<u64> = accrual_history
+        .epochs
+        .iter()
+        .map(|accrual| accrual.epoch)
+        .collect();
+    assert_eq!(epochs.len(), 3);
+    assert!(epochs.windows(2).all(|pair| pair[1] == pair[0] + 1));
+    assert!(
+        accrual_history
+            .epoch_borrow_interest_rate(epochs[0])
+            .unwrap()
+            .unwrap()
+            > Rate::zero()
+    );
+
+    // changing the epoch length starts over
+    set_accrual_epoch(
+        &mut test,
+        &lending_market,
+        &wsol_reserve,
+        &lending_market_owner.keypair,
+        EPOCH_SLOTS * 2,
+    )
+    .await
+    .unwrap();
+    let accrual_history = test
+        .load_account::<ReserveAccrualHistory>(accrual_history_pubkey)
+        .await;
+    assert_eq!(accrual_history.account.epoch_slots, EPOCH_SLOTS * 2);
+    assert!(accrual_history.account.epochs.is_empty());
+}
+
+#[tokio::test]
+async fn test_fail_set_reserve_accrual_epoch() {
+    let (mut test, lending_market, _, wsol_reserve, _, _, lending_market_owner) =
+        scenario_1(&test_reserve_config(), &test_reserve_config()).await;
+
+    // only the lending market owner can enable the history
+    let impostor = Keypair::new();
+    let res = set_accrual_epoch(
+        &mut test,
+        &lending_market,
+        &wsol_reserve,
+        &impostor,
+        EPOCH_SLOTS,
+    )
+    .await;
+    expect_lending_error(res, LendingError::InvalidMarketOwner);
+
+    let res = set_accrual_epoch(
+        &mut test,
+        &lending_market,
+        &wsol_reserve,
+        &lending_market_owner.keypair,
+        0,
+    )
+    .await;
+    expect_lending_error(res, LendingError::InvalidConfig);
+
+    // the history has to exist before refreshes can record into it
+    let res = refresh_with_history(&mut test, &wsol_reserve).await;
+    expect_lending_error(res, LendingError::InvalidAccountInput);
+}
@@ -0,0 +1,280 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use crate::solend_program_test::*;
+use helpers::*;
+use solana_program::native_token::LAMPORTS_PER_SOL;
+use solana_program_test::*;
+use solana_sdk::{
+    instruction::InstructionError, signature::Keypair, signature::Signer,
+    transaction::TransactionError,
+};
+use solend_program::{
+    error::LendingError,
+    instruction::{close_reserve, wind_down_reserve},
+    math::{Decimal, WAD},
+    state::*,
+};
+
+async fn wind_down(
+    test: &mut SolendProgramTest,
+    lending_market: &Info<LendingMarket>,
+    reserve: &Info<Reserve>,
+    signer: &Keypair,
+    ltv_decay_per_slot_wad: u64,
+) -> Result<(), BanksClientError> {
+    test.process_transaction(
+        &[wind_down_reserve(
+            solend_program::id(),
+            ltv_decay_per_slot_wad,
+            reserve.pubkey,
+            lending_market.pubkey,
+            signer.pubkey(),
+        )],
+        Some(&[signer]),
+    )
+    .await
+}
+
+async fn close(
+    test: &mut SolendProgramTest,
+    lending_market: &Info<LendingMarket>,
+    reserve: &Info<Reserve>,
+    signer: &Keypair,
+) -> Result<(), BanksClientError> {
+    test.process_transaction(
+        &[close_reserve(
+            solend_program::id(),
+            reserve.pubkey,
+            lending_market.pubkey,
+            signer.pubkey(),
+        )],
+        Some(&[signer]),
+    )
+    .await
+}
+
+#[tokio::test]
+async fn test_ltv_decays_until_closed() {
+    let (
+        mut test,
+        lending_market,
+        usdc_reserve,
+        _wsol_reserve,
+        _user,
+        obligation,
+        lending_market_owner,
+    ) = scenario_1(&test_reserve_config(), &test_reserve_config()).await;
+
+    // 100k USDC at 50% LTV
+    assert_eq!(
+        obligation.account.allowed_borrow_value,
+        Decimal::from(50_000u64)
+    );
+
+    // 0.01% every slot
+    wind_down(
+        &mut test,
+        &lending_market,
+        &usdc_reserve,
+        &lending_market_owner.keypair,
+        WAD / 10_000,
+    )
+    .await
+    .unwrap();
+
+    test.advance_clock_by_slots(1000).await;
+    lending_market
+        .refresh_obligation(&mut test, &obligation)
+        .await
+        .unwrap();
+
+    let obligation_post = test.load_account::<Obligation>(obligation.pubkey).await;
+    assert_eq!(
+        obligation_post.account.allowed_borrow_value,
+        Decimal::from(40_000u64)
+    );
+
+    // the liquidation threshold doesn't decay
+    assert_eq!(
+        obligation_post.account.unhealthy_borrow_value,
+        obligation.account.unhealthy_borrow_value
+    );
+
+    close(
+        &mut test,
+        &lending_market,
+        &usdc_reserve,
+        &lending_market_owner.keypair,
+    )
+    .await
+    .unwrap();
+
+    let usdc_reserve_post = test.load_account::<Reserve>(usdc_reserve.pubkey).await;
+    assert_eq!(usdc_reserve_post.account.status, ReserveStatus::Closed);
+
+    test.advance_clock_by_slots(1).await;
+    lending_market
+        .refresh_obligation(&mut test, &obligation)
+        .await
+        .unwrap();
+
+    let obligation_post = test.load_account::<Obligation>(obligation.pubkey).await;
+    assert_eq!(
+        obligation_post.account.allowed_borrow_value,
+        Decimal::zero()
+    );
+}
+
+#[tokio::test]
+async fn test_wind_down_blocks_deposits_and_borrows() {
+    let (
+        mut test,
+        lending_market,
+        _usdc_reserve,
+        wsol_reserve,
+        user,
+        obligation,
+        lending_market_owner,
+    ) = scenario_1(&test_reserve_config(), &test_reserve_config()).await;
+
+    // a decay of 0 only stops deposits and borrows
+    wind_down(
+        &mut test,
+        &lending_market,
+        &wsol_reserve,
+        &lending_market_owner.keypair,
+        0,
+    )
+    .await
+    .unwrap();
+
+    test.advance_clock_by_slots(1).await;
+
+    let wsol_depositor = User::new_with_balances(
+        &mut test,
+        &[
+            (&wsol_mint::id(), LAMPORTS_PER_SOL),
+            (&wsol_reserve.account.collateral.mint_pubkey, 0),
+        ],
+    )
+    .await;
+
+    let res = lending_market
+        .deposit(&mut test, &wsol_reserve, &wsol_depositor, LAMPORTS_PER_SOL)
+        .await;
+
+    expect_lending_error(res, LendingError::ReserveNotActive);
+
+    let res = lending_market
+        .borrow_obligation_liquidity(&mut test, &wsol_reserve, &obligation, &user, None, 1)
+        .await;
+
+    expect_lending_error(res, LendingError::ReserveNotActive);
+
+    // repaying still works and interest keeps accruing
+    lending_market
+        .repay_obligation_liquidity(
+            &mut test,
+            &wsol_reserve,
+            &obligation,
+            &user,
+            LAMPORTS_PER_SOL,
+        )
+        .await
+        .unwrap();
+
+    let wsol_reserve_post = test.load_account::<Reserve>(wsol_reserve.pubkey).await;
+    assert!(
+        wsol_reserve_post
+            .account
+            .liquidity
+            .cumulative_borrow_rate_wads
+            > wsol_reserve.account.liquidity.cumulative_borrow_rate_wads
+    );
+}
+
+#[tokio::test]
+async fn test_invalid_status_transitions() {
+    let (
+        mut test,
+        lending_market,
+        usdc_reserve,
+        _wsol_reserve,
+        user,
+        _obligation,
+        lending_market_owner,
+    ) = scenario_1(&test_reserve_config(), &test_reserve_config()).await;
+
+    let expect_err = |res: Result<(), BanksClientError>, err: LendingError| {
+        assert_eq!(
+            res.unwrap_err().unwrap(),
+            TransactionError::InstructionError(0, InstructionError::Custom(err as u32))
+        );
+    };
+
+    // only the owner or risk authority can wind down a reserve
+    expect_err(
+        wind_down(&mut test, &lending_market, &usdc_reserve, &user.keypair, 1).await,
+        LendingError::InvalidSigner,
+    );
+
+    // active reserves have to wind down before closing
+    expect_err(
+        close(
+            &mut test,
+            &lending_market,
+            &usdc_reserve,
+            &lending_market_owner.keypair,
+        )
+        .await,
+        LendingError::InvalidConfig,
+    );
+
+    wind_down(
+        &mut test,
+        &lending_market,
+        &usdc_reserve,
+        &lending_market_owner.keypair,
+        100,
+    )
+    .await
+    .unwrap();
+
+    // the decay can't be slowed down
+    expect_err(
+        wind_down(
+            &mut test,
+            &lending_market,
+            &usdc_reserve,
+            &lending_market_owner.keypair,
+            99,
+        )
+        .await,
+        LendingError::InvalidConfig,
+    );
+
+    close(
+        &mut test,
+        &lending_market,
+        &usdc_reserve,
+        &lending_market_owner.keypair,
+    )
+    .await
+    .unwrap();
+
+    // closed is final
+    test.advance_clock_by_slots(1).await;
+    expect_err(
+        wind_down(
+            &mut test,
+            &lending_market,
+            &usdc_reserve,
+            &lending_market_owner.keypair,
+            100,
+        )
+        .await,
+        LendingError::InvalidConfig,
+    );
+}
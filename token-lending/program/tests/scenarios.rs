@@ -0,0 +1,111 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use crate::solend_program_test::{ObligationArgs, PriceArgs, ReserveArgs};
+use helpers::scenario::Scenario;
+use helpers::*;
+use solana_program::native_token::LAMPORTS_PER_SOL;
+use solana_program_test::*;
+use solend_program::{
+    error::LendingError,
+    math::Decimal,
+    state::{ReserveConfig, ReserveFees},
+};
+
+fn price(price: i64, expo: i32) -> PriceArgs {
+    PriceArgs {
+        price,
+        conf: 0,
+        expo,
+        ema_price: price,
+        ema_conf: 0,
+    }
+}
+
+fn usdc_wsol_scenario() -> Scenario {
+    Scenario::new()
+        .reserve(ReserveArgs {
+            mint: usdc_mint::id(),
+            config: ReserveConfig {
+                liquidation_bonus: 0,
+                max_liquidation_bonus: 0,
+                protocol_liquidation_fee: 0,
+                ..test_reserve_config()
+            },
+            liquidity_amount: 100_000 * FRACTIONAL_TO_USDC,
+            price: price(10, -1),
+        })
+        .reserve(ReserveArgs {
+            mint: wsol_mint::id(),
+            config: ReserveConfig {
+                fees: ReserveFees::default(),
+                optimal_borrow_rate: 0,
+                max_borrow_rate: 0,
+                ..test_reserve_config()
+            },
+            liquidity_amount: LAMPORTS_PER_SOL,
+            price: price(10, 0),
+        })
+        .obligation(ObligationArgs {
+            deposits: vec![(usdc_mint::id(), 20 * FRACTIONAL_TO_USDC)],
+            borrows: vec![(wsol_mint::id(), LAMPORTS_PER_SOL)],
+        })
+        .obligation(ObligationArgs {
+            deposits: vec![(wsol_mint::id(), LAMPORTS_PER_SOL)],
+            borrows: vec![],
+        })
+}
+
+#[tokio::test]
+async fn test_depeg_liquidate_forgive_withdraw() {
+    let state = usdc_wsol_scenario()
+        // USDC depegs to 0.1
+        .set_price(usdc_mint::id(), price(1, -1))
+        .advance_slots(1)
+        .forgive_debt(0, wsol_mint::id(), u64::MAX)
+        .expect_error(LendingError::InvalidAccountInput)
+        // 0.2 SOL is repaid, 2 USDC is withdrawn
+        .liquidate(0, wsol_mint::id(), usdc_mint::id(), u64::MAX)
+        .check(|state| assert!(state.obligation(0).account.deposits.is_empty()))
+        .advance_slots(1)
+        .forgive_debt(0, wsol_mint::id(), u64::MAX)
+        .check(|state| {
+            assert!(state.obligation(0).account.borrows.is_empty());
+            assert_eq!(
+                state
+                    .reserve(&wsol_mint::id())
+                    .account
+                    .liquidity
+                    .lifetime_forgiven_amount_wads,
+                Decimal::from(LAMPORTS_PER_SOL * 4 / 5)
+            );
+        })
+        .advance_slots(1)
+        .withdraw(1, wsol_mint::id(), u64::MAX)
+        .run()
+        .await;
+
+    // 0.8 SOL is forgiven out of 2 SOL, so the depositor takes a 40% haircut
+    let mut test = state.test;
+    assert_eq!(
+        state.users[1]
+            .get_balance(&mut test, &wsol_mint::id())
+            .await,
+        Some(LAMPORTS_PER_SOL * 6 / 10)
+    );
+}
+
+#[tokio::test]
+async fn test_repay_before_depeg() {
+    usdc_wsol_scenario()
+        .repay(0, wsol_mint::id(), u64::MAX)
+        .check(|state| assert!(state.obligation(0).account.borrows.is_empty()))
+        .set_price(usdc_mint::id(), price(1, -1))
+        .advance_slots(1)
+        .liquidate(0, wsol_mint::id(), usdc_mint::id(), u64::MAX)
+        .expect_error(LendingError::ObligationBorrowsZero)
+        .withdraw(0, usdc_mint::id(), u64::MAX)
+        .run()
+        .await;
+}
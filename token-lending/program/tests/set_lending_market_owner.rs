@@ -10,16 +10,23 @@ use helpers::*;
 use solana_program::instruction::{AccountMeta, Instruction};
 use solana_program_test::*;
 use solana_sdk::{
-    instruction::InstructionError,
     pubkey::Pubkey,
     signature::{Keypair, Signer},
-    transaction::TransactionError,
 };
 use solend_program::state::LendingMarket;
 use solend_program::state::RateLimiterConfig;
+use solend_program::state::Reserve;
+use solend_program::state::MAX_SLOTS_PER_YEAR;
+use solend_program::state::SLOTS_PER_YEAR;
 use solend_sdk::state::RateLimiter;
 
-use solend_program::{error::LendingError, instruction::LendingInstruction};
+use solend_program::{
+    error::LendingError,
+    instruction::{
+        refresh_reserve, set_lending_market_owner_and_config_with_slots_per_year,
+        LendingInstruction,
+    },
+};
 
 async fn setup() -> (SolendProgramTest, Info<LendingMarket>, User) {
     let (test, lending_market, _usdc_reserve, _, lending_market_owner, _user) =
@@ -28,6 +35,28 @@ async fn setup() -> (SolendProgramTest, Info<LendingMarket>, User) {
     (test, lending_market, lending_market_owner)
 }
 
+async fn set_slots_per_year(
+    test: &mut SolendProgramTest,
+    lending_market: &Info<LendingMarket>,
+    lending_market_owner: &User,
+    slots_per_year: u64,
+) -> Result<(), BanksClientError> {
+    test.process_transaction(
+        &[set_lending_market_owner_and_config_with_slots_per_year(
+            solend_program::id(),
+            lending_market.pubkey,
+            lending_market_owner.keypair.pubkey(),
+            lending_market.account.owner,
+            lending_market.account.rate_limiter.config,
+            lending_market.account.whitelisted_liquidator,
+            lending_market.account.risk_authority,
+            slots_per_year,
+        )],
+        Some(&[&lending_market_owner.keypair]),
+    )
+    .await
+}
+
 #[tokio::test]
 async fn test_success() {
     let (mut test, lending_market, lending_market_owner) = setup().await;
@@ -82,17 +111,9 @@ async fn test_invalid_owner() {
             None,
             new_risk_authority.pubkey(),
         )
-        .await
-        .unwrap_err()
-        .unwrap();
+        .await;
 
-    assert_eq!(
-        res,
-        TransactionError::InstructionError(
-            0,
-            InstructionError::Custom(LendingError::InvalidMarketOwner as u32)
-        )
-    );
+    expect_lending_error(res, LendingError::InvalidMarketOwner);
 }
 
 #[tokio::test]
@@ -113,20 +134,92 @@ async fn test_owner_not_signer() {
                     rate_limiter_config: RateLimiterConfig::default(),
                     whitelisted_liquidator: None,
                     risk_authority: new_risk_authority.pubkey(),
+                    slots_per_year: None,
                 }
                 .pack(),
             }],
             None,
         )
+        .await;
+
+    expect_lending_error(res, LendingError::InvalidSigner);
+}
+
+#[tokio::test]
+async fn test_set_slots_per_year() {
+    let (mut test, lending_market, usdc_reserve, _, lending_market_owner, _user) =
+        setup_world(&test_reserve_config(), &test_reserve_config()).await;
+    assert_eq!(lending_market.account.slots_per_year, SLOTS_PER_YEAR);
+    assert_eq!(usdc_reserve.account.slots_per_year, SLOTS_PER_YEAR);
+
+    set_slots_per_year(
+        &mut test,
+        &lending_market,
+        &lending_market_owner,
+        MAX_SLOTS_PER_YEAR,
+    )
+    .await
+    .unwrap();
+
+    let lending_market = test
+        .load_account::<LendingMarket>(lending_market.pubkey)
+        .await;
+    assert_eq!(lending_market.account.slots_per_year, MAX_SLOTS_PER_YEAR);
+
+    // leaving slots per year out of the instruction keeps the current value
+    lending_market
+        .set_lending_market_owner_and_config(
+            &mut test,
+            &lending_market_owner,
+            &lending_market.account.owner,
+            lending_market.account.rate_limiter.config,
+            lending_market.account.whitelisted_liquidator,
+            lending_market.account.risk_authority,
+        )
         .await
-        .unwrap_err()
         .unwrap();
+    let lending_market = test
+        .load_account::<LendingMarket>(lending_market.pubkey)
+        .await;
+    assert_eq!(lending_market.account.slots_per_year, MAX_SLOTS_PER_YEAR);
 
-    assert_eq!(
-        res,
-        TransactionError::InstructionError(
-            0,
-            InstructionError::Custom(LendingError::InvalidSigner as u32)
-        )
+    // refreshing without the lending market, here only passing in pyth, keeps the reserve's value
+    test.advance_clock_by_slots(1).await;
+    let mut refresh = refresh_reserve(
+        solend_program::id(),
+        usdc_reserve.pubkey,
+        usdc_reserve.account.liquidity.pyth_oracle_pubkey,
+        usdc_reserve.account.liquidity.switchboard_oracle_pubkey,
     );
+    refresh.accounts.truncate(2);
+    test.process_transaction(&[refresh], None).await.unwrap();
+    let usdc_reserve_post = test.load_account::<Reserve>(usdc_reserve.pubkey).await;
+    assert_eq!(usdc_reserve_post.account.slots_per_year, SLOTS_PER_YEAR);
+
+    // reserves accrue at the market's value from their next refresh with the lending market
+    test.advance_clock_by_slots(1).await;
+    lending_market
+        .refresh_reserve(&mut test, &usdc_reserve)
+        .await
+        .unwrap();
+
+    let usdc_reserve_post = test.load_account::<Reserve>(usdc_reserve.pubkey).await;
+    assert_eq!(usdc_reserve_post.account.slots_per_year, MAX_SLOTS_PER_YEAR);
+}
+
+#[tokio::test]
+async fn test_invalid_slots_per_year() {
+    let (mut test, lending_market, lending_market_owner) = setup().await;
+
+    for slots_per_year in [0, MAX_SLOTS_PER_YEAR + 1] {
+        let res = set_slots_per_year(
+            &mut test,
+            &lending_market,
+            &lending_market_owner,
+            slots_per_year,
+        )
+        .await;
+
+        expect_lending_error(res, LendingError::InvalidConfig);
+    }
 }
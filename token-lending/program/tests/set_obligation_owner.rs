@@ -0,0 +1,276 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use crate::solend_program_test::*;
+use helpers::*;
+use solana_program::native_token::LAMPORTS_PER_SOL;
+use solana_program_test::*;
+use solana_sdk::{
+    instruction::InstructionError,
+    signature::{Keypair, Signer},
+    transaction::TransactionError,
+};
+use solend_program::{
+    error::LendingError,
+    instruction::{approve_borrow_allowance, find_borrow_allowances_address, set_obligation_owner},
+    state::{
+        BorrowAllowances, LendingMarket, Obligation, RateLimiterConfig, Reserve, ReserveConfig,
+    },
+};
+
+struct Setup {
+    test: SolendProgramTest,
+    lending_market: Info<LendingMarket>,
+    usdc_reserve: Info<Reserve>,
+    wsol_reserve: Info<Reserve>,
+    lending_market_owner: User,
+    user: User,
+    obligation: Info<Obligation>,
+}
+
+// an obligation with 100 USDC of collateral, in a market with 5 SOL to borrow
+async fn setup(wsol_reserve_config: &ReserveConfig) -> Setup {
+    let (mut test, lending_market, usdc_reserve, wsol_reserve, lending_market_owner, user) =
+        setup_world(&test_reserve_config(), wsol_reserve_config).await;
+
+    let obligation = lending_market
+        .init_obligation(&mut test, Keypair::new(), &user)
+        .await
+        .unwrap();
+    lending_market
+        .deposit(&mut test, &usdc_reserve, &user, 100_000_000)
+        .await
+        .unwrap();
+    let usdc_reserve = test.load_account(usdc_reserve.pubkey).await;
+    lending_market
+        .deposit_obligation_collateral(&mut test, &usdc_reserve, &obligation, &user, 100_000_000)
+        .await
+        .unwrap();
+
+    let wsol_depositor = User::new_with_balances(
+        &mut test,
+        &[
+            (&wsol_mint::id(), 5 * LAMPORTS_PER_SOL),
+            (&wsol_reserve.account.collateral.mint_pubkey, 0),
+        ],
+    )
+    .await;
+    lending_market
+        .deposit(
+            &mut test,
+            &wsol_reserve,
+            &wsol_depositor,
+            5 * LAMPORTS_PER_SOL,
+        )
+        .await
+        .unwrap();
+
+    let usdc_reserve = test.load_account(usdc_reserve.pubkey).await;
+    let wsol_reserve = test.load_account(wsol_reserve.pubkey).await;
+    let obligation = test.load_account(obligation.pubkey).await;
+    Setup {
+        test,
+        lending_market,
+        usdc_reserve,
+        wsol_reserve,
+        lending_market_owner,
+        user,
+        obligation,
+    }
+}
+
+async fn set_owner(setup: &mut Setup, new_owner: &User) -> Result<(), BanksClientError> {
+    setup
+        .test
+        .process_transaction(
+            &[set_obligation_owner(
+                solend_program::id(),
+                setup.obligation.pubkey,
+                setup.lending_market.pubkey,
+                setup.user.keypair.pubkey(),
+                new_owner.keypair.pubkey(),
+            )],
+            Some(&[&setup.user.keypair, &new_owner.keypair]),
+        )
+        .await
+}
+
+#[tokio::test]
+async fn test_success() {
+    let mut setup = setup(&test_reserve_config()).await;
+
+    let delegate = Keypair::new();
+    setup
+        .test
+        .process_transaction(
+            &[approve_borrow_allowance(
+                solend_program::id(),
+                LAMPORTS_PER_SOL,
+                setup.obligation.pubkey,
+                setup.wsol_reserve.pubkey,
+                delegate.pubkey(),
+                setup.user.keypair.pubkey(),
+            )],
+            Some(&[&setup.user.keypair]),
+        )
+        .await
+        .unwrap();
+
+    let new_owner = User::new_with_balances(&mut setup.test, &[]).await;
+    set_owner(&mut setup, &new_owner).await.unwrap();
+
+    let obligation = setup
+        .test
+        .load_account::<Obligation>(setup.obligation.pubkey)
+        .await;
+    assert_eq!(obligation.account.owner, new_owner.keypair.pubkey());
+    assert_eq!(
+        obligation.account.deposits,
+        setup.obligation.account.deposits
+    );
+
+    // allowances granted by the previous owner are revoked
+    let (borrow_allowances_pubkey, _) =
+        find_borrow_allowances_address(&solend_program::id(), &setup.obligation.pubkey);
+    let borrow_allowances = setup
+        .test
+        .load_account::<BorrowAllowances>(borrow_allowances_pubkey)
+        .await;
+    assert!(borrow_allowances.account.allowances.is_empty());
+
+    // the previous owner can't withdraw anymore
+    let res = setup
+        .lending_market
+        .withdraw_obligation_collateral(
+            &mut setup.test,
+            &setup.usdc_reserve,
+            &obligation,
+            &setup.user,
+            1,
+        )
+        .await;
+    expect_lending_error(res, LendingError::InvalidObligationOwner);
+}
+
+#[tokio::test]
+async fn test_fail_new_owner_not_signer() {
+    let mut setup = setup(&test_reserve_config()).await;
+
+    let new_owner = Keypair::new();
+    let mut instruction = set_obligation_owner(
+        solend_program::id(),
+        setup.obligation.pubkey,
+        setup.lending_market.pubkey,
+        setup.user.keypair.pubkey(),
+        new_owner.pubkey(),
+    );
+    instruction.accounts[3].is_signer = false;
+
+    let res = setup
+        .test
+        .process_transaction(&[instruction], Some(&[&setup.user.keypair]))
+        .await;
+    expect_lending_error(res, LendingError::InvalidSigner);
+}
+
+#[tokio::test]
+async fn test_fail_without_borrow_allowances() {
+    let mut setup = setup(&test_reserve_config()).await;
+
+    // skipping the allowances account would keep the previous owner's delegates around
+    let new_owner = User::new_with_balances(&mut setup.test, &[]).await;
+    let mut instruction = set_obligation_owner(
+        solend_program::id(),
+        setup.obligation.pubkey,
+        setup.lending_market.pubkey,
+        setup.user.keypair.pubkey(),
+        new_owner.keypair.pubkey(),
+    );
+    instruction.accounts.pop();
+
+    let res = setup
+        .test
+        .process_transaction(
+            &[instruction],
+            Some(&[&setup.user.keypair, &new_owner.keypair]),
+        )
+        .await
+        .unwrap_err()
+        .into_transaction_error();
+    assert_eq!(
+        res,
+        TransactionError::InstructionError(0, InstructionError::NotEnoughAccountKeys)
+    );
+
+    // nor can another account stand in for it
+    let mut instruction = set_obligation_owner(
+        solend_program::id(),
+        setup.obligation.pubkey,
+        setup.lending_market.pubkey,
+        setup.user.keypair.pubkey(),
+        new_owner.keypair.pubkey(),
+    );
+    instruction.accounts[4].pubkey = Keypair::new().pubkey();
+
+    let res = setup
+        .test
+        .process_transaction(
+            &[instruction],
+            Some(&[&setup.user.keypair, &new_owner.keypair]),
+        )
+        .await;
+    expect_lending_error(res, LendingError::InvalidAccountInput);
+}
+
+#[tokio::test]
+async fn test_fail_whitelisted_liquidator() {
+    let mut setup = setup(&test_reserve_config()).await;
+
+    let liquidator = User::new_with_balances(&mut setup.test, &[]).await;
+    setup
+        .lending_market
+        .set_lending_market_owner_and_config(
+            &mut setup.test,
+            &setup.lending_market_owner,
+            &setup.lending_market_owner.keypair.pubkey(),
+            RateLimiterConfig::default(),
+            Some(liquidator.keypair.pubkey()),
+            setup.lending_market.account.risk_authority,
+        )
+        .await
+        .unwrap();
+
+    let res = set_owner(&mut setup, &liquidator).await;
+    expect_lending_error(res, LendingError::ObligationOwnerChangeBlocked);
+}
+
+#[tokio::test]
+async fn test_fail_locked_fixed_rate() {
+    let mut setup = setup(&ReserveConfig {
+        fixed_borrow_rate_bps: 500,
+        fixed_borrow_max_duration_slots: 1_000,
+        ..test_reserve_config()
+    })
+    .await;
+
+    setup
+        .lending_market
+        .borrow_obligation_liquidity_fixed_rate(
+            &mut setup.test,
+            &setup.wsol_reserve,
+            &setup.obligation,
+            &setup.user,
+            LAMPORTS_PER_SOL,
+        )
+        .await
+        .unwrap();
+
+    let new_owner = User::new_with_balances(&mut setup.test, &[]).await;
+    let res = set_owner(&mut setup, &new_owner).await;
+    expect_lending_error(res, LendingError::ObligationOwnerChangeBlocked);
+
+    // the lock ends when the fixed rate matures
+    setup.test.advance_clock_by_slots(1_000).await;
+    set_owner(&mut setup, &new_owner).await.unwrap();
+}
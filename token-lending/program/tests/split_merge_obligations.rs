@@ -0,0 +1,235 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use crate::solend_program_test::*;
+use helpers::*;
+use solana_program::{native_token::LAMPORTS_PER_SOL, pubkey::Pubkey};
+use solana_program_test::*;
+use solana_sdk::{
+    instruction::InstructionError, signature::Keypair, signature::Signer,
+    transaction::TransactionError,
+};
+use solend_program::{
+    error::LendingError,
+    instruction::{merge_obligations, refresh_reserve, split_obligation},
+    math::Decimal,
+    state::{LendingMarket, Obligation, Reserve},
+};
+
+async fn refresh_reserves(test: &mut SolendProgramTest, reserves: &[&Info<Reserve>]) {
+    let instructions = reserves
+        .iter()
+        .map(|reserve| {
+            refresh_reserve(
+                solend_program::id(),
+                reserve.pubkey,
+                reserve.account.liquidity.pyth_oracle_pubkey,
+                reserve.account.liquidity.switchboard_oracle_pubkey,
+            )
+        })
+        .collect::<Vec<_>>();
+    test.process_transaction(&instructions, None).await.unwrap();
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn split(
+    test: &mut SolendProgramTest,
+    lending_market: &Info<LendingMarket>,
+    user: &User,
+    source: &Info<Obligation>,
+    destination: &Info<Obligation>,
+    deposits: Vec<(Pubkey, u64)>,
+    borrows: Vec<(Pubkey, u64)>,
+    reserves: &[&Info<Reserve>],
+) -> Result<(), TransactionError> {
+    refresh_reserves(test, reserves).await;
+    test.process_transaction(
+        &[split_obligation(
+            solend_program::id(),
+            deposits,
+            borrows,
+            source.pubkey,
+            destination.pubkey,
+            lending_market.pubkey,
+            user.keypair.pubkey(),
+            reserves.iter().map(|reserve| reserve.pubkey).collect(),
+        )],
+        Some(&[&user.keypair]),
+    )
+    .await
+    .map_err(|e| e.unwrap())
+}
+
+#[tokio::test]
+async fn test_split_and_merge() {
+    let (mut test, lending_market, usdc_reserve, wsol_reserve, user, obligation, _) =
+        scenario_1(&test_reserve_config(), &test_reserve_config()).await;
+    let destination = lending_market
+        .init_obligation(&mut test, Keypair::new(), &user)
+        .await
+        .unwrap();
+
+    test.advance_clock_by_slots(1).await;
+    split(
+        &mut test,
+        &lending_market,
+        &user,
+        &obligation,
+        &destination,
+        vec![(usdc_reserve.pubkey, 40_000_000_000)],
+        vec![(wsol_reserve.pubkey, 4 * LAMPORTS_PER_SOL)],
+        &[&usdc_reserve, &wsol_reserve],
+    )
+    .await
+    .unwrap();
+
+    let obligation_post = test.load_account::<Obligation>(obligation.pubkey).await;
+    let destination_post = test.load_account::<Obligation>(destination.pubkey).await;
+    assert_eq!(
+        obligation_post.account.deposits[0].deposited_amount,
+        60_000_000_000
+    );
+    assert_eq!(
+        destination_post.account.deposits[0].deposited_amount,
+        40_000_000_000
+    );
+    assert_eq!(
+        destination_post.account.borrows[0].borrowed_amount_wads,
+        Decimal::from(4 * LAMPORTS_PER_SOL)
+    );
+    // the source keeps the interest accrued since the last refresh
+    assert!(
+        obligation_post.account.borrows[0].borrowed_amount_wads
+            >= Decimal::from(6 * LAMPORTS_PER_SOL)
+    );
+    for obligation in [&obligation_post, &destination_post] {
+        assert!(obligation.account.borrowed_value > Decimal::zero());
+        assert!(
+            obligation.account.borrowed_value_upper_bound
+                <= obligation.account.allowed_borrow_value
+        );
+    }
+
+    // merging everything back leaves the destination empty
+    test.advance_clock_by_slots(1).await;
+    lending_market
+        .refresh_obligation(&mut test, &obligation_post)
+        .await
+        .unwrap();
+    lending_market
+        .refresh_obligation(&mut test, &destination_post)
+        .await
+        .unwrap();
+    test.process_transaction(
+        &[merge_obligations(
+            solend_program::id(),
+            obligation.pubkey,
+            lending_market.pubkey,
+            user.keypair.pubkey(),
+            vec![destination.pubkey],
+        )],
+        Some(&[&user.keypair]),
+    )
+    .await
+    .unwrap();
+
+    let obligation_post = test.load_account::<Obligation>(obligation.pubkey).await;
+    let destination_post = test.load_account::<Obligation>(destination.pubkey).await;
+    assert!(destination_post.account.deposits.is_empty());
+    assert!(destination_post.account.borrows.is_empty());
+    assert_eq!(
+        obligation_post.account.deposits[0].deposited_amount,
+        100_000_000_000
+    );
+    assert_eq!(obligation_post.account.borrows.len(), 1);
+    assert!(
+        obligation_post.account.borrows[0].borrowed_amount_wads
+            >= Decimal::from(10 * LAMPORTS_PER_SOL)
+    );
+    assert!(obligation_post.account.last_update.stale);
+}
+
+#[tokio::test]
+async fn test_fail_split_unhealthy() {
+    let (mut test, lending_market, usdc_reserve, wsol_reserve, user, obligation, _) =
+        scenario_1(&test_reserve_config(), &test_reserve_config()).await;
+    let destination = lending_market
+        .init_obligation(&mut test, Keypair::new(), &user)
+        .await
+        .unwrap();
+
+    // the borrow would be left without collateral
+    let res = split(
+        &mut test,
+        &lending_market,
+        &user,
+        &obligation,
+        &destination,
+        vec![(usdc_reserve.pubkey, u64::MAX)],
+        vec![],
+        &[&usdc_reserve, &wsol_reserve],
+    )
+    .await;
+    expect_lending_error(res, LendingError::ObligationSplitUnhealthy);
+}
+
+#[tokio::test]
+async fn test_fail_split_invalid_destination() {
+    let (mut test, lending_market, usdc_reserve, wsol_reserve, user, obligation, _) =
+        scenario_1(&test_reserve_config(), &test_reserve_config()).await;
+
+    // the destination has to be a different, empty obligation of the same owner
+    let other_user = User::new_with_balances(&mut test, &[]).await;
+    let other_destination = lending_market
+        .init_obligation(&mut test, Keypair::new(), &other_user)
+        .await
+        .unwrap();
+    for (destination, error) in [
+        (&obligation, LendingError::InvalidAccountInput),
+        (&other_destination, LendingError::InvalidObligationOwner),
+    ] {
+        let res = split(
+            &mut test,
+            &lending_market,
+            &user,
+            &obligation,
+            destination,
+            vec![(usdc_reserve.pubkey, 1)],
+            vec![],
+            &[&usdc_reserve, &wsol_reserve],
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(
+            res,
+            TransactionError::InstructionError(0, InstructionError::Custom(error as u32))
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_fail_merge_stale() {
+    let (mut test, lending_market, _usdc_reserve, _wsol_reserve, user, obligation, _) =
+        scenario_1(&test_reserve_config(), &test_reserve_config()).await;
+    let other = lending_market
+        .init_obligation(&mut test, Keypair::new(), &user)
+        .await
+        .unwrap();
+
+    test.advance_clock_by_slots(1).await;
+    let res = test
+        .process_transaction(
+            &[merge_obligations(
+                solend_program::id(),
+                other.pubkey,
+                lending_market.pubkey,
+                user.keypair.pubkey(),
+                vec![obligation.pubkey],
+            )],
+            Some(&[&user.keypair]),
+        )
+        .await;
+
+    expect_lending_error(res, LendingError::ObligationStale);
+}
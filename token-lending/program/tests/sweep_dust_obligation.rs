@@ -0,0 +1,282 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use crate::solend_program_test::*;
+use helpers::*;
+use solana_program::native_token::LAMPORTS_PER_SOL;
+use solana_program::program_pack::Pack;
+use solana_program::sysvar::rent::Rent;
+use solana_program_test::*;
+use solana_sdk::{instruction::Instruction, signature::Keypair, signature::Signer};
+use solend_program::{
+    error::LendingError,
+    instruction::{
+        approve_borrow_allowance, find_borrow_allowances_address, set_dust_sweep_threshold,
+        sweep_dust_obligation,
+    },
+    math::{Decimal, TryAdd, WAD},
+    state::{LendingMarket, Obligation, Reserve},
+};
+
+async fn setup_dust_obligation() -> (
+    SolendProgramTest,
+    Info<LendingMarket>,
+    Info<Reserve>,
+    User,
+    User,
+    Info<Obligation>,
+) {
+    let (mut test, lending_market, usdc_reserve, _wsol_reserve, lending_market_owner, user) =
+        setup_world(&test_reserve_config(), &test_reserve_config()).await;
+
+    let obligation = lending_market
+        .init_obligation(&mut test, Keypair::new(), &user)
+        .await
+        .unwrap();
+
+    // $1 of USDC
+    lending_market
+        .deposit_reserve_liquidity_and_obligation_collateral(
+            &mut test,
+            &usdc_reserve,
+            &obligation,
+            &user,
+            1_000_000,
+        )
+        .await
+        .unwrap();
+
+    test.process_transaction(
+        &[set_dust_sweep_threshold(
+            solend_program::id(),
+            2 * WAD,
+            lending_market.pubkey,
+            lending_market_owner.keypair.pubkey(),
+        )],
+        Some(&[&lending_market_owner.keypair]),
+    )
+    .await
+    .unwrap();
+
+    test.advance_clock_by_slots(1).await;
+    lending_market
+        .refresh_obligation(&mut test, &obligation)
+        .await
+        .unwrap();
+
+    let usdc_reserve = test.load_account::<Reserve>(usdc_reserve.pubkey).await;
+    let obligation = test.load_account::<Obligation>(obligation.pubkey).await;
+    (
+        test,
+        lending_market,
+        usdc_reserve,
+        lending_market_owner,
+        user,
+        obligation,
+    )
+}
+
+fn sweep(
+    lending_market: &Info<LendingMarket>,
+    reserve: &Info<Reserve>,
+    obligation: &Info<Obligation>,
+    signer: &User,
+) -> Instruction {
+    sweep_dust_obligation(
+        solend_program::id(),
+        obligation.pubkey,
+        obligation.account.owner,
+        lending_market.pubkey,
+        signer.keypair.pubkey(),
+        vec![(
+            reserve.pubkey,
+            reserve.account.collateral.mint_pubkey,
+            reserve.account.collateral.supply_pubkey,
+        )],
+    )
+}
+
+#[tokio::test]
+async fn test_success() {
+    let (mut test, lending_market, usdc_reserve, lending_market_owner, user, obligation) =
+        setup_dust_obligation().await;
+
+    // the borrow allowances account is closed along with the obligation
+    test.process_transaction(
+        &[approve_borrow_allowance(
+            solend_program::id(),
+            LAMPORTS_PER_SOL,
+            obligation.pubkey,
+            usdc_reserve.pubkey,
+            Keypair::new().pubkey(),
+            user.keypair.pubkey(),
+        )],
+        Some(&[&user.keypair]),
+    )
+    .await
+    .unwrap();
+    let (borrow_allowances_pubkey, _) =
+        find_borrow_allowances_address(&solend_program::id(), &obligation.pubkey);
+    let borrow_allowances_lamports = test
+        .context
+        .banks_client
+        .get_balance(borrow_allowances_pubkey)
+        .await
+        .unwrap();
+
+    let owner_lamports = test
+        .context
+        .banks_client
+        .get_balance(user.keypair.pubkey())
+        .await
+        .unwrap();
+
+    test.process_transaction(
+        &[sweep(
+            &lending_market,
+            &usdc_reserve,
+            &obligation,
+            &lending_market_owner,
+        )],
+        Some(&[&lending_market_owner.keypair]),
+    )
+    .await
+    .unwrap();
+
+    for pubkey in [obligation.pubkey, borrow_allowances_pubkey] {
+        assert!(test
+            .context
+            .banks_client
+            .get_account(pubkey)
+            .await
+            .unwrap()
+            .is_none());
+    }
+    assert_eq!(
+        test.context
+            .banks_client
+            .get_balance(user.keypair.pubkey())
+            .await
+            .unwrap(),
+        owner_lamports
+            + Rent::default().minimum_balance(Obligation::LEN)
+            + borrow_allowances_lamports
+    );
+
+    // the collateral is burned and its liquidity is claimable as protocol fees
+    let usdc_reserve_post = test.load_account::<Reserve>(usdc_reserve.pubkey).await;
+    assert_eq!(
+        usdc_reserve_post.account.collateral.mint_total_supply,
+        usdc_reserve.account.collateral.mint_total_supply - 1_000_000
+    );
+    assert_eq!(
+        usdc_reserve_post
+            .account
+            .liquidity
+            .accumulated_protocol_fees_wads,
+        usdc_reserve
+            .account
+            .liquidity
+            .accumulated_protocol_fees_wads
+            .try_add(Decimal::from(1_000_000u64))
+            .unwrap()
+    );
+    assert!(usdc_reserve_post.account.last_update.stale);
+}
+
+#[tokio::test]
+async fn test_fail_not_dust() {
+    let (mut test, lending_market, usdc_reserve, lending_market_owner, _user, obligation) =
+        setup_dust_obligation().await;
+
+    test.process_transaction(
+        &[set_dust_sweep_threshold(
+            solend_program::id(),
+            WAD,
+            lending_market.pubkey,
+            lending_market_owner.keypair.pubkey(),
+        )],
+        Some(&[&lending_market_owner.keypair]),
+    )
+    .await
+    .unwrap();
+
+    let res = test
+        .process_transaction(
+            &[sweep(
+                &lending_market,
+                &usdc_reserve,
+                &obligation,
+                &lending_market_owner,
+            )],
+            Some(&[&lending_market_owner.keypair]),
+        )
+        .await;
+
+    expect_lending_error(res, LendingError::ObligationNotDust);
+}
+
+#[tokio::test]
+async fn test_fail_invalid_signer() {
+    let (mut test, lending_market, usdc_reserve, _lending_market_owner, user, obligation) =
+        setup_dust_obligation().await;
+
+    let res = test
+        .process_transaction(
+            &[sweep(&lending_market, &usdc_reserve, &obligation, &user)],
+            Some(&[&user.keypair]),
+        )
+        .await;
+
+    expect_lending_error(res, LendingError::InvalidSigner);
+}
+
+#[tokio::test]
+async fn test_fail_stale_reserve() {
+    let (mut test, lending_market, usdc_reserve, lending_market_owner, user, obligation) =
+        setup_dust_obligation().await;
+
+    // depositing leaves the reserve stale while the obligation is still fresh
+    lending_market
+        .deposit(&mut test, &usdc_reserve, &user, 1_000_000)
+        .await
+        .unwrap();
+
+    let res = test
+        .process_transaction(
+            &[sweep(
+                &lending_market,
+                &usdc_reserve,
+                &obligation,
+                &lending_market_owner,
+            )],
+            Some(&[&lending_market_owner.keypair]),
+        )
+        .await;
+
+    expect_lending_error(res, LendingError::ReserveStale);
+}
+
+#[tokio::test]
+async fn test_fail_missing_derived_account() {
+    let (mut test, lending_market, usdc_reserve, lending_market_owner, _user, obligation) =
+        setup_dust_obligation().await;
+
+    let (borrow_allowances_pubkey, _) =
+        find_borrow_allowances_address(&solend_program::id(), &obligation.pubkey);
+    let mut instruction = sweep(
+        &lending_market,
+        &usdc_reserve,
+        &obligation,
+        &lending_market_owner,
+    );
+    instruction
+        .accounts
+        .retain(|account| account.pubkey != borrow_allowances_pubkey);
+    let res = test
+        .process_transaction(&[instruction], Some(&[&lending_market_owner.keypair]))
+        .await;
+
+    expect_lending_error(res, LendingError::InvalidAccountInput);
+}
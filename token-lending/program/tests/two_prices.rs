@@ -10,8 +10,6 @@ use crate::solend_program_test::PriceArgs;
 use crate::solend_program_test::ReserveArgs;
 use crate::solend_program_test::TokenBalanceChange;
 use solana_program::native_token::LAMPORTS_PER_SOL;
-use solana_sdk::instruction::InstructionError;
-use solana_sdk::transaction::TransactionError;
 use solend_program::error::LendingError;
 
 use solend_program::state::ReserveConfig;
@@ -137,17 +135,9 @@ async fn test_borrow() {
             None,
             u64::MAX,
         )
-        .await
-        .unwrap_err()
-        .unwrap();
+        .await;
 
-    assert_eq!(
-        err,
-        TransactionError::InstructionError(
-            1,
-            InstructionError::Custom(LendingError::BorrowTooLarge as u32)
-        )
-    );
+    expect_lending_error(err, LendingError::BorrowTooLarge);
 }
 
 #[tokio::test]
@@ -282,17 +272,9 @@ async fn test_withdraw() {
                 &users[0],
                 u64::MAX,
             )
-            .await
-            .unwrap_err()
-            .unwrap();
-
-        assert_eq!(
-            err,
-            TransactionError::InstructionError(
-                1,
-                InstructionError::Custom(LendingError::WithdrawTooLarge as u32)
-            )
-        );
+            .await;
+
+        expect_lending_error(err, LendingError::WithdrawTooLarge);
     }
 }
 
@@ -369,17 +351,9 @@ async fn test_liquidation_doesnt_use_smoothed_price() {
             &users[0],
             u64::MAX,
         )
-        .await
-        .unwrap_err()
-        .unwrap();
+        .await;
 
-    assert_eq!(
-        err,
-        TransactionError::InstructionError(
-            1,
-            InstructionError::Custom(LendingError::ObligationHealthy as u32)
-        )
-    );
+    expect_lending_error(err, LendingError::ObligationHealthy);
 
     test.set_price(
         &usdc_mint::id(),
@@ -405,17 +379,9 @@ async fn test_liquidation_doesnt_use_smoothed_price() {
             &users[0],
             u64::MAX,
         )
-        .await
-        .unwrap_err()
-        .unwrap();
+        .await;
 
-    assert_eq!(
-        err,
-        TransactionError::InstructionError(
-            1,
-            InstructionError::Custom(LendingError::ObligationHealthy as u32)
-        )
-    );
+    expect_lending_error(err, LendingError::ObligationHealthy);
 
     // now set the spot prices. this time, the liquidation should actually work
     test.set_price(
@@ -14,11 +14,7 @@ use crate::solend_program_test::User;
 use helpers::*;
 use solana_program::example_mocks::solana_sdk::Pubkey;
 use solana_program_test::*;
-use solana_sdk::{
-    instruction::InstructionError,
-    signature::{Keypair, Signer},
-    transaction::TransactionError,
-};
+use solana_sdk::signature::{Keypair, Signer};
 use solend_program::state::RateLimiter;
 use solend_program::state::RateLimiterConfig;
 use solend_program::state::Reserve;
@@ -200,17 +196,9 @@ async fn test_update_invalid_oracle_config() {
                 switchboard_feed_pubkey: Some(NULL_PUBKEY),
             }),
         )
-        .await
-        .unwrap_err()
-        .unwrap();
+        .await;
 
-    assert_eq!(
-        res,
-        TransactionError::InstructionError(
-            1,
-            InstructionError::Custom(LendingError::InvalidOracleConfig as u32)
-        )
-    );
+    expect_lending_error(res, LendingError::InvalidOracleConfig);
 
     // this should be fine
     lending_market
@@ -263,17 +251,9 @@ async fn test_update_invalid_oracle_config() {
                 switchboard_feed_pubkey: Some(NULL_PUBKEY),
             }),
         )
-        .await
-        .unwrap_err()
-        .unwrap();
+        .await;
 
-    assert_eq!(
-        res,
-        TransactionError::InstructionError(
-            1,
-            InstructionError::Custom(LendingError::InvalidOracleConfig as u32)
-        )
-    );
+    expect_lending_error(res, LendingError::InvalidOracleConfig);
 }
 
 #[tokio::test]
@@ -329,17 +309,9 @@ async fn test_update_reserve_config_invalid_signers() {
             new_rate_limiter_config,
             None,
         )
-        .await
-        .unwrap_err()
-        .unwrap();
+        .await;
 
-    assert_eq!(
-        res,
-        TransactionError::InstructionError(
-            1,
-            InstructionError::Custom(LendingError::InvalidSigner as u32)
-        )
-    );
+    expect_lending_error(res, LendingError::InvalidSigner);
 
     // case 2: try to update without signing
     let err = test
@@ -361,17 +333,9 @@ async fn test_update_reserve_config_invalid_signers() {
             )],
             None,
         )
-        .await
-        .unwrap_err()
-        .unwrap();
+        .await;
 
-    assert_eq!(
-        err,
-        TransactionError::InstructionError(
-            0,
-            InstructionError::Custom(LendingError::InvalidSigner as u32)
-        )
-    );
+    expect_lending_error(err, LendingError::InvalidSigner);
 }
 
 /// Creates an Malicious 'UpdateReserveConfig' instruction (no signer needed)
@@ -0,0 +1,211 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use crate::solend_program_test::*;
+use helpers::*;
+use solana_program::{instruction::InstructionError, program_error::ProgramError};
+use solana_program_test::*;
+use solana_sdk::{signature::Signer, transaction::TransactionError};
+use solend_program::{
+    instruction::{refresh_obligation, refresh_reserve},
+    state::*,
+};
+use vault::{
+    find_obligation_address, find_vault_authority_address,
+    instruction::{deposit, init_vault, withdraw},
+};
+
+const DEPOSIT_AMOUNT: u64 = 100 * FRACTIONAL_TO_USDC;
+
+async fn setup() -> (SolendProgramTest, Info<LendingMarket>, Info<Reserve>, User) {
+    let (mut test, lending_market, reserves, _obligations, _users, _lending_market_owner) =
+        custom_scenario(
+            &[ReserveArgs {
+                mint: usdc_mint::id(),
+                config: test_reserve_config(),
+                liquidity_amount: 100_000 * FRACTIONAL_TO_USDC,
+                price: PriceArgs {
+                    price: 1,
+                    conf: 0,
+                    expo: 0,
+                    ema_price: 1,
+                    ema_conf: 0,
+                },
+            }],
+            &[],
+        )
+        .await;
+
+    let usdc_reserve = reserves[0].clone();
+    let user = User::new_with_balances(
+        &mut test,
+        &[
+            (&usdc_mint::id(), 1_000 * FRACTIONAL_TO_USDC),
+            (&usdc_reserve.account.collateral.mint_pubkey, 0),
+        ],
+    )
+    .await;
+
+    (test, lending_market, usdc_reserve, user)
+}
+
+async fn init(
+    test: &mut SolendProgramTest,
+    lending_market: &Info<LendingMarket>,
+    user: &User,
+) -> Result<(), BanksClientError> {
+    let payer = test.context.payer.pubkey();
+    test.process_transaction(
+        &[init_vault(
+            vault::id(),
+            solend_program::id(),
+            user.keypair.pubkey(),
+            payer,
+            lending_market.pubkey,
+        )],
+        Some(&[&user.keypair]),
+    )
+    .await
+}
+
+#[tokio::test]
+async fn test_vault_lifecycle() {
+    let (mut test, lending_market, usdc_reserve, user) = setup().await;
+
+    init(&mut test, &lending_market, &user).await.unwrap();
+
+    let (vault_authority, _) =
+        find_vault_authority_address(&vault::id(), &lending_market.pubkey, &user.keypair.pubkey());
+    let (obligation_pubkey, _) = find_obligation_address(&vault::id(), &vault_authority);
+    let obligation = test.load_account::<Obligation>(obligation_pubkey).await;
+    assert_eq!(obligation.account.owner, vault_authority);
+    assert_eq!(obligation.account.lending_market, lending_market.pubkey);
+
+    // deposit
+    test.process_transaction(
+        &[deposit(
+            vault::id(),
+            solend_program::id(),
+            DEPOSIT_AMOUNT,
+            user.keypair.pubkey(),
+            user.get_account(&usdc_mint::id()).unwrap(),
+            user.get_account(&usdc_reserve.account.collateral.mint_pubkey)
+                .unwrap(),
+            usdc_reserve.pubkey,
+            &usdc_reserve.account,
+        )],
+        Some(&[&user.keypair]),
+    )
+    .await
+    .unwrap();
+
+    let obligation = test.load_account::<Obligation>(obligation_pubkey).await;
+    assert_eq!(obligation.account.deposits.len(), 1);
+    assert_eq!(
+        obligation.account.deposits[0].deposit_reserve,
+        usdc_reserve.pubkey
+    );
+    assert_eq!(
+        user.get_balance(&mut test, &usdc_mint::id()).await,
+        Some(1_000 * FRACTIONAL_TO_USDC - DEPOSIT_AMOUNT)
+    );
+
+    // withdraw everything back to the user
+    test.advance_clock_by_slots(1).await;
+    test.process_transaction(
+        &[
+            refresh_reserve(
+                solend_program::id(),
+                usdc_reserve.pubkey,
+                usdc_reserve.account.liquidity.pyth_oracle_pubkey,
+                usdc_reserve.account.liquidity.switchboard_oracle_pubkey,
+            ),
+            refresh_obligation(
+                solend_program::id(),
+                obligation_pubkey,
+                vec![usdc_reserve.pubkey],
+            ),
+            withdraw(
+                vault::id(),
+                solend_program::id(),
+                u64::MAX,
+                user.keypair.pubkey(),
+                user.get_account(&usdc_mint::id()).unwrap(),
+                user.get_account(&usdc_reserve.account.collateral.mint_pubkey)
+                    .unwrap(),
+                usdc_reserve.pubkey,
+                &usdc_reserve.account,
+            ),
+        ],
+        Some(&[&user.keypair]),
+    )
+    .await
+    .unwrap();
+
+    let obligation = test.load_account::<Obligation>(obligation_pubkey).await;
+    assert!(obligation.account.deposits.is_empty());
+    assert_eq!(
+        user.get_balance(&mut test, &usdc_mint::id()).await,
+        Some(1_000 * FRACTIONAL_TO_USDC)
+    );
+}
+
+#[tokio::test]
+async fn test_fail_withdraw_from_other_users_vault() {
+    let (mut test, lending_market, usdc_reserve, user) = setup().await;
+    init(&mut test, &lending_market, &user).await.unwrap();
+
+    test.process_transaction(
+        &[deposit(
+            vault::id(),
+            solend_program::id(),
+            DEPOSIT_AMOUNT,
+            user.keypair.pubkey(),
+            user.get_account(&usdc_mint::id()).unwrap(),
+            user.get_account(&usdc_reserve.account.collateral.mint_pubkey)
+                .unwrap(),
+            usdc_reserve.pubkey,
+            &usdc_reserve.account,
+        )],
+        Some(&[&user.keypair]),
+    )
+    .await
+    .unwrap();
+
+    // the thief signs, but passes the user's vault authority and obligation
+    let thief = User::new_with_balances(
+        &mut test,
+        &[
+            (&usdc_mint::id(), 0),
+            (&usdc_reserve.account.collateral.mint_pubkey, 0),
+        ],
+    )
+    .await;
+    let mut instruction = withdraw(
+        vault::id(),
+        solend_program::id(),
+        u64::MAX,
+        user.keypair.pubkey(),
+        thief.get_account(&usdc_mint::id()).unwrap(),
+        thief
+            .get_account(&usdc_reserve.account.collateral.mint_pubkey)
+            .unwrap(),
+        usdc_reserve.pubkey,
+        &usdc_reserve.account,
+    );
+    instruction.accounts[0].pubkey = thief.keypair.pubkey();
+
+    let res = test
+        .process_transaction(&[instruction], Some(&[&thief.keypair]))
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        res,
+        TransactionError::InstructionError(
+            0,
+            InstructionError::from(u64::from(ProgramError::InvalidSeeds))
+        )
+    );
+}
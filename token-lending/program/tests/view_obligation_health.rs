@@ -0,0 +1,86 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use crate::solend_program_test::*;
+use helpers::*;
+use solana_program::program_pack::Pack;
+use solana_program_test::*;
+use solend_program::{
+    error::LendingError,
+    instruction::{refresh_obligation, refresh_reserve, view_obligation_health},
+    state::{Obligation, ObligationHealth},
+};
+
+#[tokio::test]
+async fn test_view_obligation_health() {
+    let (mut test, _, usdc_reserve, wsol_reserve, _, obligation, _) =
+        scenario_1(&test_reserve_config(), &test_reserve_config()).await;
+
+    test.advance_clock_by_slots(1).await;
+    let refresh_reserves = [&usdc_reserve, &wsol_reserve].map(|reserve| {
+        refresh_reserve(
+            solend_program::id(),
+            reserve.pubkey,
+            reserve.account.liquidity.pyth_oracle_pubkey,
+            reserve.account.liquidity.switchboard_oracle_pubkey,
+        )
+    });
+    test.process_transaction(&refresh_reserves, None)
+        .await
+        .unwrap();
+
+    let (res, _, return_data) = test
+        .simulate_transaction(
+            &[view_obligation_health(
+                solend_program::id(),
+                obligation.pubkey,
+                vec![usdc_reserve.pubkey, wsol_reserve.pubkey],
+            )],
+            None,
+        )
+        .await;
+    res.unwrap();
+    let return_data = return_data.unwrap();
+    assert_eq!(return_data.program_id, solend_program::id());
+    let health = ObligationHealth::unpack_from_slice(&return_data.data).unwrap();
+
+    // the view doesn't write the refreshed obligation back
+    let obligation_post = test.load_account::<Obligation>(obligation.pubkey).await;
+    assert_eq!(obligation_post.account, obligation.account);
+
+    test.process_transaction(
+        &[refresh_obligation(
+            solend_program::id(),
+            obligation.pubkey,
+            vec![usdc_reserve.pubkey, wsol_reserve.pubkey],
+        )],
+        None,
+    )
+    .await
+    .unwrap();
+
+    let obligation_post = test.load_account::<Obligation>(obligation.pubkey).await;
+    assert_eq!(health, ObligationHealth::from(&obligation_post.account));
+    assert!(health.borrowed_value < health.allowed_borrow_value);
+}
+
+#[tokio::test]
+async fn test_fail_stale_reserves() {
+    let (mut test, _, usdc_reserve, wsol_reserve, _, obligation, _) =
+        scenario_1(&test_reserve_config(), &test_reserve_config()).await;
+
+    test.advance_clock_by_slots(1).await;
+
+    let (res, _, _) = test
+        .simulate_transaction(
+            &[view_obligation_health(
+                solend_program::id(),
+                obligation.pubkey,
+                vec![usdc_reserve.pubkey, wsol_reserve.pubkey],
+            )],
+            None,
+        )
+        .await;
+    expect_lending_error(res, LendingError::ReserveStale);
+}
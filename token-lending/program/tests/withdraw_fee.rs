@@ -0,0 +1,176 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use crate::solend_program_test::*;
+use helpers::*;
+use solana_program_test::*;
+use solana_sdk::signature::Signer;
+use solend_program::{
+    error::LendingError,
+    instruction::{
+        redeem_reserve_collateral, redeem_reserve_collateral_with_fee_receiver, refresh_reserve,
+    },
+    state::ReserveConfig,
+};
+use std::collections::HashSet;
+
+fn withdraw_fee_reserve_config() -> ReserveConfig {
+    ReserveConfig {
+        withdraw_fee_bps: 30,
+        ..test_reserve_config()
+    }
+}
+
+#[tokio::test]
+async fn test_redeem_with_withdraw_fee() {
+    let (mut test, lending_market, usdc_reserve, _, _, user) =
+        setup_world(&withdraw_fee_reserve_config(), &test_reserve_config()).await;
+
+    lending_market
+        .deposit(&mut test, &usdc_reserve, &user, 1_000_000)
+        .await
+        .unwrap();
+
+    let balance_checker = BalanceChecker::start(&mut test, &[&usdc_reserve, &user]).await;
+
+    lending_market
+        .redeem(&mut test, &usdc_reserve, &user, 1_000_000)
+        .await
+        .unwrap();
+
+    let (balance_changes, _) = balance_checker.find_balance_changes(&mut test).await;
+    let expected_balance_changes = HashSet::from([
+        TokenBalanceChange {
+            token_account: user.get_account(&usdc_mint::id()).unwrap(),
+            mint: usdc_mint::id(),
+            diff: 997_000,
+        },
+        TokenBalanceChange {
+            token_account: usdc_reserve.account.config.fee_receiver,
+            mint: usdc_mint::id(),
+            diff: 3_000,
+        },
+        TokenBalanceChange {
+            token_account: usdc_reserve.account.liquidity.supply_pubkey,
+            mint: usdc_mint::id(),
+            diff: -1_000_000,
+        },
+        TokenBalanceChange {
+            token_account: user
+                .get_account(&usdc_reserve.account.collateral.mint_pubkey)
+                .unwrap(),
+            mint: usdc_reserve.account.collateral.mint_pubkey,
+            diff: -1_000_000,
+        },
+    ]);
+    assert_eq!(balance_changes, expected_balance_changes);
+}
+
+#[tokio::test]
+async fn test_withdraw_all_with_withdraw_fee() {
+    let (mut test, lending_market, usdc_reserve, _, user, obligation, _) =
+        scenario_1(&withdraw_fee_reserve_config(), &test_reserve_config()).await;
+
+    let balance_checker = BalanceChecker::start(&mut test, &[&usdc_reserve, &user]).await;
+
+    lending_market
+        .withdraw_obligation_collateral_and_redeem_reserve_collateral(
+            &mut test,
+            &usdc_reserve,
+            &obligation,
+            &user,
+            u64::MAX,
+        )
+        .await
+        .unwrap();
+
+    let (balance_changes, _) = balance_checker.find_balance_changes(&mut test).await;
+    // still borrowing 100usd worth of sol so we need to leave 200usd in the obligation.
+    let withdraw_amount = (100_000 * FRACTIONAL_TO_USDC - 200 * FRACTIONAL_TO_USDC) as i128;
+    let withdraw_fee = withdraw_amount * 30 / 10_000;
+
+    let expected_balance_changes = HashSet::from([
+        TokenBalanceChange {
+            token_account: user.get_account(&usdc_mint::id()).unwrap(),
+            mint: usdc_mint::id(),
+            diff: withdraw_amount - withdraw_fee,
+        },
+        TokenBalanceChange {
+            token_account: usdc_reserve.account.config.fee_receiver,
+            mint: usdc_mint::id(),
+            diff: withdraw_fee,
+        },
+        TokenBalanceChange {
+            token_account: usdc_reserve.account.liquidity.supply_pubkey,
+            mint: usdc_mint::id(),
+            diff: -withdraw_amount,
+        },
+        TokenBalanceChange {
+            token_account: usdc_reserve.account.collateral.supply_pubkey,
+            mint: usdc_reserve.account.collateral.mint_pubkey,
+            diff: -withdraw_amount,
+        },
+    ]);
+    assert_eq!(balance_changes, expected_balance_changes);
+}
+
+#[tokio::test]
+async fn test_fail_missing_fee_receiver() {
+    let (mut test, lending_market, usdc_reserve, _, _, user) =
+        setup_world(&withdraw_fee_reserve_config(), &test_reserve_config()).await;
+
+    lending_market
+        .deposit(&mut test, &usdc_reserve, &user, 1_000_000)
+        .await
+        .unwrap();
+
+    // without the fee receiver, and with an account that isn't the reserve's fee receiver
+    let user_collateral = user
+        .get_account(&usdc_reserve.account.collateral.mint_pubkey)
+        .unwrap();
+    let user_liquidity = user.get_account(&usdc_mint::id()).unwrap();
+    let redeems = [
+        redeem_reserve_collateral(
+            solend_program::id(),
+            1_000_000,
+            user_collateral,
+            user_liquidity,
+            usdc_reserve.pubkey,
+            usdc_reserve.account.collateral.mint_pubkey,
+            usdc_reserve.account.liquidity.supply_pubkey,
+            lending_market.pubkey,
+            user.keypair.pubkey(),
+        ),
+        redeem_reserve_collateral_with_fee_receiver(
+            solend_program::id(),
+            1_000_000,
+            user_collateral,
+            user_liquidity,
+            usdc_reserve.pubkey,
+            usdc_reserve.account.collateral.mint_pubkey,
+            usdc_reserve.account.liquidity.supply_pubkey,
+            lending_market.pubkey,
+            user.keypair.pubkey(),
+            user_liquidity,
+        ),
+    ];
+    for redeem in redeems {
+        let res = test
+            .process_transaction(
+                &[
+                    refresh_reserve(
+                        solend_program::id(),
+                        usdc_reserve.pubkey,
+                        usdc_reserve.account.liquidity.pyth_oracle_pubkey,
+                        usdc_reserve.account.liquidity.switchboard_oracle_pubkey,
+                    ),
+                    redeem,
+                ],
+                Some(&[&user.keypair]),
+            )
+            .await;
+
+        expect_lending_error(res, LendingError::InvalidAccountInput);
+    }
+}
@@ -3,6 +3,7 @@
 use solana_program::native_token::LAMPORTS_PER_SOL;
 use solana_program::pubkey::Pubkey;
 use solana_sdk::signature::Signer;
+use solend_program::error::LendingError;
 use solend_program::math::TryDiv;
 mod helpers;
 
@@ -149,6 +150,72 @@ async fn test_success() {
     );
 }
 
+#[tokio::test]
+async fn test_success_third_party_destination() {
+    let (mut test, lending_market, usdc_reserve, _wsol_reserve, user, obligation, _) =
+        scenario_1(&test_reserve_config(), &test_reserve_config()).await;
+
+    let custodian = User::new_with_balances(&mut test, &[(&usdc_mint::id(), 0)]).await;
+
+    let balance_checker =
+        BalanceChecker::start(&mut test, &[&usdc_reserve, &user, &custodian]).await;
+
+    lending_market
+        .withdraw_obligation_collateral_and_redeem_reserve_collateral_to(
+            &mut test,
+            &usdc_reserve,
+            &obligation,
+            &user,
+            custodian.get_account(&usdc_mint::id()).unwrap(),
+            1_000 * FRACTIONAL_TO_USDC,
+        )
+        .await
+        .unwrap();
+
+    // the owner's token accounts are untouched, the liquidity goes straight to the custodian
+    let (balance_changes, _mint_supply_changes) =
+        balance_checker.find_balance_changes(&mut test).await;
+    let withdraw_amount = (1_000 * FRACTIONAL_TO_USDC) as i128;
+
+    let expected_balance_changes = HashSet::from([
+        TokenBalanceChange {
+            token_account: custodian.get_account(&usdc_mint::id()).unwrap(),
+            mint: usdc_mint::id(),
+            diff: withdraw_amount,
+        },
+        TokenBalanceChange {
+            token_account: usdc_reserve.account.liquidity.supply_pubkey,
+            mint: usdc_mint::id(),
+            diff: -withdraw_amount,
+        },
+        TokenBalanceChange {
+            token_account: usdc_reserve.account.collateral.supply_pubkey,
+            mint: usdc_reserve.account.collateral.mint_pubkey,
+            diff: -withdraw_amount,
+        },
+    ]);
+    assert_eq!(balance_changes, expected_balance_changes);
+}
+
+#[tokio::test]
+async fn test_fail_destination_wrong_mint() {
+    let (mut test, lending_market, usdc_reserve, _wsol_reserve, user, obligation, _) =
+        scenario_1(&test_reserve_config(), &test_reserve_config()).await;
+
+    let res = lending_market
+        .withdraw_obligation_collateral_and_redeem_reserve_collateral_to(
+            &mut test,
+            &usdc_reserve,
+            &obligation,
+            &user,
+            user.get_account(&wsol_mint::id()).unwrap(),
+            1_000 * FRACTIONAL_TO_USDC,
+        )
+        .await;
+
+    expect_lending_error(res, LendingError::InvalidTokenMint);
+}
+
 #[tokio::test]
 async fn test_withdraw_max_rate_limiter() {
     let (mut test, lending_market, reserves, obligations, users, lending_market_owner) =
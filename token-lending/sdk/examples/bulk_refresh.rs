@@ -0,0 +1,104 @@
+//! Benchmark of the bulk obligation refresh on synthetic accounts, single threaded and then on
+//! thread pools of doubling size.
+//!
+//! cargo run --release --example bulk_refresh --features parallel -- [obligations]
+
+use rand::Rng;
+use solana_program::pubkey::Pubkey;
+use std::{collections::HashMap, time::Instant};
+
+use solend_sdk::{
+    bulk_refresh::{bulk_refresh_obligations_with_threads, refresh_obligations},
+    math::Decimal,
+    state::{
+        Obligation, ObligationCollateral, ObligationLiquidity, Reserve, ReserveConfig,
+        ReserveLiquidity,
+    },
+};
+
+const RESERVES: usize = 50;
+
+pub fn main() {
+    let num_obligations = std::env::args()
+        .nth(1)
+        .map(|arg| arg.parse().expect("number of obligations"))
+        .unwrap_or(500_000);
+
+    let mut rng = rand::thread_rng();
+    let reserves: HashMap<Pubkey, Reserve> = (0..RESERVES)
+        .map(|_| {
+            let price = Decimal::from(rng.gen_range(1..1_000u64));
+            let reserve = Reserve {
+                liquidity: ReserveLiquidity {
+                    cumulative_borrow_rate_wads: Decimal::one(),
+                    market_price: price,
+                    smoothed_market_price: price,
+                    ..ReserveLiquidity::default()
+                },
+                config: ReserveConfig {
+                    loan_to_value_ratio: 75,
+                    liquidation_threshold: 80,
+                    max_liquidation_threshold: 90,
+                    ..ReserveConfig::default()
+                },
+                ..Reserve::default()
+            };
+            (Pubkey::new_unique(), reserve)
+        })
+        .collect();
+    let reserve_pubkeys: Vec<Pubkey> = reserves.keys().copied().collect();
+
+    let obligations: HashMap<Pubkey, Obligation> = (0..num_obligations)
+        .map(|_| {
+            let deposits = (0..rng.gen_range(1..=3))
+                .map(|_| ObligationCollateral {
+                    deposited_amount: rng.gen_range(1..1_000_000),
+                    ..ObligationCollateral::new(reserve_pubkeys[rng.gen_range(0..RESERVES)])
+                })
+                .collect();
+            let borrows = (0..rng.gen_range(0..=3))
+                .map(|_| ObligationLiquidity {
+                    borrowed_amount_wads: Decimal::from(rng.gen_range(1..500_000u64)),
+                    ..ObligationLiquidity::new(
+                        reserve_pubkeys[rng.gen_range(0..RESERVES)],
+                        Decimal::one(),
+                    )
+                })
+                .collect();
+            let obligation = Obligation {
+                deposits,
+                borrows,
+                ..Obligation::default()
+            };
+            (Pubkey::new_unique(), obligation)
+        })
+        .collect();
+
+    println!("refreshing {} obligations", num_obligations);
+
+    let start = Instant::now();
+    let stats = refresh_obligations(&mut obligations.clone(), &reserves).unwrap();
+    let sequential = start.elapsed();
+    println!(
+        "sequential: {:?}, {} unhealthy",
+        sequential, stats.unhealthy
+    );
+
+    let mut num_threads = 1;
+    while num_threads <= std::thread::available_parallelism().map_or(1, |n| n.get()) {
+        let mut obligations = obligations.clone();
+        let start = Instant::now();
+        let parallel_stats =
+            bulk_refresh_obligations_with_threads(&mut obligations, &reserves, num_threads)
+                .unwrap();
+        let elapsed = start.elapsed();
+        assert_eq!(parallel_stats, stats);
+        println!(
+            "{} threads: {:?}, {:.2}x",
+            num_threads,
+            elapsed,
+            sequential.as_secs_f64() / elapsed.as_secs_f64()
+        );
+        num_threads *= 2;
+    }
+}
@@ -0,0 +1,105 @@
+//! Liquidator program that liquidates Solend obligations via CPI.
+//!
+//! The repay liquidity comes out of a token account owned by a PDA of the liquidator program
+//! rather than a wallet. The PDA is passed as the user transfer authority and signs through
+//! `invoke_signed`; Solend only requires the authority to be a signer, so its signature carries
+//! over into the token transfers and burns the liquidation makes on its behalf.
+//!
+//! Accounts are those of `LiquidateObligationAndRedeemReserveCollateral`, in the same order, with
+//! the liquidator authority PDA in place of the user transfer authority, followed by the Solend
+//! program. The instruction data is the liquidity amount as a little-endian u64.
+//!
+//! Running the example prints the liquidator authority of the program id in `PROGRAM_ID`.
+
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program::invoke_signed,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+use solend_sdk::instruction::liquidate_obligation_and_redeem_reserve_collateral;
+use std::{convert::TryInto, str::FromStr};
+
+/// Seed of the PDA that owns the liquidator's token accounts
+pub const LIQUIDATOR_AUTHORITY_SEED: &[u8] = b"liquidator";
+
+/// Address of the liquidator authority of `program_id`
+pub fn find_liquidator_authority_address(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[LIQUIDATOR_AUTHORITY_SEED], program_id)
+}
+
+/// Entrypoint of the liquidator program
+pub fn process_instruction(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    let liquidity_amount = instruction_data
+        .get(..8)
+        .and_then(|slice| slice.try_into().ok())
+        .map(u64::from_le_bytes)
+        .ok_or(ProgramError::InvalidInstructionData)?;
+
+    let account_info_iter = &mut accounts.iter();
+    let source_liquidity_info = next_account_info(account_info_iter)?;
+    let destination_collateral_info = next_account_info(account_info_iter)?;
+    let destination_liquidity_info = next_account_info(account_info_iter)?;
+    let repay_reserve_info = next_account_info(account_info_iter)?;
+    let repay_reserve_liquidity_supply_info = next_account_info(account_info_iter)?;
+    let withdraw_reserve_info = next_account_info(account_info_iter)?;
+    let withdraw_reserve_collateral_mint_info = next_account_info(account_info_iter)?;
+    let withdraw_reserve_collateral_supply_info = next_account_info(account_info_iter)?;
+    let withdraw_reserve_liquidity_supply_info = next_account_info(account_info_iter)?;
+    let withdraw_reserve_liquidity_fee_receiver_info = next_account_info(account_info_iter)?;
+    let obligation_info = next_account_info(account_info_iter)?;
+    let lending_market_info = next_account_info(account_info_iter)?;
+    let _lending_market_authority_info = next_account_info(account_info_iter)?;
+    let liquidator_authority_info = next_account_info(account_info_iter)?;
+    let _token_program_id = next_account_info(account_info_iter)?;
+    let _liquidation_circuit_breaker_info = next_account_info(account_info_iter)?;
+    let _market_policy_info = next_account_info(account_info_iter)?;
+    let _liquidation_stats_info = next_account_info(account_info_iter)?;
+    let solend_program_info = next_account_info(account_info_iter)?;
+
+    let (liquidator_authority, bump_seed) = find_liquidator_authority_address(program_id);
+    if &liquidator_authority != liquidator_authority_info.key {
+        msg!("Liquidator authority provided is not the liquidator authority of this program");
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    invoke_signed(
+        &liquidate_obligation_and_redeem_reserve_collateral(
+            *solend_program_info.key,
+            liquidity_amount,
+            *source_liquidity_info.key,
+            *destination_collateral_info.key,
+            *destination_liquidity_info.key,
+            *repay_reserve_info.key,
+            *repay_reserve_liquidity_supply_info.key,
+            *withdraw_reserve_info.key,
+            *withdraw_reserve_collateral_mint_info.key,
+            *withdraw_reserve_collateral_supply_info.key,
+            *withdraw_reserve_liquidity_supply_info.key,
+            *withdraw_reserve_liquidity_fee_receiver_info.key,
+            *obligation_info.key,
+            *lending_market_info.key,
+            liquidator_authority,
+        ),
+        accounts,
+        &[&[LIQUIDATOR_AUTHORITY_SEED, &[bump_seed]]],
+    )
+}
+
+pub fn main() {
+    let program_id = std::env::var("PROGRAM_ID")
+        .ok()
+        .and_then(|program_id| Pubkey::from_str(&program_id).ok())
+        .unwrap_or_else(Pubkey::new_unique);
+    let (liquidator_authority, _bump_seed) = find_liquidator_authority_address(&program_id);
+    println!(
+        "liquidator authority of {}: {}",
+        program_id, liquidator_authority
+    );
+}
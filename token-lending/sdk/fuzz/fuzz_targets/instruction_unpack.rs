@@ -0,0 +1,15 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use solend_sdk::instruction::LendingInstruction;
+
+fuzz_target!(|data: &[u8]| {
+    // unpacking never panics, and whatever unpacks packs back to the same instruction
+    match LendingInstruction::unpack(data) {
+        // the metadata is read by the processor from the raw instruction data
+        Ok(LendingInstruction::UpdateMarketMetadata) | Err(_) => {}
+        Ok(instruction) => {
+            assert_eq!(LendingInstruction::unpack(&instruction.pack()), Ok(instruction))
+        }
+    }
+});
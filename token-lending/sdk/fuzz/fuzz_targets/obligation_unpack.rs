@@ -0,0 +1,14 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use solana_program::program_pack::Pack;
+use solend_sdk::state::Obligation;
+
+fuzz_target!(|data: &[u8]| {
+    // unpacking never panics, and whatever unpacks packs back to the same value
+    if let Ok(unpacked) = Obligation::unpack(data) {
+        let mut packed = vec![0u8; Obligation::LEN];
+        Obligation::pack(unpacked.clone(), &mut packed).unwrap();
+        assert_eq!(Obligation::unpack(&packed), Ok(unpacked));
+    }
+});
@@ -0,0 +1,403 @@
+//! Adds a reserve for an existing mint to a lending market.
+//!
+//! InitReserve needs a reserve account, a collateral mint, collateral and liquidity supplies, a
+//! fee receiver and a collateral account for the initial deposit to exist, rent exempt and
+//! owned by the right program, before it runs. [AddReserveBuilder] creates all of them and
+//! packs the account creations and the InitReserve instruction into as few transactions as fit,
+//! with InitReserve always last. The initial liquidity is deposited by InitReserve itself, so the
+//! reserve is never left initialized without it.
+
+use crate::{
+    instruction::init_reserve,
+    state::{Reserve, ReserveConfig},
+    NULL_PUBKEY,
+};
+use solana_program::{
+    hash::Hash, instruction::Instruction, message::Message, program_pack::Pack, pubkey::Pubkey,
+    rent::Rent, system_instruction::create_account,
+};
+use solana_sdk::{
+    packet::PACKET_DATA_SIZE,
+    signature::{Keypair, Signature},
+    signer::{Signer, SignerError},
+    transaction::Transaction,
+};
+use spl_token::state::{Account as Token, Mint};
+
+/// Keypairs of the accounts created for a new reserve
+#[derive(Debug)]
+pub struct AddReserveKeypairs {
+    /// Reserve account
+    pub reserve: Keypair,
+    /// Reserve collateral mint
+    pub collateral_mint: Keypair,
+    /// Reserve collateral supply, holding the collateral deposited into obligations
+    pub collateral_supply: Keypair,
+    /// Reserve liquidity supply
+    pub liquidity_supply: Keypair,
+    /// Reserve liquidity fee receiver
+    pub liquidity_fee_receiver: Keypair,
+    /// Collateral account of the source liquidity owner, receiving the initial deposit
+    pub user_collateral: Keypair,
+}
+
+impl AddReserveKeypairs {
+    /// Generate new keypairs for every account
+    pub fn new() -> Self {
+        Self {
+            reserve: Keypair::new(),
+            collateral_mint: Keypair::new(),
+            collateral_supply: Keypair::new(),
+            liquidity_supply: Keypair::new(),
+            liquidity_fee_receiver: Keypair::new(),
+            user_collateral: Keypair::new(),
+        }
+    }
+
+    fn keypairs(&self) -> [&Keypair; 6] {
+        [
+            &self.reserve,
+            &self.collateral_mint,
+            &self.collateral_supply,
+            &self.liquidity_supply,
+            &self.liquidity_fee_receiver,
+            &self.user_collateral,
+        ]
+    }
+}
+
+impl Default for AddReserveKeypairs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Builder of the transactions adding a reserve to a lending market
+#[derive(Debug)]
+pub struct AddReserveBuilder {
+    program_id: Pubkey,
+    payer: Pubkey,
+    lending_market: Pubkey,
+    lending_market_owner: Pubkey,
+    liquidity_mint: Pubkey,
+    source_liquidity: Pubkey,
+    source_liquidity_owner: Pubkey,
+    liquidity_amount: u64,
+    config: ReserveConfig,
+    pyth_product: Pubkey,
+    pyth_price: Pubkey,
+    switchboard_feed: Pubkey,
+    keypairs: AddReserveKeypairs,
+}
+
+impl AddReserveBuilder {
+    /// Create a builder for a reserve of `liquidity_mint`, seeded with `liquidity_amount` from
+    /// `source_liquidity`. The source liquidity owner signs the deposit directly, no delegate is
+    /// approved.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        program_id: Pubkey,
+        payer: Pubkey,
+        lending_market: Pubkey,
+        lending_market_owner: Pubkey,
+        liquidity_mint: Pubkey,
+        source_liquidity: Pubkey,
+        source_liquidity_owner: Pubkey,
+        liquidity_amount: u64,
+    ) -> Self {
+        Self {
+            program_id,
+            payer,
+            lending_market,
+            lending_market_owner,
+            liquidity_mint,
+            source_liquidity,
+            source_liquidity_owner,
+            liquidity_amount,
+            config: ReserveConfig::default(),
+            pyth_product: NULL_PUBKEY,
+            pyth_price: NULL_PUBKEY,
+            switchboard_feed: NULL_PUBKEY,
+            keypairs: AddReserveKeypairs::new(),
+        }
+    }
+
+    /// Reserve config. Its fee receiver is replaced by the fee receiver account created here.
+    pub fn config(mut self, config: ReserveConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Oracles of the reserve liquidity, `NULL_PUBKEY` for the ones not used
+    pub fn oracles(
+        mut self,
+        pyth_product: Pubkey,
+        pyth_price: Pubkey,
+        switchboard_feed: Pubkey,
+    ) -> Self {
+        self.pyth_product = pyth_product;
+        self.pyth_price = pyth_price;
+        self.switchboard_feed = switchboard_feed;
+        self
+    }
+
+    /// Use these keypairs instead of generated ones, e.g. for a vanity reserve address
+    pub fn keypairs(mut self, keypairs: AddReserveKeypairs) -> Self {
+        self.keypairs = keypairs;
+        self
+    }
+
+    /// Instructions creating the accounts InitReserve expects, funded for rent exemption
+    fn create_account_instructions(&self, rent: &Rent) -> Vec<Instruction> {
+        let keypairs = &self.keypairs;
+        [
+            (&keypairs.reserve, Reserve::LEN, &self.program_id),
+            (&keypairs.collateral_mint, Mint::LEN, &spl_token::id()),
+            (&keypairs.collateral_supply, Token::LEN, &spl_token::id()),
+            (&keypairs.liquidity_supply, Token::LEN, &spl_token::id()),
+            (
+                &keypairs.liquidity_fee_receiver,
+                Token::LEN,
+                &spl_token::id(),
+            ),
+            (&keypairs.user_collateral, Token::LEN, &spl_token::id()),
+        ]
+        .iter()
+        .map(|&(keypair, space, owner)| {
+            create_account(
+                &self.payer,
+                &keypair.pubkey(),
+                rent.minimum_balance(space),
+                space as u64,
+                owner,
+            )
+        })
+        .collect()
+    }
+
+    fn init_reserve_instruction(&self) -> Instruction {
+        let keypairs = &self.keypairs;
+        init_reserve(
+            self.program_id,
+            self.liquidity_amount,
+            ReserveConfig {
+                fee_receiver: keypairs.liquidity_fee_receiver.pubkey(),
+                ..self.config
+            },
+            self.source_liquidity,
+            keypairs.user_collateral.pubkey(),
+            keypairs.reserve.pubkey(),
+            self.liquidity_mint,
+            keypairs.liquidity_supply.pubkey(),
+            keypairs.collateral_mint.pubkey(),
+            keypairs.collateral_supply.pubkey(),
+            self.pyth_product,
+            self.pyth_price,
+            self.switchboard_feed,
+            self.lending_market,
+            self.lending_market_owner,
+            self.source_liquidity_owner,
+        )
+    }
+
+    /// Split the instructions into transactions, with `rent` used to fund the new accounts
+    pub fn build(self, rent: &Rent) -> AddReserveTransactions {
+        let mut transactions: Vec<Vec<Instruction>> = vec![];
+        let mut current = vec![];
+        for instruction in self
+            .create_account_instructions(rent)
+            .into_iter()
+            .chain(std::iter::once(self.init_reserve_instruction()))
+        {
+            current.push(instruction);
+            if current.len() > 1 && transaction_size(&current, &self.payer) > PACKET_DATA_SIZE {
+                let instruction = current.pop().unwrap();
+                transactions.push(current);
+                current = vec![instruction];
+            }
+        }
+        transactions.push(current);
+
+        AddReserveTransactions {
+            payer: self.payer,
+            keypairs: self.keypairs,
+            transactions,
+        }
+    }
+}
+
+/// Size of a legacy transaction with `instructions`, once signed
+fn transaction_size(instructions: &[Instruction], payer: &Pubkey) -> usize {
+    let message = Message::new(instructions, Some(payer));
+    let signatures = message.header.num_required_signatures as usize;
+    // signatures are prefixed by their count, which takes a byte below 128 signatures
+    1 + signatures * std::mem::size_of::<Signature>() + message.serialize().len()
+}
+
+/// Transactions adding a reserve, see [AddReserveBuilder]
+#[derive(Debug)]
+pub struct AddReserveTransactions {
+    payer: Pubkey,
+    /// Keypairs of the accounts created
+    pub keypairs: AddReserveKeypairs,
+    /// Instructions of each transaction, to be sent in order. The last transaction initializes
+    /// the reserve.
+    pub transactions: Vec<Vec<Instruction>>,
+}
+
+impl AddReserveTransactions {
+    /// Address of the new reserve
+    pub fn reserve(&self) -> Pubkey {
+        self.keypairs.reserve.pubkey()
+    }
+
+    /// Sign the transactions. `signers` are the payer, lending market owner and source liquidity
+    /// owner, the keypairs of the new accounts are added as needed.
+    pub fn sign(
+        &self,
+        signers: &[&dyn Signer],
+        recent_blockhash: Hash,
+    ) -> Result<Vec<Transaction>, SignerError> {
+        let available_signers = signers
+            .iter()
+            .copied()
+            .chain(
+                self.keypairs
+                    .keypairs()
+                    .map(|keypair| keypair as &dyn Signer),
+            )
+            .collect::<Vec<_>>();
+
+        self.transactions
+            .iter()
+            .map(|instructions| {
+                let message = Message::new(instructions, Some(&self.payer));
+                let required_signers =
+                    &message.account_keys[..message.header.num_required_signatures as usize];
+                let mut transaction_signers = vec![];
+                for pubkey in required_signers {
+                    let signer = available_signers
+                        .iter()
+                        .find(|signer| signer.pubkey() == *pubkey)
+                        .ok_or(SignerError::NotEnoughSigners)?;
+                    transaction_signers.push(*signer);
+                }
+                let mut transaction = Transaction::new_unsigned(message);
+                transaction.try_sign(&transaction_signers, recent_blockhash)?;
+                Ok(transaction)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::instruction::LendingInstruction;
+
+    #[test]
+    fn add_reserve_transactions() {
+        let program_id = Pubkey::new_unique();
+        let payer = Keypair::new();
+        let lending_market_owner = Keypair::new();
+        let source_liquidity_owner = Keypair::new();
+        let rent = Rent::default();
+
+        let keypairs = AddReserveKeypairs::new();
+        let reserve = keypairs.reserve.pubkey();
+        let fee_receiver = keypairs.liquidity_fee_receiver.pubkey();
+        let add_reserve = AddReserveBuilder::new(
+            program_id,
+            payer.pubkey(),
+            Pubkey::new_unique(),
+            lending_market_owner.pubkey(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            source_liquidity_owner.pubkey(),
+            1_000,
+        )
+        .oracles(Pubkey::new_unique(), Pubkey::new_unique(), NULL_PUBKEY)
+        .keypairs(keypairs)
+        .build(&rent);
+        assert_eq!(add_reserve.reserve(), reserve);
+
+        // everything doesn't fit in one transaction, but the deposit is part of InitReserve
+        assert!(add_reserve.transactions.len() > 1);
+        let instructions = add_reserve.transactions.concat();
+        assert_eq!(instructions.len(), 7);
+        for instruction in &instructions[..6] {
+            assert_eq!(instruction.program_id, solana_program::system_program::id());
+        }
+        let init_reserve = add_reserve.transactions.last().unwrap().last().unwrap();
+        assert_eq!(init_reserve.program_id, program_id);
+        assert_eq!(init_reserve.accounts[2].pubkey, reserve);
+        match LendingInstruction::unpack(&init_reserve.data).unwrap() {
+            LendingInstruction::InitReserve {
+                liquidity_amount,
+                config,
+            } => {
+                assert_eq!(liquidity_amount, 1_000);
+                assert_eq!(config.fee_receiver, fee_receiver);
+            }
+            _ => panic!("unexpected instruction"),
+        }
+
+        let transactions = add_reserve
+            .sign(
+                &[&payer, &lending_market_owner, &source_liquidity_owner],
+                Hash::new_unique(),
+            )
+            .unwrap();
+        for transaction in &transactions {
+            transaction.verify().unwrap();
+            let serialized = transaction.message.serialize().len()
+                + 1
+                + transaction.signatures.len() * std::mem::size_of::<Signature>();
+            assert!(serialized <= PACKET_DATA_SIZE);
+        }
+
+        assert_eq!(
+            add_reserve
+                .sign(&[&payer, &lending_market_owner], Hash::new_unique())
+                .unwrap_err(),
+            SignerError::NotEnoughSigners
+        );
+    }
+
+    #[test]
+    fn create_accounts_rent_exempt() {
+        let payer = Pubkey::new_unique();
+        let rent = Rent::default();
+        let builder = AddReserveBuilder::new(
+            Pubkey::new_unique(),
+            payer,
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            1,
+        );
+        let instructions = builder.create_account_instructions(&rent);
+        assert_eq!(
+            instructions[0],
+            create_account(
+                &payer,
+                &builder.keypairs.reserve.pubkey(),
+                rent.minimum_balance(Reserve::LEN),
+                Reserve::LEN as u64,
+                &builder.program_id,
+            )
+        );
+        assert_eq!(
+            instructions[1],
+            create_account(
+                &payer,
+                &builder.keypairs.collateral_mint.pubkey(),
+                rent.minimum_balance(Mint::LEN),
+                Mint::LEN as u64,
+                &spl_token::id(),
+            )
+        );
+    }
+}
@@ -0,0 +1,360 @@
+//! Fee and interest attribution reports.
+//!
+//! [generate_attribution_report] compares two [SolendAccounts] snapshots, e.g. taken at the start
+//! and end of a month, and splits what every reserve earned between depositors and the protocol.
+//! Interest is derived from the snapshots alone. Origination, flash loan and liquidation fees are
+//! paid straight to fee receivers and leave no trace in reserve state, so they are only reported
+//! when the caller passes the [FeeEvent]s it collected from transaction logs over the period.
+//!
+//! Amounts are in the reserve's liquidity token, USD values use the end snapshot's market price.
+
+use crate::{
+    math::{Decimal, TryAdd, TryDiv, TryMul, TrySub},
+    offchain_utils::SolendAccounts,
+    state::Reserve,
+};
+use serde::{Serialize, Serializer};
+use solana_program::{program_error::ProgramError, pubkey::Pubkey};
+use std::{collections::BTreeMap, fmt::Display};
+
+/// A fee paid over the reporting period, as found in the transaction logs
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FeeEvent {
+    /// Origination fee charged on a borrow
+    BorrowFee {
+        /// Reserve borrowed from
+        reserve: Pubkey,
+        /// Total fee, including the host fee
+        amount: u64,
+        /// Part of the fee paid to the host
+        host_fee: u64,
+    },
+    /// Fee charged on a flash loan
+    FlashLoanFee {
+        /// Reserve the flash loan was taken from
+        reserve: Pubkey,
+        /// Total fee, including the host fee
+        amount: u64,
+        /// Part of the fee paid to the host
+        host_fee: u64,
+    },
+    /// Protocol cut of a liquidation bonus
+    LiquidationFee {
+        /// Withdraw reserve of the liquidation
+        reserve: Pubkey,
+        /// Fee, in liquidity of the withdraw reserve
+        amount: u64,
+    },
+    /// Protocol fees moved out of the reserve by RedeemFees
+    ProtocolFeesRedeemed {
+        /// Reserve the fees were redeemed from
+        reserve: Pubkey,
+        /// Liquidity redeemed
+        amount: u64,
+    },
+}
+
+impl FeeEvent {
+    fn reserve(&self) -> &Pubkey {
+        match self {
+            Self::BorrowFee { reserve, .. }
+            | Self::FlashLoanFee { reserve, .. }
+            | Self::LiquidationFee { reserve, .. }
+            | Self::ProtocolFeesRedeemed { reserve, .. } => reserve,
+        }
+    }
+}
+
+/// What a reserve earned over the reporting period
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct ReserveAttribution {
+    /// Reserve address
+    #[serde(serialize_with = "display")]
+    pub reserve: Pubkey,
+    /// Lending market of the reserve
+    #[serde(serialize_with = "display")]
+    pub lending_market: Pubkey,
+    /// Mint of the reserve liquidity
+    #[serde(serialize_with = "display")]
+    pub liquidity_mint: Pubkey,
+    /// Slot the reserve was last refreshed at in the start snapshot
+    pub start_slot: u64,
+    /// Slot the reserve was last refreshed at in the end snapshot
+    pub end_slot: u64,
+    /// Interest earned by depositors before bad debt, valued at the end cToken supply
+    pub depositor_interest: Decimal,
+    /// Bad debt forgiven and socialized across depositors
+    pub forgiven_debt: Decimal,
+    /// Protocol take of borrow interest and idle liquidity fees
+    pub protocol_take: Decimal,
+    /// Origination fees, including host fees
+    pub origination_fees: u64,
+    /// Part of the origination fees paid to hosts
+    pub origination_host_fees: u64,
+    /// Flash loan fees, including host fees
+    pub flash_loan_fees: u64,
+    /// Part of the flash loan fees paid to hosts
+    pub flash_loan_host_fees: u64,
+    /// Protocol liquidation fees
+    pub liquidation_fees: u64,
+    /// Revenue kept by the protocol: the protocol take and all fees net of host fees
+    pub protocol_revenue: Decimal,
+    /// USD value of the protocol revenue
+    pub protocol_revenue_usd: Decimal,
+    /// USD value of the depositor interest
+    pub depositor_interest_usd: Decimal,
+}
+
+/// Attribution of every reserve present in both snapshots
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct AttributionReport {
+    /// Reserves, ordered by address
+    pub reserves: Vec<ReserveAttribution>,
+    /// USD value of the depositor interest across reserves
+    pub total_depositor_interest_usd: Decimal,
+    /// USD value of the protocol revenue across reserves
+    pub total_protocol_revenue_usd: Decimal,
+}
+
+pub(crate) fn display<T: Display, S: Serializer>(
+    value: &T,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(value)
+}
+
+/// Liquidity a cToken redeems for
+fn liquidity_per_collateral(reserve: &Reserve) -> Result<Decimal, ProgramError> {
+    if reserve.collateral.mint_total_supply == 0 {
+        return Ok(Decimal::zero());
+    }
+    reserve
+        .liquidity
+        .total_supply()?
+        .try_div(reserve.collateral.mint_total_supply)
+}
+
+/// Value `amount` of the reserve liquidity in USD
+fn usd_value(reserve: &Reserve, amount: Decimal) -> Result<Decimal, ProgramError> {
+    reserve.market_value(amount)
+}
+
+/// Attribute the interest and fees earned by every reserve between the `start` and `end`
+/// snapshots. Reserves created or closed during the period are left out since they have no
+/// baseline to compare against.
+pub fn generate_attribution_report(
+    start: &SolendAccounts,
+    end: &SolendAccounts,
+    events: &[FeeEvent],
+) -> Result<AttributionReport, ProgramError> {
+    let end_reserves = end.reserves.iter().collect::<BTreeMap<_, _>>();
+    let mut report = AttributionReport::default();
+
+    for (pubkey, end_reserve) in end_reserves {
+        let start_reserve = match start.reserves.get(pubkey) {
+            Some(reserve) => reserve,
+            None => continue,
+        };
+        let attribution = attribute_reserve(
+            *pubkey,
+            start_reserve,
+            end_reserve,
+            events.iter().filter(|event| event.reserve() == pubkey),
+        )?;
+        report.total_depositor_interest_usd = report
+            .total_depositor_interest_usd
+            .try_add(attribution.depositor_interest_usd)?;
+        report.total_protocol_revenue_usd = report
+            .total_protocol_revenue_usd
+            .try_add(attribution.protocol_revenue_usd)?;
+        report.reserves.push(attribution);
+    }
+
+    Ok(report)
+}
+
+fn attribute_reserve<'a>(
+    pubkey: Pubkey,
+    start: &Reserve,
+    end: &Reserve,
+    events: impl Iterator<Item = &'a FeeEvent>,
+) -> Result<ReserveAttribution, ProgramError> {
+    let mut attribution = ReserveAttribution {
+        reserve: pubkey,
+        lending_market: end.lending_market,
+        liquidity_mint: end.liquidity.mint_pubkey,
+        start_slot: start.last_update.slot,
+        end_slot: end.last_update.slot,
+        ..ReserveAttribution::default()
+    };
+
+    let mut redeemed_protocol_fees = 0u64;
+    for event in events {
+        match *event {
+            FeeEvent::BorrowFee {
+                amount, host_fee, ..
+            } => {
+                attribution.origination_fees += amount;
+                attribution.origination_host_fees += host_fee;
+            }
+            FeeEvent::FlashLoanFee {
+                amount, host_fee, ..
+            } => {
+                attribution.flash_loan_fees += amount;
+                attribution.flash_loan_host_fees += host_fee;
+            }
+            FeeEvent::LiquidationFee { amount, .. } => attribution.liquidation_fees += amount,
+            FeeEvent::ProtocolFeesRedeemed { amount, .. } => redeemed_protocol_fees += amount,
+        }
+    }
+
+    // forgiving debt lowers the exchange rate, so it is added back to get the gross interest
+    attribution.forgiven_debt = end
+        .liquidity
+        .lifetime_forgiven_amount_wads
+        .try_sub(start.liquidity.lifetime_forgiven_amount_wads)
+        .unwrap_or_else(|_| Decimal::zero());
+    let collateral_supply = end.collateral.mint_total_supply;
+    attribution.depositor_interest = liquidity_per_collateral(end)?
+        .try_mul(collateral_supply)?
+        .try_add(attribution.forgiven_debt)?
+        .try_sub(liquidity_per_collateral(start)?.try_mul(collateral_supply)?)
+        .unwrap_or_else(|_| Decimal::zero());
+
+    // fees redeemed during the period left the accumulator, so they are added back
+    attribution.protocol_take = end
+        .liquidity
+        .accumulated_protocol_fees_wads
+        .try_add(Decimal::from(redeemed_protocol_fees))?
+        .try_sub(start.liquidity.accumulated_protocol_fees_wads)
+        .unwrap_or_else(|_| Decimal::zero());
+
+    let protocol_fees = attribution
+        .origination_fees
+        .saturating_sub(attribution.origination_host_fees)
+        + attribution
+            .flash_loan_fees
+            .saturating_sub(attribution.flash_loan_host_fees)
+        + attribution.liquidation_fees;
+    attribution.protocol_revenue = attribution
+        .protocol_take
+        .try_add(Decimal::from(protocol_fees))?;
+    attribution.protocol_revenue_usd = usd_value(end, attribution.protocol_revenue)?;
+    attribution.depositor_interest_usd = usd_value(end, attribution.depositor_interest)?;
+
+    Ok(attribution)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::state::{LastUpdate, ReserveCollateral, ReserveLiquidity};
+    use std::collections::HashMap;
+
+    fn reserve(
+        slot: u64,
+        available_amount: u64,
+        accumulated_protocol_fees: u64,
+        forgiven: u64,
+    ) -> Reserve {
+        Reserve {
+            last_update: LastUpdate { slot, stale: false },
+            liquidity: ReserveLiquidity {
+                mint_decimals: 6,
+                available_amount,
+                accumulated_protocol_fees_wads: Decimal::from(accumulated_protocol_fees),
+                lifetime_forgiven_amount_wads: Decimal::from(forgiven),
+                market_price: Decimal::from(2u64),
+                ..ReserveLiquidity::default()
+            },
+            collateral: ReserveCollateral {
+                mint_total_supply: 1_000_000,
+                ..ReserveCollateral::default()
+            },
+            ..Reserve::default()
+        }
+    }
+
+    fn snapshot(reserves: Vec<(Pubkey, Reserve)>) -> SolendAccounts {
+        SolendAccounts {
+            lending_markets: HashMap::new(),
+            reserves: reserves.into_iter().collect(),
+            obligations: HashMap::new(),
+            obligation_borrow_caps: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn attribution_report() {
+        let usdc = Pubkey::new_unique();
+        let new_reserve = Pubkey::new_unique();
+        // 1 USDC of liquidity per cToken, 20k of protocol fees owed
+        let start = snapshot(vec![(usdc, reserve(100, 1_020_000, 20_000, 0))]);
+        // 150k of interest was paid: 100k went to depositors, 50k to the protocol, of which
+        // 30k was redeemed. Then 10k of debt was forgiven.
+        let end = snapshot(vec![
+            (usdc, reserve(200, 1_130_000, 40_000, 10_000)),
+            (new_reserve, reserve(200, 1_000_000, 0, 0)),
+        ]);
+        let events = vec![
+            FeeEvent::BorrowFee {
+                reserve: usdc,
+                amount: 1_000,
+                host_fee: 200,
+            },
+            FeeEvent::FlashLoanFee {
+                reserve: usdc,
+                amount: 500,
+                host_fee: 0,
+            },
+            FeeEvent::LiquidationFee {
+                reserve: usdc,
+                amount: 300,
+            },
+            FeeEvent::ProtocolFeesRedeemed {
+                reserve: usdc,
+                amount: 30_000,
+            },
+            FeeEvent::BorrowFee {
+                reserve: new_reserve,
+                amount: 1_000,
+                host_fee: 0,
+            },
+        ];
+
+        let report = generate_attribution_report(&start, &end, &events).unwrap();
+        assert_eq!(report.reserves.len(), 1);
+        let attribution = &report.reserves[0];
+        assert_eq!(attribution.start_slot, 100);
+        assert_eq!(attribution.end_slot, 200);
+        assert_eq!(attribution.depositor_interest, Decimal::from(100_000u64));
+        assert_eq!(attribution.forgiven_debt, Decimal::from(10_000u64));
+        assert_eq!(attribution.protocol_take, Decimal::from(50_000u64));
+        assert_eq!(attribution.origination_fees, 1_000);
+        assert_eq!(attribution.origination_host_fees, 200);
+        assert_eq!(attribution.flash_loan_fees, 500);
+        assert_eq!(attribution.liquidation_fees, 300);
+        // 50k + 800 + 500 + 300
+        assert_eq!(attribution.protocol_revenue, Decimal::from(51_600u64));
+        assert_eq!(
+            report.total_protocol_revenue_usd,
+            Decimal::from(1_032u64).try_div(10_000u64).unwrap()
+        );
+        assert_eq!(
+            report.total_depositor_interest_usd,
+            Decimal::from_percent(20)
+        );
+    }
+
+    #[test]
+    fn serialize_attribution_report() {
+        let usdc = Pubkey::new_unique();
+        let start = snapshot(vec![(usdc, reserve(100, 1_000_000, 0, 0))]);
+        let end = snapshot(vec![(usdc, reserve(200, 1_000_000, 0, 0))]);
+
+        let report = generate_attribution_report(&start, &end, &[]).unwrap();
+        let serialized = serde_yaml::to_string(&report).unwrap();
+        assert!(serialized.contains(&usdc.to_string()));
+        assert!(serialized.contains("0.000000000000000000"));
+    }
+}
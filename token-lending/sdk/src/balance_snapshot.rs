@@ -0,0 +1,526 @@
+//! Merkle snapshots of user balances.
+//!
+//! [user_balances] sums what every wallet has deposited into and borrowed from each liquidity
+//! mint at a target slot, across all of its obligations, in the underlying token. A
+//! [BalanceSnapshot] commits to one side of those balances with a merkle tree over
+//! (owner, mint, amount) leaves, so points and airdrop programs can publish the root on-chain and
+//! let users claim with their proof.
+//!
+//! The tree is built the way on-chain verifiers expect it:
+//! - leaves are `sha256(0x00 || owner || mint || amount as u64 le)`
+//! - nodes are `sha256(0x01 || min(left, right) || max(left, right))`, so a proof is just the
+//!   list of sibling hashes, without left/right flags
+//! - a node without a sibling is carried up to the next level unchanged
+//!
+//! Leaves are ordered by owner then mint, so the same balances always produce the same root.
+
+use crate::{
+    error::LendingError,
+    offchain_utils::{
+        offchain_refresh_obligation, offchain_refresh_reserve_interest, SolendAccounts,
+    },
+    positions::{ReserveBorrow, ReserveDeposit},
+    state::{classify_account, AccountType, Obligation},
+};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use solana_client::rpc_client::RpcClient;
+use solana_program::{
+    clock::Slot,
+    hash::{hashv, Hash},
+    program_error::ProgramError,
+    program_pack::Pack,
+    pubkey::Pubkey,
+};
+use std::{collections::BTreeMap, error::Error, fmt::Display, str::FromStr};
+
+const LEAF_PREFIX: &[u8] = &[0];
+const NODE_PREFIX: &[u8] = &[1];
+
+/// What a wallet has deposited into and borrowed from a liquidity mint, summed over its
+/// obligations
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UserBalance {
+    /// Wallet owning the obligations
+    #[serde(with = "base58")]
+    pub owner: Pubkey,
+    /// Mint of the underlying liquidity
+    #[serde(with = "base58")]
+    pub mint: Pubkey,
+    /// Liquidity the deposited cTokens redeem for, rounded down
+    pub deposited_amount: u64,
+    /// Borrowed liquidity including accrued interest, rounded up
+    pub borrowed_amount: u64,
+}
+
+/// Side of the balances a snapshot commits to
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BalanceKind {
+    /// Underlying deposits
+    Deposited,
+    /// Underlying borrows
+    Borrowed,
+}
+
+impl BalanceKind {
+    fn amount(&self, balance: &UserBalance) -> u64 {
+        match self {
+            Self::Deposited => balance.deposited_amount,
+            Self::Borrowed => balance.borrowed_amount,
+        }
+    }
+}
+
+/// A leaf of a snapshot and the proof that it is part of the tree
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BalanceClaim {
+    /// Wallet the amount belongs to
+    #[serde(with = "base58")]
+    pub owner: Pubkey,
+    /// Mint of the underlying liquidity
+    #[serde(with = "base58")]
+    pub mint: Pubkey,
+    /// Amount of the snapshot's kind, in native units
+    pub amount: u64,
+    /// Sibling hashes from the leaf up to the root
+    #[serde(with = "base58_vec")]
+    pub proof: Vec<Hash>,
+}
+
+impl BalanceClaim {
+    /// Hash of the claim's leaf
+    pub fn leaf(&self) -> Hash {
+        leaf_hash(&self.owner, &self.mint, self.amount)
+    }
+
+    /// Whether the claim's proof leads to `root`
+    pub fn verify(&self, root: &Hash) -> bool {
+        verify_proof(&self.proof, root, &self.leaf())
+    }
+}
+
+/// Merkle tree over the non-zero balances of one kind
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BalanceSnapshot {
+    /// Slot the balances were computed at
+    pub slot: Slot,
+    /// Side of the balances the tree commits to
+    pub kind: BalanceKind,
+    /// Root of the tree, the default hash if there are no claims
+    #[serde(with = "base58")]
+    pub root: Hash,
+    /// Claims ordered by owner then mint
+    pub claims: Vec<BalanceClaim>,
+}
+
+impl BalanceSnapshot {
+    /// Builds the tree over the `kind` amounts of `balances`. Balances with a zero amount are left
+    /// out, and balances of the same owner and mint are added up.
+    pub fn new(
+        slot: Slot,
+        kind: BalanceKind,
+        balances: &[UserBalance],
+    ) -> Result<Self, ProgramError> {
+        let mut amounts: BTreeMap<(Pubkey, Pubkey), u64> = BTreeMap::new();
+        for balance in balances {
+            let amount = kind.amount(balance);
+            if amount == 0 {
+                continue;
+            }
+            let total = amounts.entry((balance.owner, balance.mint)).or_default();
+            *total = total
+                .checked_add(amount)
+                .ok_or(LendingError::MathOverflow)?;
+        }
+
+        let mut claims: Vec<BalanceClaim> = amounts
+            .into_iter()
+            .map(|((owner, mint), amount)| BalanceClaim {
+                owner,
+                mint,
+                amount,
+                proof: vec![],
+            })
+            .collect();
+
+        let mut level: Vec<Hash> = claims.iter().map(BalanceClaim::leaf).collect();
+        // index of the node each claim's path goes through at the current level
+        let mut positions: Vec<usize> = (0..claims.len()).collect();
+        while level.len() > 1 {
+            for (claim, position) in claims.iter_mut().zip(positions.iter_mut()) {
+                if let Some(sibling) = level.get(*position ^ 1) {
+                    claim.proof.push(*sibling);
+                }
+                *position /= 2;
+            }
+            level = level
+                .chunks(2)
+                .map(|pair| match pair {
+                    [left, right] => node_hash(left, right),
+                    [node] => *node,
+                    _ => unreachable!(),
+                })
+                .collect();
+        }
+
+        Ok(Self {
+            slot,
+            kind,
+            root: level.first().copied().unwrap_or_default(),
+            claims,
+        })
+    }
+
+    /// Claim of `owner` for `mint`, if they have one
+    pub fn claim(&self, owner: &Pubkey, mint: &Pubkey) -> Option<&BalanceClaim> {
+        self.claims
+            .binary_search_by(|claim| (&claim.owner, &claim.mint).cmp(&(owner, mint)))
+            .ok()
+            .map(|index| &self.claims[index])
+    }
+}
+
+/// Hash of a snapshot leaf
+pub fn leaf_hash(owner: &Pubkey, mint: &Pubkey, amount: u64) -> Hash {
+    hashv(&[
+        LEAF_PREFIX,
+        owner.as_ref(),
+        mint.as_ref(),
+        &amount.to_le_bytes(),
+    ])
+}
+
+fn node_hash(a: &Hash, b: &Hash) -> Hash {
+    let (left, right) = if a <= b { (a, b) } else { (b, a) };
+    hashv(&[NODE_PREFIX, left.as_ref(), right.as_ref()])
+}
+
+/// Whether `proof` leads from `leaf` to `root`, as an on-chain claim program would check it
+pub fn verify_proof(proof: &[Hash], root: &Hash, leaf: &Hash) -> bool {
+    proof
+        .iter()
+        .fold(*leaf, |node, sibling| node_hash(&node, sibling))
+        == *root
+}
+
+/// Balances of every wallet in `accounts` at `slot`, ordered by owner then mint. Reserve interest
+/// is accrued up to `slot` and obligations are refreshed against the accrued reserves, so `slot`
+/// can't be older than the last update of any reserve. Fails if a reserve an obligation
+/// references is missing.
+///
+/// Obligations without borrows must be in `accounts` for their deposits to count, which
+/// `get_solend_accounts_as_map` doesn't keep; fetch with [get_snapshot_accounts] instead.
+pub fn user_balances(
+    accounts: &SolendAccounts,
+    slot: Slot,
+) -> Result<Vec<UserBalance>, Box<dyn Error>> {
+    let mut reserves = accounts.reserves.clone();
+    for reserve in reserves.values_mut() {
+        offchain_refresh_reserve_interest(reserve, slot)?;
+    }
+
+    let mut balances: BTreeMap<(Pubkey, Pubkey), UserBalance> = BTreeMap::new();
+    for obligation in accounts.obligations.values() {
+        obligation.check_position_reserves(&reserves)?;
+
+        let mut obligation = obligation.clone();
+        offchain_refresh_obligation(&mut obligation, &reserves)?;
+
+        for deposit in obligation.deposit_positions(&reserves) {
+            let ReserveDeposit {
+                collateral,
+                reserve,
+            } = deposit?;
+            let amount = reserve
+                .collateral_exchange_rate()?
+                .collateral_to_liquidity(collateral.deposited_amount)?;
+            let balance = balance_entry(&mut balances, &obligation, reserve.liquidity.mint_pubkey);
+            balance.deposited_amount = balance
+                .deposited_amount
+                .checked_add(amount)
+                .ok_or(LendingError::MathOverflow)?;
+        }
+
+        for borrow in obligation.borrow_positions(&reserves) {
+            let ReserveBorrow { liquidity, reserve } = borrow?;
+            let amount = liquidity.borrowed_amount_wads.try_ceil_u64()?;
+            let balance = balance_entry(&mut balances, &obligation, reserve.liquidity.mint_pubkey);
+            balance.borrowed_amount = balance
+                .borrowed_amount
+                .checked_add(amount)
+                .ok_or(LendingError::MathOverflow)?;
+        }
+    }
+
+    Ok(balances.into_values().collect())
+}
+
+fn balance_entry<'a>(
+    balances: &'a mut BTreeMap<(Pubkey, Pubkey), UserBalance>,
+    obligation: &Obligation,
+    mint: Pubkey,
+) -> &'a mut UserBalance {
+    balances
+        .entry((obligation.owner, mint))
+        .or_insert_with(|| UserBalance {
+            owner: obligation.owner,
+            mint,
+            ..UserBalance::default()
+        })
+}
+
+/// Fetch the lending markets, reserves and every obligation of the lending program, including
+/// the obligations without borrows that `get_solend_accounts_as_map` leaves out
+pub fn get_snapshot_accounts(
+    lending_program_id: &Pubkey,
+    client: &RpcClient,
+) -> Result<SolendAccounts, Box<dyn Error>> {
+    let accounts = client.get_program_accounts(lending_program_id)?;
+
+    let mut solend_accounts = SolendAccounts::default();
+    for (pubkey, account) in accounts {
+        if classify_account(&account.data) == Some(AccountType::Obligation) {
+            if let Ok(obligation) = Obligation::unpack(&account.data) {
+                solend_accounts.obligations.insert(pubkey, obligation);
+            }
+        } else {
+            solend_accounts.insert_account(pubkey, &account.data);
+        }
+    }
+
+    Ok(solend_accounts)
+}
+
+pub(crate) mod base58 {
+    use super::*;
+
+    pub fn serialize<T: Display, S: Serializer>(
+        value: &T,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(value)
+    }
+
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+    where
+        T: FromStr,
+        T::Err: Display,
+        D: Deserializer<'de>,
+    {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+mod base58_vec {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(hashes: &[Hash], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(hashes.iter().map(|hash| hash.to_string()))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Hash>, D::Error> {
+        Vec::<String>::deserialize(deserializer)?
+            .iter()
+            .map(|hash| hash.parse().map_err(de::Error::custom))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        math::{Decimal, TryAdd},
+        state::{
+            ObligationCollateral, ObligationLiquidity, Reserve, ReserveCollateral, ReserveLiquidity,
+        },
+    };
+    use std::collections::HashMap;
+
+    fn balance(owner: Pubkey, mint: Pubkey, deposited_amount: u64) -> UserBalance {
+        UserBalance {
+            owner,
+            mint,
+            deposited_amount,
+            borrowed_amount: 0,
+        }
+    }
+
+    #[test]
+    fn snapshot_proofs() {
+        for len in 0..=9 {
+            let balances: Vec<_> = (1..=len)
+                .map(|amount| balance(Pubkey::new_unique(), Pubkey::new_unique(), amount))
+                .collect();
+            let snapshot = BalanceSnapshot::new(1, BalanceKind::Deposited, &balances).unwrap();
+            assert_eq!(snapshot.claims.len(), len as usize);
+            if len == 0 {
+                assert_eq!(snapshot.root, Hash::default());
+            }
+            if len == 1 {
+                assert_eq!(snapshot.root, snapshot.claims[0].leaf());
+            }
+
+            for claim in &snapshot.claims {
+                assert!(claim.verify(&snapshot.root));
+                assert_eq!(snapshot.claim(&claim.owner, &claim.mint), Some(claim));
+
+                let inflated = BalanceClaim {
+                    amount: claim.amount + 1,
+                    ..claim.clone()
+                };
+                assert!(!inflated.verify(&snapshot.root));
+            }
+
+            // same balances in a different order give the same tree
+            let mut reversed = balances.clone();
+            reversed.reverse();
+            assert_eq!(
+                BalanceSnapshot::new(1, BalanceKind::Deposited, &reversed).unwrap(),
+                snapshot
+            );
+        }
+    }
+
+    #[test]
+    fn snapshot_amounts() {
+        let owner = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let balances = vec![
+            balance(owner, mint, 10),
+            balance(owner, mint, 5),
+            UserBalance {
+                borrowed_amount: 7,
+                ..balance(Pubkey::new_unique(), mint, 0)
+            },
+        ];
+
+        let deposits = BalanceSnapshot::new(1, BalanceKind::Deposited, &balances).unwrap();
+        assert_eq!(deposits.claims.len(), 1);
+        assert_eq!(deposits.claim(&owner, &mint).unwrap().amount, 15);
+
+        let borrows = BalanceSnapshot::new(1, BalanceKind::Borrowed, &balances).unwrap();
+        assert_eq!(borrows.claims.len(), 1);
+        assert_eq!(borrows.claims[0].amount, 7);
+        assert_eq!(borrows.claim(&owner, &mint), None);
+
+        assert_eq!(
+            BalanceSnapshot::new(
+                1,
+                BalanceKind::Deposited,
+                &[balance(owner, mint, u64::MAX), balance(owner, mint, 1)]
+            ),
+            Err(LendingError::MathOverflow.into())
+        );
+    }
+
+    #[test]
+    fn snapshot_serde() {
+        let balances: Vec<_> = (1..=3)
+            .map(|amount| balance(Pubkey::new_unique(), Pubkey::new_unique(), amount))
+            .collect();
+        let snapshot = BalanceSnapshot::new(42, BalanceKind::Deposited, &balances).unwrap();
+
+        let json = serde_json::to_value(&snapshot).unwrap();
+        assert_eq!(json["kind"], "deposited");
+        assert_eq!(json["root"], snapshot.root.to_string());
+        assert_eq!(
+            json["claims"][0]["owner"],
+            snapshot.claims[0].owner.to_string()
+        );
+        assert_eq!(
+            json["claims"][0]["proof"][0],
+            snapshot.claims[0].proof[0].to_string()
+        );
+
+        let deserialized: BalanceSnapshot = serde_json::from_value(json).unwrap();
+        assert_eq!(deserialized, snapshot);
+    }
+
+    #[test]
+    fn balances_across_obligations() {
+        let owner = Pubkey::new_unique();
+        let reserve = |mint_pubkey: Pubkey| Reserve {
+            liquidity: ReserveLiquidity {
+                mint_pubkey,
+                available_amount: 100,
+                borrowed_amount_wads: Decimal::from(100u64),
+                cumulative_borrow_rate_wads: Decimal::one(),
+                ..ReserveLiquidity::default()
+            },
+            // a cToken redeems for 2 tokens
+            collateral: ReserveCollateral {
+                mint_total_supply: 100,
+                ..ReserveCollateral::default()
+            },
+            ..Reserve::default()
+        };
+        let usdc_reserve = (Pubkey::new_unique(), reserve(Pubkey::new_unique()));
+        let sol_reserve = (Pubkey::new_unique(), reserve(Pubkey::new_unique()));
+        let usdc_mint = usdc_reserve.1.liquidity.mint_pubkey;
+        let sol_mint = sol_reserve.1.liquidity.mint_pubkey;
+
+        let deposit = |amount| ObligationCollateral {
+            deposited_amount: amount,
+            ..ObligationCollateral::new(usdc_reserve.0)
+        };
+        let obligations = HashMap::from([
+            (
+                Pubkey::new_unique(),
+                Obligation {
+                    owner,
+                    deposits: vec![deposit(10)],
+                    borrows: vec![ObligationLiquidity {
+                        borrowed_amount_wads: Decimal::from(5u64)
+                            .try_add(Decimal::from_bps(1))
+                            .unwrap(),
+                        ..ObligationLiquidity::new(sol_reserve.0, Decimal::one())
+                    }],
+                    ..Obligation::default()
+                },
+            ),
+            (
+                Pubkey::new_unique(),
+                Obligation {
+                    owner,
+                    deposits: vec![deposit(20)],
+                    ..Obligation::default()
+                },
+            ),
+        ]);
+
+        let mut accounts = SolendAccounts {
+            reserves: HashMap::from([usdc_reserve.clone(), sol_reserve.clone()]),
+            obligations,
+            ..SolendAccounts::default()
+        };
+
+        let (first, second) = if usdc_mint < sol_mint {
+            (usdc_mint, sol_mint)
+        } else {
+            (sol_mint, usdc_mint)
+        };
+        let balances = user_balances(&accounts, 0).unwrap();
+        assert_eq!(balances.len(), 2);
+        assert_eq!((balances[0].mint, balances[1].mint), (first, second));
+
+        let usdc = balances.iter().find(|b| b.mint == usdc_mint).unwrap();
+        assert_eq!(
+            usdc,
+            &UserBalance {
+                owner,
+                mint: usdc_mint,
+                deposited_amount: 60,
+                borrowed_amount: 0,
+            }
+        );
+        // borrows round up
+        let sol = balances.iter().find(|b| b.mint == sol_mint).unwrap();
+        assert_eq!(sol.borrowed_amount, 6);
+
+        accounts.reserves.remove(&sol_reserve.0);
+        assert!(user_balances(&accounts, 0).is_err());
+    }
+}
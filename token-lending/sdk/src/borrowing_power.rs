@@ -0,0 +1,360 @@
+//! Breakdown of an obligation's borrowing power, to answer "why can't I borrow more?".
+//!
+//! [explain_borrowing_power] splits the allowed borrow value of a refreshed obligation into what
+//! each deposit contributes and the borrowed value upper bound into what each borrow consumes,
+//! then evaluates every limit BorrowObligationLiquidity applies to a borrow from a given reserve
+//! and reports which one binds.
+
+use crate::{
+    error::LendingError,
+    math::{Decimal, Rate, TryDiv, TryMul, TrySub},
+    state::{LendingMarket, Obligation, Reserve, ReserveType},
+};
+use solana_program::{clock::Slot, program_error::ProgramError, pubkey::Pubkey};
+use std::collections::HashMap;
+
+/// What a deposit adds to the allowed borrow value
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DepositContribution {
+    /// Reserve the collateral was deposited into
+    pub reserve: Pubkey,
+    /// Value of the deposit at the market price
+    pub market_value: Decimal,
+    /// Value of the deposit at the lower bound of the market and smoothed prices
+    pub market_value_lower_bound: Decimal,
+    /// Loan to value ratio of the reserve, after any wind down
+    pub loan_to_value_ratio: Rate,
+    /// Contribution to the allowed borrow value, the lower bound value times the ratio
+    pub allowed_borrow_value: Decimal,
+}
+
+/// What a borrow takes from the borrowing power
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BorrowConsumption {
+    /// Reserve the liquidity was borrowed from
+    pub reserve: Pubkey,
+    /// Value of the borrow at the market price
+    pub market_value: Decimal,
+    /// Value of the borrow at the upper bound of the market and smoothed prices
+    pub market_value_upper_bound: Decimal,
+    /// Borrow weight of the reserve
+    pub borrow_weight: Decimal,
+    /// Contribution to the borrowed value upper bound, the upper bound value times the weight
+    pub borrowed_value_upper_bound: Decimal,
+}
+
+/// A limit on how much liquidity can be borrowed from a reserve
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum BorrowConstraint {
+    /// The obligation's remaining borrow value
+    BorrowingPower,
+    /// The reserve's borrow limit
+    ReserveBorrowLimit,
+    /// The reserve's borrow utilization cap
+    UtilizationCap,
+    /// The liquidity available in the reserve
+    AvailableLiquidity,
+    /// The lending market's outflow rate limiter
+    MarketRateLimiter,
+    /// The reserve's outflow rate limiter
+    ReserveRateLimiter,
+    /// Isolated tier assets can only be borrowed alone
+    IsolatedTier,
+    /// The reserve is winding down or closed
+    ReserveNotActive,
+}
+
+/// Why an obligation can borrow as much as it can from a reserve
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BorrowingPowerBreakdown {
+    /// Allowed borrow value of the obligation
+    pub allowed_borrow_value: Decimal,
+    /// Borrowed value upper bound of the obligation
+    pub borrowed_value_upper_bound: Decimal,
+    /// Value that can still be borrowed, zero once the allowed borrow value is used up
+    pub remaining_borrow_value: Decimal,
+    /// Contribution of every deposit, in the obligation's order
+    pub deposits: Vec<DepositContribution>,
+    /// Consumption of every borrow, in the obligation's order
+    pub borrows: Vec<BorrowConsumption>,
+    /// Liquidity amount each constraint allows borrowing from the reserve, before fees
+    pub limits: Vec<(BorrowConstraint, Decimal)>,
+    /// The constraint allowing the least, the first listed on ties
+    pub binding_constraint: BorrowConstraint,
+    /// Liquidity that can be borrowed from the reserve, before fees
+    pub max_borrow_amount: Decimal,
+}
+
+/// Explain the borrowing power of `obligation` for a borrow from `borrow_reserve` at `slot`.
+///
+/// The obligation and `reserves` are expected to be refreshed, e.g. with [Obligation::refresh].
+/// `reserves` must contain the borrow reserve and every reserve
+/// the obligation deposited into or borrowed from.
+pub fn explain_borrowing_power(
+    obligation: &Obligation,
+    reserves: &HashMap<Pubkey, Reserve>,
+    lending_market: &LendingMarket,
+    borrow_reserve_pubkey: &Pubkey,
+    slot: Slot,
+) -> Result<BorrowingPowerBreakdown, ProgramError> {
+    let find_reserve = |pubkey: &Pubkey| {
+        reserves
+            .get(pubkey)
+            .ok_or(ProgramError::from(LendingError::InvalidAccountInput))
+    };
+
+    let deposits = obligation
+        .deposits
+        .iter()
+        .map(|collateral| {
+            let reserve = find_reserve(&collateral.deposit_reserve)?;
+            let liquidity_amount = reserve
+                .collateral_exchange_rate()?
+                .decimal_collateral_to_liquidity(collateral.deposited_amount.into())?;
+            let market_value_lower_bound = reserve.market_value_lower_bound(liquidity_amount)?;
+            let loan_to_value_ratio = reserve.loan_to_value_ratio();
+            Ok(DepositContribution {
+                reserve: collateral.deposit_reserve,
+                market_value: reserve.market_value(liquidity_amount)?,
+                market_value_lower_bound,
+                loan_to_value_ratio,
+                allowed_borrow_value: market_value_lower_bound.try_mul(loan_to_value_ratio)?,
+            })
+        })
+        .collect::<Result<Vec<_>, ProgramError>>()?;
+
+    let borrows = obligation
+        .borrows
+        .iter()
+        .map(|liquidity| {
+            let reserve = find_reserve(&liquidity.borrow_reserve)?;
+            let market_value_upper_bound =
+                reserve.market_value_upper_bound(liquidity.borrowed_amount_wads)?;
+            let borrow_weight = reserve.borrow_weight();
+            Ok(BorrowConsumption {
+                reserve: liquidity.borrow_reserve,
+                market_value: reserve.market_value(liquidity.borrowed_amount_wads)?,
+                market_value_upper_bound,
+                borrow_weight,
+                borrowed_value_upper_bound: market_value_upper_bound.try_mul(borrow_weight)?,
+            })
+        })
+        .collect::<Result<Vec<_>, ProgramError>>()?;
+
+    // same limits, in the same order, as BorrowObligationLiquidity
+    let borrow_reserve = find_reserve(borrow_reserve_pubkey)?;
+    let remaining_borrow_value = obligation
+        .remaining_borrow_value()
+        .unwrap_or_else(|_| Decimal::zero());
+
+    let mut limits = vec![(
+        BorrowConstraint::ReserveNotActive,
+        if borrow_reserve.status.is_active() {
+            Decimal::from(u64::MAX)
+        } else {
+            Decimal::zero()
+        },
+    )];
+
+    let isolated_tier_violation = match borrow_reserve.config.reserve_type {
+        ReserveType::Isolated => obligation
+            .borrows
+            .iter()
+            .any(|liquidity| &liquidity.borrow_reserve != borrow_reserve_pubkey),
+        ReserveType::Regular => obligation.borrowing_isolated_asset,
+    };
+    limits.push((
+        BorrowConstraint::IsolatedTier,
+        if isolated_tier_violation {
+            Decimal::zero()
+        } else {
+            Decimal::from(u64::MAX)
+        },
+    ));
+
+    limits.push((
+        BorrowConstraint::BorrowingPower,
+        borrow_reserve.usd_to_liquidity_amount_lower_bound(
+            remaining_borrow_value.try_div(borrow_reserve.borrow_weight())?,
+        )?,
+    ));
+    limits.push((
+        BorrowConstraint::ReserveBorrowLimit,
+        Decimal::from(borrow_reserve.config.borrow_limit)
+            .try_sub(borrow_reserve.liquidity.borrowed_amount_wads)
+            .unwrap_or_else(|_| Decimal::zero()),
+    ));
+    limits.push((
+        BorrowConstraint::UtilizationCap,
+        borrow_reserve.remaining_borrow_under_utilization_cap()?,
+    ));
+    limits.push((
+        BorrowConstraint::AvailableLiquidity,
+        borrow_reserve.liquidity.available_amount.into(),
+    ));
+
+    // bounded by the remaining borrow value so an unlimited rate limiter doesn't overflow
+    let market_outflow = lending_market
+        .rate_limiter
+        .clone()
+        .remaining_outflow(slot)?
+        .min(remaining_borrow_value);
+    limits.push((
+        BorrowConstraint::MarketRateLimiter,
+        borrow_reserve.usd_to_liquidity_amount_lower_bound(market_outflow)?,
+    ));
+    limits.push((
+        BorrowConstraint::ReserveRateLimiter,
+        borrow_reserve
+            .rate_limiter
+            .clone()
+            .remaining_outflow(slot)?,
+    ));
+
+    let (binding_constraint, max_borrow_amount) = limits
+        .iter()
+        .copied()
+        .reduce(|min, limit| if limit.1 < min.1 { limit } else { min })
+        .unwrap();
+
+    Ok(BorrowingPowerBreakdown {
+        allowed_borrow_value: obligation.allowed_borrow_value,
+        borrowed_value_upper_bound: obligation.borrowed_value_upper_bound,
+        remaining_borrow_value,
+        deposits,
+        borrows,
+        limits,
+        binding_constraint,
+        max_borrow_amount,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::state::{
+        ObligationCollateral, ObligationLiquidity, ReserveConfig, ReserveLiquidity, ReserveStatus,
+    };
+
+    fn setup() -> (Obligation, HashMap<Pubkey, Reserve>, Pubkey, Pubkey) {
+        let sol = Pubkey::new_unique();
+        let usdc = Pubkey::new_unique();
+        let reserves = HashMap::from([
+            (
+                sol,
+                Reserve {
+                    liquidity: ReserveLiquidity {
+                        mint_decimals: 9,
+                        available_amount: 1_000_000_000_000,
+                        cumulative_borrow_rate_wads: Decimal::one(),
+                        market_price: Decimal::from(20u64),
+                        smoothed_market_price: Decimal::from(25u64),
+                        ..ReserveLiquidity::default()
+                    },
+                    config: ReserveConfig {
+                        loan_to_value_ratio: 50,
+                        liquidation_threshold: 80,
+                        max_liquidation_threshold: 90,
+                        borrow_limit: u64::MAX,
+                        ..ReserveConfig::default()
+                    },
+                    ..Reserve::default()
+                },
+            ),
+            (
+                usdc,
+                Reserve {
+                    liquidity: ReserveLiquidity {
+                        mint_decimals: 6,
+                        available_amount: 1_000_000_000,
+                        borrowed_amount_wads: Decimal::from(20_000_000u64),
+                        cumulative_borrow_rate_wads: Decimal::one(),
+                        market_price: Decimal::one(),
+                        smoothed_market_price: Decimal::one(),
+                        ..ReserveLiquidity::default()
+                    },
+                    config: ReserveConfig {
+                        added_borrow_weight_bps: 10_000,
+                        borrow_limit: u64::MAX,
+                        ..ReserveConfig::default()
+                    },
+                    ..Reserve::default()
+                },
+            ),
+        ]);
+
+        // 10 SOL deposited, 20 USDC borrowed
+        let mut obligation = Obligation {
+            deposits: vec![ObligationCollateral {
+                deposited_amount: 10_000_000_000,
+                ..ObligationCollateral::new(sol)
+            }],
+            borrows: vec![ObligationLiquidity {
+                borrowed_amount_wads: Decimal::from(20_000_000u64),
+                ..ObligationLiquidity::new(usdc, Decimal::one())
+            }],
+            ..Obligation::default()
+        };
+        obligation
+            .refresh(&[reserves[&sol].clone()], &[reserves[&usdc].clone()], 1)
+            .unwrap();
+        (obligation, reserves, sol, usdc)
+    }
+
+    #[test]
+    fn explain() {
+        let (obligation, mut reserves, sol, usdc) = setup();
+        let lending_market = LendingMarket::default();
+
+        let breakdown =
+            explain_borrowing_power(&obligation, &reserves, &lending_market, &usdc, 1).unwrap();
+        // $200 at the $20 lower bound price, 50% LTV
+        assert_eq!(
+            breakdown.deposits[0].market_value_lower_bound,
+            Decimal::from(200u64)
+        );
+        assert_eq!(
+            breakdown.deposits[0].allowed_borrow_value,
+            Decimal::from(100u64)
+        );
+        assert_eq!(breakdown.allowed_borrow_value, Decimal::from(100u64));
+        // $20 borrowed with a borrow weight of 2
+        assert_eq!(breakdown.borrows[0].borrow_weight, Decimal::from(2u64));
+        assert_eq!(
+            breakdown.borrows[0].borrowed_value_upper_bound,
+            Decimal::from(40u64)
+        );
+        assert_eq!(breakdown.remaining_borrow_value, Decimal::from(60u64));
+        // $60 of borrowing power buys $30 of weight 2 USDC
+        assert_eq!(
+            breakdown.binding_constraint,
+            BorrowConstraint::BorrowingPower
+        );
+        assert_eq!(breakdown.max_borrow_amount, Decimal::from(30_000_000u64));
+
+        // the reserve borrow limit binds once it's lower
+        reserves.get_mut(&usdc).unwrap().config.borrow_limit = 25_000_000;
+        let breakdown =
+            explain_borrowing_power(&obligation, &reserves, &lending_market, &usdc, 1).unwrap();
+        assert_eq!(
+            breakdown.binding_constraint,
+            BorrowConstraint::ReserveBorrowLimit
+        );
+        assert_eq!(breakdown.max_borrow_amount, Decimal::from(5_000_000u64));
+
+        // borrowing an isolated asset next to another borrow isn't possible at all
+        reserves.get_mut(&sol).unwrap().config.reserve_type = ReserveType::Isolated;
+        let breakdown =
+            explain_borrowing_power(&obligation, &reserves, &lending_market, &sol, 1).unwrap();
+        assert_eq!(breakdown.binding_constraint, BorrowConstraint::IsolatedTier);
+        assert_eq!(breakdown.max_borrow_amount, Decimal::zero());
+
+        reserves.get_mut(&usdc).unwrap().status = ReserveStatus::Closed;
+        let breakdown =
+            explain_borrowing_power(&obligation, &reserves, &lending_market, &usdc, 1).unwrap();
+        assert_eq!(
+            breakdown.binding_constraint,
+            BorrowConstraint::ReserveNotActive
+        );
+    }
+}
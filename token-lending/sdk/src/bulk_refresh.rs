@@ -0,0 +1,213 @@
+//! Parallel obligation refresh for off-chain bulk processing.
+//!
+//! [offchain_refresh_obligation] only needs read access to the reserves, so a whole market worth
+//! of obligations can be refreshed on every core at once. [bulk_refresh_obligations] shards the
+//! obligations across rayon's global thread pool and [bulk_refresh_obligations_with_threads]
+//! across a dedicated pool of a given size. Both return [BulkRefreshStats] aggregated over all
+//! obligations, the same as the single threaded [refresh_obligations] computes.
+//!
+//! `cargo run --release --example bulk_refresh --features parallel` benchmarks the scaling.
+
+use crate::{
+    math::{Decimal, TryAdd},
+    offchain_utils::offchain_refresh_obligation,
+    risk_band::is_liquidatable,
+    state::{Obligation, Reserve},
+};
+use rayon::prelude::*;
+use solana_program::{program_error::ProgramError, pubkey::Pubkey};
+use std::collections::HashMap;
+
+/// Aggregate outcome of refreshing many obligations
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BulkRefreshStats {
+    /// Obligations refreshed
+    pub refreshed: usize,
+    /// Obligations that failed to refresh, e.g. because a reserve is missing, with the error
+    pub failed: Vec<(Pubkey, String)>,
+    /// Refreshed obligations whose borrowed value reached their unhealthy borrow value
+    pub unhealthy: usize,
+    /// Deposited value of the refreshed obligations
+    pub deposited_value: Decimal,
+    /// Risk adjusted borrowed value of the refreshed obligations
+    pub borrowed_value: Decimal,
+}
+
+impl BulkRefreshStats {
+    fn record(&mut self, pubkey: Pubkey, obligation: &Obligation, result: Result<(), String>) {
+        match result.and_then(|()| {
+            self.deposited_value = self
+                .deposited_value
+                .try_add(obligation.deposited_value)
+                .map_err(|err| err.to_string())?;
+            self.borrowed_value = self
+                .borrowed_value
+                .try_add(obligation.borrowed_value)
+                .map_err(|err| err.to_string())?;
+            Ok(())
+        }) {
+            Ok(()) => {
+                self.refreshed += 1;
+                if is_liquidatable(obligation) {
+                    self.unhealthy += 1;
+                }
+            }
+            Err(err) => self.failed.push((pubkey, err)),
+        }
+    }
+
+    /// Combine the stats of two disjoint sets of obligations
+    pub fn merge(mut self, other: Self) -> Result<Self, ProgramError> {
+        self.refreshed += other.refreshed;
+        self.failed.extend(other.failed);
+        self.unhealthy += other.unhealthy;
+        self.deposited_value = self.deposited_value.try_add(other.deposited_value)?;
+        self.borrowed_value = self.borrowed_value.try_add(other.borrowed_value)?;
+        Ok(self)
+    }
+}
+
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "debug", skip_all, fields(obligation = %pubkey))
+)]
+fn refresh(
+    pubkey: Pubkey,
+    obligation: &mut Obligation,
+    reserves: &HashMap<Pubkey, Reserve>,
+) -> BulkRefreshStats {
+    let result = offchain_refresh_obligation(obligation, reserves).map_err(|err| err.to_string());
+    #[cfg(feature = "tracing")]
+    if let Err(err) = &result {
+        tracing::warn!(error = %err, "obligation refresh failed");
+    }
+    let mut stats = BulkRefreshStats::default();
+    stats.record(pubkey, obligation, result);
+    stats
+}
+
+/// Refresh obligations one after the other
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(skip_all, fields(obligations = obligations.len()), err)
+)]
+pub fn refresh_obligations(
+    obligations: &mut HashMap<Pubkey, Obligation>,
+    reserves: &HashMap<Pubkey, Reserve>,
+) -> Result<BulkRefreshStats, ProgramError> {
+    obligations
+        .iter_mut()
+        .map(|(pubkey, obligation)| refresh(*pubkey, obligation, reserves))
+        .try_fold(BulkRefreshStats::default(), BulkRefreshStats::merge)
+}
+
+/// Refresh obligations in parallel on rayon's global thread pool
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(skip_all, fields(obligations = obligations.len()), err)
+)]
+pub fn bulk_refresh_obligations(
+    obligations: &mut HashMap<Pubkey, Obligation>,
+    reserves: &HashMap<Pubkey, Reserve>,
+) -> Result<BulkRefreshStats, ProgramError> {
+    obligations
+        .par_iter_mut()
+        .map(|(pubkey, obligation)| Ok(refresh(*pubkey, obligation, reserves)))
+        .try_reduce(BulkRefreshStats::default, BulkRefreshStats::merge)
+}
+
+/// Refresh obligations in parallel on a dedicated pool of `num_threads` threads
+pub fn bulk_refresh_obligations_with_threads(
+    obligations: &mut HashMap<Pubkey, Obligation>,
+    reserves: &HashMap<Pubkey, Reserve>,
+    num_threads: usize,
+) -> Result<BulkRefreshStats, Box<dyn std::error::Error>> {
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(num_threads)
+        .build()?;
+    Ok(pool.install(|| bulk_refresh_obligations(obligations, reserves))?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::state::{
+        ObligationCollateral, ObligationLiquidity, ReserveConfig, ReserveLiquidity,
+    };
+
+    fn setup() -> (HashMap<Pubkey, Obligation>, HashMap<Pubkey, Reserve>) {
+        let reserve_pubkey = Pubkey::new_unique();
+        let reserves = HashMap::from([(
+            reserve_pubkey,
+            Reserve {
+                liquidity: ReserveLiquidity {
+                    cumulative_borrow_rate_wads: Decimal::one(),
+                    market_price: Decimal::one(),
+                    smoothed_market_price: Decimal::one(),
+                    ..ReserveLiquidity::default()
+                },
+                config: ReserveConfig {
+                    loan_to_value_ratio: 50,
+                    liquidation_threshold: 80,
+                    max_liquidation_threshold: 90,
+                    ..ReserveConfig::default()
+                },
+                ..Reserve::default()
+            },
+        )]);
+
+        // borrowing 0 to 99 against 100, liquidatable from 80
+        let obligations = (0..100u64)
+            .map(|borrowed| {
+                (
+                    Pubkey::new_unique(),
+                    Obligation {
+                        deposits: vec![ObligationCollateral {
+                            deposited_amount: 100,
+                            ..ObligationCollateral::new(reserve_pubkey)
+                        }],
+                        borrows: vec![ObligationLiquidity {
+                            borrowed_amount_wads: Decimal::from(borrowed),
+                            ..ObligationLiquidity::new(reserve_pubkey, Decimal::one())
+                        }],
+                        ..Obligation::default()
+                    },
+                )
+            })
+            .collect();
+        (obligations, reserves)
+    }
+
+    #[test]
+    fn parallel_matches_sequential() {
+        let (mut obligations, reserves) = setup();
+        let mut sequential_obligations = obligations.clone();
+
+        let stats = bulk_refresh_obligations_with_threads(&mut obligations, &reserves, 4).unwrap();
+        let sequential_stats = refresh_obligations(&mut sequential_obligations, &reserves).unwrap();
+        assert_eq!(stats, sequential_stats);
+        assert_eq!(obligations, sequential_obligations);
+
+        assert_eq!(stats.refreshed, 100);
+        assert!(stats.failed.is_empty());
+        assert_eq!(stats.unhealthy, 20);
+        assert_eq!(stats.deposited_value, Decimal::from(100 * 100u64));
+        assert_eq!(stats.borrowed_value, Decimal::from(99 * 100 / 2u64));
+    }
+
+    #[test]
+    fn failures_are_collected() {
+        let (mut obligations, reserves) = setup();
+        let missing_reserve = Obligation {
+            deposits: vec![ObligationCollateral::new(Pubkey::new_unique())],
+            ..Obligation::default()
+        };
+        let missing_pubkey = Pubkey::new_unique();
+        obligations.insert(missing_pubkey, missing_reserve);
+
+        let stats = bulk_refresh_obligations(&mut obligations, &reserves).unwrap();
+        assert_eq!(stats.refreshed, 100);
+        assert_eq!(stats.failed.len(), 1);
+        assert_eq!(stats.failed[0].0, missing_pubkey);
+    }
+}
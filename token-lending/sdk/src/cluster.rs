@@ -0,0 +1,135 @@
+//! Program ids, oracle program ids and known lending markets per cluster
+
+use crate::{
+    pyth_devnet, pyth_mainnet, pyth_testnet, solend_devnet, solend_mainnet, solend_testnet,
+    switchboard_v2_devnet, switchboard_v2_mainnet,
+};
+use solana_program::{pubkey, pubkey::Pubkey};
+use std::{fmt, str::FromStr};
+
+/// Main pool lending market on mainnet
+pub const MAINNET_MAIN_POOL: Pubkey = pubkey!("4UpD2fh7xH3VP9QQaXtsS1YY3bxzWhtfpks7FatyKvdY");
+
+/// Solana cluster the lending program is deployed to
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Cluster {
+    /// Mainnet beta
+    Mainnet,
+    /// Devnet
+    Devnet,
+    /// Testnet
+    Testnet,
+}
+
+impl Cluster {
+    /// Every cluster
+    pub const ALL: [Cluster; 3] = [Cluster::Mainnet, Cluster::Devnet, Cluster::Testnet];
+
+    /// Guess the cluster from an RPC url, defaulting to mainnet
+    pub fn from_url(url: &str) -> Self {
+        if url.contains("devnet") {
+            Cluster::Devnet
+        } else if url.contains("testnet") {
+            Cluster::Testnet
+        } else {
+            Cluster::Mainnet
+        }
+    }
+
+    /// Lending program id
+    pub fn program_id(&self) -> Pubkey {
+        match self {
+            Cluster::Mainnet => solend_mainnet::id(),
+            Cluster::Devnet => solend_devnet::id(),
+            Cluster::Testnet => solend_testnet::id(),
+        }
+    }
+
+    /// Pyth oracle program id
+    pub fn pyth_program_id(&self) -> Pubkey {
+        match self {
+            Cluster::Mainnet => pyth_mainnet::id(),
+            Cluster::Devnet => pyth_devnet::id(),
+            Cluster::Testnet => pyth_testnet::id(),
+        }
+    }
+
+    /// Switchboard v2 oracle program id, None where switchboard v2 isn't deployed
+    pub fn switchboard_v2_program_id(&self) -> Option<Pubkey> {
+        match self {
+            Cluster::Mainnet => Some(switchboard_v2_mainnet::id()),
+            Cluster::Devnet => Some(switchboard_v2_devnet::id()),
+            Cluster::Testnet => None,
+        }
+    }
+
+    /// Lending markets known to be live on the cluster
+    pub fn known_markets(&self) -> &'static [Pubkey] {
+        match self {
+            Cluster::Mainnet => &[MAINNET_MAIN_POOL],
+            Cluster::Devnet | Cluster::Testnet => &[],
+        }
+    }
+}
+
+impl fmt::Display for Cluster {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Cluster::Mainnet => "mainnet-beta",
+            Cluster::Devnet => "devnet",
+            Cluster::Testnet => "testnet",
+        })
+    }
+}
+
+impl FromStr for Cluster {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mainnet" | "mainnet-beta" => Ok(Cluster::Mainnet),
+            "devnet" => Ok(Cluster::Devnet),
+            "testnet" => Ok(Cluster::Testnet),
+            _ => Err(format!("Unknown cluster {}", s)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn cluster_names_and_urls() {
+        for cluster in Cluster::ALL {
+            assert_eq!(cluster.to_string().parse::<Cluster>().unwrap(), cluster);
+        }
+        assert_eq!("mainnet".parse::<Cluster>().unwrap(), Cluster::Mainnet);
+        assert!("localnet".parse::<Cluster>().is_err());
+
+        assert_eq!(
+            Cluster::from_url("https://api.mainnet-beta.solana.com"),
+            Cluster::Mainnet
+        );
+        assert_eq!(
+            Cluster::from_url("https://api.devnet.solana.com"),
+            Cluster::Devnet
+        );
+        assert_eq!(
+            Cluster::from_url("https://api.testnet.solana.com"),
+            Cluster::Testnet
+        );
+    }
+
+    #[test]
+    fn cluster_ids() {
+        assert_eq!(Cluster::Mainnet.program_id(), solend_mainnet::id());
+        assert_eq!(Cluster::Devnet.program_id(), solend_devnet::id());
+        assert_ne!(
+            Cluster::Mainnet.pyth_program_id(),
+            Cluster::Devnet.pyth_program_id()
+        );
+        assert_eq!(Cluster::Testnet.switchboard_v2_program_id(), None);
+        assert_eq!(Cluster::Mainnet.known_markets(), &[MAINNET_MAIN_POOL]);
+    }
+}
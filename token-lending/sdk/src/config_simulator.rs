@@ -0,0 +1,420 @@
+//! Impact of a reserve config change.
+//!
+//! [simulate_config_change] applies a new [ReserveConfig] and [RateLimiterConfig] to a copy of a
+//! reserve, the way UpdateReserveConfig would, and reports the borrow and supply rates before and
+//! after at the reserve's current utilization, the interest existing borrowers would pay over the
+//! next year, what happens to the rate limiter, and whether the program would accept the config.
+//! Projections hold utilization and the borrowed amounts constant.
+
+use crate::{
+    math::{
+        apr_to_apy, apr_to_slot_rate, apy_to_apr, Decimal, Rate, TryAdd, TryDiv, TryMul, TrySub,
+    },
+    state::{validate_reserve_config, RateLimiterConfig, Reserve, ReserveConfig},
+};
+use solana_program::{clock::Slot, program_error::ProgramError};
+use std::convert::TryFrom;
+
+/// Rates and yearly interest of a reserve under one config
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReserveRates {
+    /// Variable borrow APR
+    pub borrow_apr: Rate,
+    /// Variable borrow APY, compounded every slot
+    pub borrow_apy: Rate,
+    /// Depositor APR
+    pub supply_apr: Rate,
+    /// Depositor APY, net of the protocol take rate and the idle liquidity fee
+    pub supply_apy: Rate,
+    /// Interest variable rate borrowers pay over a year, in liquidity
+    pub variable_borrow_interest: Decimal,
+    /// Interest depositors earn over a year, in liquidity
+    pub depositor_interest: Decimal,
+    /// Protocol take of borrow interest plus idle liquidity fees over a year, in liquidity
+    pub protocol_interest: Decimal,
+    /// Slots until the cumulative borrow rate overflows at the borrow APR, None if it never does
+    pub overflow_horizon_slots: Option<u64>,
+}
+
+impl ReserveRates {
+    /// Rates of `reserve` under its current config
+    pub fn new(reserve: &Reserve) -> Result<Self, ProgramError> {
+        let liquidity = &reserve.liquidity;
+        let borrow_apr = reserve.current_borrow_rate()?;
+        let borrow_apy = apr_to_apy(borrow_apr, reserve.slots_per_year)?;
+
+        // fixed rate borrows keep paying the interest they locked in
+        let variable_borrowed_amount = liquidity
+            .borrowed_amount_wads
+            .try_sub(liquidity.fixed_borrowed_amount_wads)
+            .unwrap_or_else(|_| Decimal::zero());
+        let variable_borrow_interest = variable_borrowed_amount.try_mul(borrow_apy)?;
+        let borrow_interest =
+            variable_borrow_interest.try_add(Decimal::from(liquidity.fixed_interest_per_year))?;
+
+        let take_rate = Rate::from(reserve.config.protocol_take_rate());
+        let idle_liquidity = Decimal::from(liquidity.available_amount)
+            .max(liquidity.accumulated_protocol_fees_wads)
+            .try_sub(liquidity.accumulated_protocol_fees_wads)?;
+        let idle_fee_rate = Rate::one().try_sub(
+            Rate::one()
+                .try_sub(apr_to_slot_rate(
+                    reserve.idle_liquidity_fee_rate()?,
+                    reserve.slots_per_year,
+                )?)?
+                .try_pow(reserve.slots_per_year)?,
+        )?;
+        let idle_liquidity_fee = idle_liquidity.try_mul(idle_fee_rate)?;
+
+        let protocol_interest = borrow_interest
+            .try_mul(take_rate)?
+            .try_add(idle_liquidity_fee)?;
+        let depositor_interest = borrow_interest
+            .try_sub(protocol_interest)
+            .unwrap_or_else(|_| Decimal::zero());
+
+        let total_supply = liquidity.total_supply()?;
+        let supply_apy = if total_supply == Decimal::zero() {
+            Rate::zero()
+        } else {
+            Rate::try_from(depositor_interest.try_div(total_supply)?)?
+        };
+
+        Ok(Self {
+            borrow_apr,
+            borrow_apy,
+            supply_apr: apy_to_apr(supply_apy, reserve.slots_per_year)?,
+            supply_apy,
+            variable_borrow_interest,
+            depositor_interest,
+            protocol_interest,
+            overflow_horizon_slots: reserve.cumulative_borrow_rate_overflow_horizon(borrow_apr)?,
+        })
+    }
+}
+
+/// Outflow the rate limiter allows before and after the change
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RateLimiterImpact {
+    /// Whether the new rate limiter config differs from the current one, which restarts the
+    /// window and forgets the outflows already counted against it
+    pub resets_window: bool,
+    /// Outflow still allowed in the current window, u64::MAX if the rate limiter is disabled
+    pub remaining_outflow_before: Decimal,
+    /// Outflow allowed right after the change, u64::MAX if the rate limiter is disabled
+    pub remaining_outflow_after: Decimal,
+}
+
+/// Something the new config does to existing users that risk should know about before applying it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConfigWarning {
+    /// Utilization is above the new borrow utilization cap, so new borrows are blocked
+    UtilizationAboveBorrowCap,
+    /// Borrows are above the new borrow limit, so new borrows are blocked
+    BorrowedAboveBorrowLimit,
+    /// Deposits are above the new deposit limit, so new deposits are blocked
+    SupplyAboveDepositLimit,
+    /// The new rate limiter blocks all borrows and withdrawals
+    OutflowsDisabled,
+    /// The new config lowers the amount borrowers can borrow against their deposits
+    LoanToValueRatioDecreased,
+    /// The new config lowers the liquidation threshold, which can make open obligations
+    /// liquidatable right away
+    LiquidationThresholdDecreased,
+}
+
+/// Projected impact of a config change on a reserve
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConfigChangeImpact {
+    /// Utilization the projections are made at
+    pub utilization_rate: Rate,
+    /// Rates under the current config
+    pub before: ReserveRates,
+    /// Rates under the new config
+    pub after: ReserveRates,
+    /// Effect on the rate limiter
+    pub rate_limiter: RateLimiterImpact,
+    /// Result of the program's config validation, UpdateReserveConfig fails if this is an error
+    pub validation: Result<(), ProgramError>,
+    /// Side effects on existing users
+    pub warnings: Vec<ConfigWarning>,
+}
+
+impl ConfigChangeImpact {
+    /// Change in the interest existing variable rate borrowers pay over a year, and whether it is
+    /// an increase
+    pub fn borrow_interest_change(&self) -> Result<(Decimal, bool), ProgramError> {
+        change(
+            self.before.variable_borrow_interest,
+            self.after.variable_borrow_interest,
+        )
+    }
+
+    /// Change in the interest depositors earn over a year, and whether it is an increase
+    pub fn depositor_interest_change(&self) -> Result<(Decimal, bool), ProgramError> {
+        change(
+            self.before.depositor_interest,
+            self.after.depositor_interest,
+        )
+    }
+}
+
+fn change(before: Decimal, after: Decimal) -> Result<(Decimal, bool), ProgramError> {
+    if after >= before {
+        Ok((after.try_sub(before)?, true))
+    } else {
+        Ok((before.try_sub(after)?, false))
+    }
+}
+
+/// Projected impact of updating `reserve` to `new_config` and `new_rate_limiter_config` at
+/// `slot`. An invalid config is reported in [ConfigChangeImpact::validation], and only fails
+/// the simulation if the rates can't be computed under it.
+pub fn simulate_config_change(
+    reserve: &Reserve,
+    new_config: &ReserveConfig,
+    new_rate_limiter_config: RateLimiterConfig,
+    slot: Slot,
+) -> Result<ConfigChangeImpact, ProgramError> {
+    let mut updated = reserve.clone();
+    updated.config = *new_config;
+    updated
+        .rate_limiter
+        .set_config(new_rate_limiter_config, slot);
+
+    let validation = validate_reserve_config(*new_config);
+    let before = ReserveRates::new(reserve)?;
+    // an invalid config can break the rate curve itself, e.g. optimal below min borrow rate
+    let after = ReserveRates::new(&updated).map_err(|e| validation.clone().err().unwrap_or(e))?;
+
+    let rate_limiter = RateLimiterImpact {
+        resets_window: new_rate_limiter_config != reserve.rate_limiter.config,
+        remaining_outflow_before: reserve.rate_limiter.clone().remaining_outflow(slot)?,
+        remaining_outflow_after: updated.rate_limiter.remaining_outflow(slot)?,
+    };
+
+    let utilization_rate = reserve.liquidity.utilization_rate()?;
+    let mut warnings = vec![];
+    if new_config.borrow_utilization_cap != 0
+        && utilization_rate > Rate::from(new_config.borrow_utilization_cap())
+    {
+        warnings.push(ConfigWarning::UtilizationAboveBorrowCap);
+    }
+    if reserve.liquidity.borrowed_amount_wads > Decimal::from(new_config.borrow_limit) {
+        warnings.push(ConfigWarning::BorrowedAboveBorrowLimit);
+    }
+    if reserve.liquidity.total_supply()? > Decimal::from(new_config.deposit_limit) {
+        warnings.push(ConfigWarning::SupplyAboveDepositLimit);
+    }
+    if new_rate_limiter_config.window_duration > 0 && new_rate_limiter_config.max_outflow == 0 {
+        warnings.push(ConfigWarning::OutflowsDisabled);
+    }
+    if new_config.loan_to_value_ratio < reserve.config.loan_to_value_ratio {
+        warnings.push(ConfigWarning::LoanToValueRatioDecreased);
+    }
+    if new_config.liquidation_threshold < reserve.config.liquidation_threshold {
+        warnings.push(ConfigWarning::LiquidationThresholdDecreased);
+    }
+
+    Ok(ConfigChangeImpact {
+        utilization_rate,
+        before,
+        after,
+        rate_limiter,
+        validation,
+        warnings,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        error::LendingError,
+        state::{RateLimiter, ReserveCollateral, ReserveLiquidity, SLOTS_PER_YEAR},
+    };
+
+    // 1000 deposited, half of it borrowed, at optimal utilization
+    fn reserve() -> Reserve {
+        let rate_limiter_config = RateLimiterConfig {
+            window_duration: 10,
+            max_outflow: 100,
+        };
+        let mut rate_limiter = RateLimiter::new(rate_limiter_config, 0);
+        rate_limiter.update(0, Decimal::from(60u64)).unwrap();
+
+        Reserve {
+            liquidity: ReserveLiquidity {
+                available_amount: 500,
+                borrowed_amount_wads: Decimal::from(500u64),
+                cumulative_borrow_rate_wads: Decimal::one(),
+                ..ReserveLiquidity::default()
+            },
+            collateral: ReserveCollateral {
+                mint_total_supply: 1_000,
+                ..ReserveCollateral::default()
+            },
+            config: ReserveConfig {
+                optimal_utilization_rate: 50,
+                max_utilization_rate: 90,
+                loan_to_value_ratio: 50,
+                liquidation_threshold: 60,
+                max_liquidation_threshold: 70,
+                optimal_borrow_rate: 10,
+                max_borrow_rate: 50,
+                super_max_borrow_rate: 100,
+                deposit_limit: u64::MAX,
+                borrow_limit: u64::MAX,
+                ..ReserveConfig::default()
+            },
+            rate_limiter,
+            slots_per_year: SLOTS_PER_YEAR,
+            ..Reserve::default()
+        }
+    }
+
+    #[test]
+    fn higher_borrow_rate() {
+        let reserve = reserve();
+        let new_config = ReserveConfig {
+            optimal_borrow_rate: 20,
+            protocol_take_rate: 10,
+            ..reserve.config
+        };
+
+        let impact =
+            simulate_config_change(&reserve, &new_config, reserve.rate_limiter.config, 0).unwrap();
+        assert_eq!(impact.validation, Ok(()));
+        assert_eq!(impact.warnings, vec![]);
+        assert_eq!(impact.utilization_rate, Rate::from_percent(50));
+
+        assert_eq!(impact.before.borrow_apr, Rate::from_percent(10));
+        assert_eq!(impact.after.borrow_apr, Rate::from_percent(20));
+        assert!(impact.after.borrow_apy > impact.after.borrow_apr);
+        assert_eq!(
+            impact.after.variable_borrow_interest,
+            Decimal::from(500u64)
+                .try_mul(impact.after.borrow_apy)
+                .unwrap()
+        );
+
+        // without a take rate depositors get all the interest, spread over twice the liquidity
+        assert_eq!(
+            impact.before.depositor_interest,
+            impact.before.variable_borrow_interest
+        );
+        assert_eq!(
+            impact.before.supply_apy,
+            impact.before.borrow_apy.try_div(2).unwrap()
+        );
+        assert!(impact.before.supply_apr < impact.before.supply_apy);
+
+        // 10% of the interest goes to the protocol
+        assert_eq!(
+            impact.after.protocol_interest,
+            impact
+                .after
+                .variable_borrow_interest
+                .try_div(10u64)
+                .unwrap()
+        );
+        assert_eq!(
+            impact.after.depositor_interest,
+            impact
+                .after
+                .variable_borrow_interest
+                .try_sub(impact.after.protocol_interest)
+                .unwrap()
+        );
+
+        let (borrow_interest_change, increase) = impact.borrow_interest_change().unwrap();
+        assert!(increase);
+        assert_eq!(
+            borrow_interest_change,
+            impact
+                .after
+                .variable_borrow_interest
+                .try_sub(impact.before.variable_borrow_interest)
+                .unwrap()
+        );
+        assert!(impact.after.overflow_horizon_slots < impact.before.overflow_horizon_slots);
+
+        // same rate limiter config, so the current window carries over
+        assert_eq!(
+            impact.rate_limiter,
+            RateLimiterImpact {
+                resets_window: false,
+                remaining_outflow_before: Decimal::from(40u64),
+                remaining_outflow_after: Decimal::from(40u64),
+            }
+        );
+    }
+
+    #[test]
+    fn tighter_limits() {
+        let reserve = reserve();
+        let new_config = ReserveConfig {
+            loan_to_value_ratio: 40,
+            liquidation_threshold: 55,
+            borrow_limit: 400,
+            deposit_limit: 900,
+            ..reserve.config
+        };
+
+        let impact = simulate_config_change(
+            &reserve,
+            &new_config,
+            RateLimiterConfig {
+                window_duration: 10,
+                max_outflow: 0,
+            },
+            5,
+        )
+        .unwrap();
+        assert_eq!(impact.validation, Ok(()));
+        assert_eq!(
+            impact.warnings,
+            vec![
+                ConfigWarning::BorrowedAboveBorrowLimit,
+                ConfigWarning::SupplyAboveDepositLimit,
+                ConfigWarning::OutflowsDisabled,
+                ConfigWarning::LoanToValueRatioDecreased,
+                ConfigWarning::LiquidationThresholdDecreased,
+            ]
+        );
+        assert_eq!(impact.before, impact.after);
+        assert_eq!(impact.borrow_interest_change(), Ok((Decimal::zero(), true)));
+        assert_eq!(
+            impact.rate_limiter,
+            RateLimiterImpact {
+                resets_window: true,
+                remaining_outflow_before: Decimal::from(40u64),
+                remaining_outflow_after: Decimal::zero(),
+            }
+        );
+    }
+
+    #[test]
+    fn invalid_config() {
+        let reserve = reserve();
+
+        let new_config = ReserveConfig {
+            optimal_borrow_rate: 60,
+            ..reserve.config
+        };
+        let impact =
+            simulate_config_change(&reserve, &new_config, reserve.rate_limiter.config, 0).unwrap();
+        assert_eq!(impact.validation, Err(LendingError::InvalidConfig.into()));
+        assert_eq!(impact.after.borrow_apr, Rate::from_percent(60));
+
+        // the rate curve can't be evaluated with an optimal borrow rate below the min one
+        let new_config = ReserveConfig {
+            min_borrow_rate: 20,
+            ..reserve.config
+        };
+        assert_eq!(
+            simulate_config_change(&reserve, &new_config, reserve.rate_limiter.config, 0),
+            Err(LendingError::InvalidConfig.into())
+        );
+    }
+}
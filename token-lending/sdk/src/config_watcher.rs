@@ -0,0 +1,486 @@
+//! Lending market and reserve config change notifications.
+//!
+//! A [ConfigWatcher] caches the config of every lending market and reserve it has seen and, on
+//! every poll, diffs the fetched accounts against the cache. Each field that changed is reported
+//! as a [ConfigChanged] with its old and new values, so integrators can react to e.g. a new
+//! risk authority or a tighter rate limiter without tracking the accounts themselves.
+
+use crate::{
+    offchain_utils::{get_solend_accounts_as_map, SolendAccounts},
+    state::{LendingMarket, PriceBoundsMode, RateLimiterConfig, ReserveConfig, ReserveType},
+};
+use solana_client::rpc_client::RpcClient;
+use solana_program::pubkey::Pubkey;
+use std::{collections::HashMap, error::Error, fmt};
+
+/// Account whose config changed
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ConfigAccount {
+    /// The lending market itself
+    LendingMarket,
+    /// A reserve of the lending market
+    Reserve(Pubkey),
+}
+
+/// Value of a reserve config field
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConfigValue {
+    /// Percentages, rates and other single byte fields
+    U8(u8),
+    /// Basis point fields
+    U16(u16),
+    /// Price bounds
+    U32(u32),
+    /// Limits, wads and durations
+    U64(u64),
+    /// Fee receivers
+    Pubkey(Pubkey),
+    /// Optional fee receivers
+    OptionalPubkey(Option<Pubkey>),
+    /// Reserve type
+    ReserveType(ReserveType),
+    /// Price bounds mode
+    PriceBoundsMode(PriceBoundsMode),
+}
+
+macro_rules! impl_from_for_config_value {
+    ($($ty:ty => $variant:ident),+ $(,)?) => {
+        $(
+            impl From<$ty> for ConfigValue {
+                fn from(value: $ty) -> Self {
+                    ConfigValue::$variant(value)
+                }
+            }
+        )+
+    };
+}
+
+impl_from_for_config_value! {
+    u8 => U8,
+    u16 => U16,
+    u32 => U32,
+    u64 => U64,
+    Pubkey => Pubkey,
+    Option<Pubkey> => OptionalPubkey,
+    ReserveType => ReserveType,
+    PriceBoundsMode => PriceBoundsMode,
+}
+
+impl fmt::Display for ConfigValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigValue::U8(value) => write!(f, "{}", value),
+            ConfigValue::U16(value) => write!(f, "{}", value),
+            ConfigValue::U32(value) => write!(f, "{}", value),
+            ConfigValue::U64(value) => write!(f, "{}", value),
+            ConfigValue::Pubkey(value) => write!(f, "{}", value),
+            ConfigValue::OptionalPubkey(Some(value)) => write!(f, "{}", value),
+            ConfigValue::OptionalPubkey(None) => write!(f, "none"),
+            ConfigValue::ReserveType(value) => write!(f, "{:?}", value),
+            ConfigValue::PriceBoundsMode(value) => write!(f, "{:?}", value),
+        }
+    }
+}
+
+/// A config field that changed, with its old and new values
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ConfigChange {
+    /// Lending market owner
+    Owner {
+        /// Previous owner
+        old: Pubkey,
+        /// Current owner
+        new: Pubkey,
+    },
+    /// Lending market risk authority
+    RiskAuthority {
+        /// Previous risk authority
+        old: Pubkey,
+        /// Current risk authority
+        new: Pubkey,
+    },
+    /// Lending market whitelisted liquidator
+    WhitelistedLiquidator {
+        /// Previous whitelisted liquidator
+        old: Option<Pubkey>,
+        /// Current whitelisted liquidator
+        new: Option<Pubkey>,
+    },
+    /// Rate limiter of the lending market or of a reserve
+    RateLimiter {
+        /// Previous rate limiter config
+        old: RateLimiterConfig,
+        /// Current rate limiter config
+        new: RateLimiterConfig,
+    },
+    /// Lending market slots per year
+    SlotsPerYear {
+        /// Previous slots per year
+        old: u64,
+        /// Current slots per year
+        new: u64,
+    },
+    /// Field of a reserve config
+    ReserveConfig {
+        /// Name of the [ReserveConfig] field, e.g. `fees.borrow_fee_wad`
+        field: &'static str,
+        /// Previous value
+        old: ConfigValue,
+        /// Current value
+        new: ConfigValue,
+    },
+}
+
+/// The config of a lending market or reserve changed between two polls
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConfigChanged {
+    /// Lending market the changed account belongs to
+    pub lending_market: Pubkey,
+    /// Account whose config changed
+    pub account: ConfigAccount,
+    /// Changed field
+    pub change: ConfigChange,
+}
+
+/// Changes between two lending market configs
+pub fn lending_market_config_changes(
+    old: &LendingMarket,
+    new: &LendingMarket,
+) -> Vec<ConfigChange> {
+    let mut changes = Vec::new();
+    if old.owner != new.owner {
+        changes.push(ConfigChange::Owner {
+            old: old.owner,
+            new: new.owner,
+        });
+    }
+    if old.risk_authority != new.risk_authority {
+        changes.push(ConfigChange::RiskAuthority {
+            old: old.risk_authority,
+            new: new.risk_authority,
+        });
+    }
+    if old.whitelisted_liquidator != new.whitelisted_liquidator {
+        changes.push(ConfigChange::WhitelistedLiquidator {
+            old: old.whitelisted_liquidator,
+            new: new.whitelisted_liquidator,
+        });
+    }
+    if old.rate_limiter.config != new.rate_limiter.config {
+        changes.push(ConfigChange::RateLimiter {
+            old: old.rate_limiter.config,
+            new: new.rate_limiter.config,
+        });
+    }
+    if old.slots_per_year != new.slots_per_year {
+        changes.push(ConfigChange::SlotsPerYear {
+            old: old.slots_per_year,
+            new: new.slots_per_year,
+        });
+    }
+    changes
+}
+
+/// Changes between two reserve configs, one per changed field
+pub fn reserve_config_changes(old: &ReserveConfig, new: &ReserveConfig) -> Vec<ConfigChange> {
+    let mut changes = Vec::new();
+    macro_rules! diff {
+        ($($name:literal => $($field:ident).+),+ $(,)?) => {
+            $(
+                if old.$($field).+ != new.$($field).+ {
+                    changes.push(ConfigChange::ReserveConfig {
+                        field: $name,
+                        old: old.$($field).+.into(),
+                        new: new.$($field).+.into(),
+                    });
+                }
+            )+
+        };
+    }
+    diff! {
+        "optimal_utilization_rate" => optimal_utilization_rate,
+        "max_utilization_rate" => max_utilization_rate,
+        "loan_to_value_ratio" => loan_to_value_ratio,
+        "liquidation_bonus" => liquidation_bonus,
+        "max_liquidation_bonus" => max_liquidation_bonus,
+        "liquidation_threshold" => liquidation_threshold,
+        "max_liquidation_threshold" => max_liquidation_threshold,
+        "min_borrow_rate" => min_borrow_rate,
+        "optimal_borrow_rate" => optimal_borrow_rate,
+        "max_borrow_rate" => max_borrow_rate,
+        "super_max_borrow_rate" => super_max_borrow_rate,
+        "fees.borrow_fee_wad" => fees.borrow_fee_wad,
+        "fees.flash_loan_fee_wad" => fees.flash_loan_fee_wad,
+        "fees.host_fee_percentage" => fees.host_fee_percentage,
+        "deposit_limit" => deposit_limit,
+        "borrow_limit" => borrow_limit,
+        "fee_receiver" => fee_receiver,
+        "protocol_liquidation_fee" => protocol_liquidation_fee,
+        "protocol_take_rate" => protocol_take_rate,
+        "added_borrow_weight_bps" => added_borrow_weight_bps,
+        "reserve_type" => reserve_type,
+        "deposit_soft_cap_pct" => deposit_soft_cap_pct,
+        "borrow_soft_cap_pct" => borrow_soft_cap_pct,
+        "idle_liquidity_fee_bps" => idle_liquidity_fee_bps,
+        "liquidation_fee_receiver" => liquidation_fee_receiver,
+        "borrow_utilization_cap" => borrow_utilization_cap,
+        "fixed_borrow_rate_bps" => fixed_borrow_rate_bps,
+        "fixed_borrow_max_duration_slots" => fixed_borrow_max_duration_slots,
+        "withdraw_fee_bps" => withdraw_fee_bps,
+        "min_price_bps" => min_price_bps,
+        "max_price_bps" => max_price_bps,
+        "price_bounds_mode" => price_bounds_mode,
+    }
+    changes
+}
+
+#[derive(Clone, Debug)]
+struct CachedReserve {
+    lending_market: Pubkey,
+    config: ReserveConfig,
+    rate_limiter: RateLimiterConfig,
+}
+
+/// Tracks the configs of lending markets and reserves between polls
+#[derive(Clone, Debug, Default)]
+pub struct ConfigWatcher {
+    lending_market_filter: Option<Pubkey>,
+    lending_markets: HashMap<Pubkey, LendingMarket>,
+    reserves: HashMap<Pubkey, CachedReserve>,
+}
+
+impl ConfigWatcher {
+    /// Create a watcher for every lending market of the program
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a watcher for a single lending market and its reserves
+    pub fn for_lending_market(lending_market: Pubkey) -> Self {
+        Self {
+            lending_market_filter: Some(lending_market),
+            ..Self::default()
+        }
+    }
+
+    fn is_watched(&self, lending_market: &Pubkey) -> bool {
+        match self.lending_market_filter {
+            Some(filter) => &filter == lending_market,
+            None => true,
+        }
+    }
+
+    /// Diff the configs in `accounts` against the cached ones, cache them, and return a change for
+    /// every field that differs. Accounts seen for the first time are cached without changes, and
+    /// ones missing from `accounts` keep their cached config.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            skip_all,
+            fields(
+                lending_markets = accounts.lending_markets.len(),
+                reserves = accounts.reserves.len()
+            )
+        )
+    )]
+    pub fn check(&mut self, accounts: &SolendAccounts) -> Vec<ConfigChanged> {
+        let mut changes = Vec::new();
+        for (pubkey, lending_market) in &accounts.lending_markets {
+            if !self.is_watched(pubkey) {
+                continue;
+            }
+            if let Some(cached) = self.lending_markets.get(pubkey) {
+                changes.extend(
+                    lending_market_config_changes(cached, lending_market)
+                        .into_iter()
+                        .map(|change| ConfigChanged {
+                            lending_market: *pubkey,
+                            account: ConfigAccount::LendingMarket,
+                            change,
+                        }),
+                );
+            }
+            self.lending_markets.insert(*pubkey, lending_market.clone());
+        }
+
+        for (pubkey, reserve) in &accounts.reserves {
+            if !self.is_watched(&reserve.lending_market) {
+                continue;
+            }
+            let current = CachedReserve {
+                lending_market: reserve.lending_market,
+                config: reserve.config,
+                rate_limiter: reserve.rate_limiter.config,
+            };
+            if let Some(cached) = self.reserves.get(pubkey) {
+                let mut reserve_changes = reserve_config_changes(&cached.config, &current.config);
+                if cached.rate_limiter != current.rate_limiter {
+                    reserve_changes.push(ConfigChange::RateLimiter {
+                        old: cached.rate_limiter,
+                        new: current.rate_limiter,
+                    });
+                }
+                changes.extend(reserve_changes.into_iter().map(|change| ConfigChanged {
+                    lending_market: current.lending_market,
+                    account: ConfigAccount::Reserve(*pubkey),
+                    change,
+                }));
+            }
+            self.reserves.insert(*pubkey, current);
+        }
+
+        #[cfg(feature = "tracing")]
+        for change in &changes {
+            trace_event!(
+                info,
+                lending_market = %change.lending_market,
+                account = ?change.account,
+                change = ?change.change,
+                "config changed"
+            );
+        }
+        changes
+    }
+
+    /// Fetch the lending program accounts and [check](Self::check) them
+    pub fn poll(
+        &mut self,
+        lending_program_id: &Pubkey,
+        client: &RpcClient,
+    ) -> Result<Vec<ConfigChanged>, Box<dyn Error>> {
+        let accounts = get_solend_accounts_as_map(lending_program_id, client)?;
+        Ok(self.check(&accounts))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::state::Reserve;
+
+    fn accounts_with(
+        lending_markets: &[(Pubkey, LendingMarket)],
+        reserves: &[(Pubkey, Reserve)],
+    ) -> SolendAccounts {
+        SolendAccounts {
+            lending_markets: lending_markets.iter().cloned().collect(),
+            reserves: reserves.iter().cloned().collect(),
+            obligations: HashMap::new(),
+            obligation_borrow_caps: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn reserve_config_changes_per_field() {
+        let old = ReserveConfig::default();
+        let new = ReserveConfig {
+            loan_to_value_ratio: 75,
+            liquidation_fee_receiver: Some(Pubkey::new_unique()),
+            ..old
+        };
+        let mut new_fees = new;
+        new_fees.fees.borrow_fee_wad = 1;
+
+        assert_eq!(reserve_config_changes(&old, &old), vec![]);
+        assert_eq!(
+            reserve_config_changes(&old, &new_fees),
+            vec![
+                ConfigChange::ReserveConfig {
+                    field: "loan_to_value_ratio",
+                    old: ConfigValue::U8(0),
+                    new: ConfigValue::U8(75),
+                },
+                ConfigChange::ReserveConfig {
+                    field: "fees.borrow_fee_wad",
+                    old: ConfigValue::U64(0),
+                    new: ConfigValue::U64(1),
+                },
+                ConfigChange::ReserveConfig {
+                    field: "liquidation_fee_receiver",
+                    old: ConfigValue::OptionalPubkey(None),
+                    new: ConfigValue::OptionalPubkey(new.liquidation_fee_receiver),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn watcher_reports_changes_between_polls() {
+        let lending_market_pubkey = Pubkey::new_unique();
+        let other_market_pubkey = Pubkey::new_unique();
+        let reserve_pubkey = Pubkey::new_unique();
+        let lending_market = LendingMarket::default();
+        let reserve = Reserve {
+            lending_market: lending_market_pubkey,
+            ..Reserve::default()
+        };
+        let mut watcher = ConfigWatcher::for_lending_market(lending_market_pubkey);
+
+        // the first poll only fills the cache
+        let accounts = accounts_with(
+            &[
+                (lending_market_pubkey, lending_market.clone()),
+                (other_market_pubkey, lending_market.clone()),
+            ],
+            &[(reserve_pubkey, reserve.clone())],
+        );
+        assert_eq!(watcher.check(&accounts), vec![]);
+        assert_eq!(watcher.check(&accounts), vec![]);
+
+        let risk_authority = Pubkey::new_unique();
+        let mut updated_market = lending_market.clone();
+        updated_market.risk_authority = risk_authority;
+        updated_market.rate_limiter.config.max_outflow = 100;
+        let mut updated_reserve = reserve.clone();
+        updated_reserve.config.deposit_limit = 1_000;
+        let accounts = accounts_with(
+            &[
+                (lending_market_pubkey, updated_market.clone()),
+                // other markets are ignored
+                (other_market_pubkey, updated_market),
+            ],
+            &[(reserve_pubkey, updated_reserve)],
+        );
+        let mut changes = watcher.check(&accounts);
+        changes.sort_by_key(|change| change.account != ConfigAccount::LendingMarket);
+        assert_eq!(
+            changes,
+            vec![
+                ConfigChanged {
+                    lending_market: lending_market_pubkey,
+                    account: ConfigAccount::LendingMarket,
+                    change: ConfigChange::RiskAuthority {
+                        old: Pubkey::default(),
+                        new: risk_authority,
+                    },
+                },
+                ConfigChanged {
+                    lending_market: lending_market_pubkey,
+                    account: ConfigAccount::LendingMarket,
+                    change: ConfigChange::RateLimiter {
+                        old: lending_market.rate_limiter.config,
+                        new: RateLimiterConfig {
+                            max_outflow: 100,
+                            ..lending_market.rate_limiter.config
+                        },
+                    },
+                },
+                ConfigChanged {
+                    lending_market: lending_market_pubkey,
+                    account: ConfigAccount::Reserve(reserve_pubkey),
+                    change: ConfigChange::ReserveConfig {
+                        field: "deposit_limit",
+                        old: ConfigValue::U64(0),
+                        new: ConfigValue::U64(1_000),
+                    },
+                },
+            ]
+        );
+
+        // reserves missing from a poll keep their cached config
+        let accounts = accounts_with(&[], &[]);
+        assert_eq!(watcher.check(&accounts), vec![]);
+        let accounts = accounts_with(&[], &[(reserve_pubkey, reserve)]);
+        assert_eq!(watcher.check(&accounts).len(), 1);
+    }
+}
@@ -0,0 +1,286 @@
+//! Account structs for calling the lending program from other programs.
+//!
+//! Every struct lists the accounts the matching instruction expects, in the same order as
+//! [`LendingInstruction`](crate::instruction::LendingInstruction) documents them, and invokes it
+//! with `invoke_signed`. This lets a program own obligations through a PDA: the PDA is passed as
+//! the obligation owner and its seeds as `signers_seeds`. Signers of the outer instruction, such
+//! as a user acting as transfer authority, keep their signature through the CPI and need no seeds.
+//!
+//! The lending market authority, obligation limits and market policy accounts have to be the PDAs
+//! derived by the instruction builders, otherwise the CPI fails with a missing account. The
+//! obligation limits and market policy accounts don't have to exist unless the lending market
+//! has limits or a policy.
+
+use crate::{
+    error::LendingError,
+    instruction::{
+        append_policy_accounts, deposit_reserve_liquidity_and_obligation_collateral,
+        init_obligation, insert_withdraw_fee_receiver, view_obligation_health,
+        withdraw_obligation_collateral_and_redeem_reserve_collateral,
+    },
+    state::ObligationHealth,
+};
+use solana_program::{
+    account_info::AccountInfo,
+    entrypoint::ProgramResult,
+    msg,
+    program::{get_return_data, invoke, invoke_signed},
+    program_error::ProgramError,
+    program_pack::Pack,
+};
+
+/// Accounts of an `InitObligation` CPI. The obligation has to be allocated by the caller with
+/// `Obligation::LEN` bytes and owned by the lending program.
+pub struct InitObligation<'a> {
+    /// Obligation account, uninitialized
+    pub obligation: AccountInfo<'a>,
+    /// Lending market account
+    pub lending_market: AccountInfo<'a>,
+    /// Obligation owner, signs
+    pub obligation_owner: AccountInfo<'a>,
+    /// Rent sysvar
+    pub rent: AccountInfo<'a>,
+    /// Token program
+    pub token_program: AccountInfo<'a>,
+    /// Lending program
+    pub lending_program: AccountInfo<'a>,
+}
+
+impl<'a> InitObligation<'a> {
+    /// Invoke the lending program, signing with `signers_seeds`
+    pub fn invoke_signed(self, signers_seeds: &[&[&[u8]]]) -> ProgramResult {
+        invoke_signed(
+            &init_obligation(
+                *self.lending_program.key,
+                *self.obligation.key,
+                *self.lending_market.key,
+                *self.obligation_owner.key,
+            ),
+            &[
+                self.obligation,
+                self.lending_market,
+                self.obligation_owner,
+                self.rent,
+                self.token_program,
+                self.lending_program,
+            ],
+            signers_seeds,
+        )
+    }
+}
+
+/// Accounts of a `DepositReserveLiquidityAndObligationCollateral` CPI
+pub struct DepositReserveLiquidityAndObligationCollateral<'a> {
+    /// Source liquidity token account, owned by `user_transfer_authority`
+    pub source_liquidity: AccountInfo<'a>,
+    /// Collateral token account the cTokens are minted to before being deposited, owned by
+    /// `user_transfer_authority`
+    pub user_collateral: AccountInfo<'a>,
+    /// Reserve account
+    pub reserve: AccountInfo<'a>,
+    /// Reserve liquidity supply token account
+    pub reserve_liquidity_supply: AccountInfo<'a>,
+    /// Reserve collateral mint
+    pub reserve_collateral_mint: AccountInfo<'a>,
+    /// Lending market account
+    pub lending_market: AccountInfo<'a>,
+    /// Derived lending market authority
+    pub lending_market_authority: AccountInfo<'a>,
+    /// Reserve collateral supply token account
+    pub destination_deposit_collateral: AccountInfo<'a>,
+    /// Obligation account
+    pub obligation: AccountInfo<'a>,
+    /// Obligation owner, signs
+    pub obligation_owner: AccountInfo<'a>,
+    /// Reserve pyth price account
+    pub pyth_oracle: AccountInfo<'a>,
+    /// Reserve switchboard feed account
+    pub switchboard_oracle: AccountInfo<'a>,
+    /// Transfer authority of the source liquidity and user collateral, signs
+    pub user_transfer_authority: AccountInfo<'a>,
+    /// Token program
+    pub token_program: AccountInfo<'a>,
+    /// Derived obligation limits account
+    pub obligation_limits: AccountInfo<'a>,
+    /// Lending program
+    pub lending_program: AccountInfo<'a>,
+}
+
+impl<'a> DepositReserveLiquidityAndObligationCollateral<'a> {
+    /// Invoke the lending program, signing with `signers_seeds`
+    pub fn invoke_signed(self, liquidity_amount: u64, signers_seeds: &[&[&[u8]]]) -> ProgramResult {
+        invoke_signed(
+            &deposit_reserve_liquidity_and_obligation_collateral(
+                *self.lending_program.key,
+                liquidity_amount,
+                *self.source_liquidity.key,
+                *self.user_collateral.key,
+                *self.reserve.key,
+                *self.reserve_liquidity_supply.key,
+                *self.reserve_collateral_mint.key,
+                *self.lending_market.key,
+                *self.destination_deposit_collateral.key,
+                *self.obligation.key,
+                *self.obligation_owner.key,
+                *self.pyth_oracle.key,
+                *self.switchboard_oracle.key,
+                *self.user_transfer_authority.key,
+            ),
+            &[
+                self.source_liquidity,
+                self.user_collateral,
+                self.reserve,
+                self.reserve_liquidity_supply,
+                self.reserve_collateral_mint,
+                self.lending_market,
+                self.lending_market_authority,
+                self.destination_deposit_collateral,
+                self.obligation,
+                self.obligation_owner,
+                self.pyth_oracle,
+                self.switchboard_oracle,
+                self.user_transfer_authority,
+                self.token_program,
+                self.obligation_limits,
+                self.lending_program,
+            ],
+            signers_seeds,
+        )
+    }
+}
+
+/// Accounts of a `WithdrawObligationCollateralAndRedeemReserveCollateral` CPI. The reserve and
+/// obligation have to be refreshed earlier in the same transaction.
+pub struct WithdrawObligationCollateralAndRedeemReserveCollateral<'a> {
+    /// Reserve collateral supply token account
+    pub source_collateral: AccountInfo<'a>,
+    /// Collateral token account the cTokens are withdrawn to before being redeemed, owned by
+    /// `user_transfer_authority`
+    pub destination_collateral: AccountInfo<'a>,
+    /// Reserve account, refreshed
+    pub withdraw_reserve: AccountInfo<'a>,
+    /// Obligation account, refreshed
+    pub obligation: AccountInfo<'a>,
+    /// Lending market account
+    pub lending_market: AccountInfo<'a>,
+    /// Derived lending market authority
+    pub lending_market_authority: AccountInfo<'a>,
+    /// Liquidity token account receiving the redeemed liquidity
+    pub destination_liquidity: AccountInfo<'a>,
+    /// Reserve collateral mint
+    pub reserve_collateral_mint: AccountInfo<'a>,
+    /// Reserve liquidity supply token account
+    pub reserve_liquidity_supply: AccountInfo<'a>,
+    /// Obligation owner, signs
+    pub obligation_owner: AccountInfo<'a>,
+    /// Transfer authority of the destination collateral, signs
+    pub user_transfer_authority: AccountInfo<'a>,
+    /// Token program
+    pub token_program: AccountInfo<'a>,
+    /// Derived obligation limits account
+    pub obligation_limits: AccountInfo<'a>,
+    /// Derived market policy account
+    pub market_policy: AccountInfo<'a>,
+    /// Lending program
+    pub lending_program: AccountInfo<'a>,
+    /// Reserve liquidity fee receiver, required if the reserve charges a withdraw fee
+    pub reserve_liquidity_fee_receiver: Option<AccountInfo<'a>>,
+    /// Policy program of the lending market followed by its policy account, if any, empty if the
+    /// market has no policy
+    pub policy_accounts: Vec<AccountInfo<'a>>,
+}
+
+impl<'a> WithdrawObligationCollateralAndRedeemReserveCollateral<'a> {
+    /// Invoke the lending program, signing with `signers_seeds`
+    pub fn invoke_signed(
+        self,
+        collateral_amount: u64,
+        signers_seeds: &[&[&[u8]]],
+    ) -> ProgramResult {
+        let mut instruction = withdraw_obligation_collateral_and_redeem_reserve_collateral(
+            *self.lending_program.key,
+            collateral_amount,
+            *self.source_collateral.key,
+            *self.destination_collateral.key,
+            *self.withdraw_reserve.key,
+            *self.obligation.key,
+            *self.lending_market.key,
+            *self.destination_liquidity.key,
+            *self.reserve_collateral_mint.key,
+            *self.reserve_liquidity_supply.key,
+            *self.obligation_owner.key,
+            *self.user_transfer_authority.key,
+        );
+        if let Some(reserve_liquidity_fee_receiver) = &self.reserve_liquidity_fee_receiver {
+            insert_withdraw_fee_receiver(&mut instruction, *reserve_liquidity_fee_receiver.key);
+        }
+        if let Some((policy_program, policy_account)) = self.policy_accounts.split_first() {
+            append_policy_accounts(
+                &mut instruction,
+                *policy_program.key,
+                policy_account.first().map(|account_info| *account_info.key),
+            );
+        }
+        let mut account_infos = vec![
+            self.source_collateral,
+            self.destination_collateral,
+            self.withdraw_reserve,
+            self.obligation,
+            self.lending_market,
+            self.lending_market_authority,
+            self.destination_liquidity,
+            self.reserve_collateral_mint,
+            self.reserve_liquidity_supply,
+            self.obligation_owner,
+            self.user_transfer_authority,
+            self.token_program,
+            self.obligation_limits,
+            self.market_policy,
+            self.lending_program,
+        ];
+        account_infos.extend(self.reserve_liquidity_fee_receiver);
+        account_infos.extend(self.policy_accounts);
+
+        invoke_signed(&instruction, &account_infos, signers_seeds)
+    }
+}
+
+/// Accounts of a `ViewObligationHealth` CPI. The reserves have to be refreshed earlier in the same
+/// transaction.
+pub struct ViewObligationHealth<'a> {
+    /// Obligation account
+    pub obligation: AccountInfo<'a>,
+    /// Deposit reserves of the obligation followed by its borrow reserves, in order
+    pub reserves: Vec<AccountInfo<'a>>,
+    /// Lending program
+    pub lending_program: AccountInfo<'a>,
+}
+
+impl<'a> ViewObligationHealth<'a> {
+    /// Invoke the lending program and read the obligation health it returns
+    pub fn invoke(self) -> Result<ObligationHealth, ProgramError> {
+        let instruction = view_obligation_health(
+            *self.lending_program.key,
+            *self.obligation.key,
+            self.reserves.iter().map(|reserve| *reserve.key).collect(),
+        );
+        let lending_program_id = *self.lending_program.key;
+        let mut account_infos = vec![self.obligation];
+        account_infos.extend(self.reserves);
+        account_infos.push(self.lending_program);
+
+        invoke(&instruction, &account_infos)?;
+
+        match get_return_data() {
+            Some((program_id, data))
+                if program_id == lending_program_id && data.len() == ObligationHealth::LEN =>
+            {
+                ObligationHealth::unpack_from_slice(&data)
+            }
+            _ => {
+                msg!("Lending program did not return the obligation health");
+                Err(LendingError::InvalidAccountInput.into())
+            }
+        }
+    }
+}
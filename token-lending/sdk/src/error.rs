@@ -203,6 +203,78 @@ pub enum LendingError {
     /// Isolated Tier Asset Violation
     #[error("Isolated Tier Asset Violation")]
     IsolatedTierAssetViolation,
+    /// Borrow amount exceeds the delegate's borrow allowance
+    #[error("Borrow amount exceeds the delegate's borrow allowance")]
+    InsufficientBorrowAllowance,
+    /// Borrow allowance limit exceeded
+    #[error("Borrow allowance limit exceeded")]
+    BorrowAllowanceLimit,
+    /// Account state violates a lending invariant
+    #[error("Account state violates a lending invariant")]
+    InvariantViolation,
+    /// Obligation still has deposits or borrows
+    #[error("Obligation still has deposits or borrows")]
+    ObligationNotEmpty,
+    /// Reserve is winding down or closed
+    #[error("Reserve is winding down or closed")]
+    ReserveNotActive,
+    /// Liquidations are paused by the liquidation circuit breaker
+    #[error("Liquidations are paused by the liquidation circuit breaker")]
+    LiquidationCircuitBreakerTripped,
+    /// Obligation reached the lending market's limit of deposits or borrows
+    #[error("Obligation reached the lending market's limit of deposits or borrows")]
+    ObligationPositionLimit,
+    /// Pending config change can't be activated before its activation slot
+    #[error("Pending config change can't be activated before its activation slot")]
+    ConfigChangeTimelocked,
+    /// Obligation owner prefers other collateral to be seized first
+    #[error("Obligation owner prefers other collateral to be seized first")]
+    LiquidationPriorityViolation,
+    /// Lending market reached the max number of fee rebates
+    #[error("Lending market reached the max number of fee rebates")]
+    FeeRebateLimit,
+    /// Borrow would push the reserve utilization above its borrow utilization cap
+    #[error("Borrow would push the reserve utilization above its borrow utilization cap")]
+    BorrowUtilizationCapExceeded,
+    /// Reserve does not allow fixed rate borrows
+    #[error("Reserve does not allow fixed rate borrows")]
+    FixedRateBorrowDisabled,
+    /// Borrow rate mode does not match the existing borrow position
+    #[error("Borrow rate mode does not match the existing borrow position")]
+    BorrowRateModeMismatch,
+    /// Cumulative borrow rate would overflow
+    #[error("Cumulative borrow rate would overflow")]
+    CumulativeBorrowRateOverflow,
+    /// Liquidity mint decimals are not supported
+    #[error("Liquidity mint decimals are not supported")]
+    UnsupportedMintDecimals,
+    /// Obligations have to stay within their borrow limit after a split
+    #[error("Obligations have to stay within their borrow limit after a split")]
+    ObligationSplitUnhealthy,
+    /// Obligation deposited value is not below the dust sweep threshold
+    #[error("Obligation deposited value is not below the dust sweep threshold")]
+    ObligationNotDust,
+    /// Oracle price is outside of the reserve price bounds
+    #[error("Oracle price is outside of the reserve price bounds")]
+    PriceOutOfBounds,
+    /// Borrow would exceed the borrow cap set by the obligation owner
+    #[error("Borrow would exceed the borrow cap set by the obligation owner")]
+    ObligationBorrowCapExceeded,
+    /// Obligation owner cannot be changed
+    #[error("Obligation owner cannot be changed")]
+    ObligationOwnerChangeBlocked,
+    /// Flash loan exceeds the max flash loan amount of the reserve
+    #[error("Flash loan exceeds the max flash loan amount of the reserve")]
+    FlashLoanTooLarge,
+    /// Obligation would end up within the lending market's health buffer
+    #[error("Obligation would end up within the lending market's health buffer")]
+    HealthBufferBreached,
+    /// Token account is frozen by the freeze authority of its mint
+    #[error("Token account is frozen by the freeze authority of its mint")]
+    TokenAccountFrozen,
+    /// Lending market reached the max number of cross market sources
+    #[error("Lending market reached the max number of cross market sources")]
+    CrossMarketSourceLimit,
 }
 
 impl From<LendingError> for ProgramError {
@@ -0,0 +1,128 @@
+//! Context values of lending errors, surfaced through the program logs.
+//!
+//! Before returning a guard error such as `BorrowTooLarge`, the program logs the amounts and
+//! limits that made the guard trip with [log_error_context], as a single
+//! `Error context: error=<LendingError> key=value ...` line. [parse_error_contexts] extracts them
+//! from the logs of a failed transaction or simulation so they can be shown to users.
+
+use crate::error::LendingError;
+use num_traits::FromPrimitive;
+use solana_program::msg;
+use std::fmt::{self, Display};
+
+/// Prefix of the log lines written by [log_error_context]
+pub const ERROR_CONTEXT_PREFIX: &str = "Error context: ";
+
+const PROGRAM_LOG_PREFIX: &str = "Program log: ";
+
+/// Log the values that `error` is about to be returned for, as `key=value` pairs. Values are
+/// formatted with `Display` and must not contain whitespace.
+pub fn log_error_context(error: LendingError, values: &[(&str, &dyn Display)]) {
+    let mut line = format!("{}error={:?}", ERROR_CONTEXT_PREFIX, error);
+    for (key, value) in values {
+        line.push_str(&format!(" {}={}", key, value));
+    }
+    msg!(&line);
+}
+
+/// A lending error together with the context values the program logged for it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ErrorContext {
+    /// Error the values were logged for
+    pub error: LendingError,
+    /// Logged `key=value` pairs, in order
+    pub values: Vec<(String, String)>,
+}
+
+impl ErrorContext {
+    /// Parse a log line written by [log_error_context], with or without the `Program log: `
+    /// prefix the runtime adds. None for any other line.
+    pub fn parse(line: &str) -> Option<Self> {
+        let line = line.strip_prefix(PROGRAM_LOG_PREFIX).unwrap_or(line);
+        let mut pairs = line.strip_prefix(ERROR_CONTEXT_PREFIX)?.split_whitespace();
+
+        let error_name = pairs.next()?.strip_prefix("error=")?;
+        let error = (0..=u8::MAX as u32)
+            .filter_map(LendingError::from_u32)
+            .find(|error| format!("{:?}", error) == error_name)?;
+
+        let values = pairs
+            .map(|pair| {
+                let (key, value) = pair.split_once('=')?;
+                Some((key.to_string(), value.to_string()))
+            })
+            .collect::<Option<_>>()?;
+
+        Some(Self { error, values })
+    }
+
+    /// Value logged under `key`
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.values
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+impl Display for ErrorContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.error)?;
+        for (i, (key, value)) in self.values.iter().enumerate() {
+            write!(f, "{}{}: {}", if i == 0 { " (" } else { ", " }, key, value)?;
+        }
+        if !self.values.is_empty() {
+            write!(f, ")")?;
+        }
+        Ok(())
+    }
+}
+
+/// Error contexts logged in `logs`, e.g. the `logs` of an `RpcSimulateTransactionResult`, in the
+/// order they were logged
+pub fn parse_error_contexts<S: AsRef<str>>(logs: &[S]) -> Vec<ErrorContext> {
+    logs.iter()
+        .filter_map(|line| ErrorContext::parse(line.as_ref()))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_logged_context() {
+        let logs = [
+            "Program So1endDq2YkqhipRh3WViPa8hdiSpxWy6z3Z6tMCpAo invoke [1]",
+            "Program log: Instruction: Borrow Obligation Liquidity",
+            "Program log: Cannot borrow above the borrow limit",
+            "Program log: Error context: error=InvalidAmount amount=500 borrowed=9800 borrow_limit=10000",
+            "Program log: Error context: error=NotAnError amount=1",
+            "Program log: Error context: error=InvalidAmount malformed",
+            "Program So1endDq2YkqhipRh3WViPa8hdiSpxWy6z3Z6tMCpAo failed: custom program error: 0xa",
+        ];
+
+        let contexts = parse_error_contexts(&logs);
+        assert_eq!(
+            contexts,
+            vec![ErrorContext {
+                error: LendingError::InvalidAmount,
+                values: vec![
+                    ("amount".to_string(), "500".to_string()),
+                    ("borrowed".to_string(), "9800".to_string()),
+                    ("borrow_limit".to_string(), "10000".to_string()),
+                ],
+            }]
+        );
+        assert_eq!(contexts[0].get("borrow_limit"), Some("10000"));
+        assert_eq!(contexts[0].get("utilization"), None);
+        assert_eq!(
+            contexts[0].to_string(),
+            "Input amount is invalid (amount: 500, borrowed: 9800, borrow_limit: 10000)"
+        );
+
+        let context = ErrorContext::parse("Error context: error=OutflowRateLimitExceeded").unwrap();
+        assert_eq!(context.error, LendingError::OutflowRateLimitExceeded);
+        assert!(context.values.is_empty());
+    }
+}
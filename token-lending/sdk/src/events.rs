@@ -0,0 +1,480 @@
+//! Events emitted by the lending program.
+//!
+//! Events are logged with `sol_log_data`, so they show up in transaction logs as base64 encoded
+//! `Program data: ` lines. Decode the base64 and pass the bytes to [LendingEvent::unpack], or
+//! pass the whole logs to [LendingEvent::from_logs].
+
+use crate::error::LendingError;
+use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
+use solana_program::{msg, program_error::ProgramError, pubkey::Pubkey, pubkey::PUBKEY_BYTES};
+
+/// Events emitted by the lending program
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LendingEvent {
+    /// An obligation was closed and its rent refunded
+    ObligationClosed {
+        /// Obligation that was closed
+        obligation: Pubkey,
+        /// Owner of the obligation
+        owner: Pubkey,
+        /// Account that received the rent refund
+        rent_destination: Pubkey,
+        /// Lamports refunded, including those of any closed borrow allowances account
+        lamports: u64,
+    },
+    /// A reserve's deposits or borrows reached one of [RESERVE_LIMIT_THRESHOLDS] of its limit
+    ReserveLimitThresholdCrossed {
+        /// Reserve whose limit is filling up
+        reserve: Pubkey,
+        /// Which limit the threshold is of
+        kind: ReserveLimitKind,
+        /// Highest threshold crossed, in percent of the limit
+        threshold: u8,
+        /// Deposited or borrowed amount after crossing the threshold
+        amount: u64,
+        /// Deposit or borrow limit of the reserve
+        limit: u64,
+    },
+    /// Borrowed liquidity was repaid, by the obligation owner or by anyone else on their behalf
+    ObligationLiquidityRepaid {
+        /// Obligation whose borrow was repaid
+        obligation: Pubkey,
+        /// Owner of the obligation
+        owner: Pubkey,
+        /// Transfer authority of the liquidity the borrow was repaid with
+        payer: Pubkey,
+        /// Reserve the liquidity was repaid to
+        reserve: Pubkey,
+        /// Liquidity repaid
+        amount: u64,
+    },
+    /// The dust collateral of an obligation was swept into the protocol fees of a reserve
+    DustCollateralSwept {
+        /// Obligation the collateral was deposited in
+        obligation: Pubkey,
+        /// Reserve the collateral was deposited in
+        reserve: Pubkey,
+        /// Lending market owner or risk authority who swept it
+        authority: Pubkey,
+        /// Collateral burned
+        collateral_amount: u64,
+        /// Liquidity value of the collateral added to the protocol fees, rounded down
+        liquidity_amount: u64,
+    },
+    /// An obligation was transferred to a new owner
+    ObligationOwnerChanged {
+        /// Obligation that changed owner
+        obligation: Pubkey,
+        /// Owner before the change
+        previous_owner: Pubkey,
+        /// Owner after the change
+        new_owner: Pubkey,
+    },
+}
+
+/// Limit of a reserve that is watched for [LendingEvent::ReserveLimitThresholdCrossed]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReserveLimitKind {
+    /// `ReserveConfig::deposit_limit`, compared against the total liquidity supply
+    Deposit,
+    /// `ReserveConfig::borrow_limit`, compared against the borrowed amount
+    Borrow,
+}
+
+/// Percentages of a reserve limit that emit an event when reached
+pub const RESERVE_LIMIT_THRESHOLDS: [u8; 3] = [80, 90, 100];
+
+const OBLIGATION_CLOSED_LEN: usize = 1 + PUBKEY_BYTES * 3 + 8; // 105
+const RESERVE_LIMIT_THRESHOLD_CROSSED_LEN: usize = 1 + PUBKEY_BYTES + 1 + 1 + 8 + 8; // 51
+const OBLIGATION_LIQUIDITY_REPAID_LEN: usize = 1 + PUBKEY_BYTES * 4 + 8; // 137
+const DUST_COLLATERAL_SWEPT_LEN: usize = 1 + PUBKEY_BYTES * 3 + 8 + 8; // 113
+const OBLIGATION_OWNER_CHANGED_LEN: usize = 1 + PUBKEY_BYTES * 3; // 97
+
+const PROGRAM_DATA_PREFIX: &str = "Program data: ";
+
+impl LendingEvent {
+    /// Event for an amount going from `before` to `after` against `limit`, if that reached a
+    /// threshold it was below of. Only the highest threshold is reported when several are crossed
+    /// at once, and none when the amount goes down.
+    pub fn reserve_limit_threshold_crossed(
+        reserve: Pubkey,
+        kind: ReserveLimitKind,
+        before: u64,
+        after: u64,
+        limit: u64,
+    ) -> Option<Self> {
+        if limit == 0 {
+            return None;
+        }
+        RESERVE_LIMIT_THRESHOLDS
+            .iter()
+            .rev()
+            .find(|threshold| {
+                // amount >= limit * threshold / 100, without rounding or overflowing
+                let reached =
+                    |amount: u64| amount as u128 * 100 >= limit as u128 * **threshold as u128;
+                !reached(before) && reached(after)
+            })
+            .map(|threshold| Self::ReserveLimitThresholdCrossed {
+                reserve,
+                kind,
+                threshold: *threshold,
+                amount: after,
+                limit,
+            })
+    }
+
+    /// Whether the event is a repayment made by someone other than the obligation owner
+    pub fn is_third_party_repayment(&self) -> bool {
+        matches!(self, Self::ObligationLiquidityRepaid { owner, payer, .. } if owner != payer)
+    }
+
+    /// Events found in transaction logs, in the order they were emitted. `Program data: ` lines
+    /// that aren't lending events, e.g. those of other programs, are skipped.
+    pub fn from_logs<S: AsRef<str>>(logs: &[S]) -> Vec<Self> {
+        logs.iter()
+            .filter_map(|line| line.as_ref().strip_prefix(PROGRAM_DATA_PREFIX))
+            .filter_map(|data| {
+                // sol_log_data logs each field separately, events are a single one
+                let bytes = base64::decode(data.split_whitespace().next()?).ok()?;
+                Self::unpack(&bytes).ok()
+            })
+            .collect()
+    }
+
+    /// Log the event so it can be picked up from the transaction logs
+    pub fn emit(&self) {
+        solana_program::log::sol_log_data(&[&self.pack()]);
+    }
+
+    /// Pack the event into a byte buffer, prefixed by its tag
+    pub fn pack(&self) -> Vec<u8> {
+        match self {
+            Self::ObligationClosed {
+                obligation,
+                owner,
+                rent_destination,
+                lamports,
+            } => {
+                let mut buf = vec![0; OBLIGATION_CLOSED_LEN];
+                let output = array_mut_ref![buf, 0, OBLIGATION_CLOSED_LEN];
+                #[allow(clippy::ptr_offset_with_cast)]
+                let (tag, obligation_dst, owner_dst, rent_destination_dst, lamports_dst) =
+                    mut_array_refs![output, 1, PUBKEY_BYTES, PUBKEY_BYTES, PUBKEY_BYTES, 8];
+                tag[0] = 0;
+                obligation_dst.copy_from_slice(obligation.as_ref());
+                owner_dst.copy_from_slice(owner.as_ref());
+                rent_destination_dst.copy_from_slice(rent_destination.as_ref());
+                *lamports_dst = lamports.to_le_bytes();
+                buf
+            }
+            Self::ReserveLimitThresholdCrossed {
+                reserve,
+                kind,
+                threshold,
+                amount,
+                limit,
+            } => {
+                let mut buf = vec![0; RESERVE_LIMIT_THRESHOLD_CROSSED_LEN];
+                let output = array_mut_ref![buf, 0, RESERVE_LIMIT_THRESHOLD_CROSSED_LEN];
+                #[allow(clippy::ptr_offset_with_cast)]
+                let (tag, reserve_dst, kind_dst, threshold_dst, amount_dst, limit_dst) =
+                    mut_array_refs![output, 1, PUBKEY_BYTES, 1, 1, 8, 8];
+                tag[0] = 1;
+                reserve_dst.copy_from_slice(reserve.as_ref());
+                kind_dst[0] = *kind as u8;
+                threshold_dst[0] = *threshold;
+                *amount_dst = amount.to_le_bytes();
+                *limit_dst = limit.to_le_bytes();
+                buf
+            }
+            Self::ObligationLiquidityRepaid {
+                obligation,
+                owner,
+                payer,
+                reserve,
+                amount,
+            } => {
+                let mut buf = vec![0; OBLIGATION_LIQUIDITY_REPAID_LEN];
+                let output = array_mut_ref![buf, 0, OBLIGATION_LIQUIDITY_REPAID_LEN];
+                #[allow(clippy::ptr_offset_with_cast)]
+                let (tag, obligation_dst, owner_dst, payer_dst, reserve_dst, amount_dst) = mut_array_refs![
+                    output,
+                    1,
+                    PUBKEY_BYTES,
+                    PUBKEY_BYTES,
+                    PUBKEY_BYTES,
+                    PUBKEY_BYTES,
+                    8
+                ];
+                tag[0] = 2;
+                obligation_dst.copy_from_slice(obligation.as_ref());
+                owner_dst.copy_from_slice(owner.as_ref());
+                payer_dst.copy_from_slice(payer.as_ref());
+                reserve_dst.copy_from_slice(reserve.as_ref());
+                *amount_dst = amount.to_le_bytes();
+                buf
+            }
+            Self::DustCollateralSwept {
+                obligation,
+                reserve,
+                authority,
+                collateral_amount,
+                liquidity_amount,
+            } => {
+                let mut buf = vec![0; DUST_COLLATERAL_SWEPT_LEN];
+                let output = array_mut_ref![buf, 0, DUST_COLLATERAL_SWEPT_LEN];
+                #[allow(clippy::ptr_offset_with_cast)]
+                let (
+                    tag,
+                    obligation_dst,
+                    reserve_dst,
+                    authority_dst,
+                    collateral_amount_dst,
+                    liquidity_amount_dst,
+                ) = mut_array_refs![output, 1, PUBKEY_BYTES, PUBKEY_BYTES, PUBKEY_BYTES, 8, 8];
+                tag[0] = 3;
+                obligation_dst.copy_from_slice(obligation.as_ref());
+                reserve_dst.copy_from_slice(reserve.as_ref());
+                authority_dst.copy_from_slice(authority.as_ref());
+                *collateral_amount_dst = collateral_amount.to_le_bytes();
+                *liquidity_amount_dst = liquidity_amount.to_le_bytes();
+                buf
+            }
+            Self::ObligationOwnerChanged {
+                obligation,
+                previous_owner,
+                new_owner,
+            } => {
+                let mut buf = vec![0; OBLIGATION_OWNER_CHANGED_LEN];
+                let output = array_mut_ref![buf, 0, OBLIGATION_OWNER_CHANGED_LEN];
+                #[allow(clippy::ptr_offset_with_cast)]
+                let (tag, obligation_dst, previous_owner_dst, new_owner_dst) =
+                    mut_array_refs![output, 1, PUBKEY_BYTES, PUBKEY_BYTES, PUBKEY_BYTES];
+                tag[0] = 4;
+                obligation_dst.copy_from_slice(obligation.as_ref());
+                previous_owner_dst.copy_from_slice(previous_owner.as_ref());
+                new_owner_dst.copy_from_slice(new_owner.as_ref());
+                buf
+            }
+        }
+    }
+
+    /// Unpack an event from the bytes logged by the program
+    pub fn unpack(input: &[u8]) -> Result<Self, ProgramError> {
+        match input.first() {
+            Some(0) if input.len() == OBLIGATION_CLOSED_LEN => {
+                let input = array_ref![input, 0, OBLIGATION_CLOSED_LEN];
+                #[allow(clippy::ptr_offset_with_cast)]
+                let (_tag, obligation, owner, rent_destination, lamports) =
+                    array_refs![input, 1, PUBKEY_BYTES, PUBKEY_BYTES, PUBKEY_BYTES, 8];
+                Ok(Self::ObligationClosed {
+                    obligation: Pubkey::new_from_array(*obligation),
+                    owner: Pubkey::new_from_array(*owner),
+                    rent_destination: Pubkey::new_from_array(*rent_destination),
+                    lamports: u64::from_le_bytes(*lamports),
+                })
+            }
+            Some(1) if input.len() == RESERVE_LIMIT_THRESHOLD_CROSSED_LEN => {
+                let input = array_ref![input, 0, RESERVE_LIMIT_THRESHOLD_CROSSED_LEN];
+                #[allow(clippy::ptr_offset_with_cast)]
+                let (_tag, reserve, kind, threshold, amount, limit) =
+                    array_refs![input, 1, PUBKEY_BYTES, 1, 1, 8, 8];
+                let kind = match kind[0] {
+                    0 => ReserveLimitKind::Deposit,
+                    1 => ReserveLimitKind::Borrow,
+                    _ => {
+                        msg!("Reserve limit kind cannot be unpacked");
+                        return Err(LendingError::InstructionUnpackError.into());
+                    }
+                };
+                Ok(Self::ReserveLimitThresholdCrossed {
+                    reserve: Pubkey::new_from_array(*reserve),
+                    kind,
+                    threshold: threshold[0],
+                    amount: u64::from_le_bytes(*amount),
+                    limit: u64::from_le_bytes(*limit),
+                })
+            }
+            Some(2) if input.len() == OBLIGATION_LIQUIDITY_REPAID_LEN => {
+                let input = array_ref![input, 0, OBLIGATION_LIQUIDITY_REPAID_LEN];
+                #[allow(clippy::ptr_offset_with_cast)]
+                let (_tag, obligation, owner, payer, reserve, amount) = array_refs![
+                    input,
+                    1,
+                    PUBKEY_BYTES,
+                    PUBKEY_BYTES,
+                    PUBKEY_BYTES,
+                    PUBKEY_BYTES,
+                    8
+                ];
+                Ok(Self::ObligationLiquidityRepaid {
+                    obligation: Pubkey::new_from_array(*obligation),
+                    owner: Pubkey::new_from_array(*owner),
+                    payer: Pubkey::new_from_array(*payer),
+                    reserve: Pubkey::new_from_array(*reserve),
+                    amount: u64::from_le_bytes(*amount),
+                })
+            }
+            Some(3) if input.len() == DUST_COLLATERAL_SWEPT_LEN => {
+                let input = array_ref![input, 0, DUST_COLLATERAL_SWEPT_LEN];
+                #[allow(clippy::ptr_offset_with_cast)]
+                let (_tag, obligation, reserve, authority, collateral_amount, liquidity_amount) =
+                    array_refs![input, 1, PUBKEY_BYTES, PUBKEY_BYTES, PUBKEY_BYTES, 8, 8];
+                Ok(Self::DustCollateralSwept {
+                    obligation: Pubkey::new_from_array(*obligation),
+                    reserve: Pubkey::new_from_array(*reserve),
+                    authority: Pubkey::new_from_array(*authority),
+                    collateral_amount: u64::from_le_bytes(*collateral_amount),
+                    liquidity_amount: u64::from_le_bytes(*liquidity_amount),
+                })
+            }
+            Some(4) if input.len() == OBLIGATION_OWNER_CHANGED_LEN => {
+                let input = array_ref![input, 0, OBLIGATION_OWNER_CHANGED_LEN];
+                #[allow(clippy::ptr_offset_with_cast)]
+                let (_tag, obligation, previous_owner, new_owner) =
+                    array_refs![input, 1, PUBKEY_BYTES, PUBKEY_BYTES, PUBKEY_BYTES];
+                Ok(Self::ObligationOwnerChanged {
+                    obligation: Pubkey::new_from_array(*obligation),
+                    previous_owner: Pubkey::new_from_array(*previous_owner),
+                    new_owner: Pubkey::new_from_array(*new_owner),
+                })
+            }
+            _ => {
+                msg!("Event cannot be unpacked");
+                Err(LendingError::InstructionUnpackError.into())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn pack_and_unpack_events() {
+        let event = LendingEvent::ObligationClosed {
+            obligation: Pubkey::new_unique(),
+            owner: Pubkey::new_unique(),
+            rent_destination: Pubkey::new_unique(),
+            lamports: 1_000_000,
+        };
+
+        let packed = event.pack();
+        assert_eq!(LendingEvent::unpack(&packed), Ok(event));
+
+        assert_eq!(
+            LendingEvent::unpack(&packed[..packed.len() - 1]),
+            Err(LendingError::InstructionUnpackError.into())
+        );
+        assert_eq!(
+            LendingEvent::unpack(&[]),
+            Err(LendingError::InstructionUnpackError.into())
+        );
+
+        let event = LendingEvent::ReserveLimitThresholdCrossed {
+            reserve: Pubkey::new_unique(),
+            kind: ReserveLimitKind::Borrow,
+            threshold: 90,
+            amount: 95,
+            limit: 100,
+        };
+        let mut packed = event.pack();
+        assert_eq!(LendingEvent::unpack(&packed), Ok(event));
+
+        packed[1 + PUBKEY_BYTES] = 2;
+        assert_eq!(
+            LendingEvent::unpack(&packed),
+            Err(LendingError::InstructionUnpackError.into())
+        );
+
+        let event = LendingEvent::DustCollateralSwept {
+            obligation: Pubkey::new_unique(),
+            reserve: Pubkey::new_unique(),
+            authority: Pubkey::new_unique(),
+            collateral_amount: 7,
+            liquidity_amount: 9,
+        };
+        let packed = event.pack();
+        assert_eq!(packed.len(), DUST_COLLATERAL_SWEPT_LEN);
+        assert_eq!(LendingEvent::unpack(&packed), Ok(event));
+
+        let event = LendingEvent::ObligationOwnerChanged {
+            obligation: Pubkey::new_unique(),
+            previous_owner: Pubkey::new_unique(),
+            new_owner: Pubkey::new_unique(),
+        };
+        let packed = event.pack();
+        assert_eq!(packed.len(), OBLIGATION_OWNER_CHANGED_LEN);
+        assert_eq!(LendingEvent::unpack(&packed), Ok(event));
+    }
+
+    #[test]
+    fn third_party_repayments_from_logs() {
+        let owner = Pubkey::new_unique();
+        let repaid = |payer| LendingEvent::ObligationLiquidityRepaid {
+            obligation: Pubkey::new_unique(),
+            owner,
+            payer,
+            reserve: Pubkey::new_unique(),
+            amount: 100,
+        };
+        let by_owner = repaid(owner);
+        let on_behalf = repaid(Pubkey::new_unique());
+        let program_data = |event: &LendingEvent| {
+            format!("{}{}", PROGRAM_DATA_PREFIX, base64::encode(event.pack()))
+        };
+
+        let logs = vec![
+            "Program So1endDq2YkqhipRh3WViPa8hdiSpxWy6z3Z6tMCpAo invoke [1]".to_string(),
+            "Program log: Instruction: Repay Obligation Liquidity".to_string(),
+            program_data(&by_owner),
+            "Program data: AQID".to_string(),
+            program_data(&on_behalf),
+        ];
+        let events = LendingEvent::from_logs(&logs);
+        assert_eq!(events, vec![by_owner.clone(), on_behalf.clone()]);
+        assert!(!by_owner.is_third_party_repayment());
+        assert!(on_behalf.is_third_party_repayment());
+        assert_eq!(
+            events
+                .iter()
+                .filter(|event| event.is_third_party_repayment())
+                .collect::<Vec<_>>(),
+            vec![&on_behalf]
+        );
+    }
+
+    #[test]
+    fn reserve_limit_threshold_crossed() {
+        let reserve = Pubkey::new_unique();
+        let crossed = |before, after, limit| {
+            LendingEvent::reserve_limit_threshold_crossed(
+                reserve,
+                ReserveLimitKind::Deposit,
+                before,
+                after,
+                limit,
+            )
+            .map(|event| match event {
+                LendingEvent::ReserveLimitThresholdCrossed { threshold, .. } => threshold,
+                _ => unreachable!(),
+            })
+        };
+
+        assert_eq!(crossed(0, 79, 100), None);
+        assert_eq!(crossed(0, 80, 100), Some(80));
+        assert_eq!(crossed(80, 89, 100), None);
+        assert_eq!(crossed(85, 95, 100), Some(90));
+        assert_eq!(crossed(0, 100, 100), Some(100));
+        assert_eq!(crossed(100, 100, 100), None);
+        // going down doesn't re-emit
+        assert_eq!(crossed(95, 70, 100), None);
+        // 80% of 7 is 5.6, which 5 hasn't reached yet
+        assert_eq!(crossed(0, 5, 7), None);
+        assert_eq!(crossed(5, 6, 7), Some(80));
+        assert_eq!(crossed(0, u64::MAX, u64::MAX), Some(100));
+        assert_eq!(crossed(0, 10, 0), None);
+    }
+}
@@ -0,0 +1,360 @@
+//! Flat export of reserves and obligations for archives and analytics.
+//!
+//! Packed account data is laid out for the program, with nested positions and upgrade padding.
+//! The records here flatten it into one row per [ReserveRecord], [ObligationRecord] and
+//! [ObligationPositionRecord], with pubkeys in base58 and decimals as decimal strings, so a
+//! collection of them exports to csv with [to_csv] or to json through serde, and loads straight
+//! into pandas. Csv files of many account states compress well with any general purpose codec.
+//!
+//! Every record carries the [EXPORT_SCHEMA_VERSION] it was written with, so archives written
+//! over time can be read back even after columns change.
+
+use crate::{
+    attribution::display,
+    math::Decimal,
+    state::{Obligation, Reserve},
+};
+use serde::Serialize;
+use solana_program::{clock::Slot, pubkey::Pubkey};
+
+/// Version of the exported columns. Bumped whenever a record's columns change.
+pub const EXPORT_SCHEMA_VERSION: u16 = 1;
+
+/// A record exported as one csv row
+pub trait ExportRecord: Serialize {
+    /// Header matching `to_csv_row`
+    const CSV_HEADER: &'static str;
+
+    /// Format the record as a csv row
+    fn to_csv_row(&self) -> String;
+}
+
+/// Format records as csv, with a header line
+pub fn to_csv<T: ExportRecord>(records: &[T]) -> String {
+    let mut csv = String::from(T::CSV_HEADER);
+    for record in records {
+        csv.push('\n');
+        csv.push_str(&record.to_csv_row());
+    }
+    csv
+}
+
+/// A reserve at the slot it was last updated
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ReserveRecord {
+    /// Schema version the record was written with
+    pub schema_version: u16,
+    /// Reserve account
+    #[serde(serialize_with = "display")]
+    pub reserve: Pubkey,
+    /// Lending market of the reserve
+    #[serde(serialize_with = "display")]
+    pub lending_market: Pubkey,
+    /// Slot the reserve was last updated at
+    pub slot: Slot,
+    /// Liquidity mint
+    #[serde(serialize_with = "display")]
+    pub liquidity_mint: Pubkey,
+    /// Decimals of the liquidity mint
+    pub mint_decimals: u8,
+    /// Liquidity available to borrow, in native units
+    pub available_amount: u64,
+    /// Liquidity borrowed, including interest, in native units
+    pub borrowed_amount: Decimal,
+    /// Cumulative borrow rate
+    pub cumulative_borrow_rate: Decimal,
+    /// Protocol fees not redeemed yet, in native units
+    pub accumulated_protocol_fees: Decimal,
+    /// Market price of one liquidity token, in the market's quote currency
+    pub market_price: Decimal,
+    /// Collateral mint
+    #[serde(serialize_with = "display")]
+    pub collateral_mint: Pubkey,
+    /// Supply of the collateral mint
+    pub collateral_supply: u64,
+    /// Loan to value ratio, in percent
+    pub loan_to_value_ratio: u8,
+    /// Liquidation threshold, in percent
+    pub liquidation_threshold: u8,
+}
+
+impl ReserveRecord {
+    /// Flatten a reserve
+    pub fn new(pubkey: Pubkey, reserve: &Reserve) -> Self {
+        Self {
+            schema_version: EXPORT_SCHEMA_VERSION,
+            reserve: pubkey,
+            lending_market: reserve.lending_market,
+            slot: reserve.last_update.slot,
+            liquidity_mint: reserve.liquidity.mint_pubkey,
+            mint_decimals: reserve.liquidity.mint_decimals,
+            available_amount: reserve.liquidity.available_amount,
+            borrowed_amount: reserve.liquidity.borrowed_amount_wads,
+            cumulative_borrow_rate: reserve.liquidity.cumulative_borrow_rate_wads,
+            accumulated_protocol_fees: reserve.liquidity.accumulated_protocol_fees_wads,
+            market_price: reserve.liquidity.market_price,
+            collateral_mint: reserve.collateral.mint_pubkey,
+            collateral_supply: reserve.collateral.mint_total_supply,
+            loan_to_value_ratio: reserve.config.loan_to_value_ratio,
+            liquidation_threshold: reserve.config.liquidation_threshold,
+        }
+    }
+}
+
+impl ExportRecord for ReserveRecord {
+    const CSV_HEADER: &'static str = "schema_version,reserve,lending_market,slot,\
+        liquidity_mint,mint_decimals,available_amount,borrowed_amount,cumulative_borrow_rate,\
+        accumulated_protocol_fees,market_price,collateral_mint,collateral_supply,\
+        loan_to_value_ratio,liquidation_threshold";
+
+    fn to_csv_row(&self) -> String {
+        format!(
+            "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+            self.schema_version,
+            self.reserve,
+            self.lending_market,
+            self.slot,
+            self.liquidity_mint,
+            self.mint_decimals,
+            self.available_amount,
+            self.borrowed_amount,
+            self.cumulative_borrow_rate,
+            self.accumulated_protocol_fees,
+            self.market_price,
+            self.collateral_mint,
+            self.collateral_supply,
+            self.loan_to_value_ratio,
+            self.liquidation_threshold
+        )
+    }
+}
+
+/// An obligation at the slot it was last refreshed, without its deposits and borrows
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ObligationRecord {
+    /// Schema version the record was written with
+    pub schema_version: u16,
+    /// Obligation account
+    #[serde(serialize_with = "display")]
+    pub obligation: Pubkey,
+    /// Lending market of the obligation
+    #[serde(serialize_with = "display")]
+    pub lending_market: Pubkey,
+    /// Wallet owning the obligation
+    #[serde(serialize_with = "display")]
+    pub owner: Pubkey,
+    /// Slot the obligation was last refreshed at
+    pub slot: Slot,
+    /// Number of deposit reserves
+    pub deposits: usize,
+    /// Number of borrow reserves
+    pub borrows: usize,
+    /// Market value of the deposits
+    pub deposited_value: Decimal,
+    /// Risk adjusted market value of the borrows
+    pub borrowed_value: Decimal,
+    /// Max value that can be borrowed
+    pub allowed_borrow_value: Decimal,
+    /// Borrowed value the obligation can be liquidated at
+    pub unhealthy_borrow_value: Decimal,
+}
+
+impl ObligationRecord {
+    /// Flatten an obligation, see [ObligationPositionRecord::from_obligation] for its positions
+    pub fn new(pubkey: Pubkey, obligation: &Obligation) -> Self {
+        Self {
+            schema_version: EXPORT_SCHEMA_VERSION,
+            obligation: pubkey,
+            lending_market: obligation.lending_market,
+            owner: obligation.owner,
+            slot: obligation.last_update.slot,
+            deposits: obligation.deposits.len(),
+            borrows: obligation.borrows.len(),
+            deposited_value: obligation.deposited_value,
+            borrowed_value: obligation.borrowed_value,
+            allowed_borrow_value: obligation.allowed_borrow_value,
+            unhealthy_borrow_value: obligation.unhealthy_borrow_value,
+        }
+    }
+}
+
+impl ExportRecord for ObligationRecord {
+    const CSV_HEADER: &'static str = "schema_version,obligation,lending_market,owner,slot,\
+        deposits,borrows,deposited_value,borrowed_value,allowed_borrow_value,\
+        unhealthy_borrow_value";
+
+    fn to_csv_row(&self) -> String {
+        format!(
+            "{},{},{},{},{},{},{},{},{},{},{}",
+            self.schema_version,
+            self.obligation,
+            self.lending_market,
+            self.owner,
+            self.slot,
+            self.deposits,
+            self.borrows,
+            self.deposited_value,
+            self.borrowed_value,
+            self.allowed_borrow_value,
+            self.unhealthy_borrow_value
+        )
+    }
+}
+
+/// Kind of an obligation position
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PositionKind {
+    /// Collateral deposited, in collateral tokens
+    Deposit,
+    /// Liquidity borrowed, in liquidity tokens including interest
+    Borrow,
+}
+
+/// A deposit or borrow of an obligation, joined to its [ObligationRecord] on `obligation`
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ObligationPositionRecord {
+    /// Schema version the record was written with
+    pub schema_version: u16,
+    /// Obligation account
+    #[serde(serialize_with = "display")]
+    pub obligation: Pubkey,
+    /// Reserve deposited into or borrowed from
+    #[serde(serialize_with = "display")]
+    pub reserve: Pubkey,
+    /// Deposit or borrow
+    pub kind: PositionKind,
+    /// Amount deposited or borrowed, in native units
+    pub amount: Decimal,
+    /// Market value of the amount at the last refresh
+    pub market_value: Decimal,
+}
+
+impl ObligationPositionRecord {
+    /// Flatten the deposits and borrows of an obligation, deposits first
+    pub fn from_obligation(pubkey: Pubkey, obligation: &Obligation) -> Vec<Self> {
+        let deposits = obligation.deposits.iter().map(|collateral| Self {
+            schema_version: EXPORT_SCHEMA_VERSION,
+            obligation: pubkey,
+            reserve: collateral.deposit_reserve,
+            kind: PositionKind::Deposit,
+            amount: Decimal::from(collateral.deposited_amount),
+            market_value: collateral.market_value,
+        });
+        let borrows = obligation.borrows.iter().map(|liquidity| Self {
+            schema_version: EXPORT_SCHEMA_VERSION,
+            obligation: pubkey,
+            reserve: liquidity.borrow_reserve,
+            kind: PositionKind::Borrow,
+            amount: liquidity.borrowed_amount_wads,
+            market_value: liquidity.market_value,
+        });
+        deposits.chain(borrows).collect()
+    }
+}
+
+impl ExportRecord for ObligationPositionRecord {
+    const CSV_HEADER: &'static str = "schema_version,obligation,reserve,kind,amount,market_value";
+
+    fn to_csv_row(&self) -> String {
+        let kind = match self.kind {
+            PositionKind::Deposit => "deposit",
+            PositionKind::Borrow => "borrow",
+        };
+        format!(
+            "{},{},{},{},{},{}",
+            self.schema_version,
+            self.obligation,
+            self.reserve,
+            kind,
+            self.amount,
+            self.market_value
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        state::{LastUpdate, ObligationCollateral, ObligationLiquidity},
+        test_utils::reserve,
+    };
+
+    #[test]
+    fn reserve_csv() {
+        let pubkey = Pubkey::new_unique();
+        let reserve = Reserve {
+            last_update: LastUpdate::new(1_000),
+            ..reserve(1_000_000, 2)
+        };
+        let records = vec![ReserveRecord::new(pubkey, &reserve)];
+
+        let csv = to_csv(&records);
+        let lines = csv.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        let header = lines[0].split(',').collect::<Vec<_>>();
+        let row = lines[1].split(',').collect::<Vec<_>>();
+        assert_eq!(header.len(), row.len());
+        assert_eq!(row[0], EXPORT_SCHEMA_VERSION.to_string());
+        assert_eq!(row[1], pubkey.to_string());
+        assert_eq!(row[3], "1000");
+        assert_eq!(row[6], "1000000");
+        assert_eq!(row[10], "2.000000000000000000");
+    }
+
+    #[test]
+    fn obligation_positions() {
+        let pubkey = Pubkey::new_unique();
+        let deposit_reserve = Pubkey::new_unique();
+        let borrow_reserve = Pubkey::new_unique();
+        let obligation = Obligation {
+            owner: Pubkey::new_unique(),
+            deposits: vec![ObligationCollateral {
+                deposited_amount: 100,
+                market_value: Decimal::from(100u64),
+                ..ObligationCollateral::new(deposit_reserve)
+            }],
+            borrows: vec![ObligationLiquidity {
+                borrowed_amount_wads: Decimal::from(40u64),
+                market_value: Decimal::from(40u64),
+                ..ObligationLiquidity::new(borrow_reserve, Decimal::one())
+            }],
+            deposited_value: Decimal::from(100u64),
+            borrowed_value: Decimal::from(40u64),
+            ..Obligation::default()
+        };
+
+        let record = ObligationRecord::new(pubkey, &obligation);
+        assert_eq!((record.deposits, record.borrows), (1, 1));
+        assert_eq!(
+            record.to_csv_row().split(',').count(),
+            ObligationRecord::CSV_HEADER.split(',').count()
+        );
+
+        let positions = ObligationPositionRecord::from_obligation(pubkey, &obligation);
+        assert_eq!(
+            positions
+                .iter()
+                .map(|p| (p.reserve, p.kind, p.amount))
+                .collect::<Vec<_>>(),
+            vec![
+                (
+                    deposit_reserve,
+                    PositionKind::Deposit,
+                    Decimal::from(100u64)
+                ),
+                (borrow_reserve, PositionKind::Borrow, Decimal::from(40u64)),
+            ]
+        );
+        assert_eq!(
+            to_csv(&positions).lines().nth(2).unwrap().split(',').nth(3),
+            Some("borrow")
+        );
+
+        let json = serde_json::to_value(&positions[1]).unwrap();
+        assert_eq!(json["kind"], "borrow");
+        assert_eq!(json["obligation"], pubkey.to_string());
+        assert_eq!(json["schema_version"], EXPORT_SCHEMA_VERSION);
+    }
+}
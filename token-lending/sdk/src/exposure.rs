@@ -0,0 +1,332 @@
+//! Net long and short exposure of wallets, per mint.
+//!
+//! [get_wallet_exposure] nets what a wallet deposited and borrowed of every mint across all the
+//! lending markets in [SolendAccounts], for market makers who deposit and borrow the same asset in
+//! different markets. Deposits are long exposure to the liquidity they redeem for, borrows short
+//! exposure to the borrowed liquidity. Net amounts are signed and WAD scaled like the `_wads`
+//! fields of the lending program state, positive when the wallet is long.
+//!
+//! Only obligations in `accounts` are counted, and `get_solend_accounts_as_map` leaves out the
+//! ones without borrows.
+
+use crate::{
+    error::LendingError,
+    math::{Decimal, TryAdd},
+    offchain_utils::SolendAccounts,
+    portfolio::{get_user_portfolio, UserPortfolio},
+};
+use solana_program::{program_error::ProgramError, pubkey::Pubkey};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    convert::TryFrom,
+    error::Error,
+};
+
+/// Which way a position exposes its owner to the price of a mint
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExposureSide {
+    /// A deposit, gains when the price goes up
+    Long,
+    /// A borrow, gains when the price goes down
+    Short,
+}
+
+/// A deposit or borrow making up part of an exposure
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExposurePosition {
+    /// Obligation holding the position
+    pub obligation: Pubkey,
+    /// Lending market of the obligation
+    pub lending_market: Pubkey,
+    /// Reserve deposited into or borrowed from
+    pub reserve: Pubkey,
+    /// Deposit or borrow
+    pub side: ExposureSide,
+    /// Liquidity deposited or borrowed, in native units
+    pub amount: Decimal,
+    /// USD value of the position, without borrow weight
+    pub market_value: Decimal,
+}
+
+/// Net exposure of a wallet to one mint
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MintExposure {
+    /// Liquidity mint
+    pub mint: Pubkey,
+    /// Liquidity deposited across all markets, in native units
+    pub long_amount: Decimal,
+    /// Liquidity borrowed across all markets, in native units
+    pub short_amount: Decimal,
+    /// Long minus short amount, WAD scaled native units
+    pub net_amount_wads: i128,
+    /// USD value of the deposits
+    pub long_value: Decimal,
+    /// USD value of the borrows, without borrow weight
+    pub short_value: Decimal,
+    /// Long minus short value, WAD scaled USD
+    pub net_value_wads: i128,
+    /// Positions netted, in the portfolio's market and obligation order
+    pub positions: Vec<ExposurePosition>,
+}
+
+/// Net exposures of a wallet
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WalletExposure {
+    /// Wallet owning the obligations
+    pub owner: Pubkey,
+    /// Exposures, ordered by mint
+    pub exposures: Vec<MintExposure>,
+}
+
+impl WalletExposure {
+    /// Exposure to a mint, if the wallet has any position in it
+    pub fn exposure(&self, mint: &Pubkey) -> Option<&MintExposure> {
+        self.exposures
+            .iter()
+            .find(|exposure| exposure.mint == *mint)
+    }
+}
+
+/// Net exposure per mint of the obligations `owner` holds in `accounts`. Obligations are valued
+/// as in [get_user_portfolio], so reserve interest should be refreshed up to the current slot
+/// first. Fails if a reserve an obligation references is missing.
+pub fn get_wallet_exposure(
+    owner: &Pubkey,
+    accounts: &SolendAccounts,
+) -> Result<WalletExposure, Box<dyn Error>> {
+    wallet_exposure(&get_user_portfolio(owner, accounts)?)
+}
+
+/// [get_wallet_exposure] of every obligation owner in `accounts`, ordered by owner
+pub fn get_exposure_report(
+    accounts: &SolendAccounts,
+) -> Result<Vec<WalletExposure>, Box<dyn Error>> {
+    accounts
+        .obligations
+        .values()
+        .map(|obligation| obligation.owner)
+        .collect::<BTreeSet<_>>()
+        .iter()
+        .map(|owner| get_wallet_exposure(owner, accounts))
+        .collect()
+}
+
+fn wallet_exposure(portfolio: &UserPortfolio) -> Result<WalletExposure, Box<dyn Error>> {
+    let mut positions: BTreeMap<Pubkey, Vec<ExposurePosition>> = BTreeMap::new();
+    for market in &portfolio.markets {
+        for obligation in &market.obligations {
+            let position = |reserve, side, amount, market_value| ExposurePosition {
+                obligation: obligation.obligation,
+                lending_market: market.lending_market,
+                reserve,
+                side,
+                amount,
+                market_value,
+            };
+            for deposit in &obligation.deposits {
+                positions
+                    .entry(deposit.liquidity_mint)
+                    .or_default()
+                    .push(position(
+                        deposit.reserve,
+                        ExposureSide::Long,
+                        deposit.liquidity_amount,
+                        deposit.market_value,
+                    ));
+            }
+            for borrow in &obligation.borrows {
+                positions
+                    .entry(borrow.liquidity_mint)
+                    .or_default()
+                    .push(position(
+                        borrow.reserve,
+                        ExposureSide::Short,
+                        borrow.borrowed_amount,
+                        borrow.market_value,
+                    ));
+            }
+        }
+    }
+
+    let mut exposures = Vec::with_capacity(positions.len());
+    for (mint, positions) in positions {
+        let mut long_amount = Decimal::zero();
+        let mut short_amount = Decimal::zero();
+        let mut long_value = Decimal::zero();
+        let mut short_value = Decimal::zero();
+        for position in &positions {
+            match position.side {
+                ExposureSide::Long => {
+                    long_amount = long_amount.try_add(position.amount)?;
+                    long_value = long_value.try_add(position.market_value)?;
+                }
+                ExposureSide::Short => {
+                    short_amount = short_amount.try_add(position.amount)?;
+                    short_value = short_value.try_add(position.market_value)?;
+                }
+            }
+        }
+        exposures.push(MintExposure {
+            mint,
+            long_amount,
+            short_amount,
+            net_amount_wads: net_wads(long_amount, short_amount)?,
+            long_value,
+            short_value,
+            net_value_wads: net_wads(long_value, short_value)?,
+            positions,
+        });
+    }
+
+    Ok(WalletExposure {
+        owner: portfolio.owner,
+        exposures,
+    })
+}
+
+/// `long - short` as a signed WAD scaled value
+fn net_wads(long: Decimal, short: Decimal) -> Result<i128, ProgramError> {
+    let signed = |value: Decimal| -> Result<i128, ProgramError> {
+        i128::try_from(value.to_scaled_val()?).map_err(|_| LendingError::MathOverflow.into())
+    };
+    signed(long)?
+        .checked_sub(signed(short)?)
+        .ok_or_else(|| LendingError::MathOverflow.into())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        math::WAD,
+        state::{
+            Obligation, ObligationCollateral, ObligationLiquidity, Reserve, ReserveCollateral,
+            ReserveConfig, ReserveLiquidity,
+        },
+    };
+    use std::collections::HashMap;
+
+    fn reserve(lending_market: Pubkey, mint_pubkey: Pubkey, market_price: u64) -> Reserve {
+        Reserve {
+            lending_market,
+            liquidity: ReserveLiquidity {
+                mint_pubkey,
+                available_amount: 1_000,
+                market_price: Decimal::from(market_price),
+                smoothed_market_price: Decimal::from(market_price),
+                cumulative_borrow_rate_wads: Decimal::one(),
+                ..ReserveLiquidity::default()
+            },
+            collateral: ReserveCollateral {
+                mint_total_supply: 1_000,
+                ..ReserveCollateral::default()
+            },
+            config: ReserveConfig {
+                loan_to_value_ratio: 50,
+                liquidation_threshold: 80,
+                max_liquidation_threshold: 90,
+                ..ReserveConfig::default()
+            },
+            ..Reserve::default()
+        }
+    }
+
+    fn obligation(
+        owner: Pubkey,
+        lending_market: Pubkey,
+        deposits: &[(Pubkey, u64)],
+        borrows: &[(Pubkey, u64)],
+    ) -> Obligation {
+        Obligation {
+            owner,
+            lending_market,
+            deposits: deposits
+                .iter()
+                .map(|(reserve, amount)| ObligationCollateral {
+                    deposited_amount: *amount,
+                    ..ObligationCollateral::new(*reserve)
+                })
+                .collect(),
+            borrows: borrows
+                .iter()
+                .map(|(reserve, amount)| ObligationLiquidity {
+                    borrowed_amount_wads: Decimal::from(*amount),
+                    ..ObligationLiquidity::new(*reserve, Decimal::one())
+                })
+                .collect(),
+            ..Obligation::default()
+        }
+    }
+
+    #[test]
+    fn net_exposure_across_markets() {
+        let owner = Pubkey::new_unique();
+        let other_owner = Pubkey::new_unique();
+        let usdc = Pubkey::new_unique();
+        let sol = Pubkey::new_unique();
+        let (market_a, market_b) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let usdc_a = (Pubkey::new_unique(), reserve(market_a, usdc, 1));
+        let sol_a = (Pubkey::new_unique(), reserve(market_a, sol, 10));
+        let usdc_b = (Pubkey::new_unique(), reserve(market_b, usdc, 1));
+        let sol_b = (Pubkey::new_unique(), reserve(market_b, sol, 10));
+
+        // long 400 USDC and short 10 SOL in market a, short 150 USDC and long 30 SOL in market b
+        let obligation_a = (
+            Pubkey::new_unique(),
+            obligation(owner, market_a, &[(usdc_a.0, 400)], &[(sol_a.0, 10)]),
+        );
+        let obligation_b = (
+            Pubkey::new_unique(),
+            obligation(owner, market_b, &[(sol_b.0, 30)], &[(usdc_b.0, 150)]),
+        );
+        let other_obligation = (
+            Pubkey::new_unique(),
+            obligation(other_owner, market_a, &[(usdc_a.0, 100)], &[(sol_a.0, 1)]),
+        );
+
+        let accounts = SolendAccounts {
+            lending_markets: HashMap::new(),
+            reserves: HashMap::from([usdc_a.clone(), sol_a.clone(), usdc_b, sol_b]),
+            obligations: HashMap::from([
+                obligation_a.clone(),
+                obligation_b.clone(),
+                other_obligation,
+            ]),
+            obligation_borrow_caps: HashMap::new(),
+        };
+
+        let exposure = get_wallet_exposure(&owner, &accounts).unwrap();
+        assert_eq!(exposure.owner, owner);
+        assert_eq!(exposure.exposures.len(), 2);
+
+        let usdc_exposure = exposure.exposure(&usdc).unwrap();
+        assert_eq!(usdc_exposure.long_amount, Decimal::from(400u64));
+        assert_eq!(usdc_exposure.short_amount, Decimal::from(150u64));
+        assert_eq!(usdc_exposure.net_amount_wads, 250 * WAD as i128);
+        assert_eq!(usdc_exposure.net_value_wads, 250 * WAD as i128);
+        assert_eq!(usdc_exposure.positions.len(), 2);
+        let short = usdc_exposure
+            .positions
+            .iter()
+            .find(|position| position.side == ExposureSide::Short)
+            .unwrap();
+        assert_eq!(short.obligation, obligation_b.0);
+        assert_eq!(short.lending_market, market_b);
+
+        let sol_exposure = exposure.exposure(&sol).unwrap();
+        assert_eq!(sol_exposure.net_amount_wads, 20 * WAD as i128);
+        assert_eq!(sol_exposure.net_value_wads, 200 * WAD as i128);
+
+        // net short
+        let report = get_exposure_report(&accounts).unwrap();
+        assert_eq!(report.len(), 2);
+        let other_exposure = report
+            .iter()
+            .find(|exposure| exposure.owner == other_owner)
+            .unwrap();
+        let other_sol_exposure = other_exposure.exposure(&sol).unwrap();
+        assert_eq!(other_sol_exposure.net_amount_wads, -(WAD as i128));
+        assert_eq!(other_sol_exposure.net_value_wads, -10 * WAD as i128);
+        assert!(exposure.exposure(&Pubkey::new_unique()).is_none());
+    }
+}
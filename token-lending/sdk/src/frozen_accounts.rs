@@ -0,0 +1,148 @@
+//! Pre-flight check for frozen token accounts.
+//!
+//! The freeze authority of a mint can freeze any token account of it, e.g. during a compliance
+//! event. Deposits, repays, borrows and withdraws of such a mint then fail on the token transfer,
+//! with TokenAccountFrozen from the lending program. [find_frozen_token_accounts] finds the frozen
+//! accounts a transaction would move tokens through before it is sent, so clients can tell their
+//! users whether their own account or the reserve is frozen instead of showing a failed
+//! transaction.
+
+use solana_client::rpc_client::RpcClient;
+use solana_program::{instruction::Instruction, program_pack::Pack, pubkey::Pubkey};
+use solana_sdk::account::Account;
+use spl_token::state::Account as TokenAccount;
+use std::{collections::BTreeSet, error::Error};
+
+use crate::offchain_utils::MAX_MULTIPLE_ACCOUNTS;
+
+/// Frozen token account
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FrozenTokenAccount {
+    /// Token account address
+    pub pubkey: Pubkey,
+    /// Mint of the account
+    pub mint: Pubkey,
+    /// Owner of the account, the lending market authority for reserve supplies
+    pub owner: Pubkey,
+}
+
+/// Fetch the writable accounts of `instructions` and return the token accounts among them that
+/// are frozen
+#[cfg_attr(feature = "tracing", tracing::instrument(skip_all, err))]
+pub fn find_frozen_token_accounts(
+    client: &RpcClient,
+    instructions: &[Instruction],
+) -> Result<Vec<FrozenTokenAccount>, Box<dyn Error>> {
+    // token transfers only go through writable accounts
+    let pubkeys = instructions
+        .iter()
+        .flat_map(|instruction| &instruction.accounts)
+        .filter(|meta| meta.is_writable)
+        .map(|meta| meta.pubkey)
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect::<Vec<_>>();
+
+    let mut accounts = Vec::with_capacity(pubkeys.len());
+    for chunk in pubkeys.chunks(MAX_MULTIPLE_ACCOUNTS) {
+        let fetched = client
+            .get_multiple_accounts_with_commitment(chunk, client.commitment())?
+            .value;
+        accounts.extend(chunk.iter().copied().zip(fetched));
+    }
+    Ok(frozen_token_accounts(&accounts))
+}
+
+/// Frozen token accounts among fetched accounts, None if missing
+pub fn frozen_token_accounts(accounts: &[(Pubkey, Option<Account>)]) -> Vec<FrozenTokenAccount> {
+    accounts
+        .iter()
+        .filter_map(|(pubkey, account)| {
+            let account = account.as_ref()?;
+            if account.owner != spl_token::id() {
+                return None;
+            }
+            let token_account = TokenAccount::unpack(&account.data).ok()?;
+            if !token_account.is_frozen() {
+                return None;
+            }
+            Some(FrozenTokenAccount {
+                pubkey: *pubkey,
+                mint: token_account.mint,
+                owner: token_account.owner,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use spl_token::state::{AccountState, Mint};
+
+    fn token_account(mint: Pubkey, owner: Pubkey, state: AccountState) -> Account {
+        let mut data = vec![0u8; TokenAccount::LEN];
+        TokenAccount::pack(
+            TokenAccount {
+                mint,
+                owner,
+                state,
+                ..TokenAccount::default()
+            },
+            &mut data,
+        )
+        .unwrap();
+        Account {
+            lamports: 1,
+            data,
+            owner: spl_token::id(),
+            executable: false,
+            rent_epoch: 0,
+        }
+    }
+
+    #[test]
+    fn find_frozen_accounts() {
+        let mint = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        let frozen = Pubkey::new_unique();
+        let initialized = Pubkey::new_unique();
+        let mint_account = Account {
+            lamports: 1,
+            data: vec![0u8; Mint::LEN],
+            owner: spl_token::id(),
+            executable: false,
+            rent_epoch: 0,
+        };
+
+        let accounts = [
+            (
+                frozen,
+                Some(token_account(mint, owner, AccountState::Frozen)),
+            ),
+            (
+                initialized,
+                Some(token_account(mint, owner, AccountState::Initialized)),
+            ),
+            // frozen token account data owned by another program
+            (
+                Pubkey::new_unique(),
+                Some(Account {
+                    owner: Pubkey::new_unique(),
+                    ..token_account(mint, owner, AccountState::Frozen)
+                }),
+            ),
+            (mint, Some(mint_account)),
+            (Pubkey::new_unique(), None),
+        ];
+
+        assert_eq!(
+            frozen_token_accounts(&accounts),
+            vec![FrozenTokenAccount {
+                pubkey: frozen,
+                mint,
+                owner,
+            }]
+        );
+    }
+}
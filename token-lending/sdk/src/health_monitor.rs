@@ -0,0 +1,312 @@
+//! Obligation health notifications.
+//!
+//! A [HealthMonitor] watches the obligations of a set of owners and raises a [HealthAlert] when an
+//! obligation moves to a different [RiskBand], so owners can be margin called before they get
+//! liquidated. Recovering to a safer band requires clearing the threshold by a hysteresis margin,
+//! which keeps an obligation hovering around a threshold from alerting on every refresh.
+//!
+//! Alerts are delivered through a [NotificationSink]. With the `webhook` feature enabled,
+//! [WebhookSink] posts them as JSON to a configurable url.
+
+use crate::{
+    math::{Decimal, TryDiv},
+    offchain_utils::SolendAccounts,
+    risk_band::{RiskBand, RiskBandThresholds},
+    state::Obligation,
+};
+use solana_program::{program_error::ProgramError, pubkey::Pubkey};
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+    error::Error,
+};
+
+/// Ratio of an obligation's unhealthy borrow value to its borrowed value, None if nothing is
+/// borrowed. Expects a refreshed obligation.
+pub fn health_factor(obligation: &Obligation) -> Result<Option<Decimal>, ProgramError> {
+    if obligation.borrowed_value == Decimal::zero() {
+        return Ok(None);
+    }
+    obligation
+        .unhealthy_borrow_value
+        .try_div(obligation.borrowed_value)
+        .map(Some)
+}
+
+/// An obligation moved to a different risk band
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HealthAlert {
+    /// Obligation address
+    pub obligation: Pubkey,
+    /// Obligation owner
+    pub owner: Pubkey,
+    /// Lending market of the obligation
+    pub lending_market: Pubkey,
+    /// Band before this alert
+    pub previous_band: RiskBand,
+    /// Current band
+    pub band: RiskBand,
+    /// Current health factor, None if nothing is borrowed
+    pub health_factor: Option<Decimal>,
+    /// Current borrowed value
+    pub borrowed_value: Decimal,
+    /// Current unhealthy borrow value
+    pub unhealthy_borrow_value: Decimal,
+    /// Current super unhealthy borrow value
+    pub super_unhealthy_borrow_value: Decimal,
+    /// Borrow cap the owner set on the obligation, if any
+    pub borrow_cap: Option<u64>,
+}
+
+impl HealthAlert {
+    /// JSON payload of the alert. Values are strings so decimals keep their precision.
+    pub fn to_json(&self) -> String {
+        format!(
+            "{{\"obligation\":\"{}\",\"owner\":\"{}\",\"lending_market\":\"{}\",\
+             \"previous_band\":\"{}\",\"band\":\"{}\",\"health_factor\":{},\
+             \"borrowed_value\":\"{}\",\"unhealthy_borrow_value\":\"{}\",\
+             \"super_unhealthy_borrow_value\":\"{}\",\"borrow_cap\":{}}}",
+            self.obligation,
+            self.owner,
+            self.lending_market,
+            self.previous_band,
+            self.band,
+            match self.health_factor {
+                Some(health_factor) => format!("\"{}\"", health_factor),
+                None => "null".to_string(),
+            },
+            self.borrowed_value,
+            self.unhealthy_borrow_value,
+            self.super_unhealthy_borrow_value,
+            match self.borrow_cap {
+                Some(borrow_cap) => format!("\"{}\"", borrow_cap),
+                None => "null".to_string(),
+            },
+        )
+    }
+}
+
+/// Destination of health alerts
+pub trait NotificationSink {
+    /// Deliver an alert
+    fn send(&self, alert: &HealthAlert) -> Result<(), Box<dyn Error>>;
+}
+
+/// Posts alerts as JSON to a webhook
+#[cfg(feature = "webhook")]
+pub struct WebhookSink {
+    url: String,
+    client: reqwest::blocking::Client,
+}
+
+#[cfg(feature = "webhook")]
+impl WebhookSink {
+    /// Create a sink posting to `url`
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            client: reqwest::blocking::Client::new(),
+        }
+    }
+}
+
+#[cfg(feature = "webhook")]
+impl NotificationSink for WebhookSink {
+    fn send(&self, alert: &HealthAlert) -> Result<(), Box<dyn Error>> {
+        self.client
+            .post(&self.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(alert.to_json())
+            .send()?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+/// Tracks the risk band of the obligations of watched owners between refreshes
+#[derive(Clone, Debug)]
+pub struct HealthMonitor {
+    thresholds: RiskBandThresholds,
+    watched_owners: HashSet<Pubkey>,
+    bands: HashMap<Pubkey, RiskBand>,
+}
+
+impl HealthMonitor {
+    /// Create a monitor for the obligations of `watched_owners`
+    pub fn new(thresholds: RiskBandThresholds, watched_owners: HashSet<Pubkey>) -> Self {
+        Self {
+            thresholds,
+            watched_owners,
+            bands: HashMap::new(),
+        }
+    }
+
+    /// Start watching an owner's obligations
+    pub fn watch(&mut self, owner: Pubkey) {
+        self.watched_owners.insert(owner);
+    }
+
+    /// Stop watching an owner's obligations
+    pub fn unwatch(&mut self, owner: &Pubkey) {
+        self.watched_owners.remove(owner);
+    }
+
+    /// Last known band of an obligation
+    pub fn band(&self, obligation: &Pubkey) -> RiskBand {
+        self.bands
+            .get(obligation)
+            .copied()
+            .unwrap_or(RiskBand::Safe)
+    }
+
+    /// Update the bands of the watched obligations in `accounts`, which should be refreshed, and
+    /// return an alert for every obligation whose band changed. Obligations start out safe,
+    /// and ones missing from `accounts` (eg fully repaid) are forgotten.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip_all, fields(obligations = accounts.obligations.len()), err)
+    )]
+    pub fn check(&mut self, accounts: &SolendAccounts) -> Result<Vec<HealthAlert>, ProgramError> {
+        let mut alerts = Vec::new();
+        let mut bands = HashMap::new();
+        for (pubkey, obligation) in &accounts.obligations {
+            if !self.watched_owners.contains(&obligation.owner) {
+                continue;
+            }
+
+            let previous_band = self.band(pubkey);
+            let band = self.thresholds.next_band(previous_band, obligation)?;
+            if band != previous_band {
+                trace_event!(
+                    info,
+                    obligation = %pubkey,
+                    %previous_band,
+                    %band,
+                    "obligation risk band changed"
+                );
+                alerts.push(HealthAlert {
+                    obligation: *pubkey,
+                    owner: obligation.owner,
+                    lending_market: obligation.lending_market,
+                    previous_band,
+                    band,
+                    health_factor: health_factor(obligation)?,
+                    borrowed_value: obligation.borrowed_value,
+                    unhealthy_borrow_value: obligation.unhealthy_borrow_value,
+                    super_unhealthy_borrow_value: obligation.super_unhealthy_borrow_value,
+                    borrow_cap: accounts
+                        .obligation_borrow_caps
+                        .get(pubkey)
+                        .map(|borrow_cap| borrow_cap.max_borrowed_value),
+                });
+            }
+            bands.insert(*pubkey, band);
+        }
+        self.bands = bands;
+
+        // worst first
+        alerts.sort_by_key(|alert| Reverse(alert.band));
+        Ok(alerts)
+    }
+
+    /// [check](Self::check) the watched obligations and send the alerts to `sink`. Returns the
+    /// number of alerts sent.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, err))]
+    pub fn notify(
+        &mut self,
+        accounts: &SolendAccounts,
+        sink: &impl NotificationSink,
+    ) -> Result<usize, Box<dyn Error>> {
+        let alerts = self.check(accounts)?;
+        for alert in &alerts {
+            sink.send(alert)?;
+        }
+        Ok(alerts.len())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::state::ObligationBorrowCap;
+    use std::cell::RefCell;
+
+    struct RecordingSink(RefCell<Vec<HealthAlert>>);
+
+    impl NotificationSink for RecordingSink {
+        fn send(&self, alert: &HealthAlert) -> Result<(), Box<dyn Error>> {
+            self.0.borrow_mut().push(alert.clone());
+            Ok(())
+        }
+    }
+
+    fn accounts_with(obligations: &[(Pubkey, Pubkey, u64)]) -> SolendAccounts {
+        SolendAccounts {
+            lending_markets: HashMap::new(),
+            reserves: HashMap::new(),
+            obligations: obligations
+                .iter()
+                .map(|(pubkey, owner, borrowed_value)| {
+                    (
+                        *pubkey,
+                        Obligation {
+                            owner: *owner,
+                            borrowed_value: Decimal::from(*borrowed_value),
+                            unhealthy_borrow_value: Decimal::from(100u64),
+                            super_unhealthy_borrow_value: Decimal::from(120u64),
+                            ..Obligation::default()
+                        },
+                    )
+                })
+                .collect(),
+            obligation_borrow_caps: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn monitor_alerts_on_band_changes() {
+        let owner = Pubkey::new_unique();
+        let obligation = Pubkey::new_unique();
+        let unwatched = Pubkey::new_unique();
+        let mut monitor = HealthMonitor::new(RiskBandThresholds::default(), HashSet::from([owner]));
+        let sink = RecordingSink(RefCell::new(Vec::new()));
+
+        // half of the unhealthy borrow value, nothing to report
+        let accounts = accounts_with(&[(obligation, owner, 50), (unwatched, unwatched, 100)]);
+        assert_eq!(monitor.notify(&accounts, &sink).unwrap(), 0);
+
+        // 95% of the unhealthy borrow value
+        let accounts = accounts_with(&[(obligation, owner, 95), (unwatched, unwatched, 100)]);
+        assert_eq!(monitor.notify(&accounts, &sink).unwrap(), 1);
+        let alert = sink.0.borrow()[0].clone();
+        assert_eq!(alert.obligation, obligation);
+        assert_eq!(alert.previous_band, RiskBand::Safe);
+        assert_eq!(alert.band, RiskBand::Danger);
+        assert!(alert.to_json().contains("\"band\":\"danger\""));
+        assert!(alert
+            .to_json()
+            .contains("\"super_unhealthy_borrow_value\":\"120."));
+        assert!(alert.to_json().contains("\"borrow_cap\":null"));
+
+        // hovering around the threshold doesn't alert again
+        let accounts = accounts_with(&[(obligation, owner, 88)]);
+        assert_eq!(monitor.check(&accounts).unwrap(), vec![]);
+        let accounts = accounts_with(&[(obligation, owner, 95)]);
+        assert_eq!(monitor.check(&accounts).unwrap(), vec![]);
+
+        // liquidatable
+        let mut accounts = accounts_with(&[(obligation, owner, 100)]);
+        accounts
+            .obligation_borrow_caps
+            .insert(obligation, ObligationBorrowCap::new(obligation, 255, 1_000));
+        let alerts = monitor.check(&accounts).unwrap();
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].band, RiskBand::Liquidatable);
+        assert_eq!(alerts[0].borrow_cap, Some(1_000));
+        assert!(alerts[0].to_json().contains("\"borrow_cap\":\"1000\""));
+
+        // repaid obligations are forgotten
+        monitor.check(&accounts_with(&[])).unwrap();
+        assert_eq!(monitor.band(&obligation), RiskBand::Safe);
+    }
+}
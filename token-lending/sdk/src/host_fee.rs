@@ -0,0 +1,191 @@
+//! Host fee receivers out of host wallets.
+//!
+//! Borrows pay the host fee portion of the origination fee to a token account of the borrowed
+//! mint. [get_host_fee_receiver] lets frontends name only their wallet: it derives the wallet's
+//! associated token account for the liquidity mint and, if that account doesn't exist yet, either
+//! creates it in the same transaction or leaves the host fee out so the borrow still goes through.
+//!
+//! ```ignore
+//! let host_fee_receiver =
+//!     get_host_fee_receiver(&client, &host_wallet, &liquidity_mint, &payer, true)?;
+//! let mut instructions = host_fee_receiver.setup_instructions;
+//! instructions.push(borrow_obligation_liquidity(
+//!     // ...
+//!     host_fee_receiver.pubkey,
+//! ));
+//! ```
+
+use crate::instruction::{
+    create_associated_token_account_idempotent, get_associated_token_address,
+};
+use solana_client::rpc_client::RpcClient;
+use solana_program::{instruction::Instruction, program_pack::Pack, pubkey::Pubkey};
+use solana_sdk::account::Account;
+use spl_token::state::Account as TokenAccount;
+use std::error::Error;
+
+/// Host fee receiver to pass to a borrow builder
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HostFeeReceiver {
+    /// Token account receiving the host fee, None if the borrow should go without one
+    pub pubkey: Option<Pubkey>,
+    /// Instructions to run before the borrow, creating the token account if needed
+    pub setup_instructions: Vec<Instruction>,
+}
+
+impl HostFeeReceiver {
+    /// No host fee receiver
+    pub fn none() -> Self {
+        Self {
+            pubkey: None,
+            setup_instructions: Vec::new(),
+        }
+    }
+}
+
+/// Host fee receiver of `host_wallet` for borrows of `liquidity_mint`: its associated token
+/// account. A missing account is created with `payer` paying rent if `create_if_missing` is set,
+/// otherwise it and any account that isn't a token account of the mint are left out.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(skip_all, fields(host = %host_wallet, mint = %liquidity_mint), err)
+)]
+pub fn get_host_fee_receiver(
+    client: &RpcClient,
+    host_wallet: &Pubkey,
+    liquidity_mint: &Pubkey,
+    payer: &Pubkey,
+    create_if_missing: bool,
+) -> Result<HostFeeReceiver, Box<dyn Error>> {
+    let associated_token_address = get_associated_token_address(host_wallet, liquidity_mint);
+    let account = client
+        .get_account_with_commitment(&associated_token_address, client.commitment())?
+        .value;
+    Ok(host_fee_receiver(
+        host_wallet,
+        liquidity_mint,
+        payer,
+        account.as_ref(),
+        create_if_missing,
+    ))
+}
+
+/// Host fee receiver given the host's associated token account as fetched, None if missing
+pub fn host_fee_receiver(
+    host_wallet: &Pubkey,
+    liquidity_mint: &Pubkey,
+    payer: &Pubkey,
+    associated_token_account: Option<&Account>,
+    create_if_missing: bool,
+) -> HostFeeReceiver {
+    let associated_token_address = get_associated_token_address(host_wallet, liquidity_mint);
+    match associated_token_account {
+        Some(account) => {
+            let is_token_account = account.owner == spl_token::id()
+                && TokenAccount::unpack(&account.data)
+                    .map(|token_account| token_account.mint == *liquidity_mint)
+                    .unwrap_or(false);
+            if !is_token_account {
+                trace_event!(
+                    warn,
+                    account = %associated_token_address,
+                    "host fee receiver is not a token account of the liquidity mint"
+                );
+                return HostFeeReceiver::none();
+            }
+            HostFeeReceiver {
+                pubkey: Some(associated_token_address),
+                setup_instructions: Vec::new(),
+            }
+        }
+        None if create_if_missing => HostFeeReceiver {
+            pubkey: Some(associated_token_address),
+            setup_instructions: vec![create_associated_token_account_idempotent(
+                *payer,
+                *host_wallet,
+                *liquidity_mint,
+            )],
+        },
+        None => HostFeeReceiver::none(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use solana_program::program_option::COption;
+    use spl_token::state::AccountState;
+
+    fn token_account(mint: Pubkey, owner: Pubkey) -> Account {
+        let mut data = vec![0u8; TokenAccount::LEN];
+        TokenAccount::pack(
+            TokenAccount {
+                mint,
+                owner,
+                state: AccountState::Initialized,
+                delegate: COption::None,
+                close_authority: COption::None,
+                ..TokenAccount::default()
+            },
+            &mut data,
+        )
+        .unwrap();
+        Account {
+            lamports: 1,
+            data,
+            owner: spl_token::id(),
+            executable: false,
+            rent_epoch: 0,
+        }
+    }
+
+    #[test]
+    fn resolve_host_fee_receiver() {
+        let host = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let payer = Pubkey::new_unique();
+        let ata = get_associated_token_address(&host, &mint);
+
+        // existing account
+        let account = token_account(mint, host);
+        for create_if_missing in [false, true] {
+            assert_eq!(
+                host_fee_receiver(&host, &mint, &payer, Some(&account), create_if_missing),
+                HostFeeReceiver {
+                    pubkey: Some(ata),
+                    setup_instructions: vec![],
+                }
+            );
+        }
+
+        // missing account
+        let receiver = host_fee_receiver(&host, &mint, &payer, None, true);
+        assert_eq!(receiver.pubkey, Some(ata));
+        assert_eq!(
+            receiver.setup_instructions,
+            vec![create_associated_token_account_idempotent(
+                payer, host, mint
+            )]
+        );
+        assert_eq!(receiver.setup_instructions[0].accounts[1].pubkey, ata);
+        assert_eq!(
+            host_fee_receiver(&host, &mint, &payer, None, false),
+            HostFeeReceiver::none()
+        );
+
+        // wrong mint or not a token account
+        let wrong_mint = token_account(Pubkey::new_unique(), host);
+        assert_eq!(
+            host_fee_receiver(&host, &mint, &payer, Some(&wrong_mint), true),
+            HostFeeReceiver::none()
+        );
+        let not_a_token_account = Account {
+            owner: Pubkey::new_unique(),
+            ..account
+        };
+        assert_eq!(
+            host_fee_receiver(&host, &mint, &payer, Some(&not_a_token_account), true),
+            HostFeeReceiver::none()
+        );
+    }
+}
@@ -0,0 +1,227 @@
+//! Safe retries of lending transactions through durable nonces.
+//!
+//! A transaction signed with a recent blockhash can land after its sender gave up on it, and
+//! resending a rebuilt copy, with a fresher blockhash or a higher priority fee, can then apply the
+//! action twice. Signing every attempt against the same [DurableNonce] value instead makes it an
+//! idempotency key: the first attempt to land advances the nonce, and every other attempt built
+//! with the old value is rejected for good.
+//!
+//! 1. Fetch the nonce once per action with [DurableNonce::fetch] and keep it with the action
+//! 2. Build each attempt with [TransactionBuilder::durable_nonce] set to that same value
+//! 3. Before giving up or reporting a result, check [DurableNonce::status]. While the nonce is
+//!    unused, resending is safe. Once it is used the action can't apply anymore, and comparing an
+//!    obligation snapshot from before the first attempt with a fresh one through
+//!    [action_applied] tells whether it did.
+//!
+//! The nonce advances whether the transaction succeeded or failed, hence the snapshot check.
+//!
+//! [TransactionBuilder::durable_nonce]: crate::transaction_builder::TransactionBuilder::durable_nonce
+
+use crate::{
+    math::{Decimal, TrySub},
+    state::Obligation,
+};
+use solana_client::{nonce_utils, rpc_client::RpcClient};
+use solana_program::{hash::Hash, instruction::Instruction, pubkey::Pubkey, system_instruction};
+use std::error::Error;
+
+/// Durable nonce value transactions are signed against instead of a recent blockhash
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DurableNonce {
+    /// Nonce account, one per action in flight
+    pub nonce_account: Pubkey,
+    /// Authority of the nonce account, has to sign the transaction
+    pub authority: Pubkey,
+    /// Nonce value the transaction is signed against
+    pub blockhash: Hash,
+}
+
+/// Whether a durable nonce value can still be used
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NonceStatus {
+    /// The nonce account still holds the value, nothing signed against it landed yet
+    Unused,
+    /// The nonce account moved on to another value, transactions signed against the old one
+    /// landed or never will
+    Used,
+}
+
+impl DurableNonce {
+    /// Fetch the current value of `nonce_account`
+    pub fn fetch(
+        client: &RpcClient,
+        nonce_account: &Pubkey,
+        authority: &Pubkey,
+    ) -> Result<Self, Box<dyn Error>> {
+        let data = nonce_utils::data_from_account(&nonce_utils::get_account_with_commitment(
+            client,
+            nonce_account,
+            client.commitment(),
+        )?)?;
+        if data.authority != *authority {
+            return Err(format!(
+                "Nonce account {} has authority {}, not {}",
+                nonce_account, data.authority, authority
+            )
+            .into());
+        }
+        Ok(Self {
+            nonce_account: *nonce_account,
+            authority: *authority,
+            blockhash: data.blockhash(),
+        })
+    }
+
+    /// Instruction advancing the nonce, has to be the first one of the transaction
+    pub fn advance_instruction(&self) -> Instruction {
+        system_instruction::advance_nonce_account(&self.nonce_account, &self.authority)
+    }
+
+    /// Check whether the nonce value was used, at the client's commitment. Only a used nonce at
+    /// the finalized commitment guarantees no attempt will land anymore.
+    pub fn status(&self, client: &RpcClient) -> Result<NonceStatus, Box<dyn Error>> {
+        let data = nonce_utils::data_from_account(&nonce_utils::get_account_with_commitment(
+            client,
+            &self.nonce_account,
+            client.commitment(),
+        )?)?;
+        Ok(if data.blockhash() == self.blockhash {
+            NonceStatus::Unused
+        } else {
+            NonceStatus::Used
+        })
+    }
+}
+
+/// Obligation action whose effect can be told from obligation snapshots
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ObligationAction {
+    /// Collateral deposited to a reserve
+    Deposit {
+        /// Reserve the collateral is deposited to
+        reserve: Pubkey,
+    },
+    /// Collateral withdrawn from a reserve
+    Withdraw {
+        /// Reserve the collateral is withdrawn from
+        reserve: Pubkey,
+    },
+    /// Liquidity borrowed from a reserve
+    Borrow {
+        /// Reserve the liquidity is borrowed from
+        reserve: Pubkey,
+        /// Amount of liquidity borrowed, before fees
+        liquidity_amount: u64,
+    },
+    /// Liquidity repaid to a reserve
+    Repay {
+        /// Reserve the liquidity is repaid to
+        reserve: Pubkey,
+    },
+}
+
+fn deposited_amount(obligation: &Obligation, reserve: Pubkey) -> u64 {
+    obligation
+        .find_collateral_in_deposits(reserve)
+        .map(|(collateral, _)| collateral.deposited_amount)
+        .unwrap_or(0)
+}
+
+fn borrowed_amount(obligation: &Obligation, reserve: Pubkey) -> Decimal {
+    obligation
+        .find_liquidity_in_borrows(reserve)
+        .map(|(liquidity, _)| liquidity.borrowed_amount_wads)
+        .unwrap_or_else(|_| Decimal::zero())
+}
+
+/// Whether `action` applied between the `before` and `after` snapshots of an obligation.
+///
+/// Deposits only change through deposits, withdraws and liquidations, and debt only shrinks
+/// through repays and liquidations, so any move in the right direction counts. Debt also grows
+/// with interest though, so borrows have to grow it by at least `liquidity_amount`. Other actions on the obligation
+/// in between, like a liquidation, make the comparison meaningless, as does a repay smaller than
+/// the interest accrued between the snapshots.
+pub fn action_applied(before: &Obligation, after: &Obligation, action: ObligationAction) -> bool {
+    match action {
+        ObligationAction::Deposit { reserve } => {
+            deposited_amount(after, reserve) > deposited_amount(before, reserve)
+        }
+        ObligationAction::Withdraw { reserve } => {
+            deposited_amount(after, reserve) < deposited_amount(before, reserve)
+        }
+        ObligationAction::Borrow {
+            reserve,
+            liquidity_amount,
+        } => match borrowed_amount(after, reserve).try_sub(borrowed_amount(before, reserve)) {
+            Ok(borrowed) => borrowed >= Decimal::from(liquidity_amount),
+            Err(_) => false,
+        },
+        ObligationAction::Repay { reserve } => {
+            borrowed_amount(after, reserve) < borrowed_amount(before, reserve)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::state::{ObligationCollateral, ObligationLiquidity};
+
+    #[test]
+    fn obligation_actions_applied() {
+        let collateral_reserve = Pubkey::new_unique();
+        let debt_reserve = Pubkey::new_unique();
+        let before = Obligation {
+            deposits: vec![ObligationCollateral {
+                deposited_amount: 1_000,
+                ..ObligationCollateral::new(collateral_reserve)
+            }],
+            ..Obligation::default()
+        };
+        let deposit = ObligationAction::Deposit {
+            reserve: collateral_reserve,
+        };
+        let borrow = ObligationAction::Borrow {
+            reserve: debt_reserve,
+            liquidity_amount: 100,
+        };
+        assert!(!action_applied(&before, &before, deposit));
+        assert!(!action_applied(&before, &before, borrow));
+
+        // borrowed 100 plus a 1 token fee
+        let mut after = before.clone();
+        after.deposits[0].deposited_amount = 1_500;
+        after.borrows.push(ObligationLiquidity {
+            borrowed_amount_wads: Decimal::from(101u64),
+            ..ObligationLiquidity::new(debt_reserve, Decimal::one())
+        });
+        assert!(action_applied(&before, &after, deposit));
+        assert!(action_applied(&before, &after, borrow));
+        assert!(!action_applied(
+            &before,
+            &after,
+            ObligationAction::Withdraw {
+                reserve: collateral_reserve
+            }
+        ));
+
+        // interest alone doesn't count as a borrow, nor as a repay
+        let mut later = after.clone();
+        later.borrows[0].borrowed_amount_wads = Decimal::from(102u64);
+        assert!(!action_applied(&after, &later, borrow));
+        assert!(!action_applied(
+            &after,
+            &later,
+            ObligationAction::Repay {
+                reserve: debt_reserve
+            }
+        ));
+        assert!(action_applied(
+            &later,
+            &after,
+            ObligationAction::Repay {
+                reserve: debt_reserve
+            }
+        ));
+    }
+}
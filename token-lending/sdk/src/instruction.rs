@@ -1,8 +1,17 @@
 //! Instruction types
 
-use crate::state::{LendingMarketMetadata, ReserveType};
+use crate::state::{
+    DepositMode, LendingMarketMetadata, Obligation, PriceBoundsMode, ReserveType,
+    BORROW_ALLOWANCES_SEED, BORROW_GRACE_CONFIG_SEED, CONFIG_TIMELOCK_SEED,
+    CROSS_MARKET_SOURCES_SEED, DELEVERAGE_CONFIG_SEED, DELEVERAGE_CREDIT_SEED,
+    DUST_SWEEP_CONFIG_SEED, FEE_REBATES_SEED, FEE_REDEMPTION_HOOK_SEED, FLASH_LOAN_CONFIG_SEED,
+    LIQUIDATION_CIRCUIT_BREAKER_SEED, LIQUIDATION_STATS_SEED, MARKET_POLICY_SEED,
+    OBLIGATION_BORROW_CAP_SEED, OBLIGATION_LIMITS_SEED, PENDING_CONFIG_SEED,
+    RESERVE_ACCRUAL_HISTORY_SEED,
+};
 use crate::{
     error::LendingError,
+    math::Decimal,
     state::{RateLimiterConfig, ReserveConfig, ReserveFees},
 };
 use bytemuck::bytes_of;
@@ -13,8 +22,10 @@ use solana_program::{
     instruction::{AccountMeta, Instruction},
     msg,
     program_error::ProgramError,
+    program_pack::Pack,
     pubkey::{Pubkey, PUBKEY_BYTES},
-    sysvar,
+    system_instruction,
+    sysvar::{self, rent::Rent},
 };
 use std::{convert::TryInto, mem::size_of};
 
@@ -45,8 +56,17 @@ pub enum LendingInstruction {
     ///
     /// Accounts expected by this instruction:
     ///
+    /// If the lending market has an active config timelock, the change is staged instead and
+    /// applied by ActivateConfig once the timelock delay has passed.
+    ///
     ///   0. `[writable]` Lending market account.
-    ///   1. `[signer]` Current owner.
+    ///   1. `[signer, writable]` Current owner, pays for the pending config account.
+    ///   2. `[]` Config timelock account.
+    ///                     Must be a pda with seeds [lending market, "ConfigTimelock"], it doesn't
+    ///                     have to exist.
+    ///   3. `[writable]` Pending config account, where the change is staged if the timelock is
+    ///                     active. Must be a pda with seeds [lending market, "PendingConfig"].
+    ///   4. `[]` System program.
     SetLendingMarketOwnerAndConfig {
         /// The new owner
         new_owner: Pubkey,
@@ -56,6 +76,9 @@ pub enum LendingInstruction {
         whitelisted_liquidator: Option<Pubkey>,
         /// The risk authority
         risk_authority: Pubkey,
+        /// Slots per year used to compound interest, in [MIN_SLOTS_PER_YEAR, MAX_SLOTS_PER_YEAR].
+        /// Left out of the instruction data to keep the current value.
+        slots_per_year: Option<u64>,
     },
 
     // 2
@@ -76,6 +99,8 @@ pub enum LendingInstruction {
     ///   9. `[]` Pyth price account.
     ///             This will be used as the reserve liquidity oracle account.
     ///   10. `[]` Switchboard price feed account. used as a backup oracle
+    ///             For a reserve whose liquidity is the cToken of a reserve in another lending
+    ///             market, that source reserve instead, with null pyth accounts.
     ///   11 `[]` Lending market account.
     ///   12 `[]` Derived lending market authority.
     ///   13 `[signer]` Lending market owner.
@@ -83,6 +108,8 @@ pub enum LendingInstruction {
     ///   15 `[]` Clock sysvar (optional, will be removed soon).
     ///   16 `[]` Rent sysvar.
     ///   17 `[]` Token program id.
+    ///   18 `[]` Cross market sources account, required if 10 is a source reserve.
+    ///             Must be a pda with seeds [lending market, "CrossMarketSources"] allowing it.
     InitReserve {
         /// Initial amount of liquidity to deposit into the new reserve
         liquidity_amount: u64,
@@ -91,7 +118,9 @@ pub enum LendingInstruction {
     },
 
     // 3
-    /// Accrue interest and update market price of liquidity on a reserve.
+    /// Accrue interest and update market price of liquidity on a reserve. Interest accrues at the
+    /// slots per year of the lending market if it's passed, and at the value the reserve last
+    /// accrued at otherwise.
     ///
     /// Accounts expected by this instruction:
     ///
@@ -101,6 +130,10 @@ pub enum LendingInstruction {
     ///   2. `[]` Switchboard Reserve liquidity oracle account.
     ///             Must be the Switchboard price feed account specified at InitReserve.
     ///   3. `[]` Clock sysvar (optional, will be removed soon).
+    ///   4. `[]` Lending market account of the reserve (optional).
+    ///   5. `[writable]` Reserve accrual history account (optional).
+    ///                     Must be a pda with seeds [reserve, "AccrualHistory"]. Records the
+    ///                     opening values of the epochs crossed since it was last passed.
     RefreshReserve,
 
     // 4
@@ -141,6 +174,8 @@ pub enum LendingInstruction {
     ///   7. `[signer]` User transfer authority ($authority).
     ///   8. `[]` Clock sysvar (optional, will be removed soon).
     ///   9. `[]` Token program id.
+    ///   10. `[writable]` Reserve liquidity fee receiver.
+    ///                     Optional, required if the reserve charges a withdraw fee.
     RedeemReserveCollateral {
         /// Amount of collateral tokens to redeem in exchange for liquidity
         collateral_amount: u64,
@@ -149,6 +184,10 @@ pub enum LendingInstruction {
     // 6
     /// Initializes a new lending market obligation.
     ///
+    /// The obligation account can be created and funded by any account, so an app can pay rent
+    /// on behalf of the obligation owner. See `create_and_init_obligation`. The owner can refund
+    /// the rent to the payer with `CloseObligation` once the obligation is empty.
+    ///
     /// Accounts expected by this instruction:
     ///
     ///   0. `[writable]` Obligation account - uninitialized.
@@ -188,6 +227,9 @@ pub enum LendingInstruction {
     ///   6. `[signer]` User transfer authority ($authority).
     ///   7. `[]` Clock sysvar (optional, will be removed soon).
     ///   8. `[]` Token program id.
+    ///   .. Optional accounts in any order, each found by its derived address:
+    ///        `[]` Obligation limits account, required if the lending market has limits.
+    ///                     Must be a pda with seeds [lending market, "ObligationLimits"]
     DepositObligationCollateral {
         /// Amount of collateral tokens to deposit
         collateral_amount: u64,
@@ -208,6 +250,13 @@ pub enum LendingInstruction {
     ///   6. `[signer]` Obligation owner.
     ///   7. `[]` Clock sysvar (optional, will be removed soon).
     ///   8. `[]` Token program id.
+    ///   .. Optional accounts in any order, each found by its derived address:
+    ///        `[]` Obligation limits account, required if the lending market has limits.
+    ///                     Must be a pda with seeds [lending market, "ObligationLimits"]. Its
+    ///                     health buffer applies unless the obligation ignores it.
+    ///        `[]` Market policy account, required if the lending market has a policy.
+    ///                     Must be a pda with seeds [lending market, "MarketPolicy"]
+    ///        `[]` Policy program and its policy account, if the market has a policy.
     WithdrawObligationCollateral {
         /// Amount of collateral tokens to withdraw - u64::MAX for up to 100% of deposited amount
         collateral_amount: u64,
@@ -228,10 +277,31 @@ pub enum LendingInstruction {
     ///   4. `[writable]` Obligation account - refreshed.
     ///   5. `[]` Lending market account.
     ///   6. `[]` Derived lending market authority.
-    ///   7. `[signer]` Obligation owner.
+    ///   7. `[signer]` Obligation owner, or a delegate with a borrow allowance.
     ///   8. `[]` Clock sysvar (optional, will be removed soon).
     ///   9. `[]` Token program id.
     ///   10 `[optional, writable]` Host fee receiver account.
+    ///   .. Optional accounts in any order, each found by its derived address:
+    ///        `[]` Obligation limits account, required if the lending market has limits.
+    ///                     Must be a pda with seeds [lending market, "ObligationLimits"]. Its
+    ///                     health buffer applies unless the obligation ignores it.
+    ///        `[]` Obligation borrow cap account, required if the obligation has a cap.
+    ///                     Must be a pda with seeds [obligation, "ObligationBorrowCap"]
+    ///        `[]` Market policy account, required if the lending market has a policy.
+    ///                     Must be a pda with seeds [lending market, "MarketPolicy"]
+    ///        `[]` Borrow grace config account.
+    ///                     Must be a pda with seeds [borrow reserve, "BorrowGraceConfig"]. A new
+    ///                     position borrows interest-free for its grace slots. Fails if the
+    ///                     obligation already has an interest-free borrow.
+    ///        `[writable]` Deleverage credit account, required if the obligation has one.
+    ///                     Must be a pda with seeds [obligation, "DeleverageCredit"]. Borrowing
+    ///                     takes back the credit of earlier repayments.
+    ///        `[writable]` Borrow allowances account, required when signed by a delegate.
+    ///                     Must be a pda with seeds [obligation, "BorrowAllowances"]
+    ///        `[]` Instructions sysvar and fee rebates account, to claim a borrow fee rebate.
+    ///                     The fee rebates account must be a pda with seeds
+    ///                     [lending market, "FeeRebates"]
+    ///        `[]` Policy program and its policy account, if the market has a policy.
     BorrowObligationLiquidity {
         /// Amount of liquidity to borrow - u64::MAX for 100% of borrowing power
         liquidity_amount: u64,
@@ -240,6 +310,11 @@ pub enum LendingInstruction {
 
     // 11
     /// Repay borrowed liquidity to a reserve. Requires a refreshed obligation and reserve.
+    /// Anyone can repay on behalf of the obligation owner: the transfer authority doesn't have to
+    /// be the owner, and is reported as the payer of the emitted ObligationLiquidityRepaid event.
+    /// Repaying an interest-free borrow requires the obligation refreshed in the same slot, as
+    /// its waived interest is shared out by the slots since the last refresh, so third-party
+    /// repayers have to refresh it first.
     ///
     /// Accounts expected by this instruction:
     ///
@@ -253,6 +328,12 @@ pub enum LendingInstruction {
     ///   5. `[signer]` User transfer authority ($authority).
     ///   6. `[]` Clock sysvar (optional, will be removed soon).
     ///   7. `[]` Token program id.
+    ///   .. Optional accounts in any order, each found by its derived address:
+    ///        `[writable]` Deleverage credit account, credited with the value repaid if the
+    ///                     market has deleveraging enabled.
+    ///                     Must be a pda with seeds [obligation, "DeleverageCredit"]
+    ///        `[]` Deleverage config account, without which deleveraging is disabled.
+    ///                     Must be a pda with seeds [lending market, "DeleverageConfig"]
     RepayObligationLiquidity {
         /// Amount of liquidity to repay - u64::MAX for 100% of borrowed amount
         liquidity_amount: u64,
@@ -347,6 +428,9 @@ pub enum LendingInstruction {
     ///   12 `[signer]` User transfer authority ($authority).
     ///   13 `[]` Clock sysvar (optional, will be removed soon).
     ///   14 `[]` Token program id.
+    ///   .. Optional accounts in any order, each found by its derived address:
+    ///        `[]` Obligation limits account, required if the lending market has limits.
+    ///                     Must be a pda with seeds [lending market, "ObligationLimits"]
     DepositReserveLiquidityAndObligationCollateral {
         /// Amount of liquidity to deposit in exchange
         liquidity_amount: u64,
@@ -364,13 +448,30 @@ pub enum LendingInstruction {
     ///   3. `[writable]` Obligation account - refreshed.
     ///   4. `[]` Lending market account.
     ///   5. `[]` Derived lending market authority.
-    ///   6. `[writable]` User liquidity token account.
+    ///   6. `[writable]` Destination liquidity token account.
+    ///                     Minted by withdraw reserve liquidity mint. Need not be owned by the
+    ///                     obligation owner.
     ///   7. `[writable]` Reserve collateral SPL Token mint.
     ///   8. `[writable]` Reserve liquidity supply SPL Token account.
     ///   9. `[signer]` Obligation owner
     ///   10 `[signer]` User transfer authority ($authority).
     ///   11. `[]` Clock sysvar (optional, will be removed soon).
     ///   12. `[]` Token program id.
+    ///   13. `[optional, writable]` Reserve liquidity fee receiver.
+    ///                     Required if the reserve charges a withdraw fee.
+    ///   .. Optional accounts in any order, each found by its derived address:
+    ///        `[]` Obligation limits account, required if the lending market has limits.
+    ///                     Must be a pda with seeds [lending market, "ObligationLimits"]. Its
+    ///                     health buffer applies unless the obligation ignores it.
+    ///        `[]` Market policy account, required if the lending market has a policy.
+    ///                     Must be a pda with seeds [lending market, "MarketPolicy"]
+    ///        `[writable]` Deleverage credit account.
+    ///                     Must be a pda with seeds [obligation, "DeleverageCredit"]. Exempts
+    ///                     the value the obligation repaid this rate limiter window from the
+    ///                     lending market outflow limit.
+    ///        `[]` Deleverage config account, to go with the deleverage credit account.
+    ///                     Must be a pda with seeds [lending market, "DeleverageConfig"]
+    ///        `[]` Policy program and its policy account, if the market has a policy.
     WithdrawObligationCollateralAndRedeemReserveCollateral {
         /// liquidity_amount is the amount of collateral tokens to withdraw
         collateral_amount: u64,
@@ -381,13 +482,26 @@ pub enum LendingInstruction {
     ///
     /// Accounts expected by this instruction:
     ///
+    /// If the lending market has an active config timelock, changes made by the lending market
+    /// owner are staged instead and applied by ActivateConfig once the timelock delay has passed.
+    /// Changes made by the risk authority always apply immediately.
+    ///
     ///   1. `[writable]` Reserve account - refreshed
     ///   2 `[]` Lending market account.
     ///   3 `[]` Derived lending market authority.
-    ///   4 `[signer]` Lending market owner.
+    ///   4 `[signer, writable]` Lending market owner, pays for the pending config account.
     ///   5 `[]` Pyth product key.
     ///   6 `[]` Pyth price key.
     ///   7 `[]` Switchboard key.
+    ///   8 `[]` Config timelock account.
+    ///                     Must be a pda with seeds [lending market, "ConfigTimelock"], it doesn't
+    ///                     have to exist.
+    ///   9 `[writable]` Pending config account, where the change is staged if the timelock is
+    ///                     active. Must be a pda with seeds [reserve, "PendingConfig"].
+    ///   10 `[]` System program.
+    ///   11 `[]` Cross market sources account, required if 7 is a source reserve.
+    ///                     Must be a pda with seeds [lending market, "CrossMarketSources"]
+    ///                     allowing it.
     UpdateReserveConfig {
         /// Reserve config to update to
         config: ReserveConfig,
@@ -413,12 +527,33 @@ pub enum LendingInstruction {
     ///   6. `[writable]` Withdraw reserve collateral SPL Token mint.
     ///   7. `[writable]` Withdraw reserve collateral supply SPL Token account.
     ///   8. `[writable]` Withdraw reserve liquidity supply SPL Token account.
-    ///   9. `[writable]` Withdraw reserve liquidity fee receiver account.
+    ///   9. `[writable]` Withdraw reserve liquidation fee receiver account, the reserve's
+    ///                     liquidation_fee_receiver or fee_receiver if unset.
     ///   10 `[writable]` Obligation account - refreshed.
     ///   11 `[]` Lending market account.
     ///   12 `[]` Derived lending market authority.
     ///   13 `[signer]` User transfer authority ($authority).
     ///   14 `[]` Token program id.
+    ///   .. Optional accounts in any order, each found by its derived address:
+    ///        `[writable]` Liquidation circuit breaker account, required if the lending market
+    ///                     has one enabled.
+    ///                     Must be a pda with seeds [lending market, "LiquidationCircuitBreaker"]
+    ///        `[]` Market policy account, required if the lending market has a policy.
+    ///                     Must be a pda with seeds [lending market, "MarketPolicy"]
+    ///        `[writable]` Liquidation stats account.
+    ///                     Must be a pda with seeds [lending market, "LiquidationStats"]
+    ///        `[writable]` Obligation owner.
+    ///                     If the liquidation leaves the obligation without deposits or borrows,
+    ///                     the obligation is closed and its rent refunded to the owner. The
+    ///                     accounts derived from the obligation are closed along with it, and
+    ///                     must all be passed, whether they exist or not, for it to be closed:
+    ///        `[writable]` Borrow allowances account.
+    ///                     Must be a pda with seeds [obligation, "BorrowAllowances"]
+    ///        `[writable]` Obligation borrow cap account.
+    ///                     Must be a pda with seeds [obligation, "ObligationBorrowCap"]
+    ///        `[writable]` Deleverage credit account.
+    ///                     Must be a pda with seeds [obligation, "DeleverageCredit"]
+    ///        `[]` Policy program and its policy account, if the market has a policy.
     LiquidateObligationAndRedeemReserveCollateral {
         /// Amount of liquidity to repay - u64::MAX for up to 100% of borrowed amount
         liquidity_amount: u64,
@@ -432,6 +567,10 @@ pub enum LendingInstruction {
     ///   3. `[]` Lending market account.
     ///   4. `[]` Derived lending market authority.
     ///   5. `[]` Token program id.
+    ///   6. `[]` Fee redemption hook account, only if the reserve has one.
+    ///                     Must be a pda with seeds [reserve, "FeeRedemptionHook"]
+    ///   7. `[]` Hook program.
+    ///   8.. Accounts forwarded to the hook program, matching the hook accounts hash.
     RedeemFees,
 
     // 19
@@ -446,7 +585,9 @@ pub enum LendingInstruction {
     ///   4. `[]` Derived lending market authority.
     ///   5. `[]` Instructions sysvar.
     ///   6. `[]` Token program id.
-    ///   7. `[]` Clock sysvar (optional, will be removed soon).
+    ///   7. `[]` Flash loan config account.
+    ///                     Must be a pda with seeds [reserve, "FlashLoanConfig"], it doesn't
+    ///                     have to exist.
     FlashBorrowReserveLiquidity {
         /// Amount of liquidity to flash borrow
         liquidity_amount: u64,
@@ -461,13 +602,17 @@ pub enum LendingInstruction {
     ///                     $authority can transfer $liquidity_amount.
     ///   1. `[writable]` Destination liquidity token account.
     ///   2. `[writable]` Flash loan fee receiver account.
-    ///                     Must match the reserve liquidity fee receiver.
+    ///                     Must match the flash loan fee receiver of the flash loan config, or
+    ///                     the reserve liquidity fee receiver if it has none.
     ///   3. `[writable]` Host fee receiver.
     ///   4. `[writable]` Reserve account.
     ///   5. `[]` Lending market account.
     ///   6. `[signer]` User transfer authority ($authority).
     ///   7. `[]` Instructions sysvar.
     ///   8. `[]` Token program id.
+    ///   9. `[writable]` Flash loan config account, its flash loan counters are updated.
+    ///                     Must be a pda with seeds [reserve, "FlashLoanConfig"], it doesn't
+    ///                     have to exist.
     FlashRepayReserveLiquidity {
         /// Amount of liquidity to flash repay
         liquidity_amount: u64,
@@ -498,6 +643,632 @@ pub enum LendingInstruction {
     /// Must be a pda with seeds [lending_market, "MetaData"]
     /// 3. `[]` System program
     UpdateMarketMetadata,
+
+    // 23
+    /// Approve a delegate to borrow liquidity from a reserve against an obligation, up to an
+    /// allowance. Replaces any existing allowance of the delegate on that reserve.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[]` Obligation account.
+    ///   1. `[writable]` Borrow allowances account.
+    ///                     Must be a pda with seeds [obligation, "BorrowAllowances"]
+    ///   2. `[]` Borrow reserve account.
+    ///   3. `[]` Delegate.
+    ///   4. `[signer, writable]` Obligation owner, pays for the borrow allowances account.
+    ///   5. `[]` System program
+    ApproveBorrowAllowance {
+        /// Amount of liquidity the delegate may borrow, including borrow fees
+        liquidity_amount: u64,
+    },
+
+    // 24
+    /// Revoke a delegate's borrow allowance on a reserve.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[]` Obligation account.
+    ///   1. `[writable]` Borrow allowances account.
+    ///                     Must be a pda with seeds [obligation, "BorrowAllowances"]
+    ///   2. `[]` Borrow reserve account.
+    ///   3. `[]` Delegate.
+    ///   4. `[signer]` Obligation owner.
+    RevokeBorrowAllowance,
+
+    // 25
+    /// Repay borrowed liquidity to a reserve to receive collateral at a discount from an unhealthy
+    /// obligation, without redeeming it. The liquidator receives cTokens only and gets an extra
+    /// COLLATERAL_LIQUIDATION_EXTRA_BONUS_BPS on top of the regular bonus, since no liquidity
+    /// leaves the withdraw reserve. The protocol liquidation fee is taken in cTokens and converted
+    /// into accumulated protocol fees. Requires a refreshed obligation and reserves.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[writable]` Source liquidity token account.
+    ///                     Minted by repay reserve liquidity mint.
+    ///                     $authority can transfer $liquidity_amount.
+    ///   1. `[writable]` Destination collateral token account.
+    ///                     Minted by withdraw reserve collateral mint.
+    ///   2. `[writable]` Repay reserve account - refreshed.
+    ///   3. `[writable]` Repay reserve liquidity supply SPL Token account.
+    ///   4. `[writable]` Withdraw reserve account - refreshed.
+    ///   5. `[writable]` Withdraw reserve collateral SPL Token mint.
+    ///   6. `[writable]` Withdraw reserve collateral supply SPL Token account.
+    ///   7. `[writable]` Obligation account - refreshed.
+    ///   8. `[]` Lending market account.
+    ///   9. `[]` Derived lending market authority.
+    ///   10 `[signer]` User transfer authority ($authority).
+    ///   11 `[]` Token program id.
+    ///   .. Optional accounts in any order, each found by its derived address:
+    ///        `[writable]` Liquidation circuit breaker account, required if the lending market
+    ///                     has one enabled.
+    ///                     Must be a pda with seeds [lending market, "LiquidationCircuitBreaker"]
+    ///        `[]` Market policy account, required if the lending market has a policy.
+    ///                     Must be a pda with seeds [lending market, "MarketPolicy"]
+    ///        `[writable]` Liquidation stats account.
+    ///                     Must be a pda with seeds [lending market, "LiquidationStats"]
+    ///        `[]` Policy program and its policy account, if the market has a policy.
+    LiquidateObligationForCollateral {
+        /// Amount of liquidity to repay - u64::MAX for up to 100% of borrowed amount
+        liquidity_amount: u64,
+    },
+
+    // 26
+    /// Close an obligation with no deposits or borrows, refunding its rent to an account chosen
+    /// by the obligation owner. The accounts derived from the obligation are closed along with
+    /// it. Emits a `LendingEvent::ObligationClosed` event.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[writable]` Obligation account.
+    ///   1. `[writable]` Rent destination account.
+    ///   2. `[signer]` Obligation owner.
+    ///   .. Accounts derived from the obligation in any order, whether they exist or not:
+    ///        `[writable]` Borrow allowances account.
+    ///                     Must be a pda with seeds [obligation, "BorrowAllowances"]
+    ///        `[writable]` Obligation borrow cap account.
+    ///                     Must be a pda with seeds [obligation, "ObligationBorrowCap"]
+    ///        `[writable]` Deleverage credit account.
+    ///                     Must be a pda with seeds [obligation, "DeleverageCredit"]
+    CloseObligation,
+
+    // 27
+    /// Wind down a reserve ahead of delisting it. The reserve stops accepting deposits and
+    /// borrows, and its loan to value ratio decays every slot until it reaches 0. Interest
+    /// accrual, repayments, withdrawals and liquidations keep working.
+    ///
+    /// Calling this on a reserve that is already winding down restarts the decay from the current
+    /// loan to value ratio, and the decay can only be sped up.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[writable]` Reserve account.
+    ///   1. `[]` Lending market account.
+    ///   2. `[signer]` Lending market owner or risk authority.
+    WindDownReserve {
+        /// Decrease in loan to value ratio every slot, as a Wad
+        ltv_decay_per_slot_wad: u64,
+    },
+
+    // 28
+    /// Close a reserve that is winding down, setting its loan to value ratio to 0.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[writable]` Reserve account.
+    ///   1. `[]` Lending market account.
+    ///   2. `[signer]` Lending market owner or risk authority.
+    CloseReserve,
+
+    // 29
+    /// Configure the liquidation circuit breaker of a lending market. Once the value liquidated
+    /// in a window reaches the max, liquidations pause for `pause_duration` slots. Markets with a
+    /// whitelisted liquidator already only let that liquidator through, so the breaker doesn't
+    /// apply to them. While the breaker is enabled, liquidations have to pass its account.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[writable]` Lending market account.
+    ///   1. `[writable]` Liquidation circuit breaker account.
+    ///                     Must be a pda with seeds [lending market, "LiquidationCircuitBreaker"]
+    ///   2. `[signer, writable]` Lending market owner, pays for the circuit breaker account.
+    ///   3. `[]` System program
+    SetLiquidationCircuitBreaker {
+        /// Window and max liquidated value in the quote currency. A window duration of 0
+        /// disables the breaker.
+        config: RateLimiterConfig,
+        /// Number of slots liquidations are paused for once the breaker trips
+        pause_duration: u64,
+    },
+
+    // 30
+    /// Limit the number of distinct reserves an obligation in a lending market can deposit into
+    /// and borrow from, below the MAX_OBLIGATION_RESERVES hard cap. Obligations already over the
+    /// limits keep their positions but can't add new ones.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[writable]` Lending market account.
+    ///   1. `[writable]` Obligation limits account.
+    ///                     Must be a pda with seeds [lending market, "ObligationLimits"]
+    ///   2. `[signer, writable]` Lending market owner, pays for the obligation limits account.
+    ///   3. `[]` System program
+    SetObligationLimits {
+        /// Max number of reserves an obligation can deposit into
+        max_deposits: u8,
+        /// Max number of reserves an obligation can borrow from
+        max_borrows: u8,
+    },
+
+    // 31
+    /// Set the delay config changes of a lending market are staged for before they apply. Raising
+    /// the delay applies immediately, lowering it is staged behind the current delay itself.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[]` Lending market account.
+    ///   1. `[writable]` Config timelock account.
+    ///                     Must be a pda with seeds [lending market, "ConfigTimelock"]
+    ///   2. `[signer, writable]` Lending market owner, pays for the timelock and pending config
+    ///                     accounts.
+    ///   3. `[writable]` Pending config account.
+    ///                     Must be a pda with seeds [config timelock, "PendingConfig"]
+    ///   4. `[]` System program
+    SetConfigTimelock {
+        /// Number of slots config changes are staged for
+        delay_slots: u64,
+    },
+
+    // 32
+    /// Apply a staged config change once its activation slot is reached. Anyone can activate a
+    /// change, the rent of the pending config account goes back to the lending market owner.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[writable]` Lending market account.
+    ///   1. `[writable]` Pending config account.
+    ///                     Must be a pda with seeds [target, "PendingConfig"]
+    ///   2. `[writable]` Target account the change applies to: a reserve, the lending market or
+    ///                     the config timelock.
+    ///   3. `[writable]` Lending market owner.
+    ActivateConfig,
+
+    // 33
+    /// Cancel a staged config change
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[]` Lending market account.
+    ///   1. `[writable]` Pending config account.
+    ///                     Must be a pda with seeds [target, "PendingConfig"]
+    ///   2. `[]` Target account the change applies to.
+    ///   3. `[signer]` Lending market owner or risk authority.
+    ///   4. `[writable]` Lending market owner, receives the rent of the pending config account.
+    CancelConfig,
+
+    // 34
+    /// Set the priority a deposit is seized with on liquidation. Liquidators have to seize the
+    /// collateral with the highest priority first, so owners can choose e.g. to give up
+    /// stablecoins before their LSTs.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[writable]` Obligation account.
+    ///   1. `[]` Deposit reserve account.
+    ///   2. `[signer]` Obligation owner.
+    SetCollateralLiquidationPriority {
+        /// Liquidation priority, collateral with higher priority is seized first
+        liquidation_priority: u8,
+    },
+
+    // 35
+    /// Grant a share of the borrow fee back to borrows routed by a program, or signed by an
+    /// owner. The program rebate applies when the borrow is CPI'd straight from the top level
+    /// instruction of that program.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[]` Lending market account.
+    ///   1. `[writable]` Fee rebates account.
+    ///                     Must be a pda with seeds [lending market, "FeeRebates"]
+    ///   2. `[signer, writable]` Lending market owner, pays for the fee rebates account.
+    ///   3. `[]` System program
+    SetBorrowFeeRebate {
+        /// Program id or borrow signer the rebate applies to
+        key: Pubkey,
+        /// Share of the borrow fee waived, in basis points. 0 removes the rebate.
+        rebate_bps: u16,
+    },
+
+    // 36
+    /// Set the program RedeemFees calls with `on_fees_redeemed` after transferring a reserve's
+    /// protocol fees, and the accounts forwarded to it. Only the lending market owner can set
+    /// it, which allowlists the program for this reserve.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[writable]` Reserve account.
+    ///   1. `[]` Lending market account.
+    ///   2. `[writable]` Fee redemption hook account.
+    ///                     Must be a pda with seeds [reserve, "FeeRedemptionHook"]
+    ///   3. `[signer, writable]` Lending market owner, pays for the fee redemption hook account.
+    ///   4. `[]` System program
+    SetFeeRedemptionHook {
+        /// Program to call, the default pubkey disables the hook
+        hook_program: Pubkey,
+        /// Hash of the accounts forwarded to the hook program, see
+        /// `fee_redemption_hook_accounts_hash`
+        accounts_hash: [u8; 32],
+    },
+
+    // 37
+    /// Borrow liquidity from a reserve at its fixed borrow rate. The rate is locked on the
+    /// obligation's position in the reserve until the reserve's fixed borrow max duration has
+    /// passed, after which the position converts to the variable rate. A position can't mix
+    /// fixed and variable rate debt.
+    ///
+    /// Accounts expected by this instruction are the same as BorrowObligationLiquidity.
+    BorrowObligationLiquidityFixedRate {
+        /// Amount of liquidity to borrow - u64::MAX for 100% of borrowing power
+        liquidity_amount: u64,
+    },
+
+    // 38
+    /// Create the liquidation stats account of a lending market. Once it exists, liquidations
+    /// that pass it record their count, size and realized bonus in it.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[]` Lending market account.
+    ///   1. `[writable]` Liquidation stats account.
+    ///                     Must be a pda with seeds [lending market, "LiquidationStats"]
+    ///   2. `[signer, writable]` Lending market owner, pays for the liquidation stats account.
+    ///   3. `[]` System program
+    InitLiquidationStats,
+
+    // 39
+    /// Deposit liquidity into a reserve and either deposit the minted collateral into an
+    /// obligation, like DepositReserveLiquidityAndObligationCollateral, or keep it in the
+    /// destination collateral token account, like DepositReserveLiquidity. Without an explicit
+    /// mode the obligation's default deposit mode decides.
+    ///
+    /// Accounts expected by this instruction are the same as
+    /// DepositReserveLiquidityAndObligationCollateral. The obligation owner only has to sign when
+    /// the collateral goes into the obligation.
+    DepositReserveLiquidityWithMode {
+        /// Amount of liquidity to deposit in exchange
+        liquidity_amount: u64,
+        /// What to do with the minted collateral, None for the obligation's default
+        mode: Option<DepositMode>,
+    },
+
+    // 40
+    /// Set what DepositReserveLiquidityWithMode does with the minted collateral when the
+    /// instruction doesn't say.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[writable]` Obligation account.
+    ///   1. `[signer]` Obligation owner.
+    SetObligationDepositMode {
+        /// Default deposit mode
+        mode: DepositMode,
+    },
+
+    // 41
+    /// Refresh an obligation in memory without writing it back, and set its packed
+    /// ObligationHealth as return data, so calling programs can read its health without
+    /// duplicating the refresh math. Requires refreshed reserves, in the same order as
+    /// RefreshObligation.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[]` Obligation account.
+    ///   .. `[]` Collateral deposit reserve accounts - refreshed, all, in order.
+    ///   .. `[]` Liquidity borrow reserve accounts - refreshed, all, in order.
+    ViewObligationHealth,
+
+    // 42
+    /// Move selected deposits and borrows of an obligation into another, empty obligation of the
+    /// same owner, e.g. to isolate the risk of a position. Both obligations are refreshed with
+    /// the reserves provided and have to stay within their borrow limit afterwards.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[writable]` Source obligation account.
+    ///   1. `[writable]` Destination obligation account - initialized, empty.
+    ///   2. `[]` Lending market account.
+    ///   3. `[signer]` Obligation owner.
+    ///   .. `[]` Collateral deposit reserve accounts of the source obligation - refreshed, all, in
+    ///                     order.
+    ///   .. `[]` Liquidity borrow reserve accounts of the source obligation - refreshed, all, in
+    ///                     order.
+    SplitObligation {
+        /// Deposit reserves and collateral amounts to move - u64::MAX for the whole deposit
+        deposits: Vec<(Pubkey, u64)>,
+        /// Borrow reserves and liquidity amounts to move - u64::MAX for the whole borrow
+        borrows: Vec<(Pubkey, u64)>,
+    },
+
+    // 43
+    /// Move all deposits and borrows of obligations into another obligation of the same owner.
+    /// The merged obligations are left empty, to be closed with CloseObligation. All obligations
+    /// have to be refreshed in the current slot, and the destination is stale afterwards.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[writable]` Destination obligation account - refreshed.
+    ///   1. `[]` Lending market account.
+    ///   2. `[signer]` Obligation owner.
+    ///   3. `[]` Obligation limits account.
+    ///                     Must be a pda with seeds [lending market, "ObligationLimits"]
+    ///   .. `[writable]` Obligation accounts to merge - refreshed.
+    MergeObligations,
+
+    // 44
+    /// Set the deposited value below which obligations without borrows can be swept with
+    /// SweepDustObligation.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[]` Lending market account.
+    ///   1. `[writable]` Dust sweep config account.
+    ///                     Must be a pda with seeds [lending market, "DustSweepConfig"]
+    ///   2. `[signer, writable]` Lending market owner, pays for the dust sweep config account.
+    ///   3. `[]` System program
+    SetDustSweepThreshold {
+        /// Max deposited value of a swept obligation, in quote currency wads. 0 disables sweeps.
+        max_deposited_value_wad: u64,
+    },
+
+    // 45
+    /// Close an obligation without borrows whose deposited value is below the dust sweep
+    /// threshold. Its collateral is burned into the protocol fees of the deposit reserves, to be
+    /// claimed with RedeemFees. The accounts derived from the obligation are closed along with
+    /// it, and all of the rent goes back to the obligation owner.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[writable]` Obligation account - refreshed.
+    ///   1. `[writable]` Obligation owner, receives the rent.
+    ///   2. `[]` Lending market account.
+    ///   3. `[signer]` Lending market owner or risk authority.
+    ///   4. `[]` Dust sweep config account.
+    ///                     Must be a pda with seeds [lending market, "DustSweepConfig"]
+    ///   5. `[]` Derived lending market authority.
+    ///   6. `[]` Token program id.
+    ///   .. For each deposit of the obligation, in order:
+    ///        `[writable]` Collateral deposit reserve account - refreshed.
+    ///        `[writable]` Reserve collateral SPL Token mint.
+    ///        `[writable]` Reserve collateral supply SPL Token account.
+    ///   .. Accounts derived from the obligation in any order, whether they exist or not:
+    ///        `[writable]` Borrow allowances account.
+    ///                     Must be a pda with seeds [obligation, "BorrowAllowances"]
+    ///        `[writable]` Obligation borrow cap account.
+    ///                     Must be a pda with seeds [obligation, "ObligationBorrowCap"]
+    ///        `[writable]` Deleverage credit account.
+    ///                     Must be a pda with seeds [obligation, "DeleverageCredit"]
+    SweepDustObligation,
+
+    // 46
+    /// Cap the borrowed value of an obligation below what its collateral allows. Borrows that
+    /// would take the obligation above the cap fail, existing borrows are left alone.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[writable]` Obligation account.
+    ///   1. `[writable]` Obligation borrow cap account.
+    ///                     Must be a pda with seeds [obligation, "ObligationBorrowCap"]
+    ///   2. `[signer, writable]` Obligation owner, pays for the borrow cap account.
+    ///   3. `[]` System program
+    SetObligationBorrowCap {
+        /// Max borrowed value, in whole units of the quote currency. u64::MAX removes the cap.
+        max_borrowed_value: u64,
+    },
+
+    // 47
+    /// Set the program borrows, withdrawals and liquidations in a lending market call with
+    /// `check_policy` before going through, e.g. to only let KYC'd wallets borrow. Only the
+    /// lending market owner can set it.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[writable]` Lending market account.
+    ///   1. `[writable]` Market policy account.
+    ///                     Must be a pda with seeds [lending market, "MarketPolicy"]
+    ///   2. `[signer, writable]` Lending market owner, pays for the market policy account.
+    ///   3. `[]` System program
+    SetMarketPolicy {
+        /// Program to call, the default pubkey removes the policy
+        policy_program: Pubkey,
+        /// Account passed to the policy program read-only, the default pubkey for none
+        policy_account: Pubkey,
+    },
+
+    // 48
+    /// Start recording the opening interest accrual values of a reserve every `epoch_slots`
+    /// slots, or change the epoch length, which clears the recorded epochs. Only the lending
+    /// market owner can set it.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[]` Reserve account.
+    ///   1. `[writable]` Reserve accrual history account.
+    ///                     Must be a pda with seeds [reserve, "AccrualHistory"]
+    ///   2. `[]` Lending market account.
+    ///   3. `[signer, writable]` Lending market owner, pays for the accrual history account.
+    ///   4. `[]` System program
+    SetReserveAccrualEpoch {
+        /// Length of an epoch in slots
+        epoch_slots: u64,
+    },
+
+    // 49
+    /// Set how much an obligation can withdraw past the lending market outflow limit in a rate
+    /// limiter window by repaying its own debt first. Only the lending market owner can set it.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[]` Lending market account.
+    ///   1. `[writable]` Deleverage config account.
+    ///                     Must be a pda with seeds [lending market, "DeleverageConfig"]
+    ///   2. `[signer, writable]` Lending market owner, pays for the deleverage config account.
+    ///   3. `[]` System program
+    SetDeleverageConfig {
+        /// Max exempt value per obligation and window, in whole units of the quote currency. Zero
+        /// disables the exemption.
+        max_exempt_value: u64,
+    },
+
+    // 50
+    /// Create the deleverage credit account of an obligation, so its repayments can exempt its
+    /// withdrawals from the lending market outflow limit.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[writable]` Obligation account.
+    ///   1. `[writable]` Deleverage credit account.
+    ///                     Must be a pda with seeds [obligation, "DeleverageCredit"]
+    ///   2. `[signer, writable]` Obligation owner, pays for the deleverage credit account.
+    ///   3. `[]` System program
+    InitDeleverageCredit,
+
+    // 51
+    /// Transfer an obligation to a new owner, e.g. to move a position to a new wallet without
+    /// closing it. Both owners have to sign. Obligations with a borrow whose fixed rate is still
+    /// locked, or whose current or new owner is the whitelisted liquidator of the lending market,
+    /// can't change owner.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[writable]` Obligation account.
+    ///   1. `[]` Lending market account.
+    ///   2. `[signer]` Obligation owner.
+    ///   3. `[signer]` New obligation owner.
+    ///   4. `[writable]` Borrow allowances account, whose allowances granted by the
+    ///                     previous owner are all revoked.
+    ///                     Must be a pda with seeds [obligation, "BorrowAllowances"]
+    SetObligationOwner,
+
+    // 52
+    /// Enable or disable flash loans on a reserve and cap how much liquidity a single flash borrow
+    /// can take, independently of the flash loan fee. Only the lending market owner can set it.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[]` Reserve account.
+    ///   1. `[]` Lending market account.
+    ///   2. `[writable]` Flash loan config account.
+    ///                     Must be a pda with seeds [reserve, "FlashLoanConfig"]
+    ///   3. `[signer, writable]` Lending market owner, pays for the flash loan config account.
+    ///   4. `[]` System program
+    SetFlashLoanConfig {
+        /// Whether flash loans are allowed on the reserve
+        flash_loans_enabled: bool,
+        /// Max liquidity a single flash borrow can take, in native units. u64::MAX for no cap
+        max_flash_loan_amount: u64,
+    },
+
+    // 53
+    /// Same as LiquidateObligationAndRedeemReserveCollateral, with the amount to repay given as a
+    /// market value instead of liquidity. The value is capped at MAX_LIQUIDATABLE_VALUE_AT_ONCE
+    /// and converted into repay reserve liquidity at the price the obligation was refreshed with,
+    /// the same price calculate_liquidation values the borrow at, so liquidators spend at most
+    /// that value whatever the price does between quoting and execution. The close factor still
+    /// applies on top. Market policies are asked about a liquidity amount of u64::MAX.
+    ///
+    /// Accounts expected by this instruction: same as
+    /// LiquidateObligationAndRedeemReserveCollateral.
+    LiquidateObligationAndRedeemReserveCollateralByValue {
+        /// Max market value of the liquidity to repay, in quote currency wads
+        max_repay_value_wad: u128,
+    },
+
+    // 54
+    /// Set the health buffer of a lending market, a share of an obligation's allowed borrow value
+    /// that user initiated borrows and withdrawals can't eat into. Max borrows and withdrawals
+    /// stop at the buffer, other amounts that would end up within it fail. Liquidations and the
+    /// unhealthy borrow value are unaffected, and obligations can opt out with
+    /// SetIgnoreHealthBuffer.
+    ///
+    /// Accounts expected by this instruction: same as SetObligationLimits.
+    SetObligationHealthBuffer {
+        /// Health buffer in bps of the allowed borrow value, at most MAX_HEALTH_BUFFER_BPS
+        health_buffer_bps: u16,
+    },
+
+    // 55
+    /// Opt an obligation out of, or back into, the health buffer of its lending market, letting
+    /// borrows and withdrawals go all the way to the allowed borrow value.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[writable]` Obligation account.
+    ///   1. `[signer]` Obligation owner.
+    SetIgnoreHealthBuffer {
+        /// Whether the health buffer is ignored
+        ignore_health_buffer: bool,
+    },
+
+    // 56
+    /// Route the protocol share of a reserve's flash loan fees to a token account of its own
+    /// instead of the reserve fee receiver, e.g. to account for flash loan revenue separately.
+    /// Passing the reserve fee receiver routes them back to it. Only the lending market owner can
+    /// set it.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[]` Reserve account.
+    ///   1. `[]` Lending market account.
+    ///   2. `[writable]` Flash loan config account.
+    ///                     Must be a pda with seeds [reserve, "FlashLoanConfig"]
+    ///   3. `[signer, writable]` Lending market owner, pays for the flash loan config account.
+    ///   4. `[]` System program
+    ///   5. `[]` Flash loan fee receiver, a token account of the reserve liquidity mint.
+    SetFlashLoanFeeReceiver,
+
+    // 57
+    /// Set the interest-free grace period of new borrows from a reserve. A position opened while
+    /// it's set has the interest it accrues over its first grace slots waived out of the
+    /// reserve's protocol fees, as far as they go, and then converts to the variable rate. An
+    /// obligation has at most one interest-free borrow at a time, and opening a position in
+    /// another reserve with a grace period fails until it's repaid or settled after its grace
+    /// period. Only the lending market owner can set it.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[]` Reserve account.
+    ///   1. `[]` Lending market account.
+    ///   2. `[writable]` Borrow grace config account.
+    ///                     Must be a pda with seeds [reserve, "BorrowGraceConfig"]
+    ///   3. `[signer, writable]` Lending market owner, pays for the borrow grace config account.
+    ///   4. `[]` System program
+    SetBorrowGraceConfig {
+        /// Number of slots new borrows have their interest waived for, at most the reserve's
+        /// slots per year. 0 for no grace period
+        grace_slots: u64,
+    },
+
+    // 58
+    /// Allow, or stop allowing, the cTokens of a reserve in another lending market as the
+    /// liquidity of reserves in this one, priced from that source reserve. Sources have to be
+    /// priced by pyth, which reserves priced from a source never are, so sources can't be chained.
+    /// Disallowing a source keeps its existing reserves priced from it, but their config can't be
+    /// updated until they're moved to another oracle. Only the lending market owner can set it.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[]` Lending market account.
+    ///   1. `[writable]` Cross market sources account.
+    ///                     Must be a pda with seeds [lending market, "CrossMarketSources"]
+    ///   2. `[signer, writable]` Lending market owner, pays for the cross market sources account.
+    ///   3. `[]` System program
+    SetCrossMarketSource {
+        /// Reserve of another lending market
+        source_reserve: Pubkey,
+        /// Whether its cTokens are allowed
+        allowed: bool,
+    },
 }
 
 impl LendingInstruction {
@@ -528,7 +1299,12 @@ impl LendingInstruction {
                     _ => return Err(LendingError::InstructionUnpackError.into()),
                 };
 
-                let (risk_authority, _rest) = Self::unpack_pubkey(rest)?;
+                let (risk_authority, rest) = Self::unpack_pubkey(rest)?;
+                let slots_per_year = if rest.is_empty() {
+                    None
+                } else {
+                    Some(Self::unpack_u64(rest)?.0)
+                };
                 Self::SetLendingMarketOwnerAndConfig {
                     new_owner,
                     rate_limiter_config: RateLimiterConfig {
@@ -537,6 +1313,7 @@ impl LendingInstruction {
                     },
                     whitelisted_liquidator,
                     risk_authority,
+                    slots_per_year,
                 }
             }
             2 => {
@@ -561,7 +1338,25 @@ impl LendingInstruction {
                 let (added_borrow_weight_bps, rest) = Self::unpack_u64(rest)?;
                 let (asset_type, rest) = Self::unpack_u8(rest)?;
                 let (max_liquidation_bonus, rest) = Self::unpack_u8(rest)?;
-                let (max_liquidation_threshold, _rest) = Self::unpack_u8(rest)?;
+                let (max_liquidation_threshold, rest) = Self::unpack_u8(rest)?;
+                let (deposit_soft_cap_pct, rest) = Self::unpack_u8(rest)?;
+                let (borrow_soft_cap_pct, rest) = Self::unpack_u8(rest)?;
+                let (idle_liquidity_fee_bps, rest) = Self::unpack_u64(rest)?;
+                let (liquidation_fee_receiver, rest) = match Self::unpack_u8(rest)? {
+                    (0, rest) => (None, rest),
+                    (1, rest) => {
+                        let (pubkey, rest) = Self::unpack_pubkey(rest)?;
+                        (Some(pubkey), rest)
+                    }
+                    _ => return Err(LendingError::InstructionUnpackError.into()),
+                };
+                let (borrow_utilization_cap, rest) = Self::unpack_u8(rest)?;
+                let (fixed_borrow_rate_bps, rest) = Self::unpack_u16(rest)?;
+                let (fixed_borrow_max_duration_slots, rest) = Self::unpack_u64(rest)?;
+                let (withdraw_fee_bps, rest) = Self::unpack_u16(rest)?;
+                let (min_price_bps, rest) = Self::unpack_u32(rest)?;
+                let (max_price_bps, rest) = Self::unpack_u32(rest)?;
+                let (price_bounds_mode, _rest) = Self::unpack_u8(rest)?;
                 Self::InitReserve {
                     liquidity_amount,
                     config: ReserveConfig {
@@ -587,7 +1382,18 @@ impl LendingInstruction {
                         protocol_liquidation_fee,
                         protocol_take_rate,
                         added_borrow_weight_bps,
-                        reserve_type: ReserveType::from_u8(asset_type).unwrap(),
+                        reserve_type: Self::unpack_reserve_type(asset_type)?,
+                        deposit_soft_cap_pct,
+                        borrow_soft_cap_pct,
+                        idle_liquidity_fee_bps,
+                        liquidation_fee_receiver,
+                        borrow_utilization_cap,
+                        fixed_borrow_rate_bps,
+                        fixed_borrow_max_duration_slots,
+                        withdraw_fee_bps,
+                        min_price_bps,
+                        max_price_bps,
+                        price_bounds_mode: Self::unpack_price_bounds_mode(price_bounds_mode)?,
                     },
                 }
             }
@@ -656,6 +1462,24 @@ impl LendingInstruction {
                 let (asset_type, rest) = Self::unpack_u8(rest)?;
                 let (max_liquidation_bonus, rest) = Self::unpack_u8(rest)?;
                 let (max_liquidation_threshold, rest) = Self::unpack_u8(rest)?;
+                let (deposit_soft_cap_pct, rest) = Self::unpack_u8(rest)?;
+                let (borrow_soft_cap_pct, rest) = Self::unpack_u8(rest)?;
+                let (idle_liquidity_fee_bps, rest) = Self::unpack_u64(rest)?;
+                let (liquidation_fee_receiver, rest) = match Self::unpack_u8(rest)? {
+                    (0, rest) => (None, rest),
+                    (1, rest) => {
+                        let (pubkey, rest) = Self::unpack_pubkey(rest)?;
+                        (Some(pubkey), rest)
+                    }
+                    _ => return Err(LendingError::InstructionUnpackError.into()),
+                };
+                let (borrow_utilization_cap, rest) = Self::unpack_u8(rest)?;
+                let (fixed_borrow_rate_bps, rest) = Self::unpack_u16(rest)?;
+                let (fixed_borrow_max_duration_slots, rest) = Self::unpack_u64(rest)?;
+                let (withdraw_fee_bps, rest) = Self::unpack_u16(rest)?;
+                let (min_price_bps, rest) = Self::unpack_u32(rest)?;
+                let (max_price_bps, rest) = Self::unpack_u32(rest)?;
+                let (price_bounds_mode, rest) = Self::unpack_u8(rest)?;
                 let (window_duration, rest) = Self::unpack_u64(rest)?;
                 let (max_outflow, _rest) = Self::unpack_u64(rest)?;
 
@@ -683,7 +1507,18 @@ impl LendingInstruction {
                         protocol_liquidation_fee,
                         protocol_take_rate,
                         added_borrow_weight_bps,
-                        reserve_type: ReserveType::from_u8(asset_type).unwrap(),
+                        reserve_type: Self::unpack_reserve_type(asset_type)?,
+                        deposit_soft_cap_pct,
+                        borrow_soft_cap_pct,
+                        idle_liquidity_fee_bps,
+                        liquidation_fee_receiver,
+                        borrow_utilization_cap,
+                        fixed_borrow_rate_bps,
+                        fixed_borrow_max_duration_slots,
+                        withdraw_fee_bps,
+                        min_price_bps,
+                        max_price_bps,
+                        price_bounds_mode: Self::unpack_price_bounds_mode(price_bounds_mode)?,
                     },
                     rate_limiter_config: RateLimiterConfig {
                         window_duration,
@@ -713,32 +1548,232 @@ impl LendingInstruction {
                 Self::ForgiveDebt { liquidity_amount }
             }
             22 => Self::UpdateMarketMetadata,
-            _ => {
-                msg!("Instruction cannot be unpacked");
-                return Err(LendingError::InstructionUnpackError.into());
+            23 => {
+                let (liquidity_amount, _rest) = Self::unpack_u64(rest)?;
+                Self::ApproveBorrowAllowance { liquidity_amount }
             }
-        })
-    }
-
-    fn unpack_u64(input: &[u8]) -> Result<(u64, &[u8]), ProgramError> {
-        if input.len() < 8 {
-            msg!("u64 cannot be unpacked");
-            return Err(LendingError::InstructionUnpackError.into());
-        }
-        let (bytes, rest) = input.split_at(8);
-        let value = bytes
-            .get(..8)
-            .and_then(|slice| slice.try_into().ok())
-            .map(u64::from_le_bytes)
-            .ok_or(LendingError::InstructionUnpackError)?;
-        Ok((value, rest))
-    }
-
-    fn unpack_u8(input: &[u8]) -> Result<(u8, &[u8]), ProgramError> {
-        if input.is_empty() {
-            msg!("u8 cannot be unpacked");
-            return Err(LendingError::InstructionUnpackError.into());
-        }
+            24 => Self::RevokeBorrowAllowance,
+            25 => {
+                let (liquidity_amount, _rest) = Self::unpack_u64(rest)?;
+                Self::LiquidateObligationForCollateral { liquidity_amount }
+            }
+            26 => Self::CloseObligation,
+            27 => {
+                let (ltv_decay_per_slot_wad, _rest) = Self::unpack_u64(rest)?;
+                Self::WindDownReserve {
+                    ltv_decay_per_slot_wad,
+                }
+            }
+            28 => Self::CloseReserve,
+            29 => {
+                let (window_duration, rest) = Self::unpack_u64(rest)?;
+                let (max_outflow, rest) = Self::unpack_u64(rest)?;
+                let (pause_duration, _rest) = Self::unpack_u64(rest)?;
+                Self::SetLiquidationCircuitBreaker {
+                    config: RateLimiterConfig {
+                        window_duration,
+                        max_outflow,
+                    },
+                    pause_duration,
+                }
+            }
+            30 => {
+                let (max_deposits, rest) = Self::unpack_u8(rest)?;
+                let (max_borrows, _rest) = Self::unpack_u8(rest)?;
+                Self::SetObligationLimits {
+                    max_deposits,
+                    max_borrows,
+                }
+            }
+            31 => {
+                let (delay_slots, _rest) = Self::unpack_u64(rest)?;
+                Self::SetConfigTimelock { delay_slots }
+            }
+            32 => Self::ActivateConfig,
+            33 => Self::CancelConfig,
+            34 => {
+                let (liquidation_priority, _rest) = Self::unpack_u8(rest)?;
+                Self::SetCollateralLiquidationPriority {
+                    liquidation_priority,
+                }
+            }
+            35 => {
+                let (key, rest) = Self::unpack_pubkey(rest)?;
+                let (rebate_bps, _rest) = Self::unpack_u16(rest)?;
+                Self::SetBorrowFeeRebate { key, rebate_bps }
+            }
+            36 => {
+                let (hook_program, rest) = Self::unpack_pubkey(rest)?;
+                let (accounts_hash, _rest) = Self::unpack_bytes32(rest)?;
+                Self::SetFeeRedemptionHook {
+                    hook_program,
+                    accounts_hash: *accounts_hash,
+                }
+            }
+            37 => {
+                let (liquidity_amount, _rest) = Self::unpack_u64(rest)?;
+                Self::BorrowObligationLiquidityFixedRate { liquidity_amount }
+            }
+            38 => Self::InitLiquidationStats,
+            39 => {
+                let (liquidity_amount, rest) = Self::unpack_u64(rest)?;
+                let (mode, _rest) = Self::unpack_u8(rest)?;
+                let mode = match mode {
+                    0 => None,
+                    mode => Some(Self::unpack_deposit_mode(mode - 1)?),
+                };
+                Self::DepositReserveLiquidityWithMode {
+                    liquidity_amount,
+                    mode,
+                }
+            }
+            40 => {
+                let (mode, _rest) = Self::unpack_u8(rest)?;
+                Self::SetObligationDepositMode {
+                    mode: Self::unpack_deposit_mode(mode)?,
+                }
+            }
+            41 => Self::ViewObligationHealth,
+            42 => {
+                let (deposits, rest) = Self::unpack_reserve_amounts(rest)?;
+                let (borrows, _rest) = Self::unpack_reserve_amounts(rest)?;
+                Self::SplitObligation { deposits, borrows }
+            }
+            43 => Self::MergeObligations,
+            44 => {
+                let (max_deposited_value_wad, _rest) = Self::unpack_u64(rest)?;
+                Self::SetDustSweepThreshold {
+                    max_deposited_value_wad,
+                }
+            }
+            45 => Self::SweepDustObligation,
+            46 => {
+                let (max_borrowed_value, _rest) = Self::unpack_u64(rest)?;
+                Self::SetObligationBorrowCap { max_borrowed_value }
+            }
+            47 => {
+                let (policy_program, rest) = Self::unpack_pubkey(rest)?;
+                let (policy_account, _rest) = Self::unpack_pubkey(rest)?;
+                Self::SetMarketPolicy {
+                    policy_program,
+                    policy_account,
+                }
+            }
+            48 => {
+                let (epoch_slots, _rest) = Self::unpack_u64(rest)?;
+                Self::SetReserveAccrualEpoch { epoch_slots }
+            }
+            49 => {
+                let (max_exempt_value, _rest) = Self::unpack_u64(rest)?;
+                Self::SetDeleverageConfig { max_exempt_value }
+            }
+            50 => Self::InitDeleverageCredit,
+            51 => Self::SetObligationOwner,
+            52 => {
+                let (flash_loans_enabled, rest) = Self::unpack_bool(rest)?;
+                let (max_flash_loan_amount, _rest) = Self::unpack_u64(rest)?;
+                Self::SetFlashLoanConfig {
+                    flash_loans_enabled,
+                    max_flash_loan_amount,
+                }
+            }
+            53 => {
+                let (max_repay_value_wad, _rest) = Self::unpack_u128(rest)?;
+                Self::LiquidateObligationAndRedeemReserveCollateralByValue {
+                    max_repay_value_wad,
+                }
+            }
+            54 => {
+                let (health_buffer_bps, _rest) = Self::unpack_u16(rest)?;
+                Self::SetObligationHealthBuffer { health_buffer_bps }
+            }
+            55 => {
+                let (ignore_health_buffer, _rest) = Self::unpack_bool(rest)?;
+                Self::SetIgnoreHealthBuffer {
+                    ignore_health_buffer,
+                }
+            }
+            56 => Self::SetFlashLoanFeeReceiver,
+            57 => {
+                let (grace_slots, _rest) = Self::unpack_u64(rest)?;
+                Self::SetBorrowGraceConfig { grace_slots }
+            }
+            58 => {
+                let (source_reserve, rest) = Self::unpack_pubkey(rest)?;
+                let (allowed, _rest) = Self::unpack_bool(rest)?;
+                Self::SetCrossMarketSource {
+                    source_reserve,
+                    allowed,
+                }
+            }
+            _ => {
+                msg!("Instruction cannot be unpacked");
+                return Err(LendingError::InstructionUnpackError.into());
+            }
+        })
+    }
+
+    fn unpack_u64(input: &[u8]) -> Result<(u64, &[u8]), ProgramError> {
+        if input.len() < 8 {
+            msg!("u64 cannot be unpacked");
+            return Err(LendingError::InstructionUnpackError.into());
+        }
+        let (bytes, rest) = input.split_at(8);
+        let value = bytes
+            .get(..8)
+            .and_then(|slice| slice.try_into().ok())
+            .map(u64::from_le_bytes)
+            .ok_or(LendingError::InstructionUnpackError)?;
+        Ok((value, rest))
+    }
+
+    fn unpack_u128(input: &[u8]) -> Result<(u128, &[u8]), ProgramError> {
+        if input.len() < 16 {
+            msg!("u128 cannot be unpacked");
+            return Err(LendingError::InstructionUnpackError.into());
+        }
+        let (bytes, rest) = input.split_at(16);
+        let value = bytes
+            .get(..16)
+            .and_then(|slice| slice.try_into().ok())
+            .map(u128::from_le_bytes)
+            .ok_or(LendingError::InstructionUnpackError)?;
+        Ok((value, rest))
+    }
+
+    fn unpack_u32(input: &[u8]) -> Result<(u32, &[u8]), ProgramError> {
+        if input.len() < 4 {
+            msg!("u32 cannot be unpacked");
+            return Err(LendingError::InstructionUnpackError.into());
+        }
+        let (bytes, rest) = input.split_at(4);
+        let value = bytes
+            .get(..4)
+            .and_then(|slice| slice.try_into().ok())
+            .map(u32::from_le_bytes)
+            .ok_or(LendingError::InstructionUnpackError)?;
+        Ok((value, rest))
+    }
+
+    fn unpack_u16(input: &[u8]) -> Result<(u16, &[u8]), ProgramError> {
+        if input.len() < 2 {
+            msg!("u16 cannot be unpacked");
+            return Err(LendingError::InstructionUnpackError.into());
+        }
+        let (bytes, rest) = input.split_at(2);
+        let value = bytes
+            .get(..2)
+            .and_then(|slice| slice.try_into().ok())
+            .map(u16::from_le_bytes)
+            .ok_or(LendingError::InstructionUnpackError)?;
+        Ok((value, rest))
+    }
+
+    fn unpack_u8(input: &[u8]) -> Result<(u8, &[u8]), ProgramError> {
+        if input.is_empty() {
+            msg!("u8 cannot be unpacked");
+            return Err(LendingError::InstructionUnpackError.into());
+        }
         let (bytes, rest) = input.split_at(1);
         let value = bytes
             .get(..1)
@@ -748,6 +1783,52 @@ impl LendingInstruction {
         Ok((value, rest))
     }
 
+    fn unpack_deposit_mode(value: u8) -> Result<DepositMode, ProgramError> {
+        DepositMode::from_u8(value).ok_or_else(|| {
+            msg!("Deposit mode is invalid");
+            LendingError::InstructionUnpackError.into()
+        })
+    }
+
+    fn unpack_bool(input: &[u8]) -> Result<(bool, &[u8]), ProgramError> {
+        let (value, rest) = Self::unpack_u8(input)?;
+        match value {
+            0 => Ok((false, rest)),
+            1 => Ok((true, rest)),
+            _ => {
+                msg!("Boolean cannot be unpacked");
+                Err(LendingError::InstructionUnpackError.into())
+            }
+        }
+    }
+
+    fn unpack_reserve_type(value: u8) -> Result<ReserveType, ProgramError> {
+        ReserveType::from_u8(value).ok_or_else(|| {
+            msg!("Reserve type is invalid");
+            LendingError::InstructionUnpackError.into()
+        })
+    }
+
+    fn unpack_price_bounds_mode(value: u8) -> Result<PriceBoundsMode, ProgramError> {
+        PriceBoundsMode::from_u8(value).ok_or_else(|| {
+            msg!("Price bounds mode is invalid");
+            LendingError::InstructionUnpackError.into()
+        })
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn unpack_reserve_amounts(input: &[u8]) -> Result<(Vec<(Pubkey, u64)>, &[u8]), ProgramError> {
+        let (len, mut rest) = Self::unpack_u8(input)?;
+        let mut reserve_amounts = Vec::with_capacity(len as usize);
+        for _ in 0..len {
+            let (reserve, next) = Self::unpack_pubkey(rest)?;
+            let (amount, next) = Self::unpack_u64(next)?;
+            reserve_amounts.push((reserve, amount));
+            rest = next;
+        }
+        Ok((reserve_amounts, rest))
+    }
+
     fn unpack_bytes32(input: &[u8]) -> Result<(&[u8; 32], &[u8]), ProgramError> {
         if input.len() < 32 {
             msg!("32 bytes cannot be unpacked");
@@ -789,6 +1870,7 @@ impl LendingInstruction {
                 rate_limiter_config: config,
                 whitelisted_liquidator,
                 risk_authority,
+                slots_per_year,
             } => {
                 buf.push(1);
                 buf.extend_from_slice(new_owner.as_ref());
@@ -804,6 +1886,9 @@ impl LendingInstruction {
                     }
                 };
                 buf.extend_from_slice(risk_authority.as_ref());
+                if let Some(slots_per_year) = slots_per_year {
+                    buf.extend_from_slice(&slots_per_year.to_le_bytes());
+                }
             }
             Self::InitReserve {
                 liquidity_amount,
@@ -833,6 +1918,17 @@ impl LendingInstruction {
                         protocol_take_rate,
                         added_borrow_weight_bps: borrow_weight_bps,
                         reserve_type: asset_type,
+                        deposit_soft_cap_pct,
+                        borrow_soft_cap_pct,
+                        idle_liquidity_fee_bps,
+                        liquidation_fee_receiver,
+                        borrow_utilization_cap,
+                        fixed_borrow_rate_bps,
+                        fixed_borrow_max_duration_slots,
+                        withdraw_fee_bps,
+                        min_price_bps,
+                        max_price_bps,
+                        price_bounds_mode,
                     },
             } => {
                 buf.push(2);
@@ -858,6 +1954,25 @@ impl LendingInstruction {
                 buf.extend_from_slice(&(asset_type as u8).to_le_bytes());
                 buf.extend_from_slice(&max_liquidation_bonus.to_le_bytes());
                 buf.extend_from_slice(&max_liquidation_threshold.to_le_bytes());
+                buf.extend_from_slice(&deposit_soft_cap_pct.to_le_bytes());
+                buf.extend_from_slice(&borrow_soft_cap_pct.to_le_bytes());
+                buf.extend_from_slice(&idle_liquidity_fee_bps.to_le_bytes());
+                match liquidation_fee_receiver {
+                    Some(receiver) => {
+                        buf.push(1);
+                        buf.extend_from_slice(receiver.as_ref());
+                    }
+                    None => {
+                        buf.push(0);
+                    }
+                };
+                buf.extend_from_slice(&borrow_utilization_cap.to_le_bytes());
+                buf.extend_from_slice(&fixed_borrow_rate_bps.to_le_bytes());
+                buf.extend_from_slice(&fixed_borrow_max_duration_slots.to_le_bytes());
+                buf.extend_from_slice(&withdraw_fee_bps.to_le_bytes());
+                buf.extend_from_slice(&min_price_bps.to_le_bytes());
+                buf.extend_from_slice(&max_price_bps.to_le_bytes());
+                buf.extend_from_slice(&(price_bounds_mode as u8).to_le_bytes());
             }
             Self::RefreshReserve => {
                 buf.push(3);
@@ -934,6 +2049,25 @@ impl LendingInstruction {
                 buf.extend_from_slice(&(config.reserve_type as u8).to_le_bytes());
                 buf.extend_from_slice(&config.max_liquidation_bonus.to_le_bytes());
                 buf.extend_from_slice(&config.max_liquidation_threshold.to_le_bytes());
+                buf.extend_from_slice(&config.deposit_soft_cap_pct.to_le_bytes());
+                buf.extend_from_slice(&config.borrow_soft_cap_pct.to_le_bytes());
+                buf.extend_from_slice(&config.idle_liquidity_fee_bps.to_le_bytes());
+                match config.liquidation_fee_receiver {
+                    Some(receiver) => {
+                        buf.push(1);
+                        buf.extend_from_slice(receiver.as_ref());
+                    }
+                    None => {
+                        buf.push(0);
+                    }
+                };
+                buf.extend_from_slice(&config.borrow_utilization_cap.to_le_bytes());
+                buf.extend_from_slice(&config.fixed_borrow_rate_bps.to_le_bytes());
+                buf.extend_from_slice(&config.fixed_borrow_max_duration_slots.to_le_bytes());
+                buf.extend_from_slice(&config.withdraw_fee_bps.to_le_bytes());
+                buf.extend_from_slice(&config.min_price_bps.to_le_bytes());
+                buf.extend_from_slice(&config.max_price_bps.to_le_bytes());
+                buf.extend_from_slice(&(config.price_bounds_mode as u8).to_le_bytes());
                 buf.extend_from_slice(&rate_limiter_config.window_duration.to_le_bytes());
                 buf.extend_from_slice(&rate_limiter_config.max_outflow.to_le_bytes());
             }
@@ -962,6 +2096,187 @@ impl LendingInstruction {
             }
             // special handling for this instruction, bc the instruction is too big to deserialize
             Self::UpdateMarketMetadata => {}
+            Self::ApproveBorrowAllowance { liquidity_amount } => {
+                buf.push(23);
+                buf.extend_from_slice(&liquidity_amount.to_le_bytes());
+            }
+            Self::RevokeBorrowAllowance => {
+                buf.push(24);
+            }
+            Self::LiquidateObligationForCollateral { liquidity_amount } => {
+                buf.push(25);
+                buf.extend_from_slice(&liquidity_amount.to_le_bytes());
+            }
+            Self::CloseObligation => {
+                buf.push(26);
+            }
+            Self::WindDownReserve {
+                ltv_decay_per_slot_wad,
+            } => {
+                buf.push(27);
+                buf.extend_from_slice(&ltv_decay_per_slot_wad.to_le_bytes());
+            }
+            Self::CloseReserve => {
+                buf.push(28);
+            }
+            Self::SetLiquidationCircuitBreaker {
+                config,
+                pause_duration,
+            } => {
+                buf.push(29);
+                buf.extend_from_slice(&config.window_duration.to_le_bytes());
+                buf.extend_from_slice(&config.max_outflow.to_le_bytes());
+                buf.extend_from_slice(&pause_duration.to_le_bytes());
+            }
+            Self::SetObligationLimits {
+                max_deposits,
+                max_borrows,
+            } => {
+                buf.push(30);
+                buf.extend_from_slice(&max_deposits.to_le_bytes());
+                buf.extend_from_slice(&max_borrows.to_le_bytes());
+            }
+            Self::SetConfigTimelock { delay_slots } => {
+                buf.push(31);
+                buf.extend_from_slice(&delay_slots.to_le_bytes());
+            }
+            Self::ActivateConfig => {
+                buf.push(32);
+            }
+            Self::CancelConfig => {
+                buf.push(33);
+            }
+            Self::SetCollateralLiquidationPriority {
+                liquidation_priority,
+            } => {
+                buf.push(34);
+                buf.extend_from_slice(&liquidation_priority.to_le_bytes());
+            }
+            Self::SetBorrowFeeRebate { key, rebate_bps } => {
+                buf.push(35);
+                buf.extend_from_slice(key.as_ref());
+                buf.extend_from_slice(&rebate_bps.to_le_bytes());
+            }
+            Self::SetFeeRedemptionHook {
+                hook_program,
+                accounts_hash,
+            } => {
+                buf.push(36);
+                buf.extend_from_slice(hook_program.as_ref());
+                buf.extend_from_slice(&accounts_hash);
+            }
+            Self::BorrowObligationLiquidityFixedRate { liquidity_amount } => {
+                buf.push(37);
+                buf.extend_from_slice(&liquidity_amount.to_le_bytes());
+            }
+            Self::InitLiquidationStats => {
+                buf.push(38);
+            }
+            Self::DepositReserveLiquidityWithMode {
+                liquidity_amount,
+                mode,
+            } => {
+                buf.push(39);
+                buf.extend_from_slice(&liquidity_amount.to_le_bytes());
+                buf.push(mode.map_or(0, |mode| mode as u8 + 1));
+            }
+            Self::SetObligationDepositMode { mode } => {
+                buf.push(40);
+                buf.push(mode as u8);
+            }
+            Self::ViewObligationHealth => {
+                buf.push(41);
+            }
+            Self::SplitObligation {
+                ref deposits,
+                ref borrows,
+            } => {
+                buf.push(42);
+                for reserve_amounts in [deposits, borrows] {
+                    buf.push(reserve_amounts.len() as u8);
+                    for (reserve, amount) in reserve_amounts {
+                        buf.extend_from_slice(reserve.as_ref());
+                        buf.extend_from_slice(&amount.to_le_bytes());
+                    }
+                }
+            }
+            Self::MergeObligations => {
+                buf.push(43);
+            }
+            Self::SetDustSweepThreshold {
+                max_deposited_value_wad,
+            } => {
+                buf.push(44);
+                buf.extend_from_slice(&max_deposited_value_wad.to_le_bytes());
+            }
+            Self::SweepDustObligation => {
+                buf.push(45);
+            }
+            Self::SetObligationBorrowCap { max_borrowed_value } => {
+                buf.push(46);
+                buf.extend_from_slice(&max_borrowed_value.to_le_bytes());
+            }
+            Self::SetMarketPolicy {
+                policy_program,
+                policy_account,
+            } => {
+                buf.push(47);
+                buf.extend_from_slice(policy_program.as_ref());
+                buf.extend_from_slice(policy_account.as_ref());
+            }
+            Self::SetReserveAccrualEpoch { epoch_slots } => {
+                buf.push(48);
+                buf.extend_from_slice(&epoch_slots.to_le_bytes());
+            }
+            Self::SetDeleverageConfig { max_exempt_value } => {
+                buf.push(49);
+                buf.extend_from_slice(&max_exempt_value.to_le_bytes());
+            }
+            Self::InitDeleverageCredit => {
+                buf.push(50);
+            }
+            Self::SetObligationOwner => {
+                buf.push(51);
+            }
+            Self::SetFlashLoanConfig {
+                flash_loans_enabled,
+                max_flash_loan_amount,
+            } => {
+                buf.push(52);
+                buf.push(flash_loans_enabled as u8);
+                buf.extend_from_slice(&max_flash_loan_amount.to_le_bytes());
+            }
+            Self::LiquidateObligationAndRedeemReserveCollateralByValue {
+                max_repay_value_wad,
+            } => {
+                buf.push(53);
+                buf.extend_from_slice(&max_repay_value_wad.to_le_bytes());
+            }
+            Self::SetObligationHealthBuffer { health_buffer_bps } => {
+                buf.push(54);
+                buf.extend_from_slice(&health_buffer_bps.to_le_bytes());
+            }
+            Self::SetIgnoreHealthBuffer {
+                ignore_health_buffer,
+            } => {
+                buf.push(55);
+                buf.push(ignore_health_buffer as u8);
+            }
+            Self::SetFlashLoanFeeReceiver => {
+                buf.push(56);
+            }
+            Self::SetBorrowGraceConfig { grace_slots } => {
+                buf.push(57);
+                buf.extend_from_slice(&grace_slots.to_le_bytes());
+            }
+            Self::SetCrossMarketSource {
+                source_reserve,
+                allowed,
+            } => {
+                buf.push(58);
+                buf.extend_from_slice(source_reserve.as_ref());
+                buf.push(allowed as u8);
+            }
         }
         buf
     }
@@ -994,6 +2309,7 @@ pub fn init_lending_market(
 }
 
 /// Creates a 'SetLendingMarketOwner' instruction.
+#[allow(clippy::too_many_arguments)]
 pub fn set_lending_market_owner_and_config(
     program_id: Pubkey,
     lending_market_pubkey: Pubkey,
@@ -1003,22 +2319,63 @@ pub fn set_lending_market_owner_and_config(
     whitelisted_liquidator: Option<Pubkey>,
     risk_authority: Pubkey,
 ) -> Instruction {
+    let (config_timelock_pubkey, _bump_seed) =
+        find_config_timelock_address(&program_id, &lending_market_pubkey);
+    let (pending_config_pubkey, _bump_seed) =
+        find_pending_config_address(&program_id, &lending_market_pubkey);
     Instruction {
         program_id,
         accounts: vec![
             AccountMeta::new(lending_market_pubkey, false),
-            AccountMeta::new_readonly(lending_market_owner, true),
+            AccountMeta::new(lending_market_owner, true),
+            AccountMeta::new_readonly(config_timelock_pubkey, false),
+            AccountMeta::new(pending_config_pubkey, false),
+            AccountMeta::new_readonly(system_program::id(), false),
         ],
         data: LendingInstruction::SetLendingMarketOwnerAndConfig {
             new_owner,
             rate_limiter_config,
             whitelisted_liquidator,
             risk_authority,
+            slots_per_year: None,
         }
         .pack(),
     }
 }
 
+/// Creates a 'SetLendingMarketOwner' instruction that also sets the slots per year of the lending
+/// market.
+#[allow(clippy::too_many_arguments)]
+pub fn set_lending_market_owner_and_config_with_slots_per_year(
+    program_id: Pubkey,
+    lending_market_pubkey: Pubkey,
+    lending_market_owner: Pubkey,
+    new_owner: Pubkey,
+    rate_limiter_config: RateLimiterConfig,
+    whitelisted_liquidator: Option<Pubkey>,
+    risk_authority: Pubkey,
+    slots_per_year: u64,
+) -> Instruction {
+    let mut instruction = set_lending_market_owner_and_config(
+        program_id,
+        lending_market_pubkey,
+        lending_market_owner,
+        new_owner,
+        rate_limiter_config,
+        whitelisted_liquidator,
+        risk_authority,
+    );
+    instruction.data = LendingInstruction::SetLendingMarketOwnerAndConfig {
+        new_owner,
+        rate_limiter_config,
+        whitelisted_liquidator,
+        risk_authority,
+        slots_per_year: Some(slots_per_year),
+    }
+    .pack();
+    instruction
+}
+
 /// Creates an 'InitReserve' instruction.
 #[allow(clippy::too_many_arguments)]
 pub fn init_reserve(
@@ -1043,6 +2400,8 @@ pub fn init_reserve(
         &[&lending_market_pubkey.to_bytes()[..PUBKEY_BYTES]],
         &program_id,
     );
+    let (cross_market_sources_pubkey, _bump_seed) =
+        find_cross_market_sources_address(&program_id, &lending_market_pubkey);
     let accounts = vec![
         AccountMeta::new(source_liquidity_pubkey, false),
         AccountMeta::new(destination_collateral_pubkey, false),
@@ -1061,6 +2420,7 @@ pub fn init_reserve(
         AccountMeta::new_readonly(user_transfer_authority_pubkey, true),
         AccountMeta::new_readonly(sysvar::rent::id(), false),
         AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new_readonly(cross_market_sources_pubkey, false),
     ];
     Instruction {
         program_id,
@@ -1092,6 +2452,49 @@ pub fn refresh_reserve(
     }
 }
 
+/// Creates a `RefreshReserve` instruction that accrues interest at the slots per year of the
+/// lending market instead of the value the reserve last accrued at
+pub fn refresh_reserve_with_lending_market(
+    program_id: Pubkey,
+    reserve_pubkey: Pubkey,
+    reserve_liquidity_pyth_oracle_pubkey: Pubkey,
+    reserve_liquidity_switchboard_oracle_pubkey: Pubkey,
+    lending_market_pubkey: Pubkey,
+) -> Instruction {
+    let mut instruction = refresh_reserve(
+        program_id,
+        reserve_pubkey,
+        reserve_liquidity_pyth_oracle_pubkey,
+        reserve_liquidity_switchboard_oracle_pubkey,
+    );
+    instruction
+        .accounts
+        .push(AccountMeta::new_readonly(lending_market_pubkey, false));
+    instruction
+}
+
+/// Creates a `RefreshReserve` instruction that also records into the accrual history of the
+/// reserve
+pub fn refresh_reserve_with_accrual_history(
+    program_id: Pubkey,
+    reserve_pubkey: Pubkey,
+    reserve_liquidity_pyth_oracle_pubkey: Pubkey,
+    reserve_liquidity_switchboard_oracle_pubkey: Pubkey,
+) -> Instruction {
+    let mut instruction = refresh_reserve(
+        program_id,
+        reserve_pubkey,
+        reserve_liquidity_pyth_oracle_pubkey,
+        reserve_liquidity_switchboard_oracle_pubkey,
+    );
+    let (accrual_history_pubkey, _bump_seed) =
+        find_reserve_accrual_history_address(&program_id, &reserve_pubkey);
+    instruction
+        .accounts
+        .push(AccountMeta::new(accrual_history_pubkey, false));
+    instruction
+}
+
 /// Creates a 'DepositReserveLiquidity' instruction.
 #[allow(clippy::too_many_arguments)]
 pub fn deposit_reserve_liquidity(
@@ -1160,6 +2563,39 @@ pub fn redeem_reserve_collateral(
     }
 }
 
+/// Creates a 'RedeemReserveCollateral' instruction passing the reserve liquidity fee receiver,
+/// required if the reserve charges a withdraw fee
+#[allow(clippy::too_many_arguments)]
+pub fn redeem_reserve_collateral_with_fee_receiver(
+    program_id: Pubkey,
+    collateral_amount: u64,
+    source_collateral_pubkey: Pubkey,
+    destination_liquidity_pubkey: Pubkey,
+    reserve_pubkey: Pubkey,
+    reserve_collateral_mint_pubkey: Pubkey,
+    reserve_liquidity_supply_pubkey: Pubkey,
+    lending_market_pubkey: Pubkey,
+    user_transfer_authority_pubkey: Pubkey,
+    reserve_liquidity_fee_receiver_pubkey: Pubkey,
+) -> Instruction {
+    let mut instruction = redeem_reserve_collateral(
+        program_id,
+        collateral_amount,
+        source_collateral_pubkey,
+        destination_liquidity_pubkey,
+        reserve_pubkey,
+        reserve_collateral_mint_pubkey,
+        reserve_liquidity_supply_pubkey,
+        lending_market_pubkey,
+        user_transfer_authority_pubkey,
+    );
+    instruction.accounts.push(AccountMeta::new(
+        reserve_liquidity_fee_receiver_pubkey,
+        false,
+    ));
+    instruction
+}
+
 /// Creates an 'InitObligation' instruction.
 #[allow(clippy::too_many_arguments)]
 pub fn init_obligation(
@@ -1181,6 +2617,33 @@ pub fn init_obligation(
     }
 }
 
+/// Creates the instructions to allocate and initialize an obligation whose rent is paid by
+/// `payer_pubkey`, which can be different from the owner. The payer, the obligation account and
+/// the owner must all sign.
+pub fn create_and_init_obligation(
+    program_id: Pubkey,
+    payer_pubkey: Pubkey,
+    obligation_pubkey: Pubkey,
+    lending_market_pubkey: Pubkey,
+    obligation_owner_pubkey: Pubkey,
+) -> Vec<Instruction> {
+    vec![
+        system_instruction::create_account(
+            &payer_pubkey,
+            &obligation_pubkey,
+            Rent::default().minimum_balance(Obligation::LEN),
+            Obligation::LEN as u64,
+            &program_id,
+        ),
+        init_obligation(
+            program_id,
+            obligation_pubkey,
+            lending_market_pubkey,
+            obligation_owner_pubkey,
+        ),
+    ]
+}
+
 /// Creates a 'RefreshObligation' instruction.
 #[allow(clippy::too_many_arguments)]
 pub fn refresh_obligation(
@@ -1214,6 +2677,8 @@ pub fn deposit_obligation_collateral(
     obligation_owner_pubkey: Pubkey,
     user_transfer_authority_pubkey: Pubkey,
 ) -> Instruction {
+    let (obligation_limits_pubkey, _bump_seed) =
+        find_obligation_limits_address(&program_id, &lending_market_pubkey);
     Instruction {
         program_id,
         accounts: vec![
@@ -1225,6 +2690,7 @@ pub fn deposit_obligation_collateral(
             AccountMeta::new_readonly(obligation_owner_pubkey, true),
             AccountMeta::new_readonly(user_transfer_authority_pubkey, true),
             AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(obligation_limits_pubkey, false),
         ],
         data: LendingInstruction::DepositObligationCollateral { collateral_amount }.pack(),
     }
@@ -1252,6 +2718,8 @@ pub fn deposit_reserve_liquidity_and_obligation_collateral(
         &[&lending_market_pubkey.to_bytes()[..PUBKEY_BYTES]],
         &program_id,
     );
+    let (obligation_limits_pubkey, _bump_seed) =
+        find_obligation_limits_address(&program_id, &lending_market_pubkey);
     Instruction {
         program_id,
         accounts: vec![
@@ -1269,6 +2737,7 @@ pub fn deposit_reserve_liquidity_and_obligation_collateral(
             AccountMeta::new_readonly(reserve_liquidity_switchboard_oracle_pubkey, false),
             AccountMeta::new_readonly(user_transfer_authority_pubkey, true),
             AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(obligation_limits_pubkey, false),
         ],
         data: LendingInstruction::DepositReserveLiquidityAndObligationCollateral {
             liquidity_amount,
@@ -1277,15 +2746,65 @@ pub fn deposit_reserve_liquidity_and_obligation_collateral(
     }
 }
 
-/// Creates a 'WithdrawObligationCollateralAndRedeemReserveCollateral' instruction.
+/// Creates a 'DepositReserveLiquidityWithMode' instruction. With `mode` None the obligation's
+/// default deposit mode decides whether the collateral goes into the obligation.
 #[allow(clippy::too_many_arguments)]
-pub fn withdraw_obligation_collateral_and_redeem_reserve_collateral(
+pub fn deposit_reserve_liquidity_with_mode(
     program_id: Pubkey,
-    collateral_amount: u64,
-    source_collateral_pubkey: Pubkey,
-    destination_collateral_pubkey: Pubkey,
-    withdraw_reserve_pubkey: Pubkey,
-    obligation_pubkey: Pubkey,
+    liquidity_amount: u64,
+    mode: Option<DepositMode>,
+    source_liquidity_pubkey: Pubkey,
+    user_collateral_pubkey: Pubkey,
+    reserve_pubkey: Pubkey,
+    reserve_liquidity_supply_pubkey: Pubkey,
+    reserve_collateral_mint_pubkey: Pubkey,
+    lending_market_pubkey: Pubkey,
+    destination_deposit_collateral_pubkey: Pubkey,
+    obligation_pubkey: Pubkey,
+    obligation_owner_pubkey: Pubkey,
+    reserve_liquidity_pyth_oracle_pubkey: Pubkey,
+    reserve_liquidity_switchboard_oracle_pubkey: Pubkey,
+    user_transfer_authority_pubkey: Pubkey,
+) -> Instruction {
+    let mut instruction = deposit_reserve_liquidity_and_obligation_collateral(
+        program_id,
+        liquidity_amount,
+        source_liquidity_pubkey,
+        user_collateral_pubkey,
+        reserve_pubkey,
+        reserve_liquidity_supply_pubkey,
+        reserve_collateral_mint_pubkey,
+        lending_market_pubkey,
+        destination_deposit_collateral_pubkey,
+        obligation_pubkey,
+        obligation_owner_pubkey,
+        reserve_liquidity_pyth_oracle_pubkey,
+        reserve_liquidity_switchboard_oracle_pubkey,
+        user_transfer_authority_pubkey,
+    );
+    // the owner doesn't sign deposits that are known to stay out of the obligation
+    if mode == Some(DepositMode::Hold) {
+        instruction.accounts[9].is_signer = false;
+    }
+    instruction.data = LendingInstruction::DepositReserveLiquidityWithMode {
+        liquidity_amount,
+        mode,
+    }
+    .pack();
+    instruction
+}
+
+/// Creates a 'WithdrawObligationCollateralAndRedeemReserveCollateral' instruction. The redeemed
+/// liquidity is sent to `destination_liquidity_pubkey`, which can be owned by someone other than
+/// the obligation owner.
+#[allow(clippy::too_many_arguments)]
+pub fn withdraw_obligation_collateral_and_redeem_reserve_collateral(
+    program_id: Pubkey,
+    collateral_amount: u64,
+    source_collateral_pubkey: Pubkey,
+    destination_collateral_pubkey: Pubkey,
+    withdraw_reserve_pubkey: Pubkey,
+    obligation_pubkey: Pubkey,
     lending_market_pubkey: Pubkey,
     destination_liquidity_pubkey: Pubkey,
     reserve_collateral_mint_pubkey: Pubkey,
@@ -1297,22 +2816,29 @@ pub fn withdraw_obligation_collateral_and_redeem_reserve_collateral(
         &[&lending_market_pubkey.to_bytes()[..PUBKEY_BYTES]],
         &program_id,
     );
+    let (obligation_limits_pubkey, _bump_seed) =
+        find_obligation_limits_address(&program_id, &lending_market_pubkey);
+    let (market_policy_pubkey, _bump_seed) =
+        find_market_policy_address(&program_id, &lending_market_pubkey);
+    let mut accounts = vec![
+        AccountMeta::new(source_collateral_pubkey, false),
+        AccountMeta::new(destination_collateral_pubkey, false),
+        AccountMeta::new(withdraw_reserve_pubkey, false),
+        AccountMeta::new(obligation_pubkey, false),
+        AccountMeta::new(lending_market_pubkey, false),
+        AccountMeta::new_readonly(lending_market_authority_pubkey, false),
+        AccountMeta::new(destination_liquidity_pubkey, false),
+        AccountMeta::new(reserve_collateral_mint_pubkey, false),
+        AccountMeta::new(reserve_liquidity_supply_pubkey, false),
+        AccountMeta::new_readonly(obligation_owner_pubkey, true),
+        AccountMeta::new_readonly(user_transfer_authority_pubkey, true),
+        AccountMeta::new_readonly(spl_token::id(), false),
+    ];
+    accounts.push(AccountMeta::new_readonly(obligation_limits_pubkey, false));
+    accounts.push(AccountMeta::new_readonly(market_policy_pubkey, false));
     Instruction {
         program_id,
-        accounts: vec![
-            AccountMeta::new(source_collateral_pubkey, false),
-            AccountMeta::new(destination_collateral_pubkey, false),
-            AccountMeta::new(withdraw_reserve_pubkey, false),
-            AccountMeta::new(obligation_pubkey, false),
-            AccountMeta::new(lending_market_pubkey, false),
-            AccountMeta::new_readonly(lending_market_authority_pubkey, false),
-            AccountMeta::new(destination_liquidity_pubkey, false),
-            AccountMeta::new(reserve_collateral_mint_pubkey, false),
-            AccountMeta::new(reserve_liquidity_supply_pubkey, false),
-            AccountMeta::new_readonly(obligation_owner_pubkey, true),
-            AccountMeta::new_readonly(user_transfer_authority_pubkey, true),
-            AccountMeta::new_readonly(spl_token::id(), false),
-        ],
+        accounts,
         data: LendingInstruction::WithdrawObligationCollateralAndRedeemReserveCollateral {
             collateral_amount,
         }
@@ -1320,6 +2846,55 @@ pub fn withdraw_obligation_collateral_and_redeem_reserve_collateral(
     }
 }
 
+/// Creates a 'WithdrawObligationCollateralAndRedeemReserveCollateral' instruction passing the
+/// reserve liquidity fee receiver, required if the reserve charges a withdraw fee
+#[allow(clippy::too_many_arguments)]
+pub fn withdraw_obligation_collateral_and_redeem_reserve_collateral_with_fee_receiver(
+    program_id: Pubkey,
+    collateral_amount: u64,
+    source_collateral_pubkey: Pubkey,
+    destination_collateral_pubkey: Pubkey,
+    withdraw_reserve_pubkey: Pubkey,
+    obligation_pubkey: Pubkey,
+    lending_market_pubkey: Pubkey,
+    destination_liquidity_pubkey: Pubkey,
+    reserve_collateral_mint_pubkey: Pubkey,
+    reserve_liquidity_supply_pubkey: Pubkey,
+    obligation_owner_pubkey: Pubkey,
+    user_transfer_authority_pubkey: Pubkey,
+    reserve_liquidity_fee_receiver_pubkey: Pubkey,
+) -> Instruction {
+    let mut instruction = withdraw_obligation_collateral_and_redeem_reserve_collateral(
+        program_id,
+        collateral_amount,
+        source_collateral_pubkey,
+        destination_collateral_pubkey,
+        withdraw_reserve_pubkey,
+        obligation_pubkey,
+        lending_market_pubkey,
+        destination_liquidity_pubkey,
+        reserve_collateral_mint_pubkey,
+        reserve_liquidity_supply_pubkey,
+        obligation_owner_pubkey,
+        user_transfer_authority_pubkey,
+    );
+    insert_withdraw_fee_receiver(&mut instruction, reserve_liquidity_fee_receiver_pubkey);
+    instruction
+}
+
+/// Inserts the reserve liquidity fee receiver into a
+/// `WithdrawObligationCollateralAndRedeemReserveCollateral` instruction, as the first of the
+/// optional accounts right after the token program
+pub(crate) fn insert_withdraw_fee_receiver(
+    instruction: &mut Instruction,
+    reserve_liquidity_fee_receiver_pubkey: Pubkey,
+) {
+    instruction.accounts.insert(
+        12,
+        AccountMeta::new(reserve_liquidity_fee_receiver_pubkey, false),
+    );
+}
+
 /// Creates a 'WithdrawObligationCollateral' instruction.
 #[allow(clippy::too_many_arguments)]
 pub fn withdraw_obligation_collateral(
@@ -1336,6 +2911,10 @@ pub fn withdraw_obligation_collateral(
         &[&lending_market_pubkey.to_bytes()[..PUBKEY_BYTES]],
         &program_id,
     );
+    let (obligation_limits_pubkey, _bump_seed) =
+        find_obligation_limits_address(&program_id, &lending_market_pubkey);
+    let (market_policy_pubkey, _bump_seed) =
+        find_market_policy_address(&program_id, &lending_market_pubkey);
     Instruction {
         program_id,
         accounts: vec![
@@ -1347,12 +2926,15 @@ pub fn withdraw_obligation_collateral(
             AccountMeta::new_readonly(lending_market_authority_pubkey, false),
             AccountMeta::new_readonly(obligation_owner_pubkey, true),
             AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(obligation_limits_pubkey, false),
+            AccountMeta::new_readonly(market_policy_pubkey, false),
         ],
         data: LendingInstruction::WithdrawObligationCollateral { collateral_amount }.pack(),
     }
 }
 
-/// Creates a 'BorrowObligationLiquidity' instruction.
+/// Creates a 'BorrowObligationLiquidity' instruction. Off chain, `host_fee::get_host_fee_receiver`
+/// resolves the host fee receiver out of a host wallet.
 #[allow(clippy::too_many_arguments)]
 pub fn borrow_obligation_liquidity(
     program_id: Pubkey,
@@ -1370,6 +2952,16 @@ pub fn borrow_obligation_liquidity(
         &[&lending_market_pubkey.to_bytes()[..PUBKEY_BYTES]],
         &program_id,
     );
+    let (obligation_limits_pubkey, _bump_seed) =
+        find_obligation_limits_address(&program_id, &lending_market_pubkey);
+    let (obligation_borrow_cap_pubkey, _bump_seed) =
+        find_obligation_borrow_cap_address(&program_id, &obligation_pubkey);
+    let (market_policy_pubkey, _bump_seed) =
+        find_market_policy_address(&program_id, &lending_market_pubkey);
+    let (borrow_grace_config_pubkey, _bump_seed) =
+        find_borrow_grace_config_address(&program_id, &borrow_reserve_pubkey);
+    let (deleverage_credit_pubkey, _bump_seed) =
+        find_deleverage_credit_address(&program_id, &obligation_pubkey);
     let mut accounts = vec![
         AccountMeta::new(source_liquidity_pubkey, false),
         AccountMeta::new(destination_liquidity_pubkey, false),
@@ -1384,6 +2976,13 @@ pub fn borrow_obligation_liquidity(
     if let Some(host_fee_receiver_pubkey) = host_fee_receiver_pubkey {
         accounts.push(AccountMeta::new(host_fee_receiver_pubkey, false));
     }
+    accounts.extend([
+        AccountMeta::new_readonly(obligation_limits_pubkey, false),
+        AccountMeta::new_readonly(obligation_borrow_cap_pubkey, false),
+        AccountMeta::new_readonly(market_policy_pubkey, false),
+        AccountMeta::new_readonly(borrow_grace_config_pubkey, false),
+        AccountMeta::new(deleverage_credit_pubkey, false),
+    ]);
     Instruction {
         program_id,
         accounts,
@@ -1391,6 +2990,108 @@ pub fn borrow_obligation_liquidity(
     }
 }
 
+/// Creates a 'BorrowObligationLiquidityFixedRate' instruction.
+#[allow(clippy::too_many_arguments)]
+pub fn borrow_obligation_liquidity_fixed_rate(
+    program_id: Pubkey,
+    liquidity_amount: u64,
+    source_liquidity_pubkey: Pubkey,
+    destination_liquidity_pubkey: Pubkey,
+    borrow_reserve_pubkey: Pubkey,
+    borrow_reserve_liquidity_fee_receiver_pubkey: Pubkey,
+    obligation_pubkey: Pubkey,
+    lending_market_pubkey: Pubkey,
+    obligation_owner_pubkey: Pubkey,
+    host_fee_receiver_pubkey: Option<Pubkey>,
+) -> Instruction {
+    Instruction {
+        data: LendingInstruction::BorrowObligationLiquidityFixedRate { liquidity_amount }.pack(),
+        ..borrow_obligation_liquidity(
+            program_id,
+            liquidity_amount,
+            source_liquidity_pubkey,
+            destination_liquidity_pubkey,
+            borrow_reserve_pubkey,
+            borrow_reserve_liquidity_fee_receiver_pubkey,
+            obligation_pubkey,
+            lending_market_pubkey,
+            obligation_owner_pubkey,
+            host_fee_receiver_pubkey,
+        )
+    }
+}
+
+/// Creates a 'BorrowObligationLiquidity' instruction signed by a delegate, drawing on the
+/// delegate's borrow allowance instead of requiring the obligation owner's signature.
+#[allow(clippy::too_many_arguments)]
+pub fn delegated_borrow_obligation_liquidity(
+    program_id: Pubkey,
+    liquidity_amount: u64,
+    source_liquidity_pubkey: Pubkey,
+    destination_liquidity_pubkey: Pubkey,
+    borrow_reserve_pubkey: Pubkey,
+    borrow_reserve_liquidity_fee_receiver_pubkey: Pubkey,
+    obligation_pubkey: Pubkey,
+    lending_market_pubkey: Pubkey,
+    delegate_pubkey: Pubkey,
+    host_fee_receiver_pubkey: Option<Pubkey>,
+) -> Instruction {
+    let mut instruction = borrow_obligation_liquidity(
+        program_id,
+        liquidity_amount,
+        source_liquidity_pubkey,
+        destination_liquidity_pubkey,
+        borrow_reserve_pubkey,
+        borrow_reserve_liquidity_fee_receiver_pubkey,
+        obligation_pubkey,
+        lending_market_pubkey,
+        delegate_pubkey,
+        host_fee_receiver_pubkey,
+    );
+    let (borrow_allowances_pubkey, _bump_seed) =
+        find_borrow_allowances_address(&program_id, &obligation_pubkey);
+    instruction
+        .accounts
+        .push(AccountMeta::new(borrow_allowances_pubkey, false));
+    instruction
+}
+
+/// Creates a 'BorrowObligationLiquidity' instruction that claims the lending market's borrow fee
+/// rebate for the calling program or the obligation owner, if any.
+#[allow(clippy::too_many_arguments)]
+pub fn rebated_borrow_obligation_liquidity(
+    program_id: Pubkey,
+    liquidity_amount: u64,
+    source_liquidity_pubkey: Pubkey,
+    destination_liquidity_pubkey: Pubkey,
+    borrow_reserve_pubkey: Pubkey,
+    borrow_reserve_liquidity_fee_receiver_pubkey: Pubkey,
+    obligation_pubkey: Pubkey,
+    lending_market_pubkey: Pubkey,
+    obligation_owner_pubkey: Pubkey,
+    host_fee_receiver_pubkey: Option<Pubkey>,
+) -> Instruction {
+    let mut instruction = borrow_obligation_liquidity(
+        program_id,
+        liquidity_amount,
+        source_liquidity_pubkey,
+        destination_liquidity_pubkey,
+        borrow_reserve_pubkey,
+        borrow_reserve_liquidity_fee_receiver_pubkey,
+        obligation_pubkey,
+        lending_market_pubkey,
+        obligation_owner_pubkey,
+        host_fee_receiver_pubkey,
+    );
+    let (fee_rebates_pubkey, _bump_seed) =
+        find_fee_rebates_address(&program_id, &lending_market_pubkey);
+    instruction.accounts.extend([
+        AccountMeta::new_readonly(sysvar::instructions::id(), false),
+        AccountMeta::new_readonly(fee_rebates_pubkey, false),
+    ]);
+    instruction
+}
+
 /// Creates a `RepayObligationLiquidity` instruction
 #[allow(clippy::too_many_arguments)]
 pub fn repay_obligation_liquidity(
@@ -1473,14 +3174,24 @@ pub fn update_reserve_config(
         &[&lending_market_pubkey.to_bytes()[..PUBKEY_BYTES]],
         &program_id,
     );
+    let (config_timelock_pubkey, _bump_seed) =
+        find_config_timelock_address(&program_id, &lending_market_pubkey);
+    let (pending_config_pubkey, _bump_seed) =
+        find_pending_config_address(&program_id, &reserve_pubkey);
+    let (cross_market_sources_pubkey, _bump_seed) =
+        find_cross_market_sources_address(&program_id, &lending_market_pubkey);
     let accounts = vec![
         AccountMeta::new(reserve_pubkey, false),
         AccountMeta::new_readonly(lending_market_pubkey, false),
         AccountMeta::new_readonly(lending_market_authority_pubkey, false),
-        AccountMeta::new_readonly(lending_market_owner_pubkey, true),
+        AccountMeta::new(lending_market_owner_pubkey, true),
         AccountMeta::new_readonly(pyth_product_pubkey, false),
         AccountMeta::new_readonly(pyth_price_pubkey, false),
         AccountMeta::new_readonly(switchboard_feed_pubkey, false),
+        AccountMeta::new_readonly(config_timelock_pubkey, false),
+        AccountMeta::new(pending_config_pubkey, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(cross_market_sources_pubkey, false),
     ];
     Instruction {
         program_id,
@@ -1516,6 +3227,12 @@ pub fn liquidate_obligation_and_redeem_reserve_collateral(
         &[&lending_market_pubkey.to_bytes()[..PUBKEY_BYTES]],
         &program_id,
     );
+    let (liquidation_circuit_breaker_pubkey, _bump_seed) =
+        find_liquidation_circuit_breaker_address(&program_id, &lending_market_pubkey);
+    let (liquidation_stats_pubkey, _bump_seed) =
+        find_liquidation_stats_address(&program_id, &lending_market_pubkey);
+    let (market_policy_pubkey, _bump_seed) =
+        find_market_policy_address(&program_id, &lending_market_pubkey);
     Instruction {
         program_id,
         accounts: vec![
@@ -1534,6 +3251,9 @@ pub fn liquidate_obligation_and_redeem_reserve_collateral(
             AccountMeta::new_readonly(lending_market_authority_pubkey, false),
             AccountMeta::new_readonly(user_transfer_authority_pubkey, true),
             AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new(liquidation_circuit_breaker_pubkey, false),
+            AccountMeta::new_readonly(market_policy_pubkey, false),
+            AccountMeta::new(liquidation_stats_pubkey, false),
         ],
         data: LendingInstruction::LiquidateObligationAndRedeemReserveCollateral {
             liquidity_amount,
@@ -1542,6 +3262,65 @@ pub fn liquidate_obligation_and_redeem_reserve_collateral(
     }
 }
 
+/// Creates a `LiquidateObligationAndRedeemReserveCollateralByValue` instruction, repaying at
+/// most `max_repay_value` worth of the borrow
+#[allow(clippy::too_many_arguments)]
+pub fn liquidate_obligation_and_redeem_reserve_collateral_by_value(
+    program_id: Pubkey,
+    max_repay_value: Decimal,
+    source_liquidity_pubkey: Pubkey,
+    destination_collateral_pubkey: Pubkey,
+    destination_liquidity_pubkey: Pubkey,
+    repay_reserve_pubkey: Pubkey,
+    repay_reserve_liquidity_supply_pubkey: Pubkey,
+    withdraw_reserve_pubkey: Pubkey,
+    withdraw_reserve_collateral_mint_pubkey: Pubkey,
+    withdraw_reserve_collateral_supply_pubkey: Pubkey,
+    withdraw_reserve_liquidity_supply_pubkey: Pubkey,
+    withdraw_reserve_liquidity_fee_receiver_pubkey: Pubkey,
+    obligation_pubkey: Pubkey,
+    lending_market_pubkey: Pubkey,
+    user_transfer_authority_pubkey: Pubkey,
+) -> Instruction {
+    let mut instruction = liquidate_obligation_and_redeem_reserve_collateral(
+        program_id,
+        u64::MAX,
+        source_liquidity_pubkey,
+        destination_collateral_pubkey,
+        destination_liquidity_pubkey,
+        repay_reserve_pubkey,
+        repay_reserve_liquidity_supply_pubkey,
+        withdraw_reserve_pubkey,
+        withdraw_reserve_collateral_mint_pubkey,
+        withdraw_reserve_collateral_supply_pubkey,
+        withdraw_reserve_liquidity_supply_pubkey,
+        withdraw_reserve_liquidity_fee_receiver_pubkey,
+        obligation_pubkey,
+        lending_market_pubkey,
+        user_transfer_authority_pubkey,
+    );
+    instruction.data = LendingInstruction::LiquidateObligationAndRedeemReserveCollateralByValue {
+        max_repay_value_wad: max_repay_value.to_scaled_val().unwrap_or(u128::MAX),
+    }
+    .pack();
+    instruction
+}
+
+/// Appends the obligation owner and the accounts derived from the obligation to a
+/// `LiquidateObligationAndRedeemReserveCollateral` instruction, so an obligation the liquidation
+/// empties is closed and its rent refunded to the owner
+pub fn append_auto_close_accounts(
+    instruction: &mut Instruction,
+    obligation_pubkey: Pubkey,
+    obligation_owner_pubkey: Pubkey,
+) {
+    instruction
+        .accounts
+        .push(AccountMeta::new(obligation_owner_pubkey, false));
+    let derived_accounts = obligation_derived_accounts(&instruction.program_id, &obligation_pubkey);
+    instruction.accounts.extend(derived_accounts);
+}
+
 /// Creates a `RedeemFees` instruction
 pub fn redeem_fees(
     program_id: Pubkey,
@@ -1569,6 +3348,34 @@ pub fn redeem_fees(
     }
 }
 
+/// Creates a `RedeemFees` instruction for a reserve with a fee redemption hook. The forwarded
+/// accounts have to hash to the hook's accounts hash.
+pub fn redeem_fees_with_hook(
+    program_id: Pubkey,
+    reserve_pubkey: Pubkey,
+    reserve_liquidity_fee_receiver_pubkey: Pubkey,
+    reserve_supply_liquidity_pubkey: Pubkey,
+    lending_market_pubkey: Pubkey,
+    hook_program_id: Pubkey,
+    forwarded_accounts: Vec<AccountMeta>,
+) -> Instruction {
+    let mut instruction = redeem_fees(
+        program_id,
+        reserve_pubkey,
+        reserve_liquidity_fee_receiver_pubkey,
+        reserve_supply_liquidity_pubkey,
+        lending_market_pubkey,
+    );
+    let (fee_redemption_hook_pubkey, _bump_seed) =
+        find_fee_redemption_hook_address(&program_id, &reserve_pubkey);
+    instruction.accounts.extend([
+        AccountMeta::new_readonly(fee_redemption_hook_pubkey, false),
+        AccountMeta::new_readonly(hook_program_id, false),
+    ]);
+    instruction.accounts.extend(forwarded_accounts);
+    instruction
+}
+
 /// Creates a 'FlashBorrowReserveLiquidity' instruction.
 #[allow(clippy::too_many_arguments)]
 pub fn flash_borrow_reserve_liquidity(
@@ -1583,6 +3390,8 @@ pub fn flash_borrow_reserve_liquidity(
         &[&lending_market_pubkey.to_bytes()[..PUBKEY_BYTES]],
         &program_id,
     );
+    let (flash_loan_config_pubkey, _bump_seed) =
+        find_flash_loan_config_address(&program_id, &reserve_pubkey);
 
     Instruction {
         program_id,
@@ -1594,12 +3403,15 @@ pub fn flash_borrow_reserve_liquidity(
             AccountMeta::new_readonly(lending_market_authority_pubkey, false),
             AccountMeta::new_readonly(sysvar::instructions::id(), false),
             AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(flash_loan_config_pubkey, false),
         ],
         data: LendingInstruction::FlashBorrowReserveLiquidity { liquidity_amount }.pack(),
     }
 }
 
-/// Creates a 'FlashRepayReserveLiquidity' instruction.
+/// Creates a 'FlashRepayReserveLiquidity' instruction. `reserve_liquidity_fee_receiver_pubkey`
+/// has to be the flash loan fee receiver of the reserve's flash loan config if it has one, see
+/// [crate::state::FlashLoanConfig::fee_receiver].
 #[allow(clippy::too_many_arguments)]
 pub fn flash_repay_reserve_liquidity(
     program_id: Pubkey,
@@ -1613,6 +3425,8 @@ pub fn flash_repay_reserve_liquidity(
     lending_market_pubkey: Pubkey,
     user_transfer_authority_pubkey: Pubkey,
 ) -> Instruction {
+    let (flash_loan_config_pubkey, _bump_seed) =
+        find_flash_loan_config_address(&program_id, &reserve_pubkey);
     Instruction {
         program_id,
         accounts: vec![
@@ -1625,6 +3439,7 @@ pub fn flash_repay_reserve_liquidity(
             AccountMeta::new_readonly(user_transfer_authority_pubkey, true),
             AccountMeta::new_readonly(sysvar::instructions::id(), false),
             AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new(flash_loan_config_pubkey, false),
         ],
         data: LendingInstruction::FlashRepayReserveLiquidity {
             liquidity_amount,
@@ -1688,42 +3503,1178 @@ pub fn update_market_metadata(
     }
 }
 
-#[cfg(test)]
-mod test {
-    use super::*;
-    use rand::Rng;
-
-    #[test]
-    fn pack_and_unpack_instructions() {
-        let mut rng = rand::thread_rng();
+/// Derives the borrow allowances address of an obligation
+pub fn find_borrow_allowances_address(
+    program_id: &Pubkey,
+    obligation_pubkey: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            &obligation_pubkey.to_bytes()[..PUBKEY_BYTES],
+            BORROW_ALLOWANCES_SEED,
+        ],
+        program_id,
+    )
+}
 
-        for _ in 0..100 {
-            {
-                let instruction = LendingInstruction::InitLendingMarket {
-                    owner: Pubkey::new_unique(),
-                    quote_currency: [rng.gen::<u8>(); 32],
-                };
+/// Creates an `ApproveBorrowAllowance` instruction
+pub fn approve_borrow_allowance(
+    program_id: Pubkey,
+    liquidity_amount: u64,
+    obligation_pubkey: Pubkey,
+    borrow_reserve_pubkey: Pubkey,
+    delegate_pubkey: Pubkey,
+    obligation_owner_pubkey: Pubkey,
+) -> Instruction {
+    let (borrow_allowances_pubkey, _bump_seed) =
+        find_borrow_allowances_address(&program_id, &obligation_pubkey);
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(obligation_pubkey, false),
+            AccountMeta::new(borrow_allowances_pubkey, false),
+            AccountMeta::new_readonly(borrow_reserve_pubkey, false),
+            AccountMeta::new_readonly(delegate_pubkey, false),
+            AccountMeta::new(obligation_owner_pubkey, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        data: LendingInstruction::ApproveBorrowAllowance { liquidity_amount }.pack(),
+    }
+}
 
-                let packed = instruction.pack();
-                let unpacked = LendingInstruction::unpack(&packed).unwrap();
-                assert_eq!(instruction, unpacked);
-            }
+/// Creates a `RevokeBorrowAllowance` instruction
+pub fn revoke_borrow_allowance(
+    program_id: Pubkey,
+    obligation_pubkey: Pubkey,
+    borrow_reserve_pubkey: Pubkey,
+    delegate_pubkey: Pubkey,
+    obligation_owner_pubkey: Pubkey,
+) -> Instruction {
+    let (borrow_allowances_pubkey, _bump_seed) =
+        find_borrow_allowances_address(&program_id, &obligation_pubkey);
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(obligation_pubkey, false),
+            AccountMeta::new(borrow_allowances_pubkey, false),
+            AccountMeta::new_readonly(borrow_reserve_pubkey, false),
+            AccountMeta::new_readonly(delegate_pubkey, false),
+            AccountMeta::new_readonly(obligation_owner_pubkey, true),
+        ],
+        data: LendingInstruction::RevokeBorrowAllowance.pack(),
+    }
+}
 
-            // set lending market owner and config
-            {
-                let instruction = LendingInstruction::SetLendingMarketOwnerAndConfig {
-                    new_owner: Pubkey::new_unique(),
-                    rate_limiter_config: RateLimiterConfig {
-                        window_duration: rng.gen::<u64>(),
-                        max_outflow: rng.gen::<u64>(),
-                    },
-                    whitelisted_liquidator: if rng.gen_bool(0.5) {
-                        None
-                    } else {
-                        Some(Pubkey::new_unique())
-                    },
-                    risk_authority: Pubkey::new_unique(),
-                };
+/// Creates a `LiquidateObligationForCollateral` instruction
+#[allow(clippy::too_many_arguments)]
+pub fn liquidate_obligation_for_collateral(
+    program_id: Pubkey,
+    liquidity_amount: u64,
+    source_liquidity_pubkey: Pubkey,
+    destination_collateral_pubkey: Pubkey,
+    repay_reserve_pubkey: Pubkey,
+    repay_reserve_liquidity_supply_pubkey: Pubkey,
+    withdraw_reserve_pubkey: Pubkey,
+    withdraw_reserve_collateral_mint_pubkey: Pubkey,
+    withdraw_reserve_collateral_supply_pubkey: Pubkey,
+    obligation_pubkey: Pubkey,
+    lending_market_pubkey: Pubkey,
+    user_transfer_authority_pubkey: Pubkey,
+) -> Instruction {
+    let (lending_market_authority_pubkey, _bump_seed) = Pubkey::find_program_address(
+        &[&lending_market_pubkey.to_bytes()[..PUBKEY_BYTES]],
+        &program_id,
+    );
+    let (liquidation_circuit_breaker_pubkey, _bump_seed) =
+        find_liquidation_circuit_breaker_address(&program_id, &lending_market_pubkey);
+    let (liquidation_stats_pubkey, _bump_seed) =
+        find_liquidation_stats_address(&program_id, &lending_market_pubkey);
+    let (market_policy_pubkey, _bump_seed) =
+        find_market_policy_address(&program_id, &lending_market_pubkey);
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(source_liquidity_pubkey, false),
+            AccountMeta::new(destination_collateral_pubkey, false),
+            AccountMeta::new(repay_reserve_pubkey, false),
+            AccountMeta::new(repay_reserve_liquidity_supply_pubkey, false),
+            AccountMeta::new(withdraw_reserve_pubkey, false),
+            AccountMeta::new(withdraw_reserve_collateral_mint_pubkey, false),
+            AccountMeta::new(withdraw_reserve_collateral_supply_pubkey, false),
+            AccountMeta::new(obligation_pubkey, false),
+            AccountMeta::new_readonly(lending_market_pubkey, false),
+            AccountMeta::new_readonly(lending_market_authority_pubkey, false),
+            AccountMeta::new_readonly(user_transfer_authority_pubkey, true),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new(liquidation_circuit_breaker_pubkey, false),
+            AccountMeta::new_readonly(market_policy_pubkey, false),
+            AccountMeta::new(liquidation_stats_pubkey, false),
+        ],
+        data: LendingInstruction::LiquidateObligationForCollateral { liquidity_amount }.pack(),
+    }
+}
+
+/// Creates a `CloseObligation` instruction
+pub fn close_obligation(
+    program_id: Pubkey,
+    obligation_pubkey: Pubkey,
+    rent_destination_pubkey: Pubkey,
+    obligation_owner_pubkey: Pubkey,
+) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new(obligation_pubkey, false),
+        AccountMeta::new(rent_destination_pubkey, false),
+        AccountMeta::new_readonly(obligation_owner_pubkey, true),
+    ];
+    accounts.extend(obligation_derived_accounts(&program_id, &obligation_pubkey));
+    Instruction {
+        program_id,
+        accounts,
+        data: LendingInstruction::CloseObligation.pack(),
+    }
+}
+
+/// Accounts derived from an obligation, which instructions closing the obligation close too
+fn obligation_derived_accounts(
+    program_id: &Pubkey,
+    obligation_pubkey: &Pubkey,
+) -> Vec<AccountMeta> {
+    let (borrow_allowances_pubkey, _bump_seed) =
+        find_borrow_allowances_address(program_id, obligation_pubkey);
+    let (obligation_borrow_cap_pubkey, _bump_seed) =
+        find_obligation_borrow_cap_address(program_id, obligation_pubkey);
+    let (deleverage_credit_pubkey, _bump_seed) =
+        find_deleverage_credit_address(program_id, obligation_pubkey);
+    vec![
+        AccountMeta::new(borrow_allowances_pubkey, false),
+        AccountMeta::new(obligation_borrow_cap_pubkey, false),
+        AccountMeta::new(deleverage_credit_pubkey, false),
+    ]
+}
+
+/// Creates a `WindDownReserve` instruction
+pub fn wind_down_reserve(
+    program_id: Pubkey,
+    ltv_decay_per_slot_wad: u64,
+    reserve_pubkey: Pubkey,
+    lending_market_pubkey: Pubkey,
+    signer_pubkey: Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(reserve_pubkey, false),
+            AccountMeta::new_readonly(lending_market_pubkey, false),
+            AccountMeta::new_readonly(signer_pubkey, true),
+        ],
+        data: LendingInstruction::WindDownReserve {
+            ltv_decay_per_slot_wad,
+        }
+        .pack(),
+    }
+}
+
+/// Creates a `CloseReserve` instruction
+pub fn close_reserve(
+    program_id: Pubkey,
+    reserve_pubkey: Pubkey,
+    lending_market_pubkey: Pubkey,
+    signer_pubkey: Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(reserve_pubkey, false),
+            AccountMeta::new_readonly(lending_market_pubkey, false),
+            AccountMeta::new_readonly(signer_pubkey, true),
+        ],
+        data: LendingInstruction::CloseReserve.pack(),
+    }
+}
+
+/// Derives the liquidation circuit breaker address of a lending market
+pub fn find_liquidation_circuit_breaker_address(
+    program_id: &Pubkey,
+    lending_market_pubkey: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            &lending_market_pubkey.to_bytes()[..PUBKEY_BYTES],
+            LIQUIDATION_CIRCUIT_BREAKER_SEED,
+        ],
+        program_id,
+    )
+}
+
+/// Derives the liquidation stats address of a lending market
+pub fn find_liquidation_stats_address(
+    program_id: &Pubkey,
+    lending_market_pubkey: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            &lending_market_pubkey.to_bytes()[..PUBKEY_BYTES],
+            LIQUIDATION_STATS_SEED,
+        ],
+        program_id,
+    )
+}
+
+/// Derives the fee rebates address of a lending market
+pub fn find_fee_rebates_address(
+    program_id: &Pubkey,
+    lending_market_pubkey: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            &lending_market_pubkey.to_bytes()[..PUBKEY_BYTES],
+            FEE_REBATES_SEED,
+        ],
+        program_id,
+    )
+}
+
+/// Derives the cross market sources address of a lending market
+pub fn find_cross_market_sources_address(
+    program_id: &Pubkey,
+    lending_market_pubkey: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            &lending_market_pubkey.to_bytes()[..PUBKEY_BYTES],
+            CROSS_MARKET_SOURCES_SEED,
+        ],
+        program_id,
+    )
+}
+
+/// Creates a `SetCrossMarketSource` instruction
+pub fn set_cross_market_source(
+    program_id: Pubkey,
+    source_reserve: Pubkey,
+    allowed: bool,
+    lending_market_pubkey: Pubkey,
+    lending_market_owner_pubkey: Pubkey,
+) -> Instruction {
+    let (cross_market_sources_pubkey, _bump_seed) =
+        find_cross_market_sources_address(&program_id, &lending_market_pubkey);
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(lending_market_pubkey, false),
+            AccountMeta::new(cross_market_sources_pubkey, false),
+            AccountMeta::new(lending_market_owner_pubkey, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        data: LendingInstruction::SetCrossMarketSource {
+            source_reserve,
+            allowed,
+        }
+        .pack(),
+    }
+}
+
+/// Derives the obligation limits address of a lending market
+pub fn find_obligation_limits_address(
+    program_id: &Pubkey,
+    lending_market_pubkey: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            &lending_market_pubkey.to_bytes()[..PUBKEY_BYTES],
+            OBLIGATION_LIMITS_SEED,
+        ],
+        program_id,
+    )
+}
+
+/// Creates a `SetObligationLimits` instruction
+pub fn set_obligation_limits(
+    program_id: Pubkey,
+    max_deposits: u8,
+    max_borrows: u8,
+    lending_market_pubkey: Pubkey,
+    lending_market_owner_pubkey: Pubkey,
+) -> Instruction {
+    let (obligation_limits_pubkey, _bump_seed) =
+        find_obligation_limits_address(&program_id, &lending_market_pubkey);
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(lending_market_pubkey, false),
+            AccountMeta::new(obligation_limits_pubkey, false),
+            AccountMeta::new(lending_market_owner_pubkey, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        data: LendingInstruction::SetObligationLimits {
+            max_deposits,
+            max_borrows,
+        }
+        .pack(),
+    }
+}
+
+/// Creates a `SetObligationHealthBuffer` instruction
+pub fn set_obligation_health_buffer(
+    program_id: Pubkey,
+    health_buffer_bps: u16,
+    lending_market_pubkey: Pubkey,
+    lending_market_owner_pubkey: Pubkey,
+) -> Instruction {
+    let (obligation_limits_pubkey, _bump_seed) =
+        find_obligation_limits_address(&program_id, &lending_market_pubkey);
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(lending_market_pubkey, false),
+            AccountMeta::new(obligation_limits_pubkey, false),
+            AccountMeta::new(lending_market_owner_pubkey, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        data: LendingInstruction::SetObligationHealthBuffer { health_buffer_bps }.pack(),
+    }
+}
+
+/// Derives the config timelock address of a lending market
+pub fn find_config_timelock_address(
+    program_id: &Pubkey,
+    lending_market_pubkey: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            &lending_market_pubkey.to_bytes()[..PUBKEY_BYTES],
+            CONFIG_TIMELOCK_SEED,
+        ],
+        program_id,
+    )
+}
+
+/// Derives the pending config address of a reserve, lending market or config timelock
+pub fn find_pending_config_address(program_id: &Pubkey, target_pubkey: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            &target_pubkey.to_bytes()[..PUBKEY_BYTES],
+            PENDING_CONFIG_SEED,
+        ],
+        program_id,
+    )
+}
+
+/// Creates a `SetConfigTimelock` instruction
+pub fn set_config_timelock(
+    program_id: Pubkey,
+    delay_slots: u64,
+    lending_market_pubkey: Pubkey,
+    lending_market_owner_pubkey: Pubkey,
+) -> Instruction {
+    let (config_timelock_pubkey, _bump_seed) =
+        find_config_timelock_address(&program_id, &lending_market_pubkey);
+    let (pending_config_pubkey, _bump_seed) =
+        find_pending_config_address(&program_id, &config_timelock_pubkey);
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(lending_market_pubkey, false),
+            AccountMeta::new(config_timelock_pubkey, false),
+            AccountMeta::new(lending_market_owner_pubkey, true),
+            AccountMeta::new(pending_config_pubkey, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        data: LendingInstruction::SetConfigTimelock { delay_slots }.pack(),
+    }
+}
+
+/// Creates an `ActivateConfig` instruction. `target_pubkey` is the reserve, lending market or
+/// config timelock the staged change applies to.
+pub fn activate_config(
+    program_id: Pubkey,
+    target_pubkey: Pubkey,
+    lending_market_pubkey: Pubkey,
+    lending_market_owner_pubkey: Pubkey,
+) -> Instruction {
+    let (pending_config_pubkey, _bump_seed) =
+        find_pending_config_address(&program_id, &target_pubkey);
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(lending_market_pubkey, false),
+            AccountMeta::new(pending_config_pubkey, false),
+            AccountMeta::new(target_pubkey, false),
+            AccountMeta::new(lending_market_owner_pubkey, false),
+        ],
+        data: LendingInstruction::ActivateConfig.pack(),
+    }
+}
+
+/// Creates a `CancelConfig` instruction
+pub fn cancel_config(
+    program_id: Pubkey,
+    target_pubkey: Pubkey,
+    lending_market_pubkey: Pubkey,
+    signer_pubkey: Pubkey,
+    lending_market_owner_pubkey: Pubkey,
+) -> Instruction {
+    let (pending_config_pubkey, _bump_seed) =
+        find_pending_config_address(&program_id, &target_pubkey);
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(lending_market_pubkey, false),
+            AccountMeta::new(pending_config_pubkey, false),
+            AccountMeta::new_readonly(target_pubkey, false),
+            AccountMeta::new_readonly(signer_pubkey, true),
+            AccountMeta::new(lending_market_owner_pubkey, false),
+        ],
+        data: LendingInstruction::CancelConfig.pack(),
+    }
+}
+
+/// Creates a `SetLiquidationCircuitBreaker` instruction
+pub fn set_liquidation_circuit_breaker(
+    program_id: Pubkey,
+    config: RateLimiterConfig,
+    pause_duration: u64,
+    lending_market_pubkey: Pubkey,
+    lending_market_owner_pubkey: Pubkey,
+) -> Instruction {
+    let (liquidation_circuit_breaker_pubkey, _bump_seed) =
+        find_liquidation_circuit_breaker_address(&program_id, &lending_market_pubkey);
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(lending_market_pubkey, false),
+            AccountMeta::new(liquidation_circuit_breaker_pubkey, false),
+            AccountMeta::new(lending_market_owner_pubkey, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        data: LendingInstruction::SetLiquidationCircuitBreaker {
+            config,
+            pause_duration,
+        }
+        .pack(),
+    }
+}
+
+/// Creates an `InitLiquidationStats` instruction
+pub fn init_liquidation_stats(
+    program_id: Pubkey,
+    lending_market_pubkey: Pubkey,
+    lending_market_owner_pubkey: Pubkey,
+) -> Instruction {
+    let (liquidation_stats_pubkey, _bump_seed) =
+        find_liquidation_stats_address(&program_id, &lending_market_pubkey);
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(lending_market_pubkey, false),
+            AccountMeta::new(liquidation_stats_pubkey, false),
+            AccountMeta::new(lending_market_owner_pubkey, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        data: LendingInstruction::InitLiquidationStats.pack(),
+    }
+}
+
+/// Creates a `SetCollateralLiquidationPriority` instruction
+pub fn set_collateral_liquidation_priority(
+    program_id: Pubkey,
+    liquidation_priority: u8,
+    obligation_pubkey: Pubkey,
+    deposit_reserve_pubkey: Pubkey,
+    obligation_owner_pubkey: Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(obligation_pubkey, false),
+            AccountMeta::new_readonly(deposit_reserve_pubkey, false),
+            AccountMeta::new_readonly(obligation_owner_pubkey, true),
+        ],
+        data: LendingInstruction::SetCollateralLiquidationPriority {
+            liquidation_priority,
+        }
+        .pack(),
+    }
+}
+
+/// Creates a `SetObligationDepositMode` instruction
+pub fn set_obligation_deposit_mode(
+    program_id: Pubkey,
+    mode: DepositMode,
+    obligation_pubkey: Pubkey,
+    obligation_owner_pubkey: Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(obligation_pubkey, false),
+            AccountMeta::new_readonly(obligation_owner_pubkey, true),
+        ],
+        data: LendingInstruction::SetObligationDepositMode { mode }.pack(),
+    }
+}
+
+/// Creates a `SetIgnoreHealthBuffer` instruction
+pub fn set_ignore_health_buffer(
+    program_id: Pubkey,
+    ignore_health_buffer: bool,
+    obligation_pubkey: Pubkey,
+    obligation_owner_pubkey: Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(obligation_pubkey, false),
+            AccountMeta::new_readonly(obligation_owner_pubkey, true),
+        ],
+        data: LendingInstruction::SetIgnoreHealthBuffer {
+            ignore_health_buffer,
+        }
+        .pack(),
+    }
+}
+
+/// Creates a `ViewObligationHealth` instruction
+pub fn view_obligation_health(
+    program_id: Pubkey,
+    obligation_pubkey: Pubkey,
+    reserve_pubkeys: Vec<Pubkey>,
+) -> Instruction {
+    let mut accounts = vec![AccountMeta::new_readonly(obligation_pubkey, false)];
+    accounts.extend(
+        reserve_pubkeys
+            .into_iter()
+            .map(|pubkey| AccountMeta::new_readonly(pubkey, false)),
+    );
+    Instruction {
+        program_id,
+        accounts,
+        data: LendingInstruction::ViewObligationHealth.pack(),
+    }
+}
+
+/// Creates a `SplitObligation` instruction. `reserve_pubkeys` are the deposit and borrow
+/// reserves of the source obligation, in the same order as for `refresh_obligation`.
+#[allow(clippy::too_many_arguments)]
+pub fn split_obligation(
+    program_id: Pubkey,
+    deposits: Vec<(Pubkey, u64)>,
+    borrows: Vec<(Pubkey, u64)>,
+    source_obligation_pubkey: Pubkey,
+    destination_obligation_pubkey: Pubkey,
+    lending_market_pubkey: Pubkey,
+    obligation_owner_pubkey: Pubkey,
+    reserve_pubkeys: Vec<Pubkey>,
+) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new(source_obligation_pubkey, false),
+        AccountMeta::new(destination_obligation_pubkey, false),
+        AccountMeta::new_readonly(lending_market_pubkey, false),
+        AccountMeta::new_readonly(obligation_owner_pubkey, true),
+    ];
+    accounts.extend(
+        reserve_pubkeys
+            .into_iter()
+            .map(|pubkey| AccountMeta::new_readonly(pubkey, false)),
+    );
+    Instruction {
+        program_id,
+        accounts,
+        data: LendingInstruction::SplitObligation { deposits, borrows }.pack(),
+    }
+}
+
+/// Creates a `MergeObligations` instruction
+pub fn merge_obligations(
+    program_id: Pubkey,
+    destination_obligation_pubkey: Pubkey,
+    lending_market_pubkey: Pubkey,
+    obligation_owner_pubkey: Pubkey,
+    source_obligation_pubkeys: Vec<Pubkey>,
+) -> Instruction {
+    let (obligation_limits_pubkey, _bump_seed) =
+        find_obligation_limits_address(&program_id, &lending_market_pubkey);
+    let mut accounts = vec![
+        AccountMeta::new(destination_obligation_pubkey, false),
+        AccountMeta::new_readonly(lending_market_pubkey, false),
+        AccountMeta::new_readonly(obligation_owner_pubkey, true),
+        AccountMeta::new_readonly(obligation_limits_pubkey, false),
+    ];
+    accounts.extend(
+        source_obligation_pubkeys
+            .into_iter()
+            .map(|pubkey| AccountMeta::new(pubkey, false)),
+    );
+    Instruction {
+        program_id,
+        accounts,
+        data: LendingInstruction::MergeObligations.pack(),
+    }
+}
+
+/// Derives the associated token account of a wallet for a mint
+pub fn get_associated_token_address(wallet_pubkey: &Pubkey, mint_pubkey: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[
+            &wallet_pubkey.to_bytes(),
+            &spl_token::id().to_bytes(),
+            &mint_pubkey.to_bytes(),
+        ],
+        &crate::spl_associated_token_account::id(),
+    )
+    .0
+}
+
+/// Creates an associated token account program instruction creating the associated token
+/// account of a wallet for a mint, which succeeds without doing anything if it already exists
+pub fn create_associated_token_account_idempotent(
+    payer_pubkey: Pubkey,
+    wallet_pubkey: Pubkey,
+    mint_pubkey: Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id: crate::spl_associated_token_account::id(),
+        accounts: vec![
+            AccountMeta::new(payer_pubkey, true),
+            AccountMeta::new(
+                get_associated_token_address(&wallet_pubkey, &mint_pubkey),
+                false,
+            ),
+            AccountMeta::new_readonly(wallet_pubkey, false),
+            AccountMeta::new_readonly(mint_pubkey, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+        ],
+        // CreateIdempotent
+        data: vec![1],
+    }
+}
+
+/// Derives the dust sweep config address of a lending market
+pub fn find_dust_sweep_config_address(
+    program_id: &Pubkey,
+    lending_market_pubkey: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            &lending_market_pubkey.to_bytes()[..PUBKEY_BYTES],
+            DUST_SWEEP_CONFIG_SEED,
+        ],
+        program_id,
+    )
+}
+
+/// Creates a `SetDustSweepThreshold` instruction
+pub fn set_dust_sweep_threshold(
+    program_id: Pubkey,
+    max_deposited_value_wad: u64,
+    lending_market_pubkey: Pubkey,
+    lending_market_owner_pubkey: Pubkey,
+) -> Instruction {
+    let (dust_sweep_config_pubkey, _bump_seed) =
+        find_dust_sweep_config_address(&program_id, &lending_market_pubkey);
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(lending_market_pubkey, false),
+            AccountMeta::new(dust_sweep_config_pubkey, false),
+            AccountMeta::new(lending_market_owner_pubkey, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        data: LendingInstruction::SetDustSweepThreshold {
+            max_deposited_value_wad,
+        }
+        .pack(),
+    }
+}
+
+/// Creates a `SweepDustObligation` instruction. `deposit_reserves` are the (reserve, collateral
+/// mint, collateral supply) accounts of every deposit of the obligation, in order.
+pub fn sweep_dust_obligation(
+    program_id: Pubkey,
+    obligation_pubkey: Pubkey,
+    obligation_owner_pubkey: Pubkey,
+    lending_market_pubkey: Pubkey,
+    signer_pubkey: Pubkey,
+    deposit_reserves: Vec<(Pubkey, Pubkey, Pubkey)>,
+) -> Instruction {
+    let (dust_sweep_config_pubkey, _bump_seed) =
+        find_dust_sweep_config_address(&program_id, &lending_market_pubkey);
+    let (lending_market_authority_pubkey, _bump_seed) = Pubkey::find_program_address(
+        &[&lending_market_pubkey.to_bytes()[..PUBKEY_BYTES]],
+        &program_id,
+    );
+    let mut accounts = vec![
+        AccountMeta::new(obligation_pubkey, false),
+        AccountMeta::new(obligation_owner_pubkey, false),
+        AccountMeta::new_readonly(lending_market_pubkey, false),
+        AccountMeta::new_readonly(signer_pubkey, true),
+        AccountMeta::new_readonly(dust_sweep_config_pubkey, false),
+        AccountMeta::new_readonly(lending_market_authority_pubkey, false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+    ];
+    for (reserve_pubkey, collateral_mint_pubkey, collateral_supply_pubkey) in deposit_reserves {
+        accounts.push(AccountMeta::new(reserve_pubkey, false));
+        accounts.push(AccountMeta::new(collateral_mint_pubkey, false));
+        accounts.push(AccountMeta::new(collateral_supply_pubkey, false));
+    }
+    accounts.extend(obligation_derived_accounts(&program_id, &obligation_pubkey));
+    Instruction {
+        program_id,
+        accounts,
+        data: LendingInstruction::SweepDustObligation.pack(),
+    }
+}
+
+/// Derives the borrow cap address of an obligation
+pub fn find_obligation_borrow_cap_address(
+    program_id: &Pubkey,
+    obligation_pubkey: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            &obligation_pubkey.to_bytes()[..PUBKEY_BYTES],
+            OBLIGATION_BORROW_CAP_SEED,
+        ],
+        program_id,
+    )
+}
+
+/// Creates a `SetObligationBorrowCap` instruction
+pub fn set_obligation_borrow_cap(
+    program_id: Pubkey,
+    max_borrowed_value: u64,
+    obligation_pubkey: Pubkey,
+    obligation_owner_pubkey: Pubkey,
+) -> Instruction {
+    let (obligation_borrow_cap_pubkey, _bump_seed) =
+        find_obligation_borrow_cap_address(&program_id, &obligation_pubkey);
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(obligation_pubkey, false),
+            AccountMeta::new(obligation_borrow_cap_pubkey, false),
+            AccountMeta::new(obligation_owner_pubkey, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        data: LendingInstruction::SetObligationBorrowCap { max_borrowed_value }.pack(),
+    }
+}
+
+/// Creates a `SetBorrowFeeRebate` instruction
+pub fn set_borrow_fee_rebate(
+    program_id: Pubkey,
+    key: Pubkey,
+    rebate_bps: u16,
+    lending_market_pubkey: Pubkey,
+    lending_market_owner_pubkey: Pubkey,
+) -> Instruction {
+    let (fee_rebates_pubkey, _bump_seed) =
+        find_fee_rebates_address(&program_id, &lending_market_pubkey);
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(lending_market_pubkey, false),
+            AccountMeta::new(fee_rebates_pubkey, false),
+            AccountMeta::new(lending_market_owner_pubkey, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        data: LendingInstruction::SetBorrowFeeRebate { key, rebate_bps }.pack(),
+    }
+}
+
+/// Derives the fee redemption hook address of a reserve
+pub fn find_fee_redemption_hook_address(
+    program_id: &Pubkey,
+    reserve_pubkey: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            &reserve_pubkey.to_bytes()[..PUBKEY_BYTES],
+            FEE_REDEMPTION_HOOK_SEED,
+        ],
+        program_id,
+    )
+}
+
+/// Creates a `SetFeeRedemptionHook` instruction
+pub fn set_fee_redemption_hook(
+    program_id: Pubkey,
+    hook_program: Pubkey,
+    accounts_hash: [u8; 32],
+    reserve_pubkey: Pubkey,
+    lending_market_pubkey: Pubkey,
+    lending_market_owner_pubkey: Pubkey,
+) -> Instruction {
+    let (fee_redemption_hook_pubkey, _bump_seed) =
+        find_fee_redemption_hook_address(&program_id, &reserve_pubkey);
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(reserve_pubkey, false),
+            AccountMeta::new_readonly(lending_market_pubkey, false),
+            AccountMeta::new(fee_redemption_hook_pubkey, false),
+            AccountMeta::new(lending_market_owner_pubkey, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        data: LendingInstruction::SetFeeRedemptionHook {
+            hook_program,
+            accounts_hash,
+        }
+        .pack(),
+    }
+}
+
+/// Derives the market policy address of a lending market
+pub fn find_market_policy_address(
+    program_id: &Pubkey,
+    lending_market_pubkey: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            &lending_market_pubkey.to_bytes()[..PUBKEY_BYTES],
+            MARKET_POLICY_SEED,
+        ],
+        program_id,
+    )
+}
+
+/// Creates a `SetMarketPolicy` instruction
+pub fn set_market_policy(
+    program_id: Pubkey,
+    policy_program: Pubkey,
+    policy_account: Option<Pubkey>,
+    lending_market_pubkey: Pubkey,
+    lending_market_owner_pubkey: Pubkey,
+) -> Instruction {
+    let (market_policy_pubkey, _bump_seed) =
+        find_market_policy_address(&program_id, &lending_market_pubkey);
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(lending_market_pubkey, false),
+            AccountMeta::new(market_policy_pubkey, false),
+            AccountMeta::new(lending_market_owner_pubkey, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        data: LendingInstruction::SetMarketPolicy {
+            policy_program,
+            policy_account: policy_account.unwrap_or_default(),
+        }
+        .pack(),
+    }
+}
+
+/// Appends the policy program of a lending market and its policy account, if any, to a borrow,
+/// withdrawal or liquidation instruction built for that market
+pub fn append_policy_accounts(
+    instruction: &mut Instruction,
+    policy_program: Pubkey,
+    policy_account: Option<Pubkey>,
+) {
+    instruction
+        .accounts
+        .push(AccountMeta::new_readonly(policy_program, false));
+    if let Some(policy_account) = policy_account {
+        instruction
+            .accounts
+            .push(AccountMeta::new_readonly(policy_account, false));
+    }
+}
+
+/// Derives the accrual history address of a reserve
+pub fn find_reserve_accrual_history_address(
+    program_id: &Pubkey,
+    reserve_pubkey: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            &reserve_pubkey.to_bytes()[..PUBKEY_BYTES],
+            RESERVE_ACCRUAL_HISTORY_SEED,
+        ],
+        program_id,
+    )
+}
+
+/// Creates a `SetReserveAccrualEpoch` instruction
+pub fn set_reserve_accrual_epoch(
+    program_id: Pubkey,
+    epoch_slots: u64,
+    reserve_pubkey: Pubkey,
+    lending_market_pubkey: Pubkey,
+    lending_market_owner_pubkey: Pubkey,
+) -> Instruction {
+    let (accrual_history_pubkey, _bump_seed) =
+        find_reserve_accrual_history_address(&program_id, &reserve_pubkey);
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(reserve_pubkey, false),
+            AccountMeta::new(accrual_history_pubkey, false),
+            AccountMeta::new_readonly(lending_market_pubkey, false),
+            AccountMeta::new(lending_market_owner_pubkey, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        data: LendingInstruction::SetReserveAccrualEpoch { epoch_slots }.pack(),
+    }
+}
+
+/// Derives the deleverage config address of a lending market
+pub fn find_deleverage_config_address(
+    program_id: &Pubkey,
+    lending_market_pubkey: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            &lending_market_pubkey.to_bytes()[..PUBKEY_BYTES],
+            DELEVERAGE_CONFIG_SEED,
+        ],
+        program_id,
+    )
+}
+
+/// Derives the deleverage credit address of an obligation
+pub fn find_deleverage_credit_address(
+    program_id: &Pubkey,
+    obligation_pubkey: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            &obligation_pubkey.to_bytes()[..PUBKEY_BYTES],
+            DELEVERAGE_CREDIT_SEED,
+        ],
+        program_id,
+    )
+}
+
+/// Creates a `SetDeleverageConfig` instruction
+pub fn set_deleverage_config(
+    program_id: Pubkey,
+    max_exempt_value: u64,
+    lending_market_pubkey: Pubkey,
+    lending_market_owner_pubkey: Pubkey,
+) -> Instruction {
+    let (deleverage_config_pubkey, _bump_seed) =
+        find_deleverage_config_address(&program_id, &lending_market_pubkey);
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(lending_market_pubkey, false),
+            AccountMeta::new(deleverage_config_pubkey, false),
+            AccountMeta::new(lending_market_owner_pubkey, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        data: LendingInstruction::SetDeleverageConfig { max_exempt_value }.pack(),
+    }
+}
+
+/// Creates an `InitDeleverageCredit` instruction
+pub fn init_deleverage_credit(
+    program_id: Pubkey,
+    obligation_pubkey: Pubkey,
+    obligation_owner_pubkey: Pubkey,
+) -> Instruction {
+    let (deleverage_credit_pubkey, _bump_seed) =
+        find_deleverage_credit_address(&program_id, &obligation_pubkey);
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(obligation_pubkey, false),
+            AccountMeta::new(deleverage_credit_pubkey, false),
+            AccountMeta::new(obligation_owner_pubkey, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        data: LendingInstruction::InitDeleverageCredit.pack(),
+    }
+}
+
+/// Creates a `SetObligationOwner` instruction, revoking the borrow allowances of the obligation
+pub fn set_obligation_owner(
+    program_id: Pubkey,
+    obligation_pubkey: Pubkey,
+    lending_market_pubkey: Pubkey,
+    obligation_owner_pubkey: Pubkey,
+    new_obligation_owner_pubkey: Pubkey,
+) -> Instruction {
+    let (borrow_allowances_pubkey, _bump_seed) =
+        find_borrow_allowances_address(&program_id, &obligation_pubkey);
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(obligation_pubkey, false),
+            AccountMeta::new_readonly(lending_market_pubkey, false),
+            AccountMeta::new_readonly(obligation_owner_pubkey, true),
+            AccountMeta::new_readonly(new_obligation_owner_pubkey, true),
+            AccountMeta::new(borrow_allowances_pubkey, false),
+        ],
+        data: LendingInstruction::SetObligationOwner.pack(),
+    }
+}
+
+/// Derives the flash loan config address of a reserve
+pub fn find_flash_loan_config_address(
+    program_id: &Pubkey,
+    reserve_pubkey: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            &reserve_pubkey.to_bytes()[..PUBKEY_BYTES],
+            FLASH_LOAN_CONFIG_SEED,
+        ],
+        program_id,
+    )
+}
+
+/// Creates a `SetFlashLoanConfig` instruction
+pub fn set_flash_loan_config(
+    program_id: Pubkey,
+    flash_loans_enabled: bool,
+    max_flash_loan_amount: u64,
+    reserve_pubkey: Pubkey,
+    lending_market_pubkey: Pubkey,
+    lending_market_owner_pubkey: Pubkey,
+) -> Instruction {
+    let (flash_loan_config_pubkey, _bump_seed) =
+        find_flash_loan_config_address(&program_id, &reserve_pubkey);
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(reserve_pubkey, false),
+            AccountMeta::new_readonly(lending_market_pubkey, false),
+            AccountMeta::new(flash_loan_config_pubkey, false),
+            AccountMeta::new(lending_market_owner_pubkey, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        data: LendingInstruction::SetFlashLoanConfig {
+            flash_loans_enabled,
+            max_flash_loan_amount,
+        }
+        .pack(),
+    }
+}
+
+/// Creates a `SetFlashLoanFeeReceiver` instruction
+pub fn set_flash_loan_fee_receiver(
+    program_id: Pubkey,
+    flash_loan_fee_receiver_pubkey: Pubkey,
+    reserve_pubkey: Pubkey,
+    lending_market_pubkey: Pubkey,
+    lending_market_owner_pubkey: Pubkey,
+) -> Instruction {
+    let (flash_loan_config_pubkey, _bump_seed) =
+        find_flash_loan_config_address(&program_id, &reserve_pubkey);
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(reserve_pubkey, false),
+            AccountMeta::new_readonly(lending_market_pubkey, false),
+            AccountMeta::new(flash_loan_config_pubkey, false),
+            AccountMeta::new(lending_market_owner_pubkey, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(flash_loan_fee_receiver_pubkey, false),
+        ],
+        data: LendingInstruction::SetFlashLoanFeeReceiver.pack(),
+    }
+}
+
+/// Derives the borrow grace config address of a reserve
+pub fn find_borrow_grace_config_address(
+    program_id: &Pubkey,
+    reserve_pubkey: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            &reserve_pubkey.to_bytes()[..PUBKEY_BYTES],
+            BORROW_GRACE_CONFIG_SEED,
+        ],
+        program_id,
+    )
+}
+
+/// Creates a `SetBorrowGraceConfig` instruction
+pub fn set_borrow_grace_config(
+    program_id: Pubkey,
+    grace_slots: u64,
+    reserve_pubkey: Pubkey,
+    lending_market_pubkey: Pubkey,
+    lending_market_owner_pubkey: Pubkey,
+) -> Instruction {
+    let (borrow_grace_config_pubkey, _bump_seed) =
+        find_borrow_grace_config_address(&program_id, &reserve_pubkey);
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(reserve_pubkey, false),
+            AccountMeta::new_readonly(lending_market_pubkey, false),
+            AccountMeta::new(borrow_grace_config_pubkey, false),
+            AccountMeta::new(lending_market_owner_pubkey, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        data: LendingInstruction::SetBorrowGraceConfig { grace_slots }.pack(),
+    }
+}
+
+/// Appends the deleverage credit of an obligation and the deleverage config of its lending market
+/// to a `RepayObligationLiquidity` or `WithdrawObligationCollateralAndRedeemReserveCollateral`
+/// instruction
+pub fn append_deleverage_accounts(
+    instruction: &mut Instruction,
+    obligation_pubkey: Pubkey,
+    lending_market_pubkey: Pubkey,
+) {
+    let (deleverage_credit_pubkey, _bump_seed) =
+        find_deleverage_credit_address(&instruction.program_id, &obligation_pubkey);
+    let (deleverage_config_pubkey, _bump_seed) =
+        find_deleverage_config_address(&instruction.program_id, &lending_market_pubkey);
+    instruction
+        .accounts
+        .push(AccountMeta::new(deleverage_credit_pubkey, false));
+    instruction
+        .accounts
+        .push(AccountMeta::new_readonly(deleverage_config_pubkey, false));
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn pack_and_unpack_instructions() {
+        let mut rng = rand::thread_rng();
+
+        for _ in 0..100 {
+            {
+                let instruction = LendingInstruction::InitLendingMarket {
+                    owner: Pubkey::new_unique(),
+                    quote_currency: [rng.gen::<u8>(); 32],
+                };
+
+                let packed = instruction.pack();
+                let unpacked = LendingInstruction::unpack(&packed).unwrap();
+                assert_eq!(instruction, unpacked);
+            }
+
+            // set lending market owner and config
+            {
+                let instruction = LendingInstruction::SetLendingMarketOwnerAndConfig {
+                    new_owner: Pubkey::new_unique(),
+                    rate_limiter_config: RateLimiterConfig {
+                        window_duration: rng.gen::<u64>(),
+                        max_outflow: rng.gen::<u64>(),
+                    },
+                    whitelisted_liquidator: if rng.gen_bool(0.5) {
+                        None
+                    } else {
+                        Some(Pubkey::new_unique())
+                    },
+                    risk_authority: Pubkey::new_unique(),
+                    slots_per_year: if rng.gen_bool(0.5) {
+                        None
+                    } else {
+                        Some(rng.gen::<u64>())
+                    },
+                };
 
                 let packed = instruction.pack();
                 let unpacked = LendingInstruction::unpack(&packed).unwrap();
@@ -1757,6 +4708,21 @@ mod test {
                         protocol_take_rate: rng.gen::<u8>(),
                         added_borrow_weight_bps: rng.gen::<u64>(),
                         reserve_type: ReserveType::from_u8(rng.gen::<u8>() % 2).unwrap(),
+                        deposit_soft_cap_pct: rng.gen::<u8>(),
+                        borrow_soft_cap_pct: rng.gen::<u8>(),
+                        idle_liquidity_fee_bps: rng.gen::<u64>(),
+                        liquidation_fee_receiver: if rng.gen_bool(0.5) {
+                            Some(Pubkey::new_unique())
+                        } else {
+                            None
+                        },
+                        borrow_utilization_cap: rng.gen::<u8>(),
+                        fixed_borrow_rate_bps: rng.gen::<u16>(),
+                        fixed_borrow_max_duration_slots: rng.gen::<u64>(),
+                        withdraw_fee_bps: rng.gen::<u16>(),
+                        min_price_bps: rng.gen::<u32>(),
+                        max_price_bps: rng.gen::<u32>(),
+                        price_bounds_mode: PriceBoundsMode::from_u8(rng.gen::<u8>() % 2).unwrap(),
                     },
                 };
 
@@ -1833,6 +4799,17 @@ mod test {
                 assert_eq!(instruction, unpacked);
             }
 
+            // borrow obligation liquidity at a fixed rate
+            {
+                let instruction = LendingInstruction::BorrowObligationLiquidityFixedRate {
+                    liquidity_amount: rng.gen::<u64>(),
+                };
+
+                let packed = instruction.pack();
+                let unpacked = LendingInstruction::unpack(&packed).unwrap();
+                assert_eq!(instruction, unpacked);
+            }
+
             // repay obligation liquidity
             {
                 let instruction = LendingInstruction::RepayObligationLiquidity {
@@ -1917,6 +4894,21 @@ mod test {
                         protocol_take_rate: rng.gen::<u8>(),
                         added_borrow_weight_bps: rng.gen::<u64>(),
                         reserve_type: ReserveType::from_u8(rng.gen::<u8>() % 2).unwrap(),
+                        deposit_soft_cap_pct: rng.gen::<u8>(),
+                        borrow_soft_cap_pct: rng.gen::<u8>(),
+                        idle_liquidity_fee_bps: rng.gen::<u64>(),
+                        liquidation_fee_receiver: if rng.gen_bool(0.5) {
+                            Some(Pubkey::new_unique())
+                        } else {
+                            None
+                        },
+                        borrow_utilization_cap: rng.gen::<u8>(),
+                        fixed_borrow_rate_bps: rng.gen::<u16>(),
+                        fixed_borrow_max_duration_slots: rng.gen::<u64>(),
+                        withdraw_fee_bps: rng.gen::<u16>(),
+                        min_price_bps: rng.gen::<u32>(),
+                        max_price_bps: rng.gen::<u32>(),
+                        price_bounds_mode: PriceBoundsMode::from_u8(rng.gen::<u8>() % 2).unwrap(),
                     },
                     rate_limiter_config: RateLimiterConfig {
                         window_duration: rng.gen::<u64>(),
@@ -1983,6 +4975,392 @@ mod test {
                 let unpacked = LendingInstruction::unpack(&packed).unwrap();
                 assert_eq!(instruction, unpacked);
             }
+
+            // approve borrow allowance
+            {
+                let instruction = LendingInstruction::ApproveBorrowAllowance {
+                    liquidity_amount: rng.gen::<u64>(),
+                };
+
+                let packed = instruction.pack();
+                let unpacked = LendingInstruction::unpack(&packed).unwrap();
+                assert_eq!(instruction, unpacked);
+            }
+
+            // revoke borrow allowance
+            {
+                let instruction = LendingInstruction::RevokeBorrowAllowance;
+                let packed = instruction.pack();
+                let unpacked = LendingInstruction::unpack(&packed).unwrap();
+                assert_eq!(instruction, unpacked);
+            }
+
+            // liquidate obligation for collateral
+            {
+                let instruction = LendingInstruction::LiquidateObligationForCollateral {
+                    liquidity_amount: rng.gen::<u64>(),
+                };
+
+                let packed = instruction.pack();
+                let unpacked = LendingInstruction::unpack(&packed).unwrap();
+                assert_eq!(instruction, unpacked);
+            }
+
+            // close obligation
+            {
+                let instruction = LendingInstruction::CloseObligation;
+                let packed = instruction.pack();
+                let unpacked = LendingInstruction::unpack(&packed).unwrap();
+                assert_eq!(instruction, unpacked);
+            }
+
+            // wind down reserve
+            {
+                let instruction = LendingInstruction::WindDownReserve {
+                    ltv_decay_per_slot_wad: rng.gen::<u64>(),
+                };
+                let packed = instruction.pack();
+                let unpacked = LendingInstruction::unpack(&packed).unwrap();
+                assert_eq!(instruction, unpacked);
+            }
+
+            // close reserve
+            {
+                let instruction = LendingInstruction::CloseReserve;
+                let packed = instruction.pack();
+                let unpacked = LendingInstruction::unpack(&packed).unwrap();
+                assert_eq!(instruction, unpacked);
+            }
+
+            // set liquidation circuit breaker
+            {
+                let instruction = LendingInstruction::SetLiquidationCircuitBreaker {
+                    config: RateLimiterConfig {
+                        window_duration: rng.gen::<u64>(),
+                        max_outflow: rng.gen::<u64>(),
+                    },
+                    pause_duration: rng.gen::<u64>(),
+                };
+                let packed = instruction.pack();
+                let unpacked = LendingInstruction::unpack(&packed).unwrap();
+                assert_eq!(instruction, unpacked);
+            }
+
+            // set config timelock
+            {
+                let instruction = LendingInstruction::SetConfigTimelock {
+                    delay_slots: rng.gen::<u64>(),
+                };
+                let packed = instruction.pack();
+                let unpacked = LendingInstruction::unpack(&packed).unwrap();
+                assert_eq!(instruction, unpacked);
+            }
+
+            // activate and cancel config
+            for instruction in [
+                LendingInstruction::ActivateConfig,
+                LendingInstruction::CancelConfig,
+            ] {
+                let packed = instruction.pack();
+                let unpacked = LendingInstruction::unpack(&packed).unwrap();
+                assert_eq!(instruction, unpacked);
+            }
+
+            // set obligation limits
+            {
+                let instruction = LendingInstruction::SetObligationLimits {
+                    max_deposits: rng.gen::<u8>(),
+                    max_borrows: rng.gen::<u8>(),
+                };
+                let packed = instruction.pack();
+                let unpacked = LendingInstruction::unpack(&packed).unwrap();
+                assert_eq!(instruction, unpacked);
+            }
+
+            // set obligation health buffer
+            {
+                let instruction = LendingInstruction::SetObligationHealthBuffer {
+                    health_buffer_bps: rng.gen::<u16>(),
+                };
+                let packed = instruction.pack();
+                let unpacked = LendingInstruction::unpack(&packed).unwrap();
+                assert_eq!(instruction, unpacked);
+            }
+
+            // set collateral liquidation priority
+            {
+                let instruction = LendingInstruction::SetCollateralLiquidationPriority {
+                    liquidation_priority: rng.gen::<u8>(),
+                };
+                let packed = instruction.pack();
+                let unpacked = LendingInstruction::unpack(&packed).unwrap();
+                assert_eq!(instruction, unpacked);
+            }
+
+            // set borrow fee rebate
+            {
+                let instruction = LendingInstruction::SetBorrowFeeRebate {
+                    key: Pubkey::new_unique(),
+                    rebate_bps: rng.gen::<u16>(),
+                };
+                let packed = instruction.pack();
+                let unpacked = LendingInstruction::unpack(&packed).unwrap();
+                assert_eq!(instruction, unpacked);
+            }
+
+            // set fee redemption hook
+            {
+                let instruction = LendingInstruction::SetFeeRedemptionHook {
+                    hook_program: Pubkey::new_unique(),
+                    accounts_hash: rng.gen(),
+                };
+                let packed = instruction.pack();
+                let unpacked = LendingInstruction::unpack(&packed).unwrap();
+                assert_eq!(instruction, unpacked);
+            }
+
+            // init liquidation stats
+            {
+                let instruction = LendingInstruction::InitLiquidationStats;
+                let packed = instruction.pack();
+                let unpacked = LendingInstruction::unpack(&packed).unwrap();
+                assert_eq!(instruction, unpacked);
+            }
+
+            // deposit reserve liquidity with mode
+            {
+                let instruction = LendingInstruction::DepositReserveLiquidityWithMode {
+                    liquidity_amount: rng.gen(),
+                    mode: [
+                        None,
+                        Some(DepositMode::Collateralize),
+                        Some(DepositMode::Hold),
+                    ][rng.gen_range(0..3)],
+                };
+                let packed = instruction.pack();
+                let unpacked = LendingInstruction::unpack(&packed).unwrap();
+                assert_eq!(instruction, unpacked);
+            }
+
+            // set flash loan fee receiver
+            {
+                let instruction = LendingInstruction::SetFlashLoanFeeReceiver;
+                let packed = instruction.pack();
+                let unpacked = LendingInstruction::unpack(&packed).unwrap();
+                assert_eq!(instruction, unpacked);
+            }
+
+            // set borrow grace config
+            {
+                let instruction = LendingInstruction::SetBorrowGraceConfig {
+                    grace_slots: rng.gen(),
+                };
+                let packed = instruction.pack();
+                let unpacked = LendingInstruction::unpack(&packed).unwrap();
+                assert_eq!(instruction, unpacked);
+            }
+
+            // set cross market source
+            {
+                let instruction = LendingInstruction::SetCrossMarketSource {
+                    source_reserve: Pubkey::new_unique(),
+                    allowed: rng.gen(),
+                };
+                let packed = instruction.pack();
+                let unpacked = LendingInstruction::unpack(&packed).unwrap();
+                assert_eq!(instruction, unpacked);
+            }
+
+            // set ignore health buffer
+            {
+                let instruction = LendingInstruction::SetIgnoreHealthBuffer {
+                    ignore_health_buffer: rng.gen(),
+                };
+                let packed = instruction.pack();
+                let unpacked = LendingInstruction::unpack(&packed).unwrap();
+                assert_eq!(instruction, unpacked);
+            }
+
+            // set obligation deposit mode
+            {
+                let instruction = LendingInstruction::SetObligationDepositMode {
+                    mode: if rng.gen() {
+                        DepositMode::Hold
+                    } else {
+                        DepositMode::Collateralize
+                    },
+                };
+                let packed = instruction.pack();
+                let unpacked = LendingInstruction::unpack(&packed).unwrap();
+                assert_eq!(instruction, unpacked);
+            }
+
+            // view obligation health
+            {
+                let instruction = LendingInstruction::ViewObligationHealth;
+                let packed = instruction.pack();
+                let unpacked = LendingInstruction::unpack(&packed).unwrap();
+                assert_eq!(instruction, unpacked);
+            }
+
+            // split obligation
+            {
+                let mut reserve_amounts = || {
+                    (0..rng.gen_range(0..5))
+                        .map(|_| (Pubkey::new_unique(), rng.gen::<u64>()))
+                        .collect::<Vec<_>>()
+                };
+                let instruction = LendingInstruction::SplitObligation {
+                    deposits: reserve_amounts(),
+                    borrows: reserve_amounts(),
+                };
+                let packed = instruction.pack();
+                let unpacked = LendingInstruction::unpack(&packed).unwrap();
+                assert_eq!(instruction, unpacked);
+            }
+
+            // merge obligations
+            {
+                let instruction = LendingInstruction::MergeObligations;
+                let packed = instruction.pack();
+                let unpacked = LendingInstruction::unpack(&packed).unwrap();
+                assert_eq!(instruction, unpacked);
+            }
+
+            // set dust sweep threshold
+            {
+                let instruction = LendingInstruction::SetDustSweepThreshold {
+                    max_deposited_value_wad: rng.gen(),
+                };
+                let packed = instruction.pack();
+                let unpacked = LendingInstruction::unpack(&packed).unwrap();
+                assert_eq!(instruction, unpacked);
+            }
+
+            // sweep dust obligation
+            {
+                let instruction = LendingInstruction::SweepDustObligation;
+                let packed = instruction.pack();
+                let unpacked = LendingInstruction::unpack(&packed).unwrap();
+                assert_eq!(instruction, unpacked);
+            }
+
+            // set obligation borrow cap
+            {
+                let instruction = LendingInstruction::SetObligationBorrowCap {
+                    max_borrowed_value: rng.gen(),
+                };
+                let packed = instruction.pack();
+                let unpacked = LendingInstruction::unpack(&packed).unwrap();
+                assert_eq!(instruction, unpacked);
+            }
+
+            // set market policy
+            {
+                let instruction = LendingInstruction::SetMarketPolicy {
+                    policy_program: Pubkey::new_unique(),
+                    policy_account: Pubkey::new_unique(),
+                };
+                let packed = instruction.pack();
+                let unpacked = LendingInstruction::unpack(&packed).unwrap();
+                assert_eq!(instruction, unpacked);
+            }
+
+            // set deleverage config
+            {
+                let instruction = LendingInstruction::SetDeleverageConfig {
+                    max_exempt_value: rng.gen(),
+                };
+                let packed = instruction.pack();
+                let unpacked = LendingInstruction::unpack(&packed).unwrap();
+                assert_eq!(instruction, unpacked);
+            }
+
+            // init deleverage credit
+            {
+                let instruction = LendingInstruction::InitDeleverageCredit;
+                let packed = instruction.pack();
+                let unpacked = LendingInstruction::unpack(&packed).unwrap();
+                assert_eq!(instruction, unpacked);
+            }
+
+            // set obligation owner
+            {
+                let instruction = LendingInstruction::SetObligationOwner;
+                let packed = instruction.pack();
+                let unpacked = LendingInstruction::unpack(&packed).unwrap();
+                assert_eq!(instruction, unpacked);
+            }
+
+            // set flash loan config
+            {
+                let instruction = LendingInstruction::SetFlashLoanConfig {
+                    flash_loans_enabled: rng.gen(),
+                    max_flash_loan_amount: rng.gen(),
+                };
+                let packed = instruction.pack();
+                let unpacked = LendingInstruction::unpack(&packed).unwrap();
+                assert_eq!(instruction, unpacked);
+            }
+
+            // liquidate obligation and redeem reserve collateral by value
+            {
+                let instruction =
+                    LendingInstruction::LiquidateObligationAndRedeemReserveCollateralByValue {
+                        max_repay_value_wad: rng.gen(),
+                    };
+                let packed = instruction.pack();
+                let unpacked = LendingInstruction::unpack(&packed).unwrap();
+                assert_eq!(instruction, unpacked);
+            }
+
+            // set reserve accrual epoch
+            {
+                let instruction = LendingInstruction::SetReserveAccrualEpoch {
+                    epoch_slots: rng.gen(),
+                };
+                let packed = instruction.pack();
+                let unpacked = LendingInstruction::unpack(&packed).unwrap();
+                assert_eq!(instruction, unpacked);
+            }
+        }
+    }
+
+    #[test]
+    fn unpack_invalid_reserve_type() {
+        let configs = |reserve_type| {
+            let config = ReserveConfig {
+                reserve_type,
+                ..ReserveConfig::default()
+            };
+            vec![
+                LendingInstruction::InitReserve {
+                    liquidity_amount: 0,
+                    config,
+                }
+                .pack(),
+                LendingInstruction::UpdateReserveConfig {
+                    config,
+                    rate_limiter_config: RateLimiterConfig::default(),
+                }
+                .pack(),
+            ]
+        };
+
+        for (mut regular, isolated) in configs(ReserveType::Regular)
+            .into_iter()
+            .zip(configs(ReserveType::Isolated))
+        {
+            let asset_type = regular
+                .iter()
+                .zip(&isolated)
+                .position(|(regular, isolated)| regular != isolated)
+                .unwrap();
+            regular[asset_type] = 2;
+            assert_eq!(
+                LendingInstruction::unpack(&regular),
+                Err(LendingError::InstructionUnpackError.into())
+            );
         }
     }
 }
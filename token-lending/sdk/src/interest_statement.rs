@@ -0,0 +1,349 @@
+//! Interest statements of an obligation's borrows.
+//!
+//! [generate_interest_statement] walks through chronological [ObligationSnapshot]s of an
+//! obligation and reports, for every period between two consecutive snapshots and every reserve
+//! borrowed from, the interest accrued and the interest paid:
+//! - interest accrued is what the borrows held at the start of the period accrued until its end,
+//!   computed the way the program does, at the variable or the locked fixed rate. Borrows opened
+//!   during a period only accrue from the next one on, so snapshots should be taken at least as
+//!   often as the positions change, e.g. right after every borrow.
+//! - interest paid comes from the [RepayEvent]s over the period, see
+//!   [RepayEvent::from_lending_event]. Repayments go towards the interest accrued and not yet
+//!   paid first, the rest pays down the principal. Liquidations leave no repay event, so the debt
+//!   they repay isn't counted.
+//!
+//! Amounts are in native units of the reserve's liquidity. USD values use the market price in
+//! the snapshot ending the period, the price at the time the interest accrued or was paid.
+
+use crate::{
+    attribution::display,
+    error::LendingError,
+    events::LendingEvent,
+    math::{Decimal, TryAdd, TrySub},
+    positions::ReserveBorrow,
+    state::{Obligation, Reserve},
+};
+use serde::Serialize;
+use solana_program::{clock::Slot, program_error::ProgramError, pubkey::Pubkey};
+use std::collections::{BTreeMap, HashMap};
+
+/// An obligation and its reserves at some point in time
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ObligationSnapshot {
+    /// Slot the accounts were read at
+    pub slot: Slot,
+    /// Block time of the slot, in seconds since the unix epoch
+    pub unix_timestamp: i64,
+    /// Obligation as of the slot
+    pub obligation: Obligation,
+    /// Reserves the obligation borrows from, as of the slot
+    pub reserves: HashMap<Pubkey, Reserve>,
+}
+
+/// Liquidity repaid to a borrow of the obligation, as found in the transaction logs
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RepayEvent {
+    /// Slot of the repay transaction
+    pub slot: Slot,
+    /// Reserve the liquidity was repaid to
+    pub reserve: Pubkey,
+    /// Liquidity repaid
+    pub amount: u64,
+}
+
+impl RepayEvent {
+    /// Repay of `obligation` out of `event`, logged by a transaction at `slot`
+    pub fn from_lending_event(
+        obligation: &Pubkey,
+        slot: Slot,
+        event: &LendingEvent,
+    ) -> Option<Self> {
+        match *event {
+            LendingEvent::ObligationLiquidityRepaid {
+                obligation: repaid,
+                reserve,
+                amount,
+                ..
+            } if repaid == *obligation => Some(Self {
+                slot,
+                reserve,
+                amount,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Interest on a borrow over one period
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct InterestPeriod {
+    /// Reserve borrowed from
+    #[serde(serialize_with = "display")]
+    pub reserve: Pubkey,
+    /// Mint of the reserve liquidity
+    #[serde(serialize_with = "display")]
+    pub liquidity_mint: Pubkey,
+    /// Slot the period starts at
+    pub start_slot: Slot,
+    /// Slot the period ends at
+    pub end_slot: Slot,
+    /// Block time the period starts at
+    pub start_timestamp: i64,
+    /// Block time the period ends at
+    pub end_timestamp: i64,
+    /// Interest accrued over the period
+    pub interest_accrued: Decimal,
+    /// USD value of the interest accrued
+    pub interest_accrued_usd: Decimal,
+    /// Interest paid over the period
+    pub interest_paid: Decimal,
+    /// USD value of the interest paid
+    pub interest_paid_usd: Decimal,
+}
+
+impl InterestPeriod {
+    /// Header matching `to_csv_row`
+    pub const CSV_HEADER: &'static str = "reserve,liquidity_mint,start_slot,end_slot,\
+        start_timestamp,end_timestamp,interest_accrued,interest_accrued_usd,interest_paid,\
+        interest_paid_usd";
+
+    /// Format the period as a csv row
+    pub fn to_csv_row(&self) -> String {
+        format!(
+            "{},{},{},{},{},{},{},{},{},{}",
+            self.reserve,
+            self.liquidity_mint,
+            self.start_slot,
+            self.end_slot,
+            self.start_timestamp,
+            self.end_timestamp,
+            self.interest_accrued,
+            self.interest_accrued_usd,
+            self.interest_paid,
+            self.interest_paid_usd
+        )
+    }
+}
+
+/// Interest statement of an obligation
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct InterestStatement {
+    /// Obligation address
+    #[serde(serialize_with = "display")]
+    pub obligation: Pubkey,
+    /// Owner of the obligation in the last snapshot
+    #[serde(serialize_with = "display")]
+    pub owner: Pubkey,
+    /// Periods with interest accrued or paid, ordered by period then reserve
+    pub periods: Vec<InterestPeriod>,
+    /// USD value of the interest accrued across periods
+    pub total_interest_accrued_usd: Decimal,
+    /// USD value of the interest paid across periods
+    pub total_interest_paid_usd: Decimal,
+}
+
+impl InterestStatement {
+    /// Format the periods as csv, with a header line
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from(InterestPeriod::CSV_HEADER);
+        for period in &self.periods {
+            csv.push('\n');
+            csv.push_str(&period.to_csv_row());
+        }
+        csv
+    }
+}
+
+/// Interest the borrows of `start` accrued up to `end`, by reserve
+fn accrued_interest(
+    start: &ObligationSnapshot,
+    end: &ObligationSnapshot,
+) -> Result<BTreeMap<Pubkey, Decimal>, ProgramError> {
+    let mut accrued = BTreeMap::new();
+    for borrow in start.obligation.borrow_positions(&end.reserves) {
+        let ReserveBorrow { liquidity, reserve } = borrow?;
+        let mut reserve = reserve.clone();
+        reserve.accrue_interest(end.slot)?;
+        let mut accruing = liquidity.clone();
+        accruing.accrue_reserve_interest(&reserve, end.slot)?;
+        accrued.insert(
+            liquidity.borrow_reserve,
+            accruing
+                .borrowed_amount_wads
+                .try_sub(liquidity.borrowed_amount_wads)?,
+        );
+    }
+    Ok(accrued)
+}
+
+/// Generate the interest statement of the obligation in `snapshots`. Every snapshot has to
+/// include the reserves borrowed from in the snapshot before it.
+pub fn generate_interest_statement(
+    obligation: Pubkey,
+    snapshots: &[ObligationSnapshot],
+    repays: &[RepayEvent],
+) -> Result<InterestStatement, ProgramError> {
+    let mut snapshots = snapshots.iter().collect::<Vec<_>>();
+    snapshots.sort_by_key(|snapshot| snapshot.slot);
+
+    let mut statement = InterestStatement {
+        obligation,
+        owner: snapshots
+            .last()
+            .map(|snapshot| snapshot.obligation.owner)
+            .unwrap_or_default(),
+        ..InterestStatement::default()
+    };
+    let mut unpaid_interest: HashMap<Pubkey, Decimal> = HashMap::new();
+
+    for window in snapshots.windows(2) {
+        let (start, end) = (window[0], window[1]);
+        let mut accrued = accrued_interest(start, end)?;
+        let mut repaid: BTreeMap<Pubkey, u64> = BTreeMap::new();
+        for repay in repays
+            .iter()
+            .filter(|repay| start.slot < repay.slot && repay.slot <= end.slot)
+        {
+            let amount = repaid.entry(repay.reserve).or_default();
+            *amount = amount.saturating_add(repay.amount);
+        }
+        for reserve in repaid.keys() {
+            accrued.entry(*reserve).or_insert_with(Decimal::zero);
+        }
+
+        for (reserve_pubkey, interest_accrued) in accrued {
+            let unpaid = unpaid_interest
+                .entry(reserve_pubkey)
+                .or_insert_with(Decimal::zero);
+            *unpaid = unpaid.try_add(interest_accrued)?;
+            let interest_paid =
+                Decimal::from(repaid.get(&reserve_pubkey).copied().unwrap_or(0)).min(*unpaid);
+            *unpaid = unpaid.try_sub(interest_paid)?;
+            if interest_accrued == Decimal::zero() && interest_paid == Decimal::zero() {
+                continue;
+            }
+
+            let reserve = end
+                .reserves
+                .get(&reserve_pubkey)
+                .ok_or(LendingError::InvalidAccountInput)?;
+            let period = InterestPeriod {
+                reserve: reserve_pubkey,
+                liquidity_mint: reserve.liquidity.mint_pubkey,
+                start_slot: start.slot,
+                end_slot: end.slot,
+                start_timestamp: start.unix_timestamp,
+                end_timestamp: end.unix_timestamp,
+                interest_accrued,
+                interest_accrued_usd: reserve.market_value(interest_accrued)?,
+                interest_paid,
+                interest_paid_usd: reserve.market_value(interest_paid)?,
+            };
+            statement.total_interest_accrued_usd = statement
+                .total_interest_accrued_usd
+                .try_add(period.interest_accrued_usd)?;
+            statement.total_interest_paid_usd = statement
+                .total_interest_paid_usd
+                .try_add(period.interest_paid_usd)?;
+            statement.periods.push(period);
+        }
+    }
+
+    Ok(statement)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::state::{LastUpdate, ObligationLiquidity, ReserveLiquidity};
+
+    fn reserve(slot: Slot, cumulative_borrow_rate: u64, market_price: u64) -> Reserve {
+        Reserve {
+            last_update: LastUpdate { slot, stale: false },
+            liquidity: ReserveLiquidity {
+                mint_decimals: 0,
+                cumulative_borrow_rate_wads: Decimal::from(cumulative_borrow_rate),
+                market_price: Decimal::from(market_price),
+                ..ReserveLiquidity::default()
+            },
+            ..Reserve::default()
+        }
+    }
+
+    fn snapshot(
+        slot: Slot,
+        reserve_pubkey: Pubkey,
+        reserve: Reserve,
+        borrowed_amount: u64,
+    ) -> ObligationSnapshot {
+        ObligationSnapshot {
+            slot,
+            unix_timestamp: slot as i64 * 2,
+            obligation: Obligation {
+                borrows: vec![ObligationLiquidity {
+                    borrowed_amount_wads: Decimal::from(borrowed_amount),
+                    ..ObligationLiquidity::new(
+                        reserve_pubkey,
+                        reserve.liquidity.cumulative_borrow_rate_wads,
+                    )
+                }],
+                ..Obligation::default()
+            },
+            reserves: HashMap::from([(reserve_pubkey, reserve)]),
+        }
+    }
+
+    #[test]
+    fn interest_accrued_and_paid() {
+        let obligation = Pubkey::new_unique();
+        let usdc = Pubkey::new_unique();
+        // 1000 borrowed, then the cumulative borrow rate doubles twice, with 1500 repaid in the
+        // second period. The reserves carry their rate as of each slot and accrue nothing more.
+        let snapshots = vec![
+            snapshot(300, usdc, reserve(300, 4, 2), 500),
+            snapshot(100, usdc, reserve(100, 1, 1), 1_000),
+            snapshot(200, usdc, reserve(200, 2, 2), 2_000),
+        ];
+        let repays = [
+            RepayEvent {
+                slot: 250,
+                reserve: usdc,
+                amount: 1_500,
+            },
+            // outside of the snapshots
+            RepayEvent {
+                slot: 400,
+                reserve: usdc,
+                amount: 500,
+            },
+        ];
+
+        let statement = generate_interest_statement(obligation, &snapshots, &repays).unwrap();
+        assert_eq!(statement.periods.len(), 2);
+        let first = &statement.periods[0];
+        assert_eq!((first.start_slot, first.end_slot), (100, 200));
+        assert_eq!(first.start_timestamp, 200);
+        assert_eq!(first.interest_accrued, Decimal::from(1_000u64));
+        assert_eq!(first.interest_accrued_usd, Decimal::from(2_000u64));
+        assert_eq!(first.interest_paid, Decimal::zero());
+
+        // 2000 accrued, of which 1500 repaid: the 1000 left unpaid from the first period, then
+        // 500 of the second's
+        let second = &statement.periods[1];
+        assert_eq!(second.interest_accrued, Decimal::from(2_000u64));
+        assert_eq!(second.interest_paid, Decimal::from(1_500u64));
+        assert_eq!(second.interest_paid_usd, Decimal::from(3_000u64));
+        assert_eq!(
+            statement.total_interest_accrued_usd,
+            Decimal::from(6_000u64)
+        );
+        assert_eq!(statement.total_interest_paid_usd, Decimal::from(3_000u64));
+
+        let csv = statement.to_csv();
+        assert_eq!(csv.lines().count(), 3);
+        assert_eq!(
+            csv.lines().nth(1).unwrap().split(',').count(),
+            InterestPeriod::CSV_HEADER.split(',').count()
+        );
+    }
+}
@@ -0,0 +1,283 @@
+//! Consistency checks for reserve and obligation accounts.
+//!
+//! The program runs these after every refresh when built with the `strict-invariants` feature.
+//! Off-chain, they can be used to flag corrupted or anomalous accounts.
+
+use crate::{
+    error::LendingError,
+    math::{Decimal, TryAdd},
+    state::{
+        validate_reserve_config, Obligation, Reserve, ReserveType, MAX_OBLIGATION_RESERVES,
+        MAX_SLOTS_PER_YEAR, MIN_SLOTS_PER_YEAR,
+    },
+};
+use solana_program::{entrypoint::ProgramResult, msg, pubkey::Pubkey};
+
+/// Check that a reserve's config is valid and its liquidity accounting is consistent
+pub fn check_reserve_invariants(reserve: &Reserve) -> ProgramResult {
+    validate_reserve_config(reserve.config)?;
+
+    if reserve.liquidity.cumulative_borrow_rate_wads < Decimal::one() {
+        msg!("Reserve cumulative borrow rate is less than 1");
+        return Err(LendingError::InvariantViolation.into());
+    }
+
+    if !(MIN_SLOTS_PER_YEAR..=MAX_SLOTS_PER_YEAR).contains(&reserve.slots_per_year) {
+        msg!("Reserve slots per year is out of bounds");
+        return Err(LendingError::InvariantViolation.into());
+    }
+
+    // protocol fees are paid out of the reserve's liquidity, so they can never exceed it
+    if reserve.liquidity.total_supply().is_err() {
+        msg!("Reserve accumulated protocol fees exceed its total liquidity");
+        return Err(LendingError::InvariantViolation.into());
+    }
+
+    Ok(())
+}
+
+/// Check that an obligation's positions and values are consistent. `reserves` must contain every
+/// reserve the obligation has a deposit in or a borrow from.
+///
+/// Cached values are only compared against the positions when the obligation isn't stale, since
+/// instructions other than RefreshObligation change positions without updating them.
+pub fn check_obligation_invariants(
+    obligation: &Obligation,
+    reserves: &[(Pubkey, Reserve)],
+) -> ProgramResult {
+    if obligation.deposits.len() + obligation.borrows.len() > MAX_OBLIGATION_RESERVES {
+        msg!(
+            "Obligation has more than {} deposits and borrows combined",
+            MAX_OBLIGATION_RESERVES
+        );
+        return Err(LendingError::InvariantViolation.into());
+    }
+
+    for (index, collateral) in obligation.deposits.iter().enumerate() {
+        if obligation.deposits[..index]
+            .iter()
+            .any(|other| other.deposit_reserve == collateral.deposit_reserve)
+        {
+            msg!("Obligation has duplicate deposits in a reserve");
+            return Err(LendingError::InvariantViolation.into());
+        }
+        find_reserve(reserves, &collateral.deposit_reserve)?;
+    }
+
+    let mut borrowing_isolated_asset = false;
+    for (index, liquidity) in obligation.borrows.iter().enumerate() {
+        if obligation.borrows[..index]
+            .iter()
+            .any(|other| other.borrow_reserve == liquidity.borrow_reserve)
+        {
+            msg!("Obligation has duplicate borrows from a reserve");
+            return Err(LendingError::InvariantViolation.into());
+        }
+
+        let borrow_reserve = find_reserve(reserves, &liquidity.borrow_reserve)?;
+        if liquidity.cumulative_borrow_rate_wads < Decimal::one()
+            || liquidity.cumulative_borrow_rate_wads
+                > borrow_reserve.liquidity.cumulative_borrow_rate_wads
+        {
+            msg!("Obligation borrow has a cumulative borrow rate outside of [1, reserve rate]");
+            return Err(LendingError::InvariantViolation.into());
+        }
+
+        if borrow_reserve.config.reserve_type == ReserveType::Isolated {
+            borrowing_isolated_asset = true;
+        }
+    }
+
+    if obligation.allowed_borrow_value > obligation.unhealthy_borrow_value
+        || obligation.unhealthy_borrow_value > obligation.super_unhealthy_borrow_value
+    {
+        msg!("Obligation borrow limits are out of order");
+        return Err(LendingError::InvariantViolation.into());
+    }
+
+    if obligation.last_update.stale {
+        return Ok(());
+    }
+
+    let deposited_value = obligation
+        .deposits
+        .iter()
+        .try_fold(Decimal::zero(), |acc, collateral| {
+            acc.try_add(collateral.market_value)
+        })?;
+    if deposited_value != obligation.deposited_value {
+        msg!("Obligation deposited value doesn't match its deposits");
+        return Err(LendingError::InvariantViolation.into());
+    }
+
+    // borrow weights are at least 1, so the weighted borrowed value can't be smaller
+    let borrowed_value = obligation
+        .borrows
+        .iter()
+        .try_fold(Decimal::zero(), |acc, liquidity| {
+            acc.try_add(liquidity.market_value)
+        })?;
+    if borrowed_value > obligation.borrowed_value
+        || obligation.borrowed_value > obligation.borrowed_value_upper_bound
+    {
+        msg!("Obligation borrowed value doesn't match its borrows");
+        return Err(LendingError::InvariantViolation.into());
+    }
+
+    // the flag may outlive a zeroed isolated borrow that was dropped during the refresh
+    if borrowing_isolated_asset && !obligation.borrowing_isolated_asset {
+        msg!("Obligation borrows an isolated asset but isn't flagged as doing so");
+        return Err(LendingError::InvariantViolation.into());
+    }
+
+    Ok(())
+}
+
+fn find_reserve<'a>(
+    reserves: &'a [(Pubkey, Reserve)],
+    reserve_pubkey: &Pubkey,
+) -> Result<&'a Reserve, LendingError> {
+    reserves
+        .iter()
+        .find(|(pubkey, _)| pubkey == reserve_pubkey)
+        .map(|(_, reserve)| reserve)
+        .ok_or_else(|| {
+            msg!(
+                "Reserve {} of obligation position not provided",
+                reserve_pubkey
+            );
+            LendingError::InvariantViolation
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::state::{
+        LastUpdate, ObligationCollateral, ObligationLiquidity, ReserveLiquidity, SLOTS_PER_YEAR,
+    };
+    use solana_program::program_error::ProgramError;
+
+    fn reserve() -> Reserve {
+        Reserve {
+            liquidity: ReserveLiquidity {
+                available_amount: 100,
+                cumulative_borrow_rate_wads: Decimal::from(2u64),
+                ..ReserveLiquidity::default()
+            },
+            slots_per_year: SLOTS_PER_YEAR,
+            ..Reserve::default()
+        }
+    }
+
+    fn obligation(deposit_reserve: Pubkey, borrow_reserve: Pubkey) -> Obligation {
+        Obligation {
+            last_update: LastUpdate {
+                slot: 0,
+                stale: false,
+            },
+            deposits: vec![ObligationCollateral {
+                deposit_reserve,
+                deposited_amount: 10,
+                market_value: Decimal::from(10u64),
+                liquidation_priority: 0,
+            }],
+            borrows: vec![ObligationLiquidity {
+                borrow_reserve,
+                cumulative_borrow_rate_wads: Decimal::one(),
+                borrowed_amount_wads: Decimal::from(5u64),
+                market_value: Decimal::from(5u64),
+                ..ObligationLiquidity::default()
+            }],
+            deposited_value: Decimal::from(10u64),
+            borrowed_value: Decimal::from(5u64),
+            borrowed_value_upper_bound: Decimal::from(6u64),
+            allowed_borrow_value: Decimal::from(7u64),
+            unhealthy_borrow_value: Decimal::from(8u64),
+            super_unhealthy_borrow_value: Decimal::from(9u64),
+            ..Obligation::default()
+        }
+    }
+
+    #[test]
+    fn reserve_invariants() {
+        assert_eq!(check_reserve_invariants(&reserve()), Ok(()));
+
+        let mut bad_rate = reserve();
+        bad_rate.liquidity.cumulative_borrow_rate_wads = Decimal::zero();
+        assert_eq!(
+            check_reserve_invariants(&bad_rate),
+            Err(LendingError::InvariantViolation.into())
+        );
+
+        let mut bad_slots_per_year = reserve();
+        bad_slots_per_year.slots_per_year = 0;
+        assert_eq!(
+            check_reserve_invariants(&bad_slots_per_year),
+            Err(LendingError::InvariantViolation.into())
+        );
+
+        let mut excess_fees = reserve();
+        excess_fees.liquidity.accumulated_protocol_fees_wads = Decimal::from(101u64);
+        assert_eq!(
+            check_reserve_invariants(&excess_fees),
+            Err(LendingError::InvariantViolation.into())
+        );
+
+        let mut bad_config = reserve();
+        bad_config.config.optimal_utilization_rate = 101;
+        assert_eq!(
+            check_reserve_invariants(&bad_config),
+            Err(LendingError::InvalidConfig.into())
+        );
+    }
+
+    #[test]
+    fn obligation_invariants() {
+        let deposit_reserve = Pubkey::new_unique();
+        let borrow_reserve = Pubkey::new_unique();
+        let reserves = vec![(deposit_reserve, reserve()), (borrow_reserve, reserve())];
+        let violation: Result<(), ProgramError> = Err(LendingError::InvariantViolation.into());
+
+        let valid = obligation(deposit_reserve, borrow_reserve);
+        assert_eq!(check_obligation_invariants(&valid, &reserves), Ok(()));
+
+        // missing reserve
+        assert_eq!(
+            check_obligation_invariants(&valid, &reserves[..1]),
+            violation
+        );
+
+        // duplicate deposit
+        let mut duplicate = valid.clone();
+        duplicate.deposits.push(duplicate.deposits[0].clone());
+        assert_eq!(
+            check_obligation_invariants(&duplicate, &reserves),
+            violation
+        );
+
+        // borrow accrued past its reserve
+        let mut ahead = valid.clone();
+        ahead.borrows[0].cumulative_borrow_rate_wads = Decimal::from(3u64);
+        assert_eq!(check_obligation_invariants(&ahead, &reserves), violation);
+
+        // thresholds out of order
+        let mut unordered = valid.clone();
+        unordered.allowed_borrow_value = Decimal::from(9u64);
+        unordered.unhealthy_borrow_value = Decimal::from(8u64);
+        assert_eq!(
+            check_obligation_invariants(&unordered, &reserves),
+            violation
+        );
+
+        // cached values only have to match positions when the obligation is fresh
+        let mut mismatched = valid;
+        mismatched.deposited_value = Decimal::from(11u64);
+        assert_eq!(
+            check_obligation_invariants(&mismatched, &reserves),
+            violation
+        );
+        mismatched.last_update.mark_stale();
+        assert_eq!(check_obligation_invariants(&mismatched, &reserves), Ok(()));
+    }
+}
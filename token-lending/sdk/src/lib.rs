@@ -2,12 +2,77 @@
 
 //! A lending program for the Solana blockchain.
 
+#[macro_use]
+mod telemetry;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub mod add_reserve;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod attribution;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod balance_snapshot;
+pub mod borrowing_power;
+#[cfg(all(feature = "parallel", not(target_arch = "wasm32")))]
+pub mod bulk_refresh;
+pub mod cluster;
+pub mod config_simulator;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod config_watcher;
+pub mod cpi;
 pub mod error;
+pub mod error_context;
+pub mod events;
+#[cfg(all(feature = "export", not(target_arch = "wasm32")))]
+pub mod export;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod exposure;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod frozen_accounts;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod health_monitor;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod host_fee;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod idempotency;
 pub mod instruction;
+pub mod interest_statement;
+pub mod invariants;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod market_config;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod market_metrics;
 pub mod math;
+#[cfg(not(target_arch = "wasm32"))]
 pub mod offchain_utils;
 pub mod oracles;
+pub mod outflow_schedule;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod portfolio;
+pub mod positions;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod rebasing;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod reconciliation;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod registry;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod resilient_rpc;
+pub mod risk_band;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod slot_clock;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod solvency;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod stale_positions;
 pub mod state;
+#[cfg(test)]
+mod test_utils;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod transaction_builder;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod unwind;
+#[cfg(feature = "wasm")]
+pub mod wasm;
 
 // Export current sdk types for downstream users building with a different sdk version
 pub use solana_program;
@@ -19,6 +84,11 @@ pub mod solend_mainnet {
 
 /// devnet program id
 pub mod solend_devnet {
+    solana_program::declare_id!("ALend7Ketfx5bxh6ghsCDXAoDrhvEmsXT3cynB6aPLgx");
+}
+
+/// testnet program id, the same address as mainnet
+pub mod solend_testnet {
     solana_program::declare_id!("So1endDq2YkqhipRh3WViPa8hdiSpxWy6z3Z6tMCpAo");
 }
 
@@ -29,6 +99,11 @@ pub const NULL_PUBKEY: solana_program::pubkey::Pubkey =
         138, 64, 177, 28, 19, 90, 156, 0, 0, 0, 0, 0,
     ]);
 
+/// Program id of the SPL associated token account program.
+pub mod spl_associated_token_account {
+    solana_program::declare_id!("ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL");
+}
+
 /// Mainnet program id for Switchboard v2.
 pub mod switchboard_v2_mainnet {
     solana_program::declare_id!("SW1TCH7qEPTdLsDHRgPuMQjbQxKdH2aBStViMFnt64f");
@@ -38,3 +113,18 @@ pub mod switchboard_v2_mainnet {
 pub mod switchboard_v2_devnet {
     solana_program::declare_id!("2TfB33aLaneQb5TNVwyDz3jSZXS6jdW2ARw1Dgf84XCG");
 }
+
+/// Mainnet program id for Pyth.
+pub mod pyth_mainnet {
+    solana_program::declare_id!("FsJ3A3u2vn5cTVofAjvy6y5kwABJAqYWpe4975bi2epH");
+}
+
+/// Devnet program id for Pyth.
+pub mod pyth_devnet {
+    solana_program::declare_id!("gSbePebfvPy7tRqimPoVecS2UsBvYv46ynrzWocc92s");
+}
+
+/// Testnet program id for Pyth.
+pub mod pyth_testnet {
+    solana_program::declare_id!("8tfDNiaEyrV6Q1U4DEXrEigs9DoDtkugzFbybENEbCDz");
+}
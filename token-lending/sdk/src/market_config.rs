@@ -0,0 +1,1050 @@
+//! Declarative lending market deployments.
+//!
+//! A [MarketConfig] describes everything the owner of a lending market manages: the market's
+//! owner, risk authority, whitelisted liquidator and rate limiter, its metadata, and every
+//! reserve with its config, oracles and rate limiter. It (de)serializes with serde, e.g. from
+//! TOML or JSON, with pubkeys in base58 and every value in its on-chain unit (percentages, bps,
+//! wads, slots), so a file maps one to one onto the accounts it describes.
+//!
+//! [reconcile] diffs a config against a [MarketState] fetched with [fetch_market_state] and
+//! returns the [MarketAction]s converging the market to it, each with the instructions to send:
+//!
+//! ```toml
+//! lending_market = "4UpD2fh7xH3VP9QQaXtsS1YY3bxzWhtfpks7FatyKvdY"
+//! owner = "5pHk2TmnqQzRF9L6egy5FfiyBgS7G9cMZ5RFaJAvghzw"
+//! risk_authority = "5pHk2TmnqQzRF9L6egy5FfiyBgS7G9cMZ5RFaJAvghzw"
+//! rate_limiter = { window_duration = 216000, max_outflow = 1000000 }
+//!
+//! [metadata]
+//! name = "Main Pool"
+//! description = ""
+//! image_url = ""
+//!
+//! [[reserves]]
+//! symbol = "SOL"
+//! liquidity_mint = "So11111111111111111111111111111111111111112"
+//! initial_liquidity = 1000000
+//! oracles = { pyth_product = "ALP8SdU9oARYVLgLR7LrqMNCYBnhtnQz1cj6bwgwQmgj", pyth_price = "H6ARHf6YXhGYeQfUzQNGk6rDNnLBQKrenN712K4AQJEG" }
+//! rate_limiter = { window_duration = 216000, max_outflow = 1000000000000 }
+//!
+//! [reserves.config]
+//! optimal_utilization_rate = 80
+//! # ...every other ReserveConfig field
+//! ```
+//!
+//! Reserves are never removed: reserves of the market that the config doesn't list are only
+//! reported. When the market has a config timelock, reserve and market config updates are
+//! staged by the program and still need to be activated once the timelock elapses.
+
+use crate::{
+    add_reserve::{AddReserveBuilder, AddReserveKeypairs},
+    balance_snapshot::base58,
+    config_watcher::{lending_market_config_changes, reserve_config_changes, ConfigChange},
+    instruction::{
+        get_associated_token_address, set_lending_market_owner_and_config_with_slots_per_year,
+        update_market_metadata, update_reserve_config,
+    },
+    registry::get_market_reserves,
+    state::{
+        validate_reserve_config, LendingMarket, LendingMarketMetadata, PriceBoundsMode,
+        RateLimiterConfig, Reserve, ReserveConfig, ReserveFees, ReserveType,
+    },
+    NULL_PUBKEY,
+};
+use bytemuck::{checked::try_from_bytes, Zeroable};
+use serde::{Deserialize, Serialize};
+use solana_client::rpc_client::RpcClient;
+use solana_program::{
+    instruction::Instruction,
+    program_error::ProgramError,
+    program_pack::Pack,
+    pubkey::{Pubkey, PUBKEY_BYTES},
+};
+use solana_sdk::signer::Signer;
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    fmt,
+};
+
+/// Desired state of a lending market
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MarketConfig {
+    /// Lending market address
+    #[serde(with = "base58")]
+    pub lending_market: Pubkey,
+    /// Owner of the lending market
+    #[serde(with = "base58")]
+    pub owner: Pubkey,
+    /// Risk authority of the lending market
+    #[serde(with = "base58")]
+    pub risk_authority: Pubkey,
+    /// Whitelisted liquidator, None to let anyone liquidate
+    #[serde(
+        default,
+        with = "base58_option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub whitelisted_liquidator: Option<Pubkey>,
+    /// Slots per year the market compounds interest with, left as is if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub slots_per_year: Option<u64>,
+    /// Outflow rate limiter of the market, in the quote currency
+    #[serde(with = "RateLimiterConfigDef")]
+    pub rate_limiter: RateLimiterConfig,
+    /// Metadata of the market, left as is if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<MarketMetadata>,
+    /// Reserves of the market
+    #[serde(default)]
+    pub reserves: Vec<ReserveDeployment>,
+}
+
+/// Lending market metadata. The lookup tables already on chain are kept.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MarketMetadata {
+    /// Market name, at most MARKET_NAME_SIZE bytes
+    pub name: String,
+    /// Market description, at most MARKET_DESCRIPTION_SIZE bytes
+    pub description: String,
+    /// Market image url, at most MARKET_IMAGE_URL_SIZE bytes
+    pub image_url: String,
+}
+
+impl MarketMetadata {
+    /// On-chain metadata with these strings, keeping the rest of `current`
+    pub fn to_lending_market_metadata(
+        &self,
+        current: Option<&LendingMarketMetadata>,
+    ) -> Result<LendingMarketMetadata, MarketConfigError> {
+        let mut metadata = current
+            .copied()
+            .unwrap_or_else(LendingMarketMetadata::zeroed);
+        metadata.market_name = null_padded("name", &self.name)?;
+        metadata.market_description = null_padded("description", &self.description)?;
+        metadata.market_image_url = null_padded("image_url", &self.image_url)?;
+        Ok(metadata)
+    }
+}
+
+fn null_padded<const N: usize>(
+    field: &'static str,
+    value: &str,
+) -> Result<[u8; N], MarketConfigError> {
+    if value.len() > N {
+        return Err(MarketConfigError::MetadataTooLong { field });
+    }
+    let mut bytes = [0u8; N];
+    bytes[..value.len()].copy_from_slice(value.as_bytes());
+    Ok(bytes)
+}
+
+/// Price oracles of a reserve. At least one of the pyth price and switchboard feed must be set.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ReserveOracles {
+    /// Pyth product account, only needed when the pyth price is set or changed
+    #[serde(
+        default,
+        with = "base58_option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub pyth_product: Option<Pubkey>,
+    /// Pyth price account
+    #[serde(
+        default,
+        with = "base58_option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub pyth_price: Option<Pubkey>,
+    /// Switchboard feed
+    #[serde(
+        default,
+        with = "base58_option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub switchboard_feed: Option<Pubkey>,
+}
+
+impl ReserveOracles {
+    fn pyth_product(&self) -> Pubkey {
+        self.pyth_product.unwrap_or(NULL_PUBKEY)
+    }
+
+    fn pyth_price(&self) -> Pubkey {
+        self.pyth_price.unwrap_or(NULL_PUBKEY)
+    }
+
+    fn switchboard_feed(&self) -> Pubkey {
+        self.switchboard_feed.unwrap_or(NULL_PUBKEY)
+    }
+}
+
+/// Desired state of a reserve
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ReserveDeployment {
+    /// Name the reserve is referred to by in plans and errors, e.g. its liquidity symbol
+    pub symbol: String,
+    /// Liquidity mint of the reserve
+    #[serde(with = "base58")]
+    pub liquidity_mint: Pubkey,
+    /// Reserve address. If not set, the market's reserve of `liquidity_mint`, which is created
+    /// if the market has none
+    #[serde(
+        default,
+        with = "base58_option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub address: Option<Pubkey>,
+    /// Liquidity deposited from the lending market owner's associated token account when the
+    /// reserve is created
+    #[serde(default)]
+    pub initial_liquidity: u64,
+    /// Price oracles
+    pub oracles: ReserveOracles,
+    /// Outflow rate limiter of the reserve, in liquidity tokens
+    #[serde(with = "RateLimiterConfigDef")]
+    pub rate_limiter: RateLimiterConfig,
+    /// Reserve config. A default fee receiver keeps the one the reserve has, or the one created
+    /// with it.
+    #[serde(with = "ReserveConfigDef")]
+    pub config: ReserveConfig,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "RateLimiterConfig", deny_unknown_fields)]
+struct RateLimiterConfigDef {
+    window_duration: u64,
+    max_outflow: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "ReserveFees", deny_unknown_fields)]
+struct ReserveFeesDef {
+    borrow_fee_wad: u64,
+    flash_loan_fee_wad: u64,
+    host_fee_percentage: u8,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "ReserveType", rename_all = "snake_case")]
+enum ReserveTypeDef {
+    Regular,
+    Isolated,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "PriceBoundsMode", rename_all = "snake_case")]
+enum PriceBoundsModeDef {
+    Reject,
+    Clamp,
+}
+
+// fields that are 0 or unset when the feature is off default, the others are required
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "ReserveConfig", deny_unknown_fields)]
+struct ReserveConfigDef {
+    optimal_utilization_rate: u8,
+    max_utilization_rate: u8,
+    loan_to_value_ratio: u8,
+    liquidation_bonus: u8,
+    max_liquidation_bonus: u8,
+    liquidation_threshold: u8,
+    max_liquidation_threshold: u8,
+    min_borrow_rate: u8,
+    optimal_borrow_rate: u8,
+    max_borrow_rate: u8,
+    super_max_borrow_rate: u64,
+    deposit_limit: u64,
+    borrow_limit: u64,
+    #[serde(default, with = "base58")]
+    fee_receiver: Pubkey,
+    protocol_liquidation_fee: u8,
+    protocol_take_rate: u8,
+    #[serde(default)]
+    added_borrow_weight_bps: u64,
+    #[serde(default, with = "ReserveTypeDef")]
+    reserve_type: ReserveType,
+    #[serde(default)]
+    deposit_soft_cap_pct: u8,
+    #[serde(default)]
+    borrow_soft_cap_pct: u8,
+    #[serde(default)]
+    idle_liquidity_fee_bps: u64,
+    #[serde(
+        default,
+        with = "base58_option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    liquidation_fee_receiver: Option<Pubkey>,
+    #[serde(default)]
+    borrow_utilization_cap: u8,
+    #[serde(default)]
+    fixed_borrow_rate_bps: u16,
+    #[serde(default)]
+    fixed_borrow_max_duration_slots: u64,
+    #[serde(default)]
+    withdraw_fee_bps: u16,
+    #[serde(default)]
+    min_price_bps: u32,
+    #[serde(default)]
+    max_price_bps: u32,
+    #[serde(default, with = "PriceBoundsModeDef")]
+    price_bounds_mode: PriceBoundsMode,
+    #[serde(with = "ReserveFeesDef")]
+    fees: ReserveFees,
+}
+
+mod base58_option {
+    use super::*;
+    use serde::{Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        value: &Option<Pubkey>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match value {
+            Some(pubkey) => base58::serialize(pubkey, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Pubkey>, D::Error> {
+        #[derive(Deserialize)]
+        struct Wrapper(#[serde(with = "base58")] Pubkey);
+
+        Ok(Option::<Wrapper>::deserialize(deserializer)?.map(|Wrapper(pubkey)| pubkey))
+    }
+}
+
+/// On-chain state of a lending market, as far as a [MarketConfig] describes it
+#[derive(Clone, Debug)]
+pub struct MarketState {
+    /// Lending market address
+    pub lending_market_pubkey: Pubkey,
+    /// Lending market
+    pub lending_market: LendingMarket,
+    /// Metadata of the lending market, if it has any
+    pub metadata: Option<LendingMarketMetadata>,
+    /// Every reserve of the lending market
+    pub reserves: HashMap<Pubkey, Reserve>,
+}
+
+/// Fetch a lending market, its metadata and its reserves
+pub fn fetch_market_state(
+    client: &RpcClient,
+    program_id: &Pubkey,
+    lending_market_pubkey: &Pubkey,
+) -> Result<MarketState, Box<dyn Error>> {
+    let lending_market = LendingMarket::unpack(&client.get_account_data(lending_market_pubkey)?)?;
+
+    let (metadata_pubkey, _) = Pubkey::find_program_address(
+        &[
+            &lending_market_pubkey.to_bytes()[..PUBKEY_BYTES],
+            b"MetaData",
+        ],
+        program_id,
+    );
+    let metadata = client
+        .get_account_with_commitment(&metadata_pubkey, client.commitment())?
+        .value
+        .and_then(|account| {
+            try_from_bytes::<LendingMarketMetadata>(&account.data)
+                .ok()
+                .copied()
+        });
+
+    let reserves = get_market_reserves(client, program_id, lending_market_pubkey)?
+        .into_iter()
+        .collect();
+
+    Ok(MarketState {
+        lending_market_pubkey: *lending_market_pubkey,
+        lending_market,
+        metadata,
+        reserves,
+    })
+}
+
+/// A step converging a lending market to its [MarketConfig]
+#[derive(Debug)]
+pub enum MarketAction {
+    /// Write the lending market metadata
+    UpdateMetadata {
+        /// UpdateMarketMetadata instruction
+        instruction: Instruction,
+    },
+    /// Create a reserve, funded by the payer and seeded by the lending market owner
+    AddReserve {
+        /// Reserve symbol in the config
+        symbol: String,
+        /// Address of the new reserve
+        reserve: Pubkey,
+        /// Builder of the transactions creating the reserve
+        builder: Box<AddReserveBuilder>,
+    },
+    /// Update the config, rate limiter or oracles of a reserve
+    UpdateReserve {
+        /// Reserve symbol in the config
+        symbol: String,
+        /// Reserve address
+        reserve: Pubkey,
+        /// Config and rate limiter fields that change
+        changes: Vec<ConfigChange>,
+        /// New oracles of the reserve, if they change
+        oracles: Option<ReserveOracles>,
+        /// UpdateReserveConfig instruction
+        instruction: Instruction,
+    },
+    /// Update the owner and config of the lending market
+    SetLendingMarketConfig {
+        /// Fields that change
+        changes: Vec<ConfigChange>,
+        /// SetLendingMarketOwnerAndConfig instruction
+        instruction: Instruction,
+    },
+}
+
+/// Actions converging a lending market to its [MarketConfig], see [reconcile]
+#[derive(Debug, Default)]
+pub struct MarketPlan {
+    /// Actions to execute in order
+    pub actions: Vec<MarketAction>,
+    /// Reserves of the market the config doesn't list, left as they are
+    pub unmanaged_reserves: Vec<Pubkey>,
+}
+
+impl MarketPlan {
+    /// True if the market already matches its config
+    pub fn is_empty(&self) -> bool {
+        self.actions.is_empty()
+    }
+}
+
+/// Why a [MarketConfig] can't be reconciled with its market
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MarketConfigError {
+    /// The config describes another lending market than the fetched one
+    WrongLendingMarket {
+        /// Lending market of the config
+        expected: Pubkey,
+        /// Lending market fetched
+        actual: Pubkey,
+    },
+    /// A reserve address of the config isn't a reserve of the lending market
+    ReserveNotFound {
+        /// Reserve symbol in the config
+        symbol: String,
+    },
+    /// A reserve of the config has another liquidity mint on chain
+    LiquidityMintMismatch {
+        /// Reserve symbol in the config
+        symbol: String,
+    },
+    /// The lending market has several reserves of the liquidity mint, pick one with `address`
+    AmbiguousReserve {
+        /// Reserve symbol in the config
+        symbol: String,
+    },
+    /// Several reserves of the config are the same reserve
+    DuplicateReserve {
+        /// Reserve symbol in the config
+        symbol: String,
+    },
+    /// The reserve config doesn't pass validation
+    InvalidReserveConfig {
+        /// Reserve symbol in the config
+        symbol: String,
+        /// Validation error
+        error: ProgramError,
+    },
+    /// The reserve has no price oracle, or a new pyth price without its product
+    InvalidOracles {
+        /// Reserve symbol in the config
+        symbol: String,
+    },
+    /// A reserve to create has no initial liquidity
+    MissingInitialLiquidity {
+        /// Reserve symbol in the config
+        symbol: String,
+    },
+    /// A metadata string doesn't fit its field
+    MetadataTooLong {
+        /// Metadata field
+        field: &'static str,
+    },
+}
+
+impl fmt::Display for MarketConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MarketConfigError::WrongLendingMarket { expected, actual } => write!(
+                f,
+                "config is for lending market {}, not {}",
+                expected, actual
+            ),
+            MarketConfigError::ReserveNotFound { symbol } => {
+                write!(f, "reserve {} is not in the lending market", symbol)
+            }
+            MarketConfigError::LiquidityMintMismatch { symbol } => {
+                write!(f, "reserve {} has another liquidity mint", symbol)
+            }
+            MarketConfigError::AmbiguousReserve { symbol } => write!(
+                f,
+                "lending market has several reserves of the {} mint, set their address",
+                symbol
+            ),
+            MarketConfigError::DuplicateReserve { symbol } => {
+                write!(f, "reserve {} is listed more than once", symbol)
+            }
+            MarketConfigError::InvalidReserveConfig { symbol, error } => {
+                write!(f, "reserve {} config is invalid: {}", symbol, error)
+            }
+            MarketConfigError::InvalidOracles { symbol } => write!(
+                f,
+                "reserve {} needs a pyth price or switchboard feed, and a pyth product with a new pyth price",
+                symbol
+            ),
+            MarketConfigError::MissingInitialLiquidity { symbol } => {
+                write!(f, "new reserve {} needs initial liquidity", symbol)
+            }
+            MarketConfigError::MetadataTooLong { field } => {
+                write!(f, "metadata {} is too long", field)
+            }
+        }
+    }
+}
+
+impl Error for MarketConfigError {}
+
+/// Actions converging the market in `state` to `config`. The lending market owner on chain
+/// signs every action, and `payer` funds the accounts of new reserves. Actions are ordered so
+/// metadata and reserves are handled before the lending market config, which may hand the market
+/// to a new owner.
+pub fn reconcile(
+    program_id: &Pubkey,
+    config: &MarketConfig,
+    state: &MarketState,
+    payer: &Pubkey,
+) -> Result<MarketPlan, MarketConfigError> {
+    if config.lending_market != state.lending_market_pubkey {
+        return Err(MarketConfigError::WrongLendingMarket {
+            expected: config.lending_market,
+            actual: state.lending_market_pubkey,
+        });
+    }
+    let lending_market_pubkey = state.lending_market_pubkey;
+    let owner = state.lending_market.owner;
+    let mut plan = MarketPlan::default();
+
+    if let Some(metadata) = &config.metadata {
+        let desired = metadata.to_lending_market_metadata(state.metadata.as_ref())?;
+        if state.metadata != Some(desired) {
+            plan.actions.push(MarketAction::UpdateMetadata {
+                instruction: update_market_metadata(
+                    *program_id,
+                    desired,
+                    lending_market_pubkey,
+                    owner,
+                ),
+            });
+        }
+    }
+
+    let mut managed = HashSet::new();
+    for deployment in &config.reserves {
+        let symbol = || deployment.symbol.clone();
+        let oracles = &deployment.oracles;
+        if oracles.pyth_price.is_none() && oracles.switchboard_feed.is_none() {
+            return Err(MarketConfigError::InvalidOracles { symbol: symbol() });
+        }
+
+        let existing = match deployment.address {
+            Some(address) => {
+                let reserve = state
+                    .reserves
+                    .get(&address)
+                    .ok_or_else(|| MarketConfigError::ReserveNotFound { symbol: symbol() })?;
+                if reserve.liquidity.mint_pubkey != deployment.liquidity_mint {
+                    return Err(MarketConfigError::LiquidityMintMismatch { symbol: symbol() });
+                }
+                Some((address, reserve))
+            }
+            None => {
+                let mut reserves = state
+                    .reserves
+                    .iter()
+                    .filter(|(_, reserve)| {
+                        reserve.liquidity.mint_pubkey == deployment.liquidity_mint
+                    })
+                    .map(|(pubkey, reserve)| (*pubkey, reserve));
+                let reserve = reserves.next();
+                if reserves.next().is_some() {
+                    return Err(MarketConfigError::AmbiguousReserve { symbol: symbol() });
+                }
+                reserve
+            }
+        };
+
+        match existing {
+            Some((reserve_pubkey, reserve)) => {
+                if !managed.insert(reserve_pubkey) {
+                    return Err(MarketConfigError::DuplicateReserve { symbol: symbol() });
+                }
+
+                let desired = ReserveConfig {
+                    fee_receiver: if deployment.config.fee_receiver == Pubkey::default() {
+                        reserve.config.fee_receiver
+                    } else {
+                        deployment.config.fee_receiver
+                    },
+                    ..deployment.config
+                };
+                validate_reserve_config(desired).map_err(|error| {
+                    MarketConfigError::InvalidReserveConfig {
+                        symbol: symbol(),
+                        error,
+                    }
+                })?;
+
+                let mut changes = reserve_config_changes(&reserve.config, &desired);
+                if reserve.rate_limiter.config != deployment.rate_limiter {
+                    changes.push(ConfigChange::RateLimiter {
+                        old: reserve.rate_limiter.config,
+                        new: deployment.rate_limiter,
+                    });
+                }
+                let pyth_price_changed =
+                    oracles.pyth_price() != reserve.liquidity.pyth_oracle_pubkey;
+                let oracles_changed = pyth_price_changed
+                    || oracles.switchboard_feed() != reserve.liquidity.switchboard_oracle_pubkey;
+                if pyth_price_changed
+                    && oracles.pyth_price.is_some()
+                    && oracles.pyth_product.is_none()
+                {
+                    return Err(MarketConfigError::InvalidOracles { symbol: symbol() });
+                }
+                if changes.is_empty() && !oracles_changed {
+                    continue;
+                }
+
+                plan.actions.push(MarketAction::UpdateReserve {
+                    symbol: symbol(),
+                    reserve: reserve_pubkey,
+                    changes,
+                    oracles: if oracles_changed {
+                        Some(oracles.clone())
+                    } else {
+                        None
+                    },
+                    instruction: update_reserve_config(
+                        *program_id,
+                        desired,
+                        deployment.rate_limiter,
+                        reserve_pubkey,
+                        lending_market_pubkey,
+                        owner,
+                        oracles.pyth_product(),
+                        oracles.pyth_price(),
+                        oracles.switchboard_feed(),
+                    ),
+                });
+            }
+            None => {
+                validate_reserve_config(deployment.config).map_err(|error| {
+                    MarketConfigError::InvalidReserveConfig {
+                        symbol: symbol(),
+                        error,
+                    }
+                })?;
+                if oracles.pyth_price.is_some() && oracles.pyth_product.is_none() {
+                    return Err(MarketConfigError::InvalidOracles { symbol: symbol() });
+                }
+                if deployment.initial_liquidity == 0 {
+                    return Err(MarketConfigError::MissingInitialLiquidity { symbol: symbol() });
+                }
+
+                let keypairs = AddReserveKeypairs::new();
+                let reserve_pubkey = keypairs.reserve.pubkey();
+                let fee_receiver = keypairs.liquidity_fee_receiver.pubkey();
+                plan.actions.push(MarketAction::AddReserve {
+                    symbol: symbol(),
+                    reserve: reserve_pubkey,
+                    builder: Box::new(
+                        AddReserveBuilder::new(
+                            *program_id,
+                            *payer,
+                            lending_market_pubkey,
+                            owner,
+                            deployment.liquidity_mint,
+                            get_associated_token_address(&owner, &deployment.liquidity_mint),
+                            owner,
+                            deployment.initial_liquidity,
+                        )
+                        .config(deployment.config)
+                        .oracles(
+                            oracles.pyth_product(),
+                            oracles.pyth_price(),
+                            oracles.switchboard_feed(),
+                        )
+                        .keypairs(keypairs),
+                    ),
+                });
+
+                // InitReserve leaves the rate limiter at its default
+                if deployment.rate_limiter != RateLimiterConfig::default() {
+                    plan.actions.push(MarketAction::UpdateReserve {
+                        symbol: symbol(),
+                        reserve: reserve_pubkey,
+                        changes: vec![ConfigChange::RateLimiter {
+                            old: RateLimiterConfig::default(),
+                            new: deployment.rate_limiter,
+                        }],
+                        oracles: None,
+                        instruction: update_reserve_config(
+                            *program_id,
+                            ReserveConfig {
+                                fee_receiver,
+                                ..deployment.config
+                            },
+                            deployment.rate_limiter,
+                            reserve_pubkey,
+                            lending_market_pubkey,
+                            owner,
+                            oracles.pyth_product(),
+                            oracles.pyth_price(),
+                            oracles.switchboard_feed(),
+                        ),
+                    });
+                }
+            }
+        }
+    }
+
+    plan.unmanaged_reserves = state
+        .reserves
+        .keys()
+        .filter(|pubkey| !managed.contains(*pubkey))
+        .copied()
+        .collect();
+    plan.unmanaged_reserves.sort();
+
+    let current = &state.lending_market;
+    let mut desired = current.clone();
+    desired.owner = config.owner;
+    desired.risk_authority = config.risk_authority;
+    desired.whitelisted_liquidator = config.whitelisted_liquidator;
+    desired.rate_limiter.config = config.rate_limiter;
+    desired.slots_per_year = config.slots_per_year.unwrap_or(current.slots_per_year);
+    let changes = lending_market_config_changes(current, &desired);
+    if !changes.is_empty() {
+        plan.actions.push(MarketAction::SetLendingMarketConfig {
+            changes,
+            instruction: set_lending_market_owner_and_config_with_slots_per_year(
+                *program_id,
+                lending_market_pubkey,
+                owner,
+                desired.owner,
+                desired.rate_limiter.config,
+                desired.whitelisted_liquidator,
+                desired.risk_authority,
+                desired.slots_per_year,
+            ),
+        });
+    }
+
+    Ok(plan)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        instruction::LendingInstruction,
+        state::{RateLimiter, ReserveLiquidity, MARKET_NAME_SIZE, SLOTS_PER_YEAR},
+    };
+
+    fn reserve_config() -> ReserveConfig {
+        ReserveConfig {
+            optimal_utilization_rate: 80,
+            max_utilization_rate: 90,
+            loan_to_value_ratio: 50,
+            liquidation_bonus: 5,
+            max_liquidation_bonus: 10,
+            liquidation_threshold: 55,
+            max_liquidation_threshold: 65,
+            min_borrow_rate: 0,
+            optimal_borrow_rate: 4,
+            max_borrow_rate: 30,
+            super_max_borrow_rate: 50,
+            deposit_limit: 1_000_000,
+            borrow_limit: 1_000_000,
+            protocol_liquidation_fee: 30,
+            protocol_take_rate: 10,
+            ..ReserveConfig::default()
+        }
+    }
+
+    fn deployment(symbol: &str, liquidity_mint: Pubkey) -> ReserveDeployment {
+        ReserveDeployment {
+            symbol: symbol.to_string(),
+            liquidity_mint,
+            address: None,
+            initial_liquidity: 1_000,
+            oracles: ReserveOracles {
+                pyth_product: Some(Pubkey::new_unique()),
+                pyth_price: Some(Pubkey::new_unique()),
+                switchboard_feed: None,
+            },
+            rate_limiter: RateLimiterConfig::default(),
+            config: reserve_config(),
+        }
+    }
+
+    // a market with one reserve matching `deployment`
+    fn setup() -> (MarketConfig, MarketState, Pubkey) {
+        let owner = Pubkey::new_unique();
+        let lending_market_pubkey = Pubkey::new_unique();
+        let usdc = deployment("USDC", Pubkey::new_unique());
+
+        let reserve_pubkey = Pubkey::new_unique();
+        let reserve = Reserve {
+            lending_market: lending_market_pubkey,
+            liquidity: ReserveLiquidity {
+                mint_pubkey: usdc.liquidity_mint,
+                pyth_oracle_pubkey: usdc.oracles.pyth_price.unwrap(),
+                switchboard_oracle_pubkey: NULL_PUBKEY,
+                ..ReserveLiquidity::default()
+            },
+            config: ReserveConfig {
+                fee_receiver: Pubkey::new_unique(),
+                ..reserve_config()
+            },
+            // as InitReserve leaves it
+            rate_limiter: RateLimiter::new(RateLimiterConfig::default(), 0),
+            ..Reserve::default()
+        };
+
+        let config = MarketConfig {
+            lending_market: lending_market_pubkey,
+            owner,
+            risk_authority: owner,
+            whitelisted_liquidator: None,
+            slots_per_year: None,
+            rate_limiter: LendingMarket::default().rate_limiter.config,
+            metadata: None,
+            reserves: vec![usdc],
+        };
+        let state = MarketState {
+            lending_market_pubkey,
+            lending_market: LendingMarket {
+                owner,
+                risk_authority: owner,
+                slots_per_year: SLOTS_PER_YEAR,
+                ..LendingMarket::default()
+            },
+            metadata: None,
+            reserves: HashMap::from([(reserve_pubkey, reserve)]),
+        };
+        (config, state, reserve_pubkey)
+    }
+
+    #[test]
+    fn converged_market_has_no_actions() {
+        let (config, state, _) = setup();
+        let plan = reconcile(
+            &Pubkey::new_unique(),
+            &config,
+            &state,
+            &Pubkey::new_unique(),
+        )
+        .unwrap();
+        assert!(plan.is_empty());
+        assert!(plan.unmanaged_reserves.is_empty());
+    }
+
+    #[test]
+    fn reconcile_market() {
+        let program_id = Pubkey::new_unique();
+        let (mut config, mut state, reserve_pubkey) = setup();
+        let owner = state.lending_market.owner;
+        let unmanaged = Pubkey::new_unique();
+        state.reserves.insert(unmanaged, Reserve::default());
+
+        config.reserves[0].config.deposit_limit = 2_000_000;
+        config.reserves[0].rate_limiter.max_outflow = 100;
+        config.reserves.push(ReserveDeployment {
+            rate_limiter: RateLimiterConfig {
+                window_duration: 10,
+                max_outflow: 10,
+            },
+            ..deployment("SOL", Pubkey::new_unique())
+        });
+        config.metadata = Some(MarketMetadata {
+            name: "Main Pool".to_string(),
+            description: String::new(),
+            image_url: String::new(),
+        });
+        config.owner = Pubkey::new_unique();
+
+        let plan = reconcile(&program_id, &config, &state, &Pubkey::new_unique()).unwrap();
+        assert_eq!(plan.unmanaged_reserves, vec![unmanaged]);
+        assert_eq!(plan.actions.len(), 5);
+
+        assert!(matches!(
+            plan.actions[0],
+            MarketAction::UpdateMetadata { .. }
+        ));
+
+        match &plan.actions[1] {
+            MarketAction::UpdateReserve {
+                reserve,
+                changes,
+                oracles,
+                instruction,
+                ..
+            } => {
+                assert_eq!(*reserve, reserve_pubkey);
+                assert_eq!(changes.len(), 2);
+                assert_eq!(*oracles, None);
+                match LendingInstruction::unpack(&instruction.data).unwrap() {
+                    LendingInstruction::UpdateReserveConfig {
+                        config: updated,
+                        rate_limiter_config,
+                    } => {
+                        assert_eq!(updated.deposit_limit, 2_000_000);
+                        // the fee receiver on chain is kept
+                        assert_eq!(
+                            updated.fee_receiver,
+                            state.reserves[&reserve_pubkey].config.fee_receiver
+                        );
+                        assert_eq!(rate_limiter_config.max_outflow, 100);
+                    }
+                    _ => panic!("unexpected instruction"),
+                }
+            }
+            action => panic!("unexpected action {:?}", action),
+        }
+
+        let new_reserve = match &plan.actions[2] {
+            MarketAction::AddReserve {
+                symbol, reserve, ..
+            } => {
+                assert_eq!(symbol, "SOL");
+                *reserve
+            }
+            action => panic!("unexpected action {:?}", action),
+        };
+        // the rate limiter of the new reserve is set once it exists
+        assert!(matches!(
+            &plan.actions[3],
+            MarketAction::UpdateReserve { reserve, .. } if *reserve == new_reserve
+        ));
+
+        match &plan.actions[4] {
+            MarketAction::SetLendingMarketConfig {
+                changes,
+                instruction,
+            } => {
+                assert_eq!(
+                    changes,
+                    &vec![ConfigChange::Owner {
+                        old: owner,
+                        new: config.owner,
+                    }]
+                );
+                // signed by the current owner
+                assert_eq!(instruction.accounts[1].pubkey, owner);
+            }
+            action => panic!("unexpected action {:?}", action),
+        }
+    }
+
+    #[test]
+    fn reconcile_errors() {
+        let program_id = Pubkey::new_unique();
+        let payer = Pubkey::new_unique();
+        let (config, state, reserve_pubkey) = setup();
+
+        let mut invalid = config.clone();
+        invalid.reserves[0].config.optimal_utilization_rate = 101;
+        assert!(matches!(
+            reconcile(&program_id, &invalid, &state, &payer),
+            Err(MarketConfigError::InvalidReserveConfig { .. })
+        ));
+
+        let mut duplicate = config.clone();
+        duplicate.reserves.push(ReserveDeployment {
+            address: Some(reserve_pubkey),
+            ..config.reserves[0].clone()
+        });
+        assert_eq!(
+            reconcile(&program_id, &duplicate, &state, &payer).unwrap_err(),
+            MarketConfigError::DuplicateReserve {
+                symbol: "USDC".to_string()
+            }
+        );
+
+        let mut no_oracle = config.clone();
+        no_oracle.reserves[0].oracles = ReserveOracles::default();
+        assert_eq!(
+            reconcile(&program_id, &no_oracle, &state, &payer).unwrap_err(),
+            MarketConfigError::InvalidOracles {
+                symbol: "USDC".to_string()
+            }
+        );
+
+        let mut no_liquidity = config.clone();
+        no_liquidity.reserves.push(ReserveDeployment {
+            initial_liquidity: 0,
+            ..deployment("SOL", Pubkey::new_unique())
+        });
+        assert_eq!(
+            reconcile(&program_id, &no_liquidity, &state, &payer).unwrap_err(),
+            MarketConfigError::MissingInitialLiquidity {
+                symbol: "SOL".to_string()
+            }
+        );
+
+        let mut long_name = config;
+        long_name.metadata = Some(MarketMetadata {
+            name: "a".repeat(MARKET_NAME_SIZE + 1),
+            description: String::new(),
+            image_url: String::new(),
+        });
+        assert_eq!(
+            reconcile(&program_id, &long_name, &state, &payer).unwrap_err(),
+            MarketConfigError::MetadataTooLong { field: "name" }
+        );
+    }
+
+    #[test]
+    fn market_config_serde() {
+        let (config, _, _) = setup();
+        let json = serde_json::to_value(&config).unwrap();
+        assert_eq!(json["owner"], config.owner.to_string());
+        assert_eq!(json["reserves"][0]["config"]["reserve_type"], "regular");
+        assert_eq!(json["reserves"][0]["oracles"].get("switchboard_feed"), None);
+        assert_eq!(
+            serde_json::from_value::<MarketConfig>(json).unwrap(),
+            config
+        );
+
+        // optional reserve config fields default to unset
+        let mut json = serde_json::to_value(&config).unwrap();
+        let reserve_config = json["reserves"][0]["config"].as_object_mut().unwrap();
+        reserve_config.remove("fee_receiver");
+        reserve_config.remove("withdraw_fee_bps");
+        reserve_config.remove("price_bounds_mode");
+        let parsed = serde_json::from_value::<MarketConfig>(json.clone()).unwrap();
+        assert_eq!(parsed.reserves[0].config.fee_receiver, Pubkey::default());
+
+        json["reserves"][0]["config"]["typo"] = 1.into();
+        assert!(serde_json::from_value::<MarketConfig>(json).is_err());
+    }
+}
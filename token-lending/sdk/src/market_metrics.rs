@@ -0,0 +1,209 @@
+//! TVL, utilization and rates of the reserves of a lending market at one slot, for dashboards.
+//!
+//! [get_market_metrics] fetches every reserve of a lending market and the prices of their
+//! oracles, refreshes the reserves off chain at the current slot the way RefreshReserve would,
+//! and measures each one with [ReserveMetrics::new]. Reserves whose oracles have no usable price
+//! keep their last refreshed price and are flagged `stale_price`, so one broken feed doesn't
+//! leave a gap in every series of the market.
+
+use crate::{
+    config_simulator::ReserveRates,
+    math::{Decimal, Rate, TryAdd, TryDiv},
+    offchain_utils::{get_oracle_prices, offchain_refresh_reserves, MissingPricePolicy},
+    registry::{get_market_reserves, ReserveInfo},
+    state::Reserve,
+};
+use solana_client::rpc_client::RpcClient;
+use solana_program::{clock::Slot, program_error::ProgramError, pubkey::Pubkey};
+use std::{collections::HashMap, convert::TryFrom, error::Error};
+
+/// Supply, borrows and rates of a reserve
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReserveMetrics {
+    /// Reserve address
+    pub reserve: Pubkey,
+    /// Symbol of the liquidity mint, or the mint address if it isn't a known mint
+    pub symbol: String,
+    /// Liquidity mint
+    pub mint: Pubkey,
+    /// Liquidity supplied to the reserve, borrows included, in native units
+    pub total_supply: Decimal,
+    /// Liquidity borrowed from the reserve, in native units
+    pub borrowed_amount: Decimal,
+    /// Market value of `total_supply`
+    pub supplied_value: Decimal,
+    /// Market value of `borrowed_amount`
+    pub borrowed_value: Decimal,
+    /// Share of the supply that is borrowed
+    pub utilization_rate: Rate,
+    /// Variable borrow APY
+    pub borrow_apy: Rate,
+    /// Depositor APY, net of protocol fees
+    pub supply_apy: Rate,
+    /// Whether the oracles of the reserve had no usable price, so its last refreshed price was
+    /// used
+    pub stale_price: bool,
+}
+
+impl ReserveMetrics {
+    /// Measure a reserve, refreshed at the slot the metrics are for
+    pub fn new(pubkey: Pubkey, reserve: &Reserve) -> Result<Self, ProgramError> {
+        let total_supply = reserve.liquidity.total_supply()?;
+        let borrowed_amount = reserve.liquidity.borrowed_amount_wads;
+        let rates = ReserveRates::new(reserve)?;
+        Ok(Self {
+            reserve: pubkey,
+            symbol: ReserveInfo::from_reserve(pubkey, reserve).symbol,
+            mint: reserve.liquidity.mint_pubkey,
+            total_supply,
+            borrowed_amount,
+            supplied_value: reserve.market_value(total_supply)?,
+            borrowed_value: reserve.market_value(borrowed_amount)?,
+            utilization_rate: reserve.liquidity.utilization_rate()?,
+            borrow_apy: rates.borrow_apy,
+            supply_apy: rates.supply_apy,
+            stale_price: reserve.last_update.stale,
+        })
+    }
+}
+
+/// Metrics of every reserve of a lending market at one slot
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MarketMetrics {
+    /// Lending market address
+    pub lending_market: Pubkey,
+    /// Slot the reserves were refreshed at
+    pub slot: Slot,
+    /// Metrics of the reserves, by symbol
+    pub reserves: Vec<ReserveMetrics>,
+}
+
+impl MarketMetrics {
+    /// Total value locked, the market value supplied to every reserve, borrows included
+    pub fn supplied_value(&self) -> Result<Decimal, ProgramError> {
+        self.reserves
+            .iter()
+            .try_fold(Decimal::zero(), |total, reserve| {
+                total.try_add(reserve.supplied_value)
+            })
+    }
+
+    /// Market value borrowed from every reserve
+    pub fn borrowed_value(&self) -> Result<Decimal, ProgramError> {
+        self.reserves
+            .iter()
+            .try_fold(Decimal::zero(), |total, reserve| {
+                total.try_add(reserve.borrowed_value)
+            })
+    }
+
+    /// Share of the supplied value that is borrowed, zero for an empty market
+    pub fn utilization_rate(&self) -> Result<Rate, ProgramError> {
+        let supplied_value = self.supplied_value()?;
+        if supplied_value == Decimal::zero() {
+            return Ok(Rate::zero());
+        }
+        Rate::try_from(self.borrowed_value()?.try_div(supplied_value)?)
+    }
+}
+
+/// Fetch and measure every reserve of a lending market
+pub fn get_market_metrics(
+    client: &RpcClient,
+    program_id: &Pubkey,
+    lending_market: &Pubkey,
+) -> Result<MarketMetrics, Box<dyn Error>> {
+    let mut reserves = get_market_reserves(client, program_id, lending_market)?
+        .into_iter()
+        .collect::<HashMap<_, _>>();
+    let prices = get_oracle_prices(client, &reserves)?;
+    // fetched after the reserves, so no reserve was last updated past it
+    let slot = client.get_slot()?;
+    offchain_refresh_reserves(
+        &mut reserves,
+        slot,
+        &prices,
+        MissingPricePolicy::UseLastKnown,
+    )?;
+
+    let mut metrics = reserves
+        .iter()
+        .map(|(pubkey, reserve)| ReserveMetrics::new(*pubkey, reserve))
+        .collect::<Result<Vec<_>, _>>()?;
+    metrics.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+
+    Ok(MarketMetrics {
+        lending_market: *lending_market,
+        slot,
+        reserves: metrics,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::state::{LastUpdate, ReserveCollateral, ReserveConfig, ReserveLiquidity};
+
+    #[test]
+    fn market_metrics() {
+        let reserve = Reserve {
+            last_update: LastUpdate {
+                slot: 100,
+                stale: false,
+            },
+            liquidity: ReserveLiquidity {
+                mint_decimals: 6,
+                available_amount: 600_000_000,
+                borrowed_amount_wads: Decimal::from(400_000_000u64),
+                market_price: Decimal::from(2u64),
+                smoothed_market_price: Decimal::from(2u64),
+                ..ReserveLiquidity::default()
+            },
+            collateral: ReserveCollateral {
+                mint_total_supply: 1_000_000_000,
+                ..ReserveCollateral::default()
+            },
+            config: ReserveConfig {
+                optimal_utilization_rate: 80,
+                max_utilization_rate: 90,
+                optimal_borrow_rate: 10,
+                max_borrow_rate: 30,
+                super_max_borrow_rate: 50,
+                ..ReserveConfig::default()
+            },
+            slots_per_year: 63_072_000,
+            ..Reserve::default()
+        };
+        let pubkey = Pubkey::new_unique();
+
+        // 1000 tokens supplied at $2, 400 of them borrowed
+        let metrics = ReserveMetrics::new(pubkey, &reserve).unwrap();
+        assert_eq!(metrics.total_supply, Decimal::from(1_000_000_000u64));
+        assert_eq!(metrics.supplied_value, Decimal::from(2_000u64));
+        assert_eq!(metrics.borrowed_value, Decimal::from(800u64));
+        assert_eq!(metrics.utilization_rate, Rate::from_percent(40));
+        assert!(metrics.borrow_apy > Rate::zero());
+        assert!(metrics.supply_apy > Rate::zero());
+        assert!(metrics.supply_apy < metrics.borrow_apy);
+        assert!(!metrics.stale_price);
+
+        let empty_reserve = Reserve {
+            liquidity: ReserveLiquidity {
+                available_amount: 0,
+                borrowed_amount_wads: Decimal::zero(),
+                ..reserve.liquidity.clone()
+            },
+            ..reserve.clone()
+        };
+        let empty = ReserveMetrics::new(Pubkey::new_unique(), &empty_reserve).unwrap();
+        assert_eq!(empty.utilization_rate, Rate::zero());
+        let market = MarketMetrics {
+            lending_market: Pubkey::new_unique(),
+            slot: 100,
+            reserves: vec![metrics, empty],
+        };
+        assert_eq!(market.supplied_value().unwrap(), Decimal::from(2_000u64));
+        assert_eq!(market.borrowed_value().unwrap(), Decimal::from(800u64));
+        assert_eq!(market.utilization_rate().unwrap(), Rate::from_percent(40));
+    }
+}
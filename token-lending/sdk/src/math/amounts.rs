@@ -0,0 +1,147 @@
+//! Typed token amounts and values.
+//!
+//! The program passes raw `u64`s and `Decimal`s around, which makes it easy to hand a cToken
+//! amount to something expecting liquidity. These wrappers keep the three apart in offchain code.
+//! Convert between liquidity and collateral with a reserve's `CollateralExchangeRate`, and from
+//! liquidity to a value with `Reserve::liquidity_value`.
+
+use crate::{
+    error::LendingError,
+    math::{Decimal, TryAdd, TrySub},
+};
+use solana_program::program_error::ProgramError;
+use std::fmt;
+
+/// Amount of a reserve's liquidity token, in the smallest unit of the mint
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LiquidityAmount(pub u64);
+
+/// Amount of a reserve's collateral (cToken), in the smallest unit of the mint
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CollateralAmount(pub u64);
+
+/// Value in the lending market's quote currency, usually USD
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct UsdValue(pub Decimal);
+
+macro_rules! impl_token_amount {
+    ($amount:ident) => {
+        impl $amount {
+            /// Zero amount
+            pub const ZERO: Self = Self(0);
+
+            /// The raw amount
+            pub fn amount(&self) -> u64 {
+                self.0
+            }
+        }
+
+        impl From<u64> for $amount {
+            fn from(amount: u64) -> Self {
+                Self(amount)
+            }
+        }
+
+        impl From<$amount> for u64 {
+            fn from(amount: $amount) -> Self {
+                amount.0
+            }
+        }
+
+        impl TryAdd for $amount {
+            fn try_add(self, rhs: Self) -> Result<Self, ProgramError> {
+                Ok(Self(
+                    self.0
+                        .checked_add(rhs.0)
+                        .ok_or(LendingError::MathOverflow)?,
+                ))
+            }
+        }
+
+        impl TrySub for $amount {
+            fn try_sub(self, rhs: Self) -> Result<Self, ProgramError> {
+                Ok(Self(
+                    self.0
+                        .checked_sub(rhs.0)
+                        .ok_or(LendingError::MathOverflow)?,
+                ))
+            }
+        }
+
+        impl fmt::Display for $amount {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "{}", self.0)
+            }
+        }
+    };
+}
+
+impl_token_amount!(LiquidityAmount);
+impl_token_amount!(CollateralAmount);
+
+impl UsdValue {
+    /// Zero value
+    pub fn zero() -> Self {
+        Self(Decimal::zero())
+    }
+
+    /// The raw value
+    pub fn value(&self) -> Decimal {
+        self.0
+    }
+}
+
+impl From<Decimal> for UsdValue {
+    fn from(value: Decimal) -> Self {
+        Self(value)
+    }
+}
+
+impl From<UsdValue> for Decimal {
+    fn from(value: UsdValue) -> Self {
+        value.0
+    }
+}
+
+impl TryAdd for UsdValue {
+    fn try_add(self, rhs: Self) -> Result<Self, ProgramError> {
+        Ok(Self(self.0.try_add(rhs.0)?))
+    }
+}
+
+impl TrySub for UsdValue {
+    fn try_sub(self, rhs: Self) -> Result<Self, ProgramError> {
+        Ok(Self(self.0.try_sub(rhs.0)?))
+    }
+}
+
+impl fmt::Display for UsdValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn checked_amount_math() {
+        assert_eq!(
+            LiquidityAmount(1).try_add(LiquidityAmount(2)),
+            Ok(LiquidityAmount(3))
+        );
+        assert_eq!(
+            CollateralAmount(1).try_sub(CollateralAmount(2)),
+            Err(LendingError::MathOverflow.into())
+        );
+        assert_eq!(
+            LiquidityAmount(u64::MAX).try_add(LiquidityAmount(1)),
+            Err(LendingError::MathOverflow.into())
+        );
+        assert_eq!(
+            UsdValue(Decimal::from(3u64)).try_sub(UsdValue(Decimal::one())),
+            Ok(UsdValue(Decimal::from(2u64)))
+        );
+    }
+}
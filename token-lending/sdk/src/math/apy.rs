@@ -0,0 +1,99 @@
+//! Conversions between per-slot interest rates, APRs and APYs.
+//!
+//! Interest compounds every slot, so an APR of `r` pays `r / slots_per_year` per slot and an APY
+//! of `(1 + r / slots_per_year) ^ slots_per_year - 1`. Use the `slots_per_year` of the lending
+//! market (or reserve) the rate belongs to.
+
+use crate::math::{common::*, Rate, U128};
+use solana_program::program_error::ProgramError;
+
+/// Convert a per-slot interest rate to an APR
+pub fn slot_rate_to_apr(slot_rate: Rate, slots_per_year: u64) -> Result<Rate, ProgramError> {
+    slot_rate.try_mul(slots_per_year)
+}
+
+/// Convert an APR to the interest rate charged every slot
+pub fn apr_to_slot_rate(apr: Rate, slots_per_year: u64) -> Result<Rate, ProgramError> {
+    apr.try_div(slots_per_year)
+}
+
+/// Convert a per-slot interest rate to an APY
+pub fn slot_rate_to_apy(slot_rate: Rate, slots_per_year: u64) -> Result<Rate, ProgramError> {
+    Rate::one()
+        .try_add(slot_rate)?
+        .try_pow(slots_per_year)?
+        .try_sub(Rate::one())
+}
+
+/// Convert an APY to the largest per-slot interest rate that doesn't compound past it
+pub fn apy_to_slot_rate(apy: Rate, slots_per_year: u64) -> Result<Rate, ProgramError> {
+    // (1 + r) ^ n >= 1 + n * r, so the slot rate is at most apy / n
+    let mut low = U128::zero();
+    let mut high = apy.try_div(slots_per_year)?.0;
+    while low < high {
+        let mid = low + (high - low + 1) / 2;
+        // compounding a rate that is too high can overflow
+        match slot_rate_to_apy(Rate(mid), slots_per_year) {
+            Ok(mid_apy) if mid_apy <= apy => low = mid,
+            _ => high = mid - 1,
+        }
+    }
+    Ok(Rate(low))
+}
+
+/// Convert an APR to an APY
+pub fn apr_to_apy(apr: Rate, slots_per_year: u64) -> Result<Rate, ProgramError> {
+    slot_rate_to_apy(apr_to_slot_rate(apr, slots_per_year)?, slots_per_year)
+}
+
+/// Convert an APY to an APR
+pub fn apy_to_apr(apy: Rate, slots_per_year: u64) -> Result<Rate, ProgramError> {
+    slot_rate_to_apr(apy_to_slot_rate(apy, slots_per_year)?, slots_per_year)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::state::{MAX_SLOTS_PER_YEAR, MIN_SLOTS_PER_YEAR, SLOTS_PER_YEAR};
+
+    #[test]
+    fn apr_slot_rate_round_trip() {
+        let apr = Rate::from_percent(10);
+        let slot_rate = apr_to_slot_rate(apr, SLOTS_PER_YEAR).unwrap();
+        assert_eq!(slot_rate, Rate(U128::from(1_585_489_599u64)));
+
+        // flooring the slot rate loses at most one wad unit per slot
+        let round_trip = slot_rate_to_apr(slot_rate, SLOTS_PER_YEAR).unwrap();
+        assert!(round_trip <= apr);
+        assert!(apr.try_sub(round_trip).unwrap() < Rate(U128::from(SLOTS_PER_YEAR)));
+    }
+
+    #[test]
+    fn apr_to_apy_compounds_every_slot() {
+        // compounding 10% every slot is close to continuous compounding, e^0.1 - 1 = 10.51709...%
+        let apy = apr_to_apy(Rate::from_percent(10), SLOTS_PER_YEAR).unwrap();
+        assert!(apy > Rate(U128::from(105_170_900_000_000_000u64)));
+        assert!(apy < Rate(U128::from(105_171_000_000_000_000u64)));
+
+        assert_eq!(
+            apr_to_apy(Rate::zero(), SLOTS_PER_YEAR).unwrap(),
+            Rate::zero()
+        );
+    }
+
+    #[test]
+    fn apy_to_apr_inverts_apr_to_apy() {
+        for slots_per_year in [MIN_SLOTS_PER_YEAR, SLOTS_PER_YEAR, MAX_SLOTS_PER_YEAR] {
+            for percent in [0, 1, 10, 100, 250] {
+                let apr = Rate::from_percent(percent);
+                let apy = apr_to_apy(apr, slots_per_year).unwrap();
+                let slot_rate = apy_to_slot_rate(apy, slots_per_year).unwrap();
+                assert_eq!(slot_rate, apr_to_slot_rate(apr, slots_per_year).unwrap());
+                assert_eq!(
+                    apy_to_apr(apy, slots_per_year).unwrap(),
+                    slot_rate_to_apr(slot_rate, slots_per_year).unwrap()
+                );
+            }
+        }
+    }
+}
@@ -7,6 +7,11 @@
 //! unsigned 64-bit integers. The underlying representation is a
 //! u192 rather than u256 to reduce compute cost while losing
 //! support for arithmetic operations at the high end of u64 range.
+//!
+//! Off chain, decimals are exchanged in one of two canonical forms:
+//! a string with exactly 18 decimal places, which is how `Decimal`
+//! serializes and displays, or a signed 128-bit count of wads for
+//! integer columns, with `#[serde(with = "wads")]`.
 
 #![allow(clippy::assign_op_pattern)]
 #![allow(clippy::ptr_offset_with_cast)]
@@ -16,8 +21,9 @@ use crate::{
     error::LendingError,
     math::{common::*, Rate},
 };
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 use solana_program::program_error::ProgramError;
-use std::{convert::TryFrom, fmt};
+use std::{convert::TryFrom, error::Error, fmt, str::FromStr};
 use uint::construct_uint;
 
 // U192 with 192 bits consisting of 3 x 64-bit words
@@ -26,18 +32,46 @@ construct_uint! {
 }
 
 /// Large decimal values, precise to 18 digits
+///
+/// # Examples
+///
+/// ```
+/// use solend_sdk::math::{Decimal, TryDiv, TryMul};
+///
+/// // 1.5 SOL, in lamports, priced at $20.25
+/// let amount = Decimal::from(1_500_000_000u64).try_div(1_000_000_000u64).unwrap();
+/// let price = Decimal::from(2025u64).try_div(100u64).unwrap();
+/// let value = amount.try_mul(price).unwrap();
+/// assert_eq!(value.to_string(), "30.375000000000000000");
+///
+/// // conversions back to token amounts choose a rounding direction explicitly
+/// assert_eq!(value.try_floor_u64().unwrap(), 30);
+/// assert_eq!(value.try_round_u64().unwrap(), 30);
+/// assert_eq!(value.try_ceil_u64().unwrap(), 31);
+///
+/// // 18 digits of precision, truncated on division
+/// let third = Decimal::one().try_div(3u64).unwrap();
+/// assert_eq!(third.to_string(), "0.333333333333333333");
+/// assert_eq!(Decimal::from_bps(25), Decimal::from_percent(1).try_div(4u64).unwrap());
+/// ```
 #[derive(Clone, Copy, Default, PartialEq, PartialOrd, Eq, Ord)]
 pub struct Decimal(pub U192);
 
 impl Decimal {
     /// One
-    pub fn one() -> Self {
-        Self(Self::wad())
+    pub const ONE: Self = Self::from_scaled_val(WAD as u128);
+
+    /// Zero
+    pub const ZERO: Self = Self::from_scaled_val(0);
+
+    /// One
+    pub const fn one() -> Self {
+        Self::ONE
     }
 
     /// Zero
-    pub fn zero() -> Self {
-        Self(U192::zero())
+    pub const fn zero() -> Self {
+        Self::ZERO
     }
 
     // OPTIMIZE: use const slice when fixed in BPF toolchain
@@ -51,18 +85,18 @@ impl Decimal {
     }
 
     /// Create scaled decimal from percent value
-    pub fn from_percent(percent: u8) -> Self {
-        Self(U192::from(percent as u64 * PERCENT_SCALER))
+    pub const fn from_percent(percent: u8) -> Self {
+        Self::from_scaled_val(percent as u128 * PERCENT_SCALER as u128)
     }
 
     /// Create scaled decimal from deca bps value
-    pub fn from_deca_bps(deca_bps: u8) -> Self {
-        Self::from(deca_bps as u64).try_div(1000).unwrap()
+    pub const fn from_deca_bps(deca_bps: u8) -> Self {
+        Self::from_scaled_val(deca_bps as u128 * (BPS_SCALER as u128 * 10))
     }
 
     /// Create scaled decimal from bps value
-    pub fn from_bps(bps: u64) -> Self {
-        Self::from(bps).try_div(10_000).unwrap()
+    pub const fn from_bps(bps: u64) -> Self {
+        Self::from_scaled_val(bps as u128 * BPS_SCALER as u128)
     }
 
     /// Return raw scaled value if it fits within u128
@@ -72,8 +106,8 @@ impl Decimal {
     }
 
     /// Create decimal from scaled value
-    pub fn from_scaled_val(scaled_val: u128) -> Self {
-        Self(U192::from(scaled_val))
+    pub const fn from_scaled_val(scaled_val: u128) -> Self {
+        Self(U192([scaled_val as u64, (scaled_val >> 64) as u64, 0]))
     }
 
     /// Round scaled decimal to u64
@@ -121,6 +155,103 @@ impl fmt::Display for Decimal {
     }
 }
 
+/// A string isn't a decimal in canonical form
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParseDecimalError {
+    /// Not an unsigned number with an optional fractional part
+    Invalid,
+    /// More than 18 decimal places
+    TooPrecise,
+    /// Too large to fit in a decimal
+    Overflow,
+}
+
+impl fmt::Display for ParseDecimalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ParseDecimalError::Invalid => "invalid decimal",
+            ParseDecimalError::TooPrecise => "decimal has more than 18 decimal places",
+            ParseDecimalError::Overflow => "decimal too large",
+        })
+    }
+}
+
+impl Error for ParseDecimalError {}
+
+/// Parses the canonical string form of a decimal. Fewer than 18 decimal
+/// places, or none, are accepted; more are rejected rather than truncated.
+impl FromStr for Decimal {
+    type Err = ParseDecimalError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (integer, fraction) = match s.split_once('.') {
+            Some((integer, fraction)) if !fraction.is_empty() => (integer, fraction),
+            Some(_) => return Err(ParseDecimalError::Invalid),
+            None => (s, ""),
+        };
+        if integer.is_empty()
+            || !integer
+                .bytes()
+                .chain(fraction.bytes())
+                .all(|b| b.is_ascii_digit())
+        {
+            return Err(ParseDecimalError::Invalid);
+        }
+        if fraction.len() > SCALE {
+            return Err(ParseDecimalError::TooPrecise);
+        }
+
+        let scaled_val = format!(
+            "{}{}{}",
+            integer,
+            fraction,
+            "0".repeat(SCALE - fraction.len())
+        );
+        U192::from_dec_str(&scaled_val)
+            .map(Self)
+            .map_err(|_| ParseDecimalError::Overflow)
+    }
+}
+
+impl Serialize for Decimal {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Decimal {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+/// Serde with-module (de)serializing a decimal as a signed 128-bit count of
+/// wads, for consumers storing decimals in integer columns. Decimals above
+/// `i128::MAX` wads fail to serialize and negative counts fail to deserialize.
+pub mod wads {
+    use super::*;
+
+    /// Serialize a decimal as its count of wads
+    pub fn serialize<S: Serializer>(value: &Decimal, serializer: S) -> Result<S::Ok, S::Error> {
+        let wads = value
+            .to_scaled_val()
+            .ok()
+            .and_then(|wads| i128::try_from(wads).ok())
+            .ok_or_else(|| serde::ser::Error::custom(ParseDecimalError::Overflow))?;
+        serializer.serialize_i128(wads)
+    }
+
+    /// Deserialize a decimal from its count of wads
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Decimal, D::Error> {
+        let wads = i128::deserialize(deserializer)?;
+        u128::try_from(wads)
+            .map(Decimal::from_scaled_val)
+            .map_err(|_| de::Error::custom(ParseDecimalError::Invalid))
+    }
+}
+
 impl fmt::Debug for Decimal {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}", self)
@@ -224,6 +355,7 @@ impl TryMul<Decimal> for Decimal {
 #[cfg(test)]
 mod test {
     use super::*;
+    use proptest::prelude::*;
 
     #[test]
     fn test_scaler() {
@@ -263,6 +395,24 @@ mod test {
         assert_eq!(left, Decimal::from(19u64));
     }
 
+    #[test]
+    fn test_const_constructors() {
+        const PRESETS: [Decimal; 3] = [
+            Decimal::from_percent(80),
+            Decimal::from_bps(u64::MAX),
+            Decimal::from_scaled_val(u128::MAX),
+        ];
+
+        assert_eq!(PRESETS[0], Decimal::from(8u64).try_div(10u64).unwrap());
+        assert_eq!(
+            PRESETS[1],
+            Decimal::from(u64::MAX).try_div(10_000u64).unwrap()
+        );
+        assert_eq!(PRESETS[2].to_scaled_val().unwrap(), u128::MAX);
+        assert_eq!(Decimal::ONE, Decimal::from(1u64));
+        assert_eq!(Decimal::ZERO, Decimal::from(0u64));
+    }
+
     #[test]
     fn test_to_scaled_val() {
         assert_eq!(
@@ -307,4 +457,126 @@ mod test {
             "0.000000000000000001"
         );
     }
+
+    fn extreme_values() -> Vec<Decimal> {
+        vec![
+            Decimal::zero(),
+            Decimal::from_scaled_val(1),
+            Decimal::from_scaled_val(WAD as u128 - 1),
+            Decimal::one(),
+            Decimal::from_bps(1),
+            Decimal::from(u64::MAX),
+            Decimal::from_scaled_val(i128::MAX as u128),
+            Decimal::from_scaled_val(i128::MAX as u128 + 1),
+            Decimal::from_scaled_val(u128::MAX),
+            Decimal(U192::MAX),
+        ]
+    }
+
+    #[test]
+    fn test_canonical_string() {
+        let cases = [
+            (Decimal::zero(), "0.000000000000000000"),
+            (Decimal::from_scaled_val(1), "0.000000000000000001"),
+            (Decimal::from_percent(50), "0.500000000000000000"),
+            (
+                Decimal::from(u64::MAX),
+                "18446744073709551615.000000000000000000",
+            ),
+            (
+                Decimal::from_scaled_val(u128::MAX),
+                "340282366920938463463.374607431768211455",
+            ),
+            (
+                Decimal(U192::MAX),
+                "6277101735386680763835789423207666416102.355444464034512895",
+            ),
+        ];
+        for (decimal, string) in cases {
+            assert_eq!(decimal.to_string(), string);
+            assert_eq!(string.parse::<Decimal>(), Ok(decimal));
+            assert_eq!(
+                serde_json::to_string(&decimal).unwrap(),
+                format!("\"{}\"", string)
+            );
+        }
+
+        for decimal in extreme_values() {
+            let json = serde_json::to_string(&decimal).unwrap();
+            assert_eq!(serde_json::from_str::<Decimal>(&json).unwrap(), decimal);
+        }
+    }
+
+    #[test]
+    fn test_parse_non_canonical_string() {
+        assert_eq!("1".parse::<Decimal>(), Ok(Decimal::one()));
+        assert_eq!("0.25".parse::<Decimal>(), Ok(Decimal::from_bps(2_500)));
+        assert_eq!("007.5".parse::<Decimal>(), "7.5".parse::<Decimal>());
+
+        for invalid in [
+            "", ".", "1.", ".5", "-1", "+1", "1e18", " 1", "1.0.0", "0x10",
+        ] {
+            assert_eq!(
+                invalid.parse::<Decimal>(),
+                Err(ParseDecimalError::Invalid),
+                "{:?}",
+                invalid
+            );
+        }
+        assert_eq!(
+            "0.0000000000000000001".parse::<Decimal>(),
+            Err(ParseDecimalError::TooPrecise)
+        );
+        assert_eq!(
+            "6277101735386680763835789423207666416102.355444464034512896".parse::<Decimal>(),
+            Err(ParseDecimalError::Overflow)
+        );
+        assert!(serde_json::from_str::<Decimal>("\"-1.0\"").is_err());
+        assert!(serde_json::from_str::<Decimal>("1").is_err());
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Wads(#[serde(with = "wads")] Decimal);
+
+    #[test]
+    fn test_wads() {
+        assert_eq!(serde_json::to_string(&Wads(Decimal::zero())).unwrap(), "0");
+        assert_eq!(
+            serde_json::to_string(&Wads(Decimal::one())).unwrap(),
+            "1000000000000000000"
+        );
+        assert_eq!(
+            serde_json::to_string(&Wads(Decimal::from_scaled_val(i128::MAX as u128))).unwrap(),
+            i128::MAX.to_string()
+        );
+
+        for decimal in extreme_values() {
+            match serde_json::to_string(&Wads(decimal)) {
+                Ok(json) => {
+                    assert!(decimal.to_scaled_val().unwrap() <= i128::MAX as u128);
+                    assert_eq!(serde_json::from_str::<Wads>(&json).unwrap(), Wads(decimal));
+                }
+                Err(_) => assert!(decimal > Decimal::from_scaled_val(i128::MAX as u128)),
+            }
+        }
+
+        assert!(serde_json::from_str::<Wads>("-1").is_err());
+        assert!(serde_json::from_str::<Wads>(&i128::MIN.to_string()).is_err());
+        assert!(serde_json::from_str::<Wads>("\"1\"").is_err());
+    }
+
+    proptest! {
+        #[test]
+        fn canonical_forms_round_trip(words in any::<[u64; 3]>()) {
+            let decimal = Decimal(U192(words));
+            let string = decimal.to_string();
+            prop_assert_eq!(string.len() - string.find('.').unwrap(), SCALE + 1);
+            prop_assert_eq!(string.parse::<Decimal>(), Ok(decimal));
+
+            // any count of wads up to i128::MAX
+            let wads = Decimal::from_scaled_val(u128::from(words[0]) | (u128::from(words[1] >> 1) << 64));
+            let json = serde_json::to_string(&Wads(wads)).unwrap();
+            prop_assert_eq!(serde_json::from_str::<Wads>(&json).unwrap(), Wads(wads));
+        }
+    }
 }
@@ -1,9 +1,15 @@
 //! Math for preserving precision
 
+mod amounts;
+mod apy;
 mod common;
 mod decimal;
 mod rate;
+mod units;
 
+pub use amounts::*;
+pub use apy::*;
 pub use common::*;
 pub use decimal::*;
 pub use rate::*;
+pub use units::*;
@@ -32,18 +32,43 @@ construct_uint! {
 }
 
 /// Small decimal values, precise to 18 digits
+///
+/// Used for ratios such as interest rates and utilization, where values stay close to one.
+///
+/// # Examples
+///
+/// ```
+/// use solend_sdk::math::{Decimal, Rate, TryAdd, TryMul};
+///
+/// let rate = Rate::from_percent(10);
+/// assert_eq!(rate, Rate::from_bps(1_000));
+///
+/// // 10% compounded over two periods
+/// let growth = Rate::one().try_add(rate).unwrap().try_pow(2).unwrap();
+/// assert_eq!(growth.to_string(), "1.210000000000000000");
+///
+/// // rates scale decimal amounts
+/// let interest = Decimal::from(500u64).try_mul(rate).unwrap();
+/// assert_eq!(interest, Decimal::from(50u64));
+/// ```
 #[derive(Clone, Copy, Debug, Default, PartialEq, PartialOrd, Eq, Ord)]
 pub struct Rate(pub U128);
 
 impl Rate {
     /// One
-    pub fn one() -> Self {
-        Self(Self::wad())
+    pub const ONE: Self = Self::from_scaled_val(WAD);
+
+    /// Zero
+    pub const ZERO: Self = Self::from_scaled_val(0);
+
+    /// One
+    pub const fn one() -> Self {
+        Self::ONE
     }
 
     /// Zero
-    pub fn zero() -> Self {
-        Self(U128::from(0))
+    pub const fn zero() -> Self {
+        Self::ZERO
     }
 
     // OPTIMIZE: use const slice when fixed in BPF toolchain
@@ -52,13 +77,18 @@ impl Rate {
     }
 
     /// Create scaled decimal from percent value
-    pub fn from_percent(percent: u8) -> Self {
-        Self(U128::from(percent as u64 * PERCENT_SCALER))
+    pub const fn from_percent(percent: u8) -> Self {
+        Self::from_scaled_val(percent as u64 * PERCENT_SCALER)
     }
 
     /// Create scaled decimal from percent value
-    pub fn from_percent_u64(percent: u64) -> Self {
-        Self(U128::from(percent) * PERCENT_SCALER)
+    pub const fn from_percent_u64(percent: u64) -> Self {
+        Self::from_scaled_val_u128(percent as u128 * PERCENT_SCALER as u128)
+    }
+
+    /// Create scaled decimal from bps value
+    pub const fn from_bps(bps: u64) -> Self {
+        Self::from_scaled_val_u128(bps as u128 * BPS_SCALER as u128)
     }
 
     /// Return raw scaled value
@@ -68,8 +98,13 @@ impl Rate {
     }
 
     /// Create decimal from scaled value
-    pub fn from_scaled_val(scaled_val: u64) -> Self {
-        Self(U128::from(scaled_val))
+    pub const fn from_scaled_val(scaled_val: u64) -> Self {
+        Self(U128([scaled_val, 0]))
+    }
+
+    /// Create decimal from a scaled value wider than u64
+    pub const fn from_scaled_val_u128(scaled_val: u128) -> Self {
+        Self(U128([scaled_val as u64, (scaled_val >> 64) as u64]))
     }
 
     /// Calculates base^exp
@@ -188,6 +223,21 @@ mod test {
         assert_eq!(Rate::from_percent(50).to_scaled_val(), HALF_WAD as u128);
     }
 
+    #[test]
+    fn test_const_constructors() {
+        const PRESETS: [Rate; 3] = [
+            Rate::from_percent(50),
+            Rate::from_bps(2_500),
+            Rate::from_percent_u64(1_000_000),
+        ];
+
+        assert_eq!(PRESETS[0], Rate::one().try_div(2u64).unwrap());
+        assert_eq!(PRESETS[1], Decimal::from_percent(25).try_into().unwrap());
+        assert_eq!(PRESETS[2], Decimal::from(10_000u64).try_into().unwrap());
+        assert_eq!(Rate::ONE, Rate::one());
+        assert_eq!(Rate::ZERO, Rate::from_scaled_val(0));
+    }
+
     #[test]
     fn checked_pow() {
         assert_eq!(Rate::one(), Rate::one().try_pow(u64::MAX).unwrap());
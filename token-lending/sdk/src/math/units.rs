@@ -0,0 +1,176 @@
+//! Typed percent, bps and deca-bps values.
+//!
+//! Reserve configs pack ratios as bare integers in three different units, so a `u8` could be a
+//! percent or deca bps and a `u64` could be bps or a percent. These wrappers carry the unit with
+//! the value. Widening to bps is always exact; narrowing back fails unless the value is
+//! representable in the narrower unit. `ReserveConfig` exposes its ratio fields through accessors
+//! returning these types.
+
+use crate::{
+    error::LendingError,
+    math::{Decimal, Rate, TryAdd, TrySub},
+};
+use solana_program::program_error::ProgramError;
+use std::{convert::TryFrom, fmt};
+
+/// Ratio in percent, 1 = 1%
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Percent(pub u8);
+
+/// Ratio in basis points, 1 = 0.01%
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Bps(pub u64);
+
+/// Ratio in tens of basis points, 1 = 0.1%
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DecaBps(pub u8);
+
+const BPS_PER_PERCENT: u64 = 100;
+const BPS_PER_DECA_BPS: u64 = 10;
+
+impl From<Percent> for Bps {
+    fn from(percent: Percent) -> Self {
+        Self(percent.0 as u64 * BPS_PER_PERCENT)
+    }
+}
+
+impl From<DecaBps> for Bps {
+    fn from(deca_bps: DecaBps) -> Self {
+        Self(deca_bps.0 as u64 * BPS_PER_DECA_BPS)
+    }
+}
+
+fn narrow_bps(bps: Bps, scale: u64) -> Result<u8, ProgramError> {
+    if bps.0 % scale != 0 {
+        return Err(LendingError::InvalidAmount.into());
+    }
+    u8::try_from(bps.0 / scale).map_err(|_| LendingError::MathOverflow.into())
+}
+
+impl TryFrom<Bps> for Percent {
+    type Error = ProgramError;
+    /// Fails if `bps` isn't a whole number of percent or doesn't fit in a `u8`
+    fn try_from(bps: Bps) -> Result<Self, Self::Error> {
+        narrow_bps(bps, BPS_PER_PERCENT).map(Self)
+    }
+}
+
+impl TryFrom<Bps> for DecaBps {
+    type Error = ProgramError;
+    /// Fails if `bps` isn't a whole number of deca bps or doesn't fit in a `u8`
+    fn try_from(bps: Bps) -> Result<Self, Self::Error> {
+        narrow_bps(bps, BPS_PER_DECA_BPS).map(Self)
+    }
+}
+
+impl From<Percent> for Decimal {
+    fn from(percent: Percent) -> Self {
+        Decimal::from_percent(percent.0)
+    }
+}
+
+impl From<Bps> for Decimal {
+    fn from(bps: Bps) -> Self {
+        Decimal::from_bps(bps.0)
+    }
+}
+
+impl From<DecaBps> for Decimal {
+    fn from(deca_bps: DecaBps) -> Self {
+        Decimal::from_deca_bps(deca_bps.0)
+    }
+}
+
+impl From<Percent> for Rate {
+    fn from(percent: Percent) -> Self {
+        Rate::from_percent(percent.0)
+    }
+}
+
+impl From<Bps> for Rate {
+    fn from(bps: Bps) -> Self {
+        Rate::from_bps(bps.0)
+    }
+}
+
+impl From<DecaBps> for Rate {
+    fn from(deca_bps: DecaBps) -> Self {
+        Rate::from(Bps::from(deca_bps))
+    }
+}
+
+impl TryAdd for Bps {
+    fn try_add(self, rhs: Self) -> Result<Self, ProgramError> {
+        Ok(Self(
+            self.0
+                .checked_add(rhs.0)
+                .ok_or(LendingError::MathOverflow)?,
+        ))
+    }
+}
+
+impl TrySub for Bps {
+    fn try_sub(self, rhs: Self) -> Result<Self, ProgramError> {
+        Ok(Self(
+            self.0
+                .checked_sub(rhs.0)
+                .ok_or(LendingError::MathOverflow)?,
+        ))
+    }
+}
+
+impl fmt::Display for Percent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}%", self.0)
+    }
+}
+
+impl fmt::Display for Bps {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} bps", self.0)
+    }
+}
+
+impl fmt::Display for DecaBps {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} deca bps", self.0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn unit_conversions() {
+        assert_eq!(Bps::from(Percent(255)), Bps(25_500));
+        assert_eq!(Bps::from(DecaBps(255)), Bps(2_550));
+        assert_eq!(Percent::try_from(Bps(2_500)), Ok(Percent(25)));
+        assert_eq!(DecaBps::try_from(Bps(2_550)), Ok(DecaBps(255)));
+
+        assert_eq!(
+            Percent::try_from(Bps(2_550)),
+            Err(LendingError::InvalidAmount.into())
+        );
+        assert_eq!(
+            Percent::try_from(Bps(25_600)),
+            Err(LendingError::MathOverflow.into())
+        );
+        assert_eq!(
+            DecaBps::try_from(Bps(2_560)),
+            Err(LendingError::MathOverflow.into())
+        );
+    }
+
+    #[test]
+    fn units_to_decimal_and_rate() {
+        assert_eq!(Decimal::from(Percent(5)), Decimal::from(Bps(500)));
+        assert_eq!(Decimal::from(DecaBps(50)), Decimal::from(Bps(500)));
+        assert_eq!(Rate::from(Percent(5)), Rate::from(Bps(500)));
+        assert_eq!(Rate::from(DecaBps(50)), Rate::from(Bps(500)));
+        assert_eq!(
+            Rate::from(Percent(5)),
+            Rate::try_from(Decimal::from(Percent(5))).unwrap()
+        );
+    }
+}
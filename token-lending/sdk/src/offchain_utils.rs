@@ -6,60 +6,171 @@ use solana_program::slot_history::Slot;
 use solana_program::program_error::ProgramError;
 use std::result::Result;
 
-use crate::{state::LastUpdate, NULL_PUBKEY};
+use crate::{
+    instruction::find_liquidation_stats_address, oracles::get_pyth_price_from_data,
+    state::LastUpdate, switchboard_v2_devnet, switchboard_v2_mainnet, NULL_PUBKEY,
+};
 
-use solana_program::{program_pack::Pack, pubkey::Pubkey};
+use solana_program::{clock::Clock, program_pack::Pack, pubkey::Pubkey};
 
-use crate::math::{Decimal, Rate, TryAdd, TryMul};
+use crate::math::{CollateralAmount, Decimal, LiquidityAmount, Rate, TryAdd, TryDiv, TryMul};
+use switchboard_v2::AggregatorAccountData;
 
-use crate::state::{LendingMarket, Obligation, Reserve};
-use std::{collections::HashMap, error::Error};
+use crate::state::{
+    classify_account, AccountType, CalculateLiquidationResult, LendingMarket, LiquidationStats,
+    Obligation, ObligationBorrowCap, ObligationCollateral, ObligationLiquidity, Reserve,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    fmt,
+};
 
-#[derive(Debug, Clone)]
+/// Maximum number of accounts a single getMultipleAccounts request can fetch
+pub const MAX_MULTIPLE_ACCOUNTS: usize = 100;
+
+/// Oracle prices older than this many slots are treated as missing, same as on chain
+const STALE_AFTER_SLOTS_ELAPSED: u64 = 240;
+
+/// Why a reserve has no usable oracle price off chain
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OracleError {
+    /// Both oracles of the reserve are NULL_PUBKEY, or none of its oracles were fetched
+    MissingOracle {
+        /// Reserve without an oracle
+        reserve: Pubkey,
+    },
+    /// The oracle price is stale, too uncertain or negative, or the oracle account doesn't exist
+    StalePrice {
+        /// Oracle with the unusable price
+        oracle: Pubkey,
+    },
+    /// The oracle account holds neither a pyth price nor a switchboard v2 aggregator
+    OracleParseFailure {
+        /// Oracle that doesn't parse
+        oracle: Pubkey,
+    },
+}
+
+impl fmt::Display for OracleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OracleError::MissingOracle { reserve } => {
+                write!(f, "reserve {} has no oracle price", reserve)
+            }
+            OracleError::StalePrice { oracle } => {
+                write!(
+                    f,
+                    "oracle {} price is missing, stale or too uncertain",
+                    oracle
+                )
+            }
+            OracleError::OracleParseFailure { oracle } => {
+                write!(f, "oracle {} cannot be parsed", oracle)
+            }
+        }
+    }
+}
+
+impl Error for OracleError {}
+
+/// What a bulk reserve refresh does with reserves that have no usable oracle price
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MissingPricePolicy {
+    /// Leave the reserve as it was, without accruing interest
+    Skip,
+    /// Fail the whole refresh
+    #[default]
+    Error,
+    /// Accrue interest and keep the last market price of the reserve, leaving it marked stale
+    UseLastKnown,
+}
+
+#[derive(Debug, Clone, Default)]
 pub struct SolendAccounts {
     pub lending_markets: HashMap<Pubkey, LendingMarket>,
     pub reserves: HashMap<Pubkey, Reserve>,
     pub obligations: HashMap<Pubkey, Obligation>,
+    /// Borrow caps set by obligation owners, by obligation
+    pub obligation_borrow_caps: HashMap<Pubkey, ObligationBorrowCap>,
+}
+
+impl SolendAccounts {
+    /// Add a lending program account by its type. Only obligations with borrows are kept, and
+    /// accounts of other types or that don't unpack are ignored.
+    pub(crate) fn insert_account(&mut self, pubkey: Pubkey, data: &[u8]) {
+        match classify_account(data) {
+            Some(AccountType::Obligation) => {
+                if let Ok(o) = Obligation::unpack(data) {
+                    if !o.borrows.is_empty() {
+                        self.obligations.insert(pubkey, o);
+                    }
+                }
+            }
+            Some(AccountType::Reserve) => {
+                if let Ok(r) = Reserve::unpack(data) {
+                    self.reserves.insert(pubkey, r);
+                }
+            }
+            Some(AccountType::LendingMarket) => {
+                if let Ok(l) = LendingMarket::unpack(data) {
+                    self.lending_markets.insert(pubkey, l);
+                }
+            }
+            Some(AccountType::ObligationBorrowCap) => {
+                if let Ok(c) = ObligationBorrowCap::unpack(data) {
+                    self.obligation_borrow_caps.insert(c.obligation, c);
+                }
+            }
+            _ => (),
+        }
+    }
 }
 
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(skip_all, fields(program = %lending_program_id), err)
+)]
 pub fn get_solend_accounts_as_map(
     lending_program_id: &Pubkey,
     client: &RpcClient,
 ) -> Result<SolendAccounts, Box<dyn Error>> {
     let accounts = client.get_program_accounts(lending_program_id)?;
 
-    let (lending_markets, reserves, obligations) = accounts.into_iter().fold(
-        (HashMap::new(), HashMap::new(), HashMap::new()),
-        |(mut lending_markets, mut reserves, mut obligations), (pubkey, account)| {
-            match account.data.len() {
-                Obligation::LEN => {
-                    if let Ok(o) = Obligation::unpack(&account.data) {
-                        if !o.borrows.is_empty() {
-                            obligations.insert(pubkey, o);
-                        }
-                    }
-                }
-                Reserve::LEN => {
-                    if let Ok(r) = Reserve::unpack(&account.data) {
-                        reserves.insert(pubkey, r);
-                    }
-                }
-                LendingMarket::LEN => {
-                    if let Ok(l) = LendingMarket::unpack(&account.data) {
-                        lending_markets.insert(pubkey, l);
-                    }
-                }
-                _ => (),
-            };
-            (lending_markets, reserves, obligations)
-        },
+    let mut solend_accounts = SolendAccounts::default();
+    for (pubkey, account) in accounts {
+        solend_accounts.insert_account(pubkey, &account.data);
+    }
+    trace_event!(
+        info,
+        lending_markets = solend_accounts.lending_markets.len(),
+        reserves = solend_accounts.reserves.len(),
+        obligations = solend_accounts.obligations.len(),
+        "fetched lending program accounts"
     );
 
-    Ok(SolendAccounts {
-        lending_markets,
-        reserves,
-        obligations,
-    })
+    Ok(solend_accounts)
+}
+
+/// Fetch the liquidation stats of a lending market, or None if the market never created them
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(skip_all, fields(lending_market = %lending_market), err)
+)]
+pub fn get_liquidation_stats(
+    lending_program_id: &Pubkey,
+    lending_market: &Pubkey,
+    client: &RpcClient,
+) -> Result<Option<LiquidationStats>, Box<dyn Error>> {
+    let (liquidation_stats_pubkey, _) =
+        find_liquidation_stats_address(lending_program_id, lending_market);
+    let account = client
+        .get_account_with_commitment(&liquidation_stats_pubkey, client.commitment())?
+        .value;
+    match account {
+        Some(account) => Ok(Some(LiquidationStats::unpack(&account.data)?)),
+        None => Ok(None),
+    }
 }
 
 pub fn offchain_refresh_reserve_interest(
@@ -72,66 +183,229 @@ pub fn offchain_refresh_reserve_interest(
     Ok(())
 }
 
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "debug", skip_all, fields(reserve = %pubkey), err)
+)]
 pub fn offchain_refresh_reserve(
-    _pubkey: &Pubkey,
+    pubkey: &Pubkey,
     reserve: &mut Reserve,
     slot: Slot,
     prices: &HashMap<Pubkey, Option<Decimal>>,
 ) -> Result<(), Box<dyn Error>> {
-    let pyth_oracle = reserve.liquidity.pyth_oracle_pubkey;
-    let switchboard_oracle = reserve.liquidity.switchboard_oracle_pubkey;
+    reserve.liquidity.market_price = reserve_oracle_price(pubkey, reserve, prices)?;
+    reserve.accrue_interest(slot)?;
+    reserve.last_update = LastUpdate { slot, stale: false };
 
-    let price = if let Some(Some(price)) = prices.get(&pyth_oracle) {
-        if pyth_oracle != NULL_PUBKEY {
-            Some(*price)
-        } else {
-            None
+    Ok(())
+}
+
+/// Price of a reserve from `prices`: its pyth price, falling back to its switchboard price.
+/// Oracles set to NULL_PUBKEY are ignored.
+pub fn reserve_oracle_price(
+    reserve_pubkey: &Pubkey,
+    reserve: &Reserve,
+    prices: &HashMap<Pubkey, Option<Decimal>>,
+) -> Result<Decimal, OracleError> {
+    let mut stale_oracle = None;
+    for oracle in [
+        reserve.liquidity.pyth_oracle_pubkey,
+        reserve.liquidity.switchboard_oracle_pubkey,
+    ] {
+        if oracle == NULL_PUBKEY {
+            continue;
         }
-    } else if let Some(Some(price)) = prices.get(&switchboard_oracle) {
-        if switchboard_oracle != NULL_PUBKEY {
-            Some(*price)
-        } else {
-            None
+        match prices.get(&oracle) {
+            Some(Some(price)) => return Ok(*price),
+            Some(None) => {
+                stale_oracle.get_or_insert(oracle);
+            }
+            None => (),
         }
-    } else {
-        None
-    };
+    }
 
-    if let Some(price) = price {
+    Err(match stale_oracle {
+        Some(oracle) => OracleError::StalePrice { oracle },
+        None => OracleError::MissingOracle {
+            reserve: *reserve_pubkey,
+        },
+    })
+}
+
+/// Refresh every reserve in `reserves` with `prices` as of `slot`, handling reserves without a
+/// usable price according to `policy`. Returns the reserves that had no price, along with why,
+/// unless the policy is to fail.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(skip_all, fields(reserves = reserves.len(), ?policy), err)
+)]
+pub fn offchain_refresh_reserves(
+    reserves: &mut HashMap<Pubkey, Reserve>,
+    slot: Slot,
+    prices: &HashMap<Pubkey, Option<Decimal>>,
+    policy: MissingPricePolicy,
+) -> Result<Vec<(Pubkey, OracleError)>, Box<dyn Error>> {
+    let mut missing = Vec::new();
+    for (pubkey, reserve) in reserves.iter_mut() {
+        let price = match reserve_oracle_price(pubkey, reserve, prices) {
+            Ok(price) => price,
+            Err(err) => {
+                trace_event!(warn, reserve = %pubkey, error = %err, "reserve has no price");
+                match policy {
+                    MissingPricePolicy::Error => return Err(err.into()),
+                    MissingPricePolicy::Skip => (),
+                    MissingPricePolicy::UseLastKnown => {
+                        reserve.accrue_interest(slot)?;
+                        reserve.last_update = LastUpdate { slot, stale: true };
+                    }
+                }
+                missing.push((*pubkey, err));
+                continue;
+            }
+        };
         reserve.liquidity.market_price = price;
+        reserve.accrue_interest(slot)?;
+        reserve.last_update = LastUpdate { slot, stale: false };
+    }
+
+    Ok(missing)
+}
+
+/// Fetch the prices of every oracle used by `reserves`, in the format `offchain_refresh_reserve`
+/// expects. Oracle accounts are fetched in batches of MAX_MULTIPLE_ACCOUNTS.
+///
+/// Prices that are missing, stale or, for pyth, too uncertain map to `None`. Switchboard v1 feeds
+/// aren't supported and always map to `None`.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(skip_all, fields(reserves = reserves.len()), err)
+)]
+pub fn get_oracle_prices(
+    client: &RpcClient,
+    reserves: &HashMap<Pubkey, Reserve>,
+) -> Result<HashMap<Pubkey, Option<Decimal>>, Box<dyn Error>> {
+    let oracles = oracle_pubkeys(reserves);
+
+    let slot = client.get_slot()?;
+    let mut prices = HashMap::new();
+    for chunk in oracles.chunks(MAX_MULTIPLE_ACCOUNTS) {
+        let accounts = client.get_multiple_accounts(chunk)?;
+        for (oracle, account) in chunk.iter().zip(accounts) {
+            let price = account.and_then(|account| {
+                parse_oracle_price(oracle, &account.owner, &account.data, slot)
+            });
+            prices.insert(*oracle, price);
+        }
+    }
+    trace_event!(
+        info,
+        oracles = prices.len(),
+        missing = prices.values().filter(|price| price.is_none()).count(),
+        "fetched oracle prices"
+    );
+
+    Ok(prices)
+}
+
+/// Distinct oracles of `reserves`, leaving out unset ones
+pub(crate) fn oracle_pubkeys(reserves: &HashMap<Pubkey, Reserve>) -> Vec<Pubkey> {
+    reserves
+        .values()
+        .flat_map(|reserve| {
+            [
+                reserve.liquidity.pyth_oracle_pubkey,
+                reserve.liquidity.switchboard_oracle_pubkey,
+            ]
+        })
+        .filter(|oracle| *oracle != NULL_PUBKEY)
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect()
+}
+
+/// Parse the price of a pyth or switchboard v2 oracle account as of `slot`, with the same
+/// staleness and confidence checks as the program
+pub fn parse_oracle_price(
+    oracle: &Pubkey,
+    owner: &Pubkey,
+    data: &[u8],
+    slot: Slot,
+) -> Option<Decimal> {
+    let price = try_parse_oracle_price(oracle, owner, data, slot);
+    if let Err(_err) = &price {
+        trace_event!(debug, oracle = %oracle, error = %_err, "oracle price is unusable");
+    }
+    price.ok()
+}
+
+/// Same as `parse_oracle_price`, but tells an account that isn't an oracle apart from an oracle
+/// whose price is unusable
+pub fn try_parse_oracle_price(
+    oracle: &Pubkey,
+    owner: &Pubkey,
+    data: &[u8],
+    slot: Slot,
+) -> Result<Decimal, OracleError> {
+    if *owner == switchboard_v2_mainnet::id() || *owner == switchboard_v2_devnet::id() {
+        let feed = AggregatorAccountData::new_from_bytes(data)
+            .map_err(|_| OracleError::OracleParseFailure { oracle: *oracle })?;
+        parse_switchboard_v2_price(feed, slot).ok_or(OracleError::StalePrice { oracle: *oracle })
     } else {
-        return Err("No price".into());
+        if pyth_sdk_solana::state::load_price_account(data).is_err() {
+            return Err(OracleError::OracleParseFailure { oracle: *oracle });
+        }
+        let clock = Clock {
+            slot,
+            ..Clock::default()
+        };
+        get_pyth_price_from_data(oracle, data, &clock)
+            .map(|(price, _ema_price)| price)
+            .map_err(|_| OracleError::StalePrice { oracle: *oracle })
     }
+}
 
-    reserve.accrue_interest(slot)?;
-    reserve.last_update = LastUpdate { slot, stale: false };
+fn parse_switchboard_v2_price(feed: &AggregatorAccountData, slot: Slot) -> Option<Decimal> {
+    if slot.saturating_sub(feed.latest_confirmed_round.round_open_slot) >= STALE_AFTER_SLOTS_ELAPSED
+    {
+        return None;
+    }
 
-    Ok(())
+    let price = feed.get_result().ok()?;
+    if price.mantissa < 0 {
+        return None;
+    }
+    let scale = 10u128.checked_pow(price.scale)?;
+    Decimal::from(price.mantissa as u128)
+        .try_div(Decimal::from(scale))
+        .ok()
 }
 
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "debug", skip_all, fields(owner = %o.owner), err)
+)]
 pub fn offchain_refresh_obligation(
     o: &mut Obligation,
     reserves: &HashMap<Pubkey, Reserve>,
 ) -> Result<(), Box<dyn Error>> {
+    o.check_position_reserves(reserves)?;
+
     o.deposited_value = Decimal::zero();
     o.super_unhealthy_borrow_value = Decimal::zero();
     o.unhealthy_borrow_value = Decimal::zero();
     o.borrowed_value = Decimal::zero();
 
     for collateral in &mut o.deposits {
-        let deposit_reserve = reserves
-            .get(&collateral.deposit_reserve)
-            .ok_or(ProgramError::Custom(35))?;
+        let deposit_reserve = &reserves[&collateral.deposit_reserve];
 
         let liquidity_amount = deposit_reserve
             .collateral_exchange_rate()?
             .decimal_collateral_to_liquidity(collateral.deposited_amount.into())?;
 
         let market_value = deposit_reserve.market_value(liquidity_amount)?;
-        let liquidation_threshold_rate =
-            Rate::from_percent(deposit_reserve.config.liquidation_threshold);
+        let liquidation_threshold_rate = Rate::from(deposit_reserve.config.liquidation_threshold());
         let max_liquidation_threshold_rate =
-            Rate::from_percent(deposit_reserve.config.max_liquidation_threshold);
+            Rate::from(deposit_reserve.config.max_liquidation_threshold());
 
         collateral.market_value = market_value;
 
@@ -147,8 +421,8 @@ pub fn offchain_refresh_obligation(
     let mut max_borrow_weight = None;
 
     for (index, liquidity) in o.borrows.iter_mut().enumerate() {
-        let borrow_reserve = reserves.get(&liquidity.borrow_reserve).unwrap();
-        liquidity.accrue_interest(borrow_reserve.liquidity.cumulative_borrow_rate_wads)?;
+        let borrow_reserve = &reserves[&liquidity.borrow_reserve];
+        liquidity.accrue_reserve_interest(borrow_reserve, borrow_reserve.last_update.slot)?;
 
         let market_value = borrow_reserve.market_value(liquidity.borrowed_amount_wads)?;
         liquidity.market_value = market_value;
@@ -178,3 +452,659 @@ pub fn offchain_refresh_obligation(
 
     Ok(())
 }
+
+/// An obligation and the reserves it deposits into or borrows from, refreshed off-chain
+#[derive(Debug, Clone)]
+pub struct ObligationWithReserves {
+    /// Refreshed obligation
+    pub obligation: Obligation,
+    /// Refreshed reserves of the obligation, by address
+    pub reserves: HashMap<Pubkey, Reserve>,
+    /// Slot the oracle prices were fetched at, and the reserves refreshed to
+    pub slot: Slot,
+}
+
+/// Fetch an obligation along with exactly the reserve and oracle accounts needed to refresh it,
+/// and refresh them off-chain, e.g. to simulate a liquidation without pulling every program
+/// account. Takes three requests: the obligation, then one getMultipleAccounts batch for its
+/// reserves and one for their oracles, whose addresses are only known once the reserves are in.
+/// An obligation has at most MAX_OBLIGATION_RESERVES positions, so each batch fits in one request.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(skip_all, fields(obligation = %obligation_pubkey), err)
+)]
+pub fn fetch_obligation_with_reserves(
+    client: &RpcClient,
+    obligation_pubkey: &Pubkey,
+) -> Result<ObligationWithReserves, Box<dyn Error>> {
+    let mut obligation = Obligation::unpack(&client.get_account(obligation_pubkey)?.data)?;
+
+    let mut reserve_pubkeys = Vec::new();
+    for reserve_pubkey in obligation
+        .deposits
+        .iter()
+        .map(|collateral| collateral.deposit_reserve)
+        .chain(
+            obligation
+                .borrows
+                .iter()
+                .map(|liquidity| liquidity.borrow_reserve),
+        )
+    {
+        if !reserve_pubkeys.contains(&reserve_pubkey) {
+            reserve_pubkeys.push(reserve_pubkey);
+        }
+    }
+    let mut reserves = HashMap::new();
+    for (reserve_pubkey, account) in reserve_pubkeys
+        .iter()
+        .zip(client.get_multiple_accounts(&reserve_pubkeys)?)
+    {
+        let account = account.ok_or_else(|| format!("Reserve {} not found", reserve_pubkey))?;
+        reserves.insert(*reserve_pubkey, Reserve::unpack(&account.data)?);
+    }
+
+    let mut oracles = Vec::new();
+    for oracle in reserves.values().flat_map(|reserve| {
+        [
+            reserve.liquidity.pyth_oracle_pubkey,
+            reserve.liquidity.switchboard_oracle_pubkey,
+        ]
+    }) {
+        if oracle != NULL_PUBKEY && !oracles.contains(&oracle) {
+            oracles.push(oracle);
+        }
+    }
+    let response = client.get_multiple_accounts_with_commitment(&oracles, client.commitment())?;
+    let slot = response.context.slot;
+    let prices = oracles
+        .iter()
+        .zip(response.value)
+        .map(|(oracle, account)| {
+            let price = account.and_then(|account| {
+                parse_oracle_price(oracle, &account.owner, &account.data, slot)
+            });
+            (*oracle, price)
+        })
+        .collect::<HashMap<_, _>>();
+
+    refresh_obligation_and_reserves(&mut obligation, &mut reserves, &prices, slot)?;
+    trace_event!(
+        debug,
+        reserves = reserves.len(),
+        oracles = prices.len(),
+        slot,
+        "fetched obligation with reserves"
+    );
+
+    Ok(ObligationWithReserves {
+        obligation,
+        reserves,
+        slot,
+    })
+}
+
+/// Refresh `reserves` with `prices` as of `slot`, then `obligation` with the refreshed reserves
+pub fn refresh_obligation_and_reserves(
+    obligation: &mut Obligation,
+    reserves: &mut HashMap<Pubkey, Reserve>,
+    prices: &HashMap<Pubkey, Option<Decimal>>,
+    slot: Slot,
+) -> Result<(), Box<dyn Error>> {
+    offchain_refresh_reserves(reserves, slot, prices, MissingPricePolicy::Error)?;
+    offchain_refresh_obligation(obligation, reserves)
+}
+
+/// Constant product (x * y = k) pool used to estimate the slippage of selling seized collateral
+#[derive(Debug, Clone, Copy)]
+pub struct ConstantProductPool {
+    /// Pool balance of the token being sold (the withdraw reserve liquidity)
+    pub input_reserve: u64,
+    /// Pool balance of the token being bought (the repay reserve liquidity)
+    pub output_reserve: u64,
+    /// Swap fee in basis points
+    pub fee_bps: u64,
+}
+
+impl ConstantProductPool {
+    /// Amount of output tokens received for selling `amount_in` input tokens
+    pub fn swap_output(&self, amount_in: u64) -> u64 {
+        let amount_in =
+            amount_in as u128 * 10_000u128.saturating_sub(self.fee_bps as u128) / 10_000;
+        let denominator = self.input_reserve as u128 + amount_in;
+        if denominator == 0 {
+            return 0;
+        }
+        (self.output_reserve as u128 * amount_in / denominator) as u64
+    }
+}
+
+/// A single LiquidateObligationAndRedeemReserveCollateral call sized for profit
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LiquidationSizing {
+    /// Amount of repay reserve liquidity to pass to the liquidate instruction
+    pub repay_amount: LiquidityAmount,
+    /// Amount of collateral seized from the obligation
+    pub withdraw_collateral_amount: CollateralAmount,
+    /// Amount of withdraw reserve liquidity received after redeeming and protocol fees
+    pub withdraw_liquidity_amount: LiquidityAmount,
+    /// Amount of repay reserve liquidity received from selling the withdrawn liquidity
+    pub swap_output: LiquidityAmount,
+    /// swap_output - repay_amount, in repay reserve liquidity
+    pub profit: i128,
+}
+
+/// Find the repay amount that maximizes the profit of one liquidation call when the seized
+/// collateral is sold back into the repay token. `swap` returns the repay tokens received for
+/// selling a given amount of withdraw reserve liquidity, e.g. `|a| pool.swap_output(a)` for a
+/// [ConstantProductPool]. Profit is assumed to be concave in the repay amount, which holds for
+/// any swap whose marginal price only gets worse with size.
+///
+/// Returns None if no repay amount is profitable.
+pub fn optimal_liquidation_amount<F: Fn(u64) -> u64>(
+    withdraw_reserve: &Reserve,
+    obligation: &Obligation,
+    liquidity: &ObligationLiquidity,
+    collateral: &ObligationCollateral,
+    swap: F,
+) -> Result<Option<LiquidationSizing>, ProgramError> {
+    let collateral_exchange_rate = withdraw_reserve.collateral_exchange_rate()?;
+    let max_redeemable_collateral = collateral_exchange_rate
+        .liquidity_to_collateral(withdraw_reserve.liquidity.available_amount)?;
+
+    let size = |amount: u64| -> Result<LiquidationSizing, ProgramError> {
+        let CalculateLiquidationResult {
+            repay_amount,
+            withdraw_amount,
+            bonus_rate,
+            ..
+        } = withdraw_reserve.calculate_liquidation(amount, obligation, liquidity, collateral)?;
+
+        // mirrors LiquidateObligationAndRedeemReserveCollateral: only redeemable collateral is
+        // converted to liquidity, and the protocol takes its fee out of the redeemed liquidity
+        let redeemed_collateral = withdraw_amount.min(max_redeemable_collateral);
+        let mut withdraw_liquidity_amount =
+            collateral_exchange_rate.collateral_to_liquidity(redeemed_collateral)?;
+        if withdraw_liquidity_amount > 0 {
+            let protocol_fee = withdraw_reserve
+                .calculate_protocol_liquidation_fee(withdraw_liquidity_amount, bonus_rate)?;
+            withdraw_liquidity_amount = withdraw_liquidity_amount.saturating_sub(protocol_fee);
+        }
+
+        let swap_output = swap(withdraw_liquidity_amount);
+        Ok(LiquidationSizing {
+            repay_amount: LiquidityAmount(repay_amount),
+            withdraw_collateral_amount: CollateralAmount(withdraw_amount),
+            withdraw_liquidity_amount: LiquidityAmount(withdraw_liquidity_amount),
+            swap_output: LiquidityAmount(swap_output),
+            profit: swap_output as i128 - repay_amount as i128,
+        })
+    };
+    let profit = |amount: u64| size(amount).map(|s| s.profit).unwrap_or(i128::MIN);
+
+    let max_repay_amount = size(u64::MAX)?.repay_amount.0;
+    if max_repay_amount == 0 {
+        return Ok(None);
+    }
+
+    // ternary search over the repay amount
+    let (mut lo, mut hi) = (1u64, max_repay_amount);
+    while hi - lo > 2 {
+        let m1 = lo + (hi - lo) / 3;
+        let m2 = hi - (hi - lo) / 3;
+        if profit(m1) < profit(m2) {
+            lo = m1 + 1;
+        } else {
+            hi = m2 - 1;
+        }
+    }
+
+    let best = (lo..=hi)
+        .chain(std::iter::once(max_repay_amount))
+        .filter_map(|amount| size(amount).ok())
+        .max_by_key(|sizing| sizing.profit);
+
+    Ok(best.filter(|sizing| sizing.profit > 0))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::state::{ReserveCollateral, ReserveConfig, ReserveLiquidity};
+    use bytemuck::bytes_of;
+    use pyth_sdk_solana::state::{
+        AccountType, CorpAction, PriceAccount, PriceInfo, PriceStatus, PriceType, MAGIC, VERSION_2,
+    };
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn trace_refresh_errors() {
+        use crate::telemetry::test::RecordingSubscriber;
+
+        let subscriber = RecordingSubscriber::default();
+        let mut reserve = Reserve::default();
+        let result = tracing::subscriber::with_default(subscriber.clone(), || {
+            offchain_refresh_reserve(&Pubkey::new_unique(), &mut reserve, 1, &HashMap::new())
+        });
+
+        assert!(result.is_err());
+        assert_eq!(
+            *subscriber.spans.lock().unwrap(),
+            vec!["offchain_refresh_reserve"]
+        );
+        assert_eq!(
+            *subscriber.events.lock().unwrap(),
+            vec![tracing::Level::ERROR]
+        );
+    }
+
+    #[test]
+    fn parse_oracle_prices() {
+        let oracle = Pubkey::new_unique();
+        let pyth_owner = Pubkey::new_unique();
+        let price_account = |conf| PriceAccount {
+            magic: MAGIC,
+            ver: VERSION_2,
+            atype: AccountType::Price as u32,
+            ptype: PriceType::Price,
+            expo: -1,
+            agg: PriceInfo {
+                price: 200,
+                conf,
+                status: PriceStatus::Trading,
+                corp_act: CorpAction::NoCorpAct,
+                pub_slot: 1_000,
+            },
+            ..PriceAccount::default()
+        };
+
+        let valid = price_account(1);
+        let data = bytes_of(&valid);
+        assert_eq!(
+            parse_oracle_price(&oracle, &pyth_owner, data, 1_100),
+            Some(Decimal::from(20u64))
+        );
+
+        // stale
+        assert_eq!(parse_oracle_price(&oracle, &pyth_owner, data, 1_300), None);
+
+        // confidence interval wider than 10% of the price
+        let uncertain = price_account(21);
+        assert_eq!(
+            parse_oracle_price(&oracle, &pyth_owner, bytes_of(&uncertain), 1_100),
+            None
+        );
+
+        // not a switchboard v2 aggregator
+        assert_eq!(
+            parse_oracle_price(&oracle, &switchboard_v2_mainnet::id(), &[0; 64], 1_100),
+            None
+        );
+
+        // the typed errors tell unusable prices apart from accounts that aren't oracles
+        assert_eq!(
+            try_parse_oracle_price(&oracle, &pyth_owner, data, 1_300),
+            Err(OracleError::StalePrice { oracle })
+        );
+        assert_eq!(
+            try_parse_oracle_price(&oracle, &pyth_owner, bytes_of(&uncertain), 1_100),
+            Err(OracleError::StalePrice { oracle })
+        );
+        assert_eq!(
+            try_parse_oracle_price(&oracle, &pyth_owner, &[0; 64], 1_100),
+            Err(OracleError::OracleParseFailure { oracle })
+        );
+        assert_eq!(
+            try_parse_oracle_price(&oracle, &switchboard_v2_mainnet::id(), &[0; 64], 1_100),
+            Err(OracleError::OracleParseFailure { oracle })
+        );
+    }
+
+    #[test]
+    fn reserve_oracle_prices_with_null_oracles() {
+        let reserve_pubkey = Pubkey::new_unique();
+        let pyth = Pubkey::new_unique();
+        let switchboard = Pubkey::new_unique();
+        let reserve = |pyth_oracle_pubkey, switchboard_oracle_pubkey| Reserve {
+            liquidity: ReserveLiquidity {
+                pyth_oracle_pubkey,
+                switchboard_oracle_pubkey,
+                ..ReserveLiquidity::default()
+            },
+            ..Reserve::default()
+        };
+        let price = |price: u64| Some(Decimal::from(price));
+
+        struct Case {
+            pyth: Pubkey,
+            switchboard: Pubkey,
+            prices: Vec<(Pubkey, Option<Decimal>)>,
+            expected: Result<Decimal, OracleError>,
+        }
+        let missing = Err(OracleError::MissingOracle {
+            reserve: reserve_pubkey,
+        });
+        let cases = [
+            // no oracle at all, even if someone priced NULL_PUBKEY
+            Case {
+                pyth: NULL_PUBKEY,
+                switchboard: NULL_PUBKEY,
+                prices: vec![(NULL_PUBKEY, price(1))],
+                expected: missing,
+            },
+            // switchboard only
+            Case {
+                pyth: NULL_PUBKEY,
+                switchboard,
+                prices: vec![(switchboard, price(2))],
+                expected: Ok(Decimal::from(2u64)),
+            },
+            Case {
+                pyth: NULL_PUBKEY,
+                switchboard,
+                prices: vec![(switchboard, None)],
+                expected: Err(OracleError::StalePrice {
+                    oracle: switchboard,
+                }),
+            },
+            Case {
+                pyth: NULL_PUBKEY,
+                switchboard,
+                prices: vec![],
+                expected: missing,
+            },
+            // pyth only
+            Case {
+                pyth,
+                switchboard: NULL_PUBKEY,
+                prices: vec![(pyth, price(3))],
+                expected: Ok(Decimal::from(3u64)),
+            },
+            Case {
+                pyth,
+                switchboard: NULL_PUBKEY,
+                prices: vec![(pyth, None)],
+                expected: Err(OracleError::StalePrice { oracle: pyth }),
+            },
+            // both, pyth first
+            Case {
+                pyth,
+                switchboard,
+                prices: vec![(pyth, price(3)), (switchboard, price(2))],
+                expected: Ok(Decimal::from(3u64)),
+            },
+            Case {
+                pyth,
+                switchboard,
+                prices: vec![(pyth, None), (switchboard, price(2))],
+                expected: Ok(Decimal::from(2u64)),
+            },
+            Case {
+                pyth,
+                switchboard,
+                prices: vec![(switchboard, None)],
+                expected: Err(OracleError::StalePrice {
+                    oracle: switchboard,
+                }),
+            },
+            Case {
+                pyth,
+                switchboard,
+                prices: vec![(pyth, None), (switchboard, None)],
+                expected: Err(OracleError::StalePrice { oracle: pyth }),
+            },
+        ];
+
+        for case in cases {
+            let mut reserve = reserve(case.pyth, case.switchboard);
+            let prices = case.prices.into_iter().collect();
+            assert_eq!(
+                reserve_oracle_price(&reserve_pubkey, &reserve, &prices),
+                case.expected
+            );
+
+            let result = offchain_refresh_reserve(&reserve_pubkey, &mut reserve, 0, &prices);
+            match case.expected {
+                Ok(price) => {
+                    result.unwrap();
+                    assert_eq!(reserve.liquidity.market_price, price);
+                }
+                Err(err) => assert_eq!(
+                    result.unwrap_err().downcast_ref::<OracleError>(),
+                    Some(&err)
+                ),
+            }
+        }
+    }
+
+    #[test]
+    fn refresh_reserves_with_missing_prices() {
+        let (mut reserve, _) = setup();
+        reserve.last_update.slot = 10;
+        let priced = Pubkey::new_unique();
+        let unpriced = Pubkey::new_unique();
+        let oracle = Pubkey::new_unique();
+        let reserves = HashMap::from([
+            (
+                priced,
+                Reserve {
+                    liquidity: ReserveLiquidity {
+                        pyth_oracle_pubkey: oracle,
+                        switchboard_oracle_pubkey: NULL_PUBKEY,
+                        ..reserve.liquidity.clone()
+                    },
+                    ..reserve.clone()
+                },
+            ),
+            (
+                unpriced,
+                Reserve {
+                    liquidity: ReserveLiquidity {
+                        pyth_oracle_pubkey: NULL_PUBKEY,
+                        switchboard_oracle_pubkey: NULL_PUBKEY,
+                        ..reserve.liquidity.clone()
+                    },
+                    ..reserve
+                },
+            ),
+        ]);
+        let prices = HashMap::from([(oracle, Some(Decimal::from(2u64)))]);
+        let missing = vec![(unpriced, OracleError::MissingOracle { reserve: unpriced })];
+
+        let mut refreshed = reserves.clone();
+        let err = offchain_refresh_reserves(&mut refreshed, 10, &prices, MissingPricePolicy::Error)
+            .unwrap_err();
+        assert_eq!(err.downcast_ref::<OracleError>(), Some(&missing[0].1));
+
+        let mut refreshed = reserves.clone();
+        assert_eq!(
+            offchain_refresh_reserves(&mut refreshed, 10, &prices, MissingPricePolicy::Skip)
+                .unwrap(),
+            missing
+        );
+        assert_eq!(
+            refreshed[&priced].liquidity.market_price,
+            Decimal::from(2u64)
+        );
+        assert_eq!(
+            refreshed[&priced].last_update,
+            LastUpdate {
+                slot: 10,
+                stale: false
+            }
+        );
+        assert_eq!(refreshed[&unpriced], reserves[&unpriced]);
+
+        let mut refreshed = reserves.clone();
+        assert_eq!(
+            offchain_refresh_reserves(
+                &mut refreshed,
+                10,
+                &prices,
+                MissingPricePolicy::UseLastKnown
+            )
+            .unwrap(),
+            missing
+        );
+        assert_eq!(refreshed[&unpriced].liquidity.market_price, Decimal::one());
+        assert_eq!(
+            refreshed[&unpriced].last_update,
+            LastUpdate {
+                slot: 10,
+                stale: true
+            }
+        );
+    }
+
+    #[test]
+    fn refresh_fetched_obligation_and_reserves() {
+        let (mut reserve, mut obligation) = setup();
+        let reserve_pubkey = Pubkey::new_unique();
+        let oracle = Pubkey::new_unique();
+        reserve.liquidity.pyth_oracle_pubkey = oracle;
+        reserve.last_update.slot = 100;
+        obligation.deposits[0].deposit_reserve = reserve_pubkey;
+        obligation.borrows[0].borrow_reserve = reserve_pubkey;
+        let mut reserves = HashMap::from([(reserve_pubkey, reserve)]);
+
+        // missing price
+        let prices = HashMap::from([(oracle, None)]);
+        assert!(
+            refresh_obligation_and_reserves(&mut obligation, &mut reserves, &prices, 100).is_err()
+        );
+
+        let prices = HashMap::from([(oracle, Some(Decimal::from(2u64)))]);
+        refresh_obligation_and_reserves(&mut obligation, &mut reserves, &prices, 100).unwrap();
+        assert_eq!(
+            reserves[&reserve_pubkey].liquidity.market_price,
+            Decimal::from(2u64)
+        );
+        assert!(!reserves[&reserve_pubkey].last_update.stale);
+        assert_eq!(obligation.deposited_value, Decimal::from(2_000_000u64));
+        assert_eq!(obligation.borrowed_value, Decimal::from(1_800_000u64));
+    }
+
+    fn setup() -> (Reserve, Obligation) {
+        // 1 token = $1 on both sides, 10% liquidation bonus, no protocol fee
+        let reserve = Reserve {
+            liquidity: ReserveLiquidity {
+                available_amount: 1_000_000_000,
+                market_price: Decimal::one(),
+                smoothed_market_price: Decimal::one(),
+                ..ReserveLiquidity::default()
+            },
+            collateral: ReserveCollateral {
+                mint_total_supply: 1_000_000_000,
+                ..ReserveCollateral::default()
+            },
+            config: ReserveConfig {
+                liquidation_bonus: 10,
+                max_liquidation_bonus: 10,
+                ..ReserveConfig::default()
+            },
+            ..Reserve::default()
+        };
+
+        let obligation = Obligation {
+            deposits: vec![ObligationCollateral {
+                deposited_amount: 1_000_000,
+                market_value: Decimal::from(1_000_000u64),
+                ..ObligationCollateral::default()
+            }],
+            borrows: vec![ObligationLiquidity {
+                borrowed_amount_wads: Decimal::from(900_000u64),
+                market_value: Decimal::from(900_000u64),
+                ..ObligationLiquidity::default()
+            }],
+            deposited_value: Decimal::from(1_000_000u64),
+            borrowed_value: Decimal::from(900_000u64),
+            unhealthy_borrow_value: Decimal::from(800_000u64),
+            super_unhealthy_borrow_value: Decimal::from(800_000u64),
+            ..Obligation::default()
+        };
+
+        (reserve, obligation)
+    }
+
+    #[test]
+    fn constant_product_swap_output() {
+        let pool = ConstantProductPool {
+            input_reserve: 1_000,
+            output_reserve: 1_000,
+            fee_bps: 0,
+        };
+        assert_eq!(pool.swap_output(0), 0);
+        assert_eq!(pool.swap_output(1_000), 500);
+
+        let pool = ConstantProductPool {
+            fee_bps: 10_000,
+            ..pool
+        };
+        assert_eq!(pool.swap_output(1_000), 0);
+    }
+
+    #[test]
+    fn optimal_liquidation_without_slippage_is_max() {
+        let (reserve, obligation) = setup();
+        let sizing = optimal_liquidation_amount(
+            &reserve,
+            &obligation,
+            &obligation.borrows[0],
+            &obligation.deposits[0],
+            |amount| amount,
+        )
+        .unwrap()
+        .unwrap();
+
+        // close factor caps a single call at 20% of the borrowed value
+        assert_eq!(sizing.repay_amount, LiquidityAmount(180_000));
+        // 10% bonus minus the minimum protocol fee of 1 token
+        assert_eq!(sizing.profit, 17_999);
+    }
+
+    #[test]
+    fn optimal_liquidation_with_slippage() {
+        let (reserve, obligation) = setup();
+        let pool = ConstantProductPool {
+            input_reserve: 1_000_000,
+            output_reserve: 1_000_000,
+            fee_bps: 0,
+        };
+        let sizing = optimal_liquidation_amount(
+            &reserve,
+            &obligation,
+            &obligation.borrows[0],
+            &obligation.deposits[0],
+            |amount| pool.swap_output(amount),
+        )
+        .unwrap()
+        .unwrap();
+
+        // profit(r) = 1e6 * 1.1r / (1e6 + 1.1r) - r peaks around r = 44k
+        assert!(sizing.repay_amount.0 > 40_000 && sizing.repay_amount.0 < 50_000);
+        for repay_amount in [sizing.repay_amount.0 / 2, sizing.repay_amount.0 * 2] {
+            let withdraw_amount = repay_amount * 11 / 10;
+            assert!(
+                (pool.swap_output(withdraw_amount) as i128 - repay_amount as i128) <= sizing.profit
+            );
+        }
+
+        // a pool this shallow makes every liquidation unprofitable
+        let pool = ConstantProductPool {
+            input_reserve: 1_000,
+            output_reserve: 1_000,
+            fee_bps: 30,
+        };
+        assert_eq!(
+            optimal_liquidation_amount(
+                &reserve,
+                &obligation,
+                &obligation.borrows[0],
+                &obligation.deposits[0],
+                |amount| pool.swap_output(amount),
+            )
+            .unwrap(),
+            None
+        );
+    }
+}
@@ -7,7 +7,8 @@ use crate::{
 use pyth_sdk_solana::Price;
 // use pyth_sdk_solana;
 use solana_program::{
-    account_info::AccountInfo, msg, program_error::ProgramError, sysvar::clock::Clock,
+    account_info::AccountInfo, msg, program_error::ProgramError, pubkey::Pubkey,
+    sysvar::clock::Clock,
 };
 use std::{convert::TryInto, result::Result};
 
@@ -15,14 +16,24 @@ pub fn get_pyth_price(
     pyth_price_info: &AccountInfo,
     clock: &Clock,
 ) -> Result<(Decimal, Decimal), ProgramError> {
-    const PYTH_CONFIDENCE_RATIO: u64 = 10;
-    const STALE_AFTER_SLOTS_ELAPSED: u64 = 240; // roughly 2 min
-
     if *pyth_price_info.key == solend_program::NULL_PUBKEY {
         return Err(LendingError::NullOracleConfig.into());
     }
 
     let data = &pyth_price_info.try_borrow_data()?;
+    get_pyth_price_from_data(pyth_price_info.key, data, clock)
+}
+
+/// Same as `get_pyth_price`, but reads the price account from raw account data so it can be used
+/// off chain
+pub fn get_pyth_price_from_data(
+    pyth_price_pubkey: &Pubkey,
+    data: &[u8],
+    clock: &Clock,
+) -> Result<(Decimal, Decimal), ProgramError> {
+    const PYTH_CONFIDENCE_RATIO: u64 = 10;
+    const STALE_AFTER_SLOTS_ELAPSED: u64 = 240; // roughly 2 min
+
     let price_account = pyth_sdk_solana::state::load_price_account(data).map_err(|e| {
         msg!("Couldn't load price feed from account info: {:?}", e);
         LendingError::InvalidOracleConfig
@@ -53,7 +64,7 @@ pub fn get_pyth_price(
 
     let market_price = pyth_price_to_decimal(&pyth_price);
     let ema_price = {
-        let price_feed = price_account.to_price_feed(pyth_price_info.key);
+        let price_feed = price_account.to_price_feed(pyth_price_pubkey);
         // this can be unchecked bc the ema price is only used to _limit_ borrows and withdraws.
         // ie staleness doesn't _really_ matter for this field.
         //
@@ -0,0 +1,149 @@
+//! When a withdraw or borrow will get past the outflow rate limiters.
+//!
+//! Every withdraw and borrow counts against two sliding window rate limiters: the reserve's, in
+//! liquidity tokens, and the lending market's, in value at the upper bound price of the reserve.
+//! [earliest_outflow_slot] finds the first slot at which both of them would accept an outflow,
+//! assuming nobody else withdraws or borrows in the meantime and prices don't move. Other users'
+//! outflows only push the slot later, so it is a lower bound to retry at rather than a promise.
+
+use crate::{
+    math::{CollateralAmount, Decimal, TryAdd},
+    state::{FeeCalculation, LendingMarket, Reserve},
+};
+use solana_program::{clock::Slot, program_error::ProgramError};
+use std::cmp::max;
+
+/// Earliest slots an outflow clears each rate limiter at
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OutflowSchedule {
+    /// Earliest slot the reserve rate limiter accepts the outflow at, None if it never will
+    pub reserve_slot: Option<Slot>,
+    /// Earliest slot the lending market rate limiter accepts the outflow at, None if it never
+    /// will
+    pub market_slot: Option<Slot>,
+}
+
+impl OutflowSchedule {
+    /// Earliest slot both rate limiters accept the outflow at, None if one of them never will
+    pub fn earliest_slot(&self) -> Option<Slot> {
+        Some(max(self.reserve_slot?, self.market_slot?))
+    }
+}
+
+/// Schedule of an outflow of `liquidity_amount` from `reserve`, from `slot` on. The reserve
+/// should be refreshed, its prices are the ones the market rate limiter is charged at.
+pub fn earliest_outflow_slot(
+    lending_market: &LendingMarket,
+    reserve: &Reserve,
+    liquidity_amount: Decimal,
+    slot: Slot,
+) -> Result<OutflowSchedule, ProgramError> {
+    Ok(OutflowSchedule {
+        reserve_slot: reserve
+            .rate_limiter
+            .earliest_slot_for(slot, liquidity_amount)?,
+        market_slot: lending_market
+            .rate_limiter
+            .earliest_slot_for(slot, reserve.market_value_upper_bound(liquidity_amount)?)?,
+    })
+}
+
+/// Schedule of withdrawing `collateral_amount` of `reserve`'s cTokens, from `slot` on
+pub fn earliest_withdraw_slot(
+    lending_market: &LendingMarket,
+    reserve: &Reserve,
+    collateral_amount: CollateralAmount,
+    slot: Slot,
+) -> Result<OutflowSchedule, ProgramError> {
+    let liquidity_amount = reserve
+        .collateral_exchange_rate()?
+        .collateral_to_liquidity(collateral_amount.0)?;
+    earliest_outflow_slot(lending_market, reserve, liquidity_amount.into(), slot)
+}
+
+/// Schedule of borrowing `receive_amount` from `reserve`, from `slot` on. The rate limiters are
+/// charged for the borrow fee too, taken without any fee rebate.
+pub fn earliest_borrow_slot(
+    lending_market: &LendingMarket,
+    reserve: &Reserve,
+    receive_amount: u64,
+    slot: Slot,
+) -> Result<OutflowSchedule, ProgramError> {
+    let receive_amount = Decimal::from(receive_amount);
+    let (borrow_fee, _) =
+        reserve
+            .config
+            .fees
+            .calculate_borrow_fees(receive_amount, FeeCalculation::Exclusive, 0)?;
+    let borrow_amount = receive_amount.try_add(borrow_fee.into())?;
+    earliest_outflow_slot(lending_market, reserve, borrow_amount, slot)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::state::{
+        RateLimiter, RateLimiterConfig, ReserveConfig, ReserveFees, ReserveLiquidity,
+    };
+
+    fn rate_limiter(window_duration: u64, max_outflow: u64) -> RateLimiter {
+        RateLimiter::new(
+            RateLimiterConfig {
+                window_duration,
+                max_outflow,
+            },
+            1_000,
+        )
+    }
+
+    #[test]
+    fn earliest_slot_of_both_limiters() {
+        let mut lending_market = LendingMarket {
+            rate_limiter: rate_limiter(100, 1_000),
+            ..LendingMarket::default()
+        };
+        let mut reserve = Reserve {
+            liquidity: ReserveLiquidity {
+                mint_decimals: 0,
+                market_price: Decimal::from(2u64),
+                smoothed_market_price: Decimal::from(2u64),
+                available_amount: 10_000,
+                ..ReserveLiquidity::default()
+            },
+            config: ReserveConfig {
+                fees: ReserveFees {
+                    borrow_fee_wad: 10_000_000_000_000_000, // 1%
+                    ..ReserveFees::default()
+                },
+                ..ReserveConfig::default()
+            },
+            rate_limiter: rate_limiter(10, 400),
+            ..Reserve::default()
+        };
+
+        // fits both right away
+        let schedule =
+            earliest_outflow_slot(&lending_market, &reserve, Decimal::from(400u64), 1_000).unwrap();
+        assert_eq!(schedule.earliest_slot(), Some(1_000));
+
+        // the previous window's outflow is spread over it, and the market window is longer
+        reserve
+            .rate_limiter
+            .update(1_000, Decimal::from(400u64))
+            .unwrap();
+        lending_market
+            .rate_limiter
+            .update(1_000, Decimal::from(900u64))
+            .unwrap();
+        let schedule =
+            earliest_outflow_slot(&lending_market, &reserve, Decimal::from(100u64), 1_000).unwrap();
+        assert_eq!(schedule.reserve_slot, Some(1_012));
+        assert_eq!(schedule.market_slot, Some(1_111));
+        assert_eq!(schedule.earliest_slot(), Some(1_111));
+
+        // borrows count their fee, 404 tokens is over the reserve max outflow
+        let schedule = earliest_borrow_slot(&lending_market, &reserve, 400, 1_000).unwrap();
+        assert_eq!(schedule.reserve_slot, None);
+        assert_eq!(schedule.earliest_slot(), None);
+    }
+}
@@ -0,0 +1,375 @@
+//! Portfolio view of a wallet's positions across lending markets.
+//!
+//! [get_user_portfolio] collects the obligations of an owner out of [SolendAccounts] and values
+//! every deposit and borrow in the underlying token and in USD, grouped by lending market, for
+//! wallet integrations that want to show a user everything they have on Solend in one place.
+
+use crate::{
+    health_monitor::health_factor,
+    math::{Decimal, TryAdd, TrySub},
+    offchain_utils::{offchain_refresh_obligation, SolendAccounts},
+    positions::{ReserveBorrow, ReserveDeposit},
+    state::{Obligation, ObligationBorrowCap, Reserve},
+};
+use solana_program::pubkey::Pubkey;
+use std::{
+    cmp::min,
+    collections::{BTreeMap, HashMap},
+    error::Error,
+};
+
+/// A deposit of an obligation
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DepositPosition {
+    /// Reserve the collateral was deposited into
+    pub reserve: Pubkey,
+    /// Mint of the underlying liquidity
+    pub liquidity_mint: Pubkey,
+    /// Deposited cTokens
+    pub collateral_amount: u64,
+    /// Liquidity the deposited cTokens redeem for
+    pub liquidity_amount: Decimal,
+    /// USD value of the deposit
+    pub market_value: Decimal,
+    /// Liquidity that can be withdrawn and redeemed right now, bounded by the obligation's
+    /// allowed borrow value as of its last refresh and the reserve's available liquidity
+    pub claimable_amount: u64,
+}
+
+/// A borrow of an obligation
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BorrowPosition {
+    /// Reserve the liquidity was borrowed from
+    pub reserve: Pubkey,
+    /// Mint of the borrowed liquidity
+    pub liquidity_mint: Pubkey,
+    /// Borrowed liquidity including accrued interest
+    pub borrowed_amount: Decimal,
+    /// USD value of the borrow, without borrow weight
+    pub market_value: Decimal,
+}
+
+/// Positions of an obligation
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ObligationPortfolio {
+    /// Obligation address
+    pub obligation: Pubkey,
+    /// Deposits, in the obligation's order
+    pub deposits: Vec<DepositPosition>,
+    /// Borrows, in the obligation's order
+    pub borrows: Vec<BorrowPosition>,
+    /// USD value of the deposits
+    pub deposited_value: Decimal,
+    /// USD value of the borrows, without borrow weight
+    pub borrowed_value: Decimal,
+    /// Ratio of unhealthy borrow value to weighted borrowed value, None if nothing is borrowed
+    pub health_factor: Option<Decimal>,
+    /// Max borrowed value the owner allows the obligation, None if they never set a cap
+    pub borrow_cap: Option<u64>,
+}
+
+impl ObligationPortfolio {
+    /// USD value of the deposits net of the borrows, zero if the obligation is underwater
+    pub fn net_value(&self) -> Decimal {
+        self.deposited_value
+            .try_sub(self.borrowed_value)
+            .unwrap_or_else(|_| Decimal::zero())
+    }
+
+    /// Value that can still be borrowed before hitting the owner's borrow cap, None if uncapped.
+    /// The collateral may allow less.
+    pub fn remaining_borrow_cap(&self) -> Option<Decimal> {
+        self.borrow_cap.map(|max_borrowed_value| {
+            ObligationBorrowCap {
+                max_borrowed_value,
+                ..ObligationBorrowCap::default()
+            }
+            .remaining_borrow_value(self.borrowed_value)
+        })
+    }
+}
+
+/// Obligations an owner holds in a lending market
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MarketPortfolio {
+    /// Lending market address
+    pub lending_market: Pubkey,
+    /// Obligations, ordered by address
+    pub obligations: Vec<ObligationPortfolio>,
+}
+
+/// Positions of a wallet across lending markets
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UserPortfolio {
+    /// Wallet owning the obligations
+    pub owner: Pubkey,
+    /// Lending markets the wallet has obligations in, ordered by address
+    pub markets: Vec<MarketPortfolio>,
+    /// USD value of all deposits
+    pub deposited_value: Decimal,
+    /// USD value of all borrows, without borrow weight
+    pub borrowed_value: Decimal,
+}
+
+impl UserPortfolio {
+    /// USD value of all deposits net of all borrows, zero if the wallet is underwater overall
+    pub fn net_value(&self) -> Decimal {
+        self.deposited_value
+            .try_sub(self.borrowed_value)
+            .unwrap_or_else(|_| Decimal::zero())
+    }
+}
+
+/// Portfolio of the obligations `owner` holds in `accounts`. Obligations are refreshed against
+/// the reserves in `accounts`, so reserve interest should be refreshed up to the current slot
+/// first, e.g. with `offchain_refresh_reserve_interest`. Fails if a reserve an obligation
+/// references is missing.
+pub fn get_user_portfolio(
+    owner: &Pubkey,
+    accounts: &SolendAccounts,
+) -> Result<UserPortfolio, Box<dyn Error>> {
+    let mut markets: BTreeMap<Pubkey, Vec<ObligationPortfolio>> = BTreeMap::new();
+    let mut obligations: Vec<_> = accounts
+        .obligations
+        .iter()
+        .filter(|(_, obligation)| obligation.owner == *owner)
+        .collect();
+    obligations.sort_by_key(|(pubkey, _)| **pubkey);
+
+    let mut deposited_value = Decimal::zero();
+    let mut borrowed_value = Decimal::zero();
+    for (pubkey, obligation) in obligations {
+        let mut portfolio = obligation_portfolio(*pubkey, obligation, &accounts.reserves)?;
+        portfolio.borrow_cap = accounts
+            .obligation_borrow_caps
+            .get(pubkey)
+            .map(|borrow_cap| borrow_cap.max_borrowed_value);
+        deposited_value = deposited_value.try_add(portfolio.deposited_value)?;
+        borrowed_value = borrowed_value.try_add(portfolio.borrowed_value)?;
+        markets
+            .entry(obligation.lending_market)
+            .or_default()
+            .push(portfolio);
+    }
+
+    Ok(UserPortfolio {
+        owner: *owner,
+        markets: markets
+            .into_iter()
+            .map(|(lending_market, obligations)| MarketPortfolio {
+                lending_market,
+                obligations,
+            })
+            .collect(),
+        deposited_value,
+        borrowed_value,
+    })
+}
+
+fn obligation_portfolio(
+    pubkey: Pubkey,
+    obligation: &Obligation,
+    reserves: &HashMap<Pubkey, Reserve>,
+) -> Result<ObligationPortfolio, Box<dyn Error>> {
+    obligation.check_position_reserves(reserves)?;
+
+    let mut obligation = obligation.clone();
+    offchain_refresh_obligation(&mut obligation, reserves)?;
+
+    let mut deposits = Vec::with_capacity(obligation.deposits.len());
+    for deposit in obligation.deposit_positions(reserves) {
+        let deposit = deposit?;
+        let ReserveDeposit {
+            collateral,
+            reserve,
+        } = deposit;
+        let withdrawable_collateral = obligation.max_withdraw_amount(collateral, reserve)?;
+        deposits.push(DepositPosition {
+            reserve: collateral.deposit_reserve,
+            liquidity_mint: reserve.liquidity.mint_pubkey,
+            collateral_amount: collateral.deposited_amount,
+            liquidity_amount: deposit.liquidity_amount()?,
+            market_value: collateral.market_value,
+            claimable_amount: min(
+                reserve
+                    .collateral_exchange_rate()?
+                    .collateral_to_liquidity(withdrawable_collateral)?,
+                reserve.liquidity.available_amount,
+            ),
+        });
+    }
+
+    let mut borrows = Vec::with_capacity(obligation.borrows.len());
+    let mut borrowed_value = Decimal::zero();
+    for borrow in obligation.borrow_positions(reserves) {
+        let ReserveBorrow { liquidity, reserve } = borrow?;
+        borrowed_value = borrowed_value.try_add(liquidity.market_value)?;
+        borrows.push(BorrowPosition {
+            reserve: liquidity.borrow_reserve,
+            liquidity_mint: reserve.liquidity.mint_pubkey,
+            borrowed_amount: liquidity.borrowed_amount_wads,
+            market_value: liquidity.market_value,
+        });
+    }
+
+    Ok(ObligationPortfolio {
+        obligation: pubkey,
+        deposits,
+        borrows,
+        deposited_value: obligation.deposited_value,
+        borrowed_value,
+        health_factor: health_factor(&obligation)?,
+        borrow_cap: None,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::state::{
+        LendingMarket, ObligationCollateral, ObligationLiquidity, ReserveCollateral, ReserveConfig,
+        ReserveLiquidity,
+    };
+
+    fn reserve(lending_market: Pubkey, available_amount: u64, market_price: u64) -> Reserve {
+        Reserve {
+            lending_market,
+            liquidity: ReserveLiquidity {
+                mint_pubkey: Pubkey::new_unique(),
+                available_amount,
+                market_price: Decimal::from(market_price),
+                smoothed_market_price: Decimal::from(market_price),
+                cumulative_borrow_rate_wads: Decimal::one(),
+                ..ReserveLiquidity::default()
+            },
+            collateral: ReserveCollateral {
+                mint_total_supply: available_amount,
+                ..ReserveCollateral::default()
+            },
+            config: ReserveConfig {
+                loan_to_value_ratio: 50,
+                liquidation_threshold: 80,
+                max_liquidation_threshold: 90,
+                ..ReserveConfig::default()
+            },
+            ..Reserve::default()
+        }
+    }
+
+    #[test]
+    fn user_portfolio() {
+        let owner = Pubkey::new_unique();
+        let market = Pubkey::new_unique();
+        let usdc_reserve = (Pubkey::new_unique(), reserve(market, 1_000, 1));
+        let sol_reserve = (Pubkey::new_unique(), reserve(market, 100, 10));
+
+        let obligation = (
+            Pubkey::new_unique(),
+            Obligation {
+                owner,
+                lending_market: market,
+                deposits: vec![ObligationCollateral {
+                    deposited_amount: 400,
+                    ..ObligationCollateral::new(usdc_reserve.0)
+                }],
+                borrows: vec![ObligationLiquidity {
+                    borrowed_amount_wads: Decimal::from(10u64),
+                    ..ObligationLiquidity::new(sol_reserve.0, Decimal::one())
+                }],
+                // 400 * 0.5 allowed, 100 borrowed as of the last refresh
+                allowed_borrow_value: Decimal::from(200u64),
+                borrowed_value_upper_bound: Decimal::from(100u64),
+                ..Obligation::default()
+            },
+        );
+        let other_owner_obligation = (
+            Pubkey::new_unique(),
+            Obligation {
+                owner: Pubkey::new_unique(),
+                lending_market: market,
+                ..Obligation::default()
+            },
+        );
+
+        let mut accounts = SolendAccounts {
+            lending_markets: HashMap::from([(market, LendingMarket::default())]),
+            reserves: HashMap::from([usdc_reserve.clone(), sol_reserve.clone()]),
+            obligations: HashMap::from([obligation.clone(), other_owner_obligation]),
+            obligation_borrow_caps: HashMap::new(),
+        };
+
+        let portfolio = get_user_portfolio(&owner, &accounts).unwrap();
+        assert_eq!(
+            portfolio,
+            UserPortfolio {
+                owner,
+                markets: vec![MarketPortfolio {
+                    lending_market: market,
+                    obligations: vec![ObligationPortfolio {
+                        obligation: obligation.0,
+                        deposits: vec![DepositPosition {
+                            reserve: usdc_reserve.0,
+                            liquidity_mint: usdc_reserve.1.liquidity.mint_pubkey,
+                            collateral_amount: 400,
+                            liquidity_amount: Decimal::from(400u64),
+                            market_value: Decimal::from(400u64),
+                            // (200 - 100) / 0.5
+                            claimable_amount: 200,
+                        }],
+                        borrows: vec![BorrowPosition {
+                            reserve: sol_reserve.0,
+                            liquidity_mint: sol_reserve.1.liquidity.mint_pubkey,
+                            borrowed_amount: Decimal::from(10u64),
+                            market_value: Decimal::from(100u64),
+                        }],
+                        deposited_value: Decimal::from(400u64),
+                        borrowed_value: Decimal::from(100u64),
+                        // 400 * 0.8 / 100
+                        health_factor: Some(Decimal::from_bps(32_000)),
+                        borrow_cap: None,
+                    }],
+                }],
+                deposited_value: Decimal::from(400u64),
+                borrowed_value: Decimal::from(100u64),
+            }
+        );
+        assert_eq!(portfolio.net_value(), Decimal::from(300u64));
+        assert_eq!(
+            portfolio.markets[0].obligations[0].remaining_borrow_cap(),
+            None
+        );
+
+        accounts.obligation_borrow_caps.insert(
+            obligation.0,
+            ObligationBorrowCap::new(obligation.0, 255, 150),
+        );
+        let portfolio = get_user_portfolio(&owner, &accounts).unwrap();
+        assert_eq!(portfolio.markets[0].obligations[0].borrow_cap, Some(150));
+        assert_eq!(
+            portfolio.markets[0].obligations[0].remaining_borrow_cap(),
+            Some(Decimal::from(50u64))
+        );
+
+        // claimable liquidity is capped by what the reserve has available
+        let usdc_liquidity = &mut accounts
+            .reserves
+            .get_mut(&usdc_reserve.0)
+            .unwrap()
+            .liquidity;
+        usdc_liquidity.available_amount = 50;
+        usdc_liquidity.borrowed_amount_wads = Decimal::from(950u64);
+        let portfolio = get_user_portfolio(&owner, &accounts).unwrap();
+        assert_eq!(
+            portfolio.markets[0].obligations[0].deposits[0].claimable_amount,
+            50
+        );
+
+        assert!(get_user_portfolio(&Pubkey::new_unique(), &accounts)
+            .unwrap()
+            .markets
+            .is_empty());
+
+        accounts.reserves.remove(&sol_reserve.0);
+        assert!(get_user_portfolio(&owner, &accounts).is_err());
+    }
+}
@@ -0,0 +1,220 @@
+//! Obligation positions joined with their reserves.
+//!
+//! Valuing a deposit or a borrow needs the reserve it's in, so off-chain code keeps looking up
+//! `deposit_reserve` and `borrow_reserve` in a map of fetched reserves. [Obligation::positions],
+//! [Obligation::deposit_positions] and [Obligation::borrow_positions] do the lookup once and yield
+//! each position along with its reserve, or a [MissingReserve] error naming the reserve that
+//! wasn't fetched.
+
+use crate::{
+    error::LendingError,
+    math::{Decimal, TryMul},
+    state::{Obligation, ObligationCollateral, ObligationLiquidity, Reserve},
+};
+use solana_program::{program_error::ProgramError, pubkey::Pubkey};
+use std::{collections::HashMap, error::Error, fmt};
+
+/// A reserve an obligation has a position in isn't among the given reserves
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MissingReserve {
+    /// Address of the reserve
+    pub reserve: Pubkey,
+}
+
+impl fmt::Display for MissingReserve {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "reserve {} not found", self.reserve)
+    }
+}
+
+impl Error for MissingReserve {}
+
+impl From<MissingReserve> for ProgramError {
+    fn from(_: MissingReserve) -> Self {
+        LendingError::InvalidAccountInput.into()
+    }
+}
+
+/// A deposit of an obligation and the reserve it's in
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ReserveDeposit<'a> {
+    /// Deposit of the obligation
+    pub collateral: &'a ObligationCollateral,
+    /// Reserve the collateral was deposited into
+    pub reserve: &'a Reserve,
+}
+
+impl ReserveDeposit<'_> {
+    /// Address of the reserve
+    pub fn reserve_pubkey(&self) -> Pubkey {
+        self.collateral.deposit_reserve
+    }
+
+    /// Liquidity the deposited collateral redeems for at the reserve's exchange rate
+    pub fn liquidity_amount(&self) -> Result<Decimal, ProgramError> {
+        self.reserve
+            .collateral_exchange_rate()?
+            .decimal_collateral_to_liquidity(self.collateral.deposited_amount.into())
+    }
+}
+
+/// A borrow of an obligation and the reserve it's from
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ReserveBorrow<'a> {
+    /// Borrow of the obligation
+    pub liquidity: &'a ObligationLiquidity,
+    /// Reserve the liquidity was borrowed from
+    pub reserve: &'a Reserve,
+}
+
+impl ReserveBorrow<'_> {
+    /// Address of the reserve
+    pub fn reserve_pubkey(&self) -> Pubkey {
+        self.liquidity.borrow_reserve
+    }
+
+    /// Market value of the borrow weighted by the reserve's borrow weight, as it counts towards
+    /// the obligation's borrowed value
+    pub fn weighted_market_value(&self) -> Result<Decimal, ProgramError> {
+        self.liquidity
+            .market_value
+            .try_mul(self.reserve.borrow_weight())
+    }
+}
+
+/// A deposit or borrow of an obligation and its reserve
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Position<'a> {
+    /// A deposit
+    Deposit(ReserveDeposit<'a>),
+    /// A borrow
+    Borrow(ReserveBorrow<'a>),
+}
+
+impl<'a> Position<'a> {
+    /// Address of the reserve
+    pub fn reserve_pubkey(&self) -> Pubkey {
+        match self {
+            Position::Deposit(deposit) => deposit.reserve_pubkey(),
+            Position::Borrow(borrow) => borrow.reserve_pubkey(),
+        }
+    }
+
+    /// Reserve of the position
+    pub fn reserve(&self) -> &'a Reserve {
+        match self {
+            Position::Deposit(deposit) => deposit.reserve,
+            Position::Borrow(borrow) => borrow.reserve,
+        }
+    }
+}
+
+fn find_reserve<'a>(
+    reserves: &'a HashMap<Pubkey, Reserve>,
+    reserve: &Pubkey,
+) -> Result<&'a Reserve, MissingReserve> {
+    reserves
+        .get(reserve)
+        .ok_or(MissingReserve { reserve: *reserve })
+}
+
+impl Obligation {
+    /// Deposits of the obligation, each with its reserve in `reserves`
+    pub fn deposit_positions<'a>(
+        &'a self,
+        reserves: &'a HashMap<Pubkey, Reserve>,
+    ) -> impl Iterator<Item = Result<ReserveDeposit<'a>, MissingReserve>> + 'a {
+        self.deposits.iter().map(move |collateral| {
+            Ok(ReserveDeposit {
+                collateral,
+                reserve: find_reserve(reserves, &collateral.deposit_reserve)?,
+            })
+        })
+    }
+
+    /// Borrows of the obligation, each with its reserve in `reserves`
+    pub fn borrow_positions<'a>(
+        &'a self,
+        reserves: &'a HashMap<Pubkey, Reserve>,
+    ) -> impl Iterator<Item = Result<ReserveBorrow<'a>, MissingReserve>> + 'a {
+        self.borrows.iter().map(move |liquidity| {
+            Ok(ReserveBorrow {
+                liquidity,
+                reserve: find_reserve(reserves, &liquidity.borrow_reserve)?,
+            })
+        })
+    }
+
+    /// Deposits then borrows of the obligation, each with its reserve in `reserves`
+    pub fn positions<'a>(
+        &'a self,
+        reserves: &'a HashMap<Pubkey, Reserve>,
+    ) -> impl Iterator<Item = Result<Position<'a>, MissingReserve>> + 'a {
+        self.deposit_positions(reserves)
+            .map(|deposit| deposit.map(Position::Deposit))
+            .chain(
+                self.borrow_positions(reserves)
+                    .map(|borrow| borrow.map(Position::Borrow)),
+            )
+    }
+
+    /// Check every reserve the obligation has a position in is in `reserves`
+    pub fn check_position_reserves(
+        &self,
+        reserves: &HashMap<Pubkey, Reserve>,
+    ) -> Result<(), MissingReserve> {
+        self.positions(reserves)
+            .try_for_each(|position| position.map(|_| ()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn positions_with_reserves() {
+        let deposit_reserve = Pubkey::new_unique();
+        let borrow_reserve = Pubkey::new_unique();
+        let missing_reserve = Pubkey::new_unique();
+        let reserves = HashMap::from([
+            (deposit_reserve, Reserve::default()),
+            (borrow_reserve, Reserve::default()),
+        ]);
+
+        let mut obligation = Obligation {
+            deposits: vec![ObligationCollateral::new(deposit_reserve)],
+            borrows: vec![ObligationLiquidity::new(borrow_reserve, Decimal::one())],
+            ..Obligation::default()
+        };
+        let positions = obligation
+            .positions(&reserves)
+            .map(|position| position.map(|position| position.reserve_pubkey()))
+            .collect::<Result<Vec<_>, _>>();
+        assert_eq!(positions, Ok(vec![deposit_reserve, borrow_reserve]));
+        assert_eq!(obligation.check_position_reserves(&reserves), Ok(()));
+
+        obligation
+            .borrows
+            .push(ObligationLiquidity::new(missing_reserve, Decimal::one()));
+        let borrows = obligation.borrow_positions(&reserves).collect::<Vec<_>>();
+        assert_eq!(borrows.len(), 2);
+        assert!(borrows[0].is_ok());
+        assert_eq!(
+            borrows[1],
+            Err(MissingReserve {
+                reserve: missing_reserve
+            })
+        );
+        assert_eq!(
+            obligation.check_position_reserves(&reserves),
+            Err(MissingReserve {
+                reserve: missing_reserve
+            })
+        );
+        assert_eq!(
+            ProgramError::from(obligation.check_position_reserves(&reserves).unwrap_err()),
+            LendingError::InvalidAccountInput.into()
+        );
+    }
+}
@@ -0,0 +1,262 @@
+//! Rebasing view of cToken balances.
+//!
+//! cToken balances stay constant while the liquidity they redeem for grows with interest. Some
+//! integrators, e.g. for payroll or accounting exports, would rather see aToken-like balances
+//! denominated in the underlying token that grow on their own. A [RebasingView] snapshots the
+//! collateral exchange rate of every reserve, with interest accrued up to a slot, and converts
+//! cToken accounts into [RebasingBalance]s in bulk.
+
+use crate::{
+    math::{CollateralAmount, LiquidityAmount},
+    offchain_utils::MAX_MULTIPLE_ACCOUNTS,
+    state::{CollateralExchangeRate, Reserve},
+};
+use solana_client::rpc_client::RpcClient;
+use solana_program::{
+    clock::Slot, program_error::ProgramError, program_pack::Pack, pubkey::Pubkey,
+};
+use spl_token::state::Account as TokenAccount;
+use std::{collections::HashMap, error::Error};
+
+/// A cToken account balance viewed in terms of the underlying liquidity
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RebasingBalance {
+    /// cToken account
+    pub token_account: Pubkey,
+    /// Owner of the cToken account
+    pub owner: Pubkey,
+    /// Reserve that minted the cTokens
+    pub reserve: Pubkey,
+    /// Mint of the underlying liquidity
+    pub liquidity_mint: Pubkey,
+    /// cToken balance
+    pub collateral_amount: CollateralAmount,
+    /// Liquidity the cToken balance redeems for, rounded down
+    pub liquidity_amount: LiquidityAmount,
+}
+
+impl RebasingBalance {
+    /// Header matching `to_csv_row`
+    pub const CSV_HEADER: &'static str =
+        "token_account,owner,reserve,liquidity_mint,collateral_amount,liquidity_amount";
+
+    /// Format the balance as a csv row, for accounting exports
+    pub fn to_csv_row(&self) -> String {
+        format!(
+            "{},{},{},{},{},{}",
+            self.token_account,
+            self.owner,
+            self.reserve,
+            self.liquidity_mint,
+            self.collateral_amount,
+            self.liquidity_amount
+        )
+    }
+}
+
+struct CTokenRate {
+    reserve: Pubkey,
+    liquidity_mint: Pubkey,
+    exchange_rate: CollateralExchangeRate,
+}
+
+/// Collateral exchange rates of a set of reserves as of a slot, keyed by cToken mint
+pub struct RebasingView {
+    slot: Slot,
+    rates: HashMap<Pubkey, CTokenRate>,
+}
+
+impl RebasingView {
+    /// Snapshot the exchange rates of `reserves` with interest accrued up to `slot`. Fails if
+    /// `slot` is before the last update of any of the reserves.
+    pub fn new(reserves: &HashMap<Pubkey, Reserve>, slot: Slot) -> Result<Self, ProgramError> {
+        let mut rates = HashMap::with_capacity(reserves.len());
+        for (pubkey, reserve) in reserves {
+            let mut reserve = reserve.clone();
+            reserve.accrue_interest(slot)?;
+            rates.insert(
+                reserve.collateral.mint_pubkey,
+                CTokenRate {
+                    reserve: *pubkey,
+                    liquidity_mint: reserve.liquidity.mint_pubkey,
+                    exchange_rate: reserve.collateral_exchange_rate()?,
+                },
+            );
+        }
+        Ok(Self { slot, rates })
+    }
+
+    /// Slot the exchange rates were accrued to
+    pub fn slot(&self) -> Slot {
+        self.slot
+    }
+
+    /// Liquidity an amount of cTokens redeems for, or None if `collateral_mint` isn't the cToken
+    /// mint of a known reserve
+    pub fn underlying_amount(
+        &self,
+        collateral_mint: &Pubkey,
+        collateral_amount: CollateralAmount,
+    ) -> Result<Option<LiquidityAmount>, ProgramError> {
+        self.rates
+            .get(collateral_mint)
+            .map(|rate| {
+                rate.exchange_rate
+                    .collateral_to_liquidity(collateral_amount.amount())
+                    .map(LiquidityAmount)
+            })
+            .transpose()
+    }
+
+    /// Rebasing balance of a token account, or None if it doesn't hold cTokens of a known reserve
+    pub fn balance(
+        &self,
+        token_account: &Pubkey,
+        account: &TokenAccount,
+    ) -> Result<Option<RebasingBalance>, ProgramError> {
+        let rate = match self.rates.get(&account.mint) {
+            Some(rate) => rate,
+            None => return Ok(None),
+        };
+        Ok(Some(RebasingBalance {
+            token_account: *token_account,
+            owner: account.owner,
+            reserve: rate.reserve,
+            liquidity_mint: rate.liquidity_mint,
+            collateral_amount: CollateralAmount(account.amount),
+            liquidity_amount: LiquidityAmount(
+                rate.exchange_rate.collateral_to_liquidity(account.amount)?,
+            ),
+        }))
+    }
+
+    /// Rebasing balances of the token accounts holding cTokens of a known reserve, in order.
+    /// Other token accounts are skipped.
+    pub fn balances<'a>(
+        &self,
+        token_accounts: impl IntoIterator<Item = (&'a Pubkey, &'a TokenAccount)>,
+    ) -> Result<Vec<RebasingBalance>, ProgramError> {
+        let mut balances = Vec::new();
+        for (pubkey, account) in token_accounts {
+            if let Some(balance) = self.balance(pubkey, account)? {
+                balances.push(balance);
+            }
+        }
+        Ok(balances)
+    }
+}
+
+/// Fetch `token_accounts` and return the rebasing balances of those holding cTokens of
+/// `reserves`, as of the current slot. Accounts are fetched in batches of MAX_MULTIPLE_ACCOUNTS;
+/// missing accounts and accounts that aren't token accounts are skipped.
+pub fn get_rebasing_balances(
+    client: &RpcClient,
+    reserves: &HashMap<Pubkey, Reserve>,
+    token_accounts: &[Pubkey],
+) -> Result<Vec<RebasingBalance>, Box<dyn Error>> {
+    let view = RebasingView::new(reserves, client.get_slot()?)?;
+
+    let mut balances = Vec::new();
+    for chunk in token_accounts.chunks(MAX_MULTIPLE_ACCOUNTS) {
+        let accounts = client.get_multiple_accounts(chunk)?;
+        for (pubkey, account) in chunk.iter().zip(accounts) {
+            let token_account = match account.and_then(|a| TokenAccount::unpack(&a.data).ok()) {
+                Some(token_account) => token_account,
+                None => continue,
+            };
+            if let Some(balance) = view.balance(pubkey, &token_account)? {
+                balances.push(balance);
+            }
+        }
+    }
+
+    Ok(balances)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        error::LendingError,
+        state::{LastUpdate, ReserveCollateral, ReserveLiquidity},
+    };
+
+    #[test]
+    fn rebasing_balances() {
+        let reserve_pubkey = Pubkey::new_unique();
+        let reserve = Reserve {
+            last_update: LastUpdate::new(20),
+            liquidity: ReserveLiquidity {
+                mint_pubkey: Pubkey::new_unique(),
+                available_amount: 150,
+                ..ReserveLiquidity::default()
+            },
+            // 1 cToken = 1.5 tokens
+            collateral: ReserveCollateral {
+                mint_pubkey: Pubkey::new_unique(),
+                mint_total_supply: 100,
+                ..ReserveCollateral::default()
+            },
+            ..Reserve::default()
+        };
+        let reserves = HashMap::from([(reserve_pubkey, reserve.clone())]);
+
+        let view = RebasingView::new(&reserves, 20).unwrap();
+        assert_eq!(
+            RebasingView::new(&reserves, 19).err(),
+            Some(LendingError::MathOverflow.into())
+        );
+
+        let ctoken_account = (
+            Pubkey::new_unique(),
+            TokenAccount {
+                mint: reserve.collateral.mint_pubkey,
+                owner: Pubkey::new_unique(),
+                amount: 11,
+                ..TokenAccount::default()
+            },
+        );
+        let other_account = (
+            Pubkey::new_unique(),
+            TokenAccount {
+                mint: reserve.liquidity.mint_pubkey,
+                amount: 11,
+                ..TokenAccount::default()
+            },
+        );
+
+        let balances = view
+            .balances([
+                (&ctoken_account.0, &ctoken_account.1),
+                (&other_account.0, &other_account.1),
+            ])
+            .unwrap();
+        assert_eq!(
+            balances,
+            vec![RebasingBalance {
+                token_account: ctoken_account.0,
+                owner: ctoken_account.1.owner,
+                reserve: reserve_pubkey,
+                liquidity_mint: reserve.liquidity.mint_pubkey,
+                collateral_amount: CollateralAmount(11),
+                liquidity_amount: LiquidityAmount(16),
+            }]
+        );
+        assert_eq!(
+            balances[0].to_csv_row().split(',').count(),
+            RebasingBalance::CSV_HEADER.split(',').count()
+        );
+
+        assert_eq!(
+            view.underlying_amount(&reserve.collateral.mint_pubkey, CollateralAmount(2))
+                .unwrap(),
+            Some(LiquidityAmount(3))
+        );
+        assert_eq!(
+            view.underlying_amount(&reserve.liquidity.mint_pubkey, CollateralAmount(2))
+                .unwrap(),
+            None
+        );
+        assert_eq!(view.slot(), 20);
+    }
+}
@@ -0,0 +1,474 @@
+//! Reconciliation of reserve accounting against SPL token balances.
+//!
+//! A reserve records how much liquidity its supply account holds and how many cTokens are in
+//! circulation. Bugs show up as drift between those numbers and the token accounts themselves.
+//! [reconcile_reserves] fetches every reserve together with its token accounts and reports each
+//! [ReconciliationIssue] found, for a scheduled integrity monitor to alert on:
+//!
+//! - the liquidity supply balance against `liquidity.available_amount`
+//! - the cToken mint supply against `collateral.mint_total_supply`
+//! - the cToken supply balance against the collateral obligations deposited, when given
+//! - the fee receivers, which have to be token accounts of the liquidity mint
+//!
+//! Each reserve is fetched in the same request as its token accounts, so they are read at the
+//! same slot. Obligation deposits come from a separate, usually older, fetch; a collateral
+//! supply mismatch is worth a second run before alerting.
+
+use crate::{offchain_utils::MAX_MULTIPLE_ACCOUNTS, state::Obligation, state::Reserve};
+use solana_client::rpc_client::RpcClient;
+use solana_program::{clock::Slot, program_pack::Pack, pubkey::Pubkey};
+use solana_sdk::account::Account;
+use spl_token::state::{Account as TokenAccount, Mint};
+use std::{collections::HashMap, error::Error, fmt};
+
+/// Accounts fetched per reserve: the reserve, its liquidity supply, collateral mint, collateral
+/// supply, fee receiver and liquidation fee receiver
+const ACCOUNTS_PER_RESERVE: usize = 6;
+
+/// Account of a reserve checked by the reconciliation
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ReserveTokenAccount {
+    /// Liquidity supply token account, holds `liquidity.available_amount`
+    LiquiditySupply,
+    /// cToken mint, with a supply of `collateral.mint_total_supply`
+    CollateralMint,
+    /// cToken supply token account, holds the collateral deposited by obligations
+    CollateralSupply,
+    /// Token account of the liquidity mint receiving protocol fees
+    FeeReceiver,
+    /// Token account of the liquidity mint receiving the protocol liquidation fee
+    LiquidationFeeReceiver,
+}
+
+impl fmt::Display for ReserveTokenAccount {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            ReserveTokenAccount::LiquiditySupply => "liquidity_supply",
+            ReserveTokenAccount::CollateralMint => "collateral_mint",
+            ReserveTokenAccount::CollateralSupply => "collateral_supply",
+            ReserveTokenAccount::FeeReceiver => "fee_receiver",
+            ReserveTokenAccount::LiquidationFeeReceiver => "liquidation_fee_receiver",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// What is wrong with a reserve token account
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Discrepancy {
+    /// The balance, or mint supply, differs from what the reserve records
+    Balance {
+        /// Amount the reserve accounting says there is
+        recorded: u64,
+        /// Amount there actually is
+        actual: u64,
+    },
+    /// The account doesn't exist, or isn't a token account or mint of the expected mint
+    InvalidAccount,
+}
+
+/// A reserve token account that doesn't reconcile
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ReconciliationIssue {
+    /// Reserve the account belongs to
+    pub reserve: Pubkey,
+    /// Token account or mint
+    pub account: Pubkey,
+    /// Role of the account in the reserve
+    pub kind: ReserveTokenAccount,
+    /// What doesn't reconcile
+    pub discrepancy: Discrepancy,
+}
+
+impl ReconciliationIssue {
+    /// Header matching `to_csv_row`
+    pub const CSV_HEADER: &'static str = "reserve,account,kind,recorded,actual,drift";
+
+    /// Actual minus recorded amount, None for invalid accounts
+    pub fn drift(&self) -> Option<i128> {
+        match self.discrepancy {
+            Discrepancy::Balance { recorded, actual } => Some(actual as i128 - recorded as i128),
+            Discrepancy::InvalidAccount => None,
+        }
+    }
+
+    /// Format the issue as a csv row, with empty amounts for invalid accounts
+    pub fn to_csv_row(&self) -> String {
+        let (recorded, actual) = match self.discrepancy {
+            Discrepancy::Balance { recorded, actual } => (recorded.to_string(), actual.to_string()),
+            Discrepancy::InvalidAccount => (String::new(), String::new()),
+        };
+        format!(
+            "{},{},{},{},{},{}",
+            self.reserve,
+            self.account,
+            self.kind,
+            recorded,
+            actual,
+            self.drift()
+                .map(|drift| drift.to_string())
+                .unwrap_or_default()
+        )
+    }
+}
+
+/// Result of a reconciliation run
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Reconciliation {
+    /// Slot of the earliest fetch
+    pub slot: Slot,
+    /// Number of reserves checked
+    pub reserves: usize,
+    /// Accounts that don't reconcile, in reserve order
+    pub issues: Vec<ReconciliationIssue>,
+}
+
+/// Collateral deposited by `obligations`, per reserve. Saturates rather than overflowing, which
+/// can only happen with corrupted obligations and shows up as a mismatch anyway.
+pub fn deposited_collateral<'a>(
+    obligations: impl IntoIterator<Item = &'a Obligation>,
+) -> HashMap<Pubkey, u64> {
+    let mut deposited = HashMap::new();
+    for collateral in obligations
+        .into_iter()
+        .flat_map(|obligation| &obligation.deposits)
+    {
+        let amount = deposited.entry(collateral.deposit_reserve).or_insert(0u64);
+        *amount = amount.saturating_add(collateral.deposited_amount);
+    }
+    deposited
+}
+
+fn token_account(account: Option<&Account>, mint: &Pubkey) -> Option<TokenAccount> {
+    account
+        .filter(|account| account.owner == spl_token::id())
+        .and_then(|account| TokenAccount::unpack(&account.data).ok())
+        .filter(|token_account| token_account.mint == *mint)
+}
+
+/// Reconcile a reserve against its token `accounts`, fetched at the same slot. The collateral
+/// supply is only checked if `deposited_collateral` is known.
+pub fn reconcile_reserve(
+    reserve_pubkey: &Pubkey,
+    reserve: &Reserve,
+    accounts: &HashMap<Pubkey, Account>,
+    deposited_collateral: Option<u64>,
+) -> Vec<ReconciliationIssue> {
+    let mut issues = Vec::new();
+    let mut check = |account: Pubkey, kind, discrepancy: Option<Discrepancy>| {
+        if let Some(discrepancy) = discrepancy {
+            issues.push(ReconciliationIssue {
+                reserve: *reserve_pubkey,
+                account,
+                kind,
+                discrepancy,
+            });
+        }
+    };
+    let balance = |recorded: u64, actual: Option<u64>| match actual {
+        Some(actual) if actual == recorded => None,
+        Some(actual) => Some(Discrepancy::Balance { recorded, actual }),
+        None => Some(Discrepancy::InvalidAccount),
+    };
+
+    let liquidity_mint = &reserve.liquidity.mint_pubkey;
+    let collateral_mint = &reserve.collateral.mint_pubkey;
+
+    let supply = reserve.liquidity.supply_pubkey;
+    check(
+        supply,
+        ReserveTokenAccount::LiquiditySupply,
+        balance(
+            reserve.liquidity.available_amount,
+            token_account(accounts.get(&supply), liquidity_mint).map(|account| account.amount),
+        ),
+    );
+
+    let mint_supply = accounts
+        .get(collateral_mint)
+        .filter(|account| account.owner == spl_token::id())
+        .and_then(|account| Mint::unpack(&account.data).ok())
+        .map(|mint| mint.supply);
+    check(
+        *collateral_mint,
+        ReserveTokenAccount::CollateralMint,
+        balance(reserve.collateral.mint_total_supply, mint_supply),
+    );
+
+    let collateral_supply = reserve.collateral.supply_pubkey;
+    let collateral_supply_amount = token_account(accounts.get(&collateral_supply), collateral_mint)
+        .map(|account| account.amount);
+    let discrepancy = match deposited_collateral {
+        Some(deposited) => balance(deposited, collateral_supply_amount),
+        None => collateral_supply_amount
+            .is_none()
+            .then_some(Discrepancy::InvalidAccount),
+    };
+    check(
+        collateral_supply,
+        ReserveTokenAccount::CollateralSupply,
+        discrepancy,
+    );
+
+    let fee_receivers = [
+        (
+            reserve.config.fee_receiver,
+            ReserveTokenAccount::FeeReceiver,
+        ),
+        (
+            reserve.config.liquidation_fee_receiver(),
+            ReserveTokenAccount::LiquidationFeeReceiver,
+        ),
+    ];
+    for (index, (fee_receiver, kind)) in fee_receivers.iter().enumerate() {
+        if fee_receivers[..index]
+            .iter()
+            .any(|(other, _)| other == fee_receiver)
+        {
+            continue;
+        }
+        let valid = token_account(accounts.get(fee_receiver), liquidity_mint).is_some();
+        check(
+            *fee_receiver,
+            *kind,
+            (!valid).then_some(Discrepancy::InvalidAccount),
+        );
+    }
+
+    issues
+}
+
+/// Fetch `reserve_pubkeys` with their token accounts and reconcile them. `deposited_collateral`,
+/// e.g. from [deposited_collateral] over every obligation of the lending program, enables the
+/// collateral supply check. Reserves that no longer exist or don't unpack are skipped.
+pub fn reconcile_reserves(
+    client: &RpcClient,
+    reserve_pubkeys: &[Pubkey],
+    deposited_collateral: Option<&HashMap<Pubkey, u64>>,
+) -> Result<Reconciliation, Box<dyn Error>> {
+    let mut slot = None;
+    let mut reserves = 0;
+    let mut issues = Vec::new();
+    for chunk in reserve_pubkeys.chunks(MAX_MULTIPLE_ACCOUNTS / ACCOUNTS_PER_RESERVE) {
+        // the token account addresses are only known once the reserves are in, then the reserves
+        // are fetched again along with them so both are read at the same slot
+        let mut pubkeys = Vec::with_capacity(chunk.len() * ACCOUNTS_PER_RESERVE);
+        for (reserve_pubkey, account) in chunk.iter().zip(client.get_multiple_accounts(chunk)?) {
+            let reserve = match account.and_then(|account| Reserve::unpack(&account.data).ok()) {
+                Some(reserve) => reserve,
+                None => continue,
+            };
+            pubkeys.extend([
+                *reserve_pubkey,
+                reserve.liquidity.supply_pubkey,
+                reserve.collateral.mint_pubkey,
+                reserve.collateral.supply_pubkey,
+                reserve.config.fee_receiver,
+                reserve.config.liquidation_fee_receiver(),
+            ]);
+        }
+        if pubkeys.is_empty() {
+            continue;
+        }
+
+        let response =
+            client.get_multiple_accounts_with_commitment(&pubkeys, client.commitment())?;
+        slot = Some(
+            slot.unwrap_or(response.context.slot)
+                .min(response.context.slot),
+        );
+        let accounts = pubkeys
+            .iter()
+            .zip(response.value)
+            .filter_map(|(pubkey, account)| account.map(|account| (*pubkey, account)))
+            .collect::<HashMap<_, _>>();
+        for reserve_pubkey in pubkeys.iter().step_by(ACCOUNTS_PER_RESERVE) {
+            let reserve = match accounts
+                .get(reserve_pubkey)
+                .and_then(|account| Reserve::unpack(&account.data).ok())
+            {
+                Some(reserve) => reserve,
+                None => continue,
+            };
+            reserves += 1;
+            issues.extend(reconcile_reserve(
+                reserve_pubkey,
+                &reserve,
+                &accounts,
+                deposited_collateral
+                    .map(|deposited| deposited.get(reserve_pubkey).copied().unwrap_or(0)),
+            ));
+        }
+    }
+
+    Ok(Reconciliation {
+        slot: match slot {
+            Some(slot) => slot,
+            None => client.get_slot()?,
+        },
+        reserves,
+        issues,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::state::{ObligationCollateral, ReserveCollateral, ReserveConfig, ReserveLiquidity};
+    use spl_token::state::AccountState;
+
+    fn token_account(mint: Pubkey, amount: u64) -> Account {
+        let mut data = vec![0; TokenAccount::LEN];
+        TokenAccount::pack(
+            TokenAccount {
+                mint,
+                amount,
+                state: AccountState::Initialized,
+                ..TokenAccount::default()
+            },
+            &mut data,
+        )
+        .unwrap();
+        Account {
+            data,
+            owner: spl_token::id(),
+            ..Account::default()
+        }
+    }
+
+    fn mint(supply: u64) -> Account {
+        let mut data = vec![0; Mint::LEN];
+        Mint::pack(
+            Mint {
+                supply,
+                is_initialized: true,
+                ..Mint::default()
+            },
+            &mut data,
+        )
+        .unwrap();
+        Account {
+            data,
+            owner: spl_token::id(),
+            ..Account::default()
+        }
+    }
+
+    #[test]
+    fn reserve_reconciliation() {
+        let reserve_pubkey = Pubkey::new_unique();
+        let reserve = Reserve {
+            liquidity: ReserveLiquidity {
+                mint_pubkey: Pubkey::new_unique(),
+                supply_pubkey: Pubkey::new_unique(),
+                available_amount: 1_000,
+                ..ReserveLiquidity::default()
+            },
+            collateral: ReserveCollateral {
+                mint_pubkey: Pubkey::new_unique(),
+                mint_total_supply: 900,
+                supply_pubkey: Pubkey::new_unique(),
+            },
+            config: ReserveConfig {
+                fee_receiver: Pubkey::new_unique(),
+                ..ReserveConfig::default()
+            },
+            ..Reserve::default()
+        };
+        let liquidity_mint = reserve.liquidity.mint_pubkey;
+        let collateral_mint = reserve.collateral.mint_pubkey;
+        let mut accounts = HashMap::from([
+            (
+                reserve.liquidity.supply_pubkey,
+                token_account(liquidity_mint, 1_000),
+            ),
+            (collateral_mint, mint(900)),
+            (
+                reserve.collateral.supply_pubkey,
+                token_account(collateral_mint, 600),
+            ),
+            (
+                reserve.config.fee_receiver,
+                token_account(liquidity_mint, 5),
+            ),
+        ]);
+
+        let obligations = [
+            Obligation {
+                deposits: vec![ObligationCollateral {
+                    deposited_amount: 400,
+                    ..ObligationCollateral::new(reserve_pubkey)
+                }],
+                ..Obligation::default()
+            },
+            Obligation {
+                deposits: vec![ObligationCollateral {
+                    deposited_amount: 200,
+                    ..ObligationCollateral::new(reserve_pubkey)
+                }],
+                ..Obligation::default()
+            },
+        ];
+        let deposited = deposited_collateral(&obligations);
+        assert_eq!(deposited[&reserve_pubkey], 600);
+
+        assert_eq!(
+            reconcile_reserve(&reserve_pubkey, &reserve, &accounts, Some(600)),
+            vec![]
+        );
+
+        // 3 tokens left the supply without going through the reserve, and the fee receiver was
+        // closed
+        accounts.insert(
+            reserve.liquidity.supply_pubkey,
+            token_account(liquidity_mint, 997),
+        );
+        accounts.remove(&reserve.config.fee_receiver);
+        let issues = reconcile_reserve(&reserve_pubkey, &reserve, &accounts, Some(601));
+        assert_eq!(
+            issues,
+            vec![
+                ReconciliationIssue {
+                    reserve: reserve_pubkey,
+                    account: reserve.liquidity.supply_pubkey,
+                    kind: ReserveTokenAccount::LiquiditySupply,
+                    discrepancy: Discrepancy::Balance {
+                        recorded: 1_000,
+                        actual: 997
+                    },
+                },
+                ReconciliationIssue {
+                    reserve: reserve_pubkey,
+                    account: reserve.collateral.supply_pubkey,
+                    kind: ReserveTokenAccount::CollateralSupply,
+                    discrepancy: Discrepancy::Balance {
+                        recorded: 601,
+                        actual: 600
+                    },
+                },
+                ReconciliationIssue {
+                    reserve: reserve_pubkey,
+                    account: reserve.config.fee_receiver,
+                    kind: ReserveTokenAccount::FeeReceiver,
+                    discrepancy: Discrepancy::InvalidAccount,
+                },
+            ]
+        );
+        assert_eq!(issues[0].drift(), Some(-3));
+        assert_eq!(issues[2].drift(), None);
+        assert_eq!(
+            issues[0].to_csv_row().split(',').count(),
+            ReconciliationIssue::CSV_HEADER.split(',').count()
+        );
+
+        // a token account of another mint doesn't count
+        accounts.insert(
+            reserve.liquidity.supply_pubkey,
+            token_account(collateral_mint, 1_000),
+        );
+        assert_eq!(
+            reconcile_reserve(&reserve_pubkey, &reserve, &accounts, None)[0].discrepancy,
+            Discrepancy::InvalidAccount
+        );
+    }
+}
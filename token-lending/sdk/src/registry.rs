@@ -0,0 +1,380 @@
+//! Registry of production lending markets, their reserves, mints and oracles.
+//!
+//! The registry shipped with the crate only pins addresses that don't change once deployed.
+//! [refresh_market] rebuilds a market's entry from chain, naming it from its
+//! [LendingMarketMetadata] and listing every reserve in it, so reserves added after this crate
+//! was published resolve too.
+//!
+//! ```ignore
+//! let main_pool = find_market(Cluster::Mainnet, "main").unwrap();
+//! let sol = main_pool.reserve("SOL").unwrap();
+//! ```
+
+use crate::{
+    cluster::{Cluster, MAINNET_MAIN_POOL},
+    state::{LendingMarketMetadata, Reserve},
+    NULL_PUBKEY,
+};
+use bytemuck::checked::try_from_bytes;
+use solana_client::{
+    rpc_client::RpcClient,
+    rpc_config::RpcProgramAccountsConfig,
+    rpc_filter::{Memcmp, RpcFilterType},
+};
+use solana_program::{
+    program_pack::Pack,
+    pubkey,
+    pubkey::{Pubkey, PUBKEY_BYTES},
+};
+use std::error::Error;
+
+/// Offset of the lending market in a packed reserve, after the version and last update
+const RESERVE_LENDING_MARKET_OFFSET: usize = 10;
+
+/// A token mint with its symbol
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KnownMint {
+    /// Token symbol
+    pub symbol: &'static str,
+    /// Mint address
+    pub mint: Pubkey,
+    /// Mint decimals
+    pub decimals: u8,
+}
+
+/// Mints listed in production markets
+pub const KNOWN_MINTS: &[KnownMint] = &[
+    KnownMint {
+        symbol: "SOL",
+        mint: pubkey!("So11111111111111111111111111111111111111112"),
+        decimals: 9,
+    },
+    KnownMint {
+        symbol: "USDC",
+        mint: pubkey!("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v"),
+        decimals: 6,
+    },
+    KnownMint {
+        symbol: "USDT",
+        mint: pubkey!("Es9vMFrzaCERmJfrF4H2FYD4KCoNkY11McCe8BenwNYB"),
+        decimals: 6,
+    },
+    KnownMint {
+        symbol: "JitoSOL",
+        mint: pubkey!("J1toso1uCk3RLmjorhTtrVwY9HJ7X8V9yYac6Y7kGCPn"),
+        decimals: 9,
+    },
+    KnownMint {
+        symbol: "mSOL",
+        mint: pubkey!("mSoLzYCxHdYgdzU16g5QSh3i5K3z3KZK7ytfqcJm7So"),
+        decimals: 9,
+    },
+    KnownMint {
+        symbol: "stSOL",
+        mint: pubkey!("7dHbWXmci3dT8UFYWYZweBLXgycu7Y3iL6trKn1Y7ARj"),
+        decimals: 9,
+    },
+    KnownMint {
+        symbol: "BONK",
+        mint: pubkey!("DezXAZ8z7PnrnRJjz3wXBoRgixCa6xjnB7YaB1pPB263"),
+        decimals: 5,
+    },
+];
+
+/// Look up a known mint by address
+pub fn find_mint(mint: &Pubkey) -> Option<&'static KnownMint> {
+    KNOWN_MINTS.iter().find(|known| &known.mint == mint)
+}
+
+/// Look up a known mint by symbol, case insensitively
+pub fn find_mint_by_symbol(symbol: &str) -> Option<&'static KnownMint> {
+    KNOWN_MINTS
+        .iter()
+        .find(|known| known.symbol.eq_ignore_ascii_case(symbol))
+}
+
+/// A reserve of a known market
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReserveInfo {
+    /// Symbol of the liquidity mint, or the mint address if it isn't a known mint
+    pub symbol: String,
+    /// Reserve address
+    pub address: Pubkey,
+    /// Liquidity mint
+    pub liquidity_mint: Pubkey,
+    /// Liquidity mint decimals
+    pub liquidity_mint_decimals: u8,
+    /// Pyth price account, NULL_PUBKEY if unset
+    pub pyth_oracle: Pubkey,
+    /// Switchboard feed, NULL_PUBKEY if unset
+    pub switchboard_oracle: Pubkey,
+}
+
+impl ReserveInfo {
+    /// Describe a reserve from its account
+    pub fn from_reserve(address: Pubkey, reserve: &Reserve) -> Self {
+        let liquidity_mint = reserve.liquidity.mint_pubkey;
+        Self {
+            symbol: find_mint(&liquidity_mint)
+                .map(|known| known.symbol.to_string())
+                .unwrap_or_else(|| liquidity_mint.to_string()),
+            address,
+            liquidity_mint,
+            liquidity_mint_decimals: reserve.liquidity.mint_decimals,
+            pyth_oracle: reserve.liquidity.pyth_oracle_pubkey,
+            switchboard_oracle: reserve.liquidity.switchboard_oracle_pubkey,
+        }
+    }
+}
+
+/// A known lending market
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MarketInfo {
+    /// Market name
+    pub name: String,
+    /// Cluster the market is deployed to
+    pub cluster: Cluster,
+    /// Lending market address
+    pub address: Pubkey,
+    /// Reserves of the market
+    pub reserves: Vec<ReserveInfo>,
+}
+
+impl MarketInfo {
+    /// Reserve of a mint symbol, case insensitively
+    pub fn reserve(&self, symbol: &str) -> Option<&ReserveInfo> {
+        self.reserves
+            .iter()
+            .find(|reserve| reserve.symbol.eq_ignore_ascii_case(symbol))
+    }
+
+    /// Reserve of a liquidity mint
+    pub fn reserve_by_mint(&self, mint: &Pubkey) -> Option<&ReserveInfo> {
+        self.reserves
+            .iter()
+            .find(|reserve| &reserve.liquidity_mint == mint)
+    }
+
+    /// Reserve by address
+    pub fn reserve_by_address(&self, address: &Pubkey) -> Option<&ReserveInfo> {
+        self.reserves
+            .iter()
+            .find(|reserve| &reserve.address == address)
+    }
+}
+
+fn known_reserve(
+    symbol: &str,
+    address: Pubkey,
+    pyth_oracle: Pubkey,
+    switchboard_oracle: Pubkey,
+) -> ReserveInfo {
+    let mint = find_mint_by_symbol(symbol).expect("known mint");
+    ReserveInfo {
+        symbol: mint.symbol.to_string(),
+        address,
+        liquidity_mint: mint.mint,
+        liquidity_mint_decimals: mint.decimals,
+        pyth_oracle,
+        switchboard_oracle,
+    }
+}
+
+/// Markets shipped with the crate for a cluster. Only long lived reserves are listed, use
+/// [refresh_market] for the full set.
+pub fn known_markets(cluster: Cluster) -> Vec<MarketInfo> {
+    match cluster {
+        Cluster::Mainnet => vec![MarketInfo {
+            name: "main".to_string(),
+            cluster,
+            address: MAINNET_MAIN_POOL,
+            reserves: vec![
+                known_reserve(
+                    "SOL",
+                    pubkey!("8PbodeaosQP19SjYFx855UMqWxH2HynZLdBXmsrbac36"),
+                    pubkey!("H6ARHf6YXhGYeQfUzQNGk6rDNnLBQKrenN712K4AQJEG"),
+                    NULL_PUBKEY,
+                ),
+                known_reserve(
+                    "USDC",
+                    pubkey!("BgxfHJDzm44T7XG68MYKx7YisTjZu73tVovyZSjJMpmw"),
+                    pubkey!("Gnt27xtC473ZT2Mw5u8wZ68Z3gULkSTb5DuxJy7eJotD"),
+                    NULL_PUBKEY,
+                ),
+            ],
+        }],
+        Cluster::Devnet | Cluster::Testnet => vec![],
+    }
+}
+
+/// Known market of a cluster by name, case insensitively
+pub fn find_market(cluster: Cluster, name: &str) -> Option<MarketInfo> {
+    known_markets(cluster)
+        .into_iter()
+        .find(|market| market.name.eq_ignore_ascii_case(name))
+}
+
+/// Name stored in a lending market's metadata account, if it has one
+pub fn market_name_from_metadata(data: &[u8]) -> Option<String> {
+    let metadata = try_from_bytes::<LendingMarketMetadata>(data).ok()?;
+    let len = metadata
+        .market_name
+        .iter()
+        .position(|byte| *byte == 0)
+        .unwrap_or(metadata.market_name.len());
+    std::str::from_utf8(&metadata.market_name[..len])
+        .ok()
+        .filter(|name| !name.is_empty())
+        .map(str::to_string)
+}
+
+/// Rebuild a market's entry from chain: its name from the lending market metadata, falling back
+/// to the name shipped with the crate and then the market address, and all of its reserves.
+pub fn refresh_market(
+    client: &RpcClient,
+    cluster: Cluster,
+    lending_market: &Pubkey,
+) -> Result<MarketInfo, Box<dyn Error>> {
+    let program_id = cluster.program_id();
+
+    let (metadata_pubkey, _) = Pubkey::find_program_address(
+        &[&lending_market.to_bytes()[..PUBKEY_BYTES], b"MetaData"],
+        &program_id,
+    );
+    let name = client
+        .get_account_with_commitment(&metadata_pubkey, client.commitment())?
+        .value
+        .and_then(|account| market_name_from_metadata(&account.data))
+        .or_else(|| {
+            known_markets(cluster)
+                .into_iter()
+                .find(|market| &market.address == lending_market)
+                .map(|market| market.name)
+        })
+        .unwrap_or_else(|| lending_market.to_string());
+
+    let mut reserves = get_market_reserves(client, &program_id, lending_market)?
+        .into_iter()
+        .map(|(pubkey, reserve)| ReserveInfo::from_reserve(pubkey, &reserve))
+        .collect::<Vec<_>>();
+    reserves.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+
+    Ok(MarketInfo {
+        name,
+        cluster,
+        address: *lending_market,
+        reserves,
+    })
+}
+
+/// Every reserve of a lending market
+pub(crate) fn get_market_reserves(
+    client: &RpcClient,
+    program_id: &Pubkey,
+    lending_market: &Pubkey,
+) -> Result<Vec<(Pubkey, Reserve)>, Box<dyn Error>> {
+    let accounts = client.get_program_accounts_with_config(
+        program_id,
+        RpcProgramAccountsConfig {
+            filters: Some(vec![
+                RpcFilterType::DataSize(Reserve::LEN as u64),
+                RpcFilterType::Memcmp(Memcmp::new_base58_encoded(
+                    RESERVE_LENDING_MARKET_OFFSET,
+                    lending_market.as_ref(),
+                )),
+            ]),
+            ..RpcProgramAccountsConfig::default()
+        },
+    )?;
+    Ok(accounts
+        .into_iter()
+        .filter_map(|(pubkey, account)| {
+            Reserve::unpack(&account.data)
+                .ok()
+                .map(|reserve| (pubkey, reserve))
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::state::{ReserveLiquidity, PROGRAM_VERSION};
+
+    #[test]
+    fn find_known_markets_and_reserves() {
+        let main_pool = find_market(Cluster::Mainnet, "Main").unwrap();
+        assert_eq!(main_pool.address, MAINNET_MAIN_POOL);
+        assert_eq!(
+            Cluster::Mainnet.known_markets(),
+            known_markets(Cluster::Mainnet)
+                .iter()
+                .map(|market| market.address)
+                .collect::<Vec<_>>()
+        );
+
+        let sol = main_pool.reserve("sol").unwrap();
+        assert_eq!(sol.liquidity_mint_decimals, 9);
+        assert_eq!(main_pool.reserve_by_mint(&sol.liquidity_mint), Some(sol));
+        assert_eq!(main_pool.reserve_by_address(&sol.address), Some(sol));
+        assert_eq!(main_pool.reserve("JitoSOL"), None);
+
+        assert!(find_market(Cluster::Devnet, "main").is_none());
+    }
+
+    #[test]
+    fn reserve_info_from_reserve() {
+        let jitosol = find_mint_by_symbol("jitosol").unwrap();
+        let reserve = Reserve {
+            version: PROGRAM_VERSION,
+            liquidity: ReserveLiquidity {
+                mint_pubkey: jitosol.mint,
+                mint_decimals: 9,
+                ..ReserveLiquidity::default()
+            },
+            ..Reserve::default()
+        };
+        let address = Pubkey::new_unique();
+        let info = ReserveInfo::from_reserve(address, &reserve);
+        assert_eq!(info.symbol, "JitoSOL");
+        assert_eq!(info.address, address);
+
+        // the lending market filter matches the packed layout
+        let mut data = vec![0u8; Reserve::LEN];
+        let lending_market = Pubkey::new_unique();
+        Reserve::pack(
+            Reserve {
+                lending_market,
+                ..reserve.clone()
+            },
+            &mut data,
+        )
+        .unwrap();
+        assert_eq!(
+            &data[RESERVE_LENDING_MARKET_OFFSET..RESERVE_LENDING_MARKET_OFFSET + PUBKEY_BYTES],
+            lending_market.as_ref()
+        );
+
+        let unknown = Reserve {
+            liquidity: ReserveLiquidity {
+                mint_pubkey: Pubkey::new_unique(),
+                ..ReserveLiquidity::default()
+            },
+            ..reserve
+        };
+        assert_eq!(
+            ReserveInfo::from_reserve(address, &unknown).symbol,
+            unknown.liquidity.mint_pubkey.to_string()
+        );
+    }
+
+    #[test]
+    fn market_name_from_metadata_bytes() {
+        let mut data = vec![0u8; std::mem::size_of::<LendingMarketMetadata>()];
+        assert_eq!(market_name_from_metadata(&data), None);
+
+        data[1..5].copy_from_slice(b"main");
+        assert_eq!(market_name_from_metadata(&data), Some("main".to_string()));
+        assert_eq!(market_name_from_metadata(&data[1..]), None);
+    }
+}
@@ -0,0 +1,530 @@
+//! Retries, request chunking and RPC failover for the off-chain fetching utilities.
+//!
+//! Fetching every account of the lending program in one getProgramAccounts request is slow, and
+//! public RPCs regularly rate limit or time it out. A [ResilientRpc] retries failed calls with
+//! exponential backoff according to a [RetryPolicy], moving on to the next of its endpoints on
+//! every retry.
+//!
+//! [fetch_solend_accounts] splits the fetch into [AccountChunk]s, one getProgramAccounts request
+//! per account type and one per lending market for obligations, and [fetch_oracle_prices] fetches
+//! oracles in batches of [MAX_MULTIPLE_ACCOUNTS]. Chunks that still fail once their retries are
+//! used up don't throw away the rest: the accounts of the successful chunks come back in a
+//! [PartialFetchError] listing the failed ones, which [resume_solend_accounts] and
+//! [resume_oracle_prices] fetch again.
+
+// ClientError is large, and partial results are returned by value so callers can resume from them
+#![allow(clippy::result_large_err)]
+
+use crate::{
+    math::Decimal,
+    offchain_utils::{oracle_pubkeys, parse_oracle_price, SolendAccounts, MAX_MULTIPLE_ACCOUNTS},
+    state::{LendingMarket, Obligation, ObligationBorrowCap, Reserve},
+};
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::{
+    client_error::{ClientError, ClientErrorKind, Result as ClientResult},
+    rpc_client::RpcClient,
+    rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig},
+    rpc_filter::{Memcmp, RpcFilterType},
+    rpc_request::RpcError,
+};
+use solana_program::{program_pack::Pack, pubkey::Pubkey};
+use std::{
+    collections::{HashMap, VecDeque},
+    error::Error,
+    fmt, thread,
+    time::Duration,
+};
+
+/// Offset of the lending market in a packed obligation, after the version and last update
+const OBLIGATION_LENDING_MARKET_OFFSET: usize = 10;
+
+/// When and how often to retry failed RPC calls
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Attempts per call, including the first one
+    pub max_attempts: u32,
+    /// Wait before the first retry, doubled for every further one
+    pub initial_backoff: Duration,
+    /// Longest wait between two attempts
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(10),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Wait before retry number `retry`, counting from 0
+    pub fn backoff(&self, retry: u32) -> Duration {
+        2u32.checked_pow(retry)
+            .and_then(|factor| self.initial_backoff.checked_mul(factor))
+            .map_or(self.max_backoff, |backoff| backoff.min(self.max_backoff))
+    }
+}
+
+/// Whether a failed call is worth retrying: transport errors, rate limits and timeouts, and
+/// server side errors of the node. Malformed requests and responses fail the same way every time.
+pub fn is_retryable(err: &ClientError) -> bool {
+    match err.kind() {
+        ClientErrorKind::Io(_) | ClientErrorKind::Reqwest(_) => true,
+        ClientErrorKind::RpcError(RpcError::RpcRequestError(_)) => true,
+        // -32000 to -32099 are reserved for server errors, -32603 is an internal error
+        ClientErrorKind::RpcError(RpcError::RpcResponseError { code, .. }) => {
+            (-32099..=-32000).contains(code) || *code == -32603
+        }
+        _ => false,
+    }
+}
+
+/// RPC clients of one or more endpoints, called with retries and failover
+pub struct ResilientRpc {
+    clients: Vec<RpcClient>,
+    policy: RetryPolicy,
+}
+
+impl ResilientRpc {
+    /// Create a resilient client calling `client` with `policy`
+    pub fn new(client: RpcClient, policy: RetryPolicy) -> Self {
+        Self {
+            clients: vec![client],
+            policy,
+        }
+    }
+
+    /// Add an endpoint to fail over to. Retries go through the endpoints in the order they were
+    /// added, starting over from the first one after the last.
+    pub fn fallback(mut self, client: RpcClient) -> Self {
+        self.clients.push(client);
+        self
+    }
+
+    /// Client of the first endpoint
+    pub fn primary(&self) -> &RpcClient {
+        &self.clients[0]
+    }
+
+    /// Make a call, retrying retryable errors on the next endpoint after the policy's backoff
+    /// until it succeeds or runs out of attempts. Returns the last error.
+    pub fn call<T>(
+        &self,
+        mut request: impl FnMut(&RpcClient) -> ClientResult<T>,
+    ) -> ClientResult<T> {
+        let mut attempt = 0;
+        loop {
+            let client = &self.clients[attempt as usize % self.clients.len()];
+            match request(client) {
+                Ok(value) => return Ok(value),
+                Err(err) if attempt + 1 < self.policy.max_attempts && is_retryable(&err) => {
+                    let backoff = self.policy.backoff(attempt);
+                    trace_event!(
+                        warn,
+                        endpoint = %client.url(),
+                        attempt,
+                        error = %err,
+                        ?backoff,
+                        "rpc call failed, retrying"
+                    );
+                    thread::sleep(backoff);
+                    attempt += 1;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+/// Part of the lending program accounts fetched with one getProgramAccounts request
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum AccountChunk {
+    /// All lending markets
+    LendingMarkets,
+    /// All reserves
+    Reserves,
+    /// All obligation borrow caps
+    ObligationBorrowCaps,
+    /// Obligations of a lending market
+    Obligations(Pubkey),
+}
+
+impl AccountChunk {
+    fn filters(&self) -> Vec<RpcFilterType> {
+        match self {
+            AccountChunk::LendingMarkets => {
+                vec![RpcFilterType::DataSize(LendingMarket::LEN as u64)]
+            }
+            AccountChunk::Reserves => vec![RpcFilterType::DataSize(Reserve::LEN as u64)],
+            AccountChunk::ObligationBorrowCaps => {
+                vec![RpcFilterType::DataSize(ObligationBorrowCap::LEN as u64)]
+            }
+            AccountChunk::Obligations(lending_market) => vec![
+                RpcFilterType::DataSize(Obligation::LEN as u64),
+                RpcFilterType::Memcmp(Memcmp::new_base58_encoded(
+                    OBLIGATION_LENDING_MARKET_OFFSET,
+                    lending_market.as_ref(),
+                )),
+            ],
+        }
+    }
+}
+
+/// A chunk that failed after all retries
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FailedChunk<C> {
+    /// The chunk
+    pub chunk: C,
+    /// Last error fetching it
+    pub error: String,
+}
+
+/// Some chunks of a fetch failed. Holds what the other chunks fetched, to resume from.
+#[derive(Clone, Debug)]
+pub struct PartialFetchError<T, C> {
+    /// Accounts of the chunks that succeeded
+    pub partial: T,
+    /// Chunks that failed, in the order they were fetched
+    pub failed: Vec<FailedChunk<C>>,
+}
+
+impl<T, C: Clone> PartialFetchError<T, C> {
+    /// Chunks to fetch again
+    pub fn failed_chunks(&self) -> Vec<C> {
+        self.failed
+            .iter()
+            .map(|failed| failed.chunk.clone())
+            .collect()
+    }
+}
+
+impl<T, C: fmt::Debug> fmt::Display for PartialFetchError<T, C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "failed to fetch {} chunks:", self.failed.len())?;
+        for failed in &self.failed {
+            write!(f, " {:?}: {};", failed.chunk, failed.error)?;
+        }
+        Ok(())
+    }
+}
+
+impl<T: fmt::Debug, C: fmt::Debug> Error for PartialFetchError<T, C> {}
+
+/// Result of a chunked fetch of the lending program accounts
+pub type SolendAccountsResult =
+    Result<SolendAccounts, PartialFetchError<SolendAccounts, AccountChunk>>;
+
+/// Result of a batched fetch of oracle prices. Failed batches are identified by their oracles.
+pub type OraclePricesResult = Result<
+    HashMap<Pubkey, Option<Decimal>>,
+    PartialFetchError<HashMap<Pubkey, Option<Decimal>>, Vec<Pubkey>>,
+>;
+
+/// Fetch the lending markets, reserves, obligation borrow caps and obligations with borrows of
+/// the lending program, in chunks. Obligations are fetched per lending market once the lending
+/// markets are in.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(skip_all, fields(program = %lending_program_id))
+)]
+pub fn fetch_solend_accounts(
+    rpc: &ResilientRpc,
+    lending_program_id: &Pubkey,
+) -> SolendAccountsResult {
+    fetch_solend_account_chunks(
+        rpc,
+        lending_program_id,
+        vec![
+            AccountChunk::LendingMarkets,
+            AccountChunk::Reserves,
+            AccountChunk::ObligationBorrowCaps,
+        ],
+        SolendAccounts::default(),
+    )
+}
+
+/// Fetch the failed chunks of a [fetch_solend_accounts] into its partial result
+pub fn resume_solend_accounts(
+    rpc: &ResilientRpc,
+    lending_program_id: &Pubkey,
+    err: PartialFetchError<SolendAccounts, AccountChunk>,
+) -> SolendAccountsResult {
+    let chunks = err.failed_chunks();
+    fetch_solend_account_chunks(rpc, lending_program_id, chunks, err.partial)
+}
+
+/// Fetch `chunks` of the lending program accounts into `accounts`. Fetching the lending markets
+/// adds a chunk for the obligations of each of them.
+pub fn fetch_solend_account_chunks(
+    rpc: &ResilientRpc,
+    lending_program_id: &Pubkey,
+    chunks: Vec<AccountChunk>,
+    mut accounts: SolendAccounts,
+) -> SolendAccountsResult {
+    let mut chunks = VecDeque::from(chunks);
+    let mut failed = Vec::new();
+    while let Some(chunk) = chunks.pop_front() {
+        let config = RpcProgramAccountsConfig {
+            filters: Some(chunk.filters()),
+            account_config: RpcAccountInfoConfig {
+                encoding: Some(UiAccountEncoding::Base64Zstd),
+                ..RpcAccountInfoConfig::default()
+            },
+            ..RpcProgramAccountsConfig::default()
+        };
+        match rpc.call(|client| {
+            client.get_program_accounts_with_config(lending_program_id, config.clone())
+        }) {
+            Ok(chunk_accounts) => {
+                trace_event!(
+                    debug,
+                    ?chunk,
+                    accounts = chunk_accounts.len(),
+                    "fetched chunk"
+                );
+                for (pubkey, account) in chunk_accounts {
+                    accounts.insert_account(pubkey, &account.data);
+                }
+                if chunk == AccountChunk::LendingMarkets {
+                    chunks.extend(
+                        accounts
+                            .lending_markets
+                            .keys()
+                            .map(|lending_market| AccountChunk::Obligations(*lending_market)),
+                    );
+                }
+            }
+            Err(err) => {
+                trace_event!(error, ?chunk, error = %err, "failed to fetch chunk");
+                failed.push(FailedChunk {
+                    chunk,
+                    error: err.to_string(),
+                });
+            }
+        }
+    }
+
+    if failed.is_empty() {
+        Ok(accounts)
+    } else {
+        Err(PartialFetchError {
+            partial: accounts,
+            failed,
+        })
+    }
+}
+
+/// Fetch the prices of every oracle used by `reserves` like
+/// [get_oracle_prices](crate::offchain_utils::get_oracle_prices), with each getMultipleAccounts
+/// batch retried on its own.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(skip_all, fields(reserves = reserves.len()))
+)]
+pub fn fetch_oracle_prices(
+    rpc: &ResilientRpc,
+    reserves: &HashMap<Pubkey, Reserve>,
+) -> OraclePricesResult {
+    let chunks = oracle_pubkeys(reserves)
+        .chunks(MAX_MULTIPLE_ACCOUNTS)
+        .map(|chunk| chunk.to_vec())
+        .collect();
+    fetch_oracle_price_chunks(rpc, chunks, HashMap::new())
+}
+
+/// Fetch the failed batches of a [fetch_oracle_prices] into its partial result
+pub fn resume_oracle_prices(
+    rpc: &ResilientRpc,
+    err: PartialFetchError<HashMap<Pubkey, Option<Decimal>>, Vec<Pubkey>>,
+) -> OraclePricesResult {
+    let chunks = err.failed_chunks();
+    fetch_oracle_price_chunks(rpc, chunks, err.partial)
+}
+
+fn fetch_oracle_price_chunks(
+    rpc: &ResilientRpc,
+    chunks: Vec<Vec<Pubkey>>,
+    mut prices: HashMap<Pubkey, Option<Decimal>>,
+) -> OraclePricesResult {
+    let mut failed = Vec::new();
+    for chunk in chunks {
+        match rpc.call(|client| {
+            client.get_multiple_accounts_with_commitment(&chunk, client.commitment())
+        }) {
+            Ok(response) => {
+                let slot = response.context.slot;
+                for (oracle, account) in chunk.iter().zip(response.value) {
+                    let price = account.and_then(|account| {
+                        parse_oracle_price(oracle, &account.owner, &account.data, slot)
+                    });
+                    prices.insert(*oracle, price);
+                }
+            }
+            Err(err) => {
+                trace_event!(error, oracles = chunk.len(), error = %err, "failed to fetch oracles");
+                failed.push(FailedChunk {
+                    chunk,
+                    error: err.to_string(),
+                });
+            }
+        }
+    }
+
+    if failed.is_empty() {
+        Ok(prices)
+    } else {
+        Err(PartialFetchError {
+            partial: prices,
+            failed,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::{cell::RefCell, io};
+
+    fn no_backoff(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts,
+            initial_backoff: Duration::ZERO,
+            max_backoff: Duration::ZERO,
+        }
+    }
+
+    fn transient_error() -> ClientError {
+        ClientErrorKind::Io(io::Error::new(io::ErrorKind::TimedOut, "timed out")).into()
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_max() {
+        let policy = RetryPolicy {
+            max_attempts: 10,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(1),
+        };
+        assert_eq!(policy.backoff(0), Duration::from_millis(100));
+        assert_eq!(policy.backoff(1), Duration::from_millis(200));
+        assert_eq!(policy.backoff(3), Duration::from_millis(800));
+        assert_eq!(policy.backoff(4), Duration::from_secs(1));
+        assert_eq!(policy.backoff(u32::MAX), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn retryable_errors() {
+        assert!(is_retryable(&transient_error()));
+        assert!(is_retryable(
+            &ClientErrorKind::RpcError(RpcError::RpcResponseError {
+                code: -32005,
+                message: "Node is unhealthy".to_string(),
+                data: solana_client::rpc_request::RpcResponseErrorData::Empty,
+            })
+            .into()
+        ));
+        assert!(!is_retryable(
+            &ClientErrorKind::RpcError(RpcError::RpcResponseError {
+                code: -32602,
+                message: "Invalid params".to_string(),
+                data: solana_client::rpc_request::RpcResponseErrorData::Empty,
+            })
+            .into()
+        ));
+        assert!(!is_retryable(
+            &ClientErrorKind::Custom("bad request".to_string()).into()
+        ));
+    }
+
+    #[test]
+    fn call_fails_over_between_endpoints() {
+        let rpc = ResilientRpc::new(RpcClient::new_mock("primary"), no_backoff(5))
+            .fallback(RpcClient::new_mock("fallback"));
+        let endpoints = RefCell::new(Vec::new());
+
+        let value = rpc
+            .call(|client| {
+                endpoints.borrow_mut().push(client.url());
+                if endpoints.borrow().len() < 4 {
+                    Err(transient_error())
+                } else {
+                    Ok(7)
+                }
+            })
+            .unwrap();
+        assert_eq!(value, 7);
+        assert_eq!(
+            endpoints.into_inner(),
+            vec![
+                "MockSender: primary",
+                "MockSender: fallback",
+                "MockSender: primary",
+                "MockSender: fallback"
+            ]
+        );
+    }
+
+    #[test]
+    fn call_gives_up() {
+        let rpc = ResilientRpc::new(RpcClient::new_mock("primary"), no_backoff(3));
+
+        let attempts = RefCell::new(0);
+        let result: ClientResult<()> = rpc.call(|_| {
+            *attempts.borrow_mut() += 1;
+            Err(transient_error())
+        });
+        assert!(result.is_err());
+        assert_eq!(attempts.into_inner(), 3);
+
+        // errors that won't go away aren't retried
+        let attempts = RefCell::new(0);
+        let result: ClientResult<()> = rpc.call(|_| {
+            *attempts.borrow_mut() += 1;
+            Err(ClientErrorKind::Custom("bad request".to_string()).into())
+        });
+        assert!(result.is_err());
+        assert_eq!(attempts.into_inner(), 1);
+    }
+
+    #[test]
+    fn obligation_lending_market_offset() {
+        let lending_market = Pubkey::new_unique();
+        let obligation = Obligation {
+            lending_market,
+            owner: Pubkey::new_unique(),
+            ..Obligation::default()
+        };
+        let mut data = vec![0; Obligation::LEN];
+        Obligation::pack(obligation, &mut data).unwrap();
+
+        assert_eq!(
+            &data[OBLIGATION_LENDING_MARKET_OFFSET..OBLIGATION_LENDING_MARKET_OFFSET + 32],
+            lending_market.as_ref()
+        );
+    }
+
+    #[test]
+    fn resume_failed_chunks() {
+        let program_id = Pubkey::new_unique();
+
+        // the "fails" mock answers every request with null
+        let rpc = ResilientRpc::new(RpcClient::new_mock("fails"), no_backoff(2));
+        let err = fetch_solend_accounts(&rpc, &program_id).unwrap_err();
+        assert_eq!(
+            err.failed_chunks(),
+            vec![
+                AccountChunk::LendingMarkets,
+                AccountChunk::Reserves,
+                AccountChunk::ObligationBorrowCaps,
+            ]
+        );
+
+        let rpc = ResilientRpc::new(RpcClient::new_mock("succeeds"), no_backoff(2));
+        let accounts = resume_solend_accounts(&rpc, &program_id, err).unwrap();
+        assert!(accounts.lending_markets.is_empty());
+        assert!(accounts.obligations.is_empty());
+    }
+}
@@ -0,0 +1,281 @@
+//! Standard risk bands of obligations.
+//!
+//! Bands compare an obligation's borrowed value with its unhealthy borrow value: an obligation
+//! is [RiskBand::Liquidatable] once it borrowed all of it, and gets margin called in the
+//! [RiskBand::Warning] and [RiskBand::Danger] bands once it borrowed a configurable share of it.
+//! Monitors, dashboards and bots should all classify obligations through [RiskBandThresholds] so
+//! a user sees the same band wherever they look.
+//!
+//! Past the unhealthy borrow value, [liquidation_depth] tells how far an obligation went towards
+//! its super unhealthy borrow value, where liquidators get the max liquidation bonus.
+
+use crate::{
+    error::LendingError,
+    math::{Decimal, TryDiv, TryMul, TrySub},
+    state::Obligation,
+};
+use solana_program::{msg, program_error::ProgramError};
+use std::{cmp::min, fmt};
+
+/// How close an obligation is to being liquidated, from safest to worst
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum RiskBand {
+    /// Borrowed value below the warning threshold, or nothing borrowed
+    Safe,
+    /// Borrowed value at or above the warning threshold, a margin call
+    Warning,
+    /// Borrowed value at or above the danger threshold, the last margin call
+    Danger,
+    /// Borrowed value reached the unhealthy borrow value, the obligation can be liquidated
+    Liquidatable,
+}
+
+impl fmt::Display for RiskBand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let band = match self {
+            RiskBand::Safe => "safe",
+            RiskBand::Warning => "warning",
+            RiskBand::Danger => "danger",
+            RiskBand::Liquidatable => "liquidatable",
+        };
+        write!(f, "{}", band)
+    }
+}
+
+/// Margin call thresholds, as shares of the unhealthy borrow value an obligation borrowed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RiskBandThresholds {
+    /// Share of the unhealthy borrow value at or above which an obligation is in the warning band
+    pub warning: Decimal,
+    /// Share of the unhealthy borrow value at or above which an obligation is in the danger band
+    pub danger: Decimal,
+    /// Margin a share has to drop below a threshold by to move to a safer band
+    pub hysteresis: Decimal,
+}
+
+impl Default for RiskBandThresholds {
+    fn default() -> Self {
+        Self {
+            warning: Decimal::from_percent(80),
+            danger: Decimal::from_percent(90),
+            hysteresis: Decimal::from_percent(4),
+        }
+    }
+}
+
+impl RiskBandThresholds {
+    /// Checks that the thresholds are increasing, below liquidation, and leave room for the
+    /// hysteresis
+    pub fn validate(&self) -> Result<(), ProgramError> {
+        if self.warning > self.danger || self.danger >= Decimal::one() {
+            msg!("Risk band thresholds must be increasing and below 1");
+            return Err(LendingError::InvalidConfig.into());
+        }
+        if self.hysteresis >= self.warning {
+            msg!("Risk band hysteresis must be below the warning threshold");
+            return Err(LendingError::InvalidConfig.into());
+        }
+        Ok(())
+    }
+
+    /// Band of an obligation. Expects a refreshed obligation.
+    pub fn band(&self, obligation: &Obligation) -> Result<RiskBand, ProgramError> {
+        self.band_of(
+            obligation.borrowed_value,
+            obligation.unhealthy_borrow_value,
+            Decimal::zero(),
+        )
+    }
+
+    /// Band to move to from `previous`. Worse bands are entered as soon as their threshold is
+    /// reached, safer ones only once the obligation dropped below it by the hysteresis margin.
+    /// Liquidatable obligations leave that band as soon as they're healthy again.
+    pub fn next_band(
+        &self,
+        previous: RiskBand,
+        obligation: &Obligation,
+    ) -> Result<RiskBand, ProgramError> {
+        let band = self.band(obligation)?;
+        if band >= previous {
+            return Ok(band);
+        }
+        Ok(previous.min(self.band_of(
+            obligation.borrowed_value,
+            obligation.unhealthy_borrow_value,
+            self.hysteresis,
+        )?))
+    }
+
+    /// Borrowed value at which an obligation with `unhealthy_borrow_value` gets margin called
+    pub fn margin_call_value(
+        &self,
+        unhealthy_borrow_value: Decimal,
+    ) -> Result<Decimal, ProgramError> {
+        unhealthy_borrow_value.try_mul(self.warning)
+    }
+
+    fn band_of(
+        &self,
+        borrowed_value: Decimal,
+        unhealthy_borrow_value: Decimal,
+        margin: Decimal,
+    ) -> Result<RiskBand, ProgramError> {
+        if borrowed_value == Decimal::zero() {
+            return Ok(RiskBand::Safe);
+        }
+        if borrowed_value >= unhealthy_borrow_value {
+            return Ok(RiskBand::Liquidatable);
+        }
+        let reached = |threshold: Decimal| -> Result<bool, ProgramError> {
+            let threshold = threshold.try_sub(min(margin, threshold))?;
+            Ok(borrowed_value >= unhealthy_borrow_value.try_mul(threshold)?)
+        };
+        Ok(if reached(self.danger)? {
+            RiskBand::Danger
+        } else if reached(self.warning)? {
+            RiskBand::Warning
+        } else {
+            RiskBand::Safe
+        })
+    }
+}
+
+/// Whether an obligation can be liquidated. Expects a refreshed obligation.
+pub fn is_liquidatable(obligation: &Obligation) -> bool {
+    obligation.borrowed_value > Decimal::zero()
+        && obligation.borrowed_value >= obligation.unhealthy_borrow_value
+}
+
+/// How far a liquidatable obligation went from its unhealthy borrow value, 0, to its super
+/// unhealthy borrow value, 1 and beyond, which the liquidation bonus scales with. None if the
+/// obligation can't be liquidated. Expects a refreshed obligation.
+pub fn liquidation_depth(obligation: &Obligation) -> Result<Option<Decimal>, ProgramError> {
+    if !is_liquidatable(obligation) {
+        return Ok(None);
+    }
+    if obligation.super_unhealthy_borrow_value <= obligation.unhealthy_borrow_value {
+        return Ok(Some(Decimal::one()));
+    }
+    let depth = obligation
+        .borrowed_value
+        .try_sub(obligation.unhealthy_borrow_value)?
+        .try_div(
+            obligation
+                .super_unhealthy_borrow_value
+                .try_sub(obligation.unhealthy_borrow_value)?,
+        )?;
+    Ok(Some(min(depth, Decimal::one())))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn obligation(borrowed_value: u64) -> Obligation {
+        Obligation {
+            borrowed_value: Decimal::from(borrowed_value),
+            unhealthy_borrow_value: Decimal::from(100u64),
+            super_unhealthy_borrow_value: Decimal::from(120u64),
+            ..Obligation::default()
+        }
+    }
+
+    #[test]
+    fn risk_bands() {
+        let thresholds = RiskBandThresholds::default();
+        let band = |borrowed_value| thresholds.band(&obligation(borrowed_value)).unwrap();
+
+        assert_eq!(band(0), RiskBand::Safe);
+        assert_eq!(band(79), RiskBand::Safe);
+        assert_eq!(band(80), RiskBand::Warning);
+        assert_eq!(band(90), RiskBand::Danger);
+        assert_eq!(band(100), RiskBand::Liquidatable);
+        assert_eq!(
+            thresholds.margin_call_value(Decimal::from(100u64)).unwrap(),
+            Decimal::from(80u64)
+        );
+
+        // borrows without any unhealthy borrow value
+        let no_collateral = Obligation {
+            borrowed_value: Decimal::one(),
+            ..Obligation::default()
+        };
+        assert_eq!(
+            thresholds.band(&no_collateral).unwrap(),
+            RiskBand::Liquidatable
+        );
+    }
+
+    #[test]
+    fn risk_bands_with_hysteresis() {
+        let thresholds = RiskBandThresholds::default();
+        let next = |previous, borrowed_value| {
+            thresholds
+                .next_band(previous, &obligation(borrowed_value))
+                .unwrap()
+        };
+
+        // worse bands are entered right away
+        assert_eq!(next(RiskBand::Safe, 95), RiskBand::Danger);
+        // safer ones need the margin
+        assert_eq!(next(RiskBand::Danger, 87), RiskBand::Danger);
+        assert_eq!(next(RiskBand::Danger, 85), RiskBand::Warning);
+        assert_eq!(next(RiskBand::Warning, 77), RiskBand::Warning);
+        assert_eq!(next(RiskBand::Warning, 75), RiskBand::Safe);
+        // except out of liquidation
+        assert_eq!(next(RiskBand::Liquidatable, 99), RiskBand::Danger);
+        assert_eq!(next(RiskBand::Liquidatable, 0), RiskBand::Safe);
+    }
+
+    #[test]
+    fn validate_thresholds() {
+        assert_eq!(RiskBandThresholds::default().validate(), Ok(()));
+        let invalid = [
+            RiskBandThresholds {
+                warning: Decimal::from_percent(95),
+                ..RiskBandThresholds::default()
+            },
+            RiskBandThresholds {
+                danger: Decimal::one(),
+                ..RiskBandThresholds::default()
+            },
+            RiskBandThresholds {
+                hysteresis: Decimal::from_percent(80),
+                ..RiskBandThresholds::default()
+            },
+        ];
+        for thresholds in invalid {
+            assert_eq!(
+                thresholds.validate(),
+                Err(LendingError::InvalidConfig.into())
+            );
+        }
+    }
+
+    #[test]
+    fn liquidation_depths() {
+        assert_eq!(liquidation_depth(&obligation(0)).unwrap(), None);
+        assert_eq!(liquidation_depth(&obligation(99)).unwrap(), None);
+        assert_eq!(
+            liquidation_depth(&obligation(100)).unwrap(),
+            Some(Decimal::zero())
+        );
+        assert_eq!(
+            liquidation_depth(&obligation(110)).unwrap(),
+            Some(Decimal::from_percent(50))
+        );
+        assert_eq!(
+            liquidation_depth(&obligation(200)).unwrap(),
+            Some(Decimal::one())
+        );
+
+        let without_range = Obligation {
+            super_unhealthy_borrow_value: Decimal::from(100u64),
+            ..obligation(100)
+        };
+        assert_eq!(
+            liquidation_depth(&without_range).unwrap(),
+            Some(Decimal::one())
+        );
+    }
+}
@@ -0,0 +1,187 @@
+//! Conversions between wall-clock time and slots for off-chain interest math.
+//!
+//! Interest accrues per slot, but analytics pipelines usually have timestamps. A [SlotClock]
+//! anchors one slot to one unix timestamp and extrapolates with a fixed slot duration, which can
+//! be configured, derived from a reserve's `slots_per_year`, or measured from the cluster's recent
+//! performance samples with [SlotClock::from_rpc].
+
+use crate::{
+    math::Decimal,
+    offchain_utils::offchain_refresh_reserve,
+    state::{LastUpdate, Reserve},
+};
+use solana_client::rpc_client::RpcClient;
+use solana_program::{
+    clock::{Clock, Slot, UnixTimestamp, DEFAULT_MS_PER_SLOT},
+    pubkey::Pubkey,
+    sysvar,
+};
+use std::{collections::HashMap, error::Error};
+
+const MS_PER_YEAR: u64 = 365 * 24 * 60 * 60 * 1000;
+
+/// Maps slots to unix timestamps and back, assuming a constant slot duration from an anchor
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SlotClock {
+    /// Slot the clock is anchored at
+    pub anchor_slot: Slot,
+    /// Unix timestamp of the anchor slot, in seconds
+    pub anchor_timestamp: UnixTimestamp,
+    /// Duration of a slot in milliseconds
+    pub ms_per_slot: u64,
+}
+
+impl SlotClock {
+    /// Create a clock anchored at `anchor_slot` produced at `anchor_timestamp`
+    pub fn new(anchor_slot: Slot, anchor_timestamp: UnixTimestamp, ms_per_slot: u64) -> Self {
+        Self {
+            anchor_slot,
+            anchor_timestamp,
+            ms_per_slot: ms_per_slot.max(1),
+        }
+    }
+
+    /// Create a clock with the slot duration a reserve's `slots_per_year` implies, so timestamps
+    /// convert to the same number of slots the reserve accrues a year of interest over
+    pub fn from_slots_per_year(
+        anchor_slot: Slot,
+        anchor_timestamp: UnixTimestamp,
+        slots_per_year: u64,
+    ) -> Self {
+        Self::new(
+            anchor_slot,
+            anchor_timestamp,
+            MS_PER_YEAR / slots_per_year.max(1),
+        )
+    }
+
+    /// Create a clock anchored at a Clock sysvar with the default slot duration
+    pub fn from_clock(clock: &Clock) -> Self {
+        Self::new(clock.slot, clock.unix_timestamp, DEFAULT_MS_PER_SLOT)
+    }
+
+    /// Create a clock anchored at the cluster's current Clock sysvar, with the average slot
+    /// duration over the last `sample_count` performance samples (one per minute). Falls back
+    /// to the default slot duration when the node has no samples.
+    pub fn from_rpc(client: &RpcClient, sample_count: usize) -> Result<Self, Box<dyn Error>> {
+        let account = client.get_account(&sysvar::clock::id())?;
+        let clock: Clock = solana_sdk::account::from_account(&account)
+            .ok_or("Clock sysvar account could not be deserialized")?;
+
+        let samples = client.get_recent_performance_samples(Some(sample_count))?;
+        let (slots, secs) = samples.iter().fold((0u64, 0u64), |(slots, secs), sample| {
+            (
+                slots + sample.num_slots,
+                secs + u64::from(sample.sample_period_secs),
+            )
+        });
+        let ms_per_slot = if slots == 0 {
+            DEFAULT_MS_PER_SLOT
+        } else {
+            secs * 1000 / slots
+        };
+
+        Ok(Self::new(clock.slot, clock.unix_timestamp, ms_per_slot))
+    }
+
+    /// Estimated unix timestamp of `slot`
+    pub fn timestamp_at(&self, slot: Slot) -> UnixTimestamp {
+        let slots = i128::from(slot) - i128::from(self.anchor_slot);
+        let ms = slots * i128::from(self.ms_per_slot);
+        let timestamp = i128::from(self.anchor_timestamp) + ms.div_euclid(1000);
+        timestamp.clamp(i64::MIN.into(), i64::MAX.into()) as UnixTimestamp
+    }
+
+    /// Estimated slot at unix timestamp `timestamp`, zero for times before the genesis estimate
+    pub fn slot_at(&self, timestamp: UnixTimestamp) -> Slot {
+        let ms = (i128::from(timestamp) - i128::from(self.anchor_timestamp)) * 1000;
+        let slot = i128::from(self.anchor_slot) + ms.div_euclid(self.ms_per_slot.into());
+        slot.clamp(0, u64::MAX.into()) as Slot
+    }
+}
+
+/// [offchain_refresh_reserve_interest](crate::offchain_utils::offchain_refresh_reserve_interest)
+/// at a unix timestamp instead of a slot
+pub fn offchain_refresh_reserve_interest_at(
+    reserve: &mut Reserve,
+    timestamp: UnixTimestamp,
+    clock: &SlotClock,
+) -> Result<(), Box<dyn Error>> {
+    let slot = clock.slot_at(timestamp);
+    reserve.accrue_interest(slot)?;
+    reserve.last_update = LastUpdate { slot, stale: false };
+
+    Ok(())
+}
+
+/// [offchain_refresh_reserve] at a unix timestamp instead of a slot
+pub fn offchain_refresh_reserve_at(
+    pubkey: &Pubkey,
+    reserve: &mut Reserve,
+    timestamp: UnixTimestamp,
+    clock: &SlotClock,
+    prices: &HashMap<Pubkey, Option<Decimal>>,
+) -> Result<(), Box<dyn Error>> {
+    offchain_refresh_reserve(pubkey, reserve, clock.slot_at(timestamp), prices)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::state::{ReserveConfig, ReserveLiquidity, SLOTS_PER_YEAR};
+
+    #[test]
+    fn round_trip() {
+        let clock = SlotClock::new(1_000_000, 1_700_000_000, 400);
+        assert_eq!(clock.timestamp_at(1_000_000), 1_700_000_000);
+        assert_eq!(clock.timestamp_at(1_000_150), 1_700_000_060);
+        assert_eq!(clock.timestamp_at(999_850), 1_699_999_940);
+        assert_eq!(clock.slot_at(1_700_000_060), 1_000_150);
+        assert_eq!(clock.slot_at(1_699_999_940), 999_850);
+
+        // before genesis
+        assert_eq!(clock.slot_at(0), 0);
+
+        let clock = SlotClock::from_slots_per_year(0, 0, SLOTS_PER_YEAR);
+        assert_eq!(clock.slot_at(365 * 24 * 60 * 60), SLOTS_PER_YEAR);
+    }
+
+    #[test]
+    fn refresh_reserve_interest_at_timestamp() {
+        let mut reserve = Reserve {
+            last_update: LastUpdate {
+                slot: 1000,
+                stale: false,
+            },
+            liquidity: ReserveLiquidity {
+                available_amount: 100,
+                borrowed_amount_wads: Decimal::from(100u64),
+                cumulative_borrow_rate_wads: Decimal::one(),
+                ..ReserveLiquidity::default()
+            },
+            config: ReserveConfig {
+                min_borrow_rate: 10,
+                optimal_borrow_rate: 10,
+                max_borrow_rate: 10,
+                super_max_borrow_rate: 10,
+                optimal_utilization_rate: 50,
+                max_utilization_rate: 100,
+                ..ReserveConfig::default()
+            },
+            slots_per_year: SLOTS_PER_YEAR,
+            ..Reserve::default()
+        };
+        let mut by_slot = reserve.clone();
+
+        let clock = SlotClock::from_slots_per_year(1000, 1_700_000_000, reserve.slots_per_year);
+        offchain_refresh_reserve_interest_at(&mut reserve, 1_700_000_000 + 86_400, &clock).unwrap();
+        crate::offchain_utils::offchain_refresh_reserve_interest(
+            &mut by_slot,
+            1000 + SLOTS_PER_YEAR / 365,
+        )
+        .unwrap();
+
+        assert_eq!(reserve, by_slot);
+        assert!(reserve.liquidity.borrowed_amount_wads > Decimal::from(100u64));
+    }
+}
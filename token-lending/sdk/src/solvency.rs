@@ -0,0 +1,386 @@
+//! Signed solvency attestations of a lending market.
+//!
+//! For every reserve, [compute_solvency] weighs what depositors are owed against what backs it:
+//! - liabilities are the cTokens in circulation, per the collateral mint, times the exchange rate
+//! - assets are the liquidity held by the supply token account plus what is borrowed, minus the
+//!   protocol fees not yet redeemed
+//!
+//! Token balances are read from the token accounts themselves rather than from the reserve's own
+//! accounting, and each reserve is read in the same request as its token accounts. Interest is
+//! accrued up to the slot they were read at.
+//!
+//! A [SolvencySummary] signed by its publisher becomes a [SignedSolvencySummary], which anyone
+//! can check with [verify_solvency]: the signature is verified and the summary recomputed from
+//! the verifier's own RPC. Balances move between the two, so the recomputed summary is expected
+//! to be solvent and to cover the same reserves, not to match amount for amount.
+//!
+//! The signed message is a fixed binary encoding of the summary, see [SolvencySummary::message],
+//! so the signature doesn't depend on how the summary is serialized for publishing.
+
+use crate::{
+    balance_snapshot::base58,
+    math::{Decimal, TryAdd, TryDiv, TryMul, TrySub},
+    offchain_utils::{offchain_refresh_reserve_interest, MAX_MULTIPLE_ACCOUNTS},
+    registry::get_market_reserves,
+    state::Reserve,
+};
+use serde::{Deserialize, Serialize};
+use solana_client::rpc_client::RpcClient;
+use solana_program::{
+    clock::Slot, program_error::ProgramError, program_pack::Pack, pubkey::Pubkey,
+};
+use solana_sdk::{
+    signature::Signature,
+    signer::{Signer, SignerError},
+};
+use spl_token::state::{Account as TokenAccount, Mint};
+use std::{collections::BTreeSet, error::Error};
+
+/// Prefix of the signed message, so a summary signature can't pass for anything else
+const MESSAGE_DOMAIN: &[u8] = b"solend-solvency-summary-v1";
+
+/// Accounts fetched per reserve: the reserve, its liquidity supply and collateral mint
+const ACCOUNTS_PER_RESERVE: usize = 3;
+
+/// Solvency of a reserve, in its liquidity's native units
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReserveSolvency {
+    /// Reserve address
+    #[serde(with = "base58")]
+    pub reserve: Pubkey,
+    /// Liquidity mint
+    #[serde(with = "base58")]
+    pub mint: Pubkey,
+    /// cTokens in circulation, per the collateral mint
+    pub collateral_supply: u64,
+    /// Liquidity the cTokens in circulation redeem for, rounded down
+    pub liabilities: u64,
+    /// Liquidity held by the supply token account
+    pub available_amount: u64,
+    /// Liquidity borrowed, with interest, rounded down
+    pub borrowed_amount: u64,
+    /// Protocol fees not yet redeemed, rounded down
+    pub protocol_fees: u64,
+    /// Available plus borrowed liquidity minus protocol fees, rounded down as a whole, so it can
+    /// be off by one from the rounded parts
+    pub assets: u64,
+}
+
+impl ReserveSolvency {
+    /// Whether the assets cover the liabilities
+    pub fn is_solvent(&self) -> bool {
+        self.assets >= self.liabilities
+    }
+
+    /// Assets minus liabilities
+    pub fn surplus(&self) -> i128 {
+        self.assets as i128 - self.liabilities as i128
+    }
+}
+
+/// Solvency of every reserve of a lending market
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SolvencySummary {
+    /// Lending market address
+    #[serde(with = "base58")]
+    pub lending_market: Pubkey,
+    /// Earliest slot the reserves were read at
+    pub slot: Slot,
+    /// Reserves, ordered by address
+    pub reserves: Vec<ReserveSolvency>,
+}
+
+impl SolvencySummary {
+    /// Whether every reserve is solvent
+    pub fn is_solvent(&self) -> bool {
+        self.reserves.iter().all(ReserveSolvency::is_solvent)
+    }
+
+    /// Message the summary is signed as: the domain prefix, the lending market, the slot and
+    /// the reserve count as a little endian u32, then for each reserve its address, mint and
+    /// amounts in field order. Amounts are little endian u64s.
+    pub fn message(&self) -> Vec<u8> {
+        let mut message = MESSAGE_DOMAIN.to_vec();
+        message.extend_from_slice(self.lending_market.as_ref());
+        message.extend_from_slice(&self.slot.to_le_bytes());
+        message.extend_from_slice(&(self.reserves.len() as u32).to_le_bytes());
+        for reserve in &self.reserves {
+            message.extend_from_slice(reserve.reserve.as_ref());
+            message.extend_from_slice(reserve.mint.as_ref());
+            for amount in [
+                reserve.collateral_supply,
+                reserve.liabilities,
+                reserve.available_amount,
+                reserve.borrowed_amount,
+                reserve.protocol_fees,
+                reserve.assets,
+            ] {
+                message.extend_from_slice(&amount.to_le_bytes());
+            }
+        }
+        message
+    }
+
+    /// Sign the summary with `signer`
+    pub fn sign(self, signer: &dyn Signer) -> Result<SignedSolvencySummary, SignerError> {
+        let signature = signer.try_sign_message(&self.message())?;
+        Ok(SignedSolvencySummary {
+            signer: signer.try_pubkey()?,
+            signature,
+            summary: self,
+        })
+    }
+}
+
+/// Solvency summary with the signature of its publisher
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignedSolvencySummary {
+    /// Signed summary
+    pub summary: SolvencySummary,
+    /// Publisher of the summary
+    #[serde(with = "base58")]
+    pub signer: Pubkey,
+    /// Signature of the summary message by the publisher
+    #[serde(with = "base58")]
+    pub signature: Signature,
+}
+
+impl SignedSolvencySummary {
+    /// Whether the signature is the signer's, over this summary
+    pub fn verify_signature(&self) -> bool {
+        self.signature
+            .verify(self.signer.as_ref(), &self.summary.message())
+    }
+}
+
+/// Solvency of `reserve` given the balance of its liquidity supply account and the supply of
+/// its collateral mint. The reserve should have accrued interest up to the slot the token
+/// accounts were read at.
+pub fn reserve_solvency(
+    reserve_pubkey: &Pubkey,
+    reserve: &Reserve,
+    supply_balance: u64,
+    collateral_supply: u64,
+) -> Result<ReserveSolvency, ProgramError> {
+    let liquidity = &reserve.liquidity;
+    // liabilities are scaled by the recorded exchange rate, total supply over mint supply,
+    // without going through the rounded `Rate`
+    let total_supply = liquidity.total_supply()?;
+    let liabilities = if reserve.collateral.mint_total_supply == 0 {
+        reserve
+            .collateral_exchange_rate()?
+            .decimal_collateral_to_liquidity(Decimal::from(collateral_supply))?
+    } else {
+        total_supply
+            .try_mul(collateral_supply)?
+            .try_div(reserve.collateral.mint_total_supply)?
+    };
+    let assets = Decimal::from(supply_balance)
+        .try_add(liquidity.borrowed_amount_wads)?
+        .try_sub(liquidity.accumulated_protocol_fees_wads)?;
+
+    Ok(ReserveSolvency {
+        reserve: *reserve_pubkey,
+        mint: liquidity.mint_pubkey,
+        collateral_supply,
+        liabilities: liabilities.try_floor_u64()?,
+        available_amount: supply_balance,
+        borrowed_amount: liquidity.borrowed_amount_wads.try_floor_u64()?,
+        protocol_fees: liquidity.accumulated_protocol_fees_wads.try_floor_u64()?,
+        assets: assets.try_floor_u64()?,
+    })
+}
+
+/// Compute the solvency of every reserve of `lending_market`
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(skip(client), fields(%program_id, %lending_market), err)
+)]
+pub fn compute_solvency(
+    client: &RpcClient,
+    program_id: &Pubkey,
+    lending_market: &Pubkey,
+) -> Result<SolvencySummary, Box<dyn Error>> {
+    let mut reserves = get_market_reserves(client, program_id, lending_market)?;
+    reserves.sort_by_key(|(pubkey, _)| *pubkey);
+
+    let mut slot = None;
+    let mut solvency = Vec::with_capacity(reserves.len());
+    for chunk in reserves.chunks(MAX_MULTIPLE_ACCOUNTS / ACCOUNTS_PER_RESERVE) {
+        // the reserves are fetched again with their token accounts, to read them at one slot
+        let pubkeys = chunk
+            .iter()
+            .flat_map(|(pubkey, reserve)| {
+                [
+                    *pubkey,
+                    reserve.liquidity.supply_pubkey,
+                    reserve.collateral.mint_pubkey,
+                ]
+            })
+            .collect::<Vec<_>>();
+        let response =
+            client.get_multiple_accounts_with_commitment(&pubkeys, client.commitment())?;
+        let chunk_slot = response.context.slot;
+        slot = Some(slot.map_or(chunk_slot, |slot: Slot| slot.min(chunk_slot)));
+
+        for (pubkeys, accounts) in pubkeys
+            .chunks(ACCOUNTS_PER_RESERVE)
+            .zip(response.value.chunks(ACCOUNTS_PER_RESERVE))
+        {
+            let account = |index: usize| {
+                accounts[index]
+                    .as_ref()
+                    .ok_or_else(|| format!("Account {} not found", pubkeys[index]))
+            };
+            let mut reserve = Reserve::unpack(&account(0)?.data)?;
+            let supply = account(1)?;
+            let collateral_mint = account(2)?;
+            if supply.owner != spl_token::id() || collateral_mint.owner != spl_token::id() {
+                return Err(format!(
+                    "Liquidity supply or collateral mint of reserve {} is not a token account",
+                    pubkeys[0]
+                )
+                .into());
+            }
+            offchain_refresh_reserve_interest(&mut reserve, chunk_slot)?;
+            solvency.push(reserve_solvency(
+                &pubkeys[0],
+                &reserve,
+                TokenAccount::unpack(&supply.data)?.amount,
+                Mint::unpack(&collateral_mint.data)?.supply,
+            )?);
+        }
+    }
+
+    Ok(SolvencySummary {
+        lending_market: *lending_market,
+        slot: match slot {
+            Some(slot) => slot,
+            None => client.get_slot()?,
+        },
+        reserves: solvency,
+    })
+}
+
+/// Outcome of verifying a signed solvency summary
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SolvencyVerification {
+    /// Whether the summary is signed by its signer
+    pub signature_valid: bool,
+    /// Summary recomputed from the verifier's RPC
+    pub recomputed: SolvencySummary,
+    /// Reserves in only one of the attested and the recomputed summaries
+    pub unmatched_reserves: Vec<Pubkey>,
+}
+
+impl SolvencyVerification {
+    /// Whether the signature is valid and both the attested and recomputed summaries are
+    /// solvent over the same reserves
+    pub fn is_valid(&self, signed: &SignedSolvencySummary) -> bool {
+        self.signature_valid
+            && self.unmatched_reserves.is_empty()
+            && signed.summary.is_solvent()
+            && self.recomputed.is_solvent()
+    }
+}
+
+/// Verify `signed` against the lending market as `client` sees it
+pub fn verify_solvency(
+    client: &RpcClient,
+    program_id: &Pubkey,
+    signed: &SignedSolvencySummary,
+) -> Result<SolvencyVerification, Box<dyn Error>> {
+    let recomputed = compute_solvency(client, program_id, &signed.summary.lending_market)?;
+    let attested = signed
+        .summary
+        .reserves
+        .iter()
+        .map(|reserve| reserve.reserve)
+        .collect::<BTreeSet<_>>();
+    let current = recomputed
+        .reserves
+        .iter()
+        .map(|reserve| reserve.reserve)
+        .collect::<BTreeSet<_>>();
+    Ok(SolvencyVerification {
+        signature_valid: signed.verify_signature(),
+        unmatched_reserves: attested.symmetric_difference(&current).copied().collect(),
+        recomputed,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::state::{ReserveCollateral, ReserveLiquidity};
+    use solana_sdk::signature::Keypair;
+
+    fn reserve() -> Reserve {
+        Reserve {
+            liquidity: ReserveLiquidity {
+                mint_pubkey: Pubkey::new_unique(),
+                available_amount: 600,
+                borrowed_amount_wads: Decimal::from(500u64)
+                    .try_add(Decimal::from_percent(50))
+                    .unwrap(),
+                accumulated_protocol_fees_wads: Decimal::from(100u64),
+                ..ReserveLiquidity::default()
+            },
+            collateral: ReserveCollateral {
+                mint_total_supply: 4_000,
+                ..ReserveCollateral::default()
+            },
+            ..Reserve::default()
+        }
+    }
+
+    #[test]
+    fn reserve_solvency_from_token_balances() {
+        let reserve = reserve();
+        let pubkey = Pubkey::new_unique();
+
+        // the token accounts match the reserve accounting: 1000.5 liquidity on either side
+        let solvency = reserve_solvency(&pubkey, &reserve, 600, 4_000).unwrap();
+        assert_eq!(solvency.liabilities, 1_000);
+        assert_eq!(solvency.borrowed_amount, 500);
+        assert_eq!(solvency.assets, 1_000);
+        assert!(solvency.is_solvent());
+
+        // 10 tokens left the supply account behind the reserve's back
+        let solvency = reserve_solvency(&pubkey, &reserve, 590, 4_000).unwrap();
+        assert_eq!(solvency.surplus(), -10);
+        assert!(!solvency.is_solvent());
+
+        // cTokens minted behind its back dilute every depositor
+        let solvency = reserve_solvency(&pubkey, &reserve, 600, 4_400).unwrap();
+        assert_eq!(solvency.liabilities, 1_100);
+        assert!(!solvency.is_solvent());
+    }
+
+    #[test]
+    fn signed_summary() {
+        let signer = Keypair::new();
+        let summary = SolvencySummary {
+            lending_market: Pubkey::new_unique(),
+            slot: 1_000,
+            reserves: vec![
+                reserve_solvency(&Pubkey::new_unique(), &reserve(), 600, 4_000).unwrap(),
+            ],
+        };
+        let signed = summary.sign(&signer).unwrap();
+        assert_eq!(signed.signer, signer.pubkey());
+        assert!(signed.verify_signature());
+
+        let json = serde_json::to_string(&signed).unwrap();
+        let published: SignedSolvencySummary = serde_json::from_str(&json).unwrap();
+        assert_eq!(published, signed);
+        assert!(published.verify_signature());
+
+        let mut tampered = published.clone();
+        tampered.summary.reserves[0].liabilities -= 1;
+        assert!(!tampered.verify_signature());
+        let mut tampered = published;
+        tampered.signer = Pubkey::new_unique();
+        assert!(!tampered.verify_signature());
+    }
+}
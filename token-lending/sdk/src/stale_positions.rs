@@ -0,0 +1,274 @@
+//! Report of abandoned obligations.
+//!
+//! Obligations nobody has touched in months and that hold next to nothing are worth reaching out
+//! about or cleaning up. [stale_positions] revalues every obligation in a [SolendAccounts] map at
+//! a target slot and keeps the ones that have been idle for long enough and whose deposits and
+//! borrows are both small enough, as [StalePosition]s that export to csv or json.
+//!
+//! An obligation's `last_update` slot moves whenever its owner or anyone else refreshes it, so
+//! idle here means "not refreshed", which includes not being liquidated.
+
+use crate::{
+    math::{Decimal, TryAdd},
+    offchain_utils::{
+        offchain_refresh_obligation, offchain_refresh_reserve_interest, SolendAccounts,
+    },
+    state::SLOTS_PER_YEAR,
+};
+use serde::{Serialize, Serializer};
+use solana_program::{clock::Slot, program_error::ProgramError, pubkey::Pubkey};
+use std::{error::Error, fmt::Display};
+
+/// What makes an obligation stale. All the conditions have to hold.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StaleThresholds {
+    /// Minimum number of slots since the obligation was last refreshed
+    pub min_idle_slots: u64,
+    /// Maximum market value of the deposits, in the market's quote currency
+    pub max_deposited_value: Decimal,
+    /// Maximum market value of the borrows, without borrow weights
+    pub max_borrowed_value: Decimal,
+}
+
+impl Default for StaleThresholds {
+    /// About three months idle with at most $10 deposited and $10 borrowed
+    fn default() -> Self {
+        Self {
+            min_idle_slots: SLOTS_PER_YEAR / 4,
+            max_deposited_value: Decimal::from(10u64),
+            max_borrowed_value: Decimal::from(10u64),
+        }
+    }
+}
+
+/// An obligation matching the [StaleThresholds] of a report
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct StalePosition {
+    /// Obligation account
+    #[serde(serialize_with = "display")]
+    pub obligation: Pubkey,
+    /// Lending market of the obligation
+    #[serde(serialize_with = "display")]
+    pub lending_market: Pubkey,
+    /// Wallet owning the obligation, the one to contact
+    #[serde(serialize_with = "display")]
+    pub owner: Pubkey,
+    /// Slot the obligation was last refreshed at
+    pub last_update_slot: Slot,
+    /// Slots between the last refresh and the report slot
+    pub idle_slots: u64,
+    /// Number of deposit reserves
+    pub deposits: usize,
+    /// Number of borrow reserves
+    pub borrows: usize,
+    /// Market value of the deposits at the report slot
+    pub deposited_value: Decimal,
+    /// Market value of the borrows at the report slot, without borrow weights
+    pub borrowed_value: Decimal,
+}
+
+impl StalePosition {
+    /// Header matching `to_csv_row`
+    pub const CSV_HEADER: &'static str = "obligation,lending_market,owner,last_update_slot,\
+        idle_slots,deposits,borrows,deposited_value,borrowed_value";
+
+    /// Format the position as a csv row
+    pub fn to_csv_row(&self) -> String {
+        format!(
+            "{},{},{},{},{},{},{},{},{}",
+            self.obligation,
+            self.lending_market,
+            self.owner,
+            self.last_update_slot,
+            self.idle_slots,
+            self.deposits,
+            self.borrows,
+            self.deposited_value,
+            self.borrowed_value
+        )
+    }
+}
+
+/// Obligations in `accounts` that are stale at `slot` by `thresholds`, longest idle first.
+/// Reserve interest is accrued up to `slot` and obligations are revalued at the last prices of
+/// their reserves, so `slot` can't be older than the last update of any reserve. Fails if a
+/// reserve an obligation references is missing.
+///
+/// Obligations without borrows are stale candidates too, but `get_solend_accounts_as_map`
+/// doesn't keep them; fetch with [crate::balance_snapshot::get_snapshot_accounts] instead.
+pub fn stale_positions(
+    accounts: &SolendAccounts,
+    slot: Slot,
+    thresholds: &StaleThresholds,
+) -> Result<Vec<StalePosition>, Box<dyn Error>> {
+    let mut reserves = accounts.reserves.clone();
+    for reserve in reserves.values_mut() {
+        offchain_refresh_reserve_interest(reserve, slot)?;
+    }
+
+    let mut positions = Vec::new();
+    for (pubkey, obligation) in &accounts.obligations {
+        let idle_slots = slot.saturating_sub(obligation.last_update.slot);
+        if idle_slots < thresholds.min_idle_slots {
+            continue;
+        }
+
+        // offchain_refresh_obligation panics on missing borrow reserves
+        if obligation
+            .deposits
+            .iter()
+            .map(|d| &d.deposit_reserve)
+            .chain(obligation.borrows.iter().map(|b| &b.borrow_reserve))
+            .any(|reserve| !reserves.contains_key(reserve))
+        {
+            return Err(ProgramError::InvalidArgument.into());
+        }
+
+        let mut obligation = obligation.clone();
+        offchain_refresh_obligation(&mut obligation, &reserves)?;
+
+        let borrowed_value = obligation
+            .borrows
+            .iter()
+            .try_fold(Decimal::zero(), |value, b| value.try_add(b.market_value))?;
+        if obligation.deposited_value > thresholds.max_deposited_value
+            || borrowed_value > thresholds.max_borrowed_value
+        {
+            continue;
+        }
+
+        positions.push(StalePosition {
+            obligation: *pubkey,
+            lending_market: obligation.lending_market,
+            owner: obligation.owner,
+            last_update_slot: obligation.last_update.slot,
+            idle_slots,
+            deposits: obligation.deposits.len(),
+            borrows: obligation.borrows.len(),
+            deposited_value: obligation.deposited_value,
+            borrowed_value,
+        });
+    }
+
+    positions.sort_by(|a, b| {
+        b.idle_slots
+            .cmp(&a.idle_slots)
+            .then(a.obligation.cmp(&b.obligation))
+    });
+    Ok(positions)
+}
+
+fn display<T: Display, S: Serializer>(value: &T, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(value)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        state::{LastUpdate, Obligation, ObligationCollateral, ObligationLiquidity, Reserve},
+        test_utils::reserve,
+    };
+
+    fn obligation(
+        last_update_slot: Slot,
+        reserve: Pubkey,
+        deposited: u64,
+        borrowed: u64,
+    ) -> Obligation {
+        let borrows = if borrowed > 0 {
+            vec![ObligationLiquidity {
+                borrowed_amount_wads: Decimal::from(borrowed),
+                ..ObligationLiquidity::new(reserve, Decimal::one())
+            }]
+        } else {
+            vec![]
+        };
+        Obligation {
+            last_update: LastUpdate::new(last_update_slot),
+            owner: Pubkey::new_unique(),
+            deposits: vec![ObligationCollateral {
+                deposited_amount: deposited,
+                ..ObligationCollateral::new(reserve)
+            }],
+            borrows,
+            ..Obligation::default()
+        }
+    }
+
+    #[test]
+    fn stale_positions_by_thresholds() {
+        let reserve_pubkey = Pubkey::new_unique();
+        let mut accounts = SolendAccounts::default();
+        accounts.reserves.insert(
+            reserve_pubkey,
+            Reserve {
+                last_update: LastUpdate::new(1_000),
+                ..reserve(1_000_000, 1)
+            },
+        );
+
+        let idle = Pubkey::new_unique();
+        let idler = Pubkey::new_unique();
+        let active = Pubkey::new_unique();
+        let whale = Pubkey::new_unique();
+        let borrower = Pubkey::new_unique();
+        accounts
+            .obligations
+            .insert(idle, obligation(500, reserve_pubkey, 5, 0));
+        accounts
+            .obligations
+            .insert(idler, obligation(100, reserve_pubkey, 10, 2));
+        accounts
+            .obligations
+            .insert(active, obligation(950, reserve_pubkey, 5, 0));
+        accounts
+            .obligations
+            .insert(whale, obligation(100, reserve_pubkey, 11, 0));
+        accounts
+            .obligations
+            .insert(borrower, obligation(100, reserve_pubkey, 10, 11));
+
+        let thresholds = StaleThresholds {
+            min_idle_slots: 100,
+            ..StaleThresholds::default()
+        };
+        let positions = stale_positions(&accounts, 1_000, &thresholds).unwrap();
+        assert_eq!(
+            positions
+                .iter()
+                .map(|p| (p.obligation, p.idle_slots))
+                .collect::<Vec<_>>(),
+            vec![(idler, 900), (idle, 500)]
+        );
+        assert_eq!(positions[0].deposited_value, Decimal::from(10u64));
+        assert_eq!(positions[0].borrowed_value, Decimal::from(2u64));
+        assert_eq!(positions[0].owner, accounts.obligations[&idler].owner,);
+        assert_eq!(
+            positions[0].to_csv_row().split(',').count(),
+            StalePosition::CSV_HEADER.split(',').count()
+        );
+
+        let json = serde_json::to_value(&positions[0]).unwrap();
+        assert_eq!(
+            json["owner"],
+            accounts.obligations[&idler].owner.to_string()
+        );
+        assert_eq!(json["deposited_value"], "10.000000000000000000");
+    }
+
+    #[test]
+    fn stale_positions_missing_reserve() {
+        let mut accounts = SolendAccounts::default();
+        accounts.obligations.insert(
+            Pubkey::new_unique(),
+            obligation(0, Pubkey::new_unique(), 1, 1),
+        );
+
+        let thresholds = StaleThresholds {
+            min_idle_slots: 0,
+            ..StaleThresholds::default()
+        };
+        assert!(stale_positions(&accounts, 1_000, &thresholds).is_err());
+    }
+}
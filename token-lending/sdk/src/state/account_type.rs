@@ -0,0 +1,298 @@
+use super::*;
+use solana_program::{program_pack::Pack, pubkey::Pubkey};
+
+/// Types of accounts owned by the lending program
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum AccountType {
+    /// [LendingMarket]
+    LendingMarket,
+    /// [LendingMarketMetadata]
+    LendingMarketMetadata,
+    /// [Reserve]
+    Reserve,
+    /// [Obligation]
+    Obligation,
+    /// [BorrowAllowances]
+    BorrowAllowances,
+    /// [LiquidationCircuitBreaker]
+    LiquidationCircuitBreaker,
+    /// [LiquidationStats]
+    LiquidationStats,
+    /// [ObligationLimits]
+    ObligationLimits,
+    /// [ConfigTimelock]
+    ConfigTimelock,
+    /// [PendingConfig]
+    PendingConfig,
+    /// [FeeRebates]
+    FeeRebates,
+    /// [FeeRedemptionHook]
+    FeeRedemptionHook,
+    /// [ObligationBorrowCap]
+    ObligationBorrowCap,
+    /// [MarketPolicy]
+    MarketPolicy,
+    /// [ReserveAccrualHistory]
+    ReserveAccrualHistory,
+    /// [DeleverageConfig]
+    DeleverageConfig,
+    /// [DeleverageCredit]
+    DeleverageCredit,
+    /// [FlashLoanConfig]
+    FlashLoanConfig,
+    /// [BorrowGraceConfig]
+    BorrowGraceConfig,
+    /// [CrossMarketSources]
+    CrossMarketSources,
+}
+
+/// Figure out which type of lending program account some account data holds.
+///
+/// The data length narrows it down to one type, and the account must then unpack as that type
+/// with a known, initialized version and the pubkeys tying it to the rest of the market set.
+/// Returns None for uninitialized, truncated or corrupted data. Lending market metadata has no
+/// version byte, so it is only matched by length.
+pub fn classify_account(data: &[u8]) -> Option<AccountType> {
+    match data.len() {
+        LendingMarket::LEN => LendingMarket::unpack(data)
+            .ok()
+            .filter(|lending_market| lending_market.owner != Pubkey::default())
+            .map(|_| AccountType::LendingMarket),
+        Reserve::LEN => Reserve::unpack(data)
+            .ok()
+            .filter(|reserve| reserve.lending_market != Pubkey::default())
+            .map(|_| AccountType::Reserve),
+        Obligation::LEN => Obligation::unpack(data)
+            .ok()
+            .filter(|obligation| {
+                obligation.lending_market != Pubkey::default()
+                    && obligation.owner != Pubkey::default()
+            })
+            .map(|_| AccountType::Obligation),
+        BorrowAllowances::LEN => BorrowAllowances::unpack(data)
+            .ok()
+            .filter(|borrow_allowances| borrow_allowances.obligation != Pubkey::default())
+            .map(|_| AccountType::BorrowAllowances),
+        LiquidationCircuitBreaker::LEN => LiquidationCircuitBreaker::unpack(data)
+            .ok()
+            .filter(|breaker| breaker.lending_market != Pubkey::default())
+            .map(|_| AccountType::LiquidationCircuitBreaker),
+        LiquidationStats::LEN => LiquidationStats::unpack(data)
+            .ok()
+            .filter(|stats| stats.lending_market != Pubkey::default())
+            .map(|_| AccountType::LiquidationStats),
+        ObligationLimits::LEN => ObligationLimits::unpack(data)
+            .ok()
+            .filter(|limits| limits.lending_market != Pubkey::default())
+            .map(|_| AccountType::ObligationLimits),
+        ConfigTimelock::LEN => ConfigTimelock::unpack(data)
+            .ok()
+            .filter(|timelock| timelock.lending_market != Pubkey::default())
+            .map(|_| AccountType::ConfigTimelock),
+        PendingConfig::LEN => PendingConfig::unpack(data)
+            .ok()
+            .filter(|pending_config| {
+                pending_config.lending_market != Pubkey::default()
+                    && pending_config.target != Pubkey::default()
+            })
+            .map(|_| AccountType::PendingConfig),
+        FeeRebates::LEN => FeeRebates::unpack(data)
+            .ok()
+            .filter(|fee_rebates| fee_rebates.lending_market != Pubkey::default())
+            .map(|_| AccountType::FeeRebates),
+        FeeRedemptionHook::LEN => FeeRedemptionHook::unpack(data)
+            .ok()
+            .filter(|hook| hook.reserve != Pubkey::default())
+            .map(|_| AccountType::FeeRedemptionHook),
+        ObligationBorrowCap::LEN => ObligationBorrowCap::unpack(data)
+            .ok()
+            .filter(|borrow_cap| borrow_cap.obligation != Pubkey::default())
+            .map(|_| AccountType::ObligationBorrowCap),
+        MarketPolicy::LEN => MarketPolicy::unpack(data)
+            .ok()
+            .filter(|market_policy| market_policy.lending_market != Pubkey::default())
+            .map(|_| AccountType::MarketPolicy),
+        ReserveAccrualHistory::LEN => ReserveAccrualHistory::unpack(data)
+            .ok()
+            .filter(|history| history.reserve != Pubkey::default())
+            .map(|_| AccountType::ReserveAccrualHistory),
+        DeleverageConfig::LEN => DeleverageConfig::unpack(data)
+            .ok()
+            .filter(|config| config.lending_market != Pubkey::default())
+            .map(|_| AccountType::DeleverageConfig),
+        DeleverageCredit::LEN => DeleverageCredit::unpack(data)
+            .ok()
+            .filter(|credit| credit.obligation != Pubkey::default())
+            .map(|_| AccountType::DeleverageCredit),
+        FlashLoanConfig::LEN => FlashLoanConfig::unpack(data)
+            .ok()
+            .filter(|config| config.reserve != Pubkey::default())
+            .map(|_| AccountType::FlashLoanConfig),
+        BorrowGraceConfig::LEN => BorrowGraceConfig::unpack(data)
+            .ok()
+            .filter(|config| config.reserve != Pubkey::default())
+            .map(|_| AccountType::BorrowGraceConfig),
+        CrossMarketSources::LEN => CrossMarketSources::unpack(data)
+            .ok()
+            .filter(|sources| sources.lending_market != Pubkey::default())
+            .map(|_| AccountType::CrossMarketSources),
+        len if len == std::mem::size_of::<LendingMarketMetadata>() => {
+            Some(AccountType::LendingMarketMetadata)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn packed<T: Pack>(account: T) -> Vec<u8> {
+        let mut data = vec![0u8; T::LEN];
+        T::pack(account, &mut data).unwrap();
+        data
+    }
+
+    fn accounts() -> Vec<(AccountType, Vec<u8>)> {
+        let lending_market = Pubkey::new_unique();
+        vec![
+            (
+                AccountType::LendingMarket,
+                packed(LendingMarket {
+                    version: PROGRAM_VERSION,
+                    owner: Pubkey::new_unique(),
+                    ..LendingMarket::default()
+                }),
+            ),
+            (
+                AccountType::Reserve,
+                packed(Reserve {
+                    version: PROGRAM_VERSION,
+                    lending_market,
+                    ..Reserve::default()
+                }),
+            ),
+            (
+                AccountType::Obligation,
+                packed(Obligation {
+                    version: PROGRAM_VERSION,
+                    lending_market,
+                    owner: Pubkey::new_unique(),
+                    ..Obligation::default()
+                }),
+            ),
+            (
+                AccountType::BorrowAllowances,
+                packed(BorrowAllowances::new(Pubkey::new_unique(), 255)),
+            ),
+            (
+                AccountType::LiquidationCircuitBreaker,
+                packed(LiquidationCircuitBreaker::new(lending_market, 255)),
+            ),
+            (
+                AccountType::LiquidationStats,
+                packed(LiquidationStats::new(lending_market, 255)),
+            ),
+            (
+                AccountType::ObligationLimits,
+                packed(ObligationLimits::new(lending_market, 255)),
+            ),
+            (
+                AccountType::ConfigTimelock,
+                packed(ConfigTimelock::new(lending_market, 255, 100)),
+            ),
+            (
+                AccountType::PendingConfig,
+                packed(PendingConfig {
+                    version: PROGRAM_VERSION,
+                    lending_market,
+                    target: Pubkey::new_unique(),
+                    ..PendingConfig::default()
+                }),
+            ),
+            (
+                AccountType::FeeRebates,
+                packed(FeeRebates::new(lending_market, 255)),
+            ),
+            (
+                AccountType::FeeRedemptionHook,
+                packed(FeeRedemptionHook::new(Pubkey::new_unique(), 255)),
+            ),
+            (
+                AccountType::ObligationBorrowCap,
+                packed(ObligationBorrowCap::new(Pubkey::new_unique(), 255, 10_000)),
+            ),
+            (
+                AccountType::MarketPolicy,
+                packed(MarketPolicy::new(lending_market, 255)),
+            ),
+            (
+                AccountType::ReserveAccrualHistory,
+                packed(ReserveAccrualHistory::new(
+                    Pubkey::new_unique(),
+                    255,
+                    216_000,
+                )),
+            ),
+            (
+                AccountType::DeleverageConfig,
+                packed(DeleverageConfig::new(lending_market, 255, 10_000)),
+            ),
+            (
+                AccountType::DeleverageCredit,
+                packed(DeleverageCredit::new(Pubkey::new_unique(), 255)),
+            ),
+            (
+                AccountType::FlashLoanConfig,
+                packed(FlashLoanConfig::new(Pubkey::new_unique(), 255)),
+            ),
+            (
+                AccountType::BorrowGraceConfig,
+                packed(BorrowGraceConfig::new(Pubkey::new_unique(), 255)),
+            ),
+            (
+                AccountType::CrossMarketSources,
+                packed(CrossMarketSources::new(lending_market, 255)),
+            ),
+        ]
+    }
+
+    #[test]
+    fn classify_accounts() {
+        for (account_type, data) in accounts() {
+            assert_eq!(classify_account(&data), Some(account_type));
+        }
+
+        let metadata = vec![0u8; std::mem::size_of::<LendingMarketMetadata>()];
+        assert_eq!(
+            classify_account(&metadata),
+            Some(AccountType::LendingMarketMetadata)
+        );
+    }
+
+    #[test]
+    fn classify_truncated_or_corrupted_accounts() {
+        assert_eq!(classify_account(&[]), None);
+
+        for (account_type, data) in accounts() {
+            // truncated
+            assert_eq!(classify_account(&data[..data.len() - 1]), None);
+
+            // uninitialized and unknown versions
+            for version in [UNINITIALIZED_VERSION, PROGRAM_VERSION + 1] {
+                let mut corrupted = data.clone();
+                corrupted[0] = version;
+                assert_eq!(classify_account(&corrupted), None, "{:?}", account_type);
+            }
+
+            // zeroed out
+            assert_eq!(classify_account(&vec![0u8; data.len()]), None);
+        }
+
+        // more borrows than an obligation can hold. the lengths are at byte 202 and 203
+        let (_, mut obligation) = accounts().remove(2);
+        obligation[202] = 0;
+        obligation[203] = MAX_OBLIGATION_RESERVES as u8;
+        assert_eq!(classify_account(&obligation), None);
+    }
+}
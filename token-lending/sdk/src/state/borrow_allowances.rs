@@ -0,0 +1,334 @@
+use super::*;
+use crate::error::LendingError;
+use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
+use solana_program::{
+    msg,
+    program_error::ProgramError,
+    program_pack::{IsInitialized, Pack, Sealed},
+    pubkey::{Pubkey, PUBKEY_BYTES},
+};
+use std::convert::TryFrom;
+
+/// Max number of delegates that can hold a borrow allowance on a single obligation
+pub const MAX_BORROW_ALLOWANCES: usize = 5;
+
+/// Seed used to derive the borrow allowances address of an obligation
+pub const BORROW_ALLOWANCES_SEED: &[u8] = b"BorrowAllowances";
+
+/// Borrow allowances granted by an obligation owner to delegates. Lives in a PDA derived from
+/// [obligation, BORROW_ALLOWANCES_SEED] since the obligation account has no free space left.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BorrowAllowances {
+    /// Version of the struct
+    pub version: u8,
+    /// Bump seed for the derived address
+    pub bump_seed: u8,
+    /// Obligation the allowances are drawn against
+    pub obligation: Pubkey,
+    /// Allowances, unique by (delegate, borrow reserve)
+    pub allowances: Vec<BorrowAllowance>,
+}
+
+/// Amount of liquidity a delegate may borrow from a reserve against an obligation
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BorrowAllowance {
+    /// Address allowed to borrow on behalf of the obligation owner
+    pub delegate: Pubkey,
+    /// Reserve the delegate may borrow from
+    pub borrow_reserve: Pubkey,
+    /// Remaining amount of liquidity, including borrow fees, the delegate may borrow
+    pub amount: u64,
+}
+
+impl BorrowAllowances {
+    /// Create new borrow allowances for an obligation
+    pub fn new(obligation: Pubkey, bump_seed: u8) -> Self {
+        let mut borrow_allowances = Self::default();
+        Self::init(&mut borrow_allowances, obligation, bump_seed);
+        borrow_allowances
+    }
+
+    /// Initialize borrow allowances for an obligation
+    pub fn init(&mut self, obligation: Pubkey, bump_seed: u8) {
+        self.version = PROGRAM_VERSION;
+        self.bump_seed = bump_seed;
+        self.obligation = obligation;
+        self.allowances = vec![];
+    }
+
+    /// Find the allowance of a delegate on a borrow reserve
+    pub fn find_allowance(
+        &self,
+        delegate: Pubkey,
+        borrow_reserve: Pubkey,
+    ) -> Option<&BorrowAllowance> {
+        self._find_allowance_index(delegate, borrow_reserve)
+            .map(|index| &self.allowances[index])
+    }
+
+    /// Set the allowance of a delegate on a borrow reserve, replacing any existing allowance
+    pub fn approve(
+        &mut self,
+        delegate: Pubkey,
+        borrow_reserve: Pubkey,
+        amount: u64,
+    ) -> Result<(), ProgramError> {
+        if amount == 0 {
+            self.revoke(delegate, borrow_reserve);
+            return Ok(());
+        }
+        if let Some(index) = self._find_allowance_index(delegate, borrow_reserve) {
+            self.allowances[index].amount = amount;
+            return Ok(());
+        }
+        if self.allowances.len() >= MAX_BORROW_ALLOWANCES {
+            msg!(
+                "Obligation cannot have more than {} borrow allowances",
+                MAX_BORROW_ALLOWANCES
+            );
+            return Err(LendingError::BorrowAllowanceLimit.into());
+        }
+        self.allowances.push(BorrowAllowance {
+            delegate,
+            borrow_reserve,
+            amount,
+        });
+        Ok(())
+    }
+
+    /// Remove the allowance of a delegate on a borrow reserve, if any
+    pub fn revoke(&mut self, delegate: Pubkey, borrow_reserve: Pubkey) {
+        if let Some(index) = self._find_allowance_index(delegate, borrow_reserve) {
+            self.allowances.remove(index);
+        }
+    }
+
+    /// Consume part of a delegate's allowance. Exhausted allowances are removed.
+    pub fn use_allowance(
+        &mut self,
+        delegate: Pubkey,
+        borrow_reserve: Pubkey,
+        amount: u64,
+    ) -> Result<(), ProgramError> {
+        let index = self
+            ._find_allowance_index(delegate, borrow_reserve)
+            .ok_or_else(|| {
+                msg!("Delegate has no borrow allowance on this reserve");
+                LendingError::InsufficientBorrowAllowance
+            })?;
+        let allowance = &mut self.allowances[index];
+        allowance.amount = allowance.amount.checked_sub(amount).ok_or_else(|| {
+            msg!(
+                "Borrow amount {} exceeds the remaining allowance {}",
+                amount,
+                allowance.amount
+            );
+            LendingError::InsufficientBorrowAllowance
+        })?;
+        if allowance.amount == 0 {
+            self.allowances.remove(index);
+        }
+        Ok(())
+    }
+
+    fn _find_allowance_index(&self, delegate: Pubkey, borrow_reserve: Pubkey) -> Option<usize> {
+        self.allowances.iter().position(|allowance| {
+            allowance.delegate == delegate && allowance.borrow_reserve == borrow_reserve
+        })
+    }
+}
+
+impl Sealed for BorrowAllowances {}
+impl IsInitialized for BorrowAllowances {
+    fn is_initialized(&self) -> bool {
+        self.version != UNINITIALIZED_VERSION
+    }
+}
+
+const BORROW_ALLOWANCE_LEN: usize = 72; // 32 + 32 + 8
+const BORROW_ALLOWANCES_LEN: usize = 459; // 1 + 1 + 32 + 1 + (72 * 5) + 64
+impl Pack for BorrowAllowances {
+    const LEN: usize = BORROW_ALLOWANCES_LEN;
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let output = array_mut_ref![dst, 0, BORROW_ALLOWANCES_LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (version, bump_seed, obligation, allowances_len, data_flat, _padding) = mut_array_refs![
+            output,
+            1,
+            1,
+            PUBKEY_BYTES,
+            1,
+            BORROW_ALLOWANCE_LEN * MAX_BORROW_ALLOWANCES,
+            64
+        ];
+
+        *version = self.version.to_le_bytes();
+        *bump_seed = self.bump_seed.to_le_bytes();
+        obligation.copy_from_slice(self.obligation.as_ref());
+        *allowances_len = u8::try_from(self.allowances.len()).unwrap().to_le_bytes();
+
+        let mut offset = 0;
+        for allowance in &self.allowances {
+            let allowance_flat = array_mut_ref![data_flat, offset, BORROW_ALLOWANCE_LEN];
+            #[allow(clippy::ptr_offset_with_cast)]
+            let (delegate, borrow_reserve, amount) =
+                mut_array_refs![allowance_flat, PUBKEY_BYTES, PUBKEY_BYTES, 8];
+            delegate.copy_from_slice(allowance.delegate.as_ref());
+            borrow_reserve.copy_from_slice(allowance.borrow_reserve.as_ref());
+            *amount = allowance.amount.to_le_bytes();
+            offset += BORROW_ALLOWANCE_LEN;
+        }
+    }
+
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![src, 0, BORROW_ALLOWANCES_LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (version, bump_seed, obligation, allowances_len, data_flat, _padding) = array_refs![
+            input,
+            1,
+            1,
+            PUBKEY_BYTES,
+            1,
+            BORROW_ALLOWANCE_LEN * MAX_BORROW_ALLOWANCES,
+            64
+        ];
+
+        let version = u8::from_le_bytes(*version);
+        if version > PROGRAM_VERSION {
+            msg!("Borrow allowances version does not match lending program version");
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        let allowances_len = u8::from_le_bytes(*allowances_len) as usize;
+        if allowances_len > MAX_BORROW_ALLOWANCES {
+            msg!("Borrow allowances length is invalid");
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        let mut allowances = Vec::with_capacity(allowances_len);
+        let mut offset = 0;
+        for _ in 0..allowances_len {
+            let allowance_flat = array_ref![data_flat, offset, BORROW_ALLOWANCE_LEN];
+            #[allow(clippy::ptr_offset_with_cast)]
+            let (delegate, borrow_reserve, amount) =
+                array_refs![allowance_flat, PUBKEY_BYTES, PUBKEY_BYTES, 8];
+            allowances.push(BorrowAllowance {
+                delegate: Pubkey::new_from_array(*delegate),
+                borrow_reserve: Pubkey::new_from_array(*borrow_reserve),
+                amount: u64::from_le_bytes(*amount),
+            });
+            offset += BORROW_ALLOWANCE_LEN;
+        }
+
+        Ok(Self {
+            version,
+            bump_seed: u8::from_le_bytes(*bump_seed),
+            obligation: Pubkey::new_from_array(*obligation),
+            allowances,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn pack_and_unpack_borrow_allowances() {
+        let mut rng = rand::thread_rng();
+        for _ in 0..100 {
+            let borrow_allowances = BorrowAllowances {
+                version: PROGRAM_VERSION,
+                bump_seed: rng.gen(),
+                obligation: Pubkey::new_unique(),
+                allowances: (0..rng.gen_range(0..=MAX_BORROW_ALLOWANCES))
+                    .map(|_| BorrowAllowance {
+                        delegate: Pubkey::new_unique(),
+                        borrow_reserve: Pubkey::new_unique(),
+                        amount: rng.gen(),
+                    })
+                    .collect(),
+            };
+
+            let mut packed = [0u8; BorrowAllowances::LEN];
+            BorrowAllowances::pack(borrow_allowances.clone(), &mut packed).unwrap();
+            let unpacked = BorrowAllowances::unpack(&packed).unwrap();
+            assert_eq!(borrow_allowances, unpacked);
+        }
+    }
+
+    #[test]
+    fn approve_and_use_allowance() {
+        let delegate = Pubkey::new_unique();
+        let reserve = Pubkey::new_unique();
+        let mut borrow_allowances = BorrowAllowances::new(Pubkey::new_unique(), 255);
+
+        borrow_allowances.approve(delegate, reserve, 100).unwrap();
+        borrow_allowances.approve(delegate, reserve, 50).unwrap();
+        assert_eq!(borrow_allowances.allowances.len(), 1);
+        assert_eq!(
+            borrow_allowances
+                .find_allowance(delegate, reserve)
+                .unwrap()
+                .amount,
+            50
+        );
+
+        assert_eq!(
+            borrow_allowances.use_allowance(delegate, reserve, 51),
+            Err(LendingError::InsufficientBorrowAllowance.into())
+        );
+        assert_eq!(
+            borrow_allowances.use_allowance(delegate, Pubkey::new_unique(), 1),
+            Err(LendingError::InsufficientBorrowAllowance.into())
+        );
+
+        borrow_allowances
+            .use_allowance(delegate, reserve, 20)
+            .unwrap();
+        assert_eq!(
+            borrow_allowances
+                .find_allowance(delegate, reserve)
+                .unwrap()
+                .amount,
+            30
+        );
+
+        borrow_allowances
+            .use_allowance(delegate, reserve, 30)
+            .unwrap();
+        assert!(borrow_allowances.allowances.is_empty());
+    }
+
+    #[test]
+    fn approve_allowance_limit() {
+        let mut borrow_allowances = BorrowAllowances::new(Pubkey::new_unique(), 255);
+        let reserve = Pubkey::new_unique();
+        for _ in 0..MAX_BORROW_ALLOWANCES {
+            borrow_allowances
+                .approve(Pubkey::new_unique(), reserve, 1)
+                .unwrap();
+        }
+
+        assert_eq!(
+            borrow_allowances.approve(Pubkey::new_unique(), reserve, 1),
+            Err(LendingError::BorrowAllowanceLimit.into())
+        );
+
+        // revoking frees up a slot
+        let delegate = borrow_allowances.allowances[0].delegate;
+        borrow_allowances.revoke(delegate, reserve);
+        borrow_allowances
+            .approve(Pubkey::new_unique(), reserve, 1)
+            .unwrap();
+
+        // approving zero revokes
+        let delegate = borrow_allowances.allowances[0].delegate;
+        borrow_allowances.approve(delegate, reserve, 0).unwrap();
+        assert!(borrow_allowances
+            .find_allowance(delegate, reserve)
+            .is_none());
+    }
+}
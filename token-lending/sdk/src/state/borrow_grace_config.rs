@@ -0,0 +1,127 @@
+use super::*;
+use crate::error::LendingError;
+use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
+use solana_program::{
+    msg,
+    program_error::ProgramError,
+    program_pack::{IsInitialized, Pack, Sealed},
+    pubkey::{Pubkey, PUBKEY_BYTES},
+};
+
+/// Seed used to derive the borrow grace config address of a reserve
+pub const BORROW_GRACE_CONFIG_SEED: &[u8] = b"BorrowGraceConfig";
+
+/// Interest-free grace period of new borrows from a reserve. Lives in a PDA derived from
+/// [reserve, BORROW_GRACE_CONFIG_SEED], configured by the lending market owner. A borrow opened
+/// while the reserve has a grace period has the interest it accrues over its first `grace_slots`
+/// slots waived out of the reserve's protocol fees, as far as they go. Reserves without one, or
+/// with zero grace slots, have no grace period.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BorrowGraceConfig {
+    /// Version of the struct
+    pub version: u8,
+    /// Bump seed for the derived address
+    pub bump_seed: u8,
+    /// Reserve the config applies to
+    pub reserve: Pubkey,
+    /// Number of slots a new borrow has its interest waived for
+    pub grace_slots: u64,
+}
+
+impl BorrowGraceConfig {
+    /// Create a new borrow grace config for a reserve, without a grace period
+    pub fn new(reserve: Pubkey, bump_seed: u8) -> Self {
+        Self {
+            version: PROGRAM_VERSION,
+            bump_seed,
+            reserve,
+            grace_slots: 0,
+        }
+    }
+}
+
+/// validates borrow grace configs
+pub fn validate_borrow_grace_config(
+    grace_slots: u64,
+    slots_per_year: u64,
+) -> Result<(), ProgramError> {
+    if grace_slots > slots_per_year {
+        msg!("Grace slots must be at most a year of slots");
+        return Err(LendingError::InvalidConfig.into());
+    }
+    Ok(())
+}
+
+impl Sealed for BorrowGraceConfig {}
+impl IsInitialized for BorrowGraceConfig {
+    fn is_initialized(&self) -> bool {
+        self.version != UNINITIALIZED_VERSION
+    }
+}
+
+const BORROW_GRACE_CONFIG_LEN: usize = 154; // 1 + 1 + 32 + 8 + 112
+impl Pack for BorrowGraceConfig {
+    const LEN: usize = BORROW_GRACE_CONFIG_LEN;
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let output = array_mut_ref![dst, 0, BORROW_GRACE_CONFIG_LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (version, bump_seed, reserve, grace_slots, _padding) =
+            mut_array_refs![output, 1, 1, PUBKEY_BYTES, 8, 112];
+
+        *version = self.version.to_le_bytes();
+        *bump_seed = self.bump_seed.to_le_bytes();
+        reserve.copy_from_slice(self.reserve.as_ref());
+        *grace_slots = self.grace_slots.to_le_bytes();
+    }
+
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![src, 0, BORROW_GRACE_CONFIG_LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (version, bump_seed, reserve, grace_slots, _padding) =
+            array_refs![input, 1, 1, PUBKEY_BYTES, 8, 112];
+
+        let version = u8::from_le_bytes(*version);
+        if version > PROGRAM_VERSION {
+            msg!("Borrow grace config version does not match lending program version");
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        Ok(Self {
+            version,
+            bump_seed: u8::from_le_bytes(*bump_seed),
+            reserve: Pubkey::new_from_array(*reserve),
+            grace_slots: u64::from_le_bytes(*grace_slots),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn pack_and_unpack_borrow_grace_config() {
+        let mut rng = rand::thread_rng();
+        let config = BorrowGraceConfig {
+            grace_slots: rng.gen(),
+            ..BorrowGraceConfig::new(Pubkey::new_unique(), rng.gen())
+        };
+
+        let mut packed = [0u8; BorrowGraceConfig::LEN];
+        BorrowGraceConfig::pack(config.clone(), &mut packed).unwrap();
+        let unpacked = BorrowGraceConfig::unpack(&packed).unwrap();
+        assert_eq!(config, unpacked);
+    }
+
+    #[test]
+    fn validate_borrow_grace_configs() {
+        assert_eq!(validate_borrow_grace_config(0, 63_072_000), Ok(()));
+        assert_eq!(validate_borrow_grace_config(63_072_000, 63_072_000), Ok(()));
+        assert_eq!(
+            validate_borrow_grace_config(63_072_001, 63_072_000),
+            Err(LendingError::InvalidConfig.into())
+        );
+    }
+}
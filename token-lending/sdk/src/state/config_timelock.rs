@@ -0,0 +1,111 @@
+use super::*;
+use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
+use solana_program::{
+    clock::Slot,
+    msg,
+    program_error::ProgramError,
+    program_pack::{IsInitialized, Pack, Sealed},
+    pubkey::{Pubkey, PUBKEY_BYTES},
+};
+
+/// Seed used to derive the config timelock address of a lending market
+pub const CONFIG_TIMELOCK_SEED: &[u8] = b"ConfigTimelock";
+
+/// Delay between the lending market owner submitting a reserve or lending market config change
+/// and the change taking effect. Changes are staged in a [PendingConfig] in the meantime, so
+/// users get a chance to react before they apply. Lives in a PDA derived from
+/// [lending market, CONFIG_TIMELOCK_SEED]; markets without one apply config changes immediately.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ConfigTimelock {
+    /// Version of the struct
+    pub version: u8,
+    /// Bump seed for the derived address
+    pub bump_seed: u8,
+    /// Lending market the timelock applies to
+    pub lending_market: Pubkey,
+    /// Number of slots config changes are staged for before they can be activated
+    pub delay_slots: u64,
+}
+
+impl ConfigTimelock {
+    /// Create a new config timelock for a lending market
+    pub fn new(lending_market: Pubkey, bump_seed: u8, delay_slots: u64) -> Self {
+        Self {
+            version: PROGRAM_VERSION,
+            bump_seed,
+            lending_market,
+            delay_slots,
+        }
+    }
+
+    /// Whether config changes have to be staged
+    pub fn is_active(&self) -> bool {
+        self.delay_slots > 0
+    }
+
+    /// Slot a config change submitted at `slot` can be activated at
+    pub fn activation_slot(&self, slot: Slot) -> Slot {
+        slot.saturating_add(self.delay_slots)
+    }
+}
+
+impl Sealed for ConfigTimelock {}
+impl IsInitialized for ConfigTimelock {
+    fn is_initialized(&self) -> bool {
+        self.version != UNINITIALIZED_VERSION
+    }
+}
+
+const CONFIG_TIMELOCK_LEN: usize = 106; // 1 + 1 + 32 + 8 + 64
+impl Pack for ConfigTimelock {
+    const LEN: usize = CONFIG_TIMELOCK_LEN;
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let output = array_mut_ref![dst, 0, CONFIG_TIMELOCK_LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (version, bump_seed, lending_market, delay_slots, _padding) =
+            mut_array_refs![output, 1, 1, PUBKEY_BYTES, 8, 64];
+
+        *version = self.version.to_le_bytes();
+        *bump_seed = self.bump_seed.to_le_bytes();
+        lending_market.copy_from_slice(self.lending_market.as_ref());
+        *delay_slots = self.delay_slots.to_le_bytes();
+    }
+
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![src, 0, CONFIG_TIMELOCK_LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (version, bump_seed, lending_market, delay_slots, _padding) =
+            array_refs![input, 1, 1, PUBKEY_BYTES, 8, 64];
+
+        let version = u8::from_le_bytes(*version);
+        if version > PROGRAM_VERSION {
+            msg!("Config timelock version does not match lending program version");
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        Ok(Self {
+            version,
+            bump_seed: u8::from_le_bytes(*bump_seed),
+            lending_market: Pubkey::new_from_array(*lending_market),
+            delay_slots: u64::from_le_bytes(*delay_slots),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn pack_and_unpack_config_timelock() {
+        let mut rng = rand::thread_rng();
+        let timelock = ConfigTimelock::new(Pubkey::new_unique(), rng.gen(), rng.gen());
+
+        let mut packed = [0u8; ConfigTimelock::LEN];
+        ConfigTimelock::pack(timelock.clone(), &mut packed).unwrap();
+        let unpacked = ConfigTimelock::unpack(&packed).unwrap();
+        assert_eq!(timelock, unpacked);
+    }
+}
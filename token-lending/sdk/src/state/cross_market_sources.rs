@@ -0,0 +1,220 @@
+use super::*;
+use crate::error::LendingError;
+use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
+use solana_program::{
+    entrypoint::ProgramResult,
+    msg,
+    program_error::ProgramError,
+    program_pack::{IsInitialized, Pack, Sealed},
+    pubkey::{Pubkey, PUBKEY_BYTES},
+};
+use std::convert::TryFrom;
+
+/// Max number of reserves of other lending markets a lending market can allow as sources
+pub const MAX_CROSS_MARKET_SOURCES: usize = 8;
+
+/// Seed used to derive the cross market sources address of a lending market
+pub const CROSS_MARKET_SOURCES_SEED: &[u8] = b"CrossMarketSources";
+
+/// Reserves of other lending markets whose cTokens a lending market accepts as the liquidity of
+/// its own reserves, priced from the source reserve. Lives in a PDA derived from
+/// [lending market, CROSS_MARKET_SOURCES_SEED] since the lending market account has no free space
+/// left. A market without one, or with an empty list, accepts no cross market reserves.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CrossMarketSources {
+    /// Version of the struct
+    pub version: u8,
+    /// Bump seed for the derived address
+    pub bump_seed: u8,
+    /// Lending market the sources are allowed in
+    pub lending_market: Pubkey,
+    /// Allowed source reserves, unique
+    pub sources: Vec<Pubkey>,
+}
+
+impl CrossMarketSources {
+    /// Create new cross market sources for a lending market
+    pub fn new(lending_market: Pubkey, bump_seed: u8) -> Self {
+        let mut cross_market_sources = Self::default();
+        Self::init(&mut cross_market_sources, lending_market, bump_seed);
+        cross_market_sources
+    }
+
+    /// Initialize cross market sources. No source is allowed to start with.
+    pub fn init(&mut self, lending_market: Pubkey, bump_seed: u8) {
+        self.version = PROGRAM_VERSION;
+        self.bump_seed = bump_seed;
+        self.lending_market = lending_market;
+        self.sources = vec![];
+    }
+
+    /// Whether a source reserve is allowed
+    pub fn is_allowed(&self, source_reserve: &Pubkey) -> bool {
+        self.sources.contains(source_reserve)
+    }
+
+    /// Allow or disallow a source reserve
+    pub fn set_source(&mut self, source_reserve: Pubkey, allowed: bool) -> ProgramResult {
+        let index = self
+            .sources
+            .iter()
+            .position(|source| *source == source_reserve);
+        match (index, allowed) {
+            (Some(index), false) => {
+                self.sources.remove(index);
+            }
+            (None, true) => {
+                if self.sources.len() >= MAX_CROSS_MARKET_SOURCES {
+                    msg!(
+                        "Lending market cannot have more than {} cross market sources",
+                        MAX_CROSS_MARKET_SOURCES
+                    );
+                    return Err(LendingError::CrossMarketSourceLimit.into());
+                }
+                self.sources.push(source_reserve);
+            }
+            (Some(_), true) | (None, false) => {}
+        }
+        Ok(())
+    }
+}
+
+impl Sealed for CrossMarketSources {}
+impl IsInitialized for CrossMarketSources {
+    fn is_initialized(&self) -> bool {
+        self.version != UNINITIALIZED_VERSION
+    }
+}
+
+const CROSS_MARKET_SOURCES_LEN: usize = 355; // 1 + 1 + 32 + 1 + (32 * 8) + 64
+impl Pack for CrossMarketSources {
+    const LEN: usize = CROSS_MARKET_SOURCES_LEN;
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let output = array_mut_ref![dst, 0, CROSS_MARKET_SOURCES_LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (version, bump_seed, lending_market, sources_len, data_flat, _padding) = mut_array_refs![
+            output,
+            1,
+            1,
+            PUBKEY_BYTES,
+            1,
+            PUBKEY_BYTES * MAX_CROSS_MARKET_SOURCES,
+            64
+        ];
+
+        *version = self.version.to_le_bytes();
+        *bump_seed = self.bump_seed.to_le_bytes();
+        lending_market.copy_from_slice(self.lending_market.as_ref());
+        *sources_len = u8::try_from(self.sources.len()).unwrap().to_le_bytes();
+
+        let mut offset = 0;
+        for source in &self.sources {
+            let source_flat = array_mut_ref![data_flat, offset, PUBKEY_BYTES];
+            source_flat.copy_from_slice(source.as_ref());
+            offset += PUBKEY_BYTES;
+        }
+    }
+
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![src, 0, CROSS_MARKET_SOURCES_LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (version, bump_seed, lending_market, sources_len, data_flat, _padding) = array_refs![
+            input,
+            1,
+            1,
+            PUBKEY_BYTES,
+            1,
+            PUBKEY_BYTES * MAX_CROSS_MARKET_SOURCES,
+            64
+        ];
+
+        let version = u8::from_le_bytes(*version);
+        if version > PROGRAM_VERSION {
+            msg!("Cross market sources version does not match lending program version");
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        let sources_len = u8::from_le_bytes(*sources_len) as usize;
+        if sources_len > MAX_CROSS_MARKET_SOURCES {
+            msg!("Cross market sources length is invalid");
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        let mut sources = Vec::with_capacity(sources_len);
+        let mut offset = 0;
+        for _ in 0..sources_len {
+            let source_flat = array_ref![data_flat, offset, PUBKEY_BYTES];
+            sources.push(Pubkey::new_from_array(*source_flat));
+            offset += PUBKEY_BYTES;
+        }
+
+        Ok(Self {
+            version,
+            bump_seed: u8::from_le_bytes(*bump_seed),
+            lending_market: Pubkey::new_from_array(*lending_market),
+            sources,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn pack_and_unpack_cross_market_sources() {
+        let mut rng = rand::thread_rng();
+        for _ in 0..100 {
+            let cross_market_sources = CrossMarketSources {
+                version: PROGRAM_VERSION,
+                bump_seed: rng.gen(),
+                lending_market: Pubkey::new_unique(),
+                sources: (0..rng.gen_range(0..=MAX_CROSS_MARKET_SOURCES))
+                    .map(|_| Pubkey::new_unique())
+                    .collect(),
+            };
+
+            let mut packed = [0u8; CrossMarketSources::LEN];
+            CrossMarketSources::pack(cross_market_sources.clone(), &mut packed).unwrap();
+            let unpacked = CrossMarketSources::unpack(&packed).unwrap();
+            assert_eq!(cross_market_sources, unpacked);
+        }
+    }
+
+    #[test]
+    fn set_sources() {
+        let source = Pubkey::new_unique();
+        let mut cross_market_sources = CrossMarketSources::new(Pubkey::new_unique(), 255);
+        assert!(!cross_market_sources.is_allowed(&source));
+
+        cross_market_sources.set_source(source, true).unwrap();
+        cross_market_sources.set_source(source, true).unwrap();
+        assert!(cross_market_sources.is_allowed(&source));
+        assert_eq!(cross_market_sources.sources.len(), 1);
+
+        cross_market_sources.set_source(source, false).unwrap();
+        assert!(!cross_market_sources.is_allowed(&source));
+        cross_market_sources.set_source(source, false).unwrap();
+    }
+
+    #[test]
+    fn set_source_limit() {
+        let mut cross_market_sources = CrossMarketSources::new(Pubkey::new_unique(), 255);
+        for _ in 0..MAX_CROSS_MARKET_SOURCES {
+            cross_market_sources
+                .set_source(Pubkey::new_unique(), true)
+                .unwrap();
+        }
+        assert_eq!(
+            cross_market_sources.set_source(Pubkey::new_unique(), true),
+            Err(LendingError::CrossMarketSourceLimit.into())
+        );
+
+        // allowed sources can still be removed
+        let source = cross_market_sources.sources[0];
+        cross_market_sources.set_source(source, false).unwrap();
+        assert!(!cross_market_sources.is_allowed(&source));
+    }
+}
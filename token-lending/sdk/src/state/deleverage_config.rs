@@ -0,0 +1,112 @@
+use super::*;
+use crate::math::Decimal;
+use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
+use solana_program::{
+    msg,
+    program_error::ProgramError,
+    program_pack::{IsInitialized, Pack, Sealed},
+    pubkey::{Pubkey, PUBKEY_BYTES},
+};
+
+/// Seed used to derive the deleverage config address of a lending market
+pub const DELEVERAGE_CONFIG_SEED: &[u8] = b"DeleverageConfig";
+
+/// How much an obligation can withdraw past the lending market outflow limit by first repaying
+/// its own debt, see [DeleverageCredit]. Lives in a PDA derived from
+/// [lending market, DELEVERAGE_CONFIG_SEED] and is only written by the lending market owner.
+/// Markets without one, or with a zero max, apply the limit to every withdrawal.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DeleverageConfig {
+    /// Version of the struct
+    pub version: u8,
+    /// Bump seed for the derived address
+    pub bump_seed: u8,
+    /// Lending market the config applies to
+    pub lending_market: Pubkey,
+    /// Max value an obligation can withdraw exempt from the market outflow limit per rate limiter
+    /// window, in whole units of the quote currency
+    pub max_exempt_value: u64,
+}
+
+impl DeleverageConfig {
+    /// Create a new deleverage config for a lending market
+    pub fn new(lending_market: Pubkey, bump_seed: u8, max_exempt_value: u64) -> Self {
+        Self {
+            version: PROGRAM_VERSION,
+            bump_seed,
+            lending_market,
+            max_exempt_value,
+        }
+    }
+
+    /// Whether repayments earn exemptions at all
+    pub fn is_enabled(&self) -> bool {
+        self.max_exempt_value > 0
+    }
+
+    /// Max exempt value per window
+    pub fn max_exempt_value(&self) -> Decimal {
+        Decimal::from(self.max_exempt_value)
+    }
+}
+
+impl Sealed for DeleverageConfig {}
+impl IsInitialized for DeleverageConfig {
+    fn is_initialized(&self) -> bool {
+        self.version != UNINITIALIZED_VERSION
+    }
+}
+
+const DELEVERAGE_CONFIG_LEN: usize = 122; // 1 + 1 + 32 + 8 + 80
+impl Pack for DeleverageConfig {
+    const LEN: usize = DELEVERAGE_CONFIG_LEN;
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let output = array_mut_ref![dst, 0, DELEVERAGE_CONFIG_LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (version, bump_seed, lending_market, max_exempt_value, _padding) =
+            mut_array_refs![output, 1, 1, PUBKEY_BYTES, 8, 80];
+
+        *version = self.version.to_le_bytes();
+        *bump_seed = self.bump_seed.to_le_bytes();
+        lending_market.copy_from_slice(self.lending_market.as_ref());
+        *max_exempt_value = self.max_exempt_value.to_le_bytes();
+    }
+
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![src, 0, DELEVERAGE_CONFIG_LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (version, bump_seed, lending_market, max_exempt_value, _padding) =
+            array_refs![input, 1, 1, PUBKEY_BYTES, 8, 80];
+
+        let version = u8::from_le_bytes(*version);
+        if version > PROGRAM_VERSION {
+            msg!("Deleverage config version does not match lending program version");
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        Ok(Self {
+            version,
+            bump_seed: u8::from_le_bytes(*bump_seed),
+            lending_market: Pubkey::new_from_array(*lending_market),
+            max_exempt_value: u64::from_le_bytes(*max_exempt_value),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn pack_and_unpack_deleverage_config() {
+        let mut rng = rand::thread_rng();
+        let config = DeleverageConfig::new(Pubkey::new_unique(), rng.gen(), rng.gen());
+
+        let mut packed = [0u8; DeleverageConfig::LEN];
+        DeleverageConfig::pack(config.clone(), &mut packed).unwrap();
+        let unpacked = DeleverageConfig::unpack(&packed).unwrap();
+        assert_eq!(config, unpacked);
+    }
+}
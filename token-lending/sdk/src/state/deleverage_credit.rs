@@ -0,0 +1,272 @@
+use super::*;
+use crate::math::{Decimal, TryAdd, TrySub};
+use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
+use solana_program::{
+    clock::Slot,
+    msg,
+    program_error::ProgramError,
+    program_pack::{IsInitialized, Pack, Sealed},
+    pubkey::{Pubkey, PUBKEY_BYTES},
+};
+
+/// Seed used to derive the deleverage credit address of an obligation
+pub const DELEVERAGE_CREDIT_SEED: &[u8] = b"DeleverageCredit";
+
+/// Value an obligation repaid in the current window of the lending market rate limiter, which it
+/// can then withdraw without counting against the market outflow limit. Repaying and withdrawing
+/// the same value leaves the market no worse off, so users unwinding a position aren't throttled.
+/// Lives in a PDA derived from [obligation, DELEVERAGE_CREDIT_SEED], created by the obligation
+/// owner.
+///
+/// The credit expires with the window, only the obligation it belongs to can use it, borrowing
+/// takes it back, and each repaid unit of value exempts a single unit of withdrawal, up to the
+/// [DeleverageConfig::max_exempt_value] of the market. Repayments are valued at the lower bound
+/// of the repay reserve price, withdrawals at the upper bound of the withdraw reserve price, as
+/// the rate limiter does. Reserve outflow limits still apply in full.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DeleverageCredit {
+    /// Version of the struct
+    pub version: u8,
+    /// Bump seed for the derived address
+    pub bump_seed: u8,
+    /// Obligation the credit belongs to
+    pub obligation: Pubkey,
+    /// Start of the lending market rate limiter window the values below were recorded in
+    pub window_start: Slot,
+    /// Value repaid by the obligation in the window
+    pub repaid_value: Decimal,
+    /// Value withdrawn exempt from the market outflow limit in the window
+    pub exempt_value: Decimal,
+}
+
+impl DeleverageCredit {
+    /// Create a new deleverage credit for an obligation
+    pub fn new(obligation: Pubkey, bump_seed: u8) -> Self {
+        Self {
+            version: PROGRAM_VERSION,
+            bump_seed,
+            obligation,
+            ..Self::default()
+        }
+    }
+
+    fn roll_window(&mut self, window_start: Slot) {
+        if window_start != self.window_start {
+            self.window_start = window_start;
+            self.repaid_value = Decimal::zero();
+            self.exempt_value = Decimal::zero();
+        }
+    }
+
+    /// Record a repayment of `repaid_value` in the window starting at `window_start`
+    pub fn record_repayment(
+        &mut self,
+        window_start: Slot,
+        repaid_value: Decimal,
+    ) -> Result<(), ProgramError> {
+        self.roll_window(window_start);
+        self.repaid_value = self.repaid_value.try_add(repaid_value)?;
+        Ok(())
+    }
+
+    /// Take back the credit of a borrow of `borrowed_value` in the window starting at
+    /// `window_start`, so repaying and borrowing again doesn't build up credit
+    pub fn record_borrow(
+        &mut self,
+        window_start: Slot,
+        borrowed_value: Decimal,
+    ) -> Result<(), ProgramError> {
+        self.roll_window(window_start);
+        self.repaid_value = self
+            .repaid_value
+            .try_sub(borrowed_value)
+            .unwrap_or_else(|_| Decimal::zero());
+        Ok(())
+    }
+
+    /// Value that can still be withdrawn exempt from the market outflow limit in the window
+    /// starting at `window_start`
+    pub fn available_exemption(
+        &self,
+        window_start: Slot,
+        config: &DeleverageConfig,
+    ) -> Result<Decimal, ProgramError> {
+        if window_start != self.window_start || !config.is_enabled() {
+            return Ok(Decimal::zero());
+        }
+        let credit = self.repaid_value.min(config.max_exempt_value());
+        Ok(credit
+            .try_sub(self.exempt_value)
+            .unwrap_or_else(|_| Decimal::zero()))
+    }
+
+    /// Record a withdrawal of `exempt_value` that didn't count against the market outflow limit
+    pub fn use_exemption(
+        &mut self,
+        window_start: Slot,
+        exempt_value: Decimal,
+    ) -> Result<(), ProgramError> {
+        self.roll_window(window_start);
+        self.exempt_value = self.exempt_value.try_add(exempt_value)?;
+        Ok(())
+    }
+}
+
+impl Sealed for DeleverageCredit {}
+impl IsInitialized for DeleverageCredit {
+    fn is_initialized(&self) -> bool {
+        self.version != UNINITIALIZED_VERSION
+    }
+}
+
+const DELEVERAGE_CREDIT_LEN: usize = 138; // 1 + 1 + 32 + 8 + 16 + 16 + 64
+impl Pack for DeleverageCredit {
+    const LEN: usize = DELEVERAGE_CREDIT_LEN;
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let output = array_mut_ref![dst, 0, DELEVERAGE_CREDIT_LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (version, bump_seed, obligation, window_start, repaid_value, exempt_value, _padding) =
+            mut_array_refs![output, 1, 1, PUBKEY_BYTES, 8, 16, 16, 64];
+
+        *version = self.version.to_le_bytes();
+        *bump_seed = self.bump_seed.to_le_bytes();
+        obligation.copy_from_slice(self.obligation.as_ref());
+        *window_start = self.window_start.to_le_bytes();
+        pack_decimal(self.repaid_value, repaid_value);
+        pack_decimal(self.exempt_value, exempt_value);
+    }
+
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![src, 0, DELEVERAGE_CREDIT_LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (version, bump_seed, obligation, window_start, repaid_value, exempt_value, _padding) =
+            array_refs![input, 1, 1, PUBKEY_BYTES, 8, 16, 16, 64];
+
+        let version = u8::from_le_bytes(*version);
+        if version > PROGRAM_VERSION {
+            msg!("Deleverage credit version does not match lending program version");
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        Ok(Self {
+            version,
+            bump_seed: u8::from_le_bytes(*bump_seed),
+            obligation: Pubkey::new_from_array(*obligation),
+            window_start: u64::from_le_bytes(*window_start),
+            repaid_value: unpack_decimal(repaid_value),
+            exempt_value: unpack_decimal(exempt_value),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn pack_and_unpack_deleverage_credit() {
+        let mut rng = rand::thread_rng();
+        let credit = DeleverageCredit {
+            version: PROGRAM_VERSION,
+            bump_seed: rng.gen(),
+            obligation: Pubkey::new_unique(),
+            window_start: rng.gen(),
+            repaid_value: Decimal::from_scaled_val(rng.gen()),
+            exempt_value: Decimal::from_scaled_val(rng.gen()),
+        };
+
+        let mut packed = [0u8; DeleverageCredit::LEN];
+        DeleverageCredit::pack(credit.clone(), &mut packed).unwrap();
+        let unpacked = DeleverageCredit::unpack(&packed).unwrap();
+        assert_eq!(credit, unpacked);
+    }
+
+    #[test]
+    fn exemption_is_used_up() {
+        let config = DeleverageConfig::new(Pubkey::new_unique(), 255, 1_000);
+        let mut credit = DeleverageCredit::new(Pubkey::new_unique(), 255);
+
+        credit.record_repayment(100, Decimal::from(300u64)).unwrap();
+        credit.record_repayment(100, Decimal::from(200u64)).unwrap();
+        assert_eq!(
+            credit.available_exemption(100, &config).unwrap(),
+            Decimal::from(500u64)
+        );
+
+        credit.use_exemption(100, Decimal::from(400u64)).unwrap();
+        assert_eq!(
+            credit.available_exemption(100, &config).unwrap(),
+            Decimal::from(100u64)
+        );
+        credit.use_exemption(100, Decimal::from(100u64)).unwrap();
+        assert_eq!(
+            credit.available_exemption(100, &config).unwrap(),
+            Decimal::zero()
+        );
+    }
+
+    #[test]
+    fn borrowing_takes_back_credit() {
+        let config = DeleverageConfig::new(Pubkey::new_unique(), 255, 1_000);
+        let mut credit = DeleverageCredit::new(Pubkey::new_unique(), 255);
+
+        credit.record_repayment(100, Decimal::from(500u64)).unwrap();
+        credit.record_borrow(100, Decimal::from(200u64)).unwrap();
+        assert_eq!(
+            credit.available_exemption(100, &config).unwrap(),
+            Decimal::from(300u64)
+        );
+
+        // borrowing more than the credit zeroes it
+        credit.record_borrow(100, Decimal::from(1_000u64)).unwrap();
+        assert_eq!(credit.repaid_value, Decimal::zero());
+        assert_eq!(
+            credit.available_exemption(100, &config).unwrap(),
+            Decimal::zero()
+        );
+    }
+
+    #[test]
+    fn exemption_is_capped_and_expires() {
+        let config = DeleverageConfig::new(Pubkey::new_unique(), 255, 1_000);
+        let mut credit = DeleverageCredit::new(Pubkey::new_unique(), 255);
+
+        credit
+            .record_repayment(100, Decimal::from(5_000u64))
+            .unwrap();
+        assert_eq!(
+            credit.available_exemption(100, &config).unwrap(),
+            Decimal::from(1_000u64)
+        );
+
+        // repaying more doesn't restore the exemption used this window
+        credit.use_exemption(100, Decimal::from(1_000u64)).unwrap();
+        credit
+            .record_repayment(100, Decimal::from(5_000u64))
+            .unwrap();
+        assert_eq!(
+            credit.available_exemption(100, &config).unwrap(),
+            Decimal::zero()
+        );
+
+        // the credit doesn't carry over to the next window
+        assert_eq!(
+            credit.available_exemption(200, &config).unwrap(),
+            Decimal::zero()
+        );
+        credit.record_repayment(200, Decimal::from(10u64)).unwrap();
+        assert_eq!(
+            credit.available_exemption(200, &config).unwrap(),
+            Decimal::from(10u64)
+        );
+
+        // nothing is exempt once the market disables it
+        let disabled = DeleverageConfig::new(config.lending_market, 255, 0);
+        assert_eq!(
+            credit.available_exemption(200, &disabled).unwrap(),
+            Decimal::zero()
+        );
+    }
+}
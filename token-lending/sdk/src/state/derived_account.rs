@@ -0,0 +1,65 @@
+use super::*;
+use solana_program::{
+    program_pack::{IsInitialized, Pack},
+    pubkey::Pubkey,
+};
+
+/// An account living at the address derived from [parent, SEED], where the parent is the
+/// lending market, reserve or obligation the account belongs to. Lets the program check all of
+/// them the same way.
+pub trait DerivedAccount: Pack + IsInitialized {
+    /// Seed the address is derived with, after the parent key
+    const SEED: &'static [u8];
+    /// Name of the account in program logs
+    const NAME: &'static str;
+
+    /// Key of the account the address is derived from
+    fn parent(&self) -> &Pubkey;
+
+    /// Bump seed of the derived address
+    fn bump_seed(&self) -> u8;
+
+    /// Finds the address derived from `parent`
+    fn find_address(program_id: &Pubkey, parent: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[parent.as_ref(), Self::SEED], program_id)
+    }
+}
+
+macro_rules! derived_account {
+    ($($account:ty: $seed:expr, $name:literal, $parent:ident;)+) => {
+        $(
+            impl DerivedAccount for $account {
+                const SEED: &'static [u8] = $seed;
+                const NAME: &'static str = $name;
+
+                fn parent(&self) -> &Pubkey {
+                    &self.$parent
+                }
+
+                fn bump_seed(&self) -> u8 {
+                    self.bump_seed
+                }
+            }
+        )+
+    };
+}
+
+derived_account! {
+    BorrowAllowances: BORROW_ALLOWANCES_SEED, "borrow allowances", obligation;
+    BorrowGraceConfig: BORROW_GRACE_CONFIG_SEED, "borrow grace config", reserve;
+    ConfigTimelock: CONFIG_TIMELOCK_SEED, "config timelock", lending_market;
+    CrossMarketSources: CROSS_MARKET_SOURCES_SEED, "cross market sources", lending_market;
+    DeleverageConfig: DELEVERAGE_CONFIG_SEED, "deleverage config", lending_market;
+    DeleverageCredit: DELEVERAGE_CREDIT_SEED, "deleverage credit", obligation;
+    DustSweepConfig: DUST_SWEEP_CONFIG_SEED, "dust sweep config", lending_market;
+    FeeRebates: FEE_REBATES_SEED, "fee rebates", lending_market;
+    FeeRedemptionHook: FEE_REDEMPTION_HOOK_SEED, "fee redemption hook", reserve;
+    FlashLoanConfig: FLASH_LOAN_CONFIG_SEED, "flash loan config", reserve;
+    LiquidationCircuitBreaker: LIQUIDATION_CIRCUIT_BREAKER_SEED, "liquidation circuit breaker", lending_market;
+    LiquidationStats: LIQUIDATION_STATS_SEED, "liquidation stats", lending_market;
+    MarketPolicy: MARKET_POLICY_SEED, "market policy", lending_market;
+    ObligationBorrowCap: OBLIGATION_BORROW_CAP_SEED, "obligation borrow cap", obligation;
+    ObligationLimits: OBLIGATION_LIMITS_SEED, "obligation limits", lending_market;
+    PendingConfig: PENDING_CONFIG_SEED, "pending config", target;
+    ReserveAccrualHistory: RESERVE_ACCRUAL_HISTORY_SEED, "accrual history", reserve;
+}
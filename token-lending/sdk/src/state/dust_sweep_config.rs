@@ -0,0 +1,123 @@
+use super::*;
+use crate::math::Decimal;
+use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
+use solana_program::{
+    msg,
+    program_error::ProgramError,
+    program_pack::{IsInitialized, Pack, Sealed},
+    pubkey::{Pubkey, PUBKEY_BYTES},
+};
+
+/// Seed used to derive the dust sweep config address of a lending market
+pub const DUST_SWEEP_CONFIG_SEED: &[u8] = b"DustSweepConfig";
+
+/// Threshold below which the collateral of an obligation without borrows is considered dust.
+/// Such obligations can't be liquidated and are rarely withdrawn from, so the lending market
+/// owner or risk authority can sweep their collateral into the protocol fees and close them.
+/// Lives in a PDA derived from [lending market, DUST_SWEEP_CONFIG_SEED]. Markets without one
+/// can't sweep any obligation.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DustSweepConfig {
+    /// Version of the struct
+    pub version: u8,
+    /// Bump seed for the derived address
+    pub bump_seed: u8,
+    /// Lending market the config applies to
+    pub lending_market: Pubkey,
+    /// Obligations with a deposited value below this can be swept, in quote currency
+    pub max_deposited_value: Decimal,
+}
+
+impl DustSweepConfig {
+    /// Create a new dust sweep config for a lending market
+    pub fn new(lending_market: Pubkey, bump_seed: u8) -> Self {
+        Self {
+            version: PROGRAM_VERSION,
+            bump_seed,
+            lending_market,
+            max_deposited_value: Decimal::zero(),
+        }
+    }
+
+    /// Whether an obligation with `deposited_value` and no borrows can be swept
+    pub fn is_dust(&self, deposited_value: Decimal) -> bool {
+        deposited_value < self.max_deposited_value
+    }
+}
+
+impl Sealed for DustSweepConfig {}
+impl IsInitialized for DustSweepConfig {
+    fn is_initialized(&self) -> bool {
+        self.version != UNINITIALIZED_VERSION
+    }
+}
+
+const DUST_SWEEP_CONFIG_LEN: usize = 114; // 1 + 1 + 32 + 16 + 64
+impl Pack for DustSweepConfig {
+    const LEN: usize = DUST_SWEEP_CONFIG_LEN;
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let output = array_mut_ref![dst, 0, DUST_SWEEP_CONFIG_LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (version, bump_seed, lending_market, max_deposited_value, _padding) =
+            mut_array_refs![output, 1, 1, PUBKEY_BYTES, 16, 64];
+
+        *version = self.version.to_le_bytes();
+        *bump_seed = self.bump_seed.to_le_bytes();
+        lending_market.copy_from_slice(self.lending_market.as_ref());
+        pack_decimal(self.max_deposited_value, max_deposited_value);
+    }
+
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![src, 0, DUST_SWEEP_CONFIG_LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (version, bump_seed, lending_market, max_deposited_value, _padding) =
+            array_refs![input, 1, 1, PUBKEY_BYTES, 16, 64];
+
+        let version = u8::from_le_bytes(*version);
+        if version > PROGRAM_VERSION {
+            msg!("Dust sweep config version does not match lending program version");
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        Ok(Self {
+            version,
+            bump_seed: u8::from_le_bytes(*bump_seed),
+            lending_market: Pubkey::new_from_array(*lending_market),
+            max_deposited_value: unpack_decimal(max_deposited_value),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn pack_and_unpack_dust_sweep_config() {
+        let mut rng = rand::thread_rng();
+        let config = DustSweepConfig {
+            version: PROGRAM_VERSION,
+            bump_seed: rng.gen(),
+            lending_market: Pubkey::new_unique(),
+            max_deposited_value: Decimal::from_scaled_val(rng.gen::<u64>() as u128),
+        };
+
+        let mut packed = [0u8; DustSweepConfig::LEN];
+        DustSweepConfig::pack(config.clone(), &mut packed).unwrap();
+        let unpacked = DustSweepConfig::unpack(&packed).unwrap();
+        assert_eq!(config, unpacked);
+    }
+
+    #[test]
+    fn dust_threshold_is_exclusive() {
+        let mut config = DustSweepConfig::new(Pubkey::new_unique(), 255);
+        assert!(!config.is_dust(Decimal::zero()));
+
+        config.max_deposited_value = Decimal::one();
+        assert!(config.is_dust(Decimal::zero()));
+        assert!(config.is_dust(Decimal::from_percent(99)));
+        assert!(!config.is_dust(Decimal::one()));
+    }
+}
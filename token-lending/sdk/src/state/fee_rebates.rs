@@ -0,0 +1,274 @@
+use super::*;
+use crate::error::LendingError;
+use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
+use solana_program::{
+    entrypoint::ProgramResult,
+    msg,
+    program_error::ProgramError,
+    program_pack::{IsInitialized, Pack, Sealed},
+    pubkey::{Pubkey, PUBKEY_BYTES},
+};
+use std::convert::TryFrom;
+
+/// Max number of programs and owners a lending market can grant borrow fee rebates to
+pub const MAX_FEE_REBATES: usize = 8;
+
+/// Seed used to derive the fee rebates address of a lending market
+pub const FEE_REBATES_SEED: &[u8] = b"FeeRebates";
+
+/// Highest rebate, waiving the whole borrow fee
+pub const MAX_FEE_REBATE_BPS: u16 = 10_000;
+
+/// Borrow fee rebates granted by a lending market to protocols routing volume to it. Lives in a
+/// PDA derived from [lending market, FEE_REBATES_SEED] since the lending market account has no
+/// free space left.
+///
+/// An entry applies to a borrow when its key is either the program of the top level instruction
+/// the borrow is CPI'd from, or the signer of the borrow. The first can't be spoofed since the
+/// lending program reads it from the instructions sysvar, the second since a PDA can only sign
+/// through its own program.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FeeRebates {
+    /// Version of the struct
+    pub version: u8,
+    /// Bump seed for the derived address
+    pub bump_seed: u8,
+    /// Lending market the rebates apply to
+    pub lending_market: Pubkey,
+    /// Rebates, unique by key
+    pub rebates: Vec<FeeRebate>,
+}
+
+/// Share of the borrow fee waived for a program or owner
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FeeRebate {
+    /// Program id or borrow signer the rebate applies to
+    pub key: Pubkey,
+    /// Share of the borrow fee waived, in basis points
+    pub rebate_bps: u16,
+}
+
+impl FeeRebates {
+    /// Create new fee rebates for a lending market
+    pub fn new(lending_market: Pubkey, bump_seed: u8) -> Self {
+        let mut fee_rebates = Self::default();
+        Self::init(&mut fee_rebates, lending_market, bump_seed);
+        fee_rebates
+    }
+
+    /// Initialize fee rebates. There are no rebates to start with.
+    pub fn init(&mut self, lending_market: Pubkey, bump_seed: u8) {
+        self.version = PROGRAM_VERSION;
+        self.bump_seed = bump_seed;
+        self.lending_market = lending_market;
+        self.rebates = vec![];
+    }
+
+    /// Find the rebate of a program or owner
+    pub fn find_rebate(&self, key: Pubkey) -> Option<&FeeRebate> {
+        self.rebates.iter().find(|rebate| rebate.key == key)
+    }
+
+    /// Set the rebate of a program or owner, replacing any existing rebate. A rebate of 0
+    /// removes the entry.
+    pub fn set_rebate(&mut self, key: Pubkey, rebate_bps: u16) -> ProgramResult {
+        if rebate_bps > MAX_FEE_REBATE_BPS {
+            msg!("Fee rebate must be in range [0, {}]", MAX_FEE_REBATE_BPS);
+            return Err(LendingError::InvalidConfig.into());
+        }
+        let index = self.rebates.iter().position(|rebate| rebate.key == key);
+        match (index, rebate_bps) {
+            (Some(index), 0) => {
+                self.rebates.remove(index);
+            }
+            (Some(index), _) => self.rebates[index].rebate_bps = rebate_bps,
+            (None, 0) => {}
+            (None, _) => {
+                if self.rebates.len() >= MAX_FEE_REBATES {
+                    msg!(
+                        "Lending market cannot have more than {} fee rebates",
+                        MAX_FEE_REBATES
+                    );
+                    return Err(LendingError::FeeRebateLimit.into());
+                }
+                self.rebates.push(FeeRebate { key, rebate_bps });
+            }
+        }
+        Ok(())
+    }
+
+    /// Rebate of a borrow, the highest of the rebates of the calling program and the signer
+    pub fn rebate_bps(&self, calling_program: Option<Pubkey>, signer: Pubkey) -> u16 {
+        calling_program
+            .into_iter()
+            .chain(std::iter::once(signer))
+            .filter_map(|key| self.find_rebate(key))
+            .map(|rebate| rebate.rebate_bps)
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+impl Sealed for FeeRebates {}
+impl IsInitialized for FeeRebates {
+    fn is_initialized(&self) -> bool {
+        self.version != UNINITIALIZED_VERSION
+    }
+}
+
+const FEE_REBATE_LEN: usize = 34; // 32 + 2
+const FEE_REBATES_LEN: usize = 371; // 1 + 1 + 32 + 1 + (34 * 8) + 64
+impl Pack for FeeRebates {
+    const LEN: usize = FEE_REBATES_LEN;
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let output = array_mut_ref![dst, 0, FEE_REBATES_LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (version, bump_seed, lending_market, rebates_len, data_flat, _padding) = mut_array_refs![
+            output,
+            1,
+            1,
+            PUBKEY_BYTES,
+            1,
+            FEE_REBATE_LEN * MAX_FEE_REBATES,
+            64
+        ];
+
+        *version = self.version.to_le_bytes();
+        *bump_seed = self.bump_seed.to_le_bytes();
+        lending_market.copy_from_slice(self.lending_market.as_ref());
+        *rebates_len = u8::try_from(self.rebates.len()).unwrap().to_le_bytes();
+
+        let mut offset = 0;
+        for rebate in &self.rebates {
+            let rebate_flat = array_mut_ref![data_flat, offset, FEE_REBATE_LEN];
+            #[allow(clippy::ptr_offset_with_cast)]
+            let (key, rebate_bps) = mut_array_refs![rebate_flat, PUBKEY_BYTES, 2];
+            key.copy_from_slice(rebate.key.as_ref());
+            *rebate_bps = rebate.rebate_bps.to_le_bytes();
+            offset += FEE_REBATE_LEN;
+        }
+    }
+
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![src, 0, FEE_REBATES_LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (version, bump_seed, lending_market, rebates_len, data_flat, _padding) = array_refs![
+            input,
+            1,
+            1,
+            PUBKEY_BYTES,
+            1,
+            FEE_REBATE_LEN * MAX_FEE_REBATES,
+            64
+        ];
+
+        let version = u8::from_le_bytes(*version);
+        if version > PROGRAM_VERSION {
+            msg!("Fee rebates version does not match lending program version");
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        let rebates_len = u8::from_le_bytes(*rebates_len) as usize;
+        if rebates_len > MAX_FEE_REBATES {
+            msg!("Fee rebates length is invalid");
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        let mut rebates = Vec::with_capacity(rebates_len);
+        let mut offset = 0;
+        for _ in 0..rebates_len {
+            let rebate_flat = array_ref![data_flat, offset, FEE_REBATE_LEN];
+            #[allow(clippy::ptr_offset_with_cast)]
+            let (key, rebate_bps) = array_refs![rebate_flat, PUBKEY_BYTES, 2];
+            rebates.push(FeeRebate {
+                key: Pubkey::new_from_array(*key),
+                rebate_bps: u16::from_le_bytes(*rebate_bps),
+            });
+            offset += FEE_REBATE_LEN;
+        }
+
+        Ok(Self {
+            version,
+            bump_seed: u8::from_le_bytes(*bump_seed),
+            lending_market: Pubkey::new_from_array(*lending_market),
+            rebates,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn pack_and_unpack_fee_rebates() {
+        let mut rng = rand::thread_rng();
+        for _ in 0..100 {
+            let fee_rebates = FeeRebates {
+                version: PROGRAM_VERSION,
+                bump_seed: rng.gen(),
+                lending_market: Pubkey::new_unique(),
+                rebates: (0..rng.gen_range(0..=MAX_FEE_REBATES))
+                    .map(|_| FeeRebate {
+                        key: Pubkey::new_unique(),
+                        rebate_bps: rng.gen_range(1..=MAX_FEE_REBATE_BPS),
+                    })
+                    .collect(),
+            };
+
+            let mut packed = [0u8; FeeRebates::LEN];
+            FeeRebates::pack(fee_rebates.clone(), &mut packed).unwrap();
+            let unpacked = FeeRebates::unpack(&packed).unwrap();
+            assert_eq!(fee_rebates, unpacked);
+        }
+    }
+
+    #[test]
+    fn set_and_apply_rebates() {
+        let aggregator = Pubkey::new_unique();
+        let vault = Pubkey::new_unique();
+        let mut fee_rebates = FeeRebates::new(Pubkey::new_unique(), 255);
+
+        assert_eq!(
+            fee_rebates.set_rebate(aggregator, MAX_FEE_REBATE_BPS + 1),
+            Err(LendingError::InvalidConfig.into())
+        );
+
+        fee_rebates.set_rebate(aggregator, 2_000).unwrap();
+        fee_rebates.set_rebate(aggregator, 5_000).unwrap();
+        fee_rebates.set_rebate(vault, 1_000).unwrap();
+        assert_eq!(fee_rebates.rebates.len(), 2);
+
+        assert_eq!(fee_rebates.rebate_bps(Some(aggregator), vault), 5_000);
+        assert_eq!(fee_rebates.rebate_bps(None, vault), 1_000);
+        assert_eq!(fee_rebates.rebate_bps(None, aggregator), 5_000);
+        assert_eq!(
+            fee_rebates.rebate_bps(Some(Pubkey::new_unique()), Pubkey::new_unique()),
+            0
+        );
+
+        // setting a rebate of 0 removes it
+        fee_rebates.set_rebate(aggregator, 0).unwrap();
+        assert!(fee_rebates.find_rebate(aggregator).is_none());
+        assert_eq!(fee_rebates.rebate_bps(Some(aggregator), vault), 1_000);
+    }
+
+    #[test]
+    fn set_rebate_limit() {
+        let mut fee_rebates = FeeRebates::new(Pubkey::new_unique(), 255);
+        for _ in 0..MAX_FEE_REBATES {
+            fee_rebates.set_rebate(Pubkey::new_unique(), 100).unwrap();
+        }
+        assert_eq!(
+            fee_rebates.set_rebate(Pubkey::new_unique(), 100),
+            Err(LendingError::FeeRebateLimit.into())
+        );
+
+        // updating an existing entry still works
+        let key = fee_rebates.rebates[0].key;
+        fee_rebates.set_rebate(key, 200).unwrap();
+        assert_eq!(fee_rebates.find_rebate(key).unwrap().rebate_bps, 200);
+    }
+}
@@ -0,0 +1,221 @@
+use super::*;
+use crate::error::LendingError;
+use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
+use solana_program::{
+    entrypoint::ProgramResult,
+    hash::{hashv, HASH_BYTES},
+    instruction::{AccountMeta, Instruction},
+    msg,
+    program_error::ProgramError,
+    program_pack::{IsInitialized, Pack, Sealed},
+    pubkey::{Pubkey, PUBKEY_BYTES},
+};
+
+/// Seed used to derive the fee redemption hook address of a reserve
+pub const FEE_REDEMPTION_HOOK_SEED: &[u8] = b"FeeRedemptionHook";
+
+/// Instruction data prefix of the `on_fees_redeemed` call made to the hook program, the first 8
+/// bytes of sha256("global:on_fees_redeemed") so Anchor programs can implement it as is
+pub const ON_FEES_REDEEMED_DISCRIMINATOR: [u8; 8] = [135, 7, 199, 141, 51, 224, 135, 78];
+
+/// Program a reserve calls after transferring its protocol fees to the fee receiver in
+/// RedeemFees, e.g. to sweep them into an insurance fund or a staking contract. Lives in a PDA
+/// derived from [reserve, FEE_REDEMPTION_HOOK_SEED] and is only written by the lending market
+/// owner, which is what allowlists the hook program.
+///
+/// RedeemFees is permissionless, so the accounts forwarded to the hook are pinned by their hash
+/// rather than left to the caller.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FeeRedemptionHook {
+    /// Version of the struct
+    pub version: u8,
+    /// Bump seed for the derived address
+    pub bump_seed: u8,
+    /// Reserve the hook is called for
+    pub reserve: Pubkey,
+    /// Program invoked with `on_fees_redeemed`, the default pubkey when the hook is disabled
+    pub hook_program: Pubkey,
+    /// [fee_redemption_hook_accounts_hash] of the accounts forwarded to the hook program
+    pub accounts_hash: [u8; HASH_BYTES],
+}
+
+impl FeeRedemptionHook {
+    /// Create a new, disabled fee redemption hook for a reserve
+    pub fn new(reserve: Pubkey, bump_seed: u8) -> Self {
+        let mut hook = Self::default();
+        Self::init(&mut hook, reserve, bump_seed);
+        hook
+    }
+
+    /// Initialize a fee redemption hook. It starts out disabled.
+    pub fn init(&mut self, reserve: Pubkey, bump_seed: u8) {
+        self.version = PROGRAM_VERSION;
+        self.bump_seed = bump_seed;
+        self.reserve = reserve;
+        self.hook_program = Pubkey::default();
+        self.accounts_hash = [0; HASH_BYTES];
+    }
+
+    /// Whether RedeemFees has to call the hook program
+    pub fn is_enabled(&self) -> bool {
+        self.hook_program != Pubkey::default()
+    }
+
+    /// Check the accounts passed to RedeemFees for the hook are the ones it was set up with
+    pub fn check_accounts(&self, hook_program: &Pubkey, accounts: &[Pubkey]) -> ProgramResult {
+        if &self.hook_program != hook_program {
+            msg!("Hook program does not match the reserve fee redemption hook program");
+            return Err(LendingError::InvalidAccountInput.into());
+        }
+        if fee_redemption_hook_accounts_hash(accounts) != self.accounts_hash {
+            msg!("Accounts forwarded to the fee redemption hook do not match its accounts hash");
+            return Err(LendingError::InvalidAccountInput.into());
+        }
+        Ok(())
+    }
+}
+
+/// Hash of the accounts forwarded to a fee redemption hook, in order
+pub fn fee_redemption_hook_accounts_hash(accounts: &[Pubkey]) -> [u8; HASH_BYTES] {
+    let accounts = accounts.iter().map(|key| key.as_ref()).collect::<Vec<_>>();
+    hashv(&accounts).to_bytes()
+}
+
+/// Creates the `on_fees_redeemed` instruction RedeemFees invokes the hook program with, after
+/// `amount` of liquidity was transferred to the fee receiver. The instruction data is
+/// [ON_FEES_REDEEMED_DISCRIMINATOR] followed by the amount as a little endian u64.
+///
+/// Accounts passed to the hook program:
+///
+///   0. `[]` Reserve account.
+///   1. `[writable]` Reserve liquidity fee receiver account.
+///   2. `[]` Lending market account.
+///   3. .. Forwarded accounts, as passed to RedeemFees.
+pub fn on_fees_redeemed(
+    hook_program: Pubkey,
+    amount: u64,
+    reserve_pubkey: Pubkey,
+    reserve_liquidity_fee_receiver_pubkey: Pubkey,
+    lending_market_pubkey: Pubkey,
+    forwarded_accounts: Vec<AccountMeta>,
+) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new_readonly(reserve_pubkey, false),
+        AccountMeta::new(reserve_liquidity_fee_receiver_pubkey, false),
+        AccountMeta::new_readonly(lending_market_pubkey, false),
+    ];
+    accounts.extend(forwarded_accounts);
+
+    let mut data = ON_FEES_REDEEMED_DISCRIMINATOR.to_vec();
+    data.extend_from_slice(&amount.to_le_bytes());
+    Instruction {
+        program_id: hook_program,
+        accounts,
+        data,
+    }
+}
+
+impl Sealed for FeeRedemptionHook {}
+impl IsInitialized for FeeRedemptionHook {
+    fn is_initialized(&self) -> bool {
+        self.version != UNINITIALIZED_VERSION
+    }
+}
+
+const FEE_REDEMPTION_HOOK_LEN: usize = 162; // 1 + 1 + 32 + 32 + 32 + 64
+impl Pack for FeeRedemptionHook {
+    const LEN: usize = FEE_REDEMPTION_HOOK_LEN;
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let output = array_mut_ref![dst, 0, FEE_REDEMPTION_HOOK_LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (version, bump_seed, reserve, hook_program, accounts_hash, _padding) =
+            mut_array_refs![output, 1, 1, PUBKEY_BYTES, PUBKEY_BYTES, HASH_BYTES, 64];
+
+        *version = self.version.to_le_bytes();
+        *bump_seed = self.bump_seed.to_le_bytes();
+        reserve.copy_from_slice(self.reserve.as_ref());
+        hook_program.copy_from_slice(self.hook_program.as_ref());
+        *accounts_hash = self.accounts_hash;
+    }
+
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![src, 0, FEE_REDEMPTION_HOOK_LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (version, bump_seed, reserve, hook_program, accounts_hash, _padding) =
+            array_refs![input, 1, 1, PUBKEY_BYTES, PUBKEY_BYTES, HASH_BYTES, 64];
+
+        let version = u8::from_le_bytes(*version);
+        if version > PROGRAM_VERSION {
+            msg!("Fee redemption hook version does not match lending program version");
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        Ok(Self {
+            version,
+            bump_seed: u8::from_le_bytes(*bump_seed),
+            reserve: Pubkey::new_from_array(*reserve),
+            hook_program: Pubkey::new_from_array(*hook_program),
+            accounts_hash: *accounts_hash,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::Rng;
+    use solana_program::hash::hash;
+
+    #[test]
+    fn pack_and_unpack_fee_redemption_hook() {
+        let mut rng = rand::thread_rng();
+        let hook = FeeRedemptionHook {
+            version: PROGRAM_VERSION,
+            bump_seed: rng.gen(),
+            reserve: Pubkey::new_unique(),
+            hook_program: Pubkey::new_unique(),
+            accounts_hash: rng.gen(),
+        };
+
+        let mut packed = [0u8; FeeRedemptionHook::LEN];
+        FeeRedemptionHook::pack(hook.clone(), &mut packed).unwrap();
+        let unpacked = FeeRedemptionHook::unpack(&packed).unwrap();
+        assert_eq!(hook, unpacked);
+    }
+
+    #[test]
+    fn on_fees_redeemed_discriminator() {
+        assert_eq!(
+            ON_FEES_REDEEMED_DISCRIMINATOR,
+            hash(b"global:on_fees_redeemed").to_bytes()[..8]
+        );
+    }
+
+    #[test]
+    fn check_accounts() {
+        let hook_program = Pubkey::new_unique();
+        let accounts = [Pubkey::new_unique(), Pubkey::new_unique()];
+        let mut hook = FeeRedemptionHook::new(Pubkey::new_unique(), 255);
+        assert!(!hook.is_enabled());
+
+        hook.hook_program = hook_program;
+        hook.accounts_hash = fee_redemption_hook_accounts_hash(&accounts);
+        assert!(hook.is_enabled());
+        hook.check_accounts(&hook_program, &accounts).unwrap();
+
+        assert_eq!(
+            hook.check_accounts(&Pubkey::new_unique(), &accounts),
+            Err(LendingError::InvalidAccountInput.into())
+        );
+        // order matters
+        assert_eq!(
+            hook.check_accounts(&hook_program, &[accounts[1], accounts[0]]),
+            Err(LendingError::InvalidAccountInput.into())
+        );
+        assert_eq!(
+            hook.check_accounts(&hook_program, &accounts[..1]),
+            Err(LendingError::InvalidAccountInput.into())
+        );
+    }
+}
@@ -0,0 +1,319 @@
+use super::*;
+use crate::error::LendingError;
+use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
+use solana_program::{
+    msg,
+    program_error::ProgramError,
+    program_pack::{IsInitialized, Pack, Sealed},
+    pubkey::{Pubkey, PUBKEY_BYTES},
+};
+
+/// Seed used to derive the flash loan config address of a reserve
+pub const FLASH_LOAN_CONFIG_SEED: &[u8] = b"FlashLoanConfig";
+
+/// Whether a reserve lends out flash loans and how much of its liquidity a single flash loan can
+/// take, e.g. to turn flash loans off on thin reserves without touching the flash loan fee, and
+/// where the protocol share of flash loan fees goes. Lives in a PDA derived from
+/// [reserve, FLASH_LOAN_CONFIG_SEED], configured by the lending market owner. Flash repays add to
+/// its counters. Reserves without one allow flash loans of any size and pay flash loan fees to
+/// the reserve fee receiver, untracked.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FlashLoanConfig {
+    /// Version of the struct
+    pub version: u8,
+    /// Bump seed for the derived address
+    pub bump_seed: u8,
+    /// Reserve the config applies to
+    pub reserve: Pubkey,
+    /// Whether FlashBorrowReserveLiquidity is allowed on the reserve
+    pub flash_loans_enabled: bool,
+    /// Max liquidity a single flash borrow can take, in native units. u64::MAX for no cap
+    pub max_flash_loan_amount: u64,
+    /// Token account receiving the protocol share of flash loan fees. The default pubkey for the
+    /// reserve fee receiver
+    pub flash_loan_fee_receiver: Pubkey,
+    /// Number of flash loans repaid
+    pub flash_loan_count: u64,
+    /// Liquidity flash borrowed and repaid, in native units
+    pub flash_loan_volume: u128,
+    /// Flash loan fees paid to the flash loan fee receiver, in native units
+    pub flash_loan_protocol_fees: u128,
+    /// Flash loan fees paid to host fee receivers, in native units
+    pub flash_loan_host_fees: u128,
+}
+
+impl FlashLoanConfig {
+    /// Create a new flash loan config for a reserve, allowing flash loans of any size
+    pub fn new(reserve: Pubkey, bump_seed: u8) -> Self {
+        Self {
+            version: PROGRAM_VERSION,
+            bump_seed,
+            reserve,
+            flash_loans_enabled: true,
+            max_flash_loan_amount: u64::MAX,
+            flash_loan_fee_receiver: Pubkey::default(),
+            flash_loan_count: 0,
+            flash_loan_volume: 0,
+            flash_loan_protocol_fees: 0,
+            flash_loan_host_fees: 0,
+        }
+    }
+
+    /// Token account the protocol share of flash loan fees has to be paid to
+    pub fn fee_receiver(&self, reserve_fee_receiver: &Pubkey) -> Pubkey {
+        if self.flash_loan_fee_receiver == Pubkey::default() {
+            *reserve_fee_receiver
+        } else {
+            self.flash_loan_fee_receiver
+        }
+    }
+
+    /// Adds a repaid flash loan of `liquidity_amount` and the fees paid on it to the counters
+    pub fn record_flash_loan(
+        &mut self,
+        liquidity_amount: u64,
+        protocol_fee: u64,
+        host_fee: u64,
+    ) -> Result<(), ProgramError> {
+        self.flash_loan_count = self
+            .flash_loan_count
+            .checked_add(1)
+            .ok_or(LendingError::MathOverflow)?;
+        self.flash_loan_volume = self
+            .flash_loan_volume
+            .checked_add(liquidity_amount as u128)
+            .ok_or(LendingError::MathOverflow)?;
+        self.flash_loan_protocol_fees = self
+            .flash_loan_protocol_fees
+            .checked_add(protocol_fee as u128)
+            .ok_or(LendingError::MathOverflow)?;
+        self.flash_loan_host_fees = self
+            .flash_loan_host_fees
+            .checked_add(host_fee as u128)
+            .ok_or(LendingError::MathOverflow)?;
+        Ok(())
+    }
+
+    /// Checks a flash borrow of `liquidity_amount` against the config
+    pub fn check_flash_borrow(&self, liquidity_amount: u64) -> Result<(), ProgramError> {
+        if !self.flash_loans_enabled {
+            msg!("Flash loans are disabled for this reserve");
+            return Err(LendingError::FlashLoansDisabled.into());
+        }
+        if liquidity_amount > self.max_flash_loan_amount {
+            msg!(
+                "Flash borrow of {} exceeds the reserve max flash loan amount of {}",
+                liquidity_amount,
+                self.max_flash_loan_amount
+            );
+            return Err(LendingError::FlashLoanTooLarge.into());
+        }
+        Ok(())
+    }
+}
+
+/// validates flash loan configs
+pub fn validate_flash_loan_config(
+    flash_loans_enabled: bool,
+    max_flash_loan_amount: u64,
+) -> Result<(), ProgramError> {
+    if flash_loans_enabled && max_flash_loan_amount == 0 {
+        msg!("Max flash loan amount must be positive, disable flash loans instead");
+        return Err(LendingError::InvalidConfig.into());
+    }
+    Ok(())
+}
+
+impl Sealed for FlashLoanConfig {}
+impl IsInitialized for FlashLoanConfig {
+    fn is_initialized(&self) -> bool {
+        self.version != UNINITIALIZED_VERSION
+    }
+}
+
+const FLASH_LOAN_CONFIG_LEN: usize = 171; // 1 + 1 + 32 + 1 + 8 + 32 + 8 + 16 + 16 + 16 + 40
+impl Pack for FlashLoanConfig {
+    const LEN: usize = FLASH_LOAN_CONFIG_LEN;
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let output = array_mut_ref![dst, 0, FLASH_LOAN_CONFIG_LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (
+            version,
+            bump_seed,
+            reserve,
+            flash_loans_enabled,
+            max_flash_loan_amount,
+            flash_loan_fee_receiver,
+            flash_loan_count,
+            flash_loan_volume,
+            flash_loan_protocol_fees,
+            flash_loan_host_fees,
+            _padding,
+        ) = mut_array_refs![
+            output,
+            1,
+            1,
+            PUBKEY_BYTES,
+            1,
+            8,
+            PUBKEY_BYTES,
+            8,
+            16,
+            16,
+            16,
+            40
+        ];
+
+        *version = self.version.to_le_bytes();
+        *bump_seed = self.bump_seed.to_le_bytes();
+        reserve.copy_from_slice(self.reserve.as_ref());
+        pack_bool(self.flash_loans_enabled, flash_loans_enabled);
+        *max_flash_loan_amount = self.max_flash_loan_amount.to_le_bytes();
+        flash_loan_fee_receiver.copy_from_slice(self.flash_loan_fee_receiver.as_ref());
+        *flash_loan_count = self.flash_loan_count.to_le_bytes();
+        *flash_loan_volume = self.flash_loan_volume.to_le_bytes();
+        *flash_loan_protocol_fees = self.flash_loan_protocol_fees.to_le_bytes();
+        *flash_loan_host_fees = self.flash_loan_host_fees.to_le_bytes();
+    }
+
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![src, 0, FLASH_LOAN_CONFIG_LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (
+            version,
+            bump_seed,
+            reserve,
+            flash_loans_enabled,
+            max_flash_loan_amount,
+            flash_loan_fee_receiver,
+            flash_loan_count,
+            flash_loan_volume,
+            flash_loan_protocol_fees,
+            flash_loan_host_fees,
+            _padding,
+        ) = array_refs![
+            input,
+            1,
+            1,
+            PUBKEY_BYTES,
+            1,
+            8,
+            PUBKEY_BYTES,
+            8,
+            16,
+            16,
+            16,
+            40
+        ];
+
+        let version = u8::from_le_bytes(*version);
+        if version > PROGRAM_VERSION {
+            msg!("Flash loan config version does not match lending program version");
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        Ok(Self {
+            version,
+            bump_seed: u8::from_le_bytes(*bump_seed),
+            reserve: Pubkey::new_from_array(*reserve),
+            flash_loans_enabled: unpack_bool(flash_loans_enabled)?,
+            max_flash_loan_amount: u64::from_le_bytes(*max_flash_loan_amount),
+            flash_loan_fee_receiver: Pubkey::new_from_array(*flash_loan_fee_receiver),
+            flash_loan_count: u64::from_le_bytes(*flash_loan_count),
+            flash_loan_volume: u128::from_le_bytes(*flash_loan_volume),
+            flash_loan_protocol_fees: u128::from_le_bytes(*flash_loan_protocol_fees),
+            flash_loan_host_fees: u128::from_le_bytes(*flash_loan_host_fees),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn pack_and_unpack_flash_loan_config() {
+        let mut rng = rand::thread_rng();
+        let config = FlashLoanConfig {
+            flash_loans_enabled: rng.gen(),
+            max_flash_loan_amount: rng.gen(),
+            flash_loan_fee_receiver: Pubkey::new_unique(),
+            flash_loan_count: rng.gen(),
+            flash_loan_volume: rng.gen(),
+            flash_loan_protocol_fees: rng.gen(),
+            flash_loan_host_fees: rng.gen(),
+            ..FlashLoanConfig::new(Pubkey::new_unique(), rng.gen())
+        };
+
+        let mut packed = [0u8; FlashLoanConfig::LEN];
+        FlashLoanConfig::pack(config.clone(), &mut packed).unwrap();
+        let unpacked = FlashLoanConfig::unpack(&packed).unwrap();
+        assert_eq!(config, unpacked);
+    }
+
+    #[test]
+    fn check_flash_borrow() {
+        let mut config = FlashLoanConfig::new(Pubkey::new_unique(), 255);
+        assert_eq!(config.check_flash_borrow(u64::MAX), Ok(()));
+
+        config.max_flash_loan_amount = 1_000;
+        assert_eq!(config.check_flash_borrow(1_000), Ok(()));
+        assert_eq!(
+            config.check_flash_borrow(1_001),
+            Err(LendingError::FlashLoanTooLarge.into())
+        );
+
+        config.flash_loans_enabled = false;
+        assert_eq!(
+            config.check_flash_borrow(1),
+            Err(LendingError::FlashLoansDisabled.into())
+        );
+    }
+
+    #[test]
+    fn flash_loan_fee_receiver() {
+        let reserve_fee_receiver = Pubkey::new_unique();
+        let mut config = FlashLoanConfig::new(Pubkey::new_unique(), 255);
+        assert_eq!(
+            config.fee_receiver(&reserve_fee_receiver),
+            reserve_fee_receiver
+        );
+
+        let flash_loan_fee_receiver = Pubkey::new_unique();
+        config.flash_loan_fee_receiver = flash_loan_fee_receiver;
+        assert_eq!(
+            config.fee_receiver(&reserve_fee_receiver),
+            flash_loan_fee_receiver
+        );
+    }
+
+    #[test]
+    fn record_flash_loan() {
+        let mut config = FlashLoanConfig::new(Pubkey::new_unique(), 255);
+        config.record_flash_loan(1_000_000, 2_400, 600).unwrap();
+        config.record_flash_loan(u64::MAX, 0, 0).unwrap();
+        assert_eq!(config.flash_loan_count, 2);
+        assert_eq!(config.flash_loan_volume, 1_000_000 + u64::MAX as u128);
+        assert_eq!(config.flash_loan_protocol_fees, 2_400);
+        assert_eq!(config.flash_loan_host_fees, 600);
+
+        config.flash_loan_count = u64::MAX;
+        assert_eq!(
+            config.record_flash_loan(1, 0, 0),
+            Err(LendingError::MathOverflow.into())
+        );
+    }
+
+    #[test]
+    fn validate_flash_loan_configs() {
+        assert_eq!(validate_flash_loan_config(true, u64::MAX), Ok(()));
+        assert_eq!(validate_flash_loan_config(true, 1), Ok(()));
+        assert_eq!(validate_flash_loan_config(false, 0), Ok(()));
+        assert_eq!(
+            validate_flash_loan_config(true, 0),
+            Err(LendingError::InvalidConfig.into())
+        );
+    }
+}
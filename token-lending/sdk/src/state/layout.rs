@@ -0,0 +1,95 @@
+/// Declares the byte layout of a packed account once and generates borrowed views over it.
+///
+/// ```ignore
+/// account_layout! {
+///     ExampleLayout, ExampleLayoutMut, EXAMPLE_LEN = 41 {
+///         version: 1,
+///         owner: PUBKEY_BYTES,
+///         _padding: 8,
+///     }
+/// }
+/// ```
+///
+/// expands to `const EXAMPLE_LEN: usize = 41`, a compile time assertion that the field sizes sum
+/// to `EXAMPLE_LEN`, and two structs holding one `&[u8; N]` (or `&mut [u8; N]`) per field, in
+/// declaration order. `Pack` impls destructure those structs instead of repeating the field
+/// sizes in both `pack_into_slice` and `unpack_from_slice`.
+macro_rules! account_layout {
+    (
+        $(#[$meta:meta])*
+        $layout:ident, $layout_mut:ident, $len:ident = $len_value:literal {
+            $($field:ident: $size:expr),+ $(,)?
+        }
+    ) => {
+        const $len: usize = $len_value;
+
+        const _: () = assert!(
+            0 $(+ $size)+ == $len,
+            concat!("field sizes of ", stringify!($layout), " do not add up to ", stringify!($len))
+        );
+
+        $(#[$meta])*
+        #[allow(dead_code)]
+        struct $layout<'a> {
+            $($field: &'a [u8; $size],)+
+        }
+
+        impl<'a> $layout<'a> {
+            fn new(src: &'a [u8]) -> Self {
+                let src = arrayref::array_ref![src, 0, $len];
+                #[allow(clippy::ptr_offset_with_cast)]
+                let ($($field,)+) = arrayref::array_refs![src, $($size),+];
+                Self { $($field,)+ }
+            }
+        }
+
+        $(#[$meta])*
+        #[allow(dead_code)]
+        struct $layout_mut<'a> {
+            $($field: &'a mut [u8; $size],)+
+        }
+
+        impl<'a> $layout_mut<'a> {
+            fn new(dst: &'a mut [u8]) -> Self {
+                let dst = arrayref::array_mut_ref![dst, 0, $len];
+                #[allow(clippy::ptr_offset_with_cast)]
+                let ($($field,)+) = arrayref::mut_array_refs![dst, $($size),+];
+                Self { $($field,)+ }
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use solana_program::pubkey::PUBKEY_BYTES;
+
+    account_layout! {
+        TestLayout, TestLayoutMut, TEST_LEN = 41 {
+            version: 1,
+            owner: PUBKEY_BYTES,
+            _padding: 8,
+        }
+    }
+
+    #[test]
+    fn layout_views_share_offsets() {
+        let mut data = [0u8; TEST_LEN];
+        {
+            let TestLayoutMut { version, owner, .. } = TestLayoutMut::new(&mut data);
+            *version = [7];
+            *owner = [1; PUBKEY_BYTES];
+        }
+        assert_eq!(data[0], 7);
+        assert_eq!(&data[1..33], &[1; PUBKEY_BYTES]);
+
+        let TestLayout {
+            version,
+            owner,
+            _padding,
+        } = TestLayout::new(&data);
+        assert_eq!(*version, [7]);
+        assert_eq!(*owner, [1; PUBKEY_BYTES]);
+        assert_eq!(*_padding, [0; 8]);
+    }
+}
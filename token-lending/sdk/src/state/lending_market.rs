@@ -6,6 +6,7 @@ use solana_program::{
     program_pack::{IsInitialized, Pack, Sealed},
     pubkey::{Pubkey, PUBKEY_BYTES},
 };
+use std::convert::TryFrom;
 
 /// Lending market state
 #[derive(Clone, Debug, Default, PartialEq, Eq)]
@@ -31,6 +32,18 @@ pub struct LendingMarket {
     pub whitelisted_liquidator: Option<Pubkey>,
     /// risk authority (additional pubkey used for setting params)
     pub risk_authority: Pubkey,
+    /// Slots per year used to compound interest in this market's reserves. Reserves accrue at it
+    /// whenever they are refreshed with the lending market account passed.
+    pub slots_per_year: u64,
+    /// Whether the market has obligation limits, which borrows, deposits and withdrawals then
+    /// have to be passed
+    pub has_obligation_limits: bool,
+    /// Whether the market has an enabled policy, which borrows, withdrawals and liquidations then
+    /// have to be passed
+    pub has_market_policy: bool,
+    /// Whether the market has an enabled liquidation circuit breaker, which liquidations then
+    /// have to be passed
+    pub has_liquidation_circuit_breaker: bool,
 }
 
 impl LendingMarket {
@@ -53,6 +66,10 @@ impl LendingMarket {
         self.rate_limiter = RateLimiter::default();
         self.whitelisted_liquidator = None;
         self.risk_authority = params.owner;
+        self.slots_per_year = SLOTS_PER_YEAR;
+        self.has_obligation_limits = false;
+        self.has_market_policy = false;
+        self.has_liquidation_circuit_breaker = false;
     }
 }
 
@@ -80,7 +97,7 @@ impl IsInitialized for LendingMarket {
     }
 }
 
-const LENDING_MARKET_LEN: usize = 290; // 1 + 1 + 32 + 32 + 32 + 32 + 32 + 56 + 32 + 40
+const LENDING_MARKET_LEN: usize = 290; // 1 + 1 + 32 + 32 + 32 + 32 + 32 + 56 + 32 + 32 + 4 + 1 + 1 + 1 + 1
 impl Pack for LendingMarket {
     const LEN: usize = LENDING_MARKET_LEN;
 
@@ -98,6 +115,10 @@ impl Pack for LendingMarket {
             rate_limiter,
             whitelisted_liquidator,
             risk_authority,
+            slots_per_year,
+            has_obligation_limits,
+            has_market_policy,
+            has_liquidation_circuit_breaker,
             _padding,
         ) = mut_array_refs![
             output,
@@ -111,7 +132,11 @@ impl Pack for LendingMarket {
             RATE_LIMITER_LEN,
             PUBKEY_BYTES,
             PUBKEY_BYTES,
-            8
+            4,
+            1,
+            1,
+            1,
+            1
         ];
 
         *version = self.version.to_le_bytes();
@@ -131,6 +156,16 @@ impl Pack for LendingMarket {
             }
         }
         risk_authority.copy_from_slice(self.risk_authority.as_ref());
+        // always within MIN_SLOTS_PER_YEAR..=MAX_SLOTS_PER_YEAR, which fits in a u32
+        *slots_per_year = u32::try_from(self.slots_per_year)
+            .unwrap_or(u32::MAX)
+            .to_le_bytes();
+        pack_bool(self.has_obligation_limits, has_obligation_limits);
+        pack_bool(self.has_market_policy, has_market_policy);
+        pack_bool(
+            self.has_liquidation_circuit_breaker,
+            has_liquidation_circuit_breaker,
+        );
     }
 
     /// Unpacks a byte buffer into a [LendingMarketInfo](struct.LendingMarketInfo.html)
@@ -148,6 +183,10 @@ impl Pack for LendingMarket {
             rate_limiter,
             whitelisted_liquidator,
             risk_authority,
+            slots_per_year,
+            has_obligation_limits,
+            has_market_policy,
+            has_liquidation_circuit_breaker,
             _padding,
         ) = array_refs![
             input,
@@ -161,7 +200,11 @@ impl Pack for LendingMarket {
             RATE_LIMITER_LEN,
             PUBKEY_BYTES,
             PUBKEY_BYTES,
-            8
+            4,
+            1,
+            1,
+            1,
+            1
         ];
 
         let version = u8::from_le_bytes(*version);
@@ -193,6 +236,14 @@ impl Pack for LendingMarket {
             } else {
                 Pubkey::new_from_array(*risk_authority)
             },
+            // markets created before slots per year was configurable have zeroes here
+            slots_per_year: match u32::from_le_bytes(*slots_per_year) {
+                0 => SLOTS_PER_YEAR,
+                slots_per_year => u64::from(slots_per_year),
+            },
+            has_obligation_limits: unpack_bool(has_obligation_limits)?,
+            has_market_policy: unpack_bool(has_market_policy)?,
+            has_liquidation_circuit_breaker: unpack_bool(has_liquidation_circuit_breaker)?,
         })
     }
 }
@@ -220,6 +271,10 @@ mod test {
                 Some(Pubkey::new_unique())
             },
             risk_authority: Pubkey::new_unique(),
+            slots_per_year: rng.gen_range(MIN_SLOTS_PER_YEAR..=MAX_SLOTS_PER_YEAR),
+            has_obligation_limits: rng.gen(),
+            has_market_policy: rng.gen(),
+            has_liquidation_circuit_breaker: rng.gen(),
         };
 
         let mut packed = vec![0u8; LendingMarket::LEN];
@@ -0,0 +1,247 @@
+use super::*;
+use crate::{error::LendingError, math::Decimal};
+use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
+use solana_program::{
+    clock::Slot,
+    msg,
+    program_error::ProgramError,
+    program_pack::{IsInitialized, Pack, Sealed},
+    pubkey::{Pubkey, PUBKEY_BYTES},
+};
+
+/// Seed used to derive the liquidation circuit breaker address of a lending market
+pub const LIQUIDATION_CIRCUIT_BREAKER_SEED: &[u8] = b"LiquidationCircuitBreaker";
+
+/// Circuit breaker on the value liquidated in a lending market. Lives in a PDA derived from
+/// [lending market, LIQUIDATION_CIRCUIT_BREAKER_SEED] since the lending market account has no
+/// free space left.
+///
+/// Liquidated value is tracked with a [RateLimiter] over a sliding window. The liquidation that
+/// uses up the last of the window's allowance goes through and trips the breaker, which pauses
+/// liquidations for `pause_duration` slots. This bounds the damage of a manipulated oracle price
+/// setting off mass liquidations.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LiquidationCircuitBreaker {
+    /// Version of the struct
+    pub version: u8,
+    /// Bump seed for the derived address
+    pub bump_seed: u8,
+    /// Lending market the breaker belongs to
+    pub lending_market: Pubkey,
+    /// Liquidated value in the quote currency, over a sliding window. A window duration of 0
+    /// disables the breaker.
+    pub rate_limiter: RateLimiter,
+    /// Number of slots liquidations are paused for once the breaker trips
+    pub pause_duration: u64,
+    /// Liquidations are paused until this slot, exclusive
+    pub paused_until_slot: Slot,
+}
+
+impl LiquidationCircuitBreaker {
+    /// Create a new liquidation circuit breaker for a lending market
+    pub fn new(lending_market: Pubkey, bump_seed: u8) -> Self {
+        let mut breaker = Self::default();
+        Self::init(&mut breaker, lending_market, bump_seed);
+        breaker
+    }
+
+    /// Initialize a liquidation circuit breaker. It starts out disabled.
+    pub fn init(&mut self, lending_market: Pubkey, bump_seed: u8) {
+        self.version = PROGRAM_VERSION;
+        self.bump_seed = bump_seed;
+        self.lending_market = lending_market;
+        self.rate_limiter = RateLimiter::new(RateLimiterConfig::default(), 0);
+        self.pause_duration = 0;
+        self.paused_until_slot = 0;
+    }
+
+    /// Set the window, max liquidated value and pause duration. Resets the liquidated value and
+    /// lifts any pause if the window or max value change.
+    pub fn set_config(&mut self, config: RateLimiterConfig, pause_duration: u64, slot: Slot) {
+        if config != self.rate_limiter.config {
+            self.rate_limiter = RateLimiter::new(config, slot);
+            self.paused_until_slot = 0;
+        }
+        self.pause_duration = pause_duration;
+    }
+
+    /// Whether the breaker limits liquidations at all
+    pub fn is_enabled(&self) -> bool {
+        self.rate_limiter.config.window_duration != 0
+    }
+
+    /// Whether liquidations are paused at a slot
+    pub fn is_paused(&self, slot: Slot) -> bool {
+        slot < self.paused_until_slot
+    }
+
+    /// Record the value of a liquidation. Errors if liquidations are paused, and trips the breaker
+    /// if this liquidation reaches the max liquidated value of the window.
+    pub fn record_liquidation(&mut self, slot: Slot, value: Decimal) -> Result<(), ProgramError> {
+        if !self.is_enabled() {
+            return Ok(());
+        }
+        if self.is_paused(slot) {
+            msg!(
+                "Liquidations are paused until slot {} by the liquidation circuit breaker",
+                self.paused_until_slot
+            );
+            return Err(LendingError::LiquidationCircuitBreakerTripped.into());
+        }
+
+        let remaining_value = self.rate_limiter.remaining_outflow(slot)?;
+        if value < remaining_value {
+            return self.rate_limiter.update(slot, value);
+        }
+
+        self.rate_limiter.update(slot, remaining_value)?;
+        self.paused_until_slot = slot.saturating_add(self.pause_duration);
+        msg!(
+            "Liquidation circuit breaker tripped, liquidations are paused until slot {}",
+            self.paused_until_slot
+        );
+        Ok(())
+    }
+}
+
+impl Sealed for LiquidationCircuitBreaker {}
+impl IsInitialized for LiquidationCircuitBreaker {
+    fn is_initialized(&self) -> bool {
+        self.version != UNINITIALIZED_VERSION
+    }
+}
+
+const LIQUIDATION_CIRCUIT_BREAKER_LEN: usize = 226; // 1 + 1 + 32 + 56 + 8 + 8 + 120
+impl Pack for LiquidationCircuitBreaker {
+    const LEN: usize = LIQUIDATION_CIRCUIT_BREAKER_LEN;
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let output = array_mut_ref![dst, 0, LIQUIDATION_CIRCUIT_BREAKER_LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (
+            version,
+            bump_seed,
+            lending_market,
+            rate_limiter,
+            pause_duration,
+            paused_until_slot,
+            _padding,
+        ) = mut_array_refs![output, 1, 1, PUBKEY_BYTES, RATE_LIMITER_LEN, 8, 8, 120];
+
+        *version = self.version.to_le_bytes();
+        *bump_seed = self.bump_seed.to_le_bytes();
+        lending_market.copy_from_slice(self.lending_market.as_ref());
+        self.rate_limiter.pack_into_slice(rate_limiter);
+        *pause_duration = self.pause_duration.to_le_bytes();
+        *paused_until_slot = self.paused_until_slot.to_le_bytes();
+    }
+
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![src, 0, LIQUIDATION_CIRCUIT_BREAKER_LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (
+            version,
+            bump_seed,
+            lending_market,
+            rate_limiter,
+            pause_duration,
+            paused_until_slot,
+            _padding,
+        ) = array_refs![input, 1, 1, PUBKEY_BYTES, RATE_LIMITER_LEN, 8, 8, 120];
+
+        let version = u8::from_le_bytes(*version);
+        if version > PROGRAM_VERSION {
+            msg!("Liquidation circuit breaker version does not match lending program version");
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        Ok(Self {
+            version,
+            bump_seed: u8::from_le_bytes(*bump_seed),
+            lending_market: Pubkey::new_from_array(*lending_market),
+            rate_limiter: RateLimiter::unpack_from_slice(rate_limiter)?,
+            pause_duration: u64::from_le_bytes(*pause_duration),
+            paused_until_slot: u64::from_le_bytes(*paused_until_slot),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn pack_and_unpack_liquidation_circuit_breaker() {
+        let mut rng = rand::thread_rng();
+        let breaker = LiquidationCircuitBreaker {
+            version: PROGRAM_VERSION,
+            bump_seed: rng.gen(),
+            lending_market: Pubkey::new_unique(),
+            rate_limiter: rand_rate_limiter(),
+            pause_duration: rng.gen(),
+            paused_until_slot: rng.gen(),
+        };
+
+        let mut packed = [0u8; LiquidationCircuitBreaker::LEN];
+        LiquidationCircuitBreaker::pack(breaker.clone(), &mut packed).unwrap();
+        let unpacked = LiquidationCircuitBreaker::unpack(&packed).unwrap();
+        assert_eq!(breaker, unpacked);
+    }
+
+    #[test]
+    fn record_liquidation_trips_breaker() {
+        let mut breaker = LiquidationCircuitBreaker::new(Pubkey::new_unique(), 255);
+
+        // disabled by default
+        breaker
+            .record_liquidation(10, Decimal::from(u64::MAX))
+            .unwrap();
+        assert!(!breaker.is_paused(10));
+
+        breaker.set_config(
+            RateLimiterConfig {
+                window_duration: 10,
+                max_outflow: 100,
+            },
+            50,
+            10,
+        );
+
+        breaker
+            .record_liquidation(10, Decimal::from(60u64))
+            .unwrap();
+        assert!(!breaker.is_paused(11));
+
+        // the liquidation that crosses the max goes through, later ones are paused
+        breaker
+            .record_liquidation(11, Decimal::from(60u64))
+            .unwrap();
+        assert_eq!(breaker.paused_until_slot, 61);
+        assert_eq!(
+            breaker.record_liquidation(60, Decimal::from(1u64)),
+            Err(LendingError::LiquidationCircuitBreakerTripped.into())
+        );
+
+        // by the time the pause is over the window has moved on
+        breaker
+            .record_liquidation(61, Decimal::from(60u64))
+            .unwrap();
+        assert!(!breaker.is_paused(62));
+
+        // changing the limits lifts the pause
+        breaker
+            .record_liquidation(62, Decimal::from(40u64))
+            .unwrap();
+        assert!(breaker.is_paused(62));
+        breaker.set_config(
+            RateLimiterConfig {
+                window_duration: 10,
+                max_outflow: 200,
+            },
+            50,
+            62,
+        );
+        assert!(!breaker.is_paused(62));
+    }
+}
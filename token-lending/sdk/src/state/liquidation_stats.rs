@@ -0,0 +1,223 @@
+use super::*;
+use crate::{
+    error::LendingError,
+    math::{Decimal, TryAdd, TryDiv, TrySub},
+};
+use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
+use solana_program::{
+    clock::Slot,
+    msg,
+    program_error::ProgramError,
+    program_pack::{IsInitialized, Pack, Sealed},
+    pubkey::{Pubkey, PUBKEY_BYTES},
+};
+
+/// Seed used to derive the liquidation stats address of a lending market
+pub const LIQUIDATION_STATS_SEED: &[u8] = b"LiquidationStats";
+
+/// Running totals over the liquidations of a lending market, to tune liquidation bonuses from
+/// observed executions. Lives in a PDA derived from [lending market, LIQUIDATION_STATS_SEED] and
+/// is only updated once the lending market owner has created it.
+///
+/// Values are in the quote currency at the oracle prices of the liquidation. The realized bonus
+/// is the value of the collateral seized over the value of the liquidity repaid, protocol fee
+/// included.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LiquidationStats {
+    /// Version of the struct
+    pub version: u8,
+    /// Bump seed for the derived address
+    pub bump_seed: u8,
+    /// Lending market the stats belong to
+    pub lending_market: Pubkey,
+    /// Number of liquidations
+    pub liquidation_count: u64,
+    /// Total value of the liquidity repaid by liquidators
+    pub total_repaid_value: Decimal,
+    /// Total value of the collateral seized over the liquidity repaid
+    pub total_bonus_value: Decimal,
+    /// Value repaid by the largest liquidation
+    pub max_repaid_value: Decimal,
+    /// Slot of the last liquidation
+    pub last_liquidation_slot: Slot,
+}
+
+impl LiquidationStats {
+    /// Create new liquidation stats for a lending market
+    pub fn new(lending_market: Pubkey, bump_seed: u8) -> Self {
+        Self {
+            version: PROGRAM_VERSION,
+            bump_seed,
+            lending_market,
+            ..Self::default()
+        }
+    }
+
+    /// Record a liquidation repaying `repaid_value` worth of liquidity for `seized_value` worth
+    /// of collateral
+    pub fn record_liquidation(
+        &mut self,
+        slot: Slot,
+        repaid_value: Decimal,
+        seized_value: Decimal,
+    ) -> Result<(), ProgramError> {
+        // seizing less than was repaid happens when the collateral is depleted
+        let bonus_value = if seized_value > repaid_value {
+            seized_value.try_sub(repaid_value)?
+        } else {
+            Decimal::zero()
+        };
+
+        self.liquidation_count = self
+            .liquidation_count
+            .checked_add(1)
+            .ok_or(LendingError::MathOverflow)?;
+        self.total_repaid_value = self.total_repaid_value.try_add(repaid_value)?;
+        self.total_bonus_value = self.total_bonus_value.try_add(bonus_value)?;
+        self.max_repaid_value = self.max_repaid_value.max(repaid_value);
+        self.last_liquidation_slot = slot;
+        Ok(())
+    }
+
+    /// Average value repaid by a liquidation, zero if there were none
+    pub fn average_repaid_value(&self) -> Result<Decimal, ProgramError> {
+        if self.liquidation_count == 0 {
+            return Ok(Decimal::zero());
+        }
+        self.total_repaid_value.try_div(self.liquidation_count)
+    }
+
+    /// Average realized bonus, as a fraction of the value repaid weighted by liquidation size.
+    /// Zero if there were no liquidations.
+    pub fn average_bonus_rate(&self) -> Result<Decimal, ProgramError> {
+        if self.total_repaid_value == Decimal::zero() {
+            return Ok(Decimal::zero());
+        }
+        self.total_bonus_value.try_div(self.total_repaid_value)
+    }
+}
+
+impl Sealed for LiquidationStats {}
+impl IsInitialized for LiquidationStats {
+    fn is_initialized(&self) -> bool {
+        self.version != UNINITIALIZED_VERSION
+    }
+}
+
+const LIQUIDATION_STATS_LEN: usize = 194; // 1 + 1 + 32 + 8 + 16 + 16 + 16 + 8 + 96
+impl Pack for LiquidationStats {
+    const LEN: usize = LIQUIDATION_STATS_LEN;
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let output = array_mut_ref![dst, 0, LIQUIDATION_STATS_LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (
+            version,
+            bump_seed,
+            lending_market,
+            liquidation_count,
+            total_repaid_value,
+            total_bonus_value,
+            max_repaid_value,
+            last_liquidation_slot,
+            _padding,
+        ) = mut_array_refs![output, 1, 1, PUBKEY_BYTES, 8, 16, 16, 16, 8, 96];
+
+        *version = self.version.to_le_bytes();
+        *bump_seed = self.bump_seed.to_le_bytes();
+        lending_market.copy_from_slice(self.lending_market.as_ref());
+        *liquidation_count = self.liquidation_count.to_le_bytes();
+        pack_decimal(self.total_repaid_value, total_repaid_value);
+        pack_decimal(self.total_bonus_value, total_bonus_value);
+        pack_decimal(self.max_repaid_value, max_repaid_value);
+        *last_liquidation_slot = self.last_liquidation_slot.to_le_bytes();
+    }
+
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![src, 0, LIQUIDATION_STATS_LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (
+            version,
+            bump_seed,
+            lending_market,
+            liquidation_count,
+            total_repaid_value,
+            total_bonus_value,
+            max_repaid_value,
+            last_liquidation_slot,
+            _padding,
+        ) = array_refs![input, 1, 1, PUBKEY_BYTES, 8, 16, 16, 16, 8, 96];
+
+        let version = u8::from_le_bytes(*version);
+        if version > PROGRAM_VERSION {
+            msg!("Liquidation stats version does not match lending program version");
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        Ok(Self {
+            version,
+            bump_seed: u8::from_le_bytes(*bump_seed),
+            lending_market: Pubkey::new_from_array(*lending_market),
+            liquidation_count: u64::from_le_bytes(*liquidation_count),
+            total_repaid_value: unpack_decimal(total_repaid_value),
+            total_bonus_value: unpack_decimal(total_bonus_value),
+            max_repaid_value: unpack_decimal(max_repaid_value),
+            last_liquidation_slot: u64::from_le_bytes(*last_liquidation_slot),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn pack_and_unpack_liquidation_stats() {
+        let mut rng = rand::thread_rng();
+        let stats = LiquidationStats {
+            version: PROGRAM_VERSION,
+            bump_seed: rng.gen(),
+            lending_market: Pubkey::new_unique(),
+            liquidation_count: rng.gen(),
+            total_repaid_value: Decimal::from_scaled_val(rng.gen()),
+            total_bonus_value: Decimal::from_scaled_val(rng.gen()),
+            max_repaid_value: Decimal::from_scaled_val(rng.gen()),
+            last_liquidation_slot: rng.gen(),
+        };
+
+        let mut packed = [0u8; LiquidationStats::LEN];
+        LiquidationStats::pack(stats.clone(), &mut packed).unwrap();
+        let unpacked = LiquidationStats::unpack(&packed).unwrap();
+        assert_eq!(stats, unpacked);
+    }
+
+    #[test]
+    fn record_liquidations() {
+        let mut stats = LiquidationStats::new(Pubkey::new_unique(), 255);
+        assert_eq!(stats.average_repaid_value().unwrap(), Decimal::zero());
+        assert_eq!(stats.average_bonus_rate().unwrap(), Decimal::zero());
+
+        stats
+            .record_liquidation(10, Decimal::from(100u64), Decimal::from(105u64))
+            .unwrap();
+        stats
+            .record_liquidation(20, Decimal::from(300u64), Decimal::from(330u64))
+            .unwrap();
+        // collateral ran out, no bonus
+        stats
+            .record_liquidation(30, Decimal::from(200u64), Decimal::from(150u64))
+            .unwrap();
+
+        assert_eq!(stats.liquidation_count, 3);
+        assert_eq!(stats.total_repaid_value, Decimal::from(600u64));
+        assert_eq!(stats.total_bonus_value, Decimal::from(35u64));
+        assert_eq!(stats.max_repaid_value, Decimal::from(300u64));
+        assert_eq!(stats.last_liquidation_slot, 30);
+        assert_eq!(stats.average_repaid_value().unwrap(), Decimal::from(200u64));
+        assert_eq!(
+            stats.average_bonus_rate().unwrap(),
+            Decimal::from(35u64).try_div(600u64).unwrap()
+        );
+    }
+}
@@ -0,0 +1,246 @@
+use super::*;
+use crate::error::LendingError;
+use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
+use solana_program::{
+    instruction::{AccountMeta, Instruction},
+    msg,
+    program_error::ProgramError,
+    program_pack::{IsInitialized, Pack, Sealed},
+    pubkey::{Pubkey, PUBKEY_BYTES},
+};
+use std::convert::TryFrom;
+
+/// Seed used to derive the market policy address of a lending market
+pub const MARKET_POLICY_SEED: &[u8] = b"MarketPolicy";
+
+/// Instruction data prefix of the `check_policy` call made to the policy program, the first 8
+/// bytes of sha256("global:check_policy") so Anchor programs can implement it as is
+pub const CHECK_POLICY_DISCRIMINATOR: [u8; 8] = [28, 88, 170, 179, 239, 136, 25, 35];
+
+/// External program a lending market asks before every borrow, withdrawal and liquidation, e.g.
+/// to restrict borrowing to KYC'd wallets. Lives in a PDA derived from
+/// [lending market, MARKET_POLICY_SEED] and is only written by the lending market owner.
+///
+/// The policy program is called with `check_policy` and aborts the instruction by failing. It is
+/// passed among the optional accounts of the instruction, along with the policy account if the
+/// market policy has one. The policy program only gets read access to a fixed set of accounts and
+/// no signatures, so it can't move funds or change state on behalf of the caller.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MarketPolicy {
+    /// Version of the struct
+    pub version: u8,
+    /// Bump seed for the derived address
+    pub bump_seed: u8,
+    /// Lending market the policy applies to
+    pub lending_market: Pubkey,
+    /// Program invoked with `check_policy`, the default pubkey when there is no policy
+    pub policy_program: Pubkey,
+    /// Account the policy program reads, such as its allowlist, the default pubkey for none
+    pub policy_account: Pubkey,
+}
+
+impl MarketPolicy {
+    /// Create a new market policy without a policy program
+    pub fn new(lending_market: Pubkey, bump_seed: u8) -> Self {
+        let mut market_policy = Self::default();
+        Self::init(&mut market_policy, lending_market, bump_seed);
+        market_policy
+    }
+
+    /// Initialize a market policy. It starts out without a policy program.
+    pub fn init(&mut self, lending_market: Pubkey, bump_seed: u8) {
+        self.version = PROGRAM_VERSION;
+        self.bump_seed = bump_seed;
+        self.lending_market = lending_market;
+        self.policy_program = Pubkey::default();
+        self.policy_account = Pubkey::default();
+    }
+
+    /// Whether the policy program has to be called
+    pub fn is_enabled(&self) -> bool {
+        self.policy_program != Pubkey::default()
+    }
+}
+
+/// Action a policy program is asked about
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum PolicyAction {
+    /// Borrowing liquidity from a reserve
+    Borrow = 0,
+    /// Withdrawing collateral from an obligation
+    Withdraw = 1,
+    /// Liquidating an obligation
+    Liquidate = 2,
+}
+
+impl TryFrom<u8> for PolicyAction {
+    type Error = ProgramError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(PolicyAction::Borrow),
+            1 => Ok(PolicyAction::Withdraw),
+            2 => Ok(PolicyAction::Liquidate),
+            _ => {
+                msg!("Policy action {} is invalid", value);
+                Err(LendingError::InstructionUnpackError.into())
+            }
+        }
+    }
+}
+
+/// Creates the `check_policy` instruction the lending program invokes the policy program with.
+/// The instruction data is [CHECK_POLICY_DISCRIMINATOR], the action as a u8 and the amount as a
+/// little endian u64: the liquidity amount to borrow, the collateral amount to withdraw or the
+/// liquidity amount to repay, u64::MAX standing for as much as possible.
+///
+/// The lending program has checked the signer signed the instruction before calling the policy
+/// program, but doesn't forward the signature. All accounts are passed read-only.
+///
+/// Accounts passed to the policy program:
+///
+///   0. `[]` Lending market account.
+///   1. `[]` Obligation account.
+///   2. `[]` Reserve borrowed from, withdrawn from or repaid to.
+///   3. `[]` Signer: the obligation owner or borrow delegate, or the liquidator.
+///   4. `[]` Policy account, if the market policy has one.
+#[allow(clippy::too_many_arguments)]
+pub fn check_policy(
+    policy_program: Pubkey,
+    action: PolicyAction,
+    amount: u64,
+    lending_market_pubkey: Pubkey,
+    obligation_pubkey: Pubkey,
+    reserve_pubkey: Pubkey,
+    signer_pubkey: Pubkey,
+    policy_account: Option<Pubkey>,
+) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new_readonly(lending_market_pubkey, false),
+        AccountMeta::new_readonly(obligation_pubkey, false),
+        AccountMeta::new_readonly(reserve_pubkey, false),
+        AccountMeta::new_readonly(signer_pubkey, false),
+    ];
+    if let Some(policy_account) = policy_account {
+        accounts.push(AccountMeta::new_readonly(policy_account, false));
+    }
+
+    let mut data = CHECK_POLICY_DISCRIMINATOR.to_vec();
+    data.push(action as u8);
+    data.extend_from_slice(&amount.to_le_bytes());
+    Instruction {
+        program_id: policy_program,
+        accounts,
+        data,
+    }
+}
+
+impl Sealed for MarketPolicy {}
+impl IsInitialized for MarketPolicy {
+    fn is_initialized(&self) -> bool {
+        self.version != UNINITIALIZED_VERSION
+    }
+}
+
+const MARKET_POLICY_LEN: usize = 130; // 1 + 1 + 32 + 32 + 32 + 32
+impl Pack for MarketPolicy {
+    const LEN: usize = MARKET_POLICY_LEN;
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let output = array_mut_ref![dst, 0, MARKET_POLICY_LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (version, bump_seed, lending_market, policy_program, policy_account, _padding) =
+            mut_array_refs![output, 1, 1, PUBKEY_BYTES, PUBKEY_BYTES, PUBKEY_BYTES, 32];
+
+        *version = self.version.to_le_bytes();
+        *bump_seed = self.bump_seed.to_le_bytes();
+        lending_market.copy_from_slice(self.lending_market.as_ref());
+        policy_program.copy_from_slice(self.policy_program.as_ref());
+        policy_account.copy_from_slice(self.policy_account.as_ref());
+    }
+
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![src, 0, MARKET_POLICY_LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (version, bump_seed, lending_market, policy_program, policy_account, _padding) =
+            array_refs![input, 1, 1, PUBKEY_BYTES, PUBKEY_BYTES, PUBKEY_BYTES, 32];
+
+        let version = u8::from_le_bytes(*version);
+        if version > PROGRAM_VERSION {
+            msg!("Market policy version does not match lending program version");
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        Ok(Self {
+            version,
+            bump_seed: u8::from_le_bytes(*bump_seed),
+            lending_market: Pubkey::new_from_array(*lending_market),
+            policy_program: Pubkey::new_from_array(*policy_program),
+            policy_account: Pubkey::new_from_array(*policy_account),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::Rng;
+    use solana_program::hash::hash;
+
+    #[test]
+    fn pack_and_unpack_market_policy() {
+        let market_policy = MarketPolicy {
+            version: PROGRAM_VERSION,
+            bump_seed: rand::thread_rng().gen(),
+            lending_market: Pubkey::new_unique(),
+            policy_program: Pubkey::new_unique(),
+            policy_account: Pubkey::new_unique(),
+        };
+
+        let mut packed = [0u8; MarketPolicy::LEN];
+        MarketPolicy::pack(market_policy.clone(), &mut packed).unwrap();
+        let unpacked = MarketPolicy::unpack(&packed).unwrap();
+        assert_eq!(market_policy, unpacked);
+    }
+
+    #[test]
+    fn check_policy_discriminator() {
+        assert_eq!(
+            CHECK_POLICY_DISCRIMINATOR,
+            hash(b"global:check_policy").to_bytes()[..8]
+        );
+    }
+
+    #[test]
+    fn check_policy_instruction() {
+        let policy_program = Pubkey::new_unique();
+        let policy_account = Pubkey::new_unique();
+        let instruction = check_policy(
+            policy_program,
+            PolicyAction::Withdraw,
+            1_000,
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Some(policy_account),
+        );
+
+        assert_eq!(instruction.program_id, policy_program);
+        assert_eq!(instruction.accounts.len(), 5);
+        assert!(instruction
+            .accounts
+            .iter()
+            .all(|meta| !meta.is_signer && !meta.is_writable));
+        assert_eq!(instruction.accounts[4].pubkey, policy_account);
+        assert_eq!(instruction.data[..8], CHECK_POLICY_DISCRIMINATOR);
+        assert_eq!(
+            PolicyAction::try_from(instruction.data[8]).unwrap(),
+            PolicyAction::Withdraw
+        );
+        assert_eq!(instruction.data[9..], 1_000u64.to_le_bytes());
+        assert!(PolicyAction::try_from(3).is_err());
+        assert!(!MarketPolicy::new(Pubkey::new_unique(), 255).is_enabled());
+    }
+}
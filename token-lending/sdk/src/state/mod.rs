@@ -1,18 +1,60 @@
 //! State types
 
+mod account_type;
+mod borrow_allowances;
+mod borrow_grace_config;
+mod config_timelock;
+mod cross_market_sources;
+mod deleverage_config;
+mod deleverage_credit;
+mod derived_account;
+mod dust_sweep_config;
+mod fee_rebates;
+mod fee_redemption_hook;
+mod flash_loan_config;
 mod last_update;
+#[macro_use]
+mod layout;
 mod lending_market;
 mod lending_market_metadata;
+mod liquidation_circuit_breaker;
+mod liquidation_stats;
+mod market_policy;
 mod obligation;
+mod obligation_borrow_cap;
+mod obligation_limits;
+mod pending_config;
 mod rate_limiter;
 mod reserve;
+mod reserve_accrual_history;
+mod staking_boost;
 
+pub use account_type::*;
+pub use borrow_allowances::*;
+pub use borrow_grace_config::*;
+pub use config_timelock::*;
+pub use cross_market_sources::*;
+pub use deleverage_config::*;
+pub use deleverage_credit::*;
+pub use derived_account::*;
+pub use dust_sweep_config::*;
+pub use fee_rebates::*;
+pub use fee_redemption_hook::*;
+pub use flash_loan_config::*;
 pub use last_update::*;
 pub use lending_market::*;
 pub use lending_market_metadata::*;
+pub use liquidation_circuit_breaker::*;
+pub use liquidation_stats::*;
+pub use market_policy::*;
 pub use obligation::*;
+pub use obligation_borrow_cap::*;
+pub use obligation_limits::*;
+pub use pending_config::*;
 pub use rate_limiter::*;
 pub use reserve::*;
+pub use reserve_accrual_history::*;
+pub use staking_boost::*;
 
 use crate::math::{Decimal, WAD};
 use solana_program::{msg, program_error::ProgramError};
@@ -33,6 +75,12 @@ pub const UNINITIALIZED_VERSION: u8 = 0;
 // 2 (slots per second) * 60 * 60 * 24 * 365 = 63072000
 pub const SLOTS_PER_YEAR: u64 = 63072000;
 
+/// Lowest slots per year a lending market can be configured with, ie 1 second slots
+pub const MIN_SLOTS_PER_YEAR: u64 = 31536000;
+
+/// Highest slots per year a lending market can be configured with, ie 200ms slots
+pub const MAX_SLOTS_PER_YEAR: u64 = 157680000;
+
 // Helpers
 fn pack_decimal(decimal: Decimal, dst: &mut [u8; 16]) {
     *dst = decimal
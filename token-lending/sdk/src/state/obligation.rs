@@ -1,9 +1,8 @@
 use super::*;
 use crate::{
     error::LendingError,
-    math::{Decimal, Rate, TryAdd, TryDiv, TryMul, TrySub},
+    math::{Bps, Decimal, Rate, TryAdd, TryDiv, TryMul, TrySub},
 };
-use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
 use solana_program::{
     clock::Slot,
     entrypoint::ProgramResult,
@@ -59,6 +58,23 @@ pub struct Obligation {
     pub super_unhealthy_borrow_value: Decimal,
     /// True if the obligation is currently borrowing an isolated tier asset
     pub borrowing_isolated_asset: bool,
+    /// What DepositReserveLiquidityWithMode does with the minted collateral when the instruction
+    /// doesn't say
+    pub default_deposit_mode: DepositMode,
+    /// Set by the owner to opt out of the lending market's health buffer on borrows and
+    /// withdrawals, down to the allowed borrow value
+    pub ignore_health_buffer: bool,
+    /// Whether the owner set a borrow cap, which borrows then have to be passed
+    pub has_borrow_cap: bool,
+    /// Whether the owner created a deleverage credit account, which borrows then have to be
+    /// passed to take back the credit
+    pub has_deleverage_credit: bool,
+    /// Slot the grace period of the obligation's interest-free borrow ends at, 0 without one. An
+    /// obligation has at most one interest-free borrow at a time.
+    pub grace_end_slot: Slot,
+    /// Interest the interest-free borrow accrued within its grace period that hasn't been waived
+    /// yet, see [Obligation::settle_grace_interest]
+    pub grace_interest_wads: Decimal,
 }
 
 impl Obligation {
@@ -79,6 +95,130 @@ impl Obligation {
         self.borrows = params.borrows;
     }
 
+    /// Recompute the market values of the deposits and borrows and the obligation's totals, as
+    /// RefreshObligation does. `deposit_reserves` and `borrow_reserves` are the reserves of
+    /// `deposits` and `borrows` in the same order, already refreshed.
+    pub fn refresh(
+        &mut self,
+        deposit_reserves: &[Reserve],
+        borrow_reserves: &[Reserve],
+        slot: Slot,
+    ) -> ProgramResult {
+        if deposit_reserves.len() != self.deposits.len()
+            || borrow_reserves.len() != self.borrows.len()
+        {
+            msg!("Reserves provided do not match the obligation deposits and borrows");
+            return Err(LendingError::InvalidAccountInput.into());
+        }
+
+        let mut deposited_value = Decimal::zero();
+        let mut borrowed_value = Decimal::zero();
+        let mut borrowed_value_upper_bound = Decimal::zero();
+        let mut allowed_borrow_value = Decimal::zero();
+        let mut unhealthy_borrow_value = Decimal::zero();
+        let mut super_unhealthy_borrow_value = Decimal::zero();
+
+        for (collateral, deposit_reserve) in self.deposits.iter_mut().zip(deposit_reserves) {
+            let liquidity_amount = deposit_reserve
+                .collateral_exchange_rate()?
+                .decimal_collateral_to_liquidity(collateral.deposited_amount.into())?;
+
+            let market_value = deposit_reserve.market_value(liquidity_amount)?;
+            let market_value_lower_bound =
+                deposit_reserve.market_value_lower_bound(liquidity_amount)?;
+
+            let loan_to_value_rate = deposit_reserve.loan_to_value_ratio();
+            let liquidation_threshold_rate =
+                Rate::from(deposit_reserve.config.liquidation_threshold());
+            let max_liquidation_threshold_rate =
+                Rate::from(deposit_reserve.config.max_liquidation_threshold());
+
+            collateral.market_value = market_value;
+            deposited_value = deposited_value.try_add(market_value)?;
+            allowed_borrow_value = allowed_borrow_value
+                .try_add(market_value_lower_bound.try_mul(loan_to_value_rate)?)?;
+            unhealthy_borrow_value = unhealthy_borrow_value
+                .try_add(market_value.try_mul(liquidation_threshold_rate)?)?;
+            super_unhealthy_borrow_value = super_unhealthy_borrow_value
+                .try_add(market_value.try_mul(max_liquidation_threshold_rate)?)?;
+        }
+
+        for (index, borrow_reserve) in borrow_reserves.iter().enumerate() {
+            self.accrue_borrow_interest(index, borrow_reserve, slot)?;
+        }
+
+        let mut borrowing_isolated_asset = false;
+        let mut max_borrow_weight = None;
+        for (index, (liquidity, borrow_reserve)) in
+            self.borrows.iter_mut().zip(borrow_reserves).enumerate()
+        {
+            if borrow_reserve.config.reserve_type == ReserveType::Isolated {
+                borrowing_isolated_asset = true;
+            }
+
+            let borrow_weight_and_pubkey = (
+                borrow_reserve.config.added_borrow_weight_bps,
+                liquidity.borrow_reserve,
+            );
+            max_borrow_weight = match max_borrow_weight {
+                None => {
+                    if liquidity.borrowed_amount_wads > Decimal::zero() {
+                        Some((borrow_weight_and_pubkey, index))
+                    } else {
+                        None
+                    }
+                }
+                Some((max_borrow_weight_and_pubkey, _)) => {
+                    if liquidity.borrowed_amount_wads > Decimal::zero()
+                        && borrow_weight_and_pubkey > max_borrow_weight_and_pubkey
+                    {
+                        Some((borrow_weight_and_pubkey, index))
+                    } else {
+                        max_borrow_weight
+                    }
+                }
+            };
+
+            let market_value = borrow_reserve.market_value(liquidity.borrowed_amount_wads)?;
+            let market_value_upper_bound =
+                borrow_reserve.market_value_upper_bound(liquidity.borrowed_amount_wads)?;
+            liquidity.market_value = market_value;
+
+            borrowed_value =
+                borrowed_value.try_add(market_value.try_mul(borrow_reserve.borrow_weight())?)?;
+            borrowed_value_upper_bound = borrowed_value_upper_bound
+                .try_add(market_value_upper_bound.try_mul(borrow_reserve.borrow_weight())?)?;
+        }
+
+        self.deposited_value = deposited_value;
+        self.borrowed_value = borrowed_value;
+        self.borrowed_value_upper_bound = borrowed_value_upper_bound;
+        self.borrowing_isolated_asset = borrowing_isolated_asset;
+
+        let global_unhealthy_borrow_value = Decimal::from(70000000u64);
+        let global_allowed_borrow_value = Decimal::from(65000000u64);
+
+        self.allowed_borrow_value = min(allowed_borrow_value, global_allowed_borrow_value);
+        self.unhealthy_borrow_value = min(unhealthy_borrow_value, global_unhealthy_borrow_value);
+        self.super_unhealthy_borrow_value =
+            min(super_unhealthy_borrow_value, global_unhealthy_borrow_value);
+
+        self.last_update.update_slot(slot);
+
+        // move the ObligationLiquidity with the max borrow weight to the front
+        if let Some((_, max_borrow_weight_index)) = max_borrow_weight {
+            self.borrows.swap(0, max_borrow_weight_index);
+        }
+
+        // filter out ObligationCollaterals and ObligationLiquiditys with an amount of zero
+        self.deposits
+            .retain(|collateral| collateral.deposited_amount > 0);
+        self.borrows
+            .retain(|liquidity| liquidity.borrowed_amount_wads > Decimal::zero());
+
+        Ok(())
+    }
+
     /// Calculate the current ratio of borrowed value to deposited value
     pub fn loan_to_value(&self) -> Result<Decimal, ProgramError> {
         self.borrowed_value.try_div(self.deposited_value)
@@ -88,6 +228,12 @@ impl Obligation {
     pub fn repay(&mut self, settle_amount: Decimal, liquidity_index: usize) -> ProgramResult {
         let liquidity = &mut self.borrows[liquidity_index];
         if settle_amount == liquidity.borrowed_amount_wads {
+            // the grace period ends with the interest-free borrow, along with interest nobody
+            // settled
+            if liquidity.rate_mode == BorrowRateMode::InterestFree {
+                self.grace_end_slot = 0;
+                self.grace_interest_wads = Decimal::zero();
+            }
             self.borrows.remove(liquidity_index);
         } else {
             liquidity.repay(settle_amount)?;
@@ -95,6 +241,106 @@ impl Obligation {
         Ok(())
     }
 
+    /// Accrue interest on `borrows[liquidity_index]` up to `slot`, see
+    /// [ObligationLiquidity::accrue_reserve_interest]. What an interest-free borrow accrues
+    /// within its grace period is added to the grace interest to waive, shared out by slots when
+    /// the accrual crosses the end of the grace period.
+    pub fn accrue_borrow_interest(
+        &mut self,
+        liquidity_index: usize,
+        reserve: &Reserve,
+        slot: Slot,
+    ) -> ProgramResult {
+        let liquidity = &mut self.borrows[liquidity_index];
+        let borrowed_amount_wads = liquidity.borrowed_amount_wads;
+        liquidity.accrue_reserve_interest(reserve, slot)?;
+
+        let accrual_slot = self.last_update.slot;
+        if liquidity.rate_mode != BorrowRateMode::InterestFree
+            || accrual_slot >= self.grace_end_slot
+            || slot <= accrual_slot
+        {
+            return Ok(());
+        }
+        let interest = liquidity
+            .borrowed_amount_wads
+            .try_sub(borrowed_amount_wads)?;
+        let grace_interest = if slot <= self.grace_end_slot {
+            interest
+        } else {
+            interest
+                .try_mul(self.grace_end_slot - accrual_slot)?
+                .try_div(slot - accrual_slot)?
+        };
+        self.grace_interest_wads = self.grace_interest_wads.try_add(grace_interest)?;
+        Ok(())
+    }
+
+    /// Borrow at the variable rate into `borrows[liquidity_index]`. An empty position borrowing
+    /// while the reserve has `grace_slots` is interest-free for that many slots from `slot`.
+    /// The grace period is tracked on the obligation, so opening one while the obligation
+    /// already has an interest-free borrow fails. Borrowing more into it within its grace period
+    /// doesn't extend it.
+    pub fn borrow_variable_rate(
+        &mut self,
+        liquidity_index: usize,
+        borrow_amount: Decimal,
+        reserve: &mut Reserve,
+        slot: Slot,
+        grace_slots: u64,
+    ) -> ProgramResult {
+        self.settle_grace_interest(liquidity_index, reserve, slot)?;
+        let liquidity = &mut self.borrows[liquidity_index];
+        match liquidity.rate_mode {
+            BorrowRateMode::Variable
+                if liquidity.borrowed_amount_wads == Decimal::zero() && grace_slots > 0 =>
+            {
+                if self.grace_end_slot != 0 {
+                    msg!("Obligation already has an interest-free borrow");
+                    return Err(LendingError::BorrowRateModeMismatch.into());
+                }
+                liquidity.rate_mode = BorrowRateMode::InterestFree;
+                liquidity.cumulative_borrow_rate_wads =
+                    reserve.liquidity.cumulative_borrow_rate_wads;
+                self.grace_end_slot = slot
+                    .checked_add(grace_slots)
+                    .ok_or(LendingError::MathOverflow)?;
+            }
+            BorrowRateMode::Variable | BorrowRateMode::InterestFree => {}
+            BorrowRateMode::Fixed => {
+                msg!("Cannot borrow at the variable rate into a fixed rate position");
+                return Err(LendingError::BorrowRateModeMismatch.into());
+            }
+        }
+        liquidity.borrow(borrow_amount)
+    }
+
+    /// Waive the grace interest of the interest-free borrow in `borrows[liquidity_index]` out of
+    /// the protocol fees of its reserve, see [ReserveLiquidity::waive_interest]. The borrower
+    /// owes what the fees can't fund. Once the grace period is over, the borrow converts to the
+    /// variable rate. The borrow has to have accrued interest up to `slot` already.
+    pub fn settle_grace_interest(
+        &mut self,
+        liquidity_index: usize,
+        reserve: &mut Reserve,
+        slot: Slot,
+    ) -> ProgramResult {
+        let liquidity = &mut self.borrows[liquidity_index];
+        if liquidity.rate_mode != BorrowRateMode::InterestFree {
+            return Ok(());
+        }
+        let waived_interest = reserve
+            .liquidity
+            .waive_interest(self.grace_interest_wads.min(liquidity.borrowed_amount_wads))?;
+        liquidity.borrowed_amount_wads = liquidity.borrowed_amount_wads.try_sub(waived_interest)?;
+        self.grace_interest_wads = Decimal::zero();
+        if slot >= self.grace_end_slot {
+            liquidity.rate_mode = BorrowRateMode::Variable;
+            self.grace_end_slot = 0;
+        }
+        Ok(())
+    }
+
     /// Withdraw collateral and remove it from deposits if zeroed out
     pub fn withdraw(&mut self, withdraw_amount: u64, collateral_index: usize) -> ProgramResult {
         let collateral = &mut self.deposits[collateral_index];
@@ -106,17 +352,154 @@ impl Obligation {
         Ok(())
     }
 
+    /// Move `collateral_amount` of the collateral deposited in `deposit_reserve` to
+    /// `destination`, u64::MAX for all of it. A new deposit in `destination` keeps the
+    /// collateral's liquidation priority. Market values are left for the next refresh.
+    pub fn transfer_collateral(
+        &mut self,
+        deposit_reserve: Pubkey,
+        collateral_amount: u64,
+        destination: &mut Obligation,
+    ) -> ProgramResult {
+        let (collateral, collateral_index) = self.find_collateral_in_deposits(deposit_reserve)?;
+        let collateral_amount = if collateral_amount == u64::MAX {
+            collateral.deposited_amount
+        } else {
+            collateral_amount
+        };
+        if collateral_amount == 0 || collateral_amount > collateral.deposited_amount {
+            msg!("Collateral amount to move must be between 1 and the deposited amount");
+            return Err(LendingError::InvalidAmount.into());
+        }
+        let liquidation_priority = collateral.liquidation_priority;
+
+        let destination_collateral =
+            destination.find_or_add_collateral_to_deposits(deposit_reserve)?;
+        if destination_collateral.deposited_amount == 0 {
+            destination_collateral.liquidation_priority = liquidation_priority;
+        }
+        destination_collateral.deposit(collateral_amount)?;
+        self.withdraw(collateral_amount, collateral_index)
+    }
+
+    /// Move `liquidity_amount` of the liquidity borrowed from `borrow_reserve` to `destination`,
+    /// u64::MAX for all of it. Fixed rate debt keeps its rate and maturity, with its share of the
+    /// fixed interest. Both positions have to have accrued interest up to the same slot if
+    /// `destination` already borrows from the reserve.
+    pub fn transfer_liquidity(
+        &mut self,
+        borrow_reserve: Pubkey,
+        liquidity_amount: u64,
+        destination: &mut Obligation,
+    ) -> ProgramResult {
+        let (liquidity, liquidity_index) = self.find_liquidity_in_borrows(borrow_reserve)?;
+        // the grace period belongs to the obligation, not the borrow
+        if liquidity.rate_mode == BorrowRateMode::InterestFree {
+            msg!("Interest-free borrows can't be moved to another obligation");
+            return Err(LendingError::BorrowRateModeMismatch.into());
+        }
+        let settle_amount = if liquidity_amount == u64::MAX {
+            liquidity.borrowed_amount_wads
+        } else {
+            Decimal::from(liquidity_amount)
+        };
+        if settle_amount == Decimal::zero() || settle_amount > liquidity.borrowed_amount_wads {
+            msg!("Liquidity amount to move must be between 1 and the borrowed amount");
+            return Err(LendingError::InvalidAmount.into());
+        }
+
+        let moved = ObligationLiquidity {
+            borrowed_amount_wads: settle_amount,
+            market_value: Decimal::zero(),
+            fixed_interest_per_year: liquidity.fixed_interest_settled(settle_amount)?,
+            ..liquidity.clone()
+        };
+        destination.add_liquidity(moved)?;
+        self.repay(settle_amount, liquidity_index)
+    }
+
+    /// Move all deposits and borrows of `source` into this obligation, leaving `source` empty
+    pub fn merge(&mut self, source: &mut Obligation) -> ProgramResult {
+        let deposit_reserves = source
+            .deposits
+            .iter()
+            .filter(|collateral| collateral.deposited_amount > 0)
+            .map(|collateral| collateral.deposit_reserve)
+            .collect::<Vec<_>>();
+        for deposit_reserve in deposit_reserves {
+            source.transfer_collateral(deposit_reserve, u64::MAX, self)?;
+        }
+
+        let borrow_reserves = source
+            .borrows
+            .iter()
+            .filter(|liquidity| liquidity.borrowed_amount_wads > Decimal::zero())
+            .map(|liquidity| liquidity.borrow_reserve)
+            .collect::<Vec<_>>();
+        for borrow_reserve in borrow_reserves {
+            source.transfer_liquidity(borrow_reserve, u64::MAX, self)?;
+        }
+
+        self.borrowing_isolated_asset |= source.borrowing_isolated_asset;
+        source.deposits.clear();
+        source.borrows.clear();
+        Ok(())
+    }
+
+    fn add_liquidity(&mut self, liquidity: ObligationLiquidity) -> ProgramResult {
+        let existing = self.find_or_add_liquidity_to_borrows(
+            liquidity.borrow_reserve,
+            liquidity.cumulative_borrow_rate_wads,
+        )?;
+        if existing.borrowed_amount_wads == Decimal::zero() {
+            *existing = liquidity;
+            return Ok(());
+        }
+
+        if existing.rate_mode != liquidity.rate_mode {
+            msg!("Cannot combine fixed and variable rate debt in one borrow position");
+            return Err(LendingError::BorrowRateModeMismatch.into());
+        }
+        if existing.cumulative_borrow_rate_wads != liquidity.cumulative_borrow_rate_wads
+            || existing.fixed_rate_accrual_slot != liquidity.fixed_rate_accrual_slot
+        {
+            msg!("Borrow positions must have accrued interest up to the same slot to be combined");
+            return Err(LendingError::ObligationStale.into());
+        }
+        existing.borrow(liquidity.borrowed_amount_wads)?;
+        existing.fixed_interest_per_year = existing
+            .fixed_interest_per_year
+            .checked_add(liquidity.fixed_interest_per_year)
+            .ok_or(LendingError::MathOverflow)?;
+        // the earlier maturity, so combining positions never extends a locked rate
+        existing.fixed_rate_maturity_slot = existing
+            .fixed_rate_maturity_slot
+            .min(liquidity.fixed_rate_maturity_slot);
+        Ok(())
+    }
+
     /// calculate the maximum amount of collateral that can be borrowed
     pub fn max_withdraw_amount(
         &self,
         collateral: &ObligationCollateral,
         withdraw_reserve: &Reserve,
+    ) -> Result<u64, ProgramError> {
+        self.max_withdraw_amount_within(collateral, withdraw_reserve, self.allowed_borrow_value)
+    }
+
+    /// Calculate the maximum collateral amount that can be withdrawn while keeping the borrowed
+    /// value below `allowed_borrow_value` instead of the obligation's own
+    pub fn max_withdraw_amount_within(
+        &self,
+        collateral: &ObligationCollateral,
+        withdraw_reserve: &Reserve,
+        allowed_borrow_value: Decimal,
     ) -> Result<u64, ProgramError> {
         if self.borrows.is_empty() {
             return Ok(collateral.deposited_amount);
         }
 
-        if self.allowed_borrow_value <= self.borrowed_value_upper_bound {
+        if allowed_borrow_value <= self.borrowed_value_upper_bound {
             return Ok(0);
         }
 
@@ -126,8 +509,7 @@ impl Obligation {
         }
 
         // max usd value that can be withdrawn
-        let max_withdraw_value = self
-            .allowed_borrow_value
+        let max_withdraw_value = allowed_borrow_value
             .try_sub(self.borrowed_value_upper_bound)?
             .try_div(loan_to_value_ratio)?;
 
@@ -151,11 +533,8 @@ impl Obligation {
             withdraw_reserve.liquidity.smoothed_market_price,
         );
 
-        let decimals = 10u64
-            .checked_pow(withdraw_reserve.liquidity.mint_decimals as u32)
-            .ok_or(LendingError::MathOverflow)?;
-
-        let max_withdraw_liquidity_amount = max_withdraw_value.try_mul(decimals)?.try_div(price)?;
+        let max_withdraw_liquidity_amount =
+            withdraw_reserve.liquidity_amount_at_price(max_withdraw_value, price)?;
 
         // convert max withdraw liquidity amount to max withdraw collateral amount
         Ok(min(
@@ -167,12 +546,35 @@ impl Obligation {
         ))
     }
 
+    /// Highest liquidation priority among the collateral that has value left to seize
+    pub fn max_liquidation_priority(&self) -> u8 {
+        self.deposits
+            .iter()
+            .filter(|collateral| collateral.market_value > Decimal::zero())
+            .map(|collateral| collateral.liquidation_priority)
+            .max()
+            .unwrap_or(0)
+    }
+
     /// Calculate the maximum liquidity value that can be borrowed
     pub fn remaining_borrow_value(&self) -> Result<Decimal, ProgramError> {
         self.allowed_borrow_value
             .try_sub(self.borrowed_value_upper_bound)
     }
 
+    /// Allowed borrow value left after keeping `health_buffer` of it free, or the whole allowed
+    /// borrow value if the owner opted out of the buffer
+    pub fn buffered_allowed_borrow_value(
+        &self,
+        health_buffer: Bps,
+    ) -> Result<Decimal, ProgramError> {
+        if self.ignore_health_buffer || health_buffer == Bps(0) {
+            return Ok(self.allowed_borrow_value);
+        }
+        self.allowed_borrow_value
+            .try_mul(Rate::one().try_sub(Rate::from(health_buffer))?)
+    }
+
     /// Calculate the maximum liquidation amount for a given liquidity
     pub fn max_liquidation_amount(
         &self,
@@ -308,6 +710,68 @@ impl IsInitialized for Obligation {
     }
 }
 
+/// Health values of a refreshed obligation, returned by `ViewObligationHealth` as return data
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ObligationHealth {
+    /// Market value of liquidity borrowed, weighted by borrow weight
+    pub borrowed_value: Decimal,
+    /// The maximum borrow value at the weighted average loan to value ratio
+    pub allowed_borrow_value: Decimal,
+    /// The dangerous borrow value at the weighted average liquidation threshold
+    pub unhealthy_borrow_value: Decimal,
+}
+
+impl From<&Obligation> for ObligationHealth {
+    fn from(obligation: &Obligation) -> Self {
+        Self {
+            borrowed_value: obligation.borrowed_value,
+            allowed_borrow_value: obligation.allowed_borrow_value,
+            unhealthy_borrow_value: obligation.unhealthy_borrow_value,
+        }
+    }
+}
+
+account_layout! {
+    /// Byte layout of packed obligation health return data
+    ObligationHealthLayout, ObligationHealthLayoutMut, OBLIGATION_HEALTH_LEN = 48 {
+        borrowed_value: 16,
+        allowed_borrow_value: 16,
+        unhealthy_borrow_value: 16,
+    }
+}
+
+impl Sealed for ObligationHealth {}
+
+impl Pack for ObligationHealth {
+    const LEN: usize = OBLIGATION_HEALTH_LEN;
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let ObligationHealthLayoutMut {
+            borrowed_value,
+            allowed_borrow_value,
+            unhealthy_borrow_value,
+        } = ObligationHealthLayoutMut::new(dst);
+
+        pack_decimal(self.borrowed_value, borrowed_value);
+        pack_decimal(self.allowed_borrow_value, allowed_borrow_value);
+        pack_decimal(self.unhealthy_borrow_value, unhealthy_borrow_value);
+    }
+
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let ObligationHealthLayout {
+            borrowed_value,
+            allowed_borrow_value,
+            unhealthy_borrow_value,
+        } = ObligationHealthLayout::new(src);
+
+        Ok(Self {
+            borrowed_value: unpack_decimal(borrowed_value),
+            allowed_borrow_value: unpack_decimal(allowed_borrow_value),
+            unhealthy_borrow_value: unpack_decimal(unhealthy_borrow_value),
+        })
+    }
+}
+
 /// Obligation collateral state
 #[derive(Clone, Debug, Default, PartialEq, Eq)]
 pub struct ObligationCollateral {
@@ -317,6 +781,10 @@ pub struct ObligationCollateral {
     pub deposited_amount: u64,
     /// Collateral market value in quote currency
     pub market_value: Decimal,
+    /// Order the owner wants collateral seized in on liquidation. Liquidators have to seize the
+    /// collateral with the highest priority first, collateral with equal priority in any order.
+    /// Defaults to 0, and is reset when the collateral is fully withdrawn.
+    pub liquidation_priority: u8,
 }
 
 impl ObligationCollateral {
@@ -326,6 +794,7 @@ impl ObligationCollateral {
             deposit_reserve,
             deposited_amount: 0,
             market_value: Decimal::zero(),
+            liquidation_priority: 0,
         }
     }
 
@@ -348,6 +817,42 @@ impl ObligationCollateral {
     }
 }
 
+/// What a deposit does with the collateral minted for the deposited liquidity
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DepositMode {
+    /// Deposit the collateral into the obligation, where it counts towards borrowing power and
+    /// can be liquidated
+    #[default]
+    Collateralize = 0,
+    /// Keep the collateral in the depositor's token account, out of reach of liquidations
+    Hold = 1,
+}
+
+impl DepositMode {
+    /// Deposit mode from its byte representation
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(DepositMode::Collateralize),
+            1 => Some(DepositMode::Hold),
+            _ => None,
+        }
+    }
+}
+
+/// How a borrow position accrues interest
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BorrowRateMode {
+    /// Compounds at the reserve's utilization based borrow rate
+    #[default]
+    Variable = 0,
+    /// Accrues simple interest at the reserve's fixed borrow rate, locked at borrow time
+    Fixed = 1,
+    /// Accrues at the variable rate, but the interest accrued until the end of the reserve's
+    /// borrow grace period is waived out of the reserve's protocol fees. Converts to the variable
+    /// rate once the grace period is over.
+    InterestFree = 2,
+}
+
 /// Obligation liquidity state
 #[derive(Clone, Debug, Default, PartialEq, Eq)]
 pub struct ObligationLiquidity {
@@ -359,6 +864,15 @@ pub struct ObligationLiquidity {
     pub borrowed_amount_wads: Decimal,
     /// Liquidity market value in quote currency
     pub market_value: Decimal,
+    /// Whether the position accrues interest at the variable or a fixed rate
+    pub rate_mode: BorrowRateMode,
+    /// Interest accrued per year in fixed rate mode, in liquidity tokens
+    pub fixed_interest_per_year: u64,
+    /// Slot after which the fixed rate expires and the position can be converted to the variable
+    /// rate
+    pub fixed_rate_maturity_slot: Slot,
+    /// Slot up to which fixed rate interest was accrued
+    pub fixed_rate_accrual_slot: Slot,
 }
 
 impl ObligationLiquidity {
@@ -369,15 +883,124 @@ impl ObligationLiquidity {
             cumulative_borrow_rate_wads,
             borrowed_amount_wads: Decimal::zero(),
             market_value: Decimal::zero(),
+            rate_mode: BorrowRateMode::Variable,
+            fixed_interest_per_year: 0,
+            fixed_rate_maturity_slot: 0,
+            fixed_rate_accrual_slot: 0,
         }
     }
 
     /// Decrease borrowed liquidity
     pub fn repay(&mut self, settle_amount: Decimal) -> ProgramResult {
+        let fixed_interest_settled = self.fixed_interest_settled(settle_amount)?;
         self.borrowed_amount_wads = self.borrowed_amount_wads.try_sub(settle_amount)?;
+        self.fixed_interest_per_year -= fixed_interest_settled;
         Ok(())
     }
 
+    /// Part of the fixed interest per year that stops accruing when `settle_amount` is repaid
+    pub fn fixed_interest_settled(&self, settle_amount: Decimal) -> Result<u64, ProgramError> {
+        if self.rate_mode != BorrowRateMode::Fixed || self.borrowed_amount_wads == Decimal::zero() {
+            return Ok(0);
+        }
+        if settle_amount >= self.borrowed_amount_wads {
+            return Ok(self.fixed_interest_per_year);
+        }
+        Decimal::from(self.fixed_interest_per_year)
+            .try_mul(settle_amount)?
+            .try_div(self.borrowed_amount_wads)?
+            .try_floor_u64()
+    }
+
+    /// Liquidity worth `value` at the price the borrow was last refreshed with, rounded down. The
+    /// value is capped at MAX_LIQUIDATABLE_VALUE_AT_ONCE, as a liquidation can't repay more.
+    pub fn liquidity_amount_for_value(&self, value: Decimal) -> Result<u64, ProgramError> {
+        if self.market_value == Decimal::zero() {
+            msg!("Obligation borrow value is zero");
+            return Err(LendingError::ObligationLiquidityEmpty.into());
+        }
+        self.borrowed_amount_wads
+            .try_mul(value.min(Decimal::from(MAX_LIQUIDATABLE_VALUE_AT_ONCE)))?
+            .try_div(self.market_value)?
+            .try_floor_u64()
+    }
+
+    /// Increase borrowed liquidity at the fixed rate of `reserve`, locking in the rate until the
+    /// fixed rate expires. Borrowing more into an existing fixed rate position adds to its
+    /// interest without extending its maturity. Returns the interest per year added.
+    pub fn borrow_fixed_rate(
+        &mut self,
+        borrow_amount: Decimal,
+        reserve: &Reserve,
+        slot: Slot,
+    ) -> Result<u64, ProgramError> {
+        let interest_per_year = reserve.fixed_interest_per_year(borrow_amount)?;
+        match self.rate_mode {
+            BorrowRateMode::Fixed => {}
+            BorrowRateMode::Variable if self.borrowed_amount_wads == Decimal::zero() => {
+                self.rate_mode = BorrowRateMode::Fixed;
+                self.fixed_interest_per_year = 0;
+                self.fixed_rate_maturity_slot = slot
+                    .checked_add(reserve.config.fixed_borrow_max_duration_slots)
+                    .ok_or(LendingError::MathOverflow)?;
+                self.fixed_rate_accrual_slot = slot;
+            }
+            BorrowRateMode::Variable | BorrowRateMode::InterestFree => {
+                msg!("Cannot borrow at a fixed rate into a variable rate position");
+                return Err(LendingError::BorrowRateModeMismatch.into());
+            }
+        }
+        self.borrow(borrow_amount)?;
+        self.fixed_interest_per_year = self
+            .fixed_interest_per_year
+            .checked_add(interest_per_year)
+            .ok_or(LendingError::MathOverflow)?;
+        Ok(interest_per_year)
+    }
+
+    /// Convert a fixed rate position past its maturity to the variable rate, moving its debt out
+    /// of the reserve's fixed rate bucket. Returns whether the position was converted.
+    pub fn convert_matured_fixed_rate(
+        &mut self,
+        reserve: &mut Reserve,
+        slot: Slot,
+    ) -> Result<bool, ProgramError> {
+        if self.rate_mode != BorrowRateMode::Fixed || slot < self.fixed_rate_maturity_slot {
+            return Ok(false);
+        }
+        reserve
+            .liquidity
+            .settle_fixed_rate_borrow(self, self.borrowed_amount_wads)?;
+        self.rate_mode = BorrowRateMode::Variable;
+        self.fixed_interest_per_year = 0;
+        self.fixed_rate_maturity_slot = 0;
+        self.fixed_rate_accrual_slot = 0;
+        self.cumulative_borrow_rate_wads = reserve.liquidity.cumulative_borrow_rate_wads;
+        Ok(true)
+    }
+
+    /// Accrue interest up to `slot` at the variable rate or the locked fixed rate. `reserve` has
+    /// to have accrued interest up to `slot` already.
+    pub fn accrue_reserve_interest(&mut self, reserve: &Reserve, slot: Slot) -> ProgramResult {
+        match self.rate_mode {
+            BorrowRateMode::Variable | BorrowRateMode::InterestFree => {
+                self.accrue_interest(reserve.liquidity.cumulative_borrow_rate_wads)
+            }
+            BorrowRateMode::Fixed => {
+                let slots_elapsed = slot.saturating_sub(self.fixed_rate_accrual_slot);
+                let interest = Decimal::from(self.fixed_interest_per_year)
+                    .try_mul(slots_elapsed)?
+                    .try_div(reserve.slots_per_year)?;
+                self.borrowed_amount_wads = self.borrowed_amount_wads.try_add(interest)?;
+                self.fixed_rate_accrual_slot = self.fixed_rate_accrual_slot.max(slot);
+                // kept current so the position picks up variable interest from the right point
+                // once converted
+                self.cumulative_borrow_rate_wads = reserve.liquidity.cumulative_borrow_rate_wads;
+                Ok(())
+            }
+        }
+    }
+
     /// Increase borrowed liquidity
     pub fn borrow(&mut self, borrow_amount: Decimal) -> ProgramResult {
         self.borrowed_amount_wads = self.borrowed_amount_wads.try_add(borrow_amount)?;
@@ -408,17 +1031,66 @@ impl ObligationLiquidity {
     }
 }
 
-const OBLIGATION_COLLATERAL_LEN: usize = 88; // 32 + 8 + 16 + 32
-const OBLIGATION_LIQUIDITY_LEN: usize = 112; // 32 + 16 + 16 + 16 + 32
-const OBLIGATION_LEN: usize = 1300; // 1 + 8 + 1 + 32 + 32 + 16 + 16 + 16 + 16 + 64 + 1 + 1 + (88 * 1) + (112 * 9)
-                                    // @TODO: break this up by obligation / collateral / liquidity https://git.io/JOCca
+account_layout! {
+    /// Byte layout of a packed obligation collateral
+    ObligationCollateralLayout, ObligationCollateralLayoutMut, OBLIGATION_COLLATERAL_LEN = 88 {
+        deposit_reserve: PUBKEY_BYTES,
+        deposited_amount: 8,
+        market_value: 16,
+        liquidation_priority: 1,
+        _padding_deposit: 31,
+    }
+}
+
+account_layout! {
+    /// Byte layout of a packed obligation liquidity
+    ObligationLiquidityLayout, ObligationLiquidityLayoutMut, OBLIGATION_LIQUIDITY_LEN = 112 {
+        borrow_reserve: PUBKEY_BYTES,
+        cumulative_borrow_rate_wads: 16,
+        borrowed_amount_wads: 16,
+        market_value: 16,
+        rate_mode: 1,
+        fixed_interest_per_year: 8,
+        fixed_rate_maturity_slot: 8,
+        fixed_rate_accrual_slot: 8,
+        _padding_borrow: 7,
+    }
+}
+
+account_layout! {
+    /// Byte layout of a packed obligation
+    ObligationLayout, ObligationLayoutMut, OBLIGATION_LEN = 1300 {
+        version: 1,
+        last_update_slot: 8,
+        last_update_stale: 1,
+        lending_market: PUBKEY_BYTES,
+        owner: PUBKEY_BYTES,
+        deposited_value: 16,
+        borrowed_value: 16,
+        allowed_borrow_value: 16,
+        unhealthy_borrow_value: 16,
+        borrowed_value_upper_bound: 16,
+        borrowing_isolated_asset: 1,
+        super_unhealthy_borrow_value: 16,
+        default_deposit_mode: 1,
+        ignore_health_buffer: 1,
+        has_borrow_cap: 1,
+        has_deleverage_credit: 1,
+        grace_end_slot: 8,
+        grace_interest_wads: 16,
+        _padding: 3,
+        deposits_len: 1,
+        borrows_len: 1,
+        data_flat: OBLIGATION_COLLATERAL_LEN
+            + (OBLIGATION_LIQUIDITY_LEN * (MAX_OBLIGATION_RESERVES - 1)),
+    }
+}
+
 impl Pack for Obligation {
     const LEN: usize = OBLIGATION_LEN;
 
     fn pack_into_slice(&self, dst: &mut [u8]) {
-        let output = array_mut_ref![dst, 0, OBLIGATION_LEN];
-        #[allow(clippy::ptr_offset_with_cast)]
-        let (
+        let ObligationLayoutMut {
             version,
             last_update_slot,
             last_update_stale,
@@ -431,29 +1103,17 @@ impl Pack for Obligation {
             borrowed_value_upper_bound,
             borrowing_isolated_asset,
             super_unhealthy_borrow_value,
-            _padding,
+            default_deposit_mode,
+            ignore_health_buffer,
+            has_borrow_cap,
+            has_deleverage_credit,
+            grace_end_slot,
+            grace_interest_wads,
             deposits_len,
             borrows_len,
             data_flat,
-        ) = mut_array_refs![
-            output,
-            1,
-            8,
-            1,
-            PUBKEY_BYTES,
-            PUBKEY_BYTES,
-            16,
-            16,
-            16,
-            16,
-            16,
-            1,
-            16,
-            31,
-            1,
-            1,
-            OBLIGATION_COLLATERAL_LEN + (OBLIGATION_LIQUIDITY_LEN * (MAX_OBLIGATION_RESERVES - 1))
-        ];
+            ..
+        } = ObligationLayoutMut::new(dst);
 
         // obligation
         *version = self.version.to_le_bytes();
@@ -471,6 +1131,12 @@ impl Pack for Obligation {
             self.super_unhealthy_borrow_value,
             super_unhealthy_borrow_value,
         );
+        *default_deposit_mode = (self.default_deposit_mode as u8).to_le_bytes();
+        pack_bool(self.ignore_health_buffer, ignore_health_buffer);
+        pack_bool(self.has_borrow_cap, has_borrow_cap);
+        pack_bool(self.has_deleverage_credit, has_deleverage_credit);
+        *grace_end_slot = self.grace_end_slot.to_le_bytes();
+        pack_decimal(self.grace_interest_wads, grace_interest_wads);
 
         *deposits_len = u8::try_from(self.deposits.len()).unwrap().to_le_bytes();
         *borrows_len = u8::try_from(self.borrows.len()).unwrap().to_le_bytes();
@@ -479,27 +1145,33 @@ impl Pack for Obligation {
 
         // deposits
         for collateral in &self.deposits {
-            let deposits_flat = array_mut_ref![data_flat, offset, OBLIGATION_COLLATERAL_LEN];
-            #[allow(clippy::ptr_offset_with_cast)]
-            let (deposit_reserve, deposited_amount, market_value, _padding_deposit) =
-                mut_array_refs![deposits_flat, PUBKEY_BYTES, 8, 16, 32];
+            let ObligationCollateralLayoutMut {
+                deposit_reserve,
+                deposited_amount,
+                market_value,
+                liquidation_priority,
+                ..
+            } = ObligationCollateralLayoutMut::new(&mut data_flat[offset..]);
             deposit_reserve.copy_from_slice(collateral.deposit_reserve.as_ref());
             *deposited_amount = collateral.deposited_amount.to_le_bytes();
             pack_decimal(collateral.market_value, market_value);
+            *liquidation_priority = collateral.liquidation_priority.to_le_bytes();
             offset += OBLIGATION_COLLATERAL_LEN;
         }
 
         // borrows
         for liquidity in &self.borrows {
-            let borrows_flat = array_mut_ref![data_flat, offset, OBLIGATION_LIQUIDITY_LEN];
-            #[allow(clippy::ptr_offset_with_cast)]
-            let (
+            let ObligationLiquidityLayoutMut {
                 borrow_reserve,
                 cumulative_borrow_rate_wads,
                 borrowed_amount_wads,
                 market_value,
-                _padding_borrow,
-            ) = mut_array_refs![borrows_flat, PUBKEY_BYTES, 16, 16, 16, 32];
+                rate_mode,
+                fixed_interest_per_year,
+                fixed_rate_maturity_slot,
+                fixed_rate_accrual_slot,
+                ..
+            } = ObligationLiquidityLayoutMut::new(&mut data_flat[offset..]);
             borrow_reserve.copy_from_slice(liquidity.borrow_reserve.as_ref());
             pack_decimal(
                 liquidity.cumulative_borrow_rate_wads,
@@ -507,15 +1179,17 @@ impl Pack for Obligation {
             );
             pack_decimal(liquidity.borrowed_amount_wads, borrowed_amount_wads);
             pack_decimal(liquidity.market_value, market_value);
+            *rate_mode = (liquidity.rate_mode as u8).to_le_bytes();
+            *fixed_interest_per_year = liquidity.fixed_interest_per_year.to_le_bytes();
+            *fixed_rate_maturity_slot = liquidity.fixed_rate_maturity_slot.to_le_bytes();
+            *fixed_rate_accrual_slot = liquidity.fixed_rate_accrual_slot.to_le_bytes();
             offset += OBLIGATION_LIQUIDITY_LEN;
         }
     }
 
     /// Unpacks a byte buffer into an [ObligationInfo](struct.ObligationInfo.html).
     fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
-        let input = array_ref![src, 0, OBLIGATION_LEN];
-        #[allow(clippy::ptr_offset_with_cast)]
-        let (
+        let ObligationLayout {
             version,
             last_update_slot,
             last_update_stale,
@@ -528,29 +1202,17 @@ impl Pack for Obligation {
             borrowed_value_upper_bound,
             borrowing_isolated_asset,
             super_unhealthy_borrow_value,
-            _padding,
+            default_deposit_mode,
+            ignore_health_buffer,
+            has_borrow_cap,
+            has_deleverage_credit,
+            grace_end_slot,
+            grace_interest_wads,
             deposits_len,
             borrows_len,
             data_flat,
-        ) = array_refs![
-            input,
-            1,
-            8,
-            1,
-            PUBKEY_BYTES,
-            PUBKEY_BYTES,
-            16,
-            16,
-            16,
-            16,
-            16,
-            1,
-            16,
-            31,
-            1,
-            1,
-            OBLIGATION_COLLATERAL_LEN + (OBLIGATION_LIQUIDITY_LEN * (MAX_OBLIGATION_RESERVES - 1))
-        ];
+            ..
+        } = ObligationLayout::new(src);
 
         let version = u8::from_le_bytes(*version);
         if version > PROGRAM_VERSION {
@@ -558,39 +1220,71 @@ impl Pack for Obligation {
             return Err(ProgramError::InvalidAccountData);
         }
 
+        let default_deposit_mode =
+            DepositMode::from_u8(default_deposit_mode[0]).ok_or_else(|| {
+                msg!("Obligation default deposit mode is invalid");
+                ProgramError::InvalidAccountData
+            })?;
+
         let deposits_len = u8::from_le_bytes(*deposits_len);
         let borrows_len = u8::from_le_bytes(*borrows_len);
+        if deposits_len as usize * OBLIGATION_COLLATERAL_LEN
+            + borrows_len as usize * OBLIGATION_LIQUIDITY_LEN
+            > data_flat.len()
+        {
+            msg!("Obligation deposits and borrows do not fit in the obligation");
+            return Err(ProgramError::InvalidAccountData);
+        }
         let mut deposits = Vec::with_capacity(deposits_len as usize + 1);
         let mut borrows = Vec::with_capacity(borrows_len as usize + 1);
 
         let mut offset = 0;
         for _ in 0..deposits_len {
-            let deposits_flat = array_ref![data_flat, offset, OBLIGATION_COLLATERAL_LEN];
-            #[allow(clippy::ptr_offset_with_cast)]
-            let (deposit_reserve, deposited_amount, market_value, _padding_deposit) =
-                array_refs![deposits_flat, PUBKEY_BYTES, 8, 16, 32];
+            let ObligationCollateralLayout {
+                deposit_reserve,
+                deposited_amount,
+                market_value,
+                liquidation_priority,
+                ..
+            } = ObligationCollateralLayout::new(&data_flat[offset..]);
             deposits.push(ObligationCollateral {
                 deposit_reserve: Pubkey::new(deposit_reserve),
                 deposited_amount: u64::from_le_bytes(*deposited_amount),
                 market_value: unpack_decimal(market_value),
+                liquidation_priority: u8::from_le_bytes(*liquidation_priority),
             });
             offset += OBLIGATION_COLLATERAL_LEN;
         }
         for _ in 0..borrows_len {
-            let borrows_flat = array_ref![data_flat, offset, OBLIGATION_LIQUIDITY_LEN];
-            #[allow(clippy::ptr_offset_with_cast)]
-            let (
+            let ObligationLiquidityLayout {
                 borrow_reserve,
                 cumulative_borrow_rate_wads,
                 borrowed_amount_wads,
                 market_value,
-                _padding_borrow,
-            ) = array_refs![borrows_flat, PUBKEY_BYTES, 16, 16, 16, 32];
+                rate_mode,
+                fixed_interest_per_year,
+                fixed_rate_maturity_slot,
+                fixed_rate_accrual_slot,
+                ..
+            } = ObligationLiquidityLayout::new(&data_flat[offset..]);
+            let rate_mode = match rate_mode[0] {
+                0 => BorrowRateMode::Variable,
+                1 => BorrowRateMode::Fixed,
+                2 => BorrowRateMode::InterestFree,
+                _ => {
+                    msg!("Obligation liquidity rate mode is invalid");
+                    return Err(ProgramError::InvalidAccountData);
+                }
+            };
             borrows.push(ObligationLiquidity {
                 borrow_reserve: Pubkey::new(borrow_reserve),
                 cumulative_borrow_rate_wads: unpack_decimal(cumulative_borrow_rate_wads),
                 borrowed_amount_wads: unpack_decimal(borrowed_amount_wads),
                 market_value: unpack_decimal(market_value),
+                rate_mode,
+                fixed_interest_per_year: u64::from_le_bytes(*fixed_interest_per_year),
+                fixed_rate_maturity_slot: u64::from_le_bytes(*fixed_rate_maturity_slot),
+                fixed_rate_accrual_slot: u64::from_le_bytes(*fixed_rate_accrual_slot),
             });
             offset += OBLIGATION_LIQUIDITY_LEN;
         }
@@ -612,6 +1306,12 @@ impl Pack for Obligation {
             unhealthy_borrow_value: unpack_decimal(unhealthy_borrow_value),
             super_unhealthy_borrow_value: unpack_decimal(super_unhealthy_borrow_value),
             borrowing_isolated_asset: unpack_bool(borrowing_isolated_asset)?,
+            default_deposit_mode,
+            ignore_health_buffer: unpack_bool(ignore_health_buffer)?,
+            has_borrow_cap: unpack_bool(has_borrow_cap)?,
+            has_deleverage_credit: unpack_bool(has_deleverage_credit)?,
+            grace_end_slot: u64::from_le_bytes(*grace_end_slot),
+            grace_interest_wads: unpack_decimal(grace_interest_wads),
         })
     }
 }
@@ -630,6 +1330,54 @@ mod test {
         Decimal::from_scaled_val(rand::thread_rng().gen())
     }
 
+    #[test]
+    fn max_liquidation_priority() {
+        let collateral = |liquidation_priority: u8, market_value: u64| ObligationCollateral {
+            market_value: Decimal::from(market_value),
+            liquidation_priority,
+            ..ObligationCollateral::new(Pubkey::new_unique())
+        };
+
+        let mut obligation = Obligation::default();
+        assert_eq!(obligation.max_liquidation_priority(), 0);
+
+        obligation.deposits = vec![collateral(1, 10), collateral(3, 10), collateral(2, 10)];
+        assert_eq!(obligation.max_liquidation_priority(), 3);
+
+        // collateral without value left to seize doesn't block the rest
+        obligation.deposits[1].market_value = Decimal::zero();
+        assert_eq!(obligation.max_liquidation_priority(), 2);
+    }
+
+    #[test]
+    fn liquidity_amount_for_value() {
+        // 2_000_000 tokens borrowed at $2
+        let liquidity = ObligationLiquidity {
+            borrowed_amount_wads: Decimal::from(2_000_000u64),
+            market_value: Decimal::from(4_000_000u64),
+            ..ObligationLiquidity::new(Pubkey::new_unique(), Decimal::one())
+        };
+        assert_eq!(
+            liquidity.liquidity_amount_for_value(Decimal::from(100u64)),
+            Ok(50)
+        );
+        // rounds down
+        assert_eq!(
+            liquidity.liquidity_amount_for_value(Decimal::from(399u64).try_div(100).unwrap()),
+            Ok(1)
+        );
+        assert_eq!(
+            liquidity.liquidity_amount_for_value(Decimal::from(u64::MAX)),
+            Ok(MAX_LIQUIDATABLE_VALUE_AT_ONCE / 2)
+        );
+
+        let empty = ObligationLiquidity::new(Pubkey::new_unique(), Decimal::one());
+        assert_eq!(
+            empty.liquidity_amount_for_value(Decimal::one()),
+            Err(LendingError::ObligationLiquidityEmpty.into())
+        );
+    }
+
     #[test]
     fn pack_and_unpack_obligation() {
         let mut rng = rand::thread_rng();
@@ -646,12 +1394,21 @@ mod test {
                     deposit_reserve: Pubkey::new_unique(),
                     deposited_amount: rng.gen(),
                     market_value: rand_decimal(),
+                    liquidation_priority: rng.gen(),
                 }],
                 borrows: vec![ObligationLiquidity {
                     borrow_reserve: Pubkey::new_unique(),
                     cumulative_borrow_rate_wads: rand_decimal(),
                     borrowed_amount_wads: rand_decimal(),
                     market_value: rand_decimal(),
+                    rate_mode: match rng.gen_range(0..3) {
+                        0 => BorrowRateMode::Variable,
+                        1 => BorrowRateMode::Fixed,
+                        _ => BorrowRateMode::InterestFree,
+                    },
+                    fixed_interest_per_year: rng.gen(),
+                    fixed_rate_maturity_slot: rng.gen(),
+                    fixed_rate_accrual_slot: rng.gen(),
                 }],
                 deposited_value: rand_decimal(),
                 borrowed_value: rand_decimal(),
@@ -660,6 +1417,16 @@ mod test {
                 unhealthy_borrow_value: rand_decimal(),
                 super_unhealthy_borrow_value: rand_decimal(),
                 borrowing_isolated_asset: rng.gen(),
+                default_deposit_mode: if rng.gen() {
+                    DepositMode::Hold
+                } else {
+                    DepositMode::Collateralize
+                },
+                ignore_health_buffer: rng.gen(),
+                has_borrow_cap: rng.gen(),
+                has_deleverage_credit: rng.gen(),
+                grace_end_slot: rng.gen(),
+                grace_interest_wads: rand_decimal(),
             };
 
             let mut packed = [0u8; OBLIGATION_LEN];
@@ -669,6 +1436,30 @@ mod test {
         }
     }
 
+    #[test]
+    fn pack_and_unpack_obligation_health() {
+        let obligation = Obligation {
+            borrowed_value: rand_decimal(),
+            allowed_borrow_value: rand_decimal(),
+            unhealthy_borrow_value: rand_decimal(),
+            ..Obligation::default()
+        };
+        let health = ObligationHealth::from(&obligation);
+
+        let mut packed = [0u8; OBLIGATION_HEALTH_LEN];
+        health.pack_into_slice(&mut packed);
+        let unpacked = ObligationHealth::unpack_from_slice(&packed).unwrap();
+        assert_eq!(unpacked.borrowed_value, obligation.borrowed_value);
+        assert_eq!(
+            unpacked.allowed_borrow_value,
+            obligation.allowed_borrow_value
+        );
+        assert_eq!(
+            unpacked.unhealthy_borrow_value,
+            obligation.unhealthy_borrow_value
+        );
+    }
+
     #[test]
     fn obligation_accrue_interest_failure() {
         assert_eq!(
@@ -793,6 +1584,473 @@ mod test {
         }
     }
 
+    #[test]
+    fn refresh() {
+        let reserve = |price: u64, added_borrow_weight_bps: u64| Reserve {
+            liquidity: ReserveLiquidity {
+                cumulative_borrow_rate_wads: Decimal::one(),
+                market_price: Decimal::from(price),
+                smoothed_market_price: Decimal::from(price),
+                ..ReserveLiquidity::default()
+            },
+            config: ReserveConfig {
+                loan_to_value_ratio: 50,
+                liquidation_threshold: 80,
+                max_liquidation_threshold: 90,
+                added_borrow_weight_bps,
+                ..ReserveConfig::default()
+            },
+            ..Reserve::default()
+        };
+        let deposit_reserves = [reserve(2, 0), reserve(1, 0)];
+        let borrow_reserves = [reserve(1, 0), reserve(1, 10_000), reserve(1, 0)];
+
+        let mut obligation = Obligation {
+            deposits: vec![
+                ObligationCollateral {
+                    deposited_amount: 50,
+                    ..ObligationCollateral::new(Pubkey::new_unique())
+                },
+                ObligationCollateral::new(Pubkey::new_unique()),
+            ],
+            borrows: vec![
+                ObligationLiquidity {
+                    borrowed_amount_wads: Decimal::from(10u64),
+                    ..ObligationLiquidity::new(Pubkey::new_unique(), Decimal::one())
+                },
+                ObligationLiquidity {
+                    borrowed_amount_wads: Decimal::from(5u64),
+                    ..ObligationLiquidity::new(Pubkey::new_unique(), Decimal::one())
+                },
+                ObligationLiquidity::new(Pubkey::new_unique(), Decimal::one()),
+            ],
+            ..Obligation::default()
+        };
+        let heaviest_borrow = obligation.borrows[1].borrow_reserve;
+
+        assert_eq!(
+            obligation.refresh(&deposit_reserves, &borrow_reserves[..2], 7),
+            Err(LendingError::InvalidAccountInput.into())
+        );
+
+        obligation
+            .refresh(&deposit_reserves, &borrow_reserves, 7)
+            .unwrap();
+        assert_eq!(obligation.deposited_value, Decimal::from(100u64));
+        assert_eq!(obligation.allowed_borrow_value, Decimal::from(50u64));
+        assert_eq!(obligation.unhealthy_borrow_value, Decimal::from(80u64));
+        assert_eq!(
+            obligation.super_unhealthy_borrow_value,
+            Decimal::from(90u64)
+        );
+        // the second borrow counts twice
+        assert_eq!(obligation.borrowed_value, Decimal::from(20u64));
+        assert_eq!(obligation.last_update.slot, 7);
+
+        // empty positions are dropped and the heaviest borrow comes first
+        assert_eq!(obligation.deposits.len(), 1);
+        assert_eq!(obligation.borrows.len(), 2);
+        assert_eq!(obligation.borrows[0].borrow_reserve, heaviest_borrow);
+    }
+
+    #[test]
+    fn fixed_rate_borrow() {
+        let mut reserve = Reserve {
+            last_update: LastUpdate::new(10),
+            liquidity: ReserveLiquidity {
+                available_amount: 1_000,
+                cumulative_borrow_rate_wads: Decimal::one(),
+                ..ReserveLiquidity::default()
+            },
+            config: ReserveConfig {
+                fixed_borrow_rate_bps: 1_000,
+                fixed_borrow_max_duration_slots: 100,
+                ..ReserveConfig::default()
+            },
+            slots_per_year: 1_000,
+            ..Reserve::default()
+        };
+        let borrow_amount = Decimal::from(100u64);
+
+        let mut variable = ObligationLiquidity {
+            borrowed_amount_wads: Decimal::one(),
+            ..ObligationLiquidity::new(Pubkey::new_unique(), Decimal::one())
+        };
+        assert_eq!(
+            variable.borrow_fixed_rate(borrow_amount, &reserve, 10),
+            Err(LendingError::BorrowRateModeMismatch.into())
+        );
+
+        // 10% of 100 a year
+        let mut liquidity = ObligationLiquidity::new(Pubkey::new_unique(), Decimal::one());
+        let interest_per_year = liquidity
+            .borrow_fixed_rate(borrow_amount, &reserve, 10)
+            .unwrap();
+        assert_eq!(interest_per_year, 10);
+        assert_eq!(liquidity.rate_mode, BorrowRateMode::Fixed);
+        assert_eq!(liquidity.fixed_rate_maturity_slot, 110);
+        reserve.liquidity.borrow(borrow_amount).unwrap();
+        reserve
+            .liquidity
+            .borrow_fixed_rate(borrow_amount, interest_per_year)
+            .unwrap();
+
+        // a tenth of a year later the position and the reserve both accrued 1
+        reserve.accrue_interest(110).unwrap();
+        reserve.last_update.update_slot(110);
+        liquidity.accrue_reserve_interest(&reserve, 110).unwrap();
+        assert_eq!(liquidity.borrowed_amount_wads, Decimal::from(101u64));
+        assert_eq!(
+            reserve.liquidity.borrowed_amount_wads,
+            Decimal::from(101u64)
+        );
+        assert_eq!(
+            reserve.liquidity.fixed_borrowed_amount_wads,
+            Decimal::from(101u64)
+        );
+
+        // repaying half stops half of the interest
+        let settle_amount = Decimal::from(101u64).try_div(2u64).unwrap();
+        reserve
+            .liquidity
+            .settle_fixed_rate_borrow(&liquidity, settle_amount)
+            .unwrap();
+        reserve.liquidity.repay(50, settle_amount).unwrap();
+        liquidity.repay(settle_amount).unwrap();
+        assert_eq!(liquidity.fixed_interest_per_year, 5);
+        assert_eq!(reserve.liquidity.fixed_interest_per_year, 5);
+        assert_eq!(reserve.liquidity.fixed_borrowed_amount_wads, settle_amount);
+
+        // matured, the rest moves to the variable rate
+        assert!(liquidity
+            .convert_matured_fixed_rate(&mut reserve, 110)
+            .unwrap());
+        assert_eq!(liquidity.rate_mode, BorrowRateMode::Variable);
+        assert_eq!(liquidity.fixed_interest_per_year, 0);
+        assert_eq!(liquidity.borrowed_amount_wads, settle_amount);
+        assert_eq!(reserve.liquidity.fixed_interest_per_year, 0);
+        assert_eq!(
+            reserve.liquidity.fixed_borrowed_amount_wads,
+            Decimal::zero()
+        );
+        assert!(!liquidity
+            .convert_matured_fixed_rate(&mut reserve, 111)
+            .unwrap());
+    }
+
+    fn grace_period_reserve() -> Reserve {
+        Reserve {
+            last_update: LastUpdate::new(10),
+            liquidity: ReserveLiquidity {
+                available_amount: 1_000,
+                cumulative_borrow_rate_wads: Decimal::one(),
+                accumulated_protocol_fees_wads: Decimal::from(10u64),
+                ..ReserveLiquidity::default()
+            },
+            // 10% a year at any utilization
+            config: ReserveConfig {
+                optimal_utilization_rate: 100,
+                max_utilization_rate: 100,
+                min_borrow_rate: 10,
+                optimal_borrow_rate: 10,
+                max_borrow_rate: 10,
+                fixed_borrow_rate_bps: 1_000,
+                ..ReserveConfig::default()
+            },
+            slots_per_year: 1_000,
+            ..Reserve::default()
+        }
+    }
+
+    fn accrue(reserve: &mut Reserve, obligation: &mut Obligation, slot: Slot) {
+        reserve.accrue_interest(slot).unwrap();
+        reserve.last_update.update_slot(slot);
+        obligation.accrue_borrow_interest(0, reserve, slot).unwrap();
+        obligation.last_update.update_slot(slot);
+    }
+
+    fn grace_period_obligation(reserve: &Reserve) -> Obligation {
+        Obligation {
+            last_update: LastUpdate::new(10),
+            borrows: vec![ObligationLiquidity::new(
+                Pubkey::new_unique(),
+                reserve.liquidity.cumulative_borrow_rate_wads,
+            )],
+            ..Obligation::default()
+        }
+    }
+
+    #[test]
+    fn interest_free_borrow() {
+        let mut reserve = grace_period_reserve();
+        let mut obligation = grace_period_obligation(&reserve);
+        let borrow_amount = Decimal::from(100u64);
+
+        reserve.liquidity.borrow(borrow_amount).unwrap();
+        obligation
+            .borrow_variable_rate(0, borrow_amount, &mut reserve, 10, 50)
+            .unwrap();
+        assert_eq!(
+            obligation.borrows[0].rate_mode,
+            BorrowRateMode::InterestFree
+        );
+        assert_eq!(obligation.grace_end_slot, 60);
+        assert_eq!(
+            obligation.borrows[0].borrow_fixed_rate(borrow_amount, &reserve, 10),
+            Err(LendingError::BorrowRateModeMismatch.into())
+        );
+
+        // interest accrues on both sides alike, and is recorded to be waived
+        accrue(&mut reserve, &mut obligation, 35);
+        assert_eq!(
+            obligation.borrows[0].borrowed_amount_wads,
+            reserve.liquidity.borrowed_amount_wads
+        );
+        let interest = reserve
+            .liquidity
+            .borrowed_amount_wads
+            .try_sub(borrow_amount)
+            .unwrap();
+        assert!(interest > Decimal::zero());
+        assert_eq!(obligation.grace_interest_wads, interest);
+
+        // borrowing more waives it out of the protocol fees, without extending the grace period
+        reserve.liquidity.borrow(borrow_amount).unwrap();
+        obligation
+            .borrow_variable_rate(0, borrow_amount, &mut reserve, 35, 50)
+            .unwrap();
+        assert_eq!(obligation.grace_end_slot, 60);
+        assert_eq!(obligation.grace_interest_wads, Decimal::zero());
+        assert_eq!(
+            obligation.borrows[0].borrowed_amount_wads,
+            Decimal::from(200u64)
+        );
+        assert_eq!(
+            reserve.liquidity.borrowed_amount_wads,
+            Decimal::from(200u64)
+        );
+        assert_eq!(
+            reserve.liquidity.accumulated_protocol_fees_wads,
+            Decimal::from(10u64).try_sub(interest).unwrap()
+        );
+
+        // accrued 45 slots, only the 25 up to the end of the grace period are waived
+        accrue(&mut reserve, &mut obligation, 80);
+        let borrowed_amount_wads = obligation.borrows[0].borrowed_amount_wads;
+        let reserve_borrowed_amount_wads = reserve.liquidity.borrowed_amount_wads;
+        let interest = borrowed_amount_wads.try_sub(Decimal::from(200u64)).unwrap();
+        let grace_interest = interest.try_mul(25u64).unwrap().try_div(45u64).unwrap();
+        assert_eq!(obligation.grace_interest_wads, grace_interest);
+
+        // settling past the grace period waives it off both sides alike, and converts the
+        // position to the variable rate
+        let protocol_fees = reserve.liquidity.accumulated_protocol_fees_wads;
+        obligation
+            .settle_grace_interest(0, &mut reserve, 80)
+            .unwrap();
+        assert_eq!(obligation.borrows[0].rate_mode, BorrowRateMode::Variable);
+        assert_eq!(obligation.grace_end_slot, 0);
+        assert_eq!(obligation.grace_interest_wads, Decimal::zero());
+        assert_eq!(
+            reserve.liquidity.accumulated_protocol_fees_wads,
+            protocol_fees.try_sub(grace_interest).unwrap()
+        );
+        assert_eq!(
+            obligation.borrows[0].borrowed_amount_wads,
+            borrowed_amount_wads.try_sub(grace_interest).unwrap()
+        );
+        assert_eq!(
+            reserve.liquidity.borrowed_amount_wads,
+            reserve_borrowed_amount_wads
+                .try_sub(grace_interest)
+                .unwrap()
+        );
+
+        accrue(&mut reserve, &mut obligation, 90);
+        assert_eq!(obligation.grace_interest_wads, Decimal::zero());
+    }
+
+    #[test]
+    fn interest_free_borrow_exceeding_protocol_fees() {
+        let mut reserve = grace_period_reserve();
+        reserve.liquidity.accumulated_protocol_fees_wads = Decimal::from_percent(10);
+        let mut obligation = grace_period_obligation(&reserve);
+        let borrow_amount = Decimal::from(100u64);
+        reserve.liquidity.borrow(borrow_amount).unwrap();
+        obligation
+            .borrow_variable_rate(0, borrow_amount, &mut reserve, 10, 50)
+            .unwrap();
+        accrue(&mut reserve, &mut obligation, 60);
+        let borrowed_amount_wads = reserve.liquidity.borrowed_amount_wads;
+        let total_supply = reserve.liquidity.total_supply().unwrap();
+
+        // the fees only fund part of the interest, the borrower owes the rest
+        obligation
+            .settle_grace_interest(0, &mut reserve, 60)
+            .unwrap();
+        assert_eq!(
+            reserve.liquidity.accumulated_protocol_fees_wads,
+            Decimal::zero()
+        );
+        assert_eq!(
+            reserve.liquidity.borrowed_amount_wads,
+            borrowed_amount_wads
+                .try_sub(Decimal::from_percent(10))
+                .unwrap()
+        );
+        assert_eq!(
+            obligation.borrows[0].borrowed_amount_wads,
+            reserve.liquidity.borrowed_amount_wads
+        );
+        assert_eq!(reserve.liquidity.total_supply().unwrap(), total_supply);
+        assert_eq!(obligation.grace_interest_wads, Decimal::zero());
+    }
+
+    #[test]
+    fn one_interest_free_borrow_per_obligation() {
+        let mut reserve = grace_period_reserve();
+        let mut obligation = grace_period_obligation(&reserve);
+        obligation
+            .borrow_variable_rate(0, Decimal::from(100u64), &mut reserve, 10, 50)
+            .unwrap();
+
+        // another reserve with a grace period can't open a second one while it's running, nor
+        // after it ended until the interest-free borrow is settled
+        obligation.borrows.push(ObligationLiquidity::new(
+            Pubkey::new_unique(),
+            Decimal::one(),
+        ));
+        for slot in [10, 60, 70] {
+            assert_eq!(
+                obligation.borrow_variable_rate(1, Decimal::from(100u64), &mut reserve, slot, 50),
+                Err(LendingError::BorrowRateModeMismatch.into())
+            );
+        }
+        assert_eq!(obligation.borrows[1].borrowed_amount_wads, Decimal::zero());
+        assert_eq!(obligation.grace_end_slot, 60);
+
+        // reserves without one still lend to it at the variable rate
+        obligation
+            .borrow_variable_rate(1, Decimal::from(100u64), &mut reserve, 10, 0)
+            .unwrap();
+        assert_eq!(obligation.borrows[1].rate_mode, BorrowRateMode::Variable);
+
+        // nor can the interest-free borrow be moved out
+        let borrow_reserve = obligation.borrows[0].borrow_reserve;
+        assert_eq!(
+            obligation.transfer_liquidity(borrow_reserve, 1, &mut Obligation::default()),
+            Err(LendingError::BorrowRateModeMismatch.into())
+        );
+
+        // repaying it in full ends the grace period
+        obligation.repay(Decimal::from(100u64), 0).unwrap();
+        assert_eq!(obligation.grace_end_slot, 0);
+    }
+
+    #[test]
+    fn transfer_and_merge_positions() {
+        let deposit_reserve = Pubkey::new_unique();
+        let borrow_reserve = Pubkey::new_unique();
+        let fixed_reserve = Pubkey::new_unique();
+        let mut source = Obligation {
+            deposits: vec![ObligationCollateral {
+                deposited_amount: 100,
+                liquidation_priority: 2,
+                ..ObligationCollateral::new(deposit_reserve)
+            }],
+            borrows: vec![
+                ObligationLiquidity {
+                    borrowed_amount_wads: Decimal::from(50u64),
+                    ..ObligationLiquidity::new(borrow_reserve, Decimal::one())
+                },
+                ObligationLiquidity {
+                    borrowed_amount_wads: Decimal::from(100u64),
+                    rate_mode: BorrowRateMode::Fixed,
+                    fixed_interest_per_year: 10,
+                    fixed_rate_maturity_slot: 200,
+                    fixed_rate_accrual_slot: 10,
+                    ..ObligationLiquidity::new(fixed_reserve, Decimal::one())
+                },
+            ],
+            ..Obligation::default()
+        };
+        let mut destination = Obligation::default();
+
+        assert_eq!(
+            source.transfer_collateral(deposit_reserve, 101, &mut destination),
+            Err(LendingError::InvalidAmount.into())
+        );
+        assert_eq!(
+            source.transfer_liquidity(Pubkey::new_unique(), 1, &mut destination),
+            Err(LendingError::InvalidObligationLiquidity.into())
+        );
+
+        // partial moves split the position, keeping its priority and fixed rate terms
+        source
+            .transfer_collateral(deposit_reserve, 40, &mut destination)
+            .unwrap();
+        source
+            .transfer_liquidity(fixed_reserve, 30, &mut destination)
+            .unwrap();
+        assert_eq!(source.deposits[0].deposited_amount, 60);
+        assert_eq!(destination.deposits[0].deposited_amount, 40);
+        assert_eq!(destination.deposits[0].liquidation_priority, 2);
+        assert_eq!(source.borrows[1].fixed_interest_per_year, 7);
+        assert_eq!(
+            destination.borrows[0],
+            ObligationLiquidity {
+                borrowed_amount_wads: Decimal::from(30u64),
+                rate_mode: BorrowRateMode::Fixed,
+                fixed_interest_per_year: 3,
+                fixed_rate_maturity_slot: 200,
+                fixed_rate_accrual_slot: 10,
+                ..ObligationLiquidity::new(fixed_reserve, Decimal::one())
+            }
+        );
+
+        // whole positions move out entirely
+        source
+            .transfer_liquidity(borrow_reserve, u64::MAX, &mut destination)
+            .unwrap();
+        assert_eq!(source.borrows.len(), 1);
+        assert_eq!(
+            destination.borrows[1].borrowed_amount_wads,
+            Decimal::from(50u64)
+        );
+
+        // fixed and variable debt can't be combined
+        let mut variable = Obligation {
+            borrows: vec![ObligationLiquidity {
+                borrowed_amount_wads: Decimal::one(),
+                ..ObligationLiquidity::new(fixed_reserve, Decimal::one())
+            }],
+            ..Obligation::default()
+        };
+        assert_eq!(
+            variable.merge(&mut source.clone()),
+            Err(LendingError::BorrowRateModeMismatch.into())
+        );
+
+        // neither can debt that accrued up to different slots
+        let mut stale = source.clone();
+        stale.borrows[0].fixed_rate_accrual_slot = 5;
+        assert_eq!(
+            destination.clone().merge(&mut stale),
+            Err(LendingError::ObligationStale.into())
+        );
+
+        destination.merge(&mut source).unwrap();
+        assert!(source.deposits.is_empty() && source.borrows.is_empty());
+        assert_eq!(destination.deposits.len(), 1);
+        assert_eq!(destination.deposits[0].deposited_amount, 100);
+        assert_eq!(destination.borrows.len(), 2);
+        assert_eq!(
+            destination.borrows[0].borrowed_amount_wads,
+            Decimal::from(100u64)
+        );
+        assert_eq!(destination.borrows[0].fixed_interest_per_year, 10);
+    }
+
     #[test]
     fn max_liquidation_amount_normal() {
         let obligation_liquidity = ObligationLiquidity {
@@ -1064,4 +2322,71 @@ mod test {
             assert_eq!(max_withdraw_amount, test_case.expected_max_withdraw_amount);
         }
     }
+
+    #[test]
+    fn max_withdraw_amount_within_health_buffer() {
+        let mut obligation = Obligation {
+            deposits: vec![ObligationCollateral {
+                deposited_amount: 20 * LAMPORTS_PER_SOL,
+                ..ObligationCollateral::default()
+            }],
+            borrows: vec![ObligationLiquidity {
+                borrowed_amount_wads: Decimal::from(10u64),
+                ..ObligationLiquidity::default()
+            }],
+            allowed_borrow_value: Decimal::from(100u64),
+            borrowed_value_upper_bound: Decimal::from(50u64),
+            ..Obligation::default()
+        };
+        let reserve = Reserve {
+            config: ReserveConfig {
+                loan_to_value_ratio: 50,
+                ..ReserveConfig::default()
+            },
+            liquidity: ReserveLiquidity {
+                available_amount: 100 * LAMPORTS_PER_SOL,
+                market_price: Decimal::from(10u64),
+                smoothed_market_price: Decimal::from(5u64),
+                mint_decimals: 9,
+                ..ReserveLiquidity::default()
+            },
+            collateral: ReserveCollateral {
+                mint_total_supply: 50 * LAMPORTS_PER_SOL,
+                ..ReserveCollateral::default()
+            },
+            ..Reserve::default()
+        };
+
+        // a 20% buffer leaves $80 of allowed borrow value, so $30 / 0.5 = $60 can be withdrawn
+        // => 12 SOL => 6 cSOL
+        let buffered = obligation
+            .buffered_allowed_borrow_value(Bps(2_000))
+            .unwrap();
+        assert_eq!(buffered, Decimal::from(80u64));
+        assert_eq!(
+            obligation
+                .max_withdraw_amount_within(&obligation.deposits[0], &reserve, buffered)
+                .unwrap(),
+            6 * LAMPORTS_PER_SOL
+        );
+
+        // a buffer above the remaining room leaves nothing to withdraw
+        let buffered = obligation
+            .buffered_allowed_borrow_value(Bps(5_000))
+            .unwrap();
+        assert_eq!(
+            obligation
+                .max_withdraw_amount_within(&obligation.deposits[0], &reserve, buffered)
+                .unwrap(),
+            0
+        );
+
+        obligation.ignore_health_buffer = true;
+        assert_eq!(
+            obligation
+                .buffered_allowed_borrow_value(Bps(2_000))
+                .unwrap(),
+            obligation.allowed_borrow_value
+        );
+    }
 }
@@ -0,0 +1,146 @@
+use super::*;
+use crate::{
+    error::LendingError,
+    math::{Decimal, TrySub},
+};
+use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
+use solana_program::{
+    entrypoint::ProgramResult,
+    msg,
+    program_error::ProgramError,
+    program_pack::{IsInitialized, Pack, Sealed},
+    pubkey::{Pubkey, PUBKEY_BYTES},
+};
+
+/// Seed used to derive the borrow cap address of an obligation
+pub const OBLIGATION_BORROW_CAP_SEED: &[u8] = b"ObligationBorrowCap";
+
+/// Limit an obligation owner puts on their own borrowed value, on top of what the collateral
+/// allows. Lives in a PDA derived from [obligation, OBLIGATION_BORROW_CAP_SEED] since the
+/// obligation account has no free space left. Obligations without one are only limited by their
+/// collateral.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ObligationBorrowCap {
+    /// Version of the struct
+    pub version: u8,
+    /// Bump seed for the derived address
+    pub bump_seed: u8,
+    /// Obligation the cap applies to
+    pub obligation: Pubkey,
+    /// Max borrowed value of the obligation, in whole units of the quote currency
+    pub max_borrowed_value: u64,
+}
+
+impl ObligationBorrowCap {
+    /// Create a new borrow cap for an obligation
+    pub fn new(obligation: Pubkey, bump_seed: u8, max_borrowed_value: u64) -> Self {
+        Self {
+            version: PROGRAM_VERSION,
+            bump_seed,
+            obligation,
+            max_borrowed_value,
+        }
+    }
+
+    /// Value that can still be borrowed by an obligation that borrowed `borrowed_value`
+    pub fn remaining_borrow_value(&self, borrowed_value: Decimal) -> Decimal {
+        Decimal::from(self.max_borrowed_value)
+            .try_sub(borrowed_value)
+            .unwrap_or_else(|_| Decimal::zero())
+    }
+
+    /// Check the borrowed value of an obligation that just borrowed
+    pub fn check_borrowed_value(&self, borrowed_value: Decimal) -> ProgramResult {
+        if borrowed_value > Decimal::from(self.max_borrowed_value) {
+            msg!(
+                "Obligation borrowed value {} would exceed the borrow cap of {} set by its owner",
+                borrowed_value,
+                self.max_borrowed_value
+            );
+            return Err(LendingError::ObligationBorrowCapExceeded.into());
+        }
+        Ok(())
+    }
+}
+
+impl Sealed for ObligationBorrowCap {}
+impl IsInitialized for ObligationBorrowCap {
+    fn is_initialized(&self) -> bool {
+        self.version != UNINITIALIZED_VERSION
+    }
+}
+
+const OBLIGATION_BORROW_CAP_LEN: usize = 98; // 1 + 1 + 32 + 8 + 56
+impl Pack for ObligationBorrowCap {
+    const LEN: usize = OBLIGATION_BORROW_CAP_LEN;
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let output = array_mut_ref![dst, 0, OBLIGATION_BORROW_CAP_LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (version, bump_seed, obligation, max_borrowed_value, _padding) =
+            mut_array_refs![output, 1, 1, PUBKEY_BYTES, 8, 56];
+
+        *version = self.version.to_le_bytes();
+        *bump_seed = self.bump_seed.to_le_bytes();
+        obligation.copy_from_slice(self.obligation.as_ref());
+        *max_borrowed_value = self.max_borrowed_value.to_le_bytes();
+    }
+
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![src, 0, OBLIGATION_BORROW_CAP_LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (version, bump_seed, obligation, max_borrowed_value, _padding) =
+            array_refs![input, 1, 1, PUBKEY_BYTES, 8, 56];
+
+        let version = u8::from_le_bytes(*version);
+        if version > PROGRAM_VERSION {
+            msg!("Obligation borrow cap version does not match lending program version");
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        Ok(Self {
+            version,
+            bump_seed: u8::from_le_bytes(*bump_seed),
+            obligation: Pubkey::new_from_array(*obligation),
+            max_borrowed_value: u64::from_le_bytes(*max_borrowed_value),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn pack_and_unpack_obligation_borrow_cap() {
+        let mut rng = rand::thread_rng();
+        let borrow_cap = ObligationBorrowCap::new(Pubkey::new_unique(), rng.gen(), rng.gen());
+
+        let mut packed = [0u8; ObligationBorrowCap::LEN];
+        ObligationBorrowCap::pack(borrow_cap.clone(), &mut packed).unwrap();
+        let unpacked = ObligationBorrowCap::unpack(&packed).unwrap();
+        assert_eq!(borrow_cap, unpacked);
+    }
+
+    #[test]
+    fn borrow_cap_is_inclusive() {
+        let borrow_cap = ObligationBorrowCap::new(Pubkey::new_unique(), 255, 10_000);
+        assert_eq!(
+            borrow_cap.remaining_borrow_value(Decimal::from(2_500u64)),
+            Decimal::from(7_500u64)
+        );
+        assert_eq!(
+            borrow_cap.remaining_borrow_value(Decimal::from(20_000u64)),
+            Decimal::zero()
+        );
+
+        assert!(borrow_cap
+            .check_borrowed_value(Decimal::from(10_000u64))
+            .is_ok());
+        assert_eq!(
+            borrow_cap.check_borrowed_value(Decimal::from(10_001u64)),
+            Err(LendingError::ObligationBorrowCapExceeded.into())
+        );
+    }
+}
@@ -0,0 +1,242 @@
+use super::*;
+use crate::{error::LendingError, math::Bps};
+use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
+use solana_program::{
+    entrypoint::ProgramResult,
+    msg,
+    program_error::ProgramError,
+    program_pack::{IsInitialized, Pack, Sealed},
+    pubkey::{Pubkey, PUBKEY_BYTES},
+};
+
+/// Seed used to derive the obligation limits address of a lending market
+pub const OBLIGATION_LIMITS_SEED: &[u8] = b"ObligationLimits";
+
+/// Max health buffer, in bps of the allowed borrow value
+pub const MAX_HEALTH_BUFFER_BPS: u16 = 5_000;
+
+/// Market wide limits on the number of distinct deposits and borrows an obligation can have,
+/// below the MAX_OBLIGATION_RESERVES hard cap. Refreshing and liquidating an obligation costs
+/// compute for every position, so this bounds the worst case. Also holds the health buffer user
+/// initiated borrows and withdrawals keep below the allowed borrow value. Lives in a PDA derived
+/// from [lending market, OBLIGATION_LIMITS_SEED] since the lending market account has no free
+/// space left. Markets without one only have the hard cap and no buffer.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ObligationLimits {
+    /// Version of the struct
+    pub version: u8,
+    /// Bump seed for the derived address
+    pub bump_seed: u8,
+    /// Lending market the limits apply to
+    pub lending_market: Pubkey,
+    /// Max number of reserves an obligation can deposit into
+    pub max_deposits: u8,
+    /// Max number of reserves an obligation can borrow from
+    pub max_borrows: u8,
+    /// Share of the allowed borrow value, in bps, borrows and withdrawals can't eat into. Only
+    /// applies to user initiated borrows and withdrawals, liquidations ignore it.
+    pub health_buffer_bps: u16,
+}
+
+impl ObligationLimits {
+    /// Create new obligation limits for a lending market
+    pub fn new(lending_market: Pubkey, bump_seed: u8) -> Self {
+        let mut limits = Self::default();
+        Self::init(&mut limits, lending_market, bump_seed);
+        limits
+    }
+
+    /// Initialize obligation limits. They start out at the hard cap.
+    pub fn init(&mut self, lending_market: Pubkey, bump_seed: u8) {
+        self.version = PROGRAM_VERSION;
+        self.bump_seed = bump_seed;
+        self.lending_market = lending_market;
+        self.max_deposits = MAX_OBLIGATION_RESERVES as u8;
+        self.max_borrows = MAX_OBLIGATION_RESERVES as u8;
+        self.health_buffer_bps = 0;
+    }
+
+    /// Set the limits, which must be in range [1, MAX_OBLIGATION_RESERVES]
+    pub fn set_limits(&mut self, max_deposits: u8, max_borrows: u8) -> ProgramResult {
+        for limit in [max_deposits, max_borrows] {
+            if limit == 0 || limit as usize > MAX_OBLIGATION_RESERVES {
+                msg!(
+                    "Obligation deposit and borrow limits must be in range [1, {}]",
+                    MAX_OBLIGATION_RESERVES
+                );
+                return Err(LendingError::InvalidConfig.into());
+            }
+        }
+        self.max_deposits = max_deposits;
+        self.max_borrows = max_borrows;
+        Ok(())
+    }
+
+    /// Set the health buffer, which must be at most MAX_HEALTH_BUFFER_BPS
+    pub fn set_health_buffer(&mut self, health_buffer_bps: u16) -> ProgramResult {
+        if health_buffer_bps > MAX_HEALTH_BUFFER_BPS {
+            msg!(
+                "Health buffer must be at most {} bps",
+                MAX_HEALTH_BUFFER_BPS
+            );
+            return Err(LendingError::InvalidConfig.into());
+        }
+        self.health_buffer_bps = health_buffer_bps;
+        Ok(())
+    }
+
+    /// Health buffer kept below the allowed borrow value
+    pub fn health_buffer(&self) -> Bps {
+        Bps(self.health_buffer_bps as u64)
+    }
+
+    /// Check the number of deposits of an obligation that just added a deposit
+    pub fn check_deposits(&self, deposits_len: usize) -> ProgramResult {
+        if deposits_len > self.max_deposits as usize {
+            msg!(
+                "Obligation cannot deposit into more than {} reserves in this lending market",
+                self.max_deposits
+            );
+            return Err(LendingError::ObligationPositionLimit.into());
+        }
+        Ok(())
+    }
+
+    /// Check the number of borrows of an obligation that just added a borrow
+    pub fn check_borrows(&self, borrows_len: usize) -> ProgramResult {
+        if borrows_len > self.max_borrows as usize {
+            msg!(
+                "Obligation cannot borrow from more than {} reserves in this lending market",
+                self.max_borrows
+            );
+            return Err(LendingError::ObligationPositionLimit.into());
+        }
+        Ok(())
+    }
+}
+
+impl Sealed for ObligationLimits {}
+impl IsInitialized for ObligationLimits {
+    fn is_initialized(&self) -> bool {
+        self.version != UNINITIALIZED_VERSION
+    }
+}
+
+const OBLIGATION_LIMITS_LEN: usize = 100; // 1 + 1 + 32 + 1 + 1 + 2 + 62
+impl Pack for ObligationLimits {
+    const LEN: usize = OBLIGATION_LIMITS_LEN;
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let output = array_mut_ref![dst, 0, OBLIGATION_LIMITS_LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (
+            version,
+            bump_seed,
+            lending_market,
+            max_deposits,
+            max_borrows,
+            health_buffer_bps,
+            _padding,
+        ) = mut_array_refs![output, 1, 1, PUBKEY_BYTES, 1, 1, 2, 62];
+
+        *version = self.version.to_le_bytes();
+        *bump_seed = self.bump_seed.to_le_bytes();
+        lending_market.copy_from_slice(self.lending_market.as_ref());
+        *max_deposits = self.max_deposits.to_le_bytes();
+        *max_borrows = self.max_borrows.to_le_bytes();
+        *health_buffer_bps = self.health_buffer_bps.to_le_bytes();
+    }
+
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![src, 0, OBLIGATION_LIMITS_LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (
+            version,
+            bump_seed,
+            lending_market,
+            max_deposits,
+            max_borrows,
+            health_buffer_bps,
+            _padding,
+        ) = array_refs![input, 1, 1, PUBKEY_BYTES, 1, 1, 2, 62];
+
+        let version = u8::from_le_bytes(*version);
+        if version > PROGRAM_VERSION {
+            msg!("Obligation limits version does not match lending program version");
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        Ok(Self {
+            version,
+            bump_seed: u8::from_le_bytes(*bump_seed),
+            lending_market: Pubkey::new_from_array(*lending_market),
+            max_deposits: u8::from_le_bytes(*max_deposits),
+            max_borrows: u8::from_le_bytes(*max_borrows),
+            health_buffer_bps: u16::from_le_bytes(*health_buffer_bps),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn pack_and_unpack_obligation_limits() {
+        let mut rng = rand::thread_rng();
+        let limits = ObligationLimits {
+            version: PROGRAM_VERSION,
+            bump_seed: rng.gen(),
+            lending_market: Pubkey::new_unique(),
+            max_deposits: rng.gen(),
+            max_borrows: rng.gen(),
+            health_buffer_bps: rng.gen(),
+        };
+
+        let mut packed = [0u8; ObligationLimits::LEN];
+        ObligationLimits::pack(limits.clone(), &mut packed).unwrap();
+        let unpacked = ObligationLimits::unpack(&packed).unwrap();
+        assert_eq!(limits, unpacked);
+    }
+
+    #[test]
+    fn set_and_check_limits() {
+        let mut limits = ObligationLimits::new(Pubkey::new_unique(), 255);
+        limits.check_deposits(MAX_OBLIGATION_RESERVES).unwrap();
+
+        assert_eq!(
+            limits.set_limits(0, 1),
+            Err(LendingError::InvalidConfig.into())
+        );
+        assert_eq!(
+            limits.set_limits(1, MAX_OBLIGATION_RESERVES as u8 + 1),
+            Err(LendingError::InvalidConfig.into())
+        );
+
+        limits.set_limits(3, 1).unwrap();
+        limits.check_deposits(3).unwrap();
+        assert_eq!(
+            limits.check_deposits(4),
+            Err(LendingError::ObligationPositionLimit.into())
+        );
+        limits.check_borrows(1).unwrap();
+        assert_eq!(
+            limits.check_borrows(2),
+            Err(LendingError::ObligationPositionLimit.into())
+        );
+    }
+
+    #[test]
+    fn set_health_buffer() {
+        let mut limits = ObligationLimits::new(Pubkey::new_unique(), 255);
+        assert_eq!(limits.health_buffer(), Bps(0));
+
+        assert_eq!(
+            limits.set_health_buffer(MAX_HEALTH_BUFFER_BPS + 1),
+            Err(LendingError::InvalidConfig.into())
+        );
+        limits.set_health_buffer(200).unwrap();
+        assert_eq!(limits.health_buffer(), Bps(200));
+    }
+}
@@ -0,0 +1,209 @@
+use super::*;
+use crate::{error::LendingError, instruction::LendingInstruction};
+use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
+use solana_program::{
+    clock::Slot,
+    msg,
+    program_error::ProgramError,
+    program_pack::{IsInitialized, Pack, Sealed},
+    pubkey::{Pubkey, PUBKEY_BYTES},
+};
+
+/// Seed used to derive the pending config address of a reserve, lending market or config timelock
+pub const PENDING_CONFIG_SEED: &[u8] = b"PendingConfig";
+
+/// Max length of the packed instruction a pending config holds
+pub const MAX_PENDING_CONFIG_INSTRUCTION_LEN: usize = 512;
+
+/// A config change staged by a [ConfigTimelock]. Holds the packed `UpdateReserveConfig`,
+/// `SetLendingMarketOwnerAndConfig` or `SetConfigTimelock` instruction that was submitted, which
+/// `ActivateConfig` applies once the activation slot is reached. Lives in a PDA derived from
+/// [target, PENDING_CONFIG_SEED], so each reserve, lending market and timelock has at most one
+/// change pending and submitting another replaces it.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PendingConfig {
+    /// Version of the struct
+    pub version: u8,
+    /// Bump seed for the derived address
+    pub bump_seed: u8,
+    /// Lending market the change belongs to
+    pub lending_market: Pubkey,
+    /// Account the change applies to
+    pub target: Pubkey,
+    /// First slot the change can be activated at
+    pub activation_slot: Slot,
+    /// Pyth price oracle a reserve config change switches to
+    pub pyth_oracle: Pubkey,
+    /// Switchboard feed a reserve config change switches to
+    pub switchboard_oracle: Pubkey,
+    /// Packed instruction holding the change
+    pub instruction: Vec<u8>,
+}
+
+impl PendingConfig {
+    /// Unpack the staged instruction
+    pub fn instruction(&self) -> Result<LendingInstruction, ProgramError> {
+        LendingInstruction::unpack(&self.instruction)
+    }
+
+    /// Set the staged instruction
+    pub fn set_instruction(
+        &mut self,
+        instruction: &LendingInstruction,
+    ) -> Result<(), ProgramError> {
+        let packed = instruction.pack();
+        if packed.len() > MAX_PENDING_CONFIG_INSTRUCTION_LEN {
+            msg!("Config change is too large to be staged");
+            return Err(LendingError::InvalidConfig.into());
+        }
+        self.instruction = packed;
+        Ok(())
+    }
+
+    /// Whether the change can be activated at `slot`
+    pub fn can_activate(&self, slot: Slot) -> bool {
+        slot >= self.activation_slot
+    }
+}
+
+impl Sealed for PendingConfig {}
+impl IsInitialized for PendingConfig {
+    fn is_initialized(&self) -> bool {
+        self.version != UNINITIALIZED_VERSION
+    }
+}
+
+const PENDING_CONFIG_LEN: usize = 716; // 1 + 1 + 32 + 32 + 8 + 32 + 32 + 2 + 512 + 64
+impl Pack for PendingConfig {
+    const LEN: usize = PENDING_CONFIG_LEN;
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let output = array_mut_ref![dst, 0, PENDING_CONFIG_LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (
+            version,
+            bump_seed,
+            lending_market,
+            target,
+            activation_slot,
+            pyth_oracle,
+            switchboard_oracle,
+            instruction_len,
+            instruction,
+            _padding,
+        ) = mut_array_refs![
+            output,
+            1,
+            1,
+            PUBKEY_BYTES,
+            PUBKEY_BYTES,
+            8,
+            PUBKEY_BYTES,
+            PUBKEY_BYTES,
+            2,
+            MAX_PENDING_CONFIG_INSTRUCTION_LEN,
+            64
+        ];
+
+        *version = self.version.to_le_bytes();
+        *bump_seed = self.bump_seed.to_le_bytes();
+        lending_market.copy_from_slice(self.lending_market.as_ref());
+        target.copy_from_slice(self.target.as_ref());
+        *activation_slot = self.activation_slot.to_le_bytes();
+        pyth_oracle.copy_from_slice(self.pyth_oracle.as_ref());
+        switchboard_oracle.copy_from_slice(self.switchboard_oracle.as_ref());
+        *instruction_len = (self.instruction.len() as u16).to_le_bytes();
+        instruction.fill(0);
+        instruction[..self.instruction.len()].copy_from_slice(&self.instruction);
+    }
+
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![src, 0, PENDING_CONFIG_LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (
+            version,
+            bump_seed,
+            lending_market,
+            target,
+            activation_slot,
+            pyth_oracle,
+            switchboard_oracle,
+            instruction_len,
+            instruction,
+            _padding,
+        ) = array_refs![
+            input,
+            1,
+            1,
+            PUBKEY_BYTES,
+            PUBKEY_BYTES,
+            8,
+            PUBKEY_BYTES,
+            PUBKEY_BYTES,
+            2,
+            MAX_PENDING_CONFIG_INSTRUCTION_LEN,
+            64
+        ];
+
+        let version = u8::from_le_bytes(*version);
+        if version > PROGRAM_VERSION {
+            msg!("Pending config version does not match lending program version");
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        let instruction_len = u16::from_le_bytes(*instruction_len) as usize;
+        if instruction_len > MAX_PENDING_CONFIG_INSTRUCTION_LEN {
+            msg!("Pending config instruction length is out of bounds");
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        Ok(Self {
+            version,
+            bump_seed: u8::from_le_bytes(*bump_seed),
+            lending_market: Pubkey::new_from_array(*lending_market),
+            target: Pubkey::new_from_array(*target),
+            activation_slot: u64::from_le_bytes(*activation_slot),
+            pyth_oracle: Pubkey::new_from_array(*pyth_oracle),
+            switchboard_oracle: Pubkey::new_from_array(*switchboard_oracle),
+            instruction: instruction[..instruction_len].to_vec(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::state::{RateLimiterConfig, ReserveConfig};
+
+    #[test]
+    fn pack_and_unpack_pending_config() {
+        let mut pending_config = PendingConfig {
+            version: PROGRAM_VERSION,
+            bump_seed: 254,
+            lending_market: Pubkey::new_unique(),
+            target: Pubkey::new_unique(),
+            activation_slot: 100,
+            pyth_oracle: Pubkey::new_unique(),
+            switchboard_oracle: Pubkey::new_unique(),
+            instruction: Vec::new(),
+        };
+        let instruction = LendingInstruction::UpdateReserveConfig {
+            config: ReserveConfig {
+                fee_receiver: Pubkey::new_unique(),
+                liquidation_fee_receiver: Some(Pubkey::new_unique()),
+                ..ReserveConfig::default()
+            },
+            rate_limiter_config: RateLimiterConfig::default(),
+        };
+        pending_config.set_instruction(&instruction).unwrap();
+
+        let mut packed = [0u8; PendingConfig::LEN];
+        PendingConfig::pack(pending_config.clone(), &mut packed).unwrap();
+        let unpacked = PendingConfig::unpack(&packed).unwrap();
+        assert_eq!(pending_config, unpacked);
+        assert_eq!(unpacked.instruction().unwrap(), instruction);
+
+        assert!(!unpacked.can_activate(99));
+        assert!(unpacked.can_activate(100));
+    }
+}
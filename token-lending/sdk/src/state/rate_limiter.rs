@@ -5,6 +5,7 @@ use solana_program::{program_error::ProgramError, slot_history::Slot};
 
 use crate::{
     error::LendingError,
+    error_context::log_error_context,
     math::{Decimal, TryAdd, TryDiv, TryMul, TrySub},
 };
 use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
@@ -54,6 +55,37 @@ impl RateLimiter {
         }
     }
 
+    /// Replace the config, resetting the windows if it changed. Outflow recorded under the old
+    /// config says nothing about the new one, so the new limits start from a clean slate.
+    pub fn set_config(&mut self, config: RateLimiterConfig, cur_slot: u64) {
+        if config != self.config {
+            *self = Self::new(config, cur_slot);
+        }
+    }
+
+    /// Start of the current window
+    pub fn window_start(&self) -> Slot {
+        self.window_start
+    }
+
+    /// Start of the window `cur_slot` falls in, `cur_slot` itself if the rate limiter is disabled
+    pub fn window_start_at(&self, cur_slot: Slot) -> Slot {
+        if self.config.window_duration == 0 {
+            return cur_slot;
+        }
+        cur_slot / self.config.window_duration * self.config.window_duration
+    }
+
+    /// Outflow of the previous window
+    pub fn prev_qty(&self) -> Decimal {
+        self.prev_qty
+    }
+
+    /// Outflow of the current window so far
+    pub fn cur_qty(&self) -> Decimal {
+        self.cur_qty
+    }
+
     fn _update(&mut self, cur_slot: u64) -> Result<(), ProgramError> {
         if cur_slot < self.window_start {
             msg!("Current slot is less than window start, which is impossible");
@@ -121,6 +153,52 @@ impl RateLimiter {
         Ok(diff)
     }
 
+    /// Earliest slot at or after `cur_slot` at which an outflow of `qty` would be accepted,
+    /// assuming nothing else flows out in the meantime. None if `qty` is more than the max
+    /// outflow, which no amount of waiting fixes.
+    pub fn earliest_slot_for(
+        &self,
+        cur_slot: Slot,
+        qty: Decimal,
+    ) -> Result<Option<Slot>, ProgramError> {
+        if self.config.window_duration == 0 {
+            return Ok(Some(cur_slot));
+        }
+        if qty > Decimal::from(self.config.max_outflow) {
+            return Ok(None);
+        }
+
+        let fits = |slot: Slot| -> Result<bool, ProgramError> {
+            let mut rate_limiter = *self;
+            Ok(rate_limiter.remaining_outflow(slot)? >= qty)
+        };
+        if fits(cur_slot)? {
+            return Ok(Some(cur_slot));
+        }
+
+        // the remaining outflow only grows with time, and two windows later everything recorded
+        // so far has been dropped
+        let mut lo = cur_slot;
+        let mut hi = self
+            .window_start_at(cur_slot)
+            .checked_add(
+                self.config
+                    .window_duration
+                    .checked_mul(2)
+                    .ok_or(LendingError::MathOverflow)?,
+            )
+            .ok_or(LendingError::MathOverflow)?;
+        while hi - lo > 1 {
+            let mid = lo + (hi - lo) / 2;
+            if fits(mid)? {
+                hi = mid;
+            } else {
+                lo = mid;
+            }
+        }
+        Ok(Some(hi))
+    }
+
     /// update rate limiter with new quantity. errors if rate limit has been reached
     pub fn update(&mut self, cur_slot: u64, qty: Decimal) -> Result<(), ProgramError> {
         // rate limiter is disabled if window duration == 0. this is here because we don't want to
@@ -133,6 +211,14 @@ impl RateLimiter {
 
         let cur_outflow = self.current_outflow(cur_slot)?;
         if cur_outflow.try_add(qty)? > Decimal::from(self.config.max_outflow) {
+            log_error_context(
+                LendingError::OutflowRateLimitExceeded,
+                &[
+                    ("amount", &qty),
+                    ("current_outflow", &cur_outflow),
+                    ("max_outflow", &self.config.max_outflow),
+                ],
+            );
             Err(LendingError::OutflowRateLimitExceeded.into())
         } else {
             self.cur_qty = self.cur_qty.try_add(qty)?;
@@ -144,6 +230,199 @@ impl RateLimiter {
 #[cfg(test)]
 mod test {
     use super::*;
+    use proptest::prelude::*;
+
+    fn config_strategy() -> impl Strategy<Value = RateLimiterConfig> {
+        (1..50u64, 1..1_000_000u64).prop_map(|(window_duration, max_outflow)| RateLimiterConfig {
+            window_duration,
+            max_outflow,
+        })
+    }
+
+    // (slots to advance, outflow to try) steps, with enough large outflows to hit the limit
+    fn steps_strategy() -> impl Strategy<Value = Vec<(u64, u64)>> {
+        proptest::collection::vec((0..20u64, 0..600_000u64), 1..60)
+    }
+
+    proptest! {
+        #[test]
+        fn outflow_never_exceeds_max_per_window(
+            config in config_strategy(),
+            start_slot in 1_000..2_000u64,
+            steps in steps_strategy(),
+        ) {
+            let mut rate_limiter = RateLimiter::new(config, start_slot);
+            let mut slot = start_slot;
+            let mut accepted = Vec::new();
+            for (advance, qty) in steps {
+                slot += advance;
+                if rate_limiter.update(slot, Decimal::from(qty)).is_ok() {
+                    accepted.push((slot, qty));
+                }
+            }
+
+            let outflow_between = |from: u64, to: u64| -> u128 {
+                accepted
+                    .iter()
+                    .filter(|(slot, _)| (from..=to).contains(slot))
+                    .map(|(_, qty)| *qty as u128)
+                    .sum()
+            };
+            let max_outflow = config.max_outflow as u128;
+            for end in start_slot..=slot {
+                // every aligned window stays within the limit
+                if end % config.window_duration == config.window_duration - 1 {
+                    prop_assert!(
+                        outflow_between(end + 1 - config.window_duration, end) <= max_outflow
+                    );
+                }
+                // and any sliding window within twice of it
+                prop_assert!(
+                    outflow_between(end.saturating_sub(config.window_duration), end)
+                        <= 2 * max_outflow
+                );
+            }
+        }
+
+        #[test]
+        fn unused_capacity_carries_into_next_window(
+            config in config_strategy(),
+            window in 1..1_000u64,
+            offset in 0..50u64,
+            next_offset in 0..50u64,
+            used_pct in 0..=100u64,
+        ) {
+            let window_start = window * config.window_duration;
+            let offset = offset % config.window_duration;
+            let next_offset = next_offset % config.window_duration;
+            let used = config.max_outflow * used_pct / 100;
+
+            let mut rate_limiter = RateLimiter::new(config, window_start);
+            rate_limiter.update(window_start + offset, Decimal::from(used)).unwrap();
+            prop_assert_eq!(rate_limiter.cur_qty(), Decimal::from(used));
+
+            // the previous window's outflow is spread over it, so the part still inside the
+            // sliding window counts against the next one
+            let next_slot = window_start + config.window_duration + next_offset;
+            let prev_weight = Decimal::from(config.window_duration - next_offset - 1)
+                .try_div(config.window_duration)
+                .unwrap();
+            let expected = Decimal::from(config.max_outflow)
+                .try_sub(prev_weight.try_mul(used).unwrap())
+                .unwrap();
+            prop_assert_eq!(rate_limiter.remaining_outflow(next_slot), Ok(expected));
+            prop_assert_eq!(rate_limiter.window_start(), window_start + config.window_duration);
+            prop_assert_eq!(rate_limiter.prev_qty(), Decimal::from(used));
+            prop_assert_eq!(rate_limiter.cur_qty(), Decimal::zero());
+
+            // a window later nothing carries over
+            let later_slot = next_slot + config.window_duration;
+            prop_assert_eq!(
+                rate_limiter.remaining_outflow(later_slot),
+                Ok(Decimal::from(config.max_outflow))
+            );
+        }
+
+        #[test]
+        fn conversions_do_not_lose_outflow(
+            config in config_strategy(),
+            start_slot in 1_000..2_000u64,
+            steps in steps_strategy(),
+        ) {
+            let mut rate_limiter = RateLimiter::new(config, start_slot);
+            let mut slot = start_slot;
+            for (advance, qty) in steps {
+                slot += advance;
+                let remaining = rate_limiter.remaining_outflow(slot).unwrap();
+                let before = rate_limiter;
+
+                // all of the remaining outflow, as a token amount, can be used
+                let max_qty = remaining.try_floor_u64().unwrap();
+                let qty = qty.min(max_qty);
+                prop_assert_eq!(rate_limiter.update(slot, Decimal::from(qty)), Ok(()));
+                prop_assert_eq!(
+                    rate_limiter.cur_qty(),
+                    before.cur_qty().try_add(Decimal::from(qty)).unwrap()
+                );
+                prop_assert_eq!(
+                    rate_limiter.remaining_outflow(slot),
+                    Ok(remaining.try_sub(Decimal::from(qty)).unwrap())
+                );
+
+                // packing keeps the exact outflow
+                let mut packed = [0u8; RATE_LIMITER_LEN];
+                rate_limiter.pack_into_slice(&mut packed);
+                prop_assert_eq!(RateLimiter::unpack_from_slice(&packed), Ok(rate_limiter));
+            }
+        }
+
+        #[test]
+        fn earliest_slot_is_first_accepting_slot(
+            config in config_strategy(),
+            start_slot in 1_000..2_000u64,
+            steps in steps_strategy(),
+            qty in 0..1_200_000u64,
+        ) {
+            let mut rate_limiter = RateLimiter::new(config, start_slot);
+            let mut slot = start_slot;
+            for (advance, qty) in steps {
+                slot += advance;
+                rate_limiter.update(slot, Decimal::from(qty)).ok();
+            }
+
+            let qty = Decimal::from(qty);
+            match rate_limiter.earliest_slot_for(slot, qty).unwrap() {
+                Some(earliest) => {
+                    prop_assert!(earliest >= slot);
+                    let (mut at, mut before) = (rate_limiter, rate_limiter);
+                    prop_assert_eq!(at.update(earliest, qty), Ok(()));
+                    if earliest > slot {
+                        prop_assert!(before.update(earliest - 1, qty).is_err());
+                    }
+                }
+                None => prop_assert!(qty > Decimal::from(config.max_outflow)),
+            }
+        }
+
+        #[test]
+        fn config_updates_reset_state(
+            config in config_strategy(),
+            new_config in config_strategy(),
+            start_slot in 1_000..2_000u64,
+            steps in steps_strategy(),
+            advance in 0..100u64,
+        ) {
+            let mut rate_limiter = RateLimiter::new(config, start_slot);
+            let mut slot = start_slot;
+            for (advance, qty) in steps {
+                slot += advance;
+                rate_limiter.update(slot, Decimal::from(qty)).ok();
+            }
+
+            // the same config keeps the recorded outflow
+            let before = rate_limiter;
+            rate_limiter.set_config(config, slot + advance);
+            prop_assert_eq!(rate_limiter, before);
+
+            // a new one starts over in the window of the slot it was set at
+            let slot = slot + advance;
+            rate_limiter.set_config(new_config, slot);
+            prop_assert_eq!(rate_limiter.config, new_config);
+            prop_assert!(rate_limiter.window_start() <= slot);
+            if new_config != config {
+                prop_assert_eq!(rate_limiter.prev_qty(), Decimal::zero());
+                prop_assert_eq!(rate_limiter.cur_qty(), Decimal::zero());
+                prop_assert_eq!(
+                    rate_limiter.remaining_outflow(slot),
+                    Ok(Decimal::from(new_config.max_outflow))
+                );
+                prop_assert_eq!(
+                    rate_limiter.update(slot, Decimal::from(new_config.max_outflow)),
+                    Ok(())
+                );
+            }
+        }
+    }
 
     #[test]
     fn test_rate_limiter() {
@@ -1,11 +1,13 @@
 use super::*;
 use crate::{
     error::LendingError,
-    math::{Decimal, Rate, TryAdd, TryDiv, TryMul, TrySub},
+    error_context::log_error_context,
+    math::{
+        apr_to_apy, apr_to_slot_rate, Bps, CollateralAmount, DecaBps, Decimal, LiquidityAmount,
+        Percent, Rate, TryAdd, TryDiv, TryMul, TrySub, UsdValue,
+    },
 };
-use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
 use num_derive::FromPrimitive;
-use num_traits::FromPrimitive;
 use solana_program::{
     clock::Slot,
     entrypoint::ProgramResult,
@@ -35,6 +37,27 @@ pub const MAX_BONUS_PCT: u8 = 25;
 /// Maximum protocol liquidation fee in deca bps (1 deca bp = 10 bps)
 pub const MAX_PROTOCOL_LIQUIDATION_FEE_DECA_BPS: u8 = 50;
 
+/// Extra bonus in bps paid to liquidators that take seized collateral as cTokens instead of
+/// redeeming it. Still capped by MAX_BONUS_PCT.
+pub const COLLATERAL_LIQUIDATION_EXTRA_BONUS_BPS: u64 = 50;
+
+/// Largest cumulative borrow rate a reserve can reach, bounded by the 16 bytes its scaled value
+/// is packed into. Interest stops accruing with [LendingError::CumulativeBorrowRateOverflow] once
+/// the rate would grow past it.
+pub const MAX_CUMULATIVE_BORROW_RATE: Decimal = Decimal::from_scaled_val(u128::MAX);
+
+/// Maximum liquidity mint decimals a reserve can be initialized with. Values are converted with
+/// 10^decimals in u64 and Decimal math, which is tested without overflow up to here.
+pub const MAX_MINT_DECIMALS: u8 = 18;
+
+/// Collateral minted on reserve initialization that stays in the reserve collateral supply for
+/// good. The collateral mint supply can then never be redeemed down to the few tokens a first
+/// depositor needs to inflate the exchange rate and round later deposits down to nothing.
+pub const LOCKED_INITIAL_COLLATERAL: u64 = 1000;
+
+/// Maximum withdraw fee a reserve can charge, in basis points
+pub const MAX_WITHDRAW_FEE_BPS: u16 = 1000;
+
 /// Lending market reserve state
 #[derive(Clone, Debug, Default, PartialEq)]
 pub struct Reserve {
@@ -52,6 +75,12 @@ pub struct Reserve {
     pub config: ReserveConfig,
     /// Outflow Rate Limiter (denominated in tokens)
     pub rate_limiter: RateLimiter,
+    /// Slots per year used to compound interest, copied from the lending market
+    pub slots_per_year: u64,
+    /// Whether the reserve is active, winding down or closed
+    pub status: ReserveStatus,
+    /// Whether RedeemFees has to call the reserve's [FeeRedemptionHook]
+    pub has_fee_redemption_hook: bool,
 }
 
 impl Reserve {
@@ -71,18 +100,64 @@ impl Reserve {
         self.collateral = params.collateral;
         self.config = params.config;
         self.rate_limiter = RateLimiter::new(params.rate_limiter_config, params.current_slot);
+        self.slots_per_year = params.slots_per_year;
+        self.status = ReserveStatus::Active;
     }
 
     /// get borrow weight. Guaranteed to be greater than 1
     pub fn borrow_weight(&self) -> Decimal {
         Decimal::one()
-            .try_add(Decimal::from_bps(self.config.added_borrow_weight_bps))
+            .try_add(Decimal::from(self.config.added_borrow_weight_bps()))
             .unwrap()
     }
 
-    /// get loan to value ratio as a Rate
+    /// get the annual idle liquidity fee as a Rate
+    pub fn idle_liquidity_fee_rate(&self) -> Result<Rate, ProgramError> {
+        Ok(self.config.idle_liquidity_fee_bps().into())
+    }
+
+    /// get loan to value ratio as a Rate, decayed up to the last update slot if the reserve is
+    /// winding down
     pub fn loan_to_value_ratio(&self) -> Rate {
-        Rate::from_percent(self.config.loan_to_value_ratio)
+        self.status.loan_to_value_ratio(
+            Rate::from(self.config.loan_to_value_ratio()),
+            self.last_update.slot,
+        )
+    }
+
+    /// 10^mint_decimals, the number of liquidity token units in a whole token
+    fn decimals_scaler(&self) -> Result<u64, ProgramError> {
+        10u64
+            .checked_pow(self.liquidity.mint_decimals as u32)
+            .ok_or_else(|| LendingError::MathOverflow.into())
+    }
+
+    /// Value of a liquidity amount at a price. Multiplies before dividing by 10^decimals and
+    /// divides first when the product overflows, e.g. for large amounts of 18 decimal tokens,
+    /// which only drops digits below 10^-18 of a token.
+    pub fn value_at_price(
+        &self,
+        liquidity_amount: Decimal,
+        price: Decimal,
+    ) -> Result<Decimal, ProgramError> {
+        let decimals_scaler = self.decimals_scaler()?;
+        price
+            .try_mul(liquidity_amount)
+            .and_then(|value| value.try_div(decimals_scaler))
+            .or_else(|_| price.try_mul(liquidity_amount.try_div(decimals_scaler)?))
+    }
+
+    /// Liquidity amount worth a value at a price, the inverse of [Reserve::value_at_price]
+    pub fn liquidity_amount_at_price(
+        &self,
+        value: Decimal,
+        price: Decimal,
+    ) -> Result<Decimal, ProgramError> {
+        let decimals_scaler = self.decimals_scaler()?;
+        value
+            .try_mul(decimals_scaler)
+            .and_then(|value| value.try_div(price))
+            .or_else(|_| value.try_div(price)?.try_mul(decimals_scaler))
     }
 
     /// Convert USD to liquidity tokens.
@@ -92,28 +167,71 @@ impl Reserve {
         quote_amount: Decimal,
     ) -> Result<Decimal, ProgramError> {
         // quote amount / max(market price, smoothed price) * 10**decimals
-        quote_amount
-            .try_mul(Decimal::from(
-                (10u128)
-                    .checked_pow(self.liquidity.mint_decimals as u32)
-                    .ok_or(LendingError::MathOverflow)?,
-            ))?
-            .try_div(max(
+        self.liquidity_amount_at_price(
+            quote_amount,
+            max(
                 self.liquidity.smoothed_market_price,
                 self.liquidity.market_price,
-            ))
+            ),
+        )
     }
 
     /// find current market value of tokens
     pub fn market_value(&self, liquidity_amount: Decimal) -> Result<Decimal, ProgramError> {
-        self.liquidity
-            .market_price
-            .try_mul(liquidity_amount)?
-            .try_div(Decimal::from(
-                (10u128)
-                    .checked_pow(self.liquidity.mint_decimals as u32)
-                    .ok_or(LendingError::MathOverflow)?,
-            ))
+        self.value_at_price(liquidity_amount, self.liquidity.market_price)
+    }
+
+    /// Market value of an amount of liquidity
+    pub fn liquidity_value(
+        &self,
+        liquidity_amount: LiquidityAmount,
+    ) -> Result<UsdValue, ProgramError> {
+        Ok(UsdValue(self.market_value(liquidity_amount.0.into())?))
+    }
+
+    /// Market value of an amount of collateral, at the current collateral exchange rate
+    pub fn collateral_value(
+        &self,
+        collateral_amount: CollateralAmount,
+    ) -> Result<UsdValue, ProgramError> {
+        let liquidity_amount = self
+            .collateral_exchange_rate()?
+            .decimal_collateral_to_liquidity(collateral_amount.0.into())?;
+        Ok(UsdValue(self.market_value(liquidity_amount)?))
+    }
+
+    /// Amount of liquidity worth a value, rounded down and priced at
+    /// max(market_price, smoothed_market_price)
+    pub fn value_to_liquidity_amount_lower_bound(
+        &self,
+        value: UsdValue,
+    ) -> Result<LiquidityAmount, ProgramError> {
+        Ok(LiquidityAmount(
+            self.usd_to_liquidity_amount_lower_bound(value.0)?
+                .try_floor_u64()?,
+        ))
+    }
+
+    /// Amount to borrow to receive a value of liquidity, eg how much SOL is a $500 borrow?
+    /// Rounded down and priced at max(market_price, smoothed_market_price) like the borrow limit
+    /// check, so the borrow adds at most `value` to the obligation's borrowed value before the
+    /// borrow fee and borrow weight.
+    pub fn borrow_amount_for_value(
+        &self,
+        value: UsdValue,
+    ) -> Result<LiquidityAmount, ProgramError> {
+        self.value_to_liquidity_amount_lower_bound(value)
+    }
+
+    /// Amount to repay to pay off a value of debt. Rounded up and priced at
+    /// max(market_price, smoothed_market_price) like the obligation's borrowed value, so the
+    /// repayment removes at least `value` from it. Repayments are capped at the borrowed amount
+    /// on chain.
+    pub fn repay_amount_for_value(&self, value: UsdValue) -> Result<LiquidityAmount, ProgramError> {
+        Ok(LiquidityAmount(
+            self.usd_to_liquidity_amount_lower_bound(value.0)?
+                .try_ceil_u64()?,
+        ))
     }
 
     /// find the current upper bound market value of tokens.
@@ -127,13 +245,7 @@ impl Reserve {
             self.liquidity.smoothed_market_price,
         );
 
-        price_upper_bound
-            .try_mul(liquidity_amount)?
-            .try_div(Decimal::from(
-                (10u128)
-                    .checked_pow(self.liquidity.mint_decimals as u32)
-                    .ok_or(LendingError::MathOverflow)?,
-            ))
+        self.value_at_price(liquidity_amount, price_upper_bound)
     }
 
     /// find the current lower bound market value of tokens.
@@ -147,13 +259,7 @@ impl Reserve {
             self.liquidity.smoothed_market_price,
         );
 
-        price_lower_bound
-            .try_mul(liquidity_amount)?
-            .try_div(Decimal::from(
-                (10u128)
-                    .checked_pow(self.liquidity.mint_decimals as u32)
-                    .ok_or(LendingError::MathOverflow)?,
-            ))
+        self.value_at_price(liquidity_amount, price_lower_bound)
     }
 
     /// Record deposited liquidity and return amount of collateral tokens to mint
@@ -161,6 +267,17 @@ impl Reserve {
         let collateral_amount = self
             .collateral_exchange_rate()?
             .liquidity_to_collateral(liquidity_amount)?;
+        if collateral_amount == 0 {
+            msg!("Liquidity amount is too small to mint any collateral");
+            log_error_context(
+                LendingError::InvalidAmount,
+                &[
+                    ("amount", &liquidity_amount),
+                    ("exchange_rate", &self.collateral_exchange_rate()?.0),
+                ],
+            );
+            return Err(LendingError::InvalidAmount.into());
+        }
 
         self.liquidity.deposit(liquidity_amount)?;
         self.collateral.mint(collateral_amount)?;
@@ -168,6 +285,28 @@ impl Reserve {
         Ok(collateral_amount)
     }
 
+    /// Record the liquidity deposited on initialization and return the amount of collateral to
+    /// mint to the depositor. [LOCKED_INITIAL_COLLATERAL] of the minted collateral is kept back
+    /// and has to be minted to the reserve collateral supply instead.
+    pub fn deposit_initial_liquidity(
+        &mut self,
+        liquidity_amount: u64,
+    ) -> Result<u64, ProgramError> {
+        let collateral_amount = self
+            .collateral_exchange_rate()?
+            .liquidity_to_collateral(liquidity_amount)?;
+        if collateral_amount < LOCKED_INITIAL_COLLATERAL {
+            msg!(
+                "Reserve must be initialized with liquidity worth at least {} collateral",
+                LOCKED_INITIAL_COLLATERAL
+            );
+            return Err(LendingError::InvalidAmount.into());
+        }
+
+        let collateral_amount = self.deposit_liquidity(liquidity_amount)?;
+        Ok(collateral_amount - LOCKED_INITIAL_COLLATERAL)
+    }
+
     /// Record redeemed collateral and return amount of liquidity to withdraw
     pub fn redeem_collateral(&mut self, collateral_amount: u64) -> Result<u64, ProgramError> {
         let collateral_exchange_rate = self.collateral_exchange_rate()?;
@@ -180,13 +319,72 @@ impl Reserve {
         Ok(liquidity_amount)
     }
 
+    /// Liquidity that can still be borrowed before the utilization reaches the borrow utilization
+    /// cap, u64::MAX if the reserve has no cap
+    pub fn remaining_borrow_under_utilization_cap(&self) -> Result<Decimal, ProgramError> {
+        if self.config.borrow_utilization_cap == 0 {
+            return Ok(Decimal::from(u64::MAX));
+        }
+        let max_borrowed_amount = self
+            .liquidity
+            .borrowed_amount_wads
+            .try_add(Decimal::from(self.liquidity.available_amount))?
+            .try_mul(Rate::from(self.config.borrow_utilization_cap()))?;
+        Ok(max_borrowed_amount
+            .try_sub(self.liquidity.borrowed_amount_wads)
+            .unwrap_or_else(|_| Decimal::zero()))
+    }
+
     /// Calculate the current borrow rate
+    ///
+    /// The rate is piecewise linear in utilization: it goes from `min_borrow_rate` to
+    /// `optimal_borrow_rate` up to `optimal_utilization_rate`, then to `max_borrow_rate` at
+    /// `max_utilization_rate`, and then to `super_max_borrow_rate` at full utilization.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use solend_sdk::{
+    ///     math::{Decimal, Rate},
+    ///     state::{Reserve, ReserveConfig, ReserveLiquidity},
+    /// };
+    ///
+    /// let reserve_with = |borrowed: u64, available: u64| Reserve {
+    ///     liquidity: ReserveLiquidity {
+    ///         borrowed_amount_wads: Decimal::from(borrowed),
+    ///         available_amount: available,
+    ///         ..ReserveLiquidity::default()
+    ///     },
+    ///     config: ReserveConfig {
+    ///         min_borrow_rate: 0,
+    ///         optimal_utilization_rate: 80,
+    ///         optimal_borrow_rate: 10,
+    ///         max_utilization_rate: 90,
+    ///         max_borrow_rate: 50,
+    ///         super_max_borrow_rate: 100,
+    ///         ..ReserveConfig::default()
+    ///     },
+    ///     ..Reserve::default()
+    /// };
+    ///
+    /// // 40% utilization is halfway to optimal
+    /// let rate = reserve_with(40, 60).current_borrow_rate().unwrap();
+    /// assert_eq!(rate, Rate::from_percent(5));
+    ///
+    /// // 85% utilization is halfway between optimal and max
+    /// let rate = reserve_with(85, 15).current_borrow_rate().unwrap();
+    /// assert_eq!(rate, Rate::from_percent(30));
+    ///
+    /// // 95% utilization is halfway between max and full
+    /// let rate = reserve_with(95, 5).current_borrow_rate().unwrap();
+    /// assert_eq!(rate, Rate::from_percent(75));
+    /// ```
     pub fn current_borrow_rate(&self) -> Result<Rate, ProgramError> {
         let utilization_rate = self.liquidity.utilization_rate()?;
-        let optimal_utilization_rate = Rate::from_percent(self.config.optimal_utilization_rate);
-        let max_utilization_rate = Rate::from_percent(self.config.max_utilization_rate);
+        let optimal_utilization_rate = Rate::from(self.config.optimal_utilization_rate());
+        let max_utilization_rate = Rate::from(self.config.max_utilization_rate());
         if utilization_rate <= optimal_utilization_rate {
-            let min_rate = Rate::from_percent(self.config.min_borrow_rate);
+            let min_rate = Rate::from(self.config.min_borrow_rate());
 
             if optimal_utilization_rate == Rate::zero() {
                 return Ok(min_rate);
@@ -206,8 +404,8 @@ impl Reserve {
                 .try_sub(optimal_utilization_rate)?
                 .try_div(max_utilization_rate.try_sub(optimal_utilization_rate)?)?;
 
-            let optimal_borrow_rate = Rate::from_percent(self.config.optimal_borrow_rate);
-            let max_borrow_rate = Rate::from_percent(self.config.max_borrow_rate);
+            let optimal_borrow_rate = Rate::from(self.config.optimal_borrow_rate());
+            let max_borrow_rate = Rate::from(self.config.max_borrow_rate());
             let rate_range = max_borrow_rate.try_sub(optimal_borrow_rate)?;
 
             weight.try_mul(rate_range)?.try_add(optimal_borrow_rate)
@@ -221,7 +419,7 @@ impl Reserve {
                 ))?
                 .into();
 
-            let max_borrow_rate = Rate::from_percent(self.config.max_borrow_rate);
+            let max_borrow_rate = Rate::from(self.config.max_borrow_rate());
             let super_max_borrow_rate = Rate::from_percent_u64(self.config.super_max_borrow_rate);
             let rate_range: Decimal = super_max_borrow_rate.try_sub(max_borrow_rate)?.into();
 
@@ -234,49 +432,211 @@ impl Reserve {
         }
     }
 
+    /// Calculate the current borrow APY, compounded every slot at the reserve's slots per year
+    pub fn current_borrow_apy(&self) -> Result<Rate, ProgramError> {
+        apr_to_apy(self.current_borrow_rate()?, self.slots_per_year)
+    }
+
     /// Collateral exchange rate
+    ///
+    /// cTokens are minted one to one with liquidity until the reserve has a supply, and then at
+    /// the ratio of cToken supply to total liquidity, so accrued interest raises the amount of
+    /// liquidity each cToken redeems for. Conversions in both directions round down.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use solend_sdk::{
+    ///     math::{Decimal, Rate},
+    ///     state::{Reserve, ReserveCollateral, ReserveLiquidity},
+    /// };
+    ///
+    /// let mut reserve = Reserve::default();
+    /// let exchange_rate = reserve.collateral_exchange_rate().unwrap();
+    /// assert_eq!(Rate::from(exchange_rate), Rate::one());
+    ///
+    /// // 1000 cTokens backed by 1000 deposited plus 100 of accrued interest
+    /// reserve.liquidity = ReserveLiquidity {
+    ///     available_amount: 600,
+    ///     borrowed_amount_wads: Decimal::from(500u64),
+    ///     ..ReserveLiquidity::default()
+    /// };
+    /// reserve.collateral = ReserveCollateral {
+    ///     mint_total_supply: 1_000,
+    ///     ..ReserveCollateral::default()
+    /// };
+    ///
+    /// let exchange_rate = reserve.collateral_exchange_rate().unwrap();
+    /// assert_eq!(exchange_rate.collateral_to_liquidity(1_000).unwrap(), 1_100);
+    /// assert_eq!(exchange_rate.liquidity_to_collateral(1_100).unwrap(), 999);
+    /// ```
     pub fn collateral_exchange_rate(&self) -> Result<CollateralExchangeRate, ProgramError> {
         let total_liquidity = self.liquidity.total_supply()?;
         self.collateral.exchange_rate(total_liquidity)
     }
 
+    /// Market and smoothed prices of one collateral token, used to price this reserve's cTokens
+    /// when they are the liquidity of a reserve in another lending market
+    pub fn collateral_prices(&self) -> Result<(Decimal, Decimal), ProgramError> {
+        let exchange_rate = self.collateral_exchange_rate()?;
+        Ok((
+            exchange_rate.decimal_collateral_to_liquidity(self.liquidity.market_price)?,
+            exchange_rate.decimal_collateral_to_liquidity(self.liquidity.smoothed_market_price)?,
+        ))
+    }
+
     /// Update borrow rate and accrue interest
     pub fn accrue_interest(&mut self, current_slot: Slot) -> ProgramResult {
         let slots_elapsed = self.last_update.slots_elapsed(current_slot)?;
         if slots_elapsed > 0 {
             let current_borrow_rate = self.current_borrow_rate()?;
-            let take_rate = Rate::from_percent(self.config.protocol_take_rate);
+            let take_rate = Rate::from(self.config.protocol_take_rate());
+            let idle_liquidity_fee_rate = self.idle_liquidity_fee_rate()?;
+            self.liquidity.compound_interest(
+                current_borrow_rate,
+                slots_elapsed,
+                self.slots_per_year,
+                take_rate,
+                idle_liquidity_fee_rate,
+            )?;
             self.liquidity
-                .compound_interest(current_borrow_rate, slots_elapsed, take_rate)?;
+                .accrue_fixed_interest(slots_elapsed, self.slots_per_year, take_rate)?;
         }
         Ok(())
     }
 
-    /// Borrow liquidity up to a maximum market value
+    /// Slots until the cumulative borrow rate grows past [MAX_CUMULATIVE_BORROW_RATE] if
+    /// interest keeps accruing at `borrow_rate`, None if it never does. E.g. at 100% APR a
+    /// reserve starting from a cumulative rate of 1 has about 47 years.
+    pub fn cumulative_borrow_rate_overflow_horizon(
+        &self,
+        borrow_rate: Rate,
+    ) -> Result<Option<u64>, ProgramError> {
+        let slot_growth: Decimal = Rate::one()
+            .try_add(apr_to_slot_rate(borrow_rate, self.slots_per_year)?)?
+            .into();
+        if slot_growth == Decimal::one() {
+            return Ok(None);
+        }
+
+        // growths[i] is the growth over 2^i slots, up to the largest that fits on its own
+        let mut growths = vec![slot_growth];
+        while growths.len() < u64::BITS as usize {
+            let last = growths[growths.len() - 1];
+            match last.try_mul(last) {
+                Ok(growth) if growth <= MAX_CUMULATIVE_BORROW_RATE => growths.push(growth),
+                _ => break,
+            }
+        }
+
+        let mut cumulative_borrow_rate = self.liquidity.cumulative_borrow_rate_wads;
+        let mut slots = 0u64;
+        for (i, growth) in growths.iter().enumerate().rev() {
+            if let Ok(next) = cumulative_borrow_rate.try_mul(*growth) {
+                if next <= MAX_CUMULATIVE_BORROW_RATE {
+                    cumulative_borrow_rate = next;
+                    slots += 1 << i;
+                }
+            }
+        }
+        Ok(Some(slots))
+    }
+
+    /// Interest per year locked by a fixed rate borrow of `borrow_amount`, rounded up
+    pub fn fixed_interest_per_year(&self, borrow_amount: Decimal) -> Result<u64, ProgramError> {
+        if self.config.fixed_borrow_rate_bps == 0 {
+            msg!("Reserve does not allow fixed rate borrows");
+            return Err(LendingError::FixedRateBorrowDisabled.into());
+        }
+        borrow_amount
+            .try_mul(Decimal::from(self.config.fixed_borrow_rate_bps()))?
+            .try_ceil_u64()
+    }
+
+    /// Borrow liquidity up to a maximum market value, waiving `fee_rebate_bps` of the borrow fee
+    ///
+    /// A specific `amount_to_borrow` is what the borrower receives, with the fee added on top of
+    /// the debt. `u64::MAX` borrows as much as `max_borrow_value`, `remaining_reserve_borrow` and
+    /// the available liquidity allow, with the fee taken out of the amount received.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use solend_sdk::{
+    ///     error::LendingError,
+    ///     math::Decimal,
+    ///     solana_program::program_error::ProgramError,
+    ///     state::{Reserve, ReserveConfig, ReserveFees, ReserveLiquidity},
+    /// };
+    ///
+    /// // a $1 token with 6 decimals, a 1% borrow fee and 20% of fees going to the host
+    /// let reserve = Reserve {
+    ///     liquidity: ReserveLiquidity {
+    ///         mint_decimals: 6,
+    ///         available_amount: 1_000_000_000,
+    ///         market_price: Decimal::one(),
+    ///         smoothed_market_price: Decimal::one(),
+    ///         ..ReserveLiquidity::default()
+    ///     },
+    ///     config: ReserveConfig {
+    ///         fees: ReserveFees {
+    ///             borrow_fee_wad: 10_000_000_000_000_000,
+    ///             host_fee_percentage: 20,
+    ///             ..ReserveFees::default()
+    ///         },
+    ///         ..ReserveConfig::default()
+    ///     },
+    ///     ..Reserve::default()
+    /// };
+    /// let remaining_reserve_borrow = Decimal::from(u64::MAX);
+    ///
+    /// // receive 100 tokens and owe 101
+    /// let result = reserve
+    ///     .calculate_borrow(100_000_000, Decimal::from(200u64), remaining_reserve_borrow, 0)
+    ///     .unwrap();
+    /// assert_eq!(result.receive_amount, 100_000_000);
+    /// assert_eq!(result.borrow_amount, Decimal::from(101_000_000u64));
+    /// assert_eq!(result.borrow_fee, 1_000_000);
+    /// assert_eq!(result.host_fee, 200_000);
+    ///
+    /// // the fee counts towards the borrow limit
+    /// let err = reserve
+    ///     .calculate_borrow(100_000_000, Decimal::from(100u64), remaining_reserve_borrow, 0)
+    ///     .unwrap_err();
+    /// assert_eq!(err, ProgramError::from(LendingError::BorrowTooLarge));
+    ///
+    /// // borrowing the max takes the fee out of the amount received
+    /// let result = reserve
+    ///     .calculate_borrow(u64::MAX, Decimal::from(101u64), remaining_reserve_borrow, 0)
+    ///     .unwrap();
+    /// assert_eq!(result.borrow_amount, Decimal::from(101_000_000u64));
+    /// assert_eq!(result.receive_amount, 100_000_000);
+    ///
+    /// // a 50% rebate halves the fee
+    /// let result = reserve
+    ///     .calculate_borrow(100_000_000, Decimal::from(200u64), remaining_reserve_borrow, 5_000)
+    ///     .unwrap();
+    /// assert_eq!(result.borrow_fee, 500_000);
+    /// ```
     pub fn calculate_borrow(
         &self,
         amount_to_borrow: u64,
         max_borrow_value: Decimal,
         remaining_reserve_borrow: Decimal,
+        fee_rebate_bps: u16,
     ) -> Result<CalculateBorrowResult, ProgramError> {
-        // @TODO: add lookup table https://git.io/JOCYq
-        let decimals = 10u64
-            .checked_pow(self.liquidity.mint_decimals as u32)
-            .ok_or(LendingError::MathOverflow)?;
         if amount_to_borrow == u64::MAX {
-            let borrow_amount = max_borrow_value
-                .try_mul(decimals)?
-                .try_div(max(
-                    self.liquidity.market_price,
-                    self.liquidity.smoothed_market_price,
-                ))?
-                .try_div(self.borrow_weight())?
+            let borrow_amount = self
+                .usd_to_liquidity_amount_lower_bound(
+                    max_borrow_value.try_div(self.borrow_weight())?,
+                )?
                 .min(remaining_reserve_borrow)
                 .min(self.liquidity.available_amount.into());
-            let (borrow_fee, host_fee) = self
-                .config
-                .fees
-                .calculate_borrow_fees(borrow_amount, FeeCalculation::Inclusive)?;
+            let (borrow_fee, host_fee) = self.config.fees.calculate_borrow_fees(
+                borrow_amount,
+                FeeCalculation::Inclusive,
+                fee_rebate_bps,
+            )?;
             let receive_amount = borrow_amount
                 .try_floor_u64()?
                 .checked_sub(borrow_fee)
@@ -291,10 +651,11 @@ impl Reserve {
         } else {
             let receive_amount = amount_to_borrow;
             let borrow_amount = Decimal::from(receive_amount);
-            let (borrow_fee, host_fee) = self
-                .config
-                .fees
-                .calculate_borrow_fees(borrow_amount, FeeCalculation::Exclusive)?;
+            let (borrow_fee, host_fee) = self.config.fees.calculate_borrow_fees(
+                borrow_amount,
+                FeeCalculation::Exclusive,
+                fee_rebate_bps,
+            )?;
 
             let borrow_amount = borrow_amount.try_add(borrow_fee.into())?;
             let borrow_value = self
@@ -302,6 +663,14 @@ impl Reserve {
                 .try_mul(self.borrow_weight())?;
             if borrow_value > max_borrow_value {
                 msg!("Borrow value cannot exceed maximum borrow value");
+                log_error_context(
+                    LendingError::BorrowTooLarge,
+                    &[
+                        ("amount", &amount_to_borrow),
+                        ("borrow_value", &borrow_value),
+                        ("max_borrow_value", &max_borrow_value),
+                    ],
+                );
                 return Err(LendingError::BorrowTooLarge.into());
             }
 
@@ -341,9 +710,9 @@ impl Reserve {
             return Err(LendingError::ObligationHealthy.into());
         }
 
-        let liquidation_bonus = Decimal::from_percent(self.config.liquidation_bonus);
-        let max_liquidation_bonus = Decimal::from_percent(self.config.max_liquidation_bonus);
-        let protocol_liquidation_fee = Decimal::from_deca_bps(self.config.protocol_liquidation_fee);
+        let liquidation_bonus = Decimal::from(self.config.liquidation_bonus());
+        let max_liquidation_bonus = Decimal::from(self.config.max_liquidation_bonus());
+        let protocol_liquidation_fee = Decimal::from(self.config.protocol_liquidation_fee());
 
         // could also return the average of liquidation bonus and max liquidation bonus here, but
         // i don't think it matters
@@ -383,6 +752,19 @@ impl Reserve {
         Ok(min(bonus, Decimal::from_percent(MAX_BONUS_PCT)))
     }
 
+    /// Calculate bonus for a liquidation that pays out in cTokens only. This is the regular bonus
+    /// plus COLLATERAL_LIQUIDATION_EXTRA_BONUS_BPS, in range [0, MAX_BONUS_PCT]
+    pub fn calculate_collateral_liquidation_bonus(
+        &self,
+        obligation: &Obligation,
+    ) -> Result<Decimal, ProgramError> {
+        let bonus = self
+            .calculate_bonus(obligation)?
+            .try_add(Decimal::from_bps(COLLATERAL_LIQUIDATION_EXTRA_BONUS_BPS))?;
+
+        Ok(min(bonus, Decimal::from_percent(MAX_BONUS_PCT)))
+    }
+
     /// Liquidate some or all of an unhealthy obligation
     pub fn calculate_liquidation(
         &self,
@@ -392,7 +774,45 @@ impl Reserve {
         collateral: &ObligationCollateral,
     ) -> Result<CalculateLiquidationResult, ProgramError> {
         let bonus_rate = self.calculate_bonus(obligation)?.try_add(Decimal::one())?;
+        self._calculate_liquidation(
+            amount_to_liquidate,
+            obligation,
+            liquidity,
+            collateral,
+            bonus_rate,
+        )
+    }
+
+    /// Liquidate some or all of an unhealthy obligation, paying out the seized collateral in
+    /// cTokens only. The liquidator gets the extra bonus from
+    /// calculate_collateral_liquidation_bonus in exchange for not redeeming.
+    pub fn calculate_collateral_liquidation(
+        &self,
+        amount_to_liquidate: u64,
+        obligation: &Obligation,
+        liquidity: &ObligationLiquidity,
+        collateral: &ObligationCollateral,
+    ) -> Result<CalculateLiquidationResult, ProgramError> {
+        let bonus_rate = self
+            .calculate_collateral_liquidation_bonus(obligation)?
+            .try_add(Decimal::one())?;
+        self._calculate_liquidation(
+            amount_to_liquidate,
+            obligation,
+            liquidity,
+            collateral,
+            bonus_rate,
+        )
+    }
 
+    fn _calculate_liquidation(
+        &self,
+        amount_to_liquidate: u64,
+        obligation: &Obligation,
+        liquidity: &ObligationLiquidity,
+        collateral: &ObligationCollateral,
+        bonus_rate: Decimal,
+    ) -> Result<CalculateLiquidationResult, ProgramError> {
         let max_amount = if amount_to_liquidate == u64::MAX {
             liquidity.borrowed_amount_wads
         } else {
@@ -500,13 +920,20 @@ impl Reserve {
         // After deploying must update all reserves to set liquidation fee then redeploy with this line instead of hardcode
         let protocol_fee = std::cmp::max(
             nonbonus_amount
-                .try_mul(Decimal::from_deca_bps(self.config.protocol_liquidation_fee))?
+                .try_mul(Decimal::from(self.config.protocol_liquidation_fee()))?
                 .try_ceil_u64()?,
             1,
         );
         Ok(protocol_fee)
     }
 
+    /// Calculate the withdraw fee on `liquidity_amount` redeemed liquidity, rounded up
+    pub fn calculate_withdraw_fee(&self, liquidity_amount: u64) -> Result<u64, ProgramError> {
+        Decimal::from(liquidity_amount)
+            .try_mul(Decimal::from(self.config.withdraw_fee_bps()))?
+            .try_ceil_u64()
+    }
+
     /// Calculate protocol fee redemption accounting for availible liquidity and accumulated fees
     pub fn calculate_redeem_fees(&self) -> Result<u64, ProgramError> {
         Ok(min(
@@ -516,6 +943,36 @@ impl Reserve {
                 .try_floor_u64()?,
         ))
     }
+
+    /// Ratio of current deposits and borrows to their hard and soft caps. A ratio above 1 means
+    /// the cap has been exceeded. Caps that are zero or unset have no ratio.
+    pub fn cap_utilization(&self) -> Result<CapUtilization, ProgramError> {
+        let total_supply = self.liquidity.total_supply()?;
+        let borrowed_amount = self.liquidity.borrowed_amount_wads;
+
+        Ok(CapUtilization {
+            deposit_limit_ratio: cap_ratio(total_supply, self.config.deposit_limit, 100)?,
+            deposit_soft_cap_ratio: cap_ratio(
+                total_supply,
+                self.config.deposit_limit,
+                self.config.deposit_soft_cap_pct,
+            )?,
+            borrow_limit_ratio: cap_ratio(borrowed_amount, self.config.borrow_limit, 100)?,
+            borrow_soft_cap_ratio: cap_ratio(
+                borrowed_amount,
+                self.config.borrow_limit,
+                self.config.borrow_soft_cap_pct,
+            )?,
+        })
+    }
+}
+
+fn cap_ratio(amount: Decimal, limit: u64, cap_pct: u8) -> Result<Option<Decimal>, ProgramError> {
+    let cap = Decimal::from(limit).try_mul(Rate::from_percent(cap_pct))?;
+    if cap == Decimal::zero() {
+        return Ok(None);
+    }
+    amount.try_div(cap).map(Some)
 }
 
 /// Initialize a reserve
@@ -532,6 +989,8 @@ pub struct InitReserveParams {
     pub config: ReserveConfig,
     /// rate limiter config
     pub rate_limiter_config: RateLimiterConfig,
+    /// Slots per year of the lending market
+    pub slots_per_year: u64,
 }
 
 /// Calculate borrow result
@@ -571,6 +1030,19 @@ pub struct CalculateLiquidationResult {
     pub bonus_rate: Decimal,
 }
 
+/// Reserve cap utilization, used to alert before deposit and borrow limits are hit
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CapUtilization {
+    /// Total supply divided by the deposit limit
+    pub deposit_limit_ratio: Option<Decimal>,
+    /// Total supply divided by the soft deposit cap
+    pub deposit_soft_cap_ratio: Option<Decimal>,
+    /// Borrowed amount divided by the borrow limit
+    pub borrow_limit_ratio: Option<Decimal>,
+    /// Borrowed amount divided by the soft borrow cap
+    pub borrow_soft_cap_ratio: Option<Decimal>,
+}
+
 /// Reserve liquidity
 #[derive(Clone, Debug, Default, PartialEq, Eq)]
 pub struct ReserveLiquidity {
@@ -596,6 +1068,14 @@ pub struct ReserveLiquidity {
     pub market_price: Decimal,
     /// Smoothed reserve liquidity market price for the liquidity (eg TWAP, VWAP, EMA)
     pub smoothed_market_price: Decimal,
+    /// Total bad debt ever forgiven, i.e. socialized across ctoken holders
+    pub lifetime_forgiven_amount_wads: Decimal,
+    /// Part of borrowed_amount_wads borrowed at fixed rates, which doesn't compound at the
+    /// variable rate
+    pub fixed_borrowed_amount_wads: Decimal,
+    /// Interest accrued per year by the fixed rate borrows, the sum of their
+    /// [ObligationLiquidity::fixed_interest_per_year]
+    pub fixed_interest_per_year: u64,
 }
 
 impl ReserveLiquidity {
@@ -613,6 +1093,9 @@ impl ReserveLiquidity {
             accumulated_protocol_fees_wads: Decimal::zero(),
             market_price: params.market_price,
             smoothed_market_price: params.smoothed_market_price,
+            lifetime_forgiven_amount_wads: Decimal::zero(),
+            fixed_borrowed_amount_wads: Decimal::zero(),
+            fixed_interest_per_year: 0,
         }
     }
 
@@ -636,6 +1119,13 @@ impl ReserveLiquidity {
     pub fn withdraw(&mut self, liquidity_amount: u64) -> ProgramResult {
         if liquidity_amount > self.available_amount {
             msg!("Withdraw amount cannot exceed available amount");
+            log_error_context(
+                LendingError::InsufficientLiquidity,
+                &[
+                    ("amount", &liquidity_amount),
+                    ("available", &self.available_amount),
+                ],
+            );
             return Err(LendingError::InsufficientLiquidity.into());
         }
         self.available_amount = self
@@ -678,7 +1168,79 @@ impl ReserveLiquidity {
     /// this reserve.
     pub fn forgive_debt(&mut self, liquidity_amount: Decimal) -> ProgramResult {
         self.borrowed_amount_wads = self.borrowed_amount_wads.try_sub(liquidity_amount)?;
+        self.lifetime_forgiven_amount_wads = self
+            .lifetime_forgiven_amount_wads
+            .try_add(liquidity_amount)?;
+
+        Ok(())
+    }
+
+    /// Move a fixed rate borrow of `borrow_amount`, accruing `interest_per_year`, into the fixed
+    /// rate bucket. The amount has to be borrowed with [ReserveLiquidity::borrow] as well.
+    pub fn borrow_fixed_rate(
+        &mut self,
+        borrow_amount: Decimal,
+        interest_per_year: u64,
+    ) -> ProgramResult {
+        self.fixed_borrowed_amount_wads = self.fixed_borrowed_amount_wads.try_add(borrow_amount)?;
+        self.fixed_interest_per_year = self
+            .fixed_interest_per_year
+            .checked_add(interest_per_year)
+            .ok_or(LendingError::MathOverflow)?;
+        Ok(())
+    }
+
+    /// Remove `settle_amount` of a borrow position from the fixed rate bucket when it is repaid,
+    /// liquidated or forgiven. Does nothing for variable rate positions.
+    pub fn settle_fixed_rate_borrow(
+        &mut self,
+        liquidity: &ObligationLiquidity,
+        settle_amount: Decimal,
+    ) -> ProgramResult {
+        if liquidity.rate_mode != BorrowRateMode::Fixed {
+            return Ok(());
+        }
+        let interest_per_year = liquidity.fixed_interest_settled(settle_amount)?;
+        self.fixed_borrowed_amount_wads = self
+            .fixed_borrowed_amount_wads
+            .try_sub(settle_amount.min(self.fixed_borrowed_amount_wads))?;
+        self.fixed_interest_per_year = self
+            .fixed_interest_per_year
+            .saturating_sub(interest_per_year);
+        Ok(())
+    }
 
+    /// Waive up to `interest` of a borrow's debt out of the accumulated protocol fees, so
+    /// depositors keep earning on it. Returns the interest waived, no more than the fees.
+    pub fn waive_interest(&mut self, interest: Decimal) -> Result<Decimal, ProgramError> {
+        let waived_interest = interest
+            .min(self.accumulated_protocol_fees_wads)
+            .min(self.borrowed_amount_wads);
+        self.borrowed_amount_wads = self.borrowed_amount_wads.try_sub(waived_interest)?;
+        self.accumulated_protocol_fees_wads = self
+            .accumulated_protocol_fees_wads
+            .try_sub(waived_interest)?;
+        Ok(waived_interest)
+    }
+
+    /// Accrue the simple interest of the fixed rate borrows over elapsed slots
+    fn accrue_fixed_interest(
+        &mut self,
+        slots_elapsed: u64,
+        slots_per_year: u64,
+        take_rate: Rate,
+    ) -> ProgramResult {
+        if self.fixed_interest_per_year == 0 {
+            return Ok(());
+        }
+        let interest = Decimal::from(self.fixed_interest_per_year)
+            .try_mul(slots_elapsed)?
+            .try_div(slots_per_year)?;
+        self.fixed_borrowed_amount_wads = self.fixed_borrowed_amount_wads.try_add(interest)?;
+        self.borrowed_amount_wads = self.borrowed_amount_wads.try_add(interest)?;
+        self.accumulated_protocol_fees_wads = interest
+            .try_mul(take_rate)?
+            .try_add(self.accumulated_protocol_fees_wads)?;
         Ok(())
     }
 
@@ -707,25 +1269,53 @@ impl ReserveLiquidity {
         self.borrowed_amount_wads.try_div(denominator)?.try_into()
     }
 
-    /// Compound current borrow rate over elapsed slots
+    /// Compound current borrow rate over elapsed slots, and charge the idle liquidity fee on
+    /// liquidity that is neither borrowed nor owed to the protocol
     fn compound_interest(
         &mut self,
         current_borrow_rate: Rate,
         slots_elapsed: u64,
+        slots_per_year: u64,
         take_rate: Rate,
+        idle_liquidity_fee_rate: Rate,
     ) -> ProgramResult {
-        let slot_interest_rate = current_borrow_rate.try_div(SLOTS_PER_YEAR)?;
+        // the idle fee is moved from depositors to the protocol, which lowers total_supply and
+        // therefore the collateral exchange rate. The liquidity itself never leaves the reserve.
+        if idle_liquidity_fee_rate > Rate::zero() {
+            let idle_liquidity = Decimal::from(self.available_amount)
+                .max(self.accumulated_protocol_fees_wads)
+                .try_sub(self.accumulated_protocol_fees_wads)?;
+            let remaining_rate = Rate::one()
+                .try_sub(apr_to_slot_rate(idle_liquidity_fee_rate, slots_per_year)?)?
+                .try_pow(slots_elapsed)?;
+            let idle_liquidity_fee =
+                idle_liquidity.try_mul(Rate::one().try_sub(remaining_rate)?)?;
+            self.accumulated_protocol_fees_wads = self
+                .accumulated_protocol_fees_wads
+                .try_add(idle_liquidity_fee)?;
+        }
+
+        let slot_interest_rate = apr_to_slot_rate(current_borrow_rate, slots_per_year)?;
         let compounded_interest_rate = Rate::one()
             .try_add(slot_interest_rate)?
-            .try_pow(slots_elapsed)?;
-        self.cumulative_borrow_rate_wads = self
+            .try_pow(slots_elapsed)
+            .map_err(|_| cumulative_borrow_rate_overflow())?;
+        let cumulative_borrow_rate_wads = self
             .cumulative_borrow_rate_wads
-            .try_mul(compounded_interest_rate)?;
+            .try_mul(compounded_interest_rate)
+            .map_err(|_| cumulative_borrow_rate_overflow())?;
+        if cumulative_borrow_rate_wads > MAX_CUMULATIVE_BORROW_RATE {
+            return Err(cumulative_borrow_rate_overflow());
+        }
+        self.cumulative_borrow_rate_wads = cumulative_borrow_rate_wads;
 
-        let net_new_debt = self
+        let variable_borrowed_amount_wads = self
             .borrowed_amount_wads
+            .try_sub(self.fixed_borrowed_amount_wads)
+            .unwrap_or_else(|_| Decimal::zero());
+        let net_new_debt = variable_borrowed_amount_wads
             .try_mul(compounded_interest_rate)?
-            .try_sub(self.borrowed_amount_wads)?;
+            .try_sub(variable_borrowed_amount_wads)?;
 
         self.accumulated_protocol_fees_wads = net_new_debt
             .try_mul(take_rate)?
@@ -736,6 +1326,11 @@ impl ReserveLiquidity {
     }
 }
 
+fn cumulative_borrow_rate_overflow() -> ProgramError {
+    msg!("Cumulative borrow rate would overflow, interest can't accrue at the current borrow rate");
+    LendingError::CumulativeBorrowRateOverflow.into()
+}
+
 /// Create a new reserve liquidity
 pub struct NewReserveLiquidityParams {
     /// Reserve liquidity mint address
@@ -849,6 +1444,26 @@ impl CollateralExchangeRate {
     ) -> Result<Decimal, ProgramError> {
         liquidity_amount.try_mul(self.0)
     }
+
+    /// Convert an amount of reserve collateral to liquidity, rounding down
+    pub fn collateral_amount_to_liquidity(
+        &self,
+        collateral_amount: CollateralAmount,
+    ) -> Result<LiquidityAmount, ProgramError> {
+        Ok(LiquidityAmount(
+            self.collateral_to_liquidity(collateral_amount.0)?,
+        ))
+    }
+
+    /// Convert an amount of reserve liquidity to collateral, rounding down
+    pub fn liquidity_amount_to_collateral(
+        &self,
+        liquidity_amount: LiquidityAmount,
+    ) -> Result<CollateralAmount, ProgramError> {
+        Ok(CollateralAmount(
+            self.liquidity_to_collateral(liquidity_amount.0)?,
+        ))
+    }
 }
 
 impl From<CollateralExchangeRate> for Rate {
@@ -900,6 +1515,107 @@ pub struct ReserveConfig {
     pub added_borrow_weight_bps: u64,
     /// Type of the reserve (Regular, Isolated)
     pub reserve_type: ReserveType,
+    /// Soft deposit cap used for alerting, as a percentage of deposit_limit. 0 if unset
+    pub deposit_soft_cap_pct: u8,
+    /// Soft borrow cap used for alerting, as a percentage of borrow_limit. 0 if unset
+    pub borrow_soft_cap_pct: u8,
+    /// Annual fee charged to depositors on idle (unborrowed) liquidity, in basis points,
+    /// compounded every slot and accrued to the protocol. 0 for regular reserves
+    pub idle_liquidity_fee_bps: u64,
+    /// Receiver of the protocol liquidation fee, eg an insurance vault. Falls back to fee_receiver
+    /// if unset
+    pub liquidation_fee_receiver: Option<Pubkey>,
+    /// Utilization above which borrows are rejected, as a percentage, leaving a buffer for
+    /// withdrawals. Must be in [optimal_utilization_rate, max_utilization_rate]. 0 if unset
+    pub borrow_utilization_cap: u8,
+    /// Annual rate, in bps, locked by borrows in fixed rate mode. 0 if fixed rate borrows are
+    /// disabled
+    pub fixed_borrow_rate_bps: u16,
+    /// Slots a fixed rate borrow keeps its rate for before it can be converted to the variable
+    /// rate
+    pub fixed_borrow_max_duration_slots: u64,
+    /// Fee charged on liquidity redeemed by depositors, in basis points, sent to fee_receiver.
+    /// Liquidations don't pay it. 0 if unset
+    pub withdraw_fee_bps: u16,
+    /// Lowest plausible oracle price of the liquidity, in basis points of the quote currency.
+    /// 0 if unset
+    pub min_price_bps: u32,
+    /// Highest plausible oracle price of the liquidity, in basis points of the quote currency.
+    /// 0 if unset
+    pub max_price_bps: u32,
+    /// What refresh_reserve does with oracle prices outside [min_price_bps, max_price_bps]
+    pub price_bounds_mode: PriceBoundsMode,
+}
+
+impl ReserveConfig {
+    /// Token account the protocol liquidation fee is sent to
+    pub fn liquidation_fee_receiver(&self) -> Pubkey {
+        self.liquidation_fee_receiver.unwrap_or(self.fee_receiver)
+    }
+
+    /// Checks an oracle price against the plausible price bounds of the reserve, returning the
+    /// price to use: the price itself if in bounds, the closest bound in clamp mode
+    pub fn bound_price(&self, price: Decimal) -> Result<Decimal, ProgramError> {
+        let min_price = Decimal::from(self.min_price_bps());
+        let max_price = Decimal::from(self.max_price_bps());
+        let bounded_price = if self.min_price_bps > 0 && price < min_price {
+            min_price
+        } else if self.max_price_bps > 0 && price > max_price {
+            max_price
+        } else {
+            return Ok(price);
+        };
+
+        match self.price_bounds_mode {
+            PriceBoundsMode::Reject => {
+                msg!(
+                    "Oracle price {} is outside of the reserve price bounds [{}, {}]",
+                    price,
+                    min_price,
+                    max_price
+                );
+                Err(LendingError::PriceOutOfBounds.into())
+            }
+            PriceBoundsMode::Clamp => Ok(bounded_price),
+        }
+    }
+}
+
+macro_rules! reserve_config_units {
+    ($($field:ident: $unit:ident,)*) => {
+        impl ReserveConfig {
+            $(
+                #[doc = concat!("`", stringify!($field), "` as a typed [", stringify!($unit), "]")]
+                pub fn $field(&self) -> $unit {
+                    $unit(self.$field.into())
+                }
+            )*
+        }
+    };
+}
+
+reserve_config_units! {
+    optimal_utilization_rate: Percent,
+    max_utilization_rate: Percent,
+    loan_to_value_ratio: Percent,
+    liquidation_bonus: Percent,
+    max_liquidation_bonus: Percent,
+    liquidation_threshold: Percent,
+    max_liquidation_threshold: Percent,
+    min_borrow_rate: Percent,
+    optimal_borrow_rate: Percent,
+    max_borrow_rate: Percent,
+    protocol_liquidation_fee: DecaBps,
+    protocol_take_rate: Percent,
+    added_borrow_weight_bps: Bps,
+    deposit_soft_cap_pct: Percent,
+    borrow_soft_cap_pct: Percent,
+    idle_liquidity_fee_bps: Bps,
+    borrow_utilization_cap: Percent,
+    fixed_borrow_rate_bps: Bps,
+    withdraw_fee_bps: Bps,
+    min_price_bps: Bps,
+    max_price_bps: Bps,
 }
 
 /// validates reserve configs
@@ -967,8 +1683,9 @@ pub fn validate_reserve_config(config: ReserveConfig) -> ProgramResult {
         );
         return Err(LendingError::InvalidConfig.into());
     }
-    if config.max_liquidation_bonus as u64 * 100 + config.protocol_liquidation_fee as u64 * 10
-        > MAX_BONUS_PCT as u64 * 100
+    if Bps::from(config.max_liquidation_bonus())
+        .try_add(Bps::from(config.protocol_liquidation_fee()))?
+        > Bps::from(Percent(MAX_BONUS_PCT))
     {
         msg!(
             "Max liquidation bonus + protocol liquidation fee must be in pct range [0, {}]",
@@ -980,6 +1697,44 @@ pub fn validate_reserve_config(config: ReserveConfig) -> ProgramResult {
         msg!("Protocol take rate must be in range [0, 100]");
         return Err(LendingError::InvalidConfig.into());
     }
+    if config.deposit_soft_cap_pct > 100 {
+        msg!("Deposit soft cap must be in range [0, 100]");
+        return Err(LendingError::InvalidConfig.into());
+    }
+    if config.borrow_soft_cap_pct > 100 {
+        msg!("Borrow soft cap must be in range [0, 100]");
+        return Err(LendingError::InvalidConfig.into());
+    }
+    if config.idle_liquidity_fee_bps > 10_000 {
+        msg!("Idle liquidity fee must be in range [0, 10_000] bps");
+        return Err(LendingError::InvalidConfig.into());
+    }
+    if config.liquidation_fee_receiver == Some(Pubkey::default()) {
+        msg!("Liquidation fee receiver must be unset or a valid token account");
+        return Err(LendingError::InvalidConfig.into());
+    }
+    if config.borrow_utilization_cap != 0
+        && (config.borrow_utilization_cap < config.optimal_utilization_rate
+            || config.borrow_utilization_cap > config.max_utilization_rate)
+    {
+        msg!("Borrow utilization cap must be 0 or in range [optimal_utilization_rate, max_utilization_rate]");
+        return Err(LendingError::InvalidConfig.into());
+    }
+    if (config.fixed_borrow_rate_bps == 0) != (config.fixed_borrow_max_duration_slots == 0) {
+        msg!("Fixed borrow rate and max duration must be both set or both 0");
+        return Err(LendingError::InvalidConfig.into());
+    }
+    if config.withdraw_fee_bps > MAX_WITHDRAW_FEE_BPS {
+        msg!(
+            "Withdraw fee must be in range [0, {}] bps",
+            MAX_WITHDRAW_FEE_BPS
+        );
+        return Err(LendingError::InvalidConfig.into());
+    }
+    if config.max_price_bps > 0 && config.min_price_bps > config.max_price_bps {
+        msg!("Min price must be at most max price");
+        return Err(LendingError::InvalidConfig.into());
+    }
 
     if config.reserve_type == ReserveType::Isolated
         && !(config.loan_to_value_ratio == 0 && config.liquidation_threshold == 0)
@@ -1011,6 +1766,79 @@ impl FromStr for ReserveType {
     }
 }
 
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, FromPrimitive)]
+/// What refresh_reserve does with an oracle price outside of the reserve price bounds
+pub enum PriceBoundsMode {
+    #[default]
+    /// the refresh fails, freezing the reserve until the oracle recovers
+    Reject = 0,
+    /// the price is clamped to the closest bound
+    Clamp = 1,
+}
+
+impl FromStr for PriceBoundsMode {
+    type Err = ProgramError;
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input {
+            "Reject" => Ok(PriceBoundsMode::Reject),
+            "Clamp" => Ok(PriceBoundsMode::Clamp),
+            _ => Err(LendingError::InvalidConfig.into()),
+        }
+    }
+}
+
+/// Lifecycle of a reserve being delisted. Reserves only move forward through the states:
+/// Active -> WindDown -> Closed.
+///
+/// Reserves that aren't active accept no new deposits or borrows, but keep accruing interest and
+/// can still be repaid, withdrawn from, redeemed and liquidated.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ReserveStatus {
+    /// Regular operation
+    #[default]
+    Active,
+    /// Loan to value ratio decays linearly every slot, down to 0
+    WindDown {
+        /// Slot the current decay started at
+        start_slot: Slot,
+        /// Loan to value ratio when the current decay started, as a Wad
+        start_loan_to_value_ratio_wad: u64,
+        /// Decrease in loan to value ratio every slot, as a Wad
+        ltv_decay_per_slot_wad: u64,
+    },
+    /// Loan to value ratio is 0
+    Closed,
+}
+
+impl ReserveStatus {
+    /// Effective loan to value ratio at a slot, which is never above the configured one
+    pub fn loan_to_value_ratio(&self, config_loan_to_value_ratio: Rate, slot: Slot) -> Rate {
+        match *self {
+            Self::Active => config_loan_to_value_ratio,
+            Self::WindDown {
+                start_slot,
+                start_loan_to_value_ratio_wad,
+                ltv_decay_per_slot_wad,
+            } => {
+                let decay = (ltv_decay_per_slot_wad as u128)
+                    .saturating_mul(slot.saturating_sub(start_slot) as u128);
+                let decayed = (start_loan_to_value_ratio_wad as u128).saturating_sub(decay);
+                // can't exceed the starting ratio, so it fits in a u64
+                min(
+                    config_loan_to_value_ratio,
+                    Rate::from_scaled_val(decayed as u64),
+                )
+            }
+            Self::Closed => Rate::zero(),
+        }
+    }
+
+    /// Whether the reserve accepts new deposits and borrows
+    pub fn is_active(&self) -> bool {
+        *self == Self::Active
+    }
+}
+
 /// Additional fee information on a reserve
 ///
 /// These exist separately from interest accrual fees, and are specifically for the program owner
@@ -1033,13 +1861,20 @@ pub struct ReserveFees {
 }
 
 impl ReserveFees {
-    /// Calculate the owner and host fees on borrow
+    /// Calculate the owner and host fees on borrow, with `fee_rebate_bps` of the borrow fee
+    /// waived for borrows routed by protocols the lending market grants a rebate to
     pub fn calculate_borrow_fees(
         &self,
         borrow_amount: Decimal,
         fee_calculation: FeeCalculation,
+        fee_rebate_bps: u16,
     ) -> Result<(u64, u64), ProgramError> {
-        self.calculate_fees(borrow_amount, self.borrow_fee_wad, fee_calculation)
+        let fee_rebate_bps = min(fee_rebate_bps, MAX_FEE_REBATE_BPS) as u128;
+        let borrow_fee_wad = (self.borrow_fee_wad as u128)
+            .checked_mul(MAX_FEE_REBATE_BPS as u128 - fee_rebate_bps)
+            .ok_or(LendingError::MathOverflow)?
+            / MAX_FEE_REBATE_BPS as u128;
+        self.calculate_fees(borrow_amount, borrow_fee_wad as u64, fee_calculation)
     }
 
     /// Calculate the owner and host fees on flash loan
@@ -1089,6 +1924,10 @@ impl ReserveFees {
             let borrow_fee_decimal = borrow_fee_amount.max(minimum_fee.into());
             if borrow_fee_decimal >= amount {
                 msg!("Borrow amount is too small to receive liquidity after fees");
+                log_error_context(
+                    LendingError::BorrowTooSmall,
+                    &[("amount", &amount), ("borrow_fee", &borrow_fee_decimal)],
+                );
                 return Err(LendingError::BorrowTooSmall.into());
             }
 
@@ -1124,15 +1963,92 @@ impl IsInitialized for Reserve {
     }
 }
 
-const RESERVE_LEN: usize = 619; // 1 + 8 + 1 + 32 + 32 + 1 + 32 + 32 + 32 + 8 + 16 + 16 + 16 + 32 + 8 + 32 + 1 + 1 + 1 + 1 + 1 + 1 + 1 + 8 + 8 + 1 + 8 + 8 + 32 + 1 + 1 + 16 + 230
+account_layout! {
+    /// Byte layout of a packed reserve
+    ReserveLayout, ReserveLayoutMut, RESERVE_LEN = 619 {
+        version: 1,
+        last_update_slot: 8,
+        last_update_stale: 1,
+        lending_market: PUBKEY_BYTES,
+        liquidity_mint_pubkey: PUBKEY_BYTES,
+        liquidity_mint_decimals: 1,
+        liquidity_supply_pubkey: PUBKEY_BYTES,
+        liquidity_pyth_oracle_pubkey: PUBKEY_BYTES,
+        liquidity_switchboard_oracle_pubkey: PUBKEY_BYTES,
+        liquidity_available_amount: 8,
+        liquidity_borrowed_amount_wads: 16,
+        liquidity_cumulative_borrow_rate_wads: 16,
+        liquidity_market_price: 16,
+        collateral_mint_pubkey: PUBKEY_BYTES,
+        collateral_mint_total_supply: 8,
+        collateral_supply_pubkey: PUBKEY_BYTES,
+        config_optimal_utilization_rate: 1,
+        config_loan_to_value_ratio: 1,
+        config_liquidation_bonus: 1,
+        config_liquidation_threshold: 1,
+        config_min_borrow_rate: 1,
+        config_optimal_borrow_rate: 1,
+        config_max_borrow_rate: 1,
+        config_fees_borrow_fee_wad: 8,
+        config_fees_flash_loan_fee_wad: 8,
+        config_fees_host_fee_percentage: 1,
+        config_deposit_limit: 8,
+        config_borrow_limit: 8,
+        config_fee_receiver: PUBKEY_BYTES,
+        config_protocol_liquidation_fee: 1,
+        config_protocol_take_rate: 1,
+        liquidity_accumulated_protocol_fees_wads: 16,
+        rate_limiter: RATE_LIMITER_LEN,
+        config_added_borrow_weight_bps: 8,
+        liquidity_smoothed_market_price: 16,
+        config_asset_type: 1,
+        config_max_utilization_rate: 1,
+        config_super_max_borrow_rate: 8,
+        config_max_liquidation_bonus: 1,
+        config_max_liquidation_threshold: 1,
+        config_deposit_soft_cap_pct: 1,
+        config_borrow_soft_cap_pct: 1,
+        config_idle_liquidity_fee_bps: 8,
+        slots_per_year: 8,
+        status: 1,
+        status_wind_down_start_slot: 8,
+        status_wind_down_start_loan_to_value_ratio_wad: 8,
+        status_wind_down_ltv_decay_per_slot_wad: 8,
+        config_liquidation_fee_receiver: PUBKEY_BYTES,
+        liquidity_lifetime_forgiven_amount_wads: 16,
+        config_borrow_utilization_cap: 1,
+        has_fee_redemption_hook: 1,
+        config_fixed_borrow_rate_bps: 2,
+        config_fixed_borrow_max_duration_slots: 8,
+        liquidity_fixed_borrowed_amount_wads: 16,
+        liquidity_fixed_interest_per_year: 8,
+        config_withdraw_fee_bps: 2,
+        config_min_price_bps: 4,
+        config_max_price_bps: 4,
+        config_price_bounds_mode: 1,
+    }
+}
+
+impl Reserve {
+    /// Packs only `last_update` into an already packed reserve, for refreshes that change nothing
+    /// else
+    pub fn pack_last_update(&self, output: &mut [u8]) {
+        let ReserveLayoutMut {
+            last_update_slot,
+            last_update_stale,
+            ..
+        } = ReserveLayoutMut::new(output);
+        *last_update_slot = self.last_update.slot.to_le_bytes();
+        pack_bool(self.last_update.stale, last_update_stale);
+    }
+}
+
 impl Pack for Reserve {
     const LEN: usize = RESERVE_LEN;
 
     // @TODO: break this up by reserve / liquidity / collateral / config https://git.io/JOCca
     fn pack_into_slice(&self, output: &mut [u8]) {
-        let output = array_mut_ref![output, 0, RESERVE_LEN];
-        #[allow(clippy::ptr_offset_with_cast)]
-        let (
+        let ReserveLayoutMut {
             version,
             last_update_slot,
             last_update_stale,
@@ -1173,51 +2089,28 @@ impl Pack for Reserve {
             config_super_max_borrow_rate,
             config_max_liquidation_bonus,
             config_max_liquidation_threshold,
-            _padding,
-        ) = mut_array_refs![
-            output,
-            1,
-            8,
-            1,
-            PUBKEY_BYTES,
-            PUBKEY_BYTES,
-            1,
-            PUBKEY_BYTES,
-            PUBKEY_BYTES,
-            PUBKEY_BYTES,
-            8,
-            16,
-            16,
-            16,
-            PUBKEY_BYTES,
-            8,
-            PUBKEY_BYTES,
-            1,
-            1,
-            1,
-            1,
-            1,
-            1,
-            1,
-            8,
-            8,
-            1,
-            8,
-            8,
-            PUBKEY_BYTES,
-            1,
-            1,
-            16,
-            RATE_LIMITER_LEN,
-            8,
-            16,
-            1,
-            1,
-            8,
-            1,
-            1,
-            138
-        ];
+            config_deposit_soft_cap_pct,
+            config_borrow_soft_cap_pct,
+            config_idle_liquidity_fee_bps,
+            slots_per_year,
+            status,
+            status_wind_down_start_slot,
+            status_wind_down_start_loan_to_value_ratio_wad,
+            status_wind_down_ltv_decay_per_slot_wad,
+            config_liquidation_fee_receiver,
+            liquidity_lifetime_forgiven_amount_wads,
+            config_borrow_utilization_cap,
+            has_fee_redemption_hook,
+            config_fixed_borrow_rate_bps,
+            config_fixed_borrow_max_duration_slots,
+            liquidity_fixed_borrowed_amount_wads,
+            liquidity_fixed_interest_per_year,
+            config_withdraw_fee_bps,
+            config_min_price_bps,
+            config_max_price_bps,
+            config_price_bounds_mode,
+            ..
+        } = ReserveLayoutMut::new(output);
 
         // reserve
         *version = self.version.to_le_bytes();
@@ -1250,6 +2143,15 @@ impl Pack for Reserve {
             self.liquidity.smoothed_market_price,
             liquidity_smoothed_market_price,
         );
+        pack_decimal(
+            self.liquidity.lifetime_forgiven_amount_wads,
+            liquidity_lifetime_forgiven_amount_wads,
+        );
+        pack_decimal(
+            self.liquidity.fixed_borrowed_amount_wads,
+            liquidity_fixed_borrowed_amount_wads,
+        );
+        *liquidity_fixed_interest_per_year = self.liquidity.fixed_interest_per_year.to_le_bytes();
 
         // collateral
         collateral_mint_pubkey.copy_from_slice(self.collateral.mint_pubkey.as_ref());
@@ -1277,17 +2179,52 @@ impl Pack for Reserve {
         *config_asset_type = (self.config.reserve_type as u8).to_le_bytes();
 
         self.rate_limiter.pack_into_slice(rate_limiter);
+        *slots_per_year = self.slots_per_year.to_le_bytes();
+
+        // status
+        *status_wind_down_start_slot = [0; 8];
+        *status_wind_down_start_loan_to_value_ratio_wad = [0; 8];
+        *status_wind_down_ltv_decay_per_slot_wad = [0; 8];
+        match self.status {
+            ReserveStatus::Active => status[0] = 0,
+            ReserveStatus::WindDown {
+                start_slot,
+                start_loan_to_value_ratio_wad,
+                ltv_decay_per_slot_wad,
+            } => {
+                status[0] = 1;
+                *status_wind_down_start_slot = start_slot.to_le_bytes();
+                *status_wind_down_start_loan_to_value_ratio_wad =
+                    start_loan_to_value_ratio_wad.to_le_bytes();
+                *status_wind_down_ltv_decay_per_slot_wad = ltv_decay_per_slot_wad.to_le_bytes();
+            }
+            ReserveStatus::Closed => status[0] = 2,
+        }
 
         *config_added_borrow_weight_bps = self.config.added_borrow_weight_bps.to_le_bytes();
         *config_max_liquidation_bonus = self.config.max_liquidation_bonus.to_le_bytes();
         *config_max_liquidation_threshold = self.config.max_liquidation_threshold.to_le_bytes();
+        *config_deposit_soft_cap_pct = self.config.deposit_soft_cap_pct.to_le_bytes();
+        *config_borrow_soft_cap_pct = self.config.borrow_soft_cap_pct.to_le_bytes();
+        *config_idle_liquidity_fee_bps = self.config.idle_liquidity_fee_bps.to_le_bytes();
+        match self.config.liquidation_fee_receiver {
+            Some(receiver) => config_liquidation_fee_receiver.copy_from_slice(receiver.as_ref()),
+            None => config_liquidation_fee_receiver.copy_from_slice(&[0u8; 32]),
+        }
+        *config_borrow_utilization_cap = self.config.borrow_utilization_cap.to_le_bytes();
+        *config_fixed_borrow_rate_bps = self.config.fixed_borrow_rate_bps.to_le_bytes();
+        *config_fixed_borrow_max_duration_slots =
+            self.config.fixed_borrow_max_duration_slots.to_le_bytes();
+        *config_withdraw_fee_bps = self.config.withdraw_fee_bps.to_le_bytes();
+        *config_min_price_bps = self.config.min_price_bps.to_le_bytes();
+        *config_max_price_bps = self.config.max_price_bps.to_le_bytes();
+        *config_price_bounds_mode = (self.config.price_bounds_mode as u8).to_le_bytes();
+        pack_bool(self.has_fee_redemption_hook, has_fee_redemption_hook);
     }
 
     /// Unpacks a byte buffer into a [ReserveInfo](struct.ReserveInfo.html).
     fn unpack_from_slice(input: &[u8]) -> Result<Self, ProgramError> {
-        let input = array_ref![input, 0, RESERVE_LEN];
-        #[allow(clippy::ptr_offset_with_cast)]
-        let (
+        let ReserveLayout {
             version,
             last_update_slot,
             last_update_stale,
@@ -1328,51 +2265,28 @@ impl Pack for Reserve {
             config_super_max_borrow_rate,
             config_max_liquidation_bonus,
             config_max_liquidation_threshold,
-            _padding,
-        ) = array_refs![
-            input,
-            1,
-            8,
-            1,
-            PUBKEY_BYTES,
-            PUBKEY_BYTES,
-            1,
-            PUBKEY_BYTES,
-            PUBKEY_BYTES,
-            PUBKEY_BYTES,
-            8,
-            16,
-            16,
-            16,
-            PUBKEY_BYTES,
-            8,
-            PUBKEY_BYTES,
-            1,
-            1,
-            1,
-            1,
-            1,
-            1,
-            1,
-            8,
-            8,
-            1,
-            8,
-            8,
-            PUBKEY_BYTES,
-            1,
-            1,
-            16,
-            RATE_LIMITER_LEN,
-            8,
-            16,
-            1,
-            1,
-            8,
-            1,
-            1,
-            138
-        ];
+            config_deposit_soft_cap_pct,
+            config_borrow_soft_cap_pct,
+            config_idle_liquidity_fee_bps,
+            slots_per_year,
+            status,
+            status_wind_down_start_slot,
+            status_wind_down_start_loan_to_value_ratio_wad,
+            status_wind_down_ltv_decay_per_slot_wad,
+            config_liquidation_fee_receiver,
+            liquidity_lifetime_forgiven_amount_wads,
+            config_borrow_utilization_cap,
+            has_fee_redemption_hook,
+            config_fixed_borrow_rate_bps,
+            config_fixed_borrow_max_duration_slots,
+            liquidity_fixed_borrowed_amount_wads,
+            liquidity_fixed_interest_per_year,
+            config_withdraw_fee_bps,
+            config_min_price_bps,
+            config_max_price_bps,
+            config_price_bounds_mode,
+            ..
+        } = ReserveLayout::new(input);
 
         let version = u8::from_le_bytes(*version);
         if version > PROGRAM_VERSION {
@@ -1395,6 +2309,42 @@ impl Pack for Reserve {
             u8::from_le_bytes(*config_max_liquidation_threshold),
         );
 
+        let status = match status[0] {
+            0 => ReserveStatus::Active,
+            1 => ReserveStatus::WindDown {
+                start_slot: u64::from_le_bytes(*status_wind_down_start_slot),
+                start_loan_to_value_ratio_wad: u64::from_le_bytes(
+                    *status_wind_down_start_loan_to_value_ratio_wad,
+                ),
+                ltv_decay_per_slot_wad: u64::from_le_bytes(
+                    *status_wind_down_ltv_decay_per_slot_wad,
+                ),
+            },
+            2 => ReserveStatus::Closed,
+            _ => {
+                msg!("Reserve status is invalid");
+                return Err(ProgramError::InvalidAccountData);
+            }
+        };
+
+        let reserve_type = match config_asset_type[0] {
+            0 => ReserveType::Regular,
+            1 => ReserveType::Isolated,
+            _ => {
+                msg!("Reserve type is invalid");
+                return Err(ProgramError::InvalidAccountData);
+            }
+        };
+
+        let price_bounds_mode = match config_price_bounds_mode[0] {
+            0 => PriceBoundsMode::Reject,
+            1 => PriceBoundsMode::Clamp,
+            _ => {
+                msg!("Reserve price bounds mode is invalid");
+                return Err(ProgramError::InvalidAccountData);
+            }
+        };
+
         Ok(Self {
             version,
             last_update: LastUpdate {
@@ -1418,6 +2368,11 @@ impl Pack for Reserve {
                 ),
                 market_price: unpack_decimal(liquidity_market_price),
                 smoothed_market_price: unpack_decimal(liquidity_smoothed_market_price),
+                lifetime_forgiven_amount_wads: unpack_decimal(
+                    liquidity_lifetime_forgiven_amount_wads,
+                ),
+                fixed_borrowed_amount_wads: unpack_decimal(liquidity_fixed_borrowed_amount_wads),
+                fixed_interest_per_year: u64::from_le_bytes(*liquidity_fixed_interest_per_year),
             },
             collateral: ReserveCollateral {
                 mint_pubkey: Pubkey::new_from_array(*collateral_mint_pubkey),
@@ -1461,9 +2416,33 @@ impl Pack for Reserve {
                 ),
                 protocol_take_rate: u8::from_le_bytes(*config_protocol_take_rate),
                 added_borrow_weight_bps: u64::from_le_bytes(*config_added_borrow_weight_bps),
-                reserve_type: ReserveType::from_u8(config_asset_type[0]).unwrap(),
+                reserve_type,
+                deposit_soft_cap_pct: u8::from_le_bytes(*config_deposit_soft_cap_pct),
+                borrow_soft_cap_pct: u8::from_le_bytes(*config_borrow_soft_cap_pct),
+                idle_liquidity_fee_bps: u64::from_le_bytes(*config_idle_liquidity_fee_bps),
+                liquidation_fee_receiver: if config_liquidation_fee_receiver == &[0u8; 32] {
+                    None
+                } else {
+                    Some(Pubkey::new_from_array(*config_liquidation_fee_receiver))
+                },
+                borrow_utilization_cap: u8::from_le_bytes(*config_borrow_utilization_cap),
+                fixed_borrow_rate_bps: u16::from_le_bytes(*config_fixed_borrow_rate_bps),
+                fixed_borrow_max_duration_slots: u64::from_le_bytes(
+                    *config_fixed_borrow_max_duration_slots,
+                ),
+                withdraw_fee_bps: u16::from_le_bytes(*config_withdraw_fee_bps),
+                min_price_bps: u32::from_le_bytes(*config_min_price_bps),
+                max_price_bps: u32::from_le_bytes(*config_max_price_bps),
+                price_bounds_mode,
             },
             rate_limiter: RateLimiter::unpack_from_slice(rate_limiter)?,
+            // reserves created before slots per year was configurable have zeroes here
+            slots_per_year: match u64::from_le_bytes(*slots_per_year) {
+                0 => SLOTS_PER_YEAR,
+                slots_per_year => slots_per_year,
+            },
+            status,
+            has_fee_redemption_hook: unpack_bool(has_fee_redemption_hook)?,
         })
     }
 }
@@ -1472,6 +2451,7 @@ impl Pack for Reserve {
 mod test {
     use super::*;
     use crate::math::{PERCENT_SCALER, WAD};
+    use num_traits::FromPrimitive;
     use proptest::prelude::*;
     use rand::Rng;
     use solana_program::native_token::LAMPORTS_PER_SOL;
@@ -1509,6 +2489,9 @@ mod test {
                     accumulated_protocol_fees_wads: rand_decimal(),
                     market_price: rand_decimal(),
                     smoothed_market_price: rand_decimal(),
+                    lifetime_forgiven_amount_wads: rand_decimal(),
+                    fixed_borrowed_amount_wads: rand_decimal(),
+                    fixed_interest_per_year: rng.gen(),
                 },
                 collateral: ReserveCollateral {
                     mint_pubkey: Pubkey::new_unique(),
@@ -1539,14 +2522,52 @@ mod test {
                     protocol_take_rate: rng.gen(),
                     added_borrow_weight_bps: rng.gen(),
                     reserve_type: ReserveType::from_u8(rng.gen::<u8>() % 2).unwrap(),
+                    deposit_soft_cap_pct: rng.gen(),
+                    borrow_soft_cap_pct: rng.gen(),
+                    idle_liquidity_fee_bps: rng.gen(),
+                    liquidation_fee_receiver: if rng.gen_bool(0.5) {
+                        Some(Pubkey::new_unique())
+                    } else {
+                        None
+                    },
+                    borrow_utilization_cap: rng.gen(),
+                    fixed_borrow_rate_bps: rng.gen(),
+                    fixed_borrow_max_duration_slots: rng.gen(),
+                    withdraw_fee_bps: rng.gen(),
+                    min_price_bps: rng.gen(),
+                    max_price_bps: rng.gen(),
+                    price_bounds_mode: PriceBoundsMode::from_u8(rng.gen::<u8>() % 2).unwrap(),
                 },
                 rate_limiter: rand_rate_limiter(),
+                slots_per_year: rng.gen_range(MIN_SLOTS_PER_YEAR..=MAX_SLOTS_PER_YEAR),
+                status: match rng.gen::<u8>() % 3 {
+                    0 => ReserveStatus::Active,
+                    1 => ReserveStatus::WindDown {
+                        start_slot: rng.gen(),
+                        start_loan_to_value_ratio_wad: rng.gen(),
+                        ltv_decay_per_slot_wad: rng.gen(),
+                    },
+                    _ => ReserveStatus::Closed,
+                },
+                has_fee_redemption_hook: rng.gen(),
             };
 
             let mut packed = [0u8; Reserve::LEN];
             Reserve::pack(reserve.clone(), &mut packed).unwrap();
             let unpacked = Reserve::unpack(&packed).unwrap();
             assert_eq!(reserve, unpacked);
+
+            let refreshed = Reserve {
+                last_update: LastUpdate {
+                    slot: rng.gen(),
+                    stale: rng.gen(),
+                },
+                ..reserve
+            };
+            refreshed.pack_last_update(&mut packed);
+            let unpacked = Reserve::unpack(&packed).unwrap();
+            assert_eq!(refreshed, unpacked);
+            assert_eq!(refreshed.last_update.stale, unpacked.last_update.stale);
         }
     }
 
@@ -1698,6 +2719,7 @@ mod test {
                     optimal_utilization_rate: 100,
                     ..ReserveConfig::default()
                 },
+                slots_per_year: SLOTS_PER_YEAR,
                 ..Reserve::default()
             };
 
@@ -1720,15 +2742,23 @@ mod test {
             slots_elapsed in 0..=SLOTS_PER_YEAR,
             borrow_rate in 0..=u8::MAX,
             take_rate in 0..=100u8,
+            idle_liquidity_fee_rate in 0..=100u8,
         ) {
             let mut reserve = Reserve::default();
             let borrow_rate = Rate::from_percent(borrow_rate);
             let take_rate = Rate::from_percent(take_rate);
+            let idle_liquidity_fee_rate = Rate::from_percent(idle_liquidity_fee_rate);
 
             // Simulate running for max 1000 years, assuming that interest is
             // compounded at least once a year
             for _ in 0..1000 {
-                reserve.liquidity.compound_interest(borrow_rate, slots_elapsed, take_rate)?;
+                reserve.liquidity.compound_interest(
+                    borrow_rate,
+                    slots_elapsed,
+                    SLOTS_PER_YEAR,
+                    take_rate,
+                    idle_liquidity_fee_rate,
+                )?;
                 reserve.liquidity.cumulative_borrow_rate_wads.to_scaled_val()?;
                 reserve.liquidity.accumulated_protocol_fees_wads.to_scaled_val()?;
             }
@@ -1753,6 +2783,7 @@ mod test {
                     super_max_borrow_rate: borrow_rate as u64,
                     ..ReserveConfig::default()
                 },
+                slots_per_year: SLOTS_PER_YEAR,
                 ..Reserve::default()
             };
 
@@ -1779,7 +2810,7 @@ mod test {
                 flash_loan_fee_wad,
                 host_fee_percentage,
             };
-            let (total_fee, host_fee) = fees.calculate_borrow_fees(Decimal::from(borrow_amount), FeeCalculation::Exclusive)?;
+            let (total_fee, host_fee) = fees.calculate_borrow_fees(Decimal::from(borrow_amount), FeeCalculation::Exclusive, 0)?;
 
             // The total fee can't be greater than the amount borrowed, as long
             // as amount borrowed is greater than 2.
@@ -1849,6 +2880,104 @@ mod test {
         }
     }
 
+    #[test]
+    fn cumulative_borrow_rate_overflow_horizon() {
+        let mut reserve = Reserve {
+            last_update: LastUpdate::new(0),
+            liquidity: ReserveLiquidity {
+                cumulative_borrow_rate_wads: Decimal::one(),
+                ..ReserveLiquidity::default()
+            },
+            // 100% APR with nothing borrowed
+            config: ReserveConfig {
+                min_borrow_rate: 100,
+                ..ReserveConfig::default()
+            },
+            slots_per_year: SLOTS_PER_YEAR,
+            ..Reserve::default()
+        };
+        assert_eq!(
+            reserve.cumulative_borrow_rate_overflow_horizon(Rate::zero()),
+            Ok(None)
+        );
+
+        // e^t > 2^128 / 10^18 after 47.3 years
+        let horizon = reserve
+            .cumulative_borrow_rate_overflow_horizon(reserve.current_borrow_rate().unwrap())
+            .unwrap()
+            .unwrap();
+        assert_eq!(horizon / SLOTS_PER_YEAR, 47);
+
+        // a quarter of the max doubles twice in about 1.4 years
+        reserve.liquidity.cumulative_borrow_rate_wads =
+            MAX_CUMULATIVE_BORROW_RATE.try_div(4u64).unwrap();
+        let horizon = reserve
+            .cumulative_borrow_rate_overflow_horizon(reserve.current_borrow_rate().unwrap())
+            .unwrap()
+            .unwrap();
+        assert_eq!(horizon * 10 / SLOTS_PER_YEAR, 13);
+
+        let mut accrued = reserve.clone();
+        accrued.accrue_interest(horizon - 1_000).unwrap();
+        assert!(accrued.liquidity.cumulative_borrow_rate_wads <= MAX_CUMULATIVE_BORROW_RATE);
+        let mut packed = [0u8; Reserve::LEN];
+        Reserve::pack(accrued, &mut packed).unwrap();
+
+        let mut overflowed = reserve.clone();
+        assert_eq!(
+            overflowed.accrue_interest(horizon + 1_000),
+            Err(LendingError::CumulativeBorrowRateOverflow.into())
+        );
+    }
+
+    #[test]
+    fn accrue_interest_at_extreme_rates() {
+        // 1,000,000% APR at full utilization
+        let mut reserve = Reserve {
+            last_update: LastUpdate::new(0),
+            liquidity: ReserveLiquidity {
+                borrowed_amount_wads: Decimal::one(),
+                cumulative_borrow_rate_wads: Decimal::one(),
+                ..ReserveLiquidity::default()
+            },
+            config: ReserveConfig {
+                optimal_utilization_rate: 80,
+                max_utilization_rate: 90,
+                super_max_borrow_rate: 1_000_000,
+                ..ReserveConfig::default()
+            },
+            slots_per_year: SLOTS_PER_YEAR,
+            ..Reserve::default()
+        };
+        let borrow_rate = reserve.current_borrow_rate().unwrap();
+        assert_eq!(borrow_rate, Rate::from_percent_u64(1_000_000));
+
+        // overflows in under a week instead of decades
+        let horizon = reserve
+            .cumulative_borrow_rate_overflow_horizon(borrow_rate)
+            .unwrap()
+            .unwrap();
+        assert!(horizon < SLOTS_PER_YEAR / 52);
+
+        // a year without a refresh compounds past what a Rate holds
+        assert_eq!(
+            reserve.clone().accrue_interest(SLOTS_PER_YEAR),
+            Err(LendingError::CumulativeBorrowRateOverflow.into())
+        );
+
+        // refreshing regularly gets up to the horizon and then fails
+        let mut slot = 0;
+        while slot < horizon {
+            slot = min(slot + 10_000, horizon);
+            reserve.accrue_interest(slot).unwrap();
+            reserve.last_update.update_slot(slot);
+        }
+        assert_eq!(
+            reserve.accrue_interest(horizon + 10),
+            Err(LendingError::CumulativeBorrowRateOverflow.into())
+        );
+    }
+
     #[test]
     fn borrow_fee_calculation_min_host() {
         let fees = ReserveFees {
@@ -1859,19 +2988,19 @@ mod test {
 
         // only 2 tokens borrowed, get error
         let err = fees
-            .calculate_borrow_fees(Decimal::from(2u64), FeeCalculation::Exclusive)
+            .calculate_borrow_fees(Decimal::from(2u64), FeeCalculation::Exclusive, 0)
             .unwrap_err();
         assert_eq!(err, LendingError::BorrowTooSmall.into()); // minimum of 3 tokens
 
         // only 1 token borrowed, get error
         let err = fees
-            .calculate_borrow_fees(Decimal::one(), FeeCalculation::Exclusive)
+            .calculate_borrow_fees(Decimal::one(), FeeCalculation::Exclusive, 0)
             .unwrap_err();
         assert_eq!(err, LendingError::BorrowTooSmall.into());
 
         // 0 amount borrowed, 0 fee
         let (total_fee, host_fee) = fees
-            .calculate_borrow_fees(Decimal::zero(), FeeCalculation::Exclusive)
+            .calculate_borrow_fees(Decimal::zero(), FeeCalculation::Exclusive, 0)
             .unwrap();
         assert_eq!(total_fee, 0);
         assert_eq!(host_fee, 0);
@@ -1887,20 +3016,20 @@ mod test {
 
         // only 2 tokens borrowed, ok
         let (total_fee, host_fee) = fees
-            .calculate_borrow_fees(Decimal::from(2u64), FeeCalculation::Exclusive)
+            .calculate_borrow_fees(Decimal::from(2u64), FeeCalculation::Exclusive, 0)
             .unwrap();
         assert_eq!(total_fee, 1);
         assert_eq!(host_fee, 0);
 
         // only 1 token borrowed, get error
         let err = fees
-            .calculate_borrow_fees(Decimal::one(), FeeCalculation::Exclusive)
+            .calculate_borrow_fees(Decimal::one(), FeeCalculation::Exclusive, 0)
             .unwrap_err();
         assert_eq!(err, LendingError::BorrowTooSmall.into()); // minimum of 2 tokens
 
         // 0 amount borrowed, 0 fee
         let (total_fee, host_fee) = fees
-            .calculate_borrow_fees(Decimal::zero(), FeeCalculation::Exclusive)
+            .calculate_borrow_fees(Decimal::zero(), FeeCalculation::Exclusive, 0)
             .unwrap();
         assert_eq!(total_fee, 0);
         assert_eq!(host_fee, 0);
@@ -1915,7 +3044,7 @@ mod test {
         };
 
         let (total_fee, host_fee) = fees
-            .calculate_borrow_fees(Decimal::from(1000u64), FeeCalculation::Exclusive)
+            .calculate_borrow_fees(Decimal::from(1000u64), FeeCalculation::Exclusive, 0)
             .unwrap();
 
         assert_eq!(total_fee, 10); // 1% of 1000
@@ -1931,13 +3060,71 @@ mod test {
         };
 
         let (total_fee, host_fee) = fees
-            .calculate_borrow_fees(Decimal::from(1000u64), FeeCalculation::Exclusive)
+            .calculate_borrow_fees(Decimal::from(1000u64), FeeCalculation::Exclusive, 0)
             .unwrap();
 
         assert_eq!(total_fee, 10); // 1% of 1000
         assert_eq!(host_fee, 0); // 0 host fee
     }
 
+    #[test]
+    fn borrow_fee_calculation_rebate() {
+        let fees = ReserveFees {
+            borrow_fee_wad: 10_000_000_000_000_000, // 1%
+            flash_loan_fee_wad: 0,
+            host_fee_percentage: 20,
+        };
+
+        // 25% of the fee is waived
+        let (total_fee, host_fee) = fees
+            .calculate_borrow_fees(Decimal::from(1000u64), FeeCalculation::Exclusive, 2_500)
+            .unwrap();
+        assert_eq!(total_fee, 8); // 0.75% of 1000, rounded
+        assert_eq!(host_fee, 2); // 20% of 7.5, rounded
+
+        // the whole fee is waived, including the minimum fee
+        for fee_rebate_bps in [MAX_FEE_REBATE_BPS, u16::MAX] {
+            let (total_fee, host_fee) = fees
+                .calculate_borrow_fees(
+                    Decimal::from(1000u64),
+                    FeeCalculation::Inclusive,
+                    fee_rebate_bps,
+                )
+                .unwrap();
+            assert_eq!(total_fee, 0);
+            assert_eq!(host_fee, 0);
+        }
+    }
+
+    #[test]
+    fn remaining_borrow_under_utilization_cap() {
+        let mut reserve = Reserve {
+            liquidity: ReserveLiquidity {
+                available_amount: 600,
+                borrowed_amount_wads: Decimal::from(400u64),
+                ..ReserveLiquidity::default()
+            },
+            ..Reserve::default()
+        };
+        assert_eq!(
+            reserve.remaining_borrow_under_utilization_cap().unwrap(),
+            Decimal::from(u64::MAX)
+        );
+
+        reserve.config.borrow_utilization_cap = 90;
+        assert_eq!(
+            reserve.remaining_borrow_under_utilization_cap().unwrap(),
+            Decimal::from(500u64)
+        );
+
+        // already above the cap
+        reserve.config.borrow_utilization_cap = 30;
+        assert_eq!(
+            reserve.remaining_borrow_under_utilization_cap().unwrap(),
+            Decimal::zero()
+        );
+    }
+
     #[test]
     fn calculate_protocol_liquidation_fee() {
         let mut reserve = Reserve {
@@ -1964,6 +3151,141 @@ mod test {
         );
     }
 
+    #[test]
+    fn bound_price() {
+        let mut config = ReserveConfig::default();
+        // no bounds
+        assert_eq!(
+            config.bound_price(Decimal::from(10u64)),
+            Ok(Decimal::from(10u64))
+        );
+
+        // a stablecoin that prints $10, or depegs to $0.50
+        config.min_price_bps = 9_000;
+        config.max_price_bps = 11_000;
+        for price in [Decimal::from(10u64), Decimal::from_percent(50)] {
+            assert_eq!(
+                config.bound_price(price),
+                Err(LendingError::PriceOutOfBounds.into())
+            );
+        }
+        // bounds are inclusive
+        for price in [
+            Decimal::from_bps(9_000),
+            Decimal::one(),
+            Decimal::from_bps(11_000),
+        ] {
+            assert_eq!(config.bound_price(price), Ok(price));
+        }
+
+        config.price_bounds_mode = PriceBoundsMode::Clamp;
+        assert_eq!(
+            config.bound_price(Decimal::from(10u64)),
+            Ok(Decimal::from_bps(11_000))
+        );
+        assert_eq!(
+            config.bound_price(Decimal::from_percent(50)),
+            Ok(Decimal::from_bps(9_000))
+        );
+
+        // only a floor
+        config.max_price_bps = 0;
+        assert_eq!(
+            config.bound_price(Decimal::from(10u64)),
+            Ok(Decimal::from(10u64))
+        );
+    }
+
+    #[test]
+    fn unpack_invalid_price_bounds_mode() {
+        let mut packed = [0u8; Reserve::LEN];
+        Reserve::pack(Reserve::default(), &mut packed).unwrap();
+        *ReserveLayoutMut::new(&mut packed).config_price_bounds_mode = [2];
+        assert_eq!(
+            Reserve::unpack_from_slice(&packed),
+            Err(ProgramError::InvalidAccountData)
+        );
+    }
+
+    #[test]
+    fn unpack_invalid_reserve_type() {
+        let mut packed = [0u8; Reserve::LEN];
+        Reserve::pack(Reserve::default(), &mut packed).unwrap();
+        *ReserveLayoutMut::new(&mut packed).config_asset_type = [2];
+        assert_eq!(
+            Reserve::unpack_from_slice(&packed),
+            Err(ProgramError::InvalidAccountData)
+        );
+    }
+
+    #[test]
+    fn calculate_withdraw_fee() {
+        let mut reserve = Reserve::default();
+        assert_eq!(reserve.calculate_withdraw_fee(1_000_000).unwrap(), 0);
+
+        reserve.config.withdraw_fee_bps = 30;
+        assert_eq!(reserve.calculate_withdraw_fee(1_000_000).unwrap(), 3_000);
+        // rounds up
+        assert_eq!(reserve.calculate_withdraw_fee(1).unwrap(), 1);
+        assert_eq!(reserve.calculate_withdraw_fee(0).unwrap(), 0);
+    }
+
+    #[test]
+    fn calculate_collateral_liquidation() {
+        let mut reserve = Reserve {
+            config: ReserveConfig {
+                liquidation_bonus: 5,
+                max_liquidation_bonus: 5,
+                ..Default::default()
+            },
+            ..Reserve::default()
+        };
+        let obligation = Obligation {
+            borrowed_value: Decimal::from(100u64),
+            unhealthy_borrow_value: Decimal::from(50u64),
+            super_unhealthy_borrow_value: Decimal::from(50u64),
+            ..Obligation::default()
+        };
+        let liquidity = ObligationLiquidity {
+            borrowed_amount_wads: Decimal::from(100u64),
+            market_value: Decimal::from(100u64),
+            ..ObligationLiquidity::default()
+        };
+        let collateral = ObligationCollateral {
+            deposited_amount: 100_000,
+            market_value: Decimal::from(1000u64),
+            ..ObligationCollateral::default()
+        };
+
+        let redeemed = reserve
+            .calculate_liquidation(10, &obligation, &liquidity, &collateral)
+            .unwrap();
+        let unredeemed = reserve
+            .calculate_collateral_liquidation(10, &obligation, &liquidity, &collateral)
+            .unwrap();
+        assert_eq!(redeemed.withdraw_amount, 1050);
+        assert_eq!(unredeemed.withdraw_amount, 1055);
+        assert_eq!(unredeemed.repay_amount, redeemed.repay_amount);
+        assert_eq!(unredeemed.settle_amount, redeemed.settle_amount);
+        assert_eq!(
+            unredeemed.bonus_rate,
+            redeemed
+                .bonus_rate
+                .try_add(Decimal::from_bps(COLLATERAL_LIQUIDATION_EXTRA_BONUS_BPS))
+                .unwrap()
+        );
+
+        // the extra bonus can't push the total bonus past the max
+        reserve.config.liquidation_bonus = MAX_BONUS_PCT;
+        reserve.config.max_liquidation_bonus = MAX_BONUS_PCT;
+        assert_eq!(
+            reserve
+                .calculate_collateral_liquidation_bonus(&obligation)
+                .unwrap(),
+            Decimal::from_percent(MAX_BONUS_PCT)
+        );
+    }
+
     #[test]
     fn market_value() {
         let reserve = Reserve {
@@ -2002,6 +3324,29 @@ mod test {
         );
     }
 
+    #[test]
+    fn collateral_prices() {
+        let reserve = Reserve {
+            liquidity: ReserveLiquidity {
+                available_amount: 200,
+                market_price: Decimal::from(25u64),
+                smoothed_market_price: Decimal::from(50u64),
+                ..ReserveLiquidity::default()
+            },
+            collateral: ReserveCollateral {
+                mint_total_supply: 100,
+                ..ReserveCollateral::default()
+            },
+            ..Reserve::default()
+        };
+
+        // each cToken is redeemable for 2 tokens
+        assert_eq!(
+            reserve.collateral_prices().unwrap(),
+            (Decimal::from(50u64), Decimal::from(100u64))
+        );
+    }
+
     #[test]
     fn usd_to_liquidity_amount_lower_bound() {
         let reserve = Reserve {
@@ -2022,6 +3367,374 @@ mod test {
         );
     }
 
+    #[test]
+    fn first_depositor_inflation() {
+        // the only collateral holder redeemed down to a single token and inflated the liquidity
+        // behind it, e.g. with accrued interest
+        let mut reserve = Reserve {
+            liquidity: ReserveLiquidity {
+                available_amount: 1 + 1_000_000,
+                ..ReserveLiquidity::default()
+            },
+            collateral: ReserveCollateral {
+                mint_total_supply: 1,
+                ..ReserveCollateral::default()
+            },
+            ..Reserve::default()
+        };
+        // a deposit that would be rounded down to no collateral, handing the liquidity to the
+        // attacker, is rejected
+        assert_eq!(
+            reserve.deposit_liquidity(1_000_000),
+            Err(LendingError::InvalidAmount.into())
+        );
+
+        // with the initial collateral locked the supply can't be redeemed that far
+        let mut reserve = Reserve::default();
+        assert_eq!(
+            reserve.deposit_initial_liquidity(LOCKED_INITIAL_COLLATERAL - 1),
+            Err(LendingError::InvalidAmount.into())
+        );
+        let owner_collateral = reserve
+            .deposit_initial_liquidity(LOCKED_INITIAL_COLLATERAL + 1)
+            .unwrap();
+        assert_eq!(owner_collateral, 1);
+        assert_eq!(reserve.redeem_collateral(owner_collateral).unwrap(), 1);
+        assert_eq!(
+            reserve.collateral.mint_total_supply,
+            LOCKED_INITIAL_COLLATERAL
+        );
+
+        // and the same donation mostly goes to the locked collateral, the victim loses <0.1%
+        reserve.liquidity.available_amount += 1_000_000;
+        let victim_collateral = reserve.deposit_liquidity(1_000_000).unwrap();
+        let redeemed = reserve.redeem_collateral(victim_collateral).unwrap();
+        assert!(redeemed > 1_000_000 - 1_000_000 / 1000, "{}", redeemed);
+    }
+
+    #[test]
+    fn mint_decimals_0_to_18() {
+        for mint_decimals in 0..=MAX_MINT_DECIMALS {
+            let reserve = Reserve {
+                liquidity: ReserveLiquidity {
+                    mint_decimals,
+                    available_amount: u64::MAX,
+                    market_price: Decimal::from(300u64),
+                    smoothed_market_price: Decimal::from(300u64),
+                    ..ReserveLiquidity::default()
+                },
+                ..Reserve::default()
+            };
+
+            // a million whole tokens round trips exactly
+            let amount = Decimal::from(10u64.pow(mint_decimals as u32))
+                .try_mul(1_000_000u64)
+                .unwrap();
+            let value = Decimal::from(300_000_000u64);
+            assert_eq!(reserve.market_value(amount).unwrap(), value);
+            assert_eq!(reserve.market_value_upper_bound(amount).unwrap(), value);
+            assert_eq!(reserve.market_value_lower_bound(amount).unwrap(), value);
+            assert_eq!(
+                reserve.usd_to_liquidity_amount_lower_bound(value).unwrap(),
+                amount
+            );
+
+            // the largest possible amounts don't overflow
+            let max_value = reserve.market_value(Decimal::from(u64::MAX)).unwrap();
+            assert!(max_value > Decimal::zero());
+            reserve
+                .usd_to_liquidity_amount_lower_bound(Decimal::from(u64::MAX))
+                .unwrap();
+
+            let result = reserve
+                .calculate_borrow(u64::MAX, value, amount, 0)
+                .unwrap();
+            assert_eq!(result.borrow_amount, amount.min(Decimal::from(u64::MAX)));
+
+            let result = reserve
+                .calculate_borrow(u64::MAX - 1, max_value, amount, 0)
+                .unwrap();
+            assert_eq!(result.borrow_amount, Decimal::from(u64::MAX - 1));
+        }
+    }
+
+    #[test]
+    fn borrow_and_repay_amounts_for_value() {
+        let reserve = Reserve {
+            liquidity: ReserveLiquidity {
+                mint_decimals: 6,
+                market_price: Decimal::from(3u64),
+                smoothed_market_price: Decimal::from(2u64),
+                ..ReserveLiquidity::default()
+            },
+            ..Reserve::default()
+        };
+
+        let value = UsdValue(Decimal::from(100u64));
+        let borrow_amount = reserve.borrow_amount_for_value(value).unwrap();
+        let repay_amount = reserve.repay_amount_for_value(value).unwrap();
+        assert_eq!(borrow_amount, LiquidityAmount(33_333_333));
+        assert_eq!(repay_amount, LiquidityAmount(33_333_334));
+
+        // consistent with how the borrow check and the obligation value debt
+        assert!(
+            reserve
+                .market_value_upper_bound(borrow_amount.0.into())
+                .unwrap()
+                <= value.0
+        );
+        assert!(
+            reserve
+                .market_value_upper_bound(repay_amount.0.into())
+                .unwrap()
+                >= value.0
+        );
+
+        let exact = UsdValue(Decimal::from(3u64));
+        assert_eq!(
+            reserve.borrow_amount_for_value(exact).unwrap(),
+            reserve.repay_amount_for_value(exact).unwrap()
+        );
+    }
+
+    #[test]
+    fn typed_amount_conversions() {
+        // 2 cTokens per token
+        let reserve = Reserve {
+            liquidity: ReserveLiquidity {
+                mint_decimals: 9,
+                available_amount: 10 * LAMPORTS_PER_SOL,
+                market_price: Decimal::from(25u64),
+                smoothed_market_price: Decimal::from(50u64),
+                ..ReserveLiquidity::default()
+            },
+            collateral: ReserveCollateral {
+                mint_total_supply: 20 * LAMPORTS_PER_SOL,
+                ..ReserveCollateral::default()
+            },
+            ..Reserve::default()
+        };
+
+        let exchange_rate = reserve.collateral_exchange_rate().unwrap();
+        assert_eq!(
+            exchange_rate
+                .collateral_amount_to_liquidity(CollateralAmount(4 * LAMPORTS_PER_SOL))
+                .unwrap(),
+            LiquidityAmount(2 * LAMPORTS_PER_SOL)
+        );
+        assert_eq!(
+            exchange_rate
+                .liquidity_amount_to_collateral(LiquidityAmount(2 * LAMPORTS_PER_SOL))
+                .unwrap(),
+            CollateralAmount(4 * LAMPORTS_PER_SOL)
+        );
+
+        assert_eq!(
+            reserve
+                .liquidity_value(LiquidityAmount(2 * LAMPORTS_PER_SOL))
+                .unwrap(),
+            UsdValue(Decimal::from(50u64))
+        );
+        assert_eq!(
+            reserve
+                .collateral_value(CollateralAmount(4 * LAMPORTS_PER_SOL))
+                .unwrap(),
+            UsdValue(Decimal::from(50u64))
+        );
+        assert_eq!(
+            reserve
+                .value_to_liquidity_amount_lower_bound(UsdValue(Decimal::from(100u64)))
+                .unwrap(),
+            LiquidityAmount(2 * LAMPORTS_PER_SOL)
+        );
+    }
+
+    #[test]
+    fn cap_utilization() {
+        let reserve = Reserve {
+            liquidity: ReserveLiquidity {
+                available_amount: 45,
+                borrowed_amount_wads: Decimal::from(45u64),
+                ..ReserveLiquidity::default()
+            },
+            config: ReserveConfig {
+                deposit_limit: 100,
+                borrow_limit: 50,
+                deposit_soft_cap_pct: 90,
+                ..ReserveConfig::default()
+            },
+            ..Reserve::default()
+        };
+
+        assert_eq!(
+            reserve.cap_utilization().unwrap(),
+            CapUtilization {
+                deposit_limit_ratio: Some(Decimal::from_percent(90)),
+                deposit_soft_cap_ratio: Some(Decimal::one()),
+                borrow_limit_ratio: Some(Decimal::from_percent(90)),
+                borrow_soft_cap_ratio: None,
+            }
+        );
+
+        // borrows disabled
+        let reserve = Reserve {
+            config: ReserveConfig {
+                borrow_limit: 0,
+                borrow_soft_cap_pct: 90,
+                ..reserve.config
+            },
+            ..reserve
+        };
+        let cap_utilization = reserve.cap_utilization().unwrap();
+        assert_eq!(cap_utilization.borrow_limit_ratio, None);
+        assert_eq!(cap_utilization.borrow_soft_cap_ratio, None);
+    }
+
+    #[test]
+    fn wind_down_loan_to_value_ratio() {
+        let config_ltv = Rate::from_percent(50);
+        assert_eq!(
+            ReserveStatus::Active.loan_to_value_ratio(config_ltv, 1_000),
+            config_ltv
+        );
+        assert_eq!(
+            ReserveStatus::Closed.loan_to_value_ratio(config_ltv, 1_000),
+            Rate::zero()
+        );
+
+        // 50% decaying by 0.01% every slot hits 0 after 5000 slots
+        let status = ReserveStatus::WindDown {
+            start_slot: 100,
+            start_loan_to_value_ratio_wad: WAD / 2,
+            ltv_decay_per_slot_wad: WAD / 10_000,
+        };
+        assert_eq!(status.loan_to_value_ratio(config_ltv, 100), config_ltv);
+        assert_eq!(
+            status.loan_to_value_ratio(config_ltv, 1_100),
+            Rate::from_percent(40)
+        );
+        assert_eq!(status.loan_to_value_ratio(config_ltv, 5_100), Rate::zero());
+        assert_eq!(
+            status.loan_to_value_ratio(config_ltv, u64::MAX),
+            Rate::zero()
+        );
+
+        // slots before the start don't decay
+        assert_eq!(status.loan_to_value_ratio(config_ltv, 0), config_ltv);
+
+        // lowering the configured ratio below the decayed one takes effect immediately
+        assert_eq!(
+            status.loan_to_value_ratio(Rate::from_percent(30), 1_100),
+            Rate::from_percent(30)
+        );
+
+        // the reserve decays up to its last update
+        let reserve = Reserve {
+            last_update: LastUpdate {
+                slot: 1_100,
+                stale: false,
+            },
+            config: ReserveConfig {
+                loan_to_value_ratio: 50,
+                ..ReserveConfig::default()
+            },
+            status,
+            ..Reserve::default()
+        };
+        assert_eq!(reserve.loan_to_value_ratio(), Rate::from_percent(40));
+    }
+
+    #[test]
+    fn idle_liquidity_fee() {
+        let mut reserve = Reserve {
+            liquidity: ReserveLiquidity {
+                available_amount: 1_000_000_000,
+                ..ReserveLiquidity::default()
+            },
+            collateral: ReserveCollateral {
+                mint_total_supply: 1_000_000_000,
+                ..ReserveCollateral::default()
+            },
+            config: ReserveConfig {
+                idle_liquidity_fee_bps: 1_000,
+                ..ReserveConfig::default()
+            },
+            slots_per_year: SLOTS_PER_YEAR,
+            ..Reserve::default()
+        };
+
+        // a single slot charges the per-slot rate
+        let mut one_slot = reserve.clone();
+        one_slot.accrue_interest(1).unwrap();
+        let expected_fee = Decimal::from(1_000_000_000u64)
+            .try_mul(Rate::from_percent(10).try_div(SLOTS_PER_YEAR).unwrap())
+            .unwrap();
+        assert_eq!(
+            one_slot.liquidity.accumulated_protocol_fees_wads,
+            expected_fee
+        );
+        assert_eq!(one_slot.liquidity.available_amount, 1_000_000_000);
+
+        // after a year depositors hold 1e9 * e^-0.1
+        reserve.accrue_interest(SLOTS_PER_YEAR).unwrap();
+        let total_supply = reserve
+            .liquidity
+            .total_supply()
+            .unwrap()
+            .try_floor_u64()
+            .unwrap();
+        assert!((904_837_417..=904_837_418).contains(&total_supply));
+        assert_eq!(
+            reserve
+                .collateral_exchange_rate()
+                .unwrap()
+                .collateral_to_liquidity(1_000_000_000)
+                .unwrap(),
+            total_supply
+        );
+
+        // borrowed liquidity and liquidity owed to the protocol aren't charged
+        let mut reserve = Reserve {
+            liquidity: ReserveLiquidity {
+                available_amount: 100,
+                borrowed_amount_wads: Decimal::from(1_000_000_000u64),
+                accumulated_protocol_fees_wads: Decimal::from(100u64),
+                ..reserve.liquidity
+            },
+            ..reserve
+        };
+        reserve.accrue_interest(2 * SLOTS_PER_YEAR).unwrap();
+        assert_eq!(
+            reserve.liquidity.accumulated_protocol_fees_wads,
+            Decimal::from(100u64)
+        );
+    }
+
+    #[test]
+    fn lifetime_forgiven_amount() {
+        let mut liquidity = ReserveLiquidity {
+            borrowed_amount_wads: Decimal::from(100u64),
+            ..ReserveLiquidity::default()
+        };
+
+        liquidity.forgive_debt(Decimal::from(30u64)).unwrap();
+        liquidity.forgive_debt(Decimal::from(20u64)).unwrap();
+        assert_eq!(liquidity.borrowed_amount_wads, Decimal::from(50u64));
+        assert_eq!(
+            liquidity.lifetime_forgiven_amount_wads,
+            Decimal::from(50u64)
+        );
+
+        assert_eq!(
+            liquidity.forgive_debt(Decimal::from(51u64)),
+            Err(LendingError::MathOverflow.into())
+        );
+        assert_eq!(
+            liquidity.lifetime_forgiven_amount_wads,
+            Decimal::from(50u64)
+        );
+    }
+
     #[derive(Debug, Clone)]
     struct ReserveConfigTestCase {
         config: ReserveConfig,
@@ -2093,6 +3806,128 @@ mod test {
                     ..ReserveConfig::default()
                 },
                 result: Err(LendingError::InvalidConfig.into()),
+            }),
+            Just(ReserveConfigTestCase {
+                config: ReserveConfig {
+                    deposit_soft_cap_pct: 100,
+                    borrow_soft_cap_pct: 90,
+                    ..ReserveConfig::default()
+                },
+                result: Ok(())
+            }),
+            Just(ReserveConfigTestCase {
+                config: ReserveConfig {
+                    deposit_soft_cap_pct: 101,
+                    ..ReserveConfig::default()
+                },
+                result: Err(LendingError::InvalidConfig.into()),
+            }),
+            Just(ReserveConfigTestCase {
+                config: ReserveConfig {
+                    borrow_soft_cap_pct: 101,
+                    ..ReserveConfig::default()
+                },
+                result: Err(LendingError::InvalidConfig.into()),
+            }),
+            Just(ReserveConfigTestCase {
+                config: ReserveConfig {
+                    idle_liquidity_fee_bps: 10_001,
+                    ..ReserveConfig::default()
+                },
+                result: Err(LendingError::InvalidConfig.into()),
+            }),
+            Just(ReserveConfigTestCase {
+                config: ReserveConfig {
+                    liquidation_fee_receiver: Some(Pubkey::default()),
+                    ..ReserveConfig::default()
+                },
+                result: Err(LendingError::InvalidConfig.into()),
+            }),
+            Just(ReserveConfigTestCase {
+                config: ReserveConfig {
+                    optimal_utilization_rate: 80,
+                    max_utilization_rate: 95,
+                    borrow_utilization_cap: 90,
+                    ..ReserveConfig::default()
+                },
+                result: Ok(()),
+            }),
+            Just(ReserveConfigTestCase {
+                config: ReserveConfig {
+                    optimal_utilization_rate: 80,
+                    max_utilization_rate: 95,
+                    borrow_utilization_cap: 79,
+                    ..ReserveConfig::default()
+                },
+                result: Err(LendingError::InvalidConfig.into()),
+            }),
+            Just(ReserveConfigTestCase {
+                config: ReserveConfig {
+                    optimal_utilization_rate: 80,
+                    max_utilization_rate: 95,
+                    borrow_utilization_cap: 96,
+                    ..ReserveConfig::default()
+                },
+                result: Err(LendingError::InvalidConfig.into()),
+            }),
+            Just(ReserveConfigTestCase {
+                config: ReserveConfig {
+                    fixed_borrow_rate_bps: 500,
+                    fixed_borrow_max_duration_slots: 1_000,
+                    ..ReserveConfig::default()
+                },
+                result: Ok(()),
+            }),
+            Just(ReserveConfigTestCase {
+                config: ReserveConfig {
+                    fixed_borrow_rate_bps: 500,
+                    ..ReserveConfig::default()
+                },
+                result: Err(LendingError::InvalidConfig.into()),
+            }),
+            Just(ReserveConfigTestCase {
+                config: ReserveConfig {
+                    fixed_borrow_max_duration_slots: 1_000,
+                    ..ReserveConfig::default()
+                },
+                result: Err(LendingError::InvalidConfig.into()),
+            }),
+            Just(ReserveConfigTestCase {
+                config: ReserveConfig {
+                    withdraw_fee_bps: MAX_WITHDRAW_FEE_BPS,
+                    ..ReserveConfig::default()
+                },
+                result: Ok(()),
+            }),
+            Just(ReserveConfigTestCase {
+                config: ReserveConfig {
+                    withdraw_fee_bps: MAX_WITHDRAW_FEE_BPS + 1,
+                    ..ReserveConfig::default()
+                },
+                result: Err(LendingError::InvalidConfig.into()),
+            }),
+            Just(ReserveConfigTestCase {
+                config: ReserveConfig {
+                    min_price_bps: 9_000,
+                    max_price_bps: 9_000,
+                    ..ReserveConfig::default()
+                },
+                result: Ok(()),
+            }),
+            Just(ReserveConfigTestCase {
+                config: ReserveConfig {
+                    min_price_bps: 9_000,
+                    ..ReserveConfig::default()
+                },
+                result: Ok(()),
+            }),
+            Just(ReserveConfigTestCase {
+                config: ReserveConfig {
+                    min_price_bps: 11_000,
+                    max_price_bps: 9_000,
+                    ..ReserveConfig::default()
+                },
+                result: Err(LendingError::InvalidConfig.into()),
             })
         ]
     }
@@ -2378,13 +4213,15 @@ mod test {
                 deposits: vec![ObligationCollateral {
                     deposit_reserve: Pubkey::new_unique(),
                     deposited_amount: test_case.deposit_amount,
-                    market_value: test_case.deposit_market_value
+                    market_value: test_case.deposit_market_value,
+                    liquidation_priority: 0,
                 }],
                 borrows: vec![ObligationLiquidity {
                     borrow_reserve: Pubkey::new_unique(),
                     cumulative_borrow_rate_wads: Decimal::one(),
                     borrowed_amount_wads: Decimal::from(test_case.borrow_amount),
                     market_value: test_case.borrow_market_value,
+                    ..ObligationLiquidity::default()
                 }],
                 borrowed_value: test_case.borrow_market_value,
                 unhealthy_borrow_value: test_case.borrow_market_value,
@@ -2592,6 +4429,7 @@ mod test {
                 test_case.borrow_amount,
                 test_case.remaining_borrow_value,
                 test_case.remaining_reserve_capacity,
+                0,
             ), test_case.result);
         }
     }
@@ -0,0 +1,367 @@
+use super::*;
+use crate::{
+    error::LendingError,
+    math::{Decimal, Rate, TryDiv, TrySub},
+};
+use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
+use solana_program::{
+    clock::Slot,
+    msg,
+    program_error::ProgramError,
+    program_pack::{IsInitialized, Pack, Sealed},
+    pubkey::{Pubkey, PUBKEY_BYTES},
+};
+use std::convert::TryFrom;
+
+/// Number of epochs a reserve accrual history keeps
+pub const MAX_ACCRUAL_EPOCHS: usize = 30;
+
+/// Seed used to derive the accrual history address of a reserve
+pub const RESERVE_ACCRUAL_HISTORY_SEED: &[u8] = b"AccrualHistory";
+
+/// Opening interest accrual values of a reserve over its last [MAX_ACCRUAL_EPOCHS] epochs, so
+/// per epoch interest reconciles exactly. Lives in a PDA derived from
+/// [reserve, RESERVE_ACCRUAL_HISTORY_SEED] and is only updated once the lending market owner
+/// has created it.
+///
+/// Epoch `n` opens at slot `n * epoch_slots`. The first `RefreshReserve` passed the history after
+/// a boundary records the values the reserve had at the boundary slot, accruing interest from its
+/// last update up to that slot, so they don't depend on when the refresh lands. Epochs the reserve
+/// moved past without the history being passed can't be recovered and are missing.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ReserveAccrualHistory {
+    /// Version of the struct
+    pub version: u8,
+    /// Bump seed for the derived address
+    pub bump_seed: u8,
+    /// Reserve the history belongs to
+    pub reserve: Pubkey,
+    /// Length of an epoch in slots
+    pub epoch_slots: u64,
+    /// Recorded epochs, oldest first
+    pub epochs: Vec<EpochAccrual>,
+}
+
+/// Accrual values of a reserve at the opening slot of an epoch
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EpochAccrual {
+    /// Epoch number, the epoch opens at slot `epoch * epoch_slots`
+    pub epoch: u64,
+    /// Cumulative borrow rate of the reserve
+    pub cumulative_borrow_rate_wads: Decimal,
+    /// Protocol fees accumulated by the reserve and not yet redeemed
+    pub accumulated_protocol_fees_wads: Decimal,
+}
+
+impl ReserveAccrualHistory {
+    /// Create a new accrual history for a reserve
+    pub fn new(reserve: Pubkey, bump_seed: u8, epoch_slots: u64) -> Self {
+        Self {
+            version: PROGRAM_VERSION,
+            bump_seed,
+            reserve,
+            epoch_slots,
+            epochs: vec![],
+        }
+    }
+
+    /// Epoch a slot falls into
+    pub fn epoch_at(&self, slot: Slot) -> Result<u64, ProgramError> {
+        slot.checked_div(self.epoch_slots)
+            .ok_or_else(|| LendingError::MathOverflow.into())
+    }
+
+    /// Record the opening values of every epoch the reserve crossed into since the last recorded
+    /// one, up to the epoch of `current_slot`. `reserve` must not have accrued interest past its
+    /// last update yet.
+    pub fn record_epochs(
+        &mut self,
+        reserve: &Reserve,
+        current_slot: Slot,
+    ) -> Result<(), ProgramError> {
+        let current_epoch = self.epoch_at(current_slot)?;
+        let next_epoch = match self.epochs.last() {
+            Some(last) if last.epoch >= current_epoch => return Ok(()),
+            Some(last) => last.epoch + 1,
+            None => current_epoch,
+        };
+
+        // older epochs would be dropped again right away
+        let first_kept = current_epoch.saturating_sub(MAX_ACCRUAL_EPOCHS as u64 - 1);
+        for epoch in next_epoch.max(first_kept)..=current_epoch {
+            let opening_slot = epoch
+                .checked_mul(self.epoch_slots)
+                .ok_or(LendingError::MathOverflow)?;
+            // the reserve already changed after the boundary, its opening values are gone
+            if opening_slot < reserve.last_update.slot {
+                continue;
+            }
+
+            let mut opening = reserve.clone();
+            opening.accrue_interest(opening_slot)?;
+            if self.epochs.len() == MAX_ACCRUAL_EPOCHS {
+                self.epochs.remove(0);
+            }
+            self.epochs.push(EpochAccrual {
+                epoch,
+                cumulative_borrow_rate_wads: opening.liquidity.cumulative_borrow_rate_wads,
+                accumulated_protocol_fees_wads: opening.liquidity.accumulated_protocol_fees_wads,
+            });
+        }
+        Ok(())
+    }
+
+    /// Opening values of an epoch, if it was recorded
+    pub fn find_epoch(&self, epoch: u64) -> Option<&EpochAccrual> {
+        self.epochs.iter().find(|accrual| accrual.epoch == epoch)
+    }
+
+    /// Most recently recorded epoch
+    pub fn latest_epoch(&self) -> Option<&EpochAccrual> {
+        self.epochs.last()
+    }
+
+    /// Interest a variable rate borrow accrued over an epoch, as a fraction of its debt at the
+    /// opening of the epoch. None unless both the epoch and the one after it were recorded.
+    pub fn epoch_borrow_interest_rate(&self, epoch: u64) -> Result<Option<Rate>, ProgramError> {
+        let (opening, closing) = match (self.find_epoch(epoch), self.find_epoch(epoch + 1)) {
+            (Some(opening), Some(closing)) => (opening, closing),
+            _ => return Ok(None),
+        };
+        let growth = closing
+            .cumulative_borrow_rate_wads
+            .try_div(opening.cumulative_borrow_rate_wads)?
+            .try_sub(Decimal::one())?;
+        Ok(Some(Rate::try_from(growth)?))
+    }
+}
+
+impl Sealed for ReserveAccrualHistory {}
+impl IsInitialized for ReserveAccrualHistory {
+    fn is_initialized(&self) -> bool {
+        self.version != UNINITIALIZED_VERSION
+    }
+}
+
+const EPOCH_ACCRUAL_LEN: usize = 40; // 8 + 16 + 16
+const RESERVE_ACCRUAL_HISTORY_LEN: usize = 1307; // 1 + 1 + 32 + 8 + 1 + (40 * 30) + 64
+impl Pack for ReserveAccrualHistory {
+    const LEN: usize = RESERVE_ACCRUAL_HISTORY_LEN;
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let output = array_mut_ref![dst, 0, RESERVE_ACCRUAL_HISTORY_LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (version, bump_seed, reserve, epoch_slots, epochs_len, data_flat, _padding) = mut_array_refs![
+            output,
+            1,
+            1,
+            PUBKEY_BYTES,
+            8,
+            1,
+            EPOCH_ACCRUAL_LEN * MAX_ACCRUAL_EPOCHS,
+            64
+        ];
+
+        *version = self.version.to_le_bytes();
+        *bump_seed = self.bump_seed.to_le_bytes();
+        reserve.copy_from_slice(self.reserve.as_ref());
+        *epoch_slots = self.epoch_slots.to_le_bytes();
+        *epochs_len = u8::try_from(self.epochs.len()).unwrap().to_le_bytes();
+
+        let mut offset = 0;
+        for accrual in &self.epochs {
+            let accrual_flat = array_mut_ref![data_flat, offset, EPOCH_ACCRUAL_LEN];
+            #[allow(clippy::ptr_offset_with_cast)]
+            let (epoch, cumulative_borrow_rate_wads, accumulated_protocol_fees_wads) =
+                mut_array_refs![accrual_flat, 8, 16, 16];
+            *epoch = accrual.epoch.to_le_bytes();
+            pack_decimal(
+                accrual.cumulative_borrow_rate_wads,
+                cumulative_borrow_rate_wads,
+            );
+            pack_decimal(
+                accrual.accumulated_protocol_fees_wads,
+                accumulated_protocol_fees_wads,
+            );
+            offset += EPOCH_ACCRUAL_LEN;
+        }
+    }
+
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![src, 0, RESERVE_ACCRUAL_HISTORY_LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (version, bump_seed, reserve, epoch_slots, epochs_len, data_flat, _padding) = array_refs![
+            input,
+            1,
+            1,
+            PUBKEY_BYTES,
+            8,
+            1,
+            EPOCH_ACCRUAL_LEN * MAX_ACCRUAL_EPOCHS,
+            64
+        ];
+
+        let version = u8::from_le_bytes(*version);
+        if version > PROGRAM_VERSION {
+            msg!("Reserve accrual history version does not match lending program version");
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        let epochs_len = u8::from_le_bytes(*epochs_len) as usize;
+        if epochs_len > MAX_ACCRUAL_EPOCHS {
+            msg!("Reserve accrual history length is invalid");
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        let mut epochs = Vec::with_capacity(epochs_len);
+        let mut offset = 0;
+        for _ in 0..epochs_len {
+            let accrual_flat = array_ref![data_flat, offset, EPOCH_ACCRUAL_LEN];
+            #[allow(clippy::ptr_offset_with_cast)]
+            let (epoch, cumulative_borrow_rate_wads, accumulated_protocol_fees_wads) =
+                array_refs![accrual_flat, 8, 16, 16];
+            epochs.push(EpochAccrual {
+                epoch: u64::from_le_bytes(*epoch),
+                cumulative_borrow_rate_wads: unpack_decimal(cumulative_borrow_rate_wads),
+                accumulated_protocol_fees_wads: unpack_decimal(accumulated_protocol_fees_wads),
+            });
+            offset += EPOCH_ACCRUAL_LEN;
+        }
+
+        Ok(Self {
+            version,
+            bump_seed: u8::from_le_bytes(*bump_seed),
+            reserve: Pubkey::new_from_array(*reserve),
+            epoch_slots: u64::from_le_bytes(*epoch_slots),
+            epochs,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn pack_and_unpack_reserve_accrual_history() {
+        let mut rng = rand::thread_rng();
+        for _ in 0..100 {
+            let history = ReserveAccrualHistory {
+                version: PROGRAM_VERSION,
+                bump_seed: rng.gen(),
+                reserve: Pubkey::new_unique(),
+                epoch_slots: rng.gen(),
+                epochs: (0..rng.gen_range(0..=MAX_ACCRUAL_EPOCHS))
+                    .map(|_| EpochAccrual {
+                        epoch: rng.gen(),
+                        cumulative_borrow_rate_wads: Decimal::from_scaled_val(rng.gen()),
+                        accumulated_protocol_fees_wads: Decimal::from_scaled_val(rng.gen()),
+                    })
+                    .collect(),
+            };
+
+            let mut packed = [0u8; ReserveAccrualHistory::LEN];
+            ReserveAccrualHistory::pack(history.clone(), &mut packed).unwrap();
+            let unpacked = ReserveAccrualHistory::unpack(&packed).unwrap();
+            assert_eq!(history, unpacked);
+        }
+    }
+
+    fn borrowing_reserve(last_update_slot: Slot) -> Reserve {
+        let mut reserve = Reserve {
+            last_update: LastUpdate::new(last_update_slot),
+            slots_per_year: SLOTS_PER_YEAR,
+            config: ReserveConfig {
+                min_borrow_rate: 50,
+                optimal_borrow_rate: 50,
+                max_borrow_rate: 50,
+                super_max_borrow_rate: 50,
+                optimal_utilization_rate: 100,
+                max_utilization_rate: 100,
+                protocol_take_rate: 10,
+                ..ReserveConfig::default()
+            },
+            ..Reserve::default()
+        };
+        reserve.liquidity.cumulative_borrow_rate_wads = Decimal::one();
+        reserve.liquidity.available_amount = 1_000_000;
+        reserve.liquidity.borrowed_amount_wads = Decimal::from(1_000_000u64);
+        reserve
+    }
+
+    #[test]
+    fn record_opening_values_at_the_boundary() {
+        let mut history = ReserveAccrualHistory::new(Pubkey::new_unique(), 255, 1_000);
+        let reserve = borrowing_reserve(900);
+
+        // refreshed well after the boundary, the snapshot is still taken at slot 1000
+        history.record_epochs(&reserve, 1_700).unwrap();
+        let mut expected = reserve.clone();
+        expected.accrue_interest(1_000).unwrap();
+        assert_eq!(
+            history.epochs,
+            vec![EpochAccrual {
+                epoch: 1,
+                cumulative_borrow_rate_wads: expected.liquidity.cumulative_borrow_rate_wads,
+                accumulated_protocol_fees_wads: expected.liquidity.accumulated_protocol_fees_wads,
+            }]
+        );
+        assert!(expected.liquidity.cumulative_borrow_rate_wads > Decimal::one());
+
+        // nothing more to record until the next boundary
+        history.record_epochs(&reserve, 1_999).unwrap();
+        assert_eq!(history.epochs.len(), 1);
+
+        // epochs crossed without an interaction are filled in
+        let mut reserve = reserve;
+        reserve.accrue_interest(1_700).unwrap();
+        reserve.last_update.update_slot(1_700);
+        history.record_epochs(&reserve, 4_000).unwrap();
+        assert_eq!(
+            history
+                .epochs
+                .iter()
+                .map(|accrual| accrual.epoch)
+                .collect::<Vec<_>>(),
+            vec![1, 2, 3, 4]
+        );
+        assert!(history.epoch_borrow_interest_rate(1).unwrap().unwrap() > Rate::zero());
+        assert_eq!(history.epoch_borrow_interest_rate(4).unwrap(), None);
+        assert_eq!(history.latest_epoch().unwrap().epoch, 4);
+    }
+
+    #[test]
+    fn skip_boundaries_already_passed() {
+        let mut history = ReserveAccrualHistory::new(Pubkey::new_unique(), 255, 1_000);
+
+        // the reserve changed after the boundary without the history being passed
+        history
+            .record_epochs(&borrowing_reserve(1_200), 1_300)
+            .unwrap();
+        assert!(history.epochs.is_empty());
+
+        history
+            .record_epochs(&borrowing_reserve(1_300), 2_000)
+            .unwrap();
+        assert_eq!(history.epochs.len(), 1);
+        assert_eq!(history.find_epoch(2).unwrap().epoch, 2);
+        assert_eq!(history.find_epoch(1), None);
+    }
+
+    #[test]
+    fn keep_last_epochs() {
+        let mut history = ReserveAccrualHistory::new(Pubkey::new_unique(), 255, 10);
+        history.record_epochs(&borrowing_reserve(0), 0).unwrap();
+        history
+            .record_epochs(&borrowing_reserve(0), 10 * (MAX_ACCRUAL_EPOCHS as u64 + 5))
+            .unwrap();
+
+        assert_eq!(history.epochs.len(), MAX_ACCRUAL_EPOCHS);
+        assert_eq!(history.epochs[0].epoch, 6);
+        assert_eq!(
+            history.latest_epoch().unwrap().epoch,
+            MAX_ACCRUAL_EPOCHS as u64 + 5
+        );
+    }
+}
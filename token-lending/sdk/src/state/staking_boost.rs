@@ -0,0 +1,339 @@
+//! Liquidity mining boosts for cTokens locked in an external staking program.
+//!
+//! The lending program doesn't know about staking: users lock reserve collateral (cTokens) in a
+//! staking program, and a rewards program boosts their liquidity mining rewards by how long the
+//! cTokens stay locked. This module holds what both sides need to agree on:
+//!
+//! - the `get_locked_balance` call a staking program implements, which sets a packed
+//!   [LockedBalance] as return data, and [query_locked_balance] to make it through a CPI;
+//! - [StakingBoostConfig], the per-reserve boost a rewards program stores and applies with
+//!   [StakingBoostConfig::boosted_balance].
+
+use crate::{
+    error::LendingError,
+    math::{Decimal, TryAdd, TryDiv, TryMul},
+};
+use solana_program::{
+    account_info::AccountInfo,
+    clock::Slot,
+    entrypoint::ProgramResult,
+    instruction::{AccountMeta, Instruction},
+    msg,
+    program::{get_return_data, invoke},
+    program_error::ProgramError,
+    program_pack::{Pack, Sealed},
+    pubkey::{Pubkey, PUBKEY_BYTES},
+};
+
+/// Instruction data of the `get_locked_balance` call made to a staking program, the first 8 bytes
+/// of sha256("global:get_locked_balance") so Anchor programs can implement it as is
+pub const GET_LOCKED_BALANCE_DISCRIMINATOR: [u8; 8] = [189, 154, 100, 216, 108, 108, 188, 147];
+
+/// Maximum boost a reserve can configure, in basis points on top of the locked balance
+pub const MAX_STAKING_BOOST_BPS: u16 = 30_000;
+
+/// cTokens an owner has locked in a staking program, set as return data by `get_locked_balance`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LockedBalance {
+    /// Owner of the locked cTokens
+    pub owner: Pubkey,
+    /// Reserve collateral mint of the locked cTokens
+    pub collateral_mint: Pubkey,
+    /// Amount of cTokens locked
+    pub amount: u64,
+    /// Slot the cTokens can be unlocked at
+    pub unlock_slot: Slot,
+}
+
+/// Boost a rewards program applies to the locked cTokens of a reserve. Only balances reported by
+/// `staking_program` count.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StakingBoostConfig {
+    /// Reserve whose cTokens are boosted
+    pub reserve: Pubkey,
+    /// Staking program trusted to report locked balances, the default pubkey when the boost is
+    /// disabled
+    pub staking_program: Pubkey,
+    /// Boost in basis points for a balance locked for at least `full_boost_lock_slots`
+    pub max_boost_bps: u16,
+    /// Remaining lock duration earning the full boost, shorter locks earn a proportional part of it
+    pub full_boost_lock_slots: u64,
+}
+
+impl StakingBoostConfig {
+    /// Whether locked balances of the reserve are boosted
+    pub fn is_enabled(&self) -> bool {
+        self.staking_program != Pubkey::default() && self.max_boost_bps > 0
+    }
+
+    /// Check the config can be stored
+    pub fn validate(&self) -> ProgramResult {
+        if self.max_boost_bps > MAX_STAKING_BOOST_BPS {
+            msg!(
+                "Staking boost must be in range [0, {}] bps",
+                MAX_STAKING_BOOST_BPS
+            );
+            return Err(LendingError::InvalidConfig.into());
+        }
+        if self.max_boost_bps > 0 && self.full_boost_lock_slots == 0 {
+            msg!("Full boost lock duration must be set when the staking boost is");
+            return Err(LendingError::InvalidConfig.into());
+        }
+        Ok(())
+    }
+
+    /// Boost of `locked` at `slot` in basis points, scaled by how much of the full boost lock
+    /// duration is left
+    pub fn boost_bps(&self, locked: &LockedBalance, slot: Slot) -> Result<Decimal, ProgramError> {
+        if !self.is_enabled() {
+            return Ok(Decimal::zero());
+        }
+        let remaining_lock_slots = locked
+            .unlock_slot
+            .saturating_sub(slot)
+            .min(self.full_boost_lock_slots);
+
+        Decimal::from(self.max_boost_bps as u64)
+            .try_mul(remaining_lock_slots)?
+            .try_div(self.full_boost_lock_slots)
+    }
+
+    /// Balance `locked` earns rewards on at `slot`, the locked amount plus its boost, rounded
+    /// down
+    pub fn boosted_balance(&self, locked: &LockedBalance, slot: Slot) -> Result<u64, ProgramError> {
+        let boost = self.boost_bps(locked, slot)?.try_div(10_000)?;
+        Decimal::from(locked.amount)
+            .try_add(Decimal::from(locked.amount).try_mul(boost)?)?
+            .try_floor_u64()
+    }
+}
+
+/// Creates the `get_locked_balance` instruction a staking program answers with the [LockedBalance]
+/// of `owner` for `collateral_mint`.
+///
+/// Accounts passed to the staking program:
+///
+///   0. `[]` Owner of the locked cTokens.
+///   1. `[]` Reserve collateral mint.
+///   2. .. Accounts the staking program needs to look up the balance, e.g. its lock account.
+pub fn get_locked_balance(
+    staking_program: Pubkey,
+    owner: Pubkey,
+    collateral_mint: Pubkey,
+    lock_accounts: Vec<Pubkey>,
+) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new_readonly(owner, false),
+        AccountMeta::new_readonly(collateral_mint, false),
+    ];
+    accounts.extend(
+        lock_accounts
+            .into_iter()
+            .map(|pubkey| AccountMeta::new_readonly(pubkey, false)),
+    );
+    Instruction {
+        program_id: staking_program,
+        accounts,
+        data: GET_LOCKED_BALANCE_DISCRIMINATOR.to_vec(),
+    }
+}
+
+/// Ask `staking_program` for the cTokens `owner` has locked, checking the returned balance is the
+/// one asked for
+pub fn query_locked_balance<'a>(
+    staking_program: &AccountInfo<'a>,
+    owner: &AccountInfo<'a>,
+    collateral_mint: &AccountInfo<'a>,
+    lock_accounts: &[AccountInfo<'a>],
+) -> Result<LockedBalance, ProgramError> {
+    let instruction = get_locked_balance(
+        *staking_program.key,
+        *owner.key,
+        *collateral_mint.key,
+        lock_accounts.iter().map(|account| *account.key).collect(),
+    );
+    let mut account_infos = vec![owner.clone(), collateral_mint.clone()];
+    account_infos.extend_from_slice(lock_accounts);
+    account_infos.push(staking_program.clone());
+    invoke(&instruction, &account_infos)?;
+
+    let locked = match get_return_data() {
+        Some((program_id, data))
+            if program_id == *staking_program.key && data.len() == LockedBalance::LEN =>
+        {
+            LockedBalance::unpack_from_slice(&data)?
+        }
+        _ => {
+            msg!("Staking program did not return a locked balance");
+            return Err(LendingError::InvalidAccountInput.into());
+        }
+    };
+    if locked.owner != *owner.key || locked.collateral_mint != *collateral_mint.key {
+        msg!("Staking program returned the locked balance of another owner or mint");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+    Ok(locked)
+}
+
+account_layout! {
+    /// Byte layout of a packed locked balance
+    LockedBalanceLayout, LockedBalanceLayoutMut, LOCKED_BALANCE_LEN = 80 {
+        owner: PUBKEY_BYTES,
+        collateral_mint: PUBKEY_BYTES,
+        amount: 8,
+        unlock_slot: 8,
+    }
+}
+
+impl Sealed for LockedBalance {}
+
+impl Pack for LockedBalance {
+    const LEN: usize = LOCKED_BALANCE_LEN;
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let LockedBalanceLayoutMut {
+            owner,
+            collateral_mint,
+            amount,
+            unlock_slot,
+        } = LockedBalanceLayoutMut::new(dst);
+
+        owner.copy_from_slice(self.owner.as_ref());
+        collateral_mint.copy_from_slice(self.collateral_mint.as_ref());
+        *amount = self.amount.to_le_bytes();
+        *unlock_slot = self.unlock_slot.to_le_bytes();
+    }
+
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let LockedBalanceLayout {
+            owner,
+            collateral_mint,
+            amount,
+            unlock_slot,
+        } = LockedBalanceLayout::new(src);
+
+        Ok(Self {
+            owner: Pubkey::new_from_array(*owner),
+            collateral_mint: Pubkey::new_from_array(*collateral_mint),
+            amount: u64::from_le_bytes(*amount),
+            unlock_slot: u64::from_le_bytes(*unlock_slot),
+        })
+    }
+}
+
+account_layout! {
+    /// Byte layout of a packed staking boost config
+    StakingBoostConfigLayout, StakingBoostConfigLayoutMut, STAKING_BOOST_CONFIG_LEN = 106 {
+        reserve: PUBKEY_BYTES,
+        staking_program: PUBKEY_BYTES,
+        max_boost_bps: 2,
+        full_boost_lock_slots: 8,
+        _padding: 32,
+    }
+}
+
+impl Sealed for StakingBoostConfig {}
+
+impl Pack for StakingBoostConfig {
+    const LEN: usize = STAKING_BOOST_CONFIG_LEN;
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let StakingBoostConfigLayoutMut {
+            reserve,
+            staking_program,
+            max_boost_bps,
+            full_boost_lock_slots,
+            ..
+        } = StakingBoostConfigLayoutMut::new(dst);
+
+        reserve.copy_from_slice(self.reserve.as_ref());
+        staking_program.copy_from_slice(self.staking_program.as_ref());
+        *max_boost_bps = self.max_boost_bps.to_le_bytes();
+        *full_boost_lock_slots = self.full_boost_lock_slots.to_le_bytes();
+    }
+
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let StakingBoostConfigLayout {
+            reserve,
+            staking_program,
+            max_boost_bps,
+            full_boost_lock_slots,
+            ..
+        } = StakingBoostConfigLayout::new(src);
+
+        let config = Self {
+            reserve: Pubkey::new_from_array(*reserve),
+            staking_program: Pubkey::new_from_array(*staking_program),
+            max_boost_bps: u16::from_le_bytes(*max_boost_bps),
+            full_boost_lock_slots: u64::from_le_bytes(*full_boost_lock_slots),
+        };
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn pack_and_unpack() {
+        let locked = LockedBalance {
+            owner: Pubkey::new_unique(),
+            collateral_mint: Pubkey::new_unique(),
+            amount: 1_000,
+            unlock_slot: 500,
+        };
+        let mut packed = [0u8; LOCKED_BALANCE_LEN];
+        locked.pack_into_slice(&mut packed);
+        assert_eq!(LockedBalance::unpack_from_slice(&packed), Ok(locked));
+
+        let mut config = StakingBoostConfig {
+            reserve: Pubkey::new_unique(),
+            staking_program: Pubkey::new_unique(),
+            max_boost_bps: 5_000,
+            full_boost_lock_slots: 1_000,
+        };
+        let mut packed = [0u8; STAKING_BOOST_CONFIG_LEN];
+        config.pack_into_slice(&mut packed);
+        assert_eq!(StakingBoostConfig::unpack_from_slice(&packed), Ok(config));
+
+        config.max_boost_bps = MAX_STAKING_BOOST_BPS + 1;
+        config.pack_into_slice(&mut packed);
+        assert_eq!(
+            StakingBoostConfig::unpack_from_slice(&packed),
+            Err(LendingError::InvalidConfig.into())
+        );
+    }
+
+    #[test]
+    fn boosted_balance() {
+        let config = StakingBoostConfig {
+            reserve: Pubkey::new_unique(),
+            staking_program: Pubkey::new_unique(),
+            max_boost_bps: 5_000,
+            full_boost_lock_slots: 1_000,
+        };
+        let locked = LockedBalance {
+            owner: Pubkey::new_unique(),
+            collateral_mint: Pubkey::new_unique(),
+            amount: 1_000,
+            unlock_slot: 2_000,
+        };
+
+        // locked for longer than the full boost duration
+        assert_eq!(config.boosted_balance(&locked, 0).unwrap(), 1_500);
+        assert_eq!(config.boosted_balance(&locked, 1_000).unwrap(), 1_500);
+        // half of the full boost duration left
+        assert_eq!(config.boosted_balance(&locked, 1_500).unwrap(), 1_250);
+        // unlocked
+        assert_eq!(config.boosted_balance(&locked, 2_000).unwrap(), 1_000);
+        assert_eq!(config.boosted_balance(&locked, 3_000).unwrap(), 1_000);
+
+        let disabled = StakingBoostConfig {
+            staking_program: Pubkey::default(),
+            ..config
+        };
+        assert_eq!(disabled.boosted_balance(&locked, 0).unwrap(), 1_000);
+    }
+}
@@ -0,0 +1,61 @@
+//! Feature gated tracing of the off-chain utilities.
+//!
+//! With the `tracing` feature, account fetching, off-chain refreshes, the transaction builder, the
+//! health monitor and the config watcher open a span per call, tagged with the obligation, reserve
+//! or lending market it is about, and report failures and outcomes as events. Spans time the calls
+//! they cover, e.g. `tracing_subscriber`'s `FmtSpan::CLOSE` logs how long each one was busy.
+//! Without the feature none of it is compiled in.
+
+/// Emit a tracing event at `$level` with the `tracing` feature, nothing without it. Takes the
+/// same arguments as the `tracing` macro of that level.
+// only the off-chain modules use it, and those are left out of wasm builds
+#[allow(unused_macros)]
+macro_rules! trace_event {
+    ($level:ident, $($arg:tt)+) => {
+        #[cfg(feature = "tracing")]
+        ::tracing::$level!($($arg)+);
+    };
+}
+
+#[cfg(all(test, feature = "tracing"))]
+pub(crate) mod test {
+    use std::sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    };
+    use tracing::{
+        span::{Attributes, Id, Record},
+        Event, Level, Metadata, Subscriber,
+    };
+
+    /// Subscriber recording the names of the spans opened and the levels of the events emitted
+    #[derive(Clone, Default)]
+    pub(crate) struct RecordingSubscriber {
+        next_id: Arc<AtomicU64>,
+        pub(crate) spans: Arc<Mutex<Vec<&'static str>>>,
+        pub(crate) events: Arc<Mutex<Vec<Level>>>,
+    }
+
+    impl Subscriber for RecordingSubscriber {
+        fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            self.spans.lock().unwrap().push(span.metadata().name());
+            Id::from_u64(self.next_id.fetch_add(1, Ordering::Relaxed) + 1)
+        }
+
+        fn record(&self, _span: &Id, _values: &Record<'_>) {}
+
+        fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+        fn event(&self, event: &Event<'_>) {
+            self.events.lock().unwrap().push(*event.metadata().level());
+        }
+
+        fn enter(&self, _span: &Id) {}
+
+        fn exit(&self, _span: &Id) {}
+    }
+}
@@ -0,0 +1,30 @@
+//! Fixtures shared by the unit tests of the sdk modules.
+
+use crate::{
+    math::Decimal,
+    state::{Reserve, ReserveCollateral, ReserveLiquidity},
+};
+use solana_program::pubkey::Pubkey;
+
+/// Reserve of a new mint holding `available_amount` liquidity tokens priced at `market_price`,
+/// without borrows, where one collateral token redeems for one liquidity token. Tests override
+/// what they need with struct update syntax.
+pub(crate) fn reserve(available_amount: u64, market_price: u64) -> Reserve {
+    Reserve {
+        liquidity: ReserveLiquidity {
+            mint_pubkey: Pubkey::new_unique(),
+            supply_pubkey: Pubkey::new_unique(),
+            available_amount,
+            market_price: Decimal::from(market_price),
+            smoothed_market_price: Decimal::from(market_price),
+            cumulative_borrow_rate_wads: Decimal::one(),
+            ..ReserveLiquidity::default()
+        },
+        collateral: ReserveCollateral {
+            mint_pubkey: Pubkey::new_unique(),
+            mint_total_supply: available_amount,
+            supply_pubkey: Pubkey::new_unique(),
+        },
+        ..Reserve::default()
+    }
+}
@@ -0,0 +1,401 @@
+//! Builds v0 transactions out of lending instructions.
+//!
+//! With a [ComputeBudgetConfig], [TransactionBuilder::build] simulates the transaction first and
+//! sets the compute unit limit to what it consumed plus a margin, then prices the compute units
+//! from the fees recently paid to write lock the same accounts. Guessed limits either waste
+//! priority fees or fail outright once the market gets congested.
+//!
+//! With a [DurableNonce], the transaction is signed against the nonce instead of a recent
+//! blockhash, so that retries can't apply twice, see [crate::idempotency].
+
+use crate::idempotency::DurableNonce;
+use serde::Deserialize;
+use serde_json::json;
+use solana_client::{
+    rpc_client::RpcClient, rpc_config::RpcSimulateTransactionConfig, rpc_request::RpcRequest,
+    rpc_response::RpcSimulateTransactionResult,
+};
+use solana_program::{
+    address_lookup_table_account::AddressLookupTableAccount,
+    hash::Hash,
+    instruction::Instruction,
+    message::{v0, VersionedMessage},
+    pubkey::Pubkey,
+};
+use solana_sdk::{
+    compute_budget::ComputeBudgetInstruction, signature::Signature, signer::signers::Signers,
+    transaction::VersionedTransaction,
+};
+use std::{collections::BTreeSet, error::Error};
+
+/// Max compute units a transaction can request
+pub const MAX_COMPUTE_UNIT_LIMIT: u32 = 1_400_000;
+
+/// Max number of accounts getRecentPrioritizationFees accepts
+const MAX_PRIORITIZATION_FEE_ACCOUNTS: usize = 128;
+
+/// How to size the compute budget of a transaction
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ComputeBudgetConfig {
+    /// Compute units added on top of the simulated consumption, in basis points of it
+    pub compute_unit_margin_bps: u64,
+    /// Lower bound on the compute unit limit
+    pub min_compute_unit_limit: u32,
+    /// Percentile of the recent prioritization fees to pay, from 0 to 100
+    pub priority_fee_percentile: u8,
+    /// Upper bound on the compute unit price, in micro lamports
+    pub max_compute_unit_price: u64,
+}
+
+impl Default for ComputeBudgetConfig {
+    fn default() -> Self {
+        Self {
+            compute_unit_margin_bps: 1_000,
+            min_compute_unit_limit: 10_000,
+            priority_fee_percentile: 75,
+            max_compute_unit_price: 1_000_000,
+        }
+    }
+}
+
+impl ComputeBudgetConfig {
+    /// Compute unit limit for a transaction that consumed `units_consumed` when simulated
+    pub fn compute_unit_limit(&self, units_consumed: u64) -> u32 {
+        let margin = units_consumed.saturating_mul(self.compute_unit_margin_bps) / 10_000;
+        let limit = units_consumed
+            .saturating_add(margin)
+            .min(MAX_COMPUTE_UNIT_LIMIT as u64) as u32;
+        limit.max(self.min_compute_unit_limit)
+    }
+
+    /// Compute unit price out of recent prioritization fees, in micro lamports
+    pub fn compute_unit_price(&self, recent_fees: &[u64]) -> u64 {
+        if recent_fees.is_empty() {
+            return 0;
+        }
+        let mut fees = recent_fees.to_vec();
+        fees.sort_unstable();
+        let percentile = self.priority_fee_percentile.min(100) as usize;
+        let index = ((fees.len() - 1) * percentile + 50) / 100;
+        fees[index].min(self.max_compute_unit_price)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RecentPrioritizationFee {
+    prioritization_fee: u64,
+}
+
+/// Builder of v0 transactions
+#[derive(Clone, Debug)]
+pub struct TransactionBuilder {
+    payer: Pubkey,
+    instructions: Vec<Instruction>,
+    lookup_tables: Vec<AddressLookupTableAccount>,
+    compute_budget: Option<ComputeBudgetConfig>,
+    durable_nonce: Option<DurableNonce>,
+}
+
+impl TransactionBuilder {
+    /// Create a builder for a transaction paid for by `payer`
+    pub fn new(payer: Pubkey) -> Self {
+        Self {
+            payer,
+            instructions: vec![],
+            lookup_tables: vec![],
+            compute_budget: None,
+            durable_nonce: None,
+        }
+    }
+
+    /// Append an instruction
+    pub fn instruction(mut self, instruction: Instruction) -> Self {
+        self.instructions.push(instruction);
+        self
+    }
+
+    /// Append instructions
+    pub fn instructions(mut self, instructions: impl IntoIterator<Item = Instruction>) -> Self {
+        self.instructions.extend(instructions);
+        self
+    }
+
+    /// Compress account keys through address lookup tables
+    pub fn lookup_tables(mut self, lookup_tables: Vec<AddressLookupTableAccount>) -> Self {
+        self.lookup_tables = lookup_tables;
+        self
+    }
+
+    /// Size the compute budget by simulating the transaction when building it
+    pub fn compute_budget(mut self, config: ComputeBudgetConfig) -> Self {
+        self.compute_budget = Some(config);
+        self
+    }
+
+    /// Sign the transaction against `nonce` rather than a recent blockhash. The nonce authority
+    /// has to be one of the signers.
+    pub fn durable_nonce(mut self, nonce: DurableNonce) -> Self {
+        self.durable_nonce = Some(nonce);
+        self
+    }
+
+    /// Index the next appended instruction will have in the built transaction, counting the
+    /// nonce advance and compute budget instructions prepended to it. Instructions referring to
+    /// others by index, like `FlashRepayReserveLiquidity`, need it.
+    pub fn instruction_index(&self) -> usize {
+        let nonce_instructions = if self.durable_nonce.is_some() { 1 } else { 0 };
+        let compute_budget_instructions = if self.compute_budget.is_some() { 2 } else { 0 };
+        nonce_instructions + compute_budget_instructions + self.instructions.len()
+    }
+
+    fn compile(
+        &self,
+        compute_budget_instructions: Vec<Instruction>,
+        recent_blockhash: Hash,
+    ) -> Result<VersionedMessage, Box<dyn Error>> {
+        // the nonce advance has to come first for the runtime to treat it as a nonce transaction
+        let instructions = self
+            .durable_nonce
+            .iter()
+            .map(DurableNonce::advance_instruction)
+            .chain(compute_budget_instructions)
+            .chain(self.instructions.iter().cloned())
+            .collect::<Vec<_>>();
+        Ok(VersionedMessage::V0(v0::Message::try_compile(
+            &self.payer,
+            &instructions,
+            &self.lookup_tables,
+            recent_blockhash,
+        )?))
+    }
+
+    fn compute_budget_instructions(unit_limit: u32, unit_price: u64) -> Vec<Instruction> {
+        vec![
+            ComputeBudgetInstruction::set_compute_unit_limit(unit_limit),
+            ComputeBudgetInstruction::set_compute_unit_price(unit_price),
+        ]
+    }
+
+    /// Fetch the fees recently paid to write lock the accounts the instructions write to
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, err)
+    )]
+    fn recent_prioritization_fees(&self, client: &RpcClient) -> Result<Vec<u64>, Box<dyn Error>> {
+        let writable_accounts = self
+            .instructions
+            .iter()
+            .flat_map(|instruction| instruction.accounts.iter())
+            .filter(|account| account.is_writable)
+            .map(|account| account.pubkey.to_string())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .take(MAX_PRIORITIZATION_FEE_ACCOUNTS)
+            .collect::<Vec<_>>();
+        let fees: Vec<RecentPrioritizationFee> = client.send(
+            RpcRequest::Custom {
+                method: "getRecentPrioritizationFees",
+            },
+            json!([writable_accounts]),
+        )?;
+        Ok(fees.into_iter().map(|fee| fee.prioritization_fee).collect())
+    }
+
+    /// Simulate the transaction without signing it, with the max compute unit limit if a
+    /// compute budget config was set
+    fn simulate_with(
+        &self,
+        client: &RpcClient,
+        recent_blockhash: Hash,
+    ) -> Result<RpcSimulateTransactionResult, Box<dyn Error>> {
+        // the compute budget instructions are simulated too since they cost compute units
+        let compute_budget_instructions = match self.compute_budget {
+            Some(_) => Self::compute_budget_instructions(MAX_COMPUTE_UNIT_LIMIT, 0),
+            None => vec![],
+        };
+        let message = self.compile(compute_budget_instructions, recent_blockhash)?;
+        let transaction = VersionedTransaction {
+            signatures: vec![
+                Signature::default();
+                message.header().num_required_signatures as usize
+            ],
+            message,
+        };
+        Ok(client
+            .simulate_transaction_with_config(
+                &transaction,
+                RpcSimulateTransactionConfig {
+                    sig_verify: false,
+                    replace_recent_blockhash: true,
+                    ..RpcSimulateTransactionConfig::default()
+                },
+            )?
+            .value)
+    }
+
+    /// Dry run the transaction, without signatures. A failed simulation is returned rather than
+    /// turned into an error, so its logs can be inspected.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, err)
+    )]
+    pub fn simulate(
+        &self,
+        client: &RpcClient,
+    ) -> Result<RpcSimulateTransactionResult, Box<dyn Error>> {
+        self.simulate_with(client, client.get_latest_blockhash()?)
+    }
+
+    /// Simulate the transaction and return the compute budget instructions it needs
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, err)
+    )]
+    fn size_compute_budget(
+        &self,
+        client: &RpcClient,
+        config: &ComputeBudgetConfig,
+        recent_blockhash: Hash,
+    ) -> Result<Vec<Instruction>, Box<dyn Error>> {
+        let simulation = self.simulate_with(client, recent_blockhash)?;
+        if let Some(err) = simulation.err {
+            return Err(format!(
+                "Transaction simulation failed: {}, logs: {:#?}",
+                err,
+                simulation.logs.unwrap_or_default()
+            )
+            .into());
+        }
+        let units_consumed = simulation
+            .units_consumed
+            .ok_or("Simulation did not report the compute units consumed")?;
+
+        let unit_limit = config.compute_unit_limit(units_consumed);
+        let unit_price = config.compute_unit_price(&self.recent_prioritization_fees(client)?);
+        trace_event!(
+            debug,
+            units_consumed,
+            unit_limit,
+            unit_price,
+            "sized compute budget"
+        );
+        Ok(Self::compute_budget_instructions(unit_limit, unit_price))
+    }
+
+    /// Build and sign the transaction. Simulates it against `client` first if a compute budget
+    /// config was set.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            skip_all,
+            fields(payer = %self.payer, instructions = self.instructions.len()),
+            err
+        )
+    )]
+    pub fn build<T: Signers>(
+        &self,
+        client: &RpcClient,
+        signers: &T,
+    ) -> Result<VersionedTransaction, Box<dyn Error>> {
+        let recent_blockhash = match &self.durable_nonce {
+            Some(nonce) => nonce.blockhash,
+            None => client.get_latest_blockhash()?,
+        };
+        let compute_budget_instructions = match &self.compute_budget {
+            Some(config) => self.size_compute_budget(client, config, recent_blockhash)?,
+            None => vec![],
+        };
+        let message = self.compile(compute_budget_instructions, recent_blockhash)?;
+        Ok(VersionedTransaction::try_new(message, signers)?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn compute_unit_limit() {
+        let config = ComputeBudgetConfig::default();
+        // 10% margin
+        assert_eq!(config.compute_unit_limit(200_000), 220_000);
+        assert_eq!(config.compute_unit_limit(1_000), 10_000);
+        assert_eq!(config.compute_unit_limit(1_300_000), MAX_COMPUTE_UNIT_LIMIT);
+        assert_eq!(config.compute_unit_limit(u64::MAX), MAX_COMPUTE_UNIT_LIMIT);
+    }
+
+    #[test]
+    fn compute_unit_price() {
+        let config = ComputeBudgetConfig {
+            priority_fee_percentile: 50,
+            max_compute_unit_price: 1_000,
+            ..ComputeBudgetConfig::default()
+        };
+        assert_eq!(config.compute_unit_price(&[]), 0);
+        assert_eq!(config.compute_unit_price(&[30, 10, 20]), 20);
+        assert_eq!(config.compute_unit_price(&[5_000, 0, 10_000]), 1_000);
+
+        let config = ComputeBudgetConfig {
+            priority_fee_percentile: 100,
+            ..config
+        };
+        assert_eq!(config.compute_unit_price(&[30, 10, 20]), 30);
+    }
+
+    #[test]
+    fn compile_with_compute_budget() {
+        let payer = Pubkey::new_unique();
+        let builder = TransactionBuilder::new(payer).instruction(Instruction::new_with_bytes(
+            Pubkey::new_unique(),
+            &[],
+            vec![],
+        ));
+        let message = builder
+            .compile(
+                TransactionBuilder::compute_budget_instructions(220_000, 5),
+                Hash::default(),
+            )
+            .unwrap();
+        assert_eq!(message.static_account_keys()[0], payer);
+        assert_eq!(message.instructions().len(), 3);
+        assert_eq!(
+            message.instructions()[0].data,
+            ComputeBudgetInstruction::set_compute_unit_limit(220_000).data
+        );
+    }
+
+    #[test]
+    fn compile_with_durable_nonce() {
+        let nonce = DurableNonce {
+            nonce_account: Pubkey::new_unique(),
+            authority: Pubkey::new_unique(),
+            blockhash: Hash::new_unique(),
+        };
+        let builder = TransactionBuilder::new(Pubkey::new_unique())
+            .durable_nonce(nonce)
+            .compute_budget(ComputeBudgetConfig::default())
+            .instruction(Instruction::new_with_bytes(
+                Pubkey::new_unique(),
+                &[],
+                vec![],
+            ));
+        assert_eq!(builder.instruction_index(), 4);
+
+        let message = builder
+            .compile(
+                TransactionBuilder::compute_budget_instructions(220_000, 5),
+                nonce.blockhash,
+            )
+            .unwrap();
+        assert_eq!(message.instructions().len(), 4);
+        assert_eq!(
+            message.instructions()[0].data,
+            nonce.advance_instruction().data
+        );
+        assert_eq!(*message.recent_blockhash(), nonce.blockhash);
+        // the nonce authority signs
+        assert!(message.is_signer(1));
+        assert_eq!(message.static_account_keys()[1], nonce.authority);
+    }
+}
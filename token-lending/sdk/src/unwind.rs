@@ -0,0 +1,678 @@
+//! Flash loan assisted unwinding of looped positions.
+//!
+//! A looped position (deposit SOL, borrow USDC, swap to SOL, deposit again...) can't be unwound
+//! by withdrawing first, since the collateral is what keeps the borrow healthy, nor by repaying
+//! first, since the debt tokens were swapped away. [plan_unwind] does both in one transaction:
+//!
+//! 1. flash borrow the debt liquidity to repay
+//! 2. repay the obligation's debt with it
+//! 3. withdraw and redeem just enough collateral to buy the flash loan back
+//! 4. swap the collateral liquidity into debt liquidity through a [SwapVenue]
+//! 5. flash repay the loan and its fee
+//!
+//! The repay amount is sized to bring the obligation to a target health factor, see
+//! [health_factor], accounting for the flash loan fee, the withdraw fee and the worst case
+//! slippage of the swap. Everything happens in a single transaction so either all of it lands or
+//! none of it does; [UnwindPlan::simulate] dry runs it first.
+
+use crate::{
+    error::LendingError,
+    health_monitor::health_factor,
+    instruction::{
+        append_deleverage_accounts, create_associated_token_account_idempotent,
+        flash_borrow_reserve_liquidity, flash_repay_reserve_liquidity,
+        get_associated_token_address, refresh_obligation, refresh_reserve_with_lending_market,
+        repay_obligation_liquidity,
+        withdraw_obligation_collateral_and_redeem_reserve_collateral_with_fee_receiver,
+        LendingInstruction,
+    },
+    math::{CollateralAmount, Decimal, LiquidityAmount, Rate, TryAdd, TryDiv, TryMul, TrySub},
+    offchain_utils::{offchain_refresh_obligation, ObligationWithReserves},
+    state::{Obligation, Reserve},
+    transaction_builder::TransactionBuilder,
+};
+use solana_client::{rpc_client::RpcClient, rpc_response::RpcSimulateTransactionResult};
+use solana_program::{
+    address_lookup_table_account::AddressLookupTableAccount, instruction::Instruction,
+    pubkey::Pubkey,
+};
+use std::{convert::TryFrom, error::Error, fmt};
+
+/// A swap the unwind needs, handed to a [SwapVenue]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SwapRequest {
+    /// Mint sold, the collateral reserve liquidity mint
+    pub input_mint: Pubkey,
+    /// Mint bought, the debt reserve liquidity mint
+    pub output_mint: Pubkey,
+    /// Exact amount of input tokens to sell
+    pub input_amount: u64,
+    /// Output tokens the swap has to return at least, or fail
+    pub min_output_amount: u64,
+    /// Wallet owning both token accounts, signs the transaction
+    pub owner: Pubkey,
+    /// Token account to sell from
+    pub source_token_account: Pubkey,
+    /// Token account to receive the output in
+    pub destination_token_account: Pubkey,
+}
+
+/// Instructions carrying out a [SwapRequest]
+#[derive(Clone, Debug, Default)]
+pub struct SwapInstructions {
+    /// Swap instructions, which must fail if less than the min output amount comes out
+    pub instructions: Vec<Instruction>,
+    /// Lookup tables to compress the swap accounts with
+    pub lookup_tables: Vec<AddressLookupTableAccount>,
+}
+
+/// Where the collateral gets swapped back into the debt token, e.g. an aggregator client. Any
+/// `Fn(&SwapRequest) -> Result<SwapInstructions, Box<dyn Error>>` closure is one.
+pub trait SwapVenue {
+    /// Build the instructions swapping as requested
+    fn swap(&self, request: &SwapRequest) -> Result<SwapInstructions, Box<dyn Error>>;
+}
+
+impl<F> SwapVenue for F
+where
+    F: Fn(&SwapRequest) -> Result<SwapInstructions, Box<dyn Error>>,
+{
+    fn swap(&self, request: &SwapRequest) -> Result<SwapInstructions, Box<dyn Error>> {
+        self(request)
+    }
+}
+
+/// What to unwind and how far
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UnwindParams {
+    /// Reserve of the collateral to withdraw and sell
+    pub collateral_reserve: Pubkey,
+    /// Reserve of the debt to repay
+    pub debt_reserve: Pubkey,
+    /// Health factor to bring the obligation to. The debt repaid is capped at the whole debt.
+    pub target_health_factor: Decimal,
+    /// Worst slippage of the swap against oracle prices to size for, in basis points
+    pub max_slippage_bps: u64,
+    /// Repay and withdraw through the obligation's deleverage credit, so the withdrawal doesn't
+    /// count against the lending market outflow limit. The credit account has to exist.
+    pub use_deleverage_credit: bool,
+    /// Flash loan fee receiver set in the flash loan config of the debt reserve, None if it has
+    /// none and flash loan fees go to the reserve fee receiver
+    pub flash_loan_fee_receiver: Option<Pubkey>,
+}
+
+/// Amounts of an unwind
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UnwindSizing {
+    /// Debt liquidity flash borrowed and repaid
+    pub repay_amount: LiquidityAmount,
+    /// Flash loan fee, host fee included
+    pub flash_loan_fee: u64,
+    /// Collateral withdrawn from the obligation
+    pub withdraw_collateral_amount: CollateralAmount,
+    /// Collateral liquidity sold, after the withdraw fee
+    pub swap_input_amount: LiquidityAmount,
+    /// Debt liquidity the swap has to return, the flash loan and its fee
+    pub min_swap_output_amount: LiquidityAmount,
+    /// Health factor of the obligation before the unwind
+    pub health_factor_before: Option<Decimal>,
+    /// Health factor after the unwind at current prices, None if nothing is left borrowed
+    pub health_factor_after: Option<Decimal>,
+}
+
+/// Why an unwind can't be planned
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum UnwindError {
+    /// A reserve of the params isn't among the fetched reserves of the obligation
+    ReserveNotFound(Pubkey),
+    /// The obligation doesn't deposit into the collateral reserve
+    NoDeposit(Pubkey),
+    /// The obligation doesn't borrow from the debt reserve
+    NoBorrow(Pubkey),
+    /// The obligation is at the target health factor already
+    AlreadyHealthy,
+    /// Selling collateral costs more health than repaying debt gains, at this target, slippage
+    /// and fees
+    TargetUnreachable,
+    /// The unwind needs more collateral than the obligation deposits
+    InsufficientCollateral,
+    /// The debt reserve doesn't have the liquidity to flash borrow
+    InsufficientLiquidity,
+}
+
+impl fmt::Display for UnwindError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            UnwindError::ReserveNotFound(reserve) => write!(f, "reserve {} not found", reserve),
+            UnwindError::NoDeposit(reserve) => {
+                write!(f, "obligation has no deposit in reserve {}", reserve)
+            }
+            UnwindError::NoBorrow(reserve) => {
+                write!(f, "obligation has no borrow from reserve {}", reserve)
+            }
+            UnwindError::AlreadyHealthy => {
+                write!(f, "obligation is already at the target health factor")
+            }
+            UnwindError::TargetUnreachable => write!(
+                f,
+                "target health factor can't be reached by selling this collateral"
+            ),
+            UnwindError::InsufficientCollateral => {
+                write!(f, "obligation doesn't deposit enough collateral")
+            }
+            UnwindError::InsufficientLiquidity => {
+                write!(
+                    f,
+                    "debt reserve doesn't have enough liquidity to flash borrow"
+                )
+            }
+        }
+    }
+}
+
+impl Error for UnwindError {}
+
+/// Size an unwind of `position`, returning the obligation as it would be after it
+fn size(
+    position: &ObligationWithReserves,
+    params: &UnwindParams,
+) -> Result<(UnwindSizing, Obligation), Box<dyn Error>> {
+    let obligation = &position.obligation;
+    let reserve = |pubkey: &Pubkey| -> Result<&Reserve, UnwindError> {
+        position
+            .reserves
+            .get(pubkey)
+            .ok_or(UnwindError::ReserveNotFound(*pubkey))
+    };
+    let collateral_reserve = reserve(&params.collateral_reserve)?;
+    let debt_reserve = reserve(&params.debt_reserve)?;
+    let collateral_index = obligation
+        .deposits
+        .iter()
+        .position(|collateral| collateral.deposit_reserve == params.collateral_reserve)
+        .ok_or(UnwindError::NoDeposit(params.collateral_reserve))?;
+    let liquidity_index = obligation
+        .borrows
+        .iter()
+        .position(|liquidity| liquidity.borrow_reserve == params.debt_reserve)
+        .ok_or(UnwindError::NoBorrow(params.debt_reserve))?;
+
+    let target = params.target_health_factor;
+    let target_unhealthy_borrow_value = obligation.borrowed_value.try_mul(target)?;
+    if obligation.unhealthy_borrow_value >= target_unhealthy_borrow_value {
+        return Err(UnwindError::AlreadyHealthy.into());
+    }
+    let shortfall = target_unhealthy_borrow_value.try_sub(obligation.unhealthy_borrow_value)?;
+
+    // repaying one unit of debt value gains `target * borrow weight` of the shortfall, and the
+    // collateral sold to pay for it loses `liquidation threshold` per unit of its value, which
+    // is the debt value grossed up for fees and slippage
+    let swap_haircut = Decimal::one().try_sub(Decimal::from_bps(params.max_slippage_bps))?;
+    let withdraw_haircut =
+        Decimal::one().try_sub(collateral_reserve.config.withdraw_fee_bps().into())?;
+    let flash_loan_fee_rate =
+        Decimal::from_scaled_val(debt_reserve.config.fees.flash_loan_fee_wad.into());
+    let collateral_cost = Decimal::one()
+        .try_add(flash_loan_fee_rate)?
+        .try_mul(Rate::from(
+            collateral_reserve.config.liquidation_threshold(),
+        ))?
+        .try_div(swap_haircut.try_mul(withdraw_haircut)?)?;
+    let repay_gain = target.try_mul(debt_reserve.borrow_weight())?;
+    if repay_gain <= collateral_cost {
+        return Err(UnwindError::TargetUnreachable.into());
+    }
+
+    let debt_unit_value = debt_reserve.market_value(Decimal::one())?;
+    let collateral_unit_value = collateral_reserve.market_value(Decimal::one())?;
+    let borrowed_amount = obligation.borrows[liquidity_index].borrowed_amount_wads;
+    let repay_amount = shortfall
+        .try_div(repay_gain.try_sub(collateral_cost)?)?
+        .try_div(debt_unit_value)?
+        .min(borrowed_amount)
+        .try_ceil_u64()?;
+    if repay_amount > debt_reserve.liquidity.available_amount {
+        return Err(UnwindError::InsufficientLiquidity.into());
+    }
+
+    let (origination_fee, host_fee) = debt_reserve
+        .config
+        .fees
+        .calculate_flash_loan_fees(repay_amount.into())?;
+    let flash_loan_fee = origination_fee
+        .checked_add(host_fee)
+        .ok_or(LendingError::MathOverflow)?;
+    let min_swap_output_amount = repay_amount
+        .checked_add(flash_loan_fee)
+        .ok_or(LendingError::MathOverflow)?;
+
+    let withdraw_liquidity_amount = Decimal::from(min_swap_output_amount)
+        .try_mul(debt_unit_value)?
+        .try_div(collateral_unit_value.try_mul(swap_haircut)?)?
+        .try_div(withdraw_haircut)?;
+    let exchange_rate = collateral_reserve.collateral_exchange_rate()?;
+    let withdraw_collateral_amount = exchange_rate
+        .decimal_liquidity_to_collateral(withdraw_liquidity_amount)?
+        .try_ceil_u64()?;
+    if withdraw_collateral_amount > obligation.deposits[collateral_index].deposited_amount {
+        return Err(UnwindError::InsufficientCollateral.into());
+    }
+    let redeemed_amount = exchange_rate.collateral_to_liquidity(withdraw_collateral_amount)?;
+    let swap_input_amount = redeemed_amount
+        .checked_sub(collateral_reserve.calculate_withdraw_fee(redeemed_amount)?)
+        .ok_or(LendingError::MathOverflow)?;
+
+    let mut after = obligation.clone();
+    after.repay(
+        Decimal::from(repay_amount).min(borrowed_amount),
+        liquidity_index,
+    )?;
+    after.withdraw(withdraw_collateral_amount, collateral_index)?;
+    offchain_refresh_obligation(&mut after, &position.reserves)?;
+
+    let sizing = UnwindSizing {
+        repay_amount: LiquidityAmount(repay_amount),
+        flash_loan_fee,
+        withdraw_collateral_amount: CollateralAmount(withdraw_collateral_amount),
+        swap_input_amount: LiquidityAmount(swap_input_amount),
+        min_swap_output_amount: LiquidityAmount(min_swap_output_amount),
+        health_factor_before: health_factor(obligation)?,
+        health_factor_after: health_factor(&after)?,
+    };
+    Ok((sizing, after))
+}
+
+/// Size an unwind of a refreshed obligation and its reserves without building instructions
+pub fn size_unwind(
+    position: &ObligationWithReserves,
+    params: &UnwindParams,
+) -> Result<UnwindSizing, Box<dyn Error>> {
+    Ok(size(position, params)?.0)
+}
+
+/// An unwind ready to be put in a transaction
+#[derive(Clone, Debug)]
+pub struct UnwindPlan {
+    /// Amounts of the unwind
+    pub sizing: UnwindSizing,
+    /// Lookup tables of the swap
+    pub lookup_tables: Vec<AddressLookupTableAccount>,
+    /// Instructions as if they started the transaction
+    instructions: Vec<Instruction>,
+    /// Position of the flash borrow in `instructions`
+    flash_borrow_index: usize,
+    /// Position of the flash repay in `instructions`
+    flash_repay_index: usize,
+}
+
+impl UnwindPlan {
+    /// The instructions of the unwind, to be placed from instruction `first_index` of the
+    /// transaction on. The flash repay points at the flash borrow by its index in the
+    /// transaction.
+    pub fn instructions(&self, first_index: usize) -> Result<Vec<Instruction>, Box<dyn Error>> {
+        let borrow_instruction_index = u8::try_from(first_index + self.flash_borrow_index)
+            .map_err(|_| "flash borrow instruction index doesn't fit in a u8")?;
+        let mut instructions = self.instructions.clone();
+        instructions[self.flash_repay_index].data =
+            LendingInstruction::FlashRepayReserveLiquidity {
+                liquidity_amount: self.sizing.repay_amount.0,
+                borrow_instruction_index,
+            }
+            .pack();
+        Ok(instructions)
+    }
+
+    /// Append the unwind to `builder`, setting the lookup tables of the swap on it
+    pub fn append_to(
+        &self,
+        builder: TransactionBuilder,
+    ) -> Result<TransactionBuilder, Box<dyn Error>> {
+        let instructions = self.instructions(builder.instruction_index())?;
+        Ok(builder
+            .instructions(instructions)
+            .lookup_tables(self.lookup_tables.clone()))
+    }
+
+    /// Dry run the unwind in a transaction paid for by `payer`
+    pub fn simulate(
+        &self,
+        client: &RpcClient,
+        payer: Pubkey,
+    ) -> Result<RpcSimulateTransactionResult, Box<dyn Error>> {
+        self.append_to(TransactionBuilder::new(payer))?
+            .simulate(client)
+    }
+}
+
+/// Plan an unwind of the obligation at `obligation_pubkey`, whose refreshed state and reserves
+/// are `position`, e.g. from [crate::offchain_utils::fetch_obligation_with_reserves]. The
+/// obligation owner signs and pays for the associated token accounts the unwind goes through.
+///
+/// The withdrawal still has to leave the obligation within its allowed borrow value, and both
+/// outflow rate limiters have to let it through; simulate the plan to find out.
+pub fn plan_unwind(
+    program_id: &Pubkey,
+    obligation_pubkey: &Pubkey,
+    position: &ObligationWithReserves,
+    params: &UnwindParams,
+    venue: &impl SwapVenue,
+) -> Result<UnwindPlan, Box<dyn Error>> {
+    let (sizing, after) = size(position, params)?;
+    let program_id = *program_id;
+    let obligation_pubkey = *obligation_pubkey;
+    let obligation = &position.obligation;
+    let owner = obligation.owner;
+    let lending_market = obligation.lending_market;
+    let collateral_reserve = &position.reserves[&params.collateral_reserve];
+    let debt_reserve = &position.reserves[&params.debt_reserve];
+
+    let debt_liquidity = get_associated_token_address(&owner, &debt_reserve.liquidity.mint_pubkey);
+    let collateral_liquidity =
+        get_associated_token_address(&owner, &collateral_reserve.liquidity.mint_pubkey);
+    let collateral =
+        get_associated_token_address(&owner, &collateral_reserve.collateral.mint_pubkey);
+
+    let mut instructions = vec![
+        create_associated_token_account_idempotent(
+            owner,
+            owner,
+            debt_reserve.liquidity.mint_pubkey,
+        ),
+        create_associated_token_account_idempotent(
+            owner,
+            owner,
+            collateral_reserve.liquidity.mint_pubkey,
+        ),
+        create_associated_token_account_idempotent(
+            owner,
+            owner,
+            collateral_reserve.collateral.mint_pubkey,
+        ),
+    ];
+
+    let flash_borrow_index = instructions.len();
+    instructions.push(flash_borrow_reserve_liquidity(
+        program_id,
+        sizing.repay_amount.0,
+        debt_reserve.liquidity.supply_pubkey,
+        debt_liquidity,
+        params.debt_reserve,
+        lending_market,
+    ));
+
+    // the flash borrow leaves the reserve stale
+    instructions.push(refresh_reserve_with_lending_market(
+        program_id,
+        params.debt_reserve,
+        debt_reserve.liquidity.pyth_oracle_pubkey,
+        debt_reserve.liquidity.switchboard_oracle_pubkey,
+        debt_reserve.lending_market,
+    ));
+    let mut repay = repay_obligation_liquidity(
+        program_id,
+        sizing.repay_amount.0,
+        debt_liquidity,
+        debt_reserve.liquidity.supply_pubkey,
+        params.debt_reserve,
+        obligation_pubkey,
+        lending_market,
+        owner,
+    );
+    if params.use_deleverage_credit {
+        append_deleverage_accounts(&mut repay, obligation_pubkey, lending_market);
+    }
+    instructions.push(repay);
+
+    // withdrawing needs the obligation refreshed, with the positions it has after the repay
+    let reserve_pubkeys = after
+        .deposits
+        .iter()
+        .map(|collateral| collateral.deposit_reserve)
+        .chain(
+            after
+                .borrows
+                .iter()
+                .map(|liquidity| liquidity.borrow_reserve),
+        )
+        .collect::<Vec<_>>();
+    let mut refreshed = Vec::new();
+    for reserve_pubkey in &reserve_pubkeys {
+        if refreshed.contains(reserve_pubkey) {
+            continue;
+        }
+        refreshed.push(*reserve_pubkey);
+        let reserve = &position.reserves[reserve_pubkey];
+        instructions.push(refresh_reserve_with_lending_market(
+            program_id,
+            *reserve_pubkey,
+            reserve.liquidity.pyth_oracle_pubkey,
+            reserve.liquidity.switchboard_oracle_pubkey,
+            reserve.lending_market,
+        ));
+    }
+    instructions.push(refresh_obligation(
+        program_id,
+        obligation_pubkey,
+        reserve_pubkeys,
+    ));
+
+    let mut withdraw =
+        withdraw_obligation_collateral_and_redeem_reserve_collateral_with_fee_receiver(
+            program_id,
+            sizing.withdraw_collateral_amount.0,
+            collateral_reserve.collateral.supply_pubkey,
+            collateral,
+            params.collateral_reserve,
+            obligation_pubkey,
+            lending_market,
+            collateral_liquidity,
+            collateral_reserve.collateral.mint_pubkey,
+            collateral_reserve.liquidity.supply_pubkey,
+            owner,
+            owner,
+            collateral_reserve.config.fee_receiver,
+        );
+    if params.use_deleverage_credit {
+        append_deleverage_accounts(&mut withdraw, obligation_pubkey, lending_market);
+    }
+    instructions.push(withdraw);
+
+    let swap = venue.swap(&SwapRequest {
+        input_mint: collateral_reserve.liquidity.mint_pubkey,
+        output_mint: debt_reserve.liquidity.mint_pubkey,
+        input_amount: sizing.swap_input_amount.0,
+        min_output_amount: sizing.min_swap_output_amount.0,
+        owner,
+        source_token_account: collateral_liquidity,
+        destination_token_account: debt_liquidity,
+    })?;
+    instructions.extend(swap.instructions);
+
+    let flash_repay_index = instructions.len();
+    instructions.push(flash_repay_reserve_liquidity(
+        program_id,
+        sizing.repay_amount.0,
+        0, // set once the position in the transaction is known
+        debt_liquidity,
+        debt_reserve.liquidity.supply_pubkey,
+        params
+            .flash_loan_fee_receiver
+            .unwrap_or(debt_reserve.config.fee_receiver),
+        debt_reserve.config.fee_receiver,
+        params.debt_reserve,
+        lending_market,
+        owner,
+    ));
+
+    Ok(UnwindPlan {
+        sizing,
+        lookup_tables: swap.lookup_tables,
+        instructions,
+        flash_borrow_index,
+        flash_repay_index,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        state::{
+            ObligationCollateral, ObligationLiquidity, ReserveCollateral, ReserveConfig,
+            ReserveFees, ReserveLiquidity,
+        },
+        transaction_builder::ComputeBudgetConfig,
+    };
+    use std::collections::HashMap;
+
+    fn reserve(price: u64, liquidation_threshold: u8) -> Reserve {
+        Reserve {
+            liquidity: ReserveLiquidity {
+                mint_pubkey: Pubkey::new_unique(),
+                mint_decimals: 6,
+                supply_pubkey: Pubkey::new_unique(),
+                market_price: Decimal::from(price),
+                smoothed_market_price: Decimal::from(price),
+                available_amount: 1_000_000_000_000,
+                cumulative_borrow_rate_wads: Decimal::one(),
+                ..ReserveLiquidity::default()
+            },
+            collateral: ReserveCollateral {
+                mint_pubkey: Pubkey::new_unique(),
+                mint_total_supply: 1_000_000_000_000,
+                supply_pubkey: Pubkey::new_unique(),
+            },
+            config: ReserveConfig {
+                liquidation_threshold,
+                fees: ReserveFees {
+                    flash_loan_fee_wad: 3_000_000_000_000_000, // 0.3%
+                    ..ReserveFees::default()
+                },
+                ..ReserveConfig::default()
+            },
+            ..Reserve::default()
+        }
+    }
+
+    // 1000 SOL at $10 deposited, 7000 USDC borrowed, health factor 8000 / 7000
+    fn looped_position() -> (ObligationWithReserves, UnwindParams) {
+        let sol = Pubkey::new_unique();
+        let usdc = Pubkey::new_unique();
+        let mut reserves = HashMap::new();
+        reserves.insert(sol, reserve(10, 80));
+        reserves.insert(usdc, reserve(1, 0));
+
+        let mut obligation = Obligation {
+            owner: Pubkey::new_unique(),
+            lending_market: Pubkey::new_unique(),
+            deposits: vec![ObligationCollateral {
+                deposited_amount: 1_000_000_000,
+                ..ObligationCollateral::new(sol)
+            }],
+            borrows: vec![ObligationLiquidity {
+                borrowed_amount_wads: Decimal::from(7_000_000_000u64),
+                ..ObligationLiquidity::new(usdc, Decimal::one())
+            }],
+            ..Obligation::default()
+        };
+        offchain_refresh_obligation(&mut obligation, &reserves).unwrap();
+
+        let params = UnwindParams {
+            collateral_reserve: sol,
+            debt_reserve: usdc,
+            target_health_factor: Decimal::from_percent(150),
+            max_slippage_bps: 100,
+            use_deleverage_credit: false,
+            flash_loan_fee_receiver: None,
+        };
+        let position = ObligationWithReserves {
+            obligation,
+            reserves,
+            slot: 0,
+        };
+        (position, params)
+    }
+
+    #[test]
+    fn unwind_sizing() {
+        let (position, params) = looped_position();
+        let sizing = size_unwind(&position, &params).unwrap();
+
+        // the target is reached, up to rounding
+        let after = sizing.health_factor_after.unwrap();
+        assert!(after > Decimal::from_bps(14_999));
+        assert!(after < Decimal::from_bps(15_001));
+
+        // the swap buys back the flash loan and its fee even at max slippage
+        assert_eq!(
+            sizing.min_swap_output_amount.0,
+            sizing.repay_amount.0 + sizing.flash_loan_fee
+        );
+        assert!(sizing.flash_loan_fee > 0);
+        assert!(sizing.swap_input_amount.0 * 10 * 99 / 100 >= sizing.min_swap_output_amount.0);
+
+        let params = UnwindParams {
+            target_health_factor: Decimal::one(),
+            ..params
+        };
+        assert_eq!(
+            size_unwind(&position, &params)
+                .unwrap_err()
+                .downcast_ref::<UnwindError>(),
+            Some(&UnwindError::AlreadyHealthy)
+        );
+    }
+
+    #[test]
+    fn unwind_plan_instructions() {
+        let (position, params) = looped_position();
+        let swap_program = Pubkey::new_unique();
+        let venue = |request: &SwapRequest| -> Result<SwapInstructions, Box<dyn Error>> {
+            Ok(SwapInstructions {
+                instructions: vec![Instruction::new_with_bytes(
+                    swap_program,
+                    &request.min_output_amount.to_le_bytes(),
+                    vec![],
+                )],
+                lookup_tables: vec![],
+            })
+        };
+        let plan = plan_unwind(
+            &crate::solend_mainnet::id(),
+            &Pubkey::new_unique(),
+            &position,
+            &params,
+            &venue,
+        )
+        .unwrap();
+
+        // the compute budget instructions go first
+        let builder = TransactionBuilder::new(position.obligation.owner)
+            .compute_budget(ComputeBudgetConfig::default());
+        let instructions = plan.instructions(builder.instruction_index()).unwrap();
+        let flash_borrow_index = instructions
+            .iter()
+            .position(|instruction| {
+                matches!(
+                    LendingInstruction::unpack(&instruction.data),
+                    Ok(LendingInstruction::FlashBorrowReserveLiquidity { .. })
+                )
+            })
+            .unwrap();
+        let swap_index = instructions
+            .iter()
+            .position(|instruction| instruction.program_id == swap_program)
+            .unwrap();
+        assert_eq!(
+            instructions[swap_index].data,
+            plan.sizing.min_swap_output_amount.0.to_le_bytes()
+        );
+        assert_eq!(
+            LendingInstruction::unpack(&instructions.last().unwrap().data).unwrap(),
+            LendingInstruction::FlashRepayReserveLiquidity {
+                liquidity_amount: plan.sizing.repay_amount.0,
+                borrow_instruction_index: (flash_borrow_index + 2) as u8,
+            }
+        );
+    }
+}
@@ -0,0 +1,520 @@
+//! wasm-bindgen bindings for the browser.
+//!
+//! Frontends load the account data they fetched into these wrappers and get health, max borrow,
+//! max withdraw and liquidation numbers from the same code the program runs, instead of a port
+//! that drifts from it. Decimals cross the boundary as [WasmDecimal], which parses from and
+//! prints to the scaled integer representation, and pubkeys as base58 strings.
+//!
+//! Each binding wraps a plain function returning a `ProgramError`, which is what the tests below
+//! call since `JsValue` only works on wasm targets.
+
+use crate::{
+    math::{Decimal, TryAdd, TryDiv, TryMul, TrySub},
+    state::{CalculateLiquidationResult, Obligation, Reserve},
+};
+use solana_program::{
+    clock::Slot, msg, program_error::ProgramError, program_pack::Pack, pubkey::Pubkey,
+};
+use std::{collections::HashMap, fmt::Display, str::FromStr};
+use wasm_bindgen::prelude::*;
+
+use crate::error::LendingError;
+
+fn js_error(err: impl Display) -> JsValue {
+    JsValue::from_str(&err.to_string())
+}
+
+fn parse_pubkey(pubkey: &str) -> Result<Pubkey, ProgramError> {
+    Pubkey::from_str(pubkey).map_err(|_| {
+        msg!("Pubkey {} is not valid base58", pubkey);
+        ProgramError::InvalidArgument
+    })
+}
+
+/// Fixed point decimal with 18 digits of precision, the [Decimal] of the program
+#[wasm_bindgen(js_name = Decimal)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WasmDecimal(Decimal);
+
+#[wasm_bindgen(js_class = Decimal)]
+impl WasmDecimal {
+    /// Decimal holding a whole number
+    #[wasm_bindgen(js_name = fromU64)]
+    pub fn from_u64(value: u64) -> WasmDecimal {
+        Self(Decimal::from(value))
+    }
+
+    /// Decimal from its scaled value, ie the number times 10^18, as a base 10 string
+    #[wasm_bindgen(js_name = fromScaledVal)]
+    pub fn from_scaled_val(scaled_val: &str) -> Result<WasmDecimal, JsValue> {
+        u128::from_str(scaled_val)
+            .map(|scaled_val| Self(Decimal::from_scaled_val(scaled_val)))
+            .map_err(js_error)
+    }
+
+    /// Scaled value, ie the number times 10^18, as a base 10 string
+    #[wasm_bindgen(js_name = toScaledVal)]
+    pub fn to_scaled_val(&self) -> Result<String, JsValue> {
+        self.0
+            .to_scaled_val()
+            .map(|scaled_val| scaled_val.to_string())
+            .map_err(js_error)
+    }
+
+    /// Number with all 18 decimals, e.g. "1.500000000000000000"
+    #[wasm_bindgen(js_name = toString)]
+    pub fn to_display_string(&self) -> String {
+        self.0.to_string()
+    }
+
+    /// Add two decimals
+    pub fn add(&self, rhs: &WasmDecimal) -> Result<WasmDecimal, JsValue> {
+        self.0.try_add(rhs.0).map(Self).map_err(js_error)
+    }
+
+    /// Subtract a decimal, erroring when the result would be negative
+    pub fn sub(&self, rhs: &WasmDecimal) -> Result<WasmDecimal, JsValue> {
+        self.0.try_sub(rhs.0).map(Self).map_err(js_error)
+    }
+
+    /// Multiply two decimals
+    pub fn mul(&self, rhs: &WasmDecimal) -> Result<WasmDecimal, JsValue> {
+        self.0.try_mul(rhs.0).map(Self).map_err(js_error)
+    }
+
+    /// Divide by a decimal
+    pub fn div(&self, rhs: &WasmDecimal) -> Result<WasmDecimal, JsValue> {
+        self.0.try_div(rhs.0).map(Self).map_err(js_error)
+    }
+
+    /// Round down to a whole number
+    pub fn floor(&self) -> Result<u64, JsValue> {
+        self.0.try_floor_u64().map_err(js_error)
+    }
+
+    /// Round up to a whole number
+    pub fn ceil(&self) -> Result<u64, JsValue> {
+        self.0.try_ceil_u64().map_err(js_error)
+    }
+}
+
+/// Reserves of a lending market, keyed by pubkey
+#[wasm_bindgen(js_name = Reserves)]
+#[derive(Clone, Debug, Default)]
+pub struct WasmReserves {
+    reserves: HashMap<Pubkey, Reserve>,
+}
+
+impl WasmReserves {
+    fn get(&self, pubkey: &Pubkey) -> Result<&Reserve, ProgramError> {
+        self.reserves.get(pubkey).ok_or_else(|| {
+            msg!("Reserve {} was not added", pubkey);
+            ProgramError::InvalidArgument
+        })
+    }
+
+    fn _insert(&mut self, pubkey: &str, data: &[u8]) -> Result<(), ProgramError> {
+        self.reserves
+            .insert(parse_pubkey(pubkey)?, Reserve::unpack(data)?);
+        Ok(())
+    }
+
+    fn _accrue_interest(&mut self, slot: Slot) -> Result<(), ProgramError> {
+        for reserve in self.reserves.values_mut() {
+            reserve.accrue_interest(slot)?;
+            reserve.last_update.update_slot(slot);
+        }
+        Ok(())
+    }
+}
+
+#[wasm_bindgen(js_class = Reserves)]
+impl WasmReserves {
+    /// Empty set of reserves
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> WasmReserves {
+        Self::default()
+    }
+
+    /// Add or replace a reserve from its account data
+    pub fn insert(&mut self, pubkey: &str, data: &[u8]) -> Result<(), JsValue> {
+        self._insert(pubkey, data).map_err(js_error)
+    }
+
+    /// Accrue interest on every reserve up to `slot`, like RefreshReserve without the oracle
+    /// price update
+    #[wasm_bindgen(js_name = accrueInterest)]
+    pub fn accrue_interest(&mut self, slot: u64) -> Result<(), JsValue> {
+        self._accrue_interest(slot).map_err(js_error)
+    }
+
+    /// Set the spot and smoothed prices of a reserve's liquidity, as the oracle would
+    #[wasm_bindgen(js_name = setPrice)]
+    pub fn set_price(
+        &mut self,
+        pubkey: &str,
+        market_price: &WasmDecimal,
+        smoothed_market_price: &WasmDecimal,
+    ) -> Result<(), JsValue> {
+        let pubkey = parse_pubkey(pubkey).map_err(js_error)?;
+        let reserve = self
+            .reserves
+            .get_mut(&pubkey)
+            .ok_or_else(|| js_error(format!("Reserve {} was not added", pubkey)))?;
+        reserve.liquidity.market_price = market_price.0;
+        reserve.liquidity.smoothed_market_price = smoothed_market_price.0;
+        Ok(())
+    }
+}
+
+/// Outcome of a liquidation, see [CalculateLiquidationResult]
+#[wasm_bindgen(js_name = Liquidation)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WasmLiquidation {
+    /// Liquidity the liquidator repays
+    #[wasm_bindgen(js_name = repayAmount)]
+    pub repay_amount: u64,
+    /// Collateral the liquidator receives, in cTokens
+    #[wasm_bindgen(js_name = withdrawAmount)]
+    pub withdraw_amount: u64,
+    settle_amount: Decimal,
+    bonus_rate: Decimal,
+}
+
+#[wasm_bindgen(js_class = Liquidation)]
+impl WasmLiquidation {
+    /// Debt settled, including any bad debt when the collateral runs out
+    #[wasm_bindgen(getter, js_name = settleAmount)]
+    pub fn settle_amount(&self) -> WasmDecimal {
+        WasmDecimal(self.settle_amount)
+    }
+
+    /// Liquidator bonus plus one, including the protocol fee
+    #[wasm_bindgen(getter, js_name = bonusRate)]
+    pub fn bonus_rate(&self) -> WasmDecimal {
+        WasmDecimal(self.bonus_rate)
+    }
+}
+
+/// An obligation and the calculations the program runs on it
+#[wasm_bindgen(js_name = Obligation)]
+#[derive(Clone, Debug)]
+pub struct WasmObligation(Obligation);
+
+impl WasmObligation {
+    fn _refresh(&mut self, reserves: &WasmReserves, slot: Slot) -> Result<(), ProgramError> {
+        let deposit_reserves = self
+            .0
+            .deposits
+            .iter()
+            .map(|collateral| reserves.get(&collateral.deposit_reserve).cloned())
+            .collect::<Result<Vec<_>, _>>()?;
+        let borrow_reserves = self
+            .0
+            .borrows
+            .iter()
+            .map(|liquidity| reserves.get(&liquidity.borrow_reserve).cloned())
+            .collect::<Result<Vec<_>, _>>()?;
+        self.0.refresh(&deposit_reserves, &borrow_reserves, slot)
+    }
+
+    fn _max_borrow_amount(
+        &self,
+        reserves: &WasmReserves,
+        borrow_reserve: &Pubkey,
+    ) -> Result<u64, ProgramError> {
+        let borrow_reserve = reserves.get(borrow_reserve)?;
+        let remaining_borrow_value = self
+            .0
+            .remaining_borrow_value()
+            .unwrap_or_else(|_| Decimal::zero());
+        if remaining_borrow_value == Decimal::zero() {
+            return Ok(0);
+        }
+        let remaining_reserve_capacity = Decimal::from(borrow_reserve.config.borrow_limit)
+            .try_sub(borrow_reserve.liquidity.borrowed_amount_wads)
+            .unwrap_or_else(|_| Decimal::zero())
+            .min(borrow_reserve.remaining_borrow_under_utilization_cap()?);
+
+        Ok(borrow_reserve
+            .calculate_borrow(
+                u64::MAX,
+                remaining_borrow_value,
+                remaining_reserve_capacity,
+                0,
+            )?
+            .receive_amount)
+    }
+
+    fn _max_withdraw_amount(
+        &self,
+        reserves: &WasmReserves,
+        withdraw_reserve: &Pubkey,
+    ) -> Result<u64, ProgramError> {
+        let (collateral, _) = self.0.find_collateral_in_deposits(*withdraw_reserve)?;
+        self.0
+            .max_withdraw_amount(collateral, reserves.get(withdraw_reserve)?)
+    }
+
+    fn _simulate_liquidation(
+        &self,
+        reserves: &WasmReserves,
+        repay_reserve: &Pubkey,
+        withdraw_reserve: &Pubkey,
+        liquidity_amount: u64,
+    ) -> Result<WasmLiquidation, ProgramError> {
+        if self.0.borrowed_value < self.0.unhealthy_borrow_value {
+            msg!("Obligation is healthy and cannot be liquidated");
+            return Err(LendingError::ObligationHealthy.into());
+        }
+        let (liquidity, _) = self.0.find_liquidity_in_borrows(*repay_reserve)?;
+        let (collateral, _) = self.0.find_collateral_in_deposits(*withdraw_reserve)?;
+        let CalculateLiquidationResult {
+            settle_amount,
+            repay_amount,
+            withdraw_amount,
+            bonus_rate,
+        } = reserves.get(withdraw_reserve)?.calculate_liquidation(
+            liquidity_amount,
+            &self.0,
+            liquidity,
+            collateral,
+        )?;
+        Ok(WasmLiquidation {
+            repay_amount,
+            withdraw_amount,
+            settle_amount,
+            bonus_rate,
+        })
+    }
+}
+
+#[wasm_bindgen(js_class = Obligation)]
+impl WasmObligation {
+    /// Obligation from its account data
+    pub fn unpack(data: &[u8]) -> Result<WasmObligation, JsValue> {
+        Obligation::unpack(data).map(Self).map_err(js_error)
+    }
+
+    /// Recompute the obligation's values like RefreshObligation. Every deposit and borrow
+    /// reserve has to be in `reserves`, with prices set and interest accrued up to `slot`.
+    pub fn refresh(&mut self, reserves: &WasmReserves, slot: u64) -> Result<(), JsValue> {
+        self._refresh(reserves, slot).map_err(js_error)
+    }
+
+    /// Market value of the deposits
+    #[wasm_bindgen(getter, js_name = depositedValue)]
+    pub fn deposited_value(&self) -> WasmDecimal {
+        WasmDecimal(self.0.deposited_value)
+    }
+
+    /// Risk adjusted market value of the borrows
+    #[wasm_bindgen(getter, js_name = borrowedValue)]
+    pub fn borrowed_value(&self) -> WasmDecimal {
+        WasmDecimal(self.0.borrowed_value)
+    }
+
+    /// Value that can be borrowed against the deposits
+    #[wasm_bindgen(getter, js_name = allowedBorrowValue)]
+    pub fn allowed_borrow_value(&self) -> WasmDecimal {
+        WasmDecimal(self.0.allowed_borrow_value)
+    }
+
+    /// Borrowed value at which the obligation can be liquidated
+    #[wasm_bindgen(getter, js_name = unhealthyBorrowValue)]
+    pub fn unhealthy_borrow_value(&self) -> WasmDecimal {
+        WasmDecimal(self.0.unhealthy_borrow_value)
+    }
+
+    /// Max amount of liquidity BorrowObligationLiquidity with u64::MAX would send, after fees.
+    /// Outflow rate limits and fee rebates are not taken into account.
+    #[wasm_bindgen(js_name = maxBorrowAmount)]
+    pub fn max_borrow_amount(
+        &self,
+        reserves: &WasmReserves,
+        borrow_reserve: &str,
+    ) -> Result<u64, JsValue> {
+        let borrow_reserve = parse_pubkey(borrow_reserve).map_err(js_error)?;
+        self._max_borrow_amount(reserves, &borrow_reserve)
+            .map_err(js_error)
+    }
+
+    /// Max amount of cTokens WithdrawObligationCollateral with u64::MAX would withdraw
+    #[wasm_bindgen(js_name = maxWithdrawAmount)]
+    pub fn max_withdraw_amount(
+        &self,
+        reserves: &WasmReserves,
+        withdraw_reserve: &str,
+    ) -> Result<u64, JsValue> {
+        let withdraw_reserve = parse_pubkey(withdraw_reserve).map_err(js_error)?;
+        self._max_withdraw_amount(reserves, &withdraw_reserve)
+            .map_err(js_error)
+    }
+
+    /// Outcome of liquidating the obligation by repaying `liquidity_amount` of the repay reserve,
+    /// u64::MAX for as much as allowed, and seizing collateral of the withdraw reserve
+    #[wasm_bindgen(js_name = simulateLiquidation)]
+    pub fn simulate_liquidation(
+        &self,
+        reserves: &WasmReserves,
+        repay_reserve: &str,
+        withdraw_reserve: &str,
+        liquidity_amount: u64,
+    ) -> Result<WasmLiquidation, JsValue> {
+        let repay_reserve = parse_pubkey(repay_reserve).map_err(js_error)?;
+        let withdraw_reserve = parse_pubkey(withdraw_reserve).map_err(js_error)?;
+        self._simulate_liquidation(
+            reserves,
+            &repay_reserve,
+            &withdraw_reserve,
+            liquidity_amount,
+        )
+        .map_err(js_error)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::state::{
+        LastUpdate, ObligationCollateral, ObligationLiquidity, ReserveCollateral, ReserveConfig,
+        ReserveLiquidity,
+    };
+
+    fn reserve(price: u64, loan_to_value_ratio: u8, liquidation_threshold: u8) -> Reserve {
+        Reserve {
+            version: 1,
+            last_update: LastUpdate::new(0),
+            liquidity: ReserveLiquidity {
+                mint_decimals: 0,
+                available_amount: 1_000,
+                cumulative_borrow_rate_wads: Decimal::one(),
+                market_price: Decimal::from(price),
+                smoothed_market_price: Decimal::from(price),
+                ..ReserveLiquidity::default()
+            },
+            collateral: ReserveCollateral {
+                mint_total_supply: 1_000,
+                ..ReserveCollateral::default()
+            },
+            config: ReserveConfig {
+                loan_to_value_ratio,
+                liquidation_threshold,
+                max_liquidation_threshold: liquidation_threshold,
+                liquidation_bonus: 5,
+                max_liquidation_bonus: 5,
+                borrow_limit: u64::MAX,
+                ..ReserveConfig::default()
+            },
+            slots_per_year: 100,
+            ..Reserve::default()
+        }
+    }
+
+    fn setup() -> (WasmReserves, WasmObligation, Pubkey, Pubkey) {
+        let deposit_reserve = Pubkey::new_unique();
+        let borrow_reserve = Pubkey::new_unique();
+        let mut reserves = WasmReserves::new();
+        reserves
+            .reserves
+            .insert(deposit_reserve, reserve(1, 50, 80));
+        reserves.reserves.insert(borrow_reserve, reserve(1, 0, 0));
+
+        let obligation = WasmObligation(Obligation {
+            deposits: vec![ObligationCollateral {
+                deposited_amount: 100,
+                ..ObligationCollateral::new(deposit_reserve)
+            }],
+            borrows: vec![ObligationLiquidity {
+                borrowed_amount_wads: Decimal::from(40u64),
+                ..ObligationLiquidity::new(borrow_reserve, Decimal::one())
+            }],
+            ..Obligation::default()
+        });
+        (reserves, obligation, deposit_reserve, borrow_reserve)
+    }
+
+    #[test]
+    fn decimal_math() {
+        let a = WasmDecimal::from_u64(3);
+        let b = WasmDecimal(Decimal::from_percent(50));
+        assert_eq!(
+            a.add(&b).unwrap().to_display_string(),
+            "3.500000000000000000"
+        );
+        assert_eq!(a.mul(&b).unwrap().ceil().unwrap(), 2);
+        assert_eq!(a.div(&b).unwrap(), WasmDecimal::from_u64(6));
+        assert_eq!(
+            WasmDecimal::from_scaled_val("1500000000000000000").unwrap(),
+            WasmDecimal::from_u64(1).add(&b).unwrap()
+        );
+        assert_eq!(b.to_scaled_val().unwrap(), "500000000000000000");
+    }
+
+    #[test]
+    fn refresh_and_max_amounts() {
+        let (mut reserves, mut obligation, deposit_reserve, borrow_reserve) = setup();
+        reserves._accrue_interest(10).unwrap();
+        obligation._refresh(&reserves, 10).unwrap();
+
+        assert_eq!(obligation.deposited_value(), WasmDecimal::from_u64(100));
+        assert_eq!(obligation.borrowed_value(), WasmDecimal::from_u64(40));
+        assert_eq!(obligation.allowed_borrow_value(), WasmDecimal::from_u64(50));
+        assert_eq!(
+            obligation.unhealthy_borrow_value(),
+            WasmDecimal::from_u64(80)
+        );
+        assert_eq!(obligation.0.last_update.slot, 10);
+
+        // 10 of borrowing power left and no borrow fee
+        assert_eq!(
+            obligation
+                ._max_borrow_amount(&reserves, &borrow_reserve)
+                .unwrap(),
+            10
+        );
+        // 10 / 50% ltv
+        assert_eq!(
+            obligation
+                ._max_withdraw_amount(&reserves, &deposit_reserve)
+                .unwrap(),
+            20
+        );
+
+        assert_eq!(
+            obligation._refresh(&WasmReserves::new(), 10),
+            Err(ProgramError::InvalidArgument)
+        );
+    }
+
+    #[test]
+    fn simulate_liquidation() {
+        let (mut reserves, mut obligation, deposit_reserve, borrow_reserve) = setup();
+        obligation._refresh(&reserves, 0).unwrap();
+        assert_eq!(
+            obligation._simulate_liquidation(&reserves, &borrow_reserve, &deposit_reserve, 10),
+            Err(LendingError::ObligationHealthy.into())
+        );
+
+        // collateral halves in value, 50 * 80% < 40 borrowed
+        reserves
+            .reserves
+            .get_mut(&deposit_reserve)
+            .unwrap()
+            .liquidity
+            .market_price = Decimal::from_percent(50);
+        obligation._refresh(&reserves, 0).unwrap();
+        // at most 20% of the 40 borrowed can be repaid
+        let liquidation = obligation
+            ._simulate_liquidation(&reserves, &borrow_reserve, &deposit_reserve, u64::MAX)
+            .unwrap();
+        assert_eq!(liquidation.repay_amount, 8);
+        assert_eq!(liquidation.settle_amount(), WasmDecimal::from_u64(8));
+        // 5% bonus and no protocol fee, 8 * 1.05 / 0.5 rounded down
+        assert_eq!(
+            liquidation.bonus_rate(),
+            WasmDecimal(Decimal::from_percent(105))
+        );
+        assert_eq!(liquidation.withdraw_amount, 16);
+    }
+}
@@ -0,0 +1,249 @@
+//! Accounts packed by the previous release must keep unpacking to the same values.
+//!
+//! The fixtures in `fixtures/<release>` are hex dumps of accounts packed by that release, see
+//! `fixtures/README.md`. Every field the release wrote is checked against the value it was packed
+//! with, and every field added since, which lives in what used to be padding, against the value an
+//! upgraded account is expected to start out with. Fields appended into padding that isn't zero
+//! on existing accounts, or shifted offsets, fail here before they corrupt accounts on chain.
+
+use solana_program::{
+    program_pack::{IsInitialized, Pack},
+    pubkey::Pubkey,
+};
+use solend_sdk::{
+    math::Decimal,
+    state::{
+        BorrowRateMode, DepositMode, LastUpdate, LendingMarket, Obligation, PriceBoundsMode,
+        RateLimiter, RateLimiterConfig, Reserve, ReserveStatus, ReserveType, SLOTS_PER_YEAR,
+    },
+};
+
+fn key(n: u8) -> Pubkey {
+    Pubkey::new_from_array([n; 32])
+}
+
+fn fixture(hex: &str) -> Vec<u8> {
+    let hex = hex.trim();
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+        .collect()
+}
+
+/// Unpack `data`, and check that packing the result back only writes to bytes that were padding
+/// in the release that packed it, and that it unpacks the same again
+fn unpack_and_repack<T: Pack + IsInitialized + PartialEq + std::fmt::Debug>(
+    data: &[u8],
+    padding: &[std::ops::Range<usize>],
+) -> T {
+    assert_eq!(data.len(), T::LEN);
+    let account = T::unpack(data).unwrap();
+
+    let mut repacked = vec![0u8; T::LEN];
+    T::pack_into_slice(&account, &mut repacked);
+    for (i, (old, new)) in data.iter().zip(&repacked).enumerate() {
+        if old != new {
+            assert!(
+                padding.iter().any(|range| range.contains(&i)),
+                "byte {} changed from {} to {} on repack",
+                i,
+                old,
+                new
+            );
+        }
+    }
+    assert_eq!(T::unpack(&repacked).unwrap(), account);
+
+    account
+}
+
+mod v2_0_2 {
+    use super::*;
+
+    #[test]
+    fn lending_market() {
+        let lending_market: LendingMarket = unpack_and_repack(
+            &fixture(include_str!("fixtures/v2.0.2/lending_market.hex")),
+            &[282..290],
+        );
+
+        let mut quote_currency = [0u8; 32];
+        quote_currency[..3].copy_from_slice(b"USD");
+        assert_eq!(lending_market.version, 1);
+        assert_eq!(lending_market.bump_seed, 254);
+        assert_eq!(lending_market.owner, key(1));
+        assert_eq!(lending_market.quote_currency, quote_currency);
+        assert_eq!(lending_market.token_program_id, key(2));
+        assert_eq!(lending_market.oracle_program_id, key(3));
+        assert_eq!(lending_market.switchboard_oracle_program_id, key(4));
+        assert_eq!(
+            lending_market.rate_limiter,
+            RateLimiter::new(
+                RateLimiterConfig {
+                    window_duration: 86_400,
+                    max_outflow: 1_000_000,
+                },
+                100,
+            )
+        );
+        assert_eq!(lending_market.whitelisted_liquidator, Some(key(5)));
+        assert_eq!(lending_market.risk_authority, key(6));
+
+        // added since
+        assert_eq!(lending_market.slots_per_year, SLOTS_PER_YEAR);
+    }
+
+    #[test]
+    fn reserve() {
+        let reserve: Reserve = unpack_and_repack(
+            &fixture(include_str!("fixtures/v2.0.2/reserve.hex")),
+            &[481..619],
+        );
+
+        assert_eq!(reserve.version, 1);
+        assert_eq!(
+            reserve.last_update,
+            LastUpdate {
+                slot: 1_000,
+                stale: true,
+            }
+        );
+        assert_eq!(reserve.lending_market, key(10));
+
+        let liquidity = &reserve.liquidity;
+        assert_eq!(liquidity.mint_pubkey, key(11));
+        assert_eq!(liquidity.mint_decimals, 6);
+        assert_eq!(liquidity.supply_pubkey, key(12));
+        assert_eq!(liquidity.pyth_oracle_pubkey, key(13));
+        assert_eq!(liquidity.switchboard_oracle_pubkey, key(14));
+        assert_eq!(liquidity.available_amount, 5_000_000);
+        assert_eq!(liquidity.borrowed_amount_wads, Decimal::from(3_000_000u64));
+        assert_eq!(
+            liquidity.cumulative_borrow_rate_wads,
+            Decimal::from_percent(105)
+        );
+        assert_eq!(
+            liquidity.accumulated_protocol_fees_wads,
+            Decimal::from(1_234u64)
+        );
+        assert_eq!(liquidity.market_price, Decimal::from_percent(101));
+        assert_eq!(liquidity.smoothed_market_price, Decimal::from_percent(99));
+
+        assert_eq!(reserve.collateral.mint_pubkey, key(15));
+        assert_eq!(reserve.collateral.mint_total_supply, 7_500_000);
+        assert_eq!(reserve.collateral.supply_pubkey, key(16));
+
+        let config = &reserve.config;
+        assert_eq!(config.optimal_utilization_rate, 80);
+        assert_eq!(config.max_utilization_rate, 90);
+        assert_eq!(config.loan_to_value_ratio, 75);
+        assert_eq!(config.liquidation_bonus, 5);
+        assert_eq!(config.max_liquidation_bonus, 10);
+        assert_eq!(config.liquidation_threshold, 80);
+        assert_eq!(config.max_liquidation_threshold, 85);
+        assert_eq!(config.min_borrow_rate, 1);
+        assert_eq!(config.optimal_borrow_rate, 8);
+        assert_eq!(config.max_borrow_rate, 50);
+        assert_eq!(config.super_max_borrow_rate, 150);
+        assert_eq!(config.fees.borrow_fee_wad, 1_000_000_000_000_000);
+        assert_eq!(config.fees.flash_loan_fee_wad, 3_000_000_000_000_000);
+        assert_eq!(config.fees.host_fee_percentage, 20);
+        assert_eq!(config.deposit_limit, 100_000_000);
+        assert_eq!(config.borrow_limit, 50_000_000);
+        assert_eq!(config.fee_receiver, key(17));
+        assert_eq!(config.protocol_liquidation_fee, 30);
+        assert_eq!(config.protocol_take_rate, 10);
+        assert_eq!(config.added_borrow_weight_bps, 2_500);
+        assert_eq!(config.reserve_type, ReserveType::Isolated);
+
+        assert_eq!(
+            reserve.rate_limiter,
+            RateLimiter::new(
+                RateLimiterConfig {
+                    window_duration: 3_600,
+                    max_outflow: 2_000_000,
+                },
+                900,
+            )
+        );
+
+        // added since
+        assert_eq!(liquidity.lifetime_forgiven_amount_wads, Decimal::zero());
+        assert_eq!(liquidity.fixed_borrowed_amount_wads, Decimal::zero());
+        assert_eq!(liquidity.fixed_interest_per_year, 0);
+        assert_eq!(config.deposit_soft_cap_pct, 0);
+        assert_eq!(config.borrow_soft_cap_pct, 0);
+        assert_eq!(config.idle_liquidity_fee_bps, 0);
+        assert_eq!(config.liquidation_fee_receiver, None);
+        assert_eq!(config.borrow_utilization_cap, 0);
+        assert_eq!(config.fixed_borrow_rate_bps, 0);
+        assert_eq!(config.fixed_borrow_max_duration_slots, 0);
+        assert_eq!(config.withdraw_fee_bps, 0);
+        assert_eq!(config.min_price_bps, 0);
+        assert_eq!(config.max_price_bps, 0);
+        assert_eq!(config.price_bounds_mode, PriceBoundsMode::Reject);
+        assert_eq!(reserve.slots_per_year, SLOTS_PER_YEAR);
+        assert_eq!(reserve.status, ReserveStatus::Active);
+        assert!(!reserve.has_fee_redemption_hook);
+    }
+
+    #[test]
+    fn obligation() {
+        let obligation: Obligation = unpack_and_repack(
+            &fixture(include_str!("fixtures/v2.0.2/obligation.hex")),
+            &[],
+        );
+
+        assert_eq!(obligation.version, 1);
+        assert_eq!(
+            obligation.last_update,
+            LastUpdate {
+                slot: 2_000,
+                stale: false,
+            }
+        );
+        assert_eq!(obligation.lending_market, key(10));
+        assert_eq!(obligation.owner, key(20));
+        assert_eq!(obligation.deposited_value, Decimal::from(500u64));
+        assert_eq!(obligation.borrowed_value, Decimal::from(250u64));
+        assert_eq!(obligation.borrowed_value_upper_bound, Decimal::from(260u64));
+        assert_eq!(obligation.allowed_borrow_value, Decimal::from(350u64));
+        assert_eq!(obligation.unhealthy_borrow_value, Decimal::from(400u64));
+        assert_eq!(
+            obligation.super_unhealthy_borrow_value,
+            Decimal::from(425u64)
+        );
+        assert!(obligation.borrowing_isolated_asset);
+
+        assert_eq!(obligation.deposits.len(), 2);
+        for (deposit, (reserve, deposited_amount, market_value)) in obligation
+            .deposits
+            .iter()
+            .zip([(key(21), 4_000_000, 400u64), (key(22), 10, 100)])
+        {
+            assert_eq!(deposit.deposit_reserve, reserve);
+            assert_eq!(deposit.deposited_amount, deposited_amount);
+            assert_eq!(deposit.market_value, Decimal::from(market_value));
+            // added since
+            assert_eq!(deposit.liquidation_priority, 0);
+        }
+
+        assert_eq!(obligation.borrows.len(), 1);
+        let borrow = &obligation.borrows[0];
+        assert_eq!(borrow.borrow_reserve, key(23));
+        assert_eq!(
+            borrow.cumulative_borrow_rate_wads,
+            Decimal::from_percent(110)
+        );
+        assert_eq!(borrow.borrowed_amount_wads, Decimal::from(2_500_000u64));
+        assert_eq!(borrow.market_value, Decimal::from(250u64));
+
+        // added since
+        assert_eq!(borrow.rate_mode, BorrowRateMode::Variable);
+        assert_eq!(borrow.fixed_interest_per_year, 0);
+        assert_eq!(borrow.fixed_rate_maturity_slot, 0);
+        assert_eq!(borrow.fixed_rate_accrual_slot, 0);
+        assert_eq!(obligation.default_deposit_mode, DepositMode::Collateralize);
+        assert!(!obligation.ignore_health_buffer);
+    }
+}
@@ -0,0 +1,17 @@
+//! Program entrypoint definitions
+
+#![cfg(all(target_arch = "bpf", not(feature = "no-entrypoint")))]
+
+use crate::processor;
+use solana_program::{
+    account_info::AccountInfo, entrypoint, entrypoint::ProgramResult, pubkey::Pubkey,
+};
+
+entrypoint!(process_instruction);
+fn process_instruction(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    processor::process_instruction(program_id, accounts, instruction_data)
+}
@@ -0,0 +1,261 @@
+//! Instruction types
+
+use crate::{find_obligation_address, find_vault_authority_address};
+use solana_program::{
+    instruction::{AccountMeta, Instruction},
+    program_error::ProgramError,
+    pubkey::{Pubkey, PUBKEY_BYTES},
+    system_program, sysvar,
+};
+use solend_sdk::{
+    instruction::{find_market_policy_address, find_obligation_limits_address},
+    state::Reserve,
+};
+use std::convert::TryInto;
+
+/// Instructions supported by the vault program
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum VaultInstruction {
+    // 0
+    /// Creates the user's obligation, owned by their vault authority
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[signer]` User.
+    ///   1. `[signer, writable]` Payer of the obligation rent.
+    ///   2. `[]` Vault authority - seeds [VAULT_AUTHORITY_SEED, lending market, user].
+    ///   3. `[writable]` Obligation - seeds [OBLIGATION_SEED, vault authority], uninitialized.
+    ///   4. `[]` Lending market account.
+    ///   5. `[]` Rent sysvar.
+    ///   6. `[]` Token program id.
+    ///   7. `[]` System program id.
+    ///   8. `[]` Lending program id.
+    InitVault,
+
+    // 1
+    /// Deposits the user's liquidity as collateral of their obligation
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[signer]` User, transfer authority of the liquidity and collateral accounts.
+    ///   1. `[writable]` Vault authority.
+    ///   2. `[writable]` User liquidity token account.
+    ///   3. `[writable]` User collateral token account.
+    ///   4. `[writable]` Reserve account.
+    ///   5. `[writable]` Reserve liquidity supply SPL Token account.
+    ///   6. `[writable]` Reserve collateral SPL Token mint.
+    ///   7. `[]` Lending market account.
+    ///   8. `[]` Derived lending market authority.
+    ///   9. `[writable]` Reserve collateral supply SPL Token account.
+    ///   10 `[writable]` Obligation account.
+    ///   11 `[]` Pyth price oracle account.
+    ///   12 `[]` Switchboard price feed oracle account.
+    ///   13 `[]` Token program id.
+    ///   14 `[]` Obligation limits account.
+    ///   15 `[]` Lending program id.
+    Deposit {
+        /// Amount of liquidity to deposit
+        liquidity_amount: u64,
+    },
+
+    // 2
+    /// Withdraws collateral of the user's obligation and redeems it to the user. The reserve and
+    /// obligation must be refreshed earlier in the transaction.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[signer]` User, transfer authority of the collateral account.
+    ///   1. `[]` Vault authority.
+    ///   2. `[writable]` Reserve collateral supply SPL Token account.
+    ///   3. `[writable]` User collateral token account.
+    ///   4. `[writable]` Reserve account - refreshed.
+    ///   5. `[writable]` Obligation account - refreshed.
+    ///   6. `[writable]` Lending market account.
+    ///   7. `[]` Derived lending market authority.
+    ///   8. `[writable]` User liquidity token account.
+    ///   9. `[writable]` Reserve collateral SPL Token mint.
+    ///   10 `[writable]` Reserve liquidity supply SPL Token account.
+    ///   11 `[]` Token program id.
+    ///   12 `[]` Obligation limits account.
+    ///   13 `[]` Market policy account.
+    ///   14 `[]` Lending program id.
+    ///   15 `[writable]` Reserve liquidity fee receiver - required if the reserve charges a
+    ///                     withdraw fee.
+    ///
+    /// Lending markets with a policy program aren't supported.
+    Withdraw {
+        /// Amount of collateral to withdraw - u64::MAX for all of it
+        collateral_amount: u64,
+    },
+}
+
+impl VaultInstruction {
+    /// Unpacks a byte buffer into a [VaultInstruction](enum.VaultInstruction.html).
+    pub fn unpack(input: &[u8]) -> Result<Self, ProgramError> {
+        let (&tag, rest) = input
+            .split_first()
+            .ok_or(ProgramError::InvalidInstructionData)?;
+        Ok(match tag {
+            0 => Self::InitVault,
+            1 => Self::Deposit {
+                liquidity_amount: Self::unpack_u64(rest)?,
+            },
+            2 => Self::Withdraw {
+                collateral_amount: Self::unpack_u64(rest)?,
+            },
+            _ => return Err(ProgramError::InvalidInstructionData),
+        })
+    }
+
+    fn unpack_u64(input: &[u8]) -> Result<u64, ProgramError> {
+        input
+            .get(..8)
+            .and_then(|bytes| bytes.try_into().ok())
+            .map(u64::from_le_bytes)
+            .ok_or(ProgramError::InvalidInstructionData)
+    }
+
+    /// Packs a [VaultInstruction](enum.VaultInstruction.html) into a byte buffer.
+    pub fn pack(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(9);
+        match self {
+            Self::InitVault => buf.push(0),
+            Self::Deposit { liquidity_amount } => {
+                buf.push(1);
+                buf.extend_from_slice(&liquidity_amount.to_le_bytes());
+            }
+            Self::Withdraw { collateral_amount } => {
+                buf.push(2);
+                buf.extend_from_slice(&collateral_amount.to_le_bytes());
+            }
+        }
+        buf
+    }
+}
+
+fn lending_market_authority(lending_program_id: &Pubkey, lending_market: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[&lending_market.to_bytes()[..PUBKEY_BYTES]],
+        lending_program_id,
+    )
+    .0
+}
+
+/// Creates an 'InitVault' instruction.
+pub fn init_vault(
+    program_id: Pubkey,
+    lending_program_id: Pubkey,
+    user: Pubkey,
+    payer: Pubkey,
+    lending_market: Pubkey,
+) -> Instruction {
+    let (vault_authority, _) = find_vault_authority_address(&program_id, &lending_market, &user);
+    let (obligation, _) = find_obligation_address(&program_id, &vault_authority);
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(user, true),
+            AccountMeta::new(payer, true),
+            AccountMeta::new_readonly(vault_authority, false),
+            AccountMeta::new(obligation, false),
+            AccountMeta::new_readonly(lending_market, false),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(lending_program_id, false),
+        ],
+        data: VaultInstruction::InitVault.pack(),
+    }
+}
+
+/// Creates a 'Deposit' instruction.
+#[allow(clippy::too_many_arguments)]
+pub fn deposit(
+    program_id: Pubkey,
+    lending_program_id: Pubkey,
+    liquidity_amount: u64,
+    user: Pubkey,
+    user_liquidity: Pubkey,
+    user_collateral: Pubkey,
+    reserve_pubkey: Pubkey,
+    reserve: &Reserve,
+) -> Instruction {
+    let lending_market = reserve.lending_market;
+    let (vault_authority, _) = find_vault_authority_address(&program_id, &lending_market, &user);
+    let (obligation, _) = find_obligation_address(&program_id, &vault_authority);
+    let (obligation_limits, _) =
+        find_obligation_limits_address(&lending_program_id, &lending_market);
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(user, true),
+            AccountMeta::new(vault_authority, false),
+            AccountMeta::new(user_liquidity, false),
+            AccountMeta::new(user_collateral, false),
+            AccountMeta::new(reserve_pubkey, false),
+            AccountMeta::new(reserve.liquidity.supply_pubkey, false),
+            AccountMeta::new(reserve.collateral.mint_pubkey, false),
+            AccountMeta::new_readonly(lending_market, false),
+            AccountMeta::new_readonly(
+                lending_market_authority(&lending_program_id, &lending_market),
+                false,
+            ),
+            AccountMeta::new(reserve.collateral.supply_pubkey, false),
+            AccountMeta::new(obligation, false),
+            AccountMeta::new_readonly(reserve.liquidity.pyth_oracle_pubkey, false),
+            AccountMeta::new_readonly(reserve.liquidity.switchboard_oracle_pubkey, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(obligation_limits, false),
+            AccountMeta::new_readonly(lending_program_id, false),
+        ],
+        data: VaultInstruction::Deposit { liquidity_amount }.pack(),
+    }
+}
+
+/// Creates a 'Withdraw' instruction.
+#[allow(clippy::too_many_arguments)]
+pub fn withdraw(
+    program_id: Pubkey,
+    lending_program_id: Pubkey,
+    collateral_amount: u64,
+    user: Pubkey,
+    user_liquidity: Pubkey,
+    user_collateral: Pubkey,
+    reserve_pubkey: Pubkey,
+    reserve: &Reserve,
+) -> Instruction {
+    let lending_market = reserve.lending_market;
+    let (vault_authority, _) = find_vault_authority_address(&program_id, &lending_market, &user);
+    let (obligation, _) = find_obligation_address(&program_id, &vault_authority);
+    let (obligation_limits, _) =
+        find_obligation_limits_address(&lending_program_id, &lending_market);
+    let (market_policy, _) = find_market_policy_address(&lending_program_id, &lending_market);
+    let mut accounts = vec![
+        AccountMeta::new_readonly(user, true),
+        AccountMeta::new_readonly(vault_authority, false),
+        AccountMeta::new(reserve.collateral.supply_pubkey, false),
+        AccountMeta::new(user_collateral, false),
+        AccountMeta::new(reserve_pubkey, false),
+        AccountMeta::new(obligation, false),
+        AccountMeta::new(lending_market, false),
+        AccountMeta::new_readonly(
+            lending_market_authority(&lending_program_id, &lending_market),
+            false,
+        ),
+        AccountMeta::new(user_liquidity, false),
+        AccountMeta::new(reserve.collateral.mint_pubkey, false),
+        AccountMeta::new(reserve.liquidity.supply_pubkey, false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new_readonly(obligation_limits, false),
+        AccountMeta::new_readonly(market_policy, false),
+        AccountMeta::new_readonly(lending_program_id, false),
+    ];
+    if reserve.config.withdraw_fee_bps > 0 {
+        accounts.push(AccountMeta::new(reserve.config.fee_receiver, false));
+    }
+    Instruction {
+        program_id,
+        accounts,
+        data: VaultInstruction::Withdraw { collateral_amount }.pack(),
+    }
+}
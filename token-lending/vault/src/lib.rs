@@ -0,0 +1,39 @@
+#![deny(missing_docs)]
+
+//! An example vault depositing into Solend on behalf of its users.
+//!
+//! Each user gets an obligation owned by a vault authority PDA instead of their own wallet, which
+//! is how structured products route collateral yield through a program. The vault signs for the
+//! PDA with `invoke_signed` while the user keeps signing token transfers.
+
+pub mod entrypoint;
+pub mod instruction;
+pub mod processor;
+
+pub use solana_program;
+use solana_program::pubkey::Pubkey;
+
+solana_program::declare_id!("Vau1t11111111111111111111111111111111111111");
+
+/// Seed of the vault authority, derived from [VAULT_AUTHORITY_SEED, lending market, user]
+pub const VAULT_AUTHORITY_SEED: &[u8] = b"vault";
+
+/// Seed of the obligation, derived from [OBLIGATION_SEED, vault authority]
+pub const OBLIGATION_SEED: &[u8] = b"obligation";
+
+/// Find the vault authority of a user, which owns the user's obligation
+pub fn find_vault_authority_address(
+    program_id: &Pubkey,
+    lending_market: &Pubkey,
+    user: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[VAULT_AUTHORITY_SEED, lending_market.as_ref(), user.as_ref()],
+        program_id,
+    )
+}
+
+/// Find the obligation of a vault authority
+pub fn find_obligation_address(program_id: &Pubkey, vault_authority: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[OBLIGATION_SEED, vault_authority.as_ref()], program_id)
+}
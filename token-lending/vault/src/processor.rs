@@ -0,0 +1,237 @@
+//! Program state processor
+
+use crate::{instruction::VaultInstruction, OBLIGATION_SEED, VAULT_AUTHORITY_SEED};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program::invoke_signed,
+    program_error::ProgramError,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    system_instruction,
+    sysvar::{rent::Rent, Sysvar},
+};
+use solend_sdk::{cpi, state::Obligation};
+
+/// Processes an instruction
+pub fn process_instruction(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    input: &[u8],
+) -> ProgramResult {
+    match VaultInstruction::unpack(input)? {
+        VaultInstruction::InitVault => {
+            msg!("Instruction: Init Vault");
+            process_init_vault(program_id, accounts)
+        }
+        VaultInstruction::Deposit { liquidity_amount } => {
+            msg!("Instruction: Deposit");
+            process_deposit(program_id, liquidity_amount, accounts)
+        }
+        VaultInstruction::Withdraw { collateral_amount } => {
+            msg!("Instruction: Withdraw");
+            process_withdraw(program_id, collateral_amount, accounts)
+        }
+    }
+}
+
+/// Checks the user signed and returns the bump seed of their vault authority
+fn vault_authority_bump_seed(
+    program_id: &Pubkey,
+    user_info: &AccountInfo,
+    vault_authority_info: &AccountInfo,
+    lending_market_info: &AccountInfo,
+) -> Result<u8, ProgramError> {
+    if !user_info.is_signer {
+        msg!("User must be a signer");
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    let (vault_authority, bump_seed) =
+        crate::find_vault_authority_address(program_id, lending_market_info.key, user_info.key);
+    if vault_authority != *vault_authority_info.key {
+        msg!("Vault authority does not match the user and lending market");
+        return Err(ProgramError::InvalidSeeds);
+    }
+    Ok(bump_seed)
+}
+
+fn process_init_vault(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let user_info = next_account_info(account_info_iter)?;
+    let payer_info = next_account_info(account_info_iter)?;
+    let vault_authority_info = next_account_info(account_info_iter)?;
+    let obligation_info = next_account_info(account_info_iter)?;
+    let lending_market_info = next_account_info(account_info_iter)?;
+    let rent_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let lending_program_info = next_account_info(account_info_iter)?;
+
+    let bump_seed = vault_authority_bump_seed(
+        program_id,
+        user_info,
+        vault_authority_info,
+        lending_market_info,
+    )?;
+    let (obligation, obligation_bump_seed) =
+        crate::find_obligation_address(program_id, vault_authority_info.key);
+    if obligation != *obligation_info.key {
+        msg!("Obligation does not match the vault authority");
+        return Err(ProgramError::InvalidSeeds);
+    }
+
+    invoke_signed(
+        &system_instruction::create_account(
+            payer_info.key,
+            obligation_info.key,
+            Rent::get()?.minimum_balance(Obligation::LEN),
+            Obligation::LEN as u64,
+            lending_program_info.key,
+        ),
+        &[
+            payer_info.clone(),
+            obligation_info.clone(),
+            system_program_info.clone(),
+        ],
+        &[&[
+            OBLIGATION_SEED,
+            vault_authority_info.key.as_ref(),
+            &[obligation_bump_seed],
+        ]],
+    )?;
+
+    cpi::InitObligation {
+        obligation: obligation_info.clone(),
+        lending_market: lending_market_info.clone(),
+        obligation_owner: vault_authority_info.clone(),
+        rent: rent_info.clone(),
+        token_program: token_program_info.clone(),
+        lending_program: lending_program_info.clone(),
+    }
+    .invoke_signed(&[&[
+        VAULT_AUTHORITY_SEED,
+        lending_market_info.key.as_ref(),
+        user_info.key.as_ref(),
+        &[bump_seed],
+    ]])
+}
+
+fn process_deposit(
+    program_id: &Pubkey,
+    liquidity_amount: u64,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let user_info = next_account_info(account_info_iter)?;
+    let vault_authority_info = next_account_info(account_info_iter)?;
+    let source_liquidity_info = next_account_info(account_info_iter)?;
+    let user_collateral_info = next_account_info(account_info_iter)?;
+    let reserve_info = next_account_info(account_info_iter)?;
+    let reserve_liquidity_supply_info = next_account_info(account_info_iter)?;
+    let reserve_collateral_mint_info = next_account_info(account_info_iter)?;
+    let lending_market_info = next_account_info(account_info_iter)?;
+    let lending_market_authority_info = next_account_info(account_info_iter)?;
+    let destination_collateral_info = next_account_info(account_info_iter)?;
+    let obligation_info = next_account_info(account_info_iter)?;
+    let pyth_oracle_info = next_account_info(account_info_iter)?;
+    let switchboard_oracle_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+    let obligation_limits_info = next_account_info(account_info_iter)?;
+    let lending_program_info = next_account_info(account_info_iter)?;
+
+    let bump_seed = vault_authority_bump_seed(
+        program_id,
+        user_info,
+        vault_authority_info,
+        lending_market_info,
+    )?;
+
+    cpi::DepositReserveLiquidityAndObligationCollateral {
+        source_liquidity: source_liquidity_info.clone(),
+        user_collateral: user_collateral_info.clone(),
+        reserve: reserve_info.clone(),
+        reserve_liquidity_supply: reserve_liquidity_supply_info.clone(),
+        reserve_collateral_mint: reserve_collateral_mint_info.clone(),
+        lending_market: lending_market_info.clone(),
+        lending_market_authority: lending_market_authority_info.clone(),
+        destination_deposit_collateral: destination_collateral_info.clone(),
+        obligation: obligation_info.clone(),
+        obligation_owner: vault_authority_info.clone(),
+        pyth_oracle: pyth_oracle_info.clone(),
+        switchboard_oracle: switchboard_oracle_info.clone(),
+        user_transfer_authority: user_info.clone(),
+        token_program: token_program_info.clone(),
+        obligation_limits: obligation_limits_info.clone(),
+        lending_program: lending_program_info.clone(),
+    }
+    .invoke_signed(
+        liquidity_amount,
+        &[&[
+            VAULT_AUTHORITY_SEED,
+            lending_market_info.key.as_ref(),
+            user_info.key.as_ref(),
+            &[bump_seed],
+        ]],
+    )
+}
+
+fn process_withdraw(
+    program_id: &Pubkey,
+    collateral_amount: u64,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let user_info = next_account_info(account_info_iter)?;
+    let vault_authority_info = next_account_info(account_info_iter)?;
+    let source_collateral_info = next_account_info(account_info_iter)?;
+    let destination_collateral_info = next_account_info(account_info_iter)?;
+    let withdraw_reserve_info = next_account_info(account_info_iter)?;
+    let obligation_info = next_account_info(account_info_iter)?;
+    let lending_market_info = next_account_info(account_info_iter)?;
+    let lending_market_authority_info = next_account_info(account_info_iter)?;
+    let destination_liquidity_info = next_account_info(account_info_iter)?;
+    let reserve_collateral_mint_info = next_account_info(account_info_iter)?;
+    let reserve_liquidity_supply_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+    let obligation_limits_info = next_account_info(account_info_iter)?;
+    let market_policy_info = next_account_info(account_info_iter)?;
+    let lending_program_info = next_account_info(account_info_iter)?;
+    let reserve_liquidity_fee_receiver_info = next_account_info(account_info_iter).ok();
+
+    let bump_seed = vault_authority_bump_seed(
+        program_id,
+        user_info,
+        vault_authority_info,
+        lending_market_info,
+    )?;
+
+    cpi::WithdrawObligationCollateralAndRedeemReserveCollateral {
+        source_collateral: source_collateral_info.clone(),
+        destination_collateral: destination_collateral_info.clone(),
+        withdraw_reserve: withdraw_reserve_info.clone(),
+        obligation: obligation_info.clone(),
+        lending_market: lending_market_info.clone(),
+        lending_market_authority: lending_market_authority_info.clone(),
+        destination_liquidity: destination_liquidity_info.clone(),
+        reserve_collateral_mint: reserve_collateral_mint_info.clone(),
+        reserve_liquidity_supply: reserve_liquidity_supply_info.clone(),
+        obligation_owner: vault_authority_info.clone(),
+        user_transfer_authority: user_info.clone(),
+        token_program: token_program_info.clone(),
+        obligation_limits: obligation_limits_info.clone(),
+        market_policy: market_policy_info.clone(),
+        lending_program: lending_program_info.clone(),
+        reserve_liquidity_fee_receiver: reserve_liquidity_fee_receiver_info.cloned(),
+        policy_accounts: Vec::new(),
+    }
+    .invoke_signed(
+        collateral_amount,
+        &[&[
+            VAULT_AUTHORITY_SEED,
+            lending_market_info.key.as_ref(),
+            user_info.key.as_ref(),
+            &[bump_seed],
+        ]],
+    )
+}